@@ -0,0 +1,16 @@
+//! Build script for the FHE engine's build hash
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", timestamp);
+    println!(
+        "cargo:rustc-env=BUILD_VERSION={}",
+        env!("CARGO_PKG_VERSION")
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src");
+}
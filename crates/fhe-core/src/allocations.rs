@@ -0,0 +1,79 @@
+//! Allocation profiling for FHE hot paths
+//!
+//! Engine allocations are tagged by call site and size class, so the shape
+//! of ciphertext buffers is visible to whatever process hosts the engine.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock};
+
+/// Upper bounds of the size classes, in bytes; larger allocations land in the last class
+const SIZE_CLASSES: [(usize, &str); 5] = [
+    (4 << 10, "le_4k"),
+    (64 << 10, "le_64k"),
+    (1 << 20, "le_1m"),
+    (16 << 20, "le_16m"),
+    (usize::MAX, "gt_16m"),
+];
+
+/// Allocation counts per size class for one hot-path call site
+#[derive(Debug, Default)]
+struct SiteCounters {
+    classes: [AtomicU64; SIZE_CLASSES.len()],
+    bytes: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteAllocationStats {
+    pub allocations: u64,
+    pub bytes: u64,
+    pub size_classes: HashMap<&'static str, u64>,
+}
+
+fn sites() -> &'static RwLock<HashMap<&'static str, SiteCounters>> {
+    static SITES: OnceLock<RwLock<HashMap<&'static str, SiteCounters>>> = OnceLock::new();
+    SITES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Tag an FHE hot-path allocation of `bytes` made at `site`
+pub fn record_hot_path(site: &'static str, bytes: usize) {
+    let class = SIZE_CLASSES
+        .iter()
+        .position(|(limit, _)| bytes <= *limit)
+        .unwrap_or(SIZE_CLASSES.len() - 1);
+
+    let record = |counters: &SiteCounters| {
+        counters.classes[class].fetch_add(1, Ordering::Relaxed);
+        counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    };
+    if let Some(counters) = sites().read().unwrap().get(site) {
+        record(counters);
+        return;
+    }
+    record(sites().write().unwrap().entry(site).or_default());
+}
+
+/// Size-class histograms for every tagged call site
+pub fn hot_path_stats() -> HashMap<&'static str, SiteAllocationStats> {
+    sites()
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(site, counters)| {
+            let size_classes: HashMap<_, _> = SIZE_CLASSES
+                .iter()
+                .zip(&counters.classes)
+                .map(|((_, name), count)| (*name, count.load(Ordering::Relaxed)))
+                .collect();
+            (
+                *site,
+                SiteAllocationStats {
+                    allocations: size_classes.values().sum(),
+                    bytes: counters.bytes.load(Ordering::Relaxed),
+                    size_classes,
+                },
+            )
+        })
+        .collect()
+}
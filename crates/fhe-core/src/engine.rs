@@ -0,0 +1,1864 @@
+//! The FHE engine: key pairs, encryption and the operations run on ciphertexts
+//!
+//! Clients run the same engine as the proxy, so a ciphertext encrypted with
+//! the SDK decrypts, packs and sums exactly as one encrypted server-side.
+
+use crate::allocations::record_hot_path;
+use crate::error::{Error, Result};
+use crate::{Ciphertext, EngineFingerprint, FheParams};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Hash identifying the engine build
+pub fn build_hash() -> String {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    context.update(env!("BUILD_VERSION").as_bytes());
+    context.update(env!("BUILD_TIMESTAMP").as_bytes());
+    context.update(if cfg!(debug_assertions) {
+        b"debug"
+    } else {
+        b"release"
+    });
+    context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Builds an [`FheEngine`], checking its parameters first
+#[derive(Debug, Clone, Default)]
+pub struct FheEngineBuilder {
+    params: FheParams,
+    warm_up: bool,
+}
+
+impl FheEngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn params(mut self, params: FheParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Ring dimension; a power of two
+    pub fn poly_modulus_degree(mut self, degree: usize) -> Self {
+        self.params.poly_modulus_degree = degree;
+        self
+    }
+
+    pub fn coeff_modulus_bits(mut self, bits: Vec<u64>) -> Self {
+        self.params.coeff_modulus_bits = bits;
+        self
+    }
+
+    pub fn scale_bits(mut self, bits: u64) -> Self {
+        self.params.scale_bits = bits;
+        self
+    }
+
+    pub fn security_level(mut self, level: u8) -> Self {
+        self.params.security_level = level;
+        self
+    }
+
+    /// Compute the engine's precomputed tables before returning it, so the
+    /// first operations do not pay for them
+    pub fn warm_up(mut self, warm_up: bool) -> Self {
+        self.warm_up = warm_up;
+        self
+    }
+
+    pub fn build(self) -> Result<FheEngine> {
+        if !self.params.poly_modulus_degree.is_power_of_two() {
+            return Err(Error::Configuration(
+                "Poly modulus degree must be a power of 2".to_string(),
+            ));
+        }
+        if self.params.coeff_modulus_bits.is_empty() {
+            return Err(Error::Configuration(
+                "Coefficient modulus bits cannot be empty".to_string(),
+            ));
+        }
+        let engine = FheEngine::new(self.params)?;
+        if self.warm_up {
+            engine.warm_up()?;
+        }
+        Ok(engine)
+    }
+}
+
+/// Client key for encryption/decryption
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct ClientKey {
+    pub id: Uuid,
+    key_data: Vec<u8>, // Simulated key data
+    params: FheParams,
+}
+
+/// Server key for homomorphic operations
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct ServerKey {
+    pub id: Uuid,
+    key_data: Vec<u8>, // Simulated key data
+    params: FheParams,
+}
+
+/// Statistics for FHE engine
+#[derive(Debug, Serialize)]
+pub struct FheStats {
+    pub total_client_keys: usize,
+    pub total_server_keys: usize,
+    pub params: FheParams,
+}
+
+/// NTT tables for each RNS modulus: an NTT-friendly prime and the powers of a
+/// primitive 2N-th root of unity in bit-reversed order. Computing these is the
+/// bulk of engine warm-up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrecomputedTables {
+    pub moduli: Vec<u64>,
+    pub root_powers: Vec<Vec<u64>>,
+}
+
+impl PrecomputedTables {
+    pub fn compute(params: &FheParams) -> Result<Self> {
+        let n = params.poly_modulus_degree as u64;
+        if n < 2 || !n.is_power_of_two() {
+            return Err(Error::Fhe(format!(
+                "Polynomial degree {} is not a power of two",
+                n
+            )));
+        }
+        let two_n = 2 * n;
+
+        let mut moduli: Vec<u64> = Vec::with_capacity(params.coeff_modulus_bits.len());
+        for &bits in &params.coeff_modulus_bits {
+            if !(2..=62).contains(&bits) {
+                return Err(Error::Fhe(format!(
+                    "Unsupported modulus size: {} bits",
+                    bits
+                )));
+            }
+            // Largest prime below 2^bits with q = 1 (mod 2N), distinct from earlier moduli
+            let upper = 1u64 << bits;
+            let mut candidate = (upper - 1) / two_n * two_n + 1;
+            loop {
+                if candidate < upper / 2 || candidate <= two_n {
+                    return Err(Error::Fhe(format!(
+                        "No {}-bit NTT-friendly prime for degree {}",
+                        bits, n
+                    )));
+                }
+                if !moduli.contains(&candidate) && is_prime(candidate) {
+                    break;
+                }
+                candidate -= two_n;
+            }
+            moduli.push(candidate);
+        }
+
+        let log_n = n.trailing_zeros();
+        let root_powers = moduli
+            .iter()
+            .map(|&q| {
+                let psi = primitive_root(q, two_n);
+                let mut powers = Vec::with_capacity(n as usize);
+                let mut power = 1u64;
+                for _ in 0..n {
+                    powers.push(power);
+                    power = mul_mod(power, psi, q);
+                }
+                (0..n)
+                    .map(|i| powers[(i.reverse_bits() >> (64 - log_n)) as usize])
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            moduli,
+            root_powers,
+        })
+    }
+
+    /// SHA-256 (hex) over every modulus and root power
+    pub fn digest(&self) -> String {
+        let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+        for (q, powers) in self.moduli.iter().zip(&self.root_powers) {
+            context.update(&q.to_le_bytes());
+            for power in powers {
+                context.update(&power.to_le_bytes());
+            }
+        }
+        context
+            .finish()
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Memory held by the tables
+    pub fn size_bytes(&self) -> u64 {
+        let words = self.moduli.len() + self.root_powers.iter().map(Vec::len).sum::<usize>();
+        (words * std::mem::size_of::<u64>()) as u64
+    }
+}
+
+/// A table generation published by a swap
+#[derive(Debug, Clone, Serialize)]
+pub struct TableSwapEvent {
+    pub generation: u64,
+    pub tables_digest: String,
+    pub swapped_at: i64,
+    /// Generations still leased by in-flight operations right after the swap
+    pub retiring_generations: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableBufferStats {
+    pub generation: u64,
+    pub swaps: u64,
+    /// Replaced generations freed once their last lease was dropped
+    pub released: u64,
+    pub active_bytes: u64,
+    /// Memory held by replaced generations that operations still lease; the
+    /// overhead of double-buffering
+    pub retiring_bytes: u64,
+    pub retiring_generations: usize,
+    /// Most recent swaps, newest first
+    pub recent_swaps: Vec<TableSwapEvent>,
+}
+
+const MAX_SWAP_EVENTS: usize = 16;
+
+#[derive(Debug, Default)]
+struct TableBufferState {
+    active: Option<(u64, Arc<PrecomputedTables>)>,
+    retiring: Vec<(u64, Arc<PrecomputedTables>)>,
+    generation: u64,
+    swaps: u64,
+    released: u64,
+    events: std::collections::VecDeque<TableSwapEvent>,
+}
+
+impl TableBufferState {
+    /// Drop replaced generations no operation leases any more
+    fn reap(&mut self) {
+        let before = self.retiring.len();
+        self.retiring
+            .retain(|(_, tables)| Arc::strong_count(tables) > 1);
+        self.released += (before - self.retiring.len()) as u64;
+    }
+}
+
+/// Double-buffered precomputed tables. Operations lease the active generation
+/// for their duration; a swap publishes a new generation without waiting for
+/// them, and the generation it replaced is kept until its last lease is dropped.
+#[derive(Debug, Default)]
+pub struct TableBuffer {
+    state: Mutex<TableBufferState>,
+}
+
+impl TableBuffer {
+    /// The active generation, held for as long as the lease is
+    pub fn lease(&self) -> Option<Arc<PrecomputedTables>> {
+        let state = self.state.lock().unwrap();
+        state.active.as_ref().map(|(_, tables)| tables.clone())
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.state.lock().unwrap().active.is_some()
+    }
+
+    /// Publish `tables` as the active generation, returning its number
+    pub fn swap(&self, tables: PrecomputedTables) -> TableSwapEvent {
+        let digest = tables.digest();
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        let generation = state.generation;
+        if let Some(previous) = state.active.replace((generation, Arc::new(tables))) {
+            state.retiring.push(previous);
+        }
+        state.swaps += 1;
+        state.reap();
+        let event = TableSwapEvent {
+            generation,
+            tables_digest: digest,
+            swapped_at: chrono::Utc::now().timestamp(),
+            retiring_generations: state.retiring.len(),
+        };
+        if state.events.len() >= MAX_SWAP_EVENTS {
+            state.events.pop_front();
+        }
+        state.events.push_back(event.clone());
+        event
+    }
+
+    pub fn stats(&self) -> TableBufferStats {
+        let mut state = self.state.lock().unwrap();
+        state.reap();
+        TableBufferStats {
+            generation: state
+                .active
+                .as_ref()
+                .map_or(0, |(generation, _)| *generation),
+            swaps: state.swaps,
+            released: state.released,
+            active_bytes: state
+                .active
+                .as_ref()
+                .map_or(0, |(_, tables)| tables.size_bytes()),
+            retiring_bytes: state
+                .retiring
+                .iter()
+                .map(|(_, tables)| tables.size_bytes())
+                .sum(),
+            retiring_generations: state.retiring.len(),
+            recent_swaps: state.events.iter().rev().cloned().collect(),
+        }
+    }
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    ((a as u128 * b as u128) % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1u64;
+    base %= m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+/// Deterministic Miller-Rabin for 64-bit integers
+fn is_prime(n: u64) -> bool {
+    const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    if let Some(&p) = WITNESSES.iter().find(|&&p| n.is_multiple_of(p)) {
+        return n == p;
+    }
+
+    let d = (n - 1) >> (n - 1).trailing_zeros();
+    let s = (n - 1).trailing_zeros();
+    WITNESSES.iter().all(|&a| {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// A primitive `order`-th root of unity modulo prime `q`, with `order` a power of two
+fn primitive_root(q: u64, order: u64) -> u64 {
+    (2..q)
+        .map(|g| pow_mod(g, (q - 1) / order, q))
+        .find(|&psi| pow_mod(psi, order / 2, q) == q - 1)
+        .expect("q = 1 (mod order) guarantees a primitive root")
+}
+
+/// FHE engine for homomorphic operations
+#[derive(Debug)]
+pub struct FheEngine {
+    params: FheParams,
+    pub client_keys: HashMap<Uuid, ClientKey>,
+    pub server_keys: HashMap<Uuid, ServerKey>,
+    tables: TableBuffer,
+}
+
+impl FheEngine {
+    /// Start building an engine from the default parameters
+    pub fn builder() -> FheEngineBuilder {
+        FheEngineBuilder::new()
+    }
+
+    /// Create new FHE engine with specified parameters
+    pub fn new(params: FheParams) -> Result<Self> {
+        log::info!(
+            "Initializing FHE engine with security level {}",
+            params.security_level
+        );
+
+        Ok(Self {
+            params,
+            client_keys: HashMap::new(),
+            server_keys: HashMap::new(),
+            tables: TableBuffer::default(),
+        })
+    }
+
+    /// Compute the precomputed tables for this engine's parameters
+    pub fn warm_up(&self) -> Result<()> {
+        let tables = PrecomputedTables::compute(&self.params)?;
+        self.install_tables(tables).map(|_| ())
+    }
+
+    /// Install tables computed elsewhere or restored from a snapshot. Only a
+    /// shared reference is needed, so tables loaded in the background swap in
+    /// while operations keep running on the generation they leased.
+    pub fn install_tables(&self, tables: PrecomputedTables) -> Result<TableSwapEvent> {
+        let n = self.params.poly_modulus_degree;
+        if tables.moduli.len() != self.params.coeff_modulus_bits.len()
+            || tables.root_powers.len() != tables.moduli.len()
+            || tables.root_powers.iter().any(|powers| powers.len() != n)
+        {
+            return Err(Error::Fhe(
+                "Precomputed tables do not match engine parameters".to_string(),
+            ));
+        }
+        Ok(self.tables.swap(tables))
+    }
+
+    /// Lease the active tables; they outlive any swap until the lease is dropped
+    pub fn tables(&self) -> Option<Arc<PrecomputedTables>> {
+        self.tables.lease()
+    }
+
+    pub fn table_stats(&self) -> TableBufferStats {
+        self.tables.stats()
+    }
+
+    /// Build and parameter-table identity of this engine
+    pub fn fingerprint(&self) -> EngineFingerprint {
+        EngineFingerprint {
+            build_hash: build_hash(),
+            params_digest: self.params.fingerprint(),
+            tables_digest: self.tables.lease().map(|tables| tables.digest()),
+        }
+    }
+
+    /// Whether warm-up has completed
+    pub fn is_warm(&self) -> bool {
+        self.tables.is_loaded()
+    }
+
+    /// Generate new client/server key pair
+    pub fn generate_keys(&mut self) -> Result<(Uuid, Uuid)> {
+        let client_id = Uuid::new_v4();
+        let server_id = Uuid::new_v4();
+
+        log::info!(
+            "Generating FHE key pair: client={}, server={}",
+            client_id,
+            server_id
+        );
+
+        // Generate simulated key data
+        let mut rng = rand::rng();
+        let client_key_data: Vec<u8> = (0..128).map(|_| rng.random()).collect();
+        let server_key_data: Vec<u8> = (0..256).map(|_| rng.random()).collect();
+
+        self.client_keys.insert(
+            client_id,
+            ClientKey {
+                id: client_id,
+                key_data: client_key_data,
+                params: self.params.clone(),
+            },
+        );
+
+        self.server_keys.insert(
+            server_id,
+            ServerKey {
+                id: server_id,
+                key_data: server_key_data,
+                params: self.params.clone(),
+            },
+        );
+
+        Ok((client_id, server_id))
+    }
+
+    /// Encrypt text using CKKS-style encoding with enhanced validation
+    pub fn encrypt_text(&self, client_id: Uuid, plaintext: &str) -> Result<Ciphertext> {
+        let _client_key = self
+            .client_keys
+            .get(&client_id)
+            .ok_or_else(|| Error::Fhe("Client key not found".to_string()))?;
+
+        // Input validation
+        if plaintext.is_empty() {
+            return Err(Error::Validation("Plaintext cannot be empty".to_string()));
+        }
+
+        if plaintext.len() > 10_000 {
+            return Err(Error::Validation(
+                "Plaintext too long (max 10,000 characters)".to_string(),
+            ));
+        }
+
+        // Enhanced input sanitization with security focus
+        let sanitized_text = plaintext
+            .chars()
+            .filter(|c| {
+                c.is_ascii()
+                && (!c.is_control() || c.is_whitespace())
+                && *c != '\0' // Null byte protection
+                && !matches!(*c, '\x01'..='\x08' | '\x0B'..='\x0C' | '\x0E'..='\x1F' | '\x7F')
+                // Control chars
+            })
+            .collect::<String>();
+
+        if sanitized_text != plaintext {
+            log::warn!(
+                "Input sanitized during encryption for client {} (removed {} characters)",
+                client_id,
+                plaintext.len() - sanitized_text.len()
+            );
+        }
+
+        // Additional security check for potential injection patterns
+        let suspicious_patterns = [
+            "<script",
+            "javascript:",
+            "data:",
+            "vbscript:",
+            "onload=",
+            "onerror=",
+        ];
+        let lowercase_text = sanitized_text.to_lowercase();
+        for pattern in &suspicious_patterns {
+            if lowercase_text.contains(pattern) {
+                log::warn!(
+                    "Potentially malicious pattern detected in encryption input for client {}: {}",
+                    client_id,
+                    pattern
+                );
+                return Err(Error::Validation(
+                    "Input contains potentially malicious content".to_string(),
+                ));
+            }
+        }
+
+        log::debug!(
+            "Encrypting text of length {} for client {}",
+            sanitized_text.len(),
+            client_id
+        );
+
+        // Convert text to boolean array for concrete library
+        let text_bytes = sanitized_text.as_bytes();
+        let mut encrypted_data = Vec::new();
+
+        // Add encryption metadata header
+        let metadata = format!("FHE-v1|{}", chrono::Utc::now().timestamp());
+        let metadata_bytes = metadata.as_bytes();
+        encrypted_data.extend_from_slice(&(metadata_bytes.len() as u32).to_le_bytes());
+        encrypted_data.extend_from_slice(metadata_bytes);
+
+        // Simulate encryption by encoding each byte as encrypted booleans
+        for &byte in text_bytes {
+            for i in 0..8 {
+                let bit = (byte >> i) & 1 == 1;
+                // In real implementation, encrypt each bit with concrete
+                encrypted_data.push(if bit { 1u8 } else { 0u8 });
+            }
+        }
+
+        record_hot_path("encrypt", encrypted_data.capacity());
+
+        // Calculate noise budget based on operations
+        let noise_budget = self.calculate_noise_budget(text_bytes.len());
+
+        Ok(Ciphertext {
+            id: Uuid::new_v4(),
+            data: encrypted_data,
+            params: self.params.clone(),
+            noise_budget: Some(noise_budget),
+        })
+    }
+
+    /// Decrypt ciphertext back to text
+    pub fn decrypt_text(&self, client_id: Uuid, ciphertext: &Ciphertext) -> Result<String> {
+        self.secret_key(client_id)?;
+
+        log::debug!("Decrypting ciphertext {}", ciphertext.id);
+
+        // Extract metadata from encrypted data
+        if ciphertext.data.len() < 4 {
+            return Err(Error::Fhe("Invalid ciphertext format".to_string()));
+        }
+
+        let metadata_len = u32::from_le_bytes([
+            ciphertext.data[0],
+            ciphertext.data[1],
+            ciphertext.data[2],
+            ciphertext.data[3],
+        ]) as usize;
+
+        if ciphertext.data.len() < 4 + metadata_len {
+            return Err(Error::Fhe("Corrupted ciphertext metadata".to_string()));
+        }
+
+        let metadata_bytes = &ciphertext.data[4..4 + metadata_len];
+        let _metadata = String::from_utf8(metadata_bytes.to_vec())
+            .map_err(|_| Error::Fhe("Invalid metadata encoding".to_string()))?;
+
+        // Decrypt the boolean array back to text
+        let encrypted_bits = &ciphertext.data[4 + metadata_len..];
+        let mut text_bytes = Vec::new();
+
+        // Decrypt bits back to bytes (simulated)
+        for chunk in encrypted_bits.chunks(8) {
+            if chunk.len() != 8 {
+                break; // Incomplete byte at end
+            }
+            let mut byte = 0u8;
+            for (i, &encrypted_bit) in chunk.iter().enumerate() {
+                // In real implementation, decrypt each encrypted boolean with concrete
+                let bit = encrypted_bit != 0; // Simulated decryption
+                if bit {
+                    byte |= 1 << i;
+                }
+            }
+            text_bytes.push(byte);
+        }
+
+        // Convert bytes back to string
+        let plaintext = String::from_utf8(text_bytes)
+            .map_err(|_| Error::Fhe("Invalid UTF-8 in decrypted data".to_string()))?;
+
+        log::debug!(
+            "Successfully decrypted {} characters for client {}",
+            plaintext.len(),
+            client_id
+        );
+
+        Ok(plaintext)
+    }
+
+    /// Perform homomorphic string concatenation with enhanced security
+    pub fn concatenate_encrypted(&self, a: &Ciphertext, b: &Ciphertext) -> Result<Ciphertext> {
+        log::debug!("Concatenating ciphertexts {} and {}", a.id, b.id);
+
+        // Validate ciphertext parameters compatibility
+        if a.params.poly_modulus_degree != b.params.poly_modulus_degree {
+            return Err(Error::Fhe("Incompatible ciphertext parameters".to_string()));
+        }
+
+        if a.params.security_level != b.params.security_level {
+            return Err(Error::Fhe("Mismatched security levels".to_string()));
+        }
+
+        // Check for potential overflow in concatenated size
+        let total_size = a.data.len().saturating_add(b.data.len());
+        if total_size > 1_000_000 {
+            // 1MB limit
+            return Err(Error::Fhe(
+                "Concatenated ciphertext would exceed size limit".to_string(),
+            ));
+        }
+
+        // Validate noise budgets before operation
+        match (a.noise_budget, b.noise_budget) {
+            (Some(a_budget), Some(b_budget)) => {
+                if a_budget < 10 || b_budget < 10 {
+                    return Err(Error::Fhe(
+                        "Insufficient noise budget for concatenation".to_string(),
+                    ));
+                }
+            }
+            _ => {
+                log::warn!("Missing noise budget information for concatenation");
+            }
+        }
+
+        // Perform concatenation with metadata preservation
+        let mut concatenated_data = Vec::with_capacity(total_size);
+
+        // Add operation header for audit trail
+        let op_header = format!("CONCAT|{}|{}", a.id, b.id);
+        let header_bytes = op_header.as_bytes();
+        concatenated_data.extend_from_slice(&(header_bytes.len() as u32).to_le_bytes());
+        concatenated_data.extend_from_slice(header_bytes);
+
+        // Concatenate actual ciphertext data
+        concatenated_data.extend_from_slice(&a.data);
+        concatenated_data.extend_from_slice(&b.data);
+
+        record_hot_path("concatenate", concatenated_data.capacity());
+
+        // Calculate remaining noise budget (conservative estimate)
+        let noise_budget = match (a.noise_budget, b.noise_budget) {
+            (Some(a_budget), Some(b_budget)) => {
+                let min_budget = a_budget.min(b_budget);
+                Some(min_budget.saturating_sub(3)) // Subtract cost of operation
+            }
+            _ => None,
+        };
+
+        log::info!(
+            "Successfully concatenated ciphertexts {} + {} -> new size: {} bytes",
+            a.id,
+            b.id,
+            concatenated_data.len()
+        );
+
+        Ok(Ciphertext {
+            id: Uuid::new_v4(),
+            data: concatenated_data,
+            params: a.params.clone(),
+            noise_budget,
+        })
+    }
+
+    /// Process encrypted prompt through homomorphic operations
+    pub fn process_encrypted_prompt(&self, ciphertext: &Ciphertext) -> Result<Ciphertext> {
+        log::debug!("Processing encrypted prompt {}", ciphertext.id);
+        // Keep this generation alive even if new tables swap in meanwhile
+        let _tables = self.tables.lease();
+
+        // Simulate processing by applying transformation to encrypted data
+        let processed_data = ciphertext.data.clone();
+
+        // Add processing header to indicate transformation
+        let header = b"PROCESSED:";
+        let mut result_data = header.to_vec();
+        result_data.extend_from_slice(&processed_data);
+        record_hot_path("process", result_data.capacity());
+
+        Ok(Ciphertext {
+            id: Uuid::new_v4(),
+            data: result_data,
+            params: ciphertext.params.clone(),
+            noise_budget: ciphertext.noise_budget.map(|b| b.saturating_sub(5)),
+        })
+    }
+
+    /// Validate ciphertext integrity
+    pub fn validate_ciphertext(&self, ciphertext: &Ciphertext) -> Result<bool> {
+        // Check noise budget
+        if let Some(budget) = ciphertext.noise_budget {
+            if budget < 10 {
+                log::warn!("Low noise budget: {} bits", budget);
+                return Ok(false);
+            }
+        }
+
+        // Check parameter consistency
+        let differences = ciphertext.params.differences(&self.params);
+        if !differences.is_empty() {
+            return Err(Error::ParamMismatch(format!(
+                "ciphertext differs in {}",
+                differences.join(", ")
+            )));
+        }
+
+        Ok(true)
+    }
+
+    /// Get engine statistics
+    pub fn get_stats(&self) -> FheStats {
+        FheStats {
+            total_client_keys: self.client_keys.len(),
+            total_server_keys: self.server_keys.len(),
+            params: self.params.clone(),
+        }
+    }
+
+    /// Get encryption parameters
+    pub fn get_params(&self) -> &FheParams {
+        &self.params
+    }
+
+    /// Validate engine state for health checks
+    pub fn validate_state(&self) -> Result<()> {
+        if self.client_keys.is_empty() && self.server_keys.is_empty() {
+            return Err(Error::Configuration("No keys generated".to_string()));
+        }
+
+        // Check if parameters are valid
+        if self.params.poly_modulus_degree == 0 {
+            return Err(Error::Configuration("Invalid FHE parameters".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Estimate computation cost for operation
+    pub fn estimate_cost(&self, operation: &str, input_size: usize) -> Result<u64> {
+        let base_cost = match operation {
+            "encrypt" => input_size as u64 * 100,
+            "decrypt" => input_size as u64 * 80,
+            "add" => input_size as u64 * 50,
+            "multiply" => input_size as u64 * 200,
+            "concatenate" => input_size as u64 * 10,
+            _ => return Err(Error::Fhe(format!("Unknown operation: {}", operation))),
+        };
+
+        // Scale by security level
+        let security_multiplier = self.params.security_level as u64 / 64;
+        Ok(base_cost * security_multiplier)
+    }
+
+    /// Calculate noise budget for ciphertext
+    fn calculate_noise_budget(&self, data_size: usize) -> u64 {
+        let base_budget: u64 = 60; // Starting noise budget
+        let size_penalty = (data_size / 100) as u64; // Penalty for larger data
+        let security_bonus = (self.params.security_level as u64 / 32).saturating_sub(1);
+
+        base_budget
+            .saturating_sub(size_penalty)
+            .saturating_add(security_bonus)
+    }
+
+    /// Validate ciphertext format and metadata
+    pub fn validate_ciphertext_format(&self, ciphertext: &Ciphertext) -> Result<()> {
+        if ciphertext.data.len() < 8 {
+            return Err(Error::Fhe("Ciphertext data too short".to_string()));
+        }
+
+        // Extract metadata header
+        let metadata_len = u32::from_le_bytes([
+            ciphertext.data[0],
+            ciphertext.data[1],
+            ciphertext.data[2],
+            ciphertext.data[3],
+        ]) as usize;
+
+        if metadata_len > 100 || ciphertext.data.len() < 4 + metadata_len {
+            return Err(Error::Fhe("Invalid ciphertext metadata".to_string()));
+        }
+
+        let metadata = String::from_utf8_lossy(&ciphertext.data[4..4 + metadata_len]);
+
+        if !metadata.starts_with("FHE-v1|") {
+            return Err(Error::Fhe("Unsupported ciphertext version".to_string()));
+        }
+
+        log::debug!(
+            "Validated ciphertext {} with metadata: {}",
+            ciphertext.id,
+            metadata
+        );
+        Ok(())
+    }
+
+    /// Enhanced decrypt with validation and retry logic
+    pub fn decrypt_text_safe(&self, client_id: Uuid, ciphertext: &Ciphertext) -> Result<String> {
+        self.secret_key(client_id)?;
+
+        log::debug!(
+            "Decrypting ciphertext {} for client {}",
+            ciphertext.id,
+            client_id
+        );
+
+        // Validate noise budget before attempting decryption
+        if let Some(budget) = ciphertext.noise_budget {
+            if budget < 5 {
+                return Err(Error::Fhe(format!(
+                    "Insufficient noise budget for decryption: {} bits (minimum 5 required)",
+                    budget
+                )));
+            }
+        }
+
+        // Check if this is a processed ciphertext
+        let data = if ciphertext.data.starts_with(b"PROCESSED:") {
+            &ciphertext.data[10..] // Skip "PROCESSED:" prefix
+        } else {
+            &ciphertext.data
+        };
+
+        // Validate ciphertext format on clean data
+        let temp_ciphertext = Ciphertext {
+            id: ciphertext.id,
+            data: data.to_vec(),
+            params: ciphertext.params.clone(),
+            noise_budget: ciphertext.noise_budget,
+        };
+
+        self.validate_ciphertext_format(&temp_ciphertext)?;
+        self.validate_ciphertext(&temp_ciphertext)?;
+
+        // Extract metadata header
+        if data.len() < 4 {
+            return Err(Error::Fhe("Invalid ciphertext metadata".to_string()));
+        }
+
+        let metadata_len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+
+        if data.len() < 4 + metadata_len {
+            return Err(Error::Fhe("Invalid ciphertext metadata length".to_string()));
+        }
+
+        // Skip metadata and decrypt the actual data
+        let encrypted_bits = &data[4 + metadata_len..];
+        let mut text_bytes = Vec::new();
+
+        for chunk in encrypted_bits.chunks(8) {
+            if chunk.len() == 8 {
+                let mut byte = 0u8;
+                for (i, &bit_byte) in chunk.iter().enumerate() {
+                    if bit_byte != 0 {
+                        byte |= 1 << i;
+                    }
+                }
+                text_bytes.push(byte);
+            }
+        }
+
+        let result = String::from_utf8(text_bytes)
+            .map_err(|e| Error::Fhe(format!("UTF-8 decode error: {}", e)))?;
+
+        // Additional validation
+        if result.len() > 10_000 {
+            return Err(Error::Fhe("Decrypted text suspiciously long".to_string()));
+        }
+
+        Ok(result)
+    }
+
+    /// Perform key rotation for enhanced security
+    pub fn rotate_keys(&mut self, client_id: Uuid) -> Result<Uuid> {
+        if !self.client_keys.contains_key(&client_id) {
+            return Err(Error::Fhe("Client key not found".to_string()));
+        }
+
+        let new_server_id = Uuid::new_v4();
+        let mut rng = rand::rng();
+        let server_key_data: Vec<u8> = (0..256).map(|_| rng.random()).collect();
+
+        self.server_keys.insert(
+            new_server_id,
+            ServerKey {
+                id: new_server_id,
+                key_data: server_key_data,
+                params: self.params.clone(),
+            },
+        );
+
+        log::info!(
+            "Rotated server key for client {}: new server key {}",
+            client_id,
+            new_server_id
+        );
+        Ok(new_server_id)
+    }
+
+    /// Raw client key material, for escrow
+    pub fn export_client_key(&self, client_id: Uuid) -> Result<Vec<u8>> {
+        self.secret_key(client_id).map(|key| key.key_data.clone())
+    }
+
+    /// Hand over a client key's secret material to its new holder (an HSM);
+    /// the engine keeps encrypting under the key but can no longer decrypt
+    pub fn take_secret_key(&mut self, client_id: Uuid) -> Result<Vec<u8>> {
+        self.secret_key(client_id)?;
+        let key = self
+            .client_keys
+            .get_mut(&client_id)
+            .ok_or_else(|| Error::Fhe("Client key not found".to_string()))?;
+        Ok(std::mem::take(&mut key.key_data))
+    }
+
+    /// Whether the engine holds the secret material of a client key
+    pub fn holds_secret_key(&self, client_id: Uuid) -> bool {
+        self.secret_key(client_id).is_ok()
+    }
+
+    fn secret_key(&self, client_id: Uuid) -> Result<&ClientKey> {
+        let key = self
+            .client_keys
+            .get(&client_id)
+            .ok_or_else(|| Error::Fhe("Client key not found".to_string()))?;
+        if key.key_data.is_empty() {
+            return Err(Error::Fhe(
+                "Client secret key is held outside the engine".to_string(),
+            ));
+        }
+        Ok(key)
+    }
+
+    /// Reinstate a client key recovered from escrow
+    pub fn restore_client_key(&mut self, client_id: Uuid, key_data: Vec<u8>) {
+        log::info!("Restoring client key {} from escrow", client_id);
+        self.client_keys.insert(
+            client_id,
+            ClientKey {
+                id: client_id,
+                key_data,
+                params: self.params.clone(),
+            },
+        );
+    }
+
+    /// Get encryption statistics
+    pub fn get_encryption_stats(&self) -> EncryptionStats {
+        EncryptionStats {
+            total_client_keys: self.client_keys.len(),
+            total_server_keys: self.server_keys.len(),
+            security_level: self.params.security_level,
+            poly_modulus_degree: self.params.poly_modulus_degree,
+            max_noise_budget: 60,
+        }
+    }
+
+    /// Batch key generation for improved efficiency
+    pub fn generate_key_batch(&mut self, count: usize) -> Result<Vec<(Uuid, Uuid)>> {
+        let mut key_pairs = Vec::with_capacity(count);
+
+        log::info!("Generating batch of {} FHE key pairs", count);
+
+        for i in 0..count {
+            let (client_id, server_id) = self.generate_keys()?;
+            key_pairs.push((client_id, server_id));
+
+            if (i + 1) % 10 == 0 {
+                log::debug!("Generated {}/{} key pairs", i + 1, count);
+            }
+        }
+
+        log::info!("Successfully generated {} key pairs", count);
+        Ok(key_pairs)
+    }
+
+    /// Cleanup expired keys to prevent memory leaks
+    pub fn cleanup_expired_keys(&mut self, max_age: std::time::Duration) -> Result<usize> {
+        let _now = std::time::Instant::now();
+        let removed_count = 0;
+
+        // Note: In a real implementation, you'd store creation timestamps with keys
+        // For simulation, we'll just log the cleanup operation
+        log::debug!(
+            "Performed key cleanup (would remove keys older than {:?})",
+            max_age
+        );
+
+        Ok(removed_count)
+    }
+
+    /// Validate and repair ciphertext if possible
+    pub fn repair_ciphertext(&self, ciphertext: &mut Ciphertext) -> Result<bool> {
+        // Check if noise budget is critically low
+        if let Some(budget) = ciphertext.noise_budget {
+            if budget < 10 {
+                log::warn!(
+                    "Ciphertext {} has critically low noise budget: {} bits",
+                    ciphertext.id,
+                    budget
+                );
+
+                // In a real FHE implementation, bootstrapping would be performed here
+                // For simulation, we'll just log the operation
+                log::info!("Performing bootstrapping on ciphertext {}", ciphertext.id);
+
+                // Simulate bootstrapping by resetting noise budget
+                ciphertext.noise_budget = Some(50);
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Generate compressed public key for bandwidth optimization
+    pub fn generate_compressed_public_key(&self, client_id: Uuid) -> Result<Vec<u8>> {
+        let _client_key = self
+            .client_keys
+            .get(&client_id)
+            .ok_or_else(|| Error::Fhe("Client key not found".to_string()))?;
+
+        // Simulate compressed public key generation
+        let mut rng = rand::rng();
+        let compressed_key: Vec<u8> = (0..64).map(|_| rng.random()).collect();
+
+        log::debug!(
+            "Generated compressed public key for client {}: {} bytes",
+            client_id,
+            compressed_key.len()
+        );
+
+        Ok(compressed_key)
+    }
+
+    /// Split a ciphertext into ordered chunks of at most `chunk_size` plaintext bytes
+    ///
+    /// Each chunk is a standalone ciphertext, so clients can decrypt only the
+    /// prefix of a long response they actually need.
+    pub fn split_into_chunks(
+        &self,
+        ciphertext: &Ciphertext,
+        chunk_size: usize,
+    ) -> Result<Vec<Ciphertext>> {
+        if chunk_size == 0 {
+            return Err(Error::Validation(
+                "Chunk size must be greater than 0".to_string(),
+            ));
+        }
+
+        // Processed ciphertexts carry the original layout behind their header
+        let encrypted_bits = encrypted_payload(&ciphertext.data)?;
+        let ranges = char_aligned_ranges(encrypted_bits, chunk_size);
+        let total_chunks = ranges.len();
+        let timestamp = chrono::Utc::now().timestamp();
+
+        let chunks = ranges
+            .into_iter()
+            .map(|range| &encrypted_bits[range])
+            .enumerate()
+            .map(|(index, bits)| {
+                let metadata = format!(
+                    "FHE-v1|{}|chunk={}/{}|parent={}",
+                    timestamp, index, total_chunks, ciphertext.id
+                );
+                let mut chunk_data = Vec::with_capacity(4 + metadata.len() + bits.len());
+                chunk_data.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+                chunk_data.extend_from_slice(metadata.as_bytes());
+                chunk_data.extend_from_slice(bits);
+                record_hot_path("split_chunks", chunk_data.capacity());
+
+                Ciphertext {
+                    id: Uuid::new_v4(),
+                    data: chunk_data,
+                    params: ciphertext.params.clone(),
+                    noise_budget: ciphertext.noise_budget,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        log::debug!(
+            "Split ciphertext {} into {} chunks of up to {} bytes",
+            ciphertext.id,
+            chunks.len(),
+            chunk_size
+        );
+
+        Ok(chunks)
+    }
+
+    /// Join chunks, processed or not, back into one standalone ciphertext in order
+    ///
+    /// The inverse of [`split_into_chunks`](Self::split_into_chunks); the
+    /// result decrypts to the chunks' plaintexts concatenated.
+    pub fn merge_chunks(&self, parent: Uuid, chunks: &[Ciphertext]) -> Result<Ciphertext> {
+        let first = chunks
+            .first()
+            .ok_or_else(|| Error::Validation("No chunks to merge".to_string()))?;
+        if let Some(chunk) = chunks.iter().find(|c| c.params != first.params) {
+            return Err(Error::ParamMismatch(format!(
+                "Chunk {} was encrypted under different parameters",
+                chunk.id
+            )));
+        }
+
+        let metadata = format!(
+            "FHE-v1|{}|merged={}|parent={}",
+            chrono::Utc::now().timestamp(),
+            chunks.len(),
+            parent
+        );
+        let mut data = Vec::with_capacity(
+            4 + metadata.len() + chunks.iter().map(|c| c.data.len()).sum::<usize>(),
+        );
+        data.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        data.extend_from_slice(metadata.as_bytes());
+        for chunk in chunks {
+            data.extend_from_slice(encrypted_payload(&chunk.data)?);
+        }
+        record_hot_path("merge_chunks", data.capacity());
+
+        Ok(Ciphertext {
+            id: Uuid::new_v4(),
+            data,
+            params: first.params.clone(),
+            noise_budget: chunks.iter().filter_map(|c| c.noise_budget).min(),
+        })
+    }
+
+    /// Encrypt a real number for homomorphic arithmetic
+    pub fn encrypt_value(&self, client_id: Uuid, value: f64) -> Result<Ciphertext> {
+        if !self.client_keys.contains_key(&client_id) {
+            return Err(Error::Fhe("Client key not found".to_string()));
+        }
+        if !value.is_finite() {
+            return Err(Error::Validation(
+                "Only finite numbers can be encrypted".to_string(),
+            ));
+        }
+        Ok(self.encode_value(value, Some(self.calculate_noise_budget(8))))
+    }
+
+    /// Decrypt a ciphertext produced by [`encrypt_value`](Self::encrypt_value)
+    /// or the arithmetic below
+    pub fn decrypt_value(&self, client_id: Uuid, ciphertext: &Ciphertext) -> Result<f64> {
+        self.decrypt_text(client_id, ciphertext)?
+            .parse()
+            .map_err(|_| Error::Fhe("Ciphertext does not hold an encrypted number".to_string()))
+    }
+
+    /// Homomorphic sum of encrypted numbers
+    ///
+    /// Items are added pairwise, so the noise budget drops with the depth of
+    /// the addition tree, log2 of the item count, rather than the count.
+    pub fn sum_encrypted(&self, items: &[Ciphertext]) -> Result<Ciphertext> {
+        let first = items
+            .first()
+            .ok_or_else(|| Error::Validation("No ciphertexts to sum".to_string()))?;
+        if let Some(item) = items.iter().find(|c| c.params != first.params) {
+            return Err(Error::ParamMismatch(format!(
+                "Ciphertext {} was encrypted under different parameters",
+                item.id
+            )));
+        }
+
+        let depth = items.len().next_power_of_two().trailing_zeros() as u64;
+        let noise_budget = if items.iter().all(|c| c.noise_budget.is_some()) {
+            let budget = items.iter().filter_map(|c| c.noise_budget).min();
+            Some(consume_noise_budget(budget, depth * ADD_NOISE_COST, "sum")?)
+        } else {
+            log::warn!("Missing noise budget information for encrypted sum");
+            None
+        };
+
+        let mut total = 0.0;
+        for item in items {
+            total += encrypted_value(item)?;
+        }
+        record_hot_path("sum_encrypted", items.len() * 64);
+        Ok(self.encode_value(total, noise_budget))
+    }
+
+    /// Multiply an encrypted number by a plaintext scalar, consuming one rescale
+    pub fn multiply_plain(&self, ciphertext: &Ciphertext, scalar: f64) -> Result<Ciphertext> {
+        if !scalar.is_finite() {
+            return Err(Error::Validation(
+                "Scalar must be a finite number".to_string(),
+            ));
+        }
+        let noise_budget = ciphertext
+            .noise_budget
+            .map(|budget| consume_noise_budget(Some(budget), PLAIN_MUL_NOISE_COST, "multiply"))
+            .transpose()?;
+        let value = encrypted_value(ciphertext)? * scalar;
+        Ok(self.encode_value(value, noise_budget))
+    }
+
+    fn encode_value(&self, value: f64, noise_budget: Option<u64>) -> Ciphertext {
+        let metadata = format!("FHE-v1|{}|{}", chrono::Utc::now().timestamp(), REAL_TAG);
+        let digits = value.to_string();
+        let mut data = Vec::with_capacity(4 + metadata.len() + digits.len() * 8);
+        data.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        data.extend_from_slice(metadata.as_bytes());
+        for byte in digits.bytes() {
+            data.extend((0..8).map(|i| (byte >> i) & 1));
+        }
+        Ciphertext {
+            id: Uuid::new_v4(),
+            data,
+            params: self.params.clone(),
+            noise_budget,
+        }
+    }
+}
+
+/// Where one prompt sits inside a packed ciphertext
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlotRange {
+    /// ID of the ciphertext the prompt was packed from
+    pub source: Uuid,
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// A shared ciphertext carrying several prompts, with the slot layout needed
+/// to demultiplex results
+#[derive(Debug, Clone)]
+pub struct PackedCiphertext {
+    pub ciphertext: Ciphertext,
+    pub slots: Vec<SlotRange>,
+}
+
+/// Packs short prompts into shared ciphertexts so one homomorphic pass serves
+/// several of them. Each encrypted bit occupies one CKKS slot.
+#[derive(Debug, Clone)]
+pub struct PackingOptimizer {
+    params: FheParams,
+}
+
+impl PackingOptimizer {
+    pub fn new(params: FheParams) -> Self {
+        Self { params }
+    }
+
+    /// CKKS packs N/2 values per ciphertext
+    pub fn slot_count(&self) -> usize {
+        self.params.poly_modulus_degree / 2
+    }
+
+    /// Whether a ciphertext leaves enough slots free to be worth packing
+    pub fn is_short(&self, ciphertext: &Ciphertext) -> bool {
+        encrypted_payload(&ciphertext.data)
+            .is_ok_and(|payload| payload.len() <= self.slot_count() / 2)
+    }
+
+    /// Group ciphertexts into shared ones, first-fit by decreasing size.
+    /// Ciphertexts that fill a whole ciphertext on their own get a pack of one.
+    pub fn pack(&self, ciphertexts: &[Ciphertext]) -> Result<Vec<PackedCiphertext>> {
+        let capacity = self.slot_count();
+        let mut payloads = ciphertexts
+            .iter()
+            .map(|ct| {
+                if ct.params != self.params {
+                    return Err(Error::ParamMismatch(format!(
+                        "ciphertext {} cannot be packed",
+                        ct.id
+                    )));
+                }
+                Ok((ct, encrypted_payload(&ct.data)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        payloads.sort_by_key(|(_, payload)| std::cmp::Reverse(payload.len()));
+
+        let mut bins: Vec<Vec<(&Ciphertext, &[u8])>> = Vec::new();
+        for (ct, payload) in payloads {
+            let bin = bins.iter_mut().find(|bin| {
+                bin.iter().map(|(_, p)| p.len()).sum::<usize>() + payload.len() <= capacity
+            });
+            match bin {
+                Some(bin) => bin.push((ct, payload)),
+                None => bins.push(vec![(ct, payload)]),
+            }
+        }
+
+        let timestamp = chrono::Utc::now().timestamp();
+        Ok(bins
+            .into_iter()
+            .map(|bin| {
+                let metadata = format!("FHE-v1|{}|packed={}", timestamp, bin.len());
+                let mut data = Vec::new();
+                data.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+                data.extend_from_slice(metadata.as_bytes());
+
+                let mut slots = Vec::with_capacity(bin.len());
+                let mut offset = 0;
+                for (ct, payload) in &bin {
+                    slots.push(SlotRange {
+                        source: ct.id,
+                        offset,
+                        len: payload.len(),
+                    });
+                    data.extend_from_slice(payload);
+                    offset += payload.len();
+                }
+                record_hot_path("pack", data.capacity());
+
+                PackedCiphertext {
+                    ciphertext: Ciphertext {
+                        id: Uuid::new_v4(),
+                        data,
+                        params: self.params.clone(),
+                        noise_budget: bin.iter().filter_map(|(ct, _)| ct.noise_budget).min(),
+                    },
+                    slots,
+                }
+            })
+            .collect())
+    }
+
+    /// Split a processed packed ciphertext back into one processed
+    /// ciphertext per prompt, in slot order
+    pub fn unpack(&self, processed: &Ciphertext, slots: &[SlotRange]) -> Result<Vec<Ciphertext>> {
+        let payload = encrypted_payload(&processed.data)?;
+        let timestamp = chrono::Utc::now().timestamp();
+
+        slots
+            .iter()
+            .map(|slot| {
+                let bits = payload
+                    .get(slot.offset..slot.offset + slot.len)
+                    .ok_or_else(|| {
+                        Error::Fhe("Slot range outside packed ciphertext".to_string())
+                    })?;
+                let metadata = format!("FHE-v1|{}|unpacked={}", timestamp, slot.source);
+                let mut data = b"PROCESSED:".to_vec();
+                data.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+                data.extend_from_slice(metadata.as_bytes());
+                data.extend_from_slice(bits);
+
+                Ok(Ciphertext {
+                    id: Uuid::new_v4(),
+                    data,
+                    params: processed.params.clone(),
+                    noise_budget: processed.noise_budget,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Noise budget a ciphertext needs left to still decrypt correctly
+const MIN_NOISE_BUDGET: u64 = 10;
+/// Budget consumed by one level of homomorphic additions
+const ADD_NOISE_COST: u64 = 1;
+/// Budget consumed by a plaintext multiplication and the rescale after it
+const PLAIN_MUL_NOISE_COST: u64 = 10;
+/// Metadata marker of ciphertexts holding a real number
+const REAL_TAG: &str = "real";
+
+fn consume_noise_budget(budget: Option<u64>, cost: u64, operation: &str) -> Result<u64> {
+    match budget {
+        Some(budget) if budget >= MIN_NOISE_BUDGET + cost => Ok(budget - cost),
+        _ => Err(Error::Fhe(format!(
+            "Insufficient noise budget for encrypted {}",
+            operation
+        ))),
+    }
+}
+
+/// Slot value of an encrypted number. Stands in for arithmetic on the
+/// encrypted coefficients, which the simulated scheme does not model.
+fn encrypted_value(ciphertext: &Ciphertext) -> Result<f64> {
+    let data = ciphertext
+        .data
+        .strip_prefix(b"PROCESSED:".as_slice())
+        .unwrap_or(&ciphertext.data);
+    let not_a_number = || {
+        Error::Fhe(format!(
+            "Ciphertext {} does not hold an encrypted number",
+            ciphertext.id
+        ))
+    };
+    let metadata_len = data
+        .get(..4)
+        .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .ok_or_else(not_a_number)?;
+    let metadata = data.get(4..4 + metadata_len).ok_or_else(not_a_number)?;
+    if !metadata.ends_with(format!("|{}", REAL_TAG).as_bytes()) {
+        return Err(not_a_number());
+    }
+    let digits: Vec<u8> = data[4 + metadata_len..]
+        .chunks_exact(8)
+        .map(|bits| (0..8).fold(0u8, |byte, i| byte | ((bits[i] & 1) << i)))
+        .collect();
+    std::str::from_utf8(&digits)
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(not_a_number)
+}
+
+/// Encrypted bits behind the metadata header, skipping any processing prefix
+fn encrypted_payload(data: &[u8]) -> Result<&[u8]> {
+    let data = data.strip_prefix(b"PROCESSED:".as_slice()).unwrap_or(data);
+    if data.len() < 4 {
+        return Err(Error::Fhe("Invalid ciphertext format".to_string()));
+    }
+
+    let metadata_len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if data.len() < 4 + metadata_len {
+        return Err(Error::Fhe("Corrupted ciphertext metadata".to_string()));
+    }
+    Ok(&data[4 + metadata_len..])
+}
+
+/// Split encrypted bits into ranges of at most `chunk_size` plaintext bytes
+/// that never cut a UTF-8 character in half
+///
+/// A character wider than `chunk_size` gets a range of its own.
+fn char_aligned_ranges(bits: &[u8], chunk_size: usize) -> Vec<std::ops::Range<usize>> {
+    // Bits are stored least significant first, so a continuation byte
+    // (0b10xxxxxx) has bit 7 set and bit 6 clear
+    let is_continuation = |byte: usize| {
+        let start = byte * 8;
+        bits.len() >= start + 8 && bits[start + 7] != 0 && bits[start + 6] == 0
+    };
+
+    let total_bytes = bits.len().div_ceil(8);
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < total_bytes {
+        let mut end = (start + chunk_size).min(total_bytes);
+        while end < total_bytes && end > start && is_continuation(end) {
+            end -= 1;
+        }
+        if end == start {
+            end = start + 1;
+            while end < total_bytes && is_continuation(end) {
+                end += 1;
+            }
+        }
+        ranges.push(start * 8..(end * 8).min(bits.len()));
+        start = end;
+    }
+    ranges
+}
+
+/// Encryption statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionStats {
+    pub total_client_keys: usize,
+    pub total_server_keys: usize,
+    pub security_level: u8,
+    pub poly_modulus_degree: usize,
+    pub max_noise_budget: u64,
+}
+
+impl Default for FheEngine {
+    fn default() -> Self {
+        Self::new(FheParams::default()).expect("Failed to create FHE engine")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fhe_params_default() {
+        let params = FheParams::default();
+        assert_eq!(params.poly_modulus_degree, 16384);
+        assert_eq!(params.security_level, 128);
+        assert!(!params.coeff_modulus_bits.is_empty());
+    }
+
+    #[test]
+    fn test_fhe_engine_creation() {
+        let params = FheParams::default();
+        let engine = FheEngine::new(params);
+        assert!(engine.is_ok());
+    }
+
+    #[test]
+    fn test_key_generation() {
+        let params = FheParams::default();
+        let mut engine = FheEngine::new(params).expect("Failed to create engine");
+
+        let result = engine.generate_keys();
+        assert!(result.is_ok());
+
+        let (client_id, server_id) = result.unwrap();
+        assert_ne!(client_id, Uuid::nil());
+        assert_ne!(server_id, Uuid::nil());
+    }
+
+    #[test]
+    fn test_encryption_decryption() {
+        let params = FheParams::default();
+        let mut engine = FheEngine::new(params).expect("Failed to create engine");
+
+        let (client_id, _) = engine.generate_keys().expect("Failed to generate keys");
+
+        let plaintext = "Hello FHE!";
+        let ciphertext = engine
+            .encrypt_text(client_id, plaintext)
+            .expect("Failed to encrypt");
+        let decrypted = engine
+            .decrypt_text(client_id, &ciphertext)
+            .expect("Failed to decrypt");
+
+        assert_eq!(plaintext, decrypted);
+        assert!(!ciphertext.data.is_empty());
+        assert!(ciphertext.noise_budget.is_some());
+    }
+
+    #[test]
+    fn test_input_validation() {
+        let params = FheParams::default();
+        let mut engine = FheEngine::new(params).expect("Failed to create engine");
+
+        let (client_id, _) = engine.generate_keys().expect("Failed to generate keys");
+
+        // Test empty input
+        assert!(engine.encrypt_text(client_id, "").is_err());
+
+        // Test too long input
+        let long_text = "x".repeat(15000);
+        assert!(engine.encrypt_text(client_id, &long_text).is_err());
+
+        // Test malicious input
+        let malicious = "text<script>alert('xss')</script>";
+        assert!(engine.encrypt_text(client_id, malicious).is_err());
+    }
+
+    #[test]
+    fn test_split_into_chunks() {
+        let params = FheParams::default();
+        let mut engine = FheEngine::new(params).expect("Failed to create engine");
+
+        let (client_id, _) = engine.generate_keys().expect("Failed to generate keys");
+
+        let ciphertext = engine
+            .encrypt_text(client_id, "Hello chunked FHE!")
+            .expect("Failed to encrypt");
+        let processed = engine
+            .process_encrypted_prompt(&ciphertext)
+            .expect("Failed to process");
+
+        let chunks = engine
+            .split_into_chunks(&processed, 5)
+            .expect("Failed to split");
+        assert_eq!(chunks.len(), 4);
+
+        let decrypted = chunks
+            .iter()
+            .map(|chunk| engine.decrypt_text(client_id, chunk).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decrypted[0], "Hello");
+        assert_eq!(decrypted.concat(), "Hello chunked FHE!");
+
+        assert!(engine.split_into_chunks(&processed, 0).is_err());
+
+        // Multi-byte characters stay whole, even when wider than a chunk
+        let text = "héllo wörld €";
+        let mut data = Vec::new();
+        data.extend_from_slice(&6u32.to_le_bytes());
+        data.extend_from_slice(b"FHE-v1");
+        for byte in text.bytes() {
+            data.extend((0..8).map(|i| (byte >> i) & 1));
+        }
+        let wide = Ciphertext {
+            id: Uuid::new_v4(),
+            data,
+            params: engine.params.clone(),
+            noise_budget: None,
+        };
+        for chunk_size in 1..=4 {
+            let decrypted = engine
+                .split_into_chunks(&wide, chunk_size)
+                .expect("Failed to split")
+                .iter()
+                .map(|chunk| engine.decrypt_text(client_id, chunk).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(decrypted.concat(), text);
+        }
+
+        // Processed chunks merge back into one decryptable ciphertext
+        let processed_chunks = chunks
+            .iter()
+            .map(|chunk| engine.process_encrypted_prompt(chunk).unwrap())
+            .collect::<Vec<_>>();
+        let merged = engine
+            .merge_chunks(processed.id, &processed_chunks)
+            .expect("Failed to merge");
+        assert_eq!(
+            engine.decrypt_text(client_id, &merged).unwrap(),
+            "Hello chunked FHE!"
+        );
+        assert!(engine.merge_chunks(processed.id, &[]).is_err());
+    }
+
+    #[test]
+    fn test_precomputed_tables() {
+        let params = FheParams {
+            poly_modulus_degree: 1024,
+            coeff_modulus_bits: vec![40, 40, 30],
+            ..FheParams::default()
+        };
+        let engine = FheEngine::new(params).expect("Failed to create engine");
+        assert!(!engine.is_warm());
+        engine.warm_up().expect("Failed to warm up");
+
+        let tables = engine.tables().unwrap();
+        assert_eq!(tables.moduli.len(), 3);
+        assert_ne!(tables.moduli[0], tables.moduli[1]);
+        for (&q, powers) in tables.moduli.iter().zip(&tables.root_powers) {
+            assert_eq!(q % 2048, 1);
+            assert!(is_prime(q));
+            // Bit-reversed order starts with psi^0 and psi^(N/2), a 4th root of unity
+            assert_eq!(powers[0], 1);
+            assert_eq!(pow_mod(powers[1], 4, q), 1);
+        }
+    }
+
+    #[test]
+    fn test_table_swap_waits_for_leases() {
+        let params = FheParams {
+            poly_modulus_degree: 256,
+            coeff_modulus_bits: vec![30, 30],
+            ..FheParams::default()
+        };
+        let engine = FheEngine::new(params.clone()).unwrap();
+        engine.warm_up().unwrap();
+        let in_flight = engine.tables().unwrap();
+        let size = in_flight.size_bytes();
+        assert_eq!(size, (2 + 2 * 256) * 8);
+
+        // The swap publishes at once; the leased generation is kept alongside
+        let event = engine
+            .install_tables(PrecomputedTables::compute(&params).unwrap())
+            .unwrap();
+        assert_eq!((event.generation, event.retiring_generations), (2, 1));
+        let stats = engine.table_stats();
+        assert_eq!((stats.active_bytes, stats.retiring_bytes), (size, size));
+
+        // Finishing the operation frees the old generation
+        drop(in_flight);
+        let stats = engine.table_stats();
+        assert_eq!(
+            (stats.retiring_bytes, stats.released, stats.swaps),
+            (0, 1, 2)
+        );
+        assert_eq!(stats.recent_swaps[0].generation, 2);
+    }
+
+    #[test]
+    fn test_param_negotiation() {
+        let server = FheParams::default();
+        let light = FheParams {
+            poly_modulus_degree: 8192,
+            coeff_modulus_bits: vec![60, 40, 60],
+            ..FheParams::default()
+        };
+        assert_eq!(server.fingerprint(), FheParams::default().fingerprint());
+        assert_ne!(server.fingerprint(), light.fingerprint());
+        assert_eq!(
+            light.differences(&server),
+            vec!["poly_modulus_degree", "coeff_modulus_bits"]
+        );
+
+        // Security level dominates the distance to a supported profile
+        let weaker = FheParams {
+            security_level: 80,
+            ..FheParams::default()
+        };
+        let requested = FheParams {
+            poly_modulus_degree: 4096,
+            ..FheParams::default()
+        };
+        let supported = [weaker, light.clone()];
+        assert_eq!(requested.closest(&supported), Some(&light));
+
+        // Ciphertexts carrying other parameters are rejected with a dedicated error
+        let engine = FheEngine::new(server).expect("Failed to create engine");
+        let ciphertext = Ciphertext {
+            id: Uuid::new_v4(),
+            data: vec![0; 16],
+            params: light,
+            noise_budget: Some(40),
+        };
+        assert!(matches!(
+            engine.validate_ciphertext(&ciphertext),
+            Err(Error::ParamMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_prompt_packing_round_trip() {
+        let params = FheParams {
+            poly_modulus_degree: 1024,
+            ..FheParams::default()
+        };
+        let mut engine = FheEngine::new(params.clone()).expect("Failed to create engine");
+        let (client_id, _) = engine.generate_keys().expect("Failed to generate keys");
+        let prompts = ["hi", "short prompt", "a slightly longer prompt"];
+        let ciphertexts: Vec<Ciphertext> = prompts
+            .iter()
+            .map(|p| engine.encrypt_text(client_id, p).unwrap())
+            .collect();
+
+        // 512 slots hold all three prompts (16 + 96 + 192 bits)
+        let optimizer = PackingOptimizer::new(params);
+        assert!(ciphertexts.iter().all(|ct| optimizer.is_short(ct)));
+        let packed = optimizer.pack(&ciphertexts).unwrap();
+        assert_eq!(packed.len(), 1);
+        assert_eq!(packed[0].slots.len(), 3);
+
+        let processed = engine
+            .process_encrypted_prompt(&packed[0].ciphertext)
+            .unwrap();
+        let results = optimizer.unpack(&processed, &packed[0].slots).unwrap();
+        for (slot, result) in packed[0].slots.iter().zip(&results) {
+            let index = ciphertexts
+                .iter()
+                .position(|ct| ct.id == slot.source)
+                .unwrap();
+            let plaintext = engine
+                .decrypt_text(client_id, &strip_processing_header(result))
+                .unwrap();
+            assert_eq!(plaintext, prompts[index]);
+        }
+
+        // A prompt larger than the free slots starts a new ciphertext
+        let long = engine.encrypt_text(client_id, &"x".repeat(50)).unwrap();
+        let packed = optimizer.pack(&[ciphertexts[2].clone(), long]).unwrap();
+        assert_eq!(packed.len(), 2);
+    }
+
+    /// Processed ciphertexts decrypt once the processing header is stripped
+    fn strip_processing_header(ciphertext: &Ciphertext) -> Ciphertext {
+        Ciphertext {
+            data: ciphertext.data[b"PROCESSED:".len()..].to_vec(),
+            ..ciphertext.clone()
+        }
+    }
+
+    #[test]
+    fn test_encrypted_sum_and_mean() {
+        let mut engine = FheEngine::new(FheParams::default()).expect("Failed to create engine");
+        let (client_id, _) = engine.generate_keys().expect("Failed to generate keys");
+        let scores = [0.25, 0.5, 1.0, -0.75, 2.0];
+        let items: Vec<Ciphertext> = scores
+            .iter()
+            .map(|score| engine.encrypt_value(client_id, *score).unwrap())
+            .collect();
+
+        // Batch outputs carry a processing prefix; arithmetic sees through it
+        let mut processed = items.clone();
+        processed[0] = engine.process_encrypted_prompt(&items[0]).unwrap();
+
+        let sum = engine.sum_encrypted(&processed).unwrap();
+        assert_eq!(engine.decrypt_value(client_id, &sum).unwrap(), 3.0);
+        // Five items take three levels of pairwise additions
+        let fresh_budget = processed
+            .iter()
+            .filter_map(|c| c.noise_budget)
+            .min()
+            .unwrap();
+        assert_eq!(sum.noise_budget, Some(fresh_budget - 3 * ADD_NOISE_COST));
+
+        let mean = engine.multiply_plain(&sum, 1.0 / 5.0).unwrap();
+        // CKKS arithmetic is approximate
+        assert!((engine.decrypt_value(client_id, &mean).unwrap() - 0.6).abs() < 1e-9);
+        assert_eq!(
+            mean.noise_budget,
+            Some(fresh_budget - 3 * ADD_NOISE_COST - PLAIN_MUL_NOISE_COST)
+        );
+
+        // Exhausted budgets and text ciphertexts are refused
+        let mut tired = items[1].clone();
+        tired.noise_budget = Some(MIN_NOISE_BUDGET);
+        assert!(engine.sum_encrypted(&[items[0].clone(), tired]).is_err());
+        let text = engine.encrypt_text(client_id, "0.5").unwrap();
+        assert!(engine.sum_encrypted(&[items[0].clone(), text]).is_err());
+    }
+
+    #[test]
+    fn test_engine_stats() {
+        let params = FheParams::default();
+        let mut engine = FheEngine::new(params).expect("Failed to create engine");
+
+        let initial_stats = engine.get_stats();
+        assert_eq!(initial_stats.total_client_keys, 0);
+        assert_eq!(initial_stats.total_server_keys, 0);
+
+        let _ = engine.generate_keys().expect("Failed to generate keys");
+
+        let stats = engine.get_stats();
+        assert_eq!(stats.total_client_keys, 1);
+        assert_eq!(stats.total_server_keys, 1);
+    }
+}
@@ -0,0 +1,29 @@
+//! Errors raised by the FHE engine and the shared wire types
+
+use thiserror::Error as ThisError;
+
+/// Result type for FHE engine operations
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    /// FHE operation errors
+    #[error("FHE error: {0}")]
+    Fhe(String),
+
+    /// Input the engine refuses to operate on
+    #[error("Validation error: {0}")]
+    Validation(String),
+
+    /// Engine parameters or tables that cannot be used
+    #[error("Configuration error: {0}")]
+    Configuration(String),
+
+    /// Ciphertext parameters do not match the engine's
+    #[error("FHE parameter mismatch: {0}")]
+    ParamMismatch(String),
+
+    /// Serialization errors
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
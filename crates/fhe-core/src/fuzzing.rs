@@ -0,0 +1,97 @@
+//! Structured `arbitrary` inputs for the FHE types
+//!
+//! Implemented here rather than in the server because of the orphan rule;
+//! the server's `fuzzing` module re-exports [`WireFormat`] alongside its own
+//! protocol types.
+
+use crate::{Ciphertext, FheParams};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use uuid::Uuid;
+
+/// Metadata prefix expected at the start of every ciphertext header
+const WIRE_VERSION: &str = "FHE-v1|";
+
+/// Raw ciphertext bytes in the engine's wire format:
+/// `[u32 LE metadata length][metadata][one byte per encrypted bit]`
+#[derive(Debug, Clone)]
+pub struct WireFormat(pub Vec<u8>);
+
+impl<'a> Arbitrary<'a> for WireFormat {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        if u.ratio(1, 8)? {
+            return Ok(Self(Vec::arbitrary(u)?));
+        }
+
+        let mut metadata = WIRE_VERSION.to_string();
+        metadata.push_str(&String::arbitrary(u)?);
+        // Occasionally lie about the length to exercise the truncation checks
+        let declared_len = if u.ratio(1, 8)? {
+            u32::arbitrary(u)?
+        } else {
+            metadata.len() as u32
+        };
+
+        let mut data = declared_len.to_le_bytes().to_vec();
+        data.extend_from_slice(metadata.as_bytes());
+        let bits: Vec<bool> = Vec::arbitrary(u)?;
+        data.extend(bits.into_iter().map(u8::from));
+        Ok(Self(data))
+    }
+}
+
+impl<'a> Arbitrary<'a> for FheParams {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        if u.ratio(1, 8)? {
+            return Ok(Self {
+                poly_modulus_degree: usize::arbitrary(u)?,
+                coeff_modulus_bits: Vec::arbitrary(u)?,
+                scale_bits: u64::arbitrary(u)?,
+                security_level: u8::arbitrary(u)?,
+            });
+        }
+
+        let levels = u.int_in_range(1..=8)?;
+        let coeff_modulus_bits = (0..levels)
+            .map(|_| u.int_in_range(20..=60))
+            .collect::<Result<Vec<u64>>>()?;
+        Ok(Self {
+            poly_modulus_degree: 1 << u.int_in_range(10..=16)?,
+            coeff_modulus_bits,
+            scale_bits: u.int_in_range(20..=60)?,
+            security_level: *u.choose(&[80, 128, 192])?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Ciphertext {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            id: Uuid::from_u128(u128::arbitrary(u)?),
+            data: WireFormat::arbitrary(u)?.0,
+            params: FheParams::arbitrary(u)?,
+            noise_budget: Option::arbitrary(u)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_structured_ciphertexts_use_wire_format() {
+        let seed: Vec<u8> = (0..4096u32).map(|i| (i * 31 % 251) as u8).collect();
+        let mut u = Unstructured::new(&seed);
+
+        let mut well_formed = 0;
+        for _ in 0..32 {
+            let ciphertext = Ciphertext::arbitrary(&mut u).unwrap();
+            if ciphertext.data.len() >= 4 + WIRE_VERSION.len()
+                && ciphertext.data[4..].starts_with(WIRE_VERSION.as_bytes())
+            {
+                well_formed += 1;
+            }
+        }
+        assert!(well_formed > 0);
+    }
+}
@@ -0,0 +1,204 @@
+//! FHE primitives shared by the proxy server and its clients
+//!
+//! Parameter sets, the ciphertext container and engine fingerprints: the
+//! types that cross the wire between a client and the proxy, including the
+//! metadata block of every completion response. The engine and its key
+//! handling live here too, so clients encrypt and decrypt without the
+//! server's HTTP stack, storage backends or GPU bindings.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub mod allocations;
+pub mod engine;
+pub mod error;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod response_metadata;
+
+pub use engine::FheEngine;
+pub use error::{Error, Result};
+pub use response_metadata::ResponseMetadata;
+
+/// FHE parameters for CKKS-like operations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FheParams {
+    pub poly_modulus_degree: usize,
+    pub coeff_modulus_bits: Vec<u64>,
+    pub scale_bits: u64,
+    pub security_level: u8,
+}
+
+impl Default for FheParams {
+    fn default() -> Self {
+        Self {
+            poly_modulus_degree: 16384,
+            coeff_modulus_bits: vec![60, 40, 40, 60],
+            scale_bits: 40,
+            security_level: 128,
+        }
+    }
+}
+
+impl FheParams {
+    /// Stable SHA-256 fingerprint of the parameter set. Clients echo it with
+    /// every ciphertext so a mismatch is caught before any operation runs.
+    pub fn fingerprint(&self) -> String {
+        let canonical = serde_json::to_vec(self).unwrap_or_default();
+        ring::digest::digest(&ring::digest::SHA256, &canonical)
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Field names that differ from `other`
+    pub fn differences(&self, other: &FheParams) -> Vec<&'static str> {
+        let mut differences = Vec::new();
+        if self.poly_modulus_degree != other.poly_modulus_degree {
+            differences.push("poly_modulus_degree");
+        }
+        if self.coeff_modulus_bits != other.coeff_modulus_bits {
+            differences.push("coeff_modulus_bits");
+        }
+        if self.scale_bits != other.scale_bits {
+            differences.push("scale_bits");
+        }
+        if self.security_level != other.security_level {
+            differences.push("security_level");
+        }
+        differences
+    }
+
+    /// The supported profile nearest to these parameters. Security level
+    /// weighs most, then ring dimension, then modulus chain and scale.
+    pub fn closest<'a>(&self, supported: &'a [FheParams]) -> Option<&'a FheParams> {
+        supported
+            .iter()
+            .min_by_key(|candidate| self.distance(candidate))
+    }
+
+    fn distance(&self, other: &FheParams) -> u64 {
+        let log2 = |degree: usize| degree.max(1).ilog2() as i64;
+        let total_bits = |params: &FheParams| params.coeff_modulus_bits.iter().sum::<u64>() as i64;
+
+        let security = (self.security_level as i64 - other.security_level as i64).unsigned_abs();
+        let degree =
+            (log2(self.poly_modulus_degree) - log2(other.poly_modulus_degree)).unsigned_abs();
+        let modulus = (total_bits(self) - total_bits(other)).unsigned_abs()
+            + (self.coeff_modulus_bits.len() as i64 - other.coeff_modulus_bits.len() as i64)
+                .unsigned_abs();
+        let scale = (self.scale_bits as i64 - other.scale_bits as i64).unsigned_abs();
+
+        security * 1_000_000 + degree * 10_000 + modulus + scale
+    }
+}
+
+/// Encrypted ciphertext container
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ciphertext {
+    pub id: Uuid,
+    pub data: Vec<u8>,
+    pub params: FheParams,
+    pub noise_budget: Option<u64>,
+}
+
+/// What an engine computes with. Engines from different builds, or with
+/// different parameters or NTT tables, mis-handle each other's ciphertexts
+/// without failing, so they must not serve the same pool.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EngineFingerprint {
+    pub build_hash: String,
+    pub params_digest: String,
+    /// `None` until the engine computes or installs its tables
+    pub tables_digest: Option<String>,
+}
+
+impl EngineFingerprint {
+    /// Whether two engines may serve the same pool. An engine without tables
+    /// yet will compute them from its parameters, which must already match.
+    pub fn is_compatible(&self, other: &EngineFingerprint) -> bool {
+        self.build_hash == other.build_hash
+            && self.params_digest == other.params_digest
+            && match (&self.tables_digest, &other.tables_digest) {
+                (Some(ours), Some(theirs)) => ours == theirs,
+                _ => true,
+            }
+    }
+}
+
+impl std::fmt::Display for EngineFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let short = |digest: &str| digest.chars().take(12).collect::<String>();
+        write!(
+            f,
+            "build {} params {} tables {}",
+            short(&self.build_hash),
+            short(&self.params_digest),
+            self.tables_digest
+                .as_deref()
+                .map_or("none".to_string(), short)
+        )
+    }
+}
+
+/// Statement signed by the proxy for each request processed in FHE mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttestationStatement {
+    pub request_digest: String,
+    pub mode: String,
+    pub parameter_profile: FheParams,
+    pub engine_build_hash: String,
+    pub key_id: String,
+    pub issued_at: i64,
+}
+
+/// Signed attestation; the signature covers the compact JSON encoding of `statement`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub statement: AttestationStatement,
+    pub algorithm: String,
+    pub signature: String, // Base64 encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_fingerprint_and_closest_profile() {
+        let params = FheParams::default();
+        assert_eq!(params.fingerprint(), FheParams::default().fingerprint());
+        assert_eq!(params.fingerprint().len(), 64);
+
+        let small = FheParams {
+            poly_modulus_degree: 8192,
+            coeff_modulus_bits: vec![60, 40, 60],
+            ..FheParams::default()
+        };
+        assert_eq!(
+            params.differences(&small),
+            vec!["poly_modulus_degree", "coeff_modulus_bits"]
+        );
+        assert_ne!(params.fingerprint(), small.fingerprint());
+
+        let weak = FheParams {
+            security_level: 80,
+            ..FheParams::default()
+        };
+        let supported = [weak.clone(), small.clone()];
+        assert_eq!(params.closest(&supported), Some(&small));
+        assert_eq!(params.closest(&[]), None);
+
+        let ciphertext = Ciphertext {
+            id: Uuid::new_v4(),
+            data: vec![1, 2, 3],
+            params: small,
+            noise_budget: Some(40),
+        };
+        let decoded: Ciphertext =
+            serde_json::from_str(&serde_json::to_string(&ciphertext).unwrap()).unwrap();
+        assert_eq!(decoded.data, ciphertext.data);
+        assert_eq!(decoded.params, ciphertext.params);
+    }
+}
@@ -0,0 +1,195 @@
+//! Versioned metadata attached to completion responses
+//!
+//! Every completion carries a `metadata` block with a fixed set of fields,
+//! described by [`ResponseMetadata`]. The proxy serializes these types and
+//! `proxy-client` deserializes them, so SDKs read exactly what was sent.
+//!
+//! Fields are only added to the schema in a new `version`; until then an
+//! experimental field goes under `extensions` as `<namespace>.<field>`, for a
+//! namespace registered in the proxy's `response_metadata.extension_namespaces`. The
+//! proxy validates the block before sending it, so a response never carries a
+//! field outside the schema or the registered namespaces. Clients should
+//! ignore fields they don't know, which lets a newer proxy add them.
+
+use crate::error::{Error, Result};
+use crate::{Attestation, EngineFingerprint};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Schema version this build produces
+pub const RESPONSE_METADATA_VERSION: u32 = 1;
+
+/// Hex digits of each fingerprint part kept in an engine id
+const ENGINE_ID_DIGITS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    /// The response cache was not consulted
+    Bypass,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessingTimes {
+    /// From receiving the request to building the response
+    pub total_ms: u64,
+    /// Spent on homomorphic processing; absent for cache hits
+    pub fhe_ms: Option<u64>,
+}
+
+/// What the request consumed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResponseCost {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Ciphertext bytes received and returned
+    pub ciphertext_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ResponseMetadata {
+    pub version: u32,
+    pub processing: ProcessingTimes,
+    pub cache: CacheStatus,
+    /// Build and parameter identity of the engine that served the request
+    pub engine_id: String,
+    pub noise_budget: Option<u64>,
+    pub attestation: Option<Attestation>,
+    pub cost: Option<ResponseCost>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extensions: BTreeMap<String, serde_json::Value>,
+}
+
+impl ResponseMetadata {
+    pub fn new(cache: CacheStatus, engine: &EngineFingerprint) -> Self {
+        Self {
+            version: RESPONSE_METADATA_VERSION,
+            processing: ProcessingTimes::default(),
+            cache,
+            engine_id: engine_id(engine),
+            noise_budget: None,
+            attestation: None,
+            cost: None,
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    /// Read the `metadata` block of a completion response
+    pub fn from_response(response: &serde_json::Value) -> Result<Self> {
+        let metadata = response
+            .get("metadata")
+            .ok_or_else(|| Error::Validation("Response has no metadata".to_string()))?;
+        Ok(serde_json::from_value(metadata.clone())?)
+    }
+
+    /// Set an extension field if its namespace is registered; returns whether
+    /// it was set
+    pub fn extend(
+        &mut self,
+        namespaces: &[String],
+        key: &str,
+        value: impl Into<serde_json::Value>,
+    ) -> bool {
+        if extension_namespace(key).is_some_and(|ns| namespaces.iter().any(|n| n == ns)) {
+            self.extensions.insert(key.to_string(), value.into());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Check the block against the schema and the registered namespaces
+    pub fn validate(&self, namespaces: &[String]) -> Result<()> {
+        if self.version != RESPONSE_METADATA_VERSION {
+            return Err(Error::Validation(format!(
+                "Response metadata version {} is not {}",
+                self.version, RESPONSE_METADATA_VERSION
+            )));
+        }
+        if self.engine_id.is_empty() {
+            return Err(Error::Validation(
+                "Response metadata has no engine id".to_string(),
+            ));
+        }
+        if let Some(fhe_ms) = self.processing.fhe_ms {
+            if fhe_ms > self.processing.total_ms {
+                return Err(Error::Validation(format!(
+                    "FHE time {}ms exceeds total time {}ms",
+                    fhe_ms, self.processing.total_ms
+                )));
+            }
+        }
+        for key in self.extensions.keys() {
+            match extension_namespace(key) {
+                Some(namespace) if namespaces.iter().any(|n| n == namespace) => {}
+                _ => {
+                    return Err(Error::Validation(format!(
+                        "Extension {:?} is not in a registered namespace",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Namespace of a `<namespace>.<field>` extension key
+fn extension_namespace(key: &str) -> Option<&str> {
+    key.split_once('.')
+        .filter(|(namespace, field)| !namespace.is_empty() && !field.is_empty())
+        .map(|(namespace, _)| namespace)
+}
+
+fn engine_id(engine: &EngineFingerprint) -> String {
+    let short = |digest: &str| digest.chars().take(ENGINE_ID_DIGITS).collect::<String>();
+    format!(
+        "{}-{}",
+        short(&engine.build_hash),
+        short(&engine.params_digest)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extensions_need_a_registered_namespace() {
+        let engine = EngineFingerprint {
+            build_hash: "ab".repeat(32),
+            params_digest: "cd".repeat(32),
+            tables_digest: None,
+        };
+        let namespaces = vec!["experimental".to_string()];
+        let mut metadata = ResponseMetadata::new(CacheStatus::Miss, &engine);
+        assert_eq!(metadata.engine_id, "abababababab-cdcdcdcdcdcd");
+
+        assert!(metadata.extend(&namespaces, "experimental.packed", true));
+        assert!(!metadata.extend(&namespaces, "vendor.packed", true));
+        assert!(!metadata.extend(&namespaces, "experimental", true));
+        assert!(metadata.validate(&namespaces).is_ok());
+
+        // A round trip through JSON is lossless, and unknown fields are ignored
+        let mut response = serde_json::json!({ "metadata": metadata });
+        response["metadata"]["added_in_v2"] = serde_json::json!(1);
+        assert_eq!(
+            ResponseMetadata::from_response(&response).unwrap(),
+            metadata
+        );
+
+        metadata
+            .extensions
+            .insert("vendor.packed".to_string(), serde_json::json!(true));
+        assert!(metadata.validate(&namespaces).is_err());
+        metadata.extensions.clear();
+        metadata.processing = ProcessingTimes {
+            total_ms: 1,
+            fhe_ms: Some(2),
+        };
+        assert!(metadata.validate(&namespaces).is_err());
+    }
+}
@@ -0,0 +1,450 @@
+//! Typed async client for the homomorphic LLM proxy
+//!
+//! Covers the calls an application makes against a running proxy: fetching
+//! its parameters, generating keys, encrypting and decrypting text and
+//! submitting encrypted completions. Depends only on `fhe-core` and an HTTP
+//! client, so applications do not pull in the server.
+//!
+//! Every request carries the parameter fingerprint the client was configured
+//! with where the proxy checks it, and responses encrypted under different
+//! parameters are refused rather than handed back. The checks the proxy leaves
+//! to clients (prompt linting, response signatures and redaction policies)
+//! live in [`lint`], [`signing`] and [`redaction`].
+
+use fhe_core::FheParams;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pub mod lint;
+pub mod redaction;
+pub mod signing;
+
+pub use fhe_core::response_metadata::{
+    CacheStatus, ProcessingTimes, ResponseCost, ResponseMetadata, RESPONSE_METADATA_VERSION,
+};
+pub use fhe_core::Ciphertext;
+
+/// Result type for client calls
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors a client call can fail with
+#[derive(Debug, ThisError)]
+pub enum Error {
+    /// Configuration errors
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    /// Transport errors, including timeouts and undecodable bodies
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The proxy answered with a non-success status
+    #[error("Proxy returned HTTP {status} for {path}")]
+    Status { status: u16, path: String },
+
+    /// The proxy works with other parameters than the client was built for
+    #[error("FHE parameter mismatch: {}", .0.join(", "))]
+    ParamsMismatch(Vec<&'static str>),
+
+    /// A signature or signed bundle failed verification
+    #[error("Security error: {0}")]
+    Security(String),
+
+    /// Input the client refuses to use, such as an invalid redaction pattern
+    #[error("Validation error: {0}")]
+    Validation(String),
+
+    /// Serialization errors
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// How an application reaches a running proxy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ClientConfig {
+    /// Base URL of the proxy, e.g. `https://fhe-proxy.internal:8080`
+    pub endpoint: String,
+    /// Sent as `x-api-key`
+    pub api_key: Option<String>,
+    /// Sent as `x-tenant-id`
+    pub tenant: Option<String>,
+    /// Parameters the client encrypts with; must match the proxy's
+    pub params: FheParams,
+    pub timeout: Duration,
+}
+
+impl ClientConfig {
+    pub fn builder(endpoint: impl Into<String>) -> ClientConfigBuilder {
+        ClientConfigBuilder {
+            config: ClientConfig {
+                endpoint: endpoint.into(),
+                api_key: None,
+                tenant: None,
+                params: FheParams::default(),
+                timeout: Duration::from_secs(30),
+            },
+        }
+    }
+
+    /// Full URL of an API path such as `/v1/encrypt`
+    pub fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.endpoint.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// Headers every request to the proxy carries
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if let Some(key) = &self.api_key {
+            headers.push(("x-api-key", key.clone()));
+        }
+        if let Some(tenant) = &self.tenant {
+            headers.push(("x-tenant-id", tenant.clone()));
+        }
+        headers
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientConfigBuilder {
+    config: ClientConfig,
+}
+
+impl ClientConfigBuilder {
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.config.api_key = Some(key.into());
+        self
+    }
+
+    pub fn tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.config.tenant = Some(tenant.into());
+        self
+    }
+
+    pub fn params(mut self, params: FheParams) -> Self {
+        self.config.params = params;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> Result<ClientConfig> {
+        let endpoint = &self.config.endpoint;
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            return Err(Error::Config(format!(
+                "Proxy endpoint must be an http(s) URL: {:?}",
+                endpoint
+            )));
+        }
+        if self.config.timeout.is_zero() {
+            return Err(Error::Config(
+                "Client timeout must be greater than 0".to_string(),
+            ));
+        }
+        Ok(self.config)
+    }
+}
+
+/// Keys generated for a new session
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyPair {
+    pub session_id: Uuid,
+    pub client_id: Uuid,
+    pub server_id: Uuid,
+    pub params: FheParams,
+}
+
+/// Text encrypted by the proxy under a client key
+#[derive(Debug, Clone, Deserialize)]
+pub struct EncryptedText {
+    pub ciphertext_id: Uuid,
+    /// Base64 encoded
+    pub encrypted_data: String,
+    pub params: FheParams,
+    pub noise_budget: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DecryptedText {
+    pub ciphertext_id: Uuid,
+    pub plaintext: String,
+}
+
+/// An encrypted prompt for `/v1/chat/completions`
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionRequest {
+    pub ciphertext_id: Uuid,
+    /// Base64 encoded
+    pub encrypted_data: String,
+    pub provider: String,
+    pub model: String,
+    /// Filled in from the client's parameters when left empty
+    pub params_hash: Option<String>,
+}
+
+impl CompletionRequest {
+    pub fn new(encrypted: &EncryptedText, provider: &str, model: &str) -> Self {
+        Self {
+            ciphertext_id: encrypted.ciphertext_id,
+            encrypted_data: encrypted.encrypted_data.clone(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            params_hash: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Completion {
+    /// `fhe-` followed by a UUID
+    pub id: String,
+    pub model: String,
+    pub fhe_metadata: CompletionMetadata,
+    /// Versioned block every completion carries: timings, cache status,
+    /// engine, cost and attestation
+    pub metadata: ResponseMetadata,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionMetadata {
+    /// Ciphertext holding the encrypted response, for `decrypt`
+    pub processed_ciphertext_id: Uuid,
+    pub noise_budget_remaining: Option<u64>,
+    pub encryption_params: FheParams,
+}
+
+/// Client for one proxy
+#[derive(Debug, Clone)]
+pub struct ProxyClient {
+    config: ClientConfig,
+    http: reqwest::Client,
+}
+
+impl ProxyClient {
+    pub fn new(config: ClientConfig) -> Result<Self> {
+        let http = reqwest::Client::builder().timeout(config.timeout).build()?;
+        Ok(Self { config, http })
+    }
+
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// The proxy's parameters
+    pub async fn params(&self) -> Result<FheParams> {
+        self.send(self.http.get(self.config.url("/v1/params")), "/v1/params")
+            .await
+    }
+
+    /// Fail early when the proxy works with other parameters than ours
+    pub async fn check_params(&self) -> Result<()> {
+        self.expect_params(&self.params().await?)
+    }
+
+    pub async fn generate_keys(&self) -> Result<KeyPair> {
+        let path = "/v1/keys/generate";
+        let keys: KeyPair = self
+            .send(self.http.post(self.config.url(path)), path)
+            .await?;
+        self.expect_params(&keys.params)?;
+        Ok(keys)
+    }
+
+    pub async fn encrypt(&self, client_id: Uuid, text: &str) -> Result<EncryptedText> {
+        let path = "/v1/encrypt";
+        let body = serde_json::json!({
+            "text": text,
+            "client_id": client_id,
+            "params": self.config.params,
+        });
+        let encrypted: EncryptedText = self
+            .send(self.http.post(self.config.url(path)).json(&body), path)
+            .await?;
+        self.expect_params(&encrypted.params)?;
+        Ok(encrypted)
+    }
+
+    pub async fn decrypt(&self, client_id: Uuid, ciphertext_id: Uuid) -> Result<DecryptedText> {
+        let path = "/v1/decrypt";
+        let body = serde_json::json!({
+            "ciphertext_id": ciphertext_id,
+            "client_id": client_id,
+        });
+        self.send(self.http.post(self.config.url(path)).json(&body), path)
+            .await
+    }
+
+    pub async fn complete(&self, mut request: CompletionRequest) -> Result<Completion> {
+        let path = "/v1/chat/completions";
+        request
+            .params_hash
+            .get_or_insert_with(|| self.config.params.fingerprint());
+        let completion: Completion = self
+            .send(self.http.post(self.config.url(path)).json(&request), path)
+            .await?;
+        self.expect_params(&completion.fhe_metadata.encryption_params)?;
+        Ok(completion)
+    }
+
+    fn expect_params(&self, params: &FheParams) -> Result<()> {
+        let differences = self.config.params.differences(params);
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ParamsMismatch(differences))
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        mut request: reqwest::RequestBuilder,
+        path: &str,
+    ) -> Result<T> {
+        for (name, value) in self.config.headers() {
+            request = request.header(name, value);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(Error::Status {
+                status: response.status().as_u16(),
+                path: path.to_string(),
+            });
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::{fixtures, MockProxy};
+
+    #[tokio::test]
+    async fn test_client_round_trip_against_mock_proxy() {
+        let params = fixtures::small_params();
+        let client_id = Uuid::new_v4();
+        let ciphertext_id = Uuid::new_v4();
+        let proxy = MockProxy::start()
+            .await
+            .respond("GET", "/v1/params", 200, serde_json::json!(params))
+            .respond(
+                "POST",
+                "/v1/encrypt",
+                200,
+                serde_json::json!({
+                    "ciphertext_id": ciphertext_id,
+                    "encrypted_data": "AAEC",
+                    "params": params,
+                    "noise_budget": 40
+                }),
+            )
+            .respond("POST", "/v1/decrypt", 403, serde_json::json!({}));
+
+        let config = ClientConfig::builder(proxy.url())
+            .api_key("sk-test")
+            .params(params.clone())
+            .build()
+            .unwrap();
+        let client = ProxyClient::new(config).unwrap();
+        client.check_params().await.unwrap();
+
+        let encrypted = client.encrypt(client_id, "hello").await.unwrap();
+        assert_eq!(encrypted.ciphertext_id, ciphertext_id);
+        let request = proxy.requests().pop().unwrap();
+        assert_eq!(request.path, "/v1/encrypt");
+        assert_eq!(request.header("x-api-key"), Some("sk-test"));
+        assert_eq!(request.body["client_id"], serde_json::json!(client_id));
+
+        assert!(matches!(
+            client.decrypt(client_id, ciphertext_id).await,
+            Err(Error::Status { status: 403, .. })
+        ));
+
+        // A proxy on other parameters is caught before anything is encrypted
+        let client = ProxyClient::new(ClientConfig::builder(proxy.url()).build().unwrap()).unwrap();
+        assert!(matches!(
+            client.check_params().await,
+            Err(Error::ParamsMismatch(differences)) if differences.contains(&"poly_modulus_degree")
+        ));
+        assert!(ClientConfig::builder("proxy.example").build().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_complete_round_trip_against_mock_proxy() {
+        let params = fixtures::small_params();
+        let processed_id = Uuid::new_v4();
+        let proxy = MockProxy::start().await.respond(
+            "POST",
+            "/v1/chat/completions",
+            200,
+            serde_json::json!({
+                "id": format!("fhe-{}", Uuid::new_v4()),
+                "object": "chat.completion",
+                "model": "gpt-4",
+                "fhe_metadata": {
+                    "processed_ciphertext_id": processed_id,
+                    "noise_budget_remaining": 30,
+                    "encryption_params": params
+                },
+                "metadata": {
+                    "version": RESPONSE_METADATA_VERSION,
+                    "processing": { "total_ms": 42, "fhe_ms": 12 },
+                    "cache": "miss",
+                    "engine_id": "abababababab-cdcdcdcdcdcd",
+                    "noise_budget": 30,
+                    "attestation": null,
+                    "cost": {
+                        "prompt_tokens": 8,
+                        "completion_tokens": 16,
+                        "ciphertext_bytes": 96
+                    },
+                    "added_in_v2": true
+                }
+            }),
+        );
+
+        let config = ClientConfig::builder(proxy.url())
+            .params(params.clone())
+            .build()
+            .unwrap();
+        let client = ProxyClient::new(config).unwrap();
+        let encrypted = EncryptedText {
+            ciphertext_id: Uuid::new_v4(),
+            encrypted_data: "AAEC".to_string(),
+            params: params.clone(),
+            noise_budget: Some(40),
+        };
+        let completion = client
+            .complete(CompletionRequest::new(&encrypted, "openai", "gpt-4"))
+            .await
+            .unwrap();
+        assert!(completion.id.starts_with("fhe-"));
+        assert_eq!(
+            completion.fhe_metadata.processed_ciphertext_id,
+            processed_id
+        );
+        // Fields a newer proxy adds are ignored
+        let metadata = &completion.metadata;
+        assert_eq!(metadata.cache, CacheStatus::Miss);
+        assert_eq!(metadata.processing.fhe_ms, Some(12));
+        assert_eq!(metadata.cost.as_ref().unwrap().completion_tokens, 16);
+        assert_eq!(metadata.engine_id, "abababababab-cdcdcdcdcdcd");
+
+        let request = proxy.requests().pop().unwrap();
+        assert_eq!(request.body["provider"], "openai");
+        assert_eq!(
+            request.body["params_hash"],
+            serde_json::json!(params.fingerprint())
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Client-side prompt linting
+//!
+//! The proxy only ever sees ciphertext, so structural checks on a prompt
+//! (length, unfilled placeholders, characters that corrupt or hide text) run
+//! on the client before encryption. [`lint_prompt`] produces a report bound to
+//! the ciphertext it describes and to the version of the policy it applied,
+//! which the client sends with the completion as `lint_report`.
+
+use ring::digest;
+use serde::{Deserialize, Serialize};
+
+/// Version of the report format
+pub const LINT_PROTOCOL: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintRule {
+    Empty,
+    Length,
+    ForbiddenPlaceholder,
+    ControlCharacter,
+    InvisibleCharacter,
+    /// U+FFFD, left behind when text was decoded with the wrong encoding
+    ReplacementCharacter,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LintFinding {
+    pub rule: LintRule,
+    /// Occurrences; 1 for whole-prompt rules
+    pub count: usize,
+}
+
+/// What a client found linting one prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LintReport {
+    pub protocol: u32,
+    /// [`LintPolicy::version`] of the policy applied
+    pub policy_version: String,
+    /// Hex SHA-256 of the `encrypted_data` sent with the report
+    pub ciphertext_sha256: String,
+    pub chars: usize,
+    pub findings: Vec<LintFinding>,
+    pub linted_at: i64,
+}
+
+impl LintReport {
+    pub fn passed(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// The checks a policy makes, as served to clients at `/v1/lint/policy`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LintPolicy {
+    pub protocol: u32,
+    pub version: String,
+    pub max_chars: usize,
+    pub forbidden_placeholders: Vec<String>,
+    pub reject_control_characters: bool,
+    pub reject_invisible_characters: bool,
+    pub max_report_age_seconds: u64,
+}
+
+impl LintPolicy {
+    pub fn new(
+        max_chars: usize,
+        forbidden_placeholders: Vec<String>,
+        reject_control_characters: bool,
+        reject_invisible_characters: bool,
+        max_report_age_seconds: u64,
+    ) -> Self {
+        // The version fingerprints the checks, so a client linting against
+        // an outdated policy is caught
+        let checks = serde_json::json!([
+            LINT_PROTOCOL,
+            max_chars,
+            forbidden_placeholders,
+            reject_control_characters,
+            reject_invisible_characters,
+        ]);
+        Self {
+            protocol: LINT_PROTOCOL,
+            version: hex(
+                &digest::digest(&digest::SHA256, checks.to_string().as_bytes()).as_ref()[..8],
+            ),
+            max_chars,
+            forbidden_placeholders,
+            reject_control_characters,
+            reject_invisible_characters,
+            max_report_age_seconds,
+        }
+    }
+}
+
+pub fn ciphertext_digest(encrypted_data: &str) -> String {
+    hex(digest::digest(&digest::SHA256, encrypted_data.as_bytes()).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}'
+    )
+}
+
+/// Lint `prompt` on the client/// Lint `prompt` on the client, before it is encrypted into `encrypted_data`
+pub fn lint_prompt(prompt: &str, policy: &LintPolicy, encrypted_data: &str) -> LintReport {
+    let chars = prompt.chars().count();
+    let mut findings = Vec::new();
+    let mut find = |rule, count| {
+        if count > 0 {
+            findings.push(LintFinding { rule, count });
+        }
+    };
+    find(LintRule::Empty, usize::from(prompt.trim().is_empty()));
+    find(LintRule::Length, usize::from(chars > policy.max_chars));
+    find(
+        LintRule::ForbiddenPlaceholder,
+        policy
+            .forbidden_placeholders
+            .iter()
+            .map(|placeholder| prompt.matches(placeholder.as_str()).count())
+            .sum(),
+    );
+    if policy.reject_control_characters {
+        find(
+            LintRule::ControlCharacter,
+            prompt
+                .chars()
+                .filter(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+                .count(),
+        );
+    }
+    if policy.reject_invisible_characters {
+        find(
+            LintRule::InvisibleCharacter,
+            prompt.chars().filter(|c| is_invisible(*c)).count(),
+        );
+    }
+    find(
+        LintRule::ReplacementCharacter,
+        prompt.matches('\u{FFFD}').count(),
+    );
+
+    LintReport {
+        protocol: LINT_PROTOCOL,
+        policy_version: policy.version.clone(),
+        ciphertext_sha256: ciphertext_digest(encrypted_data),
+        chars,
+        findings,
+        linted_at: chrono::Utc::now().timestamp(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lints_without_the_server() {
+        let policy = LintPolicy::new(20, vec!["{{".to_string()], true, true, 300);
+        let report = lint_prompt("Dear {{name}}, see\u{200B} the notes", &policy, "ct-1");
+        let rules: Vec<LintRule> = report.findings.iter().map(|f| f.rule).collect();
+        assert_eq!(
+            rules,
+            [
+                LintRule::Length,
+                LintRule::ForbiddenPlaceholder,
+                LintRule::InvisibleCharacter
+            ]
+        );
+        assert_eq!(report.ciphertext_sha256, ciphertext_digest("ct-1"));
+
+        // Any change to the checks changes the version clients lint against
+        let looser = LintPolicy::new(200, vec!["{{".to_string()], true, true, 300);
+        assert_ne!(looser.version, policy.version);
+        assert!(lint_prompt("Summarize the notes", &looser, "ct-2").passed());
+    }
+}
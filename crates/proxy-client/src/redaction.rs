@@ -0,0 +1,87 @@
+//! Redaction policies, applied by the client after decryption
+//!
+//! The proxy never sees response plaintext, so each tenant's policy travels to
+//! its clients as a bundle signed by the proxy. The client verifies the bundle
+//! against the published JWKS and applies it to the text it decrypts.
+
+use crate::signing::{verify_detached, JwkSet};
+use crate::{Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+fn default_replacement() -> String {
+    "[REDACTED]".to_string()
+}
+
+/// One rule of a policy: every match of `pattern` is replaced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedactionRule {
+    pub id: String,
+    /// Regular expression in `regex` crate syntax
+    pub pattern: String,
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+}
+
+/// Policy descriptor as supplied by the tenant
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RedactionPolicy {
+    pub rules: Vec<RedactionRule>,
+}
+
+/// A versioned policy; the signature covers its compact JSON encoding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyBundle {
+    pub tenant: String,
+    pub version: u64,
+    pub issued_at: i64,
+    pub policy: RedactionPolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPolicyBundle {
+    pub bundle: PolicyBundle,
+    pub key_id: String,
+    pub algorithm: String,
+    pub signature: String, // Base64 encoded
+}
+
+/// Client-side check that a bundle was signed by the proxy for `tenant`
+pub fn verify_policy_bundle(
+    signed: &SignedPolicyBundle,
+    jwks: &JwkSet,
+    tenant: &str,
+) -> Result<()> {
+    if signed.bundle.tenant != tenant {
+        return Err(Error::Security(
+            "Redaction policy bundle is for a different tenant".to_string(),
+        ));
+    }
+    verify_detached(
+        jwks,
+        &signed.key_id,
+        &signed.algorithm,
+        &serde_json::to_vec(&signed.bundle)?,
+        &signed.signature,
+    )
+}
+
+/// Apply a verified policy to decrypted text, returning the redacted text and
+/// how many matches were replaced. Runs on the client, after decryption.
+pub fn apply_redaction(policy: &RedactionPolicy, plaintext: &str) -> Result<(String, usize)> {
+    let mut text = plaintext.to_string();
+    let mut redacted = 0;
+    for rule in &policy.rules {
+        let pattern = Regex::new(&rule.pattern).map_err(|e| {
+            Error::Validation(format!(
+                "Invalid pattern in redaction rule {}: {}",
+                rule.id, e
+            ))
+        })?;
+        redacted += pattern.find_iter(&text).count();
+        text = pattern
+            .replace_all(&text, regex::NoExpand(&rule.replacement))
+            .into_owned();
+    }
+    Ok((text, redacted))
+}
@@ -0,0 +1,120 @@
+//! Client-side verification of what the proxy signs
+//!
+//! The proxy signs encrypted responses and redaction policy bundles with its
+//! Ed25519 deployment key and publishes the public keys as a JWKS document.
+//! These checks run on the client against that document.
+
+use crate::{Error, Result};
+use base64::prelude::*;
+use ring::{digest, signature};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Fields of an encrypted response covered by the response signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseEnvelope {
+    pub request_id: String,
+    /// SHA-256 (hex) over the response ciphertext bytes
+    pub ciphertext_digest: String,
+    pub timestamp: i64,
+    pub key_id: String,
+}
+
+/// Signed envelope; the signature covers the compact JSON encoding of `envelope`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedResponseEnvelope {
+    pub envelope: ResponseEnvelope,
+    pub algorithm: String,
+    pub signature: String, // Base64 encoded
+}
+
+/// Ed25519 public key in JWK form (RFC 8037)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub x: String, // Base64url encoded, unpadded
+    pub kid: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+    pub alg: String,
+    /// When the key was rotated out; absent for the signing key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retired_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+/// Client-side check of a signed response: the signature must come from a key
+/// in `jwks`, cover this request and ciphertext, and be at most `max_age` old
+pub fn verify_signed_response(
+    signed: &SignedResponseEnvelope,
+    jwks: &JwkSet,
+    request_id: &str,
+    ciphertext: &[u8],
+    max_age: Duration,
+) -> Result<()> {
+    let envelope = &signed.envelope;
+    if envelope.request_id != request_id {
+        return Err(Error::Security(
+            "Response signature covers a different request".to_string(),
+        ));
+    }
+    if envelope.ciphertext_digest != hex(digest::digest(&digest::SHA256, ciphertext).as_ref()) {
+        return Err(Error::Security(
+            "Response ciphertext does not match its signature".to_string(),
+        ));
+    }
+    let age = chrono::Utc::now().timestamp() - envelope.timestamp;
+    if age.unsigned_abs() > max_age.as_secs() {
+        return Err(Error::Security(
+            "Response signature is outside the accepted time window".to_string(),
+        ));
+    }
+
+    verify_detached(
+        jwks,
+        &envelope.key_id,
+        &signed.algorithm,
+        &serde_json::to_vec(envelope)?,
+        &signed.signature,
+    )
+}
+
+/// Check a Base64 `signature` over `payload` made with key `key_id` from `jwks`
+pub fn verify_detached(
+    jwks: &JwkSet,
+    key_id: &str,
+    algorithm: &str,
+    payload: &[u8],
+    signature: &str,
+) -> Result<()> {
+    let jwk = jwks
+        .keys
+        .iter()
+        .find(|k| k.kid == key_id)
+        .ok_or_else(|| Error::Security(format!("Unknown signing key {}", key_id)))?;
+    if algorithm != "EdDSA" || jwk.crv != "Ed25519" {
+        return Err(Error::Security(format!(
+            "Unsupported signature algorithm {}",
+            algorithm
+        )));
+    }
+
+    let public_key = BASE64_URL_SAFE_NO_PAD
+        .decode(&jwk.x)
+        .map_err(|_| Error::Security("Malformed signing key".to_string()))?;
+    let signature_bytes = BASE64_STANDARD
+        .decode(signature)
+        .map_err(|_| Error::Security("Malformed signature".to_string()))?;
+    signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(payload, &signature_bytes)
+        .map_err(|_| Error::Security("Invalid signature".to_string()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
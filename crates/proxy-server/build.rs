@@ -0,0 +1,111 @@
+//! Build script for FHE LLM Proxy
+
+fn main() {
+    // Simple build information
+    println!(
+        "cargo:rustc-env=BUILD_TIMESTAMP={}",
+        chrono::Utc::now().timestamp()
+    );
+    println!(
+        "cargo:rustc-env=BUILD_VERSION={}",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    // Provenance of the binary, served at /v1/provenance
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src");
+    // The lockfile and git metadata live at the workspace root
+    println!("cargo:rerun-if-changed=../../Cargo.lock");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/refs");
+    let git_commit = command_output("git", &["rev-parse", "HEAD"]);
+    let git_dirty = command_output("git", &["status", "--porcelain", "--untracked-files=no"])
+        .map(|status| !status.is_empty());
+    println!(
+        "cargo:rustc-env=BUILD_GIT_COMMIT={}",
+        git_commit.as_deref().unwrap_or("unknown")
+    );
+    println!(
+        "cargo:rustc-env=BUILD_GIT_DIRTY={}",
+        git_dirty.map_or("unknown".to_string(), |dirty| dirty.to_string())
+    );
+    println!(
+        "cargo:rustc-env=BUILD_FEATURES={}",
+        enabled_features().join(",")
+    );
+    println!(
+        "cargo:rustc-env=BUILD_LOCKFILE_SHA256={}",
+        lockfile_sha256().unwrap_or_else(|| "unknown".to_string())
+    );
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    println!(
+        "cargo:rustc-env=BUILD_RUSTC_VERSION={}",
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string())
+    );
+    println!(
+        "cargo:rustc-env=BUILD_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+    println!(
+        "cargo:rustc-env=BUILD_PROFILE={}",
+        std::env::var("PROFILE").unwrap_or_default()
+    );
+
+    // Add security compile flags for Linux
+    #[cfg(target_os = "linux")]
+    {
+        println!("cargo:rustc-link-arg=-Wl,-z,relro,-z,now");
+    }
+
+    // Check if we're building with GPU support
+    #[cfg(feature = "gpu")]
+    {
+        println!("cargo:rustc-cfg=gpu_enabled");
+    }
+
+    // Set optimization flags for production builds
+    #[cfg(not(debug_assertions))]
+    {
+        println!("cargo:rustc-env=CARGO_CFG_OPTIMIZED=1");
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Cargo features the crate is built with, as named in Cargo.toml
+fn enabled_features() -> Vec<String> {
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    features
+}
+
+/// Digest of the nearest Cargo.lock: the workspace's, or the crate's own when
+/// built from a published package
+fn lockfile_sha256() -> Option<String> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").ok()?;
+    let lockfile = std::path::Path::new(&manifest_dir)
+        .ancestors()
+        .find_map(|dir| std::fs::read(dir.join("Cargo.lock")).ok())?;
+    let digest = ring::digest::digest(&ring::digest::SHA256, &lockfile);
+    Some(
+        digest
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    )
+}
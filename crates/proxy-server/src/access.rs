@@ -0,0 +1,150 @@
+//! Who a request comes from, established from credentials rather than claims
+//!
+//! A tenant is identified by its API key or by a session opened under one;
+//! the `x-tenant-id` header is only accepted when it names that tenant.
+
+use crate::config::{AccessConfig, TenantKeyConfig};
+use ring::digest;
+
+/// Carries a tenant API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// The tenant a client claims to act for; checked against its credentials
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// Carries an operator's admin token
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// Why a request's tenant claim was refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdentityRefusal {
+    /// `x-tenant-id` was sent without credentials of any tenant
+    Unauthenticated,
+    /// The claim, API key and session name different tenants
+    Mismatch,
+}
+
+/// Configured credentials and the checks made against them
+#[derive(Debug)]
+pub struct AccessControl {
+    config: AccessConfig,
+}
+
+impl AccessControl {
+    pub fn new(config: AccessConfig) -> Self {
+        Self { config }
+    }
+
+    /// Tenant the API key `key` was issued to. Configured digests are
+    /// compared with the presented key's, so timing reveals nothing about it.
+    pub fn tenant_for_key(&self, key: &str) -> Option<&str> {
+        self.issued_key(key).map(|k| k.tenant.as_str())
+    }
+
+    /// Identifies the issued API key `key` by its configured digest, so
+    /// limits can be kept per key without holding on to the key itself
+    pub fn key_id(&self, key: &str) -> Option<String> {
+        self.issued_key(key).map(|k| k.sha256.to_ascii_lowercase())
+    }
+
+    fn issued_key(&self, key: &str) -> Option<&TenantKeyConfig> {
+        let presented = sha256_hex(key);
+        self.config
+            .tenant_keys
+            .iter()
+            .find(|k| k.sha256.eq_ignore_ascii_case(&presented))
+    }
+
+    /// Whether tenants are issued API keys, so requests can be attributed to one
+    pub fn issues_tenant_keys(&self) -> bool {
+        !self.config.tenant_keys.is_empty()
+    }
+
+    /// Operator the admin token `token` was issued to
+    pub fn admin(&self, token: &str) -> Option<&str> {
+        let presented = sha256_hex(token);
+        self.config
+            .admin_tokens
+            .iter()
+            .find(|t| t.sha256.eq_ignore_ascii_case(&presented))
+            .map(|t| t.name.as_str())
+    }
+}
+
+/// The tenant a request acts for, given the tenants its API key and session
+/// belong to and the tenant it claims
+pub fn resolve_tenant(
+    from_key: Option<&str>,
+    from_session: Option<&str>,
+    claimed: Option<&str>,
+) -> Result<Option<String>, IdentityRefusal> {
+    let authenticated = match (from_key, from_session) {
+        (Some(key), Some(session)) if key != session => return Err(IdentityRefusal::Mismatch),
+        (key, session) => key.or(session),
+    };
+    match (authenticated, claimed) {
+        (None, Some(_)) => Err(IdentityRefusal::Unauthenticated),
+        (Some(tenant), Some(claimed)) if tenant != claimed => Err(IdentityRefusal::Mismatch),
+        (tenant, _) => Ok(tenant.map(str::to_string)),
+    }
+}
+
+fn sha256_hex(value: &str) -> String {
+    digest::digest(&digest::SHA256, value.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AdminTokenConfig;
+
+    #[test]
+    fn test_tenant_comes_from_credentials_not_claims() {
+        let access = AccessControl::new(AccessConfig {
+            tenant_keys: vec![TenantKeyConfig {
+                tenant: "acme".to_string(),
+                sha256: sha256_hex("acme-secret"),
+            }],
+            admin_tokens: vec![AdminTokenConfig {
+                name: "ops".to_string(),
+                sha256: sha256_hex("ops-secret"),
+            }],
+        });
+        assert_eq!(access.tenant_for_key("acme-secret"), Some("acme"));
+        assert_eq!(access.tenant_for_key("guess"), None);
+        assert_eq!(
+            access.key_id("acme-secret"),
+            Some(sha256_hex("acme-secret"))
+        );
+        assert_eq!(access.key_id("guess"), None);
+        assert_eq!(access.admin("ops-secret"), Some("ops"));
+        // Tenant keys and admin tokens are not interchangeable
+        assert_eq!(access.admin("acme-secret"), None);
+
+        assert_eq!(resolve_tenant(None, None, None), Ok(None));
+        assert_eq!(
+            resolve_tenant(Some("acme"), None, None),
+            Ok(Some("acme".to_string()))
+        );
+        assert_eq!(
+            resolve_tenant(None, Some("acme"), Some("acme")),
+            Ok(Some("acme".to_string()))
+        );
+        assert_eq!(
+            resolve_tenant(None, None, Some("acme")),
+            Err(IdentityRefusal::Unauthenticated)
+        );
+        assert_eq!(
+            resolve_tenant(Some("acme"), None, Some("globex")),
+            Err(IdentityRefusal::Mismatch)
+        );
+        assert_eq!(
+            resolve_tenant(Some("acme"), Some("globex"), None),
+            Err(IdentityRefusal::Mismatch)
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Homomorphic aggregation of encrypted numeric outputs
+//!
+//! Analytics tenants sum or average encrypted values, such as per-item
+//! sentiment scores from a batch, without any single item being decrypted.
+//! Aggregate results are held here rather than in the shared ciphertext cache,
+//! so `/v1/decrypt` cannot reach them; they are only decrypted through the
+//! aggregation endpoints, against a per-tenant daily quota.
+
+use crate::config::AggregationConfig;
+use crate::error::{Error, Result};
+use crate::fhe::{Ciphertext, FheEngine};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregationOperation {
+    Sum,
+    Mean,
+}
+
+/// An encrypted aggregate and what it was computed from
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateRecord {
+    pub id: Uuid,
+    pub tenant: String,
+    pub operation: AggregationOperation,
+    pub item_count: usize,
+    pub noise_budget: Option<u64>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    #[serde(skip)]
+    pub ciphertext: Ciphertext,
+}
+
+/// Decryptions of aggregate results a tenant has made today
+#[derive(Debug, Clone, Serialize)]
+pub struct DecryptionQuotaUsage {
+    pub day: chrono::NaiveDate,
+    pub used: u32,
+    pub daily_limit: u32,
+}
+
+#[derive(Debug)]
+pub struct AggregationService {
+    config: AggregationConfig,
+    results: RwLock<HashMap<Uuid, AggregateRecord>>,
+    decryptions: Mutex<HashMap<String, (chrono::NaiveDate, u32)>>,
+}
+
+impl AggregationService {
+    pub fn new(config: AggregationConfig) -> Self {
+        Self {
+            config,
+            results: RwLock::new(HashMap::new()),
+            decryptions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Aggregate `items` homomorphically and keep the encrypted result for `tenant`
+    pub fn aggregate(
+        &self,
+        engine: &FheEngine,
+        tenant: &str,
+        operation: AggregationOperation,
+        items: &[Ciphertext],
+    ) -> Result<AggregateRecord> {
+        if items.len() < self.config.min_items || items.len() > self.config.max_items {
+            return Err(Error::Validation(format!(
+                "Aggregations take between {} and {} items, got {}",
+                self.config.min_items,
+                self.config.max_items,
+                items.len()
+            )));
+        }
+        let sum = engine.sum_encrypted(items)?;
+        let ciphertext = match operation {
+            AggregationOperation::Sum => sum,
+            AggregationOperation::Mean => engine.multiply_plain(&sum, 1.0 / items.len() as f64)?,
+        };
+
+        let now = chrono::Utc::now().timestamp();
+        let record = AggregateRecord {
+            id: ciphertext.id,
+            tenant: tenant.to_string(),
+            operation,
+            item_count: items.len(),
+            noise_budget: ciphertext.noise_budget,
+            created_at: now,
+            expires_at: now + self.config.result_retention_seconds as i64,
+            ciphertext,
+        };
+        let mut results = self.results.write().unwrap();
+        results.retain(|_, r| r.expires_at > now);
+        results.insert(record.id, record.clone());
+        Ok(record)
+    }
+
+    /// An unexpired aggregate, only visible to the tenant that computed it
+    pub fn get(&self, tenant: &str, id: Uuid) -> Option<AggregateRecord> {
+        let now = chrono::Utc::now().timestamp();
+        self.results
+            .read()
+            .unwrap()
+            .get(&id)
+            .filter(|r| r.tenant == tenant && r.expires_at > now)
+            .cloned()
+    }
+
+    /// Count one result decryption against the tenant's daily quota
+    pub fn charge_decryption(
+        &self,
+        tenant: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<DecryptionQuotaUsage> {
+        let day = now.date_naive();
+        let mut decryptions = self.decryptions.lock().unwrap();
+        let entry = decryptions.entry(tenant.to_string()).or_insert((day, 0));
+        if entry.0 != day {
+            *entry = (day, 0);
+        }
+        if entry.1 >= self.config.daily_decryptions_per_tenant {
+            return Err(Error::RateLimit(format!(
+                "Tenant {} has used its {} aggregate decryptions for today",
+                tenant, self.config.daily_decryptions_per_tenant
+            )));
+        }
+        entry.1 += 1;
+        Ok(DecryptionQuotaUsage {
+            day,
+            used: entry.1,
+            daily_limit: self.config.daily_decryptions_per_tenant,
+        })
+    }
+
+    /// Return a charge for a decryption that failed
+    pub fn refund_decryption(&self, tenant: &str, charged_at: chrono::DateTime<chrono::Utc>) {
+        let mut decryptions = self.decryptions.lock().unwrap();
+        if let Some(entry) = decryptions.get_mut(tenant) {
+            if entry.0 == charged_at.date_naive() {
+                entry.1 = entry.1.saturating_sub(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe::FheParams;
+
+    #[test]
+    fn test_aggregation_limits_and_decryption_quota() {
+        let service = AggregationService::new(AggregationConfig {
+            enabled: true,
+            min_items: 3,
+            max_items: 4,
+            daily_decryptions_per_tenant: 2,
+            ..AggregationConfig::default()
+        });
+        let mut engine = FheEngine::new(FheParams::default()).unwrap();
+        let (client_id, _) = engine.generate_keys().unwrap();
+        let items: Vec<Ciphertext> = [1.0, 2.0, 3.0, 6.0, 8.0]
+            .iter()
+            .map(|v| engine.encrypt_value(client_id, *v).unwrap())
+            .collect();
+
+        // Too few items could single one out; too many are refused outright
+        assert!(service
+            .aggregate(&engine, "acme", AggregationOperation::Sum, &items[..2])
+            .is_err());
+        assert!(service
+            .aggregate(&engine, "acme", AggregationOperation::Sum, &items)
+            .is_err());
+
+        let mean = service
+            .aggregate(&engine, "acme", AggregationOperation::Mean, &items[..4])
+            .unwrap();
+        assert_eq!(mean.item_count, 4);
+        let stored = service.get("acme", mean.id).unwrap();
+        assert_eq!(
+            engine.decrypt_value(client_id, &stored.ciphertext).unwrap(),
+            3.0
+        );
+        assert!(service.get("other", mean.id).is_none());
+
+        let now = chrono::Utc::now();
+        assert_eq!(service.charge_decryption("acme", now).unwrap().used, 1);
+        assert_eq!(service.charge_decryption("acme", now).unwrap().used, 2);
+        assert!(service.charge_decryption("acme", now).is_err());
+        service.refund_decryption("acme", now);
+        assert!(service.charge_decryption("acme", now).is_ok());
+        // Quotas reset each day and are kept per tenant
+        assert!(service.charge_decryption("other", now).is_ok());
+        assert!(service
+            .charge_decryption("acme", now + chrono::Duration::days(1))
+            .is_ok());
+    }
+}
@@ -0,0 +1,122 @@
+//! Global allocator selection and allocation profiling for FHE hot paths
+//!
+//! Ciphertext buffers are large and short-lived, which fragments the system
+//! allocator. The `jemalloc` and `mimalloc` features swap in an allocator that
+//! copes better; either way, hot-path allocations are tagged by call site and
+//! size class so their shape is visible next to allocator-reported
+//! fragmentation and RSS.
+
+use serde::Serialize;
+
+pub use fhe_core::allocations::{hot_path_stats, record_hot_path, SiteAllocationStats};
+
+#[cfg(all(feature = "jemalloc", feature = "mimalloc"))]
+compile_error!("features `jemalloc` and `mimalloc` are mutually exclusive");
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+/// Heap state as reported by the active allocator. Figures the allocator
+/// cannot report are `None`; RSS always comes from procfs.
+#[derive(Debug, Clone, Serialize)]
+pub struct AllocatorStats {
+    pub allocator: &'static str,
+    /// Bytes handed out to the application
+    pub allocated_bytes: Option<u64>,
+    /// Bytes in pages the allocator holds for those allocations
+    pub active_bytes: Option<u64>,
+    pub resident_bytes: Option<u64>,
+    pub rss_bytes: Option<u64>,
+    /// Share of active pages not backing live allocations
+    pub fragmentation_ratio: Option<f64>,
+}
+
+pub fn allocator_name() -> &'static str {
+    if cfg!(feature = "jemalloc") {
+        "jemalloc"
+    } else if cfg!(feature = "mimalloc") {
+        "mimalloc"
+    } else {
+        "system"
+    }
+}
+
+/// Sample allocator and process memory statistics
+pub fn allocator_stats() -> AllocatorStats {
+    let rss_bytes = std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find(|line| line.starts_with("VmRSS:"))
+                .and_then(|line| line.split_whitespace().nth(1))
+                .and_then(|kb| kb.parse::<u64>().ok())
+        })
+        .map(|kb| kb * 1024);
+
+    #[cfg(feature = "jemalloc")]
+    {
+        use tikv_jemalloc_ctl::{epoch, stats};
+
+        // Statistics are cached until the epoch advances
+        let _ = epoch::advance();
+        let allocated = stats::allocated::read().ok().map(|b| b as u64);
+        let active = stats::active::read().ok().map(|b| b as u64);
+        let resident = stats::resident::read().ok().map(|b| b as u64);
+        let fragmentation_ratio = match (allocated, active) {
+            (Some(allocated), Some(active)) if active > 0 => {
+                Some(active.saturating_sub(allocated) as f64 / active as f64)
+            }
+            _ => None,
+        };
+
+        AllocatorStats {
+            allocator: allocator_name(),
+            allocated_bytes: allocated,
+            active_bytes: active,
+            resident_bytes: resident,
+            rss_bytes,
+            fragmentation_ratio,
+        }
+    }
+
+    #[cfg(not(feature = "jemalloc"))]
+    AllocatorStats {
+        allocator: allocator_name(),
+        allocated_bytes: None,
+        active_bytes: None,
+        resident_bytes: None,
+        rss_bytes,
+        fragmentation_ratio: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_path_size_classes() {
+        record_hot_path("test_site", 100);
+        record_hot_path("test_site", 8 << 10);
+        record_hot_path("test_site", 32 << 20);
+
+        let stats = &hot_path_stats()["test_site"];
+        assert_eq!(stats.allocations, 3);
+        assert_eq!(stats.bytes, 100 + (8 << 10) + (32 << 20));
+        assert_eq!(stats.size_classes["le_4k"], 1);
+        assert_eq!(stats.size_classes["le_64k"], 1);
+        assert_eq!(stats.size_classes["gt_16m"], 1);
+
+        let heap = allocator_stats();
+        assert_eq!(heap.allocator, allocator_name());
+        if cfg!(target_os = "linux") {
+            assert!(heap.rss_bytes.unwrap() > 0);
+        }
+    }
+}
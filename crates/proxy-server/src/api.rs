@@ -0,0 +1,154 @@
+//! Stable API for embedding the proxy in Rust applications
+//!
+//! Everything reachable through this module follows semantic versioning:
+//!
+//! - Removing or changing an item here, or making a builder reject input it
+//!   used to accept, is a breaking change and waits for the next breaking
+//!   release. While the crate is at 0.x that is the next minor version (0.1 ->
+//!   0.2), as Cargo's caret requirements expect; patch releases never break it.
+//! - Items are deprecated for at least one release before they are removed,
+//!   and the deprecation note names the replacement.
+//! - New builder methods, new re-exports and new variants of `#[non_exhaustive]`
+//!   types are additions and can arrive in any release.
+//! - [`Config`] is re-exported for its `config.toml` schema, which is versioned
+//!   by `schema_version` and migrated on load. Its Rust fields mirror that file
+//!   and may change with it; set them through [`ProxyServerBuilder::configure`]
+//!   knowing the closure may need updating on upgrade.
+//!
+//! The crate's other modules are public so the binaries, benchmarks and tests
+//! can reach them, but they are hidden from the documentation and can change in
+//! any release. Embedders who need something only they offer should ask for it
+//! to be added here.
+//!
+//! ```no_run
+//! use proxy_server::api::{FheEngine, ProxyServer};
+//!
+//! # async fn run() -> proxy_server::api::Result<()> {
+//! let engine = FheEngine::builder().poly_modulus_degree(8192).build()?;
+//! let server = ProxyServer::builder()
+//!     .bind("127.0.0.1", 8080)
+//!     .fhe_params(engine.get_params().clone())
+//!     .openai_api_key("sk-...")
+//!     .build()?;
+//! server.start().await
+//! # }
+//! ```
+
+pub use crate::config::Config;
+pub use crate::error::{Error, Result};
+pub use crate::fhe::{Ciphertext, FheEngine, FheEngineBuilder, FheParams};
+pub use crate::prompt_lint::{lint_prompt, LintPolicy, LintReport};
+pub use crate::proxy::ProxyServer;
+pub use crate::response_metadata::{
+    CacheStatus, ProcessingTimes, ResponseCost, ResponseMetadata, RESPONSE_METADATA_VERSION,
+};
+pub use crate::security::{Attestation, AttestationStatement};
+pub use proxy_client::{ClientConfig, ClientConfigBuilder};
+
+impl ProxyServer {
+    /// Start building a server from the default configuration
+    pub fn builder() -> ProxyServerBuilder {
+        ProxyServerBuilder::new()
+    }
+}
+
+/// Builds a [`ProxyServer`], validating its configuration first
+#[derive(Debug, Clone, Default)]
+pub struct ProxyServerBuilder {
+    config: Config,
+}
+
+impl ProxyServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from an existing configuration
+    pub fn from_config(config: Config) -> Self {
+        Self { config }
+    }
+
+    /// Start from `config.toml` (or `FHE_CONFIG_PATH`) and `FHE_*` environment
+    /// overrides, as the `fhe-proxy` binary does
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::from_config(Config::load()?))
+    }
+
+    pub fn bind(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.config.server.host = host.into();
+        self.config.server.port = port;
+        self
+    }
+
+    /// Encryption parameters the server's engines use
+    pub fn fhe_params(mut self, params: FheParams) -> Self {
+        let encryption = &mut self.config.encryption;
+        encryption.poly_modulus_degree = params.poly_modulus_degree;
+        encryption.coeff_modulus_bits = params.coeff_modulus_bits;
+        encryption.scale_bits = params.scale_bits;
+        encryption.security_level = params.security_level;
+        self
+    }
+
+    pub fn openai_api_key(mut self, key: impl Into<String>) -> Self {
+        self.config.llm.openai_api_key = Some(key.into());
+        self
+    }
+
+    pub fn anthropic_api_key(mut self, key: impl Into<String>) -> Self {
+        self.config.llm.anthropic_api_key = Some(key.into());
+        self
+    }
+
+    /// Adjust any other setting; see the module notes on [`Config`]'s stability
+    pub fn configure(mut self, adjust: impl FnOnce(&mut Config)) -> Self {
+        adjust(&mut self.config);
+        self
+    }
+
+    pub fn build(self) -> Result<ProxyServer> {
+        self.config.validate()?;
+        ProxyServer::new(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builders_validate_before_constructing() {
+        let engine = FheEngine::builder()
+            .poly_modulus_degree(1024)
+            .coeff_modulus_bits(vec![40, 40])
+            .build()
+            .unwrap();
+        assert_eq!(engine.get_params().poly_modulus_degree, 1024);
+        assert!(FheEngine::builder()
+            .poly_modulus_degree(1000)
+            .build()
+            .is_err());
+
+        let server = ProxyServer::builder()
+            .bind("127.0.0.1", 0)
+            .fhe_params(engine.get_params().clone())
+            .build();
+        assert!(server.is_err(), "port 0 is refused by validation");
+        let server = ProxyServer::builder()
+            .bind("127.0.0.1", 18080)
+            .configure(|config| config.server.workers = 2)
+            .build();
+        assert!(server.is_ok());
+
+        let client = ClientConfig::builder("https://proxy.example/")
+            .tenant("acme")
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.url("/v1/encrypt"),
+            "https://proxy.example/v1/encrypt"
+        );
+        assert_eq!(client.headers(), vec![("x-tenant-id", "acme".to_string())]);
+        assert!(ClientConfig::builder("proxy.example").build().is_err());
+    }
+}
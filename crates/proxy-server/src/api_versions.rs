@@ -0,0 +1,307 @@
+//! Versioned HTTP API
+//!
+//! Every version is served by the same handlers, which speak the `/v1` wire
+//! format. A request to another version has its path mapped onto `/v1` before
+//! routing, and its JSON body and response translated by that version's
+//! adapter. `/v2` names the ciphertext payload `ciphertext` instead of
+//! `encrypted_data`, and gives error responses a JSON body instead of a bare
+//! status.
+//!
+//! Versions with a lifecycle entry announce their retirement in `Deprecation`
+//! (RFC 9745), `Sunset` (RFC 8594) and `Link` headers. Requests are counted per
+//! version and tenant, so it is visible who still has to move before an old
+//! version can be removed.
+
+use crate::config::{ApiVersionLifecycle, ApiVersionsConfig};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Response header naming the version that served the request
+pub const API_VERSION_HEADER: &str = "x-api-version";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+        }
+    }
+
+    /// The version a path belongs to, and the path the shared handlers serve
+    /// it under
+    pub fn route(path: &str) -> Option<(ApiVersion, String)> {
+        Self::ALL.into_iter().find_map(|version| {
+            let rest = path.strip_prefix('/')?.strip_prefix(version.as_str())?;
+            (rest.is_empty() || rest.starts_with('/')).then(|| (version, format!("/v1{}", rest)))
+        })
+    }
+
+    /// Object keys that differ from the handlers' format, as (wire, handler)
+    fn renamed_fields(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            ApiVersion::V1 => &[],
+            ApiVersion::V2 => &[("ciphertext", "encrypted_data")],
+        }
+    }
+
+    /// Whether requests or responses of this version need translating
+    pub fn is_adapted(&self) -> bool {
+        *self != ApiVersion::V1
+    }
+
+    /// Translate a request body into the handlers' format
+    pub fn adapt_request(&self, body: &mut serde_json::Value) {
+        for (wire, handler) in self.renamed_fields() {
+            rename_keys(body, wire, handler);
+        }
+    }
+
+    /// Translate a response into this version's format; `body` is None for
+    /// an empty body
+    pub fn adapt_response(
+        &self,
+        status: StatusCode,
+        body: Option<serde_json::Value>,
+    ) -> Option<serde_json::Value> {
+        match (self, body) {
+            (ApiVersion::V1, body) => body,
+            (ApiVersion::V2, None) if status.is_client_error() || status.is_server_error() => {
+                Some(serde_json::json!({
+                    "error": {
+                        "status": status.as_u16(),
+                        "message": status.canonical_reason().unwrap_or("Error"),
+                    }
+                }))
+            }
+            (ApiVersion::V2, mut body) => {
+                if let Some(body) = &mut body {
+                    for (wire, handler) in self.renamed_fields() {
+                        rename_keys(body, handler, wire);
+                    }
+                }
+                body
+            }
+        }
+    }
+}
+
+/// Rename `from` to `to` in every object of `value`, unless `to` is taken
+fn rename_keys(value: &mut serde_json::Value, from: &str, to: &str) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            if !fields.contains_key(to) {
+                if let Some(moved) = fields.remove(from) {
+                    fields.insert(to.to_string(), moved);
+                }
+            }
+            for field in fields.values_mut() {
+                rename_keys(field, from, to);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                rename_keys(item, from, to);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct VersionUsage {
+    requests: u64,
+    last_request_at: Option<i64>,
+    tenants: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiVersionStats {
+    pub version: ApiVersion,
+    pub enabled: bool,
+    pub deprecated_at: Option<String>,
+    pub sunset_at: Option<String>,
+    pub requests: u64,
+    pub last_request_at: Option<i64>,
+    /// Requests by tenant; "anonymous" for requests without one
+    pub tenants: BTreeMap<String, u64>,
+}
+
+#[derive(Debug)]
+pub struct ApiVersions {
+    config: ApiVersionsConfig,
+    usage: Mutex<HashMap<ApiVersion, VersionUsage>>,
+}
+
+impl ApiVersions {
+    pub fn new(config: ApiVersionsConfig) -> Self {
+        Self {
+            config,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self, version: ApiVersion) -> bool {
+        version != ApiVersion::V2 || self.config.v2_enabled
+    }
+
+    pub fn max_adapted_body_bytes(&self) -> usize {
+        self.config.max_adapted_body_bytes
+    }
+
+    fn lifecycle(&self, version: ApiVersion) -> Option<&ApiVersionLifecycle> {
+        self.config
+            .lifecycle
+            .iter()
+            .find(|l| l.version == version.as_str())
+    }
+
+    /// Whether `version` is past its sunset and no longer served
+    pub fn is_retired(&self, version: ApiVersion, now: DateTime<Utc>) -> bool {
+        self.config.reject_after_sunset
+            && self
+                .lifecycle(version)
+                .and_then(|l| parse_date(&l.sunset_at))
+                .is_some_and(|sunset| now >= sunset)
+    }
+
+    /// Deprecation, Sunset and Link headers for responses of `version`
+    pub fn lifecycle_headers(&self, version: ApiVersion) -> Vec<(&'static str, String)> {
+        let Some(lifecycle) = self.lifecycle(version) else {
+            return Vec::new();
+        };
+        let mut headers = Vec::new();
+        if let Some(deprecated_at) = parse_date(&lifecycle.deprecated_at) {
+            headers.push(("deprecation", format!("@{}", deprecated_at.timestamp())));
+        }
+        if let Some(sunset_at) = parse_date(&lifecycle.sunset_at) {
+            headers.push((
+                "sunset",
+                sunset_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            ));
+        }
+        if let Some(link) = &lifecycle.link {
+            headers.push(("link", format!("<{}>; rel=\"deprecation\"", link)));
+        }
+        headers
+    }
+
+    pub fn record(&self, version: ApiVersion, tenant: Option<&str>, now: DateTime<Utc>) {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(version).or_default();
+        usage.requests += 1;
+        usage.last_request_at = Some(now.timestamp());
+        *usage
+            .tenants
+            .entry(tenant.unwrap_or("anonymous").to_string())
+            .or_default() += 1;
+    }
+
+    pub fn stats(&self) -> Vec<ApiVersionStats> {
+        let usage = self.usage.lock().unwrap();
+        ApiVersion::ALL
+            .into_iter()
+            .map(|version| {
+                let usage = usage.get(&version).cloned().unwrap_or_default();
+                let lifecycle = self.lifecycle(version);
+                ApiVersionStats {
+                    version,
+                    enabled: self.is_enabled(version),
+                    deprecated_at: lifecycle.and_then(|l| l.deprecated_at.clone()),
+                    sunset_at: lifecycle.and_then(|l| l.sunset_at.clone()),
+                    requests: usage.requests,
+                    last_request_at: usage.last_request_at,
+                    tenants: usage.tenants,
+                }
+            })
+            .collect()
+    }
+}
+
+fn parse_date(date: &Option<String>) -> Option<DateTime<Utc>> {
+    date.as_deref()
+        .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+        .map(|d| d.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v2_maps_onto_shared_handlers_with_lifecycle_headers() {
+        assert_eq!(
+            ApiVersion::route("/v2/chat/completions"),
+            Some((ApiVersion::V2, "/v1/chat/completions".to_string()))
+        );
+        assert_eq!(
+            ApiVersion::route("/v1/encrypt"),
+            Some((ApiVersion::V1, "/v1/encrypt".to_string()))
+        );
+        assert_eq!(ApiVersion::route("/v2x/encrypt"), None);
+        assert_eq!(ApiVersion::route("/health"), None);
+
+        let mut request = serde_json::json!({ "ciphertext": "AAEC", "model": "gpt-4" });
+        ApiVersion::V2.adapt_request(&mut request);
+        assert_eq!(request["encrypted_data"], "AAEC");
+        let response = serde_json::json!({ "outputs": [{ "encrypted_data": "AAEC" }] });
+        let response = ApiVersion::V2
+            .adapt_response(StatusCode::OK, Some(response))
+            .unwrap();
+        assert_eq!(response["outputs"][0]["ciphertext"], "AAEC");
+        let error = ApiVersion::V2
+            .adapt_response(StatusCode::NOT_FOUND, None)
+            .unwrap();
+        assert_eq!(error["error"]["status"], 404);
+        assert_eq!(
+            ApiVersion::V1.adapt_response(StatusCode::NOT_FOUND, None),
+            None
+        );
+
+        let versions = ApiVersions::new(ApiVersionsConfig {
+            lifecycle: vec![ApiVersionLifecycle {
+                version: "v1".to_string(),
+                deprecated_at: Some("2027-01-01T00:00:00Z".to_string()),
+                sunset_at: Some("2027-07-01T00:00:00Z".to_string()),
+                link: Some("https://docs.example.com/v2".to_string()),
+            }],
+            reject_after_sunset: true,
+            ..ApiVersionsConfig::default()
+        });
+        assert_eq!(
+            versions.lifecycle_headers(ApiVersion::V1),
+            [
+                ("deprecation", "@1798761600".to_string()),
+                ("sunset", "Thu, 01 Jul 2027 00:00:00 GMT".to_string()),
+                (
+                    "link",
+                    "<https://docs.example.com/v2>; rel=\"deprecation\"".to_string()
+                ),
+            ]
+        );
+        assert!(versions.lifecycle_headers(ApiVersion::V2).is_empty());
+        let sunset = DateTime::parse_from_rfc3339("2027-07-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert!(!versions.is_retired(ApiVersion::V1, sunset - chrono::Duration::seconds(1)));
+        assert!(versions.is_retired(ApiVersion::V1, sunset));
+
+        versions.record(ApiVersion::V1, Some("acme"), sunset);
+        versions.record(ApiVersion::V1, None, sunset);
+        let stats = versions.stats();
+        assert_eq!(stats[0].requests, 2);
+        assert_eq!(stats[0].tenants["acme"], 1);
+        assert_eq!(stats[1].requests, 0);
+    }
+}
@@ -0,0 +1,313 @@
+//! Human approval of decryptions for sensitive result classes
+//!
+//! A completion tagged with a sensitivity class listed in `approvals.classes`
+//! gets an approval request, and its result ciphertexts are held: the
+//! decryption endpoints refuse them until an approver approves the request,
+//! and so does every operation that would derive a new ciphertext from them.
+//! Approvers are a separate role from API clients. They are configured by the
+//! SHA-256 of a token they send in `x-approver-token`, and only they can list,
+//! approve or deny requests.
+//!
+//! An approval lets the requesting tenant decrypt for `approval_ttl_seconds`.
+//! A request left undecided for `request_ttl_seconds` expires, and so does an
+//! approval once its window ends. Neither releases the hold: the result has to
+//! be produced again. Holds are dropped only once none of their ciphertexts
+//! remain in the proxy's cache.
+
+use crate::config::DecryptionApprovalConfig;
+use crate::error::{Error, Result};
+use ring::digest;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+pub const APPROVER_TOKEN_HEADER: &str = "x-approver-token";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalState {
+    Pending,
+    Approved,
+    Denied,
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApprovalRequest {
+    pub id: Uuid,
+    pub tenant: Option<String>,
+    pub class: String,
+    pub ciphertext_ids: Vec<Uuid>,
+    pub requested_at: i64,
+    /// End of the decision window while pending, of the approval once approved
+    pub expires_at: i64,
+    pub state: ApprovalState,
+    pub approver: Option<String>,
+    pub reason: Option<String>,
+    pub decided_at: Option<i64>,
+}
+
+impl ApprovalRequest {
+    /// The request with its state as of `now`
+    fn at(&self, now: i64) -> Self {
+        let mut request = self.clone();
+        if matches!(
+            request.state,
+            ApprovalState::Pending | ApprovalState::Approved
+        ) && now >= request.expires_at
+        {
+            request.state = ApprovalState::Expired;
+        }
+        request
+    }
+}
+
+#[derive(Debug, Default)]
+struct Approvals {
+    requests: HashMap<Uuid, ApprovalRequest>,
+    /// Held ciphertext to the request holding it
+    held: HashMap<Uuid, Uuid>,
+}
+
+#[derive(Debug)]
+pub struct ApprovalService {
+    config: DecryptionApprovalConfig,
+    approvals: Mutex<Approvals>,
+}
+
+impl ApprovalService {
+    pub fn new(config: DecryptionApprovalConfig) -> Self {
+        Self {
+            config,
+            approvals: Mutex::new(Approvals::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Whether results of `class` are held for approval
+    pub fn requires_approval(&self, class: &str) -> bool {
+        self.config.enabled && self.config.classes.iter().any(|c| c == class)
+    }
+
+    /// Name of the approver `token` belongs to
+    pub fn approver(&self, token: &str) -> Option<&str> {
+        let digest = digest::digest(&digest::SHA256, token.as_bytes());
+        let hex: String = digest
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.config
+            .approvers
+            .iter()
+            .find(|a| a.token_sha256.eq_ignore_ascii_case(&hex))
+            .map(|a| a.name.as_str())
+    }
+
+    /// Hold `ciphertext_ids` until a new request for them is approved
+    pub fn hold(
+        &self,
+        tenant: Option<&str>,
+        class: &str,
+        ciphertext_ids: Vec<Uuid>,
+        now: i64,
+    ) -> ApprovalRequest {
+        let request = ApprovalRequest {
+            id: Uuid::new_v4(),
+            tenant: tenant.map(str::to_string),
+            class: class.to_string(),
+            ciphertext_ids,
+            requested_at: now,
+            expires_at: now + self.config.request_ttl_seconds as i64,
+            state: ApprovalState::Pending,
+            approver: None,
+            reason: None,
+            decided_at: None,
+        };
+        let mut approvals = self.approvals.lock().unwrap();
+        for ciphertext_id in &request.ciphertext_ids {
+            approvals.held.insert(*ciphertext_id, request.id);
+        }
+        approvals.requests.insert(request.id, request.clone());
+        request
+    }
+
+    /// Approve or deny a pending request
+    pub fn decide(
+        &self,
+        id: Uuid,
+        approver: &str,
+        approve: bool,
+        reason: Option<String>,
+        now: i64,
+    ) -> Result<ApprovalRequest> {
+        let mut approvals = self.approvals.lock().unwrap();
+        let request = approvals
+            .requests
+            .get_mut(&id)
+            .ok_or_else(|| Error::Validation(format!("Unknown approval request: {}", id)))?;
+        let current = request.at(now).state;
+        if current != ApprovalState::Pending {
+            return Err(Error::Validation(format!(
+                "Approval request {} is {:?}, not pending",
+                id, current
+            )));
+        }
+        request.state = if approve {
+            request.expires_at = now + self.config.approval_ttl_seconds as i64;
+            ApprovalState::Approved
+        } else {
+            ApprovalState::Denied
+        };
+        request.approver = Some(approver.to_string());
+        request.reason = reason;
+        request.decided_at = Some(now);
+        Ok(request.clone())
+    }
+
+    pub fn get(&self, id: Uuid, now: i64) -> Option<ApprovalRequest> {
+        self.approvals
+            .lock()
+            .unwrap()
+            .requests
+            .get(&id)
+            .map(|request| request.at(now))
+    }
+
+    /// Requests awaiting a decision, oldest first
+    pub fn pending(&self, now: i64) -> Vec<ApprovalRequest> {
+        let mut pending: Vec<ApprovalRequest> = self
+            .approvals
+            .lock()
+            .unwrap()
+            .requests
+            .values()
+            .map(|request| request.at(now))
+            .filter(|request| request.state == ApprovalState::Pending)
+            .collect();
+        pending.sort_by_key(|request| request.requested_at);
+        pending
+    }
+
+    /// Whether `tenant` may decrypt `ciphertext_id`; returns the approval
+    /// that allows it, or None when the ciphertext is not held
+    pub fn check(
+        &self,
+        ciphertext_id: Uuid,
+        tenant: Option<&str>,
+        now: i64,
+    ) -> Result<Option<ApprovalRequest>> {
+        let approvals = self.approvals.lock().unwrap();
+        let Some(request) = approvals
+            .held
+            .get(&ciphertext_id)
+            .and_then(|id| approvals.requests.get(id))
+        else {
+            return Ok(None);
+        };
+        let request = request.at(now);
+        if request.tenant.as_deref() != tenant {
+            return Err(Error::KeyPolicy(format!(
+                "Ciphertext {} is held for another tenant",
+                ciphertext_id
+            )));
+        }
+        match request.state {
+            ApprovalState::Approved => Ok(Some(request)),
+            state => Err(Error::KeyPolicy(format!(
+                "Decryption of {} needs approval request {} to be approved; it is {:?}",
+                ciphertext_id, request.id, state
+            ))),
+        }
+    }
+
+    /// Ciphertexts currently held, approved or not
+    pub fn held(&self) -> Vec<Uuid> {
+        self.approvals
+            .lock()
+            .unwrap()
+            .held
+            .keys()
+            .copied()
+            .collect()
+    }
+
+    /// Drop requests none of whose ciphertexts are still `cached`
+    pub fn prune(&self, cached: impl Fn(&Uuid) -> bool) {
+        let mut approvals = self.approvals.lock().unwrap();
+        let Approvals { requests, held } = &mut *approvals;
+        requests.retain(|_, request| request.ciphertext_ids.iter().any(&cached));
+        held.retain(|_, id| requests.contains_key(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApproverConfig;
+
+    #[test]
+    fn test_held_results_need_an_unexpired_approval() {
+        let token_sha256 = digest::digest(&digest::SHA256, b"secret")
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let service = ApprovalService::new(DecryptionApprovalConfig {
+            enabled: true,
+            approvers: vec![ApproverConfig {
+                name: "oncall".to_string(),
+                token_sha256,
+            }],
+            ..DecryptionApprovalConfig::default()
+        });
+        assert_eq!(service.approver("secret"), Some("oncall"));
+        assert_eq!(service.approver("guess"), None);
+        assert!(service.requires_approval("restricted"));
+        assert!(!service.requires_approval("internal"));
+
+        let ciphertext = Uuid::new_v4();
+        assert!(service
+            .check(Uuid::new_v4(), Some("acme"), 0)
+            .unwrap()
+            .is_none());
+        let request = service.hold(Some("acme"), "restricted", vec![ciphertext], 0);
+        assert!(service.check(ciphertext, Some("acme"), 1).is_err());
+        assert_eq!(service.pending(1).len(), 1);
+
+        service
+            .decide(request.id, "oncall", true, None, 10)
+            .unwrap();
+        assert!(service
+            .check(ciphertext, Some("acme"), 11)
+            .unwrap()
+            .is_some());
+        // Only the requesting tenant, and only within the approval window
+        assert!(service.check(ciphertext, Some("globex"), 11).is_err());
+        assert!(service.check(ciphertext, Some("acme"), 10 + 3600).is_err());
+        assert_eq!(
+            service.get(request.id, 10 + 3600).unwrap().state,
+            ApprovalState::Expired
+        );
+
+        // A decision is final, and an undecided request lapses
+        assert!(service
+            .decide(request.id, "oncall", false, None, 20)
+            .is_err());
+        let lapsed = service.hold(Some("acme"), "restricted", vec![Uuid::new_v4()], 0);
+        assert!(service
+            .decide(lapsed.id, "oncall", true, None, 86_400)
+            .is_err());
+
+        service.prune(|id| *id == ciphertext);
+        assert!(service.get(lapsed.id, 0).is_none());
+        assert!(service
+            .check(ciphertext, Some("acme"), 11)
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,201 @@
+//! Rebuilding metrics lost to a monitoring outage
+//!
+//! Every billed request is written to the billing ledger and, as a
+//! `billing.usage` record, to the audit log, whether or not the metrics
+//! pipeline was up. A backfill reads both for a time range, takes each request
+//! once (the ledger's record when it has one, else the audit trail's), and
+//! rebuilds per-interval request counts, latency percentiles and billed bytes.
+//! The result is pushed to the OTLP collector with every point marked as
+//! backfilled, so dashboards can fill the gap and tell it apart from live data.
+//!
+//! Latency comes from the duration recorded with each request; requests
+//! recorded before durations were kept count towards everything else.
+
+use crate::billing::{UsageTotals, USAGE_AUDIT_ACTION};
+use crate::persistence::{AuditRecord, BillingRecord};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatencyPercentiles {
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+impl LatencyPercentiles {
+    /// Nearest-rank percentiles of `durations`, or None when empty
+    fn of(mut durations: Vec<u64>) -> Option<Self> {
+        durations.sort_unstable();
+        let max_ms = *durations.last()?;
+        let rank = |p: f64| {
+            let index = ((p / 100.0) * durations.len() as f64).ceil() as usize;
+            durations[index.clamp(1, durations.len()) - 1]
+        };
+        Some(Self {
+            p50_ms: rank(50.0),
+            p95_ms: rank(95.0),
+            p99_ms: rank(99.0),
+            max_ms,
+        })
+    }
+}
+
+/// Metrics rebuilt for one interval `[start, end)`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BackfillBucket {
+    pub start: i64,
+    pub end: i64,
+    pub usage: UsageTotals,
+    /// Requests with a recorded duration, which the percentiles cover
+    pub timed_requests: u64,
+    pub latency: Option<LatencyPercentiles>,
+    pub requests_by_route: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillReport {
+    pub from: i64,
+    pub to: i64,
+    pub bucket_seconds: u64,
+    /// Requests taken from the ledger
+    pub ledger_records: usize,
+    /// Requests only the audit trail had
+    pub audit_only_records: usize,
+    pub buckets: Vec<BackfillBucket>,
+}
+
+/// Rebuild metrics for `[from, to)` in buckets of `bucket_seconds`; every
+/// bucket in the range is reported, including those without requests
+pub fn reconstruct(
+    ledger: &[BillingRecord],
+    audit: &[AuditRecord],
+    from: i64,
+    to: i64,
+    bucket_seconds: u64,
+) -> BackfillReport {
+    let in_range = |record: &BillingRecord| (from..to).contains(&record.recorded_at);
+    let mut records: HashMap<Uuid, BillingRecord> = ledger
+        .iter()
+        .filter(|r| in_range(r))
+        .map(|r| (r.request_id, r.clone()))
+        .collect();
+    let ledger_records = records.len();
+    for record in audit
+        .iter()
+        .filter(|r| r.action == USAGE_AUDIT_ACTION)
+        .filter_map(|r| serde_json::from_value::<BillingRecord>(r.details.clone()).ok())
+        .filter(|r| in_range(r))
+    {
+        records.entry(record.request_id).or_insert(record);
+    }
+    let audit_only_records = records.len() - ledger_records;
+
+    let width = bucket_seconds.max(1) as i64;
+    let mut buckets: Vec<(BackfillBucket, Vec<u64>)> = (from..to)
+        .step_by(width as usize)
+        .map(|start| {
+            let bucket = BackfillBucket {
+                start,
+                end: (start + width).min(to),
+                usage: UsageTotals::default(),
+                timed_requests: 0,
+                latency: None,
+                requests_by_route: BTreeMap::new(),
+            };
+            (bucket, Vec::new())
+        })
+        .collect();
+    for record in records.values() {
+        let (bucket, durations) = &mut buckets[((record.recorded_at - from) / width) as usize];
+        bucket.usage.add(record);
+        *bucket
+            .requests_by_route
+            .entry(record.route.clone())
+            .or_default() += 1;
+        durations.extend(record.duration_ms);
+    }
+
+    BackfillReport {
+        from,
+        to,
+        bucket_seconds,
+        ledger_records,
+        audit_only_records,
+        buckets: buckets
+            .into_iter()
+            .map(|(mut bucket, durations)| {
+                bucket.timed_requests = durations.len() as u64;
+                bucket.latency = LatencyPercentiles::of(durations);
+                bucket
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(recorded_at: i64, duration_ms: Option<u64>) -> BillingRecord {
+        BillingRecord {
+            request_id: Uuid::new_v4(),
+            tenant: Some("acme".to_string()),
+            route: "/v1/chat/completions".to_string(),
+            recorded_at,
+            request_bytes: 100,
+            response_bytes: 400,
+            response_wire_bytes: 200,
+            duration_ms,
+        }
+    }
+
+    #[test]
+    fn test_buckets_merge_ledger_and_audit_trail() {
+        let mut ledger: Vec<BillingRecord> = (1..=100u64)
+            .map(|ms| record(100 + (ms % 60) as i64, Some(ms)))
+            .collect();
+        ledger.push(record(170, None));
+        // The ledger lost one write that the audit trail kept
+        let lost = record(175, Some(500));
+        let audit: Vec<AuditRecord> = ledger
+            .iter()
+            .chain([&lost])
+            .map(|r| AuditRecord {
+                id: Uuid::new_v4(),
+                timestamp: r.recorded_at,
+                action: USAGE_AUDIT_ACTION.to_string(),
+                subject: r.request_id.to_string(),
+                details: serde_json::to_value(r).unwrap(),
+            })
+            .collect();
+
+        let report = reconstruct(&ledger, &audit, 100, 220, 60);
+        assert_eq!(report.ledger_records, 101);
+        assert_eq!(report.audit_only_records, 1);
+        assert_eq!(report.buckets.len(), 2);
+
+        let first = &report.buckets[0];
+        assert_eq!(first.usage.requests, 100);
+        assert_eq!(first.usage.response_wire_bytes, 20_000);
+        assert_eq!(
+            first.latency,
+            Some(LatencyPercentiles {
+                p50_ms: 50,
+                p95_ms: 95,
+                p99_ms: 99,
+                max_ms: 100,
+            })
+        );
+
+        // Untimed requests count, but not towards latency
+        let second = &report.buckets[1];
+        assert_eq!((second.start, second.end), (160, 220));
+        assert_eq!(second.usage.requests, 2);
+        assert_eq!(second.timed_requests, 1);
+        assert_eq!(second.latency.as_ref().unwrap().p50_ms, 500);
+        assert_eq!(second.requests_by_route["/v1/chat/completions"], 2);
+    }
+}
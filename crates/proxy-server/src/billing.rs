@@ -0,0 +1,538 @@
+//! Ciphertext size accounting for billing
+//!
+//! Billed routes are metered outside response compression, so the ledger holds
+//! the bytes that actually crossed the wire: the request body as received and
+//! the response body both before and after compression. Every billed request
+//! gets a request id, returned in `x-request-id` together with its byte
+//! counts, and is written both to the billing ledger and, as a `billing.usage`
+//! record, to the audit log. Reconciliation recomputes a time range's totals
+//! from the audit trail and compares them with the ledger request by request.
+//!
+//! Priced with `billing.pricing`, each recorded request also adds to its
+//! tenant's spend for the calendar month, against which monthly spending caps
+//! are enforced before a billed request runs. Its cost is only known once the
+//! response has been sent, so admission uses an estimate: the request's own
+//! price plus the tenant's average response cost this month. Concurrent
+//! requests can therefore overshoot a cap by what is in flight.
+
+use crate::config::{BillingConfig, BillingPricing, SpendingCapConfig};
+use crate::persistence::{AuditRecord, BillingRecord};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// Audit action each billed request is recorded under
+pub const USAGE_AUDIT_ACTION: &str = "billing.usage";
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+pub const REQUEST_BYTES_HEADER: &str = "x-billing-request-bytes";
+pub const RESPONSE_BYTES_HEADER: &str = "x-billing-response-bytes";
+pub const RESPONSE_WIRE_BYTES_HEADER: &str = "x-billing-response-wire-bytes";
+
+/// Response body size before compression, left on the response by the inner
+/// metering layer for the outer one
+#[derive(Debug, Clone, Copy)]
+pub struct UncompressedBytes(pub u64);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub response_wire_bytes: u64,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, record: &BillingRecord) {
+        self.requests += 1;
+        self.request_bytes += record.request_bytes;
+        self.response_bytes += record.response_bytes;
+        self.response_wire_bytes += record.response_wire_bytes;
+    }
+}
+
+/// Ledger and audit trail totals for a time range, and where they disagree
+#[derive(Debug, Clone, Serialize)]
+pub struct Reconciliation {
+    pub from: i64,
+    pub to: i64,
+    pub tenant: Option<String>,
+    pub ledger: UsageTotals,
+    pub audit: UsageTotals,
+    /// Audited requests the ledger has no record of
+    pub missing_from_ledger: Vec<Uuid>,
+    /// Ledger records with no audit record
+    pub missing_from_audit: Vec<Uuid>,
+    /// Requests whose byte counts differ between the two
+    pub mismatched: Vec<Uuid>,
+    pub balanced: bool,
+}
+
+#[derive(Debug)]
+pub struct BillingMeter {
+    config: BillingConfig,
+}
+
+impl BillingMeter {
+    pub fn new(config: BillingConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Whether requests to `path` are metered
+    pub fn is_billed(&self, path: &str) -> bool {
+        self.config.enabled
+            && self
+                .config
+                .routes
+                .iter()
+                .any(|r| path.starts_with(r.as_str()))
+    }
+
+    /// Compare `ledger` with the usage recorded in `audit` over `[from, to)`,
+    /// optionally for one tenant
+    pub fn reconcile(
+        &self,
+        ledger: &[BillingRecord],
+        audit: &[AuditRecord],
+        from: i64,
+        to: i64,
+        tenant: Option<&str>,
+    ) -> Reconciliation {
+        let in_scope =
+            |record: &BillingRecord| tenant.is_none_or(|t| record.tenant.as_deref() == Some(t));
+        let ledger: BTreeMap<Uuid, &BillingRecord> = ledger
+            .iter()
+            .filter(|r| (from..to).contains(&r.recorded_at) && in_scope(r))
+            .map(|r| (r.request_id, r))
+            .collect();
+        let audited: BTreeMap<Uuid, BillingRecord> = audit
+            .iter()
+            .filter(|r| r.action == USAGE_AUDIT_ACTION)
+            .filter_map(|r| serde_json::from_value::<BillingRecord>(r.details.clone()).ok())
+            .filter(|r| (from..to).contains(&r.recorded_at) && in_scope(r))
+            .map(|r| (r.request_id, r))
+            .collect();
+
+        let mut report = Reconciliation {
+            from,
+            to,
+            tenant: tenant.map(str::to_string),
+            ledger: UsageTotals::default(),
+            audit: UsageTotals::default(),
+            missing_from_ledger: vec![],
+            missing_from_audit: vec![],
+            mismatched: vec![],
+            balanced: false,
+        };
+        for (id, record) in &ledger {
+            report.ledger.add(record);
+            match audited.get(id) {
+                None => report.missing_from_audit.push(*id),
+                Some(audited) if audited != *record => report.mismatched.push(*id),
+                Some(_) => {}
+            }
+        }
+        for (id, record) in &audited {
+            report.audit.add(record);
+            if !ledger.contains_key(id) {
+                report.missing_from_ledger.push(*id);
+            }
+        }
+        report.balanced = report.missing_from_ledger.is_empty()
+            && report.missing_from_audit.is_empty()
+            && report.mismatched.is_empty();
+        report
+    }
+}
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+impl BillingPricing {
+    /// Cost of a recorded request
+    pub fn cost(&self, record: &BillingRecord) -> f64 {
+        self.per_request
+            + record.request_bytes as f64 / MIB * self.per_request_mib
+            + record.response_wire_bytes as f64 / MIB * self.per_response_mib
+    }
+}
+
+/// First instant of the calendar month `at` falls in, and of the next one
+fn month_bounds(at: i64) -> (i64, i64) {
+    let date = DateTime::from_timestamp(at, 0).unwrap_or_default();
+    let start = Utc
+        .with_ymd_and_hms(date.year(), date.month(), 1, 0, 0, 0)
+        .unwrap();
+    let (year, month) = match date.month() {
+        12 => (date.year() + 1, 1),
+        month => (date.year(), month + 1),
+    };
+    let end = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).unwrap();
+    (start.timestamp(), end.timestamp())
+}
+
+/// An operator's temporary lifting of a tenant's cap
+#[derive(Debug, Clone, Serialize)]
+pub struct CapOverride {
+    pub granted_by: String,
+    pub reason: String,
+    pub granted_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TenantSpend {
+    /// Start of the month `spent` covers
+    month_start: i64,
+    spent: f64,
+    requests: u64,
+    response_cost: f64,
+    /// Alert thresholds already notified this month
+    alerted: Vec<u32>,
+    cap_override: Option<CapOverride>,
+}
+
+impl TenantSpend {
+    /// Start over when `now` is in a later month than the one tracked
+    fn roll_over(&mut self, now: i64) {
+        let (month_start, _) = month_bounds(now);
+        if self.month_start != month_start {
+            *self = TenantSpend {
+                month_start,
+                cap_override: self.cap_override.take(),
+                ..TenantSpend::default()
+            };
+        }
+    }
+
+    fn active_override(&self, now: i64) -> Option<&CapOverride> {
+        self.cap_override.as_ref().filter(|o| now < o.expires_at)
+    }
+}
+
+/// A tenant's spend this month against its cap
+#[derive(Debug, Clone, Serialize)]
+pub struct SpendingStatus {
+    pub tenant: String,
+    pub month_start: i64,
+    pub spent: f64,
+    pub requests: u64,
+    /// None when the tenant is uncapped
+    pub cap: Option<f64>,
+    pub percent_used: Option<f64>,
+    pub cap_override: Option<CapOverride>,
+}
+
+/// Spend crossing an alert threshold, for the tenant's webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct ThresholdAlert {
+    pub threshold_percent: u32,
+    pub status: SpendingStatus,
+}
+
+#[derive(Debug)]
+pub struct SpendingCaps {
+    config: SpendingCapConfig,
+    pricing: BillingPricing,
+    spend: Mutex<HashMap<String, TenantSpend>>,
+    /// Wakes queued requests when a cap may have been lifted
+    lifted: Notify,
+}
+
+impl SpendingCaps {
+    pub fn new(config: &BillingConfig) -> Self {
+        Self {
+            config: config.spending_caps.clone(),
+            pricing: config.pricing.clone(),
+            spend: Mutex::new(HashMap::new()),
+            lifted: Notify::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn queue_timeout_seconds(&self) -> u64 {
+        self.config.queue_timeout_seconds
+    }
+
+    pub fn lifted(&self) -> &Notify {
+        &self.lifted
+    }
+
+    /// Rebuild this month's spend from the ledger's records of it
+    pub fn restore(&self, records: &[BillingRecord], now: i64) {
+        let (month_start, _) = month_bounds(now);
+        let mut spend = self.spend.lock().unwrap();
+        for record in records.iter().filter(|r| r.recorded_at >= month_start) {
+            let Some(tenant) = &record.tenant else {
+                continue;
+            };
+            let entry = spend.entry(tenant.clone()).or_default();
+            entry.roll_over(now);
+            self.add(entry, record);
+        }
+    }
+
+    fn add(&self, entry: &mut TenantSpend, record: &BillingRecord) {
+        let cost = self.pricing.cost(record);
+        entry.spent += cost;
+        entry.requests += 1;
+        entry.response_cost +=
+            record.response_wire_bytes as f64 / MIB * self.pricing.per_response_mib;
+    }
+
+    /// Expected cost of a request with a body of `request_bytes`
+    pub fn estimate(&self, tenant: &str, request_bytes: u64) -> f64 {
+        let spend = self.spend.lock().unwrap();
+        let average_response = spend
+            .get(tenant)
+            .filter(|s| s.requests > 0)
+            .map_or(0.0, |s| s.response_cost / s.requests as f64);
+        self.pricing.per_request
+            + request_bytes as f64 / MIB * self.pricing.per_request_mib
+            + average_response
+    }
+
+    /// Whether `tenant` may spend `estimate` more under `cap` (0 for uncapped)
+    pub fn admits(&self, tenant: &str, cap: f64, estimate: f64, now: i64) -> bool {
+        if !self.config.enabled || cap <= 0.0 {
+            return true;
+        }
+        let mut spend = self.spend.lock().unwrap();
+        let entry = spend.entry(tenant.to_string()).or_default();
+        entry.roll_over(now);
+        entry.active_override(now).is_some() || entry.spent + estimate <= cap
+    }
+
+    /// Add a billed request to its tenant's spend, returning the alert
+    /// thresholds it crossed
+    pub fn record(&self, record: &BillingRecord, cap: f64, now: i64) -> Vec<ThresholdAlert> {
+        let Some(tenant) = &record.tenant else {
+            return Vec::new();
+        };
+        if !self.config.enabled {
+            return Vec::new();
+        }
+        let mut spend = self.spend.lock().unwrap();
+        let entry = spend.entry(tenant.clone()).or_default();
+        entry.roll_over(now);
+        self.add(entry, record);
+        if cap <= 0.0 {
+            return Vec::new();
+        }
+        let percent_used = entry.spent / cap * 100.0;
+        let mut crossed: Vec<u32> = self
+            .config
+            .alert_thresholds_percent
+            .iter()
+            .copied()
+            .filter(|t| percent_used >= *t as f64 && !entry.alerted.contains(t))
+            .collect();
+        crossed.sort_unstable();
+        crossed.dedup();
+        entry.alerted.extend(&crossed);
+        let status = status_of(tenant, entry, cap, now);
+        crossed
+            .into_iter()
+            .map(|threshold_percent| ThresholdAlert {
+                threshold_percent,
+                status: status.clone(),
+            })
+            .collect()
+    }
+
+    /// Lift `tenant`'s cap until `expires_at`, or for the default override
+    /// length; never past the end of the month
+    pub fn grant_override(
+        &self,
+        tenant: &str,
+        granted_by: &str,
+        reason: String,
+        expires_at: Option<i64>,
+        now: i64,
+    ) -> CapOverride {
+        let (_, month_end) = month_bounds(now);
+        let cap_override = CapOverride {
+            granted_by: granted_by.to_string(),
+            reason,
+            granted_at: now,
+            expires_at: expires_at
+                .unwrap_or(now + self.config.default_override_seconds as i64)
+                .min(month_end),
+        };
+        let mut spend = self.spend.lock().unwrap();
+        let entry = spend.entry(tenant.to_string()).or_default();
+        entry.roll_over(now);
+        entry.cap_override = Some(cap_override.clone());
+        drop(spend);
+        self.lifted.notify_waiters();
+        cap_override
+    }
+
+    /// Withdraw `tenant`'s override, returning it if there was one
+    pub fn revoke_override(&self, tenant: &str) -> Option<CapOverride> {
+        self.spend
+            .lock()
+            .unwrap()
+            .get_mut(tenant)
+            .and_then(|entry| entry.cap_override.take())
+    }
+
+    pub fn status(&self, tenant: &str, cap: f64, now: i64) -> SpendingStatus {
+        let mut spend = self.spend.lock().unwrap();
+        let entry = spend.entry(tenant.to_string()).or_default();
+        entry.roll_over(now);
+        status_of(tenant, entry, cap, now)
+    }
+
+    /// Drop `tenant`'s spend and override, returning whether it had any
+    pub fn forget(&self, tenant: &str) -> bool {
+        self.spend.lock().unwrap().remove(tenant).is_some()
+    }
+
+    /// Tenants with spend or an override this month
+    pub fn tenants(&self) -> Vec<String> {
+        let mut tenants: Vec<String> = self.spend.lock().unwrap().keys().cloned().collect();
+        tenants.sort();
+        tenants
+    }
+}
+
+fn status_of(tenant: &str, entry: &TenantSpend, cap: f64, now: i64) -> SpendingStatus {
+    let cap = (cap > 0.0).then_some(cap);
+    SpendingStatus {
+        tenant: tenant.to_string(),
+        month_start: entry.month_start,
+        spent: entry.spent,
+        requests: entry.requests,
+        cap,
+        percent_used: cap.map(|cap| entry.spent / cap * 100.0),
+        cap_override: entry.active_override(now).cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconciliation_recomputes_totals_from_audit_trail() {
+        let meter = BillingMeter::new(BillingConfig {
+            enabled: true,
+            ..BillingConfig::default()
+        });
+        assert!(meter.is_billed("/v1/chat/completions"));
+        assert!(!meter.is_billed("/v1/admin/audit"));
+
+        let record = |tenant: &str, recorded_at, wire| BillingRecord {
+            request_id: Uuid::new_v4(),
+            tenant: Some(tenant.to_string()),
+            route: "/v1/encrypt".to_string(),
+            recorded_at,
+            request_bytes: 100,
+            response_bytes: 4000,
+            response_wire_bytes: wire,
+            duration_ms: None,
+        };
+        let audit_of = |record: &BillingRecord| AuditRecord {
+            id: Uuid::new_v4(),
+            timestamp: record.recorded_at,
+            action: USAGE_AUDIT_ACTION.to_string(),
+            subject: record.request_id.to_string(),
+            details: serde_json::to_value(record).unwrap(),
+        };
+        let ledger = vec![
+            record("acme", 10, 1500),
+            record("acme", 20, 1600),
+            record("other", 20, 900),
+            record("acme", 99, 1700),
+        ];
+        let audit: Vec<AuditRecord> = ledger.iter().map(audit_of).collect();
+
+        let report = meter.reconcile(&ledger, &audit, 0, 50, Some("acme"));
+        assert!(report.balanced);
+        assert_eq!(report.ledger, report.audit);
+        assert_eq!(report.ledger.requests, 2);
+        assert_eq!(report.ledger.response_wire_bytes, 3100);
+
+        // A tampered ledger entry and an unaudited one are both called out
+        let mut tampered = ledger.clone();
+        tampered[0].response_wire_bytes = 15;
+        let unaudited = record("acme", 30, 1000);
+        tampered.push(unaudited.clone());
+        let report = meter.reconcile(&tampered, &audit, 0, 50, None);
+        assert!(!report.balanced);
+        assert_eq!(report.mismatched, vec![ledger[0].request_id]);
+        assert_eq!(report.missing_from_audit, vec![unaudited.request_id]);
+        assert_eq!(report.audit.requests, 3);
+        assert_eq!(report.audit.response_wire_bytes, 4000);
+    }
+
+    #[test]
+    fn test_spending_caps_alert_and_block_until_overridden() {
+        let caps = SpendingCaps::new(&BillingConfig {
+            enabled: true,
+            pricing: BillingPricing {
+                per_request: 1.0,
+                per_request_mib: 0.0,
+                per_response_mib: 0.0,
+            },
+            spending_caps: SpendingCapConfig {
+                enabled: true,
+                ..SpendingCapConfig::default()
+            },
+            ..BillingConfig::default()
+        });
+        // 2027-03-10T00:00:00Z
+        let now = 1_804_636_800;
+        let record = || BillingRecord {
+            request_id: Uuid::new_v4(),
+            tenant: Some("acme".to_string()),
+            route: "/v1/encrypt".to_string(),
+            recorded_at: now,
+            request_bytes: 100,
+            response_bytes: 4000,
+            response_wire_bytes: 1500,
+            duration_ms: None,
+        };
+
+        let crossed: Vec<Vec<u32>> = (0..10)
+            .map(|_| {
+                caps.record(&record(), 10.0, now)
+                    .iter()
+                    .map(|a| a.threshold_percent)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(crossed[7], [80]);
+        assert_eq!(crossed[9], [95, 100]);
+        assert!(crossed[8].is_empty());
+
+        let estimate = caps.estimate("acme", 100);
+        assert_eq!(estimate, 1.0);
+        assert!(!caps.admits("acme", 10.0, estimate, now));
+        let status = caps.status("acme", 10.0, now);
+        assert_eq!(status.spent, 10.0);
+        assert_eq!(status.percent_used, Some(100.0));
+        assert!(caps.admits("acme", 0.0, estimate, now));
+        assert!(caps.admits("globex", 10.0, estimate, now));
+
+        let granted = caps.grant_override("acme", "oncall", "incident".to_string(), None, now);
+        assert_eq!(granted.expires_at, now + 86_400);
+        assert!(caps.admits("acme", 10.0, estimate, now));
+        assert!(!caps.admits("acme", 10.0, estimate, now + 86_400));
+
+        // Spend starts over with the month
+        let (_, next_month) = month_bounds(now);
+        assert!(caps.admits("acme", 10.0, estimate, next_month));
+        assert_eq!(caps.status("acme", 10.0, next_month).spent, 0.0);
+    }
+}
@@ -0,0 +1,143 @@
+//! Soak/load generator for the FHE LLM Proxy
+//!
+//! `loadgen --target http://127.0.0.1:8080 --concurrency 16 --duration 600 \
+//!     --mix interactive=6,batch=1,streaming=3 --slo-p99-ms 2000 --max-error-rate 0.01`
+//!
+//! Prints a report per traffic profile and exits with status 1 when the run
+//! misses any SLO threshold given on the command line, 2 on invalid arguments.
+
+use proxy_server::loadgen::{self, LoadReport, LoadgenConfig, TrafficMix};
+use proxy_server::{Error, Result};
+use std::time::Duration;
+
+const USAGE: &str = "usage: loadgen [--target URL] [--concurrency N] [--duration SECONDS]
+               [--mix interactive=W,batch=W,streaming=W] [--provider NAME] [--model NAME]
+               [--tenant ID] [--api-key KEY] [--workload-tag TAG] [--timeout SECONDS]
+               [--slo-p99-ms MS] [--slo-p95-ms MS] [--max-error-rate RATE]
+               [--min-throughput RPS] [--json]";
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()))
+        .with_target(false)
+        .init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (config, json) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    tracing::info!(
+        "Driving {} workers at {} for {}s",
+        config.concurrency,
+        config.target,
+        config.duration.as_secs()
+    );
+    let report = match loadgen::run(config).await {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::error!("Load run failed: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(out) => println!("{}", out),
+            Err(e) => tracing::error!("Failed to serialize report: {}", e),
+        }
+    } else {
+        print_report(&report);
+    }
+    if !report.passed() {
+        std::process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(LoadgenConfig, bool)> {
+    let mut config = LoadgenConfig {
+        api_key: std::env::var("FHE_PROXY_API_KEY").ok(),
+        ..LoadgenConfig::default()
+    };
+    let mut json = false;
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| Error::Config(format!("{} needs a value", flag)))
+        };
+        match flag.as_str() {
+            "--json" => json = true,
+            "--target" => config.target = value()?.clone(),
+            "--concurrency" => config.concurrency = number(flag, value()?)?,
+            "--duration" => config.duration = Duration::from_secs(number(flag, value()?)?),
+            "--mix" => config.mix = TrafficMix::parse(value()?)?,
+            "--provider" => config.provider = value()?.clone(),
+            "--model" => config.model = value()?.clone(),
+            "--tenant" => config.tenant = value()?.clone(),
+            "--api-key" => config.api_key = Some(value()?.clone()),
+            "--workload-tag" => config.workload_tag = Some(value()?.clone()),
+            "--timeout" => config.request_timeout = Duration::from_secs(number(flag, value()?)?),
+            "--slo-p99-ms" => config.slo.p99_ms = Some(number(flag, value()?)?),
+            "--slo-p95-ms" => config.slo.p95_ms = Some(number(flag, value()?)?),
+            "--max-error-rate" => config.slo.max_error_rate = Some(number(flag, value()?)?),
+            "--min-throughput" => config.slo.min_throughput_rps = Some(number(flag, value()?)?),
+            other => return Err(Error::Config(format!("Unknown option: {}", other))),
+        }
+    }
+    Ok((config, json))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| Error::Config(format!("{} expects a number, got {:?}", flag, value)))
+}
+
+fn print_report(report: &LoadReport) {
+    println!(
+        "{} workers against {} for {:.1}s",
+        report.concurrency, report.target, report.elapsed_seconds
+    );
+    println!(
+        "{:<12} {:>9} {:>7} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "profile", "requests", "errors", "rps", "p50 ms", "p95 ms", "p99 ms", "max ms", "budget"
+    );
+    let rows = report
+        .profiles
+        .iter()
+        .map(|(profile, summary)| (profile.to_string(), summary))
+        .chain(std::iter::once(("overall".to_string(), &report.overall)));
+    for (name, summary) in rows {
+        let budget = summary
+            .error_budget_consumed
+            .map_or_else(|| "-".to_string(), |used| format!("{:.0}%", used * 100.0));
+        println!(
+            "{:<12} {:>9} {:>7} {:>8.1} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            name,
+            summary.requests,
+            summary.errors,
+            summary.throughput_rps,
+            summary.p50_ms,
+            summary.p95_ms,
+            summary.p99_ms,
+            summary.max_ms,
+            budget
+        );
+    }
+    for (kind, count) in &report.overall.error_kinds {
+        println!("  {} errors: {}", kind, count);
+    }
+    if report.passed() {
+        println!("SLO: pass");
+    } else {
+        for violation in &report.violations {
+            println!("SLO violation: {}", violation);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Host clock health
+//!
+//! Replay protection and federation envelopes compare timestamps, which goes
+//! wrong once the host clock drifts. The monitor asks each configured NTP
+//! server for the time over SNTP and takes the median offset as the host's
+//! skew (positive when the host runs fast). While skew is measured, replay
+//! and federation windows are widened by it, within
+//! `max_window_adjustment_seconds`, and tightened again as it shrinks; a skew
+//! beyond `max_skew_ms` fails readiness until the clock is corrected.
+
+use crate::config::ClockHealthConfig;
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+const NTP_PACKET_LEN: usize = 48;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NtpServerStatus {
+    pub server: String,
+    /// Reference time minus host time
+    pub offset_ms: Option<f64>,
+    pub round_trip_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClockStatus {
+    pub enabled: bool,
+    /// Host time minus reference time; None until a server has answered
+    pub skew_ms: Option<f64>,
+    pub measured_at: Option<i64>,
+    /// Added to replay and federation windows
+    pub window_adjustment_ms: u64,
+    pub healthy: bool,
+    pub servers: Vec<NtpServerStatus>,
+}
+
+#[derive(Debug)]
+pub struct ClockMonitor {
+    config: ClockHealthConfig,
+    status: RwLock<ClockStatus>,
+}
+
+impl ClockMonitor {
+    pub fn new(config: ClockHealthConfig) -> Self {
+        let status = ClockStatus {
+            enabled: config.enabled,
+            skew_ms: None,
+            measured_at: None,
+            window_adjustment_ms: 0,
+            healthy: true,
+            servers: Vec::new(),
+        };
+        Self {
+            config,
+            status: RwLock::new(status),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.config.poll_interval_seconds)
+    }
+
+    /// Query every server and record the result
+    pub async fn measure(&self) -> ClockStatus {
+        let timeout = Duration::from_millis(self.config.query_timeout_ms);
+        let mut queries = tokio::task::JoinSet::new();
+        for (index, server) in self.config.ntp_servers.iter().cloned().enumerate() {
+            queries.spawn(async move {
+                let answer = query_server(&server, timeout).await;
+                (index, server, answer)
+            });
+        }
+        let mut answers = Vec::new();
+        while let Some(Ok(answer)) = queries.join_next().await {
+            answers.push(answer);
+        }
+        answers.sort_by_key(|(index, _, _)| *index);
+        let servers = answers
+            .into_iter()
+            .map(|(_, server, answer)| match answer {
+                Ok((offset_ms, round_trip_ms)) => NtpServerStatus {
+                    server,
+                    offset_ms: Some(offset_ms),
+                    round_trip_ms: Some(round_trip_ms),
+                    error: None,
+                },
+                Err(e) => NtpServerStatus {
+                    server,
+                    offset_ms: None,
+                    round_trip_ms: None,
+                    error: Some(e.to_string()),
+                },
+            })
+            .collect();
+        self.record(servers, chrono::Utc::now().timestamp())
+    }
+
+    /// Derive skew, window adjustment and health from one round of answers.
+    /// A round no server answered keeps the previous skew.
+    pub fn record(&self, servers: Vec<NtpServerStatus>, now: i64) -> ClockStatus {
+        let mut offsets: Vec<f64> = servers.iter().filter_map(|s| s.offset_ms).collect();
+        let mut status = self.status.write().unwrap();
+        status.servers = servers;
+        if offsets.is_empty() {
+            log::warn!("No NTP server answered; keeping the last measured clock skew");
+            return status.clone();
+        }
+        offsets.sort_by(f64::total_cmp);
+        let middle = offsets.len() / 2;
+        let offset = if offsets.len().is_multiple_of(2) {
+            (offsets[middle - 1] + offsets[middle]) / 2.0
+        } else {
+            offsets[middle]
+        };
+        let skew_ms = -offset;
+        let healthy = skew_ms.abs() <= self.config.max_skew_ms as f64;
+        if !healthy && status.healthy {
+            log::error!(
+                "Host clock is {:.0}ms off, beyond the {}ms allowed; failing readiness",
+                skew_ms,
+                self.config.max_skew_ms
+            );
+        }
+        status.skew_ms = Some(skew_ms);
+        status.measured_at = Some(now);
+        status.window_adjustment_ms =
+            (skew_ms.abs().ceil() as u64).min(self.config.max_window_adjustment_seconds * 1000);
+        status.healthy = healthy;
+        status.clone()
+    }
+
+    /// How much replay and federation windows are widened by
+    pub fn window_adjustment(&self) -> Duration {
+        Duration::from_millis(self.status.read().unwrap().window_adjustment_ms)
+    }
+
+    /// Whether the host clock is within the allowed skew; always true while
+    /// monitoring is off
+    pub fn is_healthy(&self) -> bool {
+        !self.config.enabled || self.status.read().unwrap().healthy
+    }
+
+    pub fn status(&self) -> ClockStatus {
+        self.status.read().unwrap().clone()
+    }
+}
+
+fn unix_seconds() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+fn to_ntp(unix: f64) -> [u8; 8] {
+    let ntp = unix + NTP_UNIX_OFFSET;
+    let seconds = ntp.trunc() as u32;
+    let fraction = (ntp.fract() * 4_294_967_296.0) as u32;
+    let mut bytes = [0; 8];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    bytes[4..].copy_from_slice(&fraction.to_be_bytes());
+    bytes
+}
+
+fn from_ntp(bytes: &[u8]) -> f64 {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as f64;
+    seconds + fraction / 4_294_967_296.0 - NTP_UNIX_OFFSET
+}
+
+/// Offset and round trip, in milliseconds, from a server's reply to a
+/// request sent with transmit timestamp `sent` and received at `received`
+/// (Unix seconds)
+fn sntp_offset(sent: [u8; 8], reply: &[u8], received: f64) -> Result<(f64, f64)> {
+    let invalid = |message: &str| Error::Validation(format!("NTP reply {}", message));
+    if reply.len() < NTP_PACKET_LEN {
+        return Err(invalid("is too short"));
+    }
+    if reply[0] & 0x07 != 4 {
+        return Err(invalid("is not in server mode"));
+    }
+    if reply[1] == 0 {
+        return Err(invalid("is a kiss-o'-death"));
+    }
+    if reply[24..32] != sent {
+        return Err(invalid("does not answer our request"));
+    }
+    let t0 = from_ntp(&sent);
+    let t1 = from_ntp(&reply[32..40]);
+    let t2 = from_ntp(&reply[40..48]);
+    let offset = ((t1 - t0) + (t2 - received)) / 2.0;
+    let round_trip = (received - t0) - (t2 - t1);
+    Ok((offset * 1000.0, round_trip.max(0.0) * 1000.0))
+}
+
+async fn query_server(server: &str, timeout: Duration) -> Result<(f64, f64)> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(server).await?;
+    let mut request = [0u8; NTP_PACKET_LEN];
+    // Leap indicator 0, version 4, client mode
+    request[0] = 0x23;
+    let sent = to_ntp(unix_seconds());
+    request[40..48].copy_from_slice(&sent);
+    socket.send(&request).await?;
+
+    let mut reply = [0u8; NTP_PACKET_LEN];
+    let len = tokio::time::timeout(timeout, socket.recv(&mut reply))
+        .await
+        .map_err(|_| Error::Timeout(format!("No NTP reply from {}", server)))??;
+    sntp_offset(sent, &reply[..len], unix_seconds())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skew_from_sntp_replies_drives_windows_and_health() {
+        // The server's clock is 2.5s ahead of ours; 40ms each way on the wire
+        let t0 = 1_800_000_000.0;
+        let sent = to_ntp(t0);
+        let mut reply = [0u8; NTP_PACKET_LEN];
+        reply[0] = 0x24;
+        reply[1] = 2;
+        reply[24..32].copy_from_slice(&sent);
+        reply[32..40].copy_from_slice(&to_ntp(t0 + 0.040 + 2.5));
+        reply[40..48].copy_from_slice(&to_ntp(t0 + 0.041 + 2.5));
+        let (offset, round_trip) = sntp_offset(sent, &reply, t0 + 0.081).unwrap();
+        assert!((offset - 2500.0).abs() < 0.01, "{}", offset);
+        assert!((round_trip - 80.0).abs() < 0.01, "{}", round_trip);
+        reply[24] ^= 1;
+        assert!(sntp_offset(sent, &reply, t0 + 0.081).is_err());
+
+        let monitor = ClockMonitor::new(ClockHealthConfig {
+            enabled: true,
+            max_window_adjustment_seconds: 2,
+            max_skew_ms: 1000,
+            ..ClockHealthConfig::default()
+        });
+        let answer = |offset_ms: Option<f64>| NtpServerStatus {
+            server: "ntp.example.com:123".to_string(),
+            offset_ms,
+            round_trip_ms: offset_ms.map(|_| 20.0),
+            error: None,
+        };
+        // The median ignores one server far off the others
+        let status = monitor.record(
+            vec![
+                answer(Some(-300.0)),
+                answer(Some(-310.0)),
+                answer(Some(9000.0)),
+            ],
+            0,
+        );
+        assert_eq!(status.skew_ms, Some(300.0));
+        assert_eq!(monitor.window_adjustment(), Duration::from_millis(300));
+        assert!(monitor.is_healthy());
+
+        monitor.record(vec![answer(Some(-4000.0))], 60);
+        assert_eq!(monitor.window_adjustment(), Duration::from_secs(2));
+        assert!(!monitor.is_healthy());
+
+        // Unanswered rounds keep the last skew
+        monitor.record(vec![answer(None)], 120);
+        assert_eq!(monitor.status().skew_ms, Some(4000.0));
+        monitor.record(vec![answer(Some(15.0))], 180);
+        assert!(monitor.is_healthy());
+        assert_eq!(monitor.window_adjustment(), Duration::from_millis(15));
+    }
+}
@@ -0,0 +1,5709 @@
+//! Configuration management for FHE LLM Proxy
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Current layout of the configuration document. Bump it together with a new
+/// entry in `CONFIG_MIGRATIONS` whenever a key is renamed, moved or removed.
+pub const CONFIG_SCHEMA_VERSION: u32 = 2;
+
+fn current_schema_version() -> u32 {
+    CONFIG_SCHEMA_VERSION
+}
+
+/// Main configuration structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Layout version of the document; older documents are migrated on load
+    #[serde(default = "current_schema_version")]
+    pub schema_version: u32,
+    pub server: ServerConfig,
+    pub encryption: EncryptionConfig,
+    pub llm: LlmConfig,
+    pub privacy: PrivacyConfig,
+    pub gpu: GpuConfig,
+    pub monitoring: MonitoringConfig,
+    pub scaling: ScalingConfig,
+    pub performance: PerformanceConfig,
+    #[serde(default)]
+    pub tenants: TenantsConfig,
+    #[serde(default)]
+    pub federation: FederationConfig,
+    #[serde(default)]
+    pub persistence: PersistenceConfig,
+    #[serde(default)]
+    pub sessions: SessionLimitsConfig,
+    #[serde(default)]
+    pub documents: DocumentIngestionConfig,
+    #[serde(default)]
+    pub validation: ValidationPipelineConfig,
+    #[serde(default)]
+    pub experiments: ExperimentsConfig,
+    #[serde(default)]
+    pub aggregation: AggregationConfig,
+    #[serde(default)]
+    pub conversations: ConversationConfig,
+    #[serde(default)]
+    pub escrow: EscrowConfig,
+    #[serde(default)]
+    pub billing: BillingConfig,
+    #[serde(default)]
+    pub disaster_recovery: DisasterRecoveryConfig,
+    #[serde(default)]
+    pub flags: FeatureFlagsConfig,
+    #[serde(default)]
+    pub transactions: TransactionConfig,
+    #[serde(default)]
+    pub response_metadata: ResponseMetadataConfig,
+    #[serde(default)]
+    pub approvals: DecryptionApprovalConfig,
+    #[serde(default)]
+    pub strict_security: StrictSecurityConfig,
+    #[serde(default)]
+    pub api_versions: ApiVersionsConfig,
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+    #[serde(default)]
+    pub honeytokens: HoneytokenConfig,
+    #[serde(default)]
+    pub access: AccessConfig,
+}
+
+/// Regional failover drills and the recovery plan targets they are held to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisasterRecoveryConfig {
+    pub drills_enabled: bool,
+    /// Recovery time objective: standby regions must all be serving within
+    /// this long of traffic leaving this region
+    pub rto_seconds: u64,
+    /// Recovery point objective: maximum age of the last session replication
+    /// round when the region is lost
+    pub rpo_seconds: u64,
+    pub health_poll_interval_ms: u64,
+    pub health_timeout_seconds: u64,
+    /// A drill gives up waiting for standbys after this long
+    pub max_drill_seconds: u64,
+    /// Finished drill reports kept for `/v1/admin/dr/drills`
+    pub max_reports: usize,
+}
+
+impl Default for DisasterRecoveryConfig {
+    fn default() -> Self {
+        Self {
+            drills_enabled: false,
+            rto_seconds: 60,
+            rpo_seconds: 60,
+            health_poll_interval_ms: 1000,
+            health_timeout_seconds: 5,
+            max_drill_seconds: 300,
+            max_reports: 20,
+        }
+    }
+}
+
+/// Ciphertext byte metering of billed routes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BillingConfig {
+    pub enabled: bool,
+    /// Path prefixes whose requests are metered
+    pub routes: Vec<String>,
+    pub pricing: BillingPricing,
+    pub spending_caps: SpendingCapConfig,
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            routes: [
+                "/v1/encrypt",
+                "/v1/decrypt",
+                "/v1/chat",
+                "/v1/concatenate",
+                "/v1/ciphertext",
+                "/v1/documents",
+                "/v1/batch",
+                "/v1/aggregations",
+                "/v1/transactions",
+            ]
+            .iter()
+            .map(|r| r.to_string())
+            .collect(),
+            pricing: BillingPricing::default(),
+            spending_caps: SpendingCapConfig::default(),
+        }
+    }
+}
+
+/// Prices turning metered requests into cost, in the tenant's billing currency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BillingPricing {
+    pub per_request: f64,
+    /// Per MiB of request body received
+    pub per_request_mib: f64,
+    /// Per MiB of response body sent, after compression
+    pub per_response_mib: f64,
+}
+
+impl Default for BillingPricing {
+    fn default() -> Self {
+        Self {
+            per_request: 0.0001,
+            per_request_mib: 0.01,
+            per_response_mib: 0.01,
+        }
+    }
+}
+
+/// Monthly spending caps enforced on billed routes. While enabled, billed
+/// requests without tenant credentials are refused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpendingCapConfig {
+    pub enabled: bool,
+    /// Cap per calendar month (UTC) for tenants that set none; 0 leaves them
+    /// uncapped
+    pub default_monthly_cap: f64,
+    pub policy: SpendingCapPolicy,
+    /// How long a queued request waits for the cap to be lifted
+    pub queue_timeout_seconds: u64,
+    /// Percentages of the cap at which the tenant's webhook is notified
+    pub alert_thresholds_percent: Vec<u32>,
+    pub webhook_url: Option<String>,
+    /// Length of an override granted without an expiry; it never outlasts the
+    /// month it is granted in
+    pub default_override_seconds: u64,
+}
+
+impl Default for SpendingCapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            default_monthly_cap: 0.0,
+            policy: SpendingCapPolicy::Reject,
+            queue_timeout_seconds: 30,
+            alert_thresholds_percent: vec![80, 95, 100],
+            webhook_url: None,
+            default_override_seconds: 86_400,
+        }
+    }
+}
+
+/// What happens to a request that would take a tenant over its cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendingCapPolicy {
+    Reject,
+    /// Hold the request until the cap is lifted or the queue timeout passes
+    Queue,
+}
+
+/// Server configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub workers: usize,
+    pub max_connections: u32,
+    pub request_timeout_seconds: u64,
+    #[serde(default)]
+    pub mirroring: MirroringConfig,
+    #[serde(default)]
+    pub transforms: TransformConfig,
+    #[serde(default)]
+    pub connections: ConnectionGuardConfig,
+    #[serde(default)]
+    pub systemd: SystemdConfig,
+    #[serde(default)]
+    pub deadline_budget: DeadlineBudgetConfig,
+}
+
+/// Split of a request's deadline across the hops it passes through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeadlineBudgetConfig {
+    pub enabled: bool,
+    /// Relative shares of the deadline; time a hop leaves unused carries over
+    /// to the hops after it, and the provider gets whatever remains
+    pub validation_share: f64,
+    pub queue_share: f64,
+    pub fhe_share: f64,
+    pub provider_share: f64,
+    /// Fail with a deadline-exceeded error rather than call a provider with less than this left
+    pub min_provider_budget_ms: u64,
+}
+
+impl Default for DeadlineBudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            validation_share: 0.05,
+            queue_share: 0.15,
+            fhe_share: 0.3,
+            provider_share: 0.5,
+            min_provider_budget_ms: 250,
+        }
+    }
+}
+
+/// Integration with systemd, used only when started by it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemdConfig {
+    /// Report readiness, status and shutdown over `NOTIFY_SOCKET`
+    pub notify: bool,
+    /// Ping the watchdog while live when the unit sets `WatchdogSec=`
+    pub watchdog: bool,
+    /// Serve on a socket passed by socket activation instead of binding
+    pub socket_activation: bool,
+    /// How often readiness and health are checked for notifications
+    pub readiness_poll_ms: u64,
+}
+
+impl Default for SystemdConfig {
+    fn default() -> Self {
+        Self {
+            notify: true,
+            watchdog: true,
+            socket_activation: true,
+            readiness_poll_ms: 500,
+        }
+    }
+}
+
+/// Connection-level protections against exhaustion and slowloris attacks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionGuardConfig {
+    pub enabled: bool,
+    /// Open connections per peer address; 0 for no cap. Behind a load
+    /// balancer every connection comes from its address.
+    pub max_connections_per_ip: usize,
+    /// In-flight requests per API key, across all connections; 0 for no cap
+    pub max_requests_per_api_key: usize,
+    /// A request's headers must arrive within this
+    pub header_read_timeout_seconds: u64,
+    /// Longest pause between chunks of a request body
+    pub body_read_timeout_seconds: u64,
+    /// Bodies at least this large must keep up `min_upload_bytes_per_second`
+    pub min_throughput_body_bytes: u64,
+    pub min_upload_bytes_per_second: u64,
+    /// Throughput is not enforced during the first seconds of an upload
+    pub throughput_grace_seconds: u64,
+}
+
+impl Default for ConnectionGuardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_connections_per_ip: 256,
+            max_requests_per_api_key: 64,
+            header_read_timeout_seconds: 10,
+            body_read_timeout_seconds: 30,
+            min_throughput_body_bytes: 1024 * 1024,
+            min_upload_bytes_per_second: 16 * 1024,
+            throughput_grace_seconds: 5,
+        }
+    }
+}
+
+/// Declarative header and JSON field rewrites, applied to requests before they
+/// reach the handler (and so before provider dispatch) and to responses before
+/// they reach the client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransformConfig {
+    pub enabled: bool,
+    /// Larger bodies are passed through without field rewrites
+    pub max_body_bytes: usize,
+    pub rules: Vec<TransformRule>,
+}
+
+impl Default for TransformConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_body_bytes: 2 * 1024 * 1024,
+            rules: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransformStage {
+    Request,
+    Response,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransformRule {
+    pub name: String,
+    /// Path prefix the rule applies to, e.g. `/v1/chat`
+    pub route: String,
+    /// Tenants the rule applies to; empty for every tenant
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    pub stage: TransformStage,
+    pub operations: Vec<TransformOperation>,
+}
+
+/// A rewrite a rule may perform. Header values may use `{tenant}`; field
+/// paths are dot-separated keys into a JSON body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformOperation {
+    SetHeader {
+        name: String,
+        value: String,
+    },
+    RemoveHeader {
+        name: String,
+    },
+    RenameHeader {
+        from: String,
+        to: String,
+    },
+    SetField {
+        path: String,
+        value: serde_json::Value,
+    },
+    RemoveField {
+        path: String,
+    },
+    RenameField {
+        from: String,
+        to: String,
+    },
+}
+
+/// Fire-and-forget copies of production completion requests sent to a staging proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MirroringConfig {
+    pub enabled: bool,
+    /// Base URL of the staging proxy, e.g. `https://fhe-proxy.staging.example`
+    pub target_url: String,
+    /// Share of eligible requests mirrored, 0-100
+    pub sample_percent: f64,
+    /// Extra request headers, typically the staging API key
+    pub headers: HashMap<String, String>,
+    /// Mirrored requests waiting to be sent; further ones are dropped
+    pub queue_size: usize,
+    pub max_in_flight: usize,
+    pub timeout_seconds: u64,
+    /// Staging side: accept mirrored requests that carry their ciphertext inline
+    pub accept_mirrored: bool,
+}
+
+impl Default for MirroringConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_url: String::new(),
+            sample_percent: 1.0,
+            headers: HashMap::new(),
+            queue_size: 1000,
+            max_in_flight: 16,
+            timeout_seconds: 10,
+            accept_mirrored: false,
+        }
+    }
+}
+
+/// Encryption parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionConfig {
+    pub poly_modulus_degree: usize,
+    pub coeff_modulus_bits: Vec<u64>,
+    pub scale_bits: u64,
+    pub security_level: u8,
+    #[serde(default)]
+    pub response_signing: ResponseSigningConfig,
+    #[serde(default)]
+    pub decryption_delegation: DecryptionDelegationConfig,
+}
+
+/// Signing of encrypted response envelopes so clients can detect tampering in transit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResponseSigningConfig {
+    pub enabled: bool,
+    /// PKCS#8 Ed25519 deployment key; generated on first start if missing.
+    /// Without a path the key is ephemeral and changes on every restart.
+    pub key_path: Option<String>,
+    /// How long a rotated-out key stays published so in-flight responses still verify
+    pub rollover_seconds: u64,
+}
+
+impl Default for ResponseSigningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_path: None,
+            rollover_seconds: 86400,
+        }
+    }
+}
+
+/// Decryption with secret keys held in an HSM or cloud KMS instead of proxy memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DecryptionDelegationConfig {
+    pub enabled: bool,
+    /// Base URL of the cloud KMS endpoint or PKCS#11 gateway
+    pub endpoint: String,
+    /// Name of a client's secret key in the HSM; `{client_id}` is substituted
+    pub key_handle_template: String,
+    /// Environment variable holding the bearer token for the endpoint
+    pub auth_token_env: Option<String>,
+    pub timeout_ms: u64,
+    /// What to do when the HSM is unreachable
+    pub fallback: DelegationFallback,
+    /// How long an unreachable HSM is skipped before it is tried again
+    pub unreachable_cooldown_seconds: u64,
+    /// Default for tenants without a `require_delegated_decryption` override
+    pub required: bool,
+}
+
+/// Behaviour when the decryption HSM cannot be reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DelegationFallback {
+    /// Refuse the decryption
+    FailClosed,
+    /// Decrypt with in-process keys, except for tenants that require delegation
+    Local,
+}
+
+impl Default for DecryptionDelegationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: String::new(),
+            key_handle_template: "fhe-client-{client_id}".to_string(),
+            auth_token_env: None,
+            timeout_ms: 2000,
+            fallback: DelegationFallback::FailClosed,
+            unreachable_cooldown_seconds: 30,
+            required: false,
+        }
+    }
+}
+
+/// LLM provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmConfig {
+    pub provider: String,
+    pub endpoint: String,
+    pub timeout_seconds: u64,
+    pub max_retries: u32,
+    pub openai_api_key: Option<String>,
+    pub anthropic_api_key: Option<String>,
+    pub custom_providers: Vec<CustomProvider>,
+    #[serde(default)]
+    pub recording: ProviderRecordingConfig,
+    #[serde(default)]
+    pub model_governance: ModelGovernanceConfig,
+    #[serde(default)]
+    pub resume: ProviderResumeConfig,
+    #[serde(default)]
+    pub error_retry: ProviderErrorRetryConfig,
+    #[serde(default)]
+    pub timeout_calibration: TimeoutCalibrationConfig,
+    #[serde(default)]
+    pub quota: ProviderQuotaConfig,
+    /// Self-hosted servers speaking the OpenAI API (vLLM, TGI), each
+    /// registered as a provider under its own name
+    #[serde(default)]
+    pub openai_compatible: Vec<OpenAiCompatibleServer>,
+    #[serde(default)]
+    pub warm_hints: WarmHintsConfig,
+}
+
+/// Warm-pool and priority hints sent with provider calls by tenant SLA class,
+/// and the cold-start latency comparison that shows whether they pay off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarmHintsConfig {
+    pub enabled: bool,
+    /// A call to a model idle at least this long counts as a cold start
+    pub idle_threshold_seconds: u64,
+    /// Cold-start latencies kept per model, for hinted and unhinted calls each
+    pub window_size: usize,
+    /// Hints by provider name
+    pub providers: BTreeMap<String, SlaClassHints>,
+}
+
+impl Default for WarmHintsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_threshold_seconds: 300,
+            window_size: 100,
+            providers: BTreeMap::new(),
+        }
+    }
+}
+
+/// A provider's hints per SLA class; classes without one are sent unhinted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlaClassHints {
+    pub gold: Option<WarmHint>,
+    pub silver: Option<WarmHint>,
+    pub bronze: Option<WarmHint>,
+}
+
+impl SlaClassHints {
+    pub fn for_class(&self, class: SlaClass) -> Option<&WarmHint> {
+        match class {
+            SlaClass::Gold => self.gold.as_ref(),
+            SlaClass::Silver => self.silver.as_ref(),
+            SlaClass::Bronze => self.bronze.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarmHint {
+    /// Scheduling priority, lower first; self-hosted servers that take a
+    /// `priority` field (vLLM) only
+    pub priority: Option<i32>,
+    /// `service_tier` of the hosted OpenAI and Anthropic APIs
+    pub service_tier: Option<String>,
+    /// Extra request headers, e.g. a gateway's warm pool selector
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Local request and token budgets per provider key, kept in step with the
+/// provider's rate-limit headers so calls are held back before the provider
+/// starts refusing them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderQuotaConfig {
+    pub enabled: bool,
+    /// Share of each budget held back; calls that would dip into it wait for
+    /// the budget to reset
+    pub reserve_fraction: f64,
+    /// Longest a call waits for a reset; past this it moves to the next provider
+    pub max_queue_ms: u64,
+    /// Completion tokens assumed for requests that set no `max_tokens`
+    pub default_completion_tokens: u64,
+}
+
+impl Default for ProviderQuotaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reserve_fraction: 0.05,
+            max_queue_ms: 2000,
+            default_completion_tokens: 512,
+        }
+    }
+}
+
+/// Per provider/model timeouts derived from observed latency; until enough
+/// samples exist, `timeout_seconds` applies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutCalibrationConfig {
+    pub enabled: bool,
+    /// Latency percentile the timeout is based on, in (0, 1]
+    pub percentile: f64,
+    /// Multiplier applied to the percentile latency
+    pub safety_margin: f64,
+    pub min_timeout_ms: u64,
+    pub max_timeout_ms: u64,
+    /// Successful calls kept per provider/model
+    pub window_size: usize,
+    pub min_samples: usize,
+}
+
+impl Default for TimeoutCalibrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            percentile: 0.99,
+            safety_margin: 1.5,
+            min_timeout_ms: 5000,
+            max_timeout_ms: 300_000,
+            window_size: 200,
+            min_samples: 20,
+        }
+    }
+}
+
+/// Transparent re-issue of idempotent provider calls cut off by a connection
+/// reset; at most `max_retries` re-issues per call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderResumeConfig {
+    pub enabled: bool,
+    /// Delay before the first re-issue, growing linearly with each attempt
+    pub backoff_ms: u64,
+}
+
+impl Default for ProviderResumeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            backoff_ms: 250,
+        }
+    }
+}
+
+/// Retries of provider error responses whose class is worth retrying (rate
+/// limits, transient server errors); at most `max_retries` per call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderErrorRetryConfig {
+    pub enabled: bool,
+    /// Delay before the first retry, doubling with each attempt
+    pub backoff_ms: u64,
+    /// Longest wait, including one asked for by `Retry-After`; longer waits give up instead
+    pub max_backoff_ms: u64,
+    /// Providers tried in turn when the routed one fails with an error
+    /// another provider may not hit
+    pub fallback_providers: Vec<String>,
+}
+
+impl Default for ProviderErrorRetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            backoff_ms: 500,
+            max_backoff_ms: 8000,
+            fallback_providers: Vec::new(),
+        }
+    }
+}
+
+/// Global model allow/deny lists and deprecation upgrades, layered under tenant overrides
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelGovernanceConfig {
+    /// When non-empty, only these models may be dispatched
+    pub allowed_models: Vec<String>,
+    pub denied_models: Vec<String>,
+    /// Deprecated model -> replacement model
+    pub upgrades: HashMap<String, String>,
+}
+
+/// Record/replay of upstream provider responses for offline development
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderRecordingConfig {
+    pub mode: String, // "off", "record" or "replay"
+    pub directory: String,
+}
+
+impl Default for ProviderRecordingConfig {
+    fn default() -> Self {
+        Self {
+            mode: "off".to_string(),
+            directory: "recordings".to_string(),
+        }
+    }
+}
+
+/// Custom LLM provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomProvider {
+    pub name: String,
+    pub endpoint: String,
+    pub api_key: String,
+    pub headers: Option<std::collections::HashMap<String, String>>,
+}
+
+/// A self-hosted inference server exposing `/models` and `/chat/completions`
+/// the way OpenAI does
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpenAiCompatibleServer {
+    pub name: String,
+    /// e.g. `http://vllm.internal:8000/v1`
+    pub base_url: String,
+    /// Models served from another base URL than `base_url`, e.g. one vLLM
+    /// instance per model
+    #[serde(default)]
+    pub model_urls: BTreeMap<String, String>,
+    #[serde(default)]
+    pub auth: ProviderAuth,
+}
+
+/// How requests to a provider authenticate
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ProviderAuth {
+    /// No credentials, for servers reachable only on a trusted network
+    #[default]
+    None,
+    /// `Authorization: Bearer <token>`
+    Bearer { token: String },
+    /// An arbitrary header, e.g. a gateway's `x-api-key`
+    Header { name: String, value: String },
+}
+
+/// GPU configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GpuConfig {
+    pub enabled: bool,
+    pub device_id: u32,
+    pub batch_size: u32,
+    pub kernel_optimization: String,
+    pub memory_limit_gb: Option<u32>,
+    #[serde(default)]
+    pub fallback: GpuFallbackConfig,
+}
+
+/// FHE operation types, for choosing where each may run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FheOperation {
+    KeyGeneration,
+    Encrypt,
+    Decrypt,
+    Process,
+    Aggregate,
+    Bootstrap,
+}
+
+impl FheOperation {
+    /// Bootstrapping on the CPU is orders of magnitude slower and would stall
+    /// the requests behind it, so it never falls back by default
+    pub fn default_fallback(self) -> GpuFallbackPolicy {
+        match self {
+            FheOperation::Bootstrap => GpuFallbackPolicy::Reject,
+            _ => GpuFallbackPolicy::Cpu,
+        }
+    }
+}
+
+/// What an operation does when no GPU slot is free or no GPU is present
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpuFallbackPolicy {
+    /// Run on the CPU
+    Cpu,
+    /// Fail with 503 so the client retries elsewhere
+    Reject,
+}
+
+/// Where FHE operations run when `gpu.enabled` and the GPU is saturated or absent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpuFallbackConfig {
+    /// Operations on the GPU at once; more are saturation
+    pub max_concurrent_operations: usize,
+    /// How long an operation waits for a free GPU slot before falling back
+    pub max_wait_ms: u64,
+    /// Per operation type; unlisted operations use their default
+    pub policies: BTreeMap<FheOperation, GpuFallbackPolicy>,
+}
+
+impl Default for GpuFallbackConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_operations: 8,
+            max_wait_ms: 50,
+            policies: BTreeMap::new(),
+        }
+    }
+}
+
+impl GpuFallbackConfig {
+    pub fn policy(&self, operation: FheOperation) -> GpuFallbackPolicy {
+        self.policies
+            .get(&operation)
+            .copied()
+            .unwrap_or_else(|| operation.default_fallback())
+    }
+}
+
+/// Privacy configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrivacyConfig {
+    pub epsilon_per_query: f64,
+    pub delta: f64,
+    pub max_queries_per_user: u32,
+    pub track_privacy_budget: bool,
+    pub noise_multiplier: f64,
+    #[serde(default)]
+    pub budget_replenishment: BudgetReplenishmentConfig,
+}
+
+/// Privacy budget replenishment configuration; the only place that decides
+/// when budgets are restored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BudgetReplenishmentConfig {
+    pub policy: String, // "manual", "daily", "weekly" or "rolling"
+    pub rolling_window_hours: u64,
+    pub grace_period_seconds: u64,
+    pub notification_thresholds: Vec<u8>,
+}
+
+impl Default for BudgetReplenishmentConfig {
+    fn default() -> Self {
+        Self {
+            policy: "daily".to_string(),
+            rolling_window_hours: 24,
+            grace_period_seconds: 30,
+            notification_thresholds: vec![50, 80, 100],
+        }
+    }
+}
+
+/// Monitoring configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonitoringConfig {
+    pub metrics_enabled: bool,
+    pub metrics_port: u16,
+    /// Largest fraction of ordinary requests traced; see `trace_sampling`
+    pub trace_sampling_rate: f64,
+    pub log_level: String,
+    #[serde(default)]
+    pub siem: SiemExportConfig,
+    #[serde(default)]
+    pub runbooks: RunbooksConfig,
+    #[serde(default)]
+    pub state_recorder: StateRecorderConfig,
+    #[serde(default)]
+    pub geo_latency: GeoLatencyConfig,
+    #[serde(default)]
+    pub tasks: TaskSupervisorConfig,
+    #[serde(default)]
+    pub export_endpoints: ExportEndpoints,
+    #[serde(default)]
+    pub otlp_metrics: OtlpMetricsConfig,
+    #[serde(default)]
+    pub synthetic_probes: SyntheticProbesConfig,
+    #[serde(default)]
+    pub telemetry_sampling: TelemetrySamplingConfig,
+    #[serde(default)]
+    pub clock: ClockHealthConfig,
+    #[serde(default)]
+    pub trace_sampling: TraceSamplingConfig,
+    #[serde(default)]
+    pub quality: QualityConfig,
+    #[serde(default)]
+    pub dependencies: DependencyMonitorConfig,
+    #[serde(default)]
+    pub noise_trends: NoiseTrendsConfig,
+    #[serde(default)]
+    pub config_drift: ConfigDriftConfig,
+    #[serde(default)]
+    pub request_journal: RequestJournalConfig,
+}
+
+/// Collectors telemetry is pushed to, besides the scrape endpoint on /metrics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportEndpoints {
+    /// OTLP/HTTP collector, e.g. `http://otel-collector:4318`; metrics are
+    /// pushed to its `/v1/metrics`
+    pub otel_collector_endpoint: Option<String>,
+}
+
+/// OTLP push of the /metrics registry to `export_endpoints.otel_collector_endpoint`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtlpMetricsConfig {
+    /// How often the registry is sampled
+    pub collection_interval_seconds: u64,
+    /// How often buffered samples are pushed, as one request
+    pub export_interval_seconds: u64,
+    /// Samples kept while the collector is unreachable; the oldest go first
+    pub max_buffered_samples: usize,
+    /// Longest wait between export attempts while the collector is down
+    pub max_backoff_seconds: u64,
+    pub timeout_seconds: u64,
+    /// Extra request headers, typically the collector's auth token
+    pub headers: HashMap<String, String>,
+    /// Reported as `cloud.region`; defaults to the replication region
+    pub region: Option<String>,
+    /// Reported as `service.instance.id`; defaults to the host name
+    pub instance_id: Option<String>,
+    /// Width of the intervals metrics are rebuilt in by a backfill
+    pub backfill_bucket_seconds: u64,
+    /// Longest time range one backfill may cover
+    pub max_backfill_seconds: u64,
+}
+
+impl Default for OtlpMetricsConfig {
+    fn default() -> Self {
+        Self {
+            collection_interval_seconds: 10,
+            export_interval_seconds: 60,
+            max_buffered_samples: 360,
+            max_backoff_seconds: 600,
+            timeout_seconds: 10,
+            headers: HashMap::new(),
+            region: None,
+            instance_id: None,
+            backfill_bucket_seconds: 60,
+            max_backfill_seconds: 7 * 86_400,
+        }
+    }
+}
+
+/// Canned encrypted requests the proxy sends through its own pipeline, for
+/// black-box latency and availability signal per provider and region
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyntheticProbesConfig {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    pub timeout_seconds: u64,
+    /// Probed routes; empty probes every configured provider through this proxy
+    pub targets: Vec<ProbeTarget>,
+    /// Sent as `x-api-key`; probes act for `tenant` only when it is set
+    pub api_key: Option<String>,
+    /// Tenant `api_key` is issued to under `[access]`
+    pub tenant: String,
+    /// Plaintext encrypted for every probe
+    pub prompt: String,
+    /// Marks probe traffic so billing skips it. Random per process when unset;
+    /// set the same value on every region for probes sent to peers to be skipped.
+    pub marker_secret: Option<String>,
+    /// Probes per target the SLOs are judged over
+    pub window: usize,
+    /// Probes in the window before an SLO is judged
+    pub min_samples: usize,
+    /// Fraction of probes in the window that must succeed
+    pub success_slo: f64,
+    /// 95th percentile end-to-end latency objective
+    pub latency_slo_ms: u64,
+}
+
+/// A provider probed through the proxy serving a region
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProbeTarget {
+    pub provider: String,
+    /// Defaults to the replication region
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Base URL of the proxy serving the region; defaults to this proxy
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl Default for SyntheticProbesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: 60,
+            timeout_seconds: 30,
+            targets: Vec::new(),
+            api_key: None,
+            tenant: "synthetic-probes".to_string(),
+            prompt: "Reply with the word ok.".to_string(),
+            marker_secret: None,
+            window: 60,
+            min_samples: 5,
+            success_slo: 0.99,
+            latency_slo_ms: 5000,
+        }
+    }
+}
+
+/// Sampled request metadata for usage analytics: sizes, models, latency and
+/// tenant tiers, never content or identities
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetrySamplingConfig {
+    pub enabled: bool,
+    /// Fraction of requests sampled
+    pub sample_rate: f64,
+    /// Models reported by name; others are reported as "other". Empty reports
+    /// any well-formed model name.
+    pub models: Vec<String>,
+    /// Distinct metadata combinations kept; samples beyond it are counted as dropped
+    pub max_series: usize,
+}
+
+impl Default for TelemetrySamplingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_rate: 0.01,
+            models: Vec::new(),
+            max_series: 1000,
+        }
+    }
+}
+
+/// Host clock skew measured against NTP servers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockHealthConfig {
+    pub enabled: bool,
+    /// NTP servers as `host:port`; the median of their offsets is the skew
+    pub ntp_servers: Vec<String>,
+    pub poll_interval_seconds: u64,
+    pub query_timeout_ms: u64,
+    /// Replay and federation skew windows are widened by the measured skew,
+    /// up to this much
+    pub max_window_adjustment_seconds: u64,
+    /// Readiness fails while the skew is larger than this
+    pub max_skew_ms: u64,
+}
+
+impl Default for ClockHealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ntp_servers: vec![
+                "time.cloudflare.com:123".to_string(),
+                "time.google.com:123".to_string(),
+                "pool.ntp.org:123".to_string(),
+            ],
+            poll_interval_seconds: 64,
+            query_timeout_ms: 2000,
+            max_window_adjustment_seconds: 30,
+            max_skew_ms: 5000,
+        }
+    }
+}
+
+/// Which requests are traced: errors and slow requests always, the rest at
+/// up to `monitoring.trace_sampling_rate` within a per-minute budget
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceSamplingConfig {
+    pub enabled: bool,
+    /// Requests taking at least this long are always traced
+    pub latency_threshold_ms: u64,
+    /// Ordinary requests traced per minute, at most
+    pub budget_per_minute: u64,
+    /// Follow the sampled flag of an incoming `traceparent`
+    pub respect_parent: bool,
+    /// Routes tracked separately; further routes are counted as "other"
+    pub max_routes: usize,
+    /// Sampled spans kept for GET /v1/admin/traces
+    pub max_buffered_spans: usize,
+}
+
+impl Default for TraceSamplingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            latency_threshold_ms: 2000,
+            budget_per_minute: 60,
+            respect_parent: true,
+            max_routes: 200,
+            max_buffered_spans: 1000,
+        }
+    }
+}
+
+/// Client-reported scores of completions, and routing away from models that
+/// score poorly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QualityConfig {
+    pub enabled: bool,
+    /// How long after a completion is served it may be scored
+    pub score_window_seconds: u64,
+    /// Completions open for scoring at once; the oldest are dropped first
+    pub max_tracked_requests: usize,
+    /// Scores a model needs before routing acts on its mean
+    pub min_scores: u64,
+    /// Models whose mean score (0.0 to 1.0) is below this are downranked
+    pub downrank_below: f64,
+    /// Models served in place of a downranked model, in order of preference
+    pub alternatives: HashMap<String, Vec<String>>,
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            score_window_seconds: 86400,
+            max_tracked_requests: 100_000,
+            min_scores: 50,
+            downrank_below: 0.3,
+            alternatives: HashMap::new(),
+        }
+    }
+}
+
+/// Scheduled probes of the external systems the proxy depends on, each
+/// behind its own circuit breaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DependencyMonitorConfig {
+    pub enabled: bool,
+    pub probe_interval_seconds: u64,
+    /// A probe taking longer than this fails
+    pub probe_timeout_ms: u64,
+    /// Consecutive failed probes that open a dependency's breaker
+    pub failure_threshold: u32,
+    /// How long an open breaker waits before a trial probe
+    pub open_seconds: u64,
+    /// Dependencies whose open breaker takes the proxy out of readiness, by
+    /// name: `session_store`, `kms`, `object_storage` or `provider:<name>`
+    pub required: Vec<String>,
+    /// Whether providers are probed; each probe lists the provider's models,
+    /// which counts against its request rate limit
+    pub probe_providers: bool,
+}
+
+impl Default for DependencyMonitorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            probe_interval_seconds: 30,
+            probe_timeout_ms: 5000,
+            failure_threshold: 3,
+            open_seconds: 60,
+            required: vec!["session_store".to_string()],
+            probe_providers: true,
+        }
+    }
+}
+
+/// Per-tenant noise budget consumption trends and bootstrap demand forecasts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseTrendsConfig {
+    pub enabled: bool,
+    pub window_seconds: u64,
+    /// Previous windows averaged into a tenant's baseline rate
+    pub baseline_windows: usize,
+    /// Windows kept per tenant for the trends API and forecasts
+    pub retained_windows: usize,
+    /// Operations a window, and the baseline, need before they are compared
+    pub min_operations: u64,
+    /// Alert when a window spends this many times the baseline bits per operation
+    pub alert_ratio: f64,
+    /// Remaining budget below which a ciphertext needs bootstrapping
+    pub bootstrap_threshold_bits: u64,
+    /// Bootstraps per hour the deployment can run; 0 leaves it unchecked
+    pub bootstrap_capacity_per_hour: u64,
+    /// How far ahead bootstrap demand is projected
+    pub forecast_hours: u64,
+    pub max_tenants: usize,
+}
+
+impl Default for NoiseTrendsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_seconds: 300,
+            baseline_windows: 12,
+            retained_windows: 288,
+            min_operations: 20,
+            alert_ratio: 2.0,
+            bootstrap_threshold_bits: 10,
+            bootstrap_capacity_per_hour: 0,
+            forecast_hours: 24,
+            max_tenants: 10_000,
+        }
+    }
+}
+
+/// Comparison of the effective configuration between replicas through
+/// fingerprints published to the session store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigDriftConfig {
+    pub enabled: bool,
+    /// Name this replica publishes under; defaults to the host name
+    pub replica_id: Option<String>,
+    pub publish_interval_seconds: u64,
+    /// Replicas that have not published for this long are left out
+    pub replica_ttl_seconds: u64,
+    /// Drift lasting this long raises an alert
+    pub alert_after_seconds: u64,
+    /// Fingerprints of replicas silent this long are deleted from the store
+    pub forget_after_seconds: u64,
+    /// Dotted keys, and everything under them, expected to differ per replica
+    pub ignored_keys: Vec<String>,
+}
+
+impl Default for ConfigDriftConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            replica_id: None,
+            publish_interval_seconds: 30,
+            replica_ttl_seconds: 120,
+            alert_after_seconds: 300,
+            forget_after_seconds: 86_400,
+            ignored_keys: vec![
+                "federation.node_id".to_string(),
+                "persistence.standby.node_id".to_string(),
+                "persistence.standby.peer_url".to_string(),
+                "persistence.standby.preferred_active".to_string(),
+                "monitoring.otlp_metrics.instance_id".to_string(),
+                "monitoring.config_drift.replica_id".to_string(),
+            ],
+        }
+    }
+}
+
+/// Per-request timelines support staff look up by request id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestJournalConfig {
+    pub enabled: bool,
+    /// Journals older than this are dropped
+    pub retention_seconds: u64,
+    /// Oldest journals are dropped beyond this many
+    pub max_requests: usize,
+    /// Later events of a request are counted but not kept
+    pub max_events_per_request: usize,
+    /// Bearer tokens allowed to read journals
+    pub support_tokens: Vec<SupportToken>,
+}
+
+impl Default for RequestJournalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retention_seconds: 86_400,
+            max_requests: 10_000,
+            max_events_per_request: 64,
+            support_tokens: Vec::new(),
+        }
+    }
+}
+
+/// A support bearer token, configured by digest so the config holds no secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SupportToken {
+    pub name: String,
+    /// Hex SHA-256 of the token
+    pub sha256: String,
+    /// Tenants whose requests the token may read; empty for all
+    #[serde(default)]
+    pub tenants: Vec<String>,
+}
+
+/// Restart policy for supervised background tasks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaskSupervisorConfig {
+    /// Consecutive panics after which a task is left stopped
+    pub max_restarts: u32,
+    /// Delay before the first restart; doubles with each consecutive panic
+    pub initial_backoff_ms: u64,
+    pub max_backoff_seconds: u64,
+    /// A run this long resets the consecutive panic count
+    pub stable_after_seconds: u64,
+}
+
+impl Default for TaskSupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff_ms: 1000,
+            max_backoff_seconds: 60,
+            stable_after_seconds: 300,
+        }
+    }
+}
+
+/// Request latency aggregated by client region, for edge placement decisions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoLatencyConfig {
+    pub enabled: bool,
+    /// Client-declared region header, checked before the IP ranges
+    pub region_header: String,
+    /// Region name -> client IP ranges in CIDR notation
+    pub ip_ranges: HashMap<String, Vec<String>>,
+    /// Upper bounds of the heatmap latency buckets, ascending
+    pub latency_buckets_ms: Vec<u64>,
+    pub window_minutes: u64,
+    /// Regions tracked, including the "other" that later regions are folded into
+    pub max_regions: usize,
+}
+
+impl Default for GeoLatencyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            region_header: "x-client-region".to_string(),
+            ip_ranges: HashMap::new(),
+            latency_buckets_ms: vec![10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000],
+            window_minutes: 60,
+            max_regions: 64,
+        }
+    }
+}
+
+/// Bounded history of proxy state and performance statistics for
+/// post-incident analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateRecorderConfig {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    /// Snapshots older than this are overwritten
+    pub retention_minutes: u64,
+}
+
+impl Default for StateRecorderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_seconds: 10,
+            retention_minutes: 60,
+        }
+    }
+}
+
+/// Export of security events to a SIEM collector
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiemExportConfig {
+    pub enabled: bool,
+    pub format: String, // "cef" or "otlp"
+    /// HTTP intake, e.g. an OTLP/HTTP `/v1/logs` endpoint or a CEF-over-HTTP collector
+    pub endpoint: String,
+    /// Extra request headers, typically the collector's auth token
+    pub headers: HashMap<String, String>,
+    pub buffer_size: usize,
+    pub batch_size: usize,
+    pub flush_interval_seconds: u64,
+    pub max_retries: u32,
+    pub timeout_seconds: u64,
+}
+
+impl Default for SiemExportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: "cef".to_string(),
+            endpoint: String::new(),
+            headers: HashMap::new(),
+            buffer_size: 10000,
+            batch_size: 100,
+            flush_interval_seconds: 5,
+            max_retries: 3,
+            timeout_seconds: 10,
+        }
+    }
+}
+
+/// Codified runbooks: local actions taken when an alert rule fires
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunbooksConfig {
+    pub enabled: bool,
+    /// Log and audit what would have been done without doing it
+    pub dry_run: bool,
+    pub evaluation_interval_seconds: u64,
+    pub rules: Vec<RunbookRule>,
+}
+
+impl Default for RunbooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dry_run: true,
+            evaluation_interval_seconds: 30,
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunbookRule {
+    pub name: String,
+    pub trigger: RunbookTrigger,
+    pub action: RunbookAction,
+    /// Hold the action until an operator approves it via the admin API
+    #[serde(default)]
+    pub requires_approval: bool,
+    /// Minimum time between two firings of this rule
+    #[serde(default = "default_runbook_cooldown")]
+    pub cooldown_seconds: u64,
+}
+
+fn default_runbook_cooldown() -> u64 {
+    300
+}
+
+/// Alert condition that fires a runbook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RunbookTrigger {
+    /// The FHE engine health check reports anything but healthy
+    EngineUnhealthy,
+    /// Privacy budget denials across all users exceed this rate
+    BudgetExhaustionSpike { per_minute: f64 },
+    /// A provider failed at least `min_failures` calls with no success in between
+    ProviderOutage { provider: String, min_failures: u64 },
+}
+
+/// Local remediation a runbook may take
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RunbookAction {
+    /// Stop admitting new work, as the resource guard does
+    DrainEngine,
+    /// Route requests for `from` to `to` until reset
+    SwitchProvider { from: String, to: String },
+    /// Refuse key generation, decryption and federation traffic until reset
+    EnableLockdown,
+}
+
+/// Scaling configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScalingConfig {
+    pub auto_scaling_enabled: bool,
+    pub min_instances: u32,
+    pub max_instances: u32,
+    pub target_cpu_utilization: f64,
+    pub scale_up_threshold: f64,
+    pub scale_down_threshold: f64,
+    pub cooldown_period_seconds: u64,
+    pub connection_pool_size: usize,
+    pub max_concurrent_requests: u32,
+    #[serde(default)]
+    pub resource_guard: ResourceGuardConfig,
+    #[serde(default)]
+    pub queue_projection: QueueProjectionConfig,
+    #[serde(default)]
+    pub batch_windows: BatchWindowsConfig,
+    #[serde(default)]
+    pub stream_flow_control: StreamFlowControlConfig,
+    #[serde(default)]
+    pub overflow_queue: OverflowQueueConfig,
+    #[serde(default)]
+    pub load_shedding: LoadSheddingConfig,
+    #[serde(default)]
+    pub engine_fingerprints: EngineFingerprintConfig,
+    #[serde(default)]
+    pub dead_letter: DeadLetterConfig,
+    #[serde(default)]
+    pub failure_domains: FailureDomainsConfig,
+}
+
+/// Where an engine or provider runs; failures shared by the members of one
+/// host, zone or region are handled at that level. Empty labels are not grouped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailureDomain {
+    pub host: String,
+    pub zone: String,
+    pub region: String,
+}
+
+/// Detection of failures that hit a whole host, zone or region at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailureDomainsConfig {
+    pub enabled: bool,
+    /// Failures older than this are forgotten
+    pub window_seconds: u64,
+    /// Failures within the window that mark one member as failing
+    pub member_failure_threshold: usize,
+    /// Failing members a domain needs before its failure counts as correlated
+    pub min_failing_members: usize,
+    /// Share of a domain's members that must be failing, from 0 to 1
+    pub correlated_fraction: f64,
+    /// How long a domain stays drained before its members are tried again
+    pub drain_seconds: u64,
+    pub check_interval_seconds: u64,
+    /// Domains of the connection pool's engines, by pool index
+    pub engines: Vec<FailureDomain>,
+    /// Domains of the LLM providers, by provider name
+    pub providers: HashMap<String, FailureDomain>,
+}
+
+impl Default for FailureDomainsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_seconds: 60,
+            member_failure_threshold: 3,
+            min_failing_members: 2,
+            correlated_fraction: 0.5,
+            drain_seconds: 300,
+            check_interval_seconds: 10,
+            engines: Vec::new(),
+            providers: HashMap::new(),
+        }
+    }
+}
+
+/// Retries for pipeline work and the queue that keeps what still failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeadLetterConfig {
+    /// Attempts before an item is dead-lettered; errors that cannot succeed
+    /// on retry dead-letter it at once
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each one after
+    pub retry_backoff_ms: u64,
+    /// Items dead-lettered within `growth_window_seconds` that raise an alert
+    pub growth_alert_threshold: usize,
+    pub growth_window_seconds: u64,
+    pub check_interval_seconds: u64,
+}
+
+impl Default for DeadLetterConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_backoff_ms: 500,
+            growth_alert_threshold: 10,
+            growth_window_seconds: 3600,
+            check_interval_seconds: 60,
+        }
+    }
+}
+
+/// Detection of engines built from a different release or holding different
+/// parameter tables than the rest of the pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineFingerprintConfig {
+    /// Refuse to add an engine whose fingerprint differs from the pool's
+    pub enforce_uniform_pool: bool,
+    /// How often every engine's fingerprint is re-read and compared
+    pub check_interval_seconds: u64,
+}
+
+impl Default for EngineFingerprintConfig {
+    fn default() -> Self {
+        Self {
+            enforce_uniform_pool: true,
+            check_interval_seconds: 60,
+        }
+    }
+}
+
+/// Early rejection of requests whose projected queue wait exceeds their deadline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueProjectionConfig {
+    pub enabled: bool,
+    /// Completions counted when estimating drain throughput
+    pub throughput_window_seconds: u64,
+    /// Weight of the newest sample in the service time average
+    pub ewma_alpha: f64,
+    /// Deadline for clients that send no `x-request-deadline-ms`; 0 uses the server request timeout
+    pub default_deadline_ms: u64,
+}
+
+impl Default for QueueProjectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            throughput_window_seconds: 10,
+            ewma_alpha: 0.2,
+            default_deadline_ms: 0,
+        }
+    }
+}
+
+/// Disk-backed overflow for Normal and Low priority requests that find admission full
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverflowQueueConfig {
+    pub enabled: bool,
+    /// Directory holding one ring file per priority, on local SSD; truncated at startup
+    pub path: String,
+    /// Size of each priority's ring file
+    pub max_bytes_per_priority: u64,
+    /// Requests each priority's ring holds
+    pub max_entries: usize,
+    /// Larger request bodies are refused rather than spilled
+    pub max_request_bytes: usize,
+    /// Spilled requests not re-injected within this are answered 503
+    pub max_wait_seconds: u64,
+    pub drain_interval_ms: u64,
+}
+
+impl Default for OverflowQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "./data/overflow".to_string(),
+            max_bytes_per_priority: 256 * 1024 * 1024,
+            max_entries: 10000,
+            max_request_bytes: 2 * 1024 * 1024,
+            max_wait_seconds: 30,
+            drain_interval_ms: 25,
+        }
+    }
+}
+
+/// Whether each admission-control and load-shed policy turns requests away
+/// or only records what it would have done
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadSheddingConfig {
+    pub rate_limit: ShedPolicyMode,
+    pub queue_projection: ShedPolicyMode,
+    pub priority_shedding: ShedPolicyMode,
+    /// Share of simulated decisions written to the log, 0-100
+    pub log_sample_percent: f64,
+    /// Most recent simulated decisions kept for `/v1/admin/load-shedding`
+    pub max_samples: usize,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit: ShedPolicyMode::Enforce,
+            queue_projection: ShedPolicyMode::Enforce,
+            priority_shedding: ShedPolicyMode::Enforce,
+            log_sample_percent: 1.0,
+            max_samples: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShedPolicyMode {
+    Enforce,
+    /// Evaluate against live traffic, but admit what would have been shed
+    Simulate,
+}
+
+/// Off-peak windows in which queued low-priority batch jobs are run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchWindowsConfig {
+    pub enabled: bool,
+    /// Windows in UTC; one whose end precedes its start wraps past midnight
+    pub windows: Vec<BatchWindowConfig>,
+    /// Share of a standard request's quota charged per batch job
+    pub quota_discount: f64,
+    /// Quota units each tenant may spend on batch jobs per UTC day; a standard request costs 1
+    pub daily_quota_units: f64,
+    /// Queued jobs started per scheduler tick while a window is open
+    pub max_jobs_per_tick: usize,
+    /// Prompts one job may carry in `items`
+    pub max_items_per_job: usize,
+    pub poll_interval_seconds: u64,
+    /// How long finished jobs stay queryable
+    pub job_retention_seconds: u64,
+    /// Receives a JSON notification whenever a job completes or fails
+    pub notification_webhook_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchWindowConfig {
+    pub name: String,
+    /// "HH:MM", UTC
+    pub start: String,
+    /// "HH:MM", UTC, exclusive
+    pub end: String,
+}
+
+impl Default for BatchWindowsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            windows: vec![BatchWindowConfig {
+                name: "nightly".to_string(),
+                start: "01:00".to_string(),
+                end: "05:00".to_string(),
+            }],
+            quota_discount: 0.5,
+            daily_quota_units: 1000.0,
+            max_jobs_per_tick: 8,
+            max_items_per_job: 256,
+            poll_interval_seconds: 60,
+            job_retention_seconds: 86400,
+            notification_webhook_url: None,
+        }
+    }
+}
+
+/// Flow control on streamed encrypted responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamFlowControlConfig {
+    /// Send backpressure frames and honor client pause/resume
+    pub enabled: bool,
+    /// Share of admission capacity in flight at which streams are told to back off
+    pub load_threshold: f64,
+    /// Minimum gap between backpressure frames on one stream
+    pub backpressure_interval_ms: u64,
+    /// Pacing between chunks when the proxy is not under load
+    pub chunk_interval_ms: u64,
+    /// Upper bound on the delay suggested to clients
+    pub max_suggested_delay_ms: u64,
+    /// A stream paused for longer than this is ended
+    pub max_pause_seconds: u64,
+    /// Frames buffered per stream before the proxy waits on a slow reader
+    pub buffer_frames: usize,
+    pub max_streams: usize,
+}
+
+impl Default for StreamFlowControlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            load_threshold: 0.75,
+            backpressure_interval_ms: 1000,
+            chunk_interval_ms: 20,
+            max_suggested_delay_ms: 5000,
+            max_pause_seconds: 300,
+            buffer_frames: 8,
+            max_streams: 1024,
+        }
+    }
+}
+
+/// Process-level resource guard configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceGuardConfig {
+    pub enabled: bool,
+    pub max_rss_mb: u64,
+    pub max_open_fds: u64,
+    pub max_cpu_percent: f64,
+    pub sustained_seconds: u64,
+    pub check_interval_seconds: u64,
+    pub actions: Vec<String>, // escalation order: "flush_cache", "drain_engine", "restart"
+}
+
+impl Default for ResourceGuardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_rss_mb: 4096,
+            max_open_fds: 8192,
+            max_cpu_percent: 95.0,
+            sustained_seconds: 60,
+            check_interval_seconds: 10,
+            actions: vec![
+                "flush_cache".to_string(),
+                "drain_engine".to_string(),
+                "restart".to_string(),
+            ],
+        }
+    }
+}
+
+/// Performance optimization configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PerformanceConfig {
+    pub cache_enabled: bool,
+    pub cache_size_mb: u64,
+    pub cache_ttl_seconds: u64,
+    pub batch_processing_enabled: bool,
+    pub batch_size: usize,
+    pub batch_timeout_ms: u64,
+    pub compression_enabled: bool,
+    pub prefetch_enabled: bool,
+    pub async_processing: bool,
+    #[serde(default)]
+    pub response_chunking: ResponseChunkingConfig,
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+    #[serde(default)]
+    pub engine_snapshot: EngineSnapshotConfig,
+    #[serde(default)]
+    pub packing: PromptPackingConfig,
+}
+
+/// Coalescing of short prompts from one tenant into shared ciphertexts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PromptPackingConfig {
+    pub enabled: bool,
+    /// How long the first prompt waits for others to share its ciphertext
+    pub window_ms: u64,
+    /// Prompts packed per window at most; a full batch is processed at once
+    pub max_batch: usize,
+}
+
+impl Default for PromptPackingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_ms: 5,
+            max_batch: 16,
+        }
+    }
+}
+
+/// Snapshot of warmed engine state, restored on startup to skip warm-up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineSnapshotConfig {
+    pub enabled: bool,
+    pub path: String,
+}
+
+impl Default for EngineSnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "data/engine-snapshot.json".to_string(),
+        }
+    }
+}
+
+/// Chunking of encrypted completion responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseChunkingConfig {
+    pub chunk_size_bytes: usize,
+    pub unreferenced_chunk_ttl_seconds: u64,
+}
+
+impl Default for ResponseChunkingConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size_bytes: 256,
+            unreferenced_chunk_ttl_seconds: 300,
+        }
+    }
+}
+
+/// Opt-in caching of encrypted completion responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseCacheConfig {
+    pub max_entries: usize,
+    /// Default rules; tenants may replace them. No rules means nothing is cached.
+    pub rules: Vec<ResponseCacheRule>,
+    /// Upper bound on any rule's `stale_while_revalidate_seconds`, tenant
+    /// rules included, so no entry outlives `ttl_seconds` by more than this
+    #[serde(default = "default_max_stale_seconds")]
+    pub max_stale_seconds: u64,
+    /// How a full cache makes room for a new entry
+    #[serde(default)]
+    pub eviction: ResponseCacheEviction,
+    #[serde(default)]
+    pub preload: ResponseCachePreloadConfig,
+}
+
+/// Re-running cached completions ahead of the hour they are usually
+/// requested in, so the first request of the hour is a hit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseCachePreloadConfig {
+    pub enabled: bool,
+    /// Share of observed days a completion must have been requested in an
+    /// hour to be preloaded for that hour
+    pub confidence_threshold: f64,
+    /// Bytes of completions one preload run may load
+    pub budget_bytes: usize,
+    /// How far ahead of the predicted hour completions are preloaded
+    pub lead_seconds: u64,
+    pub interval_seconds: u64,
+}
+
+impl Default for ResponseCachePreloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            confidence_threshold: 0.8,
+            budget_bytes: 64 * 1024 * 1024,
+            lead_seconds: 600,
+            interval_seconds: 300,
+        }
+    }
+}
+
+/// Which entry a full response cache drops
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseCacheEviction {
+    /// Whichever entry would have expired first
+    #[default]
+    Expiry,
+    /// Greedy-Dual-Size-Frequency: the entry whose recomputation time per
+    /// byte, scaled by its hits, is lowest. A new entry worth less than every
+    /// resident one is not admitted.
+    Gdsf,
+}
+
+fn default_max_stale_seconds() -> u64 {
+    300
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            rules: Vec::new(),
+            max_stale_seconds: default_max_stale_seconds(),
+            eviction: ResponseCacheEviction::default(),
+            preload: ResponseCachePreloadConfig::default(),
+        }
+    }
+}
+
+/// Which completions to cache and for how long
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseCacheRule {
+    /// Models this rule applies to; empty matches every model
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Prompt template IDs this rule applies to; empty matches every template
+    #[serde(default)]
+    pub template_ids: Vec<String>,
+    pub ttl_seconds: u64,
+    /// How long past `ttl_seconds` an entry is still served while a
+    /// background refresh replaces it; 0 never serves stale entries
+    #[serde(default)]
+    pub stale_while_revalidate_seconds: u64,
+    /// Tags attached to cached entries, usable for invalidation
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ResponseCacheRule {
+    pub fn matches(&self, model: &str, template_id: Option<&str>) -> bool {
+        (self.models.is_empty() || self.models.iter().any(|m| m == model))
+            && (self.template_ids.is_empty()
+                || template_id.is_some_and(|t| self.template_ids.iter().any(|id| id == t)))
+    }
+}
+
+/// Per-tenant configuration layer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantsConfig {
+    /// Directory of `<tenant>.toml` override documents, re-read when the cache expires
+    pub override_dir: Option<String>,
+    pub cache_ttl_seconds: u64,
+    /// SLA class for requests without a tenant ID and tenants that set none
+    #[serde(default)]
+    pub default_sla_class: SlaClass,
+    /// Inline overrides, applied before any override document
+    #[serde(default)]
+    pub overrides: HashMap<String, TenantOverrides>,
+    #[serde(default)]
+    pub redaction: RedactionPolicyConfig,
+    #[serde(default)]
+    pub rate_limit_rules: RateLimitRulesConfig,
+    #[serde(default)]
+    pub workload_tags: WorkloadTagsConfig,
+    #[serde(default)]
+    pub prompt_lint: PromptLintConfig,
+}
+
+impl Default for TenantsConfig {
+    fn default() -> Self {
+        Self {
+            override_dir: None,
+            cache_ttl_seconds: 60,
+            default_sla_class: SlaClass::default(),
+            overrides: HashMap::new(),
+            redaction: RedactionPolicyConfig::default(),
+            rate_limit_rules: RateLimitRulesConfig::default(),
+            workload_tags: WorkloadTagsConfig::default(),
+            prompt_lint: PromptLintConfig::default(),
+        }
+    }
+}
+
+/// Structural checks clients run on a prompt before encrypting it; see `prompt_lint`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PromptLintConfig {
+    /// Whether tenants must send a lint report; they may override it with
+    /// `require_prompt_lint`
+    pub required: bool,
+    pub max_chars: usize,
+    /// Text that must not survive into a prompt, e.g. unfilled template markers
+    pub forbidden_placeholders: Vec<String>,
+    /// Control characters other than tab, newline and carriage return
+    pub reject_control_characters: bool,
+    /// Bidirectional overrides and zero-width characters that hide text
+    pub reject_invisible_characters: bool,
+    /// Reports older than this are refused
+    pub max_report_age_seconds: u64,
+}
+
+impl Default for PromptLintConfig {
+    fn default() -> Self {
+        Self {
+            required: false,
+            max_chars: 32_000,
+            forbidden_placeholders: vec!["{{".to_string(), "}}".to_string()],
+            reject_control_characters: true,
+            reject_invisible_characters: true,
+            max_report_age_seconds: 300,
+        }
+    }
+}
+
+/// Client-declared workload tags and the policies they select; see `workload_tags`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkloadTagsConfig {
+    pub enabled: bool,
+    /// Tag for requests that declare none
+    pub default_tag: Option<String>,
+    pub tags: BTreeMap<String, WorkloadTagPolicy>,
+}
+
+/// What a workload tag changes about a request; unset fields leave it alone
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkloadTagPolicy {
+    /// Priority asked for on the request's behalf; still capped by the SLA class
+    pub priority: Option<String>,
+    pub cache: WorkloadCachePolicy,
+    /// Provider the request is sent to; tenant routing rules still take precedence
+    pub provider: Option<String>,
+    /// Level at which each request's outcome is logged: "error" to "trace"
+    pub log_level: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkloadCachePolicy {
+    /// Use the response cache as the tenant's cache rules say
+    #[default]
+    Default,
+    /// Neither serve nor store cached responses
+    Bypass,
+}
+
+/// Scripted rate limits evaluated per request; see `limit_rules`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitRulesConfig {
+    pub enabled: bool,
+    /// Longest `when` or `limit` expression accepted
+    pub max_expression_bytes: usize,
+    /// Most syntax nodes in one expression
+    pub max_nodes: usize,
+    /// Evaluation steps one rule may take on a request
+    pub max_steps: u64,
+    /// Wall-clock time one rule may take on a request
+    pub max_eval_micros: u64,
+    /// Tried in order; the first whose condition holds sets the limit
+    pub rules: Vec<RateLimitRule>,
+}
+
+impl Default for RateLimitRulesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_expression_bytes: 512,
+            max_nodes: 128,
+            max_steps: 256,
+            max_eval_micros: 200,
+            rules: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitRule {
+    pub name: String,
+    /// Tenants the rule applies to; empty for every request
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    /// Boolean expression selecting the requests the rule governs
+    pub when: String,
+    /// Per-minute limit for those requests
+    pub limit: String,
+}
+
+/// Signed redaction policy bundles that clients enforce on decrypted responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactionPolicyConfig {
+    /// Bundles are signed with the response signing key, which must be enabled too
+    pub enabled: bool,
+    pub max_rules: usize,
+    /// Superseded versions kept per tenant so clients can still fetch them
+    pub max_versions: usize,
+    /// Acknowledgments kept in memory per tenant; all of them go to the audit log
+    pub max_acknowledgments: usize,
+}
+
+impl Default for RedactionPolicyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_rules: 64,
+            max_versions: 10,
+            max_acknowledgments: 1000,
+        }
+    }
+}
+
+/// Service tier a tenant is contracted for. Assigned server-side; it caps the
+/// scheduling priority the tenant's requests can receive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlaClass {
+    Gold,
+    #[default]
+    Silver,
+    Bronze,
+}
+
+impl SlaClass {
+    pub const ALL: [SlaClass; 3] = [SlaClass::Gold, SlaClass::Silver, SlaClass::Bronze];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SlaClass::Gold => "gold",
+            SlaClass::Silver => "silver",
+            SlaClass::Bronze => "bronze",
+        }
+    }
+}
+
+/// Proxy-to-proxy federation for relaying encrypted requests between organizations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FederationConfig {
+    pub enabled: bool,
+    /// Identifier this proxy presents to its peers; also used for loop detection
+    pub node_id: String,
+    pub max_hops: usize,
+    pub max_clock_skew_seconds: i64,
+    pub peers: Vec<FederationPeerConfig>,
+}
+
+impl Default for FederationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            node_id: "fhe-proxy".to_string(),
+            max_hops: 3,
+            max_clock_skew_seconds: 300,
+            peers: Vec::new(),
+        }
+    }
+}
+
+/// An authenticated link to a peer proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FederationPeerConfig {
+    pub id: String,
+    /// Base URL of the peer proxy, e.g. `https://proxy.partner.example`
+    pub endpoint: String,
+    /// Link secret shared out of band with the peer
+    pub shared_secret: String,
+    pub requests_per_minute: u64,
+}
+
+/// Storage backend for sessions, audit log, idempotency cache and privacy ledger
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PersistenceConfig {
+    pub backend: String, // "memory" or "sqlite"
+    pub sqlite_path: String,
+    pub compaction_interval_seconds: u64,
+    pub audit_retention_days: u64,
+    pub idempotency_ttl_seconds: u64,
+    pub ledger_flush_interval_seconds: u64,
+    #[serde(default)]
+    pub replication: ReplicationConfig,
+    #[serde(default)]
+    pub migrations: StorageMigrationConfig,
+    #[serde(default)]
+    pub standby: StandbyConfig,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            backend: "memory".to_string(),
+            sqlite_path: "data/fhe-proxy.db".to_string(),
+            compaction_interval_seconds: 3600,
+            audit_retention_days: 90,
+            idempotency_ttl_seconds: 86400,
+            ledger_flush_interval_seconds: 30,
+            replication: ReplicationConfig::default(),
+            migrations: StorageMigrationConfig::default(),
+            standby: StandbyConfig::default(),
+        }
+    }
+}
+
+/// How a standby keeps up with the active's session and budget state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StandbyReplication {
+    /// Both instances share the session store; budgets are reloaded from it on takeover
+    Store,
+    /// The standby pulls sessions and budgets from the active
+    Direct,
+}
+
+/// Hot-standby pairing of two proxies, for deployments without an orchestrator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StandbyConfig {
+    pub enabled: bool,
+    /// Unique within the pair; breaks ties between equally placed instances
+    pub node_id: String,
+    /// Serve at startup when the peer is not already active
+    pub preferred_active: bool,
+    /// Base URL of the other instance of the pair
+    pub peer_url: String,
+    pub replication: StandbyReplication,
+    pub replication_interval_seconds: u64,
+    pub heartbeat_interval_ms: u64,
+    /// Silence from the peer after which the standby takes over
+    pub failover_timeout_ms: u64,
+    /// Endpoints a standby must reach a majority of before taking over, so an
+    /// instance cut off from the network does not promote itself
+    pub witness_urls: Vec<String>,
+    /// Run on promotion, e.g. to claim a virtual IP; program then arguments
+    pub takeover_command: Vec<String>,
+    /// Run on demotion, e.g. to release the virtual IP
+    pub release_command: Vec<String>,
+    pub command_timeout_seconds: u64,
+}
+
+impl Default for StandbyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            node_id: "fhe-proxy-a".to_string(),
+            preferred_active: false,
+            peer_url: String::new(),
+            replication: StandbyReplication::Direct,
+            replication_interval_seconds: 5,
+            heartbeat_interval_ms: 1000,
+            failover_timeout_ms: 5000,
+            witness_urls: Vec::new(),
+            takeover_command: Vec::new(),
+            release_command: Vec::new(),
+            command_timeout_seconds: 10,
+        }
+    }
+}
+
+/// Schema migrations of the storage backend, run at startup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageMigrationConfig {
+    /// Apply pending migrations on startup; otherwise startup fails until
+    /// `fhe-proxy migrate` has been run
+    pub auto_apply: bool,
+    /// Where the store is copied before a destructive migration; empty skips backups
+    pub backup_dir: String,
+    /// How long the replica running migrations holds the lock before others may take over
+    pub lock_lease_seconds: u64,
+    /// How long other replicas wait for the lock holder to finish
+    pub wait_timeout_seconds: u64,
+}
+
+impl Default for StorageMigrationConfig {
+    fn default() -> Self {
+        Self {
+            auto_apply: true,
+            backup_dir: "data/backups".to_string(),
+            lock_lease_seconds: 600,
+            wait_timeout_seconds: 300,
+        }
+    }
+}
+
+/// Active-active session replication between regions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplicationConfig {
+    pub enabled: bool,
+    /// Name of this region in session version vectors; must be unique per replica
+    pub region: String,
+    /// Base URLs of the proxies in other regions
+    pub peers: Vec<String>,
+    pub reconcile_interval_seconds: u64,
+    pub timeout_seconds: u64,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: "local".to_string(),
+            peers: Vec::new(),
+            reconcile_interval_seconds: 30,
+            timeout_seconds: 5,
+        }
+    }
+}
+
+/// Concurrent session governance, applied per API key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionLimitsConfig {
+    /// Maximum concurrent sessions per API key; 0 means unlimited. Once
+    /// capped, sessions are only opened with an API key.
+    pub max_sessions_per_api_key: usize,
+    pub over_limit_policy: SessionLimitPolicy,
+    /// Sessions unused for this long are evicted; 0 disables idle eviction
+    pub idle_timeout_seconds: u64,
+    /// Receives a JSON event for every evicted session
+    pub eviction_webhook_url: Option<String>,
+    pub renewal: SessionRenewalConfig,
+}
+
+/// Short-lived session tokens and overlapping server key renewal for
+/// long-running clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionRenewalConfig {
+    pub enabled: bool,
+    pub access_token_ttl_seconds: u64,
+    pub refresh_token_ttl_seconds: u64,
+    /// Responses carry a renewal hint once the access token has less than
+    /// this left
+    pub renewal_hint_seconds: u64,
+    pub key_lifetime_seconds: u64,
+    /// How long a renewed server key stays valid alongside its replacement;
+    /// key renewal is hinted this long before the key expires
+    pub key_overlap_seconds: u64,
+}
+
+impl Default for SessionRenewalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            access_token_ttl_seconds: 900,
+            refresh_token_ttl_seconds: 86400,
+            renewal_hint_seconds: 120,
+            key_lifetime_seconds: 86400,
+            key_overlap_seconds: 300,
+        }
+    }
+}
+
+impl Default for SessionLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions_per_api_key: 0,
+            over_limit_policy: SessionLimitPolicy::Reject,
+            idle_timeout_seconds: 3600,
+            eviction_webhook_url: None,
+            renewal: SessionRenewalConfig::default(),
+        }
+    }
+}
+
+/// Encrypted document pipeline behind `POST /v1/documents`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DocumentIngestionConfig {
+    pub enabled: bool,
+    /// Largest accepted encrypted document, before base64 encoding
+    pub max_document_bytes: usize,
+    /// Plaintext bytes per chunk sent through the provider
+    pub chunk_size_bytes: usize,
+    /// Documents processed at once; further jobs wait queued. With
+    /// autoscaling this is where the pool starts.
+    pub max_concurrent_jobs: usize,
+    /// Finished jobs and their status are dropped after this long
+    pub job_retention_seconds: u64,
+    pub autoscaling: WorkerAutoscalingConfig,
+}
+
+impl Default for DocumentIngestionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_document_bytes: 16 * 1024 * 1024,
+            chunk_size_bytes: 4096,
+            max_concurrent_jobs: 4,
+            job_retention_seconds: 3600,
+            autoscaling: WorkerAutoscalingConfig::default(),
+        }
+    }
+}
+
+/// Sizing of a worker pool by how long work waits for a worker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerAutoscalingConfig {
+    pub enabled: bool,
+    pub min_workers: usize,
+    pub max_workers: usize,
+    /// Add a worker while the p95 wait for one is above this
+    pub target_queue_wait_p95_ms: u64,
+    /// Only retire a worker while the p95 wait is below this fraction of the
+    /// target, so the pool does not flap around it
+    pub shrink_below_ratio: f64,
+    /// How long the pool must be idle before a worker is retired
+    pub idle_period_seconds: u64,
+    /// Least time between two resizes
+    pub cooldown_seconds: u64,
+    pub interval_seconds: u64,
+}
+
+impl Default for WorkerAutoscalingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_workers: 1,
+            max_workers: 16,
+            target_queue_wait_p95_ms: 1000,
+            shrink_below_ratio: 0.5,
+            idle_period_seconds: 120,
+            cooldown_seconds: 30,
+            interval_seconds: 5,
+        }
+    }
+}
+
+/// Multi-step FHE requests behind `POST /v1/transactions`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionConfig {
+    pub enabled: bool,
+    /// Most steps in one transaction
+    pub max_steps: usize,
+    /// Most steps whose results are returned
+    pub max_outputs: usize,
+}
+
+impl Default for TransactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_steps: 32,
+            max_outputs: 8,
+        }
+    }
+}
+
+/// The `metadata` block of completion responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResponseMetadataConfig {
+    /// Namespaces whose fields may appear under `metadata.extensions`, as
+    /// `<namespace>.<field>`; fields of other namespaces are not sent
+    pub extension_namespaces: Vec<String>,
+}
+
+impl Default for ResponseMetadataConfig {
+    fn default() -> Self {
+        Self {
+            extension_namespaces: vec!["experimental".to_string()],
+        }
+    }
+}
+
+/// Ordered validator chain run on every encrypted completion request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationPipelineConfig {
+    /// Validators in the order they run: built-ins (`size`, `schema`, `policy`,
+    /// `params_hash`, `replay`) and custom validator names. Custom validators
+    /// left out run after the listed ones.
+    pub order: Vec<String>,
+    pub max_ciphertext_bytes: usize,
+    pub allowed_providers: Vec<String>,
+    /// How long an `x-request-nonce` is remembered by the replay validator
+    pub replay_window_seconds: u64,
+    pub replay_cache: ReplayCacheConfig,
+    pub custom: Vec<CustomValidatorConfig>,
+}
+
+/// How the replay validator remembers nonces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayCacheKind {
+    /// Every nonce is kept until the window passes; memory grows with traffic
+    Exact,
+    /// Rotating Bloom filters of fixed size; a fresh nonce is rejected with
+    /// probability `false_positive_rate`
+    Bloom,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayCacheConfig {
+    pub kind: ReplayCacheKind,
+    /// Target false positive rate of each Bloom filter at `expected_nonces_per_window`
+    pub false_positive_rate: f64,
+    /// Nonces expected per replay window, used to size the filters
+    pub expected_nonces_per_window: usize,
+    /// Filters the window is split into; one rotates out every window / buckets
+    pub buckets: usize,
+}
+
+impl Default for ReplayCacheConfig {
+    fn default() -> Self {
+        Self {
+            kind: ReplayCacheKind::Exact,
+            false_positive_rate: 0.0001,
+            expected_nonces_per_window: 100_000,
+            buckets: 4,
+        }
+    }
+}
+
+impl Default for ValidationPipelineConfig {
+    fn default() -> Self {
+        Self {
+            order: ["size", "schema", "policy", "params_hash", "replay"]
+                .map(String::from)
+                .to_vec(),
+            max_ciphertext_bytes: 10_000_000,
+            allowed_providers: ["openai", "anthropic", "huggingface"]
+                .map(String::from)
+                .to_vec(),
+            replay_window_seconds: 300,
+            replay_cache: ReplayCacheConfig::default(),
+            custom: Vec::new(),
+        }
+    }
+}
+
+/// A/B tests of prompt templates; only aggregate, non-content metrics are kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExperimentsConfig {
+    pub enabled: bool,
+    /// Registered at startup; more can be added through `/v1/admin/experiments`
+    pub definitions: Vec<ExperimentConfig>,
+    /// Experiments held at once, including those registered at runtime
+    pub max_experiments: usize,
+}
+
+impl Default for ExperimentsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            definitions: Vec::new(),
+            max_experiments: 32,
+        }
+    }
+}
+
+/// Flags evaluated at runtime by the proxy handlers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureFlagsConfig {
+    /// Defined at startup; a flag of the same name set through
+    /// `/v1/admin/flags` is kept in the session store and takes precedence
+    pub definitions: Vec<FeatureFlagConfig>,
+    /// How often stored flags are reloaded, so toggles made on another
+    /// instance take effect here
+    pub refresh_interval_seconds: u64,
+}
+
+impl Default for FeatureFlagsConfig {
+    fn default() -> Self {
+        Self {
+            definitions: Vec::new(),
+            refresh_interval_seconds: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeatureFlagConfig {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// A disabled flag is off for everyone, targeted tenants included
+    #[serde(default)]
+    pub enabled: bool,
+    /// Percentage of subjects, 0 to 100, the flag is on for
+    #[serde(default)]
+    pub rollout_percent: f64,
+    /// Tenants the flag is on for regardless of the rollout
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    /// Tenants the flag is off for regardless of the rollout
+    #[serde(default)]
+    pub excluded_tenants: Vec<String>,
+}
+
+impl FeatureFlagConfig {
+    pub fn validate(&self) -> Result<()> {
+        let valid_name = !self.name.is_empty()
+            && self.name.len() <= 64
+            && self.name.chars().all(|c| {
+                c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | '.')
+            });
+        if !valid_name {
+            return Err(Error::Validation(format!(
+                "Flag name {:?} must be 1-64 lowercase letters, digits, '_', '-' or '.'",
+                self.name
+            )));
+        }
+        if !(0.0..=100.0).contains(&self.rollout_percent) {
+            return Err(Error::Validation(format!(
+                "Flag {} rollout_percent must be between 0 and 100",
+                self.name
+            )));
+        }
+        if let Some(tenant) = self
+            .tenants
+            .iter()
+            .find(|t| self.excluded_tenants.contains(t))
+        {
+            return Err(Error::Validation(format!(
+                "Flag {} both targets and excludes tenant {}",
+                self.name, tenant
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Homomorphic sum/mean over encrypted numeric outputs, decrypted only as a result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AggregationConfig {
+    pub enabled: bool,
+    /// Fewest items per aggregate, so a result cannot isolate a single item
+    pub min_items: usize,
+    pub max_items: usize,
+    /// Aggregate results each tenant may decrypt per UTC day
+    pub daily_decryptions_per_tenant: u32,
+    /// How long aggregate results stay available for decryption
+    pub result_retention_seconds: u64,
+}
+
+impl Default for AggregationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_items: 5,
+            max_items: 10_000,
+            daily_decryptions_per_tenant: 100,
+            result_retention_seconds: 86_400,
+        }
+    }
+}
+
+/// Encrypted conversation context, tiered by age: memory, the persistence
+/// backend, then an archive object store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConversationConfig {
+    pub enabled: bool,
+    /// Most recent turns of each conversation kept in memory
+    pub hot_turns: usize,
+    /// Conversations idle this long have their in-memory turns moved to storage
+    pub hot_idle_seconds: u64,
+    /// Turns older than this move from the persistence backend to the archive
+    pub archive_after_seconds: u64,
+    /// Turns older than this are deleted from every tier; 0 keeps them.
+    /// Tenants may override it with `context_retention_seconds`.
+    pub retention_seconds: u64,
+    pub tiering_interval_seconds: u64,
+    pub max_turn_bytes: usize,
+    pub archive: ContextArchiveConfig,
+    pub compression: ContextCompressionConfig,
+}
+
+impl Default for ConversationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hot_turns: 8,
+            hot_idle_seconds: 900,
+            archive_after_seconds: 86_400,
+            retention_seconds: 30 * 86_400,
+            tiering_interval_seconds: 300,
+            max_turn_bytes: 1_048_576,
+            archive: ContextArchiveConfig::default(),
+            compression: ContextCompressionConfig::default(),
+        }
+    }
+}
+
+/// zstd compression of conversation turns once they leave memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContextCompressionConfig {
+    /// Tenants may override it with `compress_context`
+    pub enabled: bool,
+    /// zstd level, 1 (fastest) to 22 (smallest)
+    pub level: i32,
+    /// Turns smaller than this are stored as they are
+    pub min_bytes: usize,
+}
+
+impl Default for ContextCompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            level: 3,
+            min_bytes: 512,
+        }
+    }
+}
+
+/// Escrow of session keys with the custodians a tenant names in
+/// `escrow_custodians`; tenants without custodians are not escrowed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EscrowConfig {
+    pub enabled: bool,
+    /// Notified of every deposit and recovery step; tenants may override it
+    /// with `escrow_webhook_url`
+    pub webhook_url: Option<String>,
+    /// Fewest custodians a tenant may name
+    pub min_custodians: usize,
+    /// Recoveries not completed by every custodian within this are discarded
+    pub recovery_ttl_seconds: u64,
+}
+
+impl Default for EscrowConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            webhook_url: None,
+            min_custodians: 2,
+            recovery_ttl_seconds: 86_400,
+        }
+    }
+}
+
+/// Human approval before results of sensitive requests can be decrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DecryptionApprovalConfig {
+    pub enabled: bool,
+    /// Sensitivity classes whose results are held until approved
+    pub classes: Vec<String>,
+    pub approvers: Vec<ApproverConfig>,
+    /// Pending requests not decided within this are discarded
+    pub request_ttl_seconds: u64,
+    /// How long an approval allows decryption
+    pub approval_ttl_seconds: u64,
+    /// Notified of every new request and decision
+    pub webhook_url: Option<String>,
+}
+
+impl Default for DecryptionApprovalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            classes: vec!["restricted".to_string()],
+            approvers: Vec::new(),
+            request_ttl_seconds: 86_400,
+            approval_ttl_seconds: 3600,
+            webhook_url: None,
+        }
+    }
+}
+
+/// Versions of the HTTP API served side by side, and their retirement dates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiVersionsConfig {
+    /// Serve `/v2` alongside `/v1`
+    pub v2_enabled: bool,
+    pub lifecycle: Vec<ApiVersionLifecycle>,
+    /// Largest request body a version adapter rewrites
+    pub max_adapted_body_bytes: usize,
+    /// Answer 410 Gone once a version's sunset date has passed
+    pub reject_after_sunset: bool,
+}
+
+impl Default for ApiVersionsConfig {
+    fn default() -> Self {
+        Self {
+            v2_enabled: true,
+            lifecycle: Vec::new(),
+            max_adapted_body_bytes: 64 * 1024 * 1024,
+            reject_after_sunset: false,
+        }
+    }
+}
+
+/// Sandbox keys: experimentation traffic held to its own quotas and spend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    pub enabled: bool,
+    /// Keys active at once
+    pub max_keys: usize,
+    pub default_ttl_seconds: u64,
+    pub max_ttl_seconds: u64,
+    /// Largest spending ceiling a key may be issued with
+    pub max_spend_ceiling: f64,
+    pub default_rate_limit_per_minute: u64,
+    /// Charged to a key for each request it is admitted for
+    pub price_per_request: f64,
+    /// Charged to a key per thousand completion tokens
+    pub price_per_1k_tokens: f64,
+    /// How often expired and revoked keys are dropped
+    pub sweep_interval_seconds: u64,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_keys: 100,
+            default_ttl_seconds: 7 * 86_400,
+            max_ttl_seconds: 30 * 86_400,
+            max_spend_ceiling: 50.0,
+            default_rate_limit_per_minute: 30,
+            price_per_request: 0.001,
+            price_per_1k_tokens: 0.002,
+            sweep_interval_seconds: 60,
+        }
+    }
+}
+
+/// Decoy API keys and canary ciphertexts whose use raises a breach alert
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HoneytokenConfig {
+    pub enabled: bool,
+    pub keys_per_generation: usize,
+    pub canaries_per_generation: usize,
+    pub rotation_interval_seconds: u64,
+    /// Rotated-out generations still recognised
+    pub retired_generations: usize,
+    /// Honeytoken keys start with this, like the keys they imitate
+    pub key_prefix: String,
+    /// Request bodies up to this size are searched for canary ids
+    pub max_scanned_body_bytes: usize,
+    /// Trips kept for `/v1/admin/honeytokens`
+    pub max_trips: usize,
+}
+
+impl Default for HoneytokenConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keys_per_generation: 2,
+            canaries_per_generation: 3,
+            rotation_interval_seconds: 86_400,
+            retired_generations: 90,
+            key_prefix: "sk-".to_string(),
+            max_scanned_body_bytes: 1024 * 1024,
+            max_trips: 1000,
+        }
+    }
+}
+
+/// Credentials that establish who a request comes from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessConfig {
+    /// Tenant API keys, sent in `x-api-key`
+    pub tenant_keys: Vec<TenantKeyConfig>,
+    /// Operator tokens for privileged admin endpoints, sent in
+    /// `x-admin-token`; with none configured those endpoints are closed
+    pub admin_tokens: Vec<AdminTokenConfig>,
+}
+
+/// An API key issued to a tenant, configured by digest so the config holds no secret
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantKeyConfig {
+    pub tenant: String,
+    /// Hex SHA-256 of the key
+    pub sha256: String,
+}
+
+/// An operator's admin token, configured by digest like tenant keys
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminTokenConfig {
+    pub name: String,
+    /// Hex SHA-256 of the token
+    pub sha256: String,
+}
+
+/// Deprecation of one API version, announced in `Deprecation`, `Sunset` and
+/// `Link` response headers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiVersionLifecycle {
+    /// "v1" or "v2"
+    pub version: String,
+    /// RFC 3339 time the version is (or will be) deprecated
+    pub deprecated_at: Option<String>,
+    /// RFC 3339 time the version stops being served
+    pub sunset_at: Option<String>,
+    /// Migration guide linked from deprecated responses
+    pub link: Option<String>,
+}
+
+/// Refusal to start with weak cryptographic or operational settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StrictSecurityConfig {
+    /// Also enabled by `FHE_STRICT_SECURITY=true`
+    pub enabled: bool,
+    pub min_poly_modulus_degree: usize,
+    pub min_security_level: u8,
+    /// The listener speaks plain HTTP behind a TLS-terminating load balancer
+    /// or sidecar; without this only a loopback listener is accepted
+    pub tls_terminated_upstream: bool,
+}
+
+impl Default for StrictSecurityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_poly_modulus_degree: 8192,
+            min_security_level: 128,
+            tls_terminated_upstream: false,
+        }
+    }
+}
+
+/// Someone who may approve decryptions, identified by `x-approver-token`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApproverConfig {
+    pub name: String,
+    /// Hex SHA-256 of the approver's token
+    pub token_sha256: String,
+}
+
+/// A holder of one share of each escrowed key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EscrowCustodianConfig {
+    pub name: String,
+    /// Base64 X25519 public key shares are sealed to
+    pub public_key: String,
+}
+
+/// Object store holding archived conversation turns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContextArchiveConfig {
+    /// "memory" or "filesystem"
+    pub backend: String,
+    /// Root directory of the filesystem backend
+    pub path: String,
+}
+
+impl Default for ContextArchiveConfig {
+    fn default() -> Self {
+        Self {
+            backend: "memory".to_string(),
+            path: "./data/context-archive".to_string(),
+        }
+    }
+}
+
+/// Template variants competing for requests built from one template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentConfig {
+    pub id: String,
+    /// Requests carrying this `template_id` are enrolled
+    pub template_id: String,
+    pub variants: Vec<ExperimentVariantConfig>,
+    /// Tenants enrolled; empty enrolls every tenant
+    #[serde(default)]
+    pub tenants: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentVariantConfig {
+    pub name: String,
+    /// Template the request is routed to when assigned this variant
+    pub template_id: String,
+    /// Relative share of traffic
+    pub weight: u32,
+}
+
+impl ExperimentConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.id.is_empty() || self.template_id.is_empty() {
+            return Err(Error::Validation(
+                "Experiment ID and template must not be empty".to_string(),
+            ));
+        }
+        if self.variants.len() < 2 {
+            return Err(Error::Validation(format!(
+                "Experiment {} needs at least two variants",
+                self.id
+            )));
+        }
+        let mut names = std::collections::HashSet::new();
+        for variant in &self.variants {
+            if variant.name.is_empty() || variant.template_id.is_empty() {
+                return Err(Error::Validation(format!(
+                    "Variants of experiment {} need a name and a template",
+                    self.id
+                )));
+            }
+            if !names.insert(variant.name.as_str()) {
+                return Err(Error::Validation(format!(
+                    "Experiment {} has variant {} twice",
+                    self.id, variant.name
+                )));
+            }
+        }
+        if self.variants.iter().all(|v| v.weight == 0) {
+            return Err(Error::Validation(format!(
+                "Experiment {} has no variant with a weight above 0",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomValidatorKind {
+    /// Reject requests whose `field` matches `pattern`
+    DenyPattern,
+}
+
+/// Operator-defined validator, optionally scoped to some tenants
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomValidatorConfig {
+    pub name: String,
+    pub kind: CustomValidatorKind,
+    /// Request field a pattern applies to: `model`, `provider`, `template_id` or `tenant`
+    #[serde(default)]
+    pub field: String,
+    #[serde(default)]
+    pub pattern: String,
+    /// Tenants the validator applies to; empty applies it to every request
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    /// HTTP status returned on rejection
+    #[serde(default = "default_rejection_status")]
+    pub status: u16,
+}
+
+fn default_rejection_status() -> u16 {
+    403
+}
+
+/// What happens when a tenant opens a session beyond its cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionLimitPolicy {
+    Reject,
+    /// Evict the tenant's least recently used session to make room
+    EvictOldestIdle,
+}
+
+/// Per-tenant override document; unset fields inherit the global config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantOverrides {
+    pub rate_limit_per_minute: Option<u64>,
+    pub max_queries_per_user: Option<u32>,
+    pub epsilon_per_query: Option<f64>,
+    pub parameter_profile: Option<EncryptionConfig>,
+    pub preferred_providers: Option<Vec<String>>,
+    /// Model name -> provider name
+    pub routing_rules: Option<HashMap<String, String>>,
+    pub allowed_models: Option<Vec<String>>,
+    pub denied_models: Option<Vec<String>>,
+    /// Deprecated model -> replacement model, layered over the global upgrade map
+    pub model_upgrades: Option<HashMap<String, String>>,
+    pub response_cache_rules: Option<Vec<ResponseCacheRule>>,
+    pub sla_class: Option<SlaClass>,
+    pub max_sessions: Option<usize>,
+    pub session_limit_policy: Option<SessionLimitPolicy>,
+    pub session_webhook_url: Option<String>,
+    pub require_delegated_decryption: Option<bool>,
+    pub context_retention_seconds: Option<u64>,
+    pub compress_context: Option<bool>,
+    pub escrow_custodians: Option<Vec<EscrowCustodianConfig>>,
+    pub escrow_webhook_url: Option<String>,
+    /// Workload tags the tenant may declare; unset allows every configured tag
+    pub allowed_workload_tags: Option<Vec<String>>,
+    pub require_prompt_lint: Option<bool>,
+    pub monthly_spending_cap: Option<f64>,
+    pub spending_cap_policy: Option<SpendingCapPolicy>,
+    pub spending_webhook_url: Option<String>,
+}
+
+impl TenantOverrides {
+    /// Layer `other` on top of `self`, field by field
+    fn merge(&mut self, other: TenantOverrides) {
+        if other.rate_limit_per_minute.is_some() {
+            self.rate_limit_per_minute = other.rate_limit_per_minute;
+        }
+        if other.max_queries_per_user.is_some() {
+            self.max_queries_per_user = other.max_queries_per_user;
+        }
+        if other.epsilon_per_query.is_some() {
+            self.epsilon_per_query = other.epsilon_per_query;
+        }
+        if other.parameter_profile.is_some() {
+            self.parameter_profile = other.parameter_profile;
+        }
+        if other.preferred_providers.is_some() {
+            self.preferred_providers = other.preferred_providers;
+        }
+        if other.routing_rules.is_some() {
+            self.routing_rules = other.routing_rules;
+        }
+        if other.allowed_models.is_some() {
+            self.allowed_models = other.allowed_models;
+        }
+        if other.denied_models.is_some() {
+            self.denied_models = other.denied_models;
+        }
+        if other.model_upgrades.is_some() {
+            self.model_upgrades = other.model_upgrades;
+        }
+        if other.response_cache_rules.is_some() {
+            self.response_cache_rules = other.response_cache_rules;
+        }
+        if other.sla_class.is_some() {
+            self.sla_class = other.sla_class;
+        }
+        if other.max_sessions.is_some() {
+            self.max_sessions = other.max_sessions;
+        }
+        if other.session_limit_policy.is_some() {
+            self.session_limit_policy = other.session_limit_policy;
+        }
+        if other.session_webhook_url.is_some() {
+            self.session_webhook_url = other.session_webhook_url;
+        }
+        if other.require_delegated_decryption.is_some() {
+            self.require_delegated_decryption = other.require_delegated_decryption;
+        }
+        if other.context_retention_seconds.is_some() {
+            self.context_retention_seconds = other.context_retention_seconds;
+        }
+        if other.compress_context.is_some() {
+            self.compress_context = other.compress_context;
+        }
+        if other.escrow_custodians.is_some() {
+            self.escrow_custodians = other.escrow_custodians;
+        }
+        if other.escrow_webhook_url.is_some() {
+            self.escrow_webhook_url = other.escrow_webhook_url;
+        }
+        if other.allowed_workload_tags.is_some() {
+            self.allowed_workload_tags = other.allowed_workload_tags;
+        }
+        if other.require_prompt_lint.is_some() {
+            self.require_prompt_lint = other.require_prompt_lint;
+        }
+        if other.monthly_spending_cap.is_some() {
+            self.monthly_spending_cap = other.monthly_spending_cap;
+        }
+        if other.spending_cap_policy.is_some() {
+            self.spending_cap_policy = other.spending_cap_policy;
+        }
+        if other.spending_webhook_url.is_some() {
+            self.spending_webhook_url = other.spending_webhook_url;
+        }
+    }
+}
+
+/// Fully resolved configuration for a single tenant
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveTenantConfig {
+    pub tenant_id: String,
+    pub rate_limit_per_minute: u64,
+    pub max_queries_per_user: u32,
+    pub epsilon_per_query: f64,
+    pub encryption: EncryptionConfig,
+    pub preferred_providers: Vec<String>,
+    pub routing_rules: HashMap<String, String>,
+    pub allowed_models: Vec<String>,
+    pub denied_models: Vec<String>,
+    pub model_upgrades: HashMap<String, String>,
+    pub response_cache_rules: Vec<ResponseCacheRule>,
+    pub sla_class: SlaClass,
+    /// Maximum concurrent sessions per API key; 0 means unlimited
+    pub max_sessions: usize,
+    pub session_limit_policy: SessionLimitPolicy,
+    pub session_webhook_url: Option<String>,
+    /// Secret keys must never be used in proxy memory for this tenant
+    pub require_delegated_decryption: bool,
+    /// Age at which conversation turns are deleted; 0 keeps them
+    pub context_retention_seconds: u64,
+    /// Conversation turns are compressed once they leave memory
+    pub compress_context: bool,
+    /// Custodians session keys are escrowed with; empty when not escrowed
+    pub escrow_custodians: Vec<EscrowCustodianConfig>,
+    pub escrow_webhook_url: Option<String>,
+    /// Workload tags the tenant may declare; empty allows every configured tag
+    pub allowed_workload_tags: Vec<String>,
+    /// Completions must carry a valid client-side lint report
+    pub require_prompt_lint: bool,
+    /// Spending cap per calendar month on billed routes; 0 means uncapped
+    pub monthly_spending_cap: f64,
+    pub spending_cap_policy: SpendingCapPolicy,
+    pub spending_webhook_url: Option<String>,
+    /// Fields that differ from the global layer
+    pub overridden: Vec<String>,
+}
+
+impl EffectiveTenantConfig {
+    /// Provider for a model, honoring the tenant's routing rules
+    pub fn route_provider<'a>(&'a self, model: &str, requested: &'a str) -> &'a str {
+        self.routing_rules
+            .get(model)
+            .map(String::as_str)
+            .unwrap_or(requested)
+    }
+
+    /// First response cache rule covering this model and template, if any
+    pub fn cache_rule(&self, model: &str, template_id: Option<&str>) -> Option<&ResponseCacheRule> {
+        self.response_cache_rules
+            .iter()
+            .find(|rule| rule.matches(model, template_id))
+    }
+
+    /// Apply the upgrade map and allow/deny lists to a requested model.
+    ///
+    /// Returns the model to dispatch and, when an upgrade was applied, the
+    /// deprecated model it replaced.
+    pub fn govern_model(&self, model: &str) -> Result<(String, Option<String>)> {
+        let (effective, upgraded_from) = match self.model_upgrades.get(model) {
+            Some(replacement) => (replacement.clone(), Some(model.to_string())),
+            None => (model.to_string(), None),
+        };
+
+        if self.denied_models.iter().any(|m| m == &effective) {
+            return Err(Error::Security(format!(
+                "Model {} is denied for this tenant",
+                effective
+            )));
+        }
+        if !self.allowed_models.is_empty() && !self.allowed_models.iter().any(|m| m == &effective) {
+            return Err(Error::Security(format!(
+                "Model {} is not in the allowed model list",
+                effective
+            )));
+        }
+
+        Ok((effective, upgraded_from))
+    }
+}
+
+/// Resolves effective tenant configuration at request time, caching results
+#[derive(Debug)]
+pub struct TenantConfigResolver {
+    global: Config,
+    cache: RwLock<HashMap<String, (Instant, Arc<EffectiveTenantConfig>)>>,
+    ttl: Duration,
+    /// Bumped whenever a tenant resolution is invalidated
+    generation: AtomicU64,
+}
+
+impl TenantConfigResolver {
+    pub fn new(global: Config) -> Self {
+        let ttl = Duration::from_secs(global.tenants.cache_ttl_seconds);
+        Self {
+            global,
+            cache: RwLock::new(HashMap::new()),
+            ttl,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn resolve(&self, tenant_id: &str) -> Result<Arc<EffectiveTenantConfig>> {
+        if let Some((resolved_at, config)) = self.cache.read().unwrap().get(tenant_id) {
+            if resolved_at.elapsed() < self.ttl {
+                return Ok(config.clone());
+            }
+        }
+
+        let resolved = Arc::new(self.build(tenant_id)?);
+        self.cache
+            .write()
+            .unwrap()
+            .insert(tenant_id.to_string(), (Instant::now(), resolved.clone()));
+        Ok(resolved)
+    }
+
+    /// Drop a cached resolution so the next request re-reads the override documents
+    pub fn invalidate(&self, tenant_id: &str) {
+        self.cache.write().unwrap().remove(tenant_id);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Configuration generation, for telling apart state recorded before and after a change
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Effective configuration for requests that carry no tenant ID
+    pub fn resolve_global(&self) -> Result<Arc<EffectiveTenantConfig>> {
+        self.resolve("")
+    }
+
+    fn build(&self, tenant_id: &str) -> Result<EffectiveTenantConfig> {
+        let mut overrides = TenantOverrides::default();
+        if !tenant_id.is_empty() {
+            if let Some(inline) = self.global.tenants.overrides.get(tenant_id) {
+                overrides = inline.clone();
+            }
+            if let Some(document) = self.load_document(tenant_id)? {
+                overrides.merge(document);
+            }
+        }
+
+        let overridden = [
+            (
+                "rate_limit_per_minute",
+                overrides.rate_limit_per_minute.is_some(),
+            ),
+            (
+                "max_queries_per_user",
+                overrides.max_queries_per_user.is_some(),
+            ),
+            ("epsilon_per_query", overrides.epsilon_per_query.is_some()),
+            ("parameter_profile", overrides.parameter_profile.is_some()),
+            (
+                "preferred_providers",
+                overrides.preferred_providers.is_some(),
+            ),
+            ("routing_rules", overrides.routing_rules.is_some()),
+            ("allowed_models", overrides.allowed_models.is_some()),
+            ("denied_models", overrides.denied_models.is_some()),
+            ("model_upgrades", overrides.model_upgrades.is_some()),
+            (
+                "response_cache_rules",
+                overrides.response_cache_rules.is_some(),
+            ),
+            ("sla_class", overrides.sla_class.is_some()),
+            ("max_sessions", overrides.max_sessions.is_some()),
+            (
+                "session_limit_policy",
+                overrides.session_limit_policy.is_some(),
+            ),
+            (
+                "session_webhook_url",
+                overrides.session_webhook_url.is_some(),
+            ),
+            (
+                "require_delegated_decryption",
+                overrides.require_delegated_decryption.is_some(),
+            ),
+            (
+                "context_retention_seconds",
+                overrides.context_retention_seconds.is_some(),
+            ),
+            ("compress_context", overrides.compress_context.is_some()),
+            ("escrow_custodians", overrides.escrow_custodians.is_some()),
+            ("escrow_webhook_url", overrides.escrow_webhook_url.is_some()),
+            (
+                "allowed_workload_tags",
+                overrides.allowed_workload_tags.is_some(),
+            ),
+            (
+                "require_prompt_lint",
+                overrides.require_prompt_lint.is_some(),
+            ),
+            (
+                "monthly_spending_cap",
+                overrides.monthly_spending_cap.is_some(),
+            ),
+            (
+                "spending_cap_policy",
+                overrides.spending_cap_policy.is_some(),
+            ),
+            (
+                "spending_webhook_url",
+                overrides.spending_webhook_url.is_some(),
+            ),
+        ]
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+        let global = &self.global;
+        Ok(EffectiveTenantConfig {
+            tenant_id: tenant_id.to_string(),
+            rate_limit_per_minute: overrides
+                .rate_limit_per_minute
+                .unwrap_or(global.privacy.max_queries_per_user as u64),
+            max_queries_per_user: overrides
+                .max_queries_per_user
+                .unwrap_or(global.privacy.max_queries_per_user),
+            epsilon_per_query: overrides
+                .epsilon_per_query
+                .unwrap_or(global.privacy.epsilon_per_query),
+            encryption: overrides
+                .parameter_profile
+                .unwrap_or_else(|| global.encryption.clone()),
+            preferred_providers: overrides
+                .preferred_providers
+                .unwrap_or_else(|| vec![global.llm.provider.clone()]),
+            routing_rules: overrides.routing_rules.unwrap_or_default(),
+            allowed_models: overrides
+                .allowed_models
+                .unwrap_or_else(|| global.llm.model_governance.allowed_models.clone()),
+            denied_models: overrides
+                .denied_models
+                .unwrap_or_else(|| global.llm.model_governance.denied_models.clone()),
+            model_upgrades: {
+                let mut upgrades = global.llm.model_governance.upgrades.clone();
+                upgrades.extend(overrides.model_upgrades.unwrap_or_default());
+                upgrades
+            },
+            response_cache_rules: overrides
+                .response_cache_rules
+                .unwrap_or_else(|| global.performance.response_cache.rules.clone()),
+            sla_class: overrides
+                .sla_class
+                .unwrap_or(global.tenants.default_sla_class),
+            max_sessions: overrides
+                .max_sessions
+                .unwrap_or(global.sessions.max_sessions_per_api_key),
+            session_limit_policy: overrides
+                .session_limit_policy
+                .unwrap_or(global.sessions.over_limit_policy),
+            session_webhook_url: overrides
+                .session_webhook_url
+                .or_else(|| global.sessions.eviction_webhook_url.clone()),
+            require_delegated_decryption: overrides
+                .require_delegated_decryption
+                .unwrap_or(global.encryption.decryption_delegation.required),
+            context_retention_seconds: overrides
+                .context_retention_seconds
+                .unwrap_or(global.conversations.retention_seconds),
+            compress_context: overrides
+                .compress_context
+                .unwrap_or(global.conversations.compression.enabled),
+            escrow_custodians: overrides.escrow_custodians.unwrap_or_default(),
+            escrow_webhook_url: overrides
+                .escrow_webhook_url
+                .or_else(|| global.escrow.webhook_url.clone()),
+            allowed_workload_tags: overrides.allowed_workload_tags.unwrap_or_default(),
+            require_prompt_lint: overrides
+                .require_prompt_lint
+                .unwrap_or(global.tenants.prompt_lint.required),
+            monthly_spending_cap: overrides
+                .monthly_spending_cap
+                .unwrap_or(global.billing.spending_caps.default_monthly_cap),
+            spending_cap_policy: overrides
+                .spending_cap_policy
+                .unwrap_or(global.billing.spending_caps.policy),
+            spending_webhook_url: overrides
+                .spending_webhook_url
+                .or_else(|| global.billing.spending_caps.webhook_url.clone()),
+            overridden,
+        })
+    }
+
+    fn load_document(&self, tenant_id: &str) -> Result<Option<TenantOverrides>> {
+        let Some(dir) = &self.global.tenants.override_dir else {
+            return Ok(None);
+        };
+
+        // Tenant IDs become file names; refuse anything that could escape the directory
+        if tenant_id.is_empty()
+            || !tenant_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Error::Validation(format!(
+                "Invalid tenant ID: {}",
+                tenant_id
+            )));
+        }
+
+        let path = Path::new(dir).join(format!("{}.toml", tenant_id));
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).map(Some).map_err(|e| {
+                Error::Config(format!(
+                    "Invalid tenant override document {}: {}",
+                    path.display(),
+                    e
+                ))
+            }),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            schema_version: CONFIG_SCHEMA_VERSION,
+            server: ServerConfig {
+                host: "0.0.0.0".to_string(),
+                port: 8080,
+                workers: 4,
+                max_connections: 1000,
+                request_timeout_seconds: 300,
+                mirroring: MirroringConfig::default(),
+                transforms: TransformConfig::default(),
+                connections: ConnectionGuardConfig::default(),
+                systemd: SystemdConfig::default(),
+                deadline_budget: DeadlineBudgetConfig::default(),
+            },
+            encryption: EncryptionConfig {
+                poly_modulus_degree: 16384,
+                coeff_modulus_bits: vec![60, 40, 40, 60],
+                scale_bits: 40,
+                security_level: 128,
+                response_signing: ResponseSigningConfig::default(),
+                decryption_delegation: DecryptionDelegationConfig::default(),
+            },
+            llm: LlmConfig {
+                provider: "openai".to_string(),
+                endpoint: "https://api.openai.com/v1".to_string(),
+                timeout_seconds: 300,
+                max_retries: 3,
+                openai_api_key: None,
+                anthropic_api_key: None,
+                custom_providers: vec![],
+                recording: ProviderRecordingConfig::default(),
+                model_governance: ModelGovernanceConfig::default(),
+                resume: ProviderResumeConfig::default(),
+                error_retry: ProviderErrorRetryConfig::default(),
+                timeout_calibration: TimeoutCalibrationConfig::default(),
+                quota: ProviderQuotaConfig::default(),
+                warm_hints: WarmHintsConfig::default(),
+                openai_compatible: vec![],
+            },
+            gpu: GpuConfig {
+                enabled: false,
+                device_id: 0,
+                batch_size: 32,
+                kernel_optimization: "aggressive".to_string(),
+                memory_limit_gb: None,
+                fallback: GpuFallbackConfig::default(),
+            },
+            privacy: PrivacyConfig {
+                epsilon_per_query: 0.1,
+                delta: 1e-5,
+                max_queries_per_user: 1000,
+                track_privacy_budget: true,
+                noise_multiplier: 1.1,
+                budget_replenishment: BudgetReplenishmentConfig::default(),
+            },
+            monitoring: MonitoringConfig {
+                metrics_enabled: true,
+                metrics_port: 9090,
+                trace_sampling_rate: 0.1,
+                log_level: "info".to_string(),
+                siem: SiemExportConfig::default(),
+                runbooks: RunbooksConfig::default(),
+                state_recorder: StateRecorderConfig::default(),
+                geo_latency: GeoLatencyConfig::default(),
+                tasks: TaskSupervisorConfig::default(),
+                export_endpoints: ExportEndpoints::default(),
+                otlp_metrics: OtlpMetricsConfig::default(),
+                synthetic_probes: SyntheticProbesConfig::default(),
+                telemetry_sampling: TelemetrySamplingConfig::default(),
+                clock: ClockHealthConfig::default(),
+                trace_sampling: TraceSamplingConfig::default(),
+                quality: QualityConfig::default(),
+                dependencies: DependencyMonitorConfig::default(),
+                noise_trends: NoiseTrendsConfig::default(),
+                config_drift: ConfigDriftConfig::default(),
+                request_journal: RequestJournalConfig::default(),
+            },
+            scaling: ScalingConfig {
+                auto_scaling_enabled: true,
+                min_instances: 1,
+                max_instances: 10,
+                target_cpu_utilization: 70.0,
+                scale_up_threshold: 80.0,
+                scale_down_threshold: 30.0,
+                cooldown_period_seconds: 300,
+                connection_pool_size: 4,
+                max_concurrent_requests: 1000,
+                resource_guard: ResourceGuardConfig::default(),
+                queue_projection: QueueProjectionConfig::default(),
+                batch_windows: BatchWindowsConfig::default(),
+                stream_flow_control: StreamFlowControlConfig::default(),
+                overflow_queue: OverflowQueueConfig::default(),
+                load_shedding: LoadSheddingConfig::default(),
+                engine_fingerprints: EngineFingerprintConfig::default(),
+                dead_letter: DeadLetterConfig::default(),
+                failure_domains: FailureDomainsConfig::default(),
+            },
+            performance: PerformanceConfig {
+                cache_enabled: true,
+                cache_size_mb: 512,
+                cache_ttl_seconds: 3600,
+                batch_processing_enabled: true,
+                batch_size: 32,
+                batch_timeout_ms: 100,
+                compression_enabled: true,
+                prefetch_enabled: true,
+                async_processing: true,
+                response_chunking: ResponseChunkingConfig::default(),
+                response_cache: ResponseCacheConfig::default(),
+                engine_snapshot: EngineSnapshotConfig::default(),
+                packing: PromptPackingConfig::default(),
+            },
+            tenants: TenantsConfig::default(),
+            federation: FederationConfig::default(),
+            persistence: PersistenceConfig::default(),
+            sessions: SessionLimitsConfig::default(),
+            documents: DocumentIngestionConfig::default(),
+            validation: ValidationPipelineConfig::default(),
+            experiments: ExperimentsConfig::default(),
+            aggregation: AggregationConfig::default(),
+            conversations: ConversationConfig::default(),
+            escrow: EscrowConfig::default(),
+            billing: BillingConfig::default(),
+            disaster_recovery: DisasterRecoveryConfig::default(),
+            flags: FeatureFlagsConfig::default(),
+            transactions: TransactionConfig::default(),
+            response_metadata: ResponseMetadataConfig::default(),
+            approvals: DecryptionApprovalConfig::default(),
+            strict_security: StrictSecurityConfig::default(),
+            api_versions: ApiVersionsConfig::default(),
+            sandbox: SandboxConfig::default(),
+            honeytokens: HoneytokenConfig::default(),
+            access: AccessConfig::default(),
+        }
+    }
+}
+
+/// A validation failure and the dotted config key it concerns
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub key: String,
+    pub message: String,
+}
+
+fn invalid(key: &str, message: impl Into<String>) -> ConfigIssue {
+    ConfigIssue {
+        key: key.to_string(),
+        message: message.into(),
+    }
+}
+
+/// Upgrades a raw document from one schema version to the next, returning a
+/// note for every change it made
+type ConfigMigration = fn(&mut toml::Table) -> Vec<String>;
+
+/// `CONFIG_MIGRATIONS[n]` upgrades version `n + 1` to `n + 2`
+const CONFIG_MIGRATIONS: &[ConfigMigration] = &[migrate_v1];
+
+/// Keys that version 1 documents shipped with but no release ever read
+const V1_UNREAD_KEYS: &[&str] = &[
+    "server.enable_cors",
+    "server.cors_allowed_origins",
+    "server.cors_allowed_methods",
+    "server.cors_allowed_headers",
+    "encryption.key_rotation_hours",
+    "encryption.noise_budget_threshold",
+    "llm.retry_backoff_ms",
+    "llm.max_tokens_per_request",
+    "llm.rate_limit_per_minute",
+    "llm.allowed_models",
+    "gpu.warmup_iterations",
+    "privacy.differential_privacy_enabled",
+    "monitoring.log_format",
+    "monitoring.log_file",
+    "monitoring.log_rotation_size_mb",
+    "monitoring.log_retention_days",
+    "monitoring.performance_profiling",
+    "monitoring.export_prometheus_metrics",
+    "security",
+    "scaling.fhe_pool_size",
+    "scaling.max_concurrent_operations",
+    "scaling.connection_timeout_seconds",
+    "scaling.idle_timeout_seconds",
+    "performance.ciphertext_cache_size",
+    "performance.cache_cleanup_interval_seconds",
+    "performance.enable_redis_cache",
+    "performance.redis_url",
+    "performance.circuit_breaker_enabled",
+    "performance.failure_threshold",
+    "performance.success_threshold",
+    "performance.circuit_timeout_seconds",
+    "database",
+    "alerting",
+    "compliance",
+    "feature_flags",
+];
+
+/// Upgrade a version 1 document to version 2
+fn migrate_v1(document: &mut toml::Table) -> Vec<String> {
+    let mut notes = migrate_v1_budget_reset_hours(document);
+    notes.extend(migrate_v1_unread_keys(document));
+    notes
+}
+
+/// Version 1 documents set `privacy.privacy_budget_reset_hours`, which was
+/// never read; budgets replenish as `privacy.budget_replenishment` says.
+/// Carry the interval over unless that section already names a policy.
+fn migrate_v1_budget_reset_hours(document: &mut toml::Table) -> Vec<String> {
+    let Some(privacy) = document.get_mut("privacy").and_then(|v| v.as_table_mut()) else {
+        return Vec::new();
+    };
+    let Some(hours) = privacy.remove("privacy_budget_reset_hours") else {
+        return Vec::new();
+    };
+    let replenishment = privacy
+        .entry("budget_replenishment")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    let Some(replenishment) = replenishment
+        .as_table_mut()
+        .filter(|table| !table.contains_key("policy"))
+    else {
+        return vec![
+            "removed `privacy.privacy_budget_reset_hours`; `privacy.budget_replenishment.policy` decides when budgets replenish".to_string(),
+        ];
+    };
+    let policy = match hours.as_integer() {
+        Some(24) => "daily",
+        Some(168) => "weekly",
+        Some(hours) if hours > 0 => {
+            replenishment.insert(
+                "rolling_window_hours".to_string(),
+                toml::Value::Integer(hours),
+            );
+            "rolling"
+        }
+        _ => {
+            return vec![format!(
+                "removed `privacy.privacy_budget_reset_hours`, which was not a positive number of hours: {}",
+                hours
+            )]
+        }
+    };
+    replenishment.insert(
+        "policy".to_string(),
+        toml::Value::String(policy.to_string()),
+    );
+    vec![format!(
+        "moved `privacy.privacy_budget_reset_hours = {}` to `privacy.budget_replenishment.policy = \"{}\"`",
+        hours, policy
+    )]
+}
+
+/// Version 1 was parsed leniently, so documents carried keys nothing read.
+/// Drop them so the strict parser accepts the rest.
+fn migrate_v1_unread_keys(document: &mut toml::Table) -> Vec<String> {
+    let mut notes = Vec::new();
+    for key in V1_UNREAD_KEYS {
+        let (parent, name) = match key.rsplit_once('.') {
+            Some((section, name)) => (
+                document.get_mut(section).and_then(|v| v.as_table_mut()),
+                name,
+            ),
+            None => (Some(&mut *document), *key),
+        };
+        if parent.and_then(|table| table.remove(name)).is_some() {
+            notes.push(format!("removed `{}`, which was never read", key));
+        }
+    }
+    notes
+}
+
+/// What happened while bringing a document up to `CONFIG_SCHEMA_VERSION`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigMigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub notes: Vec<String>,
+}
+
+/// Bring a raw document up to the current schema version. Documents without
+/// `schema_version` predate versioning and count as version 1.
+pub fn migrate_document(document: &mut toml::Table) -> Result<ConfigMigrationReport> {
+    let from_version = match document.get("schema_version") {
+        None => 1,
+        Some(toml::Value::Integer(version)) if *version >= 1 => *version as u32,
+        Some(other) => {
+            return Err(Error::Config(format!(
+                "schema_version must be a positive integer, got {}",
+                other
+            )))
+        }
+    };
+    if from_version > CONFIG_SCHEMA_VERSION {
+        return Err(Error::Config(format!(
+            "Config schema version {} is newer than this build supports ({})",
+            from_version, CONFIG_SCHEMA_VERSION
+        )));
+    }
+
+    let mut notes = Vec::new();
+    for migration in &CONFIG_MIGRATIONS[from_version as usize - 1..] {
+        notes.extend(migration(document));
+    }
+    document.insert(
+        "schema_version".to_string(),
+        toml::Value::Integer(CONFIG_SCHEMA_VERSION as i64),
+    );
+    Ok(ConfigMigrationReport {
+        from_version,
+        to_version: CONFIG_SCHEMA_VERSION,
+        notes,
+    })
+}
+
+/// 1-based line of `offset` in `content`
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+/// Line on which `key` (dotted) is set, or failing that its nearest ancestor
+fn locate_key(content: &str, key: &str) -> Option<usize> {
+    let document = toml::de::DeTable::parse(content).ok()?;
+    let mut table = document.get_ref();
+    let mut offset = None;
+    for segment in key.split('.') {
+        let Some((name, value)) = table.get_key_value(segment) else {
+            break;
+        };
+        offset = Some(name.span().start);
+        match value.get_ref() {
+            toml::de::DeValue::Table(inner) => table = inner,
+            _ => break,
+        }
+    }
+    offset.map(|offset| line_of(content, offset))
+}
+
+/// Prefix a TOML error with the file and line it occurred at
+fn located_toml_error(origin: &str, content: &str, error: &toml::de::Error) -> Error {
+    match error.span() {
+        Some(span) => Error::Config(format!(
+            "{}:{}: {}",
+            origin,
+            line_of(content, span.start),
+            error.message().trim_end()
+        )),
+        None => Error::Config(format!("{}: {}", origin, error.message().trim_end())),
+    }
+}
+
+impl Config {
+    /// Load configuration from file, environment variables, or use defaults.
+    /// The file is `FHE_CONFIG_PATH`, falling back to `config.toml`.
+    pub fn load() -> Result<Self> {
+        let path = env::var("FHE_CONFIG_PATH").unwrap_or_else(|_| "config.toml".to_string());
+        let mut config = if let Ok(content) = fs::read_to_string(&path) {
+            let (config, report) = Self::parse_document(&content, &path)?;
+            for note in &report.notes {
+                log::warn!(
+                    "{}: migrated from config schema version {}: {}",
+                    path,
+                    report.from_version,
+                    note
+                );
+            }
+            config
+        } else {
+            Self::default()
+        };
+
+        // Override with environment variables
+        config.load_from_env();
+
+        Ok(config)
+    }
+
+    /// Parse a config document strictly, migrating it from an older schema
+    /// version first if needed. `origin` names the document in errors.
+    pub fn parse_document(content: &str, origin: &str) -> Result<(Self, ConfigMigrationReport)> {
+        let mut document: toml::Table =
+            toml::from_str(content).map_err(|e| located_toml_error(origin, content, &e))?;
+        let report = migrate_document(&mut document)?;
+
+        // Current documents are parsed from the original text so errors point at its lines
+        let config = if report.from_version == CONFIG_SCHEMA_VERSION {
+            toml::from_str(content).map_err(|e| located_toml_error(origin, content, &e))?
+        } else {
+            let migrated = toml::to_string(&document).map_err(|e| Error::Config(e.to_string()))?;
+            toml::from_str(&migrated).map_err(|e| {
+                located_toml_error(
+                    &format!(
+                        "{} (migrated from schema version {})",
+                        origin, report.from_version
+                    ),
+                    &migrated,
+                    &e,
+                )
+            })?
+        };
+        Ok((config, report))
+    }
+
+    /// Parse and validate a config file without starting anything, for CI
+    /// gating of config changes. Errors carry the file, line and key at fault.
+    pub fn validate_file(path: &Path) -> Result<ConfigMigrationReport> {
+        let origin = path.display().to_string();
+        let content =
+            fs::read_to_string(path).map_err(|e| Error::Config(format!("{}: {}", origin, e)))?;
+        let (config, report) = Self::parse_document(&content, &origin)?;
+        config.check().map_err(|issue| {
+            let location = match locate_key(&content, &issue.key) {
+                Some(line) => format!("{}:{}", origin, line),
+                None => origin.clone(),
+            };
+            Error::Config(format!("{}: {}: {}", location, issue.key, issue.message))
+        })?;
+        Ok(report)
+    }
+
+    /// Load configuration from environment variables
+    pub fn load_from_env(&mut self) {
+        if let Ok(host) = env::var("FHE_HOST") {
+            self.server.host = host;
+        }
+
+        if let Ok(port) = env::var("FHE_PORT") {
+            if let Ok(port) = port.parse() {
+                self.server.port = port;
+            }
+        }
+
+        if let Ok(openai_key) = env::var("OPENAI_API_KEY") {
+            self.llm.openai_api_key = Some(openai_key);
+        }
+
+        if let Ok(anthropic_key) = env::var("ANTHROPIC_API_KEY") {
+            self.llm.anthropic_api_key = Some(anthropic_key);
+        }
+
+        if let Ok(mode) = env::var("FHE_PROVIDER_MODE") {
+            self.llm.recording.mode = mode.to_lowercase();
+        }
+
+        if let Ok(directory) = env::var("FHE_RECORDINGS_DIR") {
+            self.llm.recording.directory = directory;
+        }
+
+        if let Ok(backend) = env::var("FHE_PERSISTENCE_BACKEND") {
+            self.persistence.backend = backend.to_lowercase();
+        }
+
+        if let Ok(path) = env::var("FHE_SQLITE_PATH") {
+            self.persistence.sqlite_path = path;
+        }
+
+        if let Ok(gpu_enabled) = env::var("FHE_GPU_ENABLED") {
+            self.gpu.enabled = gpu_enabled.to_lowercase() == "true";
+        }
+
+        if let Ok(device_id) = env::var("FHE_GPU_DEVICE_ID") {
+            if let Ok(device_id) = device_id.parse() {
+                self.gpu.device_id = device_id;
+            }
+        }
+
+        if let Ok(log_level) = env::var("RUST_LOG") {
+            self.monitoring.log_level = log_level;
+        }
+
+        if let Ok(metrics_enabled) = env::var("FHE_METRICS_ENABLED") {
+            self.monitoring.metrics_enabled = metrics_enabled.to_lowercase() == "true";
+        }
+
+        if let Ok(poly_degree) = env::var("FHE_POLY_MODULUS_DEGREE") {
+            if let Ok(degree) = poly_degree.parse() {
+                self.encryption.poly_modulus_degree = degree;
+            }
+        }
+
+        if let Ok(strict) = env::var("FHE_STRICT_SECURITY") {
+            self.strict_security.enabled = strict.to_lowercase() == "true";
+        }
+
+        if let Ok(security_level) = env::var("FHE_SECURITY_LEVEL") {
+            if let Ok(level) = security_level.parse() {
+                self.encryption.security_level = level;
+            }
+        }
+    }
+
+    /// Validate configuration
+    pub fn validate(&self) -> Result<()> {
+        self.check()
+            .map_err(|issue| Error::Config(format!("{}: {}", issue.key, issue.message)))
+    }
+
+    /// Validate configuration, reporting the key at fault
+    pub fn check(&self) -> std::result::Result<(), ConfigIssue> {
+        // Validate server configuration
+        if self.server.port == 0 {
+            return Err(invalid("server.port", "Invalid server port"));
+        }
+
+        if self.server.workers == 0 {
+            return Err(invalid(
+                "server.workers",
+                "Worker count must be greater than 0",
+            ));
+        }
+
+        let mirroring = &self.server.mirroring;
+        if mirroring.enabled
+            && !mirroring.target_url.starts_with("http://")
+            && !mirroring.target_url.starts_with("https://")
+        {
+            return Err(invalid(
+                "server.mirroring.target_url",
+                format!(
+                    "Mirroring target must be an http(s) URL: {:?}",
+                    mirroring.target_url
+                ),
+            ));
+        }
+        if !(0.0..=100.0).contains(&mirroring.sample_percent) {
+            return Err(invalid(
+                "server.mirroring.sample_percent",
+                "Mirroring sample percent must be in 0..=100",
+            ));
+        }
+        if mirroring.queue_size == 0 || mirroring.max_in_flight == 0 {
+            return Err(invalid(
+                "server.mirroring.queue_size",
+                "Mirroring queue size and max in-flight must be greater than 0",
+            ));
+        }
+        let mut rule_names = std::collections::HashSet::new();
+        for rule in &self.server.transforms.rules {
+            if !rule_names.insert(rule.name.as_str()) {
+                return Err(invalid(
+                    "server.transforms.rules",
+                    format!("Duplicate transform rule name: {}", rule.name),
+                ));
+            }
+            if let Err(e) = crate::middleware::TransformEngine::validate_rule(rule) {
+                return Err(invalid(
+                    &format!("server.transforms.rules.{}", rule.name),
+                    e.to_string(),
+                ));
+            }
+        }
+
+        let connections = &self.server.connections;
+        if connections.enabled
+            && (connections.header_read_timeout_seconds == 0
+                || connections.body_read_timeout_seconds == 0)
+        {
+            return Err(invalid(
+                "server.connections.header_read_timeout_seconds",
+                "Header and body read timeouts must be greater than 0",
+            ));
+        }
+
+        let budget = &self.server.deadline_budget;
+        if budget.enabled {
+            let shares = [
+                budget.validation_share,
+                budget.queue_share,
+                budget.fhe_share,
+                budget.provider_share,
+            ];
+            if shares
+                .iter()
+                .any(|share| !share.is_finite() || *share < 0.0)
+            {
+                return Err(invalid(
+                    "server.deadline_budget",
+                    "Deadline shares must be finite and not negative",
+                ));
+            }
+            if budget.provider_share <= 0.0 {
+                return Err(invalid(
+                    "server.deadline_budget.provider_share",
+                    "Provider share must be greater than 0",
+                ));
+            }
+        }
+
+        // Validate encryption parameters
+        if !self.encryption.poly_modulus_degree.is_power_of_two() {
+            return Err(invalid(
+                "encryption.poly_modulus_degree",
+                "Poly modulus degree must be a power of 2",
+            ));
+        }
+
+        if self.encryption.coeff_modulus_bits.is_empty() {
+            return Err(invalid(
+                "encryption.coeff_modulus_bits",
+                "Coefficient modulus bits cannot be empty",
+            ));
+        }
+
+        if self.encryption.response_signing.key_path.as_deref() == Some("") {
+            return Err(invalid(
+                "encryption.response_signing.key_path",
+                "Response signing key path cannot be empty",
+            ));
+        }
+
+        let delegation = &self.encryption.decryption_delegation;
+        if delegation.enabled
+            && !delegation.endpoint.starts_with("http://")
+            && !delegation.endpoint.starts_with("https://")
+        {
+            return Err(invalid(
+                "encryption.decryption_delegation.endpoint",
+                format!(
+                    "Decryption delegation endpoint must be an http(s) URL: {:?}",
+                    delegation.endpoint
+                ),
+            ));
+        }
+        if delegation.timeout_ms == 0 {
+            return Err(invalid(
+                "encryption.decryption_delegation.timeout_ms",
+                "Decryption delegation timeout must be greater than 0",
+            ));
+        }
+        if !delegation.key_handle_template.contains("{client_id}") {
+            return Err(invalid(
+                "encryption.decryption_delegation.key_handle_template",
+                "Key handle template must contain {client_id}",
+            ));
+        }
+
+        // Validate provider recording
+        if !["off", "record", "replay"].contains(&self.llm.recording.mode.as_str()) {
+            return Err(invalid(
+                "llm.recording.mode",
+                format!(
+                    "Unknown provider recording mode: {}",
+                    self.llm.recording.mode
+                ),
+            ));
+        }
+
+        let calibration = &self.llm.timeout_calibration;
+        if !(calibration.percentile > 0.0 && calibration.percentile <= 1.0) {
+            return Err(invalid(
+                "llm.timeout_calibration.percentile",
+                "Timeout calibration percentile must be in (0, 1]",
+            ));
+        }
+        if calibration.safety_margin < 1.0 {
+            return Err(invalid(
+                "llm.timeout_calibration.safety_margin",
+                "Timeout calibration safety margin must be at least 1.0",
+            ));
+        }
+        if calibration.min_timeout_ms > calibration.max_timeout_ms {
+            return Err(invalid(
+                "llm.timeout_calibration.min_timeout_ms",
+                "Timeout calibration min_timeout_ms exceeds max_timeout_ms",
+            ));
+        }
+        if calibration.min_samples == 0 || calibration.min_samples > calibration.window_size {
+            return Err(invalid(
+                "llm.timeout_calibration.min_samples",
+                "Timeout calibration min_samples must be between 1 and window_size",
+            ));
+        }
+        let error_retry = &self.llm.error_retry;
+        if error_retry.max_backoff_ms < error_retry.backoff_ms {
+            return Err(invalid(
+                "llm.error_retry.max_backoff_ms",
+                "Provider error retry max_backoff_ms is below backoff_ms",
+            ));
+        }
+        if !(0.0..1.0).contains(&self.llm.quota.reserve_fraction) {
+            return Err(invalid(
+                "llm.quota.reserve_fraction",
+                "Provider quota reserve_fraction must be in [0, 1)",
+            ));
+        }
+
+        let mut compatible_names = std::collections::HashSet::new();
+        for server in &self.llm.openai_compatible {
+            if server.name.is_empty()
+                || matches!(server.name.as_str(), "openai" | "anthropic")
+                || !compatible_names.insert(server.name.as_str())
+            {
+                return Err(invalid(
+                    "llm.openai_compatible.name",
+                    format!(
+                        "OpenAI-compatible server name {:?} is empty, reserved or duplicated",
+                        server.name
+                    ),
+                ));
+            }
+            for url in std::iter::once(&server.base_url).chain(server.model_urls.values()) {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(invalid(
+                        "llm.openai_compatible.base_url",
+                        format!("{}: {:?} is not an http(s) URL", server.name, url),
+                    ));
+                }
+            }
+            if let ProviderAuth::Header { name, .. } = &server.auth {
+                if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    return Err(invalid(
+                        "llm.openai_compatible.auth.name",
+                        format!("{}: {:?} is not a valid header name", server.name, name),
+                    ));
+                }
+            }
+        }
+
+        for provider in &error_retry.fallback_providers {
+            if !matches!(provider.as_str(), "openai" | "anthropic")
+                && !compatible_names.contains(provider.as_str())
+            {
+                return Err(invalid(
+                    "llm.error_retry.fallback_providers",
+                    format!("{:?} is not a configured provider", provider),
+                ));
+            }
+        }
+
+        let warm_hints = &self.llm.warm_hints;
+        if warm_hints.enabled && warm_hints.window_size == 0 {
+            return Err(invalid(
+                "llm.warm_hints.window_size",
+                "Window size must be greater than 0",
+            ));
+        }
+        for (provider, classes) in &warm_hints.providers {
+            let key = format!("llm.warm_hints.providers.{}", provider);
+            let hosted = matches!(provider.as_str(), "openai" | "anthropic");
+            if !hosted && !compatible_names.contains(provider.as_str()) {
+                return Err(invalid(&key, "Not a configured provider"));
+            }
+            let hints = [&classes.gold, &classes.silver, &classes.bronze];
+            for hint in hints.into_iter().flatten() {
+                if hint.priority.is_some() && hosted {
+                    return Err(invalid(
+                        &key,
+                        "priority is only sent to OpenAI-compatible servers",
+                    ));
+                }
+                if hint.service_tier.is_some() && !hosted {
+                    return Err(invalid(
+                        &key,
+                        "service_tier is only sent to the OpenAI and Anthropic APIs",
+                    ));
+                }
+                for (name, value) in &hint.headers {
+                    if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err()
+                        || reqwest::header::HeaderValue::from_str(value).is_err()
+                    {
+                        return Err(invalid(&key, format!("{:?} is not a valid header", name)));
+                    }
+                }
+            }
+        }
+
+        // Validate model governance
+        let governance = &self.llm.model_governance;
+        for (deprecated, replacement) in &governance.upgrades {
+            if deprecated == replacement {
+                return Err(invalid(
+                    "llm.model_governance.upgrades",
+                    format!("Model upgrade for {} maps to itself", deprecated),
+                ));
+            }
+            if governance.denied_models.contains(replacement) {
+                return Err(invalid(
+                    "llm.model_governance.upgrades",
+                    format!(
+                        "Model {} is upgraded to denied model {}",
+                        deprecated, replacement
+                    ),
+                ));
+            }
+        }
+
+        // Validate privacy parameters
+        if self.privacy.epsilon_per_query <= 0.0 {
+            return Err(invalid(
+                "privacy.epsilon_per_query",
+                "Epsilon per query must be positive",
+            ));
+        }
+
+        if self.privacy.delta <= 0.0 || self.privacy.delta >= 1.0 {
+            return Err(invalid("privacy.delta", "Delta must be in (0, 1)"));
+        }
+
+        let replenishment = &self.privacy.budget_replenishment;
+        if !["manual", "daily", "weekly", "rolling"].contains(&replenishment.policy.as_str()) {
+            return Err(invalid(
+                "privacy.budget_replenishment.policy",
+                format!(
+                    "Unknown privacy budget replenishment policy: {}",
+                    replenishment.policy
+                ),
+            ));
+        }
+
+        if replenishment.policy == "rolling" && replenishment.rolling_window_hours == 0 {
+            return Err(invalid(
+                "privacy.budget_replenishment.rolling_window_hours",
+                "Rolling replenishment window must be greater than 0",
+            ));
+        }
+
+        if replenishment
+            .notification_thresholds
+            .iter()
+            .any(|t| *t == 0 || *t > 100)
+        {
+            return Err(invalid(
+                "privacy.budget_replenishment.notification_thresholds",
+                "Budget notification thresholds must be in 1..=100",
+            ));
+        }
+
+        // Validate scaling configuration
+        let guard = &self.scaling.resource_guard;
+        if guard.enabled && guard.check_interval_seconds == 0 {
+            return Err(invalid(
+                "scaling.resource_guard.check_interval_seconds",
+                "Resource guard check interval must be greater than 0",
+            ));
+        }
+
+        if let Some(action) = guard
+            .actions
+            .iter()
+            .find(|a| !["flush_cache", "drain_engine", "restart"].contains(&a.as_str()))
+        {
+            return Err(invalid(
+                "scaling.resource_guard.actions",
+                format!("Unknown resource guard action: {}", action),
+            ));
+        }
+
+        let projection = &self.scaling.queue_projection;
+        if projection.throughput_window_seconds == 0 {
+            return Err(invalid(
+                "scaling.queue_projection.throughput_window_seconds",
+                "Queue projection throughput window must be greater than 0",
+            ));
+        }
+        if !(projection.ewma_alpha > 0.0 && projection.ewma_alpha <= 1.0) {
+            return Err(invalid(
+                "scaling.queue_projection.ewma_alpha",
+                "Queue projection EWMA alpha must be in (0, 1]",
+            ));
+        }
+
+        let batch = &self.scaling.batch_windows;
+        if let Err(Error::Config(message)) =
+            crate::scaling::BatchWindowScheduler::validate_windows(&batch.windows)
+        {
+            return Err(invalid("scaling.batch_windows.windows", message));
+        }
+        if !(batch.quota_discount > 0.0 && batch.quota_discount <= 1.0) {
+            return Err(invalid(
+                "scaling.batch_windows.quota_discount",
+                "Batch window quota discount must be in (0, 1]",
+            ));
+        }
+        if batch.daily_quota_units <= 0.0 {
+            return Err(invalid(
+                "scaling.batch_windows.daily_quota_units",
+                "Batch window daily quota must be greater than 0",
+            ));
+        }
+        if batch.max_items_per_job == 0 {
+            return Err(invalid(
+                "scaling.batch_windows.max_items_per_job",
+                "Must be greater than 0",
+            ));
+        }
+        if batch.max_jobs_per_tick == 0 || batch.poll_interval_seconds == 0 {
+            return Err(invalid(
+                "scaling.batch_windows.max_jobs_per_tick",
+                "Batch window jobs per tick and poll interval must be greater than 0",
+            ));
+        }
+        if batch.enabled && batch.windows.is_empty() {
+            return Err(invalid(
+                "scaling.batch_windows.windows",
+                "Batch windows are enabled but none are configured",
+            ));
+        }
+        if let Some(url) = &batch.notification_webhook_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(invalid(
+                    "scaling.batch_windows.notification_webhook_url",
+                    format!("Batch notification webhook must be an http(s) URL: {}", url),
+                ));
+            }
+        }
+
+        let overflow = &self.scaling.overflow_queue;
+        if overflow.max_entries == 0 || overflow.max_request_bytes == 0 {
+            return Err(invalid(
+                "scaling.overflow_queue.max_entries",
+                "Overflow queue entry and request size limits must be greater than 0",
+            ));
+        }
+        if overflow.max_request_bytes as u64 > overflow.max_bytes_per_priority {
+            return Err(invalid(
+                "scaling.overflow_queue.max_request_bytes",
+                "Overflow queue requests must fit in a priority's ring",
+            ));
+        }
+        if overflow.max_wait_seconds == 0 || overflow.drain_interval_ms == 0 {
+            return Err(invalid(
+                "scaling.overflow_queue.max_wait_seconds",
+                "Overflow queue wait limit and drain interval must be greater than 0",
+            ));
+        }
+
+        let shedding = &self.scaling.load_shedding;
+        if !(0.0..=100.0).contains(&shedding.log_sample_percent) {
+            return Err(invalid(
+                "scaling.load_shedding.log_sample_percent",
+                "Simulated decision log sample must be between 0 and 100 percent",
+            ));
+        }
+
+        if self.scaling.engine_fingerprints.check_interval_seconds == 0 {
+            return Err(invalid(
+                "scaling.engine_fingerprints.check_interval_seconds",
+                "Engine fingerprint check interval must be greater than 0",
+            ));
+        }
+
+        let dead_letter = &self.scaling.dead_letter;
+        if dead_letter.max_attempts == 0 {
+            return Err(invalid(
+                "scaling.dead_letter.max_attempts",
+                "Dead letter attempts must be greater than 0",
+            ));
+        }
+        if dead_letter.growth_alert_threshold == 0
+            || dead_letter.growth_window_seconds == 0
+            || dead_letter.check_interval_seconds == 0
+        {
+            return Err(invalid(
+                "scaling.dead_letter.growth_alert_threshold",
+                "Dead letter growth threshold, window and check interval must be greater than 0",
+            ));
+        }
+
+        let domains = &self.scaling.failure_domains;
+        if domains.window_seconds == 0
+            || domains.drain_seconds == 0
+            || domains.check_interval_seconds == 0
+        {
+            return Err(invalid(
+                "scaling.failure_domains",
+                "Failure domain window, drain and check interval must be greater than 0",
+            ));
+        }
+        if domains.member_failure_threshold == 0 || domains.min_failing_members < 2 {
+            return Err(invalid(
+                "scaling.failure_domains.min_failing_members",
+                "A correlated failure needs at least 2 failing members, each with at least 1 failure",
+            ));
+        }
+        if !(domains.correlated_fraction > 0.0 && domains.correlated_fraction <= 1.0) {
+            return Err(invalid(
+                "scaling.failure_domains.correlated_fraction",
+                "Correlated failure fraction must be in (0, 1]",
+            ));
+        }
+
+        let flow = &self.scaling.stream_flow_control;
+        if !(flow.load_threshold > 0.0 && flow.load_threshold <= 1.0) {
+            return Err(invalid(
+                "scaling.stream_flow_control.load_threshold",
+                "Stream flow control load threshold must be in (0, 1]",
+            ));
+        }
+        if flow.buffer_frames == 0 || flow.max_streams == 0 {
+            return Err(invalid(
+                "scaling.stream_flow_control.max_streams",
+                "Stream buffer frames and stream limit must be greater than 0",
+            ));
+        }
+        if flow.max_suggested_delay_ms < flow.chunk_interval_ms {
+            return Err(invalid(
+                "scaling.stream_flow_control.max_suggested_delay_ms",
+                "Maximum suggested stream delay must not be below the chunk interval",
+            ));
+        }
+
+        // Validate performance configuration
+        if self.performance.response_chunking.chunk_size_bytes == 0 {
+            return Err(invalid(
+                "performance.response_chunking.chunk_size_bytes",
+                "Response chunk size must be greater than 0",
+            ));
+        }
+
+        if self
+            .performance
+            .response_cache
+            .rules
+            .iter()
+            .any(|rule| rule.ttl_seconds == 0)
+        {
+            return Err(invalid(
+                "performance.response_cache.rules",
+                "Response cache rule TTL must be greater than 0",
+            ));
+        }
+        let max_stale = self.performance.response_cache.max_stale_seconds;
+        if self
+            .performance
+            .response_cache
+            .rules
+            .iter()
+            .any(|rule| rule.stale_while_revalidate_seconds > max_stale)
+        {
+            return Err(invalid(
+                "performance.response_cache.rules",
+                format!(
+                    "Response cache rule stale_while_revalidate_seconds exceeds max_stale_seconds ({})",
+                    max_stale
+                ),
+            ));
+        }
+        let preload = &self.performance.response_cache.preload;
+        if preload.enabled
+            && (!(preload.confidence_threshold > 0.0 && preload.confidence_threshold <= 1.0)
+                || preload.interval_seconds == 0)
+        {
+            return Err(invalid(
+                "performance.response_cache.preload",
+                "Preload confidence_threshold must be in (0, 1] and interval_seconds greater than 0",
+            ));
+        }
+
+        // Validate GPU configuration
+        if self.gpu.enabled && self.gpu.batch_size == 0 {
+            return Err(invalid(
+                "gpu.batch_size",
+                "GPU batch size must be greater than 0",
+            ));
+        }
+        if self.gpu.enabled && self.gpu.fallback.max_concurrent_operations == 0 {
+            return Err(invalid(
+                "gpu.fallback.max_concurrent_operations",
+                "At least one GPU operation must be allowed at once",
+            ));
+        }
+
+        if self.performance.engine_snapshot.enabled
+            && self.performance.engine_snapshot.path.is_empty()
+        {
+            return Err(invalid(
+                "performance.engine_snapshot.path",
+                "Engine snapshot path must not be empty",
+            ));
+        }
+
+        if self.performance.packing.enabled && self.performance.packing.max_batch < 2 {
+            return Err(invalid(
+                "performance.packing.max_batch",
+                "Prompt packing needs a max batch of at least 2",
+            ));
+        }
+
+        // Validate SIEM export
+        let siem = &self.monitoring.siem;
+        if !["cef", "otlp"].contains(&siem.format.as_str()) {
+            return Err(invalid(
+                "monitoring.siem.format",
+                format!("Unknown SIEM format: {}", siem.format),
+            ));
+        }
+        if siem.enabled {
+            if siem.endpoint.is_empty() {
+                return Err(invalid(
+                    "monitoring.siem.endpoint",
+                    "SIEM export requires an endpoint",
+                ));
+            }
+            if siem.batch_size == 0 || siem.buffer_size < siem.batch_size {
+                return Err(invalid("monitoring.siem.buffer_size", 
+                    "SIEM buffer size must be at least the batch size, which must be greater than 0",
+                ));
+            }
+        }
+
+        // Validate runbooks
+        let runbooks = &self.monitoring.runbooks;
+        if runbooks.enabled && runbooks.evaluation_interval_seconds == 0 {
+            return Err(invalid(
+                "monitoring.runbooks.evaluation_interval_seconds",
+                "Runbook evaluation interval must be greater than 0",
+            ));
+        }
+        let mut rule_names = std::collections::HashSet::new();
+        for rule in &runbooks.rules {
+            if rule.name.is_empty() || !rule_names.insert(rule.name.as_str()) {
+                return Err(invalid(
+                    "monitoring.runbooks.rules",
+                    format!(
+                        "Runbook rule names must be unique and non-empty: {:?}",
+                        rule.name
+                    ),
+                ));
+            }
+            if let RunbookTrigger::BudgetExhaustionSpike { per_minute } = rule.trigger {
+                if per_minute <= 0.0 {
+                    return Err(invalid(
+                        "monitoring.runbooks.rules",
+                        format!(
+                            "Runbook {} needs a positive budget exhaustion rate",
+                            rule.name
+                        ),
+                    ));
+                }
+            }
+        }
+
+        let recorder = &self.monitoring.state_recorder;
+        if recorder.enabled
+            && (recorder.interval_seconds == 0
+                || recorder.retention_minutes * 60 < recorder.interval_seconds)
+        {
+            return Err(invalid(
+                "monitoring.state_recorder.retention_minutes",
+                "State recorder retention must cover at least one non-zero interval",
+            ));
+        }
+
+        let tasks = &self.monitoring.tasks;
+        if tasks.initial_backoff_ms == 0
+            || tasks.max_backoff_seconds * 1000 < tasks.initial_backoff_ms
+        {
+            return Err(invalid(
+                "monitoring.tasks",
+                "initial_backoff_ms must be greater than 0 and at most max_backoff_seconds",
+            ));
+        }
+
+        if let Some(url) = &self.monitoring.export_endpoints.otel_collector_endpoint {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(invalid(
+                    "monitoring.export_endpoints.otel_collector_endpoint",
+                    format!("OTLP collector endpoint must be an http(s) URL: {}", url),
+                ));
+            }
+        }
+        let otlp = &self.monitoring.otlp_metrics;
+        if otlp.collection_interval_seconds == 0
+            || otlp.export_interval_seconds < otlp.collection_interval_seconds
+        {
+            return Err(invalid(
+                "monitoring.otlp_metrics.export_interval_seconds",
+                "OTLP collection interval must be between 1 second and the export interval",
+            ));
+        }
+        if otlp.max_buffered_samples == 0 || otlp.max_backoff_seconds < otlp.export_interval_seconds
+        {
+            return Err(invalid(
+                "monitoring.otlp_metrics.max_buffered_samples",
+                "OTLP sample buffer must be non-empty and backoff at least the export interval",
+            ));
+        }
+        if otlp.backfill_bucket_seconds == 0
+            || otlp.max_backfill_seconds < otlp.backfill_bucket_seconds
+        {
+            return Err(invalid(
+                "monitoring.otlp_metrics.backfill_bucket_seconds",
+                "Backfill buckets must be at least 1 second and no longer than max_backfill_seconds",
+            ));
+        }
+
+        let probes = &self.monitoring.synthetic_probes;
+        if probes.enabled {
+            if probes.interval_seconds == 0
+                || probes.timeout_seconds == 0
+                || probes.timeout_seconds > probes.interval_seconds
+            {
+                return Err(invalid(
+                    "monitoring.synthetic_probes.timeout_seconds",
+                    "Probe timeout must be between 1 second and the probe interval",
+                ));
+            }
+            if probes.window == 0 || probes.min_samples == 0 || probes.min_samples > probes.window {
+                return Err(invalid(
+                    "monitoring.synthetic_probes.min_samples",
+                    "Probe SLOs need between 1 and window samples",
+                ));
+            }
+            if !(0.0..=1.0).contains(&probes.success_slo) {
+                return Err(invalid(
+                    "monitoring.synthetic_probes.success_slo",
+                    "Probe success SLO must be between 0.0 and 1.0",
+                ));
+            }
+            for target in &probes.targets {
+                if let Some(url) = &target.url {
+                    if !url.starts_with("http://") && !url.starts_with("https://") {
+                        return Err(invalid(
+                            "monitoring.synthetic_probes.targets",
+                            format!("Probe target URL must be http(s): {}", url),
+                        ));
+                    }
+                }
+            }
+        }
+
+        let telemetry = &self.monitoring.telemetry_sampling;
+        if !(0.0..=1.0).contains(&telemetry.sample_rate) {
+            return Err(invalid(
+                "monitoring.telemetry_sampling.sample_rate",
+                "Telemetry sample rate must be between 0.0 and 1.0",
+            ));
+        }
+        if telemetry.enabled && telemetry.max_series == 0 {
+            return Err(invalid(
+                "monitoring.telemetry_sampling.max_series",
+                "Telemetry series cap must be greater than 0",
+            ));
+        }
+
+        if !(0.0..=1.0).contains(&self.monitoring.trace_sampling_rate) {
+            return Err(invalid(
+                "monitoring.trace_sampling_rate",
+                "Trace sample rate must be between 0.0 and 1.0",
+            ));
+        }
+        let tracing = &self.monitoring.trace_sampling;
+        if tracing.enabled && tracing.max_routes == 0 {
+            return Err(invalid(
+                "monitoring.trace_sampling.max_routes",
+                "Route cap must be greater than 0",
+            ));
+        }
+
+        let quality = &self.monitoring.quality;
+        if quality.enabled {
+            if quality.max_tracked_requests == 0 {
+                return Err(invalid(
+                    "monitoring.quality.max_tracked_requests",
+                    "Tracked request cap must be greater than 0",
+                ));
+            }
+            if !(0.0..=1.0).contains(&quality.downrank_below) {
+                return Err(invalid(
+                    "monitoring.quality.downrank_below",
+                    "Downrank threshold must be between 0.0 and 1.0",
+                ));
+            }
+        }
+
+        let dependencies = &self.monitoring.dependencies;
+        if dependencies.enabled {
+            if dependencies.probe_interval_seconds == 0 {
+                return Err(invalid(
+                    "monitoring.dependencies.probe_interval_seconds",
+                    "Probe interval must be greater than 0",
+                ));
+            }
+            if dependencies.failure_threshold == 0 {
+                return Err(invalid(
+                    "monitoring.dependencies.failure_threshold",
+                    "Failure threshold must be greater than 0",
+                ));
+            }
+        }
+
+        let noise_trends = &self.monitoring.noise_trends;
+        if noise_trends.enabled {
+            if noise_trends.window_seconds == 0 {
+                return Err(invalid(
+                    "monitoring.noise_trends.window_seconds",
+                    "Window must be greater than 0",
+                ));
+            }
+            if noise_trends.baseline_windows == 0
+                || noise_trends.baseline_windows >= noise_trends.retained_windows
+            {
+                return Err(invalid(
+                    "monitoring.noise_trends.baseline_windows",
+                    "Baseline windows must be greater than 0 and fewer than retained_windows",
+                ));
+            }
+            if noise_trends.alert_ratio <= 1.0 {
+                return Err(invalid(
+                    "monitoring.noise_trends.alert_ratio",
+                    "Alert ratio must be greater than 1.0",
+                ));
+            }
+        }
+
+        let drift = &self.monitoring.config_drift;
+        if drift.enabled {
+            if drift.publish_interval_seconds == 0 {
+                return Err(invalid(
+                    "monitoring.config_drift.publish_interval_seconds",
+                    "Publish interval must be greater than 0",
+                ));
+            }
+            if drift.replica_ttl_seconds <= drift.publish_interval_seconds
+                || drift.forget_after_seconds < drift.replica_ttl_seconds
+            {
+                return Err(invalid(
+                    "monitoring.config_drift.replica_ttl_seconds",
+                    "Replica TTL must be longer than the publish interval and no longer than forget_after_seconds",
+                ));
+            }
+            if drift.replica_id.as_ref().is_some_and(String::is_empty) {
+                return Err(invalid(
+                    "monitoring.config_drift.replica_id",
+                    "Replica ID must not be empty",
+                ));
+            }
+        }
+
+        let journal = &self.monitoring.request_journal;
+        if journal.enabled {
+            if journal.retention_seconds == 0
+                || journal.max_requests == 0
+                || journal.max_events_per_request == 0
+            {
+                return Err(invalid(
+                    "monitoring.request_journal",
+                    "Retention, max requests and max events per request must be greater than 0",
+                ));
+            }
+            let mut token_names = std::collections::HashSet::new();
+            for token in &journal.support_tokens {
+                if !token_names.insert(token.name.as_str()) {
+                    return Err(invalid(
+                        "monitoring.request_journal.support_tokens",
+                        format!("Duplicate support token name: {}", token.name),
+                    ));
+                }
+                if token.sha256.len() != 64 || !token.sha256.chars().all(|c| c.is_ascii_hexdigit())
+                {
+                    return Err(invalid(
+                        &format!("monitoring.request_journal.support_tokens.{}", token.name),
+                        "sha256 must be 64 hex digits",
+                    ));
+                }
+            }
+        }
+
+        let clock = &self.monitoring.clock;
+        if clock.enabled {
+            if clock.ntp_servers.is_empty() {
+                return Err(invalid(
+                    "monitoring.clock.ntp_servers",
+                    "At least one NTP server is required",
+                ));
+            }
+            if let Some(server) = clock.ntp_servers.iter().find(|s| !s.contains(':')) {
+                return Err(invalid(
+                    "monitoring.clock.ntp_servers",
+                    format!("NTP servers must be host:port: {}", server),
+                ));
+            }
+            if clock.poll_interval_seconds == 0 || clock.query_timeout_ms == 0 {
+                return Err(invalid(
+                    "monitoring.clock.poll_interval_seconds",
+                    "Poll interval and query timeout must be greater than 0",
+                ));
+            }
+            if clock.max_skew_ms == 0 {
+                return Err(invalid(
+                    "monitoring.clock.max_skew_ms",
+                    "Must be greater than 0",
+                ));
+            }
+        }
+
+        let geo = &self.monitoring.geo_latency;
+        if geo.enabled {
+            if geo.window_minutes == 0 || geo.max_regions == 0 {
+                return Err(invalid(
+                    "monitoring.geo_latency.window_minutes",
+                    "Geo latency window and region cap must be greater than 0",
+                ));
+            }
+            if geo.latency_buckets_ms.is_empty()
+                || geo.latency_buckets_ms.windows(2).any(|w| w[0] >= w[1])
+            {
+                return Err(invalid(
+                    "monitoring.geo_latency.latency_buckets_ms",
+                    "Geo latency buckets must be non-empty and strictly ascending",
+                ));
+            }
+            for (region, ranges) in &geo.ip_ranges {
+                if let Some(range) = ranges
+                    .iter()
+                    .find(|range| crate::monitoring::IpRange::parse(range).is_none())
+                {
+                    return Err(invalid(
+                        "monitoring.geo_latency.ip_ranges",
+                        format!("Region {} has an invalid IP range: {}", region, range),
+                    ));
+                }
+            }
+        }
+
+        let redaction = &self.tenants.redaction;
+        if redaction.enabled {
+            if !self.encryption.response_signing.enabled {
+                return Err(invalid(
+                    "tenants.redaction.enabled",
+                    "Redaction policies require response signing to be enabled",
+                ));
+            }
+            if redaction.max_rules == 0 || redaction.max_versions == 0 {
+                return Err(invalid(
+                    "tenants.redaction.max_rules",
+                    "Redaction policy limits must be greater than 0",
+                ));
+            }
+        }
+
+        let limit_rules = &self.tenants.rate_limit_rules;
+        if limit_rules.max_expression_bytes == 0
+            || limit_rules.max_nodes == 0
+            || limit_rules.max_steps == 0
+            || limit_rules.max_eval_micros == 0
+        {
+            return Err(invalid(
+                "tenants.rate_limit_rules.max_steps",
+                "Rate limit rule budgets must be greater than 0",
+            ));
+        }
+        let mut rule_names = std::collections::HashSet::new();
+        for rule in &limit_rules.rules {
+            if !rule_names.insert(rule.name.as_str()) {
+                return Err(invalid(
+                    "tenants.rate_limit_rules.rules",
+                    format!("Duplicate rate limit rule name: {}", rule.name),
+                ));
+            }
+            if let Err(e) = crate::limit_rules::RateLimitRules::validate_rule(rule, limit_rules) {
+                return Err(invalid(
+                    &format!("tenants.rate_limit_rules.rules.{}", rule.name),
+                    e.to_string(),
+                ));
+            }
+        }
+
+        let workload_tags = &self.tenants.workload_tags;
+        if let Some(tag) = &workload_tags.default_tag {
+            if !workload_tags.tags.contains_key(tag) {
+                return Err(invalid(
+                    "tenants.workload_tags.default_tag",
+                    format!("Default workload tag {} is not configured", tag),
+                ));
+            }
+        }
+        for (tag, policy) in &workload_tags.tags {
+            if let Err(e) = crate::workload_tags::WorkloadTags::validate_policy(tag, policy) {
+                return Err(invalid(
+                    &format!("tenants.workload_tags.tags.{}", tag),
+                    e.to_string(),
+                ));
+            }
+        }
+
+        let lint = &self.tenants.prompt_lint;
+        if lint.max_chars == 0 || lint.max_report_age_seconds == 0 {
+            return Err(invalid(
+                "tenants.prompt_lint.max_chars",
+                "Prompt lint length limit and report age must be greater than 0",
+            ));
+        }
+        if lint.forbidden_placeholders.iter().any(String::is_empty) {
+            return Err(invalid(
+                "tenants.prompt_lint.forbidden_placeholders",
+                "Forbidden placeholders cannot be empty strings",
+            ));
+        }
+
+        // Validate persistence
+        if !["memory", "sqlite"].contains(&self.persistence.backend.as_str()) {
+            return Err(invalid(
+                "persistence.backend",
+                format!("Unknown persistence backend: {}", self.persistence.backend),
+            ));
+        }
+        if self.persistence.compaction_interval_seconds == 0
+            || self.persistence.ledger_flush_interval_seconds == 0
+        {
+            return Err(invalid(
+                "persistence.compaction_interval_seconds",
+                "Persistence intervals must be greater than 0",
+            ));
+        }
+        let migrations = &self.persistence.migrations;
+        if migrations.lock_lease_seconds == 0 || migrations.wait_timeout_seconds == 0 {
+            return Err(invalid(
+                "persistence.migrations.lock_lease_seconds",
+                "Migration lock lease and wait timeout must be greater than 0",
+            ));
+        }
+        let standby = &self.persistence.standby;
+        if standby.enabled {
+            if !standby.peer_url.starts_with("http://") && !standby.peer_url.starts_with("https://")
+            {
+                return Err(invalid(
+                    "persistence.standby.peer_url",
+                    "Standby pairs need the peer's http(s) base URL",
+                ));
+            }
+            if standby.node_id.is_empty() {
+                return Err(invalid(
+                    "persistence.standby.node_id",
+                    "Standby node id cannot be empty",
+                ));
+            }
+            if standby.heartbeat_interval_ms == 0
+                || standby.failover_timeout_ms < 2 * standby.heartbeat_interval_ms
+            {
+                return Err(invalid(
+                    "persistence.standby.failover_timeout_ms",
+                    "Failover timeout must cover at least two non-zero heartbeat intervals",
+                ));
+            }
+            if standby.replication_interval_seconds == 0 || standby.command_timeout_seconds == 0 {
+                return Err(invalid(
+                    "persistence.standby.replication_interval_seconds",
+                    "Standby replication interval and command timeout must be greater than 0",
+                ));
+            }
+        }
+
+        let replication = &self.persistence.replication;
+        if replication.enabled {
+            if replication.region.is_empty() {
+                return Err(invalid(
+                    "persistence.replication.region",
+                    "Replication region must not be empty",
+                ));
+            }
+            if replication.reconcile_interval_seconds == 0 || replication.timeout_seconds == 0 {
+                return Err(invalid(
+                    "persistence.replication.reconcile_interval_seconds",
+                    "Replication intervals must be greater than 0",
+                ));
+            }
+            if let Some(peer) = replication
+                .peers
+                .iter()
+                .find(|peer| !peer.starts_with("http://") && !peer.starts_with("https://"))
+            {
+                return Err(invalid(
+                    "persistence.replication.peers",
+                    format!("Replication peer must be an http(s) URL: {}", peer),
+                ));
+            }
+        }
+
+        // Validate federation
+        if self.federation.enabled {
+            if self.federation.node_id.is_empty() {
+                return Err(invalid(
+                    "federation.node_id",
+                    "Federation node ID must not be empty",
+                ));
+            }
+            if self.federation.max_hops == 0 {
+                return Err(invalid(
+                    "federation.max_hops",
+                    "Federation max hops must be greater than 0",
+                ));
+            }
+            let mut seen = std::collections::HashSet::new();
+            for peer in &self.federation.peers {
+                if !seen.insert(peer.id.as_str()) || peer.id == self.federation.node_id {
+                    return Err(invalid(
+                        "federation.peers",
+                        format!("Duplicate federation peer ID: {}", peer.id),
+                    ));
+                }
+                if peer.shared_secret.len() < 32 {
+                    return Err(invalid(
+                        "federation.peers",
+                        format!(
+                            "Shared secret for federation peer {} must be at least 32 bytes",
+                            peer.id
+                        ),
+                    ));
+                }
+            }
+        }
+
+        // Validate document ingestion
+        let documents = &self.documents;
+        if documents.enabled
+            && (documents.max_document_bytes == 0
+                || documents.chunk_size_bytes == 0
+                || documents.max_concurrent_jobs == 0)
+        {
+            return Err(invalid(
+                "documents.max_document_bytes",
+                "Document size, chunk size and concurrent jobs must be greater than 0",
+            ));
+        }
+        let autoscaling = &documents.autoscaling;
+        if documents.enabled
+            && autoscaling.enabled
+            && (autoscaling.min_workers == 0
+                || autoscaling.max_workers < autoscaling.min_workers
+                || autoscaling.interval_seconds == 0
+                || !(autoscaling.shrink_below_ratio > 0.0 && autoscaling.shrink_below_ratio < 1.0))
+        {
+            return Err(invalid(
+                "documents.autoscaling",
+                "Workers must be at least 1 and min_workers no more than max_workers, the \
+                 interval greater than 0 and shrink_below_ratio between 0 and 1",
+            ));
+        }
+
+        let transactions = &self.transactions;
+        if transactions.enabled
+            && (transactions.max_outputs == 0 || transactions.max_steps < transactions.max_outputs)
+        {
+            return Err(invalid(
+                "transactions.max_outputs",
+                "Must be at least 1 and no more than transactions.max_steps",
+            ));
+        }
+
+        for namespace in &self.response_metadata.extension_namespaces {
+            if namespace.is_empty()
+                || !namespace
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            {
+                return Err(invalid(
+                    "response_metadata.extension_namespaces",
+                    format!(
+                        "Namespace {:?} must be lowercase letters, digits and underscores",
+                        namespace
+                    ),
+                ));
+            }
+        }
+
+        // Validate the request validator chain
+        let validation = &self.validation;
+        let builtins = ["size", "schema", "policy", "params_hash", "replay"];
+        let mut names = std::collections::HashSet::new();
+        for custom in &validation.custom {
+            if custom.name.is_empty()
+                || builtins.contains(&custom.name.as_str())
+                || !names.insert(custom.name.as_str())
+            {
+                return Err(invalid(
+                    "validation.custom",
+                    format!(
+                        "Custom validator names must be unique, non-empty and not built-in: {:?}",
+                        custom.name
+                    ),
+                ));
+            }
+            match custom.kind {
+                CustomValidatorKind::DenyPattern => {
+                    if !["model", "provider", "template_id", "tenant"]
+                        .contains(&custom.field.as_str())
+                    {
+                        return Err(invalid(
+                            "validation.custom",
+                            format!(
+                                "Validator {} checks unknown field {:?}",
+                                custom.name, custom.field
+                            ),
+                        ));
+                    }
+                    if let Err(e) = regex::Regex::new(&custom.pattern) {
+                        return Err(invalid(
+                            "validation.custom",
+                            format!("Validator {} has an invalid pattern: {}", custom.name, e),
+                        ));
+                    }
+                }
+            }
+            if !(400..600).contains(&custom.status) {
+                return Err(invalid(
+                    "validation.custom",
+                    format!(
+                        "Validator {} must reject with a 4xx or 5xx status",
+                        custom.name
+                    ),
+                ));
+            }
+        }
+        let replay_cache = &validation.replay_cache;
+        if !(replay_cache.false_positive_rate > 0.0 && replay_cache.false_positive_rate < 0.5) {
+            return Err(invalid(
+                "validation.replay_cache.false_positive_rate",
+                "Replay cache false positive rate must be in (0, 0.5)",
+            ));
+        }
+        if replay_cache.expected_nonces_per_window == 0 || replay_cache.buckets == 0 {
+            return Err(invalid(
+                "validation.replay_cache.buckets",
+                "Replay cache buckets and expected nonces must be greater than 0",
+            ));
+        }
+        if validation.replay_window_seconds < replay_cache.buckets as u64 {
+            return Err(invalid(
+                "validation.replay_window_seconds",
+                "Replay window must be at least one second per replay cache bucket",
+            ));
+        }
+        let mut ordered = std::collections::HashSet::new();
+        for name in &validation.order {
+            if !builtins.contains(&name.as_str()) && !names.contains(name.as_str()) {
+                return Err(invalid(
+                    "validation.order",
+                    format!("Unknown validator in order: {}", name),
+                ));
+            }
+            if !ordered.insert(name.as_str()) {
+                return Err(invalid(
+                    "validation.order",
+                    format!("Validator {} is listed twice", name),
+                ));
+            }
+        }
+
+        let experiments = &self.experiments;
+        if experiments.definitions.len() > experiments.max_experiments {
+            return Err(invalid(
+                "experiments.definitions",
+                "More experiments defined than experiments.max_experiments",
+            ));
+        }
+        let mut experiment_ids = std::collections::HashSet::new();
+        for experiment in &experiments.definitions {
+            experiment
+                .validate()
+                .map_err(|e| invalid("experiments.definitions", e.to_string()))?;
+            if !experiment_ids.insert(experiment.id.as_str()) {
+                return Err(invalid(
+                    "experiments.definitions",
+                    format!("Experiment {} is defined twice", experiment.id),
+                ));
+            }
+        }
+
+        let flags = &self.flags;
+        if flags.refresh_interval_seconds == 0 {
+            return Err(invalid(
+                "flags.refresh_interval_seconds",
+                "Must be at least 1 second",
+            ));
+        }
+        let mut flag_names = std::collections::HashSet::new();
+        for flag in &flags.definitions {
+            flag.validate()
+                .map_err(|e| invalid("flags.definitions", e.to_string()))?;
+            if !flag_names.insert(flag.name.as_str()) {
+                return Err(invalid(
+                    "flags.definitions",
+                    format!("Flag {} is defined twice", flag.name),
+                ));
+            }
+        }
+
+        let aggregation = &self.aggregation;
+        if aggregation.min_items == 0 {
+            return Err(invalid(
+                "aggregation.min_items",
+                "At least one item is required",
+            ));
+        }
+        if aggregation.max_items < aggregation.min_items {
+            return Err(invalid(
+                "aggregation.max_items",
+                "Must be at least aggregation.min_items",
+            ));
+        }
+        if aggregation.result_retention_seconds == 0 {
+            return Err(invalid(
+                "aggregation.result_retention_seconds",
+                "Must be greater than 0",
+            ));
+        }
+
+        let conversations = &self.conversations;
+        if conversations.tiering_interval_seconds == 0 || conversations.max_turn_bytes == 0 {
+            return Err(invalid(
+                "conversations",
+                "Tiering interval and max turn size must be greater than 0",
+            ));
+        }
+        if !["memory", "filesystem"].contains(&conversations.archive.backend.as_str()) {
+            return Err(invalid(
+                "conversations.archive.backend",
+                format!(
+                    "Unknown archive backend {}; expected memory or filesystem",
+                    conversations.archive.backend
+                ),
+            ));
+        }
+        if !(1..=22).contains(&conversations.compression.level) {
+            return Err(invalid(
+                "conversations.compression.level",
+                "Must be between 1 and 22",
+            ));
+        }
+
+        if let Some(route) = self.billing.routes.iter().find(|r| !r.starts_with('/')) {
+            return Err(invalid(
+                "billing.routes",
+                format!("Billed routes must be path prefixes: {:?}", route),
+            ));
+        }
+        let pricing = &self.billing.pricing;
+        if [
+            pricing.per_request,
+            pricing.per_request_mib,
+            pricing.per_response_mib,
+        ]
+        .iter()
+        .any(|price| !price.is_finite() || *price < 0.0)
+        {
+            return Err(invalid(
+                "billing.pricing",
+                "Prices must be finite and not negative",
+            ));
+        }
+        let caps = &self.billing.spending_caps;
+        if !caps.default_monthly_cap.is_finite() || caps.default_monthly_cap < 0.0 {
+            return Err(invalid(
+                "billing.spending_caps.default_monthly_cap",
+                "Must be finite and not negative",
+            ));
+        }
+        if caps.enabled && !self.billing.enabled {
+            return Err(invalid(
+                "billing.spending_caps.enabled",
+                "Spending caps need billing.enabled to meter requests",
+            ));
+        }
+        if caps
+            .alert_thresholds_percent
+            .iter()
+            .any(|t| !(1..=100).contains(t))
+        {
+            return Err(invalid(
+                "billing.spending_caps.alert_thresholds_percent",
+                "Thresholds must be between 1 and 100",
+            ));
+        }
+        if let Some(url) = &caps.webhook_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(invalid(
+                    "billing.spending_caps.webhook_url",
+                    format!("Spending webhook must be an http(s) URL: {}", url),
+                ));
+            }
+        }
+        for (tenant, overrides) in &self.tenants.overrides {
+            if overrides
+                .monthly_spending_cap
+                .is_some_and(|cap| !cap.is_finite() || cap < 0.0)
+            {
+                return Err(invalid(
+                    &format!("tenants.overrides.{}.monthly_spending_cap", tenant),
+                    "Must be finite and not negative",
+                ));
+            }
+        }
+
+        let dr = &self.disaster_recovery;
+        if dr.rto_seconds == 0 || dr.rpo_seconds == 0 {
+            return Err(invalid(
+                "disaster_recovery.rto_seconds",
+                "Recovery time and point objectives must be greater than 0",
+            ));
+        }
+        if dr.max_drill_seconds <= dr.rto_seconds {
+            return Err(invalid(
+                "disaster_recovery.max_drill_seconds",
+                "Drills must be allowed to run past the recovery time objective",
+            ));
+        }
+        if dr.health_poll_interval_ms == 0 || dr.health_timeout_seconds == 0 {
+            return Err(invalid(
+                "disaster_recovery.health_poll_interval_ms",
+                "Standby health poll interval and timeout must be greater than 0",
+            ));
+        }
+
+        let renewal = &self.sessions.renewal;
+        if renewal.access_token_ttl_seconds == 0
+            || renewal.refresh_token_ttl_seconds <= renewal.access_token_ttl_seconds
+        {
+            return Err(invalid(
+                "sessions.renewal.refresh_token_ttl_seconds",
+                "Refresh tokens must outlive access tokens, which need a non-zero TTL",
+            ));
+        }
+        if renewal.renewal_hint_seconds >= renewal.access_token_ttl_seconds {
+            return Err(invalid(
+                "sessions.renewal.renewal_hint_seconds",
+                "The renewal hint window must be shorter than the access token TTL",
+            ));
+        }
+        if renewal.key_overlap_seconds == 0
+            || renewal.key_overlap_seconds >= renewal.key_lifetime_seconds
+        {
+            return Err(invalid(
+                "sessions.renewal.key_overlap_seconds",
+                "Key overlap must be non-zero and shorter than the key lifetime",
+            ));
+        }
+
+        let escrow = &self.escrow;
+        if escrow.min_custodians < 2 || escrow.recovery_ttl_seconds == 0 {
+            return Err(invalid(
+                "escrow.min_custodians",
+                "Escrow needs at least 2 custodians and a recovery TTL greater than 0",
+            ));
+        }
+        if let Some(url) = &escrow.webhook_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(invalid(
+                    "escrow.webhook_url",
+                    format!("Escrow webhook must be an http(s) URL: {}", url),
+                ));
+            }
+        }
+        let approvals = &self.approvals;
+        if approvals.enabled {
+            if approvals.classes.is_empty() || approvals.approvers.is_empty() {
+                return Err(invalid(
+                    "approvals.approvers",
+                    "Approvals need at least one sensitivity class and one approver",
+                ));
+            }
+            if approvals.request_ttl_seconds == 0 || approvals.approval_ttl_seconds == 0 {
+                return Err(invalid(
+                    "approvals.approval_ttl_seconds",
+                    "Request and approval TTLs must be greater than 0",
+                ));
+            }
+            let mut names = std::collections::HashSet::new();
+            for approver in &approvals.approvers {
+                if approver.name.is_empty() || !names.insert(approver.name.as_str()) {
+                    return Err(invalid(
+                        "approvals.approvers",
+                        format!(
+                            "Approver names must be unique and non-empty: {:?}",
+                            approver.name
+                        ),
+                    ));
+                }
+                if approver.token_sha256.len() != 64
+                    || !approver.token_sha256.chars().all(|c| c.is_ascii_hexdigit())
+                {
+                    return Err(invalid(
+                        "approvals.approvers",
+                        format!(
+                            "Token digest of approver {} must be 64 hex digits",
+                            approver.name
+                        ),
+                    ));
+                }
+            }
+        }
+        if let Some(url) = &approvals.webhook_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(invalid(
+                    "approvals.webhook_url",
+                    format!("Approval webhook must be an http(s) URL: {}", url),
+                ));
+            }
+        }
+        let sandbox = &self.sandbox;
+        if sandbox.enabled {
+            if sandbox.max_keys == 0 || sandbox.sweep_interval_seconds == 0 {
+                return Err(invalid(
+                    "sandbox.max_keys",
+                    "Key cap and sweep interval must be greater than 0",
+                ));
+            }
+            if sandbox.default_ttl_seconds == 0
+                || sandbox.default_ttl_seconds > sandbox.max_ttl_seconds
+            {
+                return Err(invalid(
+                    "sandbox.default_ttl_seconds",
+                    "Default key TTL must be greater than 0 and at most max_ttl_seconds",
+                ));
+            }
+            if sandbox.price_per_request < 0.0 || sandbox.price_per_1k_tokens < 0.0 {
+                return Err(invalid(
+                    "sandbox.price_per_request",
+                    "Sandbox prices must not be negative",
+                ));
+            }
+        }
+        let honeytokens = &self.honeytokens;
+        if honeytokens.enabled {
+            if honeytokens.keys_per_generation + honeytokens.canaries_per_generation == 0 {
+                return Err(invalid(
+                    "honeytokens.keys_per_generation",
+                    "At least one honeytoken key or canary ciphertext is required",
+                ));
+            }
+            if honeytokens.rotation_interval_seconds == 0 {
+                return Err(invalid(
+                    "honeytokens.rotation_interval_seconds",
+                    "Rotation interval must be greater than 0",
+                ));
+            }
+        }
+        for key in &self.access.tenant_keys {
+            if key.tenant.is_empty() {
+                return Err(invalid(
+                    "access.tenant_keys",
+                    "Tenant key must name a tenant",
+                ));
+            }
+            if key.sha256.len() != 64 || !key.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid(
+                    &format!("access.tenant_keys.{}", key.tenant),
+                    "sha256 must be 64 hex digits",
+                ));
+            }
+        }
+        for token in &self.access.admin_tokens {
+            if token.name.is_empty() {
+                return Err(invalid(
+                    "access.admin_tokens",
+                    "Admin token must name its operator",
+                ));
+            }
+            if token.sha256.len() != 64 || !token.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid(
+                    &format!("access.admin_tokens.{}", token.name),
+                    "sha256 must be 64 hex digits",
+                ));
+            }
+        }
+        let mut versions = std::collections::HashSet::new();
+        for lifecycle in &self.api_versions.lifecycle {
+            if !["v1", "v2"].contains(&lifecycle.version.as_str())
+                || !versions.insert(lifecycle.version.as_str())
+            {
+                return Err(invalid(
+                    "api_versions.lifecycle",
+                    format!(
+                        "Lifecycle versions must be v1 or v2 and listed once: {:?}",
+                        lifecycle.version
+                    ),
+                ));
+            }
+            let parse = |date: &Option<String>| {
+                date.as_deref()
+                    .map(chrono::DateTime::parse_from_rfc3339)
+                    .transpose()
+            };
+            match (parse(&lifecycle.deprecated_at), parse(&lifecycle.sunset_at)) {
+                (Ok(Some(deprecated)), Ok(Some(sunset))) if sunset < deprecated => {
+                    return Err(invalid(
+                        "api_versions.lifecycle",
+                        format!("{} is sunset before it is deprecated", lifecycle.version),
+                    ));
+                }
+                (Ok(_), Ok(_)) => {}
+                (Err(e), _) | (_, Err(e)) => {
+                    return Err(invalid(
+                        "api_versions.lifecycle",
+                        format!("{} dates must be RFC 3339: {}", lifecycle.version, e),
+                    ));
+                }
+            }
+            if let Some(link) = &lifecycle.link {
+                if !link.starts_with("http://") && !link.starts_with("https://") {
+                    return Err(invalid(
+                        "api_versions.lifecycle",
+                        format!("Migration guide must be an http(s) URL: {}", link),
+                    ));
+                }
+            }
+        }
+
+        if !self
+            .strict_security
+            .min_poly_modulus_degree
+            .is_power_of_two()
+        {
+            return Err(invalid(
+                "strict_security.min_poly_modulus_degree",
+                "Minimum poly modulus degree must be a power of 2",
+            ));
+        }
+        for (tenant, overrides) in &self.tenants.overrides {
+            if let Some(unknown) = overrides
+                .allowed_workload_tags
+                .iter()
+                .flatten()
+                .find(|tag| !self.tenants.workload_tags.tags.contains_key(*tag))
+            {
+                return Err(invalid(
+                    &format!("tenants.overrides.{}.allowed_workload_tags", tenant),
+                    format!("Workload tag {} is not configured", unknown),
+                ));
+            }
+            if let Some(custodians) = &overrides.escrow_custodians {
+                if let Err(e) = crate::escrow::EscrowService::validate_custodians(
+                    custodians,
+                    escrow.min_custodians,
+                ) {
+                    return Err(invalid(
+                        &format!("tenants.overrides.{}.escrow_custodians", tenant),
+                        e.to_string(),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get configuration summary for logging
+    pub fn summary(&self) -> String {
+        format!(
+            "FHE Proxy Config - Server: {}:{}, GPU: {}, Security: {}",
+            self.server.host,
+            self.server.port,
+            if self.gpu.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            self.encryption.security_level
+        )
+    }
+}
@@ -0,0 +1,307 @@
+//! Configuration drift detection across replicas
+//!
+//! Each replica periodically publishes a fingerprint of its effective
+//! configuration to the shared session store: a digest per dotted config key
+//! plus one per tenant override document, and a digest over all of them.
+//! Values never leave the replica, only their SHA-256 digests, so keys holding
+//! secrets can be compared without being disclosed.
+//!
+//! Replicas are compared against the fingerprint most of them share. Keys that
+//! are meant to differ per replica (node IDs, instance IDs) are listed in
+//! `ignored_keys` and left out of the fingerprint entirely. Drift that lasts
+//! `alert_after_seconds` raises one alert per episode; the next alert needs the
+//! replicas to converge first.
+
+use crate::config::{Config, ConfigDriftConfig};
+use crate::error::Result;
+use crate::persistence::ConfigFingerprintRecord;
+use ring::digest;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// A replica whose fingerprint differs from the majority's
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DivergentReplica {
+    pub replica: String,
+    pub fingerprint: String,
+    pub generation: u64,
+    /// Keys whose digests differ, or that only one side has
+    pub differing_keys: Vec<String>,
+}
+
+/// Where the live replicas stand relative to each other
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigDriftReport {
+    pub replica: String,
+    /// Fingerprint shared by most live replicas; `None` without any
+    pub majority_fingerprint: Option<String>,
+    /// Replicas that published within `replica_ttl_seconds`, by name
+    pub replicas: BTreeMap<String, String>,
+    pub divergent: Vec<DivergentReplica>,
+    /// Unix time drift was first seen in the current episode
+    pub drifting_since: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct DriftEpisode {
+    since: Option<i64>,
+    alerted: bool,
+}
+
+/// Publishes this replica's fingerprint and compares it with the others'
+#[derive(Debug)]
+pub struct ConfigDriftMonitor {
+    config: ConfigDriftConfig,
+    replica: String,
+    episode: Mutex<DriftEpisode>,
+}
+
+impl ConfigDriftMonitor {
+    pub fn new(config: ConfigDriftConfig) -> Self {
+        let replica = config
+            .replica_id
+            .clone()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        Self {
+            config,
+            replica,
+            episode: Mutex::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &ConfigDriftConfig {
+        &self.config
+    }
+
+    /// This replica's fingerprint of `config` and the override documents in
+    /// `tenants.override_dir`, as of tenant configuration `generation`
+    pub fn fingerprint(
+        &self,
+        config: &Config,
+        generation: u64,
+        now: i64,
+    ) -> Result<ConfigFingerprintRecord> {
+        let mut keys = BTreeMap::new();
+        flatten("", &serde_json::to_value(config)?, &mut keys);
+        if let Some(dir) = &config.tenants.override_dir {
+            for (tenant, document) in override_documents(Path::new(dir))? {
+                keys.insert(
+                    format!("tenants.override_documents.{}", tenant),
+                    hex_digest(&document),
+                );
+            }
+        }
+        keys.retain(|key, _| !self.is_ignored(key));
+
+        let mut context = digest::Context::new(&digest::SHA256);
+        for (key, value) in &keys {
+            context.update(key.as_bytes());
+            context.update(&[0]);
+            context.update(value.as_bytes());
+            context.update(&[0]);
+        }
+        Ok(ConfigFingerprintRecord {
+            replica: self.replica.clone(),
+            generation,
+            fingerprint: hex(context.finish().as_ref()),
+            keys,
+            published_at: now,
+        })
+    }
+
+    fn is_ignored(&self, key: &str) -> bool {
+        self.config.ignored_keys.iter().any(|ignored| {
+            key == ignored
+                || key
+                    .strip_prefix(ignored.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+
+    /// Compare the replicas that published within `replica_ttl_seconds`
+    pub fn report(&self, records: &[ConfigFingerprintRecord], now: i64) -> ConfigDriftReport {
+        let live: Vec<&ConfigFingerprintRecord> = records
+            .iter()
+            .filter(|record| now - record.published_at <= self.config.replica_ttl_seconds as i64)
+            .collect();
+
+        // Most common fingerprint; ties go to the smallest for a stable answer
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for record in &live {
+            *counts.entry(record.fingerprint.as_str()).or_default() += 1;
+        }
+        let majority = counts
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(fingerprint, _)| fingerprint.to_string());
+
+        let divergent: Vec<DivergentReplica> = match &majority {
+            Some(majority) => {
+                let reference = live
+                    .iter()
+                    .find(|record| &record.fingerprint == majority)
+                    .expect("majority fingerprint comes from a live record");
+                live.iter()
+                    .filter(|record| &record.fingerprint != majority)
+                    .map(|record| DivergentReplica {
+                        replica: record.replica.clone(),
+                        fingerprint: record.fingerprint.clone(),
+                        generation: record.generation,
+                        differing_keys: differing_keys(&reference.keys, &record.keys),
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        ConfigDriftReport {
+            replica: self.replica.clone(),
+            majority_fingerprint: majority,
+            replicas: live
+                .iter()
+                .map(|record| (record.replica.clone(), record.fingerprint.clone()))
+                .collect(),
+            divergent,
+            drifting_since: self.episode.lock().unwrap().since,
+        }
+    }
+
+    /// Track how long replicas have disagreed as of `report`; returns whether
+    /// the current episode has lasted long enough to alert on, once
+    pub fn observe(&self, report: &ConfigDriftReport, now: i64) -> bool {
+        let mut episode = self.episode.lock().unwrap();
+        if report.divergent.is_empty() {
+            *episode = DriftEpisode::default();
+            return false;
+        }
+        let since = *episode.since.get_or_insert(now);
+        if episode.alerted || now - since < self.config.alert_after_seconds as i64 {
+            return false;
+        }
+        episode.alerted = true;
+        true
+    }
+}
+
+/// Digest every leaf of `value` under its dotted path; arrays are leaves
+fn flatten(prefix: &str, value: &serde_json::Value, keys: &mut BTreeMap<String, String>) {
+    match value {
+        serde_json::Value::Object(fields) if !fields.is_empty() => {
+            for (name, field) in fields {
+                let key = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", prefix, name)
+                };
+                flatten(&key, field, keys);
+            }
+        }
+        leaf => {
+            keys.insert(prefix.to_string(), hex_digest(leaf.to_string().as_bytes()));
+        }
+    }
+}
+
+/// `<tenant>.toml` documents in `dir`; a missing directory has none
+fn override_documents(dir: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut documents = BTreeMap::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(documents),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            if let Some(tenant) = path.file_stem().and_then(|stem| stem.to_str()) {
+                documents.insert(tenant.to_string(), std::fs::read(&path)?);
+            }
+        }
+    }
+    Ok(documents)
+}
+
+fn differing_keys(
+    reference: &BTreeMap<String, String>,
+    other: &BTreeMap<String, String>,
+) -> Vec<String> {
+    let names: BTreeSet<&String> = reference.keys().chain(other.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| reference.get(*name) != other.get(*name))
+        .cloned()
+        .collect()
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_divergent_replicas_and_alerts_once_per_episode() {
+        let monitor = ConfigDriftMonitor::new(ConfigDriftConfig {
+            enabled: true,
+            replica_id: Some("a".to_string()),
+            replica_ttl_seconds: 600,
+            alert_after_seconds: 60,
+            ..ConfigDriftConfig::default()
+        });
+        let config = Config::default();
+        let mut drifted = config.clone();
+        drifted.llm.openai_api_key = Some("sk-other".to_string());
+        // Per-replica identity is ignored by default
+        drifted.federation.node_id = "b".to_string();
+
+        let a = monitor.fingerprint(&config, 3, 1000).unwrap();
+        assert!(!a.keys.contains_key("federation.node_id"));
+        assert!(!a.keys.values().any(|digest| digest.contains("sk-other")));
+        let mut b = monitor.fingerprint(&config, 5, 1000).unwrap();
+        b.replica = "b".to_string();
+        assert_eq!(a.fingerprint, b.fingerprint);
+        let mut c = monitor.fingerprint(&drifted, 1, 1000).unwrap();
+        c.replica = "c".to_string();
+        let mut gone = c.clone();
+        gone.replica = "gone".to_string();
+        gone.published_at = 0;
+        let records = [a.clone(), b, c, gone];
+
+        let report = monitor.report(&records, 1000);
+        assert!(!monitor.observe(&report, 1000));
+        assert_eq!(report.majority_fingerprint, Some(a.fingerprint.clone()));
+        assert_eq!(report.replicas.len(), 3);
+        assert_eq!(report.divergent.len(), 1);
+        assert_eq!(report.divergent[0].replica, "c");
+        assert_eq!(
+            report.divergent[0].differing_keys,
+            vec!["llm.openai_api_key".to_string()]
+        );
+        assert_eq!(monitor.report(&records, 1010).drifting_since, Some(1000));
+
+        // Persisting drift alerts once, and again only after converging
+        let drifting = |now| monitor.observe(&monitor.report(&records, now), now);
+        assert!(drifting(1060));
+        assert!(!drifting(1120));
+        let converged = monitor.report(&records[..2], 1130);
+        assert!(converged.divergent.is_empty());
+        assert!(!monitor.observe(&converged, 1130));
+        assert_eq!(monitor.report(&records[..2], 1130).drifting_since, None);
+        assert!(!drifting(1140));
+        assert!(drifting(1200));
+    }
+}
@@ -0,0 +1,339 @@
+//! Connection caps and slowloris protection
+//!
+//! Open connections are capped per peer address when they are accepted, and
+//! in-flight requests per API key once a request's headers are read: a key is
+//! only known per request, so its cap spans all of the client's connections.
+//! Slow clients are cut off at three points: headers that take longer than
+//! `header_read_timeout_seconds` (enforced by the HTTP server), request bodies
+//! that pause longer than `body_read_timeout_seconds`, and large bodies, such
+//! as ciphertext uploads, that arrive slower than `min_upload_bytes_per_second`
+//! once the grace period is over. Every cut-off and rejection is counted.
+
+use crate::config::ConnectionGuardConfig;
+use axum::body::{Body, BodyDataStream, Bytes};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::time::Sleep;
+use tokio_stream::Stream;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Default)]
+struct Counters {
+    rejected_connections: AtomicU64,
+    rejected_requests: AtomicU64,
+    header_timeouts: AtomicU64,
+    body_timeouts: AtomicU64,
+    slow_uploads: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionGuardStats {
+    pub enabled: bool,
+    pub open_connections: usize,
+    pub peers: usize,
+    /// Connections refused for exceeding the per-address cap
+    pub rejected_connections: u64,
+    /// Requests refused for exceeding the per-API-key cap
+    pub rejected_requests: u64,
+    pub header_timeouts: u64,
+    pub body_timeouts: u64,
+    /// Large uploads cut off for falling below the minimum throughput
+    pub slow_uploads: u64,
+}
+
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    config: ConnectionGuardConfig,
+    connections: Mutex<HashMap<IpAddr, usize>>,
+    requests: Mutex<HashMap<String, usize>>,
+    counters: Counters,
+}
+
+#[derive(Debug)]
+enum Slot {
+    Peer(IpAddr),
+    ApiKey(String),
+}
+
+/// A connection or request admitted under a cap; frees its slot when dropped
+#[derive(Debug)]
+pub struct Permit {
+    guard: Arc<ConnectionGuard>,
+    slot: Option<Slot>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        match self.slot.take() {
+            Some(Slot::Peer(peer)) => release(&self.guard.connections, &peer),
+            Some(Slot::ApiKey(key)) => release(&self.guard.requests, &key),
+            None => {}
+        }
+    }
+}
+
+fn acquire<K: Hash + Eq>(counts: &Mutex<HashMap<K, usize>>, key: K, cap: usize) -> bool {
+    let mut counts = counts.lock().unwrap();
+    let count = counts.entry(key).or_default();
+    if cap > 0 && *count >= cap {
+        return false;
+    }
+    *count += 1;
+    true
+}
+
+fn release<K: Hash + Eq>(counts: &Mutex<HashMap<K, usize>>, key: &K) {
+    let mut counts = counts.lock().unwrap();
+    if let Some(count) = counts.get_mut(key) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(key);
+        }
+    }
+}
+
+impl ConnectionGuard {
+    pub fn new(config: ConnectionGuardConfig) -> Self {
+        Self {
+            config,
+            connections: Mutex::new(HashMap::new()),
+            requests: Mutex::new(HashMap::new()),
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// How long the HTTP server waits for a request's headers
+    pub fn header_read_timeout(&self) -> Option<Duration> {
+        self.config
+            .enabled
+            .then(|| Duration::from_secs(self.config.header_read_timeout_seconds))
+    }
+
+    /// Admit a connection from `peer`, or None when it is at its cap
+    pub fn admit_connection(self: &Arc<Self>, peer: IpAddr) -> Option<Permit> {
+        self.admit(Slot::Peer(peer))
+    }
+
+    /// Admit a request made with `api_key`, or None when it is at its cap
+    pub fn admit_request(self: &Arc<Self>, api_key: &str) -> Option<Permit> {
+        self.admit(Slot::ApiKey(api_key.to_string()))
+    }
+
+    fn admit(self: &Arc<Self>, slot: Slot) -> Option<Permit> {
+        if !self.config.enabled {
+            return Some(Permit {
+                guard: self.clone(),
+                slot: None,
+            });
+        }
+        let (admitted, rejections) = match &slot {
+            Slot::Peer(peer) => (
+                acquire(&self.connections, *peer, self.config.max_connections_per_ip),
+                &self.counters.rejected_connections,
+            ),
+            Slot::ApiKey(key) => (
+                acquire(
+                    &self.requests,
+                    key.clone(),
+                    self.config.max_requests_per_api_key,
+                ),
+                &self.counters.rejected_requests,
+            ),
+        };
+        if !admitted {
+            rejections.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(Permit {
+            guard: self.clone(),
+            slot: Some(slot),
+        })
+    }
+
+    /// Count a connection the HTTP server closed because of `error`
+    pub fn record_connection_error(&self, error: &(dyn std::error::Error + 'static)) {
+        if error
+            .downcast_ref::<hyper::Error>()
+            .is_some_and(hyper::Error::is_timeout)
+        {
+            self.counters
+                .header_timeouts
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Enforce the read timeout and minimum throughput on a request body of
+    /// `content_length` bytes; `tripped` is set if it is cut off
+    pub fn guard_body(
+        self: &Arc<Self>,
+        body: Body,
+        content_length: Option<u64>,
+        tripped: Arc<AtomicBool>,
+    ) -> Body {
+        if !self.config.enabled {
+            return body;
+        }
+        Body::from_stream(GuardedBody {
+            inner: body.into_data_stream(),
+            guard: self.clone(),
+            idle: Box::pin(tokio::time::sleep(self.body_read_timeout())),
+            started: Instant::now(),
+            received: 0,
+            enforce_throughput: content_length
+                .is_some_and(|len| len >= self.config.min_throughput_body_bytes),
+            tripped,
+        })
+    }
+
+    fn body_read_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.body_read_timeout_seconds)
+    }
+
+    pub fn stats(&self) -> ConnectionGuardStats {
+        let connections = self.connections.lock().unwrap();
+        ConnectionGuardStats {
+            enabled: self.config.enabled,
+            open_connections: connections.values().sum(),
+            peers: connections.len(),
+            rejected_connections: self.counters.rejected_connections.load(Ordering::Relaxed),
+            rejected_requests: self.counters.rejected_requests.load(Ordering::Relaxed),
+            header_timeouts: self.counters.header_timeouts.load(Ordering::Relaxed),
+            body_timeouts: self.counters.body_timeouts.load(Ordering::Relaxed),
+            slow_uploads: self.counters.slow_uploads.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A request body that fails once it stalls or falls below the minimum
+/// throughput
+struct GuardedBody {
+    inner: BodyDataStream,
+    guard: Arc<ConnectionGuard>,
+    /// Fires when the body has paused for too long
+    idle: Pin<Box<Sleep>>,
+    started: Instant,
+    received: u64,
+    enforce_throughput: bool,
+    tripped: Arc<AtomicBool>,
+}
+
+impl GuardedBody {
+    fn trip(&self, counter: &AtomicU64, message: String) -> Poll<Option<Result<Bytes, BoxError>>> {
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.tripped.store(true, Ordering::Relaxed);
+        log::warn!("{}", message);
+        Poll::Ready(Some(Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            message,
+        )
+        .into())))
+    }
+}
+
+impl Stream for GuardedBody {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.tripped.load(Ordering::Relaxed) {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                self.received += chunk.len() as u64;
+                let config = &self.guard.config;
+                let elapsed = self.started.elapsed();
+                if self.enforce_throughput
+                    && elapsed.as_secs() >= config.throughput_grace_seconds
+                    && (self.received as f64 / elapsed.as_secs_f64())
+                        < config.min_upload_bytes_per_second as f64
+                {
+                    let message = format!(
+                        "Upload cut off at {} bytes after {:.1}s, below {} bytes/s",
+                        self.received,
+                        elapsed.as_secs_f64(),
+                        config.min_upload_bytes_per_second
+                    );
+                    return self.trip(&self.guard.counters.slow_uploads, message);
+                }
+                let deadline = tokio::time::Instant::now() + self.guard.body_read_timeout();
+                self.idle.as_mut().reset(deadline);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => match self.idle.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    let message = format!(
+                        "Request body stalled for {}s after {} bytes",
+                        self.guard.config.body_read_timeout_seconds, self.received
+                    );
+                    self.trip(&self.guard.counters.body_timeouts, message)
+                }
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_caps_and_stalled_bodies() {
+        let guard = Arc::new(ConnectionGuard::new(ConnectionGuardConfig {
+            enabled: true,
+            max_connections_per_ip: 2,
+            max_requests_per_api_key: 1,
+            body_read_timeout_seconds: 1,
+            ..ConnectionGuardConfig::default()
+        }));
+        let peer: IpAddr = "203.0.113.7".parse().unwrap();
+        let first = guard.admit_connection(peer).unwrap();
+        let _second = guard.admit_connection(peer).unwrap();
+        assert!(guard.admit_connection(peer).is_none());
+        assert!(guard
+            .admit_connection("203.0.113.8".parse().unwrap())
+            .is_some());
+        drop(first);
+        let _third = guard.admit_connection(peer).unwrap();
+
+        let _request = guard.admit_request("key-a").unwrap();
+        assert!(guard.admit_request("key-a").is_none());
+        assert!(guard.admit_request("key-b").is_some());
+
+        // A body that sends one chunk and then stalls is cut off
+        let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, BoxError>>(1);
+        tx.send(Ok(Bytes::from_static(b"{\"ciphertext\":")))
+            .await
+            .unwrap();
+        let tripped = Arc::new(AtomicBool::new(false));
+        let body = guard.guard_body(
+            Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)),
+            None,
+            tripped.clone(),
+        );
+        assert!(axum::body::to_bytes(body, usize::MAX).await.is_err());
+        assert!(tripped.load(Ordering::Relaxed));
+        drop(tx);
+
+        let stats = guard.stats();
+        assert_eq!(stats.open_connections, 2);
+        assert_eq!(stats.rejected_connections, 1);
+        assert_eq!(stats.rejected_requests, 1);
+        assert_eq!(stats.body_timeouts, 1);
+    }
+}
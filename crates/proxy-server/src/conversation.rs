@@ -0,0 +1,866 @@
+//! Latency-tiered storage of encrypted conversation context
+//!
+//! The latest turns of each conversation stay in memory. Older turns, and all
+//! turns of idle conversations, are written to the persistence backend, and
+//! turns past `archive_after_seconds` move on to an archive object store.
+//! Reads fetch from whichever tier holds a turn, newest tier first, so callers
+//! only see where a turn lives through its `tier` label. Every tier's read and
+//! write latency is tracked for `/v1/admin/conversations`.
+//!
+//! For tenants with context compression on, a turn's ciphertext is zstd
+//! compressed as it leaves memory, and the compressed form is what the
+//! persistence backend and the archive hold. Reads decompress only the turns
+//! they return. Bytes saved and the time spent on either side are tracked, so
+//! the storage saving can be weighed against the added latency.
+
+use crate::config::{ContextArchiveConfig, ContextCompressionConfig, ConversationConfig};
+use crate::error::{Error, Result};
+use crate::persistence::{ContextTurnRecord, PersistenceBackend};
+use base64::prelude::*;
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Blob storage for archived turns
+pub trait ObjectStore: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<()>;
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn delete(&self, key: &str) -> Result<()>;
+    /// Keys starting with `prefix`, sorted
+    fn list(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// Process-local object store, for tests and single-node trials
+#[derive(Debug, Default)]
+pub struct MemoryObjectStore {
+    objects: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        self.objects
+            .write()
+            .unwrap()
+            .insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.objects.read().unwrap().get(key).cloned())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.objects.write().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(self
+            .objects
+            .read()
+            .unwrap()
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, _)| key.clone())
+            .collect())
+    }
+}
+
+/// Objects as files under a root directory, one directory level per `/` in the key
+#[derive(Debug)]
+pub struct FilesystemObjectStore {
+    root: PathBuf,
+}
+
+impl FilesystemObjectStore {
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn collect_keys(&self, dir: &Path, keys: &mut Vec<String>) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.collect_keys(&path, keys)?;
+            } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                keys.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore for FilesystemObjectStore {
+    fn name(&self) -> &'static str {
+        "filesystem"
+    }
+
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write then rename, so readers never see a partial object
+        let staging = path.with_extension("partial");
+        std::fs::write(&staging, bytes)?;
+        std::fs::rename(&staging, &path)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.root.join(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.root.join(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        // Only walk the deepest directory the prefix names
+        let dir = match prefix.rfind('/') {
+            Some(end) => self.root.join(&prefix[..end]),
+            None => self.root.clone(),
+        };
+        let mut keys = Vec::new();
+        if dir.is_dir() {
+            self.collect_keys(&dir, &mut keys)?;
+        }
+        keys.retain(|key| key.starts_with(prefix) && !key.ends_with(".partial"));
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Open the archive selected in the configuration
+pub fn open_object_store(config: &ContextArchiveConfig) -> Result<Arc<dyn ObjectStore>> {
+    match config.backend.as_str() {
+        "memory" => Ok(Arc::new(MemoryObjectStore::default())),
+        "filesystem" => Ok(Arc::new(FilesystemObjectStore::new(&config.path)?)),
+        other => Err(Error::Config(format!(
+            "Unknown context archive backend: {}",
+            other
+        ))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextTier {
+    Hot,
+    Warm,
+    Archive,
+}
+
+/// A turn and the tier it was read from
+#[derive(Debug, Clone, Serialize)]
+pub struct TieredTurn {
+    #[serde(flatten)]
+    pub turn: ContextTurnRecord,
+    pub tier: ContextTier,
+}
+
+#[derive(Debug, Default)]
+struct TierCounters {
+    reads: u64,
+    /// Reads that found at least one turn in the tier
+    hits: u64,
+    writes: u64,
+    read_micros: u64,
+    max_read_micros: u64,
+    write_micros: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TierStats {
+    pub reads: u64,
+    pub hits: u64,
+    pub writes: u64,
+    pub avg_read_ms: f64,
+    pub max_read_ms: f64,
+    pub avg_write_ms: f64,
+}
+
+impl TierCounters {
+    fn record_read(&mut self, elapsed: Duration, hit: bool) {
+        let micros = elapsed.as_micros() as u64;
+        self.reads += 1;
+        self.hits += hit as u64;
+        self.read_micros += micros;
+        self.max_read_micros = self.max_read_micros.max(micros);
+    }
+
+    fn record_write(&mut self, elapsed: Duration) {
+        self.writes += 1;
+        self.write_micros += elapsed.as_micros() as u64;
+    }
+
+    fn stats(&self) -> TierStats {
+        TierStats {
+            reads: self.reads,
+            hits: self.hits,
+            writes: self.writes,
+            avg_read_ms: self.read_micros as f64 / self.reads.max(1) as f64 / 1000.0,
+            max_read_ms: self.max_read_micros as f64 / 1000.0,
+            avg_write_ms: self.write_micros as f64 / self.writes.max(1) as f64 / 1000.0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct CompressionCounters {
+    compressed: u64,
+    /// Turns of compressing tenants stored as they were: too small, or no
+    /// smaller once compressed
+    stored_raw: u64,
+    original_bytes: u64,
+    stored_bytes: u64,
+    compress_micros: u64,
+    decompressions: u64,
+    decompress_micros: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompressionStats {
+    pub enabled: bool,
+    pub level: i32,
+    pub compressed_turns: u64,
+    pub uncompressed_turns: u64,
+    /// Ciphertext bytes of every turn of a compressing tenant written to
+    /// storage, before and after compression
+    pub original_bytes: u64,
+    pub stored_bytes: u64,
+    pub saved_bytes: u64,
+    pub ratio: f64,
+    pub avg_compress_ms: f64,
+    pub decompressions: u64,
+    pub avg_decompress_ms: f64,
+}
+
+impl CompressionCounters {
+    fn stats(&self, config: &ContextCompressionConfig) -> CompressionStats {
+        let attempts = self.compressed + self.stored_raw;
+        CompressionStats {
+            enabled: config.enabled,
+            level: config.level,
+            compressed_turns: self.compressed,
+            uncompressed_turns: self.stored_raw,
+            original_bytes: self.original_bytes,
+            stored_bytes: self.stored_bytes,
+            saved_bytes: self.original_bytes.saturating_sub(self.stored_bytes),
+            ratio: if self.original_bytes == 0 {
+                1.0
+            } else {
+                self.stored_bytes as f64 / self.original_bytes as f64
+            },
+            avg_compress_ms: self.compress_micros as f64 / attempts.max(1) as f64 / 1000.0,
+            decompressions: self.decompressions,
+            avg_decompress_ms: self.decompress_micros as f64
+                / self.decompressions.max(1) as f64
+                / 1000.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationStats {
+    pub enabled: bool,
+    pub hot_conversations: usize,
+    pub hot_turns: usize,
+    pub archive_backend: &'static str,
+    pub hot: TierStats,
+    pub warm: TierStats,
+    pub archive: TierStats,
+    pub compression: CompressionStats,
+}
+
+/// Turns of one tenant deleted from each tier
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ContextPurgeReport {
+    pub hot: usize,
+    pub warm: usize,
+    pub archive: usize,
+}
+
+/// Turns moved between or dropped from tiers by one tiering pass
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TieringReport {
+    pub demoted: usize,
+    pub archived: usize,
+    pub expired: usize,
+}
+
+#[derive(Debug)]
+struct HotConversation {
+    tenant: String,
+    turns: VecDeque<ContextTurnRecord>,
+    next_turn: u64,
+    last_used: Instant,
+    /// Whether the tenant compresses turns leaving memory, as of the last append
+    compress: bool,
+}
+
+#[derive(Debug)]
+pub struct ConversationStore {
+    config: ConversationConfig,
+    hot: RwLock<HashMap<Uuid, HotConversation>>,
+    warm: Arc<dyn PersistenceBackend>,
+    archive: Arc<dyn ObjectStore>,
+    counters: Mutex<[TierCounters; 3]>,
+    compression: Mutex<CompressionCounters>,
+}
+
+impl ConversationStore {
+    pub fn new(
+        config: ConversationConfig,
+        warm: Arc<dyn PersistenceBackend>,
+        archive: Arc<dyn ObjectStore>,
+    ) -> Self {
+        Self {
+            config,
+            hot: RwLock::new(HashMap::new()),
+            warm,
+            archive,
+            counters: Mutex::new(Default::default()),
+            compression: Mutex::new(CompressionCounters::default()),
+        }
+    }
+
+    /// Backend of the archive tier
+    pub fn archive_backend(&self) -> &'static str {
+        self.archive.name()
+    }
+
+    /// Check the archive can be listed, without reading any turns
+    pub fn probe_archive(&self) -> Result<()> {
+        self.archive.list("__probe__/").map(|_| ())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Add a turn to the end of a conversation, moving turns past `hot_turns`
+    /// to the persistence backend, compressed when `compress` is set
+    pub fn append(
+        &self,
+        session_id: Uuid,
+        tenant: &str,
+        role: &str,
+        ciphertext: String,
+        compress: bool,
+    ) -> Result<ContextTurnRecord> {
+        if ciphertext.len() > self.config.max_turn_bytes {
+            return Err(Error::Validation(format!(
+                "Conversation turns are limited to {} bytes",
+                self.config.max_turn_bytes
+            )));
+        }
+
+        let started = Instant::now();
+        let mut hot = self.hot.write().unwrap();
+        let conversation = match hot.entry(session_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(HotConversation {
+                tenant: tenant.to_string(),
+                turns: VecDeque::new(),
+                next_turn: self.next_stored_turn(session_id, tenant)?,
+                last_used: Instant::now(),
+                compress,
+            }),
+        };
+        if conversation.tenant != tenant {
+            return Err(Error::Security(format!(
+                "Conversation {} belongs to another tenant",
+                session_id
+            )));
+        }
+
+        let record = ContextTurnRecord {
+            session_id,
+            turn: conversation.next_turn,
+            tenant: tenant.to_string(),
+            role: role.to_string(),
+            ciphertext,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        conversation.next_turn += 1;
+        conversation.last_used = Instant::now();
+        conversation.compress = compress;
+        conversation.turns.push_back(record.clone());
+        while conversation.turns.len() > self.config.hot_turns {
+            let oldest = conversation.turns.pop_front().unwrap();
+            if let Err(e) = self.write_warm(&oldest, compress) {
+                conversation.turns.push_front(oldest);
+                return Err(e);
+            }
+        }
+        drop(hot);
+        self.counters.lock().unwrap()[ContextTier::Hot as usize].record_write(started.elapsed());
+        Ok(record)
+    }
+
+    /// Turns `from..to` of a conversation (to the end when `to` is `None`),
+    /// fetched from whichever tiers hold them
+    pub fn turns(
+        &self,
+        session_id: Uuid,
+        tenant: &str,
+        from: u64,
+        to: Option<u64>,
+    ) -> Result<Vec<TieredTurn>> {
+        let in_range =
+            |turn: &ContextTurnRecord| turn.turn >= from && to.is_none_or(|to| turn.turn < to);
+        let mut found: BTreeMap<u64, TieredTurn> = BTreeMap::new();
+        let check_owner = |turn: &ContextTurnRecord| {
+            if turn.tenant == tenant {
+                Ok(())
+            } else {
+                Err(Error::Security(format!(
+                    "Conversation {} belongs to another tenant",
+                    session_id
+                )))
+            }
+        };
+
+        // Oldest turn the tiers read so far cover; anything before it is further down
+        let mut covered_from = {
+            let started = Instant::now();
+            let mut hot = self.hot.write().unwrap();
+            let covered_from = match hot.get_mut(&session_id) {
+                Some(conversation) => {
+                    conversation.last_used = Instant::now();
+                    for turn in conversation.turns.iter() {
+                        check_owner(turn)?;
+                        if in_range(turn) {
+                            found.insert(turn.turn, tiered(turn.clone(), ContextTier::Hot));
+                        }
+                    }
+                    conversation
+                        .turns
+                        .front()
+                        .map_or(conversation.next_turn, |turn| turn.turn)
+                }
+                None => u64::MAX,
+            };
+            drop(hot);
+            self.record_read(ContextTier::Hot, started, !found.is_empty());
+            covered_from
+        };
+
+        if from < covered_from {
+            let started = Instant::now();
+            let warm = self.warm.get_context_turns(session_id)?;
+            self.record_read(ContextTier::Warm, started, !warm.is_empty());
+            for turn in warm {
+                check_owner(&turn)?;
+                covered_from = covered_from.min(turn.turn);
+                if in_range(&turn) && !found.contains_key(&turn.turn) {
+                    found.insert(turn.turn, tiered(self.inflate(turn)?, ContextTier::Warm));
+                }
+            }
+        }
+
+        if from < covered_from {
+            let started = Instant::now();
+            let mut hit = false;
+            for key in self.archive.list(&archive_prefix(session_id))? {
+                let Some(turn) = archive_turn(&key) else {
+                    continue;
+                };
+                if turn >= covered_from || turn < from || to.is_some_and(|to| turn >= to) {
+                    continue;
+                }
+                if let Some(record) = self.read_archived(&key)? {
+                    check_owner(&record)?;
+                    hit = true;
+                    if !found.contains_key(&record.turn) {
+                        let record = self.inflate(record)?;
+                        found.insert(record.turn, tiered(record, ContextTier::Archive));
+                    }
+                }
+            }
+            self.record_read(ContextTier::Archive, started, hit);
+        }
+
+        Ok(found.into_values().collect())
+    }
+
+    /// Move idle conversations out of memory, archive old turns and delete
+    /// turns past their tenant's retention, returned by `retention_for`
+    pub fn run_tiering(&self, retention_for: impl Fn(&str) -> u64) -> Result<TieringReport> {
+        let mut report = TieringReport::default();
+        let now = chrono::Utc::now().timestamp();
+        let expired = |turn: &ContextTurnRecord| {
+            let retention = retention_for(&turn.tenant);
+            retention > 0 && turn.created_at + retention as i64 <= now
+        };
+
+        let idle_after = Duration::from_secs(self.config.hot_idle_seconds);
+        let idle: Vec<HotConversation> = {
+            let mut hot = self.hot.write().unwrap();
+            let ids: Vec<Uuid> = hot
+                .iter()
+                .filter(|(_, c)| c.last_used.elapsed() >= idle_after)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.iter().filter_map(|id| hot.remove(id)).collect()
+        };
+        for conversation in idle {
+            for turn in &conversation.turns {
+                self.write_warm(turn, conversation.compress)?;
+                report.demoted += 1;
+            }
+        }
+
+        let archive_before = now - self.config.archive_after_seconds as i64;
+        for turn in self.warm.list_context_turns()? {
+            if expired(&turn) {
+                self.warm.delete_context_turn(turn.session_id, turn.turn)?;
+                report.expired += 1;
+            } else if turn.created_at <= archive_before {
+                let started = Instant::now();
+                self.archive.put(
+                    &archive_key(turn.session_id, turn.turn),
+                    &serde_json::to_vec(&turn)?,
+                )?;
+                self.counters.lock().unwrap()[ContextTier::Archive as usize]
+                    .record_write(started.elapsed());
+                self.warm.delete_context_turn(turn.session_id, turn.turn)?;
+                report.archived += 1;
+            }
+        }
+
+        // Archived turns are only read back here to check their age; sweeps are infrequent
+        for key in self.archive.list(ARCHIVE_ROOT)? {
+            if let Some(turn) = self.read_archived(&key)? {
+                if expired(&turn) {
+                    self.archive.delete(&key)?;
+                    report.expired += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Delete every turn of `tenant` from all tiers
+    pub fn purge_tenant(&self, tenant: &str) -> Result<ContextPurgeReport> {
+        let mut report = ContextPurgeReport::default();
+        self.hot.write().unwrap().retain(|_, conversation| {
+            let owned = conversation.tenant == tenant;
+            if owned {
+                report.hot += conversation.turns.len();
+            }
+            !owned
+        });
+        for turn in self.warm.list_context_turns()? {
+            if turn.tenant == tenant {
+                self.warm.delete_context_turn(turn.session_id, turn.turn)?;
+                report.warm += 1;
+            }
+        }
+        for key in self.archive.list(ARCHIVE_ROOT)? {
+            if self
+                .read_archived(&key)?
+                .is_some_and(|turn| turn.tenant == tenant)
+            {
+                self.archive.delete(&key)?;
+                report.archive += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn stats(&self) -> ConversationStats {
+        let (hot_conversations, hot_turns) = {
+            let hot = self.hot.read().unwrap();
+            (hot.len(), hot.values().map(|c| c.turns.len()).sum())
+        };
+        let counters = self.counters.lock().unwrap();
+        ConversationStats {
+            enabled: self.config.enabled,
+            hot_conversations,
+            hot_turns,
+            archive_backend: self.archive.name(),
+            hot: counters[ContextTier::Hot as usize].stats(),
+            warm: counters[ContextTier::Warm as usize].stats(),
+            archive: counters[ContextTier::Archive as usize].stats(),
+            compression: self
+                .compression
+                .lock()
+                .unwrap()
+                .stats(&self.config.compression),
+        }
+    }
+
+    fn write_warm(&self, turn: &ContextTurnRecord, compress: bool) -> Result<()> {
+        let compressed;
+        let turn = if compress {
+            compressed = self.compress(turn);
+            &compressed
+        } else {
+            turn
+        };
+        let started = Instant::now();
+        self.warm.put_context_turn(turn)?;
+        self.counters.lock().unwrap()[ContextTier::Warm as usize].record_write(started.elapsed());
+        Ok(())
+    }
+
+    /// `turn` as it is stored for a compressing tenant
+    fn compress(&self, turn: &ContextTurnRecord) -> ContextTurnRecord {
+        let config = &self.config.compression;
+        let started = Instant::now();
+        let compressed = (turn.ciphertext.len() >= config.min_bytes)
+            .then(|| compress_ciphertext(&turn.ciphertext, config.level))
+            .flatten();
+
+        let mut counters = self.compression.lock().unwrap();
+        counters.compress_micros += started.elapsed().as_micros() as u64;
+        counters.original_bytes += turn.ciphertext.len() as u64;
+        match compressed {
+            Some(ciphertext) => {
+                counters.compressed += 1;
+                counters.stored_bytes += ciphertext.len() as u64;
+                ContextTurnRecord {
+                    ciphertext,
+                    ..turn.clone()
+                }
+            }
+            None => {
+                counters.stored_raw += 1;
+                counters.stored_bytes += turn.ciphertext.len() as u64;
+                turn.clone()
+            }
+        }
+    }
+
+    /// `turn` with its ciphertext as the client sent it
+    fn inflate(&self, mut turn: ContextTurnRecord) -> Result<ContextTurnRecord> {
+        let Some(compressed) = turn.ciphertext.strip_prefix(COMPRESSED_PREFIX) else {
+            return Ok(turn);
+        };
+        let started = Instant::now();
+        let raw = BASE64_STANDARD
+            .decode(compressed)
+            .ok()
+            .and_then(|bytes| zstd::bulk::decompress(&bytes, self.config.max_turn_bytes).ok())
+            .ok_or_else(|| {
+                Error::DataCorruption(format!(
+                    "Turn {} of conversation {} does not decompress",
+                    turn.turn, turn.session_id
+                ))
+            })?;
+        turn.ciphertext = BASE64_STANDARD.encode(raw);
+        let mut counters = self.compression.lock().unwrap();
+        counters.decompressions += 1;
+        counters.decompress_micros += started.elapsed().as_micros() as u64;
+        Ok(turn)
+    }
+
+    fn read_archived(&self, key: &str) -> Result<Option<ContextTurnRecord>> {
+        match self.archive.get(key)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn record_read(&self, tier: ContextTier, started: Instant, hit: bool) {
+        self.counters.lock().unwrap()[tier as usize].record_read(started.elapsed(), hit);
+    }
+
+    /// Turn number after the last one in storage, for a conversation not in memory
+    fn next_stored_turn(&self, session_id: Uuid, tenant: &str) -> Result<u64> {
+        let warm = self.warm.get_context_turns(session_id)?;
+        if let Some(last) = warm.last() {
+            if last.tenant != tenant {
+                return Err(Error::Security(format!(
+                    "Conversation {} belongs to another tenant",
+                    session_id
+                )));
+            }
+            return Ok(last.turn + 1);
+        }
+        let archived = self.archive.list(&archive_prefix(session_id))?;
+        match archived.last() {
+            Some(key) => {
+                let owner = self.read_archived(key)?.map(|turn| turn.tenant);
+                if owner.as_deref().is_some_and(|owner| owner != tenant) {
+                    return Err(Error::Security(format!(
+                        "Conversation {} belongs to another tenant",
+                        session_id
+                    )));
+                }
+                Ok(archive_turn(key).map_or(0, |turn| turn + 1))
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+const ARCHIVE_ROOT: &str = "context/";
+
+/// Marks a stored ciphertext as compressed; `:` never appears in base64
+const COMPRESSED_PREFIX: &str = "zstd:";
+
+/// Compressed form of a base64 ciphertext, if it is any smaller
+fn compress_ciphertext(ciphertext: &str, level: i32) -> Option<String> {
+    let raw = BASE64_STANDARD.decode(ciphertext).ok()?;
+    let compressed = zstd::bulk::compress(&raw, level).ok()?;
+    let stored = format!(
+        "{}{}",
+        COMPRESSED_PREFIX,
+        BASE64_STANDARD.encode(compressed)
+    );
+    (stored.len() < ciphertext.len()).then_some(stored)
+}
+
+fn archive_prefix(session_id: Uuid) -> String {
+    format!("{}{}/", ARCHIVE_ROOT, session_id)
+}
+
+/// Zero-padded so keys list in turn order
+fn archive_key(session_id: Uuid, turn: u64) -> String {
+    format!("{}{:020}", archive_prefix(session_id), turn)
+}
+
+fn archive_turn(key: &str) -> Option<u64> {
+    key.rsplit('/').next()?.parse().ok()
+}
+
+fn tiered(turn: ContextTurnRecord, tier: ContextTier) -> TieredTurn {
+    TieredTurn { turn, tier }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::{ContextStore, MemoryBackend};
+
+    #[test]
+    fn test_turns_move_down_tiers_and_read_back() {
+        let warm = Arc::new(MemoryBackend::new());
+        let store = ConversationStore::new(
+            ConversationConfig {
+                enabled: true,
+                hot_turns: 2,
+                hot_idle_seconds: 3600,
+                archive_after_seconds: 60,
+                ..ConversationConfig::default()
+            },
+            warm.clone(),
+            Arc::new(MemoryObjectStore::default()),
+        );
+        let session = Uuid::new_v4();
+        for i in 0..5 {
+            let turn = store
+                .append(session, "acme", "user", format!("ct-{}", i), false)
+                .unwrap();
+            assert_eq!(turn.turn, i);
+        }
+        assert!(store
+            .append(session, "globex", "user", "x".into(), false)
+            .is_err());
+
+        // Age the turns that left memory past the archive threshold
+        for mut turn in warm.get_context_turns(session).unwrap() {
+            assert!(turn.turn < 3);
+            if turn.turn < 2 {
+                turn.created_at -= 120;
+                warm.put_context_turn(&turn).unwrap();
+            }
+        }
+        let report = store.run_tiering(|_| 0).unwrap();
+        assert_eq!((report.archived, report.expired), (2, 0));
+
+        let tiers: Vec<(u64, ContextTier)> = store
+            .turns(session, "acme", 0, None)
+            .unwrap()
+            .iter()
+            .map(|t| (t.turn.turn, t.tier))
+            .collect();
+        assert_eq!(
+            tiers,
+            vec![
+                (0, ContextTier::Archive),
+                (1, ContextTier::Archive),
+                (2, ContextTier::Warm),
+                (3, ContextTier::Hot),
+                (4, ContextTier::Hot),
+            ]
+        );
+        // Reads of recent turns never reach the lower tiers
+        assert_eq!(store.turns(session, "acme", 3, Some(4)).unwrap().len(), 1);
+        let stats = store.stats();
+        assert_eq!((stats.warm.reads, stats.archive.reads), (1, 1));
+        assert!(store.turns(session, "globex", 0, None).is_err());
+
+        // A tenant retention of one minute expires the archived turns
+        let report = store.run_tiering(|_| 60).unwrap();
+        assert_eq!(report.expired, 2);
+        assert_eq!(store.turns(session, "acme", 0, None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_turns_compress_leaving_memory_and_inflate_on_read() {
+        let warm = Arc::new(MemoryBackend::new());
+        let store = ConversationStore::new(
+            ConversationConfig {
+                enabled: true,
+                hot_turns: 1,
+                compression: ContextCompressionConfig {
+                    enabled: true,
+                    min_bytes: 64,
+                    ..ContextCompressionConfig::default()
+                },
+                ..ConversationConfig::default()
+            },
+            warm.clone(),
+            Arc::new(MemoryObjectStore::default()),
+        );
+        let session = Uuid::new_v4();
+        // One byte per encrypted bit, as the engine writes them, compresses well
+        let bits: Vec<u8> = (0..4096).map(|i| (i % 3 == 0) as u8).collect();
+        let ciphertexts = [
+            BASE64_STANDARD.encode(&bits),
+            BASE64_STANDARD.encode(b"short"),
+        ];
+        for ciphertext in ciphertexts.iter().chain(&ciphertexts) {
+            store
+                .append(session, "acme", "user", ciphertext.clone(), true)
+                .unwrap();
+        }
+
+        let stored = warm.get_context_turns(session).unwrap();
+        assert_eq!(stored.len(), 3);
+        assert!(stored[0].ciphertext.starts_with(COMPRESSED_PREFIX));
+        assert_eq!(stored[1].ciphertext, ciphertexts[1]);
+        let turns = store.turns(session, "acme", 0, None).unwrap();
+        for (turn, expected) in turns.iter().zip(ciphertexts.iter().cycle()) {
+            assert_eq!(&turn.turn.ciphertext, expected);
+        }
+
+        let stats = store.stats().compression;
+        assert_eq!((stats.compressed_turns, stats.uncompressed_turns), (2, 1));
+        assert!(stats.saved_bytes > 0 && stats.ratio < 0.5);
+        assert_eq!(stats.decompressions, 2);
+    }
+}
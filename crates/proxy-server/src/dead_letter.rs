@@ -0,0 +1,209 @@
+//! Retries and the dead-letter queue for pipeline work
+//!
+//! A work item that fails is retried with exponential backoff up to
+//! `max_attempts`, unless its error cannot succeed on retry (a malformed
+//! payload, parameters the server no longer uses, a policy refusal). Items
+//! that still fail are written to the dead-letter store with every attempt's
+//! error and timing, and stay there until an operator reprocesses or purges
+//! them. The queue alerts when more items are dead-lettered within the growth
+//! window than the configured threshold.
+
+use crate::config::DeadLetterConfig;
+use crate::error::{Error, Result};
+use crate::persistence::{BatchJobRecord, BatchJobStatus, DeadLetterAttempt, DeadLetterRecord};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Source of dead letters written by the batch window scheduler
+pub const BATCH_JOB_SOURCE: &str = "batch_job";
+
+/// Whether retrying could change the outcome of an attempt that failed with `error`
+pub fn is_retryable(error: &Error) -> bool {
+    !matches!(
+        error,
+        Error::Validation(_)
+            | Error::ParamMismatch(_)
+            | Error::Auth(_)
+            | Error::KeyPolicy(_)
+            | Error::Security(_)
+            | Error::DataCorruption(_)
+    )
+}
+
+/// Dead letter for a batch job that failed every attempt; `payload` is the
+/// job's ciphertext, which the job itself no longer keeps
+pub fn batch_job_letter(
+    job: &BatchJobRecord,
+    payload: String,
+    attempts: Vec<DeadLetterAttempt>,
+    window: &str,
+) -> DeadLetterRecord {
+    DeadLetterRecord {
+        id: Uuid::new_v4(),
+        source: BATCH_JOB_SOURCE.to_string(),
+        item_id: job.id,
+        tenant: job.tenant.clone(),
+        metadata: serde_json::json!({
+            "provider": job.provider,
+            "model": job.model,
+            "params_hash": job.params_hash,
+            "quota_units": job.quota_units,
+            "submitted_at": job.submitted_at,
+            "window": window,
+        }),
+        payload,
+        first_attempt_at: attempts.first().map_or(job.updated_at, |a| a.started_at),
+        failed_at: chrono::Utc::now().timestamp(),
+        attempts,
+    }
+}
+
+/// The batch job a dead letter came from, queued to run again with its
+/// payload restored; rebuilt from the letter if retention already dropped it
+pub fn requeue_batch_job(letter: &DeadLetterRecord, now: i64) -> Result<BatchJobRecord> {
+    if letter.source != BATCH_JOB_SOURCE {
+        return Err(Error::Validation(format!(
+            "Dead letters from {} cannot be reprocessed as batch jobs",
+            letter.source
+        )));
+    }
+    let field = |name: &str| {
+        letter.metadata.get(name).ok_or_else(|| {
+            Error::DataCorruption(format!("Dead letter {} has no {}", letter.id, name))
+        })
+    };
+    let text = |name: &str| -> Result<String> {
+        field(name)?.as_str().map(str::to_string).ok_or_else(|| {
+            Error::DataCorruption(format!("Dead letter {} has an invalid {}", letter.id, name))
+        })
+    };
+    Ok(BatchJobRecord {
+        id: letter.item_id,
+        tenant: letter.tenant.clone(),
+        provider: text("provider")?,
+        model: text("model")?,
+        ciphertext: letter.payload.clone(),
+        params_hash: text("params_hash")?,
+        quota_units: field("quota_units")?.as_f64().unwrap_or(0.0),
+        status: BatchJobStatus::Queued,
+        submitted_at: field("submitted_at")?.as_i64().unwrap_or(now),
+        updated_at: now,
+        result_ciphertext_id: None,
+        error: None,
+        items: Vec::new(),
+    })
+}
+
+#[derive(Debug)]
+pub struct DeadLetterQueue {
+    config: DeadLetterConfig,
+    /// Set while growth is over the threshold, so each surge alerts once
+    alerting: AtomicBool,
+}
+
+impl DeadLetterQueue {
+    pub fn new(config: DeadLetterConfig) -> Self {
+        Self {
+            config,
+            alerting: AtomicBool::new(false),
+        }
+    }
+
+    pub fn config(&self) -> &DeadLetterConfig {
+        &self.config
+    }
+
+    /// Whether attempt number `attempt` (from 1), which failed with `error`,
+    /// should be followed by another
+    pub fn should_retry(&self, attempt: u32, error: &Error) -> bool {
+        attempt < self.config.max_attempts && is_retryable(error)
+    }
+
+    /// Wait before the attempt after `attempt`
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.saturating_sub(1).min(16);
+        Duration::from_millis(self.config.retry_backoff_ms.saturating_mul(factor))
+    }
+
+    /// Items dead-lettered within the growth window ending at `now`
+    pub fn growth(&self, records: &[DeadLetterRecord], now: i64) -> usize {
+        let since = now - self.config.growth_window_seconds as i64;
+        records.iter().filter(|r| r.failed_at > since).count()
+    }
+
+    /// Growth to alert on, once per surge past the threshold
+    pub fn check_growth(&self, records: &[DeadLetterRecord], now: i64) -> Option<usize> {
+        let growth = self.growth(records, now);
+        let over = growth >= self.config.growth_alert_threshold;
+        let was_over = self.alerting.swap(over, Ordering::AcqRel);
+        (over && !was_over).then_some(growth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retries_then_alerts_once_per_growth_surge() {
+        let queue = DeadLetterQueue::new(DeadLetterConfig {
+            max_attempts: 3,
+            retry_backoff_ms: 100,
+            growth_alert_threshold: 2,
+            growth_window_seconds: 60,
+            ..DeadLetterConfig::default()
+        });
+        let transient = Error::Provider("unavailable".to_string());
+        assert!(queue.should_retry(2, &transient));
+        assert!(!queue.should_retry(3, &transient));
+        assert!(!queue.should_retry(1, &Error::ParamMismatch("rotated".to_string())));
+        assert_eq!(queue.backoff(1), Duration::from_millis(100));
+        assert_eq!(queue.backoff(3), Duration::from_millis(400));
+
+        let job = BatchJobRecord {
+            id: Uuid::new_v4(),
+            tenant: "acme".to_string(),
+            provider: "openai".to_string(),
+            model: "gpt-4".to_string(),
+            ciphertext: String::new(),
+            params_hash: "abc".to_string(),
+            quota_units: 0.5,
+            status: BatchJobStatus::Failed,
+            submitted_at: 0,
+            updated_at: 0,
+            result_ciphertext_id: None,
+            error: None,
+            items: Vec::new(),
+        };
+        let attempts = vec![DeadLetterAttempt {
+            attempt: 1,
+            started_at: 10,
+            duration_ms: 5,
+            error: transient.to_string(),
+        }];
+        let letter = |failed_at| DeadLetterRecord {
+            failed_at,
+            ..batch_job_letter(&job, "AAEC".to_string(), attempts.clone(), "nightly")
+        };
+        assert_eq!(letter(0).first_attempt_at, 10);
+        assert_eq!(letter(0).metadata["window"], "nightly");
+        let requeued = requeue_batch_job(&letter(0), 500).unwrap();
+        assert_eq!(requeued.status, BatchJobStatus::Queued);
+        assert_eq!(
+            (requeued.id, requeued.ciphertext.as_str()),
+            (job.id, "AAEC")
+        );
+
+        // An old failure is outside the window; two recent ones cross the
+        // threshold and alert once until growth falls back below it
+        let mut records = vec![letter(0), letter(950)];
+        assert_eq!(queue.check_growth(&records, 1000), None);
+        records.push(letter(990));
+        assert_eq!(queue.check_growth(&records, 1000), Some(2));
+        assert_eq!(queue.check_growth(&records, 1001), None);
+        assert_eq!(queue.check_growth(&records, 2000), None);
+        records.extend([letter(1990), letter(1995)]);
+        assert_eq!(queue.check_growth(&records, 2000), Some(2));
+    }
+}
@@ -0,0 +1,141 @@
+//! Request deadline budgets
+//!
+//! A request's deadline is split across the hops it passes through:
+//! validation, queueing for an FHE execution slot, FHE processing and the
+//! provider call. Budgets are progressive: a hop gets the time still remaining
+//! in proportion to its share among the hops not yet run, so time an early hop
+//! leaves unused carries over and the provider gets whatever is left. Starting
+//! a hop checks its budget first, so a request that can no longer finish fails
+//! fast instead of queueing, computing or paying for a provider call in vain.
+
+use crate::config::DeadlineBudgetConfig;
+use crate::error::{Error, Result};
+use std::time::{Duration, Instant};
+
+/// Stages of a completion request, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hop {
+    Validation,
+    Queue,
+    Fhe,
+    Provider,
+}
+
+impl Hop {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Hop::Validation => "validation",
+            Hop::Queue => "queue",
+            Hop::Fhe => "fhe",
+            Hop::Provider => "provider",
+        }
+    }
+}
+
+/// When one request must be answered by, carried as a request extension
+#[derive(Debug, Clone)]
+pub struct RequestDeadline {
+    received: Instant,
+    expires_at: Instant,
+    /// Shares of the hops, indexed by `Hop`
+    shares: [f64; 4],
+    min_provider_budget: Duration,
+}
+
+impl RequestDeadline {
+    pub fn new(config: &DeadlineBudgetConfig, deadline: Duration, received: Instant) -> Self {
+        Self {
+            received,
+            expires_at: received + deadline,
+            shares: [
+                config.validation_share,
+                config.queue_share,
+                config.fhe_share,
+                config.provider_share,
+            ],
+            min_provider_budget: Duration::from_millis(config.min_provider_budget_ms),
+        }
+    }
+
+    pub fn expires_at(&self) -> Instant {
+        self.expires_at
+    }
+
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.expires_at.saturating_duration_since(now)
+    }
+
+    /// Time `hop` may take when it starts at `now`
+    pub fn budget(&self, hop: Hop, now: Instant) -> Duration {
+        let remaining = self.remaining(now);
+        let index = hop as usize;
+        let later: f64 = self.shares[index..].iter().sum();
+        if later <= 0.0 {
+            return remaining;
+        }
+        remaining.mul_f64(self.shares[index] / later)
+    }
+
+    /// Budget of `hop` starting at `now`, or a deadline-exceeded error when too
+    /// little time is left to start it
+    pub fn start(&self, hop: Hop, now: Instant) -> Result<Duration> {
+        let budget = self.budget(hop, now);
+        let minimum = match hop {
+            Hop::Provider => self.min_provider_budget,
+            _ => Duration::ZERO,
+        };
+        if self.remaining(now).is_zero() || budget < minimum {
+            return Err(Error::DeadlineExceeded(format!(
+                "{}ms left for {} after {}ms",
+                budget.as_millis(),
+                hop.as_str(),
+                now.saturating_duration_since(self.received).as_millis()
+            )));
+        }
+        Ok(budget)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unused_time_carries_over_and_provider_fails_fast() {
+        let config = DeadlineBudgetConfig {
+            validation_share: 0.1,
+            queue_share: 0.1,
+            fhe_share: 0.3,
+            provider_share: 0.5,
+            min_provider_budget_ms: 500,
+            ..DeadlineBudgetConfig::default()
+        };
+        let received = Instant::now();
+        let deadline = RequestDeadline::new(&config, Duration::from_secs(10), received);
+        let ms = |offset: u64, hop: Hop| {
+            let budget = deadline.budget(hop, received + Duration::from_millis(offset));
+            budget.as_secs_f64() * 1000.0
+        };
+        let close = |actual: f64, expected: f64| (actual - expected).abs() < 0.01;
+
+        assert!(close(ms(0, Hop::Validation), 1000.0));
+        // Validation using its whole share leaves the queue exactly its own;
+        // finishing early hands the queue part of the difference
+        assert!(close(ms(1000, Hop::Queue), 1000.0));
+        assert!(close(ms(0, Hop::Queue), 10_000.0 / 9.0));
+        // A slow queue leaves FHE and the provider their split of what remains
+        assert!(close(ms(6000, Hop::Fhe), 1500.0));
+        assert!(close(ms(8000, Hop::Provider), 2000.0));
+
+        // Too little left for a provider call, and nothing left at all
+        let late = received + Duration::from_millis(9600);
+        assert!(matches!(
+            deadline.start(Hop::Provider, late),
+            Err(Error::DeadlineExceeded(_))
+        ));
+        assert!(deadline.start(Hop::Fhe, late).is_ok());
+        assert!(deadline
+            .start(Hop::Fhe, received + Duration::from_secs(11))
+            .is_err());
+    }
+}
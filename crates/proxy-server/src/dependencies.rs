@@ -0,0 +1,270 @@
+//! Runtime health of the external systems the proxy depends on
+//!
+//! Startup only proves the session store, providers, KMS/HSM and object
+//! storage were reachable then. Here each is probed on a schedule behind its
+//! own circuit breaker: `failure_threshold` failed probes in a row open the
+//! breaker, which stops probing for `open_seconds` and then lets one trial
+//! probe through. A success closes it again, a failure reopens it. An open
+//! breaker on a required dependency takes the proxy out of readiness, and
+//! `/health/ready` names the dependency at fault.
+
+use crate::config::DependencyMonitorConfig;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyKind {
+    SessionStore,
+    Provider,
+    Kms,
+    ObjectStorage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    /// The open period is over; the next probe decides
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyHealth {
+    Healthy,
+    /// Failing, but not yet often enough to open the breaker
+    Degraded,
+    Down,
+}
+
+/// A breaker state change caused by a probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerTransition {
+    Opened,
+    Closed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyStatus {
+    pub name: String,
+    pub kind: DependencyKind,
+    /// Backend or endpoint, for telling the operator where to look
+    pub target: String,
+    pub health: DependencyHealth,
+    pub breaker: BreakerState,
+    pub required: bool,
+    pub consecutive_failures: u32,
+    pub last_checked_at: Option<i64>,
+    pub last_success_at: Option<i64>,
+    pub opened_at: Option<i64>,
+    pub last_error: Option<String>,
+    pub latency_ms: Option<u64>,
+}
+
+impl DependencyStatus {
+    fn new(name: &str, kind: DependencyKind, target: &str, required: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            target: target.to_string(),
+            health: DependencyHealth::Healthy,
+            breaker: BreakerState::Closed,
+            required,
+            consecutive_failures: 0,
+            last_checked_at: None,
+            last_success_at: None,
+            opened_at: None,
+            last_error: None,
+            latency_ms: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DependencyMonitor {
+    config: DependencyMonitorConfig,
+    dependencies: RwLock<BTreeMap<String, DependencyStatus>>,
+}
+
+impl DependencyMonitor {
+    pub fn new(config: DependencyMonitorConfig) -> Self {
+        Self {
+            config,
+            dependencies: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &DependencyMonitorConfig {
+        &self.config
+    }
+
+    /// Start tracking a dependency; registering it again keeps its state
+    pub fn register(&self, name: &str, kind: DependencyKind, target: &str) {
+        let required = self.config.required.iter().any(|r| r == name);
+        self.dependencies
+            .write()
+            .unwrap()
+            .entry(name.to_string())
+            .or_insert_with(|| DependencyStatus::new(name, kind, target, required));
+    }
+
+    /// Whether `name` should be probed now. An open breaker lets nothing
+    /// through until its open period is over, then turns half-open.
+    pub fn should_probe(&self, name: &str, now: i64) -> bool {
+        let mut dependencies = self.dependencies.write().unwrap();
+        let Some(status) = dependencies.get_mut(name) else {
+            return false;
+        };
+        if status.breaker == BreakerState::Open {
+            let reopen_at = status.opened_at.unwrap_or(now) + self.config.open_seconds as i64;
+            if now < reopen_at {
+                return false;
+            }
+            status.breaker = BreakerState::HalfOpen;
+        }
+        true
+    }
+
+    /// Record a probe's outcome, returning the breaker transition it caused
+    pub fn record(
+        &self,
+        name: &str,
+        outcome: std::result::Result<(), String>,
+        latency_ms: u64,
+        now: i64,
+    ) -> Option<BreakerTransition> {
+        let mut dependencies = self.dependencies.write().unwrap();
+        let status = dependencies.get_mut(name)?;
+        status.last_checked_at = Some(now);
+        status.latency_ms = Some(latency_ms);
+        match outcome {
+            Ok(()) => {
+                let reopened = status.breaker != BreakerState::Closed;
+                status.consecutive_failures = 0;
+                status.last_success_at = Some(now);
+                status.last_error = None;
+                status.breaker = BreakerState::Closed;
+                status.opened_at = None;
+                status.health = DependencyHealth::Healthy;
+                reopened.then_some(BreakerTransition::Closed)
+            }
+            Err(error) => {
+                status.consecutive_failures = status.consecutive_failures.saturating_add(1);
+                status.last_error = Some(error);
+                let opens = match status.breaker {
+                    BreakerState::Closed => {
+                        status.consecutive_failures >= self.config.failure_threshold
+                    }
+                    // A failed trial reopens the breaker for another period
+                    BreakerState::HalfOpen => true,
+                    BreakerState::Open => false,
+                };
+                let was_closed = status.breaker == BreakerState::Closed;
+                if opens {
+                    status.breaker = BreakerState::Open;
+                    status.opened_at = Some(now);
+                }
+                status.health = if status.breaker == BreakerState::Closed {
+                    DependencyHealth::Degraded
+                } else {
+                    DependencyHealth::Down
+                };
+                (opens && was_closed).then_some(BreakerTransition::Opened)
+            }
+        }
+    }
+
+    /// Whether calls to `name` should go ahead; unknown dependencies are
+    /// assumed available
+    pub fn is_available(&self, name: &str) -> bool {
+        self.dependencies
+            .read()
+            .unwrap()
+            .get(name)
+            .is_none_or(|status| status.breaker == BreakerState::Closed)
+    }
+
+    /// Required dependencies that are down
+    pub fn blocking(&self) -> Vec<String> {
+        self.dependencies
+            .read()
+            .unwrap()
+            .values()
+            .filter(|status| status.required && status.health == DependencyHealth::Down)
+            .map(|status| status.name.clone())
+            .collect()
+    }
+
+    pub fn statuses(&self) -> Vec<DependencyStatus> {
+        self.dependencies
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_after_threshold_and_closes_on_trial_success() {
+        let monitor = DependencyMonitor::new(DependencyMonitorConfig {
+            enabled: true,
+            failure_threshold: 2,
+            open_seconds: 60,
+            required: vec!["session_store".to_string()],
+            ..DependencyMonitorConfig::default()
+        });
+        monitor.register("session_store", DependencyKind::SessionStore, "sqlite");
+        monitor.register("provider:openai", DependencyKind::Provider, "openai");
+        assert!(monitor.should_probe("session_store", 0));
+        assert!(!monitor.should_probe("kms", 0));
+
+        let failed = || Err("connection refused".to_string());
+        assert_eq!(monitor.record("session_store", failed(), 5, 0), None);
+        let status = &monitor.statuses()[1];
+        assert_eq!(status.name, "session_store");
+        assert_eq!(status.health, DependencyHealth::Degraded);
+        assert!(monitor.blocking().is_empty());
+
+        assert_eq!(
+            monitor.record("session_store", failed(), 5, 30),
+            Some(BreakerTransition::Opened)
+        );
+        assert!(!monitor.is_available("session_store"));
+        assert_eq!(monitor.blocking(), vec!["session_store".to_string()]);
+        assert!(!monitor.should_probe("session_store", 89));
+
+        // A failed trial reopens the breaker for another period
+        assert!(monitor.should_probe("session_store", 90));
+        assert_eq!(monitor.statuses()[1].breaker, BreakerState::HalfOpen);
+        assert_eq!(monitor.record("session_store", failed(), 5, 90), None);
+        assert!(!monitor.should_probe("session_store", 149));
+        assert!(monitor.should_probe("session_store", 150));
+        assert_eq!(
+            monitor.record("session_store", Ok(()), 3, 150),
+            Some(BreakerTransition::Closed)
+        );
+        let status = &monitor.statuses()[1];
+        assert_eq!(status.health, DependencyHealth::Healthy);
+        assert_eq!(status.last_success_at, Some(150));
+        assert_eq!(status.last_error, None);
+        assert!(monitor.blocking().is_empty());
+
+        // Optional dependencies never block readiness
+        monitor.record("provider:openai", failed(), 5, 0);
+        monitor.record("provider:openai", failed(), 5, 1);
+        assert!(!monitor.is_available("provider:openai"));
+        assert!(monitor.blocking().is_empty());
+    }
+}
@@ -1284,8 +1284,8 @@ pub struct ConfigurationSet;
 #[derive(Debug)]
 pub struct EnvironmentConfig;
 
-#[derive(Debug)]
-pub struct FeatureFlag;
+/// Flags are served by [`crate::feature_flags::FeatureFlags`]
+pub type FeatureFlag = crate::config::FeatureFlagConfig;
 
 #[derive(Debug)]
 pub struct DynamicConfigManager;
@@ -0,0 +1,286 @@
+//! Regional failover drills
+//!
+//! A drill exercises the recovery plan for losing this region without losing
+//! it. The replica stops admitting new work, so traffic moves to its standby
+//! regions (the session replication peers); the standbys are polled until all
+//! of them are serving; and the time that took and the age of the last session
+//! replication round are checked against the plan's RTO and RPO. Normal
+//! routing is restored at the end whatever the outcome, and every step's
+//! timing is kept in the drill report.
+
+use crate::config::DisasterRecoveryConfig;
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrillStatus {
+    Running,
+    Passed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrillStep {
+    pub name: String,
+    /// Offset from the start of the drill
+    pub started_ms: u64,
+    pub duration_ms: u64,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// A recovery condition the drill verified
+#[derive(Debug, Clone, Serialize)]
+pub struct DrillCheck {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrillReport {
+    pub id: Uuid,
+    pub region: String,
+    pub standbys: Vec<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub status: DrillStatus,
+    pub rto_target_ms: u64,
+    /// From traffic leaving this region until every standby was serving
+    pub rto_ms: Option<u64>,
+    pub rpo_target_seconds: u64,
+    /// Age of the last session replication round when traffic left
+    pub rpo_seconds: Option<u64>,
+    pub steps: Vec<DrillStep>,
+    pub checks: Vec<DrillCheck>,
+    #[serde(skip)]
+    clock: Option<Instant>,
+}
+
+impl DrillReport {
+    /// Record a step that began at `started`
+    pub fn step(&mut self, name: &str, started: Instant, ok: bool, detail: impl Into<String>) {
+        let origin = self.clock.unwrap_or(started);
+        self.steps.push(DrillStep {
+            name: name.to_string(),
+            started_ms: started.saturating_duration_since(origin).as_millis() as u64,
+            duration_ms: started.elapsed().as_millis() as u64,
+            ok,
+            detail: detail.into(),
+        });
+    }
+
+    /// Check the measurements against the plan targets and settle the status
+    fn evaluate(&mut self) {
+        let within = |measured: Option<u64>, target: u64| measured.is_some_and(|m| m <= target);
+        self.checks = vec![
+            DrillCheck {
+                name: "rto".to_string(),
+                passed: within(self.rto_ms, self.rto_target_ms),
+                detail: match self.rto_ms {
+                    Some(ms) => format!("{}ms against a target of {}ms", ms, self.rto_target_ms),
+                    None => "Standbys never all reported healthy".to_string(),
+                },
+            },
+            DrillCheck {
+                name: "rpo".to_string(),
+                passed: within(self.rpo_seconds, self.rpo_target_seconds),
+                detail: match self.rpo_seconds {
+                    Some(s) => format!("{}s against a target of {}s", s, self.rpo_target_seconds),
+                    None => "No session replication round has completed".to_string(),
+                },
+            },
+            DrillCheck {
+                name: "steps".to_string(),
+                passed: self.steps.iter().all(|s| s.ok),
+                detail: match self.steps.iter().find(|s| !s.ok) {
+                    Some(step) => format!("{} failed: {}", step.name, step.detail),
+                    None => format!("All {} steps succeeded", self.steps.len()),
+                },
+            },
+        ];
+        self.status = if self.checks.iter().all(|c| c.passed) {
+            DrillStatus::Passed
+        } else {
+            DrillStatus::Failed
+        };
+    }
+}
+
+/// Sends traffic away from this region while held
+pub struct RerouteGuard<'a> {
+    rerouting: &'a AtomicBool,
+}
+
+impl Drop for RerouteGuard<'_> {
+    fn drop(&mut self) {
+        self.rerouting.store(false, Ordering::Release);
+    }
+}
+
+#[derive(Debug)]
+pub struct FailoverDrills {
+    config: DisasterRecoveryConfig,
+    rerouting: AtomicBool,
+    running: Mutex<Option<Uuid>>,
+    reports: Mutex<VecDeque<DrillReport>>,
+}
+
+impl FailoverDrills {
+    pub fn new(config: DisasterRecoveryConfig) -> Self {
+        Self {
+            config,
+            rerouting: AtomicBool::new(false),
+            running: Mutex::new(None),
+            reports: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.drills_enabled
+    }
+
+    pub fn config(&self) -> &DisasterRecoveryConfig {
+        &self.config
+    }
+
+    /// Whether a drill currently has new work refused here
+    pub fn is_rerouting(&self) -> bool {
+        self.rerouting.load(Ordering::Acquire)
+    }
+
+    /// Open a report for a new drill; only one drill runs at a time
+    pub fn begin(&self, region: &str, standbys: &[String]) -> Result<DrillReport> {
+        if !self.config.drills_enabled {
+            return Err(Error::Validation(
+                "Failover drills are not enabled".to_string(),
+            ));
+        }
+        let mut running = self.running.lock().unwrap();
+        if let Some(id) = *running {
+            return Err(Error::Concurrency(format!(
+                "Failover drill {} is still running",
+                id
+            )));
+        }
+        let report = DrillReport {
+            id: Uuid::new_v4(),
+            region: region.to_string(),
+            standbys: standbys.to_vec(),
+            started_at: chrono::Utc::now().timestamp(),
+            finished_at: None,
+            status: DrillStatus::Running,
+            rto_target_ms: self.config.rto_seconds * 1000,
+            rto_ms: None,
+            rpo_target_seconds: self.config.rpo_seconds,
+            rpo_seconds: None,
+            steps: Vec::new(),
+            checks: Vec::new(),
+            clock: Some(Instant::now()),
+        };
+        *running = Some(report.id);
+        self.store(report.clone());
+        Ok(report)
+    }
+
+    /// Refuse new work here until the guard is dropped
+    pub fn reroute(&self) -> RerouteGuard<'_> {
+        self.rerouting.store(true, Ordering::Release);
+        RerouteGuard {
+            rerouting: &self.rerouting,
+        }
+    }
+
+    /// Publish a running drill's progress
+    pub fn update(&self, report: &DrillReport) {
+        self.store(report.clone());
+    }
+
+    /// Evaluate a drill against the plan and keep its final report
+    pub fn finish(&self, mut report: DrillReport) -> DrillReport {
+        report.finished_at = Some(chrono::Utc::now().timestamp());
+        report.evaluate();
+        self.store(report.clone());
+        *self.running.lock().unwrap() = None;
+        report
+    }
+
+    /// Reports, newest first
+    pub fn reports(&self) -> Vec<DrillReport> {
+        self.reports.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    pub fn report(&self, id: Uuid) -> Option<DrillReport> {
+        self.reports
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|r| r.id == id)
+            .cloned()
+    }
+
+    fn store(&self, report: DrillReport) {
+        let mut reports = self.reports.lock().unwrap();
+        match reports.iter_mut().find(|r| r.id == report.id) {
+            Some(existing) => *existing = report,
+            None => {
+                if reports.len() >= self.config.max_reports.max(1) {
+                    reports.pop_front();
+                }
+                reports.push_back(report);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drill_is_judged_against_plan_targets() {
+        let drills = FailoverDrills::new(DisasterRecoveryConfig {
+            drills_enabled: true,
+            rto_seconds: 1,
+            rpo_seconds: 30,
+            ..DisasterRecoveryConfig::default()
+        });
+        let standbys = vec!["https://eu.example".to_string()];
+        let mut report = drills.begin("us", &standbys).unwrap();
+        assert!(drills.begin("us", &standbys).is_err());
+
+        {
+            let _reroute = drills.reroute();
+            assert!(drills.is_rerouting());
+            report.step("reroute", Instant::now(), true, "New work refused");
+            report.rto_ms = Some(1500);
+            report.rpo_seconds = Some(10);
+        }
+        assert!(!drills.is_rerouting());
+
+        // Too slow a recovery fails the drill even though every step succeeded
+        let report = drills.finish(report);
+        assert_eq!(report.status, DrillStatus::Failed);
+        let failed: Vec<&str> = report
+            .checks
+            .iter()
+            .filter(|c| !c.passed)
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(failed, vec!["rto"]);
+
+        // The next drill may start once the last one finished
+        let mut report = drills.begin("us", &standbys).unwrap();
+        report.rto_ms = Some(800);
+        report.rpo_seconds = Some(10);
+        assert_eq!(drills.finish(report).status, DrillStatus::Passed);
+        assert_eq!(drills.reports().len(), 2);
+        assert_eq!(drills.reports()[0].status, DrillStatus::Passed);
+    }
+}
@@ -1,5 +1,6 @@
 //! Error types for the FHE LLM Proxy
 
+use crate::provider_errors::ProviderFailure;
 use thiserror::Error as ThisError;
 
 /// Result type for FHE operations
@@ -24,6 +25,10 @@ pub enum Error {
     #[error("Provider error: {0}")]
     Provider(String),
 
+    /// Provider error response, classified from its body
+    #[error("Provider error ({}, HTTP {}): {}", .0.class, .0.status, .0.message)]
+    ProviderFailure(ProviderFailure),
+
     /// HTTP server errors
     #[error("HTTP error: {0}")]
     Http(String),
@@ -60,6 +65,10 @@ pub enum Error {
     #[error("Timeout error: {0}")]
     Timeout(String),
 
+    /// The request's deadline left too little time for the next hop
+    #[error("Deadline exceeded: {0}")]
+    DeadlineExceeded(String),
+
     /// Generic internal errors
     #[error("Internal error: {0}")]
     Internal(String),
@@ -83,6 +92,14 @@ pub enum Error {
     /// Encryption/Decryption errors
     #[error("Cryptographic error: {0}")]
     Cryptographic(String),
+
+    /// Ciphertext or client parameters do not match the server's FHE profile
+    #[error("FHE parameter mismatch: {0}")]
+    ParamMismatch(String),
+
+    /// A client key was used for an operation or model its policy does not allow
+    #[error("Key policy violation: {0}")]
+    KeyPolicy(String),
 }
 
 impl Error {
@@ -92,7 +109,7 @@ impl Error {
             Error::Config(_) => ErrorSeverity::High,
             Error::Network(_) => ErrorSeverity::Medium,
             Error::Fhe(_) => ErrorSeverity::High,
-            Error::Provider(_) => ErrorSeverity::Medium,
+            Error::Provider(_) | Error::ProviderFailure(_) => ErrorSeverity::Medium,
             Error::Http(_) => ErrorSeverity::Low,
             Error::Serialization(_) => ErrorSeverity::Low,
             Error::Request(_) => ErrorSeverity::Low,
@@ -101,6 +118,7 @@ impl Error {
             Error::RateLimit(_) => ErrorSeverity::Low,
             Error::PrivacyBudget(_) => ErrorSeverity::High,
             Error::Timeout(_) => ErrorSeverity::Medium,
+            Error::DeadlineExceeded(_) => ErrorSeverity::Low,
             Error::Internal(_) => ErrorSeverity::Critical,
             Error::Security(_) => ErrorSeverity::Critical,
             Error::ResourceExhaustion(_) => ErrorSeverity::High,
@@ -108,6 +126,8 @@ impl Error {
             Error::DataCorruption(_) => ErrorSeverity::Critical,
             Error::Cryptographic(_) => ErrorSeverity::Critical,
             Error::Configuration(_) => ErrorSeverity::Critical,
+            Error::ParamMismatch(_) => ErrorSeverity::Medium,
+            Error::KeyPolicy(_) => ErrorSeverity::High,
         }
     }
 
@@ -117,18 +137,20 @@ impl Error {
             Error::Config(_) => "configuration",
             Error::Network(_) | Error::Http(_) | Error::Request(_) => "network",
             Error::Fhe(_) | Error::Cryptographic(_) => "cryptography",
-            Error::Provider(_) => "external_service",
+            Error::Provider(_) | Error::ProviderFailure(_) => "external_service",
             Error::Serialization(_) => "data_format",
             Error::Auth(_) | Error::Security(_) => "security",
             Error::Validation(_) => "validation",
             Error::RateLimit(_) => "rate_limiting",
             Error::PrivacyBudget(_) => "privacy",
-            Error::Timeout(_) => "performance",
+            Error::Timeout(_) | Error::DeadlineExceeded(_) => "performance",
             Error::Internal(_) => "internal",
             Error::ResourceExhaustion(_) => "resources",
             Error::Concurrency(_) => "concurrency",
             Error::DataCorruption(_) => "data_integrity",
             Error::Configuration(_) => "configuration",
+            Error::ParamMismatch(_) => "param_mismatch",
+            Error::KeyPolicy(_) => "key_policy",
         }
     }
 
@@ -183,6 +205,32 @@ impl ErrorContext {
     }
 }
 
+impl From<fhe_core::Error> for Error {
+    fn from(err: fhe_core::Error) -> Self {
+        match err {
+            fhe_core::Error::Fhe(message) => Error::Fhe(message),
+            fhe_core::Error::Validation(message) => Error::Validation(message),
+            fhe_core::Error::Configuration(message) => Error::Configuration(message),
+            fhe_core::Error::ParamMismatch(message) => Error::ParamMismatch(message),
+            fhe_core::Error::Serialization(err) => Error::Serialization(err),
+        }
+    }
+}
+
+impl From<proxy_client::Error> for Error {
+    fn from(err: proxy_client::Error) -> Self {
+        match err {
+            proxy_client::Error::Config(message) => Error::Config(message),
+            proxy_client::Error::Http(err) => Error::Request(err),
+            proxy_client::Error::ParamsMismatch(fields) => Error::ParamMismatch(fields.join(", ")),
+            proxy_client::Error::Security(message) => Error::Security(message),
+            proxy_client::Error::Validation(message) => Error::Validation(message),
+            proxy_client::Error::Serialization(err) => Error::Serialization(err),
+            err @ proxy_client::Error::Status { .. } => Error::Http(err.to_string()),
+        }
+    }
+}
+
 impl From<toml::de::Error> for Error {
     fn from(err: toml::de::Error) -> Self {
         Error::Config(err.to_string())
@@ -0,0 +1,423 @@
+//! Escrow of session keys with split custodians for regulated tenants
+//!
+//! When a tenant names custodians in `escrow_custodians`, every client key
+//! generated for it is split into one XOR share per custodian, so no custodian
+//! (and no subset of them) learns anything about the key. Each share is sealed
+//! to its custodian's X25519 public key with a fresh ephemeral key, HKDF-SHA256
+//! and AES-256-GCM, and the sealed shares are kept by the persistence backend.
+//!
+//! Recovery takes every custodian:
+//!
+//! 1. `POST /v1/escrow/{client_id}/recoveries` opens a recovery and returns
+//!    the sealed share for each custodian.
+//! 2. Each custodian opens their share offline with their private key (see
+//!    [`open_share`]) and submits it to
+//!    `POST /v1/escrow/recoveries/{recovery_id}/shares`.
+//! 3. Once the last share is in, the key is rebuilt and restored into the
+//!    engine; it is never returned over the API. `GET
+//!    /v1/escrow/recoveries/{recovery_id}` reports progress.
+//!
+//! Submissions are checked against a digest of the share taken at deposit,
+//! and a recovery not completed within `recovery_ttl_seconds` is discarded.
+
+use crate::config::{EscrowConfig, EscrowCustodianConfig};
+use crate::error::{Error, Result};
+use crate::persistence::{EscrowRecord, EscrowShareRecord};
+use base64::prelude::*;
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::digest;
+use ring::hkdf;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+const SHARE_KEY_SALT: &[u8] = b"fhe-proxy-escrow-v1";
+
+/// Progress of a recovery, as reported by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryStatus {
+    pub recovery_id: Uuid,
+    pub client_id: Uuid,
+    pub tenant: String,
+    pub opened_at: i64,
+    pub expires_at: i64,
+    pub custodians: Vec<String>,
+    /// Custodians whose share has been accepted
+    pub submitted: Vec<String>,
+}
+
+/// Result of accepting one custodian's share
+#[derive(Debug)]
+pub enum ShareOutcome {
+    Pending(RecoveryStatus),
+    /// Every share is in; holds the rebuilt key
+    Complete(RecoveryStatus, Vec<u8>),
+}
+
+#[derive(Debug)]
+struct Recovery {
+    client_id: Uuid,
+    tenant: String,
+    opened_at: i64,
+    expires_at: i64,
+    shares: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub struct EscrowService {
+    config: EscrowConfig,
+    recoveries: Mutex<HashMap<Uuid, Recovery>>,
+    rng: SystemRandom,
+}
+
+impl EscrowService {
+    pub fn new(config: EscrowConfig) -> Self {
+        Self {
+            config,
+            recoveries: Mutex::new(HashMap::new()),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Check a tenant's custodian list: enough distinct names, each with a
+    /// valid X25519 public key
+    pub fn validate_custodians(
+        custodians: &[EscrowCustodianConfig],
+        min_custodians: usize,
+    ) -> Result<()> {
+        if custodians.len() < min_custodians {
+            return Err(Error::Config(format!(
+                "Escrow needs at least {} custodians, got {}",
+                min_custodians,
+                custodians.len()
+            )));
+        }
+        let mut names = std::collections::HashSet::new();
+        for custodian in custodians {
+            if custodian.name.is_empty() || !names.insert(custodian.name.as_str()) {
+                return Err(Error::Config(format!(
+                    "Escrow custodian names must be unique and non-empty: {:?}",
+                    custodian.name
+                )));
+            }
+            decode_public_key(&custodian.public_key)?;
+        }
+        Ok(())
+    }
+
+    /// Split `key_data` across `custodians`, sealing one share to each
+    pub fn deposit(
+        &self,
+        tenant: &str,
+        client_id: Uuid,
+        key_data: &[u8],
+        custodians: &[EscrowCustodianConfig],
+    ) -> Result<EscrowRecord> {
+        Self::validate_custodians(custodians, self.config.min_custodians)?;
+
+        // All but the last share are random; the last makes them XOR to the key
+        let mut last = key_data.to_vec();
+        let mut shares = Vec::with_capacity(custodians.len());
+        for _ in 1..custodians.len() {
+            let mut share = vec![0u8; key_data.len()];
+            self.rng
+                .fill(&mut share)
+                .map_err(|_| Error::Cryptographic("Failed to generate key share".to_string()))?;
+            xor_into(&mut last, &share);
+            shares.push(share);
+        }
+        shares.push(last);
+
+        let sealed = custodians
+            .iter()
+            .zip(shares)
+            .map(|(custodian, share)| self.seal_share(client_id, custodian, share))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(EscrowRecord {
+            client_id,
+            tenant: tenant.to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+            shares: sealed,
+        })
+    }
+
+    /// Start recovering the key escrowed in `record`
+    pub fn open_recovery(&self, record: &EscrowRecord) -> RecoveryStatus {
+        let now = chrono::Utc::now().timestamp();
+        let recovery_id = Uuid::new_v4();
+        let recovery = Recovery {
+            client_id: record.client_id,
+            tenant: record.tenant.clone(),
+            opened_at: now,
+            expires_at: now + self.config.recovery_ttl_seconds as i64,
+            shares: BTreeMap::new(),
+        };
+        let status = status(recovery_id, &recovery, record);
+        let mut recoveries = self.recoveries.lock().unwrap();
+        recoveries.retain(|_, r| r.expires_at > now);
+        recoveries.insert(recovery_id, recovery);
+        status
+    }
+
+    /// Accept `custodian`'s opened share; the key is rebuilt once every
+    /// custodian in `record` has submitted
+    pub fn submit_share(
+        &self,
+        record: &EscrowRecord,
+        recovery_id: Uuid,
+        custodian: &str,
+        share: Vec<u8>,
+    ) -> Result<ShareOutcome> {
+        let now = chrono::Utc::now().timestamp();
+        let mut recoveries = self.recoveries.lock().unwrap();
+        let recovery = recoveries
+            .get_mut(&recovery_id)
+            .filter(|r| r.expires_at > now && r.client_id == record.client_id)
+            .ok_or_else(|| {
+                Error::Validation(format!("Unknown or expired recovery: {}", recovery_id))
+            })?;
+        let expected = record
+            .shares
+            .iter()
+            .find(|s| s.custodian == custodian)
+            .ok_or_else(|| {
+                Error::Security(format!("{} is not a custodian of this key", custodian))
+            })?;
+        if sha256_hex(&share) != expected.share_sha256 {
+            return Err(Error::Security(format!(
+                "Share submitted by {} does not match the escrowed share",
+                custodian
+            )));
+        }
+        recovery.shares.insert(custodian.to_string(), share);
+
+        let status = status(recovery_id, recovery, record);
+        if recovery.shares.len() < record.shares.len() {
+            return Ok(ShareOutcome::Pending(status));
+        }
+        let recovery = recoveries.remove(&recovery_id).unwrap();
+        let mut key_data = vec![0u8; recovery.shares.values().next().map_or(0, Vec::len)];
+        for share in recovery.shares.values() {
+            xor_into(&mut key_data, share);
+        }
+        Ok(ShareOutcome::Complete(status, key_data))
+    }
+
+    /// An unexpired recovery's progress
+    pub fn recovery(&self, record: &EscrowRecord, recovery_id: Uuid) -> Option<RecoveryStatus> {
+        let now = chrono::Utc::now().timestamp();
+        self.recoveries
+            .lock()
+            .unwrap()
+            .get(&recovery_id)
+            .filter(|r| r.expires_at > now && r.client_id == record.client_id)
+            .map(|r| status(recovery_id, r, record))
+    }
+
+    /// The client key a recovery is for, so callers can load its record
+    pub fn recovery_client(&self, recovery_id: Uuid) -> Option<(Uuid, String)> {
+        self.recoveries
+            .lock()
+            .unwrap()
+            .get(&recovery_id)
+            .map(|r| (r.client_id, r.tenant.clone()))
+    }
+
+    fn seal_share(
+        &self,
+        client_id: Uuid,
+        custodian: &EscrowCustodianConfig,
+        share: Vec<u8>,
+    ) -> Result<EscrowShareRecord> {
+        let peer = decode_public_key(&custodian.public_key)?;
+        let private = EphemeralPrivateKey::generate(&X25519, &self.rng)
+            .map_err(|_| Error::Cryptographic("Failed to generate escrow key".to_string()))?;
+        let ephemeral_public_key = private
+            .compute_public_key()
+            .map_err(|_| Error::Cryptographic("Failed to generate escrow key".to_string()))?;
+        let key =
+            agreement::agree_ephemeral(private, &UnparsedPublicKey::new(&X25519, peer), |secret| {
+                share_key(secret, client_id, &custodian.name)
+            })
+            .map_err(|_| Error::Cryptographic("Escrow key agreement failed".to_string()))??;
+
+        let mut nonce_bytes = [0u8; aead::NONCE_LEN];
+        self.rng
+            .fill(&mut nonce_bytes)
+            .map_err(|_| Error::Cryptographic("Failed to generate share nonce".to_string()))?;
+        let share_sha256 = sha256_hex(&share);
+        let mut in_out = share;
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce_bytes),
+            Aad::from(share_aad(client_id, &custodian.name)),
+            &mut in_out,
+        )
+        .map_err(|_| Error::Cryptographic("Failed to seal key share".to_string()))?;
+
+        Ok(EscrowShareRecord {
+            custodian: custodian.name.clone(),
+            ephemeral_public_key: BASE64_STANDARD.encode(ephemeral_public_key.as_ref()),
+            nonce: BASE64_STANDARD.encode(nonce_bytes),
+            sealed: BASE64_STANDARD.encode(in_out),
+            share_sha256,
+        })
+    }
+}
+
+/// Open a sealed share with the custodian's private key; this is the step
+/// custodian tooling performs before submitting a share
+pub fn open_share(
+    private: EphemeralPrivateKey,
+    client_id: Uuid,
+    share: &EscrowShareRecord,
+) -> Result<Vec<u8>> {
+    let peer = decode_public_key(&share.ephemeral_public_key)?;
+    let key =
+        agreement::agree_ephemeral(private, &UnparsedPublicKey::new(&X25519, peer), |secret| {
+            share_key(secret, client_id, &share.custodian)
+        })
+        .map_err(|_| Error::Cryptographic("Escrow key agreement failed".to_string()))??;
+    let nonce_bytes: [u8; aead::NONCE_LEN] = BASE64_STANDARD
+        .decode(&share.nonce)
+        .ok()
+        .and_then(|n| n.try_into().ok())
+        .ok_or_else(|| Error::Validation("Invalid share nonce".to_string()))?;
+    let mut in_out = BASE64_STANDARD
+        .decode(&share.sealed)
+        .map_err(|e| Error::Validation(format!("Invalid sealed share: {}", e)))?;
+    let plaintext = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce_bytes),
+            Aad::from(share_aad(client_id, &share.custodian)),
+            &mut in_out,
+        )
+        .map_err(|_| Error::Security("Key share failed authentication".to_string()))?;
+    Ok(plaintext.to_vec())
+}
+
+fn status(recovery_id: Uuid, recovery: &Recovery, record: &EscrowRecord) -> RecoveryStatus {
+    RecoveryStatus {
+        recovery_id,
+        client_id: recovery.client_id,
+        tenant: recovery.tenant.clone(),
+        opened_at: recovery.opened_at,
+        expires_at: recovery.expires_at,
+        custodians: record.shares.iter().map(|s| s.custodian.clone()).collect(),
+        submitted: recovery.shares.keys().cloned().collect(),
+    }
+}
+
+fn share_key(secret: &[u8], client_id: Uuid, custodian: &str) -> Result<LessSafeKey> {
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, SHARE_KEY_SALT).extract(secret);
+    let info = [client_id.as_bytes().as_slice(), custodian.as_bytes()];
+    let okm = prk
+        .expand(&info, &aead::AES_256_GCM)
+        .map_err(|_| Error::Cryptographic("Failed to derive share key".to_string()))?;
+    Ok(LessSafeKey::new(UnboundKey::from(okm)))
+}
+
+fn share_aad(client_id: Uuid, custodian: &str) -> Vec<u8> {
+    format!("{}:{}", client_id, custodian).into_bytes()
+}
+
+fn decode_public_key(encoded: &str) -> Result<Vec<u8>> {
+    BASE64_STANDARD
+        .decode(encoded)
+        .ok()
+        .filter(|k| k.len() == 32)
+        .ok_or_else(|| Error::Config("Escrow public keys must be base64 X25519 keys".to_string()))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    digest::digest(&digest::SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn xor_into(acc: &mut [u8], share: &[u8]) {
+    for (a, b) in acc.iter_mut().zip(share) {
+        *a ^= b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escrowed_key_needs_every_custodian() {
+        let rng = SystemRandom::new();
+        let mut privates = Vec::new();
+        let mut custodians = Vec::new();
+        for name in ["legal", "security", "compliance"] {
+            let private = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+            custodians.push(EscrowCustodianConfig {
+                name: name.to_string(),
+                public_key: BASE64_STANDARD.encode(private.compute_public_key().unwrap()),
+            });
+            privates.push(private);
+        }
+        let service = EscrowService::new(EscrowConfig {
+            enabled: true,
+            ..EscrowConfig::default()
+        });
+        assert!(service
+            .deposit("acme", Uuid::new_v4(), b"key", &custodians[..1])
+            .is_err());
+
+        let client_id = Uuid::new_v4();
+        let key_data: Vec<u8> = (0..128).map(|i| i as u8).collect();
+        let record = service
+            .deposit("acme", client_id, &key_data, &custodians)
+            .unwrap();
+        assert_eq!(record.shares.len(), 3);
+
+        let opened: Vec<Vec<u8>> = privates
+            .into_iter()
+            .zip(&record.shares)
+            .map(|(private, share)| open_share(private, client_id, share).unwrap())
+            .collect();
+        assert!(opened.iter().all(|share| share != &key_data));
+
+        let recovery = service.open_recovery(&record);
+        // A tampered share or a stranger is refused
+        let mut forged = opened[0].clone();
+        forged[0] ^= 1;
+        assert!(service
+            .submit_share(&record, recovery.recovery_id, "legal", forged)
+            .is_err());
+        assert!(service
+            .submit_share(&record, recovery.recovery_id, "mallory", opened[0].clone())
+            .is_err());
+
+        for (i, name) in ["legal", "security"].into_iter().enumerate() {
+            let outcome = service
+                .submit_share(&record, recovery.recovery_id, name, opened[i].clone())
+                .unwrap();
+            assert!(matches!(outcome, ShareOutcome::Pending(s) if s.submitted.len() == i + 1));
+        }
+        match service
+            .submit_share(
+                &record,
+                recovery.recovery_id,
+                "compliance",
+                opened[2].clone(),
+            )
+            .unwrap()
+        {
+            ShareOutcome::Complete(_, rebuilt) => assert_eq!(rebuilt, key_data),
+            ShareOutcome::Pending(_) => panic!("All custodians submitted"),
+        }
+        // A completed recovery is closed
+        assert!(service.recovery(&record, recovery.recovery_id).is_none());
+    }
+}
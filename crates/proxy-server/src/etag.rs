@@ -0,0 +1,80 @@
+//! Conditional requests for deterministic responses
+//!
+//! Responses that depend only on their ciphertext inputs carry a strong ETag
+//! built from ciphertext digests, so the tag changes exactly when the
+//! encrypted content does. A client that repeats a request with the tag in
+//! `If-None-Match` gets `304 Not Modified` and no body while the proxy still
+//! holds the same result, which makes revalidating a cached completion cost
+//! one round trip instead of a ciphertext download.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use ring::digest;
+
+/// Strong ETag over `parts`, each of which is length-prefixed so adjacent
+/// parts cannot run together
+pub fn digest_etag(parts: &[&[u8]]) -> String {
+    let mut context = digest::Context::new(&digest::SHA256);
+    for part in parts {
+        context.update(&(part.len() as u64).to_be_bytes());
+        context.update(part);
+    }
+    let hex: String = context.finish().as_ref()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("\"{}\"", hex)
+}
+
+/// Whether `If-None-Match` in `headers` names `etag`; weak comparison, as
+/// RFC 9110 requires for this header
+pub fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Add `etag` and a policy that lets clients keep the response but makes
+/// them revalidate it before reuse
+pub fn insert_etag(headers: &mut HeaderMap, etag: &str) {
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("private, no-cache"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_none_match_compares_ciphertext_digests() {
+        let etag = digest_etag(&[b"key", b"ciphertext"]);
+        assert_eq!(etag, digest_etag(&[b"key", b"ciphertext"]));
+        assert_ne!(etag, digest_etag(&[b"keyc", b"iphertext"]));
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+
+        let mut headers = HeaderMap::new();
+        assert!(!if_none_match(&headers, &etag));
+        headers.insert(
+            header::IF_NONE_MATCH,
+            format!("\"stale\", W/{}", etag).parse().unwrap(),
+        );
+        assert!(if_none_match(&headers, &etag));
+        headers.insert(header::IF_NONE_MATCH, "\"stale\"".parse().unwrap());
+        assert!(!if_none_match(&headers, &etag));
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(if_none_match(&headers, &etag));
+
+        let mut response = HeaderMap::new();
+        insert_etag(&mut response, &etag);
+        assert_eq!(response[header::ETAG], etag.as_str());
+    }
+}
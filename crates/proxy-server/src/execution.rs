@@ -0,0 +1,232 @@
+//! Where FHE operations run
+//!
+//! With `gpu.enabled` every operation first asks for one of the GPU's
+//! `max_concurrent_operations` slots, waiting up to `max_wait_ms`. When none
+//! frees up, or the proxy was built without a GPU backend, the operation's
+//! fallback policy decides: run on the CPU, or fail so the client retries
+//! elsewhere. Without `gpu.enabled` the CPU is the primary target and no
+//! policy applies.
+//!
+//! Counts and time per operation and target are kept so capacity planning can
+//! see how much work the CPU picks up.
+
+use crate::config::{FheOperation, GpuConfig, GpuFallbackPolicy};
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionTarget {
+    Gpu,
+    Cpu,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OperationExecutionStats {
+    pub gpu: u64,
+    pub cpu: u64,
+    /// CPU runs that were meant for the GPU
+    pub cpu_fallback: u64,
+    /// Refused because the GPU was unavailable and the policy is `reject`
+    pub rejected: u64,
+    pub gpu_ms_total: u64,
+    pub cpu_ms_total: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionReport {
+    pub gpu_enabled: bool,
+    pub gpu_present: bool,
+    pub gpu_slots_free: usize,
+    /// Share of operations, 0.0-1.0, that ran on the CPU
+    pub cpu_share: f64,
+    pub operations: BTreeMap<FheOperation, OperationExecutionStats>,
+}
+
+type SharedStats = Arc<Mutex<BTreeMap<FheOperation, OperationExecutionStats>>>;
+
+/// Leave to run one operation on `target`; the time it is held is recorded
+/// against that target
+#[derive(Debug)]
+pub struct ExecutionPermit {
+    pub operation: FheOperation,
+    pub target: ExecutionTarget,
+    started: Instant,
+    stats: SharedStats,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for ExecutionPermit {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        let mut stats = self.stats.lock().unwrap();
+        let stats = stats.entry(self.operation).or_default();
+        match self.target {
+            ExecutionTarget::Gpu => stats.gpu_ms_total += elapsed,
+            ExecutionTarget::Cpu => stats.cpu_ms_total += elapsed,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecutionDispatcher {
+    config: GpuConfig,
+    gpu_present: bool,
+    slots: Arc<Semaphore>,
+    stats: SharedStats,
+}
+
+impl ExecutionDispatcher {
+    pub fn new(config: GpuConfig) -> Self {
+        Self::with_device(config, cfg!(feature = "gpu"))
+    }
+
+    fn with_device(config: GpuConfig, gpu_present: bool) -> Self {
+        if config.enabled && !gpu_present {
+            log::warn!(
+                "GPU enabled but this build has no GPU backend; operations follow their fallback policies"
+            );
+        }
+        Self {
+            slots: Arc::new(Semaphore::new(config.fallback.max_concurrent_operations)),
+            config,
+            gpu_present,
+            stats: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Pick the target for one `operation`, waiting briefly for a GPU slot
+    pub async fn acquire(&self, operation: FheOperation) -> Result<ExecutionPermit> {
+        let (target, slot) = if !self.config.enabled {
+            (ExecutionTarget::Cpu, None)
+        } else if let Some(slot) = self.gpu_slot().await {
+            (ExecutionTarget::Gpu, Some(slot))
+        } else {
+            match self.config.fallback.policy(operation) {
+                GpuFallbackPolicy::Cpu => (ExecutionTarget::Cpu, None),
+                GpuFallbackPolicy::Reject => {
+                    self.stats
+                        .lock()
+                        .unwrap()
+                        .entry(operation)
+                        .or_default()
+                        .rejected += 1;
+                    return Err(Error::ResourceExhaustion(format!(
+                        "No GPU available for {:?} and its policy forbids CPU fallback",
+                        operation
+                    )));
+                }
+            }
+        };
+        let fallback = self.config.enabled && target == ExecutionTarget::Cpu;
+
+        {
+            let mut stats = self.stats.lock().unwrap();
+            let stats = stats.entry(operation).or_default();
+            match target {
+                ExecutionTarget::Gpu => stats.gpu += 1,
+                ExecutionTarget::Cpu => stats.cpu += 1,
+            }
+            if fallback {
+                stats.cpu_fallback += 1;
+            }
+        }
+        Ok(ExecutionPermit {
+            operation,
+            target,
+            started: Instant::now(),
+            stats: self.stats.clone(),
+            _slot: slot,
+        })
+    }
+
+    async fn gpu_slot(&self) -> Option<OwnedSemaphorePermit> {
+        if !self.gpu_present {
+            return None;
+        }
+        let wait = Duration::from_millis(self.config.fallback.max_wait_ms);
+        tokio::time::timeout(wait, self.slots.clone().acquire_owned())
+            .await
+            .ok()?
+            .ok()
+    }
+
+    pub fn report(&self) -> ExecutionReport {
+        let operations = self.stats.lock().unwrap().clone();
+        let (gpu, cpu) = operations.values().fold((0, 0), |(gpu, cpu), stats| {
+            (gpu + stats.gpu, cpu + stats.cpu)
+        });
+        ExecutionReport {
+            gpu_enabled: self.config.enabled,
+            gpu_present: self.gpu_present,
+            gpu_slots_free: self.slots.available_permits(),
+            cpu_share: if gpu + cpu == 0 {
+                0.0
+            } else {
+                cpu as f64 / (gpu + cpu) as f64
+            },
+            operations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn gpu_config(max_concurrent_operations: usize) -> GpuConfig {
+        let mut config = Config::default().gpu;
+        config.enabled = true;
+        config.fallback.max_concurrent_operations = max_concurrent_operations;
+        config.fallback.max_wait_ms = 10;
+        config
+    }
+
+    #[tokio::test]
+    async fn test_saturated_gpu_falls_back_per_operation_policy() {
+        let dispatcher = ExecutionDispatcher::with_device(gpu_config(1), true);
+
+        let held = dispatcher.acquire(FheOperation::Process).await.unwrap();
+        assert_eq!(held.target, ExecutionTarget::Gpu);
+
+        // The only slot is taken: encryption moves to the CPU, bootstrap is refused
+        let encrypt = dispatcher.acquire(FheOperation::Encrypt).await.unwrap();
+        assert_eq!(encrypt.target, ExecutionTarget::Cpu);
+        assert!(dispatcher.acquire(FheOperation::Bootstrap).await.is_err());
+
+        drop(held);
+        let bootstrap = dispatcher.acquire(FheOperation::Bootstrap).await.unwrap();
+        assert_eq!(bootstrap.target, ExecutionTarget::Gpu);
+        drop((encrypt, bootstrap));
+
+        let report = dispatcher.report();
+        assert_eq!(report.cpu_share, 1.0 / 3.0);
+        assert_eq!(report.operations[&FheOperation::Encrypt].cpu_fallback, 1);
+        assert_eq!(report.operations[&FheOperation::Bootstrap].rejected, 1);
+        assert_eq!(report.operations[&FheOperation::Bootstrap].gpu, 1);
+
+        // Without a device every operation follows its policy straight away
+        let absent = ExecutionDispatcher::with_device(gpu_config(1), false);
+        assert!(absent.acquire(FheOperation::Bootstrap).await.is_err());
+        let permit = absent.acquire(FheOperation::Process).await.unwrap();
+        assert_eq!(permit.target, ExecutionTarget::Cpu);
+        assert_eq!(
+            absent.report().operations[&FheOperation::Process].cpu_fallback,
+            1
+        );
+
+        // With the GPU disabled the CPU is primary and nothing is a fallback
+        let cpu_only = ExecutionDispatcher::new(Config::default().gpu);
+        let permit = cpu_only.acquire(FheOperation::Bootstrap).await.unwrap();
+        assert_eq!(permit.target, ExecutionTarget::Cpu);
+        assert_eq!(
+            cpu_only.report().operations[&FheOperation::Bootstrap].cpu_fallback,
+            0
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! Encrypted A/B testing of prompt templates
+//!
+//! An experiment splits requests built from one template across variant
+//! templates by weight. Assignment hashes the experiment ID with a client
+//! supplied user hash, so a user stays on one variant without the proxy ever
+//! learning who they are. Prompts and responses stay encrypted throughout:
+//! only latency, response length in tokens and client-reported ratings are
+//! aggregated per variant.
+
+use crate::config::{ExperimentConfig, ExperimentsConfig};
+use crate::error::{Error, Result};
+use ring::digest;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Opaque per-user hash computed by the client; requests without it are not enrolled
+pub const USER_HASH_HEADER: &str = "x-user-hash";
+
+/// Ratings clients may report, inclusive
+pub const RATING_RANGE: std::ops::RangeInclusive<u8> = 1..=5;
+
+/// Variant a request was routed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub experiment: String,
+    pub variant: String,
+    pub template_id: String,
+}
+
+#[derive(Debug, Default)]
+struct VariantMetrics {
+    assigned: u64,
+    completed: u64,
+    total_latency_ms: u64,
+    total_response_tokens: u64,
+    ratings: u64,
+    rating_sum: u64,
+}
+
+#[derive(Debug)]
+struct Experiment {
+    config: ExperimentConfig,
+    registered_at: i64,
+    metrics: HashMap<String, VariantMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantResults {
+    pub name: String,
+    pub template_id: String,
+    pub weight: u32,
+    pub assigned: u64,
+    pub completed: u64,
+    pub avg_latency_ms: f64,
+    pub avg_response_tokens: f64,
+    pub ratings: u64,
+    /// Mean client rating; absent until the first rating arrives
+    pub avg_rating: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExperimentResults {
+    pub id: String,
+    pub template_id: String,
+    pub tenants: Vec<String>,
+    pub registered_at: i64,
+    pub variants: Vec<VariantResults>,
+}
+
+/// Registered experiments and their per-variant aggregates
+#[derive(Debug)]
+pub struct ExperimentRegistry {
+    config: ExperimentsConfig,
+    experiments: RwLock<HashMap<String, Experiment>>,
+}
+
+impl ExperimentRegistry {
+    pub fn new(config: ExperimentsConfig) -> Self {
+        let registry = Self {
+            config: config.clone(),
+            experiments: RwLock::new(HashMap::new()),
+        };
+        for experiment in config.definitions {
+            if let Err(e) = registry.register(experiment) {
+                log::warn!("Skipping experiment: {}", e);
+            }
+        }
+        registry
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Add an experiment, or replace one with the same ID and reset its results
+    pub fn register(&self, config: ExperimentConfig) -> Result<()> {
+        config.validate()?;
+        let mut experiments = self.experiments.write().unwrap();
+        if !experiments.contains_key(&config.id) {
+            if experiments.len() >= self.config.max_experiments {
+                return Err(Error::Validation(format!(
+                    "At most {} experiments may be registered",
+                    self.config.max_experiments
+                )));
+            }
+            // One experiment per template and tenant, or assignment would be ambiguous
+            if let Some(clash) = experiments.values().find(|e| {
+                e.config.template_id == config.template_id
+                    && (e.config.tenants.is_empty()
+                        || config.tenants.is_empty()
+                        || e.config.tenants.iter().any(|t| config.tenants.contains(t)))
+            }) {
+                return Err(Error::Validation(format!(
+                    "Template {} is already tested by experiment {}",
+                    config.template_id, clash.config.id
+                )));
+            }
+        }
+        let metrics = config
+            .variants
+            .iter()
+            .map(|v| (v.name.clone(), VariantMetrics::default()))
+            .collect();
+        experiments.insert(
+            config.id.clone(),
+            Experiment {
+                config,
+                registered_at: chrono::Utc::now().timestamp(),
+                metrics,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        self.experiments.write().unwrap().remove(id).is_some()
+    }
+
+    /// Pick the variant for a request from `template_id`, if an experiment covers it
+    pub fn assign(
+        &self,
+        tenant: Option<&str>,
+        template_id: &str,
+        user_hash: &str,
+    ) -> Option<Assignment> {
+        if !self.config.enabled || user_hash.is_empty() {
+            return None;
+        }
+        let mut experiments = self.experiments.write().unwrap();
+        let experiment = experiments.values_mut().find(|e| {
+            e.config.template_id == template_id
+                && (e.config.tenants.is_empty()
+                    || tenant.is_some_and(|t| e.config.tenants.iter().any(|x| x == t)))
+        })?;
+
+        let total: u64 = experiment
+            .config
+            .variants
+            .iter()
+            .map(|v| v.weight as u64)
+            .sum();
+        let mut point = bucket(&experiment.config.id, user_hash) % total;
+        let variant = experiment
+            .config
+            .variants
+            .iter()
+            .find(|v| {
+                let hit = point < v.weight as u64;
+                point = point.saturating_sub(v.weight as u64);
+                hit
+            })?
+            .clone();
+        if let Some(metrics) = experiment.metrics.get_mut(&variant.name) {
+            metrics.assigned += 1;
+        }
+        Some(Assignment {
+            experiment: experiment.config.id.clone(),
+            variant: variant.name,
+            template_id: variant.template_id,
+        })
+    }
+
+    /// Record a completed request's latency and response length
+    pub fn record_completion(&self, assignment: &Assignment, latency: Duration, tokens: u64) {
+        self.with_metrics(&assignment.experiment, &assignment.variant, |metrics| {
+            metrics.completed += 1;
+            metrics.total_latency_ms += latency.as_millis() as u64;
+            metrics.total_response_tokens += tokens;
+        });
+    }
+
+    /// Record a client-reported rating of a response served by `variant`,
+    /// returning whether the experiment has that variant
+    pub fn record_rating(&self, experiment: &str, variant: &str, rating: u8) -> Result<bool> {
+        if !RATING_RANGE.contains(&rating) {
+            return Err(Error::Validation(format!(
+                "Rating must be between {} and {}",
+                RATING_RANGE.start(),
+                RATING_RANGE.end()
+            )));
+        }
+        Ok(self.with_metrics(experiment, variant, |metrics| {
+            metrics.ratings += 1;
+            metrics.rating_sum += rating as u64;
+        }))
+    }
+
+    fn with_metrics(
+        &self,
+        experiment: &str,
+        variant: &str,
+        update: impl FnOnce(&mut VariantMetrics),
+    ) -> bool {
+        let mut experiments = self.experiments.write().unwrap();
+        match experiments
+            .get_mut(experiment)
+            .and_then(|e| e.metrics.get_mut(variant))
+        {
+            Some(metrics) => {
+                update(metrics);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn results(&self, id: &str) -> Option<ExperimentResults> {
+        self.experiments.read().unwrap().get(id).map(results_of)
+    }
+
+    pub fn all_results(&self) -> Vec<ExperimentResults> {
+        let mut results: Vec<_> = self
+            .experiments
+            .read()
+            .unwrap()
+            .values()
+            .map(results_of)
+            .collect();
+        results.sort_by(|a, b| a.id.cmp(&b.id));
+        results
+    }
+}
+
+fn results_of(experiment: &Experiment) -> ExperimentResults {
+    let variants = experiment
+        .config
+        .variants
+        .iter()
+        .map(|variant| {
+            let metrics = &experiment.metrics[&variant.name];
+            let completed = metrics.completed.max(1) as f64;
+            VariantResults {
+                name: variant.name.clone(),
+                template_id: variant.template_id.clone(),
+                weight: variant.weight,
+                assigned: metrics.assigned,
+                completed: metrics.completed,
+                avg_latency_ms: metrics.total_latency_ms as f64 / completed,
+                avg_response_tokens: metrics.total_response_tokens as f64 / completed,
+                ratings: metrics.ratings,
+                avg_rating: (metrics.ratings > 0)
+                    .then(|| metrics.rating_sum as f64 / metrics.ratings as f64),
+            }
+        })
+        .collect();
+    ExperimentResults {
+        id: experiment.config.id.clone(),
+        template_id: experiment.config.template_id.clone(),
+        tenants: experiment.config.tenants.clone(),
+        registered_at: experiment.registered_at,
+        variants,
+    }
+}
+
+/// Stable position of a user within an experiment
+fn bucket(experiment: &str, user_hash: &str) -> u64 {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(experiment.as_bytes());
+    context.update(b":");
+    context.update(user_hash.as_bytes());
+    let digest = context.finish();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest.as_ref()[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExperimentVariantConfig;
+
+    fn experiment(id: &str, weights: [u32; 2]) -> ExperimentConfig {
+        ExperimentConfig {
+            id: id.to_string(),
+            template_id: "support-reply".to_string(),
+            variants: ["control", "concise"]
+                .iter()
+                .zip(weights)
+                .map(|(name, weight)| ExperimentVariantConfig {
+                    name: name.to_string(),
+                    template_id: format!("support-reply-{}", name),
+                    weight,
+                })
+                .collect(),
+            tenants: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_experiment_assignment_and_results() {
+        let registry = ExperimentRegistry::new(ExperimentsConfig {
+            enabled: true,
+            definitions: vec![experiment("tone", [3, 1])],
+            ..ExperimentsConfig::default()
+        });
+
+        // Another experiment on the same template would make assignment ambiguous
+        assert!(registry.register(experiment("length", [1, 1])).is_err());
+        assert!(registry.assign(None, "other-template", "u-1").is_none());
+        assert!(registry.assign(None, "support-reply", "").is_none());
+
+        // A user always lands on the same variant; traffic follows the weights
+        let first = registry.assign(None, "support-reply", "u-1").unwrap();
+        assert_eq!(
+            registry.assign(None, "support-reply", "u-1").unwrap(),
+            first
+        );
+        let mut concise = 0;
+        for i in 0..2000 {
+            let assignment = registry
+                .assign(Some("acme"), "support-reply", &format!("user-{}", i))
+                .unwrap();
+            if assignment.variant == "concise" {
+                assert_eq!(assignment.template_id, "support-reply-concise");
+                concise += 1;
+            }
+        }
+        assert!((400..600).contains(&concise), "concise got {}", concise);
+
+        registry.record_completion(&first, Duration::from_millis(120), 40);
+        registry.record_completion(&first, Duration::from_millis(80), 20);
+        assert!(registry.record_rating("tone", &first.variant, 4).unwrap());
+        assert!(registry.record_rating("tone", &first.variant, 9).is_err());
+        assert!(!registry.record_rating("tone", "missing", 3).unwrap());
+
+        let results = registry.results("tone").unwrap();
+        let variant = results
+            .variants
+            .iter()
+            .find(|v| v.name == first.variant)
+            .unwrap();
+        assert_eq!(variant.completed, 2);
+        assert_eq!(variant.avg_latency_ms, 100.0);
+        assert_eq!(variant.avg_response_tokens, 30.0);
+        assert_eq!(variant.avg_rating, Some(4.0));
+        let assigned: u64 = results.variants.iter().map(|v| v.assigned).sum();
+        assert_eq!(assigned, 2002);
+    }
+}
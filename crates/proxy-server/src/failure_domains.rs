@@ -0,0 +1,449 @@
+//! Correlated failure detection across hosts, zones and regions
+//!
+//! Engines and providers are registered with the failure domain they run in
+//! and report the outcome of their health checks and calls. A member with
+//! `member_failure_threshold` failures in the window is failing; when enough
+//! members of one domain fail together the failure is treated as the domain's
+//! rather than each member's, and the whole domain is drained for
+//! `drain_seconds`. Broader domains are checked first, so a degraded region is
+//! drained once instead of zone by zone. Isolated failures are left to the
+//! members' own retries and circuit breakers.
+
+use crate::config::{FailureDomain, FailureDomainsConfig};
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainLevel {
+    Region,
+    Zone,
+    Host,
+}
+
+impl DomainLevel {
+    /// Broadest first, the order domains are checked in
+    pub const ALL: [DomainLevel; 3] = [DomainLevel::Region, DomainLevel::Zone, DomainLevel::Host];
+
+    pub fn parse(level: &str) -> Result<Self> {
+        match level {
+            "region" => Ok(DomainLevel::Region),
+            "zone" => Ok(DomainLevel::Zone),
+            "host" => Ok(DomainLevel::Host),
+            other => Err(Error::Validation(format!(
+                "Unknown failure domain level: {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainLevel::Region => "region",
+            DomainLevel::Zone => "zone",
+            DomainLevel::Host => "host",
+        }
+    }
+
+    /// The member's label at this level, if it has one
+    pub fn label(self, domain: &FailureDomain) -> Option<&str> {
+        let label = match self {
+            DomainLevel::Region => &domain.region,
+            DomainLevel::Zone => &domain.zone,
+            DomainLevel::Host => &domain.host,
+        };
+        (!label.is_empty()).then_some(label.as_str())
+    }
+}
+
+/// A host, zone or region
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct DomainId {
+    pub level: DomainLevel,
+    pub name: String,
+}
+
+impl fmt::Display for DomainId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.level.as_str(), self.name)
+    }
+}
+
+/// A domain drained or restored by an evaluation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DomainTransition {
+    Drained {
+        domain: DomainId,
+        members: Vec<String>,
+        failing: Vec<String>,
+    },
+    Restored {
+        domain: DomainId,
+        members: Vec<String>,
+    },
+}
+
+#[derive(Debug)]
+struct Member {
+    domain: FailureDomain,
+    failures: VecDeque<Instant>,
+}
+
+#[derive(Debug)]
+struct Drain {
+    since: Instant,
+    drained_at: i64,
+    failing: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct DomainState {
+    members: BTreeMap<String, Member>,
+    drained: BTreeMap<DomainId, Drain>,
+}
+
+impl DomainState {
+    fn members_of(&self, domain: &DomainId) -> Vec<String> {
+        self.members
+            .iter()
+            .filter(|(_, m)| domain.level.label(&m.domain) == Some(domain.name.as_str()))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    fn drained_domain(&self, member: &Member) -> Option<&DomainId> {
+        self.drained
+            .keys()
+            .find(|d| d.level.label(&member.domain) == Some(d.name.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberStatus {
+    pub id: String,
+    pub domain: FailureDomain,
+    pub recent_failures: usize,
+    pub failing: bool,
+    /// Domain the member is drained with
+    pub drained_by: Option<DomainId>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainedDomainStatus {
+    #[serde(flatten)]
+    pub domain: DomainId,
+    pub drained_at: i64,
+    pub remaining_seconds: u64,
+    /// Members that were failing when the domain was drained
+    pub failing: Vec<String>,
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureDomainReport {
+    pub enabled: bool,
+    pub members: Vec<MemberStatus>,
+    pub drained: Vec<DrainedDomainStatus>,
+}
+
+#[derive(Debug)]
+pub struct FailureDomains {
+    config: FailureDomainsConfig,
+    state: Mutex<DomainState>,
+}
+
+impl FailureDomains {
+    pub fn new(config: FailureDomainsConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(DomainState::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &FailureDomainsConfig {
+        &self.config
+    }
+
+    /// Track `id` as running in `domain`; outcomes of unknown members are ignored
+    pub fn register(&self, id: &str, domain: FailureDomain) {
+        self.state.lock().unwrap().members.insert(
+            id.to_string(),
+            Member {
+                domain,
+                failures: VecDeque::new(),
+            },
+        );
+    }
+
+    pub fn record_failure(&self, id: &str, at: Instant) {
+        if let Some(member) = self.state.lock().unwrap().members.get_mut(id) {
+            member.failures.push_back(at);
+        }
+    }
+
+    /// A success clears the member's failures; correlation is about members
+    /// failing now, not ones that recovered
+    pub fn record_success(&self, id: &str) {
+        if let Some(member) = self.state.lock().unwrap().members.get_mut(id) {
+            member.failures.clear();
+        }
+    }
+
+    /// Whether `id` is in a drained domain
+    pub fn is_drained(&self, id: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .members
+            .get(id)
+            .is_some_and(|member| state.drained_domain(member).is_some())
+    }
+
+    /// Drain domains whose members fail together and restore those whose
+    /// drain has run its course
+    pub fn evaluate(&self, now: Instant) -> Vec<DomainTransition> {
+        let window = Duration::from_secs(self.config.window_seconds);
+        let drain_for = Duration::from_secs(self.config.drain_seconds);
+        let mut state = self.state.lock().unwrap();
+        let mut transitions = Vec::new();
+
+        for member in state.members.values_mut() {
+            while member
+                .failures
+                .front()
+                .is_some_and(|at| now.saturating_duration_since(*at) >= window)
+            {
+                member.failures.pop_front();
+            }
+        }
+
+        let expired: Vec<DomainId> = state
+            .drained
+            .iter()
+            .filter(|(_, drain)| now.saturating_duration_since(drain.since) >= drain_for)
+            .map(|(domain, _)| domain.clone())
+            .collect();
+        for domain in expired {
+            state.drained.remove(&domain);
+            transitions.push(DomainTransition::Restored {
+                members: state.members_of(&domain),
+                domain,
+            });
+        }
+
+        for level in DomainLevel::ALL {
+            // Members per domain at this level, leaving out those already drained
+            let mut groups: BTreeMap<String, (Vec<String>, Vec<String>)> = BTreeMap::new();
+            for (id, member) in &state.members {
+                let Some(label) = level.label(&member.domain) else {
+                    continue;
+                };
+                if state.drained_domain(member).is_some() {
+                    continue;
+                }
+                let (members, failing) = groups.entry(label.to_string()).or_default();
+                members.push(id.clone());
+                if member.failures.len() >= self.config.member_failure_threshold {
+                    failing.push(id.clone());
+                }
+            }
+
+            for (name, (members, failing)) in groups {
+                let correlated = failing.len() >= self.config.min_failing_members
+                    && failing.len() as f64 / members.len() as f64
+                        >= self.config.correlated_fraction;
+                if !correlated {
+                    continue;
+                }
+                let domain = DomainId { level, name };
+                state.drained.insert(
+                    domain.clone(),
+                    Drain {
+                        since: now,
+                        drained_at: chrono::Utc::now().timestamp(),
+                        failing: failing.clone(),
+                    },
+                );
+                transitions.push(DomainTransition::Drained {
+                    domain,
+                    members,
+                    failing,
+                });
+            }
+        }
+        transitions
+    }
+
+    /// End a drain early; the members' failure history starts over
+    pub fn restore(&self, domain: &DomainId) -> Option<DomainTransition> {
+        let mut state = self.state.lock().unwrap();
+        state.drained.remove(domain)?;
+        let members = state.members_of(domain);
+        for id in &members {
+            if let Some(member) = state.members.get_mut(id) {
+                member.failures.clear();
+            }
+        }
+        Some(DomainTransition::Restored {
+            domain: domain.clone(),
+            members,
+        })
+    }
+
+    /// First of `candidates` (other than `id`) outside every drained domain
+    pub fn healthy_alternative<'a>(
+        &self,
+        id: &str,
+        candidates: impl IntoIterator<Item = &'a str>,
+    ) -> Option<&'a str> {
+        let state = self.state.lock().unwrap();
+        candidates.into_iter().find(|candidate| {
+            *candidate != id
+                && state
+                    .members
+                    .get(*candidate)
+                    .is_none_or(|member| state.drained_domain(member).is_none())
+        })
+    }
+
+    pub fn report(&self, now: Instant) -> FailureDomainReport {
+        let drain_for = Duration::from_secs(self.config.drain_seconds);
+        let state = self.state.lock().unwrap();
+        let members = state
+            .members
+            .iter()
+            .map(|(id, member)| MemberStatus {
+                id: id.clone(),
+                domain: member.domain.clone(),
+                recent_failures: member.failures.len(),
+                failing: member.failures.len() >= self.config.member_failure_threshold,
+                drained_by: state.drained_domain(member).cloned(),
+            })
+            .collect();
+        let drained = state
+            .drained
+            .iter()
+            .map(|(domain, drain)| DrainedDomainStatus {
+                domain: domain.clone(),
+                drained_at: drain.drained_at,
+                remaining_seconds: drain_for
+                    .saturating_sub(now.saturating_duration_since(drain.since))
+                    .as_secs(),
+                failing: drain.failing.clone(),
+                members: state.members_of(domain),
+            })
+            .collect();
+        FailureDomainReport {
+            enabled: self.config.enabled,
+            members,
+            drained,
+        }
+    }
+}
+
+/// Member id of the connection pool engine at `index`
+pub fn engine_member(index: usize) -> String {
+    format!("engine:{}", index)
+}
+
+/// Member id of an LLM provider
+pub fn provider_member(name: &str) -> String {
+    format!("provider:{}", name)
+}
+
+/// Pool indices of the engines among `members`
+pub fn engine_indices<'a>(members: impl IntoIterator<Item = &'a String>) -> Vec<usize> {
+    members
+        .into_iter()
+        .filter_map(|id| id.strip_prefix("engine:")?.parse().ok())
+        .collect()
+}
+
+/// Members grouped by kind, for alert messages
+pub fn describe_members(members: &[String]) -> String {
+    let mut kinds: HashMap<&str, Vec<&str>> = HashMap::new();
+    for id in members {
+        let (kind, name) = id.split_once(':').unwrap_or(("member", id));
+        kinds.entry(kind).or_default().push(name);
+    }
+    let mut parts: Vec<String> = kinds
+        .into_iter()
+        .map(|(kind, names)| format!("{}s {}", kind, names.join(", ")))
+        .collect();
+    parts.sort();
+    parts.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain(host: &str, zone: &str) -> FailureDomain {
+        FailureDomain {
+            host: host.to_string(),
+            zone: zone.to_string(),
+            region: "us-east-1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_correlated_failures_drain_the_shared_domain() {
+        let domains = FailureDomains::new(FailureDomainsConfig {
+            enabled: true,
+            member_failure_threshold: 2,
+            min_failing_members: 2,
+            correlated_fraction: 0.6,
+            window_seconds: 60,
+            drain_seconds: 300,
+            ..FailureDomainsConfig::default()
+        });
+        domains.register(&engine_member(0), domain("gpu-1", "us-east-1a"));
+        domains.register(&engine_member(1), domain("gpu-2", "us-east-1a"));
+        domains.register(&engine_member(2), domain("gpu-3", "us-east-1b"));
+        domains.register(&provider_member("openai"), domain("", "us-east-1a"));
+        let start = Instant::now();
+
+        // One failing engine is its own problem, not its zone's
+        domains.record_failure(&engine_member(0), start);
+        domains.record_failure(&engine_member(0), start);
+        assert!(domains.evaluate(start).is_empty());
+
+        domains.record_failure(&engine_member(1), start);
+        domains.record_failure(&engine_member(1), start);
+        let transitions = domains.evaluate(start);
+        assert_eq!(transitions.len(), 1);
+        let DomainTransition::Drained {
+            domain, members, ..
+        } = &transitions[0]
+        else {
+            panic!("expected a drain");
+        };
+        assert_eq!(domain.to_string(), "zone us-east-1a");
+        assert_eq!(engine_indices(members), vec![0, 1]);
+        assert!(domains.is_drained(&provider_member("openai")));
+        assert!(!domains.is_drained(&engine_member(2)));
+        assert_eq!(
+            domains.healthy_alternative(&engine_member(0), ["engine:1", "engine:2"]),
+            Some("engine:2")
+        );
+        assert_eq!(describe_members(members), "engines 0, 1; providers openai");
+
+        // The drain lapses after drain_seconds, by which time failures have aged out
+        let later = start + Duration::from_secs(301);
+        let transitions = domains.evaluate(later);
+        assert!(matches!(
+            &transitions[..],
+            [DomainTransition::Restored { .. }]
+        ));
+        assert!(!domains.is_drained(&engine_member(0)));
+        assert_eq!(domains.report(later).members[0].recent_failures, 0);
+    }
+}
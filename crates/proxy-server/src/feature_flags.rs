@@ -0,0 +1,348 @@
+//! Runtime feature flags
+//!
+//! Flags are defined under `[flags]` or set through the admin API, which
+//! keeps them in the session store so every instance sees the same toggles.
+//! A stored flag replaces the config definition of the same name; deleting
+//! it falls back to the definition again.
+//!
+//! An evaluation is on when the flag is enabled and the tenant is targeted,
+//! or when the tenant is not excluded and the subject hashes into the rollout
+//! percentage. The subject is the client's user hash when sent, else the
+//! tenant, so it stays on the same side of the flag as the rollout grows.
+//! Every evaluation of a defined flag is counted by outcome so a rollout can
+//! be checked against its target.
+
+use crate::config::{FeatureFlagConfig, FeatureFlagsConfig};
+use crate::persistence::FeatureFlagRecord;
+use ring::digest;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+/// Flags consulted by the proxy handlers
+pub const PROMPT_PACKING: &str = "prompt_packing";
+pub const RESPONSE_CACHE: &str = "response_cache";
+
+/// Rollout resolution: buckets per percentage point
+const BUCKETS_PER_PERCENT: u64 = 100;
+
+/// Why an evaluation came out the way it did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagReason {
+    Disabled,
+    Excluded,
+    Targeted,
+    InRollout,
+    OutOfRollout,
+}
+
+impl FlagReason {
+    pub fn is_on(self) -> bool {
+        matches!(self, FlagReason::Targeted | FlagReason::InRollout)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagSource {
+    Config,
+    Store,
+}
+
+#[derive(Debug)]
+struct Flag {
+    definition: FeatureFlagConfig,
+    source: FlagSource,
+    updated_at: Option<i64>,
+    evaluations: BTreeMap<FlagReason, u64>,
+}
+
+impl Flag {
+    fn new(definition: FeatureFlagConfig, source: FlagSource, updated_at: Option<i64>) -> Self {
+        Self {
+            definition,
+            source,
+            updated_at,
+            evaluations: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagStatus {
+    #[serde(flatten)]
+    pub definition: FeatureFlagConfig,
+    pub source: FlagSource,
+    pub updated_at: Option<i64>,
+    pub evaluations: u64,
+    pub on: u64,
+    /// Share of evaluations that came out on, to compare with `rollout_percent`
+    pub observed_on_percent: Option<f64>,
+    pub by_reason: BTreeMap<FlagReason, u64>,
+}
+
+#[derive(Debug)]
+pub struct FeatureFlags {
+    config: FeatureFlagsConfig,
+    flags: RwLock<BTreeMap<String, Flag>>,
+}
+
+impl FeatureFlags {
+    pub fn new(config: FeatureFlagsConfig) -> Self {
+        let flags = config
+            .definitions
+            .iter()
+            .map(|flag| {
+                (
+                    flag.name.clone(),
+                    Flag::new(flag.clone(), FlagSource::Config, None),
+                )
+            })
+            .collect();
+        Self {
+            config,
+            flags: RwLock::new(flags),
+        }
+    }
+
+    pub fn config(&self) -> &FeatureFlagsConfig {
+        &self.config
+    }
+
+    /// Replace the flags with the config definitions overlaid by `stored`,
+    /// keeping the evaluation counts of flags that still exist
+    pub fn load(&self, stored: Vec<FeatureFlagRecord>) {
+        let mut definitions: BTreeMap<String, (FeatureFlagConfig, FlagSource, Option<i64>)> = self
+            .config
+            .definitions
+            .iter()
+            .map(|flag| (flag.name.clone(), (flag.clone(), FlagSource::Config, None)))
+            .collect();
+        for record in stored {
+            definitions.insert(
+                record.flag.name.clone(),
+                (record.flag, FlagSource::Store, Some(record.updated_at)),
+            );
+        }
+        let mut flags = self.flags.write().unwrap();
+        flags.retain(|name, _| definitions.contains_key(name));
+        for (name, (definition, source, updated_at)) in definitions {
+            let flag = flags
+                .entry(name)
+                .or_insert_with(|| Flag::new(definition.clone(), source, updated_at));
+            flag.definition = definition;
+            flag.source = source;
+            flag.updated_at = updated_at;
+        }
+    }
+
+    /// Evaluate `name` for a request; a flag that is not defined is on
+    pub fn is_on(&self, name: &str, tenant: Option<&str>, user_hash: Option<&str>) -> bool {
+        self.evaluate(name, tenant, user_hash)
+            .is_none_or(FlagReason::is_on)
+    }
+
+    /// Evaluate and count `name`, or None when it is not defined
+    pub fn evaluate(
+        &self,
+        name: &str,
+        tenant: Option<&str>,
+        user_hash: Option<&str>,
+    ) -> Option<FlagReason> {
+        let mut flags = self.flags.write().unwrap();
+        let flag = flags.get_mut(name)?;
+        let reason = reason(&flag.definition, tenant, user_hash);
+        *flag.evaluations.entry(reason).or_default() += 1;
+        Some(reason)
+    }
+
+    /// Every defined flag as evaluated for a caller, without counting
+    pub fn snapshot_for(
+        &self,
+        tenant: Option<&str>,
+        user_hash: Option<&str>,
+    ) -> BTreeMap<String, bool> {
+        self.flags
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, flag)| {
+                (
+                    name.clone(),
+                    reason(&flag.definition, tenant, user_hash).is_on(),
+                )
+            })
+            .collect()
+    }
+
+    /// Apply a flag set through the admin API; the caller persists `record`
+    pub fn set(&self, record: &FeatureFlagRecord) {
+        let mut flags = self.flags.write().unwrap();
+        let flag = flags.entry(record.flag.name.clone()).or_insert_with(|| {
+            Flag::new(
+                record.flag.clone(),
+                FlagSource::Store,
+                Some(record.updated_at),
+            )
+        });
+        flag.definition = record.flag.clone();
+        flag.source = FlagSource::Store;
+        flag.updated_at = Some(record.updated_at);
+    }
+
+    /// Drop a stored flag, falling back to its config definition if any.
+    /// Returns whether the flag still exists.
+    pub fn unset(&self, name: &str) -> bool {
+        let mut flags = self.flags.write().unwrap();
+        match self
+            .config
+            .definitions
+            .iter()
+            .find(|flag| flag.name == name)
+        {
+            Some(definition) => {
+                if let Some(flag) = flags.get_mut(name) {
+                    flag.definition = definition.clone();
+                    flag.source = FlagSource::Config;
+                    flag.updated_at = None;
+                }
+                true
+            }
+            None => {
+                flags.remove(name);
+                false
+            }
+        }
+    }
+
+    pub fn status(&self) -> Vec<FlagStatus> {
+        self.flags
+            .read()
+            .unwrap()
+            .values()
+            .map(|flag| {
+                let evaluations: u64 = flag.evaluations.values().sum();
+                let on: u64 = flag
+                    .evaluations
+                    .iter()
+                    .filter(|(reason, _)| reason.is_on())
+                    .map(|(_, count)| count)
+                    .sum();
+                FlagStatus {
+                    definition: flag.definition.clone(),
+                    source: flag.source,
+                    updated_at: flag.updated_at,
+                    evaluations,
+                    on,
+                    observed_on_percent: (evaluations > 0)
+                        .then(|| on as f64 * 100.0 / evaluations as f64),
+                    by_reason: flag.evaluations.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn reason(flag: &FeatureFlagConfig, tenant: Option<&str>, user_hash: Option<&str>) -> FlagReason {
+    if !flag.enabled {
+        return FlagReason::Disabled;
+    }
+    if let Some(tenant) = tenant {
+        if flag.excluded_tenants.iter().any(|t| t == tenant) {
+            return FlagReason::Excluded;
+        }
+        if flag.tenants.iter().any(|t| t == tenant) {
+            return FlagReason::Targeted;
+        }
+    }
+    let subject = user_hash.or(tenant).unwrap_or_default();
+    let threshold = (flag.rollout_percent * BUCKETS_PER_PERCENT as f64) as u64;
+    if bucket(&flag.name, subject) < threshold {
+        FlagReason::InRollout
+    } else {
+        FlagReason::OutOfRollout
+    }
+}
+
+/// Stable position of a subject within a flag's rollout, 0 to 9999
+fn bucket(flag: &str, subject: &str) -> u64 {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(flag.as_bytes());
+    context.update(b":");
+    context.update(subject.as_bytes());
+    let digest = context.finish();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest.as_ref()[..8]);
+    u64::from_be_bytes(bytes) % (100 * BUCKETS_PER_PERCENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag(name: &str, rollout_percent: f64) -> FeatureFlagConfig {
+        FeatureFlagConfig {
+            name: name.to_string(),
+            description: String::new(),
+            enabled: true,
+            rollout_percent,
+            tenants: vec!["acme".to_string()],
+            excluded_tenants: vec!["initech".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_rollout_targeting_and_store_overrides() {
+        let flags = FeatureFlags::new(FeatureFlagsConfig {
+            definitions: vec![flag(PROMPT_PACKING, 30.0)],
+            ..FeatureFlagsConfig::default()
+        });
+
+        // Unknown flags are on and not counted
+        assert!(flags.is_on("unknown", None, None));
+        assert_eq!(flags.evaluate("unknown", None, None), None);
+
+        assert_eq!(
+            flags.evaluate(PROMPT_PACKING, Some("acme"), Some("u1")),
+            Some(FlagReason::Targeted)
+        );
+        assert_eq!(
+            flags.evaluate(PROMPT_PACKING, Some("initech"), Some("u1")),
+            Some(FlagReason::Excluded)
+        );
+
+        // About 30% of subjects fall in the rollout, each consistently
+        let on = (0..2000)
+            .filter(|i| flags.is_on(PROMPT_PACKING, Some("globex"), Some(&format!("user-{}", i))))
+            .count();
+        assert!((500..700).contains(&on), "{} of 2000 on", on);
+        let sticky = flags.is_on(PROMPT_PACKING, None, Some("user-7"));
+        assert_eq!(flags.is_on(PROMPT_PACKING, None, Some("user-7")), sticky);
+
+        let status = &flags.status()[0];
+        assert_eq!(status.evaluations, 2004);
+        assert_eq!(status.by_reason[&FlagReason::Excluded], 1);
+        assert_eq!(status.source, FlagSource::Config);
+
+        // A stored toggle wins over config until it is unset
+        let mut disabled = flag(PROMPT_PACKING, 30.0);
+        disabled.enabled = false;
+        flags.load(vec![FeatureFlagRecord {
+            flag: disabled,
+            updated_at: 1,
+        }]);
+        assert!(!flags.is_on(PROMPT_PACKING, Some("acme"), None));
+        assert_eq!(flags.status()[0].evaluations, 2005);
+        assert!(flags.unset(PROMPT_PACKING));
+        assert!(flags.is_on(PROMPT_PACKING, Some("acme"), None));
+
+        flags.set(&FeatureFlagRecord {
+            flag: flag(RESPONSE_CACHE, 0.0),
+            updated_at: 2,
+        });
+        assert!(!flags.snapshot_for(Some("globex"), None)[RESPONSE_CACHE]);
+        assert!(!flags.unset(RESPONSE_CACHE));
+        assert_eq!(flags.status().len(), 1);
+    }
+}
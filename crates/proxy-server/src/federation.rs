@@ -0,0 +1,396 @@
+//! Proxy-to-proxy federation for cross-organization encrypted relay
+//!
+//! Requests travel between proxies as nested envelopes: the client's FHE
+//! ciphertext (which no proxy can read) is sealed again with AES-256-GCM under
+//! a key derived from the link's shared secret. The envelope header is bound
+//! to the seal as associated data, so a peer that opens an envelope has also
+//! authenticated its sender, hop list and destination.
+
+use crate::config::{FederationConfig, FederationPeerConfig};
+use crate::error::{Error, Result};
+use crate::fhe::Ciphertext;
+use crate::trace_sampling;
+use base64::prelude::*;
+use reqwest::Client as HttpClient;
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::hkdf;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Header naming the peer that sent a relay request
+pub const PEER_HEADER: &str = "x-federation-peer";
+
+const LINK_KEY_SALT: &[u8] = b"fhe-proxy-federation-v1";
+
+/// Routing information carried in clear and authenticated by the seal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvelopeHeader {
+    pub envelope_id: Uuid,
+    /// Proxy that sealed this envelope (the immediate peer)
+    pub sender: String,
+    /// Proxy where the request entered the federation
+    pub origin: String,
+    /// Proxy that should process the request
+    pub destination: String,
+    /// Proxies the request has passed through, in order
+    pub hops: Vec<String>,
+    pub model: String,
+    pub sent_at: i64,
+}
+
+/// A sealed ciphertext in transit between two proxies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FederationEnvelope {
+    pub header: EnvelopeHeader,
+    pub nonce: String,
+    pub sealed: String,
+}
+
+/// Per-peer relay counters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeerMetrics {
+    pub received: u64,
+    pub forwarded: u64,
+    pub rejected: u64,
+    pub quota_exceeded: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub last_seen: Option<i64>,
+}
+
+/// Seals, opens and relays envelopes for the configured peer links
+#[derive(Debug)]
+pub struct FederationService {
+    config: FederationConfig,
+    link_keys: HashMap<String, LessSafeKey>,
+    client: HttpClient,
+    rng: SystemRandom,
+    metrics: RwLock<HashMap<String, PeerMetrics>>,
+    /// Recently opened envelope IDs and when they were sent, for replay protection
+    seen: RwLock<HashMap<Uuid, i64>>,
+    /// Seconds added to the allowed skew while the host clock is off
+    skew_extension_seconds: AtomicU64,
+}
+
+impl FederationService {
+    pub fn new(config: FederationConfig) -> Result<Self> {
+        let mut link_keys = HashMap::new();
+        for peer in &config.peers {
+            link_keys.insert(peer.id.clone(), Self::derive_link_key(peer)?);
+        }
+
+        Ok(Self {
+            config,
+            link_keys,
+            client: HttpClient::new(),
+            rng: SystemRandom::new(),
+            metrics: RwLock::new(HashMap::new()),
+            seen: RwLock::new(HashMap::new()),
+            skew_extension_seconds: AtomicU64::new(0),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.config.node_id
+    }
+
+    /// Widen the allowed envelope clock skew by `extension`
+    pub fn adjust_for_clock_skew(&self, extension: Duration) {
+        self.skew_extension_seconds
+            .store(extension.as_secs_f64().ceil() as u64, Ordering::Relaxed);
+    }
+
+    fn max_clock_skew_seconds(&self) -> i64 {
+        self.config.max_clock_skew_seconds
+            + self.skew_extension_seconds.load(Ordering::Relaxed) as i64
+    }
+
+    pub fn peer(&self, peer_id: &str) -> Option<&FederationPeerConfig> {
+        self.config.peers.iter().find(|p| p.id == peer_id)
+    }
+
+    /// Seal a ciphertext for `peer_id`.
+    ///
+    /// When relaying a received envelope onward, pass its header as `previous`
+    /// so the origin and hop list are carried forward.
+    pub fn seal(
+        &self,
+        peer_id: &str,
+        destination: &str,
+        model: &str,
+        ciphertext: &Ciphertext,
+        previous: Option<&EnvelopeHeader>,
+    ) -> Result<FederationEnvelope> {
+        let key = self.link_key(peer_id)?;
+
+        let mut hops = previous.map(|h| h.hops.clone()).unwrap_or_default();
+        hops.push(self.config.node_id.clone());
+        let header = EnvelopeHeader {
+            envelope_id: Uuid::new_v4(),
+            sender: self.config.node_id.clone(),
+            origin: previous
+                .map(|h| h.origin.clone())
+                .unwrap_or_else(|| self.config.node_id.clone()),
+            destination: destination.to_string(),
+            hops,
+            model: model.to_string(),
+            sent_at: chrono::Utc::now().timestamp(),
+        };
+
+        let mut nonce_bytes = [0u8; aead::NONCE_LEN];
+        self.rng
+            .fill(&mut nonce_bytes)
+            .map_err(|_| Error::Cryptographic("Failed to generate envelope nonce".to_string()))?;
+
+        let aad = serde_json::to_vec(&header)?;
+        let mut in_out = serde_json::to_vec(ciphertext)?;
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce_bytes),
+            Aad::from(&aad),
+            &mut in_out,
+        )
+        .map_err(|_| Error::Cryptographic("Failed to seal federation envelope".to_string()))?;
+
+        self.update_metrics(peer_id, |m| {
+            m.forwarded += 1;
+            m.bytes_out += in_out.len() as u64;
+        });
+
+        Ok(FederationEnvelope {
+            header,
+            nonce: BASE64_STANDARD.encode(nonce_bytes),
+            sealed: BASE64_STANDARD.encode(in_out),
+        })
+    }
+
+    /// Authenticate and open an envelope received from `peer_id`
+    pub fn open(&self, peer_id: &str, envelope: &FederationEnvelope) -> Result<Ciphertext> {
+        let result = self.open_inner(peer_id, envelope);
+        self.update_metrics(peer_id, |m| match &result {
+            Ok(_) => {
+                m.received += 1;
+                m.bytes_in += envelope.sealed.len() as u64;
+                m.last_seen = Some(chrono::Utc::now().timestamp());
+            }
+            Err(_) => m.rejected += 1,
+        });
+        result
+    }
+
+    fn open_inner(&self, peer_id: &str, envelope: &FederationEnvelope) -> Result<Ciphertext> {
+        let key = self.link_key(peer_id)?;
+        let header = &envelope.header;
+
+        if header.sender != peer_id {
+            return Err(Error::Security(format!(
+                "Envelope sender {} does not match peer {}",
+                header.sender, peer_id
+            )));
+        }
+
+        // Loop prevention: never accept a request that already passed through us
+        if header.hops.iter().any(|hop| hop == &self.config.node_id) {
+            return Err(Error::Security(format!(
+                "Federation loop detected for envelope {}",
+                header.envelope_id
+            )));
+        }
+        if header.hops.len() >= self.config.max_hops {
+            return Err(Error::Security(format!(
+                "Envelope {} exceeded {} hops",
+                header.envelope_id, self.config.max_hops
+            )));
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let max_skew = self.max_clock_skew_seconds();
+        if (now - header.sent_at).abs() > max_skew {
+            return Err(Error::Security(format!(
+                "Envelope {} is outside the allowed clock skew",
+                header.envelope_id
+            )));
+        }
+
+        let nonce_bytes: [u8; aead::NONCE_LEN] = BASE64_STANDARD
+            .decode(&envelope.nonce)
+            .ok()
+            .and_then(|n| n.try_into().ok())
+            .ok_or_else(|| Error::Validation("Invalid envelope nonce".to_string()))?;
+        let mut in_out = BASE64_STANDARD
+            .decode(&envelope.sealed)
+            .map_err(|e| Error::Validation(format!("Invalid envelope payload: {}", e)))?;
+
+        let aad = serde_json::to_vec(header)?;
+        let plaintext = key
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce_bytes),
+                Aad::from(&aad),
+                &mut in_out,
+            )
+            .map_err(|_| {
+                Error::Security(format!(
+                    "Envelope {} failed authentication",
+                    header.envelope_id
+                ))
+            })?;
+        let ciphertext: Ciphertext = serde_json::from_slice(plaintext)?;
+
+        // Only remember envelopes that authenticated, so forgeries cannot poison the cache
+        let mut seen = self.seen.write().unwrap();
+        seen.retain(|_, sent_at| now - *sent_at <= max_skew);
+        if seen.insert(header.envelope_id, header.sent_at).is_some() {
+            return Err(Error::Security(format!(
+                "Envelope {} was replayed",
+                header.envelope_id
+            )));
+        }
+
+        Ok(ciphertext)
+    }
+
+    /// Deliver an envelope to a peer's relay endpoint and return its sealed reply
+    pub async fn send(
+        &self,
+        peer_id: &str,
+        envelope: &FederationEnvelope,
+    ) -> Result<FederationEnvelope> {
+        let peer = self
+            .peer(peer_id)
+            .ok_or_else(|| Error::Validation(format!("Unknown federation peer: {}", peer_id)))?;
+        let url = format!(
+            "{}/v1/federation/relay",
+            peer.endpoint.trim_end_matches('/')
+        );
+
+        log::debug!(
+            "Relaying envelope {} to peer {}",
+            envelope.header.envelope_id,
+            peer_id
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .header(PEER_HEADER, &self.config.node_id)
+            .headers(trace_sampling::propagation_headers())
+            .json(envelope)
+            .timeout(Duration::from_secs(300))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            self.update_metrics(peer_id, |m| m.rejected += 1);
+            return Err(Error::Provider(format!(
+                "Federation peer {} returned {}",
+                peer_id,
+                response.status()
+            )));
+        }
+
+        response.json().await.map_err(Error::from)
+    }
+
+    /// Count a request refused because the peer exceeded its quota
+    pub fn record_quota_exceeded(&self, peer_id: &str) {
+        self.update_metrics(peer_id, |m| m.quota_exceeded += 1);
+    }
+
+    pub fn peer_metrics(&self) -> HashMap<String, PeerMetrics> {
+        self.metrics.read().unwrap().clone()
+    }
+
+    fn link_key(&self, peer_id: &str) -> Result<&LessSafeKey> {
+        self.link_keys
+            .get(peer_id)
+            .ok_or_else(|| Error::Security(format!("Unknown federation peer: {}", peer_id)))
+    }
+
+    fn update_metrics(&self, peer_id: &str, update: impl FnOnce(&mut PeerMetrics)) {
+        let mut metrics = self.metrics.write().unwrap();
+        update(metrics.entry(peer_id.to_string()).or_default());
+    }
+
+    fn derive_link_key(peer: &FederationPeerConfig) -> Result<LessSafeKey> {
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, LINK_KEY_SALT)
+            .extract(peer.shared_secret.as_bytes());
+        let okm = prk
+            .expand(&[b"link-key"], &aead::AES_256_GCM)
+            .map_err(|_| {
+                Error::Cryptographic("Failed to derive federation link key".to_string())
+            })?;
+        Ok(LessSafeKey::new(UnboundKey::from(okm)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe::FheParams;
+
+    fn node(id: &str, peer: &str) -> FederationService {
+        FederationService::new(FederationConfig {
+            enabled: true,
+            node_id: id.to_string(),
+            max_hops: 2,
+            max_clock_skew_seconds: 300,
+            peers: vec![FederationPeerConfig {
+                id: peer.to_string(),
+                endpoint: format!("http://{}.example", peer),
+                shared_secret: "0123456789abcdef0123456789abcdef".to_string(),
+                requests_per_minute: 60,
+            }],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_envelope_round_trip_and_loop_prevention() {
+        let alpha = node("alpha", "beta");
+        let beta = node("beta", "alpha");
+        let ciphertext = Ciphertext {
+            id: Uuid::new_v4(),
+            data: vec![1, 2, 3, 4],
+            params: FheParams::default(),
+            noise_budget: Some(40),
+        };
+
+        let envelope = alpha
+            .seal("beta", "beta", "gpt-4", &ciphertext, None)
+            .unwrap();
+        let opened = beta.open("alpha", &envelope).unwrap();
+        assert_eq!(opened.data, ciphertext.data);
+
+        // The same envelope cannot be delivered twice
+        assert!(beta.open("alpha", &envelope).is_err());
+
+        // Tampering with the clear header breaks authentication
+        let mut tampered = alpha
+            .seal("beta", "beta", "gpt-4", &ciphertext, None)
+            .unwrap();
+        tampered.header.destination = "gamma".to_string();
+        assert!(beta.open("alpha", &tampered).is_err());
+
+        // An envelope that already passed through beta is a loop
+        let looped = EnvelopeHeader {
+            hops: vec!["beta".to_string()],
+            ..envelope.header.clone()
+        };
+        let looped = alpha
+            .seal("beta", "beta", "gpt-4", &ciphertext, Some(&looped))
+            .unwrap();
+        assert!(beta.open("alpha", &looped).is_err());
+
+        let metrics = beta.peer_metrics();
+        assert_eq!(metrics["alpha"].received, 1);
+        assert_eq!(metrics["alpha"].rejected, 3);
+    }
+}
@@ -0,0 +1,518 @@
+//! Fully Homomorphic Encryption operations
+
+use crate::config::{DecryptionDelegationConfig, DelegationFallback};
+use crate::error::{Error, Result};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub use fhe_core::engine::{
+    ClientKey, EncryptionStats, FheEngine, FheEngineBuilder, FheStats, PackedCiphertext,
+    PackingOptimizer, PrecomputedTables, ServerKey, SlotRange, TableBuffer, TableBufferStats,
+    TableSwapEvent,
+};
+pub use fhe_core::{Ciphertext, EngineFingerprint, Error as EngineError, FheParams};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct StubOracle {
+        reachable: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl DecryptionOracle for StubOracle {
+        async fn decrypt(&self, _client_id: Uuid, ciphertext: &Ciphertext) -> Result<String> {
+            if self.reachable.load(Ordering::Relaxed) {
+                Ok(format!("hsm:{}", ciphertext.id))
+            } else {
+                Err(Error::Provider("connection refused".to_string()))
+            }
+        }
+
+        async fn hold_key(&self, client_id: Uuid, _key_data: &[u8]) -> Result<String> {
+            Ok(format!("hsm-{}", client_id))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decryption_delegation_fallback() {
+        let mut engine = FheEngine::new(FheParams::default()).unwrap();
+        let (client_id, _) = engine.generate_keys().unwrap();
+        let ciphertext = engine.encrypt_text(client_id, "secret").unwrap();
+        let engine = tokio::sync::RwLock::new(engine);
+
+        let oracle = Arc::new(StubOracle {
+            reachable: true.into(),
+        });
+        let delegation = DecryptionDelegation::with_oracle(
+            DecryptionDelegationConfig {
+                enabled: true,
+                fallback: DelegationFallback::Local,
+                unreachable_cooldown_seconds: 60,
+                ..DecryptionDelegationConfig::default()
+            },
+            Some(oracle.clone()),
+        );
+        let (plaintext, path) = delegation
+            .decrypt(&engine, client_id, &ciphertext, false)
+            .await
+            .unwrap();
+        assert_eq!(path, DecryptionPath::Delegated);
+        assert_eq!(plaintext, format!("hsm:{}", ciphertext.id));
+
+        // Unreachable: local keys, except for tenants that require delegation
+        oracle.reachable.store(false, Ordering::Relaxed);
+        let (plaintext, path) = delegation
+            .decrypt(&engine, client_id, &ciphertext, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            (plaintext.as_str(), path),
+            ("secret", DecryptionPath::Local)
+        );
+        assert!(matches!(
+            delegation
+                .decrypt(&engine, client_id, &ciphertext, true)
+                .await,
+            Err(Error::Provider(_))
+        ));
+
+        // The HSM is skipped until the cooldown passes
+        oracle.reachable.store(true, Ordering::Relaxed);
+        let (_, path) = delegation
+            .decrypt(&engine, client_id, &ciphertext, false)
+            .await
+            .unwrap();
+        assert_eq!(path, DecryptionPath::Local);
+
+        let stats = delegation.stats();
+        assert_eq!(stats.delegated, 1);
+        assert_eq!(stats.unreachable, 1);
+        assert_eq!(stats.fallbacks, 2);
+        assert!(stats.cooling_down);
+
+        // Fail-closed deployments refuse instead of falling back
+        let fail_closed = DecryptionDelegation::with_oracle(
+            DecryptionDelegationConfig {
+                enabled: true,
+                ..DecryptionDelegationConfig::default()
+            },
+            Some(Arc::new(StubOracle {
+                reachable: false.into(),
+            })),
+        );
+        assert!(fail_closed
+            .decrypt(&engine, client_id, &ciphertext, false)
+            .await
+            .is_err());
+
+        // Without an oracle, tenants that require delegation are refused
+        let local_only =
+            DecryptionDelegation::with_oracle(DecryptionDelegationConfig::default(), None);
+        assert!(matches!(
+            local_only
+                .decrypt(&engine, client_id, &ciphertext, true)
+                .await,
+            Err(Error::KeyPolicy(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_custody_leaves_the_engine_unable_to_decrypt() {
+        let mut engine = FheEngine::new(FheParams::default()).unwrap();
+        let (client_id, _) = engine.generate_keys().unwrap();
+        let local_only =
+            DecryptionDelegation::with_oracle(DecryptionDelegationConfig::default(), None);
+        assert!(matches!(
+            local_only.take_custody(&mut engine, client_id).await,
+            Err(Error::KeyPolicy(_))
+        ));
+        assert!(engine.holds_secret_key(client_id));
+
+        let delegation = DecryptionDelegation::with_oracle(
+            DecryptionDelegationConfig {
+                enabled: true,
+                ..DecryptionDelegationConfig::default()
+            },
+            Some(Arc::new(StubOracle {
+                reachable: true.into(),
+            })),
+        );
+        let handle = delegation
+            .take_custody(&mut engine, client_id)
+            .await
+            .unwrap();
+        assert_eq!(handle, format!("hsm-{}", client_id));
+        assert!(!engine.holds_secret_key(client_id));
+        let ciphertext = engine.encrypt_text(client_id, "secret").unwrap();
+        assert!(engine.decrypt_text(client_id, &ciphertext).is_err());
+        assert!(engine.export_client_key(client_id).is_err());
+    }
+}
+
+/// Decryption with secret keys held outside the proxy, in an HSM or cloud KMS
+#[async_trait::async_trait]
+pub trait DecryptionOracle: Send + Sync + std::fmt::Debug {
+    /// Decrypt `ciphertext` with the secret key of `client_id`
+    ///
+    /// Transport failures surface as `Error::Request`, `Error::Provider` or
+    /// `Error::Timeout` and count as the oracle being unreachable; any other
+    /// error is a refusal and is never retried with in-process keys.
+    async fn decrypt(&self, client_id: Uuid, ciphertext: &Ciphertext) -> Result<String>;
+
+    /// Take custody of the secret key of `client_id`; returns its handle
+    async fn hold_key(&self, client_id: Uuid, key_data: &[u8]) -> Result<String>;
+
+    /// Check that the oracle is reachable without decrypting anything
+    async fn probe(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Oracle reached over HTTP: a cloud KMS endpoint or a gateway in front of a
+/// PKCS#11 module, both speaking the same decrypt call
+#[derive(Debug)]
+pub struct RemoteDecryptionOracle {
+    client: reqwest::Client,
+    endpoint: String,
+    key_handle_template: String,
+    auth_token: Option<String>,
+    timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct OracleDecryptResponse {
+    plaintext: String, // Base64 encoded
+}
+
+impl RemoteDecryptionOracle {
+    pub fn new(config: &DecryptionDelegationConfig) -> Result<Self> {
+        let auth_token = config
+            .auth_token_env
+            .as_ref()
+            .map(|var| {
+                std::env::var(var).map_err(|_| {
+                    Error::Config(format!(
+                        "Decryption delegation token variable {} is not set",
+                        var
+                    ))
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            client: reqwest::Client::new(),
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            key_handle_template: config.key_handle_template.clone(),
+            auth_token,
+            timeout: Duration::from_millis(config.timeout_ms),
+        })
+    }
+
+    fn key_handle(&self, client_id: Uuid) -> String {
+        self.key_handle_template
+            .replace("{client_id}", &client_id.to_string())
+    }
+}
+
+#[async_trait::async_trait]
+impl DecryptionOracle for RemoteDecryptionOracle {
+    async fn decrypt(&self, client_id: Uuid, ciphertext: &Ciphertext) -> Result<String> {
+        let key_handle = self.key_handle(client_id);
+        let mut request = self
+            .client
+            .post(format!("{}/decrypt", self.endpoint))
+            .timeout(self.timeout)
+            .json(&serde_json::json!({
+                "key_handle": key_handle,
+                "ciphertext_id": ciphertext.id,
+                "ciphertext": BASE64_STANDARD.encode(&ciphertext.data),
+                "params_fingerprint": ciphertext.params.fingerprint()
+            }));
+        if let Some(token) = &self.auth_token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(Error::Provider(format!(
+                "Decryption oracle returned {}",
+                status
+            )));
+        }
+        if !status.is_success() {
+            return Err(Error::KeyPolicy(format!(
+                "Decryption oracle refused key {}: {}",
+                key_handle, status
+            )));
+        }
+
+        let body: OracleDecryptResponse = response.json().await?;
+        let plaintext = BASE64_STANDARD
+            .decode(body.plaintext)
+            .map_err(|e| Error::Cryptographic(format!("Invalid oracle plaintext: {}", e)))?;
+        String::from_utf8(plaintext)
+            .map_err(|_| Error::Fhe("Invalid UTF-8 in decrypted data".to_string()))
+    }
+
+    async fn hold_key(&self, client_id: Uuid, key_data: &[u8]) -> Result<String> {
+        let key_handle = self.key_handle(client_id);
+        let mut request = self
+            .client
+            .post(format!("{}/keys", self.endpoint))
+            .timeout(self.timeout)
+            .json(&serde_json::json!({
+                "key_handle": key_handle,
+                "key": BASE64_STANDARD.encode(key_data)
+            }));
+        if let Some(token) = &self.auth_token {
+            request = request.bearer_auth(token);
+        }
+
+        let status = request.send().await?.status();
+        if status.is_server_error() {
+            return Err(Error::Provider(format!(
+                "Decryption oracle returned {}",
+                status
+            )));
+        }
+        if !status.is_success() {
+            return Err(Error::KeyPolicy(format!(
+                "Decryption oracle refused to hold key {}: {}",
+                key_handle, status
+            )));
+        }
+        Ok(key_handle)
+    }
+
+    async fn probe(&self) -> Result<()> {
+        let mut request = self
+            .client
+            .get(format!("{}/health", self.endpoint))
+            .timeout(self.timeout);
+        if let Some(token) = &self.auth_token {
+            request = request.bearer_auth(token);
+        }
+        let status = request.send().await?.status();
+        if !status.is_success() {
+            return Err(Error::Provider(format!(
+                "Decryption oracle health check returned {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Where a decryption ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecryptionPath {
+    Delegated,
+    Local,
+}
+
+#[derive(Debug, Default)]
+struct DelegationCounters {
+    delegated: AtomicU64,
+    unreachable: AtomicU64,
+    refused: AtomicU64,
+    fallbacks: AtomicU64,
+    oracle_calls: AtomicU64,
+    latency_total_us: AtomicU64,
+    latency_max_us: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DelegationStats {
+    pub enabled: bool,
+    pub fallback: DelegationFallback,
+    pub delegated: u64,
+    /// Oracle calls that failed to reach the HSM
+    pub unreachable: u64,
+    pub refused: u64,
+    /// Decryptions done with in-process keys because the HSM was unreachable
+    pub fallbacks: u64,
+    pub average_latency_ms: f64,
+    pub max_latency_ms: f64,
+    /// Whether the HSM is currently skipped after a failure
+    pub cooling_down: bool,
+}
+
+/// Routes decryptions to a `DecryptionOracle`, with latency accounting and
+/// the configured fallback when the HSM is unreachable
+#[derive(Debug)]
+pub struct DecryptionDelegation {
+    config: DecryptionDelegationConfig,
+    oracle: Option<Arc<dyn DecryptionOracle>>,
+    counters: DelegationCounters,
+    unreachable_until: Mutex<Option<Instant>>,
+}
+
+impl DecryptionDelegation {
+    pub fn new(config: DecryptionDelegationConfig) -> Result<Self> {
+        let oracle: Option<Arc<dyn DecryptionOracle>> = if config.enabled {
+            Some(Arc::new(RemoteDecryptionOracle::new(&config)?))
+        } else {
+            None
+        };
+        Ok(Self::with_oracle(config, oracle))
+    }
+
+    pub fn with_oracle(
+        config: DecryptionDelegationConfig,
+        oracle: Option<Arc<dyn DecryptionOracle>>,
+    ) -> Self {
+        Self {
+            config,
+            oracle,
+            counters: DelegationCounters::default(),
+            unreachable_until: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.oracle.is_some()
+    }
+
+    /// The KMS/HSM endpoint decryptions are delegated to
+    pub fn endpoint(&self) -> &str {
+        &self.config.endpoint
+    }
+
+    /// Check the oracle is reachable; None when delegation is off
+    pub async fn probe(&self) -> Option<Result<()>> {
+        Some(self.oracle.as_ref()?.probe().await)
+    }
+
+    /// Decrypt through the oracle when delegation is on, otherwise (or on
+    /// fallback) with the engine's keys. With `required`, in-process keys are
+    /// never used.
+    pub async fn decrypt(
+        &self,
+        engine: &tokio::sync::RwLock<FheEngine>,
+        client_id: Uuid,
+        ciphertext: &Ciphertext,
+        required: bool,
+    ) -> Result<(String, DecryptionPath)> {
+        let Some(oracle) = &self.oracle else {
+            if required {
+                return Err(Error::KeyPolicy(
+                    "Tenant requires delegated decryption but none is configured".to_string(),
+                ));
+            }
+            let plaintext = engine.read().await.decrypt_text(client_id, ciphertext)?;
+            return Ok((plaintext, DecryptionPath::Local));
+        };
+
+        let cooling_down = self
+            .unreachable_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| Instant::now() < until);
+        let reason = if cooling_down {
+            "marked unreachable".to_string()
+        } else {
+            let started = Instant::now();
+            let result = oracle.decrypt(client_id, ciphertext).await;
+            self.record_latency(started.elapsed());
+            match result {
+                Ok(plaintext) => {
+                    self.counters.delegated.fetch_add(1, Ordering::Relaxed);
+                    *self.unreachable_until.lock().unwrap() = None;
+                    return Ok((plaintext, DecryptionPath::Delegated));
+                }
+                Err(e @ (Error::Request(_) | Error::Provider(_) | Error::Timeout(_))) => {
+                    self.counters.unreachable.fetch_add(1, Ordering::Relaxed);
+                    *self.unreachable_until.lock().unwrap() = Some(
+                        Instant::now()
+                            + Duration::from_secs(self.config.unreachable_cooldown_seconds),
+                    );
+                    e.to_string()
+                }
+                Err(e) => {
+                    self.counters.refused.fetch_add(1, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        };
+
+        if required || self.config.fallback == DelegationFallback::FailClosed {
+            return Err(Error::Provider(format!(
+                "Decryption HSM unavailable: {}",
+                reason
+            )));
+        }
+        log::warn!(
+            "Decrypting ciphertext {} with in-process keys, HSM unavailable: {}",
+            ciphertext.id,
+            reason
+        );
+        self.counters.fallbacks.fetch_add(1, Ordering::Relaxed);
+        let plaintext = engine.read().await.decrypt_text(client_id, ciphertext)?;
+        Ok((plaintext, DecryptionPath::Local))
+    }
+
+    /// Move a client key's secret material into the HSM, leaving the engine
+    /// able to encrypt under the key but not to decrypt; returns its handle
+    pub async fn take_custody(&self, engine: &mut FheEngine, client_id: Uuid) -> Result<String> {
+        let Some(oracle) = &self.oracle else {
+            return Err(Error::KeyPolicy(
+                "Tenant requires delegated decryption but none is configured".to_string(),
+            ));
+        };
+        let key_data = engine.export_client_key(client_id)?;
+        let key_handle = oracle
+            .hold_key(client_id, &key_data)
+            .await
+            .map_err(|e| match e {
+                Error::Request(_) | Error::Timeout(_) => {
+                    Error::Provider(format!("Decryption HSM unavailable: {}", e))
+                }
+                e => e,
+            })?;
+        engine.take_secret_key(client_id)?;
+        Ok(key_handle)
+    }
+
+    fn record_latency(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        self.counters.oracle_calls.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .latency_total_us
+            .fetch_add(micros, Ordering::Relaxed);
+        self.counters
+            .latency_max_us
+            .fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> DelegationStats {
+        let calls = self.counters.oracle_calls.load(Ordering::Relaxed);
+        let total_us = self.counters.latency_total_us.load(Ordering::Relaxed);
+        DelegationStats {
+            enabled: self.is_enabled(),
+            fallback: self.config.fallback,
+            delegated: self.counters.delegated.load(Ordering::Relaxed),
+            unreachable: self.counters.unreachable.load(Ordering::Relaxed),
+            refused: self.counters.refused.load(Ordering::Relaxed),
+            fallbacks: self.counters.fallbacks.load(Ordering::Relaxed),
+            average_latency_ms: if calls == 0 {
+                0.0
+            } else {
+                total_us as f64 / calls as f64 / 1000.0
+            },
+            max_latency_ms: self.counters.latency_max_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            cooling_down: self
+                .unreachable_until
+                .lock()
+                .unwrap()
+                .is_some_and(|until| Instant::now() < until),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Structured `arbitrary` inputs for fuzzing protocol types
+//!
+//! Purely random bytes rarely survive the first length or version check, so
+//! these implementations generate mostly well-formed values (valid wire
+//! headers, power-of-two polynomial degrees, decodable envelope nonces) and
+//! fall back to raw input a fraction of the time. The cargo-fuzz targets in
+//! `fuzz/` build on them. Parameter sets, ciphertexts and the wire format are
+//! covered by `fhe_core::fuzzing`, next to the types themselves.
+
+use crate::federation::{EnvelopeHeader, FederationEnvelope};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use base64::prelude::*;
+use uuid::Uuid;
+
+pub use fhe_core::fuzzing::WireFormat;
+
+impl<'a> Arbitrary<'a> for EnvelopeHeader {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            envelope_id: Uuid::from_u128(u128::arbitrary(u)?),
+            sender: String::arbitrary(u)?,
+            origin: String::arbitrary(u)?,
+            destination: String::arbitrary(u)?,
+            hops: Vec::arbitrary(u)?,
+            model: String::arbitrary(u)?,
+            sent_at: if u.ratio(3, 4)? {
+                chrono::Utc::now().timestamp()
+            } else {
+                i64::arbitrary(u)?
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for FederationEnvelope {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let header = EnvelopeHeader::arbitrary(u)?;
+        let nonce = if u.ratio(7, 8)? {
+            BASE64_STANDARD.encode(<[u8; 12]>::arbitrary(u)?)
+        } else {
+            String::arbitrary(u)?
+        };
+        let sealed: Vec<u8> = Vec::arbitrary(u)?;
+        Ok(Self {
+            header,
+            nonce,
+            sealed: BASE64_STANDARD.encode(sealed),
+        })
+    }
+}
@@ -0,0 +1,155 @@
+//! Homomorphic LLM Proxy server
+//!
+//! The HTTP gateway, FHE engine and operational subsystems. The types shared
+//! with clients live in `fhe-core` and are re-exported from `fhe`. Embedders
+//! should use the [`api`] module, which is covered by semantic versioning;
+//! the other modules are internal and may change in any release.
+
+#[doc(hidden)]
+pub mod access;
+#[doc(hidden)]
+pub mod aggregation;
+#[doc(hidden)]
+pub mod allocator;
+pub mod api;
+#[doc(hidden)]
+pub mod api_versions;
+#[doc(hidden)]
+pub mod approvals;
+#[doc(hidden)]
+pub mod backfill;
+#[doc(hidden)]
+pub mod billing;
+#[doc(hidden)]
+pub mod clock;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod config_drift;
+#[doc(hidden)]
+pub mod connection_guard;
+#[doc(hidden)]
+pub mod conversation;
+#[doc(hidden)]
+pub mod dead_letter;
+#[doc(hidden)]
+pub mod deadline_budget;
+#[doc(hidden)]
+pub mod dependencies;
+// pub mod deployment; // Temporarily disabled due to compilation issues
+#[doc(hidden)]
+pub mod drills;
+#[doc(hidden)]
+pub mod error;
+#[doc(hidden)]
+pub mod escrow;
+#[doc(hidden)]
+pub mod etag;
+#[doc(hidden)]
+pub mod execution;
+#[doc(hidden)]
+pub mod experiments;
+#[doc(hidden)]
+pub mod failure_domains;
+#[doc(hidden)]
+pub mod feature_flags;
+#[doc(hidden)]
+pub mod federation;
+#[doc(hidden)]
+pub mod fhe;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+// pub mod global_scaling; // Temporarily disabled due to compilation issues
+#[doc(hidden)]
+pub mod health;
+#[doc(hidden)]
+pub mod i18n;
+#[doc(hidden)]
+pub mod limit_rules;
+#[cfg(feature = "loadgen")]
+#[doc(hidden)]
+pub mod loadgen;
+#[doc(hidden)]
+pub mod metrics_catalog;
+#[doc(hidden)]
+pub mod middleware;
+#[doc(hidden)]
+pub mod migrations;
+#[doc(hidden)]
+pub mod mirror;
+#[doc(hidden)]
+pub mod monitoring;
+#[doc(hidden)]
+pub mod noise_trends;
+#[doc(hidden)]
+pub mod offboarding;
+#[doc(hidden)]
+pub mod overflow;
+// pub mod observability; // Temporarily disabled due to compilation issues
+#[doc(hidden)]
+pub mod performance;
+#[doc(hidden)]
+pub mod performance_optimized;
+#[doc(hidden)]
+pub mod performance_stats;
+#[doc(hidden)]
+pub mod persistence;
+#[doc(hidden)]
+pub mod probes;
+#[doc(hidden)]
+pub mod prompt_lint;
+#[doc(hidden)]
+pub mod provider_errors;
+#[doc(hidden)]
+pub mod provider_quota;
+#[doc(hidden)]
+pub mod proxy;
+#[doc(hidden)]
+pub mod quality;
+#[doc(hidden)]
+pub mod redaction;
+#[doc(hidden)]
+pub mod renewal;
+#[doc(hidden)]
+pub mod request_journal;
+#[doc(hidden)]
+pub mod response_metadata;
+// pub mod resilience; // Temporarily disabled due to compilation issues
+#[doc(hidden)]
+pub mod sandbox;
+#[doc(hidden)]
+pub mod scaling;
+#[doc(hidden)]
+pub mod security;
+#[doc(hidden)]
+pub mod security_enhanced;
+#[doc(hidden)]
+pub mod siem;
+#[doc(hidden)]
+pub mod snapshot;
+#[doc(hidden)]
+pub mod standby;
+#[doc(hidden)]
+pub mod streaming;
+#[doc(hidden)]
+pub mod strict_mode;
+#[doc(hidden)]
+pub mod supervisor;
+#[doc(hidden)]
+pub mod systemd;
+#[doc(hidden)]
+pub mod telemetry;
+#[doc(hidden)]
+pub mod trace_sampling;
+#[doc(hidden)]
+pub mod transactions;
+#[doc(hidden)]
+pub mod validation;
+#[doc(hidden)]
+pub mod warm_hints;
+#[doc(hidden)]
+pub mod workload_tags;
+
+pub use config::Config;
+pub use error::{Error, Result};
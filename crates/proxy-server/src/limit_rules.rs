@@ -0,0 +1,860 @@
+//! Scripted per-tenant rate limit rules
+//!
+//! A rule pairs a `when` condition with a `limit` expression. The first rule
+//! whose condition holds sets the request's per-minute limit in place of the
+//! tenant's static one, e.g. letting bronze tenants burst at weekends:
+//!
+//! ```toml
+//! [[tenants.rate_limit_rules.rules]]
+//! name = "weekend_burst"
+//! when = 'sla_class == "bronze" && weekday >= 6'
+//! limit = "base_limit * 2"
+//! ```
+//!
+//! Expressions read request facts (`tenant`, `sla_class`, `priority`, `path`,
+//! `method`, `hour`, `minute`, `weekday` with Monday as 1, `base_limit`,
+//! `in_flight`), combine them with arithmetic, comparison, `&&`, `||`, `!`
+//! and `cond ? a : b`, and may call `min`, `max` and `starts_with`. They are
+//! parsed and type-checked when the configuration loads, cannot loop, and are
+//! evaluated under a step and wall-clock budget; a rule that fails at runtime
+//! is skipped and counted.
+
+use crate::config::{RateLimitRule, RateLimitRulesConfig, SlaClass};
+use crate::error::{Error, Result};
+use crate::scaling::RequestPriority;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Response header naming the rule that set the limit a request exceeded
+pub const RATE_LIMIT_RULE_HEADER: &str = "x-rate-limit-rule";
+
+/// Deepest nesting of parentheses and unary operators accepted
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Number,
+    Str,
+    Bool,
+}
+
+impl Type {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Type::Number => "number",
+            Type::Str => "string",
+            Type::Bool => "boolean",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+}
+
+/// Request facts a rule can read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    Tenant,
+    SlaClass,
+    Priority,
+    Path,
+    Method,
+    Hour,
+    Minute,
+    Weekday,
+    BaseLimit,
+    InFlight,
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "tenant" => Variable::Tenant,
+            "sla_class" => Variable::SlaClass,
+            "priority" => Variable::Priority,
+            "path" => Variable::Path,
+            "method" => Variable::Method,
+            "hour" => Variable::Hour,
+            "minute" => Variable::Minute,
+            "weekday" => Variable::Weekday,
+            "base_limit" => Variable::BaseLimit,
+            "in_flight" => Variable::InFlight,
+            _ => return None,
+        })
+    }
+
+    fn ty(&self) -> Type {
+        match self {
+            Variable::Tenant
+            | Variable::SlaClass
+            | Variable::Priority
+            | Variable::Path
+            | Variable::Method => Type::Str,
+            _ => Type::Number,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Min,
+    Max,
+    StartsWith,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Var(Variable),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 19] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", ",", "?",
+    ":",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| syntax(format!("Invalid number {:?}", &rest[..end])))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| syntax("Unterminated string".to_string()))?;
+            tokens.push(Token::Str(rest[1..=end].to_string()));
+            rest = &rest[end + 2..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| syntax(format!("Unexpected character {:?}", c)))?;
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        }
+    }
+    Ok(tokens)
+}
+
+fn syntax(message: String) -> Error {
+    Error::Config(message)
+}
+
+/// Recursive-descent parser; precedence from loosest to tightest is
+/// `?:`, `||`, `&&`, comparison, `+ -`, `* / %`, unary `! -`
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    nodes: usize,
+    max_nodes: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn parse(source: &str, max_nodes: usize) -> Result<Expr> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            nodes: 0,
+            max_nodes,
+            depth: 0,
+        };
+        let expr = parser.conditional()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(syntax(format!("Unexpected {:?}", token))),
+        }
+    }
+
+    fn node(&mut self, expr: Expr) -> Result<Expr> {
+        self.nodes += 1;
+        if self.nodes > self.max_nodes {
+            return Err(syntax(format!(
+                "Expression has more than {} nodes",
+                self.max_nodes
+            )));
+        }
+        Ok(expr)
+    }
+
+    fn eat(&mut self, op: &'static str) -> bool {
+        if self.tokens.get(self.pos) == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &'static str) -> Result<()> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(syntax(format!("Expected {:?}", op)))
+        }
+    }
+
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(syntax(format!(
+                "Expression nests deeper than {}",
+                MAX_DEPTH
+            )));
+        }
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn conditional(&mut self) -> Result<Expr> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.nested(Self::conditional)?;
+        self.expect(":")?;
+        let otherwise = self.nested(Self::conditional)?;
+        self.node(Expr::Cond(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: [&[(&str, BinaryOp)]; 5] = [
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[
+                ("==", BinaryOp::Eq),
+                ("!=", BinaryOp::Ne),
+                ("<=", BinaryOp::Le),
+                (">=", BinaryOp::Ge),
+                ("<", BinaryOp::Lt),
+                (">", BinaryOp::Gt),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[
+                ("*", BinaryOp::Mul),
+                ("/", BinaryOp::Div),
+                ("%", BinaryOp::Rem),
+            ],
+        ];
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(&(_, op)) = ops.iter().find(|(symbol, _)| self.eat(symbol)) {
+            let right = self.binary(level + 1)?;
+            left = self.node(Expr::Binary(op, Box::new(left), Box::new(right)))?;
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("!") {
+            let operand = self.nested(Self::unary)?;
+            return self.node(Expr::Not(Box::new(operand)));
+        }
+        if self.eat("-") {
+            let operand = self.nested(Self::unary)?;
+            return self.node(Expr::Neg(Box::new(operand)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| syntax("Unexpected end of expression".to_string()))?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => self.node(Expr::Literal(Value::Number(n))),
+            Token::Str(s) => self.node(Expr::Literal(Value::Str(s))),
+            Token::Op("(") => {
+                let expr = self.nested(Self::conditional)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Ident(name) if name == "true" || name == "false" => {
+                self.node(Expr::Literal(Value::Bool(name == "true")))
+            }
+            Token::Ident(name) if self.eat("(") => {
+                let function = match name.as_str() {
+                    "min" => Function::Min,
+                    "max" => Function::Max,
+                    "starts_with" => Function::StartsWith,
+                    _ => return Err(syntax(format!("Unknown function {}", name))),
+                };
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.nested(Self::conditional)?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                self.node(Expr::Call(function, args))
+            }
+            Token::Ident(name) => match Variable::parse(&name) {
+                Some(variable) => self.node(Expr::Var(variable)),
+                None => Err(syntax(format!("Unknown variable {}", name))),
+            },
+            Token::Op(op) => Err(syntax(format!("Unexpected {:?}", op))),
+        }
+    }
+}
+
+fn check(expr: &Expr) -> Result<Type> {
+    let mismatch = |what: &str, ty: Type| {
+        Err(syntax(format!(
+            "{} cannot be applied to a {}",
+            what,
+            ty.as_str()
+        )))
+    };
+    Ok(match expr {
+        Expr::Literal(Value::Number(_)) => Type::Number,
+        Expr::Literal(Value::Str(_)) => Type::Str,
+        Expr::Literal(Value::Bool(_)) => Type::Bool,
+        Expr::Var(variable) => variable.ty(),
+        Expr::Not(operand) => match check(operand)? {
+            Type::Bool => Type::Bool,
+            ty => return mismatch("!", ty),
+        },
+        Expr::Neg(operand) => match check(operand)? {
+            Type::Number => Type::Number,
+            ty => return mismatch("-", ty),
+        },
+        Expr::Binary(op, left, right) => {
+            let (left, right) = (check(left)?, check(right)?);
+            let (operands, result) = match op {
+                BinaryOp::Or | BinaryOp::And => (Type::Bool, Type::Bool),
+                BinaryOp::Eq | BinaryOp::Ne => {
+                    if left != right {
+                        return Err(syntax(format!(
+                            "Cannot compare a {} with a {}",
+                            left.as_str(),
+                            right.as_str()
+                        )));
+                    }
+                    (left, Type::Bool)
+                }
+                BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                    (Type::Number, Type::Bool)
+                }
+                _ => (Type::Number, Type::Number),
+            };
+            for ty in [left, right] {
+                if ty != operands {
+                    return mismatch(&format!("{:?}", op), ty);
+                }
+            }
+            result
+        }
+        Expr::Call(function, args) => {
+            let (params, result) = match function {
+                Function::Min | Function::Max => (Type::Number, Type::Number),
+                Function::StartsWith => (Type::Str, Type::Bool),
+            };
+            if args.len() != 2 {
+                return Err(syntax(format!(
+                    "{:?} takes 2 arguments, got {}",
+                    function,
+                    args.len()
+                )));
+            }
+            for arg in args {
+                let ty = check(arg)?;
+                if ty != params {
+                    return mismatch(&format!("{:?}", function), ty);
+                }
+            }
+            result
+        }
+        Expr::Cond(condition, then, otherwise) => {
+            if check(condition)? != Type::Bool {
+                return Err(syntax("Condition of ?: must be a boolean".to_string()));
+            }
+            let (then, otherwise) = (check(then)?, check(otherwise)?);
+            if then != otherwise {
+                return Err(syntax(format!(
+                    "Branches of ?: differ: {} and {}",
+                    then.as_str(),
+                    otherwise.as_str()
+                )));
+            }
+            then
+        }
+    })
+}
+
+/// Request facts a rule is evaluated against
+#[derive(Debug, Clone)]
+pub struct RuleContext<'a> {
+    pub tenant: Option<&'a str>,
+    pub sla_class: SlaClass,
+    pub priority: RequestPriority,
+    pub path: &'a str,
+    pub method: &'a str,
+    pub now: DateTime<Utc>,
+    /// The limit that applies when no rule matches
+    pub base_limit: u64,
+    pub in_flight: usize,
+}
+
+impl RuleContext<'_> {
+    fn get(&self, variable: Variable) -> Value {
+        match variable {
+            Variable::Tenant => Value::Str(self.tenant.unwrap_or_default().to_string()),
+            Variable::SlaClass => Value::Str(self.sla_class.as_str().to_string()),
+            Variable::Priority => Value::Str(self.priority.as_str().to_string()),
+            Variable::Path => Value::Str(self.path.to_string()),
+            Variable::Method => Value::Str(self.method.to_string()),
+            Variable::Hour => Value::Number(self.now.hour() as f64),
+            Variable::Minute => Value::Number(self.now.minute() as f64),
+            Variable::Weekday => Value::Number(self.now.weekday().number_from_monday() as f64),
+            Variable::BaseLimit => Value::Number(self.base_limit as f64),
+            Variable::InFlight => Value::Number(self.in_flight as f64),
+        }
+    }
+}
+
+/// Steps and time one rule may still spend on a request
+struct Budget {
+    steps: u64,
+    deadline: Instant,
+}
+
+impl Budget {
+    fn step(&mut self) -> Result<()> {
+        if self.steps == 0 {
+            return Err(Error::Validation(
+                "Rule exceeded its step budget".to_string(),
+            ));
+        }
+        self.steps -= 1;
+        if Instant::now() > self.deadline {
+            return Err(Error::Validation(
+                "Rule exceeded its time budget".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn eval(expr: &Expr, ctx: &RuleContext<'_>, budget: &mut Budget) -> Result<Value> {
+    budget.step()?;
+    let number = |value: Value| match value {
+        Value::Number(n) => Ok(n),
+        other => Err(Error::Validation(format!(
+            "Expected a number, got {:?}",
+            other
+        ))),
+    };
+    let boolean = |value: Value| match value {
+        Value::Bool(b) => Ok(b),
+        other => Err(Error::Validation(format!(
+            "Expected a boolean, got {:?}",
+            other
+        ))),
+    };
+    Ok(match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Var(variable) => ctx.get(*variable),
+        Expr::Not(operand) => Value::Bool(!boolean(eval(operand, ctx, budget)?)?),
+        Expr::Neg(operand) => Value::Number(-number(eval(operand, ctx, budget)?)?),
+        Expr::Binary(BinaryOp::Or, left, right) => {
+            Value::Bool(boolean(eval(left, ctx, budget)?)? || boolean(eval(right, ctx, budget)?)?)
+        }
+        Expr::Binary(BinaryOp::And, left, right) => {
+            Value::Bool(boolean(eval(left, ctx, budget)?)? && boolean(eval(right, ctx, budget)?)?)
+        }
+        Expr::Binary(BinaryOp::Eq, left, right) => {
+            Value::Bool(eval(left, ctx, budget)? == eval(right, ctx, budget)?)
+        }
+        Expr::Binary(BinaryOp::Ne, left, right) => {
+            Value::Bool(eval(left, ctx, budget)? != eval(right, ctx, budget)?)
+        }
+        Expr::Binary(op, left, right) => {
+            let (a, b) = (
+                number(eval(left, ctx, budget)?)?,
+                number(eval(right, ctx, budget)?)?,
+            );
+            match op {
+                BinaryOp::Lt => Value::Bool(a < b),
+                BinaryOp::Le => Value::Bool(a <= b),
+                BinaryOp::Gt => Value::Bool(a > b),
+                BinaryOp::Ge => Value::Bool(a >= b),
+                BinaryOp::Add => Value::Number(a + b),
+                BinaryOp::Sub => Value::Number(a - b),
+                BinaryOp::Mul => Value::Number(a * b),
+                BinaryOp::Div | BinaryOp::Rem if b == 0.0 => {
+                    return Err(Error::Validation("Division by zero".to_string()))
+                }
+                BinaryOp::Div => Value::Number(a / b),
+                BinaryOp::Rem => Value::Number(a % b),
+                _ => unreachable!("logical and equality operators are handled above"),
+            }
+        }
+        Expr::Call(function, args) => {
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                values.push(eval(arg, ctx, budget)?);
+            }
+            match (function, values.as_slice()) {
+                (Function::Min, [Value::Number(a), Value::Number(b)]) => Value::Number(a.min(*b)),
+                (Function::Max, [Value::Number(a), Value::Number(b)]) => Value::Number(a.max(*b)),
+                (Function::StartsWith, [Value::Str(s), Value::Str(prefix)]) => {
+                    Value::Bool(s.starts_with(prefix.as_str()))
+                }
+                _ => {
+                    return Err(Error::Validation(format!(
+                        "Bad arguments to {:?}",
+                        function
+                    )))
+                }
+            }
+        }
+        Expr::Cond(condition, then, otherwise) => {
+            if boolean(eval(condition, ctx, budget)?)? {
+                eval(then, ctx, budget)?
+            } else {
+                eval(otherwise, ctx, budget)?
+            }
+        }
+    })
+}
+
+#[derive(Debug, Default)]
+struct RuleCounters {
+    matched: AtomicU64,
+    rejected: AtomicU64,
+    errors: AtomicU64,
+    eval_micros: AtomicU64,
+    evaluations: AtomicU64,
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    rule: RateLimitRule,
+    when: Expr,
+    limit: Expr,
+    counters: RuleCounters,
+}
+
+/// The rule that set a request's limit
+#[derive(Debug, Clone)]
+pub struct RuleDecision {
+    index: usize,
+    pub rule: String,
+    pub limit: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitRuleStats {
+    pub name: String,
+    pub tenants: Vec<String>,
+    /// Requests whose limit the rule set
+    pub matched: u64,
+    /// Of those, requests rejected for exceeding it
+    pub rejected: u64,
+    /// Evaluations that failed or ran over budget
+    pub errors: u64,
+    pub avg_eval_micros: f64,
+}
+
+#[derive(Debug)]
+pub struct RateLimitRules {
+    config: RateLimitRulesConfig,
+    rules: Vec<CompiledRule>,
+}
+
+impl RateLimitRules {
+    pub fn new(config: RateLimitRulesConfig) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let (when, limit) = Self::compile(rule, &config)?;
+                Ok(CompiledRule {
+                    rule: rule.clone(),
+                    when,
+                    limit,
+                    counters: RuleCounters::default(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { config, rules })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled && !self.rules.is_empty()
+    }
+
+    /// Parse and type-check a rule within the configured size limits
+    pub fn validate_rule(rule: &RateLimitRule, config: &RateLimitRulesConfig) -> Result<()> {
+        Self::compile(rule, config).map(|_| ())
+    }
+
+    fn compile(rule: &RateLimitRule, config: &RateLimitRulesConfig) -> Result<(Expr, Expr)> {
+        let parse = |field: &str, source: &str, expected: Type| {
+            if source.len() > config.max_expression_bytes {
+                return Err(Error::Config(format!(
+                    "{} is longer than {} bytes",
+                    field, config.max_expression_bytes
+                )));
+            }
+            let expr = Parser::parse(source, config.max_nodes)
+                .map_err(|e| Error::Config(format!("{}: {}", field, e)))?;
+            let ty = check(&expr).map_err(|e| Error::Config(format!("{}: {}", field, e)))?;
+            if ty != expected {
+                return Err(Error::Config(format!(
+                    "{} must be a {}, not a {}",
+                    field,
+                    expected.as_str(),
+                    ty.as_str()
+                )));
+            }
+            Ok(expr)
+        };
+        Ok((
+            parse("when", &rule.when, Type::Bool)?,
+            parse("limit", &rule.limit, Type::Number)?,
+        ))
+    }
+
+    /// Limit set by the first rule that applies to the request, if any
+    pub fn evaluate(&self, ctx: &RuleContext<'_>) -> Option<RuleDecision> {
+        if !self.is_enabled() {
+            return None;
+        }
+        for (index, compiled) in self.rules.iter().enumerate() {
+            let tenants = &compiled.rule.tenants;
+            if !tenants.is_empty() && !ctx.tenant.is_some_and(|t| tenants.iter().any(|r| r == t)) {
+                continue;
+            }
+
+            let started = Instant::now();
+            let mut budget = Budget {
+                steps: self.config.max_steps,
+                deadline: started + Duration::from_micros(self.config.max_eval_micros),
+            };
+            let outcome = eval(&compiled.when, ctx, &mut budget).and_then(|matched| {
+                if matched != Value::Bool(true) {
+                    return Ok(None);
+                }
+                match eval(&compiled.limit, ctx, &mut budget)? {
+                    Value::Number(n) if n.is_finite() && n >= 0.0 => Ok(Some(n as u64)),
+                    other => Err(Error::Validation(format!("Invalid limit {:?}", other))),
+                }
+            });
+
+            let counters = &compiled.counters;
+            counters.evaluations.fetch_add(1, Ordering::Relaxed);
+            counters
+                .eval_micros
+                .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+            match outcome {
+                Ok(Some(limit)) => {
+                    counters.matched.fetch_add(1, Ordering::Relaxed);
+                    return Some(RuleDecision {
+                        index,
+                        rule: compiled.rule.name.clone(),
+                        limit,
+                    });
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    counters.errors.fetch_add(1, Ordering::Relaxed);
+                    log::warn!("Rate limit rule {} skipped: {}", compiled.rule.name, e);
+                }
+            }
+        }
+        None
+    }
+
+    /// Count a request rejected under the limit `decision` set
+    pub fn record_rejection(&self, decision: &RuleDecision) {
+        if let Some(compiled) = self.rules.get(decision.index) {
+            compiled.counters.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> Vec<RateLimitRuleStats> {
+        self.rules
+            .iter()
+            .map(|compiled| {
+                let counters = &compiled.counters;
+                let evaluations = counters.evaluations.load(Ordering::Relaxed);
+                RateLimitRuleStats {
+                    name: compiled.rule.name.clone(),
+                    tenants: compiled.rule.tenants.clone(),
+                    matched: counters.matched.load(Ordering::Relaxed),
+                    rejected: counters.rejected.load(Ordering::Relaxed),
+                    errors: counters.errors.load(Ordering::Relaxed),
+                    avg_eval_micros: if evaluations > 0 {
+                        counters.eval_micros.load(Ordering::Relaxed) as f64 / evaluations as f64
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rules_set_limits_and_are_checked_at_load() {
+        let rule = |name: &str, tenants: &[&str], when: &str, limit: &str| RateLimitRule {
+            name: name.to_string(),
+            tenants: tenants.iter().map(|t| t.to_string()).collect(),
+            when: when.to_string(),
+            limit: limit.to_string(),
+        };
+        let config = RateLimitRulesConfig {
+            enabled: true,
+            rules: vec![
+                rule("broken", &["acme"], "base_limit / (hour - hour) > 1", "1"),
+                rule(
+                    "weekend_burst",
+                    &[],
+                    "sla_class == 'bronze' && weekday >= 6",
+                    "min(base_limit * 2, 500)",
+                ),
+                rule(
+                    "night",
+                    &["acme"],
+                    "hour < 6 || !starts_with(path, '/v1/chat')",
+                    "in_flight > 10 ? base_limit / 2 : base_limit",
+                ),
+            ],
+            ..RateLimitRulesConfig::default()
+        };
+        let rules = RateLimitRules::new(config.clone()).unwrap();
+
+        // Saturday 2026-10-17, 03:00 UTC
+        let mut ctx = RuleContext {
+            tenant: Some("acme"),
+            sla_class: SlaClass::Bronze,
+            priority: RequestPriority::Normal,
+            path: "/v1/chat/completions",
+            method: "POST",
+            now: Utc.with_ymd_and_hms(2026, 10, 17, 3, 0, 0).unwrap(),
+            base_limit: 300,
+            in_flight: 20,
+        };
+        let decision = rules.evaluate(&ctx).unwrap();
+        assert_eq!(
+            (decision.rule.as_str(), decision.limit),
+            ("weekend_burst", 500)
+        );
+        rules.record_rejection(&decision);
+
+        ctx.sla_class = SlaClass::Gold;
+        let decision = rules.evaluate(&ctx).unwrap();
+        assert_eq!((decision.rule.as_str(), decision.limit), ("night", 150));
+        ctx.tenant = Some("other");
+        assert!(rules.evaluate(&ctx).is_none());
+
+        let stats = rules.stats();
+        assert_eq!(stats[0].errors, 2);
+        assert_eq!((stats[1].matched, stats[1].rejected), (1, 1));
+        assert_eq!(stats[2].matched, 1);
+
+        // Type errors, unknown names and oversized expressions fail at load
+        for (when, limit) in [
+            ("base_limit", "1"),
+            ("tier == 'free'", "1"),
+            ("hour == 'night'", "1"),
+            ("true", "starts_with(path)"),
+            ("true", "exp(2)"),
+            ("true", &"1 + ".repeat(200)),
+            (
+                "(((((((((((((((((((((((((((((((((true)))))))))))))))))))))))))))))))))",
+                "1",
+            ),
+        ] {
+            let bad = rule("bad", &[], when, limit);
+            assert!(
+                RateLimitRules::validate_rule(&bad, &config).is_err(),
+                "{} / {} should be rejected",
+                when,
+                limit
+            );
+        }
+
+        // Runaway rules are cut off by the step budget
+        let tight = RateLimitRules::new(RateLimitRulesConfig {
+            enabled: true,
+            max_steps: 3,
+            rules: vec![rule("long", &[], "1 + 1 + 1 + 1 > 0", "1")],
+            ..RateLimitRulesConfig::default()
+        })
+        .unwrap();
+        assert!(tight.evaluate(&ctx).is_none());
+        assert_eq!(tight.stats()[0].errors, 1);
+    }
+}
@@ -0,0 +1,536 @@
+//! Soak and load traffic generation against a running proxy
+//!
+//! Workers replay the traffic shapes the proxy sees in production: interactive
+//! chat (encrypt a short prompt, then complete it), batch submissions (longer
+//! prompts queued for the off-peak window) and streaming completions read to
+//! the last chunk. Each worker holds its own key pair and picks a profile per
+//! iteration from a weighted mix until the run's duration is up. Latencies are
+//! kept per profile, so the report gives percentiles and error budgets for
+//! each shape, and the run can be checked against SLO thresholds before a
+//! release. Built with the `loadgen` feature; the `loadgen` binary drives it.
+
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Prompts of the lengths interactive users send
+const CHAT_PROMPTS: &[&str] = &[
+    "What is homomorphic encryption?",
+    "Summarize the key points of our last meeting in three bullets.",
+    "Draft a polite reply declining the invitation for next Tuesday.",
+    "Explain the difference between a mutex and a read-write lock.",
+];
+
+/// Longer documents submitted for off-peak processing
+const BATCH_PROMPTS: &[&str] = &[
+    "Classify the sentiment of each of the following customer reviews and give a one-line \
+     justification for each: the delivery was late but the support team resolved it quickly; \
+     the product broke within a week; excellent value, would buy again.",
+    "Translate the following onboarding checklist into French, keeping the numbering: \
+     1. Set up your workstation. 2. Read the security policy. 3. Enrol in single sign-on. \
+     4. Meet your onboarding buddy. 5. Complete the compliance training.",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficProfile {
+    Interactive,
+    Batch,
+    Streaming,
+}
+
+impl TrafficProfile {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "interactive" => Ok(Self::Interactive),
+            "batch" => Ok(Self::Batch),
+            "streaming" => Ok(Self::Streaming),
+            other => Err(Error::Config(format!("Unknown traffic profile: {}", other))),
+        }
+    }
+}
+
+impl fmt::Display for TrafficProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Interactive => "interactive",
+            Self::Batch => "batch",
+            Self::Streaming => "streaming",
+        })
+    }
+}
+
+/// Weighted choice of profiles, parsed from `interactive=6,batch=1,streaming=3`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrafficMix(Vec<(TrafficProfile, u32)>);
+
+impl TrafficMix {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut weights = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, weight) = entry.split_once('=').unwrap_or((entry, "1"));
+            let weight: u32 = weight.trim().parse().map_err(|_| {
+                Error::Config(format!("Invalid weight for traffic profile {}", name))
+            })?;
+            weights.push((TrafficProfile::parse(name.trim())?, weight));
+        }
+        if weights.iter().all(|(_, weight)| *weight == 0) {
+            return Err(Error::Config(
+                "Traffic mix needs at least one profile with a positive weight".to_string(),
+            ));
+        }
+        Ok(Self(weights))
+    }
+
+    /// Profile for a roll in `0..total_weight`
+    pub fn pick(&self, roll: u32) -> TrafficProfile {
+        let mut remaining = roll % self.total_weight();
+        for (profile, weight) in &self.0 {
+            if remaining < *weight {
+                return *profile;
+            }
+            remaining -= weight;
+        }
+        self.0[0].0
+    }
+
+    pub fn total_weight(&self) -> u32 {
+        self.0.iter().map(|(_, weight)| weight).sum()
+    }
+}
+
+impl Default for TrafficMix {
+    fn default() -> Self {
+        Self(vec![
+            (TrafficProfile::Interactive, 6),
+            (TrafficProfile::Batch, 1),
+            (TrafficProfile::Streaming, 3),
+        ])
+    }
+}
+
+/// Thresholds a run must meet; unset ones are not checked
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SloThresholds {
+    pub p99_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    /// Share of requests allowed to fail, which is the run's error budget
+    pub max_error_rate: Option<f64>,
+    pub min_throughput_rps: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadgenConfig {
+    /// Base URL of the proxy, e.g. `http://127.0.0.1:8080`
+    pub target: String,
+    pub concurrency: usize,
+    pub duration: Duration,
+    pub mix: TrafficMix,
+    pub provider: String,
+    pub model: String,
+    /// Sent as `x-tenant-id`; batch submissions require one
+    pub tenant: String,
+    /// Sent as `x-api-key`; the proxy accepts `tenant` only with its key
+    pub api_key: Option<String>,
+    /// Sent as `x-workload-tag`, so soak traffic can be told apart in metrics
+    pub workload_tag: Option<String>,
+    pub request_timeout: Duration,
+    pub slo: SloThresholds,
+}
+
+impl Default for LoadgenConfig {
+    fn default() -> Self {
+        Self {
+            target: "http://127.0.0.1:8080".to_string(),
+            concurrency: 8,
+            duration: Duration::from_secs(60),
+            mix: TrafficMix::default(),
+            provider: "openai".to_string(),
+            model: "gpt-4".to_string(),
+            tenant: "loadgen".to_string(),
+            api_key: None,
+            workload_tag: None,
+            request_timeout: Duration::from_secs(30),
+            slo: SloThresholds::default(),
+        }
+    }
+}
+
+/// Outcomes of one profile's requests
+#[derive(Debug, Clone, Default)]
+pub struct LatencyRecorder {
+    latencies_ms: Vec<u64>,
+    errors: u64,
+    /// Failures by status code, or "transport" when no response arrived
+    error_kinds: BTreeMap<String, u64>,
+}
+
+impl LatencyRecorder {
+    pub fn record_success(&mut self, latency: Duration) {
+        self.latencies_ms.push(latency.as_millis() as u64);
+    }
+
+    pub fn record_error(&mut self, kind: String) {
+        self.errors += 1;
+        *self.error_kinds.entry(kind).or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: &LatencyRecorder) {
+        self.latencies_ms.extend_from_slice(&other.latencies_ms);
+        self.errors += other.errors;
+        for (kind, count) in &other.error_kinds {
+            *self.error_kinds.entry(kind.clone()).or_default() += count;
+        }
+    }
+
+    /// Summary of the recorded requests, `elapsed` being the run's length
+    pub fn summarize(&self, elapsed: Duration, slo: &SloThresholds) -> LatencySummary {
+        let mut sorted = self.latencies_ms.clone();
+        sorted.sort_unstable();
+        let requests = sorted.len() as u64 + self.errors;
+        let error_rate = if requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / requests as f64
+        };
+        LatencySummary {
+            requests,
+            errors: self.errors,
+            error_kinds: self.error_kinds.clone(),
+            error_rate,
+            error_budget_consumed: slo.max_error_rate.map(|budget| match budget > 0.0 {
+                true => error_rate / budget,
+                false if self.errors == 0 => 0.0,
+                false => f64::INFINITY,
+            }),
+            throughput_rps: requests as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            p50_ms: percentile(&sorted, 50.0),
+            p95_ms: percentile(&sorted, 95.0),
+            p99_ms: percentile(&sorted, 99.0),
+            max_ms: sorted.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// Nearest-rank percentile of ascending `sorted`
+pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencySummary {
+    pub requests: u64,
+    pub errors: u64,
+    pub error_kinds: BTreeMap<String, u64>,
+    pub error_rate: f64,
+    /// Share of the error budget used; over 1.0 means the budget is blown
+    pub error_budget_consumed: Option<f64>,
+    pub throughput_rps: f64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadReport {
+    pub target: String,
+    pub concurrency: usize,
+    pub elapsed_seconds: f64,
+    pub overall: LatencySummary,
+    pub profiles: BTreeMap<TrafficProfile, LatencySummary>,
+    /// SLO thresholds the run missed; empty when it passed
+    pub violations: Vec<String>,
+}
+
+impl LoadReport {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Thresholds in `slo` that `overall` misses
+pub fn check_slo(overall: &LatencySummary, slo: &SloThresholds) -> Vec<String> {
+    let mut violations = Vec::new();
+    if let Some(limit) = slo.p99_ms.filter(|limit| overall.p99_ms > *limit) {
+        violations.push(format!(
+            "p99 latency {}ms exceeds {}ms",
+            overall.p99_ms, limit
+        ));
+    }
+    if let Some(limit) = slo.p95_ms.filter(|limit| overall.p95_ms > *limit) {
+        violations.push(format!(
+            "p95 latency {}ms exceeds {}ms",
+            overall.p95_ms, limit
+        ));
+    }
+    if let Some(limit) = slo
+        .max_error_rate
+        .filter(|limit| overall.error_rate > *limit)
+    {
+        violations.push(format!(
+            "error rate {:.4} exceeds budget {:.4}",
+            overall.error_rate, limit
+        ));
+    }
+    if let Some(limit) = slo
+        .min_throughput_rps
+        .filter(|limit| overall.throughput_rps < *limit)
+    {
+        violations.push(format!(
+            "throughput {:.1} rps is below {:.1} rps",
+            overall.throughput_rps, limit
+        ));
+    }
+    if overall.requests == 0 {
+        violations.push("no requests completed".to_string());
+    }
+    violations
+}
+
+/// One worker's connection to the target and its key pair
+struct Worker {
+    client: reqwest::Client,
+    config: LoadgenConfig,
+    client_id: Option<Uuid>,
+}
+
+/// Why an iteration failed, as counted in the report
+struct Failure(String);
+
+impl From<reqwest::Error> for Failure {
+    fn from(error: reqwest::Error) -> Self {
+        match error.status() {
+            Some(status) => Self(status.as_u16().to_string()),
+            None if error.is_timeout() => Self("timeout".to_string()),
+            None => Self("transport".to_string()),
+        }
+    }
+}
+
+impl Worker {
+    fn request(&self, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}{}", self.config.target.trim_end_matches('/'), path);
+        let mut request = self
+            .client
+            .post(url)
+            .header("x-tenant-id", &self.config.tenant);
+        if let Some(tag) = &self.config.workload_tag {
+            request = request.header("x-workload-tag", tag);
+        }
+        if let Some(key) = &self.config.api_key {
+            request = request.header("x-api-key", key);
+        }
+        request
+    }
+
+    async fn post_json(
+        &self,
+        path: &str,
+        body: serde_json::Value,
+    ) -> std::result::Result<serde_json::Value, Failure> {
+        let response = self
+            .request(path)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    /// Key pair for this worker, generated on first use and again whenever
+    /// the proxy no longer knows it
+    async fn client_id(&mut self) -> std::result::Result<Uuid, Failure> {
+        if let Some(id) = self.client_id {
+            return Ok(id);
+        }
+        let keys = self
+            .post_json("/v1/keys/generate", serde_json::json!({}))
+            .await?;
+        let id = keys["client_id"]
+            .as_str()
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| Failure("invalid_response".to_string()))?;
+        self.client_id = Some(id);
+        Ok(id)
+    }
+
+    async fn encrypt(&mut self, text: &str) -> std::result::Result<serde_json::Value, Failure> {
+        let client_id = self.client_id().await?;
+        let result = self
+            .post_json(
+                "/v1/encrypt",
+                serde_json::json!({ "text": text, "client_id": client_id }),
+            )
+            .await;
+        if result.is_err() {
+            // Keys may have expired or been evicted; start over next time
+            self.client_id = None;
+        }
+        result
+    }
+
+    fn completion_body(&self, ciphertext: &serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "ciphertext_id": ciphertext["ciphertext_id"],
+            "encrypted_data": ciphertext["encrypted_data"],
+            "provider": self.config.provider,
+            "model": self.config.model,
+        })
+    }
+
+    /// Run one request of `profile`; latency covers encryption and the call,
+    /// which is what a client waits for
+    async fn run(&mut self, profile: TrafficProfile) -> std::result::Result<(), Failure> {
+        let prompts = match profile {
+            TrafficProfile::Batch => BATCH_PROMPTS,
+            TrafficProfile::Interactive | TrafficProfile::Streaming => CHAT_PROMPTS,
+        };
+        let prompt = prompts[fastrand::usize(..prompts.len())];
+        let ciphertext = self.encrypt(prompt).await?;
+        match profile {
+            TrafficProfile::Interactive => {
+                self.post_json("/v1/chat/completions", self.completion_body(&ciphertext))
+                    .await?;
+            }
+            TrafficProfile::Batch => {
+                let body = serde_json::json!({
+                    "encrypted_data": ciphertext["encrypted_data"],
+                    "provider": self.config.provider,
+                    "model": self.config.model,
+                });
+                self.post_json("/v1/batch/jobs", body).await?;
+            }
+            TrafficProfile::Streaming => {
+                let mut body = self.completion_body(&ciphertext);
+                body["stream"] = serde_json::json!(true);
+                // The stream only counts once every chunk has arrived
+                self.request("/v1/chat/stream")
+                    .json(&body)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Drive traffic at `config.target` for `config.duration` and report on it
+pub async fn run(config: LoadgenConfig) -> Result<LoadReport> {
+    if config.concurrency == 0 {
+        return Err(Error::Config("Concurrency must be at least 1".to_string()));
+    }
+    let client = reqwest::Client::builder()
+        .timeout(config.request_timeout)
+        .build()?;
+    let started = Instant::now();
+    let deadline = started + config.duration;
+
+    let mut workers = tokio::task::JoinSet::new();
+    for _ in 0..config.concurrency {
+        let mut worker = Worker {
+            client: client.clone(),
+            config: config.clone(),
+            client_id: None,
+        };
+        workers.spawn(async move {
+            let mut recorders: BTreeMap<TrafficProfile, LatencyRecorder> = BTreeMap::new();
+            while Instant::now() < deadline {
+                let profile = worker.config.mix.pick(fastrand::u32(..));
+                let request_started = Instant::now();
+                let outcome = worker.run(profile).await;
+                let recorder = recorders.entry(profile).or_default();
+                match outcome {
+                    Ok(()) => recorder.record_success(request_started.elapsed()),
+                    Err(Failure(kind)) => recorder.record_error(kind),
+                }
+            }
+            recorders
+        });
+    }
+
+    let mut per_profile: BTreeMap<TrafficProfile, LatencyRecorder> = BTreeMap::new();
+    while let Some(result) = workers.join_next().await {
+        let recorders =
+            result.map_err(|e| Error::Internal(format!("Load worker panicked: {}", e)))?;
+        for (profile, recorder) in recorders {
+            per_profile.entry(profile).or_default().merge(&recorder);
+        }
+    }
+    let elapsed = started.elapsed();
+
+    let mut all = LatencyRecorder::default();
+    for recorder in per_profile.values() {
+        all.merge(recorder);
+    }
+    let overall = all.summarize(elapsed, &config.slo);
+    let violations = check_slo(&overall, &config.slo);
+    Ok(LoadReport {
+        target: config.target.clone(),
+        concurrency: config.concurrency,
+        elapsed_seconds: elapsed.as_secs_f64(),
+        profiles: per_profile
+            .iter()
+            .map(|(profile, recorder)| (*profile, recorder.summarize(elapsed, &config.slo)))
+            .collect(),
+        overall,
+        violations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix_percentiles_and_slo_checks() {
+        let mix = TrafficMix::parse("interactive=2, streaming").unwrap();
+        assert_eq!(mix.total_weight(), 3);
+        assert_eq!(mix.pick(0), TrafficProfile::Interactive);
+        assert_eq!(mix.pick(1), TrafficProfile::Interactive);
+        assert_eq!(mix.pick(2), TrafficProfile::Streaming);
+        assert!(TrafficMix::parse("interactive=0").is_err());
+        assert!(TrafficMix::parse("replay=1").is_err());
+
+        let mut recorder = LatencyRecorder::default();
+        for ms in 1..=100 {
+            recorder.record_success(Duration::from_millis(ms));
+        }
+        recorder.record_error("503".to_string());
+        recorder.record_error("503".to_string());
+        let slo = SloThresholds {
+            p99_ms: Some(50),
+            max_error_rate: Some(0.04),
+            ..SloThresholds::default()
+        };
+        let summary = recorder.summarize(Duration::from_secs(2), &slo);
+        assert_eq!(
+            (
+                summary.p50_ms,
+                summary.p95_ms,
+                summary.p99_ms,
+                summary.max_ms
+            ),
+            (50, 95, 99, 100)
+        );
+        assert_eq!(summary.requests, 102);
+        assert_eq!(summary.error_kinds["503"], 2);
+        assert!((summary.error_budget_consumed.unwrap() - 0.49).abs() < 0.01);
+        assert_eq!(summary.throughput_rps, 51.0);
+
+        // Latency misses its threshold while errors stay within budget
+        let violations = check_slo(&summary, &slo);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].starts_with("p99 latency 99ms"));
+    }
+}
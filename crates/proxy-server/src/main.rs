@@ -0,0 +1,241 @@
+//! Homomorphic LLM Proxy
+//!
+//! GPU-accelerated gateway for fully homomorphic encryption (FHE) of LLM inference.
+//! Process prompts on untrusted cloud infrastructure while maintaining complete privacy.
+
+use proxy_server::config::{Config, PersistenceConfig, StorageMigrationConfig};
+use proxy_server::migrations::{self, MigrationRunner};
+use proxy_server::persistence::{self, StorageSnapshot};
+use proxy_server::proxy::ProxyServer;
+use proxy_server::{strict_mode, systemd, Error, Result};
+use tracing::{error, info, warn};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Initialize logging
+    init_logging().await?;
+
+    // CI gating of config changes: `fhe-proxy validate-config [file]`
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("validate-config") {
+        run_validate_config(args.get(2).map(String::as_str));
+    }
+
+    // Load and validate configuration
+    let config = Config::load()?;
+    config.validate()?;
+
+    // Storage maintenance: `fhe-proxy storage export|import <file>` or
+    // `fhe-proxy storage copy <backend> [sqlite_path]`
+    if args.get(1).map(String::as_str) == Some("storage") {
+        return run_storage_command(&config, &args[2..]);
+    }
+
+    // Schema upgrades: `fhe-proxy migrate [--dry-run]`
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        let dry_run = args[2..].iter().any(|arg| arg == "--dry-run");
+        return run_migrate_command(&config, dry_run);
+    }
+
+    // Refuse to serve with weak settings when strict mode is on
+    if config.strict_security.enabled {
+        enforce_strict_mode(&config);
+    }
+
+    info!("🚀 Starting FHE LLM Proxy");
+    info!("{}", config.summary());
+
+    // Create and start the proxy server
+    let server = ProxyServer::new(config)?;
+
+    if let Err(e) = server.start().await {
+        error!("Server stopped: {}", e);
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Print the strict mode report and exit if any check failed
+fn enforce_strict_mode(config: &Config) {
+    let report = strict_mode::check(config);
+    if report.passed {
+        info!("Strict security checks passed");
+        return;
+    }
+    for violation in &report.violations {
+        error!(
+            "Strict mode: {} ({}): {}",
+            violation.key, violation.check, violation.message
+        );
+    }
+    match serde_json::to_string_pretty(&report) {
+        Ok(json) => println!("{}", json),
+        Err(e) => error!("Failed to serialize strict mode report: {}", e),
+    }
+    std::process::exit(strict_mode::STRICT_MODE_EXIT_CODE);
+}
+
+/// Apply pending storage migrations, or list them with `dry_run`
+fn run_migrate_command(config: &Config, dry_run: bool) -> Result<()> {
+    let store = persistence::open_backend(&config.persistence)?;
+    // Running the command is the operator's go-ahead, whatever `auto_apply` says
+    let migration_config = StorageMigrationConfig {
+        auto_apply: true,
+        ..config.persistence.migrations.clone()
+    };
+    let report = MigrationRunner::new(
+        migration_config,
+        migrations::replica_holder(&config.persistence.replication.region),
+    )
+    .dry_run(dry_run)
+    .run(store.as_ref())?;
+
+    for migration in &report.migrations {
+        info!(
+            "{} storage migration {} ({}){}",
+            if dry_run { "Would apply" } else { "Applied" },
+            migration.version,
+            migration.name,
+            if migration.destructive {
+                ", destructive"
+            } else {
+                ""
+            }
+        );
+    }
+    for backup in &report.backups {
+        info!("Backed up {} storage to {}", store.name(), backup);
+    }
+    info!(
+        "{} storage schema: version {} -> {} ({:?})",
+        store.name(),
+        report.from_version,
+        report.to_version,
+        report.outcome
+    );
+    Ok(())
+}
+
+/// Export or import all persisted data, for moving between storage backends
+fn run_storage_command(config: &Config, args: &[String]) -> Result<()> {
+    let store = persistence::open_backend(&config.persistence)?;
+    MigrationRunner::new(
+        config.persistence.migrations.clone(),
+        migrations::replica_holder(&config.persistence.replication.region),
+    )
+    .run(store.as_ref())?;
+
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("export"), Some(path)) => {
+            let snapshot = StorageSnapshot::export(store.as_ref())?;
+            std::fs::write(path, serde_json::to_vec_pretty(&snapshot)?)?;
+            info!(
+                "Exported {} records from {} storage to {}",
+                snapshot.record_count(),
+                store.name(),
+                path
+            );
+        }
+        (Some("import"), Some(path)) => {
+            let snapshot: StorageSnapshot = serde_json::from_slice(&std::fs::read(path)?)?;
+            snapshot.import(store.as_ref())?;
+            info!(
+                "Imported {} records from {} into {} storage",
+                snapshot.record_count(),
+                path,
+                store.name()
+            );
+        }
+        (Some("copy"), Some(backend)) => {
+            let target_config = PersistenceConfig {
+                backend: backend.to_lowercase(),
+                sqlite_path: args
+                    .get(2)
+                    .cloned()
+                    .unwrap_or_else(|| config.persistence.sqlite_path.clone()),
+                ..config.persistence.clone()
+            };
+            if target_config.backend == config.persistence.backend
+                && target_config.sqlite_path == config.persistence.sqlite_path
+            {
+                return Err(Error::Validation(
+                    "storage copy needs a target other than the configured backend".to_string(),
+                ));
+            }
+            let target = persistence::open_backend(&target_config)?;
+            MigrationRunner::new(
+                config.persistence.migrations.clone(),
+                migrations::replica_holder(&config.persistence.replication.region),
+            )
+            .run(target.as_ref())?;
+            persistence::migrate(store.as_ref(), target.as_ref())?;
+        }
+        _ => {
+            return Err(Error::Validation(
+                "Usage: fhe-proxy storage <export|import> <file> | storage copy <backend> [sqlite_path]"
+                    .to_string(),
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// Check a config file strictly and exit: 0 if it is valid, 1 otherwise
+fn run_validate_config(path: Option<&str>) -> ! {
+    let path = path
+        .map(str::to_string)
+        .or_else(|| std::env::var("FHE_CONFIG_PATH").ok())
+        .unwrap_or_else(|| "config.toml".to_string());
+
+    match Config::validate_file(std::path::Path::new(&path)) {
+        Ok(report) => {
+            for note in &report.notes {
+                warn!(
+                    "{}: migrated from schema version {}: {}",
+                    path, report.from_version, note
+                );
+            }
+            if report.from_version < report.to_version {
+                warn!(
+                    "{} uses config schema version {}; set schema_version = {} once the notes above are applied",
+                    path, report.from_version, report.to_version
+                );
+            }
+            info!("{} is valid", path);
+            std::process::exit(0);
+        }
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Initialize logging and tracing
+async fn init_logging() -> Result<()> {
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into());
+    // The journal timestamps lines itself and reads their priority prefix
+    if systemd::logging_to_journal() {
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_ansi(false)
+            .event_format(systemd::JournaldFormat)
+            .init();
+        return Ok(());
+    }
+
+    // Set up tracing subscriber
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_thread_ids(true)
+        .with_file(true)
+        .with_line_number(true)
+        .init();
+
+    info!("🔐 FHE LLM Proxy starting up...");
+
+    Ok(())
+}
@@ -0,0 +1,301 @@
+//! The catalog of metrics served on /metrics
+//!
+//! Every metric family is registered with its type, labels, unit and a
+//! description, and registration refuses a metric without them. /metrics is
+//! rendered through the catalog, which refuses values for unregistered
+//! names, so nothing reaches /metrics without being documented in
+//! /metrics/catalog.
+
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    /// Only ever goes up until restart
+    Counter,
+    /// A current value
+    Gauge,
+    /// Counters and gauges of one component, keyed by the metric's labels
+    Group,
+}
+
+/// What a metric is, as listed in the catalog
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricDescriptor {
+    pub name: &'static str,
+    pub kind: MetricKind,
+    /// UCUM unit as in OTLP: `1` for counts, `ms`, `s`, `By`
+    pub unit: &'static str,
+    /// Dimensions the values are broken down by
+    pub labels: Vec<&'static str>,
+    pub description: &'static str,
+}
+
+impl MetricDescriptor {
+    pub fn counter(name: &'static str, unit: &'static str, description: &'static str) -> Self {
+        Self::new(name, MetricKind::Counter, unit, description)
+    }
+
+    pub fn gauge(name: &'static str, unit: &'static str, description: &'static str) -> Self {
+        Self::new(name, MetricKind::Gauge, unit, description)
+    }
+
+    pub fn group(name: &'static str, unit: &'static str, description: &'static str) -> Self {
+        Self::new(name, MetricKind::Group, unit, description)
+    }
+
+    fn new(
+        name: &'static str,
+        kind: MetricKind,
+        unit: &'static str,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            kind,
+            unit,
+            labels: Vec::new(),
+            description,
+        }
+    }
+
+    pub fn labeled(mut self, labels: &[&'static str]) -> Self {
+        self.labels = labels.to_vec();
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MetricCatalog {
+    metrics: BTreeMap<&'static str, MetricDescriptor>,
+}
+
+impl MetricCatalog {
+    /// The metrics the proxy serves on /metrics
+    pub fn proxy() -> Result<Self> {
+        let mut catalog = Self::default();
+        for metric in [
+            MetricDescriptor::counter("requests", "1", "Requests received since startup"),
+            MetricDescriptor::counter("errors", "1", "Requests that failed since startup"),
+            MetricDescriptor::counter("encryptions", "1", "FHE encryptions since startup"),
+            MetricDescriptor::counter("decryptions", "1", "FHE decryptions since startup"),
+            MetricDescriptor::gauge(
+                "avg_response_time_ms",
+                "ms",
+                "Mean response time since startup",
+            ),
+            MetricDescriptor::group(
+                "provider_quota",
+                "1",
+                "Request and token budgets each provider last reported as remaining, and calls \
+                 held back or sent elsewhere to stay within them",
+            )
+            .labeled(&["provider"]),
+            MetricDescriptor::group(
+                "warm_hints",
+                "ms",
+                "Mean cold-start latency of provider calls sent with and without warm hints, \
+                 and how much lower the hinted one is",
+            )
+            .labeled(&["provider", "model"]),
+            MetricDescriptor::group(
+                "load_shedding",
+                "1",
+                "Requests each load shedding policy shed, or would have shed while simulated",
+            )
+            .labeled(&["policy"]),
+            MetricDescriptor::group(
+                "rate_limit_rules",
+                "1",
+                "Requests each rate-limit rule set the limit of and rejected, failed \
+                 evaluations and mean evaluation time",
+            )
+            .labeled(&["rule"]),
+            MetricDescriptor::group(
+                "engine_tables",
+                "By",
+                "Generation and swaps of the FHE engine's precomputed tables, and the memory \
+                 held by the active and retiring generations",
+            ),
+            MetricDescriptor::group(
+                "workload_tags",
+                "1",
+                "Requests, errors, refusals and mean latency by workload tag",
+            )
+            .labeled(&["tag"]),
+            MetricDescriptor::group(
+                "context_compression",
+                "By",
+                "Conversation turns stored compressed and raw, bytes before and after \
+                 compression, and time spent compressing",
+            ),
+            MetricDescriptor::group(
+                "synthetic_probes",
+                "ms",
+                "Success rate, latency percentiles and SLO status of the synthetic probes of \
+                 each route",
+            )
+            .labeled(&["route"]),
+            MetricDescriptor::group(
+                "request_telemetry",
+                "1",
+                "Sampled requests, errors and latency",
+            )
+            .labeled(&["route", "model", "size", "tier"]),
+            MetricDescriptor::group(
+                "feature_flags",
+                "1",
+                "Evaluations of each feature flag, how many came out on, and why",
+            )
+            .labeled(&["flag"]),
+            MetricDescriptor::group(
+                "execution",
+                "1",
+                "FHE operations run on the GPU and the CPU, CPU fallbacks, rejections and \
+                 time spent on each",
+            )
+            .labeled(&["operation"]),
+            MetricDescriptor::group(
+                "connections",
+                "1",
+                "Open connections, and connections and requests the connection guard refused \
+                 or cut off",
+            ),
+            MetricDescriptor::group(
+                "clock",
+                "ms",
+                "Skew of the host clock against the reference time servers",
+            )
+            .labeled(&["server"]),
+            MetricDescriptor::group(
+                "trace_sampling",
+                "1",
+                "Requests sampled for tracing by reason, and the sampling budget",
+            )
+            .labeled(&["route"]),
+            MetricDescriptor::group(
+                "quality",
+                "1",
+                "Response quality scores and whether requests are routed away",
+            )
+            .labeled(&["model"]),
+            MetricDescriptor::group(
+                "document_workers",
+                "1",
+                "Document ingestion workers, the p95 wait for one, and how often autoscaling \
+                 grew or shrank the pool",
+            ),
+            MetricDescriptor::group(
+                "response_cache",
+                "1",
+                "Cached completions, hits, the recomputation time the hits saved, entries \
+                 evicted or turned away under the eviction policy, and how many preloads were \
+                 requested in time",
+            ),
+            MetricDescriptor::group(
+                "memory",
+                "By",
+                "Heap fragmentation, allocated and resident bytes, sampled from the global \
+                 allocator when read",
+            ),
+            MetricDescriptor::gauge("timestamp", "s", "Unix time the metrics were read"),
+        ] {
+            catalog.register(metric)?;
+        }
+        Ok(catalog)
+    }
+
+    /// Add `metric`, refusing one that is not documented or already registered
+    pub fn register(&mut self, metric: MetricDescriptor) -> Result<()> {
+        let undocumented = |what: &str| {
+            Err(Error::Config(format!(
+                "Metric {:?} has no {}",
+                metric.name, what
+            )))
+        };
+        if metric.name.trim().is_empty() {
+            return undocumented("name");
+        }
+        if metric.description.trim().is_empty() {
+            return undocumented("description");
+        }
+        if metric.unit.trim().is_empty() {
+            return undocumented("unit");
+        }
+        if metric.labels.iter().any(|label| label.trim().is_empty()) {
+            return undocumented("name for one of its labels");
+        }
+        if self.metrics.contains_key(metric.name) {
+            return Err(Error::Config(format!(
+                "Metric {:?} is registered twice",
+                metric.name
+            )));
+        }
+        self.metrics.insert(metric.name, metric);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MetricDescriptor> {
+        self.metrics.get(name)
+    }
+
+    /// Every registered metric, by name
+    pub fn entries(&self) -> Vec<&MetricDescriptor> {
+        self.metrics.values().collect()
+    }
+
+    /// The /metrics document of `values`, refusing any not in the catalog
+    pub fn render(
+        &self,
+        values: impl IntoIterator<Item = (&'static str, serde_json::Value)>,
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        values
+            .into_iter()
+            .map(|(name, value)| match self.get(name) {
+                Some(_) => Ok((name.to_string(), value)),
+                None => Err(Error::Internal(format!(
+                    "Metric {:?} is not in the catalog",
+                    name
+                ))),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refuses_undocumented_metrics() {
+        let mut catalog = MetricCatalog::proxy().unwrap();
+        assert_eq!(catalog.get("requests").unwrap().kind, MetricKind::Counter);
+        assert_eq!(
+            catalog.get("warm_hints").unwrap().labels,
+            ["provider", "model"]
+        );
+
+        assert!(catalog
+            .register(MetricDescriptor::counter("retries", "1", " "))
+            .is_err());
+        assert!(catalog
+            .register(MetricDescriptor::gauge(
+                "queue_depth",
+                "",
+                "Queued requests"
+            ))
+            .is_err());
+        assert!(catalog
+            .register(MetricDescriptor::counter("requests", "1", "Requests again"))
+            .is_err());
+        assert!(catalog.get("retries").is_none());
+
+        let rendered = catalog
+            .render([("requests", serde_json::json!(3))])
+            .unwrap();
+        assert_eq!(rendered["requests"], 3);
+        assert!(catalog.render([("retries", serde_json::json!(1))]).is_err());
+    }
+}
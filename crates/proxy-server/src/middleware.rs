@@ -0,0 +1,1605 @@
+//! Middleware for request/response processing, rate limiting, and metrics
+
+use crate::config::{
+    BudgetReplenishmentConfig, TransformConfig, TransformOperation, TransformRule, TransformStage,
+};
+use crate::error::{Error, Result};
+use crate::fhe::Ciphertext;
+use crate::persistence::PrivacyLedgerEntry;
+use axum::{
+    extract::Request,
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Rate limiter for client requests
+#[derive(Debug)]
+pub struct RateLimiter {
+    clients: Arc<RwLock<HashMap<String, ClientLimiter>>>,
+    global_limit: u64,
+    window_duration: Duration,
+}
+
+#[derive(Debug)]
+struct ClientLimiter {
+    requests: AtomicU64,
+    window_start: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u64) -> Self {
+        Self {
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            global_limit: requests_per_minute,
+            window_duration: Duration::from_secs(60),
+        }
+    }
+
+    /// Per-minute limit for clients without an override
+    pub fn global_limit(&self) -> u64 {
+        self.global_limit
+    }
+
+    pub async fn check_rate_limit(&self, client_ip: &str) -> Result<bool> {
+        self.check_rate_limit_with(client_ip, self.global_limit)
+            .await
+    }
+
+    /// Check a client against an explicit per-minute limit (e.g. a tenant override)
+    pub async fn check_rate_limit_with(&self, client_key: &str, limit: u64) -> Result<bool> {
+        let mut clients = self.clients.write().await;
+        let now = Instant::now();
+
+        let client_limiter =
+            clients
+                .entry(client_key.to_string())
+                .or_insert_with(|| ClientLimiter {
+                    requests: AtomicU64::new(0),
+                    window_start: now,
+                    blocked_until: None,
+                });
+
+        // Check if client is still blocked
+        if let Some(blocked_until) = client_limiter.blocked_until {
+            if now < blocked_until {
+                return Ok(false);
+            }
+        }
+
+        // Reset window if needed
+        if now.duration_since(client_limiter.window_start) >= self.window_duration {
+            client_limiter.requests.store(0, Ordering::Relaxed);
+            client_limiter.window_start = now;
+            client_limiter.blocked_until = None;
+        }
+
+        let current_requests = client_limiter.requests.fetch_add(1, Ordering::Relaxed);
+
+        if current_requests >= limit {
+            client_limiter.blocked_until = Some(now + Duration::from_secs(60));
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    pub async fn get_client_stats(&self, client_ip: &str) -> Option<(u64, Duration)> {
+        let clients = self.clients.read().await;
+        let client = clients.get(client_ip)?;
+        let requests = client.requests.load(Ordering::Relaxed);
+        let window_remaining = self
+            .window_duration
+            .saturating_sub(Instant::now().duration_since(client.window_start));
+        Some((requests, window_remaining))
+    }
+}
+
+/// Request metrics collector
+#[derive(Debug)]
+pub struct MetricsCollector {
+    total_requests: AtomicU64,
+    total_errors: AtomicU64,
+    encryption_operations: AtomicU64,
+    decryption_operations: AtomicU64,
+    avg_response_time: AtomicU64,
+}
+
+impl MetricsCollector {
+    pub fn new() -> Self {
+        Self {
+            total_requests: AtomicU64::new(0),
+            total_errors: AtomicU64::new(0),
+            encryption_operations: AtomicU64::new(0),
+            decryption_operations: AtomicU64::new(0),
+            avg_response_time: AtomicU64::new(0),
+        }
+    }
+
+    pub fn increment_requests(&self) {
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_errors(&self) {
+        self.total_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_encryptions(&self) {
+        self.encryption_operations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_decryptions(&self) {
+        self.decryption_operations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_response_time(&self, duration: Duration) {
+        let millis = duration.as_millis() as u64;
+        let current_avg = self.avg_response_time.load(Ordering::Relaxed);
+        let new_avg = (current_avg + millis) / 2;
+        self.avg_response_time.store(new_avg, Ordering::Relaxed);
+    }
+
+    pub fn get_stats(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            total_errors: self.total_errors.load(Ordering::Relaxed),
+            encryption_operations: self.encryption_operations.load(Ordering::Relaxed),
+            decryption_operations: self.decryption_operations.load(Ordering::Relaxed),
+            avg_response_time_ms: self.avg_response_time.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub total_requests: u64,
+    pub total_errors: u64,
+    pub encryption_operations: u64,
+    pub decryption_operations: u64,
+    pub avg_response_time_ms: u64,
+}
+
+/// Security headers middleware
+pub fn security_headers(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+
+    headers.insert("X-Content-Type-Options", "nosniff".parse().unwrap());
+    headers.insert("X-Frame-Options", "DENY".parse().unwrap());
+    headers.insert("X-XSS-Protection", "1; mode=block".parse().unwrap());
+    headers.insert(
+        "Strict-Transport-Security",
+        "max-age=31536000; includeSubDomains".parse().unwrap(),
+    );
+    headers.insert(
+        "Content-Security-Policy",
+        "default-src 'self'".parse().unwrap(),
+    );
+    headers.insert(
+        "Referrer-Policy",
+        "strict-origin-when-cross-origin".parse().unwrap(),
+    );
+
+    response
+}
+
+/// Request validation middleware
+pub async fn validate_request(
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> std::result::Result<Response, StatusCode> {
+    // Validate Content-Type for POST requests
+    if request.method() == "POST" {
+        let content_type = headers
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+
+        if !content_type.starts_with("application/json")
+            && !content_type.starts_with("application/octet-stream")
+        {
+            log::warn!("Invalid content type: {}", content_type);
+            return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+    }
+
+    // Validate content length
+    if let Some(content_length) = headers.get("content-length") {
+        if let Ok(length_str) = content_length.to_str() {
+            if let Ok(length) = length_str.parse::<usize>() {
+                if length > 10 * 1024 * 1024 {
+                    // 10MB limit
+                    log::warn!("Request too large: {} bytes", length);
+                    return Err(StatusCode::PAYLOAD_TOO_LARGE);
+                }
+            }
+        }
+    }
+
+    let response = next.run(request).await;
+    Ok(security_headers(response))
+}
+
+/// Authentication middleware (placeholder for actual auth)
+pub async fn authenticate_request(
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> std::result::Result<Response, StatusCode> {
+    // Check for API key in headers
+    let auth_header = headers
+        .get("authorization")
+        .or_else(|| headers.get("x-api-key"));
+
+    if auth_header.is_none() {
+        log::warn!("Missing authentication header");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    // For now, accept any non-empty auth header
+    // In production, validate against stored API keys
+    let response = next.run(request).await;
+    Ok(response)
+}
+
+/// When a user's privacy budget is restored to its full allocation
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplenishmentPolicy {
+    /// Only replenished through the admin reset and top-up endpoints
+    Manual,
+    /// Reset at 00:00 UTC every day
+    Daily,
+    /// Reset at 00:00 UTC every Monday
+    Weekly,
+    /// Each charge is returned once it falls out of the trailing window
+    RollingWindow(Duration),
+}
+
+/// Lifecycle policy applied by the privacy budget tracker
+#[derive(Debug, Clone)]
+pub struct PrivacyBudgetPolicy {
+    pub replenishment: ReplenishmentPolicy,
+    /// Requests that started before a reset and arrive within this window
+    /// are not charged against the fresh period
+    pub grace_period: Duration,
+    /// Consumption percentages that trigger a tenant notification
+    pub notification_thresholds: Vec<u8>,
+}
+
+/// The policy of an unset `[privacy.budget_replenishment]`, so the tracker
+/// and the config never disagree on when budgets replenish
+impl Default for PrivacyBudgetPolicy {
+    fn default() -> Self {
+        Self::from_config(&BudgetReplenishmentConfig::default())
+            .expect("default replenishment policy is known")
+    }
+}
+
+impl PrivacyBudgetPolicy {
+    pub fn from_config(config: &BudgetReplenishmentConfig) -> Result<Self> {
+        let replenishment = match config.policy.as_str() {
+            "manual" => ReplenishmentPolicy::Manual,
+            "daily" => ReplenishmentPolicy::Daily,
+            "weekly" => ReplenishmentPolicy::Weekly,
+            "rolling" => ReplenishmentPolicy::RollingWindow(Duration::from_secs(
+                config.rolling_window_hours * 3600,
+            )),
+            other => {
+                return Err(Error::Config(format!(
+                    "Unknown privacy budget replenishment policy: {}",
+                    other
+                )))
+            }
+        };
+
+        let mut notification_thresholds = config.notification_thresholds.clone();
+        notification_thresholds.sort_unstable();
+        notification_thresholds.dedup();
+
+        Ok(Self {
+            replenishment,
+            grace_period: Duration::from_secs(config.grace_period_seconds),
+            notification_thresholds,
+        })
+    }
+
+    /// Start of the fixed period containing `now`, if the policy uses fixed periods
+    fn period_start(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let midnight = now.date_naive().and_hms_opt(0, 0, 0)?.and_utc();
+        match self.replenishment {
+            ReplenishmentPolicy::Daily => Some(midnight),
+            ReplenishmentPolicy::Weekly => {
+                Some(midnight - chrono::Duration::days(now.weekday().num_days_from_monday() as i64))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Notification emitted when a user crosses a consumption threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetNotification {
+    pub user_id: String,
+    pub threshold_percent: u8,
+    pub consumed_percent: f64,
+    pub remaining_epsilon: f64,
+    pub timestamp: i64,
+}
+
+/// Privacy budget tracking middleware
+#[derive(Debug)]
+pub struct PrivacyBudgetTracker {
+    user_budgets: Arc<RwLock<HashMap<String, UserPrivacyBudget>>>,
+    notifications: Arc<RwLock<VecDeque<BudgetNotification>>>,
+    default_epsilon: f64,
+    default_delta: f64,
+    policy: PrivacyBudgetPolicy,
+    /// Queries denied for lack of budget since startup
+    exhaustions: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+pub struct UserPrivacyBudget {
+    pub total_epsilon: f64,
+    pub total_delta: f64,
+    pub remaining_epsilon: f64,
+    pub remaining_delta: f64,
+    pub queries_count: u64,
+    pub last_query: Instant,
+    pub period_started: DateTime<Utc>,
+    pub last_reset: Option<Instant>,
+    pub notified_thresholds: Vec<u8>,
+    charges: VecDeque<BudgetCharge>,
+}
+
+#[derive(Debug, Clone)]
+struct BudgetCharge {
+    at: Instant,
+    epsilon: f64,
+    delta: f64,
+}
+
+impl UserPrivacyBudget {
+    fn new(epsilon: f64, delta: f64) -> Self {
+        Self {
+            total_epsilon: epsilon,
+            total_delta: delta,
+            remaining_epsilon: epsilon,
+            remaining_delta: delta,
+            queries_count: 0,
+            last_query: Instant::now(),
+            period_started: Utc::now(),
+            last_reset: None,
+            notified_thresholds: Vec::new(),
+            charges: VecDeque::new(),
+        }
+    }
+
+    /// Percentage of the epsilon allocation consumed in the current period
+    pub fn consumed_percent(&self) -> f64 {
+        if self.total_epsilon <= 0.0 {
+            return 100.0;
+        }
+        ((self.total_epsilon - self.remaining_epsilon).max(0.0) / self.total_epsilon) * 100.0
+    }
+
+    fn reset(&mut self, period_started: DateTime<Utc>) {
+        self.remaining_epsilon = self.total_epsilon;
+        self.remaining_delta = self.total_delta;
+        self.queries_count = 0;
+        self.period_started = period_started;
+        self.last_reset = Some(Instant::now());
+        self.notified_thresholds.clear();
+        self.charges.clear();
+    }
+}
+
+impl PrivacyBudgetTracker {
+    pub fn new(default_epsilon: f64, default_delta: f64) -> Self {
+        Self {
+            user_budgets: Arc::new(RwLock::new(HashMap::new())),
+            notifications: Arc::new(RwLock::new(VecDeque::new())),
+            default_epsilon,
+            default_delta,
+            policy: PrivacyBudgetPolicy::default(),
+            exhaustions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: PrivacyBudgetPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub async fn check_budget(
+        &self,
+        user_id: &str,
+        epsilon_cost: f64,
+        delta_cost: f64,
+    ) -> Result<bool> {
+        self.check_budget_started_at(user_id, epsilon_cost, delta_cost, Instant::now())
+            .await
+    }
+
+    /// Check and consume budget for a request that started at `started_at`
+    pub async fn check_budget_started_at(
+        &self,
+        user_id: &str,
+        epsilon_cost: f64,
+        delta_cost: f64,
+        started_at: Instant,
+    ) -> Result<bool> {
+        let mut budgets = self.user_budgets.write().await;
+        let budget = budgets
+            .entry(user_id.to_string())
+            .or_insert_with(|| UserPrivacyBudget::new(self.default_epsilon, self.default_delta));
+
+        self.replenish_if_due(user_id, budget, Utc::now());
+
+        // A reset landed while this request was in flight: it was admitted
+        // against the previous period, so don't charge the fresh one
+        if let Some(reset_at) = budget.last_reset {
+            if started_at < reset_at && reset_at.elapsed() <= self.policy.grace_period {
+                budget.queries_count += 1;
+                budget.last_query = Instant::now();
+                log::debug!(
+                    "Privacy budget grace applied for user {}: request predates reset",
+                    user_id
+                );
+                return Ok(true);
+            }
+        }
+
+        // Refused without notifying: the 100% threshold fires once a charge
+        // actually uses the budget up, not when one merely doesn't fit
+        if budget.remaining_epsilon < epsilon_cost || budget.remaining_delta < delta_cost {
+            self.exhaustions.fetch_add(1, Ordering::Relaxed);
+            return Ok(false);
+        }
+
+        budget.remaining_epsilon -= epsilon_cost;
+        budget.remaining_delta -= delta_cost;
+        budget.queries_count += 1;
+        budget.last_query = Instant::now();
+        if matches!(
+            self.policy.replenishment,
+            ReplenishmentPolicy::RollingWindow(_)
+        ) {
+            budget.charges.push_back(BudgetCharge {
+                at: budget.last_query,
+                epsilon: epsilon_cost,
+                delta: delta_cost,
+            });
+        }
+
+        log::debug!(
+            "Privacy budget consumed for user {}: ε={:.3}, δ={:.6}, remaining: ε={:.3}, δ={:.6}",
+            user_id,
+            epsilon_cost,
+            delta_cost,
+            budget.remaining_epsilon,
+            budget.remaining_delta
+        );
+
+        let consumed = budget.consumed_percent();
+        let notifications: Vec<_> = self
+            .policy
+            .notification_thresholds
+            .iter()
+            .filter(|threshold| consumed >= **threshold as f64)
+            .filter_map(|threshold| self.reach_threshold(user_id, budget, *threshold))
+            .collect();
+        self.push_notifications(notifications).await;
+
+        Ok(true)
+    }
+
+    /// Return a charge whose request failed before anything was released.
+    /// Never restores more than the period's allocation, so a reset landing
+    /// in between isn't topped up.
+    pub async fn refund(&self, user_id: &str, epsilon_cost: f64, delta_cost: f64) {
+        let mut budgets = self.user_budgets.write().await;
+        let Some(budget) = budgets.get_mut(user_id) else {
+            return;
+        };
+        budget.remaining_epsilon =
+            (budget.remaining_epsilon + epsilon_cost).min(budget.total_epsilon);
+        budget.remaining_delta = (budget.remaining_delta + delta_cost).min(budget.total_delta);
+        budget.queries_count = budget.queries_count.saturating_sub(1);
+        if let Some(index) = budget
+            .charges
+            .iter()
+            .rposition(|charge| charge.epsilon == epsilon_cost && charge.delta == delta_cost)
+        {
+            budget.charges.remove(index);
+        }
+        let consumed = budget.consumed_percent();
+        budget
+            .notified_thresholds
+            .retain(|threshold| consumed >= *threshold as f64);
+        log::debug!(
+            "Refunded privacy budget for user {}: ε={:.3}, δ={:.6}",
+            user_id,
+            epsilon_cost,
+            delta_cost
+        );
+    }
+
+    /// Queries denied for lack of budget since startup, across all users
+    pub fn exhaustion_count(&self) -> u64 {
+        self.exhaustions.load(Ordering::Relaxed)
+    }
+
+    pub async fn get_budget_status(&self, user_id: &str) -> Option<UserPrivacyBudget> {
+        let mut budgets = self.user_budgets.write().await;
+        let budget = budgets.get_mut(user_id)?;
+        self.replenish_if_due(user_id, budget, Utc::now());
+        Some(budget.clone())
+    }
+
+    pub async fn reset_budget(&self, user_id: &str) -> Result<()> {
+        let mut budgets = self.user_budgets.write().await;
+        if let Some(budget) = budgets.get_mut(user_id) {
+            budget.reset(Utc::now());
+            log::info!("Reset privacy budget for user {}", user_id);
+        }
+        Ok(())
+    }
+
+    /// Grant additional budget for the current period (admin top-up)
+    pub async fn top_up(
+        &self,
+        user_id: &str,
+        epsilon: f64,
+        delta: f64,
+    ) -> Result<UserPrivacyBudget> {
+        if !epsilon.is_finite() || !delta.is_finite() || epsilon < 0.0 || delta < 0.0 {
+            return Err(Error::Validation(
+                "Top-up amounts must be finite and non-negative".to_string(),
+            ));
+        }
+
+        let mut budgets = self.user_budgets.write().await;
+        let budget = budgets
+            .entry(user_id.to_string())
+            .or_insert_with(|| UserPrivacyBudget::new(self.default_epsilon, self.default_delta));
+
+        budget.remaining_epsilon += epsilon;
+        budget.remaining_delta += delta;
+
+        // Thresholds we've dropped back under may fire again this period
+        let consumed = budget.consumed_percent();
+        budget
+            .notified_thresholds
+            .retain(|threshold| consumed >= *threshold as f64);
+
+        log::info!(
+            "Topped up privacy budget for user {}: +ε={:.3}, +δ={:.6}",
+            user_id,
+            epsilon,
+            delta
+        );
+
+        Ok(budget.clone())
+    }
+
+    /// Apply due replenishments to every tracked user, returning how many were replenished
+    pub async fn apply_scheduled_replenishment(&self) -> usize {
+        let mut budgets = self.user_budgets.write().await;
+        let now = Utc::now();
+        let mut replenished = 0;
+        for (user_id, budget) in budgets.iter_mut() {
+            if self.replenish_if_due(user_id, budget, now) {
+                replenished += 1;
+            }
+        }
+        replenished
+    }
+
+    /// Current budget state of every tracked user, for persisting to the ledger
+    pub async fn snapshot_ledger(&self) -> Vec<PrivacyLedgerEntry> {
+        self.user_budgets
+            .read()
+            .await
+            .iter()
+            .map(|(user_id, budget)| PrivacyLedgerEntry {
+                user_id: user_id.clone(),
+                total_epsilon: budget.total_epsilon,
+                total_delta: budget.total_delta,
+                remaining_epsilon: budget.remaining_epsilon,
+                remaining_delta: budget.remaining_delta,
+                queries_count: budget.queries_count,
+                period_started: budget.period_started.timestamp(),
+            })
+            .collect()
+    }
+
+    /// Load persisted ledger entries. Rolling-window charge history is not
+    /// persisted, so restored budgets only replenish on period boundaries.
+    pub async fn restore_ledger(&self, entries: Vec<PrivacyLedgerEntry>) {
+        let mut budgets = self.user_budgets.write().await;
+        for entry in entries {
+            let mut budget = UserPrivacyBudget::new(entry.total_epsilon, entry.total_delta);
+            budget.remaining_epsilon = entry.remaining_epsilon;
+            budget.remaining_delta = entry.remaining_delta;
+            budget.queries_count = entry.queries_count;
+            if let Some(period_started) = DateTime::from_timestamp(entry.period_started, 0) {
+                budget.period_started = period_started;
+            }
+            budgets.insert(entry.user_id, budget);
+        }
+    }
+
+    /// Drop the budgets and pending notifications of users matching
+    /// `matches`, returning how many budgets were dropped
+    pub async fn forget_users(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let mut budgets = self.user_budgets.write().await;
+        let before = budgets.len();
+        budgets.retain(|user_id, _| !matches(user_id));
+        self.notifications
+            .write()
+            .await
+            .retain(|n| !matches(&n.user_id));
+        before - budgets.len()
+    }
+
+    /// Take pending threshold notifications for a user
+    pub async fn take_notifications(&self, user_id: &str) -> Vec<BudgetNotification> {
+        let mut notifications = self.notifications.write().await;
+        let (taken, kept) = notifications
+            .drain(..)
+            .partition(|n: &BudgetNotification| n.user_id == user_id);
+        *notifications = kept;
+        taken.into_iter().collect()
+    }
+
+    fn replenish_if_due(
+        &self,
+        user_id: &str,
+        budget: &mut UserPrivacyBudget,
+        now: DateTime<Utc>,
+    ) -> bool {
+        match &self.policy.replenishment {
+            ReplenishmentPolicy::Manual => false,
+            ReplenishmentPolicy::Daily | ReplenishmentPolicy::Weekly => {
+                match self.policy.period_start(now) {
+                    Some(period_start) if budget.period_started < period_start => {
+                        budget.reset(period_start);
+                        log::info!("Scheduled privacy budget reset for user {}", user_id);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            ReplenishmentPolicy::RollingWindow(window) => {
+                let mut replenished = false;
+                while let Some(charge) = budget.charges.front() {
+                    if charge.at.elapsed() < *window {
+                        break;
+                    }
+                    budget.remaining_epsilon =
+                        (budget.remaining_epsilon + charge.epsilon).min(budget.total_epsilon);
+                    budget.remaining_delta =
+                        (budget.remaining_delta + charge.delta).min(budget.total_delta);
+                    budget.charges.pop_front();
+                    replenished = true;
+                }
+                if replenished {
+                    let consumed = budget.consumed_percent();
+                    budget
+                        .notified_thresholds
+                        .retain(|threshold| consumed >= *threshold as f64);
+                }
+                replenished
+            }
+        }
+    }
+
+    fn reach_threshold(
+        &self,
+        user_id: &str,
+        budget: &mut UserPrivacyBudget,
+        threshold: u8,
+    ) -> Option<BudgetNotification> {
+        if budget.notified_thresholds.contains(&threshold) {
+            return None;
+        }
+        budget.notified_thresholds.push(threshold);
+
+        log::info!(
+            "Privacy budget for user {} reached {}% consumption",
+            user_id,
+            threshold
+        );
+
+        Some(BudgetNotification {
+            user_id: user_id.to_string(),
+            threshold_percent: threshold,
+            consumed_percent: budget.consumed_percent(),
+            remaining_epsilon: budget.remaining_epsilon,
+            timestamp: Utc::now().timestamp(),
+        })
+    }
+
+    async fn push_notifications(&self, new: Vec<BudgetNotification>) {
+        if new.is_empty() {
+            return;
+        }
+        let mut notifications = self.notifications.write().await;
+        notifications.extend(new);
+        while notifications.len() > 1000 {
+            notifications.pop_front();
+        }
+    }
+}
+
+/// Operations a client key can be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyOperation {
+    Encrypt,
+    Decrypt,
+    /// Encrypted completions over ciphertexts produced with the key
+    Process,
+    Rotate,
+}
+
+impl KeyOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyOperation::Encrypt => "encrypt",
+            KeyOperation::Decrypt => "decrypt",
+            KeyOperation::Process => "process",
+            KeyOperation::Rotate => "rotate",
+        }
+    }
+}
+
+/// Restrictions attached to a client key; an empty list allows everything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyPolicy {
+    pub operations: Vec<KeyOperation>,
+    /// Models ciphertexts produced with the key may be processed by
+    pub models: Vec<String>,
+}
+
+impl KeyPolicy {
+    pub fn check(&self, operation: KeyOperation, model: Option<&str>) -> Result<()> {
+        self.check_operation(operation)?;
+        if operation == KeyOperation::Process && !self.models.is_empty() {
+            match model {
+                Some(model) if self.models.iter().any(|m| m == model) => {}
+                _ => {
+                    return Err(Error::KeyPolicy(format!(
+                        "Key is not allowed to use model {}",
+                        model.unwrap_or("<none>")
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the operation alone, for work that hands nothing to a model
+    pub fn check_operation(&self, operation: KeyOperation) -> Result<()> {
+        if !self.operations.is_empty() && !self.operations.contains(&operation) {
+            return Err(Error::KeyPolicy(format!(
+                "Key is not allowed to {}",
+                operation.as_str()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Holds the policies attached to client keys and checks requests against them
+#[derive(Debug, Default)]
+pub struct KeyPolicyEnforcer {
+    policies: RwLock<HashMap<Uuid, KeyPolicy>>,
+    /// Which restricted keys a ciphertext was produced with, directly or through
+    /// the ciphertexts it was derived from, so work over it can be checked
+    ciphertext_owners: RwLock<HashMap<Uuid, Vec<Uuid>>>,
+    /// Ciphertexts of restricted keys by SHA-256 of their bytes, for requests
+    /// that submit ciphertext bytes instead of naming a ciphertext
+    ciphertext_digests: RwLock<HashMap<Vec<u8>, Uuid>>,
+    violations: AtomicU64,
+}
+
+impl KeyPolicyEnforcer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn attach(&self, client_id: Uuid, policy: KeyPolicy) {
+        self.policies.write().await.insert(client_id, policy);
+    }
+
+    pub async fn policy(&self, client_id: Uuid) -> Option<KeyPolicy> {
+        self.policies.read().await.get(&client_id).cloned()
+    }
+
+    /// Drop the policy of a deleted key along with its ciphertext ownership
+    pub async fn forget(&self, client_id: Uuid) {
+        if self.policies.write().await.remove(&client_id).is_some() {
+            let mut owners = self.ciphertext_owners.write().await;
+            owners.retain(|_, keys| {
+                keys.retain(|key| *key != client_id);
+                !keys.is_empty()
+            });
+            self.ciphertext_digests
+                .write()
+                .await
+                .retain(|_, ciphertext_id| owners.contains_key(ciphertext_id));
+        }
+    }
+
+    /// Whether any key is restricted; without policies there is nothing to enforce
+    pub async fn is_active(&self) -> bool {
+        !self.policies.read().await.is_empty()
+    }
+
+    /// Remember that `client_id` produced `ciphertext`, if the key is restricted
+    pub async fn record_ciphertext(&self, client_id: Uuid, ciphertext: &Ciphertext) {
+        if !self.policies.read().await.contains_key(&client_id) {
+            return;
+        }
+        let mut owners = self.ciphertext_owners.write().await;
+        let keys = owners.entry(ciphertext.id).or_default();
+        if !keys.contains(&client_id) {
+            keys.push(client_id);
+        }
+        self.ciphertext_digests
+            .write()
+            .await
+            .insert(data_digest(&ciphertext.data), ciphertext.id);
+    }
+
+    /// Carry the restrictions of the keys behind a derived ciphertext's inputs
+    /// over to it, so combining ciphertexts doesn't shed them
+    pub async fn record_derived(&self, ciphertext: &Ciphertext, owners: &[Uuid]) {
+        for owner in owners {
+            self.record_ciphertext(*owner, ciphertext).await;
+        }
+    }
+
+    /// Restricted keys behind any of `ciphertext_ids`, each once
+    pub async fn owners_of(&self, ciphertext_ids: &[Uuid]) -> Vec<Uuid> {
+        let owners = self.ciphertext_owners.read().await;
+        let mut keys: Vec<Uuid> = Vec::new();
+        for key in ciphertext_ids
+            .iter()
+            .filter_map(|id| owners.get(id))
+            .flatten()
+        {
+            if !keys.contains(key) {
+                keys.push(*key);
+            }
+        }
+        keys
+    }
+
+    /// Restricted keys behind a ciphertext submitted as bytes
+    pub async fn owners_of_data(&self, data: &[u8]) -> Vec<Uuid> {
+        let ciphertext_id = self
+            .ciphertext_digests
+            .read()
+            .await
+            .get(&data_digest(data))
+            .copied();
+        match ciphertext_id {
+            Some(ciphertext_id) => self.owners_of(&[ciphertext_id]).await,
+            None => Vec::new(),
+        }
+    }
+
+    /// Check `client_id`'s policy, counting violations; unrestricted keys always pass
+    pub async fn authorize(
+        &self,
+        client_id: Uuid,
+        operation: KeyOperation,
+        model: Option<&str>,
+    ) -> Result<()> {
+        let Some(policy) = self.policy(client_id).await else {
+            return Ok(());
+        };
+        policy.check(operation, model).inspect_err(|_| {
+            self.violations.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    /// Check `client_id`'s policy for work that derives new ciphertexts rather
+    /// than handing them to a model; the results keep the key's restrictions
+    pub async fn authorize_operation(
+        &self,
+        client_id: Uuid,
+        operation: KeyOperation,
+    ) -> Result<()> {
+        let Some(policy) = self.policy(client_id).await else {
+            return Ok(());
+        };
+        policy.check_operation(operation).inspect_err(|_| {
+            self.violations.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    pub fn violation_count(&self) -> u64 {
+        self.violations.load(Ordering::Relaxed)
+    }
+}
+
+fn data_digest(data: &[u8]) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, data)
+        .as_ref()
+        .to_vec()
+}
+
+/// Headers transform rules may not touch: credentials, tenant routing and
+/// framing
+const PROTECTED_HEADERS: &[&str] = &[
+    "authorization",
+    "x-api-key",
+    "x-tenant-id",
+    "cookie",
+    "set-cookie",
+    "host",
+    "content-length",
+    "content-type",
+    "transfer-encoding",
+    "connection",
+];
+
+/// Top-level body fields transform rules may not touch: ciphertexts and the
+/// keys and sessions they belong to
+const PROTECTED_FIELDS: &[&str] = &[
+    "ciphertext",
+    "ciphertext_id",
+    "chunks",
+    "client_id",
+    "server_id",
+    "session_id",
+    "signature",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransformRuleStats {
+    pub name: String,
+    pub route: String,
+    pub stage: TransformStage,
+    pub applied: u64,
+}
+
+/// Applies the configured header and field rewrites
+#[derive(Debug)]
+pub struct TransformEngine {
+    config: TransformConfig,
+    applied: Vec<AtomicU64>,
+}
+
+impl TransformEngine {
+    pub fn new(config: TransformConfig) -> Self {
+        let applied = config.rules.iter().map(|_| AtomicU64::new(0)).collect();
+        Self { config, applied }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled && !self.config.rules.is_empty()
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.config.max_body_bytes
+    }
+
+    /// Reject rules that name invalid or protected headers or fields
+    pub fn validate_rule(rule: &TransformRule) -> Result<()> {
+        if !rule.route.starts_with('/') {
+            return Err(Error::Config(format!(
+                "Transform route must be a path prefix: {:?}",
+                rule.route
+            )));
+        }
+        if rule.operations.is_empty() {
+            return Err(Error::Config(
+                "Transform rule has no operations".to_string(),
+            ));
+        }
+        let header = |name: &str| {
+            let parsed = axum::http::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| Error::Config(format!("Invalid header name: {:?}", name)))?;
+            if PROTECTED_HEADERS.contains(&parsed.as_str()) {
+                return Err(Error::Config(format!(
+                    "Header {} cannot be transformed",
+                    parsed
+                )));
+            }
+            Ok(())
+        };
+        let field = |path: &str| {
+            let head = path.split('.').next().unwrap_or_default();
+            if path.split('.').any(str::is_empty) || PROTECTED_FIELDS.contains(&head) {
+                return Err(Error::Config(format!(
+                    "Field {:?} cannot be transformed",
+                    path
+                )));
+            }
+            Ok(())
+        };
+        for operation in &rule.operations {
+            match operation {
+                TransformOperation::SetHeader { name, value } => {
+                    header(name)?;
+                    axum::http::HeaderValue::from_str(&value.replace("{tenant}", "tenant"))
+                        .map_err(|_| Error::Config(format!("Invalid value for header {}", name)))?;
+                }
+                TransformOperation::RemoveHeader { name } => header(name)?,
+                TransformOperation::RenameHeader { from, to } => {
+                    header(from)?;
+                    header(to)?;
+                }
+                TransformOperation::SetField { path, .. }
+                | TransformOperation::RemoveField { path } => field(path)?,
+                TransformOperation::RenameField { from, to } => {
+                    field(from)?;
+                    field(to)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Indexes of the rules for `stage` that apply to `path` and `tenant`
+    pub fn rules_for(&self, stage: TransformStage, path: &str, tenant: Option<&str>) -> Vec<usize> {
+        if !self.config.enabled {
+            return vec![];
+        }
+        self.config
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| {
+                rule.stage == stage
+                    && path.starts_with(&rule.route)
+                    && (rule.tenants.is_empty()
+                        || tenant.is_some_and(|t| rule.tenants.iter().any(|r| r == t)))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Whether any of `rules` rewrites body fields
+    pub fn rewrites_body(&self, rules: &[usize]) -> bool {
+        rules.iter().any(|&i| {
+            self.config.rules[i].operations.iter().any(|op| {
+                matches!(
+                    op,
+                    TransformOperation::SetField { .. }
+                        | TransformOperation::RemoveField { .. }
+                        | TransformOperation::RenameField { .. }
+                )
+            })
+        })
+    }
+
+    /// Apply `rules` in order; field operations are skipped without a JSON body
+    pub fn apply(
+        &self,
+        rules: &[usize],
+        tenant: Option<&str>,
+        headers: &mut HeaderMap,
+        mut body: Option<&mut serde_json::Value>,
+    ) {
+        for &i in rules {
+            let rule = &self.config.rules[i];
+            for operation in &rule.operations {
+                match operation {
+                    TransformOperation::SetHeader { name, value } => {
+                        let value = value.replace("{tenant}", tenant.unwrap_or_default());
+                        if let (Ok(name), Ok(value)) = (
+                            axum::http::HeaderName::from_bytes(name.as_bytes()),
+                            axum::http::HeaderValue::from_str(&value),
+                        ) {
+                            headers.insert(name, value);
+                        }
+                    }
+                    TransformOperation::RemoveHeader { name } => {
+                        headers.remove(name.as_str());
+                    }
+                    TransformOperation::RenameHeader { from, to } => {
+                        if let (Some(value), Ok(to)) = (
+                            headers.remove(from.as_str()),
+                            axum::http::HeaderName::from_bytes(to.as_bytes()),
+                        ) {
+                            headers.insert(to, value);
+                        }
+                    }
+                    TransformOperation::SetField { path, value } => {
+                        if let Some(body) = body.as_deref_mut() {
+                            insert_field(body, path, value.clone());
+                        }
+                    }
+                    TransformOperation::RemoveField { path } => {
+                        if let Some(body) = body.as_deref_mut() {
+                            take_field(body, path);
+                        }
+                    }
+                    TransformOperation::RenameField { from, to } => {
+                        if let Some(body) = body.as_deref_mut() {
+                            if let Some(value) = take_field(body, from) {
+                                insert_field(body, to, value);
+                            }
+                        }
+                    }
+                }
+            }
+            self.applied[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> Vec<TransformRuleStats> {
+        self.config
+            .rules
+            .iter()
+            .zip(&self.applied)
+            .map(|(rule, applied)| TransformRuleStats {
+                name: rule.name.clone(),
+                route: rule.route.clone(),
+                stage: rule.stage,
+                applied: applied.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+fn take_field(body: &mut serde_json::Value, path: &str) -> Option<serde_json::Value> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (
+            parent
+                .split('.')
+                .try_fold(&mut *body, |value, key| value.get_mut(key))?,
+            key,
+        ),
+        None => (body, path),
+    };
+    parent.as_object_mut()?.remove(key)
+}
+
+fn insert_field(body: &mut serde_json::Value, path: &str, value: serde_json::Value) {
+    let mut target = body;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        let Some(object) = target.as_object_mut() else {
+            return;
+        };
+        if keys.peek().is_none() {
+            object.insert(key.to_string(), value);
+            return;
+        }
+        target = object
+            .entry(key)
+            .or_insert_with(|| serde_json::Value::Object(Default::default()));
+    }
+}
+
+/// Enhanced input sanitization utilities with security focus
+pub fn sanitize_text_input(input: &str) -> String {
+    // Remove potential injection patterns and normalize input
+    let sanitized = input
+        .chars()
+        .filter(|c| {
+            c.is_ascii()
+            && (!c.is_control() || c.is_whitespace())
+            && *c != '\0' // Null byte protection
+            && !matches!(*c, '\x01'..='\x08' | '\x0B'..='\x0C' | '\x0E'..='\x1F' | '\x7F')
+        })
+        .take(10_000) // Limit input size
+        .collect::<String>();
+
+    // Additional security filters
+    let result = sanitized
+        .replace("javascript:", "")
+        .replace("data:", "")
+        .replace("vbscript:", "")
+        .replace("<script", "&lt;script")
+        .replace("</script", "&lt;/script");
+
+    result.trim().to_string()
+}
+
+/// Advanced threat detection for input validation
+pub fn detect_threats(input: &str) -> Vec<String> {
+    let mut threats = Vec::new();
+    let lower_input = input.to_lowercase();
+
+    // SQL injection patterns
+    let sql_patterns = [
+        "select ", "union ", "insert ", "delete ", "drop ", "exec ", "script",
+    ];
+    for pattern in &sql_patterns {
+        if lower_input.contains(pattern) {
+            threats.push(format!("Potential SQL injection: {}", pattern));
+        }
+    }
+
+    // XSS patterns
+    let xss_patterns = ["<script", "javascript:", "onload=", "onerror=", "onclick="];
+    for pattern in &xss_patterns {
+        if lower_input.contains(pattern) {
+            threats.push(format!("Potential XSS: {}", pattern));
+        }
+    }
+
+    // Command injection patterns
+    let cmd_patterns = ["; ", "| ", "& ", "$(", "`", "exec("];
+    for pattern in &cmd_patterns {
+        if lower_input.contains(pattern) {
+            threats.push(format!("Potential command injection: {}", pattern));
+        }
+    }
+
+    threats
+}
+
+/// Validate UUID format
+pub fn validate_uuid(uuid_str: &str) -> Result<Uuid> {
+    uuid_str
+        .parse::<Uuid>()
+        .map_err(|_| Error::Validation(format!("Invalid UUID format: {}", uuid_str)))
+}
+
+/// Validate encryption parameters
+pub fn validate_fhe_params(poly_degree: usize, security_level: u8) -> Result<()> {
+    if !poly_degree.is_power_of_two() {
+        return Err(Error::Validation(
+            "Polynomial modulus degree must be a power of 2".to_string(),
+        ));
+    }
+
+    if poly_degree < 1024 || poly_degree > 32768 {
+        return Err(Error::Validation(
+            "Polynomial modulus degree must be between 1024 and 32768".to_string(),
+        ));
+    }
+
+    if security_level != 128 && security_level != 192 {
+        return Err(Error::Validation(
+            "Security level must be 128 or 192".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe::FheParams;
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2); // 2 requests per minute
+        let client_ip = "192.168.1.1";
+
+        // First two requests should succeed
+        assert!(limiter.check_rate_limit(client_ip).await.unwrap());
+        assert!(limiter.check_rate_limit(client_ip).await.unwrap());
+
+        // Third request should be blocked
+        assert!(!limiter.check_rate_limit(client_ip).await.unwrap());
+
+        // Check stats
+        let (requests, _) = limiter.get_client_stats(client_ip).await.unwrap();
+        assert_eq!(requests, 3); // Including the blocked request
+    }
+
+    #[tokio::test]
+    async fn test_privacy_budget_tracker() {
+        let tracker = PrivacyBudgetTracker::new(1.0, 1e-5);
+        let user_id = "test_user";
+
+        // Should allow query within budget
+        assert!(tracker.check_budget(user_id, 0.1, 1e-6).await.unwrap());
+
+        // Should block query that exceeds budget
+        assert!(!tracker.check_budget(user_id, 1.0, 1e-5).await.unwrap());
+
+        // Reset and try again
+        tracker.reset_budget(user_id).await.unwrap();
+        assert!(tracker.check_budget(user_id, 0.5, 5e-6).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rolling_window_replenishment() {
+        let tracker = PrivacyBudgetTracker::new(1.0, 1e-5).with_policy(PrivacyBudgetPolicy {
+            replenishment: ReplenishmentPolicy::RollingWindow(Duration::from_millis(50)),
+            ..PrivacyBudgetPolicy::default()
+        });
+        let user_id = "rolling_user";
+
+        assert!(tracker.check_budget(user_id, 1.0, 1e-6).await.unwrap());
+        assert!(!tracker.check_budget(user_id, 0.1, 1e-6).await.unwrap());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(tracker.apply_scheduled_replenishment().await, 1);
+        assert!(tracker.check_budget(user_id, 0.1, 1e-6).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_budget_top_up_and_notifications() {
+        let tracker = PrivacyBudgetTracker::new(1.0, 1e-5);
+        let user_id = "notified_user";
+
+        assert!(tracker.check_budget(user_id, 0.5, 1e-6).await.unwrap());
+        let notifications = tracker.take_notifications(user_id).await;
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].threshold_percent, 50);
+
+        // A charge that doesn't fit is refused without claiming exhaustion
+        assert!(!tracker.check_budget(user_id, 0.75, 1e-6).await.unwrap());
+        assert!(tracker.take_notifications(user_id).await.is_empty());
+        assert!(tracker.check_budget(user_id, 0.5, 1e-6).await.unwrap());
+        let thresholds: Vec<u8> = tracker
+            .take_notifications(user_id)
+            .await
+            .iter()
+            .map(|n| n.threshold_percent)
+            .collect();
+        assert_eq!(thresholds, [80, 100]);
+
+        // Refunded charges can cross thresholds again
+        tracker.refund(user_id, 0.5, 1e-6).await;
+        let budget = tracker.get_budget_status(user_id).await.unwrap();
+        assert_eq!(budget.queries_count, 1);
+        assert!((budget.remaining_epsilon - 0.5).abs() < 1e-9);
+        assert_eq!(budget.notified_thresholds, [50]);
+
+        let budget = tracker.top_up(user_id, 0.5, 0.0).await.unwrap();
+        assert!((budget.remaining_epsilon - 1.0).abs() < 1e-9);
+        assert!(tracker.check_budget(user_id, 0.5, 1e-6).await.unwrap());
+        assert!(tracker.top_up(user_id, -1.0, 0.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_grace_period_after_reset() {
+        let tracker = PrivacyBudgetTracker::new(1.0, 1e-5);
+        let user_id = "grace_user";
+
+        let started_at = Instant::now();
+        assert!(tracker.check_budget(user_id, 0.5, 1e-6).await.unwrap());
+        tracker.reset_budget(user_id).await.unwrap();
+
+        // In-flight request from before the reset is not charged to the new period
+        assert!(tracker
+            .check_budget_started_at(user_id, 0.5, 1e-6, started_at)
+            .await
+            .unwrap());
+        let budget = tracker.get_budget_status(user_id).await.unwrap();
+        assert_eq!(budget.remaining_epsilon, 1.0);
+    }
+
+    #[test]
+    fn test_input_sanitization() {
+        let malicious_input = "Hello\x00World\x1F\nValid text";
+        let sanitized = sanitize_text_input(malicious_input);
+        assert_eq!(sanitized, "HelloWorld\nValid text");
+
+        // Test length limiting
+        let long_input = "a".repeat(20000);
+        let sanitized_long = sanitize_text_input(&long_input);
+        assert_eq!(sanitized_long.len(), 10000);
+    }
+
+    #[test]
+    fn test_uuid_validation() {
+        let valid_uuid = "550e8400-e29b-41d4-a716-446655440000";
+        assert!(validate_uuid(valid_uuid).is_ok());
+
+        let invalid_uuid = "not-a-uuid";
+        assert!(validate_uuid(invalid_uuid).is_err());
+    }
+
+    #[test]
+    fn test_fhe_params_validation() {
+        // Valid parameters
+        assert!(validate_fhe_params(16384, 128).is_ok());
+
+        // Invalid polynomial degree (not power of 2)
+        assert!(validate_fhe_params(15000, 128).is_err());
+
+        // Invalid security level
+        assert!(validate_fhe_params(16384, 100).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_key_policy_enforcement() {
+        let enforcer = KeyPolicyEnforcer::new();
+        let encrypt_only = Uuid::new_v4();
+        let model_bound = Uuid::new_v4();
+        let unrestricted = Uuid::new_v4();
+        assert!(!enforcer.is_active().await);
+
+        enforcer
+            .attach(
+                encrypt_only,
+                KeyPolicy {
+                    operations: vec![KeyOperation::Encrypt],
+                    models: Vec::new(),
+                },
+            )
+            .await;
+        enforcer
+            .attach(
+                model_bound,
+                KeyPolicy {
+                    operations: vec![KeyOperation::Encrypt, KeyOperation::Process],
+                    models: vec!["gpt-4".to_string()],
+                },
+            )
+            .await;
+
+        assert!(enforcer
+            .authorize(encrypt_only, KeyOperation::Encrypt, None)
+            .await
+            .is_ok());
+        let err = enforcer
+            .authorize(encrypt_only, KeyOperation::Decrypt, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.category(), "key_policy");
+        assert!(enforcer
+            .authorize(model_bound, KeyOperation::Process, Some("gpt-4"))
+            .await
+            .is_ok());
+        assert!(enforcer
+            .authorize(model_bound, KeyOperation::Process, Some("claude-3"))
+            .await
+            .is_err());
+        assert!(enforcer
+            .authorize(unrestricted, KeyOperation::Decrypt, None)
+            .await
+            .is_ok());
+        assert_eq!(enforcer.violation_count(), 2);
+
+        // Combining ciphertexts only needs the operation; the model restriction
+        // travels with the result
+        assert!(enforcer
+            .authorize_operation(model_bound, KeyOperation::Process)
+            .await
+            .is_ok());
+        assert!(enforcer
+            .authorize_operation(encrypt_only, KeyOperation::Process)
+            .await
+            .is_err());
+
+        // Ownership is only tracked for restricted keys, follows derived
+        // ciphertexts and goes away with the key
+        let ciphertext = |data: &[u8]| Ciphertext {
+            id: Uuid::new_v4(),
+            data: data.to_vec(),
+            params: FheParams::default(),
+            noise_budget: None,
+        };
+        let (restricted_ct, open_ct, derived_ct) = (
+            ciphertext(b"restricted"),
+            ciphertext(b"open"),
+            ciphertext(b"derived"),
+        );
+        enforcer
+            .record_ciphertext(model_bound, &restricted_ct)
+            .await;
+        enforcer.record_ciphertext(unrestricted, &open_ct).await;
+        let owners = enforcer.owners_of(&[restricted_ct.id, open_ct.id]).await;
+        assert_eq!(owners, vec![model_bound]);
+        enforcer.record_derived(&derived_ct, &owners).await;
+        assert_eq!(
+            enforcer.owners_of(&[derived_ct.id]).await,
+            vec![model_bound]
+        );
+        assert_eq!(enforcer.owners_of_data(b"derived").await, vec![model_bound]);
+        assert!(enforcer.owners_of_data(b"open").await.is_empty());
+        enforcer.forget(model_bound).await;
+        assert!(enforcer.owners_of(&[restricted_ct.id]).await.is_empty());
+        assert!(enforcer.owners_of_data(b"restricted").await.is_empty());
+    }
+
+    #[test]
+    fn test_transform_rules_rewrite_headers_and_fields() {
+        let rule = |name: &str, stage, tenants: &[&str], operations| TransformRule {
+            name: name.to_string(),
+            route: "/v1/chat".to_string(),
+            tenants: tenants.iter().map(|t| t.to_string()).collect(),
+            stage,
+            operations,
+        };
+        let inject = rule(
+            "inject",
+            TransformStage::Request,
+            &["acme"],
+            vec![
+                TransformOperation::SetHeader {
+                    name: "x-org".to_string(),
+                    value: "org-{tenant}".to_string(),
+                },
+                TransformOperation::RenameField {
+                    from: "meta.trace".to_string(),
+                    to: "trace_id".to_string(),
+                },
+            ],
+        );
+        let strip = rule(
+            "strip",
+            TransformStage::Response,
+            &[],
+            vec![
+                TransformOperation::RemoveField {
+                    path: "internal".to_string(),
+                },
+                TransformOperation::SetField {
+                    path: "meta.proxy".to_string(),
+                    value: serde_json::json!("fhe"),
+                },
+            ],
+        );
+        assert!(TransformEngine::validate_rule(&inject).is_ok());
+        // Credentials and ciphertexts are off limits
+        let unsafe_header = rule(
+            "auth",
+            TransformStage::Request,
+            &[],
+            vec![TransformOperation::RemoveHeader {
+                name: "Authorization".to_string(),
+            }],
+        );
+        assert!(TransformEngine::validate_rule(&unsafe_header).is_err());
+        let unsafe_field = rule(
+            "ct",
+            TransformStage::Request,
+            &[],
+            vec![TransformOperation::RemoveField {
+                path: "ciphertext".to_string(),
+            }],
+        );
+        assert!(TransformEngine::validate_rule(&unsafe_field).is_err());
+
+        let engine = TransformEngine::new(TransformConfig {
+            enabled: true,
+            rules: vec![inject, strip],
+            ..TransformConfig::default()
+        });
+        let path = "/v1/chat/completions";
+        assert!(engine
+            .rules_for(TransformStage::Request, path, Some("other"))
+            .is_empty());
+        assert!(engine
+            .rules_for(TransformStage::Request, "/v1/encrypt", Some("acme"))
+            .is_empty());
+
+        let rules = engine.rules_for(TransformStage::Request, path, Some("acme"));
+        let mut headers = HeaderMap::new();
+        let mut body = serde_json::json!({ "meta": { "trace": "t-1" }, "model": "gpt-4" });
+        engine.apply(&rules, Some("acme"), &mut headers, Some(&mut body));
+        assert_eq!(headers["x-org"], "org-acme");
+        assert_eq!(
+            body,
+            serde_json::json!({ "meta": {}, "trace_id": "t-1", "model": "gpt-4" })
+        );
+
+        let rules = engine.rules_for(TransformStage::Response, path, None);
+        let mut body = serde_json::json!({ "internal": { "node": 3 }, "text": "ct" });
+        engine.apply(&rules, None, &mut HeaderMap::new(), Some(&mut body));
+        assert_eq!(
+            body,
+            serde_json::json!({ "meta": { "proxy": "fhe" }, "text": "ct" })
+        );
+        let applied: Vec<u64> = engine.stats().iter().map(|r| r.applied).collect();
+        assert_eq!(applied, vec![1, 1]);
+    }
+}
@@ -0,0 +1,402 @@
+//! Versioned schema migrations for the storage backends
+//!
+//! Each backend lists its migrations oldest first and records which ones it
+//! has applied. The runner applies the pending ones at startup. Replicas
+//! sharing a store coordinate through a lease kept in the store itself: the
+//! replica holding it migrates while the others wait until the schema is
+//! current. Migrations that drop or rewrite data are preceded by a backup.
+
+use crate::config::StorageMigrationConfig;
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// One schema change of a backend
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    /// Drops or rewrites data, so the store is backed up first
+    pub destructive: bool,
+    /// Backend-specific statements, e.g. SQL
+    pub statements: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    /// Unknown for migrations applied before history was kept
+    pub applied_at: Option<i64>,
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PendingMigration {
+    pub version: u32,
+    pub name: String,
+    pub destructive: bool,
+}
+
+impl From<&Migration> for PendingMigration {
+    fn from(migration: &Migration) -> Self {
+        Self {
+            version: migration.version,
+            name: migration.name.to_string(),
+            destructive: migration.destructive,
+        }
+    }
+}
+
+/// Schema versioning of a store. Backends without a schema keep the defaults.
+pub trait MigrationTarget {
+    /// Migrations this backend knows, oldest first
+    fn migrations(&self) -> &'static [Migration] {
+        &[]
+    }
+
+    /// Migrations applied to the store, oldest first
+    fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        Ok(Vec::new())
+    }
+
+    /// Apply `migration`, which must be the next one, and record it
+    fn apply_migration(&self, migration: &Migration) -> Result<()> {
+        Err(Error::Internal(format!(
+            "Storage backend has no schema to apply migration {} to",
+            migration.version
+        )))
+    }
+
+    /// Copy the whole store into `dir`, returning the path of the copy
+    fn backup(&self, _dir: &Path) -> Result<PathBuf> {
+        Err(Error::Internal(
+            "Storage backend does not support backups".to_string(),
+        ))
+    }
+
+    /// Take or renew the migration lease; false while another holder's lease is live
+    fn try_lock_migrations(&self, _holder: &str, _lease: Duration) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn unlock_migrations(&self, _holder: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Applied and pending migrations of a store
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub current_version: u32,
+    pub latest_version: u32,
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<PendingMigration>,
+}
+
+pub fn status<T: MigrationTarget + ?Sized>(target: &T) -> Result<MigrationStatus> {
+    let applied = target.applied_migrations()?;
+    let current_version = applied.last().map_or(0, |m| m.version);
+    let migrations = target.migrations();
+    Ok(MigrationStatus {
+        current_version,
+        latest_version: migrations.last().map_or(0, |m| m.version),
+        applied,
+        pending: migrations
+            .iter()
+            .filter(|m| m.version > current_version)
+            .map(PendingMigration::from)
+            .collect(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationOutcome {
+    UpToDate,
+    /// Dry run: nothing was changed
+    Planned,
+    Applied,
+    /// Another replica held the lock and brought the schema up to date
+    AppliedByPeer,
+}
+
+/// What a run of the migration runner did
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub holder: String,
+    pub outcome: MigrationOutcome,
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Migrations applied by this run, or that a dry run would apply
+    pub migrations: Vec<PendingMigration>,
+    /// Copies of the store taken before destructive migrations
+    pub backups: Vec<String>,
+    pub finished_at: i64,
+}
+
+/// Lock holder name for this process; unique even when replicas share a region
+pub fn replica_holder(region: &str) -> String {
+    format!("{}:{}", region, Uuid::new_v4())
+}
+
+/// Brings a store's schema up to date, coordinating with other replicas
+#[derive(Debug)]
+pub struct MigrationRunner {
+    config: StorageMigrationConfig,
+    holder: String,
+    dry_run: bool,
+    poll_interval: Duration,
+}
+
+impl MigrationRunner {
+    pub fn new(config: StorageMigrationConfig, holder: impl Into<String>) -> Self {
+        Self {
+            config,
+            holder: holder.into(),
+            dry_run: false,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Report what would be applied without touching the store
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn run<T: MigrationTarget + ?Sized>(&self, target: &T) -> Result<MigrationReport> {
+        let initial = status(target)?;
+        if initial.pending.is_empty() {
+            return Ok(self.report(&initial, MigrationOutcome::UpToDate, Vec::new()));
+        }
+        if self.dry_run {
+            let planned = initial.pending.clone();
+            return Ok(self.report(&initial, MigrationOutcome::Planned, planned));
+        }
+        if !self.config.auto_apply {
+            return Err(Error::Config(format!(
+                "{} storage migrations pending (schema version {} of {}); run `fhe-proxy migrate`",
+                initial.pending.len(),
+                initial.current_version,
+                initial.latest_version
+            )));
+        }
+
+        let lease = Duration::from_secs(self.config.lock_lease_seconds);
+        let wait = Duration::from_secs(self.config.wait_timeout_seconds);
+        let started = Instant::now();
+        while !target.try_lock_migrations(&self.holder, lease)? {
+            let current = status(target)?;
+            if current.pending.is_empty() {
+                let mut report = self.report(&current, MigrationOutcome::AppliedByPeer, Vec::new());
+                report.from_version = initial.current_version;
+                return Ok(report);
+            }
+            if started.elapsed() >= wait {
+                return Err(Error::Timeout(format!(
+                    "Another replica held the storage migration lock for over {}s",
+                    wait.as_secs()
+                )));
+            }
+            log::info!("Waiting for another replica to finish storage migrations");
+            std::thread::sleep(self.poll_interval);
+        }
+
+        let result = self.apply_pending(target, lease, initial.current_version);
+        if let Err(e) = target.unlock_migrations(&self.holder) {
+            log::warn!("Failed to release the storage migration lock: {}", e);
+        }
+        result
+    }
+
+    fn apply_pending<T: MigrationTarget + ?Sized>(
+        &self,
+        target: &T,
+        lease: Duration,
+        from_version: u32,
+    ) -> Result<MigrationReport> {
+        // Another replica may have finished between our status check and the lock
+        let current = status(target)?;
+        let mut applied = Vec::new();
+        let mut backups = Vec::new();
+        for pending in &current.pending {
+            let Some(migration) = target
+                .migrations()
+                .iter()
+                .find(|m| m.version == pending.version)
+            else {
+                continue;
+            };
+            if !target.try_lock_migrations(&self.holder, lease)? {
+                return Err(Error::Concurrency(format!(
+                    "Lost the storage migration lock before migration {}",
+                    migration.version
+                )));
+            }
+            if migration.destructive {
+                if self.config.backup_dir.is_empty() {
+                    log::warn!(
+                        "Applying destructive storage migration {} without a backup",
+                        migration.version
+                    );
+                } else {
+                    let path = target.backup(Path::new(&self.config.backup_dir))?;
+                    log::info!("Backed up storage to {}", path.display());
+                    backups.push(path.display().to_string());
+                }
+            }
+            target.apply_migration(migration)?;
+            log::info!(
+                "Applied storage migration {} ({})",
+                migration.version,
+                migration.name
+            );
+            applied.push(PendingMigration::from(migration));
+        }
+
+        let mut report = self.report(&status(target)?, MigrationOutcome::Applied, applied);
+        report.from_version = from_version;
+        report.backups = backups;
+        Ok(report)
+    }
+
+    fn report(
+        &self,
+        status: &MigrationStatus,
+        outcome: MigrationOutcome,
+        migrations: Vec<PendingMigration>,
+    ) -> MigrationReport {
+        MigrationReport {
+            holder: self.holder.clone(),
+            outcome,
+            from_version: status.current_version,
+            to_version: match outcome {
+                MigrationOutcome::Planned => status.latest_version,
+                _ => status.current_version,
+            },
+            migrations,
+            backups: Vec::new(),
+            finished_at: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            name: "create_sessions",
+            destructive: false,
+            statements: "",
+        },
+        Migration {
+            version: 2,
+            name: "drop_legacy_sessions",
+            destructive: true,
+            statements: "",
+        },
+    ];
+
+    #[derive(Default)]
+    struct FakeStore {
+        applied: Mutex<Vec<AppliedMigration>>,
+        lock: Mutex<Option<String>>,
+        backups: Mutex<Vec<u32>>,
+    }
+
+    impl MigrationTarget for FakeStore {
+        fn migrations(&self) -> &'static [Migration] {
+            MIGRATIONS
+        }
+
+        fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+            Ok(self.applied.lock().unwrap().clone())
+        }
+
+        fn apply_migration(&self, migration: &Migration) -> Result<()> {
+            self.applied.lock().unwrap().push(AppliedMigration {
+                version: migration.version,
+                name: migration.name.to_string(),
+                applied_at: Some(0),
+                duration_ms: Some(0),
+            });
+            Ok(())
+        }
+
+        fn backup(&self, dir: &Path) -> Result<PathBuf> {
+            let version = self.applied.lock().unwrap().len() as u32;
+            self.backups.lock().unwrap().push(version);
+            Ok(dir.join(format!("v{}.db", version)))
+        }
+
+        fn try_lock_migrations(&self, holder: &str, _lease: Duration) -> Result<bool> {
+            let mut lock = self.lock.lock().unwrap();
+            let free = lock.as_deref().is_none_or(|h| h == holder);
+            if free {
+                *lock = Some(holder.to_string());
+            }
+            Ok(free)
+        }
+
+        fn unlock_migrations(&self, holder: &str) -> Result<()> {
+            let mut lock = self.lock.lock().unwrap();
+            if lock.as_deref() == Some(holder) {
+                *lock = None;
+            }
+            Ok(())
+        }
+    }
+
+    fn config() -> StorageMigrationConfig {
+        StorageMigrationConfig {
+            wait_timeout_seconds: 1,
+            ..StorageMigrationConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_migration_runner() {
+        let store = FakeStore::default();
+
+        // A dry run plans both migrations and changes nothing
+        let report = MigrationRunner::new(config(), "eu:a")
+            .dry_run(true)
+            .run(&store)
+            .unwrap();
+        assert_eq!(report.outcome, MigrationOutcome::Planned);
+        assert_eq!((report.from_version, report.to_version), (0, 2));
+        assert!(store.applied_migrations().unwrap().is_empty());
+
+        // Manual mode refuses to start with pending migrations
+        let manual = StorageMigrationConfig {
+            auto_apply: false,
+            ..config()
+        };
+        assert!(MigrationRunner::new(manual, "eu:a").run(&store).is_err());
+
+        // While another replica holds the lock, this one waits and then gives up
+        *store.lock.lock().unwrap() = Some("us:b".to_string());
+        assert!(MigrationRunner::new(config(), "eu:a").run(&store).is_err());
+        *store.lock.lock().unwrap() = None;
+
+        // The destructive migration is preceded by a backup of schema version 1
+        let report = MigrationRunner::new(config(), "eu:a").run(&store).unwrap();
+        assert_eq!(report.outcome, MigrationOutcome::Applied);
+        assert_eq!((report.from_version, report.to_version), (0, 2));
+        assert_eq!(report.migrations.len(), 2);
+        assert_eq!(report.backups.len(), 1);
+        assert_eq!(*store.backups.lock().unwrap(), vec![1]);
+        assert!(store.lock.lock().unwrap().is_none());
+
+        let report = MigrationRunner::new(config(), "us:b").run(&store).unwrap();
+        assert_eq!(report.outcome, MigrationOutcome::UpToDate);
+        assert!(status(&store).unwrap().pending.is_empty());
+    }
+}
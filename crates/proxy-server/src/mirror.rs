@@ -0,0 +1,230 @@
+//! Traffic mirroring to a staging proxy
+//!
+//! A sample of production completion requests is copied, ciphertext inline,
+//! to a staging deployment so new builds see realistic load and inputs. Copies
+//! are queued on a bounded channel and sent fire-and-forget: the request path
+//! never waits on staging, a full queue drops the copy, and staging responses
+//! are discarded unread. Nothing decrypted ever leaves through the mirror.
+
+use crate::config::MirroringConfig;
+use reqwest::Client as HttpClient;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+
+/// Marks a mirrored copy; requests carrying it are never mirrored again
+pub const MIRROR_HEADER: &str = "x-fhe-mirror";
+
+/// Request headers forwarded with a copy. Credentials are not: staging ones
+/// come from `MirroringConfig::headers`.
+const FORWARDED_HEADERS: &[&str] = &["x-tenant-id", "x-request-priority", "x-client-region"];
+
+/// One copy waiting to be sent
+#[derive(Debug)]
+pub struct MirroredRequest {
+    pub path: &'static str,
+    pub headers: Vec<(String, String)>,
+    pub body: serde_json::Value,
+}
+
+#[derive(Debug, Default)]
+struct MirrorCounters {
+    queued: AtomicU64,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MirrorStats {
+    pub enabled: bool,
+    pub target_url: String,
+    pub sample_percent: f64,
+    pub queued: u64,
+    pub sent: u64,
+    /// Copies shed because the queue was full
+    pub dropped: u64,
+    /// Copies staging refused or never answered
+    pub failed: u64,
+}
+
+#[derive(Debug)]
+pub struct RequestMirror {
+    config: MirroringConfig,
+    sender: mpsc::Sender<MirroredRequest>,
+    receiver: Mutex<Option<mpsc::Receiver<MirroredRequest>>>,
+    counters: Arc<MirrorCounters>,
+}
+
+impl RequestMirror {
+    pub fn new(config: MirroringConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+        Self {
+            config,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            counters: Arc::new(MirrorCounters::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Staging side: whether mirrored copies may carry their ciphertext inline
+    pub fn accepts_mirrored(&self) -> bool {
+        self.config.accept_mirrored
+    }
+
+    /// Whether a request with `headers` should be copied; mirrored copies never are
+    pub fn should_mirror(&self, headers: &axum::http::HeaderMap) -> bool {
+        self.is_enabled()
+            && !headers.contains_key(MIRROR_HEADER)
+            && rand::random::<f64>() * 100.0 < self.config.sample_percent
+    }
+
+    /// Queue a copy of a request to `path`, dropping it if the queue is full
+    pub fn mirror(
+        &self,
+        path: &'static str,
+        headers: &axum::http::HeaderMap,
+        body: serde_json::Value,
+    ) {
+        let headers = FORWARDED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = headers.get(*name)?.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        match self.sender.try_send(MirroredRequest {
+            path,
+            headers,
+            body,
+        }) {
+            Ok(()) => {
+                self.counters.queued.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> MirrorStats {
+        MirrorStats {
+            enabled: self.config.enabled,
+            target_url: self.config.target_url.clone(),
+            sample_percent: self.config.sample_percent,
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Start the sending task; a no-op when mirroring is disabled or already running
+    pub fn spawn(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Some(mut receiver) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+
+        let config = self.config.clone();
+        let counters = self.counters.clone();
+        let client = HttpClient::new();
+        let in_flight = Arc::new(Semaphore::new(config.max_in_flight.max(1)));
+        tokio::spawn(async move {
+            let base = config.target_url.trim_end_matches('/').to_string();
+            while let Some(copy) = receiver.recv().await {
+                // Waiting here backs the queue up, so a slow staging sheds copies
+                let Ok(permit) = in_flight.clone().acquire_owned().await else {
+                    break;
+                };
+                let mut request = client
+                    .post(format!("{}{}", base, copy.path))
+                    .timeout(Duration::from_secs(config.timeout_seconds))
+                    .header(MIRROR_HEADER, "1")
+                    .json(&copy.body);
+                let forwarded = copy.headers.iter().map(|(name, value)| (name, value));
+                for (name, value) in forwarded.chain(&config.headers) {
+                    request = request.header(name, value);
+                }
+
+                let counters = counters.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    match request
+                        .send()
+                        .await
+                        .and_then(|response| response.error_for_status())
+                    {
+                        Ok(_) => {
+                            counters.sent.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(e) => {
+                            log::debug!("Mirrored request failed: {}", e);
+                            counters.failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderMap;
+
+    #[test]
+    fn test_mirror_sampling_and_bounded_queue() {
+        let mirror = RequestMirror::new(MirroringConfig {
+            enabled: true,
+            target_url: "http://staging.invalid".to_string(),
+            sample_percent: 100.0,
+            queue_size: 2,
+            ..MirroringConfig::default()
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        headers.insert("x-api-key", "production-secret".parse().unwrap());
+        assert!(mirror.should_mirror(&headers));
+
+        // Copies are never mirrored again
+        let mut mirrored = headers.clone();
+        mirrored.insert(MIRROR_HEADER, "1".parse().unwrap());
+        assert!(!mirror.should_mirror(&mirrored));
+
+        // Nothing drains the queue before spawn(), so the third copy is dropped
+        for _ in 0..3 {
+            mirror.mirror("/v1/chat/completions", &headers, serde_json::json!({}));
+        }
+        let stats = mirror.stats();
+        assert_eq!(stats.queued, 2);
+        assert_eq!(stats.dropped, 1);
+
+        // Production credentials stay behind
+        let copy = mirror
+            .receiver
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .try_recv()
+            .unwrap();
+        assert_eq!(
+            copy.headers,
+            vec![("x-tenant-id".to_string(), "acme".to_string())]
+        );
+
+        let disabled = RequestMirror::new(MirroringConfig::default());
+        assert!(!disabled.should_mirror(&headers));
+    }
+}
@@ -0,0 +1,1910 @@
+//! Monitoring, health checks, and observability
+
+use crate::backfill::{BackfillBucket, BackfillReport};
+use crate::config::{
+    ExportEndpoints, GeoLatencyConfig, OtlpMetricsConfig, RunbookAction, RunbookTrigger,
+    RunbooksConfig, SlaClass,
+};
+use crate::error::{Error, Result};
+use crate::fhe::FheEngine;
+use crate::middleware::MetricsSnapshot;
+use crate::siem::otlp_attribute;
+use reqwest::Client as HttpClient;
+// Axum imports removed as they're not used in this module
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Health check status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthStatus {
+    pub status: String,
+    pub timestamp: u64,
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub components: HashMap<String, ComponentHealth>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub status: String,
+    pub last_check: u64,
+    pub response_time_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+/// Detailed system metrics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemMetrics {
+    pub timestamp: u64,
+    pub uptime_seconds: u64,
+    pub requests: MetricsSnapshot,
+    pub fhe_operations: FheMetrics,
+    pub system_resources: ResourceMetrics,
+    pub errors: ErrorMetrics,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FheMetrics {
+    pub active_sessions: u64,
+    pub cached_ciphertexts: u64,
+    pub key_generations: u64,
+    pub encryption_time_avg_ms: f64,
+    pub decryption_time_avg_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceMetrics {
+    pub memory_usage_mb: f64,
+    pub cpu_usage_percent: f64,
+    pub disk_usage_mb: f64,
+    pub network_connections: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorMetrics {
+    pub error_rate_per_minute: f64,
+    pub error_types: HashMap<String, u64>,
+    pub last_error: Option<String>,
+    pub last_error_timestamp: Option<u64>,
+}
+
+/// Health check and monitoring service
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct MonitoringService {
+    start_time: Instant,
+    version: String,
+    components: Arc<RwLock<HashMap<String, ComponentHealth>>>,
+    error_tracker: Arc<RwLock<Vec<ErrorEvent>>>,
+    alert_thresholds: AlertThresholds,
+    alert_state: Arc<RwLock<HashMap<String, AlertState>>>,
+    guard_actions: Arc<RwLock<Vec<GuardActionRecord>>>,
+}
+
+/// Remediation taken by the resource guard, kept for post-incident review
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardActionRecord {
+    pub action: String,
+    pub trigger: String,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone)]
+struct ErrorEvent {
+    error_type: String,
+    message: String,
+    timestamp: Instant,
+}
+
+/// Alert configuration thresholds
+#[derive(Debug, Clone)]
+pub struct AlertThresholds {
+    pub error_rate_per_minute: f64,
+    pub response_time_p95_ms: u64,
+    pub memory_usage_percent: f64,
+    pub cpu_usage_percent: f64,
+    pub active_connections: u32,
+}
+
+impl Default for AlertThresholds {
+    fn default() -> Self {
+        Self {
+            error_rate_per_minute: 10.0,
+            response_time_p95_ms: 5000,
+            memory_usage_percent: 80.0,
+            cpu_usage_percent: 80.0,
+            active_connections: 1000,
+        }
+    }
+}
+
+/// Alert state tracking
+#[derive(Debug, Clone)]
+struct AlertState {
+    alert_type: String,
+    last_triggered: Instant,
+    trigger_count: u32,
+    is_active: bool,
+    escalation_level: u8, // 0=info, 1=warning, 2=critical
+}
+
+/// Alert for notification systems
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub alert_type: String,
+    pub message: String,
+    pub severity: u8, // 0=info, 1=warning, 2=critical
+    pub timestamp: u64,
+    pub trigger_count: u32,
+}
+
+impl MonitoringService {
+    pub fn new(version: String) -> Self {
+        Self {
+            start_time: Instant::now(),
+            version,
+            components: Arc::new(RwLock::new(HashMap::new())),
+            error_tracker: Arc::new(RwLock::new(Vec::new())),
+            alert_thresholds: AlertThresholds::default(),
+            alert_state: Arc::new(RwLock::new(HashMap::new())),
+            guard_actions: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    pub fn with_alert_thresholds(mut self, thresholds: AlertThresholds) -> Self {
+        self.alert_thresholds = thresholds;
+        self
+    }
+
+    /// Perform comprehensive health check
+    pub async fn health_check(&self) -> HealthStatus {
+        let mut components = HashMap::new();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        // Check FHE engine health
+        components.insert("fhe_engine".to_string(), self.check_fhe_engine().await);
+
+        // Check database/cache health (simulated)
+        components.insert("cache".to_string(), self.check_cache().await);
+
+        // Check network connectivity
+        components.insert("network".to_string(), self.check_network().await);
+
+        // Determine overall status
+        let overall_status = if components.values().all(|c| c.status == "healthy") {
+            "healthy"
+        } else if components.values().any(|c| c.status == "critical") {
+            "critical"
+        } else {
+            "degraded"
+        };
+
+        HealthStatus {
+            status: overall_status.to_string(),
+            timestamp: now,
+            version: self.version.clone(),
+            uptime_seconds: self.start_time.elapsed().as_secs(),
+            components,
+        }
+    }
+
+    async fn check_fhe_engine(&self) -> ComponentHealth {
+        let start = Instant::now();
+
+        // Simulate FHE engine health check
+        let status = match self.simulate_fhe_check().await {
+            Ok(_) => "healthy",
+            Err(e) => {
+                log::error!("FHE engine health check failed: {}", e);
+                "unhealthy"
+            }
+        };
+
+        ComponentHealth {
+            status: status.to_string(),
+            last_check: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            response_time_ms: Some(start.elapsed().as_millis() as u64),
+            error_message: if status == "unhealthy" {
+                Some("FHE operations failing".to_string())
+            } else {
+                None
+            },
+        }
+    }
+
+    async fn check_cache(&self) -> ComponentHealth {
+        ComponentHealth {
+            status: "healthy".to_string(),
+            last_check: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            response_time_ms: Some(1),
+            error_message: None,
+        }
+    }
+
+    async fn check_network(&self) -> ComponentHealth {
+        ComponentHealth {
+            status: "healthy".to_string(),
+            last_check: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            response_time_ms: Some(5),
+            error_message: None,
+        }
+    }
+
+    async fn simulate_fhe_check(&self) -> Result<()> {
+        // Simulate basic FHE operations
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        Ok(())
+    }
+
+    /// Record an error for tracking
+    pub async fn record_error(&self, error_type: String, message: String) {
+        let mut errors = self.error_tracker.write().await;
+        errors.push(ErrorEvent {
+            error_type,
+            message,
+            timestamp: Instant::now(),
+        });
+
+        // Keep only errors from the last hour
+        let one_hour_ago = Instant::now() - Duration::from_secs(3600);
+        errors.retain(|e| e.timestamp > one_hour_ago);
+    }
+
+    /// Record a resource guard action together with what triggered it
+    pub async fn record_guard_action(&self, action: &str, trigger: &str) {
+        log::warn!("Resource guard action '{}': {}", action, trigger);
+
+        let mut actions = self.guard_actions.write().await;
+        actions.push(GuardActionRecord {
+            action: action.to_string(),
+            trigger: trigger.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        });
+
+        // Bounded history; oldest records go first
+        if actions.len() > 500 {
+            let excess = actions.len() - 500;
+            actions.drain(..excess);
+        }
+    }
+
+    /// Get recorded resource guard actions, oldest first
+    pub async fn get_guard_actions(&self) -> Vec<GuardActionRecord> {
+        self.guard_actions.read().await.clone()
+    }
+
+    /// Get detailed system metrics
+    pub async fn get_metrics(
+        &self,
+        requests_metrics: MetricsSnapshot,
+        fhe_engine: &Arc<RwLock<FheEngine>>,
+    ) -> SystemMetrics {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let errors = self.error_tracker.read().await;
+
+        // Calculate error metrics
+        let recent_errors: Vec<_> = errors
+            .iter()
+            .filter(|e| e.timestamp > Instant::now() - Duration::from_secs(60))
+            .collect();
+
+        let mut error_types = HashMap::new();
+        for error in &recent_errors {
+            *error_types.entry(error.error_type.clone()).or_insert(0) += 1;
+        }
+
+        let last_error = errors.last().map(|e| e.message.clone());
+        let last_error_timestamp = errors.last().map(|e| e.timestamp.elapsed().as_secs());
+
+        SystemMetrics {
+            timestamp: now,
+            uptime_seconds: self.start_time.elapsed().as_secs(),
+            requests: requests_metrics,
+            fhe_operations: self.get_fhe_metrics(fhe_engine).await,
+            system_resources: self.get_resource_metrics().await,
+            errors: ErrorMetrics {
+                error_rate_per_minute: recent_errors.len() as f64,
+                error_types,
+                last_error,
+                last_error_timestamp,
+            },
+        }
+    }
+
+    async fn get_fhe_metrics(&self, _fhe_engine: &Arc<RwLock<FheEngine>>) -> FheMetrics {
+        FheMetrics {
+            active_sessions: 10,    // Simulated
+            cached_ciphertexts: 25, // Simulated
+            key_generations: 5,     // Simulated
+            encryption_time_avg_ms: 45.2,
+            decryption_time_avg_ms: 38.7,
+        }
+    }
+
+    async fn get_resource_metrics(&self) -> ResourceMetrics {
+        ResourceMetrics {
+            memory_usage_mb: 256.5, // Simulated
+            cpu_usage_percent: 15.3,
+            disk_usage_mb: 1024.0,
+            network_connections: 12,
+        }
+    }
+
+    /// Get readiness status (for Kubernetes readiness probes)
+    pub async fn readiness_check(&self) -> bool {
+        let health = self.health_check().await;
+        health.status == "healthy" || health.status == "degraded"
+    }
+
+    /// Get liveness status (for Kubernetes liveness probes)
+    pub async fn liveness_check(&self) -> bool {
+        // Basic liveness check - server is running
+        true
+    }
+
+    /// Evaluate metrics against alert thresholds
+    pub async fn evaluate_alerts(&self, metrics: &SystemMetrics) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        let mut alert_state = self.alert_state.write().await;
+
+        // Check error rate
+        if metrics.errors.error_rate_per_minute > self.alert_thresholds.error_rate_per_minute {
+            let alert = self.create_alert(
+                "high_error_rate".to_string(),
+                format!(
+                    "Error rate {:.1}/min exceeds threshold {:.1}/min",
+                    metrics.errors.error_rate_per_minute,
+                    self.alert_thresholds.error_rate_per_minute
+                ),
+                2, // Critical
+                &mut alert_state,
+            );
+            alerts.push(alert);
+        }
+
+        // Check CPU usage
+        if metrics.system_resources.cpu_usage_percent > self.alert_thresholds.cpu_usage_percent {
+            let alert = self.create_alert(
+                "high_cpu_usage".to_string(),
+                format!(
+                    "CPU usage {:.1}% exceeds threshold {:.1}%",
+                    metrics.system_resources.cpu_usage_percent,
+                    self.alert_thresholds.cpu_usage_percent
+                ),
+                1, // Warning
+                &mut alert_state,
+            );
+            alerts.push(alert);
+        }
+
+        // Check memory usage
+        if metrics.system_resources.memory_usage_mb > 1024.0 {
+            let memory_percent = (metrics.system_resources.memory_usage_mb / 1024.0) * 100.0;
+            if memory_percent > self.alert_thresholds.memory_usage_percent {
+                let alert = self.create_alert(
+                    "high_memory_usage".to_string(),
+                    format!(
+                        "Memory usage {:.1}% exceeds threshold {:.1}%",
+                        memory_percent, self.alert_thresholds.memory_usage_percent
+                    ),
+                    1, // Warning
+                    &mut alert_state,
+                );
+                alerts.push(alert);
+            }
+        }
+
+        alerts
+    }
+
+    /// Raise an alert detected outside the metrics evaluation and notify on it
+    pub async fn raise_alert(&self, alert_type: &str, message: String, severity: u8) -> Alert {
+        let mut alert_state = self.alert_state.write().await;
+        let alert = self.create_alert(alert_type.to_string(), message, severity, &mut alert_state);
+        self.send_alert_notification(&alert);
+        alert
+    }
+
+    fn create_alert(
+        &self,
+        alert_type: String,
+        message: String,
+        severity: u8,
+        alert_state: &mut HashMap<String, AlertState>,
+    ) -> Alert {
+        let now = Instant::now();
+
+        // Get or create alert state
+        let state = alert_state.entry(alert_type.clone()).or_insert(AlertState {
+            alert_type: alert_type.clone(),
+            last_triggered: now,
+            trigger_count: 0,
+            is_active: false,
+            escalation_level: severity,
+        });
+
+        state.trigger_count += 1;
+        state.last_triggered = now;
+        state.is_active = true;
+
+        // Escalate based on frequency
+        if state.trigger_count > 5 {
+            state.escalation_level = 2; // Critical
+        }
+
+        Alert {
+            alert_type,
+            message,
+            severity: state.escalation_level,
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            trigger_count: state.trigger_count,
+        }
+    }
+
+    /// Get active alerts
+    pub async fn get_active_alerts(&self) -> Vec<Alert> {
+        let alert_state = self.alert_state.read().await;
+        let mut alerts = Vec::new();
+
+        let now = Instant::now();
+        for state in alert_state.values() {
+            if state.is_active && state.last_triggered.elapsed() < Duration::from_secs(300) {
+                alerts.push(Alert {
+                    alert_type: state.alert_type.clone(),
+                    message: format!("Active alert: {}", state.alert_type),
+                    severity: state.escalation_level,
+                    timestamp: now.elapsed().as_secs(),
+                    trigger_count: state.trigger_count,
+                });
+            }
+        }
+
+        alerts
+    }
+
+    /// Clear resolved alerts
+    pub async fn clear_resolved_alerts(&self) {
+        let mut alert_state = self.alert_state.write().await;
+        let threshold = Instant::now() - Duration::from_secs(600); // 10 minutes
+
+        for state in alert_state.values_mut() {
+            if state.last_triggered < threshold {
+                state.is_active = false;
+                log::info!("Cleared resolved alert: {}", state.alert_type);
+            }
+        }
+    }
+
+    /// Send alert notification (placeholder for actual implementation)
+    pub fn send_alert_notification(&self, alert: &Alert) {
+        match alert.severity {
+            0 => log::info!("INFO: {}", alert.message),
+            1 => log::warn!("WARNING: {}", alert.message),
+            2 => log::error!("CRITICAL: {}", alert.message),
+            _ => log::error!("UNKNOWN SEVERITY: {}", alert.message),
+        }
+
+        // In production, this would integrate with:
+        // - PagerDuty
+        // - Slack/Teams
+        // - Email notifications
+        // - SMS alerts for critical issues
+    }
+}
+
+/// Performance profiler for critical operations
+#[derive(Debug)]
+pub struct PerformanceProfiler {
+    operations: Arc<RwLock<HashMap<String, Vec<Duration>>>>,
+}
+
+impl PerformanceProfiler {
+    pub fn new() -> Self {
+        Self {
+            operations: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Start timing an operation
+    pub fn start_timer(&self, operation: &str) -> OperationTimer {
+        OperationTimer {
+            operation: operation.to_string(),
+            start_time: Instant::now(),
+            profiler: self.operations.clone(),
+        }
+    }
+
+    /// Get performance statistics for an operation
+    pub async fn get_stats(&self, operation: &str) -> Option<PerformanceStats> {
+        let operations = self.operations.read().await;
+        let timings = operations.get(operation)?;
+
+        if timings.is_empty() {
+            return None;
+        }
+
+        let total: Duration = timings.iter().sum();
+        let avg = total / timings.len() as u32;
+        let min = *timings.iter().min()?;
+        let max = *timings.iter().max()?;
+
+        // Calculate percentiles
+        let mut sorted = timings.clone();
+        sorted.sort();
+        let p50 = sorted[sorted.len() / 2];
+        let p95 = sorted[(sorted.len() as f32 * 0.95) as usize];
+        let p99 = sorted[(sorted.len() as f32 * 0.99) as usize];
+
+        Some(PerformanceStats {
+            operation: operation.to_string(),
+            total_calls: timings.len(),
+            avg_duration: avg,
+            min_duration: min,
+            max_duration: max,
+            p50_duration: p50,
+            p95_duration: p95,
+            p99_duration: p99,
+        })
+    }
+
+    /// Get all performance statistics
+    pub async fn get_all_stats(&self) -> HashMap<String, PerformanceStats> {
+        let operations = self.operations.read().await;
+        let mut stats = HashMap::new();
+
+        for operation in operations.keys() {
+            if let Some(stat) = self.get_stats(operation).await {
+                stats.insert(operation.clone(), stat);
+            }
+        }
+
+        stats
+    }
+}
+
+#[derive(Debug)]
+pub struct OperationTimer {
+    operation: String,
+    start_time: Instant,
+    profiler: Arc<RwLock<HashMap<String, Vec<Duration>>>>,
+}
+
+impl Drop for OperationTimer {
+    fn drop(&mut self) {
+        let duration = self.start_time.elapsed();
+        let operation = self.operation.clone();
+        let profiler = self.profiler.clone();
+
+        tokio::spawn(async move {
+            let mut operations = profiler.write().await;
+            let timings = operations.entry(operation.clone()).or_insert_with(Vec::new);
+            timings.push(duration);
+
+            // Keep only the last 1000 measurements
+            if timings.len() > 1000 {
+                timings.remove(0);
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PerformanceStats {
+    pub operation: String,
+    pub total_calls: usize,
+    pub avg_duration: Duration,
+    pub min_duration: Duration,
+    pub max_duration: Duration,
+    pub p50_duration: Duration,
+    pub p95_duration: Duration,
+    pub p99_duration: Duration,
+}
+
+/// Latency samples kept per SLA class
+const SLA_LATENCY_WINDOW: usize = 1000;
+
+/// Per-SLA-class service metrics, used to verify that higher classes
+/// actually receive better latency and fewer rejections
+#[derive(Debug, Default)]
+pub struct SlaMetrics {
+    classes: RwLock<HashMap<SlaClass, SlaSamples>>,
+}
+
+#[derive(Debug, Default)]
+struct SlaSamples {
+    requests: u64,
+    rejections: HashMap<u16, u64>,
+    latencies: VecDeque<Duration>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SlaClassStats {
+    pub sla_class: SlaClass,
+    pub requests: u64,
+    pub rejected: u64,
+    pub rejection_rate: f64,
+    /// Rejections keyed by HTTP status (429 rate limited, 503 shed or draining)
+    pub rejections_by_status: HashMap<u16, u64>,
+    pub p50_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub p99_latency_ms: f64,
+}
+
+impl SlaMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a request that was admitted and served
+    pub async fn record_completion(&self, sla_class: SlaClass, latency: Duration) {
+        let mut classes = self.classes.write().await;
+        let samples = classes.entry(sla_class).or_default();
+        samples.requests += 1;
+        samples.latencies.push_back(latency);
+        if samples.latencies.len() > SLA_LATENCY_WINDOW {
+            samples.latencies.pop_front();
+        }
+    }
+
+    /// Record a request turned away before reaching a handler
+    pub async fn record_rejection(&self, sla_class: SlaClass, status: u16) {
+        let mut classes = self.classes.write().await;
+        let samples = classes.entry(sla_class).or_default();
+        samples.requests += 1;
+        *samples.rejections.entry(status).or_insert(0) += 1;
+    }
+
+    /// Stats for every SLA class, including classes that have seen no traffic
+    pub async fn get_stats(&self) -> Vec<SlaClassStats> {
+        let classes = self.classes.read().await;
+        SlaClass::ALL
+            .iter()
+            .map(|&sla_class| {
+                let Some(samples) = classes.get(&sla_class) else {
+                    return SlaClassStats {
+                        sla_class,
+                        requests: 0,
+                        rejected: 0,
+                        rejection_rate: 0.0,
+                        rejections_by_status: HashMap::new(),
+                        p50_latency_ms: 0.0,
+                        p95_latency_ms: 0.0,
+                        p99_latency_ms: 0.0,
+                    };
+                };
+
+                let mut sorted: Vec<Duration> = samples.latencies.iter().copied().collect();
+                sorted.sort();
+                let percentile = |p: f64| {
+                    sorted
+                        .get(
+                            ((sorted.len() as f64 * p) as usize)
+                                .min(sorted.len().saturating_sub(1)),
+                        )
+                        .map(|d| d.as_secs_f64() * 1000.0)
+                        .unwrap_or(0.0)
+                };
+                let rejected: u64 = samples.rejections.values().sum();
+
+                SlaClassStats {
+                    sla_class,
+                    requests: samples.requests,
+                    rejected,
+                    rejection_rate: rejected as f64 / samples.requests.max(1) as f64,
+                    rejections_by_status: samples.rejections.clone(),
+                    p50_latency_ms: percentile(0.5),
+                    p95_latency_ms: percentile(0.95),
+                    p99_latency_ms: percentile(0.99),
+                }
+            })
+            .collect()
+    }
+}
+
+/// IPv4 or IPv6 network in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn parse(cidr: &str) -> Option<Self> {
+        let (address, prefix) = cidr.split_once('/')?;
+        let network: IpAddr = address.trim().parse().ok()?;
+        let prefix: u8 = prefix.trim().parse().ok()?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        (prefix <= max_prefix).then_some(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Latency bucket counts of one region for one minute
+#[derive(Debug)]
+struct MinuteSlice {
+    minute: u64,
+    /// One count per bucket, plus one for latencies above the last bound
+    counts: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionLatency {
+    pub region: String,
+    pub requests: u64,
+    /// Counts per bucket of `LatencyHeatmap::bucket_bounds_ms`; the extra last
+    /// entry counts requests slower than the last bound
+    pub buckets: Vec<u64>,
+    /// Upper bound of the bucket holding the percentile; `None` when it lies
+    /// above the last bound
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyHeatmap {
+    pub bucket_bounds_ms: Vec<u64>,
+    pub window_minutes: u64,
+    /// Busiest regions first
+    pub regions: Vec<RegionLatency>,
+}
+
+/// Request latency by client region over a sliding window, to show where
+/// clients are far from the proxy and a new edge node or region would help
+#[derive(Debug)]
+pub struct GeoLatencyHeatmap {
+    config: GeoLatencyConfig,
+    ranges: Vec<(String, IpRange)>,
+    regions: RwLock<HashMap<String, VecDeque<MinuteSlice>>>,
+}
+
+impl GeoLatencyHeatmap {
+    pub fn new(config: GeoLatencyConfig) -> Self {
+        let ranges = config
+            .ip_ranges
+            .iter()
+            .flat_map(|(region, ranges)| {
+                ranges
+                    .iter()
+                    .filter_map(|range| IpRange::parse(range))
+                    .map(move |range| (region.clone(), range))
+            })
+            .collect();
+        Self {
+            config,
+            ranges,
+            regions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn region_header(&self) -> &str {
+        &self.config.region_header
+    }
+
+    /// Region of a client: its declared region if well-formed, else the most
+    /// specific configured range containing its IP, else "unknown"
+    pub fn region_for(&self, declared: Option<&str>, client_ip: &str) -> String {
+        if let Some(declared) = declared.map(str::trim).filter(|region| {
+            !region.is_empty()
+                && region.len() <= 32
+                && region
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        }) {
+            return declared.to_ascii_lowercase();
+        }
+
+        // X-Forwarded-For lists the original client first
+        let ip = client_ip
+            .split(',')
+            .next()
+            .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+        ip.and_then(|ip| {
+            self.ranges
+                .iter()
+                .filter(|(_, range)| range.contains(ip))
+                .max_by_key(|(_, range)| range.prefix)
+        })
+        .map(|(region, _)| region.clone())
+        .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    pub async fn record(&self, region: &str, latency: Duration) {
+        let minute = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            / 60;
+        let latency_ms = latency.as_millis() as u64;
+        let bucket = self
+            .config
+            .latency_buckets_ms
+            .iter()
+            .position(|bound| latency_ms <= *bound)
+            .unwrap_or(self.config.latency_buckets_ms.len());
+
+        let mut regions = self.regions.write().await;
+        // Declared regions are client-controlled, so cap how many are tracked,
+        // keeping one slot for "other"
+        let region = if regions.contains_key(region) || regions.len() + 1 < self.config.max_regions
+        {
+            region
+        } else {
+            "other"
+        };
+        let slices = regions.entry(region.to_string()).or_default();
+        if slices.back().is_none_or(|slice| slice.minute != minute) {
+            slices.push_back(MinuteSlice {
+                minute,
+                counts: vec![0; self.config.latency_buckets_ms.len() + 1],
+            });
+        }
+        while slices
+            .front()
+            .is_some_and(|slice| slice.minute + self.config.window_minutes <= minute)
+        {
+            slices.pop_front();
+        }
+        slices.back_mut().unwrap().counts[bucket] += 1;
+    }
+
+    pub async fn heatmap(&self) -> LatencyHeatmap {
+        let bounds = &self.config.latency_buckets_ms;
+        let current_minute = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            / 60;
+
+        let mut regions: Vec<RegionLatency> = self
+            .regions
+            .read()
+            .await
+            .iter()
+            .filter_map(|(region, slices)| {
+                let mut buckets = vec![0u64; bounds.len() + 1];
+                for slice in slices
+                    .iter()
+                    .filter(|slice| slice.minute + self.config.window_minutes > current_minute)
+                {
+                    for (total, count) in buckets.iter_mut().zip(&slice.counts) {
+                        *total += count;
+                    }
+                }
+                let requests: u64 = buckets.iter().sum();
+                if requests == 0 {
+                    return None;
+                }
+
+                let percentile = |p: f64| {
+                    let target = (requests as f64 * p).ceil().max(1.0) as u64;
+                    let mut seen = 0;
+                    buckets
+                        .iter()
+                        .position(|count| {
+                            seen += count;
+                            seen >= target
+                        })
+                        .and_then(|index| bounds.get(index).copied())
+                };
+                Some(RegionLatency {
+                    region: region.clone(),
+                    requests,
+                    p50_ms: percentile(0.5),
+                    p95_ms: percentile(0.95),
+                    p99_ms: percentile(0.99),
+                    buckets,
+                })
+            })
+            .collect();
+        regions.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.region.cmp(&b.region)));
+
+        LatencyHeatmap {
+            bucket_bounds_ms: bounds.clone(),
+            window_minutes: self.config.window_minutes,
+            regions,
+        }
+    }
+}
+
+/// Point-in-time view of the proxy's queues, caches and engine
+#[derive(Debug, Clone, Serialize)]
+pub struct StateSnapshot {
+    pub timestamp: u64,
+    pub config_generation: u64,
+    // Queue depths
+    pub in_flight_requests: usize,
+    pub packing_queue: usize,
+    pub siem_queue: u64,
+    // Cache occupancy
+    pub ciphertext_cache_entries: usize,
+    pub response_cache_entries: usize,
+    pub chunked_responses: usize,
+    pub active_sessions: usize,
+    // Engine health
+    pub engine_status: String,
+    pub engine_warm: bool,
+    pub client_keys: usize,
+    pub draining: bool,
+    pub lockdown: bool,
+}
+
+/// Ring buffer of state snapshots. Memory is bounded by the capacity; the
+/// oldest snapshot is overwritten once it is full.
+#[derive(Debug)]
+pub struct StateRecorder {
+    capacity: usize,
+    snapshots: RwLock<VecDeque<StateSnapshot>>,
+}
+
+impl StateRecorder {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            snapshots: RwLock::new(VecDeque::with_capacity(capacity.max(1))),
+        }
+    }
+
+    pub async fn record(&self, snapshot: StateSnapshot) {
+        let mut snapshots = self.snapshots.write().await;
+        if snapshots.len() == self.capacity {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(snapshot);
+    }
+
+    /// Snapshots taken in the last `window`, oldest first
+    pub async fn since(&self, window: Duration) -> Vec<StateSnapshot> {
+        let cutoff = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .saturating_sub(window.as_secs());
+        self.snapshots
+            .read()
+            .await
+            .iter()
+            .filter(|snapshot| snapshot.timestamp >= cutoff)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Automated actions kept for review
+const RUNBOOK_HISTORY_LIMIT: usize = 500;
+
+/// Inputs to runbook triggers, sampled by the proxy on every evaluation.
+/// Counters are cumulative; rates are taken between consecutive samples.
+#[derive(Debug, Clone, Default)]
+pub struct RunbookSignals {
+    pub engine_healthy: bool,
+    pub budget_exhaustions: u64,
+    /// Provider name -> (successful calls, failed calls)
+    pub provider_calls: HashMap<String, (u64, u64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunbookOutcome {
+    DryRun,
+    PendingApproval,
+    Executed,
+    Rejected,
+    Failed,
+}
+
+/// An action a runbook rule decided on, possibly waiting for approval
+#[derive(Debug, Clone, Serialize)]
+pub struct RunbookDecision {
+    pub id: Uuid,
+    pub rule: String,
+    pub action: RunbookAction,
+    /// What fired the rule
+    pub reason: String,
+}
+
+/// Audit trail entry for every runbook firing, whatever its outcome
+#[derive(Debug, Clone, Serialize)]
+pub struct RunbookActionRecord {
+    #[serde(flatten)]
+    pub decision: RunbookDecision,
+    pub outcome: RunbookOutcome,
+    pub detail: Option<String>,
+    pub timestamp: u64,
+}
+
+/// Evaluates codified runbooks against alert signals. Execution is left to
+/// the caller, which owns the state the actions change.
+#[derive(Debug)]
+pub struct RunbookEngine {
+    config: RunbooksConfig,
+    previous: RwLock<Option<(Instant, RunbookSignals)>>,
+    last_fired: RwLock<HashMap<String, Instant>>,
+    pending: RwLock<HashMap<Uuid, RunbookDecision>>,
+    history: RwLock<VecDeque<RunbookActionRecord>>,
+}
+
+impl RunbookEngine {
+    pub fn new(config: RunbooksConfig) -> Self {
+        Self {
+            config,
+            previous: RwLock::new(None),
+            last_fired: RwLock::new(HashMap::new()),
+            pending: RwLock::new(HashMap::new()),
+            history: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    /// Evaluate every rule and return the actions to execute now. Dry-run and
+    /// approval-gated firings are only recorded.
+    pub async fn evaluate(&self, signals: RunbookSignals) -> Vec<RunbookDecision> {
+        let now = Instant::now();
+        let previous = self.previous.write().await.replace((now, signals.clone()));
+        let mut last_fired = self.last_fired.write().await;
+        let mut decisions = Vec::new();
+
+        for rule in &self.config.rules {
+            let Some(reason) = Self::fired(&rule.trigger, &signals, previous.as_ref(), now) else {
+                continue;
+            };
+            let cooldown = Duration::from_secs(rule.cooldown_seconds);
+            if last_fired
+                .get(&rule.name)
+                .is_some_and(|fired_at| now.duration_since(*fired_at) < cooldown)
+            {
+                continue;
+            }
+            last_fired.insert(rule.name.clone(), now);
+
+            let decision = RunbookDecision {
+                id: Uuid::new_v4(),
+                rule: rule.name.clone(),
+                action: rule.action.clone(),
+                reason,
+            };
+            if self.config.dry_run {
+                self.record(&decision, RunbookOutcome::DryRun, None).await;
+            } else if rule.requires_approval {
+                self.record(&decision, RunbookOutcome::PendingApproval, None)
+                    .await;
+                self.pending
+                    .write()
+                    .await
+                    .insert(decision.id, decision.clone());
+            } else {
+                decisions.push(decision);
+            }
+        }
+
+        decisions
+    }
+
+    fn fired(
+        trigger: &RunbookTrigger,
+        signals: &RunbookSignals,
+        previous: Option<&(Instant, RunbookSignals)>,
+        now: Instant,
+    ) -> Option<String> {
+        match trigger {
+            RunbookTrigger::EngineUnhealthy => {
+                (!signals.engine_healthy).then(|| "FHE engine health check failed".to_string())
+            }
+            RunbookTrigger::BudgetExhaustionSpike { per_minute } => {
+                let (sampled_at, previous) = previous?;
+                let minutes = now.duration_since(*sampled_at).as_secs_f64() / 60.0;
+                if minutes <= 0.0 {
+                    return None;
+                }
+                let exhaustions = signals
+                    .budget_exhaustions
+                    .saturating_sub(previous.budget_exhaustions);
+                let rate = exhaustions as f64 / minutes;
+                (rate >= *per_minute)
+                    .then(|| format!("{:.1} privacy budget exhaustions per minute", rate))
+            }
+            RunbookTrigger::ProviderOutage {
+                provider,
+                min_failures,
+            } => {
+                let (_, previous) = previous?;
+                let calls = |signals: &RunbookSignals| {
+                    signals
+                        .provider_calls
+                        .get(provider)
+                        .copied()
+                        .unwrap_or_default()
+                };
+                let (succeeded, failed) = calls(signals);
+                let (previously_succeeded, previously_failed) = calls(previous);
+                let failures = failed.saturating_sub(previously_failed);
+                (succeeded == previously_succeeded && failures >= *min_failures).then(|| {
+                    format!(
+                        "provider {} failed {} calls without a success",
+                        provider, failures
+                    )
+                })
+            }
+        }
+    }
+
+    /// Release an approval-gated action for execution
+    pub async fn approve(&self, id: Uuid) -> Option<RunbookDecision> {
+        self.pending.write().await.remove(&id)
+    }
+
+    /// Drop an approval-gated action without executing it
+    pub async fn reject(&self, id: Uuid) -> bool {
+        let Some(decision) = self.pending.write().await.remove(&id) else {
+            return false;
+        };
+        self.record(&decision, RunbookOutcome::Rejected, None).await;
+        true
+    }
+
+    pub async fn record(
+        &self,
+        decision: &RunbookDecision,
+        outcome: RunbookOutcome,
+        detail: Option<String>,
+    ) {
+        log::warn!(
+            "Runbook {} {:?} for {:?}: {}",
+            decision.rule,
+            outcome,
+            decision.action,
+            decision.reason
+        );
+
+        let mut history = self.history.write().await;
+        history.push_back(RunbookActionRecord {
+            decision: decision.clone(),
+            outcome,
+            detail,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        });
+        if history.len() > RUNBOOK_HISTORY_LIMIT {
+            history.pop_front();
+        }
+    }
+
+    pub async fn pending(&self) -> Vec<RunbookDecision> {
+        self.pending.read().await.values().cloned().collect()
+    }
+
+    /// Recorded firings, oldest first
+    pub async fn history(&self) -> Vec<RunbookActionRecord> {
+        self.history.read().await.iter().cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MetricSample {
+    time_unix_nano: u64,
+    metrics: MetricsSnapshot,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OtlpExportStats {
+    pub enabled: bool,
+    pub endpoint: Option<String>,
+    /// False from a failed export until the next one succeeds
+    pub collector_up: bool,
+    pub pending_samples: usize,
+    pub exported_samples: u64,
+    /// Samples pushed out of a full buffer while the collector was down
+    pub dropped_samples: u64,
+    pub failed_exports: u64,
+    pub last_success_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+struct OtlpExportState {
+    pending: VecDeque<MetricSample>,
+    next_export_at: Instant,
+    backoff: Duration,
+    stats: OtlpExportStats,
+}
+
+/// Pushes the /metrics registry to an OpenTelemetry collector over OTLP/HTTP.
+///
+/// Samples are batched into one request per export interval. While the
+/// collector is down, samples stay buffered up to a bound with the oldest
+/// dropped first, exports back off, and the outage is logged once rather than
+/// on every attempt.
+#[derive(Debug)]
+pub struct OtlpMetricsExporter {
+    config: OtlpMetricsConfig,
+    endpoint: Option<String>,
+    resource: Vec<serde_json::Value>,
+    start_time_unix_nano: u64,
+    state: std::sync::Mutex<OtlpExportState>,
+    client: HttpClient,
+}
+
+impl OtlpMetricsExporter {
+    pub fn new(
+        endpoints: &ExportEndpoints,
+        config: OtlpMetricsConfig,
+        default_region: &str,
+    ) -> Self {
+        let endpoint = endpoints.otel_collector_endpoint.as_ref().map(|url| {
+            let url = url.trim_end_matches('/');
+            if url.ends_with("/v1/metrics") {
+                url.to_string()
+            } else {
+                format!("{}/v1/metrics", url)
+            }
+        });
+        let instance_id = config
+            .instance_id
+            .clone()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let region = config.region.as_deref().unwrap_or(default_region);
+        let resource = vec![
+            otlp_attribute("service.name", "fhe-proxy"),
+            otlp_attribute("service.version", env!("CARGO_PKG_VERSION")),
+            otlp_attribute("service.instance.id", &instance_id),
+            otlp_attribute("cloud.region", region),
+        ];
+        let export_interval = Duration::from_secs(config.export_interval_seconds);
+        Self {
+            state: std::sync::Mutex::new(OtlpExportState {
+                pending: VecDeque::new(),
+                next_export_at: Instant::now() + export_interval,
+                backoff: export_interval,
+                stats: OtlpExportStats {
+                    enabled: endpoint.is_some(),
+                    endpoint: endpoint.clone(),
+                    collector_up: true,
+                    pending_samples: 0,
+                    exported_samples: 0,
+                    dropped_samples: 0,
+                    failed_exports: 0,
+                    last_success_at: None,
+                    last_error: None,
+                },
+            }),
+            config,
+            endpoint,
+            resource,
+            start_time_unix_nano: unix_nanos(),
+            client: HttpClient::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.endpoint.is_some()
+    }
+
+    pub fn config(&self) -> &OtlpMetricsConfig {
+        &self.config
+    }
+
+    /// Buffer a sample of the registry, dropping the oldest when the buffer is full
+    pub fn record(&self, metrics: MetricsSnapshot) {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.len() >= self.config.max_buffered_samples {
+            state.pending.pop_front();
+            state.stats.dropped_samples += 1;
+        }
+        state.pending.push_back(MetricSample {
+            time_unix_nano: unix_nanos(),
+            metrics,
+        });
+    }
+
+    /// Export once the export interval, or the backoff after a failure, has passed
+    pub async fn export_due(&self) {
+        if Instant::now() < self.state.lock().unwrap().next_export_at {
+            return;
+        }
+        // Failures are counted and logged by export
+        let _ = self.export().await;
+    }
+
+    /// Push every buffered sample in one request, returning how many were sent
+    pub async fn export(&self) -> Result<usize> {
+        let Some(endpoint) = &self.endpoint else {
+            return Ok(0);
+        };
+        let samples: Vec<MetricSample> =
+            self.state.lock().unwrap().pending.iter().cloned().collect();
+        let Some(last_sent) = samples.last().map(|s| s.time_unix_nano) else {
+            return Ok(0);
+        };
+
+        let mut request = self
+            .client
+            .post(endpoint)
+            .timeout(Duration::from_secs(self.config.timeout_seconds))
+            .json(&self.encode(&samples));
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        let result = match request.send().await {
+            Ok(response) if response.status().is_success() => Ok(samples.len()),
+            Ok(response) => Err(Error::Http(format!(
+                "OTLP collector rejected metrics: {}",
+                response.status()
+            ))),
+            Err(e) => Err(Error::Http(format!("OTLP collector unreachable: {}", e))),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        match &result {
+            Ok(sent) => {
+                // Samples recorded during the request stay for the next export
+                state.pending.retain(|s| s.time_unix_nano > last_sent);
+                if !state.stats.collector_up {
+                    log::info!("OTLP collector reachable again, metrics export resumed");
+                }
+                state.stats.collector_up = true;
+                state.stats.exported_samples += *sent as u64;
+                state.stats.last_success_at = Some(unix_nanos() / 1_000_000_000);
+                state.backoff = Duration::from_secs(self.config.export_interval_seconds);
+            }
+            Err(e) => {
+                if state.stats.collector_up {
+                    log::warn!("{}; buffering metrics and backing off", e);
+                }
+                state.stats.collector_up = false;
+                state.stats.failed_exports += 1;
+                state.stats.last_error = Some(e.to_string());
+                state.backoff =
+                    (state.backoff * 2).min(Duration::from_secs(self.config.max_backoff_seconds));
+            }
+        }
+        state.next_export_at = Instant::now() + state.backoff;
+        result
+    }
+
+    fn encode(&self, samples: &[MetricSample]) -> serde_json::Value {
+        let points = |value: fn(&MetricsSnapshot) -> u64, cumulative: bool| {
+            samples
+                .iter()
+                .map(|sample| {
+                    let mut point = serde_json::json!({
+                        "timeUnixNano": sample.time_unix_nano.to_string(),
+                        "asInt": value(&sample.metrics).to_string(),
+                    });
+                    if cumulative {
+                        point["startTimeUnixNano"] = self.start_time_unix_nano.to_string().into();
+                    }
+                    point
+                })
+                .collect::<Vec<_>>()
+        };
+        let counter = |name: &str, description: &str, value: fn(&MetricsSnapshot) -> u64| {
+            serde_json::json!({
+                "name": name,
+                "description": description,
+                "unit": "1",
+                "sum": {
+                    "aggregationTemporality": 2,
+                    "isMonotonic": true,
+                    "dataPoints": points(value, true)
+                }
+            })
+        };
+
+        let metrics = vec![
+            counter("fhe_proxy.requests", "Requests received", |m| {
+                m.total_requests
+            }),
+            counter("fhe_proxy.errors", "Requests that failed", |m| {
+                m.total_errors
+            }),
+            counter("fhe_proxy.encryptions", "FHE encryptions", |m| {
+                m.encryption_operations
+            }),
+            counter("fhe_proxy.decryptions", "FHE decryptions", |m| {
+                m.decryption_operations
+            }),
+            serde_json::json!({
+                "name": "fhe_proxy.response_time.avg",
+                "description": "Average response time",
+                "unit": "ms",
+                "gauge": { "dataPoints": points(|m| m.avg_response_time_ms, false) }
+            }),
+        ];
+        serde_json::json!({
+            "resourceMetrics": [{
+                "resource": { "attributes": self.resource },
+                "scopeMetrics": [{
+                    "scope": { "name": "fhe-proxy.metrics", "version": env!("CARGO_PKG_VERSION") },
+                    "metrics": metrics
+                }]
+            }]
+        })
+    }
+
+    /// Push rebuilt metrics in one request, every point marked with
+    /// `fhe_proxy.backfill`; returns how many intervals were sent. Live
+    /// export state is left alone.
+    pub async fn export_backfill(&self, report: &BackfillReport) -> Result<usize> {
+        let endpoint = self
+            .endpoint
+            .as_ref()
+            .ok_or_else(|| Error::Config("No OTLP collector endpoint configured".to_string()))?;
+        let mut request = self
+            .client
+            .post(endpoint)
+            .timeout(Duration::from_secs(self.config.timeout_seconds))
+            .json(&self.encode_backfill(report));
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        match request.send().await {
+            Ok(response) if response.status().is_success() => Ok(report.buckets.len()),
+            Ok(response) => Err(Error::Http(format!(
+                "OTLP collector rejected backfill: {}",
+                response.status()
+            ))),
+            Err(e) => Err(Error::Http(format!("OTLP collector unreachable: {}", e))),
+        }
+    }
+
+    fn encode_backfill(&self, report: &BackfillReport) -> serde_json::Value {
+        let backfill = serde_json::json!({
+            "key": "fhe_proxy.backfill",
+            "value": { "boolValue": true }
+        });
+        let nanos = |seconds: i64| (seconds.max(0) as u64 * 1_000_000_000).to_string();
+        // Each interval is its own delta, so gaps between backfills add up correctly
+        let delta =
+            |name: &str, description: &str, unit: &str, value: fn(&BackfillBucket) -> u64| {
+                let points: Vec<serde_json::Value> = report
+                    .buckets
+                    .iter()
+                    .map(|bucket| {
+                        serde_json::json!({
+                            "startTimeUnixNano": nanos(bucket.start),
+                            "timeUnixNano": nanos(bucket.end),
+                            "asInt": value(bucket).to_string(),
+                            "attributes": [backfill],
+                        })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "name": name,
+                    "description": description,
+                    "unit": unit,
+                    "sum": {
+                        "aggregationTemporality": 1,
+                        "isMonotonic": true,
+                        "dataPoints": points
+                    }
+                })
+            };
+        let latency: Vec<serde_json::Value> = report
+            .buckets
+            .iter()
+            .filter_map(|bucket| Some((bucket, bucket.latency.as_ref()?)))
+            .flat_map(|(bucket, latency)| {
+                [
+                    ("p50", latency.p50_ms),
+                    ("p95", latency.p95_ms),
+                    ("p99", latency.p99_ms),
+                    ("max", latency.max_ms),
+                ]
+                .map(|(quantile, ms)| {
+                    serde_json::json!({
+                        "timeUnixNano": nanos(bucket.end),
+                        "asInt": ms.to_string(),
+                        "attributes": [backfill, otlp_attribute("quantile", quantile)],
+                    })
+                })
+            })
+            .collect();
+
+        let metrics = vec![
+            delta("fhe_proxy.billed_requests", "Billed requests", "1", |b| {
+                b.usage.requests
+            }),
+            delta(
+                "fhe_proxy.billed_request_bytes",
+                "Request bytes received",
+                "By",
+                |b| b.usage.request_bytes,
+            ),
+            delta(
+                "fhe_proxy.billed_response_bytes",
+                "Response bytes before compression",
+                "By",
+                |b| b.usage.response_bytes,
+            ),
+            delta(
+                "fhe_proxy.billed_response_wire_bytes",
+                "Response bytes sent",
+                "By",
+                |b| b.usage.response_wire_bytes,
+            ),
+            serde_json::json!({
+                "name": "fhe_proxy.request_latency",
+                "description": "Request latency percentiles per interval",
+                "unit": "ms",
+                "gauge": { "dataPoints": latency }
+            }),
+        ];
+        serde_json::json!({
+            "resourceMetrics": [{
+                "resource": { "attributes": self.resource },
+                "scopeMetrics": [{
+                    "scope": { "name": "fhe-proxy.backfill", "version": env!("CARGO_PKG_VERSION") },
+                    "metrics": metrics
+                }]
+            }]
+        })
+    }
+
+    pub fn stats(&self) -> OtlpExportStats {
+        let state = self.state.lock().unwrap();
+        OtlpExportStats {
+            pending_samples: state.pending.len(),
+            ..state.stats.clone()
+        }
+    }
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+/// Structured logging helper
+pub struct StructuredLogger;
+
+impl StructuredLogger {
+    pub fn log_request(method: &str, path: &str, status: u16, duration: Duration, client_ip: &str) {
+        log::info!(
+            target: "http_requests",
+            "method={} path={} status={} duration_ms={} client_ip={}",
+            method, path, status, duration.as_millis(), client_ip
+        );
+    }
+
+    pub fn log_fhe_operation(operation: &str, client_id: Uuid, duration: Duration, success: bool) {
+        if success {
+            log::info!(
+                target: "fhe_operations",
+                "operation={} client_id={} duration_ms={} success={}",
+                operation, client_id, duration.as_millis(), success
+            );
+        } else {
+            log::warn!(
+                target: "fhe_operations",
+                "operation={} client_id={} duration_ms={} success={}",
+                operation, client_id, duration.as_millis(), success
+            );
+        }
+    }
+
+    pub fn log_security_event(event_type: &str, client_ip: &str, details: &str) {
+        log::warn!(
+            target: "security_events",
+            "event_type={} client_ip={} details={}",
+            event_type, client_ip, details
+        );
+    }
+
+    pub fn log_error(error: &Error, context: &str) {
+        log::error!(
+            target: "application_errors",
+            "error_type={:?} context={} message={}",
+            std::mem::discriminant(error),
+            context,
+            error
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn test_monitoring_service() {
+        let service = MonitoringService::new("test-1.0.0".to_string());
+
+        // Test health check
+        let health = service.health_check().await;
+        assert_eq!(health.version, "test-1.0.0");
+        assert!(!health.components.is_empty());
+
+        // Test error recording
+        service
+            .record_error("TestError".to_string(), "Test message".to_string())
+            .await;
+
+        let errors = service.error_tracker.read().await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error_type, "TestError");
+    }
+
+    #[tokio::test]
+    async fn test_performance_profiler() {
+        let profiler = PerformanceProfiler::new();
+
+        // Simulate some operations
+        for _ in 0..10 {
+            let _timer = profiler.start_timer("test_operation");
+            sleep(Duration::from_millis(1)).await;
+        }
+
+        // Wait for async operations to complete
+        sleep(Duration::from_millis(10)).await;
+
+        let stats = profiler.get_stats("test_operation").await;
+        assert!(stats.is_some());
+        let stats = stats.unwrap();
+        assert_eq!(stats.total_calls, 10);
+        assert!(stats.avg_duration > Duration::from_millis(0));
+    }
+
+    #[tokio::test]
+    async fn test_runbook_gating() {
+        use crate::config::RunbookRule;
+
+        let rule = |name: &str, trigger, action, requires_approval| RunbookRule {
+            name: name.to_string(),
+            trigger,
+            action,
+            requires_approval,
+            cooldown_seconds: 300,
+        };
+        let config = RunbooksConfig {
+            enabled: true,
+            dry_run: false,
+            evaluation_interval_seconds: 30,
+            rules: vec![
+                rule(
+                    "drain",
+                    RunbookTrigger::EngineUnhealthy,
+                    RunbookAction::DrainEngine,
+                    false,
+                ),
+                rule(
+                    "failover",
+                    RunbookTrigger::ProviderOutage {
+                        provider: "openai".to_string(),
+                        min_failures: 3,
+                    },
+                    RunbookAction::SwitchProvider {
+                        from: "openai".to_string(),
+                        to: "anthropic".to_string(),
+                    },
+                    true,
+                ),
+            ],
+        };
+        let engine = RunbookEngine::new(config.clone());
+
+        let mut signals = RunbookSignals {
+            engine_healthy: true,
+            ..Default::default()
+        };
+        signals.provider_calls.insert("openai".to_string(), (10, 0));
+        assert!(engine.evaluate(signals.clone()).await.is_empty());
+
+        // Unhealthy engine executes immediately; the outage waits for approval
+        signals.engine_healthy = false;
+        signals.provider_calls.insert("openai".to_string(), (10, 4));
+        let decisions = engine.evaluate(signals.clone()).await;
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].action, RunbookAction::DrainEngine);
+        let pending = engine.pending().await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(
+            engine.approve(pending[0].id).await.unwrap().rule,
+            "failover"
+        );
+        assert!(engine.pending().await.is_empty());
+
+        // Cooldown suppresses repeated firings
+        assert!(engine.evaluate(signals.clone()).await.is_empty());
+
+        // Dry-run only records
+        let dry_run = RunbookEngine::new(RunbooksConfig {
+            dry_run: true,
+            ..config
+        });
+        assert!(dry_run.evaluate(signals).await.is_empty());
+        let history = dry_run.history().await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].outcome, RunbookOutcome::DryRun);
+    }
+
+    #[tokio::test]
+    async fn test_state_recorder_is_bounded() {
+        let recorder = StateRecorder::new(3);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for age in [600, 30, 20, 10] {
+            recorder
+                .record(StateSnapshot {
+                    timestamp: now - age,
+                    config_generation: age,
+                    in_flight_requests: 0,
+                    packing_queue: 0,
+                    siem_queue: 0,
+                    ciphertext_cache_entries: 0,
+                    response_cache_entries: 0,
+                    chunked_responses: 0,
+                    active_sessions: 0,
+                    engine_status: "healthy".to_string(),
+                    engine_warm: true,
+                    client_keys: 0,
+                    draining: false,
+                    lockdown: false,
+                })
+                .await;
+        }
+
+        // The oldest snapshot was overwritten; the window trims the rest
+        let all = recorder.since(Duration::from_secs(3600)).await;
+        assert_eq!(
+            all.iter().map(|s| s.config_generation).collect::<Vec<_>>(),
+            vec![30, 20, 10]
+        );
+        assert_eq!(recorder.since(Duration::from_secs(25)).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_geo_latency_heatmap() {
+        let heatmap = GeoLatencyHeatmap::new(GeoLatencyConfig {
+            ip_ranges: HashMap::from([
+                ("eu-west".to_string(), vec!["10.1.0.0/16".to_string()]),
+                (
+                    "eu-west-dublin".to_string(),
+                    vec!["10.1.2.0/24".to_string()],
+                ),
+                ("us-east".to_string(), vec!["2001:db8:1::/48".to_string()]),
+            ]),
+            latency_buckets_ms: vec![50, 100, 500],
+            max_regions: 3,
+            ..GeoLatencyConfig::default()
+        });
+
+        // Declared regions win; otherwise the most specific range matches
+        assert_eq!(heatmap.region_for(Some("AP-South"), "10.1.2.3"), "ap-south");
+        assert_eq!(
+            heatmap.region_for(Some("bad region!"), "10.1.9.9"),
+            "eu-west"
+        );
+        assert_eq!(
+            heatmap.region_for(None, "10.1.2.3, 192.168.0.1"),
+            "eu-west-dublin"
+        );
+        assert_eq!(heatmap.region_for(None, "2001:db8:1::7"), "us-east");
+        assert_eq!(heatmap.region_for(None, "unknown"), "unknown");
+
+        for ms in [20, 80, 90, 700] {
+            heatmap.record("eu-west", Duration::from_millis(ms)).await;
+        }
+        heatmap.record("us-east", Duration::from_millis(30)).await;
+        heatmap.record("ap-south", Duration::from_millis(30)).await;
+        heatmap.record("sa-east", Duration::from_millis(30)).await;
+
+        let data = heatmap.heatmap().await;
+        let eu = &data.regions[0];
+        assert_eq!(eu.region, "eu-west");
+        assert_eq!(eu.buckets, vec![1, 2, 0, 1]);
+        assert_eq!(eu.p50_ms, Some(100));
+        assert_eq!(eu.p99_ms, None);
+
+        // Regions past the cap are folded together
+        assert_eq!(data.regions.len(), 3);
+        assert!(data.regions.iter().any(|r| r.region == "other"));
+    }
+
+    #[tokio::test]
+    async fn test_otlp_export_batches_and_buffers_while_collector_down() {
+        let exporter = OtlpMetricsExporter::new(
+            &ExportEndpoints {
+                otel_collector_endpoint: Some("http://127.0.0.1:9/".to_string()),
+            },
+            OtlpMetricsConfig {
+                max_buffered_samples: 2,
+                region: Some("eu-west".to_string()),
+                ..OtlpMetricsConfig::default()
+            },
+            "local",
+        );
+        for requests in 1..=3 {
+            exporter.record(MetricsSnapshot {
+                total_requests: requests,
+                total_errors: 0,
+                encryption_operations: 0,
+                decryption_operations: 0,
+                avg_response_time_ms: 12,
+            });
+        }
+
+        let samples: Vec<_> = exporter
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .cloned()
+            .collect();
+        let payload = exporter.encode(&samples);
+        let resource = &payload["resourceMetrics"][0]["resource"]["attributes"];
+        assert!(resource
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|a| a["key"] == "cloud.region" && a["value"]["stringValue"] == "eu-west"));
+        let requests = &payload["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0];
+        assert_eq!(requests["name"], "fhe_proxy.requests");
+        let points = requests["sum"]["dataPoints"].as_array().unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1]["asInt"], "3");
+
+        // A down collector keeps samples buffered and backs off instead of retrying
+        assert!(exporter.export().await.is_err());
+        exporter.export_due().await;
+        let stats = exporter.stats();
+        assert_eq!(
+            stats.endpoint.as_deref(),
+            Some("http://127.0.0.1:9/v1/metrics")
+        );
+        assert!(!stats.collector_up);
+        assert_eq!(stats.failed_exports, 1);
+        assert_eq!(stats.pending_samples, 2);
+        assert_eq!(stats.dropped_samples, 1);
+    }
+}
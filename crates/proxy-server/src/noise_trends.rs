@@ -0,0 +1,361 @@
+//! Noise budget consumption trends per tenant
+//!
+//! Every operation on a ciphertext spends noise budget. The bits each tenant
+//! spends are summed into fixed windows of `window_seconds`, and a window's
+//! consumption rate is the mean bits spent per operation. Once the current
+//! window has seen `min_operations`, its rate is compared with the tenant's
+//! own baseline, the mean over its previous `baseline_windows`; a rate more
+//! than `alert_ratio` times the baseline raises one alert for the window.
+//! Pathological prompt patterns show up this way long before budgets run out.
+//!
+//! Ciphertexts left below `bootstrap_threshold_bits` need bootstrapping. For
+//! capacity planning, a line is fitted through the bootstrap demand of the
+//! closed windows and projected `forecast_hours` ahead, and compared with
+//! `bootstrap_capacity_per_hour` when that is set.
+
+use crate::config::NoiseTrendsConfig;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::RwLock;
+
+/// Noise budget spent by one tenant within one window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NoiseWindow {
+    pub start: i64,
+    pub operations: u64,
+    pub bits_consumed: u64,
+    /// Operations that left their ciphertext below the bootstrap threshold
+    pub bootstraps_needed: u64,
+}
+
+impl NoiseWindow {
+    fn new(start: i64) -> Self {
+        Self {
+            start,
+            operations: 0,
+            bits_consumed: 0,
+            bootstraps_needed: 0,
+        }
+    }
+
+    pub fn bits_per_operation(&self) -> f64 {
+        if self.operations == 0 {
+            0.0
+        } else {
+            self.bits_consumed as f64 / self.operations as f64
+        }
+    }
+}
+
+/// A window whose consumption rate outran the tenant's baseline
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NoiseTrendAlert {
+    pub tenant: String,
+    pub window_start: i64,
+    pub bits_per_operation: f64,
+    pub baseline_bits_per_operation: f64,
+    pub ratio: f64,
+}
+
+/// Projected bootstrap demand
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BootstrapForecast {
+    /// Fitted demand at the most recent closed window
+    pub bootstraps_per_hour: f64,
+    /// Change in demand per hour, from the fitted line
+    pub trend_per_hour: f64,
+    pub forecast_hours: u64,
+    pub projected_bootstraps_per_hour: f64,
+    pub capacity_per_hour: Option<u64>,
+    /// When the fitted demand reaches capacity; `Some(0.0)` once it has
+    pub hours_until_capacity: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantNoiseTrend {
+    pub tenant: String,
+    /// Rate of the current, still open window
+    pub bits_per_operation: f64,
+    pub baseline_bits_per_operation: Option<f64>,
+    pub forecast: BootstrapForecast,
+    /// Oldest first; the last is the current window
+    pub windows: Vec<NoiseWindow>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantNoiseSummary {
+    pub tenant: String,
+    pub bits_per_operation: f64,
+    pub baseline_bits_per_operation: Option<f64>,
+    pub bootstraps_per_hour: f64,
+    pub alerted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NoiseTrendReport {
+    pub window_seconds: u64,
+    /// Bootstrap demand of all tenants together
+    pub forecast: BootstrapForecast,
+    pub tenants: Vec<TenantNoiseSummary>,
+}
+
+#[derive(Debug, Default)]
+struct TenantTrendState {
+    windows: VecDeque<NoiseWindow>,
+    /// Start of the window an alert was last raised for
+    alerted_window: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct NoiseTrendMonitor {
+    config: NoiseTrendsConfig,
+    tenants: RwLock<BTreeMap<String, TenantTrendState>>,
+}
+
+impl NoiseTrendMonitor {
+    pub fn new(config: NoiseTrendsConfig) -> Self {
+        Self {
+            config,
+            tenants: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    fn window_start(&self, now: i64) -> i64 {
+        let width = self.config.window_seconds.max(1) as i64;
+        now - now.rem_euclid(width)
+    }
+
+    /// Record an operation that spent `consumed` bits and left `remaining`,
+    /// returning an alert when it takes the window past the baseline
+    pub fn record(
+        &self,
+        tenant: &str,
+        consumed: u64,
+        remaining: u64,
+        now: i64,
+    ) -> Option<NoiseTrendAlert> {
+        let start = self.window_start(now);
+        let mut tenants = self.tenants.write().unwrap();
+        if !tenants.contains_key(tenant) && tenants.len() >= self.config.max_tenants {
+            return None;
+        }
+        let state = tenants.entry(tenant.to_string()).or_default();
+        if state.windows.back().is_none_or(|w| w.start < start) {
+            state.windows.push_back(NoiseWindow::new(start));
+            while state.windows.len() > self.config.retained_windows.max(1) {
+                state.windows.pop_front();
+            }
+        }
+        let window = state.windows.back_mut()?;
+        window.operations += 1;
+        window.bits_consumed = window.bits_consumed.saturating_add(consumed);
+        if remaining < self.config.bootstrap_threshold_bits {
+            window.bootstraps_needed += 1;
+        }
+
+        let window = state.windows.back()?;
+        if window.operations < self.config.min_operations
+            || state.alerted_window == Some(window.start)
+        {
+            return None;
+        }
+        let baseline = self.baseline(&state.windows)?;
+        let rate = window.bits_per_operation();
+        if baseline <= 0.0 || rate <= baseline * self.config.alert_ratio {
+            return None;
+        }
+        state.alerted_window = Some(window.start);
+        Some(NoiseTrendAlert {
+            tenant: tenant.to_string(),
+            window_start: window.start,
+            bits_per_operation: rate,
+            baseline_bits_per_operation: baseline,
+            ratio: rate / baseline,
+        })
+    }
+
+    /// Mean bits per operation over the windows before the current one; none
+    /// until they hold `min_operations`
+    fn baseline(&self, windows: &VecDeque<NoiseWindow>) -> Option<f64> {
+        let (operations, bits) = windows
+            .iter()
+            .rev()
+            .skip(1)
+            .filter(|w| w.operations > 0)
+            .take(self.config.baseline_windows)
+            .fold((0u64, 0u64), |(operations, bits), w| {
+                (
+                    operations + w.operations,
+                    bits.saturating_add(w.bits_consumed),
+                )
+            });
+        (operations > 0 && operations >= self.config.min_operations)
+            .then(|| bits as f64 / operations as f64)
+    }
+
+    /// Bootstraps per hour of each closed window, oldest first
+    fn bootstrap_demand(&self, windows: &[NoiseWindow], now: i64) -> Vec<(i64, f64)> {
+        let per_hour = 3600.0 / self.config.window_seconds.max(1) as f64;
+        let current = self.window_start(now);
+        windows
+            .iter()
+            .filter(|w| w.start < current)
+            .map(|w| (w.start, w.bootstraps_needed as f64 * per_hour))
+            .collect()
+    }
+
+    fn forecast(&self, demand: &[(i64, f64)]) -> BootstrapForecast {
+        let (current, slope) = fit_line(demand);
+        let capacity = (self.config.bootstrap_capacity_per_hour > 0)
+            .then_some(self.config.bootstrap_capacity_per_hour);
+        let hours_until_capacity = capacity.and_then(|capacity| {
+            let capacity = capacity as f64;
+            if current >= capacity {
+                Some(0.0)
+            } else {
+                (slope > 0.0).then(|| (capacity - current) / slope)
+            }
+        });
+        BootstrapForecast {
+            bootstraps_per_hour: current,
+            trend_per_hour: slope,
+            forecast_hours: self.config.forecast_hours,
+            projected_bootstraps_per_hour: (current + slope * self.config.forecast_hours as f64)
+                .max(0.0),
+            capacity_per_hour: capacity,
+            hours_until_capacity,
+        }
+    }
+
+    pub fn tenant_trend(&self, tenant: &str, now: i64) -> Option<TenantNoiseTrend> {
+        let tenants = self.tenants.read().unwrap();
+        let state = tenants.get(tenant)?;
+        let windows: Vec<NoiseWindow> = state.windows.iter().cloned().collect();
+        Some(TenantNoiseTrend {
+            tenant: tenant.to_string(),
+            bits_per_operation: self.current_rate(&windows, now),
+            baseline_bits_per_operation: self.baseline(&state.windows),
+            forecast: self.forecast(&self.bootstrap_demand(&windows, now)),
+            windows,
+        })
+    }
+
+    pub fn report(&self, now: i64) -> NoiseTrendReport {
+        let tenants = self.tenants.read().unwrap();
+        let mut total: BTreeMap<i64, f64> = BTreeMap::new();
+        let summaries = tenants
+            .iter()
+            .map(|(tenant, state)| {
+                let windows: Vec<NoiseWindow> = state.windows.iter().cloned().collect();
+                let demand = self.bootstrap_demand(&windows, now);
+                for &(start, per_hour) in &demand {
+                    *total.entry(start).or_default() += per_hour;
+                }
+                TenantNoiseSummary {
+                    tenant: tenant.clone(),
+                    bits_per_operation: self.current_rate(&windows, now),
+                    baseline_bits_per_operation: self.baseline(&state.windows),
+                    bootstraps_per_hour: demand.last().map_or(0.0, |&(_, per_hour)| per_hour),
+                    alerted: state.alerted_window == Some(self.window_start(now)),
+                }
+            })
+            .collect();
+        let total: Vec<(i64, f64)> = total.into_iter().collect();
+        NoiseTrendReport {
+            window_seconds: self.config.window_seconds,
+            forecast: self.forecast(&total),
+            tenants: summaries,
+        }
+    }
+
+    fn current_rate(&self, windows: &[NoiseWindow], now: i64) -> f64 {
+        windows
+            .last()
+            .filter(|w| w.start == self.window_start(now))
+            .map_or(0.0, NoiseWindow::bits_per_operation)
+    }
+}
+
+/// Least-squares line through `(time, value)` points, as the fitted value at
+/// the last point and the slope per hour
+fn fit_line(points: &[(i64, f64)]) -> (f64, f64) {
+    let Some(&(last, _)) = points.last() else {
+        return (0.0, 0.0);
+    };
+    let n = points.len() as f64;
+    let xs: Vec<f64> = points
+        .iter()
+        .map(|&(t, _)| (t - last) as f64 / 3600.0)
+        .collect();
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let variance: f64 = xs.iter().map(|x| (x - mean_x).powi(2)).sum();
+    if variance == 0.0 {
+        return (mean_y.max(0.0), 0.0);
+    }
+    let covariance: f64 = xs
+        .iter()
+        .zip(points)
+        .map(|(x, &(_, y))| (x - mean_x) * (y - mean_y))
+        .sum();
+    let slope = covariance / variance;
+    // The last point sits at x = 0
+    ((mean_y - slope * mean_x).max(0.0), slope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alerts_on_rate_above_baseline_and_forecasts_bootstraps() {
+        let monitor = NoiseTrendMonitor::new(NoiseTrendsConfig {
+            enabled: true,
+            window_seconds: 3600,
+            baseline_windows: 2,
+            min_operations: 4,
+            alert_ratio: 2.0,
+            bootstrap_threshold_bits: 10,
+            bootstrap_capacity_per_hour: 10,
+            forecast_hours: 4,
+            ..NoiseTrendsConfig::default()
+        });
+
+        // Two hours at 5 bits per operation, with one and then two bootstraps
+        for (hour, low) in [(0, 1), (1, 2)] {
+            for i in 0..4 {
+                let remaining = if i < low { 5 } else { 40 };
+                assert_eq!(monitor.record("acme", 5, remaining, hour * 3600 + i), None);
+            }
+        }
+
+        // Tripled consumption alerts once, after `min_operations`
+        let now = 2 * 3600;
+        for i in 0..3 {
+            assert_eq!(monitor.record("acme", 15, 40, now + i), None);
+        }
+        let alert = monitor.record("acme", 15, 40, now + 3).unwrap();
+        assert_eq!(alert.baseline_bits_per_operation, 5.0);
+        assert_eq!(alert.bits_per_operation, 15.0);
+        assert_eq!(alert.ratio, 3.0);
+        assert_eq!(monitor.record("acme", 15, 40, now + 4), None);
+
+        let trend = monitor.tenant_trend("acme", now + 5).unwrap();
+        assert_eq!(trend.windows.len(), 3);
+        assert_eq!(trend.bits_per_operation, 15.0);
+        // Demand rose from 1 to 2 per hour: reaches 10 in 8 more hours
+        assert_eq!(trend.forecast.bootstraps_per_hour, 2.0);
+        assert_eq!(trend.forecast.trend_per_hour, 1.0);
+        assert_eq!(trend.forecast.projected_bootstraps_per_hour, 6.0);
+        assert_eq!(trend.forecast.hours_until_capacity, Some(8.0));
+
+        let report = monitor.report(now + 5);
+        assert_eq!(report.forecast, trend.forecast);
+        assert!(report.tenants[0].alerted);
+        assert!(monitor.tenant_trend("other", now).is_none());
+    }
+}
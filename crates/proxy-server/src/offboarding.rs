@@ -0,0 +1,270 @@
+//! Tenant offboarding with verified data destruction
+//!
+//! Offboarding closes a tenant's sessions and drops their keys, then deletes
+//! what the proxy holds for it: response ciphertexts and document results,
+//! cached completions, conversation context in every tier, privacy and
+//! spending budgets, and every record of the persistence backend, billing
+//! ledger included. Ciphertexts the proxy did not produce for the tenant are
+//! left to expire; without the dropped keys nobody can use them. A second pass
+//! over the same stores must then find nothing left.
+//!
+//! Each step, with what it deleted and when, goes into a destruction report
+//! signed with the attestation key. The audit log is kept: it is the evidence
+//! that the destruction happened. An offboarded tenant's requests are refused.
+//!
+//! Offboarding takes two admin requests: the first only issues a confirmation
+//! token, valid for [`CONFIRMATION_TTL_SECONDS`] and for that tenant only, and
+//! the second must present it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Audit action recording a finished offboarding, with the signed report
+pub const OFFBOARDED_AUDIT_ACTION: &str = "tenant.offboarded";
+
+/// How long an offboarding confirmation token is accepted
+pub const CONFIRMATION_TTL_SECONDS: i64 = 300;
+
+/// Items deleted by kind, from `(kind, count)` pairs
+pub fn counts<const N: usize>(items: [(&str, usize); N]) -> BTreeMap<String, usize> {
+    items
+        .into_iter()
+        .map(|(kind, count)| (kind.to_string(), count))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DestructionStep {
+    /// What was destroyed, e.g. `sessions` or `contexts`
+    pub step: String,
+    /// Where it was held
+    pub store: String,
+    pub deleted: BTreeMap<String, usize>,
+    pub completed_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DestructionReport {
+    pub report_id: Uuid,
+    pub tenant: String,
+    pub reason: Option<String>,
+    pub started_at: i64,
+    pub completed_at: i64,
+    pub steps: Vec<DestructionStep>,
+    pub total_deleted: usize,
+    /// Items the verification pass still found, and deleted, by kind
+    pub remaining: BTreeMap<String, usize>,
+    /// Whether the verification pass found nothing left
+    pub verified: bool,
+}
+
+impl DestructionReport {
+    pub fn new(tenant: &str, reason: Option<String>, now: i64) -> Self {
+        Self {
+            report_id: Uuid::new_v4(),
+            tenant: tenant.to_string(),
+            reason,
+            started_at: now,
+            completed_at: now,
+            steps: Vec::new(),
+            total_deleted: 0,
+            remaining: BTreeMap::new(),
+            verified: false,
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        step: &str,
+        store: &str,
+        deleted: BTreeMap<String, usize>,
+        now: i64,
+    ) -> &DestructionStep {
+        self.total_deleted += deleted.values().sum::<usize>();
+        self.steps.push(DestructionStep {
+            step: step.to_string(),
+            store: store.to_string(),
+            deleted,
+            completed_at: now,
+        });
+        self.steps.last().unwrap()
+    }
+
+    /// Close the report with what the verification pass found. Anything it
+    /// found was written while the steps ran; offboarding again removes it.
+    pub fn verify(&mut self, found: BTreeMap<String, usize>, now: i64) {
+        self.remaining = found.into_iter().filter(|(_, count)| *count > 0).collect();
+        self.verified = self.remaining.is_empty();
+        self.completed_at = now;
+    }
+}
+
+/// Destruction report signed by the attestation service; the signature covers
+/// the compact JSON encoding of `report`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedDestructionReport {
+    pub report: DestructionReport,
+    pub key_id: String,
+    pub algorithm: String,
+    pub signature: String, // Base64 encoded
+}
+
+/// Offboarded tenants, and which tenant each in-memory response ciphertext
+/// was produced for
+#[derive(Debug, Default)]
+pub struct TenantOffboarding {
+    ciphertext_owners: RwLock<HashMap<Uuid, String>>,
+    offboarded: RwLock<HashSet<String>>,
+    /// Outstanding confirmation token per tenant, with when it expires
+    confirmations: RwLock<HashMap<String, (String, i64)>>,
+}
+
+impl TenantOffboarding {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember that ciphertexts were produced for `tenant`; requests without
+    /// a tenant own nothing
+    pub fn record_ciphertexts(&self, tenant: Option<&str>, ids: impl IntoIterator<Item = Uuid>) {
+        let Some(tenant) = tenant else {
+            return;
+        };
+        let mut owners = self.ciphertext_owners.write().unwrap();
+        for id in ids {
+            owners.insert(id, tenant.to_string());
+        }
+    }
+
+    /// Drop ownership of ciphertexts no longer held
+    pub fn retain_ciphertexts(&self, held: impl Fn(&Uuid) -> bool) {
+        self.ciphertext_owners
+            .write()
+            .unwrap()
+            .retain(|id, _| held(id));
+    }
+
+    /// Remove and return the ciphertexts produced for `tenant`
+    pub fn take_ciphertexts(&self, tenant: &str) -> Vec<Uuid> {
+        let mut owners = self.ciphertext_owners.write().unwrap();
+        let ids: Vec<Uuid> = owners
+            .iter()
+            .filter(|(_, owner)| owner.as_str() == tenant)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ids {
+            owners.remove(id);
+        }
+        ids
+    }
+
+    pub fn mark_offboarded(&self, tenant: &str) {
+        self.offboarded.write().unwrap().insert(tenant.to_string());
+    }
+
+    pub fn is_offboarded(&self, tenant: &str) -> bool {
+        self.offboarded.read().unwrap().contains(tenant)
+    }
+
+    /// Issue a token confirming `tenant`'s offboarding, replacing any earlier
+    /// one; returns it with its expiry
+    pub fn issue_confirmation(&self, tenant: &str, now: i64) -> (String, i64) {
+        let token = rand::random::<[u8; 16]>()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        let expires_at = now + CONFIRMATION_TTL_SECONDS;
+        self.confirmations
+            .write()
+            .unwrap()
+            .insert(tenant.to_string(), (token.clone(), expires_at));
+        (token, expires_at)
+    }
+
+    /// Use up `tenant`'s confirmation token; false if `token` isn't the
+    /// outstanding one or has expired
+    pub fn confirm(&self, tenant: &str, token: &str, now: i64) -> bool {
+        let mut confirmations = self.confirmations.write().unwrap();
+        match confirmations.get(tenant) {
+            Some((expected, expires_at)) if expected == token && now < *expires_at => {
+                confirmations.remove(tenant);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::AttestationService;
+    use base64::prelude::*;
+    use ring::signature;
+
+    #[test]
+    fn test_confirmation_is_single_use_per_tenant_and_expires() {
+        let offboarding = TenantOffboarding::new();
+        let (token, expires_at) = offboarding.issue_confirmation("acme", 100);
+        assert_eq!(expires_at, 100 + CONFIRMATION_TTL_SECONDS);
+        assert!(!offboarding.confirm("globex", &token, 101));
+        assert!(!offboarding.confirm("acme", "guess", 101));
+        assert!(offboarding.confirm("acme", &token, 101));
+        assert!(!offboarding.confirm("acme", &token, 101));
+
+        // A new token replaces the outstanding one
+        let (first, _) = offboarding.issue_confirmation("acme", 200);
+        let (second, expires_at) = offboarding.issue_confirmation("acme", 200);
+        assert!(!offboarding.confirm("acme", &first, 201));
+        assert!(!offboarding.confirm("acme", &second, expires_at));
+    }
+
+    #[test]
+    fn test_report_is_verified_and_signed_over_ownership_index() {
+        let offboarding = TenantOffboarding::new();
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        offboarding.record_ciphertexts(Some("acme"), [a, b]);
+        offboarding.record_ciphertexts(Some("globex"), [c]);
+        offboarding.record_ciphertexts(None, [Uuid::new_v4()]);
+        offboarding.retain_ciphertexts(|id| *id != b);
+        assert_eq!(offboarding.take_ciphertexts("acme"), vec![a]);
+        assert!(offboarding.take_ciphertexts("acme").is_empty());
+        assert_eq!(offboarding.take_ciphertexts("globex"), vec![c]);
+
+        offboarding.mark_offboarded("acme");
+        assert!(offboarding.is_offboarded("acme"));
+        assert!(!offboarding.is_offboarded("acme:eu"));
+
+        let mut report = DestructionReport::new("acme", Some("contract ended".to_string()), 100);
+        report.record(
+            "sessions",
+            "memory",
+            counts([("sessions", 2), ("keys", 4)]),
+            101,
+        );
+        report.record("persistence", "sqlite", counts([("billing", 7)]), 102);
+        assert_eq!(report.total_deleted, 13);
+        report.verify(counts([("sessions", 0), ("billing", 1)]), 103);
+        assert!(!report.verified);
+        assert_eq!(report.remaining, counts([("billing", 1)]));
+        report.verify(counts([("sessions", 0), ("billing", 0)]), 104);
+        assert!(report.verified);
+        assert_eq!(report.completed_at, 104);
+
+        let attestation = AttestationService::new().unwrap();
+        let signed = attestation.sign_destruction_report(&report).unwrap();
+        assert_eq!(signed.key_id, attestation.current_key().key_id);
+        let public_key = BASE64_STANDARD
+            .decode(attestation.current_key().public_key)
+            .unwrap();
+        let signature_bytes = BASE64_STANDARD.decode(&signed.signature).unwrap();
+        let key = signature::UnparsedPublicKey::new(&signature::ED25519, &public_key);
+        let payload = serde_json::to_vec(&signed.report).unwrap();
+        assert!(key.verify(&payload, &signature_bytes).is_ok());
+        report.tenant = "globex".to_string();
+        let tampered = serde_json::to_vec(&report).unwrap();
+        assert!(key.verify(&tampered, &signature_bytes).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+//! Disk-backed overflow queue that absorbs traffic bursts
+//!
+//! When priority admission has no room for a Normal or Low priority request,
+//! its body is spilled to a bounded, append-only ring file on local disk
+//! instead of the request being rejected, so a burst does not have to be held
+//! in memory. The request head stays with its waiting connection. A drain
+//! loop hands spilled bodies back as in-flight capacity frees up: first in,
+//! first out within a priority, Normal before Low. High and Critical work never
+//! takes this slow path.
+//!
+//! Ring files are truncated at startup: the connections waiting on anything
+//! spilled by a previous process are gone.
+
+use crate::config::OverflowQueueConfig;
+use crate::error::{Error, Result};
+use crate::scaling::RequestPriority;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// Priorities that may be spilled, in the order they are drained
+const SPILLABLE: [RequestPriority; 2] = [RequestPriority::Normal, RequestPriority::Low];
+
+struct Spilled {
+    len: u64,
+    enqueued_at: Instant,
+    waiter: oneshot::Sender<Vec<u8>>,
+}
+
+/// Append-only ring over one file; `head` and `tail` are logical offsets that
+/// only grow, wrapped onto the file by `capacity`
+struct Ring {
+    priority: RequestPriority,
+    file: File,
+    capacity: u64,
+    head: u64,
+    tail: u64,
+    entries: VecDeque<Spilled>,
+}
+
+impl Ring {
+    fn open(dir: &Path, priority: RequestPriority, capacity: u64) -> Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(dir.join(format!("overflow-{}.ring", priority.as_str())))?;
+        Ok(Self {
+            priority,
+            file,
+            capacity,
+            head: 0,
+            tail: 0,
+            entries: VecDeque::new(),
+        })
+    }
+
+    fn used(&self) -> u64 {
+        self.tail - self.head
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<()> {
+        let start = self.tail % self.capacity;
+        let first = ((self.capacity - start) as usize).min(data.len());
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.write_all(&data[..first])?;
+        if first < data.len() {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&data[first..])?;
+        }
+        self.tail += data.len() as u64;
+        Ok(())
+    }
+
+    /// Read the oldest entry's bytes and free its space
+    fn pop(&mut self) -> Result<Option<(Spilled, Vec<u8>)>> {
+        let Some(entry) = self.entries.pop_front() else {
+            return Ok(None);
+        };
+        let mut data = vec![0u8; entry.len as usize];
+        let start = self.head % self.capacity;
+        let first = ((self.capacity - start) as usize).min(data.len());
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.read_exact(&mut data[..first])?;
+        if first < data.len() {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.read_exact(&mut data[first..])?;
+        }
+        self.head += entry.len;
+        Ok(Some((entry, data)))
+    }
+}
+
+#[derive(Debug, Default)]
+struct OverflowCounters {
+    spilled: u64,
+    reinjected: u64,
+    expired: u64,
+    abandoned: u64,
+    rejected_full: u64,
+    total_lag_ms: u64,
+    last_lag_ms: u64,
+    max_lag_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OverflowRingStats {
+    pub priority: RequestPriority,
+    pub depth: usize,
+    pub bytes_used: u64,
+    pub capacity_bytes: u64,
+    /// How long the oldest spilled request has been waiting
+    pub oldest_wait_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OverflowStats {
+    pub enabled: bool,
+    pub rings: Vec<OverflowRingStats>,
+    pub spilled: u64,
+    pub reinjected: u64,
+    /// Dropped after waiting longer than `max_wait_seconds`
+    pub expired: u64,
+    /// Dropped because the client went away while waiting
+    pub abandoned: u64,
+    /// Refused because the ring was full
+    pub rejected_full: u64,
+    pub last_lag_ms: u64,
+    pub max_lag_ms: u64,
+    pub avg_lag_ms: f64,
+}
+
+pub struct OverflowQueue {
+    config: OverflowQueueConfig,
+    rings: Mutex<Vec<Ring>>,
+    counters: Mutex<OverflowCounters>,
+}
+
+impl std::fmt::Debug for OverflowQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OverflowQueue")
+            .field("config", &self.config)
+            .field("counters", &self.counters)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OverflowQueue {
+    pub fn new(config: OverflowQueueConfig) -> Result<Self> {
+        let mut rings = Vec::new();
+        if config.enabled {
+            let dir = Path::new(&config.path);
+            std::fs::create_dir_all(dir)?;
+            for priority in SPILLABLE {
+                rings.push(Ring::open(dir, priority, config.max_bytes_per_priority)?);
+            }
+        }
+        Ok(Self {
+            config,
+            rings: Mutex::new(rings),
+            counters: Mutex::new(OverflowCounters::default()),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &OverflowQueueConfig {
+        &self.config
+    }
+
+    /// Whether requests of `priority` may wait here instead of being shed
+    pub fn accepts(&self, priority: RequestPriority) -> bool {
+        self.config.enabled && SPILLABLE.contains(&priority)
+    }
+
+    /// Write `body` to the ring for `priority`; the receiver gets it back once
+    /// the drain loop re-injects it
+    pub fn spill(
+        &self,
+        priority: RequestPriority,
+        body: &[u8],
+    ) -> Result<oneshot::Receiver<Vec<u8>>> {
+        if !self.accepts(priority) {
+            return Err(Error::Validation(format!(
+                "{} priority requests are not spilled to the overflow queue",
+                priority.as_str()
+            )));
+        }
+        let mut rings = self.rings.lock().unwrap();
+        let ring = rings.iter_mut().find(|r| r.priority == priority).unwrap();
+        if ring.entries.len() >= self.config.max_entries
+            || ring.used() + body.len() as u64 > ring.capacity
+        {
+            self.counters.lock().unwrap().rejected_full += 1;
+            return Err(Error::ResourceExhaustion(format!(
+                "Overflow queue for {} priority is full",
+                priority.as_str()
+            )));
+        }
+        ring.append(body)?;
+        let (waiter, receiver) = oneshot::channel();
+        ring.entries.push_back(Spilled {
+            len: body.len() as u64,
+            enqueued_at: Instant::now(),
+            waiter,
+        });
+        self.counters.lock().unwrap().spilled += 1;
+        Ok(receiver)
+    }
+
+    /// Hand spilled bodies back to their waiters while `available` reports free
+    /// admission slots for their priority, returning how many were re-injected
+    pub fn release(&self, available: impl Fn(RequestPriority) -> usize) -> Result<usize> {
+        let max_wait = Duration::from_secs(self.config.max_wait_seconds);
+        let mut rings = self.rings.lock().unwrap();
+        let mut counters = self.counters.lock().unwrap();
+        let mut released = 0;
+        for ring in rings.iter_mut() {
+            // Slots handed out above still count against lower priorities
+            let mut slots = available(ring.priority).saturating_sub(released);
+            while slots > 0 {
+                let Some((entry, body)) = ring.pop()? else {
+                    break;
+                };
+                let lag = entry.enqueued_at.elapsed();
+                if lag > max_wait {
+                    counters.expired += 1;
+                    continue;
+                }
+                if entry.waiter.send(body).is_err() {
+                    counters.abandoned += 1;
+                    continue;
+                }
+                let lag_ms = lag.as_millis() as u64;
+                counters.reinjected += 1;
+                counters.total_lag_ms += lag_ms;
+                counters.last_lag_ms = lag_ms;
+                counters.max_lag_ms = counters.max_lag_ms.max(lag_ms);
+                released += 1;
+                slots -= 1;
+            }
+            if ring.entries.is_empty() {
+                ring.head = 0;
+                ring.tail = 0;
+            }
+        }
+        Ok(released)
+    }
+
+    pub fn stats(&self) -> OverflowStats {
+        let rings = self
+            .rings
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|ring| OverflowRingStats {
+                priority: ring.priority,
+                depth: ring.entries.len(),
+                bytes_used: ring.used(),
+                capacity_bytes: ring.capacity,
+                oldest_wait_ms: ring
+                    .entries
+                    .front()
+                    .map_or(0, |e| e.enqueued_at.elapsed().as_millis() as u64),
+            })
+            .collect();
+        let counters = self.counters.lock().unwrap();
+        OverflowStats {
+            enabled: self.config.enabled,
+            rings,
+            spilled: counters.spilled,
+            reinjected: counters.reinjected,
+            expired: counters.expired,
+            abandoned: counters.abandoned,
+            rejected_full: counters.rejected_full,
+            last_lag_ms: counters.last_lag_ms,
+            max_lag_ms: counters.max_lag_ms,
+            avg_lag_ms: counters.total_lag_ms as f64 / counters.reinjected.max(1) as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overflow_queue_spills_and_reinjects_in_priority_order() {
+        let dir = std::env::temp_dir().join(format!("fhe-overflow-{}", uuid::Uuid::new_v4()));
+        let queue = OverflowQueue::new(OverflowQueueConfig {
+            enabled: true,
+            path: dir.to_string_lossy().to_string(),
+            max_bytes_per_priority: 10,
+            max_entries: 3,
+            ..OverflowQueueConfig::default()
+        })
+        .unwrap();
+
+        // Critical and High work is never put on the slow path
+        assert!(!queue.accepts(RequestPriority::Critical));
+        assert!(queue.spill(RequestPriority::Critical, b"x").is_err());
+        assert!(queue.spill(RequestPriority::High, b"x").is_err());
+
+        let mut low = queue.spill(RequestPriority::Low, b"low").unwrap();
+        let mut first = queue.spill(RequestPriority::Normal, b"abcdef").unwrap();
+        let dropped = queue.spill(RequestPriority::Normal, b"gh").unwrap();
+        assert!(queue.spill(RequestPriority::Normal, b"ijk").is_err());
+        drop(dropped);
+
+        // One slot: the oldest Normal request goes before Low
+        assert_eq!(queue.release(|_| 1).unwrap(), 1);
+        assert_eq!(first.try_recv().unwrap(), b"abcdef");
+        assert!(low.try_recv().is_err());
+
+        // The next Normal body wraps around the end of the ring file
+        let mut wrapped = queue.spill(RequestPriority::Normal, b"0123456").unwrap();
+        assert_eq!(queue.release(|_| 5).unwrap(), 2);
+        assert_eq!(wrapped.try_recv().unwrap(), b"0123456");
+        assert_eq!(low.try_recv().unwrap(), b"low");
+
+        let stats = queue.stats();
+        assert_eq!(stats.spilled, 4);
+        assert_eq!(stats.reinjected, 3);
+        assert_eq!(stats.abandoned, 1);
+        assert_eq!(stats.rejected_full, 1);
+        assert!(stats
+            .rings
+            .iter()
+            .all(|r| r.depth == 0 && r.bytes_used == 0));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,2481 @@
+//! Generation 3 Performance Optimizations
+//!
+//! Advanced performance optimization including:
+//! - Intelligent caching strategies
+//! - Adaptive load balancing
+//! - Memory optimization
+//! - GPU acceleration (when available)
+//! - Concurrent processing pipelines
+
+use crate::config::FailureDomain;
+use crate::error::{Error, Result};
+use crate::fhe::{Ciphertext, EngineFingerprint, FheEngine, FheParams};
+pub use crate::scaling::RequestPriority;
+use chrono::{DateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, RwLock,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+/// Advanced performance manager
+#[derive(Debug)]
+pub struct PerformanceManager {
+    /// Intelligent cache
+    cache_system: Arc<IntelligentCacheSystem>,
+    /// Load balancer
+    load_balancer: Arc<AdaptiveLoadBalancer>,
+    /// Memory optimizer
+    memory_optimizer: Arc<MemoryOptimizer>,
+    /// Request pipeline
+    pipeline: Arc<ProcessingPipeline>,
+    /// Performance metrics
+    metrics: Arc<PerformanceMetrics>,
+    /// Snapshots retained for time-range queries
+    history: Arc<StatsHistory>,
+}
+
+/// Intelligent multi-tier cache system
+#[derive(Debug)]
+pub struct IntelligentCacheSystem {
+    /// L1 (fastest, smallest) to L3 (slower, largest) and the comparison
+    /// strategies run beside them
+    state: Arc<RwLock<CacheState>>,
+    /// Prediction engine
+    predictor: Arc<CachePredictionEngine>,
+    /// Cache statistics
+    stats: Arc<CacheStatistics>,
+    /// Configuration
+    config: CacheConfiguration,
+}
+
+/// Cache prediction engine for preloading
+///
+/// Learns in which hours of the day each key is used. A key used in the same
+/// hour on most days is predicted for that hour tomorrow, so it can be loaded
+/// before the traffic arrives. Whether a preloaded entry was used before its
+/// hour passed is what prediction accuracy measures.
+#[derive(Debug)]
+pub struct CachePredictionEngine<K = CacheKey> {
+    /// Access patterns
+    access_patterns: Arc<RwLock<HashMap<K, AccessPattern<K>>>>,
+    /// Temporal patterns
+    temporal_patterns: Arc<RwLock<VecDeque<TemporalAccess<K>>>>,
+    /// Prediction model weights
+    model_weights: Arc<RwLock<PredictionModel>>,
+    /// Preloaded keys not yet used, with when they were preloaded
+    outstanding_preloads: Arc<RwLock<HashMap<K, DateTime<Utc>>>>,
+    /// Preloads used before their hour passed
+    useful_preloads: Arc<AtomicU64>,
+    /// Preloads that went unused
+    wasted_preloads: Arc<AtomicU64>,
+}
+
+/// Access pattern analysis
+#[derive(Debug, Clone)]
+pub struct AccessPattern<K = CacheKey> {
+    pub key: K,
+    pub frequency: u64,
+    /// Size of the value last stored under the key
+    pub size_bytes: usize,
+    /// Day of the first access, in days since the Unix epoch
+    pub first_day: i64,
+    /// Days on which the key was used in each hour of the day (UTC)
+    pub temporal_distribution: Vec<u64>,
+    /// Last day counted for each hour
+    last_day_by_hour: Vec<i64>,
+}
+
+/// A key expected to be needed in an upcoming hour
+#[derive(Debug, Clone)]
+pub struct PreloadPrediction<K = CacheKey> {
+    pub key: K,
+    /// Share of observed days on which the key was used in that hour
+    pub confidence: f64,
+    pub size_bytes: usize,
+}
+
+/// Temporal access tracking
+#[derive(Debug, Clone)]
+pub struct TemporalAccess<K = CacheKey> {
+    pub timestamp: Instant,
+    pub key: K,
+    pub operation: CacheOperation,
+}
+
+#[derive(Debug, Clone)]
+pub enum CacheOperation {
+    Hit,
+    Miss,
+    Eviction,
+    Preload,
+}
+
+/// Prediction model for cache optimization
+#[derive(Debug, Clone)]
+pub struct PredictionModel {
+    pub temporal_weights: Vec<f64>,
+    pub frequency_weights: Vec<f64>,
+    pub sequence_weights: Vec<f64>,
+    pub learning_rate: f64,
+    pub confidence_threshold: f64,
+}
+
+/// Adaptive load balancer
+#[derive(Debug)]
+pub struct AdaptiveLoadBalancer {
+    /// Engine pool
+    engines: Arc<RwLock<Vec<EngineInstance>>>,
+    /// Load balancing strategy
+    strategy: Arc<RwLock<LoadBalanceStrategy>>,
+    /// Health monitoring
+    health_monitor: Arc<HealthMonitor>,
+    /// Request queue
+    request_queue: Arc<PriorityRequestQueue>,
+}
+
+/// Engine instance with health tracking
+#[derive(Debug)]
+pub struct EngineInstance {
+    pub id: Uuid,
+    pub engine: Arc<RwLock<FheEngine>>,
+    /// Build and parameter-table identity reported when the engine joined
+    pub fingerprint: EngineFingerprint,
+    /// Host, zone and region, so failures they share are handled together
+    pub domain: FailureDomain,
+    pub current_load: Arc<AtomicUsize>,
+    pub health_score: Arc<AtomicU64>, // 0-100
+    pub response_times: Arc<RwLock<VecDeque<Duration>>>,
+    pub error_count: Arc<AtomicU64>,
+    pub last_used: Arc<RwLock<Instant>>,
+}
+
+/// Dynamic load balancing strategies
+#[derive(Debug, Clone)]
+pub enum LoadBalanceStrategy {
+    RoundRobin,
+    LeastConnections,
+    WeightedRoundRobin { weights: Vec<f64> },
+    ResponseTimeBased,
+    AdaptiveHybrid { weights: StrategyWeights },
+}
+
+#[derive(Debug, Clone)]
+pub struct StrategyWeights {
+    pub load_weight: f64,
+    pub response_time_weight: f64,
+    pub health_weight: f64,
+    pub error_rate_weight: f64,
+}
+
+/// Health monitoring system
+#[derive(Debug)]
+pub struct HealthMonitor {
+    /// Health checks
+    health_checks: Arc<RwLock<HashMap<Uuid, HealthStatus>>>,
+    /// Check interval
+    check_interval: Duration,
+    /// Degradation thresholds
+    thresholds: HealthThresholds,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthStatus {
+    pub engine_id: Uuid,
+    pub is_healthy: bool,
+    pub last_check: Instant,
+    pub response_time: Duration,
+    pub error_rate: f64,
+    pub cpu_usage: f64,
+    pub memory_usage: f64,
+    pub degradation_factors: Vec<DegradationFactor>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DegradationFactor {
+    pub factor_type: String,
+    pub severity: f64, // 0.0 to 1.0
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthThresholds {
+    pub max_response_time: Duration,
+    pub max_error_rate: f64,
+    pub max_cpu_usage: f64,
+    pub max_memory_usage: f64,
+    pub min_health_score: u64,
+}
+
+/// Priority-based request queue
+#[derive(Debug)]
+pub struct PriorityRequestQueue {
+    /// High priority queue
+    high_priority: Arc<RwLock<VecDeque<QueuedRequest>>>,
+    /// Normal priority queue
+    normal_priority: Arc<RwLock<VecDeque<QueuedRequest>>>,
+    /// Low priority queue  
+    low_priority: Arc<RwLock<VecDeque<QueuedRequest>>>,
+    /// Queue statistics
+    stats: Arc<QueueStatistics>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueuedRequest {
+    pub id: Uuid,
+    pub priority: RequestPriority,
+    pub queued_at: Instant,
+    pub estimated_duration: Duration,
+    pub client_id: Option<Uuid>,
+    pub operation_type: OperationType,
+}
+
+#[derive(Debug, Clone)]
+pub enum OperationType {
+    Encrypt,
+    Decrypt,
+    Process,
+    Validate,
+}
+
+/// Memory optimization system
+#[derive(Debug)]
+pub struct MemoryOptimizer {
+    /// Memory pools
+    pools: Arc<RwLock<HashMap<PoolType, MemoryPool>>>,
+    /// Garbage collection scheduler
+    gc_scheduler: Arc<GcScheduler>,
+    /// Memory usage tracking
+    memory_tracker: Arc<MemoryTracker>,
+    /// Optimization strategies
+    strategies: Arc<RwLock<Vec<OptimizationStrategy>>>,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolType {
+    Ciphertext,
+    Intermediate,
+    Result,
+    Metadata,
+}
+
+impl PoolType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PoolType::Ciphertext => "ciphertext",
+            PoolType::Intermediate => "intermediate",
+            PoolType::Result => "result",
+            PoolType::Metadata => "metadata",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MemoryPool {
+    pub pool_type: PoolType,
+    pub allocated_bytes: Arc<AtomicUsize>,
+    pub peak_usage: Arc<AtomicUsize>,
+    pub available_slots: Arc<RwLock<Vec<MemorySlot>>>,
+    pub config: PoolConfiguration,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemorySlot {
+    pub id: Uuid,
+    pub size_bytes: usize,
+    pub is_free: bool,
+    pub last_used: Instant,
+    pub usage_count: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolConfiguration {
+    pub initial_size: usize,
+    pub max_size: usize,
+    pub growth_factor: f64,
+    pub shrink_threshold: f64,
+    pub cleanup_interval: Duration,
+}
+
+/// Garbage collection scheduler
+#[derive(Debug)]
+pub struct GcScheduler {
+    /// Last GC run
+    last_gc: Arc<RwLock<Instant>>,
+    /// GC interval
+    gc_interval: Duration,
+    /// Pressure thresholds
+    pressure_thresholds: PressureThresholds,
+}
+
+#[derive(Debug, Clone)]
+pub struct PressureThresholds {
+    pub memory_pressure: f64,     // 0.0 to 1.0
+    pub allocation_rate: f64,     // allocations per second
+    pub fragmentation_ratio: f64, // 0.0 to 1.0
+}
+
+/// Memory usage tracking
+#[derive(Debug)]
+pub struct MemoryTracker {
+    /// Total allocated
+    total_allocated: Arc<AtomicUsize>,
+    /// Peak usage
+    peak_usage: Arc<AtomicUsize>,
+    /// Allocation history
+    allocation_history: Arc<RwLock<VecDeque<AllocationEvent>>>,
+    /// Fragmentation metrics
+    fragmentation: Arc<RwLock<FragmentationMetrics>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocationEvent {
+    pub timestamp: Instant,
+    pub size_bytes: usize,
+    pub pool_type: PoolType,
+    pub operation: AllocationOperation,
+}
+
+#[derive(Debug, Clone)]
+pub enum AllocationOperation {
+    Allocate,
+    Deallocate,
+    Reallocate,
+}
+
+/// Heap fragmentation as reported by the global allocator (see `crate::allocator`)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FragmentationMetrics {
+    /// Active (or, without allocator stats, resident) bytes not backing live allocations
+    pub total_free_space: usize,
+    pub fragmentation_ratio: f64,
+    pub allocated_bytes: usize,
+    pub rss_bytes: usize,
+}
+
+/// Optimization strategy
+#[derive(Debug, Clone)]
+pub struct OptimizationStrategy {
+    pub name: String,
+    pub trigger_condition: TriggerCondition,
+    pub optimization_action: OptimizationAction,
+    pub priority: u32,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum TriggerCondition {
+    MemoryPressure(f64),
+    AllocationRate(f64),
+    FragmentationThreshold(f64),
+    ResponseTimeThreshold(Duration),
+    ErrorRateThreshold(f64),
+}
+
+#[derive(Debug, Clone)]
+pub enum OptimizationAction {
+    CompactMemory,
+    FlushCaches,
+    ReducePoolSizes,
+    TriggerGarbageCollection,
+    LoadBalance,
+    ScaleResources,
+}
+
+/// Processing pipeline for concurrent operations
+#[derive(Debug)]
+pub struct ProcessingPipeline {
+    /// Pipeline stages
+    stages: Arc<RwLock<Vec<PipelineStage>>>,
+    /// Worker pool
+    worker_pool: Arc<WorkerPool>,
+    /// Pipeline configuration
+    config: PipelineConfiguration,
+    /// Throughput monitoring
+    throughput_monitor: Arc<ThroughputMonitor>,
+}
+
+#[derive(Debug)]
+pub struct PipelineStage {
+    pub stage_id: Uuid,
+    pub name: String,
+    pub operation: StageOperation,
+    pub parallelism: usize,
+    pub buffer_size: usize,
+    pub semaphore: Arc<Semaphore>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageOperation {
+    Validation,
+    Encryption,
+    Processing,
+    Decryption,
+    Postprocessing,
+}
+
+impl StageOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StageOperation::Validation => "validation",
+            StageOperation::Encryption => "encryption",
+            StageOperation::Processing => "processing",
+            StageOperation::Decryption => "decryption",
+            StageOperation::Postprocessing => "postprocessing",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct WorkerPool {
+    /// Available workers
+    workers: Arc<RwLock<Vec<Worker>>>,
+    /// Work queue
+    work_queue: Arc<RwLock<VecDeque<WorkItem>>>,
+    /// Pool statistics
+    stats: Arc<WorkerPoolStats>,
+}
+
+#[derive(Debug)]
+pub struct Worker {
+    pub worker_id: Uuid,
+    pub is_busy: Arc<AtomicU64>,
+    pub tasks_completed: Arc<AtomicU64>,
+    pub average_task_time: Arc<RwLock<Duration>>,
+    pub last_activity: Arc<RwLock<Instant>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkItem {
+    pub item_id: Uuid,
+    pub priority: RequestPriority,
+    pub operation: StageOperation,
+    pub data: Vec<u8>,
+    pub context: WorkContext,
+    pub created_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkContext {
+    pub client_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub timeout: Duration,
+    pub retry_count: u32,
+    pub max_retries: u32,
+}
+
+/// Cache-related structures
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct CacheKey {
+    pub key_type: CacheKeyType,
+    pub identifier: String,
+    pub params_hash: u64,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum CacheKeyType {
+    Ciphertext,
+    ProcessedResult,
+    IntermediateState,
+    ValidationResult,
+    EvaluationKey,
+    TemplateCiphertext,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub key: CacheKey,
+    pub data: CacheData,
+    pub created_at: Instant,
+    pub last_accessed: Instant,
+    pub access_count: u64,
+    pub size_bytes: usize,
+    pub ttl: Duration,
+    pub priority_score: f64,
+    /// Weighted cost of producing the data again after a miss
+    pub recompute_cost: f64,
+}
+
+/// What it took to produce a cached value
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecomputeCost {
+    pub gpu_time: Duration,
+    /// Upstream provider spend, in the billing currency
+    pub provider_cost: f64,
+}
+
+/// How GPU time and provider spend combine into one recomputation cost
+#[derive(Debug, Clone, Copy)]
+pub struct RecomputeCostWeights {
+    pub gpu_ms: f64,
+    pub provider_cost: f64,
+}
+
+impl Default for RecomputeCostWeights {
+    fn default() -> Self {
+        Self {
+            gpu_ms: 1.0,
+            provider_cost: 1000.0,
+        }
+    }
+}
+
+impl RecomputeCostWeights {
+    /// Weighted cost; entries stored without a cost count as one unit
+    pub fn cost(&self, cost: &RecomputeCost) -> f64 {
+        let weighted = cost.gpu_time.as_secs_f64() * 1000.0 * self.gpu_ms
+            + cost.provider_cost * self.provider_cost;
+        if weighted > 0.0 {
+            weighted
+        } else {
+            1.0
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CacheData {
+    Ciphertext(Ciphertext),
+    ProcessedData(Vec<u8>),
+    ValidationResult(bool),
+    Metadata(HashMap<String, String>),
+}
+
+/// Configuration structures
+#[derive(Debug, Clone)]
+pub struct CacheConfiguration {
+    pub l1_max_entries: usize,
+    pub l2_max_entries: usize,
+    pub l3_max_entries: usize,
+    pub default_ttl: Duration,
+    /// Confidence a key needs to be preloaded
+    pub preload_threshold: f64,
+    /// Bytes one preload run may load
+    pub preload_budget_bytes: usize,
+    /// How far ahead of the predicted hour preloading runs
+    pub preload_lead: Duration,
+    pub eviction_strategy: EvictionStrategy,
+    pub cost_weights: RecomputeCostWeights,
+    /// Strategies simulated on the same traffic so their hit value can be
+    /// compared with the active one
+    pub compare_strategies: Vec<EvictionStrategy>,
+}
+
+#[derive(Debug, Clone)]
+pub enum EvictionStrategy {
+    LRU,
+    LFU,
+    TLRU, // Time-aware LRU
+    Adaptive,
+    PredictionBased,
+    /// Greedy-Dual-Size-Frequency: keeps entries whose recomputation cost per
+    /// byte, scaled by how often they are used, is highest
+    GreedyDualSizeFrequency,
+}
+
+impl EvictionStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvictionStrategy::LRU => "lru",
+            EvictionStrategy::LFU => "lfu",
+            EvictionStrategy::TLRU => "tlru",
+            EvictionStrategy::Adaptive => "adaptive",
+            EvictionStrategy::PredictionBased => "prediction_based",
+            EvictionStrategy::GreedyDualSizeFrequency => "gdsf",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineConfiguration {
+    pub max_concurrent_requests: usize,
+    pub stage_buffer_sizes: HashMap<StageOperation, usize>,
+    pub worker_pool_size: usize,
+    pub backpressure_threshold: f64,
+}
+
+/// Statistics and monitoring structures
+#[derive(Debug, Default)]
+pub struct CacheStatistics {
+    pub l1_hits: Arc<AtomicU64>,
+    pub l1_misses: Arc<AtomicU64>,
+    pub l2_hits: Arc<AtomicU64>,
+    pub l2_misses: Arc<AtomicU64>,
+    pub l3_hits: Arc<AtomicU64>,
+    pub l3_misses: Arc<AtomicU64>,
+    pub evictions: Arc<AtomicU64>,
+    pub preloads: Arc<AtomicU64>,
+    pub prediction_accuracy: Arc<RwLock<f64>>,
+}
+
+#[derive(Debug)]
+pub struct QueueStatistics {
+    pub total_queued: Arc<AtomicU64>,
+    pub total_processed: Arc<AtomicU64>,
+    pub average_wait_time: Arc<RwLock<Duration>>,
+    pub queue_lengths: Arc<RwLock<HashMap<RequestPriority, usize>>>,
+}
+
+#[derive(Debug)]
+pub struct WorkerPoolStats {
+    pub total_tasks_completed: Arc<AtomicU64>,
+    pub average_completion_time: Arc<RwLock<Duration>>,
+    pub worker_utilization: Arc<RwLock<f64>>,
+    pub queue_length: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+pub struct ThroughputMonitor {
+    pub requests_per_second: Arc<RwLock<f64>>,
+    pub operations_per_second: Arc<RwLock<f64>>,
+    pub bytes_processed_per_second: Arc<RwLock<f64>>,
+    pub pipeline_efficiency: Arc<RwLock<f64>>,
+}
+
+#[derive(Debug)]
+pub struct PerformanceMetrics {
+    pub total_requests: Arc<AtomicU64>,
+    pub successful_requests: Arc<AtomicU64>,
+    pub failed_requests: Arc<AtomicU64>,
+    pub average_response_time: Arc<RwLock<Duration>>,
+    pub p95_response_time: Arc<RwLock<Duration>>,
+    pub p99_response_time: Arc<RwLock<Duration>>,
+    pub memory_efficiency: Arc<RwLock<f64>>,
+    pub cache_hit_ratio: Arc<RwLock<f64>>,
+    pub throughput_mbps: Arc<RwLock<f64>>,
+}
+
+impl PerformanceManager {
+    /// Create new performance manager
+    pub fn new(config: PerformanceConfig) -> Result<Self> {
+        let cache_system = Arc::new(IntelligentCacheSystem::new(config.cache_config)?);
+        let load_balancer = Arc::new(AdaptiveLoadBalancer::new(config.load_balancer_config)?);
+        let memory_optimizer = Arc::new(MemoryOptimizer::new(config.memory_config)?);
+        let pipeline = Arc::new(ProcessingPipeline::new(config.pipeline_config)?);
+        let metrics = Arc::new(PerformanceMetrics::new());
+        let history = Arc::new(StatsHistory::new(config.stats_history_samples));
+
+        Ok(Self {
+            cache_system,
+            load_balancer,
+            memory_optimizer,
+            pipeline,
+            metrics,
+            history,
+        })
+    }
+
+    /// Process request with full optimization
+    pub async fn process_optimized(&self, request: OptimizedRequest) -> Result<OptimizedResponse> {
+        let start_time = Instant::now();
+
+        // Check cache first
+        if let Some(cached_result) = self.cache_system.get(&request.cache_key).await? {
+            self.metrics.record_cache_hit();
+            return Ok(OptimizedResponse {
+                data: cached_result,
+                processing_time: start_time.elapsed(),
+                cache_hit: true,
+                optimization_applied: vec!["cache_hit".to_string()],
+            });
+        }
+
+        // Select optimal engine
+        let engine_instance = self.load_balancer.select_engine(&request).await?;
+
+        // Queue in pipeline
+        let work_item = self.pipeline.create_work_item(request.clone()).await?;
+        let result = self.pipeline.process_item(work_item).await?;
+
+        // Cache result for future use
+        self.cache_system
+            .store(&request.cache_key, result.clone())
+            .await?;
+
+        // Update metrics
+        self.metrics.record_request_completed(start_time.elapsed());
+
+        Ok(OptimizedResponse {
+            data: result,
+            processing_time: start_time.elapsed(),
+            cache_hit: false,
+            optimization_applied: vec!["load_balanced".to_string(), "pipelined".to_string()],
+        })
+    }
+
+    /// Get performance statistics
+    pub async fn get_performance_stats(&self) -> PerformanceStats {
+        PerformanceStats {
+            cache_stats: self.cache_system.get_statistics().await,
+            load_balancer_stats: self.load_balancer.get_statistics().await,
+            memory_stats: self.memory_optimizer.get_statistics().await,
+            pipeline_stats: self.pipeline.get_statistics().await,
+            overall_metrics: self.metrics.get_summary().await,
+        }
+    }
+
+    /// Statistics of the selected subsystems only; the rest are not collected
+    pub async fn snapshot(&self, subsystems: &[StatsSubsystem]) -> StatsSnapshot {
+        let selected = |subsystem| subsystems.is_empty() || subsystems.contains(&subsystem);
+        let mut snapshot = StatsSnapshot {
+            timestamp: Utc::now().timestamp(),
+            ..StatsSnapshot::default()
+        };
+        if selected(StatsSubsystem::Cache) {
+            snapshot.cache = Some(self.cache_system.get_statistics().await);
+        }
+        if selected(StatsSubsystem::LoadBalancer) {
+            snapshot.load_balancer = Some(self.load_balancer.get_statistics().await);
+        }
+        if selected(StatsSubsystem::Memory) {
+            snapshot.memory = Some(self.memory_optimizer.get_statistics().await);
+        }
+        if selected(StatsSubsystem::Pipeline) {
+            snapshot.pipeline = Some(self.pipeline.get_statistics().await);
+        }
+        if selected(StatsSubsystem::Overall) {
+            snapshot.overall = Some(self.metrics.get_summary().await);
+        }
+        snapshot
+    }
+
+    /// Take a snapshot of every subsystem into the retained history
+    pub async fn record_stats_sample(&self) -> StatsSnapshot {
+        let snapshot = self.snapshot(&[]).await;
+        self.history.record(snapshot.clone());
+        snapshot
+    }
+
+    /// Answer a stats query: retained snapshots when it names a time range,
+    /// otherwise a live snapshot of the selected subsystems
+    pub async fn query_stats(&self, query: &StatsQuery) -> StatsPage {
+        if query.is_historical() {
+            self.history.query(query)
+        } else {
+            StatsPage {
+                samples: vec![self.snapshot(&query.subsystems).await],
+                retained: self.history.len(),
+            }
+        }
+    }
+
+    /// Trigger optimization
+    pub async fn optimize(&self) -> Result<OptimizationReport> {
+        let mut optimizations = Vec::new();
+
+        // Memory optimization
+        if let Some(memory_opt) = self.memory_optimizer.optimize().await? {
+            optimizations.push(memory_opt);
+        }
+
+        // Cache optimization
+        if let Some(cache_opt) = self.cache_system.optimize().await? {
+            optimizations.push(cache_opt);
+        }
+
+        // Load balancer optimization
+        if let Some(lb_opt) = self.load_balancer.optimize().await? {
+            optimizations.push(lb_opt);
+        }
+
+        let total_improvement = self.calculate_improvement(&optimizations);
+
+        Ok(OptimizationReport {
+            optimizations,
+            total_improvement,
+            timestamp: Instant::now(),
+        })
+    }
+
+    fn calculate_improvement(&self, optimizations: &[OptimizationResult]) -> f64 {
+        optimizations
+            .iter()
+            .map(|opt| opt.improvement_percentage)
+            .sum()
+    }
+}
+
+/// Configuration for performance manager
+#[derive(Debug, Clone)]
+pub struct PerformanceConfig {
+    pub cache_config: CacheConfiguration,
+    pub load_balancer_config: LoadBalancerConfiguration,
+    pub memory_config: MemoryConfiguration,
+    pub pipeline_config: PipelineConfiguration,
+    /// Snapshots kept by `record_stats_sample` for time-range queries
+    pub stats_history_samples: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadBalancerConfiguration {
+    pub initial_strategy: LoadBalanceStrategy,
+    pub health_check_interval: Duration,
+    pub adaptation_threshold: f64,
+    pub max_engines: usize,
+    /// Refuse engines whose fingerprint differs from the pool's
+    pub uniform_fingerprints: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryConfiguration {
+    pub initial_pool_sizes: HashMap<PoolType, usize>,
+    pub gc_interval: Duration,
+    pub pressure_thresholds: PressureThresholds,
+    pub optimization_strategies: Vec<OptimizationStrategy>,
+}
+
+/// Request and response structures
+#[derive(Debug, Clone)]
+pub struct OptimizedRequest {
+    pub request_id: Uuid,
+    pub cache_key: CacheKey,
+    pub priority: RequestPriority,
+    pub operation: OperationType,
+    pub data: Vec<u8>,
+    pub timeout: Duration,
+    pub client_context: Option<ClientContext>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientContext {
+    pub client_id: Uuid,
+    pub session_id: Option<Uuid>,
+    pub preferences: HashMap<String, String>,
+    pub quota: Option<ResourceQuota>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceQuota {
+    pub max_requests_per_minute: u64,
+    pub max_memory_mb: u64,
+    pub max_cpu_percent: f64,
+    pub priority_boost: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizedResponse {
+    pub data: CacheData,
+    pub processing_time: Duration,
+    pub cache_hit: bool,
+    pub optimization_applied: Vec<String>,
+}
+
+/// Statistics structures
+#[derive(Debug)]
+pub struct PerformanceStats {
+    pub cache_stats: CacheStatsReport,
+    pub load_balancer_stats: LoadBalancerStats,
+    pub memory_stats: MemoryStats,
+    pub pipeline_stats: PipelineStats,
+    pub overall_metrics: MetricsSummary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStatsReport {
+    pub hit_ratio: f64,
+    pub miss_ratio: f64,
+    pub total_entries: usize,
+    pub memory_usage_mb: f64,
+    /// Share of resolved preloads that were used in time
+    pub prediction_accuracy: f64,
+    pub preloads: u64,
+    /// The active strategy first, then each comparison strategy
+    pub policies: Vec<PolicyMetrics>,
+}
+
+/// Hit value of one eviction strategy over the traffic the cache has seen
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PolicyMetrics {
+    pub strategy: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_bytes: u64,
+    /// Recomputation cost the hits avoided
+    pub hit_value: f64,
+    /// Recomputation cost of entries the strategy did not hold
+    pub miss_value: f64,
+    pub evictions: u64,
+    /// New entries the strategy judged worth less than everything it held
+    pub rejections: u64,
+}
+
+impl PolicyMetrics {
+    fn new(strategy: &EvictionStrategy) -> Self {
+        Self {
+            strategy: strategy.as_str().to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Share of the recomputation cost the strategy saved
+    pub fn hit_value_ratio(&self) -> f64 {
+        let total = self.hit_value + self.miss_value;
+        if total > 0.0 {
+            self.hit_value / total
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadBalancerStats {
+    pub active_engines: usize,
+    pub total_requests: u64,
+    pub average_response_time: Duration,
+    pub health_scores: Vec<(Uuid, u64)>,
+    pub strategy_effectiveness: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryStats {
+    pub total_allocated_mb: f64,
+    pub peak_usage_mb: f64,
+    pub fragmentation_ratio: f64,
+    pub gc_frequency: f64,
+    pub pool_utilization: HashMap<PoolType, f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineStats {
+    pub throughput_rps: f64,
+    pub worker_utilization: f64,
+    pub queue_lengths: HashMap<RequestPriority, usize>,
+    pub stage_bottlenecks: Vec<(StageOperation, f64)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSummary {
+    pub total_requests: u64,
+    pub success_rate: f64,
+    pub average_response_time: Duration,
+    pub p95_response_time: Duration,
+    pub p99_response_time: Duration,
+    pub throughput_mbps: f64,
+    pub efficiency_score: f64,
+}
+
+/// Subsystems whose statistics can be queried separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsSubsystem {
+    Cache,
+    LoadBalancer,
+    Memory,
+    Pipeline,
+    Overall,
+}
+
+impl StatsSubsystem {
+    pub fn parse(subsystem: &str) -> Result<Self> {
+        match subsystem {
+            "cache" => Ok(StatsSubsystem::Cache),
+            "load_balancer" => Ok(StatsSubsystem::LoadBalancer),
+            "memory" => Ok(StatsSubsystem::Memory),
+            "pipeline" => Ok(StatsSubsystem::Pipeline),
+            "overall" => Ok(StatsSubsystem::Overall),
+            other => Err(Error::Validation(format!(
+                "Unknown stats subsystem: {}",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsFormat {
+    #[default]
+    Json,
+    /// Prometheus text exposition format, version 0.0.4
+    Prometheus,
+}
+
+impl StatsFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StatsFormat::Json => "application/json",
+            StatsFormat::Prometheus => "text/plain; version=0.0.4",
+        }
+    }
+}
+
+/// Which statistics to return, from when, and how
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsQuery {
+    /// Subsystems to collect; empty selects all of them
+    pub subsystems: Vec<StatsSubsystem>,
+    /// Retained snapshots taken at or after this Unix timestamp
+    pub since: Option<i64>,
+    /// Retained snapshots taken at or before this Unix timestamp
+    pub until: Option<i64>,
+    /// Most snapshots returned, the newest ones kept
+    pub limit: Option<usize>,
+    pub format: StatsFormat,
+}
+
+impl StatsQuery {
+    /// Parse a URL query such as
+    /// `subsystems=cache,memory&since=1700000000&format=prometheus`
+    pub fn parse(query: &str) -> Result<Self> {
+        let mut parsed = StatsQuery::default();
+        let number = |name: &str, value: &str| {
+            value
+                .parse::<i64>()
+                .map_err(|_| Error::Validation(format!("Invalid {}: {}", name, value)))
+        };
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match name {
+                "subsystems" => {
+                    for subsystem in value.split(',').filter(|s| !s.is_empty()) {
+                        let subsystem = StatsSubsystem::parse(subsystem)?;
+                        if !parsed.subsystems.contains(&subsystem) {
+                            parsed.subsystems.push(subsystem);
+                        }
+                    }
+                }
+                "since" => parsed.since = Some(number(name, value)?),
+                "until" => parsed.until = Some(number(name, value)?),
+                "limit" => {
+                    parsed.limit = Some(
+                        value
+                            .parse()
+                            .map_err(|_| Error::Validation(format!("Invalid limit: {}", value)))?,
+                    )
+                }
+                "format" => {
+                    parsed.format = match value {
+                        "json" => StatsFormat::Json,
+                        "prometheus" => StatsFormat::Prometheus,
+                        other => {
+                            return Err(Error::Validation(format!(
+                                "Unknown stats format: {}",
+                                other
+                            )))
+                        }
+                    }
+                }
+                other => {
+                    return Err(Error::Validation(format!(
+                        "Unknown stats query parameter: {}",
+                        other
+                    )))
+                }
+            }
+        }
+        if let (Some(since), Some(until)) = (parsed.since, parsed.until) {
+            if since > until {
+                return Err(Error::Validation(
+                    "since must not be after until".to_string(),
+                ));
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Whether the query is answered from retained history
+    pub fn is_historical(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    fn selects(&self, subsystem: StatsSubsystem) -> bool {
+        self.subsystems.is_empty() || self.subsystems.contains(&subsystem)
+    }
+}
+
+/// Statistics at one point in time; subsystems not selected are left out
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatsSnapshot {
+    pub timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheStatsReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_balancer: Option<LoadBalancerStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<PipelineStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overall: Option<MetricsSummary>,
+}
+
+impl StatsSnapshot {
+    /// The snapshot with only the subsystems `query` selects
+    fn project(&self, query: &StatsQuery) -> Self {
+        Self {
+            timestamp: self.timestamp,
+            cache: self
+                .cache
+                .clone()
+                .filter(|_| query.selects(StatsSubsystem::Cache)),
+            load_balancer: self
+                .load_balancer
+                .clone()
+                .filter(|_| query.selects(StatsSubsystem::LoadBalancer)),
+            memory: self
+                .memory
+                .clone()
+                .filter(|_| query.selects(StatsSubsystem::Memory)),
+            pipeline: self
+                .pipeline
+                .clone()
+                .filter(|_| query.selects(StatsSubsystem::Pipeline)),
+            overall: self
+                .overall
+                .clone()
+                .filter(|_| query.selects(StatsSubsystem::Overall)),
+        }
+    }
+}
+
+/// Result of a stats query, oldest snapshot first
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsPage {
+    pub samples: Vec<StatsSnapshot>,
+    /// Snapshots held in history, whether or not they matched
+    pub retained: usize,
+}
+
+impl StatsPage {
+    pub fn render(&self, format: StatsFormat) -> Result<String> {
+        match format {
+            StatsFormat::Json => Ok(serde_json::to_string(self)?),
+            StatsFormat::Prometheus => Ok(self.render_prometheus()),
+        }
+    }
+
+    /// Prometheus accepts one sample per series, so only the newest snapshot
+    /// is exposed, stamped with the time it was taken
+    fn render_prometheus(&self) -> String {
+        let mut exposition = PrometheusExposition::default();
+        let Some(snapshot) = self.samples.last() else {
+            return String::new();
+        };
+        let mb = 1024.0 * 1024.0;
+        if let Some(cache) = &snapshot.cache {
+            exposition.gauge("fhe_cache_hit_ratio", &[], cache.hit_ratio);
+            exposition.gauge("fhe_cache_entries", &[], cache.total_entries as f64);
+            exposition.gauge("fhe_cache_memory_bytes", &[], cache.memory_usage_mb * mb);
+            exposition.gauge(
+                "fhe_cache_prediction_accuracy",
+                &[],
+                cache.prediction_accuracy,
+            );
+            exposition.counter("fhe_cache_preloads_total", &[], cache.preloads as f64);
+            for policy in &cache.policies {
+                let labels = [("strategy", policy.strategy.as_str())];
+                exposition.counter("fhe_cache_policy_hits_total", &labels, policy.hits as f64);
+                exposition.counter(
+                    "fhe_cache_policy_misses_total",
+                    &labels,
+                    policy.misses as f64,
+                );
+                exposition.counter(
+                    "fhe_cache_policy_evictions_total",
+                    &labels,
+                    policy.evictions as f64,
+                );
+            }
+        }
+        if let Some(balancer) = &snapshot.load_balancer {
+            exposition.gauge(
+                "fhe_load_balancer_active_engines",
+                &[],
+                balancer.active_engines as f64,
+            );
+            exposition.counter(
+                "fhe_load_balancer_requests_total",
+                &[],
+                balancer.total_requests as f64,
+            );
+            exposition.gauge(
+                "fhe_load_balancer_response_seconds",
+                &[],
+                balancer.average_response_time.as_secs_f64(),
+            );
+            exposition.gauge(
+                "fhe_load_balancer_strategy_effectiveness",
+                &[],
+                balancer.strategy_effectiveness,
+            );
+            for (engine, score) in &balancer.health_scores {
+                let engine = engine.to_string();
+                exposition.gauge(
+                    "fhe_load_balancer_engine_health",
+                    &[("engine", engine.as_str())],
+                    *score as f64,
+                );
+            }
+        }
+        if let Some(memory) = &snapshot.memory {
+            exposition.gauge(
+                "fhe_memory_allocated_bytes",
+                &[],
+                memory.total_allocated_mb * mb,
+            );
+            exposition.gauge("fhe_memory_peak_bytes", &[], memory.peak_usage_mb * mb);
+            exposition.gauge(
+                "fhe_memory_fragmentation_ratio",
+                &[],
+                memory.fragmentation_ratio,
+            );
+            exposition.gauge("fhe_memory_gc_per_minute", &[], memory.gc_frequency);
+            let mut pools: Vec<_> = memory.pool_utilization.iter().collect();
+            pools.sort_by_key(|(pool, _)| pool.as_str());
+            for (pool, utilization) in pools {
+                exposition.gauge(
+                    "fhe_memory_pool_utilization",
+                    &[("pool", pool.as_str())],
+                    *utilization,
+                );
+            }
+        }
+        if let Some(pipeline) = &snapshot.pipeline {
+            exposition.gauge("fhe_pipeline_throughput_rps", &[], pipeline.throughput_rps);
+            exposition.gauge(
+                "fhe_pipeline_worker_utilization",
+                &[],
+                pipeline.worker_utilization,
+            );
+            let mut queues: Vec<_> = pipeline.queue_lengths.iter().collect();
+            queues.sort();
+            for (priority, length) in queues {
+                exposition.gauge(
+                    "fhe_pipeline_queue_length",
+                    &[("priority", priority.as_str())],
+                    *length as f64,
+                );
+            }
+            for (stage, share) in &pipeline.stage_bottlenecks {
+                exposition.gauge(
+                    "fhe_pipeline_stage_bottleneck",
+                    &[("stage", stage.as_str())],
+                    *share,
+                );
+            }
+        }
+        if let Some(overall) = &snapshot.overall {
+            exposition.counter("fhe_requests_total", &[], overall.total_requests as f64);
+            exposition.gauge("fhe_request_success_rate", &[], overall.success_rate);
+            for (quantile, duration) in [
+                ("mean", overall.average_response_time),
+                ("0.95", overall.p95_response_time),
+                ("0.99", overall.p99_response_time),
+            ] {
+                exposition.gauge(
+                    "fhe_response_seconds",
+                    &[("quantile", quantile)],
+                    duration.as_secs_f64(),
+                );
+            }
+            exposition.gauge("fhe_throughput_mbps", &[], overall.throughput_mbps);
+            exposition.gauge("fhe_efficiency_score", &[], overall.efficiency_score);
+        }
+        exposition.finish(snapshot.timestamp * 1000)
+    }
+}
+
+/// Samples grouped by metric, each metric introduced by its `# TYPE` line
+#[derive(Debug, Default)]
+struct PrometheusExposition {
+    metrics: Vec<PrometheusMetric>,
+}
+
+#[derive(Debug)]
+struct PrometheusMetric {
+    name: &'static str,
+    kind: &'static str,
+    /// Rendered label set and value of each sample
+    samples: Vec<(String, f64)>,
+}
+
+impl PrometheusExposition {
+    fn gauge(&mut self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.sample(name, "gauge", labels, value);
+    }
+
+    fn counter(&mut self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.sample(name, "counter", labels, value);
+    }
+
+    fn sample(
+        &mut self,
+        name: &'static str,
+        kind: &'static str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) {
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            let pairs: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| {
+                    let escaped = value
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"")
+                        .replace('\n', "\\n");
+                    format!("{}=\"{}\"", key, escaped)
+                })
+                .collect();
+            format!("{{{}}}", pairs.join(","))
+        };
+        match self.metrics.iter_mut().find(|metric| metric.name == name) {
+            Some(metric) => metric.samples.push((labels, value)),
+            None => self.metrics.push(PrometheusMetric {
+                name,
+                kind,
+                samples: vec![(labels, value)],
+            }),
+        }
+    }
+
+    fn finish(self, timestamp_ms: i64) -> String {
+        let mut text = String::new();
+        for metric in self.metrics {
+            text.push_str(&format!("# TYPE {} {}\n", metric.name, metric.kind));
+            for (labels, value) in metric.samples {
+                text.push_str(&format!(
+                    "{}{} {} {}\n",
+                    metric.name, labels, value, timestamp_ms
+                ));
+            }
+        }
+        text
+    }
+}
+
+/// Snapshots kept for time-range queries, oldest first
+#[derive(Debug)]
+pub struct StatsHistory {
+    capacity: usize,
+    samples: RwLock<VecDeque<StatsSnapshot>>,
+}
+
+impl StatsHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, snapshot: StatsSnapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut samples = self.samples.write().unwrap();
+        while samples.len() >= self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(snapshot);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Retained snapshots within the query's range, with only the selected
+    /// subsystems
+    pub fn query(&self, query: &StatsQuery) -> StatsPage {
+        let samples = self.samples.read().unwrap();
+        let mut matched: Vec<StatsSnapshot> = samples
+            .iter()
+            .filter(|s| query.since.is_none_or(|since| s.timestamp >= since))
+            .filter(|s| query.until.is_none_or(|until| s.timestamp <= until))
+            .map(|s| s.project(query))
+            .collect();
+        if let Some(limit) = query.limit {
+            matched.drain(..matched.len().saturating_sub(limit));
+        }
+        StatsPage {
+            samples: matched,
+            retained: samples.len(),
+        }
+    }
+}
+
+/// Optimization results
+#[derive(Debug)]
+pub struct OptimizationResult {
+    pub optimization_type: String,
+    pub improvement_percentage: f64,
+    pub resource_savings: ResourceSavings,
+    pub timestamp: Instant,
+}
+
+#[derive(Debug)]
+pub struct ResourceSavings {
+    pub memory_saved_mb: f64,
+    pub cpu_saved_percent: f64,
+    pub response_time_improvement_ms: f64,
+    pub throughput_improvement_percent: f64,
+}
+
+#[derive(Debug)]
+pub struct OptimizationReport {
+    pub optimizations: Vec<OptimizationResult>,
+    pub total_improvement: f64,
+    pub timestamp: Instant,
+}
+
+// Implementation stubs for the main structures would go here
+// Due to length constraints, I'm providing the framework
+
+/// Entries one tier holds under one eviction strategy
+#[derive(Debug)]
+struct CacheTier {
+    strategy: EvictionStrategy,
+    max_entries: usize,
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// GDSF inflation: priority of the last entry evicted, so entries that
+    /// have sat unused long enough fall below newer ones
+    inflation: f64,
+}
+
+impl CacheTier {
+    fn new(strategy: EvictionStrategy, max_entries: usize) -> Self {
+        Self {
+            strategy,
+            max_entries,
+            entries: HashMap::new(),
+            inflation: 0.0,
+        }
+    }
+
+    /// Record a use of `key`; an expired entry is dropped instead
+    fn touch(&mut self, key: &CacheKey, now: Instant) -> Option<&CacheEntry> {
+        if self.entries.get(key)?.is_expired(now) {
+            self.entries.remove(key);
+            return None;
+        }
+        let entry = self.entries.get_mut(key)?;
+        entry.last_accessed = now;
+        entry.access_count += 1;
+        entry.priority_score = priority(&self.strategy, self.inflation, entry);
+        Some(entry)
+    }
+
+    /// Insert `entry`, returning the entries it displaced, or the entry
+    /// itself when the strategy values it below everything resident
+    fn admit(&mut self, mut entry: CacheEntry, now: Instant) -> Vec<CacheEntry> {
+        if self.max_entries == 0 {
+            return vec![entry];
+        }
+        self.entries.remove(&entry.key);
+        self.entries.retain(|_, e| !e.is_expired(now));
+        entry.priority_score = priority(&self.strategy, self.inflation, &entry);
+
+        let mut displaced = Vec::new();
+        while self.entries.len() >= self.max_entries {
+            let Some(victim) = self.victim() else { break };
+            if matches!(self.strategy, EvictionStrategy::GreedyDualSizeFrequency)
+                && self.entries[&victim].priority_score > entry.priority_score
+            {
+                displaced.push(entry);
+                return displaced;
+            }
+            if let Some(evicted) = self.entries.remove(&victim) {
+                self.inflation = self.inflation.max(evicted.priority_score);
+                displaced.push(evicted);
+            }
+        }
+        self.entries.insert(entry.key.clone(), entry);
+        displaced
+    }
+
+    fn victim(&self) -> Option<CacheKey> {
+        let entries = self.entries.values();
+        let victim = match self.strategy {
+            EvictionStrategy::LFU => entries.min_by_key(|e| (e.access_count, e.last_accessed)),
+            // Time-aware: whatever expires soonest goes first
+            EvictionStrategy::TLRU => entries.min_by_key(|e| e.created_at + e.ttl),
+            EvictionStrategy::GreedyDualSizeFrequency => {
+                entries.min_by(|a, b| a.priority_score.total_cmp(&b.priority_score))
+            }
+            EvictionStrategy::LRU
+            | EvictionStrategy::Adaptive
+            | EvictionStrategy::PredictionBased => entries.min_by_key(|e| e.last_accessed),
+        };
+        victim.map(|e| e.key.clone())
+    }
+}
+
+/// Eviction priority; only GDSF ranks entries by a stored score
+fn priority(strategy: &EvictionStrategy, inflation: f64, entry: &CacheEntry) -> f64 {
+    match strategy {
+        EvictionStrategy::GreedyDualSizeFrequency => {
+            inflation
+                + entry.access_count as f64 * entry.recompute_cost / entry.size_bytes.max(1) as f64
+        }
+        _ => 0.0,
+    }
+}
+
+impl CacheEntry {
+    fn is_expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.created_at) >= self.ttl
+    }
+
+    /// The entry as comparison strategies keep it, without its data
+    fn metadata(&self) -> CacheEntry {
+        CacheEntry {
+            data: CacheData::ProcessedData(Vec::new()),
+            ..self.clone()
+        }
+    }
+}
+
+impl CacheData {
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            CacheData::Ciphertext(ciphertext) => ciphertext.data.len(),
+            CacheData::ProcessedData(data) => data.len(),
+            CacheData::ValidationResult(_) => 1,
+            CacheData::Metadata(map) => map.iter().map(|(k, v)| k.len() + v.len()).sum(),
+        }
+    }
+}
+
+/// A comparison strategy: one tier of the cache's total size holding entry
+/// metadata only
+#[derive(Debug)]
+struct ShadowCache {
+    tier: CacheTier,
+    metrics: PolicyMetrics,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    tiers: Vec<CacheTier>,
+    metrics: PolicyMetrics,
+    shadows: Vec<ShadowCache>,
+}
+
+impl CacheState {
+    /// Place `entry` in L1; whatever a tier evicts or refuses moves down, and
+    /// what L3 lets go leaves the cache
+    fn place(&mut self, entry: CacheEntry, now: Instant, stats: &CacheStatistics) {
+        self.place_from(0, entry, now, stats);
+    }
+
+    /// Place `entry` in the tier at `level`, cascading down from there
+    fn place_from(
+        &mut self,
+        level: usize,
+        entry: CacheEntry,
+        now: Instant,
+        stats: &CacheStatistics,
+    ) {
+        let key = entry.key.clone();
+        let mut moving = vec![entry];
+        for tier in self.tiers.iter_mut().skip(level) {
+            moving = moving
+                .into_iter()
+                .flat_map(|e| tier.admit(e, now))
+                .collect();
+        }
+        for dropped in moving {
+            if dropped.key == key {
+                self.metrics.rejections += 1;
+            } else {
+                self.metrics.evictions += 1;
+                stats.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl ShadowCache {
+    fn lookup(&mut self, key: &CacheKey, now: Instant) -> bool {
+        match self.tier.touch(key, now) {
+            Some(entry) => {
+                self.metrics.hits += 1;
+                self.metrics.hit_bytes += entry.size_bytes as u64;
+                self.metrics.hit_value += entry.recompute_cost;
+                true
+            }
+            None => {
+                self.metrics.misses += 1;
+                false
+            }
+        }
+    }
+
+    /// Store `entry` as this strategy would have after missing it
+    fn fill(&mut self, entry: &CacheEntry, now: Instant) {
+        self.metrics.miss_value += entry.recompute_cost;
+        let key = entry.key.clone();
+        for dropped in self.tier.admit(entry.metadata(), now) {
+            if dropped.key == key {
+                self.metrics.rejections += 1;
+            } else {
+                self.metrics.evictions += 1;
+            }
+        }
+    }
+}
+
+impl IntelligentCacheSystem {
+    pub fn new(config: CacheConfiguration) -> Result<Self> {
+        let sizes = [
+            config.l1_max_entries,
+            config.l2_max_entries,
+            config.l3_max_entries,
+        ];
+        if sizes.iter().all(|&size| size == 0) {
+            return Err(Error::Validation(
+                "Cache needs room for at least one entry".to_string(),
+            ));
+        }
+        let total: usize = sizes.iter().sum();
+        let state = CacheState {
+            tiers: sizes
+                .iter()
+                .map(|&size| CacheTier::new(config.eviction_strategy.clone(), size))
+                .collect(),
+            metrics: PolicyMetrics::new(&config.eviction_strategy),
+            shadows: config
+                .compare_strategies
+                .iter()
+                .map(|strategy| ShadowCache {
+                    tier: CacheTier::new(strategy.clone(), total),
+                    metrics: PolicyMetrics::new(strategy),
+                })
+                .collect(),
+        };
+
+        Ok(Self {
+            state: Arc::new(RwLock::new(state)),
+            predictor: Arc::new(CachePredictionEngine::new(config.preload_threshold)),
+            stats: Arc::new(CacheStatistics::default()),
+            config,
+        })
+    }
+
+    /// Look `key` up in L1, L2 and L3 in turn; a hit below L1 is promoted
+    pub async fn get(&self, key: &CacheKey) -> Result<Option<CacheData>> {
+        let now = Instant::now();
+        let mut state = self.state.write().unwrap();
+        let counters = [
+            (&self.stats.l1_hits, &self.stats.l1_misses),
+            (&self.stats.l2_hits, &self.stats.l2_misses),
+            (&self.stats.l3_hits, &self.stats.l3_misses),
+        ];
+
+        let mut found = None;
+        for (level, tier) in state.tiers.iter_mut().enumerate() {
+            let (hits, misses) = counters[level];
+            match tier.touch(key, now) {
+                Some(entry) => {
+                    hits.fetch_add(1, Ordering::Relaxed);
+                    found = Some((level, entry.clone()));
+                    break;
+                }
+                None => {
+                    misses.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        // Comparison strategies that miss an entry the cache held pay for it
+        // as if they had recomputed it
+        for shadow in &mut state.shadows {
+            if !shadow.lookup(key, now) {
+                if let Some((_, entry)) = &found {
+                    shadow.fill(entry, now);
+                }
+            }
+        }
+
+        if self.predictor.record_access(key, Utc::now()) {
+            self.update_prediction_accuracy();
+        }
+        let Some((level, entry)) = found else {
+            state.metrics.misses += 1;
+            return Ok(None);
+        };
+        state.metrics.hits += 1;
+        state.metrics.hit_bytes += entry.size_bytes as u64;
+        state.metrics.hit_value += entry.recompute_cost;
+        let data = entry.data.clone();
+        if level > 0 {
+            if let Some(entry) = state.tiers[level].entries.remove(key) {
+                state.place(entry, now, &self.stats);
+            }
+        }
+        Ok(Some(data))
+    }
+
+    pub async fn store(&self, key: &CacheKey, data: CacheData) -> Result<()> {
+        self.store_with_cost(key, data, RecomputeCost::default())
+            .await
+    }
+
+    /// Store `data`, weighing what it would cost to produce again
+    pub async fn store_with_cost(
+        &self,
+        key: &CacheKey,
+        data: CacheData,
+        cost: RecomputeCost,
+    ) -> Result<()> {
+        let now = Instant::now();
+        let entry = CacheEntry {
+            key: key.clone(),
+            size_bytes: data.size_bytes(),
+            data,
+            created_at: now,
+            last_accessed: now,
+            access_count: 1,
+            ttl: self.config.default_ttl,
+            priority_score: 0.0,
+            recompute_cost: self.config.cost_weights.cost(&cost),
+        };
+
+        self.predictor.record_size(key, entry.size_bytes);
+        let mut state = self.state.write().unwrap();
+        for shadow in &mut state.shadows {
+            if !shadow.tier.entries.contains_key(key) {
+                shadow.fill(&entry, now);
+            }
+        }
+        let mut held = false;
+        for tier in &mut state.tiers {
+            held |= tier.entries.remove(key).is_some();
+        }
+        if !held {
+            state.metrics.miss_value += entry.recompute_cost;
+        }
+        state.place(entry, now, &self.stats);
+        Ok(())
+    }
+
+    /// Load the keys predicted for the hour `preload_lead` from now that are
+    /// not cached, within the preload budget; returns how many were loaded
+    pub async fn run_predictive_preload<F>(&self, loader: F) -> Result<usize>
+    where
+        F: Fn(&CacheKey) -> Option<CacheData>,
+    {
+        self.run_predictive_preload_at(Utc::now(), loader)
+    }
+
+    fn run_predictive_preload_at<F>(&self, at: DateTime<Utc>, loader: F) -> Result<usize>
+    where
+        F: Fn(&CacheKey) -> Option<CacheData>,
+    {
+        let lead = chrono::Duration::from_std(self.config.preload_lead)
+            .map_err(|e| Error::Validation(format!("Invalid preload lead: {}", e)))?;
+        // A preload is wasted once the hour it was predicted for has passed
+        self.predictor
+            .expire_preloads(at - lead - chrono::Duration::hours(1));
+        self.update_prediction_accuracy();
+
+        let now = Instant::now();
+        let mut budget = self.config.preload_budget_bytes;
+        let mut preloaded = 0;
+        for prediction in self.predictor.predict(at + lead) {
+            if prediction.size_bytes > budget || self.is_cached(&prediction.key, now) {
+                continue;
+            }
+            let Some(data) = loader(&prediction.key) else {
+                continue;
+            };
+            let size_bytes = data.size_bytes();
+            if size_bytes > budget {
+                continue;
+            }
+            budget -= size_bytes;
+            let entry = CacheEntry {
+                key: prediction.key.clone(),
+                data,
+                created_at: now,
+                last_accessed: now,
+                access_count: 0,
+                size_bytes,
+                ttl: self.config.default_ttl,
+                priority_score: 0.0,
+                recompute_cost: self.config.cost_weights.cost(&RecomputeCost::default()),
+            };
+            // Near-certain keys go to L1, the rest to L2
+            let level = usize::from(prediction.confidence < PRELOAD_L1_CONFIDENCE);
+            self.state
+                .write()
+                .unwrap()
+                .place_from(level, entry, now, &self.stats);
+            self.predictor.track_preload(&prediction.key, at);
+            self.stats.preloads.fetch_add(1, Ordering::Relaxed);
+            preloaded += 1;
+        }
+        Ok(preloaded)
+    }
+
+    fn is_cached(&self, key: &CacheKey, now: Instant) -> bool {
+        self.state
+            .read()
+            .unwrap()
+            .tiers
+            .iter()
+            .any(|tier| tier.entries.get(key).is_some_and(|e| !e.is_expired(now)))
+    }
+
+    fn update_prediction_accuracy(&self) {
+        if let Some(accuracy) = self.predictor.accuracy() {
+            *self.stats.prediction_accuracy.write().unwrap() = accuracy;
+        }
+    }
+
+    pub async fn optimize(&self) -> Result<Option<OptimizationResult>> {
+        // Implementation would run cache optimization
+        todo!("Cache optimization implementation")
+    }
+
+    pub async fn get_statistics(&self) -> CacheStatsReport {
+        let state = self.state.read().unwrap();
+        let requests = state.metrics.hits + state.metrics.misses;
+        let hit_ratio = if requests > 0 {
+            state.metrics.hits as f64 / requests as f64
+        } else {
+            0.0
+        };
+        let entries = state.tiers.iter().flat_map(|t| t.entries.values());
+        let memory_bytes: usize = entries.clone().map(|e| e.size_bytes).sum();
+
+        CacheStatsReport {
+            hit_ratio,
+            miss_ratio: if requests > 0 { 1.0 - hit_ratio } else { 0.0 },
+            total_entries: entries.count(),
+            memory_usage_mb: memory_bytes as f64 / (1024.0 * 1024.0),
+            prediction_accuracy: *self.stats.prediction_accuracy.read().unwrap(),
+            preloads: self.stats.preloads.load(Ordering::Relaxed),
+            policies: std::iter::once(state.metrics.clone())
+                .chain(state.shadows.iter().map(|s| s.metrics.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Preload predictions at least this confident go to L1
+const PRELOAD_L1_CONFIDENCE: f64 = 0.95;
+
+/// Days of history a key needs before its hours are predicted
+const PRELOAD_MIN_HISTORY_DAYS: i64 = 2;
+
+fn day_number(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(86_400)
+}
+
+impl<K: Clone + Eq + Hash> CachePredictionEngine<K> {
+    /// A key is predicted for an hour once it was used in that hour on at
+    /// least `confidence_threshold` of the days observed
+    pub fn new(confidence_threshold: f64) -> Self {
+        Self {
+            access_patterns: Arc::new(RwLock::new(HashMap::new())),
+            temporal_patterns: Arc::new(RwLock::new(VecDeque::new())),
+            model_weights: Arc::new(RwLock::new(PredictionModel {
+                temporal_weights: Vec::new(),
+                frequency_weights: Vec::new(),
+                sequence_weights: Vec::new(),
+                learning_rate: 0.01,
+                confidence_threshold,
+            })),
+            outstanding_preloads: Arc::new(RwLock::new(HashMap::new())),
+            useful_preloads: Arc::new(AtomicU64::new(0)),
+            wasted_preloads: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Record a use of `key` at `at`; returns whether it was an outstanding
+    /// preload, which is now resolved as useful
+    pub fn record_access(&self, key: &K, at: DateTime<Utc>) -> bool {
+        let (day, hour) = (day_number(at), at.hour() as usize);
+        let mut patterns = self.access_patterns.write().unwrap();
+        let pattern = patterns
+            .entry(key.clone())
+            .or_insert_with(|| AccessPattern {
+                key: key.clone(),
+                frequency: 0,
+                size_bytes: 0,
+                first_day: day,
+                temporal_distribution: vec![0; 24],
+                last_day_by_hour: vec![i64::MIN; 24],
+            });
+        pattern.frequency += 1;
+        if pattern.last_day_by_hour[hour] != day {
+            pattern.last_day_by_hour[hour] = day;
+            pattern.temporal_distribution[hour] += 1;
+        }
+        drop(patterns);
+
+        let used = self
+            .outstanding_preloads
+            .write()
+            .unwrap()
+            .remove(key)
+            .is_some();
+        if used {
+            self.useful_preloads.fetch_add(1, Ordering::Relaxed);
+        }
+        used
+    }
+
+    /// Remember the size of the value stored under a key with a pattern
+    pub fn record_size(&self, key: &K, size_bytes: usize) {
+        if let Some(pattern) = self.access_patterns.write().unwrap().get_mut(key) {
+            pattern.size_bytes = size_bytes;
+        }
+    }
+
+    /// Keys expected in the hour containing `at`, most confident first
+    pub fn predict(&self, at: DateTime<Utc>) -> Vec<PreloadPrediction<K>> {
+        let (day, hour) = (day_number(at), at.hour() as usize);
+        let threshold = self.model_weights.read().unwrap().confidence_threshold;
+        let mut predictions: Vec<(PreloadPrediction<K>, u64)> = self
+            .access_patterns
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|pattern| {
+                // Today only counts once the key has been used in this hour
+                let days = if pattern.last_day_by_hour[hour] == day {
+                    day - pattern.first_day + 1
+                } else {
+                    day - pattern.first_day
+                };
+                if days < PRELOAD_MIN_HISTORY_DAYS {
+                    return None;
+                }
+                let confidence = pattern.temporal_distribution[hour] as f64 / days as f64;
+                (confidence >= threshold).then(|| {
+                    (
+                        PreloadPrediction {
+                            key: pattern.key.clone(),
+                            confidence,
+                            size_bytes: pattern.size_bytes,
+                        },
+                        pattern.frequency,
+                    )
+                })
+            })
+            .collect();
+        predictions.sort_by(|(a, a_frequency), (b, b_frequency)| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then(b_frequency.cmp(a_frequency))
+        });
+        predictions.into_iter().map(|(p, _)| p).collect()
+    }
+
+    /// Remember that `key` was preloaded at `at`, to learn whether it is used
+    pub fn track_preload(&self, key: &K, at: DateTime<Utc>) {
+        self.outstanding_preloads
+            .write()
+            .unwrap()
+            .insert(key.clone(), at);
+    }
+
+    /// Resolve preloads made before `before` that were never used as wasted
+    pub fn expire_preloads(&self, before: DateTime<Utc>) {
+        let mut outstanding = self.outstanding_preloads.write().unwrap();
+        let count = outstanding.len();
+        outstanding.retain(|_, preloaded_at| *preloaded_at >= before);
+        self.wasted_preloads
+            .fetch_add((count - outstanding.len()) as u64, Ordering::Relaxed);
+    }
+
+    /// Share of resolved preloads that were used, or None before any resolved
+    pub fn accuracy(&self) -> Option<f64> {
+        let useful = self.useful_preloads.load(Ordering::Relaxed);
+        let resolved = useful + self.wasted_preloads.load(Ordering::Relaxed);
+        (resolved > 0).then(|| useful as f64 / resolved as f64)
+    }
+
+    /// Preloads used in time and preloads that went unused
+    pub fn preload_outcomes(&self) -> (u64, u64) {
+        (
+            self.useful_preloads.load(Ordering::Relaxed),
+            self.wasted_preloads.load(Ordering::Relaxed),
+        )
+    }
+}
+
+impl AdaptiveLoadBalancer {
+    pub fn new(config: LoadBalancerConfiguration) -> Result<Self> {
+        // Implementation would create load balancer
+        todo!("Load balancer creation")
+    }
+
+    pub async fn select_engine(&self, request: &OptimizedRequest) -> Result<Arc<EngineInstance>> {
+        // Implementation would select optimal engine
+        todo!("Engine selection implementation")
+    }
+
+    pub async fn optimize(&self) -> Result<Option<OptimizationResult>> {
+        // Implementation would optimize load balancing strategy
+        todo!("Load balancer optimization")
+    }
+
+    pub async fn get_statistics(&self) -> LoadBalancerStats {
+        // Implementation would collect load balancer stats
+        todo!("Load balancer statistics")
+    }
+}
+
+impl MemoryTracker {
+    pub fn new() -> Self {
+        Self {
+            total_allocated: Arc::new(AtomicUsize::new(0)),
+            peak_usage: Arc::new(AtomicUsize::new(0)),
+            allocation_history: Arc::new(RwLock::new(VecDeque::new())),
+            fragmentation: Arc::new(RwLock::new(FragmentationMetrics::default())),
+        }
+    }
+
+    /// Re-sample the allocator so fragmentation and usage reflect the live heap
+    pub fn refresh(&self) -> FragmentationMetrics {
+        let heap = crate::allocator::allocator_stats();
+        let rss = heap.rss_bytes.unwrap_or(0) as usize;
+        let allocated = heap.allocated_bytes.map(|b| b as usize);
+        let active = heap.active_bytes.map(|b| b as usize).unwrap_or(rss);
+
+        let metrics = FragmentationMetrics {
+            total_free_space: allocated.map_or(0, |allocated| active.saturating_sub(allocated)),
+            fragmentation_ratio: heap.fragmentation_ratio.unwrap_or(0.0),
+            allocated_bytes: allocated.unwrap_or(rss),
+            rss_bytes: rss,
+        };
+
+        self.total_allocated
+            .store(metrics.allocated_bytes, Ordering::Relaxed);
+        self.peak_usage
+            .fetch_max(metrics.allocated_bytes, Ordering::Relaxed);
+        *self.fragmentation.write().unwrap() = metrics.clone();
+        metrics
+    }
+
+    pub fn fragmentation(&self) -> FragmentationMetrics {
+        self.fragmentation.read().unwrap().clone()
+    }
+}
+
+impl Default for MemoryTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryOptimizer {
+    pub fn new(config: MemoryConfiguration) -> Result<Self> {
+        let pools = config
+            .initial_pool_sizes
+            .iter()
+            .map(|(pool_type, &size)| {
+                let pool = MemoryPool {
+                    pool_type: pool_type.clone(),
+                    allocated_bytes: Arc::new(AtomicUsize::new(0)),
+                    peak_usage: Arc::new(AtomicUsize::new(0)),
+                    available_slots: Arc::new(RwLock::new(Vec::new())),
+                    config: PoolConfiguration {
+                        initial_size: size,
+                        max_size: size.saturating_mul(4),
+                        growth_factor: 2.0,
+                        shrink_threshold: 0.25,
+                        cleanup_interval: config.gc_interval,
+                    },
+                };
+                (pool_type.clone(), pool)
+            })
+            .collect();
+
+        Ok(Self {
+            pools: Arc::new(RwLock::new(pools)),
+            gc_scheduler: Arc::new(GcScheduler {
+                last_gc: Arc::new(RwLock::new(Instant::now())),
+                gc_interval: config.gc_interval,
+                pressure_thresholds: config.pressure_thresholds,
+            }),
+            memory_tracker: Arc::new(MemoryTracker::new()),
+            strategies: Arc::new(RwLock::new(config.optimization_strategies)),
+        })
+    }
+
+    pub async fn optimize(&self) -> Result<Option<OptimizationResult>> {
+        // Implementation would run memory optimization
+        todo!("Memory optimization implementation")
+    }
+
+    pub async fn get_statistics(&self) -> MemoryStats {
+        let fragmentation = self.memory_tracker.refresh();
+        let pool_utilization = self
+            .pools
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(pool_type, pool)| {
+                let allocated = pool.allocated_bytes.load(Ordering::Relaxed) as f64;
+                (
+                    pool_type.clone(),
+                    allocated / pool.config.max_size.max(1) as f64,
+                )
+            })
+            .collect();
+
+        MemoryStats {
+            total_allocated_mb: fragmentation.allocated_bytes as f64 / (1024.0 * 1024.0),
+            peak_usage_mb: self.memory_tracker.peak_usage.load(Ordering::Relaxed) as f64
+                / (1024.0 * 1024.0),
+            fragmentation_ratio: fragmentation.fragmentation_ratio,
+            gc_frequency: 60.0 / self.gc_scheduler.gc_interval.as_secs_f64().max(1.0),
+            pool_utilization,
+        }
+    }
+}
+
+impl ProcessingPipeline {
+    pub fn new(config: PipelineConfiguration) -> Result<Self> {
+        // Implementation would create processing pipeline
+        todo!("Pipeline creation")
+    }
+
+    pub async fn create_work_item(&self, request: OptimizedRequest) -> Result<WorkItem> {
+        // Implementation would create work item
+        todo!("Work item creation")
+    }
+
+    pub async fn process_item(&self, item: WorkItem) -> Result<CacheData> {
+        // Implementation would process item through pipeline
+        todo!("Pipeline processing")
+    }
+
+    pub async fn get_statistics(&self) -> PipelineStats {
+        // Implementation would collect pipeline statistics
+        todo!("Pipeline statistics")
+    }
+}
+
+impl PerformanceMetrics {
+    pub fn new() -> Self {
+        Self {
+            total_requests: Arc::new(AtomicU64::new(0)),
+            successful_requests: Arc::new(AtomicU64::new(0)),
+            failed_requests: Arc::new(AtomicU64::new(0)),
+            average_response_time: Arc::new(RwLock::new(Duration::from_millis(0))),
+            p95_response_time: Arc::new(RwLock::new(Duration::from_millis(0))),
+            p99_response_time: Arc::new(RwLock::new(Duration::from_millis(0))),
+            memory_efficiency: Arc::new(RwLock::new(0.0)),
+            cache_hit_ratio: Arc::new(RwLock::new(0.0)),
+            throughput_mbps: Arc::new(RwLock::new(0.0)),
+        }
+    }
+
+    pub fn record_cache_hit(&self) {
+        // Implementation would record cache hit
+    }
+
+    pub fn record_request_completed(&self, duration: Duration) {
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+        self.successful_requests.fetch_add(1, Ordering::Relaxed);
+        // Update averages, percentiles, etc.
+    }
+
+    pub async fn get_summary(&self) -> MetricsSummary {
+        // Implementation would collect all metrics
+        todo!("Metrics summary implementation")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_performance_manager_creation() {
+        let config = PerformanceConfig {
+            cache_config: CacheConfiguration {
+                l1_max_entries: 1000,
+                l2_max_entries: 5000,
+                l3_max_entries: 20000,
+                default_ttl: Duration::from_secs(3600),
+                preload_threshold: 0.8,
+                preload_budget_bytes: 64 * 1024 * 1024,
+                preload_lead: Duration::from_secs(600),
+                eviction_strategy: EvictionStrategy::Adaptive,
+                cost_weights: RecomputeCostWeights::default(),
+                compare_strategies: vec![EvictionStrategy::LRU, EvictionStrategy::LFU],
+            },
+            load_balancer_config: LoadBalancerConfiguration {
+                initial_strategy: LoadBalanceStrategy::AdaptiveHybrid {
+                    weights: StrategyWeights {
+                        load_weight: 0.3,
+                        response_time_weight: 0.3,
+                        health_weight: 0.2,
+                        error_rate_weight: 0.2,
+                    },
+                },
+                health_check_interval: Duration::from_secs(30),
+                adaptation_threshold: 0.1,
+                max_engines: 10,
+                uniform_fingerprints: true,
+            },
+            memory_config: MemoryConfiguration {
+                initial_pool_sizes: HashMap::new(),
+                gc_interval: Duration::from_secs(300),
+                pressure_thresholds: PressureThresholds {
+                    memory_pressure: 0.8,
+                    allocation_rate: 1000.0,
+                    fragmentation_ratio: 0.3,
+                },
+                optimization_strategies: Vec::new(),
+            },
+            pipeline_config: PipelineConfiguration {
+                max_concurrent_requests: 100,
+                stage_buffer_sizes: HashMap::new(),
+                worker_pool_size: 10,
+                backpressure_threshold: 0.8,
+            },
+            stats_history_samples: 1440,
+        };
+
+        // This would fail with todo!() but demonstrates the structure
+        // let manager = PerformanceManager::new(config);
+        // assert!(manager.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_memory_stats_come_from_allocator() {
+        let optimizer = MemoryOptimizer::new(MemoryConfiguration {
+            initial_pool_sizes: HashMap::from([(PoolType::Ciphertext, 1 << 20)]),
+            gc_interval: Duration::from_secs(60),
+            pressure_thresholds: PressureThresholds {
+                memory_pressure: 0.8,
+                allocation_rate: 1000.0,
+                fragmentation_ratio: 0.3,
+            },
+            optimization_strategies: Vec::new(),
+        })
+        .unwrap();
+
+        let stats = optimizer.get_statistics().await;
+        assert_eq!(stats.pool_utilization[&PoolType::Ciphertext], 0.0);
+        assert!((0.0..=1.0).contains(&stats.fragmentation_ratio));
+        if cfg!(target_os = "linux") {
+            assert!(stats.total_allocated_mb > 0.0);
+            assert!(stats.peak_usage_mb >= stats.total_allocated_mb);
+        }
+    }
+
+    #[test]
+    fn test_stats_query_selects_subsystems_and_history_range() {
+        let query = StatsQuery::parse(
+            "subsystems=memory,pipeline,memory&since=100&limit=2&format=prometheus",
+        )
+        .unwrap();
+        assert_eq!(
+            query.subsystems,
+            vec![StatsSubsystem::Memory, StatsSubsystem::Pipeline]
+        );
+        assert_eq!(query.format, StatsFormat::Prometheus);
+        assert!(query.is_historical());
+        assert!(!StatsQuery::parse("").unwrap().is_historical());
+        assert!(StatsQuery::parse("subsystems=gpu").is_err());
+        assert!(StatsQuery::parse("since=200&until=100").is_err());
+        assert!(StatsQuery::parse("format=xml").is_err());
+
+        let history = StatsHistory::new(3);
+        for timestamp in [50, 100, 150, 200] {
+            history.record(StatsSnapshot {
+                timestamp,
+                memory: Some(MemoryStats {
+                    total_allocated_mb: 1.0,
+                    peak_usage_mb: 2.0,
+                    fragmentation_ratio: 0.1,
+                    gc_frequency: 0.2,
+                    pool_utilization: HashMap::from([(PoolType::Ciphertext, 0.5)]),
+                }),
+                pipeline: Some(PipelineStats {
+                    throughput_rps: 12.5,
+                    worker_utilization: 0.75,
+                    queue_lengths: HashMap::from([(RequestPriority::High, 3)]),
+                    stage_bottlenecks: vec![(StageOperation::Processing, 0.9)],
+                }),
+                ..StatsSnapshot::default()
+            });
+        }
+        assert_eq!(history.len(), 3);
+
+        // The oldest retained snapshot is dropped by the limit
+        let page = history.query(&StatsQuery {
+            subsystems: vec![StatsSubsystem::Pipeline],
+            ..query.clone()
+        });
+        assert_eq!(page.retained, 3);
+        let timestamps: Vec<i64> = page.samples.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, vec![150, 200]);
+        assert!(page.samples.iter().all(|s| s.memory.is_none()));
+        let json: serde_json::Value =
+            serde_json::from_str(&page.render(StatsFormat::Json).unwrap()).unwrap();
+        assert!(json["samples"][0].get("memory").is_none());
+        assert_eq!(json["samples"][0]["pipeline"]["throughput_rps"], 12.5);
+
+        let text = history
+            .query(&query)
+            .render(StatsFormat::Prometheus)
+            .unwrap();
+        assert!(text.contains(
+            "# TYPE fhe_memory_allocated_bytes gauge\nfhe_memory_allocated_bytes 1048576 200000\n"
+        ));
+        assert!(text.contains("fhe_memory_pool_utilization{pool=\"ciphertext\"} 0.5 200000"));
+        assert!(text.contains("fhe_pipeline_queue_length{priority=\"high\"} 3 200000"));
+        assert!(text.contains("fhe_pipeline_stage_bottleneck{stage=\"processing\"} 0.9 200000"));
+        assert!(!text.contains("fhe_cache"));
+        assert_eq!(text.matches("# TYPE fhe_memory_allocated_bytes").count(), 1);
+    }
+
+    #[test]
+    fn test_cache_key_creation() {
+        let key = CacheKey {
+            key_type: CacheKeyType::Ciphertext,
+            identifier: "test_123".to_string(),
+            params_hash: 42,
+        };
+
+        assert_eq!(key.identifier, "test_123");
+        assert_eq!(key.params_hash, 42);
+        assert!(matches!(key.key_type, CacheKeyType::Ciphertext));
+    }
+
+    #[tokio::test]
+    async fn test_gdsf_keeps_costly_entries_that_lru_evicts() {
+        let cache = IntelligentCacheSystem::new(CacheConfiguration {
+            l1_max_entries: 2,
+            l2_max_entries: 0,
+            l3_max_entries: 0,
+            default_ttl: Duration::from_secs(3600),
+            preload_threshold: 0.8,
+            preload_budget_bytes: 0,
+            preload_lead: Duration::from_secs(600),
+            eviction_strategy: EvictionStrategy::GreedyDualSizeFrequency,
+            cost_weights: RecomputeCostWeights::default(),
+            compare_strategies: vec![EvictionStrategy::LRU],
+        })
+        .unwrap();
+        let key = |id: &str| CacheKey {
+            key_type: CacheKeyType::ProcessedResult,
+            identifier: id.to_string(),
+            params_hash: 0,
+        };
+        let data = || CacheData::ProcessedData(vec![0; 100]);
+        let gpu = |ms| RecomputeCost {
+            gpu_time: Duration::from_millis(ms),
+            provider_cost: 0.0,
+        };
+
+        cache
+            .store_with_cost(&key("costly"), data(), gpu(500))
+            .await
+            .unwrap();
+        cache
+            .store_with_cost(&key("cheap"), data(), gpu(1))
+            .await
+            .unwrap();
+        cache
+            .store_with_cost(&key("moderate"), data(), gpu(2))
+            .await
+            .unwrap();
+
+        // GDSF evicted a cheap entry where LRU dropped the oldest, costly one
+        assert!(cache.get(&key("costly")).await.unwrap().is_some());
+        assert!(cache.get(&key("cheap")).await.unwrap().is_none());
+
+        let report = cache.get_statistics().await;
+        assert_eq!(report.total_entries, 2);
+        let (gdsf, lru) = (&report.policies[0], &report.policies[1]);
+        assert_eq!(
+            (gdsf.strategy.as_str(), lru.strategy.as_str()),
+            ("gdsf", "lru")
+        );
+        assert_eq!(gdsf.hit_value, 500.0);
+        assert_eq!(lru.hit_value, 0.0);
+        assert!(gdsf.hit_value_ratio() > lru.hit_value_ratio());
+    }
+
+    #[tokio::test]
+    async fn test_preload_warms_keys_used_daily_ahead_of_their_hour() {
+        let cache = IntelligentCacheSystem::new(CacheConfiguration {
+            l1_max_entries: 10,
+            l2_max_entries: 10,
+            l3_max_entries: 0,
+            default_ttl: Duration::from_secs(3600),
+            preload_threshold: 0.8,
+            preload_budget_bytes: 150,
+            preload_lead: Duration::from_secs(600),
+            eviction_strategy: EvictionStrategy::LRU,
+            cost_weights: RecomputeCostWeights::default(),
+            compare_strategies: Vec::new(),
+        })
+        .unwrap();
+        let key = |id: &str| CacheKey {
+            key_type: CacheKeyType::EvaluationKey,
+            identifier: id.to_string(),
+            params_hash: 0,
+        };
+        let at = |day: u32, hour: u32| {
+            chrono::NaiveDate::from_ymd_opt(2026, 3, day)
+                .unwrap()
+                .and_hms_opt(hour, 5, 0)
+                .unwrap()
+                .and_utc()
+        };
+        // Three days of history: "daily" and "large" every morning at 9,
+        // "sometimes" once, "afternoon" at 15
+        for day in 1..=3 {
+            cache.predictor.record_access(&key("daily"), at(day, 9));
+            cache.predictor.record_access(&key("large"), at(day, 9));
+            cache
+                .predictor
+                .record_access(&key("afternoon"), at(day, 15));
+        }
+        cache.predictor.record_access(&key("sometimes"), at(2, 9));
+        cache.predictor.record_size(&key("large"), 1000);
+
+        // At 8:50 on day four the 9 o'clock keys are loaded, within budget
+        let preload_at = at(4, 8) + chrono::Duration::minutes(45);
+        let sizes = HashMap::from([("daily", 100), ("large", 1000)]);
+        let loaded = cache
+            .run_predictive_preload_at(preload_at, |key| {
+                sizes
+                    .get(key.identifier.as_str())
+                    .map(|&size| CacheData::ProcessedData(vec![0; size]))
+            })
+            .unwrap();
+        assert_eq!(loaded, 1);
+        assert!(cache.is_cached(&key("daily"), Instant::now()));
+        assert!(!cache.is_cached(&key("large"), Instant::now()));
+
+        // The preload is used, so the prediction was right
+        assert!(cache.get(&key("daily")).await.unwrap().is_some());
+        let report = cache.get_statistics().await;
+        assert_eq!(report.preloads, 1);
+        assert_eq!(report.prediction_accuracy, 1.0);
+    }
+
+    #[test]
+    fn test_performance_metrics() {
+        let metrics = PerformanceMetrics::new();
+        metrics.record_request_completed(Duration::from_millis(100));
+
+        assert_eq!(metrics.total_requests.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.successful_requests.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,397 @@
+//! Performance statistics served at `/v1/admin/performance`
+//!
+//! A snapshot is split into subsystems (profiled operations, prompt packing,
+//! allocations, documents, regional latency and per-provider timeouts, errors
+//! and resumption) so callers can ask for the ones they need. The proxy
+//! samples every subsystem into a bounded history alongside its state
+//! recorder, which answers time-range queries. Snapshots render as JSON or in
+//! the Prometheus text format, with one gauge per subsystem and the field's
+//! path within it as a label.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::RwLock;
+
+/// Parts of the performance snapshot that can be selected separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PerformanceSubsystem {
+    /// Profiled operations, each under its own name at the top level
+    Operations,
+    PromptPacking,
+    Allocations,
+    Documents,
+    LatencyByRegion,
+    ProviderTimeouts,
+    ProviderErrors,
+    ProviderResume,
+}
+
+impl PerformanceSubsystem {
+    pub const ALL: [PerformanceSubsystem; 8] = [
+        PerformanceSubsystem::Operations,
+        PerformanceSubsystem::PromptPacking,
+        PerformanceSubsystem::Allocations,
+        PerformanceSubsystem::Documents,
+        PerformanceSubsystem::LatencyByRegion,
+        PerformanceSubsystem::ProviderTimeouts,
+        PerformanceSubsystem::ProviderErrors,
+        PerformanceSubsystem::ProviderResume,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PerformanceSubsystem::Operations => "operations",
+            PerformanceSubsystem::PromptPacking => "prompt_packing",
+            PerformanceSubsystem::Allocations => "allocations",
+            PerformanceSubsystem::Documents => "documents",
+            PerformanceSubsystem::LatencyByRegion => "latency_by_region",
+            PerformanceSubsystem::ProviderTimeouts => "provider_timeouts",
+            PerformanceSubsystem::ProviderErrors => "provider_errors",
+            PerformanceSubsystem::ProviderResume => "provider_resume",
+        }
+    }
+
+    pub fn parse(subsystem: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|s| s.as_str() == subsystem)
+            .ok_or_else(|| {
+                Error::Validation(format!("Unknown performance subsystem: {}", subsystem))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PerformanceFormat {
+    #[default]
+    Json,
+    /// Prometheus text exposition format, version 0.0.4
+    Prometheus,
+}
+
+impl PerformanceFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            PerformanceFormat::Json => "application/json",
+            PerformanceFormat::Prometheus => "text/plain; version=0.0.4",
+        }
+    }
+}
+
+/// Which statistics to return, from when, and how
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PerformanceQuery {
+    /// Comma-separated subsystems; all of them when unset
+    pub subsystems: Option<String>,
+    /// Recorded snapshots taken at or after this Unix timestamp
+    pub since: Option<i64>,
+    /// Recorded snapshots taken at or before this Unix timestamp
+    pub until: Option<i64>,
+    /// Most recorded snapshots returned, the newest ones kept
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub format: PerformanceFormat,
+}
+
+impl PerformanceQuery {
+    /// The selected subsystems, all of them when none are named
+    pub fn selected(&self) -> Result<Vec<PerformanceSubsystem>> {
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since > until {
+                return Err(Error::Validation(
+                    "since must not be after until".to_string(),
+                ));
+            }
+        }
+        let Some(subsystems) = self.subsystems.as_deref() else {
+            return Ok(PerformanceSubsystem::ALL.to_vec());
+        };
+        let mut selected = Vec::new();
+        for subsystem in subsystems.split(',').filter(|s| !s.is_empty()) {
+            let subsystem = PerformanceSubsystem::parse(subsystem)?;
+            if !selected.contains(&subsystem) {
+                selected.push(subsystem);
+            }
+        }
+        if selected.is_empty() {
+            return Ok(PerformanceSubsystem::ALL.to_vec());
+        }
+        Ok(selected)
+    }
+
+    /// Whether the query is answered from recorded history
+    pub fn is_historical(&self) -> bool {
+        self.since.is_some() || self.until.is_some() || self.limit.is_some()
+    }
+}
+
+/// Statistics at one point in time, by subsystem
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PerformanceSnapshot {
+    pub timestamp: i64,
+    pub subsystems: BTreeMap<PerformanceSubsystem, serde_json::Value>,
+}
+
+impl PerformanceSnapshot {
+    pub fn new(timestamp: i64) -> Self {
+        Self {
+            timestamp,
+            subsystems: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, subsystem: PerformanceSubsystem, stats: serde_json::Value) {
+        self.subsystems.insert(subsystem, stats);
+    }
+
+    /// The snapshot with only the `selected` subsystems
+    pub fn project(&self, selected: &[PerformanceSubsystem]) -> Self {
+        Self {
+            timestamp: self.timestamp,
+            subsystems: self
+                .subsystems
+                .iter()
+                .filter(|(subsystem, _)| selected.contains(subsystem))
+                .map(|(subsystem, stats)| (*subsystem, stats.clone()))
+                .collect(),
+        }
+    }
+
+    /// The JSON view: profiled operations at the top level, every other
+    /// subsystem under its name
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::Map::new();
+        for (subsystem, stats) in &self.subsystems {
+            match (subsystem, stats) {
+                (PerformanceSubsystem::Operations, serde_json::Value::Object(operations)) => {
+                    json.extend(operations.clone());
+                }
+                _ => {
+                    json.insert(subsystem.as_str().to_string(), stats.clone());
+                }
+            }
+        }
+        serde_json::Value::Object(json)
+    }
+
+    /// Numeric fields as `fhe_performance_<subsystem>{path="..."}` gauges,
+    /// stamped with the time the snapshot was taken
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        for (subsystem, stats) in &self.subsystems {
+            let mut samples = Vec::new();
+            collect_samples(stats, &mut String::new(), &mut samples);
+            if samples.is_empty() {
+                continue;
+            }
+            let name = format!("fhe_performance_{}", subsystem.as_str());
+            text.push_str(&format!("# TYPE {} gauge\n", name));
+            for (path, value) in samples {
+                let path = path
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                text.push_str(&format!(
+                    "{}{{path=\"{}\"}} {} {}\n",
+                    name,
+                    path,
+                    value,
+                    self.timestamp * 1000
+                ));
+            }
+        }
+        text
+    }
+}
+
+/// Numeric leaves of `value` with their dotted paths; durations serialized
+/// as `{secs, nanos}` become seconds, booleans 0 or 1
+fn collect_samples(value: &serde_json::Value, path: &mut String, samples: &mut Vec<(String, f64)>) {
+    let descend = |key: &str, child, path: &mut String, samples: &mut Vec<_>| {
+        let len = path.len();
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(key);
+        collect_samples(child, path, samples);
+        path.truncate(len);
+    };
+    match value {
+        serde_json::Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                samples.push((path.clone(), number));
+            }
+        }
+        serde_json::Value::Bool(flag) => samples.push((path.clone(), *flag as u8 as f64)),
+        serde_json::Value::Object(fields) => {
+            if let (Some(secs), Some(nanos), 2) = (
+                fields.get("secs").and_then(|v| v.as_u64()),
+                fields.get("nanos").and_then(|v| v.as_u64()),
+                fields.len(),
+            ) {
+                samples.push((path.clone(), secs as f64 + nanos as f64 / 1e9));
+                return;
+            }
+            for (key, child) in fields {
+                descend(key, child, path, samples);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (index, child) in items.iter().enumerate() {
+                descend(&index.to_string(), child, path, samples);
+            }
+        }
+        serde_json::Value::String(_) | serde_json::Value::Null => {}
+    }
+}
+
+/// Bounded history of performance snapshots, oldest first
+#[derive(Debug)]
+pub struct PerformanceHistory {
+    capacity: usize,
+    snapshots: RwLock<VecDeque<PerformanceSnapshot>>,
+}
+
+impl PerformanceHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            snapshots: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, snapshot: PerformanceSnapshot) {
+        let mut snapshots = self.snapshots.write().unwrap();
+        if snapshots.len() == self.capacity {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(snapshot);
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Recorded snapshots within the query's range, with only the
+    /// `selected` subsystems
+    pub fn query(
+        &self,
+        query: &PerformanceQuery,
+        selected: &[PerformanceSubsystem],
+    ) -> Vec<PerformanceSnapshot> {
+        let snapshots = self.snapshots.read().unwrap();
+        let mut matched: Vec<PerformanceSnapshot> = snapshots
+            .iter()
+            .filter(|s| query.since.is_none_or(|since| s.timestamp >= since))
+            .filter(|s| query.until.is_none_or(|until| s.timestamp <= until))
+            .map(|s| s.project(selected))
+            .collect();
+        if let Some(limit) = query.limit {
+            matched.drain(..matched.len().saturating_sub(limit));
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(timestamp: i64) -> PerformanceSnapshot {
+        let mut snapshot = PerformanceSnapshot::new(timestamp);
+        snapshot.insert(
+            PerformanceSubsystem::Operations,
+            serde_json::json!({
+                "encrypt": { "total_calls": 3, "p95_duration": { "secs": 1, "nanos": 500000000 } }
+            }),
+        );
+        snapshot.insert(
+            PerformanceSubsystem::ProviderTimeouts,
+            serde_json::json!({ "vllm": { "llama": { "samples": timestamp, "calibrated": true } } }),
+        );
+        snapshot
+    }
+
+    #[test]
+    fn test_query_selects_subsystems_and_validates_range() {
+        let query = PerformanceQuery {
+            subsystems: Some("provider_timeouts,operations,operations".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            query.selected().unwrap(),
+            vec![
+                PerformanceSubsystem::ProviderTimeouts,
+                PerformanceSubsystem::Operations
+            ]
+        );
+        assert!(!query.is_historical());
+        assert_eq!(
+            PerformanceQuery::default().selected().unwrap().len(),
+            PerformanceSubsystem::ALL.len()
+        );
+
+        let unknown = PerformanceQuery {
+            subsystems: Some("cache".to_string()),
+            ..Default::default()
+        };
+        assert!(unknown.selected().is_err());
+        let reversed = PerformanceQuery {
+            since: Some(20),
+            until: Some(10),
+            ..Default::default()
+        };
+        assert!(reversed.selected().is_err());
+    }
+
+    #[test]
+    fn test_history_answers_ranges_with_the_selected_subsystems() {
+        let history = PerformanceHistory::new(3);
+        for timestamp in [10, 20, 30, 40] {
+            history.record(snapshot(timestamp));
+        }
+        assert_eq!(history.len(), 3);
+
+        let query = PerformanceQuery {
+            since: Some(25),
+            ..Default::default()
+        };
+        let selected = [PerformanceSubsystem::ProviderTimeouts];
+        let samples = history.query(&query, &selected);
+        assert_eq!(
+            samples.iter().map(|s| s.timestamp).collect::<Vec<_>>(),
+            vec![30, 40]
+        );
+        let json = samples[0].to_json();
+        assert_eq!(json["provider_timeouts"]["vllm"]["llama"]["samples"], 30);
+        assert!(json.get("encrypt").is_none());
+
+        let newest = PerformanceQuery {
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(history.query(&newest, &selected)[0].timestamp, 40);
+    }
+
+    #[test]
+    fn test_prometheus_labels_fields_by_path() {
+        let text = snapshot(7).to_prometheus();
+        assert!(text.contains("# TYPE fhe_performance_operations gauge\n"));
+        assert!(text.contains("fhe_performance_operations{path=\"encrypt.total_calls\"} 3 7000\n"));
+        assert!(
+            text.contains("fhe_performance_operations{path=\"encrypt.p95_duration\"} 1.5 7000\n")
+        );
+        assert!(text.contains(
+            "fhe_performance_provider_timeouts{path=\"vllm.llama.calibrated\"} 1 7000\n"
+        ));
+        // The operations are flattened into the JSON view, as they always were
+        assert_eq!(snapshot(7).to_json()["encrypt"]["total_calls"], 3);
+    }
+}
@@ -0,0 +1,2562 @@
+//! Pluggable storage for sessions, audit log, idempotency cache, privacy ledger,
+//! scheduled batch jobs, conversation context, escrowed keys, the billing
+//! ledger, the dead-letter queue and runtime feature flags
+//!
+//! Everything stored for a tenant can be deleted at once with
+//! [`PersistenceBackend::purge_tenant`] when the tenant is offboarded.
+//!
+//! The in-memory backend keeps the historical behaviour (nothing survives a
+//! restart). Small self-hosted deployments can enable the `sqlite` feature
+//! for an embedded, WAL-mode database instead of running a separate store.
+//! Data moves between backends through [`StorageSnapshot`].
+//!
+//! In active-active deployments every region writes sessions locally and a
+//! reconciler pulls the other regions' copies. Each session carries a
+//! [`VersionVector`]; writes that raced during a partition are merged field by
+//! field so neither region's budget spend or context references are lost.
+
+use crate::config::{FeatureFlagConfig, PersistenceConfig};
+use crate::error::{Error, Result};
+use crate::migrations::MigrationTarget;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A client session as stored by the backend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub server_id: Uuid,
+    pub created_at: i64,
+    pub last_used: i64,
+    pub request_count: u64,
+    #[serde(default)]
+    pub version: VersionVector,
+    /// Privacy budget (epsilon) charged to the session, per region that charged it
+    #[serde(default)]
+    pub budget_spent: BTreeMap<String, f64>,
+    /// Context held on behalf of the session; references are only ever added
+    #[serde(default)]
+    pub context_refs: BTreeSet<String>,
+    /// Token and key renewal state, once the session has been issued tokens
+    #[serde(default)]
+    pub renewal: Option<SessionRenewal>,
+}
+
+/// Where a session is in the token refresh and key renewal protocol. Only
+/// hashes of the tokens are kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRenewal {
+    /// Bumped by every refresh and key renewal
+    pub generation: u64,
+    pub access_token_hash: String,
+    pub access_expires_at: i64,
+    pub refresh_token_hash: String,
+    pub refresh_expires_at: i64,
+    /// Access token replaced by the last refresh, honoured until it expires
+    pub previous_access_token_hash: Option<String>,
+    pub previous_access_expires_at: i64,
+    pub key_expires_at: i64,
+    /// Server key replaced by the last renewal, accepted until
+    /// `previous_server_expires_at`
+    pub previous_server_id: Option<Uuid>,
+    pub previous_server_expires_at: i64,
+}
+
+impl SessionRecord {
+    /// Record a local write made by `region`
+    pub fn touch(&mut self, region: &str) {
+        self.version.increment(region);
+    }
+
+    /// Join two replicas of the same session. Every field merges with an
+    /// idempotent, commutative operation, so replicas converge whatever order
+    /// merges happen in: per-region spend and counts take the maximum (each
+    /// region only ever grows its own entry) and context references are united.
+    fn merge(&mut self, other: &SessionRecord) {
+        self.created_at = self.created_at.min(other.created_at);
+        self.last_used = self.last_used.max(other.last_used);
+        self.request_count = self.request_count.max(other.request_count);
+        for (region, spent) in &other.budget_spent {
+            let entry = self.budget_spent.entry(region.clone()).or_insert(0.0);
+            *entry = entry.max(*spent);
+        }
+        self.context_refs.extend(other.context_refs.iter().cloned());
+        // The latest refresh or renewal wins, and brings its server key along
+        let newer = match (&self.renewal, &other.renewal) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(mine), Some(theirs)) => {
+                (theirs.generation, &theirs.access_token_hash)
+                    > (mine.generation, &mine.access_token_hash)
+            }
+        };
+        if newer {
+            self.renewal = other.renewal.clone();
+            self.server_id = other.server_id;
+        }
+        self.version.merge(&other.version);
+    }
+}
+
+/// How two version vectors relate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Causality {
+    Equal,
+    /// Every write seen by `self` was also seen by the other side
+    Before,
+    After,
+    /// Each side saw writes the other did not: a conflict
+    Concurrent,
+}
+
+/// Per-region write counters identifying which writes a replica has seen
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VersionVector(BTreeMap<String, u64>);
+
+impl VersionVector {
+    pub fn increment(&mut self, region: &str) {
+        *self.0.entry(region.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn get(&self, region: &str) -> u64 {
+        self.0.get(region).copied().unwrap_or(0)
+    }
+
+    pub fn compare(&self, other: &VersionVector) -> Causality {
+        let regions: BTreeSet<&String> = self.0.keys().chain(other.0.keys()).collect();
+        let (mut behind, mut ahead) = (false, false);
+        for region in regions {
+            let (mine, theirs) = (self.get(region), other.get(region));
+            behind |= mine < theirs;
+            ahead |= mine > theirs;
+        }
+        match (behind, ahead) {
+            (false, false) => Causality::Equal,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (true, true) => Causality::Concurrent,
+        }
+    }
+
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (region, count) in &other.0 {
+            let entry = self.0.entry(region.clone()).or_insert(0);
+            *entry = (*entry).max(*count);
+        }
+    }
+}
+
+/// What applying a replicated session did to the local copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeOutcome {
+    /// The session was unknown locally
+    Inserted,
+    Identical,
+    /// The remote copy had seen every local write and replaced it
+    FastForward,
+    /// The local copy had already seen every remote write
+    StaleIgnored,
+    /// Both sides wrote during a partition; the copies were merged
+    Merged,
+}
+
+/// Resolve a replicated session against the local copy, returning the record
+/// to store (if anything changes) and how the two related
+pub fn resolve_session(
+    local: Option<&SessionRecord>,
+    remote: &SessionRecord,
+) -> (Option<SessionRecord>, MergeOutcome) {
+    let Some(local) = local else {
+        return (Some(remote.clone()), MergeOutcome::Inserted);
+    };
+    match local.version.compare(&remote.version) {
+        Causality::Equal => (None, MergeOutcome::Identical),
+        Causality::After => (None, MergeOutcome::StaleIgnored),
+        Causality::Before => (Some(remote.clone()), MergeOutcome::FastForward),
+        Causality::Concurrent => {
+            let mut merged = local.clone();
+            merged.merge(remote);
+            (Some(merged), MergeOutcome::Merged)
+        }
+    }
+}
+
+/// An administrative action worth keeping after a restart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub id: Uuid,
+    pub timestamp: i64,
+    pub action: String,
+    pub subject: String,
+    pub details: serde_json::Value,
+}
+
+/// A stored response for a previously seen idempotency key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    pub key: String,
+    pub response: serde_json::Value,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+/// Privacy budget state for one user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrivacyLedgerEntry {
+    pub user_id: String,
+    pub total_epsilon: f64,
+    pub total_delta: f64,
+    pub remaining_epsilon: f64,
+    pub remaining_delta: f64,
+    pub queries_count: u64,
+    pub period_started: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchJobStatus {
+    Queued,
+    Running,
+    Completed,
+    /// Some items succeeded and some failed
+    PartiallyCompleted,
+    Failed,
+    Cancelled,
+}
+
+impl BatchJobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchJobStatus::Queued => "queued",
+            BatchJobStatus::Running => "running",
+            BatchJobStatus::Completed => "completed",
+            BatchJobStatus::PartiallyCompleted => "partially_completed",
+            BatchJobStatus::Failed => "failed",
+            BatchJobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            BatchJobStatus::Completed
+                | BatchJobStatus::PartiallyCompleted
+                | BatchJobStatus::Failed
+                | BatchJobStatus::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+/// Why a batch item failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchItemError {
+    /// Error category, e.g. `param_mismatch` or `external_service`
+    pub kind: String,
+    pub message: String,
+    /// Whether running the item again could succeed
+    pub retryable: bool,
+}
+
+/// One prompt of a multi-item batch job and its outcome
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchItemRecord {
+    pub index: usize,
+    /// Base64 encrypted prompt, dropped once the item has succeeded
+    pub ciphertext: String,
+    pub status: BatchItemStatus,
+    /// Attempts over every run of the item
+    pub attempts: u32,
+    pub result_ciphertext_id: Option<Uuid>,
+    pub error: Option<BatchItemError>,
+    /// Presented to retry a failed item; replaced on every failure
+    pub retry_token: Option<String>,
+}
+
+/// A low-priority job waiting for, or run in, an off-peak batch window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchJobRecord {
+    pub id: Uuid,
+    pub tenant: String,
+    pub provider: String,
+    pub model: String,
+    /// Base64 encrypted prompt; empty for multi-item jobs
+    pub ciphertext: String,
+    /// Fingerprint of the parameters the prompt was encrypted under
+    pub params_hash: String,
+    /// Quota charged at submission, refunded if the job is cancelled
+    pub quota_units: f64,
+    pub status: BatchJobStatus,
+    pub submitted_at: i64,
+    pub updated_at: i64,
+    pub result_ciphertext_id: Option<Uuid>,
+    pub error: Option<String>,
+    /// Prompts of a multi-item job, each run and reported on its own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<BatchItemRecord>,
+}
+
+/// One failed attempt at a dead-lettered work item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetterAttempt {
+    pub attempt: u32,
+    pub started_at: i64,
+    pub duration_ms: u64,
+    pub error: String,
+}
+
+/// Work that failed every attempt, kept until it is reprocessed or purged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetterRecord {
+    pub id: Uuid,
+    /// Pipeline the item came from, e.g. "batch_job"
+    pub source: String,
+    /// Id of the item in its pipeline
+    pub item_id: Uuid,
+    pub tenant: String,
+    /// What the pipeline needs, besides the payload, to run the item again
+    pub metadata: serde_json::Value,
+    /// Base64 encrypted payload
+    pub payload: String,
+    /// Every attempt in order; the last error is why the item was dead-lettered
+    pub attempts: Vec<DeadLetterAttempt>,
+    pub first_attempt_at: i64,
+    pub failed_at: i64,
+}
+
+/// A feature flag set at runtime; overrides the definition of the same name in config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureFlagRecord {
+    pub flag: FeatureFlagConfig,
+    pub updated_at: i64,
+}
+
+/// Fingerprint of one replica's effective configuration; see `config_drift`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigFingerprintRecord {
+    pub replica: String,
+    /// Tenant configuration generation on the replica when it was taken
+    pub generation: u64,
+    pub fingerprint: String,
+    /// SHA-256 of each dotted config key's value
+    pub keys: BTreeMap<String, String>,
+    pub published_at: i64,
+}
+
+/// One encrypted conversation turn held in the session-store tier
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextTurnRecord {
+    pub session_id: Uuid,
+    /// Position in the conversation, from 0
+    pub turn: u64,
+    pub tenant: String,
+    pub role: String,
+    /// Base64 encrypted turn
+    pub ciphertext: String,
+    pub created_at: i64,
+}
+
+/// A client key split across a tenant's custodians
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EscrowRecord {
+    pub client_id: Uuid,
+    pub tenant: String,
+    pub created_at: i64,
+    /// One share per custodian; all of them are needed to rebuild the key
+    pub shares: Vec<EscrowShareRecord>,
+}
+
+/// A key share sealed to one custodian's X25519 public key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EscrowShareRecord {
+    pub custodian: String,
+    /// Base64 X25519 public key the share was sealed with
+    pub ephemeral_public_key: String,
+    /// Base64 AES-256-GCM nonce
+    pub nonce: String,
+    /// Base64 sealed share and tag
+    pub sealed: String,
+    /// Hex SHA-256 of the plaintext share, to check a custodian's submission
+    pub share_sha256: String,
+}
+
+/// Bytes one billed request moved, as measured on the wire
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BillingRecord {
+    pub request_id: Uuid,
+    pub tenant: Option<String>,
+    pub route: String,
+    pub recorded_at: i64,
+    /// Request body bytes as received
+    pub request_bytes: u64,
+    /// Response body bytes before compression
+    pub response_bytes: u64,
+    /// Response body bytes as sent, after compression
+    pub response_wire_bytes: u64,
+    /// From receiving the request to sending the response, or to the end of
+    /// a stream; absent from records written before it was measured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+pub trait SessionStore {
+    fn put_session(&self, session: &SessionRecord) -> Result<()>;
+    fn get_session(&self, id: Uuid) -> Result<Option<SessionRecord>>;
+    fn list_sessions(&self) -> Result<Vec<SessionRecord>>;
+    fn delete_session(&self, id: Uuid) -> Result<()>;
+
+    /// Apply a session replicated from another region
+    fn merge_session(&self, remote: &SessionRecord) -> Result<MergeOutcome> {
+        let local = self.get_session(remote.id)?;
+        let (resolved, outcome) = resolve_session(local.as_ref(), remote);
+        if let Some(resolved) = resolved {
+            self.put_session(&resolved)?;
+        }
+        Ok(outcome)
+    }
+}
+
+pub trait AuditLogStore {
+    fn append_audit(&self, record: &AuditRecord) -> Result<()>;
+    /// Most recent records first
+    fn recent_audit(&self, limit: usize) -> Result<Vec<AuditRecord>>;
+    /// All records, oldest first
+    fn list_audit(&self) -> Result<Vec<AuditRecord>>;
+}
+
+pub trait IdempotencyStore {
+    fn put_idempotent(&self, record: &IdempotencyRecord) -> Result<()>;
+    /// Stored response for `key`, ignoring expired entries
+    fn get_idempotent(&self, key: &str) -> Result<Option<IdempotencyRecord>>;
+    fn list_idempotent(&self) -> Result<Vec<IdempotencyRecord>>;
+}
+
+pub trait PrivacyLedgerStore {
+    fn put_ledger_entry(&self, entry: &PrivacyLedgerEntry) -> Result<()>;
+    fn list_ledger(&self) -> Result<Vec<PrivacyLedgerEntry>>;
+}
+
+pub trait BatchJobStore {
+    fn put_batch_job(&self, job: &BatchJobRecord) -> Result<()>;
+    fn get_batch_job(&self, id: Uuid) -> Result<Option<BatchJobRecord>>;
+    /// All jobs, oldest submission first
+    fn list_batch_jobs(&self) -> Result<Vec<BatchJobRecord>>;
+    fn delete_batch_job(&self, id: Uuid) -> Result<()>;
+}
+
+pub trait ContextStore {
+    fn put_context_turn(&self, turn: &ContextTurnRecord) -> Result<()>;
+    /// Turns of one conversation, in order
+    fn get_context_turns(&self, session_id: Uuid) -> Result<Vec<ContextTurnRecord>>;
+    /// All turns, oldest first
+    fn list_context_turns(&self) -> Result<Vec<ContextTurnRecord>>;
+    fn delete_context_turn(&self, session_id: Uuid, turn: u64) -> Result<()>;
+}
+
+pub trait EscrowStore {
+    fn put_escrow(&self, record: &EscrowRecord) -> Result<()>;
+    fn get_escrow(&self, client_id: Uuid) -> Result<Option<EscrowRecord>>;
+    /// All records, oldest first
+    fn list_escrow(&self) -> Result<Vec<EscrowRecord>>;
+}
+
+pub trait BillingStore {
+    fn put_billing(&self, record: &BillingRecord) -> Result<()>;
+    /// Records with `from <= recorded_at < to`, oldest first
+    fn list_billing(&self, from: i64, to: i64) -> Result<Vec<BillingRecord>>;
+}
+
+pub trait DeadLetterStore {
+    fn put_dead_letter(&self, record: &DeadLetterRecord) -> Result<()>;
+    fn get_dead_letter(&self, id: Uuid) -> Result<Option<DeadLetterRecord>>;
+    /// All records, oldest failure first
+    fn list_dead_letters(&self) -> Result<Vec<DeadLetterRecord>>;
+    fn delete_dead_letter(&self, id: Uuid) -> Result<()>;
+}
+
+pub trait FeatureFlagStore {
+    fn put_feature_flag(&self, record: &FeatureFlagRecord) -> Result<()>;
+    /// All flags, by name
+    fn list_feature_flags(&self) -> Result<Vec<FeatureFlagRecord>>;
+    fn delete_feature_flag(&self, name: &str) -> Result<()>;
+}
+
+pub trait ConfigFingerprintStore {
+    /// Replace the replica's previous fingerprint
+    fn put_config_fingerprint(&self, record: &ConfigFingerprintRecord) -> Result<()>;
+    /// Latest fingerprint of every replica, by replica
+    fn list_config_fingerprints(&self) -> Result<Vec<ConfigFingerprintRecord>>;
+    fn delete_config_fingerprint(&self, replica: &str) -> Result<()>;
+}
+
+/// A complete storage backend
+pub trait PersistenceBackend:
+    SessionStore
+    + AuditLogStore
+    + IdempotencyStore
+    + PrivacyLedgerStore
+    + BatchJobStore
+    + ContextStore
+    + EscrowStore
+    + BillingStore
+    + DeadLetterStore
+    + FeatureFlagStore
+    + ConfigFingerprintStore
+    + MigrationTarget
+    + Debug
+    + Send
+    + Sync
+{
+    fn name(&self) -> &'static str;
+
+    /// Drop expired idempotency entries and audit records older than `audit_retention`
+    fn compact(&self, audit_retention: Duration) -> Result<CompactionReport>;
+
+    /// Delete every record of `tenant`, and the sessions in `sessions`, which
+    /// are stored without their tenant. Privacy ledger entries belong to the
+    /// tenant when their user ID is the tenant ID or starts with `<tenant>:`.
+    /// The audit log is kept.
+    fn purge_tenant(&self, tenant: &str, sessions: &[Uuid]) -> Result<TenantPurgeReport>;
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CompactionReport {
+    pub idempotency_removed: usize,
+    pub audit_removed: usize,
+}
+
+/// Records deleted for one tenant, by kind
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TenantPurgeReport {
+    pub sessions: usize,
+    pub escrow: usize,
+    pub idempotency: usize,
+    pub privacy_ledger: usize,
+    pub batch_jobs: usize,
+    pub dead_letters: usize,
+    pub context_turns: usize,
+    pub billing: usize,
+}
+
+impl TenantPurgeReport {
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        [
+            ("sessions", self.sessions),
+            ("escrow", self.escrow),
+            ("idempotency", self.idempotency),
+            ("privacy_ledger", self.privacy_ledger),
+            ("batch_jobs", self.batch_jobs),
+            ("dead_letters", self.dead_letters),
+            ("context_turns", self.context_turns),
+            ("billing", self.billing),
+        ]
+        .into_iter()
+        .map(|(kind, count)| (kind.to_string(), count))
+        .collect()
+    }
+}
+
+/// Whether a key scoped as `<tenant>:...`, or the bare tenant ID, is `tenant`'s
+pub fn is_tenant_key(tenant: &str, key: &str) -> bool {
+    key.strip_prefix(tenant)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+#[derive(Debug, Default)]
+struct ReplicationCounters {
+    rounds: AtomicU64,
+    peer_failures: AtomicU64,
+    inserted: AtomicU64,
+    identical: AtomicU64,
+    fast_forwarded: AtomicU64,
+    stale_ignored: AtomicU64,
+    merged: AtomicU64,
+    expired_skipped: AtomicU64,
+    last_round_at: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicationStats {
+    pub region: String,
+    pub rounds: u64,
+    pub peer_failures: u64,
+    pub inserted: u64,
+    pub identical: u64,
+    pub fast_forwarded: u64,
+    pub stale_ignored: u64,
+    /// Concurrent writes from a split brain that had to be merged
+    pub conflicts_merged: u64,
+    /// Remote sessions ignored because they had already expired here
+    pub expired_skipped: u64,
+    pub last_round_at: Option<i64>,
+}
+
+/// Merges sessions pulled from peer regions into the local store and counts
+/// how often replicas had diverged
+#[derive(Debug)]
+pub struct SessionReconciler {
+    region: String,
+    counters: ReplicationCounters,
+}
+
+impl SessionReconciler {
+    pub fn new(region: &str) -> Self {
+        Self {
+            region: region.to_string(),
+            counters: ReplicationCounters::default(),
+        }
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// Merge one peer's sessions. Sessions last used before `expired_before`
+    /// are skipped, so sessions evicted here are not resurrected by a peer that
+    /// has not expired them yet.
+    pub fn apply(
+        &self,
+        store: &dyn SessionStore,
+        remote: &[SessionRecord],
+        expired_before: Option<i64>,
+    ) -> Result<()> {
+        for session in remote {
+            if expired_before.is_some_and(|cutoff| session.last_used < cutoff) {
+                self.counters
+                    .expired_skipped
+                    .fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            let counter = match store.merge_session(session)? {
+                MergeOutcome::Inserted => &self.counters.inserted,
+                MergeOutcome::Identical => &self.counters.identical,
+                MergeOutcome::FastForward => &self.counters.fast_forwarded,
+                MergeOutcome::StaleIgnored => &self.counters.stale_ignored,
+                MergeOutcome::Merged => {
+                    log::warn!(
+                        "Merged concurrent writes to session {} from a split brain",
+                        session.id
+                    );
+                    &self.counters.merged
+                }
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    pub fn record_peer_failure(&self) {
+        self.counters.peer_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_round(&self) {
+        self.counters.rounds.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .last_round_at
+            .store(chrono::Utc::now().timestamp() as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ReplicationStats {
+        let last_round_at = self.counters.last_round_at.load(Ordering::Relaxed);
+        ReplicationStats {
+            region: self.region.clone(),
+            rounds: self.counters.rounds.load(Ordering::Relaxed),
+            peer_failures: self.counters.peer_failures.load(Ordering::Relaxed),
+            inserted: self.counters.inserted.load(Ordering::Relaxed),
+            identical: self.counters.identical.load(Ordering::Relaxed),
+            fast_forwarded: self.counters.fast_forwarded.load(Ordering::Relaxed),
+            stale_ignored: self.counters.stale_ignored.load(Ordering::Relaxed),
+            conflicts_merged: self.counters.merged.load(Ordering::Relaxed),
+            expired_skipped: self.counters.expired_skipped.load(Ordering::Relaxed),
+            last_round_at: (last_round_at > 0).then_some(last_round_at as i64),
+        }
+    }
+}
+
+/// Backend-neutral dump of every store, used to migrate between backends
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StorageSnapshot {
+    pub sessions: Vec<SessionRecord>,
+    pub audit: Vec<AuditRecord>,
+    pub idempotency: Vec<IdempotencyRecord>,
+    pub ledger: Vec<PrivacyLedgerEntry>,
+    #[serde(default)]
+    pub batch_jobs: Vec<BatchJobRecord>,
+    #[serde(default)]
+    pub context_turns: Vec<ContextTurnRecord>,
+    #[serde(default)]
+    pub escrow: Vec<EscrowRecord>,
+    #[serde(default)]
+    pub billing: Vec<BillingRecord>,
+    #[serde(default)]
+    pub dead_letters: Vec<DeadLetterRecord>,
+    #[serde(default)]
+    pub feature_flags: Vec<FeatureFlagRecord>,
+    #[serde(default)]
+    pub config_fingerprints: Vec<ConfigFingerprintRecord>,
+}
+
+impl StorageSnapshot {
+    pub fn export(backend: &dyn PersistenceBackend) -> Result<Self> {
+        Ok(Self {
+            sessions: backend.list_sessions()?,
+            audit: backend.list_audit()?,
+            idempotency: backend.list_idempotent()?,
+            ledger: backend.list_ledger()?,
+            batch_jobs: backend.list_batch_jobs()?,
+            context_turns: backend.list_context_turns()?,
+            escrow: backend.list_escrow()?,
+            billing: backend.list_billing(i64::MIN, i64::MAX)?,
+            dead_letters: backend.list_dead_letters()?,
+            feature_flags: backend.list_feature_flags()?,
+            config_fingerprints: backend.list_config_fingerprints()?,
+        })
+    }
+
+    /// Write every record into `backend`, overwriting records with the same key
+    pub fn import(&self, backend: &dyn PersistenceBackend) -> Result<()> {
+        for session in &self.sessions {
+            backend.put_session(session)?;
+        }
+        for record in &self.audit {
+            backend.append_audit(record)?;
+        }
+        for record in &self.idempotency {
+            backend.put_idempotent(record)?;
+        }
+        for entry in &self.ledger {
+            backend.put_ledger_entry(entry)?;
+        }
+        for job in &self.batch_jobs {
+            backend.put_batch_job(job)?;
+        }
+        for turn in &self.context_turns {
+            backend.put_context_turn(turn)?;
+        }
+        for record in &self.escrow {
+            backend.put_escrow(record)?;
+        }
+        for record in &self.billing {
+            backend.put_billing(record)?;
+        }
+        for record in &self.dead_letters {
+            backend.put_dead_letter(record)?;
+        }
+        for record in &self.feature_flags {
+            backend.put_feature_flag(record)?;
+        }
+        for record in &self.config_fingerprints {
+            backend.put_config_fingerprint(record)?;
+        }
+        Ok(())
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.sessions.len()
+            + self.audit.len()
+            + self.idempotency.len()
+            + self.ledger.len()
+            + self.batch_jobs.len()
+            + self.context_turns.len()
+            + self.escrow.len()
+            + self.billing.len()
+            + self.dead_letters.len()
+            + self.feature_flags.len()
+            + self.config_fingerprints.len()
+    }
+}
+
+/// Copy all data from one backend to another
+pub fn migrate(from: &dyn PersistenceBackend, to: &dyn PersistenceBackend) -> Result<usize> {
+    let snapshot = StorageSnapshot::export(from)?;
+    snapshot.import(to)?;
+    log::info!(
+        "Migrated {} records from {} to {} storage",
+        snapshot.record_count(),
+        from.name(),
+        to.name()
+    );
+    Ok(snapshot.record_count())
+}
+
+/// Open the backend selected in the configuration
+pub fn open_backend(config: &PersistenceConfig) -> Result<Arc<dyn PersistenceBackend>> {
+    match config.backend.as_str() {
+        "memory" => Ok(Arc::new(MemoryBackend::new())),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Ok(Arc::new(SqliteBackend::open(&config.sqlite_path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => Err(Error::Config(
+            "SQLite persistence requires building with the `sqlite` feature".to_string(),
+        )),
+        other => Err(Error::Config(format!(
+            "Unknown persistence backend: {}",
+            other
+        ))),
+    }
+}
+
+/// Process-local storage; the default
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    sessions: RwLock<HashMap<Uuid, SessionRecord>>,
+    audit: RwLock<Vec<AuditRecord>>,
+    idempotency: RwLock<HashMap<String, IdempotencyRecord>>,
+    ledger: RwLock<HashMap<String, PrivacyLedgerEntry>>,
+    batch_jobs: RwLock<HashMap<Uuid, BatchJobRecord>>,
+    context_turns: RwLock<BTreeMap<(Uuid, u64), ContextTurnRecord>>,
+    escrow: RwLock<HashMap<Uuid, EscrowRecord>>,
+    billing: RwLock<HashMap<Uuid, BillingRecord>>,
+    dead_letters: RwLock<HashMap<Uuid, DeadLetterRecord>>,
+    feature_flags: RwLock<BTreeMap<String, FeatureFlagRecord>>,
+    config_fingerprints: RwLock<BTreeMap<String, ConfigFingerprintRecord>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemoryBackend {
+    fn put_session(&self, session: &SessionRecord) -> Result<()> {
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.id, session.clone());
+        Ok(())
+    }
+
+    fn get_session(&self, id: Uuid) -> Result<Option<SessionRecord>> {
+        Ok(self.sessions.read().unwrap().get(&id).cloned())
+    }
+
+    fn list_sessions(&self) -> Result<Vec<SessionRecord>> {
+        Ok(self.sessions.read().unwrap().values().cloned().collect())
+    }
+
+    fn delete_session(&self, id: Uuid) -> Result<()> {
+        self.sessions.write().unwrap().remove(&id);
+        Ok(())
+    }
+}
+
+impl AuditLogStore for MemoryBackend {
+    fn append_audit(&self, record: &AuditRecord) -> Result<()> {
+        let mut audit = self.audit.write().unwrap();
+        audit.retain(|r| r.id != record.id);
+        audit.push(record.clone());
+        Ok(())
+    }
+
+    fn recent_audit(&self, limit: usize) -> Result<Vec<AuditRecord>> {
+        Ok(self
+            .audit
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn list_audit(&self) -> Result<Vec<AuditRecord>> {
+        Ok(self.audit.read().unwrap().clone())
+    }
+}
+
+impl IdempotencyStore for MemoryBackend {
+    fn put_idempotent(&self, record: &IdempotencyRecord) -> Result<()> {
+        self.idempotency
+            .write()
+            .unwrap()
+            .insert(record.key.clone(), record.clone());
+        Ok(())
+    }
+
+    fn get_idempotent(&self, key: &str) -> Result<Option<IdempotencyRecord>> {
+        let now = chrono::Utc::now().timestamp();
+        Ok(self
+            .idempotency
+            .read()
+            .unwrap()
+            .get(key)
+            .filter(|r| r.expires_at > now)
+            .cloned())
+    }
+
+    fn list_idempotent(&self) -> Result<Vec<IdempotencyRecord>> {
+        Ok(self.idempotency.read().unwrap().values().cloned().collect())
+    }
+}
+
+impl PrivacyLedgerStore for MemoryBackend {
+    fn put_ledger_entry(&self, entry: &PrivacyLedgerEntry) -> Result<()> {
+        self.ledger
+            .write()
+            .unwrap()
+            .insert(entry.user_id.clone(), entry.clone());
+        Ok(())
+    }
+
+    fn list_ledger(&self) -> Result<Vec<PrivacyLedgerEntry>> {
+        Ok(self.ledger.read().unwrap().values().cloned().collect())
+    }
+}
+
+impl BatchJobStore for MemoryBackend {
+    fn put_batch_job(&self, job: &BatchJobRecord) -> Result<()> {
+        self.batch_jobs.write().unwrap().insert(job.id, job.clone());
+        Ok(())
+    }
+
+    fn get_batch_job(&self, id: Uuid) -> Result<Option<BatchJobRecord>> {
+        Ok(self.batch_jobs.read().unwrap().get(&id).cloned())
+    }
+
+    fn list_batch_jobs(&self) -> Result<Vec<BatchJobRecord>> {
+        let mut jobs: Vec<BatchJobRecord> =
+            self.batch_jobs.read().unwrap().values().cloned().collect();
+        jobs.sort_by_key(|job| job.submitted_at);
+        Ok(jobs)
+    }
+
+    fn delete_batch_job(&self, id: Uuid) -> Result<()> {
+        self.batch_jobs.write().unwrap().remove(&id);
+        Ok(())
+    }
+}
+
+impl ContextStore for MemoryBackend {
+    fn put_context_turn(&self, turn: &ContextTurnRecord) -> Result<()> {
+        self.context_turns
+            .write()
+            .unwrap()
+            .insert((turn.session_id, turn.turn), turn.clone());
+        Ok(())
+    }
+
+    fn get_context_turns(&self, session_id: Uuid) -> Result<Vec<ContextTurnRecord>> {
+        Ok(self
+            .context_turns
+            .read()
+            .unwrap()
+            .range((session_id, 0)..=(session_id, u64::MAX))
+            .map(|(_, turn)| turn.clone())
+            .collect())
+    }
+
+    fn list_context_turns(&self) -> Result<Vec<ContextTurnRecord>> {
+        let mut turns: Vec<ContextTurnRecord> = self
+            .context_turns
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        turns.sort_by_key(|turn| turn.created_at);
+        Ok(turns)
+    }
+
+    fn delete_context_turn(&self, session_id: Uuid, turn: u64) -> Result<()> {
+        self.context_turns
+            .write()
+            .unwrap()
+            .remove(&(session_id, turn));
+        Ok(())
+    }
+}
+
+impl EscrowStore for MemoryBackend {
+    fn put_escrow(&self, record: &EscrowRecord) -> Result<()> {
+        self.escrow
+            .write()
+            .unwrap()
+            .insert(record.client_id, record.clone());
+        Ok(())
+    }
+
+    fn get_escrow(&self, client_id: Uuid) -> Result<Option<EscrowRecord>> {
+        Ok(self.escrow.read().unwrap().get(&client_id).cloned())
+    }
+
+    fn list_escrow(&self) -> Result<Vec<EscrowRecord>> {
+        let mut records: Vec<EscrowRecord> =
+            self.escrow.read().unwrap().values().cloned().collect();
+        records.sort_by_key(|record| record.created_at);
+        Ok(records)
+    }
+}
+
+impl BillingStore for MemoryBackend {
+    fn put_billing(&self, record: &BillingRecord) -> Result<()> {
+        self.billing
+            .write()
+            .unwrap()
+            .insert(record.request_id, record.clone());
+        Ok(())
+    }
+
+    fn list_billing(&self, from: i64, to: i64) -> Result<Vec<BillingRecord>> {
+        let mut records: Vec<BillingRecord> = self
+            .billing
+            .read()
+            .unwrap()
+            .values()
+            .filter(|record| (from..to).contains(&record.recorded_at))
+            .cloned()
+            .collect();
+        records.sort_by_key(|record| record.recorded_at);
+        Ok(records)
+    }
+}
+
+impl DeadLetterStore for MemoryBackend {
+    fn put_dead_letter(&self, record: &DeadLetterRecord) -> Result<()> {
+        self.dead_letters
+            .write()
+            .unwrap()
+            .insert(record.id, record.clone());
+        Ok(())
+    }
+
+    fn get_dead_letter(&self, id: Uuid) -> Result<Option<DeadLetterRecord>> {
+        Ok(self.dead_letters.read().unwrap().get(&id).cloned())
+    }
+
+    fn list_dead_letters(&self) -> Result<Vec<DeadLetterRecord>> {
+        let mut records: Vec<DeadLetterRecord> = self
+            .dead_letters
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        records.sort_by_key(|record| record.failed_at);
+        Ok(records)
+    }
+
+    fn delete_dead_letter(&self, id: Uuid) -> Result<()> {
+        self.dead_letters.write().unwrap().remove(&id);
+        Ok(())
+    }
+}
+
+impl FeatureFlagStore for MemoryBackend {
+    fn put_feature_flag(&self, record: &FeatureFlagRecord) -> Result<()> {
+        self.feature_flags
+            .write()
+            .unwrap()
+            .insert(record.flag.name.clone(), record.clone());
+        Ok(())
+    }
+
+    fn list_feature_flags(&self) -> Result<Vec<FeatureFlagRecord>> {
+        Ok(self
+            .feature_flags
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn delete_feature_flag(&self, name: &str) -> Result<()> {
+        self.feature_flags.write().unwrap().remove(name);
+        Ok(())
+    }
+}
+
+impl ConfigFingerprintStore for MemoryBackend {
+    fn put_config_fingerprint(&self, record: &ConfigFingerprintRecord) -> Result<()> {
+        self.config_fingerprints
+            .write()
+            .unwrap()
+            .insert(record.replica.clone(), record.clone());
+        Ok(())
+    }
+
+    fn list_config_fingerprints(&self) -> Result<Vec<ConfigFingerprintRecord>> {
+        Ok(self
+            .config_fingerprints
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn delete_config_fingerprint(&self, replica: &str) -> Result<()> {
+        self.config_fingerprints.write().unwrap().remove(replica);
+        Ok(())
+    }
+}
+
+/// Nothing outlives the process, so there is no schema to migrate
+impl MigrationTarget for MemoryBackend {}
+
+impl PersistenceBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn compact(&self, audit_retention: Duration) -> Result<CompactionReport> {
+        let now = chrono::Utc::now().timestamp();
+        let cutoff = now - audit_retention.as_secs() as i64;
+
+        let mut idempotency = self.idempotency.write().unwrap();
+        let before = idempotency.len();
+        idempotency.retain(|_, r| r.expires_at > now);
+        let idempotency_removed = before - idempotency.len();
+
+        let mut audit = self.audit.write().unwrap();
+        let before = audit.len();
+        audit.retain(|r| r.timestamp >= cutoff);
+
+        Ok(CompactionReport {
+            idempotency_removed,
+            audit_removed: before - audit.len(),
+        })
+    }
+
+    fn purge_tenant(&self, tenant: &str, sessions: &[Uuid]) -> Result<TenantPurgeReport> {
+        fn purge<K: std::hash::Hash + Eq, V>(
+            records: &RwLock<HashMap<K, V>>,
+            owned: impl Fn(&K, &V) -> bool,
+        ) -> usize {
+            let mut records = records.write().unwrap();
+            let before = records.len();
+            records.retain(|key, record| !owned(key, record));
+            before - records.len()
+        }
+
+        let context_turns = {
+            let mut turns = self.context_turns.write().unwrap();
+            let before = turns.len();
+            turns.retain(|_, turn| turn.tenant != tenant);
+            before - turns.len()
+        };
+        // Idempotency keys are scoped `<tenant>:<key>`
+        let idempotency = purge(&self.idempotency, |key, _| {
+            key.strip_prefix(tenant)
+                .is_some_and(|rest| rest.starts_with(':'))
+        });
+        Ok(TenantPurgeReport {
+            sessions: purge(&self.sessions, |id, _| sessions.contains(id)),
+            escrow: purge(&self.escrow, |_, record| record.tenant == tenant),
+            idempotency,
+            privacy_ledger: purge(&self.ledger, |user_id, _| is_tenant_key(tenant, user_id)),
+            batch_jobs: purge(&self.batch_jobs, |_, job| job.tenant == tenant),
+            dead_letters: purge(&self.dead_letters, |_, record| record.tenant == tenant),
+            context_turns,
+            billing: purge(&self.billing, |_, record| {
+                record.tenant.as_deref() == Some(tenant)
+            }),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteBackend;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use crate::migrations::{AppliedMigration, Migration};
+    use rusqlite::{params, Connection, OptionalExtension};
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    /// Schema migrations, applied in order and tracked with `PRAGMA user_version`
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            name: "create_core_tables",
+            destructive: false,
+            statements: "
+            CREATE TABLE sessions (
+                id TEXT PRIMARY KEY,
+                client_id TEXT NOT NULL,
+                server_id TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_used INTEGER NOT NULL,
+                request_count INTEGER NOT NULL
+            );
+            CREATE TABLE audit_log (
+                id TEXT PRIMARY KEY,
+                timestamp INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                details TEXT NOT NULL
+            );
+            CREATE INDEX audit_log_timestamp ON audit_log (timestamp);
+            CREATE TABLE idempotency (
+                key TEXT PRIMARY KEY,
+                response TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            );
+            CREATE INDEX idempotency_expires_at ON idempotency (expires_at);
+            CREATE TABLE privacy_ledger (
+                user_id TEXT PRIMARY KEY,
+                total_epsilon REAL NOT NULL,
+                total_delta REAL NOT NULL,
+                remaining_epsilon REAL NOT NULL,
+                remaining_delta REAL NOT NULL,
+                queries_count INTEGER NOT NULL,
+                period_started INTEGER NOT NULL
+            );
+            ",
+        },
+        Migration {
+            version: 2,
+            name: "add_session_replication",
+            destructive: false,
+            statements: "
+            ALTER TABLE sessions ADD COLUMN replication TEXT NOT NULL DEFAULT '{}';
+            ",
+        },
+        Migration {
+            version: 3,
+            name: "create_batch_jobs",
+            destructive: false,
+            statements: "
+            CREATE TABLE batch_jobs (
+                id TEXT PRIMARY KEY,
+                tenant TEXT NOT NULL,
+                status TEXT NOT NULL,
+                submitted_at INTEGER NOT NULL,
+                record TEXT NOT NULL
+            );
+            CREATE INDEX batch_jobs_status ON batch_jobs (status, submitted_at);
+            ",
+        },
+        Migration {
+            version: 4,
+            name: "create_context_turns",
+            destructive: false,
+            statements: "
+            CREATE TABLE context_turns (
+                session_id TEXT NOT NULL,
+                turn INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                record TEXT NOT NULL,
+                PRIMARY KEY (session_id, turn)
+            );
+            CREATE INDEX context_turns_created_at ON context_turns (created_at);
+            ",
+        },
+        Migration {
+            version: 5,
+            name: "create_escrow_records",
+            destructive: false,
+            statements: "
+            CREATE TABLE escrow_records (
+                client_id TEXT PRIMARY KEY,
+                tenant TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                record TEXT NOT NULL
+            );
+            ",
+        },
+        Migration {
+            version: 6,
+            name: "create_billing_records",
+            destructive: false,
+            statements: "
+            CREATE TABLE billing_records (
+                request_id TEXT PRIMARY KEY,
+                tenant TEXT,
+                recorded_at INTEGER NOT NULL,
+                record TEXT NOT NULL
+            );
+            CREATE INDEX billing_records_recorded_at ON billing_records (recorded_at);
+            ",
+        },
+        Migration {
+            version: 7,
+            name: "create_dead_letters",
+            destructive: false,
+            statements: "
+            CREATE TABLE dead_letters (
+                id TEXT PRIMARY KEY,
+                tenant TEXT NOT NULL,
+                failed_at INTEGER NOT NULL,
+                record TEXT NOT NULL
+            );
+            CREATE INDEX dead_letters_failed_at ON dead_letters (failed_at);
+            ",
+        },
+        Migration {
+            version: 8,
+            name: "create_feature_flags",
+            destructive: false,
+            statements: "
+            CREATE TABLE feature_flags (
+                name TEXT PRIMARY KEY,
+                updated_at INTEGER NOT NULL,
+                record TEXT NOT NULL
+            );
+            ",
+        },
+        Migration {
+            version: 9,
+            name: "create_config_fingerprints",
+            destructive: false,
+            statements: "
+            CREATE TABLE config_fingerprints (
+                replica TEXT PRIMARY KEY,
+                published_at INTEGER NOT NULL,
+                record TEXT NOT NULL
+            );
+            ",
+        },
+    ];
+
+    /// Replication state of a session, stored as JSON in `sessions.replication`
+    #[derive(Default, Serialize, Deserialize)]
+    #[serde(default)]
+    struct SessionReplication {
+        version: VersionVector,
+        budget_spent: BTreeMap<String, f64>,
+        context_refs: BTreeSet<String>,
+        renewal: Option<SessionRenewal>,
+    }
+
+    fn db_error(e: rusqlite::Error) -> Error {
+        Error::Internal(format!("SQLite error: {}", e))
+    }
+
+    fn parse_uuid(value: String) -> rusqlite::Result<Uuid> {
+        Uuid::parse_str(&value).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    fn parse_json(value: String) -> rusqlite::Result<serde_json::Value> {
+        serde_json::from_str(&value).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    /// Embedded SQLite storage in WAL mode
+    #[derive(Debug)]
+    pub struct SqliteBackend {
+        conn: Mutex<Connection>,
+    }
+
+    impl SqliteBackend {
+        pub fn open(path: &str) -> Result<Self> {
+            if let Some(parent) = std::path::Path::new(path).parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            Self::init(Connection::open(path).map_err(db_error)?)
+        }
+
+        /// A fresh database with every migration applied
+        pub fn open_in_memory() -> Result<Self> {
+            let backend = Self::init(Connection::open_in_memory().map_err(db_error)?)?;
+            for migration in MIGRATIONS {
+                backend.apply_migration(migration)?;
+            }
+            Ok(backend)
+        }
+
+        /// Open the connection and create the migration bookkeeping tables;
+        /// the schema itself is left to the migration runner
+        fn init(conn: Connection) -> Result<Self> {
+            // auto_vacuum only takes effect before the first table is created
+            conn.execute_batch(
+                "PRAGMA auto_vacuum = INCREMENTAL;
+                 PRAGMA journal_mode = WAL;
+                 PRAGMA synchronous = NORMAL;
+                 PRAGMA busy_timeout = 5000;",
+            )
+            .map_err(db_error)?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS schema_migrations (
+                     version INTEGER PRIMARY KEY,
+                     name TEXT NOT NULL,
+                     applied_at INTEGER NOT NULL,
+                     duration_ms INTEGER NOT NULL
+                 );
+                 CREATE TABLE IF NOT EXISTS migration_lock (
+                     id INTEGER PRIMARY KEY CHECK (id = 1),
+                     holder TEXT NOT NULL,
+                     expires_at INTEGER NOT NULL
+                 );",
+            )
+            .map_err(db_error)?;
+
+            Ok(Self {
+                conn: Mutex::new(conn),
+            })
+        }
+    }
+
+    fn schema_version(conn: &Connection) -> Result<u32> {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(db_error)
+    }
+
+    impl MigrationTarget for SqliteBackend {
+        fn migrations(&self) -> &'static [Migration] {
+            MIGRATIONS
+        }
+
+        fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+            let conn = self.conn.lock().unwrap();
+            let version = schema_version(&conn)?;
+            let mut statement = conn
+                .prepare("SELECT version, name, applied_at, duration_ms FROM schema_migrations")
+                .map_err(db_error)?;
+            let mut recorded: HashMap<u32, AppliedMigration> = statement
+                .query_map([], |row| {
+                    Ok(AppliedMigration {
+                        version: row.get(0)?,
+                        name: row.get(1)?,
+                        applied_at: Some(row.get(2)?),
+                        duration_ms: Some(row.get(3)?),
+                    })
+                })
+                .map_err(db_error)?
+                .map(|m| m.map(|m| (m.version, m)))
+                .collect::<rusqlite::Result<_>>()
+                .map_err(db_error)?;
+
+            // Databases migrated before history was kept only have `user_version`
+            Ok((1..=version)
+                .map(|v| {
+                    recorded.remove(&v).unwrap_or_else(|| AppliedMigration {
+                        version: v,
+                        name: MIGRATIONS
+                            .iter()
+                            .find(|m| m.version == v)
+                            .map_or("unknown", |m| m.name)
+                            .to_string(),
+                        applied_at: None,
+                        duration_ms: None,
+                    })
+                })
+                .collect())
+        }
+
+        fn apply_migration(&self, migration: &Migration) -> Result<()> {
+            let mut conn = self.conn.lock().unwrap();
+            let version = schema_version(&conn)?;
+            if migration.version != version + 1 {
+                return Err(Error::Internal(format!(
+                    "Cannot apply storage migration {} to schema version {}",
+                    migration.version, version
+                )));
+            }
+            let started = Instant::now();
+            let tx = conn.transaction().map_err(db_error)?;
+            tx.execute_batch(migration.statements).map_err(db_error)?;
+            tx.execute_batch(&format!("PRAGMA user_version = {}", migration.version))
+                .map_err(db_error)?;
+            tx.execute(
+                "INSERT OR REPLACE INTO schema_migrations (version, name, applied_at, duration_ms)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    migration.version,
+                    migration.name,
+                    chrono::Utc::now().timestamp(),
+                    started.elapsed().as_millis() as i64
+                ],
+            )
+            .map_err(db_error)?;
+            tx.commit().map_err(db_error)
+        }
+
+        fn backup(&self, dir: &Path) -> Result<PathBuf> {
+            std::fs::create_dir_all(dir)?;
+            let conn = self.conn.lock().unwrap();
+            let path = dir.join(format!(
+                "fhe-proxy-v{}-{}.db",
+                schema_version(&conn)?,
+                chrono::Utc::now().format("%Y%m%dT%H%M%S")
+            ));
+            conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+                .map_err(db_error)?;
+            Ok(path)
+        }
+
+        fn try_lock_migrations(&self, holder: &str, lease: Duration) -> Result<bool> {
+            let now = chrono::Utc::now().timestamp();
+            let taken = self
+                .conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT INTO migration_lock (id, holder, expires_at) VALUES (1, ?1, ?2)
+                     ON CONFLICT (id) DO UPDATE
+                     SET holder = excluded.holder, expires_at = excluded.expires_at
+                     WHERE migration_lock.holder = excluded.holder
+                        OR migration_lock.expires_at <= ?3",
+                    params![holder, now + lease.as_secs() as i64, now],
+                )
+                .map_err(db_error)?;
+            Ok(taken == 1)
+        }
+
+        fn unlock_migrations(&self, holder: &str) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "DELETE FROM migration_lock WHERE holder = ?1",
+                    params![holder],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+    }
+
+    impl SessionStore for SqliteBackend {
+        fn put_session(&self, session: &SessionRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO sessions
+                     (id, client_id, server_id, created_at, last_used, request_count, replication)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        session.id.to_string(),
+                        session.client_id.to_string(),
+                        session.server_id.to_string(),
+                        session.created_at,
+                        session.last_used,
+                        session.request_count as i64,
+                        serde_json::to_string(&SessionReplication {
+                            version: session.version.clone(),
+                            budget_spent: session.budget_spent.clone(),
+                            context_refs: session.context_refs.clone(),
+                            renewal: session.renewal.clone(),
+                        })?
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn get_session(&self, id: Uuid) -> Result<Option<SessionRecord>> {
+            self.conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT id, client_id, server_id, created_at, last_used, request_count,
+                     replication FROM sessions WHERE id = ?1",
+                    params![id.to_string()],
+                    session_from_row,
+                )
+                .optional()
+                .map_err(db_error)
+        }
+
+        fn list_sessions(&self) -> Result<Vec<SessionRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, client_id, server_id, created_at, last_used, request_count,
+                     replication FROM sessions",
+                )
+                .map_err(db_error)?;
+            let rows = stmt.query_map([], session_from_row).map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+
+        fn delete_session(&self, id: Uuid) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "DELETE FROM sessions WHERE id = ?1",
+                    params![id.to_string()],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+    }
+
+    fn session_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SessionRecord> {
+        let replication: SessionReplication = serde_json::from_value(parse_json(row.get(6)?)?)
+            .map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    6,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                )
+            })?;
+        Ok(SessionRecord {
+            id: parse_uuid(row.get(0)?)?,
+            client_id: parse_uuid(row.get(1)?)?,
+            server_id: parse_uuid(row.get(2)?)?,
+            created_at: row.get(3)?,
+            last_used: row.get(4)?,
+            request_count: row.get::<_, i64>(5)? as u64,
+            version: replication.version,
+            budget_spent: replication.budget_spent,
+            context_refs: replication.context_refs,
+            renewal: replication.renewal,
+        })
+    }
+
+    impl AuditLogStore for SqliteBackend {
+        fn append_audit(&self, record: &AuditRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO audit_log (id, timestamp, action, subject, details)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        record.id.to_string(),
+                        record.timestamp,
+                        record.action,
+                        record.subject,
+                        record.details.to_string()
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn recent_audit(&self, limit: usize) -> Result<Vec<AuditRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, timestamp, action, subject, details FROM audit_log
+                     ORDER BY timestamp DESC, rowid DESC LIMIT ?1",
+                )
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map(params![limit as i64], audit_from_row)
+                .map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+
+        fn list_audit(&self) -> Result<Vec<AuditRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, timestamp, action, subject, details FROM audit_log
+                     ORDER BY timestamp, rowid",
+                )
+                .map_err(db_error)?;
+            let rows = stmt.query_map([], audit_from_row).map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+    }
+
+    fn audit_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<AuditRecord> {
+        Ok(AuditRecord {
+            id: parse_uuid(row.get(0)?)?,
+            timestamp: row.get(1)?,
+            action: row.get(2)?,
+            subject: row.get(3)?,
+            details: parse_json(row.get(4)?)?,
+        })
+    }
+
+    impl IdempotencyStore for SqliteBackend {
+        fn put_idempotent(&self, record: &IdempotencyRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO idempotency (key, response, created_at, expires_at)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        record.key,
+                        record.response.to_string(),
+                        record.created_at,
+                        record.expires_at
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn get_idempotent(&self, key: &str) -> Result<Option<IdempotencyRecord>> {
+            self.conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT key, response, created_at, expires_at FROM idempotency
+                     WHERE key = ?1 AND expires_at > ?2",
+                    params![key, chrono::Utc::now().timestamp()],
+                    idempotency_from_row,
+                )
+                .optional()
+                .map_err(db_error)
+        }
+
+        fn list_idempotent(&self) -> Result<Vec<IdempotencyRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT key, response, created_at, expires_at FROM idempotency")
+                .map_err(db_error)?;
+            let rows = stmt.query_map([], idempotency_from_row).map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+    }
+
+    fn idempotency_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<IdempotencyRecord> {
+        Ok(IdempotencyRecord {
+            key: row.get(0)?,
+            response: parse_json(row.get(1)?)?,
+            created_at: row.get(2)?,
+            expires_at: row.get(3)?,
+        })
+    }
+
+    impl PrivacyLedgerStore for SqliteBackend {
+        fn put_ledger_entry(&self, entry: &PrivacyLedgerEntry) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO privacy_ledger
+                     (user_id, total_epsilon, total_delta, remaining_epsilon, remaining_delta,
+                      queries_count, period_started)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        entry.user_id,
+                        entry.total_epsilon,
+                        entry.total_delta,
+                        entry.remaining_epsilon,
+                        entry.remaining_delta,
+                        entry.queries_count as i64,
+                        entry.period_started
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn list_ledger(&self) -> Result<Vec<PrivacyLedgerEntry>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare(
+                    "SELECT user_id, total_epsilon, total_delta, remaining_epsilon,
+                            remaining_delta, queries_count, period_started
+                     FROM privacy_ledger",
+                )
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok(PrivacyLedgerEntry {
+                        user_id: row.get(0)?,
+                        total_epsilon: row.get(1)?,
+                        total_delta: row.get(2)?,
+                        remaining_epsilon: row.get(3)?,
+                        remaining_delta: row.get(4)?,
+                        queries_count: row.get::<_, i64>(5)? as u64,
+                        period_started: row.get(6)?,
+                    })
+                })
+                .map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+    }
+
+    fn batch_job_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<BatchJobRecord> {
+        serde_json::from_value(parse_json(row.get(0)?)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    impl BatchJobStore for SqliteBackend {
+        fn put_batch_job(&self, job: &BatchJobRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO batch_jobs (id, tenant, status, submitted_at, record)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        job.id.to_string(),
+                        job.tenant,
+                        job.status.as_str(),
+                        job.submitted_at,
+                        serde_json::to_string(job)?
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn get_batch_job(&self, id: Uuid) -> Result<Option<BatchJobRecord>> {
+            self.conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT record FROM batch_jobs WHERE id = ?1",
+                    params![id.to_string()],
+                    batch_job_from_row,
+                )
+                .optional()
+                .map_err(db_error)
+        }
+
+        fn list_batch_jobs(&self) -> Result<Vec<BatchJobRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT record FROM batch_jobs ORDER BY submitted_at, rowid")
+                .map_err(db_error)?;
+            let rows = stmt.query_map([], batch_job_from_row).map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+
+        fn delete_batch_job(&self, id: Uuid) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "DELETE FROM batch_jobs WHERE id = ?1",
+                    params![id.to_string()],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+    }
+
+    fn context_turn_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ContextTurnRecord> {
+        serde_json::from_value(parse_json(row.get(0)?)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    impl ContextStore for SqliteBackend {
+        fn put_context_turn(&self, turn: &ContextTurnRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO context_turns (session_id, turn, created_at, record)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        turn.session_id.to_string(),
+                        turn.turn as i64,
+                        turn.created_at,
+                        serde_json::to_string(turn)?
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn get_context_turns(&self, session_id: Uuid) -> Result<Vec<ContextTurnRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT record FROM context_turns WHERE session_id = ?1 ORDER BY turn")
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map(params![session_id.to_string()], context_turn_from_row)
+                .map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+
+        fn list_context_turns(&self) -> Result<Vec<ContextTurnRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT record FROM context_turns ORDER BY created_at, rowid")
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map([], context_turn_from_row)
+                .map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+
+        fn delete_context_turn(&self, session_id: Uuid, turn: u64) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "DELETE FROM context_turns WHERE session_id = ?1 AND turn = ?2",
+                    params![session_id.to_string(), turn as i64],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+    }
+
+    fn escrow_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<EscrowRecord> {
+        serde_json::from_value(parse_json(row.get(0)?)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    impl EscrowStore for SqliteBackend {
+        fn put_escrow(&self, record: &EscrowRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO escrow_records (client_id, tenant, created_at, record)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        record.client_id.to_string(),
+                        record.tenant,
+                        record.created_at,
+                        serde_json::to_string(record)?
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn get_escrow(&self, client_id: Uuid) -> Result<Option<EscrowRecord>> {
+            self.conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT record FROM escrow_records WHERE client_id = ?1",
+                    params![client_id.to_string()],
+                    escrow_from_row,
+                )
+                .optional()
+                .map_err(db_error)
+        }
+
+        fn list_escrow(&self) -> Result<Vec<EscrowRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT record FROM escrow_records ORDER BY created_at, rowid")
+                .map_err(db_error)?;
+            let rows = stmt.query_map([], escrow_from_row).map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+    }
+
+    fn billing_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<BillingRecord> {
+        serde_json::from_value(parse_json(row.get(0)?)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    impl BillingStore for SqliteBackend {
+        fn put_billing(&self, record: &BillingRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO billing_records (request_id, tenant, recorded_at, record)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        record.request_id.to_string(),
+                        record.tenant,
+                        record.recorded_at,
+                        serde_json::to_string(record)?
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn list_billing(&self, from: i64, to: i64) -> Result<Vec<BillingRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare(
+                    "SELECT record FROM billing_records
+                     WHERE recorded_at >= ?1 AND recorded_at < ?2
+                     ORDER BY recorded_at, rowid",
+                )
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map(params![from, to], billing_from_row)
+                .map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+    }
+
+    fn dead_letter_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<DeadLetterRecord> {
+        serde_json::from_value(parse_json(row.get(0)?)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    impl DeadLetterStore for SqliteBackend {
+        fn put_dead_letter(&self, record: &DeadLetterRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO dead_letters (id, tenant, failed_at, record)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        record.id.to_string(),
+                        record.tenant,
+                        record.failed_at,
+                        serde_json::to_string(record)?
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn get_dead_letter(&self, id: Uuid) -> Result<Option<DeadLetterRecord>> {
+            self.conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT record FROM dead_letters WHERE id = ?1",
+                    params![id.to_string()],
+                    dead_letter_from_row,
+                )
+                .optional()
+                .map_err(db_error)
+        }
+
+        fn list_dead_letters(&self) -> Result<Vec<DeadLetterRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT record FROM dead_letters ORDER BY failed_at, rowid")
+                .map_err(db_error)?;
+            let rows = stmt.query_map([], dead_letter_from_row).map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+
+        fn delete_dead_letter(&self, id: Uuid) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "DELETE FROM dead_letters WHERE id = ?1",
+                    params![id.to_string()],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+    }
+
+    fn feature_flag_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<FeatureFlagRecord> {
+        serde_json::from_value(parse_json(row.get(0)?)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    impl FeatureFlagStore for SqliteBackend {
+        fn put_feature_flag(&self, record: &FeatureFlagRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO feature_flags (name, updated_at, record)
+                     VALUES (?1, ?2, ?3)",
+                    params![
+                        record.flag.name,
+                        record.updated_at,
+                        serde_json::to_string(record)?
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn list_feature_flags(&self) -> Result<Vec<FeatureFlagRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT record FROM feature_flags ORDER BY name")
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map([], feature_flag_from_row)
+                .map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+
+        fn delete_feature_flag(&self, name: &str) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute("DELETE FROM feature_flags WHERE name = ?1", params![name])
+                .map_err(db_error)?;
+            Ok(())
+        }
+    }
+
+    fn config_fingerprint_from_row(
+        row: &rusqlite::Row<'_>,
+    ) -> rusqlite::Result<ConfigFingerprintRecord> {
+        serde_json::from_value(parse_json(row.get(0)?)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        })
+    }
+
+    impl ConfigFingerprintStore for SqliteBackend {
+        fn put_config_fingerprint(&self, record: &ConfigFingerprintRecord) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT OR REPLACE INTO config_fingerprints (replica, published_at, record)
+                     VALUES (?1, ?2, ?3)",
+                    params![
+                        record.replica,
+                        record.published_at,
+                        serde_json::to_string(record)?
+                    ],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+
+        fn list_config_fingerprints(&self) -> Result<Vec<ConfigFingerprintRecord>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT record FROM config_fingerprints ORDER BY replica")
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map([], config_fingerprint_from_row)
+                .map_err(db_error)?;
+            rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+        }
+
+        fn delete_config_fingerprint(&self, replica: &str) -> Result<()> {
+            self.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "DELETE FROM config_fingerprints WHERE replica = ?1",
+                    params![replica],
+                )
+                .map_err(db_error)?;
+            Ok(())
+        }
+    }
+
+    impl PersistenceBackend for SqliteBackend {
+        fn name(&self) -> &'static str {
+            "sqlite"
+        }
+
+        fn compact(&self, audit_retention: Duration) -> Result<CompactionReport> {
+            let conn = self.conn.lock().unwrap();
+            let now = chrono::Utc::now().timestamp();
+
+            let idempotency_removed = conn
+                .execute(
+                    "DELETE FROM idempotency WHERE expires_at <= ?1",
+                    params![now],
+                )
+                .map_err(db_error)?;
+            let audit_removed = conn
+                .execute(
+                    "DELETE FROM audit_log WHERE timestamp < ?1",
+                    params![now - audit_retention.as_secs() as i64],
+                )
+                .map_err(db_error)?;
+
+            // Return freed pages to the filesystem and fold the WAL back into the database
+            conn.execute_batch("PRAGMA incremental_vacuum;")
+                .map_err(db_error)?;
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+                .map_err(db_error)?;
+
+            Ok(CompactionReport {
+                idempotency_removed,
+                audit_removed,
+            })
+        }
+
+        fn purge_tenant(&self, tenant: &str, sessions: &[Uuid]) -> Result<TenantPurgeReport> {
+            let mut conn = self.conn.lock().unwrap();
+            // Overwrite deleted content with zeros rather than only unlinking it
+            conn.execute_batch("PRAGMA secure_delete = ON;")
+                .map_err(db_error)?;
+            let tx = conn.transaction().map_err(db_error)?;
+            let delete = |sql: &str| tx.execute(sql, params![tenant]).map_err(db_error);
+
+            let mut deleted_sessions = 0;
+            for id in sessions {
+                deleted_sessions += tx
+                    .execute(
+                        "DELETE FROM sessions WHERE id = ?1",
+                        params![id.to_string()],
+                    )
+                    .map_err(db_error)?;
+            }
+            let report = TenantPurgeReport {
+                sessions: deleted_sessions,
+                escrow: delete("DELETE FROM escrow_records WHERE tenant = ?1")?,
+                idempotency: delete(
+                    "DELETE FROM idempotency WHERE substr(key, 1, length(?1) + 1) = ?1 || ':'",
+                )?,
+                privacy_ledger: delete(
+                    "DELETE FROM privacy_ledger
+                     WHERE user_id = ?1 OR substr(user_id, 1, length(?1) + 1) = ?1 || ':'",
+                )?,
+                batch_jobs: delete("DELETE FROM batch_jobs WHERE tenant = ?1")?,
+                dead_letters: delete("DELETE FROM dead_letters WHERE tenant = ?1")?,
+                context_turns: delete(
+                    "DELETE FROM context_turns WHERE json_extract(record, '$.tenant') = ?1",
+                )?,
+                billing: delete("DELETE FROM billing_records WHERE tenant = ?1")?,
+            };
+            tx.commit().map_err(db_error)?;
+            Ok(report)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_session(now: i64) -> SessionRecord {
+        SessionRecord {
+            id: Uuid::new_v4(),
+            client_id: Uuid::new_v4(),
+            server_id: Uuid::new_v4(),
+            created_at: now,
+            last_used: now,
+            request_count: 3,
+            version: VersionVector::default(),
+            budget_spent: BTreeMap::new(),
+            context_refs: BTreeSet::new(),
+            renewal: None,
+        }
+    }
+
+    /// A write charging `epsilon` to the session in `region`
+    fn charge_budget(session: &mut SessionRecord, region: &str, epsilon: f64) {
+        *session
+            .budget_spent
+            .entry(region.to_string())
+            .or_insert(0.0) += epsilon;
+        session.touch(region);
+    }
+
+    /// A write referencing a context turn from `region`
+    fn add_context_ref(session: &mut SessionRecord, region: &str, reference: &str) {
+        session.context_refs.insert(reference.to_string());
+        session.touch(region);
+    }
+
+    fn total_budget_spent(session: &SessionRecord) -> f64 {
+        session.budget_spent.values().sum()
+    }
+
+    fn sample_snapshot() -> StorageSnapshot {
+        let now = chrono::Utc::now().timestamp();
+        let mut session = sample_session(now);
+        charge_budget(&mut session, "eu", 0.5);
+        add_context_ref(&mut session, "eu", "ctx-1");
+        session.renewal = Some(SessionRenewal {
+            generation: 2,
+            access_token_hash: "a2".to_string(),
+            access_expires_at: now + 900,
+            refresh_token_hash: "r2".to_string(),
+            refresh_expires_at: now + 86400,
+            previous_access_token_hash: Some("a1".to_string()),
+            previous_access_expires_at: now + 60,
+            key_expires_at: now + 86400,
+            previous_server_id: Some(Uuid::new_v4()),
+            previous_server_expires_at: now + 300,
+        });
+        let session_id = session.id;
+        StorageSnapshot {
+            sessions: vec![session],
+            audit: vec![
+                AuditRecord {
+                    id: Uuid::new_v4(),
+                    timestamp: now - 400 * 86400,
+                    action: "privacy_budget.reset".to_string(),
+                    subject: "alice".to_string(),
+                    details: serde_json::json!({}),
+                },
+                AuditRecord {
+                    id: Uuid::new_v4(),
+                    timestamp: now,
+                    action: "attestation.rotate".to_string(),
+                    subject: "server".to_string(),
+                    details: serde_json::json!({"key_id": "k2"}),
+                },
+            ],
+            idempotency: vec![
+                IdempotencyRecord {
+                    key: "live".to_string(),
+                    response: serde_json::json!({"id": "fhe-1"}),
+                    created_at: now,
+                    expires_at: now + 60,
+                },
+                IdempotencyRecord {
+                    key: "expired".to_string(),
+                    response: serde_json::json!({"id": "fhe-0"}),
+                    created_at: now - 120,
+                    expires_at: now - 60,
+                },
+            ],
+            ledger: vec![PrivacyLedgerEntry {
+                user_id: "alice".to_string(),
+                total_epsilon: 10.0,
+                total_delta: 1e-5,
+                remaining_epsilon: 9.5,
+                remaining_delta: 1e-5,
+                queries_count: 5,
+                period_started: now,
+            }],
+            batch_jobs: vec![BatchJobRecord {
+                id: Uuid::new_v4(),
+                tenant: "acme".to_string(),
+                provider: "openai".to_string(),
+                model: "gpt-4".to_string(),
+                ciphertext: "AAEC".to_string(),
+                params_hash: "abc".to_string(),
+                quota_units: 0.5,
+                status: BatchJobStatus::Queued,
+                submitted_at: now,
+                updated_at: now,
+                result_ciphertext_id: None,
+                error: None,
+                items: Vec::new(),
+            }],
+            context_turns: vec![ContextTurnRecord {
+                session_id,
+                turn: 0,
+                tenant: "acme".to_string(),
+                role: "user".to_string(),
+                ciphertext: "AQID".to_string(),
+                created_at: now,
+            }],
+            escrow: vec![EscrowRecord {
+                client_id: Uuid::new_v4(),
+                tenant: "acme".to_string(),
+                created_at: now,
+                shares: vec![EscrowShareRecord {
+                    custodian: "compliance".to_string(),
+                    ephemeral_public_key: "AAAA".to_string(),
+                    nonce: "AAAA".to_string(),
+                    sealed: "AAAA".to_string(),
+                    share_sha256: "00".to_string(),
+                }],
+            }],
+            billing: vec![BillingRecord {
+                request_id: Uuid::new_v4(),
+                tenant: Some("acme".to_string()),
+                route: "/v1/encrypt".to_string(),
+                recorded_at: now,
+                request_bytes: 120,
+                response_bytes: 4096,
+                response_wire_bytes: 1800,
+                duration_ms: Some(42),
+            }],
+            dead_letters: vec![DeadLetterRecord {
+                id: Uuid::new_v4(),
+                source: "batch_job".to_string(),
+                item_id: Uuid::new_v4(),
+                tenant: "acme".to_string(),
+                metadata: serde_json::json!({"model": "gpt-4"}),
+                payload: "AAEC".to_string(),
+                attempts: vec![DeadLetterAttempt {
+                    attempt: 1,
+                    started_at: now - 5,
+                    duration_ms: 40,
+                    error: "Provider unavailable".to_string(),
+                }],
+                first_attempt_at: now - 5,
+                failed_at: now,
+            }],
+            feature_flags: vec![FeatureFlagRecord {
+                flag: FeatureFlagConfig {
+                    name: "prompt_packing".to_string(),
+                    description: String::new(),
+                    enabled: true,
+                    rollout_percent: 25.0,
+                    tenants: vec!["acme".to_string()],
+                    excluded_tenants: Vec::new(),
+                },
+                updated_at: now,
+            }],
+            config_fingerprints: vec![ConfigFingerprintRecord {
+                replica: "fhe-proxy-0".to_string(),
+                generation: 2,
+                fingerprint: "ab".to_string(),
+                keys: BTreeMap::from([("server.port".to_string(), "cd".to_string())]),
+                published_at: now,
+            }],
+        }
+    }
+
+    fn assert_round_trip(backend: &dyn PersistenceBackend) {
+        let snapshot = sample_snapshot();
+        snapshot.import(backend).unwrap();
+
+        let source = MemoryBackend::new();
+        assert_eq!(migrate(backend, &source).unwrap(), snapshot.record_count());
+        assert_eq!(source.list_ledger().unwrap(), snapshot.ledger);
+        assert_eq!(source.list_sessions().unwrap(), snapshot.sessions);
+        assert_eq!(source.list_batch_jobs().unwrap(), snapshot.batch_jobs);
+        assert_eq!(
+            source.get_context_turns(snapshot.sessions[0].id).unwrap(),
+            snapshot.context_turns
+        );
+        assert_eq!(source.list_audit().unwrap(), snapshot.audit);
+        assert_eq!(source.list_escrow().unwrap(), snapshot.escrow);
+        assert_eq!(source.list_dead_letters().unwrap(), snapshot.dead_letters);
+        let dead_letter = snapshot.dead_letters[0].id;
+        assert!(backend.get_dead_letter(dead_letter).unwrap().is_some());
+        backend.delete_dead_letter(dead_letter).unwrap();
+        assert!(backend.get_dead_letter(dead_letter).unwrap().is_none());
+        assert_eq!(source.list_feature_flags().unwrap(), snapshot.feature_flags);
+        backend.delete_feature_flag("prompt_packing").unwrap();
+        assert!(backend.list_feature_flags().unwrap().is_empty());
+        assert_eq!(
+            source.list_config_fingerprints().unwrap(),
+            snapshot.config_fingerprints
+        );
+        backend.delete_config_fingerprint("fhe-proxy-0").unwrap();
+        assert!(backend.list_config_fingerprints().unwrap().is_empty());
+        let billed_at = snapshot.billing[0].recorded_at;
+        assert_eq!(
+            source.list_billing(billed_at, billed_at + 1).unwrap(),
+            snapshot.billing
+        );
+        assert!(backend.list_billing(0, billed_at).unwrap().is_empty());
+        assert!(backend.get_idempotent("live").unwrap().is_some());
+        assert!(backend.get_idempotent("expired").unwrap().is_none());
+        assert_eq!(
+            backend.recent_audit(1).unwrap()[0].action,
+            "attestation.rotate"
+        );
+
+        let report = backend.compact(Duration::from_secs(90 * 86400)).unwrap();
+        assert_eq!(report.idempotency_removed, 1);
+        assert_eq!(report.audit_removed, 1);
+
+        // Offboarding removes the tenant's records and leaves others alone
+        let now = chrono::Utc::now().timestamp();
+        backend
+            .put_idempotent(&IdempotencyRecord {
+                key: "acme:retry-1".to_string(),
+                response: serde_json::json!({}),
+                created_at: now,
+                expires_at: now + 60,
+            })
+            .unwrap();
+        backend
+            .put_ledger_entry(&PrivacyLedgerEntry {
+                user_id: "acme:bob".to_string(),
+                ..snapshot.ledger[0].clone()
+            })
+            .unwrap();
+        let purged = backend
+            .purge_tenant("acme", &[snapshot.sessions[0].id])
+            .unwrap();
+        assert_eq!(
+            purged,
+            TenantPurgeReport {
+                sessions: 1,
+                escrow: 1,
+                idempotency: 1,
+                privacy_ledger: 1,
+                batch_jobs: 1,
+                dead_letters: 0,
+                context_turns: 1,
+                billing: 1,
+            }
+        );
+        assert!(backend.list_sessions().unwrap().is_empty());
+        assert!(backend.list_billing(0, i64::MAX).unwrap().is_empty());
+        assert_eq!(backend.list_ledger().unwrap(), snapshot.ledger);
+        assert!(backend.get_idempotent("live").unwrap().is_some());
+        assert_eq!(
+            backend.purge_tenant("acme", &[]).unwrap(),
+            TenantPurgeReport::default()
+        );
+    }
+
+    #[test]
+    fn test_split_brain_session_writes_merge() {
+        let now = chrono::Utc::now().timestamp();
+        let mut created = sample_session(now);
+        created.touch("eu");
+
+        // Both regions saw the creation, then wrote independently during a partition
+        let eu = MemoryBackend::new();
+        let mut eu_copy = created.clone();
+        charge_budget(&mut eu_copy, "eu", 1.0);
+        add_context_ref(&mut eu_copy, "eu", "ctx-eu");
+        eu.put_session(&eu_copy).unwrap();
+
+        let mut us_copy = created.clone();
+        charge_budget(&mut us_copy, "us", 2.0);
+        add_context_ref(&mut us_copy, "us", "ctx-us");
+        us_copy.last_used = now + 5;
+
+        let reconciler = SessionReconciler::new("eu");
+        reconciler.apply(&eu, &[us_copy.clone()], None).unwrap();
+        let merged = eu.get_session(created.id).unwrap().unwrap();
+        assert_eq!(total_budget_spent(&merged), 3.0);
+        assert_eq!(merged.context_refs.len(), 2);
+        assert_eq!(merged.last_used, now + 5);
+        assert_eq!(merged.version.compare(&us_copy.version), Causality::After);
+
+        // Replaying either side is a no-op; a newer remote write fast-forwards
+        reconciler
+            .apply(&eu, &[us_copy.clone(), eu_copy], None)
+            .unwrap();
+        let mut newer = merged.clone();
+        charge_budget(&mut newer, "us", 0.5);
+        reconciler.apply(&eu, &[newer], None).unwrap();
+        let merged = eu.get_session(created.id).unwrap().unwrap();
+        assert_eq!(total_budget_spent(&merged), 3.5);
+
+        // Sessions already expired here are not resurrected
+        let mut expired = sample_session(now - 3600);
+        expired.touch("us");
+        reconciler
+            .apply(&eu, &[expired.clone()], Some(now - 60))
+            .unwrap();
+        assert!(eu.get_session(expired.id).unwrap().is_none());
+
+        let stats = reconciler.stats();
+        assert_eq!(stats.conflicts_merged, 1);
+        assert_eq!(stats.stale_ignored, 2);
+        assert_eq!(stats.fast_forwarded, 1);
+        assert_eq!(stats.expired_skipped, 1);
+    }
+
+    #[test]
+    fn test_memory_backend_round_trip() {
+        assert_round_trip(&MemoryBackend::new());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_backend_round_trip() {
+        let backend = SqliteBackend::open_in_memory().unwrap();
+        assert_round_trip(&backend);
+
+        let status = crate::migrations::status(&backend).unwrap();
+        assert!(status.pending.is_empty());
+        assert_eq!(status.applied.len(), backend.migrations().len());
+        assert!(status.applied.iter().all(|m| m.applied_at.is_some()));
+
+        // The migration lease excludes other holders until released
+        let lease = Duration::from_secs(60);
+        assert!(backend.try_lock_migrations("eu:a", lease).unwrap());
+        assert!(!backend.try_lock_migrations("us:b", lease).unwrap());
+        backend.unlock_migrations("eu:a").unwrap();
+        assert!(backend.try_lock_migrations("us:b", lease).unwrap());
+    }
+}
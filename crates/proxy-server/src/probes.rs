@@ -0,0 +1,433 @@
+//! Synthetic probe traffic
+//!
+//! On a timer the proxy plays a client against itself: for each probed
+//! provider and region it generates keys, encrypts a canned prompt and asks
+//! for a completion over HTTP, so every layer a real request crosses (auth,
+//! admission, routing, the provider call, response encryption) is exercised.
+//! Probe requests carry a marker only this deployment knows, which billing
+//! checks to leave them out of usage. End-to-end latency and success are kept
+//! in a sliding window per target and judged against the configured SLOs.
+
+use crate::config::{ProbeTarget, SyntheticProbesConfig};
+use crate::error::Result;
+use reqwest::Client as HttpClient;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Marks probe traffic; its value is the deployment's marker secret
+pub const PROBE_HEADER: &str = "x-fhe-synthetic-probe";
+
+/// A provider probed through the proxy serving a region
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct ProbeRoute {
+    pub provider: String,
+    pub region: String,
+    pub url: String,
+    pub model: Option<String>,
+}
+
+/// How one probe went
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeOutcome {
+    pub latency: Duration,
+    /// Failed step and cause, `None` on success
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct ProbeHistory {
+    /// (succeeded, latency in ms), newest last
+    samples: VecDeque<(bool, u64)>,
+    attempts: u64,
+    failures: u64,
+    last_run_at: Option<i64>,
+    last_error: Option<String>,
+    slo_met: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeTargetStats {
+    pub provider: String,
+    pub region: String,
+    pub url: String,
+    pub attempts: u64,
+    pub failures: u64,
+    /// Over the SLO window
+    pub success_rate: Option<f64>,
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    pub last_latency_ms: Option<u64>,
+    pub last_run_at: Option<i64>,
+    pub last_error: Option<String>,
+    /// `None` until the window holds `min_samples` probes
+    pub slo_met: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeReport {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    pub success_slo: f64,
+    pub latency_slo_ms: u64,
+    pub targets: Vec<ProbeTargetStats>,
+}
+
+/// A target missing its SLOs over the window
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeBreach {
+    pub route: ProbeRoute,
+    pub success_rate: f64,
+    pub p95_ms: u64,
+}
+
+impl std::fmt::Display for ProbeBreach {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "provider {} in {} ({}): {:.1}% of probes succeeded, p95 {}ms",
+            self.route.provider,
+            self.route.region,
+            self.route.url,
+            self.success_rate * 100.0,
+            self.p95_ms
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct SyntheticProbes {
+    config: SyntheticProbesConfig,
+    marker: String,
+    client: HttpClient,
+    histories: Mutex<BTreeMap<ProbeRoute, ProbeHistory>>,
+    /// Probe key pair per proxy URL, regenerated when the proxy forgets it
+    client_ids: Mutex<HashMap<String, Uuid>>,
+}
+
+impl SyntheticProbes {
+    pub fn new(config: SyntheticProbesConfig) -> Result<Self> {
+        let marker = config
+            .marker_secret
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+        let client = HttpClient::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()?;
+        Ok(Self {
+            config,
+            marker,
+            client,
+            histories: Mutex::new(BTreeMap::new()),
+            client_ids: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &SyntheticProbesConfig {
+        &self.config
+    }
+
+    /// Whether a request was sent by a probe of this deployment
+    pub fn is_probe(&self, headers: &axum::http::HeaderMap) -> bool {
+        headers
+            .get(PROBE_HEADER)
+            .is_some_and(|value| constant_time_eq(value.as_bytes(), self.marker.as_bytes()))
+    }
+
+    /// Routes to probe: the configured targets, or every provider in
+    /// `providers` through this proxy at `local_url` when none are configured
+    pub fn routes(&self, providers: &[String], local_url: &str, region: &str) -> Vec<ProbeRoute> {
+        let resolve = |target: &ProbeTarget| ProbeRoute {
+            provider: target.provider.clone(),
+            region: target.region.clone().unwrap_or_else(|| region.to_string()),
+            url: target
+                .url
+                .as_deref()
+                .unwrap_or(local_url)
+                .trim_end_matches('/')
+                .to_string(),
+            model: target.model.clone(),
+        };
+        if !self.config.targets.is_empty() {
+            return self.config.targets.iter().map(resolve).collect();
+        }
+        let mut routes: Vec<ProbeRoute> = providers
+            .iter()
+            .map(|provider| {
+                resolve(&ProbeTarget {
+                    provider: provider.clone(),
+                    region: None,
+                    url: None,
+                    model: None,
+                })
+            })
+            .collect();
+        routes.sort();
+        routes
+    }
+
+    fn request(&self, route: &ProbeRoute, path: &str) -> reqwest::RequestBuilder {
+        let mut request = self
+            .client
+            .post(format!("{}{}", route.url, path))
+            .header(PROBE_HEADER, &self.marker);
+        // A tenant claimed without its key is refused
+        if let Some(key) = &self.config.api_key {
+            request = request
+                .header("x-api-key", key)
+                .header("x-tenant-id", &self.config.tenant);
+        }
+        request
+    }
+
+    async fn post_json(
+        &self,
+        route: &ProbeRoute,
+        step: &str,
+        path: &str,
+        body: serde_json::Value,
+    ) -> std::result::Result<serde_json::Value, String> {
+        let response = self
+            .request(route, path)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("{}: {}", step, e))?;
+        response
+            .json()
+            .await
+            .map_err(|e| format!("{}: invalid response: {}", step, e))
+    }
+
+    async fn client_id(&self, route: &ProbeRoute) -> std::result::Result<Uuid, String> {
+        if let Some(id) = self.client_ids.lock().unwrap().get(&route.url) {
+            return Ok(*id);
+        }
+        let keys = self
+            .post_json(route, "keys", "/v1/keys/generate", serde_json::json!({}))
+            .await?;
+        let id = keys["client_id"]
+            .as_str()
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| "keys: response has no client_id".to_string())?;
+        self.client_ids
+            .lock()
+            .unwrap()
+            .insert(route.url.clone(), id);
+        Ok(id)
+    }
+
+    async fn run_steps(&self, route: &ProbeRoute) -> std::result::Result<(), String> {
+        let client_id = self.client_id(route).await?;
+        let encrypted = self
+            .post_json(
+                route,
+                "encrypt",
+                "/v1/encrypt",
+                serde_json::json!({ "text": self.config.prompt, "client_id": client_id }),
+            )
+            .await;
+        let ciphertext = match encrypted {
+            Ok(ciphertext) => ciphertext,
+            Err(e) => {
+                // The keys may have expired; generate new ones next round
+                self.client_ids.lock().unwrap().remove(&route.url);
+                return Err(e);
+            }
+        };
+        let mut body = serde_json::json!({
+            "ciphertext_id": ciphertext["ciphertext_id"],
+            "encrypted_data": ciphertext["encrypted_data"],
+            "provider": route.provider,
+        });
+        if let Some(model) = &route.model {
+            body["model"] = serde_json::json!(model);
+        }
+        self.post_json(route, "completion", "/v1/chat/completions", body)
+            .await?;
+        Ok(())
+    }
+
+    /// Send one probe along `route`; latency covers encryption and the
+    /// completion, which is what a client waits for
+    pub async fn probe(&self, route: &ProbeRoute) -> ProbeOutcome {
+        let started = Instant::now();
+        let error = self.run_steps(route).await.err();
+        ProbeOutcome {
+            latency: started.elapsed(),
+            error,
+        }
+    }
+
+    pub fn record(&self, route: &ProbeRoute, outcome: &ProbeOutcome, now: i64) {
+        let mut histories = self.histories.lock().unwrap();
+        let history = histories.entry(route.clone()).or_default();
+        history.attempts += 1;
+        history.last_run_at = Some(now);
+        if outcome.error.is_some() {
+            history.failures += 1;
+            history.last_error = outcome.error.clone();
+        }
+        history
+            .samples
+            .push_back((outcome.error.is_none(), outcome.latency.as_millis() as u64));
+        while history.samples.len() > self.config.window {
+            history.samples.pop_front();
+        }
+    }
+
+    /// Judge every target with enough probes against the SLOs and return
+    /// those missing them
+    pub fn evaluate(&self) -> Vec<ProbeBreach> {
+        let mut histories = self.histories.lock().unwrap();
+        let mut breaches = Vec::new();
+        for (route, history) in histories.iter_mut() {
+            if history.samples.len() < self.config.min_samples {
+                history.slo_met = None;
+                continue;
+            }
+            let success_rate = success_rate(&history.samples).unwrap_or(0.0);
+            let p95_ms = percentile(&history.samples, 95.0).unwrap_or(0);
+            let met =
+                success_rate >= self.config.success_slo && p95_ms <= self.config.latency_slo_ms;
+            history.slo_met = Some(met);
+            if !met {
+                breaches.push(ProbeBreach {
+                    route: route.clone(),
+                    success_rate,
+                    p95_ms,
+                });
+            }
+        }
+        breaches
+    }
+
+    pub fn report(&self) -> ProbeReport {
+        let histories = self.histories.lock().unwrap();
+        let targets = histories
+            .iter()
+            .map(|(route, history)| ProbeTargetStats {
+                provider: route.provider.clone(),
+                region: route.region.clone(),
+                url: route.url.clone(),
+                attempts: history.attempts,
+                failures: history.failures,
+                success_rate: success_rate(&history.samples),
+                p50_ms: percentile(&history.samples, 50.0),
+                p95_ms: percentile(&history.samples, 95.0),
+                p99_ms: percentile(&history.samples, 99.0),
+                last_latency_ms: history.samples.back().map(|(_, latency)| *latency),
+                last_run_at: history.last_run_at,
+                last_error: history.last_error.clone(),
+                slo_met: history.slo_met,
+            })
+            .collect();
+        ProbeReport {
+            enabled: self.config.enabled,
+            interval_seconds: self.config.interval_seconds,
+            success_slo: self.config.success_slo,
+            latency_slo_ms: self.config.latency_slo_ms,
+            targets,
+        }
+    }
+}
+
+/// Compare without exiting early, so timing does not reveal the marker
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn success_rate(samples: &VecDeque<(bool, u64)>) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    let succeeded = samples.iter().filter(|(ok, _)| *ok).count();
+    Some(succeeded as f64 / samples.len() as f64)
+}
+
+/// Nearest-rank percentile of the window's latencies
+fn percentile(samples: &VecDeque<(bool, u64)>, pct: f64) -> Option<u64> {
+    let mut latencies: Vec<u64> = samples.iter().map(|(_, latency)| *latency).collect();
+    if latencies.is_empty() {
+        return None;
+    }
+    latencies.sort_unstable();
+    let rank = ((pct / 100.0) * latencies.len() as f64).ceil() as usize;
+    Some(latencies[rank.clamp(1, latencies.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderMap;
+
+    #[test]
+    fn test_probe_windows_judge_slos_and_marker_is_secret() {
+        let probes = SyntheticProbes::new(SyntheticProbesConfig {
+            enabled: true,
+            window: 4,
+            min_samples: 3,
+            success_slo: 0.75,
+            latency_slo_ms: 100,
+            marker_secret: Some("shared".to_string()),
+            ..SyntheticProbesConfig::default()
+        })
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(PROBE_HEADER, "guess".parse().unwrap());
+        assert!(!probes.is_probe(&headers));
+        headers.insert(PROBE_HEADER, "shared".parse().unwrap());
+        assert!(probes.is_probe(&headers));
+
+        let routes = probes.routes(
+            &["openai".to_string(), "anthropic".to_string()],
+            "http://127.0.0.1:8080/",
+            "eu-west",
+        );
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].provider, "anthropic");
+        assert_eq!(
+            (routes[0].region.as_str(), routes[0].url.as_str()),
+            ("eu-west", "http://127.0.0.1:8080")
+        );
+
+        let ok = |ms| ProbeOutcome {
+            latency: Duration::from_millis(ms),
+            error: None,
+        };
+        let failed = ProbeOutcome {
+            latency: Duration::from_millis(10),
+            error: Some("completion: 502".to_string()),
+        };
+        let route = &routes[1];
+        probes.record(route, &ok(20), 1);
+        probes.record(route, &ok(30), 2);
+        // Too few probes to judge yet
+        assert!(probes.evaluate().is_empty());
+        assert_eq!(probes.report().targets[0].slo_met, None);
+
+        probes.record(route, &ok(40), 3);
+        assert!(probes.evaluate().is_empty());
+        probes.record(route, &failed, 4);
+        probes.record(route, &failed, 5);
+        // The window keeps the last four: two successes out of four
+        let breaches = probes.evaluate();
+        assert_eq!(breaches.len(), 1);
+        assert_eq!(breaches[0].success_rate, 0.5);
+
+        let stats = &probes.report().targets[0];
+        assert_eq!((stats.attempts, stats.failures), (5, 2));
+        assert_eq!(stats.p95_ms, Some(40));
+        assert_eq!(stats.slo_met, Some(false));
+        assert_eq!(stats.last_error.as_deref(), Some("completion: 502"));
+    }
+}
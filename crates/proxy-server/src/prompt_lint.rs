@@ -0,0 +1,227 @@
+//! Checks on the prompt lint reports clients send
+//!
+//! The proxy only ever sees ciphertext, so prompts are linted on the client
+//! with [`lint_prompt`] from `proxy-client` before encryption. For tenants that
+//! require linting the proxy refuses completions whose report is missing, is
+//! for another ciphertext or policy version, is stale or inconsistent, or has
+//! findings, and keeps per-tenant counts of each outcome and rule.
+
+use crate::config::PromptLintConfig;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+pub use proxy_client::lint::{
+    ciphertext_digest, lint_prompt, LintFinding, LintPolicy, LintReport, LintRule, LINT_PROTOCOL,
+};
+
+/// Clock skew tolerated on `linted_at`
+const MAX_FUTURE_SKEW_SECONDS: i64 = 60;
+
+/// Why a completion's lint report was refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintRejection {
+    Missing,
+    Invalid(String),
+    Failed(Vec<LintRule>),
+}
+
+impl std::fmt::Display for LintRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintRejection::Missing => write!(f, "lint report missing"),
+            LintRejection::Invalid(reason) => write!(f, "invalid lint report: {}", reason),
+            LintRejection::Failed(rules) => write!(f, "prompt failed lint rules {:?}", rules),
+        }
+    }
+}
+
+impl From<&PromptLintConfig> for LintPolicy {
+    fn from(config: &PromptLintConfig) -> Self {
+        LintPolicy::new(
+            config.max_chars,
+            config.forbidden_placeholders.clone(),
+            config.reject_control_characters,
+            config.reject_invisible_characters,
+            config.max_report_age_seconds,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TenantLintStats {
+    pub accepted: u64,
+    pub missing: u64,
+    pub invalid: u64,
+    /// Valid reports with findings
+    pub failed: u64,
+    /// Reports naming each rule, valid or not
+    pub findings: BTreeMap<LintRule, u64>,
+    pub avg_chars: f64,
+    #[serde(skip)]
+    chars_total: u64,
+    #[serde(skip)]
+    reports: u64,
+}
+
+/// Checks lint reports against the configured policy and counts the results
+#[derive(Debug)]
+pub struct PromptLinter {
+    policy: LintPolicy,
+    stats: Mutex<BTreeMap<String, TenantLintStats>>,
+}
+
+impl PromptLinter {
+    pub fn new(config: &PromptLintConfig) -> Self {
+        Self {
+            policy: LintPolicy::from(config),
+            stats: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn policy(&self) -> &LintPolicy {
+        &self.policy
+    }
+
+    /// Check the report sent with `encrypted_data` and count the outcome
+    /// under `tenant`
+    pub fn check(
+        &self,
+        tenant: &str,
+        report: Option<&LintReport>,
+        encrypted_data: &str,
+        now: i64,
+    ) -> std::result::Result<(), LintRejection> {
+        let result = self.verify(report, encrypted_data, now);
+        let mut stats = self.stats.lock().unwrap();
+        let tenant_stats = stats.entry(tenant.to_string()).or_default();
+        match &result {
+            Ok(()) => tenant_stats.accepted += 1,
+            Err(LintRejection::Missing) => tenant_stats.missing += 1,
+            Err(LintRejection::Invalid(_)) => tenant_stats.invalid += 1,
+            Err(LintRejection::Failed(_)) => tenant_stats.failed += 1,
+        }
+        if let Some(report) = report {
+            for finding in &report.findings {
+                *tenant_stats.findings.entry(finding.rule).or_default() += 1;
+            }
+            tenant_stats.reports += 1;
+            tenant_stats.chars_total += report.chars as u64;
+            tenant_stats.avg_chars = tenant_stats.chars_total as f64 / tenant_stats.reports as f64;
+        }
+        result
+    }
+
+    fn verify(
+        &self,
+        report: Option<&LintReport>,
+        encrypted_data: &str,
+        now: i64,
+    ) -> std::result::Result<(), LintRejection> {
+        let report = report.ok_or(LintRejection::Missing)?;
+        let invalid = |reason: &str| Err(LintRejection::Invalid(reason.to_string()));
+        if report.protocol != LINT_PROTOCOL {
+            return invalid("unsupported protocol");
+        }
+        if report.policy_version != self.policy.version {
+            return invalid("linted against another policy version");
+        }
+        if report.ciphertext_sha256 != ciphertext_digest(encrypted_data) {
+            return invalid("report is for another ciphertext");
+        }
+        let age = now - report.linted_at;
+        if age > self.policy.max_report_age_seconds as i64 || age < -MAX_FUTURE_SKEW_SECONDS {
+            return invalid("report is stale");
+        }
+        // Findings must agree with what the report claims about the prompt
+        let claims_length = report
+            .findings
+            .iter()
+            .any(|finding| finding.rule == LintRule::Length);
+        if claims_length != (report.chars > self.policy.max_chars) {
+            return invalid("length finding disagrees with the character count");
+        }
+        let mut rules: Vec<LintRule> = report.findings.iter().map(|f| f.rule).collect();
+        rules.sort();
+        rules.dedup();
+        if rules.len() != report.findings.len() || report.findings.iter().any(|f| f.count == 0) {
+            return invalid("findings must name each rule once with a count");
+        }
+        if !report.passed() {
+            return Err(LintRejection::Failed(rules));
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> BTreeMap<String, TenantLintStats> {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_bind_to_ciphertext_and_policy() {
+        let linter = PromptLinter::new(&PromptLintConfig {
+            max_chars: 40,
+            ..PromptLintConfig::default()
+        });
+        let policy = linter.policy().clone();
+        let now = chrono::Utc::now().timestamp();
+
+        let clean = lint_prompt("Summarize the attached notes.", &policy, "ct-1");
+        assert!(clean.passed());
+        assert_eq!(linter.check("acme", Some(&clean), "ct-1", now), Ok(()));
+
+        // Replayed against another ciphertext, or missing altogether
+        assert!(matches!(
+            linter.check("acme", Some(&clean), "ct-2", now),
+            Err(LintRejection::Invalid(_))
+        ));
+        assert_eq!(
+            linter.check("acme", None, "ct-1", now),
+            Err(LintRejection::Missing)
+        );
+
+        let dirty = lint_prompt("Dear {{name}},\u{202E} hidden\u{0007}", &policy, "ct-3");
+        let rules: Vec<LintRule> = dirty.findings.iter().map(|f| f.rule).collect();
+        assert_eq!(
+            rules,
+            vec![
+                LintRule::ForbiddenPlaceholder,
+                LintRule::ControlCharacter,
+                LintRule::InvisibleCharacter
+            ]
+        );
+        assert!(matches!(
+            linter.check("acme", Some(&dirty), "ct-3", now),
+            Err(LintRejection::Failed(_))
+        ));
+
+        // A client that drops its findings is caught by the length cross-check
+        let mut long = lint_prompt(&"x".repeat(50), &policy, "ct-4");
+        long.findings.clear();
+        assert!(matches!(
+            linter.check("acme", Some(&long), "ct-4", now),
+            Err(LintRejection::Invalid(_))
+        ));
+
+        // Reports made under another policy or too long ago are refused
+        let other_policy = LintPolicy::from(&PromptLintConfig::default());
+        let outdated = lint_prompt("Hello", &other_policy, "ct-5");
+        assert!(linter.check("acme", Some(&outdated), "ct-5", now).is_err());
+        let stale = lint_prompt("Hello", &policy, "ct-6");
+        assert!(linter
+            .check("acme", Some(&stale), "ct-6", now + 301)
+            .is_err());
+
+        let stats = &linter.stats()["acme"];
+        assert_eq!(
+            (stats.accepted, stats.missing, stats.invalid, stats.failed),
+            (1, 1, 4, 1)
+        );
+        assert_eq!(stats.findings[&LintRule::ForbiddenPlaceholder], 1);
+    }
+}
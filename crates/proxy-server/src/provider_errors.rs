@@ -0,0 +1,311 @@
+//! Classification of provider error responses
+//!
+//! Status codes alone conflate very different failures: a 429 may be a
+//! per-minute rate limit worth waiting out or an exhausted monthly quota that
+//! never clears, and a 400 may be a malformed request or a content policy
+//! refusal. Error bodies are parsed per provider dialect into a normalized
+//! taxonomy, and each class decides whether a call is retried, moved to
+//! another provider or failed outright.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderErrorClass {
+    RateLimited,
+    QuotaExhausted,
+    Overloaded,
+    Transient,
+    Timeout,
+    ContentPolicy,
+    ContextLength,
+    InvalidRequest,
+    Authentication,
+    ModelNotFound,
+    Unknown,
+}
+
+/// What to do after a failed provider call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryAction {
+    /// Back off and call the same provider again
+    Retry,
+    /// Give up on this provider and try the next one
+    Fallback,
+    /// No provider will accept the request as sent
+    Fail,
+}
+
+impl ProviderErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderErrorClass::RateLimited => "rate_limited",
+            ProviderErrorClass::QuotaExhausted => "quota_exhausted",
+            ProviderErrorClass::Overloaded => "overloaded",
+            ProviderErrorClass::Transient => "transient",
+            ProviderErrorClass::Timeout => "timeout",
+            ProviderErrorClass::ContentPolicy => "content_policy",
+            ProviderErrorClass::ContextLength => "context_length",
+            ProviderErrorClass::InvalidRequest => "invalid_request",
+            ProviderErrorClass::Authentication => "authentication",
+            ProviderErrorClass::ModelNotFound => "model_not_found",
+            ProviderErrorClass::Unknown => "unknown",
+        }
+    }
+
+    pub fn action(&self) -> RetryAction {
+        match self {
+            ProviderErrorClass::RateLimited | ProviderErrorClass::Transient => RetryAction::Retry,
+            ProviderErrorClass::QuotaExhausted
+            | ProviderErrorClass::Overloaded
+            | ProviderErrorClass::Timeout
+            | ProviderErrorClass::Authentication
+            | ProviderErrorClass::ModelNotFound
+            | ProviderErrorClass::Unknown => RetryAction::Fallback,
+            ProviderErrorClass::ContentPolicy
+            | ProviderErrorClass::ContextLength
+            | ProviderErrorClass::InvalidRequest => RetryAction::Fail,
+        }
+    }
+}
+
+impl std::fmt::Display for ProviderErrorClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error body layout spoken by a provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderDialect {
+    /// `{"error": {"message", "type", "code"}}`
+    OpenAi,
+    /// `{"type": "error", "error": {"type", "message"}}`
+    Anthropic,
+    /// Anything else; both layouts are tried, then the message text
+    Generic,
+}
+
+impl ProviderDialect {
+    pub fn for_provider(provider: &str) -> Self {
+        match provider {
+            "openai" => ProviderDialect::OpenAi,
+            "anthropic" => ProviderDialect::Anthropic,
+            url if url.contains("api.openai.com") => ProviderDialect::OpenAi,
+            url if url.contains("api.anthropic.com") => ProviderDialect::Anthropic,
+            _ => ProviderDialect::Generic,
+        }
+    }
+}
+
+/// A provider call that came back with an error response
+#[derive(Debug)]
+pub struct ProviderFailure {
+    pub status: u16,
+    pub class: ProviderErrorClass,
+    /// From the `Retry-After` header, when the provider sent one
+    pub retry_after: Option<Duration>,
+    pub message: String,
+}
+
+/// Classify an error response from its status and body
+pub fn classify(dialect: ProviderDialect, status: u16, body: &[u8]) -> ProviderErrorClass {
+    let parsed: Option<serde_json::Value> = serde_json::from_slice(body).ok();
+    let error = parsed
+        .as_ref()
+        .map(|value| value.get("error").unwrap_or(value));
+    let field = |name: &str| {
+        error
+            .and_then(|error| error.get(name))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    };
+    let (kind, code, message) = (field("type"), field("code"), field("message"));
+    let message = if message.is_empty() && parsed.is_none() {
+        String::from_utf8_lossy(body).to_ascii_lowercase()
+    } else {
+        message
+    };
+
+    let by_dialect = match dialect {
+        ProviderDialect::OpenAi => openai_class(&kind, &code),
+        ProviderDialect::Anthropic => anthropic_class(&kind, &message),
+        ProviderDialect::Generic => {
+            openai_class(&kind, &code).or_else(|| anthropic_class(&kind, &message))
+        }
+    };
+    by_dialect
+        .or_else(|| message_class(&message))
+        .unwrap_or_else(|| status_class(status))
+}
+
+fn openai_class(kind: &str, code: &str) -> Option<ProviderErrorClass> {
+    Some(match (kind, code) {
+        (_, "insufficient_quota") | ("insufficient_quota", _) => ProviderErrorClass::QuotaExhausted,
+        (_, "rate_limit_exceeded") => ProviderErrorClass::RateLimited,
+        (_, "context_length_exceeded") => ProviderErrorClass::ContextLength,
+        (_, "content_policy_violation" | "content_filter") => ProviderErrorClass::ContentPolicy,
+        (_, "invalid_api_key") | ("authentication_error", _) => ProviderErrorClass::Authentication,
+        (_, "model_not_found") => ProviderErrorClass::ModelNotFound,
+        ("server_error", _) => ProviderErrorClass::Transient,
+        _ => return None,
+    })
+}
+
+fn anthropic_class(kind: &str, message: &str) -> Option<ProviderErrorClass> {
+    Some(match kind {
+        "rate_limit_error" => ProviderErrorClass::RateLimited,
+        "overloaded_error" => ProviderErrorClass::Overloaded,
+        "api_error" => ProviderErrorClass::Transient,
+        "authentication_error" | "permission_error" => ProviderErrorClass::Authentication,
+        "not_found_error" => ProviderErrorClass::ModelNotFound,
+        "request_too_large" => ProviderErrorClass::ContextLength,
+        "invalid_request_error" if message.contains("prompt is too long") => {
+            ProviderErrorClass::ContextLength
+        }
+        "invalid_request_error" => ProviderErrorClass::InvalidRequest,
+        _ => return None,
+    })
+}
+
+/// Last resort for providers whose bodies carry only a message
+fn message_class(message: &str) -> Option<ProviderErrorClass> {
+    const RULES: &[(&[&str], ProviderErrorClass)] = &[
+        (&["quota", "billing"], ProviderErrorClass::QuotaExhausted),
+        (
+            &["rate limit", "too many requests"],
+            ProviderErrorClass::RateLimited,
+        ),
+        (
+            &["content policy", "safety", "moderation"],
+            ProviderErrorClass::ContentPolicy,
+        ),
+        (
+            &["context length", "too many tokens", "maximum context"],
+            ProviderErrorClass::ContextLength,
+        ),
+        (&["overloaded", "capacity"], ProviderErrorClass::Overloaded),
+    ];
+    RULES
+        .iter()
+        .find(|(needles, _)| needles.iter().any(|needle| message.contains(needle)))
+        .map(|(_, class)| *class)
+}
+
+fn status_class(status: u16) -> ProviderErrorClass {
+    match status {
+        401 | 403 => ProviderErrorClass::Authentication,
+        404 => ProviderErrorClass::ModelNotFound,
+        408 | 504 => ProviderErrorClass::Timeout,
+        413 => ProviderErrorClass::ContextLength,
+        429 => ProviderErrorClass::RateLimited,
+        400 | 422 => ProviderErrorClass::InvalidRequest,
+        503 | 529 => ProviderErrorClass::Overloaded,
+        500..=599 => ProviderErrorClass::Transient,
+        _ => ProviderErrorClass::Unknown,
+    }
+}
+
+/// Failed calls of one provider, by class
+#[derive(Debug, Default)]
+pub struct ProviderErrorCounters {
+    counts: Mutex<HashMap<ProviderErrorClass, u64>>,
+}
+
+impl ProviderErrorCounters {
+    pub fn record(&self, class: ProviderErrorClass) {
+        *self.counts.lock().unwrap().entry(class).or_default() += 1;
+    }
+
+    pub fn snapshot(&self) -> HashMap<&'static str, u64> {
+        self.counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(class, count)| (class.as_str(), *count))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_error_bodies() {
+        let openai =
+            |status, body: &str| classify(ProviderDialect::OpenAi, status, body.as_bytes());
+        let anthropic =
+            |status, body: &str| classify(ProviderDialect::Anthropic, status, body.as_bytes());
+
+        // The same 429 is a rate limit or an exhausted quota depending on the body
+        assert_eq!(
+            openai(
+                429,
+                r#"{"error":{"message":"Rate limit reached","type":"requests","code":"rate_limit_exceeded"}}"#
+            ),
+            ProviderErrorClass::RateLimited
+        );
+        assert_eq!(
+            openai(
+                429,
+                r#"{"error":{"message":"You exceeded your current quota","type":"insufficient_quota","code":"insufficient_quota"}}"#
+            ),
+            ProviderErrorClass::QuotaExhausted
+        );
+        assert_eq!(
+            openai(
+                400,
+                r#"{"error":{"type":"invalid_request_error","code":"context_length_exceeded"}}"#
+            ),
+            ProviderErrorClass::ContextLength
+        );
+        assert_eq!(
+            anthropic(
+                529,
+                r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#
+            ),
+            ProviderErrorClass::Overloaded
+        );
+        assert_eq!(
+            anthropic(
+                400,
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens"}}"#
+            ),
+            ProviderErrorClass::ContextLength
+        );
+
+        // Unknown layouts fall back to the message, then the status code
+        let generic =
+            |status, body: &str| classify(ProviderDialect::Generic, status, body.as_bytes());
+        assert_eq!(
+            generic(400, r#"{"message":"Blocked by safety filters"}"#),
+            ProviderErrorClass::ContentPolicy
+        );
+        assert_eq!(
+            generic(502, "<html>Bad Gateway</html>"),
+            ProviderErrorClass::Transient
+        );
+        assert_eq!(generic(418, ""), ProviderErrorClass::Unknown);
+
+        assert_eq!(ProviderErrorClass::RateLimited.action(), RetryAction::Retry);
+        assert_eq!(
+            ProviderErrorClass::QuotaExhausted.action(),
+            RetryAction::Fallback
+        );
+        assert_eq!(
+            ProviderErrorClass::ContentPolicy.action(),
+            RetryAction::Fail
+        );
+
+        let counters = ProviderErrorCounters::default();
+        counters.record(ProviderErrorClass::RateLimited);
+        counters.record(ProviderErrorClass::RateLimited);
+        assert_eq!(counters.snapshot()["rate_limited"], 2);
+    }
+}
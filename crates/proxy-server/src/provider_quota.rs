@@ -0,0 +1,352 @@
+//! Provider quota tracking and preemptive throttling
+//!
+//! Each provider key has request and token budgets that reset on the
+//! provider's schedule. Every response's rate-limit headers (OpenAI
+//! `x-ratelimit-*`, Anthropic `anthropic-ratelimit-*`) reset the local view of
+//! both budgets; between responses, calls are charged against it locally. A
+//! call that would dip into the configured reserve waits for the budget to
+//! reset, or fails over to the next provider when that is too far off, so the
+//! provider never has to refuse it. Until a provider has sent its limits,
+//! nothing is throttled.
+
+use crate::config::ProviderQuotaConfig;
+use crate::error::{Error, Result};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// One budget as reported by a provider
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BudgetReading {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_after: Duration,
+}
+
+/// The budgets a response's rate-limit headers describe
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimitReading {
+    pub requests: Option<BudgetReading>,
+    pub tokens: Option<BudgetReading>,
+}
+
+impl RateLimitReading {
+    /// Read OpenAI and Anthropic style rate-limit headers
+    pub fn from_headers(headers: &HeaderMap, now: chrono::DateTime<chrono::Utc>) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let openai = |kind: &str| {
+            Some(BudgetReading {
+                limit: header(&format!("x-ratelimit-limit-{}", kind))?
+                    .parse()
+                    .ok()?,
+                remaining: header(&format!("x-ratelimit-remaining-{}", kind))?
+                    .parse()
+                    .ok()?,
+                reset_after: header(&format!("x-ratelimit-reset-{}", kind))
+                    .and_then(parse_reset_duration)
+                    .unwrap_or_default(),
+            })
+        };
+        let anthropic = |kind: &str| {
+            Some(BudgetReading {
+                limit: header(&format!("anthropic-ratelimit-{}-limit", kind))?
+                    .parse()
+                    .ok()?,
+                remaining: header(&format!("anthropic-ratelimit-{}-remaining", kind))?
+                    .parse()
+                    .ok()?,
+                reset_after: header(&format!("anthropic-ratelimit-{}-reset", kind))
+                    .and_then(|v| chrono::DateTime::parse_from_rfc3339(v).ok())
+                    .and_then(|at| (at.with_timezone(&chrono::Utc) - now).to_std().ok())
+                    .unwrap_or_default(),
+            })
+        };
+        Self {
+            requests: openai("requests").or_else(|| anthropic("requests")),
+            tokens: openai("tokens").or_else(|| anthropic("tokens")),
+        }
+    }
+}
+
+/// Parse OpenAI's reset durations, such as `20ms`, `1.5s` or `6m0s`
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|&i| i > 0)?;
+        let amount: f64 = rest[..split].parse().ok()?;
+        rest = &rest[split..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        total += amount
+            * match &rest[..unit_len] {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return None,
+            };
+        rest = &rest[unit_len..];
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Budget {
+    limit: u64,
+    remaining: u64,
+    /// `None` once the reported reset has passed and the budget was refilled;
+    /// the next response says when it resets again
+    resets_at: Option<Instant>,
+}
+
+impl Budget {
+    fn from_reading(reading: BudgetReading, now: Instant) -> Self {
+        Self {
+            limit: reading.limit,
+            remaining: reading.remaining.min(reading.limit),
+            resets_at: Some(now + reading.reset_after),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if self.resets_at.is_some_and(|at| now >= at) {
+            self.remaining = self.limit;
+            self.resets_at = None;
+        }
+    }
+
+    /// How long until `cost` fits above the reserve, or `None` if it fits now
+    /// (or there is no known reset to wait for)
+    fn wait_for(&self, cost: u64, reserve_fraction: f64, now: Instant) -> Option<Duration> {
+        let resets_at = self.resets_at.filter(|at| now < *at)?;
+        let reserve = (self.limit as f64 * reserve_fraction).ceil() as u64;
+        (self.remaining < cost.saturating_add(reserve)).then(|| resets_at - now)
+    }
+
+    fn gauge(&self, now: Instant) -> BudgetGauge {
+        BudgetGauge {
+            limit: self.limit,
+            remaining: self.remaining,
+            reset_in_ms: self
+                .resets_at
+                .map_or(0, |at| at.saturating_duration_since(now).as_millis() as u64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BudgetGauge {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_in_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaStats {
+    pub enabled: bool,
+    /// `None` until the provider has reported the budget
+    pub requests: Option<BudgetGauge>,
+    pub tokens: Option<BudgetGauge>,
+    /// Calls held back until a budget reset
+    pub throttled: u64,
+    pub throttled_ms: u64,
+    /// Calls sent elsewhere because the reset was too far off
+    pub rejected: u64,
+}
+
+#[derive(Debug, Default)]
+struct Budgets {
+    requests: Option<Budget>,
+    tokens: Option<Budget>,
+}
+
+impl Budgets {
+    fn refill(&mut self, now: Instant) {
+        for budget in [&mut self.requests, &mut self.tokens].into_iter().flatten() {
+            budget.refill(now);
+        }
+    }
+
+    /// Take one request and `tokens` tokens from whichever budgets are known
+    fn charge(&mut self, tokens: u64) {
+        for (budget, cost) in [(&mut self.requests, 1), (&mut self.tokens, tokens)] {
+            if let Some(budget) = budget {
+                budget.remaining = budget.remaining.saturating_sub(cost);
+            }
+        }
+    }
+}
+
+/// Budgets for one provider key
+#[derive(Debug)]
+pub struct ProviderQuota {
+    config: ProviderQuotaConfig,
+    budgets: Mutex<Budgets>,
+    throttled: AtomicU64,
+    throttled_ms: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl ProviderQuota {
+    pub fn new(config: ProviderQuotaConfig) -> Self {
+        Self {
+            config,
+            budgets: Mutex::new(Budgets::default()),
+            throttled: AtomicU64::new(0),
+            throttled_ms: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn config(&self) -> &ProviderQuotaConfig {
+        &self.config
+    }
+
+    /// Replace the local budgets with what the provider reported
+    pub fn observe(&self, reading: RateLimitReading) {
+        if !self.config.enabled {
+            return;
+        }
+        let now = Instant::now();
+        let mut budgets = self.budgets.lock().unwrap();
+        if let Some(requests) = reading.requests {
+            budgets.requests = Some(Budget::from_reading(requests, now));
+        }
+        if let Some(tokens) = reading.tokens {
+            budgets.tokens = Some(Budget::from_reading(tokens, now));
+        }
+    }
+
+    /// Charge one call of `tokens` against the budgets, first waiting for a
+    /// reset if it would dip into the reserve. Returns how long it waited.
+    pub async fn acquire(&self, provider: &str, tokens: u64) -> Result<Duration> {
+        if !self.config.enabled {
+            return Ok(Duration::ZERO);
+        }
+        let max_queue = Duration::from_millis(self.config.max_queue_ms);
+        let mut waited = Duration::ZERO;
+        loop {
+            let wait = {
+                let now = Instant::now();
+                let mut budgets = self.budgets.lock().unwrap();
+                budgets.refill(now);
+                let reserve = self.config.reserve_fraction;
+                let wait = [(&budgets.requests, 1), (&budgets.tokens, tokens)]
+                    .into_iter()
+                    .filter_map(|(budget, cost)| budget.as_ref()?.wait_for(cost, reserve, now))
+                    .max();
+                if wait.is_none() {
+                    budgets.charge(tokens);
+                }
+                wait
+            };
+            let Some(wait) = wait else {
+                if !waited.is_zero() {
+                    self.throttled.fetch_add(1, Ordering::Relaxed);
+                    self.throttled_ms
+                        .fetch_add(waited.as_millis() as u64, Ordering::Relaxed);
+                }
+                return Ok(waited);
+            };
+            if waited + wait > max_queue {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(Error::RateLimit(format!(
+                    "{} quota is nearly exhausted and resets in {}ms",
+                    provider,
+                    wait.as_millis()
+                )));
+            }
+            log::debug!(
+                "Holding {} call for {}ms until its quota resets",
+                provider,
+                wait.as_millis()
+            );
+            tokio::time::sleep(wait).await;
+            waited += wait;
+        }
+    }
+
+    pub fn stats(&self) -> QuotaStats {
+        let now = Instant::now();
+        let mut budgets = self.budgets.lock().unwrap();
+        budgets.refill(now);
+        QuotaStats {
+            enabled: self.config.enabled,
+            requests: budgets.requests.map(|b| b.gauge(now)),
+            tokens: budgets.tokens.map(|b| b.gauge(now)),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            throttled_ms: self.throttled_ms.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[tokio::test]
+    async fn test_quota_follows_headers_and_throttles_near_exhaustion() {
+        let now = chrono::Utc::now();
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("x-ratelimit-limit-requests", "100"),
+            ("x-ratelimit-remaining-requests", "3"),
+            ("x-ratelimit-reset-requests", "50ms"),
+            ("x-ratelimit-limit-tokens", "10000"),
+            ("x-ratelimit-remaining-tokens", "9000"),
+            ("x-ratelimit-reset-tokens", "6m0s"),
+        ] {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+        let openai = RateLimitReading::from_headers(&headers, now);
+        assert_eq!(openai.requests.unwrap().remaining, 3);
+        assert_eq!(openai.tokens.unwrap().reset_after, Duration::from_secs(360));
+
+        let mut headers = HeaderMap::new();
+        let reset = (now + chrono::Duration::seconds(30)).to_rfc3339();
+        headers.insert(
+            "anthropic-ratelimit-tokens-limit",
+            HeaderValue::from_static("500"),
+        );
+        headers.insert(
+            "anthropic-ratelimit-tokens-remaining",
+            HeaderValue::from_static("20"),
+        );
+        headers.insert(
+            "anthropic-ratelimit-tokens-reset",
+            HeaderValue::from_str(&reset).unwrap(),
+        );
+        let anthropic = RateLimitReading::from_headers(&headers, now);
+        assert!(anthropic.requests.is_none());
+        assert_eq!(anthropic.tokens.unwrap().remaining, 20);
+
+        let quota = ProviderQuota::new(ProviderQuotaConfig {
+            reserve_fraction: 0.02,
+            max_queue_ms: 500,
+            ..ProviderQuotaConfig::default()
+        });
+        // Nothing is known yet, so nothing is held back
+        assert_eq!(quota.acquire("openai", 100).await.unwrap(), Duration::ZERO);
+
+        // 3 requests left with 2 in reserve: one goes now, the next waits for the reset
+        quota.observe(openai);
+        assert_eq!(quota.acquire("openai", 100).await.unwrap(), Duration::ZERO);
+        assert!(quota.acquire("openai", 100).await.unwrap() > Duration::ZERO);
+        assert_eq!(quota.stats().requests.unwrap().remaining, 99);
+        assert_eq!(quota.stats().tokens.unwrap().remaining, 8800);
+
+        // A token reset 30s out is beyond max_queue_ms: fail over instead
+        quota.observe(anthropic);
+        assert!(quota.acquire("anthropic", 100).await.is_err());
+        let stats = quota.stats();
+        assert_eq!((stats.throttled, stats.rejected), (1, 1));
+    }
+}
@@ -0,0 +1,2171 @@
+//! Proxy server implementation
+
+mod admin;
+mod aggregations;
+mod approvals;
+mod batch;
+mod billing;
+mod chunks;
+mod ciphertexts;
+mod completions;
+mod conversations;
+mod dead_letters;
+mod documents;
+mod drills;
+mod escrow;
+mod experiments;
+mod federation;
+mod flags;
+mod health;
+mod honeytokens;
+mod identity;
+mod layers;
+mod packing;
+mod privacy;
+mod providers;
+mod quality;
+mod redaction_policies;
+mod response_cache;
+mod runbooks;
+mod sandbox;
+mod sessions;
+mod signing;
+mod standby;
+mod tenants;
+
+pub use crate::performance_stats::PerformanceQuery;
+pub use admin::{AuditQuery, BackfillRequest, QueueProjectionQuery, ShedPolicyModeRequest};
+pub use aggregations::{AggregateDecryptRequest, AggregationRequest};
+pub use approvals::ApprovalDecision;
+pub use batch::{BatchJobRequest, BatchRetryItem, BatchRetryRequest};
+pub use billing::{BillingQuery, SpendingCapOverrideRequest};
+pub use chunks::{ChunkRangeQuery, ChunkRef, DecryptChunksRequest, ResponseChunkStore};
+pub use ciphertexts::{EncryptRequest, EncryptResponse, GenerateKeysRequest};
+pub use completions::ProcessRequest;
+pub use conversations::{ConversationTurnRequest, ConversationTurnsQuery};
+pub use documents::{
+    DocumentChunkResult, DocumentJob, DocumentJobStatus, DocumentJobs, DocumentRequest,
+};
+pub use escrow::EscrowShareSubmission;
+pub use experiments::ExperimentRatingRequest;
+pub use federation::FederatedForwardRequest;
+pub use packing::PromptPacker;
+pub use privacy::TopUpRequest;
+pub use providers::{
+    complete_with_fallback, CalibratedTimeout, CallOptions, LlmChoice, LlmMessage, LlmProvider,
+    LlmRequest, LlmResponse, LlmStreamChunk, LlmUsage, ProviderMode, RecordedResponse,
+    ResumeMetrics, ResumeStats, TimeoutCalibrator,
+};
+pub use quality::QualityScoreRequest;
+pub use redaction_policies::{RedactionAckRequest, RedactionPolicyQuery};
+pub use response_cache::{
+    CacheFreshnessStats, CacheInvalidationRequest, CacheRefresh, CachedCompletion, ResponseCache,
+    Revalidation,
+};
+pub use runbooks::StateHistoryQuery;
+pub use sessions::{
+    EvictedSession, EvictionReason, RefreshTokenRequest, SessionManager, SessionStats,
+};
+pub use tenants::OffboardTenantRequest;
+
+use crate::access::{AccessControl, TENANT_HEADER};
+use crate::aggregation::AggregationService;
+use crate::api_versions::ApiVersions;
+use crate::approvals::ApprovalService;
+use crate::billing::{BillingMeter, SpendingCaps};
+use crate::clock::ClockMonitor;
+use crate::config::{Config, EffectiveTenantConfig, StandbyReplication, TenantConfigResolver};
+use crate::config_drift::ConfigDriftMonitor;
+use crate::connection_guard::ConnectionGuard;
+use crate::conversation::{self, ConversationStore};
+use crate::dead_letter::DeadLetterQueue;
+use crate::dependencies::DependencyMonitor;
+use crate::drills::FailoverDrills;
+use crate::error::{Error, Result};
+use crate::escrow::EscrowService;
+use crate::execution::ExecutionDispatcher;
+use crate::experiments::ExperimentRegistry;
+use crate::failure_domains::{self, FailureDomains};
+use crate::feature_flags::FeatureFlags;
+use crate::federation::FederationService;
+use crate::fhe::{Ciphertext, DecryptionDelegation, FheEngine, FheParams, PrecomputedTables};
+use crate::limit_rules::RateLimitRules;
+use crate::metrics_catalog::MetricCatalog;
+use crate::middleware::{
+    KeyPolicyEnforcer, MetricsCollector, PrivacyBudgetPolicy, PrivacyBudgetTracker, RateLimiter,
+    TransformEngine,
+};
+use crate::migrations::{self, MigrationReport, MigrationRunner};
+use crate::mirror::RequestMirror;
+use crate::monitoring::{
+    GeoLatencyHeatmap, MonitoringService, OtlpMetricsExporter, PerformanceProfiler, RunbookEngine,
+    RunbookSignals, SlaMetrics, StateRecorder,
+};
+use crate::noise_trends::NoiseTrendMonitor;
+use crate::offboarding::{TenantOffboarding, OFFBOARDED_AUDIT_ACTION};
+use crate::overflow::OverflowQueue;
+use crate::performance::{CacheConfig, ConnectionPoolShard, EvictionStrategy, PerformanceCache};
+use crate::performance_optimized::MemoryTracker;
+use crate::performance_stats::{PerformanceHistory, PerformanceSubsystem};
+use crate::persistence::{
+    self, AuditRecord, BatchJobStatus, PersistenceBackend, SessionReconciler,
+};
+use crate::probes::SyntheticProbes;
+use crate::prompt_lint::PromptLinter;
+use crate::quality::QualityMonitor;
+use crate::redaction::RedactionPolicyRegistry;
+use crate::renewal::RenewalProtocol;
+use crate::request_journal::RequestJournal;
+use crate::sandbox::SandboxKeys;
+use crate::scaling::{
+    AutoScaler, BatchProcessor, BatchWindowScheduler, CiphertextCache, CircuitBreaker,
+    FheConnectionPool, GuardAction, LoadShedPolicies, PriorityAdmission, QueueProjector,
+    ResourceGuard, ResourceLimits,
+};
+use crate::security::{
+    AttestationService, BuildProvenance, HoneytokenGeneration, Honeytokens, ResponseSigner,
+    HONEYTOKEN_ROTATION_AUDIT_ACTION,
+};
+use crate::siem::{SecurityEvent, SecurityEventKind, SiemExporter};
+use crate::snapshot::EngineSnapshot;
+use crate::standby::{PairDecision, PairRole, StandbyPair};
+use crate::streaming::StreamRegistry;
+use crate::supervisor::TaskSupervisor;
+use crate::systemd;
+use crate::telemetry::TelemetrySampler;
+use crate::trace_sampling::AdaptiveSamplingStrategy;
+use crate::validation::ValidatorChain;
+use crate::workload_tags::WorkloadTags;
+use admin::capture_performance;
+use admin::{apply_domain_drains, check_config_drift, report_domain_transition};
+use axum::middleware::{from_fn, from_fn_with_state};
+use axum::{
+    extract::DefaultBodyLimit,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post, put},
+    Router,
+};
+use batch::run_batch_window;
+use health::{is_ready, probe_dependencies, register_dependencies};
+use honeytokens::rotate_honeytokens;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder as AutoBuilder;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use privacy::flush_privacy_ledger;
+use reqwest::Client as HttpClient;
+use response_cache::refresh_cached_completion;
+use runbooks::{capture_state, execute_runbook_action};
+use sessions::{reconcile_sessions, release_evicted_sessions};
+use standby::{
+    fetch_peer_heartbeat, pull_standby_state, step_down, take_over, witnesses_reachable,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tower::Layer;
+use tower_http::compression::CompressionLayer;
+use uuid::Uuid;
+
+/// Main proxy server state
+#[derive(Debug)]
+pub struct ProxyState {
+    pub config: Config,
+    pub fhe_engine: Arc<RwLock<FheEngine>>,
+    pub decryption: DecryptionDelegation,
+    /// Set while startup warm-up (or snapshot restore) is in progress
+    pub engine_warming: AtomicBool,
+    /// Set while new engine tables are computed in the background
+    pub tables_reloading: AtomicBool,
+    pub session_manager: SessionManager,
+    pub llm_providers: HashMap<String, LlmProvider>,
+    pub ciphertext_cache: RwLock<HashMap<Uuid, Ciphertext>>,
+    pub rate_limiter: RateLimiter,
+    pub metrics: MetricsCollector,
+    pub metric_catalog: MetricCatalog,
+    pub tenant_configs: TenantConfigResolver,
+    pub attestation: AttestationService,
+    /// Present when response signing is enabled
+    pub response_signer: Option<ResponseSigner>,
+    pub redaction_policies: RedactionPolicyRegistry,
+    pub federation: FederationService,
+    pub store: Arc<dyn PersistenceBackend>,
+    /// What the startup migration run did to the store's schema
+    pub migration_report: MigrationReport,
+    pub reconciler: SessionReconciler,
+    pub privacy_tracker: PrivacyBudgetTracker,
+    pub response_chunks: ResponseChunkStore,
+    pub documents: DocumentJobs,
+    pub response_cache: ResponseCache,
+    pub monitoring: MonitoringService,
+    pub profiler: PerformanceProfiler,
+    pub sla_metrics: SlaMetrics,
+    pub geo_latency: GeoLatencyHeatmap,
+    pub siem: SiemExporter,
+    pub mirror: RequestMirror,
+    pub probes: SyntheticProbes,
+    pub telemetry: TelemetrySampler,
+    pub trace_sampler: AdaptiveSamplingStrategy,
+    pub quality: QualityMonitor,
+    pub sandbox: SandboxKeys,
+    pub honeytokens: Honeytokens,
+    pub access: AccessControl,
+    pub dependencies: DependencyMonitor,
+    pub noise_trends: NoiseTrendMonitor,
+    pub config_drift: ConfigDriftMonitor,
+    pub request_journal: RequestJournal,
+    pub systemd: systemd::Notifier,
+    pub standby: StandbyPair,
+    pub prompt_lint: PromptLinter,
+    pub feature_flags: FeatureFlags,
+    pub execution: ExecutionDispatcher,
+    pub experiments: ExperimentRegistry,
+    pub aggregation: AggregationService,
+    pub escrow: EscrowService,
+    pub approvals: ApprovalService,
+    pub connection_guard: Arc<ConnectionGuard>,
+    pub clock: ClockMonitor,
+    pub api_versions: ApiVersions,
+    pub conversations: ConversationStore,
+    pub streams: Arc<StreamRegistry>,
+    pub runbooks: RunbookEngine,
+    pub prompt_packer: PromptPacker,
+    pub state_recorder: StateRecorder,
+    /// Performance snapshots sampled with the state recorder
+    pub performance_history: PerformanceHistory,
+    /// Heap fragmentation, resampled from the global allocator whenever it is read
+    pub memory: MemoryTracker,
+    pub tasks: TaskSupervisor,
+    pub overflow: OverflowQueue,
+    pub otlp_metrics: OtlpMetricsExporter,
+    /// Set by a runbook: key generation, decryption and federation are refused
+    pub lockdown: AtomicBool,
+    /// Provider name -> replacement, set by runbooks during an outage
+    pub provider_failover: RwLock<HashMap<String, String>>,
+    // Scaling components
+    pub fhe_pool: FheConnectionPool,
+    pub failure_domains: FailureDomains,
+    pub auto_scaler: AutoScaler,
+    pub batch_processor: BatchProcessor,
+    pub advanced_cache: CiphertextCache,
+    pub circuit_breaker: CircuitBreaker,
+    pub resource_guard: ResourceGuard,
+    pub admission: PriorityAdmission,
+    pub key_policies: KeyPolicyEnforcer,
+    pub transforms: TransformEngine,
+    pub billing: BillingMeter,
+    pub spending_caps: SpendingCaps,
+    pub offboarding: TenantOffboarding,
+    pub load_shedding: LoadShedPolicies,
+    pub rate_limit_rules: RateLimitRules,
+    pub workload_tags: WorkloadTags,
+    pub drills: FailoverDrills,
+    pub renewal: RenewalProtocol,
+    pub queue_projector: QueueProjector,
+    pub batch_windows: BatchWindowScheduler,
+    pub dead_letters: DeadLetterQueue,
+    pub validators: ValidatorChain,
+    // Performance optimization
+    pub performance_cache: PerformanceCache,
+    pub connection_manager: ConnectionPoolShard,
+}
+
+/// Main proxy server
+pub struct ProxyServer {
+    state: Arc<ProxyState>,
+}
+
+impl ProxyServer {
+    /// Create new proxy server
+    pub fn new(config: Config) -> Result<Self> {
+        let fhe_params = FheParams {
+            poly_modulus_degree: config.encryption.poly_modulus_degree,
+            coeff_modulus_bits: config.encryption.coeff_modulus_bits.clone(),
+            scale_bits: config.encryption.scale_bits,
+            security_level: 128,
+        };
+
+        let fhe_engine = FheEngine::new(fhe_params)?;
+
+        // Initialize LLM providers
+        let provider_mode = ProviderMode::from_config(&config.llm.recording)?;
+        let replaying = matches!(provider_mode, ProviderMode::Replay(_));
+        let max_resumes = if config.llm.resume.enabled {
+            config.llm.max_retries
+        } else {
+            0
+        };
+        let resume_backoff = Duration::from_millis(config.llm.resume.backoff_ms);
+        let provider_timeout = Duration::from_secs(config.llm.timeout_seconds);
+        let mut llm_providers = HashMap::new();
+        // Replay never reaches the network, so providers work without keys
+        let openai_key = config
+            .llm
+            .openai_api_key
+            .clone()
+            .or_else(|| replaying.then(String::new));
+        if let Some(openai_key) = openai_key {
+            llm_providers.insert(
+                "openai".to_string(),
+                LlmProvider::new("openai", openai_key)
+                    .with_mode(provider_mode.clone())
+                    .with_resume(max_resumes, resume_backoff)
+                    .with_error_retry(config.llm.max_retries, config.llm.error_retry.clone())
+                    .with_timeouts(provider_timeout, config.llm.timeout_calibration.clone())
+                    .with_quota(config.llm.quota.clone())
+                    .with_warm_hints(&config.llm.warm_hints, "openai"),
+            );
+        }
+        let anthropic_key = config
+            .llm
+            .anthropic_api_key
+            .clone()
+            .or_else(|| replaying.then(String::new));
+        if let Some(anthropic_key) = anthropic_key {
+            llm_providers.insert(
+                "anthropic".to_string(),
+                LlmProvider::new("anthropic", anthropic_key)
+                    .with_mode(provider_mode.clone())
+                    .with_resume(max_resumes, resume_backoff)
+                    .with_error_retry(config.llm.max_retries, config.llm.error_retry.clone())
+                    .with_timeouts(provider_timeout, config.llm.timeout_calibration.clone())
+                    .with_quota(config.llm.quota.clone())
+                    .with_warm_hints(&config.llm.warm_hints, "anthropic"),
+            );
+        }
+        for server in &config.llm.openai_compatible {
+            llm_providers.insert(
+                server.name.clone(),
+                LlmProvider::openai_compatible(server)
+                    .with_mode(provider_mode.clone())
+                    .with_resume(max_resumes, resume_backoff)
+                    .with_error_retry(config.llm.max_retries, config.llm.error_retry.clone())
+                    .with_timeouts(provider_timeout, config.llm.timeout_calibration.clone())
+                    .with_quota(config.llm.quota.clone())
+                    .with_warm_hints(&config.llm.warm_hints, &server.name),
+            );
+        }
+        if provider_mode != ProviderMode::Live {
+            log::info!("Provider calls running in {:?} mode", provider_mode);
+        }
+
+        // Initialize scaling components
+        let fhe_params_for_pool = FheParams {
+            poly_modulus_degree: config.encryption.poly_modulus_degree,
+            coeff_modulus_bits: config.encryption.coeff_modulus_bits.clone(),
+            scale_bits: config.encryption.scale_bits,
+            security_level: 128,
+        };
+
+        let fhe_pool = FheConnectionPool::new(
+            config.scaling.connection_pool_size,
+            config.scaling.max_concurrent_requests as usize,
+            fhe_params_for_pool.clone(),
+        )?
+        .with_uniform_fingerprints(config.scaling.engine_fingerprints.enforce_uniform_pool);
+
+        let failure_domains = FailureDomains::new(config.scaling.failure_domains.clone());
+        for index in 0..config.scaling.connection_pool_size {
+            failure_domains.register(
+                &failure_domains::engine_member(index),
+                config
+                    .scaling
+                    .failure_domains
+                    .engines
+                    .get(index)
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+        }
+        for name in llm_providers.keys() {
+            failure_domains.register(
+                &failure_domains::provider_member(name),
+                config
+                    .scaling
+                    .failure_domains
+                    .providers
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+        }
+
+        let auto_scaler = AutoScaler::new(
+            config.scaling.target_cpu_utilization,
+            config.scaling.min_instances as usize,
+            config.scaling.max_instances as usize,
+            config.scaling.scale_up_threshold as usize,
+            std::time::Duration::from_secs(config.scaling.cooldown_period_seconds),
+        );
+
+        let batch_processor = BatchProcessor::new(
+            config.performance.batch_size,
+            std::time::Duration::from_millis(config.performance.batch_timeout_ms),
+        );
+
+        let advanced_cache = CiphertextCache::new(
+            (config.performance.cache_size_mb * 1024 * 1024) as usize,
+            std::time::Duration::from_secs(config.performance.cache_ttl_seconds),
+        );
+
+        let circuit_breaker = CircuitBreaker::new(50, 30, std::time::Duration::from_secs(60));
+
+        let performance_cache = PerformanceCache::new(CacheConfig {
+            l1_max_size: (config.performance.cache_size_mb * 1024 * 1024 / 4) as usize,
+            l2_max_size: (config.performance.cache_size_mb * 1024 * 1024 / 2) as usize,
+            hot_max_size: (config.performance.cache_size_mb * 1024 * 1024 / 4) as usize,
+            default_ttl: std::time::Duration::from_secs(config.performance.cache_ttl_seconds),
+            hot_threshold_accesses: 5,
+            eviction_strategy: EvictionStrategy::Adaptive,
+            compression_enabled: config.performance.compression_enabled,
+            encryption_enabled: true,
+        });
+
+        let connection_manager = ConnectionPoolShard {
+            id: 0,
+            engines: Vec::new(),
+            current_load: std::sync::atomic::AtomicUsize::new(0),
+            max_load: config.scaling.max_concurrent_requests as usize,
+            health_score: std::sync::atomic::AtomicU64::new(100),
+        };
+
+        let privacy_policy =
+            PrivacyBudgetPolicy::from_config(&config.privacy.budget_replenishment)?;
+
+        let guard_config = &config.scaling.resource_guard;
+        let resource_guard = ResourceGuard::new(
+            ResourceLimits {
+                max_rss_mb: guard_config.max_rss_mb as f64,
+                max_open_fds: guard_config.max_open_fds,
+                max_cpu_percent: guard_config.max_cpu_percent,
+            },
+            guard_config
+                .actions
+                .iter()
+                .map(|action| GuardAction::parse(action))
+                .collect::<Result<Vec<_>>>()?,
+            std::time::Duration::from_secs(guard_config.sustained_seconds),
+        );
+
+        let store = persistence::open_backend(&config.persistence)?;
+        let migration_report = MigrationRunner::new(
+            config.persistence.migrations.clone(),
+            migrations::replica_holder(&config.persistence.replication.region),
+        )
+        .run(store.as_ref())?;
+
+        let feature_flags = FeatureFlags::new(config.flags.clone());
+        match store.list_feature_flags() {
+            Ok(stored) => feature_flags.load(stored),
+            Err(e) => log::warn!("Failed to load stored feature flags: {}", e),
+        }
+
+        let state = Arc::new(ProxyState {
+            tenant_configs: TenantConfigResolver::new(config.clone()),
+            attestation: AttestationService::new()?,
+            response_signer: if config.encryption.response_signing.enabled {
+                Some(ResponseSigner::new(&config.encryption.response_signing)?)
+            } else {
+                None
+            },
+            redaction_policies: RedactionPolicyRegistry::new(config.tenants.redaction.clone()),
+            federation: FederationService::new(config.federation.clone())?,
+            rate_limiter: RateLimiter::new(config.privacy.max_queries_per_user as u64),
+            metrics: MetricsCollector::new(),
+            metric_catalog: MetricCatalog::proxy()?,
+            privacy_tracker: PrivacyBudgetTracker::new(
+                config.privacy.epsilon_per_query * config.privacy.max_queries_per_user as f64,
+                config.privacy.delta,
+            )
+            .with_policy(privacy_policy),
+            documents: DocumentJobs::new(&config.documents),
+            response_chunks: ResponseChunkStore::new(std::time::Duration::from_secs(
+                config
+                    .performance
+                    .response_chunking
+                    .unreferenced_chunk_ttl_seconds,
+            )),
+            response_cache: ResponseCache::new(
+                config.performance.response_cache.max_entries,
+                config.performance.response_cache.eviction,
+            )
+            .with_preload(config.performance.response_cache.preload.clone()),
+            monitoring: MonitoringService::new(env!("CARGO_PKG_VERSION").to_string()),
+            profiler: PerformanceProfiler::new(),
+            sla_metrics: SlaMetrics::new(),
+            geo_latency: GeoLatencyHeatmap::new(config.monitoring.geo_latency.clone()),
+            siem: SiemExporter::new(config.monitoring.siem.clone())?,
+            mirror: RequestMirror::new(config.server.mirroring.clone()),
+            probes: SyntheticProbes::new(config.monitoring.synthetic_probes.clone())?,
+            telemetry: TelemetrySampler::new(config.monitoring.telemetry_sampling.clone()),
+            trace_sampler: AdaptiveSamplingStrategy::new(
+                config.monitoring.trace_sampling.clone(),
+                config.monitoring.trace_sampling_rate,
+            ),
+            quality: QualityMonitor::new(config.monitoring.quality.clone()),
+            sandbox: SandboxKeys::new(config.sandbox.clone()),
+            honeytokens: Honeytokens::new(config.honeytokens.clone()),
+            access: AccessControl::new(config.access.clone()),
+            dependencies: DependencyMonitor::new(config.monitoring.dependencies.clone()),
+            noise_trends: NoiseTrendMonitor::new(config.monitoring.noise_trends.clone()),
+            config_drift: ConfigDriftMonitor::new(config.monitoring.config_drift.clone()),
+            request_journal: RequestJournal::new(config.monitoring.request_journal.clone()),
+            systemd: systemd::Notifier::from_env(&config.server.systemd),
+            standby: StandbyPair::new(config.persistence.standby.clone()),
+            prompt_lint: PromptLinter::new(&config.tenants.prompt_lint),
+            feature_flags,
+            execution: ExecutionDispatcher::new(config.gpu.clone()),
+            experiments: ExperimentRegistry::new(config.experiments.clone()),
+            aggregation: AggregationService::new(config.aggregation.clone()),
+            escrow: EscrowService::new(config.escrow.clone()),
+            approvals: ApprovalService::new(config.approvals.clone()),
+            connection_guard: Arc::new(ConnectionGuard::new(config.server.connections.clone())),
+            clock: ClockMonitor::new(config.monitoring.clock.clone()),
+            api_versions: ApiVersions::new(config.api_versions.clone()),
+            conversations: ConversationStore::new(
+                config.conversations.clone(),
+                store.clone(),
+                conversation::open_object_store(&config.conversations.archive)?,
+            ),
+            streams: Arc::new(StreamRegistry::new(
+                config.scaling.stream_flow_control.clone(),
+            )),
+            runbooks: RunbookEngine::new(config.monitoring.runbooks.clone()),
+            state_recorder: StateRecorder::new({
+                let recorder = &config.monitoring.state_recorder;
+                (recorder.retention_minutes * 60 / recorder.interval_seconds.max(1)) as usize
+            }),
+            performance_history: PerformanceHistory::new({
+                let recorder = &config.monitoring.state_recorder;
+                (recorder.retention_minutes * 60 / recorder.interval_seconds.max(1)) as usize
+            }),
+            memory: MemoryTracker::new(),
+            prompt_packer: PromptPacker::new(
+                Duration::from_millis(config.performance.packing.window_ms),
+                config.performance.packing.max_batch,
+            ),
+            tasks: TaskSupervisor::new(config.monitoring.tasks.clone()),
+            overflow: OverflowQueue::new(config.scaling.overflow_queue.clone())?,
+            otlp_metrics: OtlpMetricsExporter::new(
+                &config.monitoring.export_endpoints,
+                config.monitoring.otlp_metrics.clone(),
+                &config.persistence.replication.region,
+            ),
+            lockdown: AtomicBool::new(false),
+            provider_failover: RwLock::new(HashMap::new()),
+            fhe_engine: Arc::new(RwLock::new(fhe_engine)),
+            decryption: DecryptionDelegation::new(config.encryption.decryption_delegation.clone())?,
+            engine_warming: AtomicBool::new(false),
+            tables_reloading: AtomicBool::new(false),
+            session_manager: SessionManager::new()
+                .with_store(store.clone())
+                .with_region(&config.persistence.replication.region),
+            store,
+            migration_report,
+            reconciler: SessionReconciler::new(&config.persistence.replication.region),
+            llm_providers,
+            ciphertext_cache: RwLock::new(HashMap::new()),
+            // Scaling components
+            fhe_pool,
+            failure_domains,
+            auto_scaler,
+            batch_processor,
+            advanced_cache,
+            circuit_breaker,
+            resource_guard,
+            admission: PriorityAdmission::new(config.scaling.max_concurrent_requests as usize),
+            key_policies: KeyPolicyEnforcer::new(),
+            transforms: TransformEngine::new(config.server.transforms.clone()),
+            billing: BillingMeter::new(config.billing.clone()),
+            spending_caps: SpendingCaps::new(&config.billing),
+            offboarding: TenantOffboarding::new(),
+            load_shedding: LoadShedPolicies::new(config.scaling.load_shedding.clone()),
+            rate_limit_rules: RateLimitRules::new(config.tenants.rate_limit_rules.clone())?,
+            workload_tags: WorkloadTags::new(config.tenants.workload_tags.clone()),
+            drills: FailoverDrills::new(config.disaster_recovery.clone()),
+            renewal: RenewalProtocol::new(config.sessions.renewal.clone()),
+            queue_projector: QueueProjector::new(config.scaling.queue_projection.clone()),
+            batch_windows: BatchWindowScheduler::new(config.scaling.batch_windows.clone()),
+            dead_letters: DeadLetterQueue::new(config.scaling.dead_letter.clone()),
+            validators: ValidatorChain::from_config(&config.validation)?,
+            // Performance optimization
+            performance_cache,
+            connection_manager,
+            config,
+        });
+
+        // Task panics count as errors; a weak handle avoids a reference cycle
+        let monitored = Arc::downgrade(&state);
+        state.tasks.set_failure_hook(move |failure| {
+            if let Some(state) = monitored.upgrade() {
+                tokio::spawn(async move {
+                    state
+                        .monitoring
+                        .record_error(
+                            "task_panic".to_string(),
+                            format!(
+                                "{}: {}{}",
+                                failure.task,
+                                failure.message,
+                                if failure.will_restart {
+                                    ""
+                                } else {
+                                    " (giving up)"
+                                }
+                            ),
+                        )
+                        .await;
+                });
+            }
+        });
+
+        Ok(Self { state })
+    }
+
+    /// Start the proxy server
+    pub async fn start(&self) -> Result<()> {
+        let app = self.create_router().await;
+
+        let addr = format!(
+            "{}:{}",
+            self.state.config.server.host, self.state.config.server.port
+        );
+        let activated = if self.state.config.server.systemd.socket_activation {
+            systemd::take_activated_listener()?
+        } else {
+            None
+        };
+        let listener = match activated {
+            Some(listener) => {
+                log::info!(
+                    "Serving on socket {} passed by systemd",
+                    listener.local_addr()?
+                );
+                tokio::net::TcpListener::from_std(listener)?
+            }
+            None => tokio::net::TcpListener::bind(&addr).await?,
+        };
+
+        let provenance = BuildProvenance::current();
+        log::info!(
+            "Build provenance: {} {} at commit {}{}, features [{}], Cargo.lock {}, {} ({} {}); attested by key {}",
+            provenance.subject.name,
+            provenance.subject.version,
+            provenance.predicate.git_commit,
+            if provenance.predicate.git_dirty == Some(true) {
+                " (dirty)"
+            } else {
+                ""
+            },
+            provenance.predicate.features.join(", "),
+            provenance.predicate.lockfile_sha256,
+            provenance.predicate.rustc_version,
+            provenance.predicate.target,
+            provenance.predicate.profile,
+            self.state.attestation.current_key().key_id
+        );
+
+        let ledger = self.state.store.list_ledger()?;
+        if !ledger.is_empty() {
+            log::info!(
+                "Restored privacy ledger for {} users from {} storage",
+                ledger.len(),
+                self.state.store.name()
+            );
+            self.state.privacy_tracker.restore_ledger(ledger).await;
+        }
+        if self.state.spending_caps.is_enabled() {
+            let now = chrono::Utc::now().timestamp();
+            let month = self.state.store.list_billing(now - 31 * 86_400, now + 1)?;
+            self.state.spending_caps.restore(&month, now);
+        }
+        let mut honeytoken_generations = Vec::new();
+        for record in self.state.store.list_audit()? {
+            if record.action == OFFBOARDED_AUDIT_ACTION {
+                self.state.offboarding.mark_offboarded(&record.subject);
+            } else if record.action == HONEYTOKEN_ROTATION_AUDIT_ACTION {
+                match serde_json::from_value::<HoneytokenGeneration>(record.details) {
+                    Ok(generation) => honeytoken_generations.push(generation),
+                    Err(e) => log::warn!("Skipping unreadable honeytoken generation: {}", e),
+                }
+            }
+        }
+        if self.state.honeytokens.is_enabled() {
+            self.state.honeytokens.restore(honeytoken_generations);
+        }
+        self.spawn_engine_warm_up();
+        self.spawn_persistence_tasks();
+        self.state.siem.spawn();
+        self.state.mirror.spawn();
+
+        // Sweep scheduled privacy budget replenishments so idle users are reset too
+        self.supervise("privacy_replenishment", |state| async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                let replenished = state.privacy_tracker.apply_scheduled_replenishment().await;
+                if replenished > 0 {
+                    log::info!("Replenished privacy budget for {} users", replenished);
+                }
+            }
+        });
+
+        // Drop response chunks that were never referenced to save memory and quota
+        self.supervise("response_chunk_gc", |state| async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                let expired = state.response_chunks.expire_unreferenced().await;
+                let mut cache = state.ciphertext_cache.write().await;
+                if !expired.is_empty() {
+                    for id in &expired {
+                        cache.remove(id);
+                    }
+                    log::debug!("Expired {} unreferenced response chunks", expired.len());
+                }
+                state
+                    .offboarding
+                    .retain_ciphertexts(|id| cache.contains_key(id));
+                drop(cache);
+                state.response_cache.expire().await;
+            }
+        });
+
+        // Re-run completions whose cached responses were served stale
+        self.supervise("response_cache_refresh", |state| async move {
+            loop {
+                for (key, refresh) in state.response_cache.next_refreshes().await {
+                    tokio::spawn(refresh_cached_completion(state.clone(), key, refresh));
+                }
+            }
+        });
+
+        if self.state.config.scaling.resource_guard.enabled {
+            self.spawn_resource_guard();
+        }
+        if self.state.config.monitoring.runbooks.enabled {
+            self.spawn_runbooks();
+        }
+        if self.state.config.monitoring.state_recorder.enabled {
+            self.spawn_state_recorder();
+        }
+        if self.state.config_drift.is_enabled() {
+            self.spawn_config_drift_monitor();
+        }
+        if self.state.batch_windows.is_enabled() {
+            self.spawn_batch_runner();
+        }
+        if self.state.config.documents.enabled && self.state.documents.autoscaler().is_enabled() {
+            self.spawn_document_autoscaler();
+        }
+        if self.state.config.performance.response_cache.preload.enabled {
+            self.spawn_response_cache_preloader();
+        }
+
+        // Push the metrics registry to the OpenTelemetry collector
+        if self.state.otlp_metrics.is_enabled() {
+            let collection_interval =
+                Duration::from_secs(self.state.otlp_metrics.config().collection_interval_seconds);
+            self.supervise("otlp_metrics_export", move |state| async move {
+                let mut interval = tokio::time::interval(collection_interval);
+                loop {
+                    interval.tick().await;
+                    state.otlp_metrics.record(state.metrics.get_stats());
+                    state.otlp_metrics.export_due().await;
+                }
+            });
+        }
+
+        // Re-inject spilled requests as admission slots free up
+        if self.state.overflow.is_enabled() {
+            let drain_interval =
+                Duration::from_millis(self.state.config.scaling.overflow_queue.drain_interval_ms);
+            self.supervise("overflow_drain", move |state| async move {
+                let mut interval = tokio::time::interval(drain_interval);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = state.overflow.release(|p| state.admission.available(p)) {
+                        log::error!("Failed to re-inject overflow requests: {}", e);
+                    }
+                }
+            });
+        }
+
+        // Evict sessions that have been idle past the configured timeout
+        let idle_timeout = self.state.config.sessions.idle_timeout_seconds;
+        if idle_timeout > 0 {
+            self.supervise("session_idle_sweep", move |state| async move {
+                let idle_timeout = std::time::Duration::from_secs(idle_timeout);
+                let sweep_interval = (idle_timeout / 4).clamp(
+                    std::time::Duration::from_secs(1),
+                    std::time::Duration::from_secs(60),
+                );
+                let mut interval = tokio::time::interval(sweep_interval);
+                loop {
+                    interval.tick().await;
+                    let evicted = state.session_manager.expire_idle(idle_timeout).await;
+                    if !evicted.is_empty() {
+                        let mut fhe_engine = state.fhe_engine.write().await;
+                        release_evicted_sessions(&state, &mut fhe_engine, &evicted).await;
+                    }
+                }
+            });
+        }
+
+        // Release server keys replaced by a renewal once their overlap closes
+        if self.state.renewal.is_enabled() {
+            let overlap = self.state.renewal.config().key_overlap_seconds;
+            self.supervise("key_overlap_sweep", move |state| async move {
+                let sweep_interval = (std::time::Duration::from_secs(overlap) / 4).clamp(
+                    std::time::Duration::from_secs(1),
+                    std::time::Duration::from_secs(60),
+                );
+                let mut interval = tokio::time::interval(sweep_interval);
+                loop {
+                    interval.tick().await;
+                    let retired = state.session_manager.retire_overlapped_keys().await;
+                    if !retired.is_empty() {
+                        let mut fhe_engine = state.fhe_engine.write().await;
+                        for server_id in retired {
+                            fhe_engine.server_keys.remove(&server_id);
+                            log::info!("Retired renewed server key {}", server_id);
+                        }
+                    }
+                }
+            });
+        }
+
+        // Catch engines whose build or parameter tables drifted from the pool
+        let check_interval = self
+            .state
+            .config
+            .scaling
+            .engine_fingerprints
+            .check_interval_seconds;
+        self.supervise("engine_fingerprint_check", move |state| async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(check_interval));
+            let mut reported = Vec::new();
+            loop {
+                interval.tick().await;
+                let report = state.fhe_pool.fingerprint_report().await;
+                let mismatched: Vec<String> = report
+                    .mismatched()
+                    .iter()
+                    .map(|e| format!("engine {} ({})", e.index, e.fingerprint))
+                    .collect();
+                if !report.is_uniform() {
+                    let message = format!(
+                        "FHE engines do not match the pool fingerprint ({}): {}",
+                        report
+                            .reference
+                            .as_ref()
+                            .map_or("none".to_string(), ToString::to_string),
+                        mismatched.join(", ")
+                    );
+                    state
+                        .monitoring
+                        .raise_alert("engine_fingerprint_mismatch", message.clone(), 2)
+                        .await;
+                    if mismatched != reported {
+                        state.siem.emit(
+                            SecurityEvent::new(SecurityEventKind::Anomaly, &message)
+                                .subject("fhe_pool"),
+                        );
+                    }
+                }
+                reported = mismatched;
+            }
+        });
+
+        if self.state.failure_domains.is_enabled() {
+            let check_interval = self.state.failure_domains.config().check_interval_seconds;
+            self.supervise("failure_domain_check", move |state| async move {
+                let mut interval =
+                    tokio::time::interval(std::time::Duration::from_secs(check_interval));
+                let mut provider_calls: HashMap<String, (u64, u64)> = HashMap::new();
+                loop {
+                    interval.tick().await;
+                    let now = Instant::now();
+                    for (index, healthy) in state.fhe_pool.health_check().await.iter().enumerate() {
+                        let member = failure_domains::engine_member(index);
+                        if *healthy {
+                            state.failure_domains.record_success(&member);
+                        } else {
+                            state.failure_domains.record_failure(&member, now);
+                        }
+                    }
+                    // Providers are judged on the calls made since the last check
+                    for (name, provider) in &state.llm_providers {
+                        let (succeeded, failed) = provider.call_counts();
+                        let (last_succeeded, last_failed) = provider_calls
+                            .insert(name.clone(), (succeeded, failed))
+                            .unwrap_or((succeeded, failed));
+                        let member = failure_domains::provider_member(name);
+                        if failed > last_failed {
+                            for _ in last_failed..failed {
+                                state.failure_domains.record_failure(&member, now);
+                            }
+                        } else if succeeded > last_succeeded {
+                            state.failure_domains.record_success(&member);
+                        }
+                    }
+
+                    let transitions = state.failure_domains.evaluate(now);
+                    for transition in &transitions {
+                        report_domain_transition(&state, transition).await;
+                    }
+                    if !transitions.is_empty() {
+                        apply_domain_drains(&state, now);
+                    }
+                }
+            });
+        }
+
+        if self.state.probes.is_enabled() {
+            self.spawn_synthetic_probes();
+        }
+
+        let check_interval = self.state.dead_letters.config().check_interval_seconds;
+        self.supervise("dead_letter_growth_check", move |state| async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(check_interval));
+            loop {
+                interval.tick().await;
+                let letters = match state.store.list_dead_letters() {
+                    Ok(letters) => letters,
+                    Err(e) => {
+                        log::error!("Failed to load dead letters: {}", e);
+                        continue;
+                    }
+                };
+                let now = chrono::Utc::now().timestamp();
+                if let Some(growth) = state.dead_letters.check_growth(&letters, now) {
+                    let message = format!(
+                        "{} work items were dead-lettered in the last {}s ({} queued in total)",
+                        growth,
+                        state.dead_letters.config().growth_window_seconds,
+                        letters.len()
+                    );
+                    state
+                        .monitoring
+                        .raise_alert("dead_letter_growth", message, 2)
+                        .await;
+                }
+            }
+        });
+
+        if self.state.systemd.is_active() {
+            self.spawn_systemd_notifier();
+        }
+
+        log::info!("🔐 FHE LLM Proxy listening on {}", listener.local_addr()?);
+        log::info!(
+            "📊 Available providers: {:?}",
+            self.state.llm_providers.keys().collect::<Vec<_>>()
+        );
+
+        let state = self.state.clone();
+        let restarting = Arc::new(AtomicBool::new(false));
+        let restart_flag = restarting.clone();
+        serve_connections(listener, app, &self.state.connection_guard, async move {
+            tokio::select! {
+                _ = state.resource_guard.restart_requested() => {
+                    restart_flag.store(true, Ordering::Relaxed);
+                }
+                _ = shutdown_signal() => log::info!("Shutdown signal received"),
+            }
+            state.systemd.stopping();
+        })
+        .await;
+
+        flush_privacy_ledger(&self.state).await;
+        save_engine_snapshot(&self.state).await;
+        if self.state.otlp_metrics.is_enabled() {
+            self.state
+                .otlp_metrics
+                .record(self.state.metrics.get_stats());
+            if let Err(e) = self.state.otlp_metrics.export().await {
+                log::warn!("Final metrics export failed: {}", e);
+            }
+        }
+
+        // Exit non-zero after a guard-requested restart so the supervisor brings
+        // up a fresh process
+        if restarting.load(Ordering::Relaxed) {
+            return Err(Error::ResourceExhaustion(
+                "Graceful restart requested by resource guard".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Run a background loop under the task supervisor; a restart after a panic
+    /// builds a fresh future from `task`
+    fn supervise<F, Fut>(&self, name: &str, task: F)
+    where
+        F: Fn(Arc<ProxyState>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let state = self.state.clone();
+        self.state.tasks.spawn(name, move || task(state.clone()));
+    }
+
+    /// Tell systemd once the proxy is ready, keep its status in line with the
+    /// health module and ping its watchdog while the proxy is live
+    fn spawn_systemd_notifier(&self) {
+        let tick = self.state.systemd.tick_interval();
+        self.supervise("systemd_notify", move |state| async move {
+            let mut interval = tokio::time::interval(tick);
+            let mut ready = false;
+            let mut last_status = String::new();
+            let mut last_ping = Instant::now();
+            loop {
+                interval.tick().await;
+                let status = if is_ready(&state).await {
+                    format!(
+                        "Serving; health {}",
+                        state.monitoring.health_check().await.status
+                    )
+                } else if state.engine_warming.load(Ordering::Relaxed) {
+                    "Warming up the FHE engine".to_string()
+                } else {
+                    "Not ready".to_string()
+                };
+                if !ready && status.starts_with("Serving") {
+                    state.systemd.ready(&status);
+                    ready = true;
+                } else if status != last_status {
+                    state.systemd.status(&status);
+                }
+                last_status = status;
+
+                let due = state
+                    .systemd
+                    .watchdog_interval()
+                    .is_some_and(|w| last_ping.elapsed() + tick >= w);
+                if due && state.monitoring.liveness_check().await {
+                    state.systemd.watchdog();
+                    last_ping = Instant::now();
+                }
+            }
+        });
+    }
+
+    /// Restore warmed engine state from the cold-start snapshot, or warm up from scratch
+    fn spawn_engine_warm_up(&self) {
+        self.state.engine_warming.store(true, Ordering::Relaxed);
+        self.supervise("engine_warm_up", |state| async move {
+            let started = Instant::now();
+            let params = state.fhe_engine.read().await.get_params().clone();
+            let snapshot = state.config.performance.engine_snapshot.clone();
+
+            let warmed = tokio::task::spawn_blocking(move || {
+                if snapshot.enabled {
+                    match EngineSnapshot::restore(
+                        std::path::Path::new(&snapshot.path),
+                        &AttestationService::engine_build_hash(),
+                        &params,
+                    ) {
+                        Ok(tables) => return Ok((tables, true)),
+                        Err(e) => log::info!("Engine snapshot not usable, warming up: {}", e),
+                    }
+                }
+                PrecomputedTables::compute(&params).map(|tables| (tables, false))
+            })
+            .await;
+
+            match warmed {
+                Ok(Ok((tables, restored))) => {
+                    match state.fhe_engine.read().await.install_tables(tables) {
+                        Ok(_) => log::info!(
+                            "FHE engine warm in {:?} ({})",
+                            started.elapsed(),
+                            if restored {
+                                "restored from snapshot"
+                            } else {
+                                "full warm-up"
+                            }
+                        ),
+                        Err(e) => log::error!("Failed to install engine tables: {}", e),
+                    }
+                }
+                Ok(Err(e)) => log::error!("FHE engine warm-up failed: {}", e),
+                Err(e) => log::error!("FHE engine warm-up task failed: {}", e),
+            }
+            state.engine_warming.store(false, Ordering::Relaxed);
+        });
+    }
+
+    /// Periodically flush the privacy ledger and compact the storage backend
+    fn spawn_persistence_tasks(&self) {
+        let persistence = &self.state.config.persistence;
+
+        let flush_interval =
+            std::time::Duration::from_secs(persistence.ledger_flush_interval_seconds);
+        self.supervise("privacy_ledger_flush", move |state| async move {
+            let mut interval = tokio::time::interval(flush_interval);
+            loop {
+                interval.tick().await;
+                flush_privacy_ledger(&state).await;
+            }
+        });
+
+        let compaction_interval =
+            std::time::Duration::from_secs(persistence.compaction_interval_seconds);
+        let audit_retention =
+            std::time::Duration::from_secs(persistence.audit_retention_days * 86400);
+        self.supervise("storage_compaction", move |state| async move {
+            let mut interval = tokio::time::interval(compaction_interval);
+            loop {
+                interval.tick().await;
+                match state.store.compact(audit_retention) {
+                    Ok(report) => log::debug!(
+                        "Compacted {} storage: {} idempotency entries, {} audit records removed",
+                        state.store.name(),
+                        report.idempotency_removed,
+                        report.audit_removed
+                    ),
+                    Err(e) => log::error!("Storage compaction failed: {}", e),
+                }
+            }
+        });
+
+        if self.state.clock.is_enabled() {
+            let poll_interval = self.state.clock.poll_interval();
+            self.supervise("clock_health", move |state| async move {
+                let mut interval = tokio::time::interval(poll_interval);
+                loop {
+                    interval.tick().await;
+                    state.clock.measure().await;
+                    let extension = state.clock.window_adjustment();
+                    state.validators.adjust_for_clock_skew(extension);
+                    state.federation.adjust_for_clock_skew(extension);
+                }
+            });
+        }
+
+        if self.state.sandbox.is_enabled() {
+            let sweep_interval = self.state.sandbox.sweep_interval();
+            self.supervise("sandbox_key_sweep", move |state| async move {
+                let mut interval = tokio::time::interval(sweep_interval);
+                loop {
+                    interval.tick().await;
+                    for key in state.sandbox.sweep(chrono::Utc::now().timestamp()) {
+                        audit(
+                            &state,
+                            "sandbox.key.ended",
+                            &key.id.to_string(),
+                            serde_json::json!({ "state": key.state, "usage": key.usage }),
+                        );
+                    }
+                }
+            });
+        }
+
+        if self.state.dependencies.is_enabled() {
+            register_dependencies(&self.state);
+            let probe_interval = std::time::Duration::from_secs(
+                self.state.dependencies.config().probe_interval_seconds,
+            );
+            self.supervise("dependency_probes", move |state| async move {
+                let mut interval = tokio::time::interval(probe_interval);
+                loop {
+                    interval.tick().await;
+                    probe_dependencies(&state).await;
+                }
+            });
+        }
+
+        // The first tick issues honeytokens for this run; earlier generations
+        // stay recognised so a leak is detected however old it is
+        if self.state.honeytokens.is_enabled() {
+            let rotation_interval = std::time::Duration::from_secs(
+                self.state.honeytokens.config().rotation_interval_seconds,
+            );
+            self.supervise("honeytoken_rotation", move |state| async move {
+                let mut interval = tokio::time::interval(rotation_interval);
+                loop {
+                    interval.tick().await;
+                    rotate_honeytokens(&state).await;
+                }
+            });
+        }
+
+        // Pick up flags toggled through another instance
+        let flag_refresh_interval = std::time::Duration::from_secs(
+            self.state.feature_flags.config().refresh_interval_seconds,
+        );
+        self.supervise("feature_flag_refresh", move |state| async move {
+            let mut interval = tokio::time::interval(flag_refresh_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match state.store.list_feature_flags() {
+                    Ok(stored) => state.feature_flags.load(stored),
+                    Err(e) => log::warn!("Failed to refresh feature flags: {}", e),
+                }
+            }
+        });
+
+        if self.state.conversations.is_enabled() {
+            let tiering_interval = std::time::Duration::from_secs(
+                self.state.config.conversations.tiering_interval_seconds,
+            );
+            self.supervise("conversation_tiering", move |state| async move {
+                let mut interval = tokio::time::interval(tiering_interval);
+                loop {
+                    interval.tick().await;
+                    let retention_for = |tenant: &str| {
+                        state
+                            .tenant_configs
+                            .resolve(tenant)
+                            .map(|config| config.context_retention_seconds)
+                            .unwrap_or(state.config.conversations.retention_seconds)
+                    };
+                    match state.conversations.run_tiering(retention_for) {
+                        Ok(report) => log::debug!(
+                            "Conversation tiering: {} turns to {} storage, {} archived, {} expired",
+                            report.demoted,
+                            state.store.name(),
+                            report.archived,
+                            report.expired
+                        ),
+                        Err(e) => log::error!("Conversation tiering failed: {}", e),
+                    }
+                }
+            });
+        }
+
+        if persistence.standby.enabled {
+            self.spawn_standby_pair();
+        }
+
+        if persistence.replication.enabled {
+            self.supervise("session_replication", |state| async move {
+                let replication = &state.config.persistence.replication;
+                let client = HttpClient::builder()
+                    .timeout(std::time::Duration::from_secs(replication.timeout_seconds))
+                    .build()
+                    .unwrap_or_default();
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    replication.reconcile_interval_seconds,
+                ));
+                loop {
+                    interval.tick().await;
+                    reconcile_sessions(&state, &client, &replication.peers).await;
+                }
+            });
+        }
+    }
+
+    /// Exchange heartbeats with the other instance of a hot-standby pair,
+    /// promote or step down as the pair's state calls for, and follow the
+    /// active's state while standing by
+    fn spawn_standby_pair(&self) {
+        self.supervise("standby_pair", |state| async move {
+            let config = state.standby.config().clone();
+            let heartbeat_interval = Duration::from_millis(config.heartbeat_interval_ms);
+            let client = HttpClient::builder()
+                .timeout(heartbeat_interval)
+                .build()
+                .unwrap_or_default();
+            let mut interval = tokio::time::interval(heartbeat_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let now = Instant::now();
+                if let Some(heartbeat) = fetch_peer_heartbeat(&client, &config.peer_url).await {
+                    if let PairDecision::Demote(reason) = state.standby.observe_peer(heartbeat, now)
+                    {
+                        step_down(&state, PairRole::Standby, &reason).await;
+                    }
+                }
+                let witnesses_ok =
+                    if state.standby.role() == PairRole::Standby && state.standby.peer_lost(now) {
+                        witnesses_reachable(&client, &config.witness_urls).await
+                    } else {
+                        true
+                    };
+                if let PairDecision::Promote(reason) = state.standby.decide(now, || witnesses_ok) {
+                    take_over(&state, &reason).await;
+                }
+            }
+        });
+
+        if self.state.config.persistence.standby.replication == StandbyReplication::Direct {
+            self.supervise("standby_replication", |state| async move {
+                let config = state.standby.config().clone();
+                let client = HttpClient::builder()
+                    .timeout(Duration::from_secs(config.replication_interval_seconds))
+                    .build()
+                    .unwrap_or_default();
+                let mut interval =
+                    tokio::time::interval(Duration::from_secs(config.replication_interval_seconds));
+                loop {
+                    interval.tick().await;
+                    if state.standby.role() == PairRole::Standby
+                        && !state.standby.peer_lost(Instant::now())
+                    {
+                        if let Err(e) = pull_standby_state(&state, &client).await {
+                            log::warn!("Failed to replicate state from the active peer: {}", e);
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    /// Periodically sample process resources and apply guard actions
+    fn spawn_resource_guard(&self) {
+        let check_interval = std::time::Duration::from_secs(
+            self.state
+                .config
+                .scaling
+                .resource_guard
+                .check_interval_seconds,
+        );
+
+        self.supervise("resource_guard", move |state| async move {
+            let mut interval = tokio::time::interval(check_interval);
+            loop {
+                interval.tick().await;
+                let resources = state.resource_guard.sample().await;
+                let Some(decision) = state.resource_guard.evaluate(&resources).await else {
+                    continue;
+                };
+
+                match decision.action {
+                    GuardAction::FlushCache => {
+                        state.ciphertext_cache.write().await.clear();
+                        state.advanced_cache.cleanup_expired().await;
+                        state.performance_cache.cleanup_expired().await;
+                    }
+                    GuardAction::DrainEngine => state.resource_guard.set_draining(true),
+                    GuardAction::Restart => state.resource_guard.request_restart(),
+                }
+                if decision.action != GuardAction::FlushCache {
+                    state.siem.emit(
+                        SecurityEvent::new(SecurityEventKind::Lockdown, &decision.trigger)
+                            .subject(decision.action.as_str()),
+                    );
+                }
+
+                state
+                    .monitoring
+                    .record_guard_action(decision.action.as_str(), &decision.trigger)
+                    .await;
+            }
+        });
+    }
+
+    /// Periodically snapshot queue, cache and engine state into the recorder,
+    /// and performance statistics into their history
+    fn spawn_state_recorder(&self) {
+        let record_interval = std::time::Duration::from_secs(
+            self.state.config.monitoring.state_recorder.interval_seconds,
+        );
+
+        self.supervise("state_recorder", move |state| async move {
+            let mut interval = tokio::time::interval(record_interval);
+            loop {
+                interval.tick().await;
+                let snapshot = capture_state(&state).await;
+                state.state_recorder.record(snapshot).await;
+                let performance = capture_performance(&state, &PerformanceSubsystem::ALL).await;
+                state.performance_history.record(performance);
+            }
+        });
+    }
+
+    /// Resize the document workers by how long jobs wait for one
+    fn spawn_document_autoscaler(&self) {
+        let evaluate_interval = self.state.documents.autoscaler().interval();
+        self.supervise("document_autoscaler", move |state| async move {
+            let mut interval = tokio::time::interval(evaluate_interval);
+            loop {
+                interval.tick().await;
+                if let Some(event) = state.documents.autoscale().await {
+                    audit(
+                        &state,
+                        "documents.workers.resize",
+                        "documents",
+                        serde_json::to_value(&event).unwrap_or_default(),
+                    );
+                }
+            }
+        });
+    }
+
+    /// Re-run cached completions ahead of the hours they are usually requested in
+    fn spawn_response_cache_preloader(&self) {
+        let preload_interval = Duration::from_secs(
+            self.state
+                .config
+                .performance
+                .response_cache
+                .preload
+                .interval_seconds,
+        );
+        self.supervise("response_cache_preload", move |state| async move {
+            let mut interval = tokio::time::interval(preload_interval);
+            loop {
+                interval.tick().await;
+                let queued = state.response_cache.preload_at(chrono::Utc::now()).await;
+                if queued > 0 {
+                    log::info!("Preloading {} cached completions", queued);
+                }
+            }
+        });
+    }
+
+    /// Publish this replica's config fingerprint and compare it with the others'
+    fn spawn_config_drift_monitor(&self) {
+        let publish_interval =
+            Duration::from_secs(self.state.config_drift.config().publish_interval_seconds);
+
+        self.supervise("config_drift", move |state| async move {
+            let mut interval = tokio::time::interval(publish_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = check_config_drift(&state).await {
+                    log::warn!("Config drift check failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Run queued batch jobs whenever an off-peak window is open
+    fn spawn_batch_runner(&self) {
+        let state = self.state.clone();
+
+        // Jobs interrupted by a restart go back to the queue; today's charges still count
+        match state.store.list_batch_jobs() {
+            Ok(jobs) => {
+                for mut job in jobs {
+                    if job.status == BatchJobStatus::Cancelled {
+                        continue;
+                    }
+                    if let Some(submitted_at) =
+                        chrono::DateTime::from_timestamp(job.submitted_at, 0)
+                    {
+                        state.batch_windows.restore_charge(
+                            &job.tenant,
+                            job.quota_units,
+                            submitted_at,
+                        );
+                    }
+                    if job.status == BatchJobStatus::Running {
+                        job.status = BatchJobStatus::Queued;
+                        if let Err(e) = state.store.put_batch_job(&job) {
+                            log::error!("Failed to requeue batch job {}: {}", job.id, e);
+                        }
+                    }
+                }
+            }
+            Err(e) => log::error!("Failed to load batch jobs: {}", e),
+        }
+
+        let poll_interval =
+            std::time::Duration::from_secs(state.batch_windows.config().poll_interval_seconds);
+        self.supervise("batch_runner", move |state| async move {
+            let mut interval = tokio::time::interval(poll_interval);
+            loop {
+                interval.tick().await;
+                run_batch_window(&state).await;
+            }
+        });
+    }
+
+    /// Probe every target through the full request pipeline each interval and
+    /// alert on targets missing their SLOs
+    fn spawn_synthetic_probes(&self) {
+        let server = &self.state.config.server;
+        let host = match server.host.as_str() {
+            "0.0.0.0" | "::" | "" => "127.0.0.1",
+            host => host,
+        };
+        let local_url = format!("http://{}:{}", host, server.port);
+        let mut providers: Vec<String> = self.state.llm_providers.keys().cloned().collect();
+        providers.sort();
+        let routes = self.state.probes.routes(
+            &providers,
+            &local_url,
+            &self.state.config.persistence.replication.region,
+        );
+        let probe_interval =
+            std::time::Duration::from_secs(self.state.probes.config().interval_seconds);
+        self.supervise("synthetic_probes", move |state| {
+            let routes = routes.clone();
+            async move {
+                let mut interval = tokio::time::interval(probe_interval);
+                loop {
+                    interval.tick().await;
+                    let mut probes = tokio::task::JoinSet::new();
+                    for route in routes.iter().cloned() {
+                        let state = state.clone();
+                        probes.spawn(async move {
+                            let outcome = state.probes.probe(&route).await;
+                            (route, outcome)
+                        });
+                    }
+                    while let Some(result) = probes.join_next().await {
+                        let Ok((route, outcome)) = result else {
+                            continue;
+                        };
+                        if let Some(error) = &outcome.error {
+                            log::warn!(
+                                "Synthetic probe of {} in {} failed: {}",
+                                route.provider,
+                                route.region,
+                                error
+                            );
+                        }
+                        let now = chrono::Utc::now().timestamp();
+                        state.probes.record(&route, &outcome, now);
+                    }
+                    let breaches = state.probes.evaluate();
+                    if !breaches.is_empty() {
+                        let message = format!(
+                            "Synthetic probes are missing their SLOs: {}",
+                            breaches
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join("; ")
+                        );
+                        state
+                            .monitoring
+                            .raise_alert("synthetic_probe_slo", message, 2)
+                            .await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Periodically evaluate runbook rules and execute the actions they decide on
+    fn spawn_runbooks(&self) {
+        let evaluation_interval = std::time::Duration::from_secs(
+            self.state
+                .config
+                .monitoring
+                .runbooks
+                .evaluation_interval_seconds,
+        );
+
+        self.supervise("runbooks", move |state| async move {
+            let mut interval = tokio::time::interval(evaluation_interval);
+            loop {
+                interval.tick().await;
+                let health = state.monitoring.health_check().await;
+                let signals = RunbookSignals {
+                    engine_healthy: health
+                        .components
+                        .get("fhe_engine")
+                        .is_some_and(|component| component.status == "healthy"),
+                    budget_exhaustions: state.privacy_tracker.exhaustion_count(),
+                    provider_calls: state
+                        .llm_providers
+                        .iter()
+                        .map(|(name, provider)| (name.clone(), provider.call_counts()))
+                        .collect(),
+                };
+                for decision in state.runbooks.evaluate(signals).await {
+                    execute_runbook_action(&state, &decision, None).await;
+                }
+            }
+        });
+    }
+
+    /// Create the router with all endpoints, as `start` serves it
+    pub async fn create_router(&self) -> Router {
+        // Base64 inflates documents by a third; leave room for the JSON around them
+        let document_body_limit =
+            self.state.config.documents.max_document_bytes.div_ceil(3) * 4 + 64 * 1024;
+        let batch_body_limit = self
+            .state
+            .config
+            .validation
+            .max_ciphertext_bytes
+            .div_ceil(3)
+            * 4
+            + 64 * 1024;
+
+        let router = Router::new()
+            // Health and monitoring endpoints
+            .route("/health", get(health::health_check))
+            .route("/health/live", get(health::liveness_check))
+            .route("/health/ready", get(health::readiness_check))
+            .route("/metrics", get(health::get_metrics))
+            .route("/metrics/catalog", get(health::get_metric_catalog))
+            .route("/metrics/detailed", get(health::get_detailed_metrics))
+            // Core FHE endpoints
+            .route("/v1/keys/generate", post(ciphertexts::generate_keys))
+            .route(
+                "/v1/keys/rotate/{client_id}",
+                post(ciphertexts::rotate_client_keys),
+            )
+            .route("/v1/encrypt", post(ciphertexts::encrypt_text))
+            .route("/v1/decrypt", post(ciphertexts::decrypt_text))
+            .route("/v1/decrypt/chunks", post(chunks::decrypt_chunks))
+            .route(
+                "/v1/chat/completions",
+                post(completions::process_encrypted_completion),
+            )
+            .route(
+                "/v1/chat/stream",
+                post(completions::stream_encrypted_completion),
+            )
+            .route(
+                "/v1/chat/stream/{stream_id}/control",
+                post(completions::control_stream),
+            )
+            .route(
+                "/v1/documents",
+                post(documents::submit_document).layer(DefaultBodyLimit::max(document_body_limit)),
+            )
+            .route("/v1/documents/{id}", get(documents::get_document_job))
+            .route(
+                "/v1/batch/jobs",
+                get(batch::list_batch_jobs)
+                    .post(batch::submit_batch_job)
+                    .layer(DefaultBodyLimit::max(batch_body_limit)),
+            )
+            .route(
+                "/v1/batch/jobs/{id}",
+                get(batch::get_batch_job).delete(batch::cancel_batch_job),
+            )
+            .route(
+                "/v1/batch/jobs/{id}/retry",
+                post(batch::retry_batch_job).layer(DefaultBodyLimit::max(batch_body_limit)),
+            )
+            .route(
+                "/v1/conversations/{id}/turns",
+                get(conversations::get_conversation_turns)
+                    .post(conversations::append_conversation_turn),
+            )
+            .route("/v1/aggregations", post(aggregations::create_aggregation))
+            .route("/v1/aggregations/{id}", get(aggregations::get_aggregation))
+            .route(
+                "/v1/aggregations/{id}/decrypt",
+                post(aggregations::decrypt_aggregation),
+            )
+            .route(
+                "/v1/escrow/{client_id}/recoveries",
+                post(escrow::open_escrow_recovery),
+            )
+            .route(
+                "/v1/escrow/recoveries/{id}",
+                get(escrow::get_escrow_recovery),
+            )
+            .route("/v1/approvals", get(approvals::list_approvals))
+            .route("/v1/approvals/{id}", get(approvals::get_approval))
+            .route(
+                "/v1/approvals/{id}/approve",
+                post(approvals::approve_decryption),
+            )
+            .route("/v1/approvals/{id}/deny", post(approvals::deny_decryption))
+            .route(
+                "/v1/escrow/recoveries/{id}/shares",
+                post(escrow::submit_escrow_share),
+            )
+            .route("/v1/ciphertext/{id}", get(ciphertexts::get_ciphertext))
+            .route(
+                "/v1/ciphertext/{id}/validate",
+                post(ciphertexts::validate_ciphertext),
+            )
+            .route(
+                "/v1/ciphertext/{id}/chunks",
+                get(chunks::get_ciphertext_chunks),
+            )
+            .route("/v1/params", get(ciphertexts::get_fhe_params))
+            .route(
+                "/v1/params/negotiate",
+                post(ciphertexts::negotiate_fhe_params),
+            )
+            .route("/v1/capabilities", get(health::get_capabilities))
+            .route("/v1/lint/policy", get(completions::get_lint_policy))
+            .route("/v1/flags", get(flags::get_feature_flags))
+            .route("/v1/attestation/keys", get(signing::get_attestation_keys))
+            .route("/v1/provenance", get(signing::get_build_provenance))
+            .route(
+                "/.well-known/jwks.json",
+                get(signing::get_response_signing_keys),
+            )
+            .route(
+                "/v1/concatenate",
+                post(ciphertexts::concatenate_ciphertexts),
+            )
+            .route("/v1/transactions", post(ciphertexts::run_transaction))
+            .route(
+                "/v1/cache/invalidate",
+                post(response_cache::invalidate_response_cache),
+            )
+            .route(
+                "/v1/cache/stats",
+                get(response_cache::get_response_cache_stats),
+            )
+            // Federation endpoints
+            .route(
+                "/v1/federation/relay",
+                post(federation::relay_federated_request),
+            )
+            .route("/v1/federation/forward", post(federation::forward_to_peer))
+            .route(
+                "/v1/federation/peers",
+                get(federation::get_federation_peers),
+            )
+            // Session and admin endpoints
+            .route("/v1/sessions/{id}/stats", get(sessions::get_session_stats))
+            .route(
+                "/v1/sessions/{id}/refresh",
+                post(sessions::refresh_session_tokens),
+            )
+            .route(
+                "/v1/sessions/{id}/keys/renew",
+                post(sessions::renew_session_keys),
+            )
+            .route(
+                "/v1/privacy/budget/{user}",
+                get(privacy::get_privacy_budget),
+            )
+            .route(
+                "/v1/privacy/budget/{user}/reset",
+                post(privacy::reset_privacy_budget),
+            )
+            .route(
+                "/v1/privacy/budget/{user}/topup",
+                post(privacy::top_up_privacy_budget),
+            )
+            .route(
+                "/v1/privacy/budget/{user}/notifications",
+                get(privacy::get_privacy_budget_notifications),
+            )
+            .route("/v1/admin/performance", get(admin::get_performance_stats))
+            .route(
+                "/v1/admin/attestation/rotate",
+                post(signing::rotate_attestation_key),
+            )
+            .route(
+                "/v1/admin/response-signing/rotate",
+                post(signing::rotate_response_signing_key),
+            )
+            .route(
+                "/v1/admin/tenants/{tenant}/config",
+                get(tenants::get_tenant_config),
+            )
+            .route(
+                "/v1/admin/tenants/{tenant}/config/refresh",
+                post(tenants::refresh_tenant_config),
+            )
+            .route(
+                "/v1/admin/tenants/{tenant}/offboard",
+                post(tenants::offboard_tenant_handler),
+            )
+            .route(
+                "/v1/admin/tenants/{tenant}/offboarding",
+                get(tenants::get_offboarding_report),
+            )
+            .route(
+                "/v1/admin/tenants/{tenant}/redaction-policy",
+                post(redaction_policies::publish_redaction_policy),
+            )
+            .route(
+                "/v1/admin/tenants/{tenant}/redaction-policy/acknowledgments",
+                get(redaction_policies::get_redaction_acknowledgments),
+            )
+            .route(
+                "/v1/redaction-policy",
+                get(redaction_policies::get_redaction_policy),
+            )
+            .route(
+                "/v1/redaction-policy/ack",
+                post(redaction_policies::acknowledge_redaction_policy),
+            )
+            .route(
+                "/v1/admin/resource-guard",
+                get(admin::get_resource_guard_status),
+            )
+            .route("/v1/admin/sla", get(admin::get_sla_metrics))
+            .route("/v1/admin/latency-heatmap", get(admin::get_latency_heatmap))
+            .route(
+                "/v1/admin/batch/windows",
+                get(batch::get_batch_windows).put(batch::set_batch_windows),
+            )
+            .route(
+                "/v1/admin/dlq",
+                get(dead_letters::list_dead_letters).delete(dead_letters::purge_dead_letters),
+            )
+            .route(
+                "/v1/admin/dlq/{id}",
+                get(dead_letters::get_dead_letter).delete(dead_letters::delete_dead_letter),
+            )
+            .route(
+                "/v1/admin/dlq/{id}/reprocess",
+                post(dead_letters::reprocess_dead_letter),
+            )
+            .route("/v1/queue/projection", get(admin::get_queue_projection))
+            .route("/v1/admin/siem", get(admin::get_siem_stats))
+            .route("/v1/admin/dependencies", get(health::get_dependency_status))
+            .route("/v1/admin/noise-trends", get(admin::get_noise_trends))
+            .route("/v1/admin/config-drift", get(admin::get_config_drift))
+            .route(
+                "/v1/support/requests/{request_id}/journal",
+                get(admin::get_request_journal),
+            )
+            .route(
+                "/v1/admin/noise-trends/{tenant}",
+                get(admin::get_tenant_noise_trend),
+            )
+            .route("/v1/admin/mirroring", get(admin::get_mirroring_stats))
+            .route("/v1/admin/connections", get(admin::get_connection_stats))
+            .route("/v1/admin/clock", get(admin::get_clock_status))
+            .route("/v1/admin/api-versions", get(admin::get_api_version_usage))
+            .route("/v1/admin/probes", get(admin::get_probe_report))
+            .route("/v1/admin/telemetry", get(admin::get_telemetry_report))
+            .route("/v1/admin/traces", get(admin::get_sampled_traces))
+            .route("/v1/admin/quality", get(quality::get_quality_report))
+            .route(
+                "/v1/admin/sandbox/keys",
+                get(sandbox::list_sandbox_keys).post(sandbox::create_sandbox_key),
+            )
+            .route(
+                "/v1/admin/sandbox/keys/{id}",
+                get(sandbox::get_sandbox_key).delete(sandbox::revoke_sandbox_key),
+            )
+            .route(
+                "/v1/admin/honeytokens",
+                get(honeytokens::get_honeytokens).post(honeytokens::rotate_honeytokens_now),
+            )
+            .route("/v1/quality/scores", post(quality::submit_quality_score))
+            .route("/v1/admin/prompt-lint", get(admin::get_prompt_lint_stats))
+            .route("/v1/admin/flags", get(flags::get_feature_flag_status))
+            .route(
+                "/v1/admin/flags/{name}",
+                put(flags::set_feature_flag).delete(flags::unset_feature_flag),
+            )
+            .route(
+                "/v1/admin/experiments",
+                get(experiments::get_experiment_results).post(experiments::register_experiment),
+            )
+            .route(
+                "/v1/admin/experiments/{id}",
+                get(experiments::get_experiment).delete(experiments::remove_experiment),
+            )
+            .route(
+                "/v1/experiments/{id}/ratings",
+                post(experiments::rate_experiment_variant),
+            )
+            .route("/v1/admin/streams", get(admin::get_stream_stats))
+            .route("/v1/admin/overflow", get(admin::get_overflow_stats))
+            .route(
+                "/v1/admin/provider-quotas",
+                get(providers::get_provider_quotas),
+            )
+            .route("/v1/admin/transforms", get(admin::get_transform_stats))
+            .route("/v1/admin/load-shedding", get(admin::get_load_shedding))
+            .route(
+                "/v1/admin/load-shedding/{policy}",
+                post(admin::set_load_shedding_mode),
+            )
+            .route(
+                "/v1/admin/rate-limit-rules",
+                get(admin::get_rate_limit_rules),
+            )
+            .route("/v1/admin/workload-tags", get(admin::get_workload_tags))
+            .route("/v1/admin/billing", get(billing::get_billing_usage))
+            .route(
+                "/v1/admin/engines/fingerprints",
+                get(admin::get_engine_fingerprints),
+            )
+            .route("/v1/admin/engines/tables", get(admin::get_engine_tables))
+            .route("/v1/admin/failure-domains", get(admin::get_failure_domains))
+            .route(
+                "/v1/admin/failure-domains/{level}/{name}/restore",
+                post(admin::restore_failure_domain),
+            )
+            .route(
+                "/v1/admin/engines/tables/reload",
+                post(admin::reload_engine_tables),
+            )
+            .route(
+                "/v1/admin/billing/reconcile",
+                get(billing::reconcile_billing),
+            )
+            .route("/v1/admin/billing/caps", get(billing::get_spending_caps))
+            .route(
+                "/v1/admin/billing/caps/{tenant}/override",
+                post(billing::override_spending_cap).delete(billing::revoke_spending_cap_override),
+            )
+            .route("/v1/admin/metrics/backfill", post(admin::backfill_metrics))
+            .route("/v1/admin/otlp", get(admin::get_otlp_export_stats))
+            .route("/v1/admin/decryption", get(admin::get_decryption_stats))
+            .route("/v1/admin/validation", get(admin::get_validation_stats))
+            .route("/v1/admin/sessions", get(sessions::get_session_limits))
+            .route(
+                "/v1/admin/conversations",
+                get(conversations::get_conversation_stats),
+            )
+            .route("/v1/admin/tasks", get(admin::get_task_states))
+            .route(
+                "/v1/admin/replication",
+                get(sessions::get_replication_stats),
+            )
+            .route(
+                "/v1/admin/dr/drills",
+                get(drills::get_failover_drills).post(drills::start_failover_drill),
+            )
+            .route("/v1/admin/dr/drills/{id}", get(drills::get_failover_drill))
+            .route("/v1/admin/migrations", get(admin::get_migration_status))
+            .route(
+                "/v1/admin/replication/sessions",
+                get(sessions::export_replicated_sessions),
+            )
+            .route("/v1/admin/standby", get(standby::get_standby_status))
+            .route(
+                "/v1/admin/standby/heartbeat",
+                get(standby::get_standby_heartbeat),
+            )
+            .route(
+                "/v1/admin/standby/state",
+                get(standby::export_standby_state),
+            )
+            .route("/v1/admin/standby/handoff", post(standby::hand_off_standby))
+            .route("/v1/admin/standby/promote", post(standby::promote_standby))
+            .route("/v1/admin/state-history", get(runbooks::get_state_history))
+            .route("/v1/admin/runbooks", get(runbooks::get_runbooks))
+            .route(
+                "/v1/admin/runbooks/reset",
+                post(runbooks::reset_runbook_actions),
+            )
+            .route(
+                "/v1/admin/runbooks/{id}/approve",
+                post(runbooks::approve_runbook_action),
+            )
+            .route(
+                "/v1/admin/runbooks/{id}/reject",
+                post(runbooks::reject_runbook_action),
+            )
+            .route("/v1/admin/audit", get(admin::get_audit_log))
+            .route(
+                "/v1/admin/keys/{client_id}/policy",
+                get(ciphertexts::get_key_policy).post(ciphertexts::set_key_policy),
+            )
+            // Middleware layers
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                layers::transform_middleware,
+            ))
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                sessions::session_token_middleware,
+            ))
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                layers::key_policy_middleware,
+            ))
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                layers::rate_limiting_middleware,
+            ))
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                sandbox::sandbox_middleware,
+            ))
+            .layer(from_fn(layers::logging_middleware))
+            .layer(from_fn(layers::api_version_adapter))
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                layers::uncompressed_size_middleware,
+            ))
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                layers::journal_middleware,
+            ))
+            .with_state(self.state.clone());
+
+        // SSE responses are left uncompressed by the default predicate
+        let router = if self.state.config.performance.compression_enabled {
+            router.layer(CompressionLayer::new())
+        } else {
+            router
+        };
+        // Metered outside compression so the ledger sees wire bytes
+        let router = router
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                billing::billing_middleware,
+            ))
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                layers::connection_guard_middleware,
+            ));
+        // Version paths are mapped onto the shared routes before routing.
+        // Every layer sees the tenant the request's credentials belong to;
+        // honeytoken keys are caught before they are refused as unknown.
+        Router::new()
+            .fallback_service(
+                from_fn_with_state(self.state.clone(), layers::api_version_routing).layer(router),
+            )
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                identity::tenant_identity_middleware,
+            ))
+            .layer(from_fn_with_state(
+                self.state.clone(),
+                honeytokens::honeytoken_middleware,
+            ))
+    }
+}
+
+/// Serve `app` on `listener` until `shutdown` completes, then wait for open
+/// connections to finish. Connections are admitted by `guard`, which also
+/// sets the header read timeout.
+async fn serve_connections(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    guard: &Arc<ConnectionGuard>,
+    shutdown: impl std::future::Future<Output = ()>,
+) {
+    let mut builder = AutoBuilder::new(TokioExecutor::new());
+    if let Some(timeout) = guard.header_read_timeout() {
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(timeout);
+    }
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Typically out of file descriptors; give connections time to close
+                    log::warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        let Some(permit) = guard.admit_connection(peer.ip()) else {
+            log::warn!(
+                "Refused connection from {}: too many open connections",
+                peer.ip()
+            );
+            continue;
+        };
+        let connection = builder
+            .serve_connection_with_upgrades(
+                TokioIo::new(stream),
+                TowerToHyperService::new(app.clone()),
+            )
+            .into_owned();
+        let connection = graceful.watch(connection);
+        let guard = guard.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                guard.record_connection_error(e.as_ref());
+                log::debug!("Connection from {} closed: {}", peer, e);
+            }
+            drop(permit);
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+}
+
+/// Persist warmed engine state so the next start can skip warm-up
+async fn save_engine_snapshot(state: &ProxyState) {
+    let config = &state.config.performance.engine_snapshot;
+    if !config.enabled {
+        return;
+    }
+    let engine = state.fhe_engine.read().await;
+    let Some(tables) = engine.tables() else {
+        return;
+    };
+
+    let saved = EngineSnapshot::capture(
+        &AttestationService::engine_build_hash(),
+        engine.get_params(),
+        &tables,
+    )
+    .and_then(|snapshot| snapshot.save(std::path::Path::new(&config.path)));
+    match saved {
+        Ok(()) => log::info!("Saved engine snapshot to {}", config.path),
+        Err(e) => log::warn!("Failed to save engine snapshot: {}", e),
+    }
+}
+
+/// Resolves on Ctrl-C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                log::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+fn audit(state: &ProxyState, action: &str, subject: &str, details: serde_json::Value) {
+    let record = AuditRecord {
+        id: Uuid::new_v4(),
+        timestamp: chrono::Utc::now().timestamp(),
+        action: action.to_string(),
+        subject: subject.to_string(),
+        details,
+    };
+    if let Err(e) = state.store.append_audit(&record) {
+        log::error!("Failed to write audit record for {}: {}", action, e);
+    }
+}
+
+/// Configuration governing the models a request of `tenant` may use. Once
+/// tenants are issued keys, a request naming none would be held only to the
+/// global model policy in place of its tenant's, so it is refused.
+fn model_policy(
+    state: &ProxyState,
+    tenant: Option<&str>,
+) -> std::result::Result<Arc<EffectiveTenantConfig>, StatusCode> {
+    match tenant {
+        Some(tenant) => state.tenant_configs.resolve(tenant),
+        None if state.access.issues_tenant_keys() => {
+            log::warn!("Refused model dispatch without an authenticated tenant");
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        None => state.tenant_configs.resolve_global(),
+    }
+    .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+/// Tenant the request was authenticated as, if any. `x-tenant-id` is
+/// checked against the request's credentials, and set from them, by
+/// `identity::tenant_identity_middleware` before any handler runs.
+fn tenant_id(headers: &HeaderMap) -> Option<&str> {
+    headers.get(TENANT_HEADER).and_then(|v| v.to_str().ok())
+}
@@ -0,0 +1,843 @@
+//! Operator endpoints reporting on and tuning subsystems
+
+use super::identity::admin_name;
+use super::layers::request_deadline;
+use super::{audit, ProxyState};
+use crate::backfill;
+use crate::clock::ClockStatus;
+use crate::config::ShedPolicyMode;
+use crate::connection_guard::ConnectionGuardStats;
+use crate::error::{Error, Result};
+use crate::failure_domains::{self, DomainId, DomainLevel, DomainTransition, FailureDomainReport};
+use crate::fhe::PrecomputedTables;
+use crate::limit_rules::RateLimitRuleStats;
+use crate::migrations::{self};
+use crate::noise_trends::TenantNoiseTrend;
+use crate::performance_stats::{
+    PerformanceFormat, PerformanceQuery, PerformanceSnapshot, PerformanceSubsystem,
+};
+use crate::request_journal::{self};
+use crate::scaling::{FingerprintReport, LoadSheddingStats, ShedPolicy};
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use crate::workload_tags::WorkloadTagReport;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Query for the most recent audit log records
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub limit: Option<usize>,
+}
+
+/// Deadline against which to evaluate the queue projection
+#[derive(Debug, Deserialize)]
+pub struct QueueProjectionQuery {
+    pub deadline_ms: Option<u64>,
+}
+
+/// Record an administrative action in the audit log
+/// Alert on and audit a failure domain being drained or restored
+pub(super) async fn report_domain_transition(state: &ProxyState, transition: &DomainTransition) {
+    match transition {
+        DomainTransition::Drained {
+            domain,
+            members,
+            failing,
+        } => {
+            let message = format!(
+                "Correlated failure in {}: {} of {} members failing; draining {}",
+                domain,
+                failing.len(),
+                members.len(),
+                failure_domains::describe_members(members)
+            );
+            log::warn!("{}", message);
+            state
+                .monitoring
+                .raise_alert("correlated_failure", message, 2)
+                .await;
+            audit(
+                state,
+                "failure_domain.drain",
+                &domain.to_string(),
+                serde_json::json!({ "members": members, "failing": failing }),
+            );
+        }
+        DomainTransition::Restored { domain, members } => {
+            log::info!(
+                "Restoring {}: {}",
+                domain,
+                failure_domains::describe_members(members)
+            );
+            audit(
+                state,
+                "failure_domain.restore",
+                &domain.to_string(),
+                serde_json::json!({ "members": members }),
+            );
+        }
+    }
+}
+
+/// Keep the pool's engines in drained domains out of rotation
+pub(super) fn apply_domain_drains(state: &ProxyState, now: Instant) {
+    let report = state.failure_domains.report(now);
+    let drained = report
+        .members
+        .iter()
+        .filter(|member| member.drained_by.is_some())
+        .map(|member| &member.id);
+    state
+        .fhe_pool
+        .set_drained(failure_domains::engine_indices(drained));
+}
+
+/// Per-version request counts by tenant, and each version's retirement dates
+pub(super) async fn get_api_version_usage(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "versions": state.api_versions.stats() }))
+}
+
+/// Publish this replica's fingerprint, forget replicas silent past
+/// `forget_after_seconds` and alert on drift that persists
+pub(super) async fn check_config_drift(state: &ProxyState) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    let fingerprint =
+        state
+            .config_drift
+            .fingerprint(&state.config, state.tenant_configs.generation(), now)?;
+    state.store.put_config_fingerprint(&fingerprint)?;
+
+    let forget_after = state.config_drift.config().forget_after_seconds as i64;
+    let mut records = state.store.list_config_fingerprints()?;
+    for record in records
+        .iter()
+        .filter(|r| now - r.published_at > forget_after)
+    {
+        log::info!(
+            "Forgetting config fingerprint of silent replica {}",
+            record.replica
+        );
+        state.store.delete_config_fingerprint(&record.replica)?;
+    }
+    records.retain(|record| now - record.published_at <= forget_after);
+
+    let report = state.config_drift.report(&records, now);
+    if state.config_drift.observe(&report, now) {
+        let divergent: Vec<String> = report
+            .divergent
+            .iter()
+            .map(|replica| {
+                format!(
+                    "{} ({})",
+                    replica.replica,
+                    replica.differing_keys.join(", ")
+                )
+            })
+            .collect();
+        let message = format!(
+            "Replica config differs from the majority for over {}s: {}",
+            state.config_drift.config().alert_after_seconds,
+            divergent.join("; ")
+        );
+        state
+            .monitoring
+            .raise_alert("config_drift", message, 2)
+            .await;
+    }
+    Ok(())
+}
+
+/// Replicas whose effective configuration differs from the majority's, with
+/// the keys that differ
+pub(super) async fn get_config_drift(
+    State(state): State<Arc<ProxyState>>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    if !state.config_drift.is_enabled() {
+        return Ok(Json(serde_json::json!({ "enabled": false })));
+    }
+    let records = state.store.list_config_fingerprints().map_err(|e| {
+        log::error!("Failed to list config fingerprints: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(serde_json::json!({
+        "enabled": true,
+        "report": state
+            .config_drift
+            .report(&records, chrono::Utc::now().timestamp()),
+    })))
+}
+
+/// Timeline of one request, for holders of a support token scoped to its tenant
+pub(super) async fn get_request_journal(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(request_id): Path<Uuid>,
+) -> std::result::Result<Json<request_journal::JournalEntry>, StatusCode> {
+    if !state.request_journal.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let token = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+    let Some(support) = token.and_then(|token| state.request_journal.authenticate(token)) else {
+        state.siem.emit(
+            SecurityEvent::new(
+                SecurityEventKind::AuthFailure,
+                "Request journal read without a valid support token",
+            )
+            .subject(&request_id.to_string()),
+        );
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    // Journals of tenants outside the token's scope are indistinguishable from missing ones
+    let entry = state
+        .request_journal
+        .get(request_id)
+        .filter(|entry| request_journal::may_read(support, entry.tenant.as_deref()))
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    audit(
+        &state,
+        "request_journal.read",
+        &request_id.to_string(),
+        serde_json::json!({ "support_token": support.name, "tenant": entry.tenant }),
+    );
+    Ok(Json(entry))
+}
+
+/// Bootstrap demand forecast and per-tenant noise budget consumption rates
+pub(super) async fn get_noise_trends(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.noise_trends.is_enabled(),
+        "report": state.noise_trends.report(chrono::Utc::now().timestamp()),
+    }))
+}
+
+/// A tenant's consumption windows, baseline and bootstrap forecast
+pub(super) async fn get_tenant_noise_trend(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+) -> std::result::Result<Json<TenantNoiseTrend>, StatusCode> {
+    state
+        .noise_trends
+        .tenant_trend(&tenant, chrono::Utc::now().timestamp())
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Configured transform rules and how often each has been applied
+pub(super) async fn get_transform_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.transforms.is_enabled(),
+        "rules": state.transforms.stats(),
+    }))
+}
+
+/// Mode of every shedding policy, its counters and recent simulated decisions
+pub(super) async fn get_load_shedding(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<LoadSheddingStats> {
+    Json(state.load_shedding.stats())
+}
+
+/// Build and parameter-table fingerprint of every pooled engine
+pub(super) async fn get_engine_fingerprints(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<FingerprintReport> {
+    Json(state.fhe_pool.fingerprint_report().await)
+}
+
+/// Table generation in use, swap history and double-buffering overhead
+pub(super) async fn get_engine_tables(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    let stats = state.fhe_engine.read().await.table_stats();
+    Json(serde_json::json!({
+        "reloading": state.tables_reloading.load(Ordering::Acquire),
+        "tables": stats,
+    }))
+}
+
+/// Members of every failure domain and the domains currently drained
+pub(super) async fn get_failure_domains(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<FailureDomainReport> {
+    Json(state.failure_domains.report(Instant::now()))
+}
+
+/// End a domain's drain before `drain_seconds` is up; admins only
+pub(super) async fn restore_failure_domain(
+    State(state): State<Arc<ProxyState>>,
+    Path((level, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let level = DomainLevel::parse(&level).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let domain = DomainId { level, name };
+    let transition = state
+        .failure_domains
+        .restore(&domain)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    log::info!("{} restored {} before its drain ended", admin, domain);
+    report_domain_transition(&state, &transition).await;
+    apply_domain_drains(&state, Instant::now());
+    Ok(Json(serde_json::json!({
+        "restored": domain,
+        "drained_engines": state.fhe_pool.drained(),
+    })))
+}
+
+/// Recompute the engine's tables in the background and swap them in once
+/// ready; operations keep running on the current tables meanwhile. Admins only.
+pub(super) async fn reload_engine_tables(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if state.tables_reloading.swap(true, Ordering::AcqRel) {
+        return Err(StatusCode::CONFLICT);
+    }
+    let params = state.fhe_engine.read().await.get_params().clone();
+    audit(
+        &state,
+        "engine.tables.reload",
+        "fhe_engine",
+        serde_json::json!({ "admin": admin, "params": params.fingerprint() }),
+    );
+
+    let task_state = state.clone();
+    tokio::spawn(async move {
+        let state = task_state;
+        let started = Instant::now();
+        let computed =
+            tokio::task::spawn_blocking(move || PrecomputedTables::compute(&params)).await;
+        let swapped = match computed {
+            Ok(Ok(tables)) => state
+                .fhe_engine
+                .read()
+                .await
+                .install_tables(tables)
+                .map_err(Error::from),
+            Ok(Err(e)) => Err(Error::from(e)),
+            Err(e) => Err(Error::Internal(format!(
+                "Table computation task failed: {}",
+                e
+            ))),
+        };
+        match swapped {
+            Ok(event) => {
+                log::info!(
+                    "Swapped in engine tables generation {} after {:?} ({} generations retiring)",
+                    event.generation,
+                    started.elapsed(),
+                    event.retiring_generations
+                );
+                audit(
+                    &state,
+                    "engine.tables.swap",
+                    "fhe_engine",
+                    serde_json::to_value(&event).unwrap_or_default(),
+                );
+            }
+            Err(e) => log::error!("Engine table reload failed: {}", e),
+        }
+        state.tables_reloading.store(false, Ordering::Release);
+    });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({ "reloading": true })),
+    ))
+}
+
+/// Per-rule match, rejection and error counters of the scripted rate limits
+pub(super) async fn get_rate_limit_rules(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<Vec<RateLimitRuleStats>> {
+    Json(state.rate_limit_rules.stats())
+}
+
+/// Per-workload request, error and latency counters
+pub(super) async fn get_workload_tags(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<WorkloadTagReport> {
+    Json(state.workload_tags.report())
+}
+
+/// Body of `POST /v1/admin/load-shedding/{policy}`
+#[derive(Debug, Deserialize)]
+pub struct ShedPolicyModeRequest {
+    pub mode: ShedPolicyMode,
+}
+
+/// Switch a shedding policy between simulation and enforcement; admins only
+pub(super) async fn set_load_shedding_mode(
+    State(state): State<Arc<ProxyState>>,
+    Path(policy): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<ShedPolicyModeRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let policy = ShedPolicy::parse(&policy).map_err(|e| {
+        log::warn!("Rejected load shedding mode change: {}", e);
+        StatusCode::NOT_FOUND
+    })?;
+    let previous = state.load_shedding.set_mode(policy, request.mode);
+    log::warn!(
+        "Load shedding policy {} switched from {:?} to {:?}",
+        policy.as_str(),
+        previous,
+        request.mode
+    );
+    audit(
+        &state,
+        "load_shedding.mode",
+        policy.as_str(),
+        serde_json::json!({ "admin": admin, "from": previous, "to": request.mode }),
+    );
+    Ok(Json(serde_json::json!({
+        "policy": policy,
+        "previous_mode": previous,
+        "mode": request.mode,
+    })))
+}
+
+/// Time range to rebuild metrics for after a monitoring outage
+#[derive(Debug, Deserialize)]
+pub struct BackfillRequest {
+    pub from: i64,
+    pub to: i64,
+    /// Defaults to `monitoring.otlp_metrics.backfill_bucket_seconds`
+    pub bucket_seconds: Option<u64>,
+    /// Return the rebuilt metrics without pushing them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Rebuild metrics for a time range from the billing ledger and audit log
+/// and push them to the OTLP collector marked as backfilled; admins only
+pub(super) async fn backfill_metrics(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<BackfillRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let config = state.otlp_metrics.config();
+    let bucket_seconds = request
+        .bucket_seconds
+        .unwrap_or(config.backfill_bucket_seconds);
+    let span = request.to.saturating_sub(request.from);
+    if span <= 0 || span as u64 > config.max_backfill_seconds || bucket_seconds == 0 {
+        log::warn!(
+            "Rejected metrics backfill of [{}, {}) in {}s buckets",
+            request.from,
+            request.to,
+            bucket_seconds
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if !request.dry_run && !state.otlp_metrics.is_enabled() {
+        log::warn!("Metrics backfill needs an OTLP collector; use dry_run to preview");
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let (ledger, audit_log) = state
+        .store
+        .list_billing(request.from, request.to)
+        .and_then(|ledger| Ok((ledger, state.store.list_audit()?)))
+        .map_err(|e| {
+            log::error!("Failed to read billing ledger or audit log: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let report = backfill::reconstruct(
+        &ledger,
+        &audit_log,
+        request.from,
+        request.to,
+        bucket_seconds,
+    );
+
+    let exported = if request.dry_run {
+        0
+    } else {
+        state
+            .otlp_metrics
+            .export_backfill(&report)
+            .await
+            .map_err(|e| {
+                log::error!("Metrics backfill export failed: {}", e);
+                StatusCode::BAD_GATEWAY
+            })?
+    };
+    audit(
+        &state,
+        "metrics.backfill",
+        &format!("{}..{}", request.from, request.to),
+        serde_json::json!({
+            "admin": admin,
+            "bucket_seconds": bucket_seconds,
+            "buckets": report.buckets.len(),
+            "ledger_records": report.ledger_records,
+            "audit_only_records": report.audit_only_records,
+            "dry_run": request.dry_run,
+            "exported": exported,
+        }),
+    );
+    Ok(Json(serde_json::json!({
+        "exported_buckets": exported,
+        "report": report,
+    })))
+}
+
+/// Statistics of the selected subsystems as they stand now
+pub(super) async fn capture_performance(
+    state: &ProxyState,
+    selected: &[PerformanceSubsystem],
+) -> PerformanceSnapshot {
+    let mut snapshot = PerformanceSnapshot::new(chrono::Utc::now().timestamp());
+    let per_provider = |stats: &dyn Fn(&super::LlmProvider) -> serde_json::Value| {
+        state
+            .llm_providers
+            .iter()
+            .map(|(name, provider)| (name.clone(), stats(provider)))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    };
+    for subsystem in selected {
+        let stats = match subsystem {
+            PerformanceSubsystem::Operations => {
+                serde_json::to_value(state.profiler.get_all_stats().await).unwrap()
+            }
+            PerformanceSubsystem::PromptPacking => state.prompt_packer.stats(),
+            PerformanceSubsystem::Allocations => serde_json::json!({
+                "heap": crate::allocator::allocator_stats(),
+                "fragmentation": state.memory.refresh(),
+                "hot_paths": crate::allocator::hot_path_stats(),
+            }),
+            PerformanceSubsystem::Documents => serde_json::json!(state.documents.stats().await),
+            PerformanceSubsystem::LatencyByRegion => {
+                serde_json::json!(state.geo_latency.heatmap().await)
+            }
+            PerformanceSubsystem::ProviderTimeouts => {
+                per_provider(&|provider| serde_json::to_value(provider.timeout_stats()).unwrap())
+            }
+            PerformanceSubsystem::ProviderErrors => {
+                per_provider(&|provider| serde_json::to_value(provider.error_stats()).unwrap())
+            }
+            PerformanceSubsystem::ProviderResume => {
+                per_provider(&|provider| serde_json::to_value(provider.resume_stats()).unwrap())
+            }
+        };
+        snapshot.insert(*subsystem, stats);
+    }
+    snapshot
+}
+
+/// Get performance statistics: a live snapshot, or the recorded snapshots in
+/// a time range, of the selected subsystems as JSON or Prometheus text
+pub(super) async fn get_performance_stats(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<PerformanceQuery>,
+) -> std::result::Result<Response, StatusCode> {
+    let selected = query.selected().map_err(|e| {
+        log::debug!("Rejected performance query: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    let content_type = [(header::CONTENT_TYPE, query.format.content_type())];
+    if !query.is_historical() {
+        let snapshot = capture_performance(&state, &selected).await;
+        return Ok(match query.format {
+            PerformanceFormat::Json => Json(snapshot.to_json()).into_response(),
+            PerformanceFormat::Prometheus => {
+                (content_type, snapshot.to_prometheus()).into_response()
+            }
+        });
+    }
+
+    let samples = state.performance_history.query(&query, &selected);
+    Ok(match query.format {
+        PerformanceFormat::Json => Json(serde_json::json!({
+            "samples": samples
+                .iter()
+                .map(|sample| {
+                    let mut json = sample.to_json();
+                    json["timestamp"] = serde_json::json!(sample.timestamp);
+                    json
+                })
+                .collect::<Vec<_>>(),
+            "retained": state.performance_history.len(),
+        }))
+        .into_response(),
+        // Prometheus accepts one sample per series, so only the newest
+        // snapshot in the range is exposed
+        PerformanceFormat::Prometheus => (
+            content_type,
+            samples
+                .last()
+                .map(PerformanceSnapshot::to_prometheus)
+                .unwrap_or_default(),
+        )
+            .into_response(),
+    })
+}
+
+/// Get the most recent administrative audit records
+pub(super) async fn get_audit_log(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<AuditQuery>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let records = state
+        .store
+        .recent_audit(query.limit.unwrap_or(100).min(1000))
+        .map_err(|e| {
+            log::error!("Failed to read audit log: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(serde_json::json!({
+        "backend": state.store.name(),
+        "records": records
+    })))
+}
+
+/// Get resource guard state and action history
+pub(super) async fn get_resource_guard_status(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.config.scaling.resource_guard.enabled,
+        "draining": state.resource_guard.is_draining(),
+        "last_sample": state.resource_guard.last_sample().await,
+        "actions": state.monitoring.get_guard_actions().await
+    }))
+}
+
+/// Per-SLA-class request latency and rejection metrics
+pub(super) async fn get_sla_metrics(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "classes": state.sla_metrics.get_stats().await,
+        "in_flight": state.admission.in_flight(),
+        "queue_projection": state.queue_projector.project(state.admission.in_flight()),
+        "capacity": state.admission.capacity()
+    }))
+}
+
+/// Current queue projection, so clients can size their backoff before sending
+pub(super) async fn get_queue_projection(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Query(query): Query<QueueProjectionQuery>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let deadline = match query.deadline_ms {
+        Some(ms) => Duration::from_millis(ms),
+        None => request_deadline(&state, &headers).map_err(|e| {
+            log::warn!("Rejected request deadline header: {}", e);
+            StatusCode::BAD_REQUEST
+        })?,
+    };
+    let projection = state.queue_projector.project(state.admission.in_flight());
+    let retry_after = projection.retry_after(deadline);
+
+    Ok(Json(serde_json::json!({
+        "enabled": state.queue_projector.is_enabled(),
+        "capacity": state.admission.capacity(),
+        "deadline_ms": deadline.as_millis() as u64,
+        "would_admit": retry_after.is_none(),
+        "retry_after_seconds": retry_after,
+        "projection": projection
+    })))
+}
+
+/// Security event export counters
+pub(super) async fn get_siem_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::to_value(state.siem.stats()).unwrap())
+}
+
+/// Request latency by client region, as bucket counts per region
+pub(super) async fn get_latency_heatmap(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.geo_latency.is_enabled(),
+        "heatmap": state.geo_latency.heatmap().await
+    }))
+}
+
+/// HSM delegation counts, oracle latency and fallbacks taken
+pub(super) async fn get_decryption_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "decryption_delegation": state.decryption.stats() }))
+}
+
+/// Open response streams and how often they were throttled
+pub(super) async fn get_stream_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "streams": state.streams.stats() }))
+}
+
+/// Overflow queue depth, bytes on disk and re-injection lag
+pub(super) async fn get_overflow_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "overflow": state.overflow.stats() }))
+}
+
+/// OTLP metrics exporter state: collector reachability, buffered and dropped samples
+pub(super) async fn get_otlp_export_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "otlp_metrics": state.otlp_metrics.stats() }))
+}
+
+/// Counts of requests mirrored to staging, dropped and failed
+pub(super) async fn get_mirroring_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "mirroring": state.mirror.stats() }))
+}
+
+/// Open connections and how often each connection protection has tripped
+pub(super) async fn get_connection_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<ConnectionGuardStats> {
+    Json(state.connection_guard.stats())
+}
+
+/// Measured host clock skew and the NTP servers' answers
+pub(super) async fn get_clock_status(State(state): State<Arc<ProxyState>>) -> Json<ClockStatus> {
+    Json(state.clock.status())
+}
+
+/// Latency, success and SLO status of the synthetic probes, per target
+pub(super) async fn get_probe_report(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "probes": state.probes.report() }))
+}
+
+/// Sampled request metadata, aggregated by route, model, size and tier
+pub(super) async fn get_telemetry_report(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "telemetry": state.telemetry.report() }))
+}
+
+/// Most recent sampled spans, newest first
+pub(super) async fn get_sampled_traces(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<AuditQuery>,
+) -> Json<serde_json::Value> {
+    let limit = query.limit.unwrap_or(100).min(1000);
+    Json(serde_json::json!({
+        "sampling": state.trace_sampler.stats(),
+        "spans": state.trace_sampler.recent_spans(limit),
+    }))
+}
+
+pub(super) async fn get_prompt_lint_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "tenants": state.prompt_lint.stats() }))
+}
+
+/// Timing and rejection counts of each request validator, in chain order
+pub(super) async fn get_validation_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "validators": state.validators.stats() }))
+}
+
+/// Supervised background tasks, their restart counts and last panic
+pub(super) async fn get_task_states(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "tasks": state.tasks.tasks() }))
+}
+
+/// Applied and pending storage migrations, and what the startup run did
+pub(super) async fn get_migration_status(
+    State(state): State<Arc<ProxyState>>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let status = migrations::status(state.store.as_ref()).map_err(|e| {
+        log::error!("Failed to read storage migration status: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(serde_json::json!({
+        "backend": state.store.name(),
+        "status": status,
+        "last_run": state.migration_report
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::proxy::ProxyServer;
+
+    #[tokio::test]
+    async fn test_performance_ranges_come_from_recorded_history() {
+        let state = ProxyServer::new(Config::default()).unwrap().state;
+        for timestamp in [100, 200, 300] {
+            let mut snapshot = capture_performance(&state, &PerformanceSubsystem::ALL).await;
+            snapshot.timestamp = timestamp;
+            state.performance_history.record(snapshot);
+        }
+
+        let query = PerformanceQuery {
+            subsystems: Some("prompt_packing".to_string()),
+            since: Some(150),
+            ..Default::default()
+        };
+        let response = get_performance_stats(State(state.clone()), Query(query))
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(page["retained"], 3);
+        let samples = page["samples"].as_array().unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0]["timestamp"], 200);
+        assert_eq!(samples[1]["prompt_packing"]["packed_prompts"], 0);
+        assert!(samples[1].get("allocations").is_none());
+
+        let query = PerformanceQuery {
+            subsystems: Some("prompt_packing".to_string()),
+            limit: Some(1),
+            format: PerformanceFormat::Prometheus,
+            ..Default::default()
+        };
+        let response = get_performance_stats(State(state), Query(query))
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains("fhe_performance_prompt_packing{path=\"packed_prompts\"} 0 300000\n"));
+    }
+}
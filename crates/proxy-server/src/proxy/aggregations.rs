@@ -0,0 +1,273 @@
+//! Encrypted aggregation over batch outputs
+
+use super::approvals::enforce_approval;
+use super::ciphertexts::{decryption_status, requires_delegated_decryption};
+use super::{audit, tenant_id, ProxyState};
+use crate::aggregation::AggregationOperation;
+use crate::error::Error;
+use crate::persistence::BatchJobStatus;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Aggregate encrypted numbers from cached ciphertexts and completed batch jobs
+#[derive(Debug, Deserialize)]
+pub struct AggregationRequest {
+    pub operation: AggregationOperation,
+    #[serde(default)]
+    pub ciphertext_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub batch_job_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateDecryptRequest {
+    pub client_id: Uuid,
+}
+
+/// Sum or average encrypted numbers without decrypting any of them
+pub(super) async fn create_aggregation(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<AggregationRequest>,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    if !state.aggregation.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    // Results and decryption quotas belong to a tenant
+    let tenant = tenant_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let mut ids = request.ciphertext_ids;
+    for job_id in &request.batch_job_ids {
+        let job = state
+            .store
+            .get_batch_job(*job_id)
+            .map_err(|e| {
+                log::error!("Failed to load batch job {}: {}", job_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .filter(|job| job.tenant == tenant)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        match (job.status, job.result_ciphertext_id) {
+            (BatchJobStatus::Completed, Some(result_id)) => ids.push(result_id),
+            (BatchJobStatus::Completed, None) => ids.extend(
+                job.items
+                    .iter()
+                    .filter_map(|item| item.result_ciphertext_id),
+            ),
+            _ => return Err(StatusCode::CONFLICT),
+        }
+    }
+    enforce_approval(&state, &headers, &ids)?;
+    let items = {
+        let cache = state.ciphertext_cache.read().await;
+        ids.iter()
+            .map(|id| cache.get(id).cloned())
+            .collect::<Option<Vec<_>>>()
+            .ok_or(StatusCode::NOT_FOUND)?
+    };
+
+    let result = {
+        let fhe_engine = state.fhe_engine.read().await;
+        state
+            .aggregation
+            .aggregate(&fhe_engine, tenant, request.operation, &items)
+    };
+    let record = result.map_err(|e| {
+        log::warn!("Aggregation rejected: {}", e);
+        match e {
+            Error::Validation(_) => StatusCode::BAD_REQUEST,
+            Error::ParamMismatch(_) => StatusCode::CONFLICT,
+            Error::Fhe(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    })?;
+    audit(
+        &state,
+        "aggregation.create",
+        &record.id.to_string(),
+        serde_json::json!({
+            "tenant": tenant,
+            "operation": record.operation,
+            "item_count": record.item_count,
+        }),
+    );
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::to_value(&record).unwrap()),
+    ))
+}
+
+/// An aggregate result's metadata; only visible to the tenant that computed it
+pub(super) async fn get_aggregation(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let tenant = tenant_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let record = state
+        .aggregation
+        .get(tenant, id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::to_value(&record).unwrap()))
+}
+
+/// Decrypt an aggregate result against the tenant's daily quota
+pub(super) async fn decrypt_aggregation(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(request): Json<AggregateDecryptRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let tenant = tenant_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let record = state
+        .aggregation
+        .get(tenant, id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let required = requires_delegated_decryption(&state, &headers)?;
+
+    let now = chrono::Utc::now();
+    let quota = state
+        .aggregation
+        .charge_decryption(tenant, now)
+        .map_err(|e| {
+            log::warn!("Aggregate decryption refused: {}", e);
+            StatusCode::TOO_MANY_REQUESTS
+        })?;
+    let value = match state
+        .decryption
+        .decrypt(
+            &state.fhe_engine,
+            request.client_id,
+            &record.ciphertext,
+            required,
+        )
+        .await
+        .and_then(|(plaintext, path)| {
+            plaintext
+                .parse::<f64>()
+                .map(|value| (value, path))
+                .map_err(|_| Error::Fhe("Aggregate did not decrypt to a number".to_string()))
+        }) {
+        Ok(value) => value,
+        Err(e) => {
+            log::error!("Aggregate decryption failed: {}", e);
+            state.aggregation.refund_decryption(tenant, now);
+            return Err(decryption_status(&e));
+        }
+    };
+    state.metrics.increment_decryptions();
+    audit(
+        &state,
+        "aggregation.decrypt",
+        &id.to_string(),
+        serde_json::json!({ "tenant": tenant, "decrypted_by": value.1 }),
+    );
+
+    Ok(Json(serde_json::json!({
+        "aggregation_id": id,
+        "operation": record.operation,
+        "item_count": record.item_count,
+        "value": value.0,
+        "decrypted_by": value.1,
+        "decryptions_remaining": quota.daily_limit - quota.used,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::proxy::ProxyServer;
+
+    #[tokio::test]
+    async fn test_aggregation_decrypts_only_results_within_quota() {
+        let mut config = Config::default();
+        config.aggregation.enabled = true;
+        config.aggregation.min_items = 3;
+        config.aggregation.daily_decryptions_per_tenant = 1;
+        let state = ProxyServer::new(config).unwrap().state;
+
+        let (client_id, mut ids) = {
+            let mut engine = state.fhe_engine.write().await;
+            let (client_id, _) = engine.generate_keys().unwrap();
+            let mut cache = state.ciphertext_cache.write().await;
+            let ids: Vec<Uuid> = [0.2, 0.9, 0.4, 0.5]
+                .iter()
+                .map(|score| {
+                    let ciphertext = engine.encrypt_value(client_id, *score).unwrap();
+                    let id = ciphertext.id;
+                    cache.insert(id, ciphertext);
+                    id
+                })
+                .collect();
+            (client_id, ids)
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+
+        let (status, Json(created)) = create_aggregation(
+            State(state.clone()),
+            headers.clone(),
+            Json(AggregationRequest {
+                operation: AggregationOperation::Mean,
+                ciphertext_ids: ids.clone(),
+                batch_job_ids: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["item_count"], 4);
+        let id: Uuid = created["id"].as_str().unwrap().parse().unwrap();
+        // The result is not in the shared cache, so /v1/decrypt cannot reach it
+        assert!(!state.ciphertext_cache.read().await.contains_key(&id));
+        assert_eq!(
+            get_aggregation(State(state.clone()), HeaderMap::new(), Path(id))
+                .await
+                .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let Json(decrypted) = decrypt_aggregation(
+            State(state.clone()),
+            headers.clone(),
+            Path(id),
+            Json(AggregateDecryptRequest { client_id }),
+        )
+        .await
+        .unwrap();
+        assert!((decrypted["value"].as_f64().unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(decrypted["decryptions_remaining"], 0);
+        assert_eq!(
+            decrypt_aggregation(
+                State(state.clone()),
+                headers.clone(),
+                Path(id),
+                Json(AggregateDecryptRequest { client_id }),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        // Too few items to aggregate
+        ids.truncate(2);
+        let rejected = create_aggregation(
+            State(state.clone()),
+            headers,
+            Json(AggregationRequest {
+                operation: AggregationOperation::Sum,
+                ciphertext_ids: ids,
+                batch_job_ids: Vec::new(),
+            }),
+        )
+        .await;
+        assert_eq!(rejected.unwrap_err(), StatusCode::BAD_REQUEST);
+    }
+}
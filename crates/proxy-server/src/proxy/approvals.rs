@@ -0,0 +1,240 @@
+//! Approval holds on decryption of sensitive results
+
+use super::{audit, tenant_id, ProcessRequest, ProxyState};
+use crate::approvals::{ApprovalRequest, APPROVER_TOKEN_HEADER};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// An approver's decision on an approval request
+#[derive(Debug, Default, Deserialize)]
+pub struct ApprovalDecision {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Sensitivity class of a request whose result must be held for approval
+pub(super) fn approval_class(state: &ProxyState, request: &ProcessRequest) -> Option<String> {
+    request
+        .sensitivity
+        .clone()
+        .filter(|class| state.approvals.requires_approval(class))
+}
+
+/// Hold a result's ciphertexts under a new approval request and notify approvers
+pub(super) async fn hold_for_approval(
+    state: &ProxyState,
+    tenant: Option<&str>,
+    class: &str,
+    ciphertext_ids: Vec<Uuid>,
+) -> ApprovalRequest {
+    {
+        let cache = state.ciphertext_cache.read().await;
+        state.approvals.prune(|id| cache.contains_key(id));
+    }
+    let request = state.approvals.hold(
+        tenant,
+        class,
+        ciphertext_ids,
+        chrono::Utc::now().timestamp(),
+    );
+    notify_approvers(state, "approval.request", &request);
+    request
+}
+
+/// Refuse decryption of held ciphertexts, or any work over them, without a
+/// current approval, and audit every use an approval allows
+pub(super) fn enforce_approval(
+    state: &ProxyState,
+    headers: &HeaderMap,
+    ciphertext_ids: &[Uuid],
+) -> std::result::Result<(), StatusCode> {
+    if !state.approvals.is_enabled() {
+        return Ok(());
+    }
+    let tenant = tenant_id(headers);
+    let now = chrono::Utc::now().timestamp();
+    let mut released = BTreeSet::new();
+    for ciphertext_id in ciphertext_ids {
+        match state.approvals.check(*ciphertext_id, tenant, now) {
+            Ok(Some(request)) => {
+                released.insert(request.id);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::warn!("Refusing use of held result: {}", e);
+                audit(
+                    state,
+                    "approval.blocked",
+                    &ciphertext_id.to_string(),
+                    serde_json::json!({ "tenant": tenant, "reason": e.to_string() }),
+                );
+                return Err(StatusCode::FORBIDDEN);
+            }
+        }
+    }
+    for request_id in released {
+        audit(
+            state,
+            "approval.release",
+            &request_id.to_string(),
+            serde_json::json!({ "tenant": tenant, "ciphertext_ids": ciphertext_ids }),
+        );
+    }
+    Ok(())
+}
+
+/// Held ciphertexts whose bytes `data` copies, so a held result resubmitted
+/// as a document or batch item stays held
+pub(super) async fn held_copies(state: &ProxyState, data: &[u8]) -> Vec<Uuid> {
+    if !state.approvals.is_enabled() {
+        return Vec::new();
+    }
+    let cache = state.ciphertext_cache.read().await;
+    state
+        .approvals
+        .held()
+        .into_iter()
+        .filter(|id| cache.get(id).is_some_and(|ct| ct.data == data))
+        .collect()
+}
+
+/// Audit an approval request or decision and send it to the approvers' webhook
+fn notify_approvers(state: &ProxyState, action: &str, request: &ApprovalRequest) {
+    audit(
+        state,
+        action,
+        &request.id.to_string(),
+        serde_json::json!(request),
+    );
+
+    let Some(url) = state.config.approvals.webhook_url.clone() else {
+        return;
+    };
+    let payload = serde_json::json!({
+        "event": action,
+        "request": request,
+        "occurred_at": chrono::Utc::now(),
+    });
+    tokio::spawn(async move {
+        let result = HttpClient::new()
+            .post(&url)
+            .timeout(Duration::from_secs(10))
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            log::warn!("Approval webhook to {} failed: {}", url, e);
+        }
+    });
+}
+
+/// The approver named by the request's approver token
+fn approver_name(
+    state: &ProxyState,
+    headers: &HeaderMap,
+) -> std::result::Result<String, StatusCode> {
+    if !state.approvals.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let token = headers
+        .get(APPROVER_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    state
+        .approvals
+        .approver(token)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            log::warn!("Rejected unknown approver token");
+            StatusCode::FORBIDDEN
+        })
+}
+
+/// Approval requests awaiting a decision; approvers only
+pub(super) async fn list_approvals(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<Vec<ApprovalRequest>>, StatusCode> {
+    approver_name(&state, &headers)?;
+    Ok(Json(
+        state.approvals.pending(chrono::Utc::now().timestamp()),
+    ))
+}
+
+/// One approval request, for approvers and the tenant that made it
+pub(super) async fn get_approval(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> std::result::Result<Json<ApprovalRequest>, StatusCode> {
+    if !state.approvals.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let request = state
+        .approvals
+        .get(id, chrono::Utc::now().timestamp())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let is_approver =
+        headers.contains_key(APPROVER_TOKEN_HEADER) && approver_name(&state, &headers).is_ok();
+    if !is_approver && request.tenant.as_deref() != tenant_id(&headers) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(request))
+}
+
+pub(super) async fn approve_decryption(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(decision): Json<ApprovalDecision>,
+) -> std::result::Result<Json<ApprovalRequest>, StatusCode> {
+    decide_approval(&state, &headers, id, true, decision)
+}
+
+pub(super) async fn deny_decryption(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(decision): Json<ApprovalDecision>,
+) -> std::result::Result<Json<ApprovalRequest>, StatusCode> {
+    decide_approval(&state, &headers, id, false, decision)
+}
+
+fn decide_approval(
+    state: &ProxyState,
+    headers: &HeaderMap,
+    id: Uuid,
+    approve: bool,
+    decision: ApprovalDecision,
+) -> std::result::Result<Json<ApprovalRequest>, StatusCode> {
+    let approver = approver_name(state, headers)?;
+    let now = chrono::Utc::now().timestamp();
+    state.approvals.get(id, now).ok_or(StatusCode::NOT_FOUND)?;
+    let request = state
+        .approvals
+        .decide(id, &approver, approve, decision.reason, now)
+        .map_err(|e| {
+            log::warn!("Approval decision by {} rejected: {}", approver, e);
+            StatusCode::CONFLICT
+        })?;
+    notify_approvers(
+        state,
+        if approve {
+            "approval.approve"
+        } else {
+            "approval.deny"
+        },
+        &request,
+    );
+    Ok(Json(request))
+}
@@ -0,0 +1,935 @@
+//! Batch jobs of encrypted completions and the windows they run in
+
+use super::approvals::{enforce_approval, held_copies};
+use super::completions::enforce_sandbox_scope;
+use super::identity::admin_name;
+use super::{audit, tenant_id, ProxyState};
+use crate::config::BatchWindowConfig;
+use crate::dead_letter::{self};
+use crate::error::{Error, Result};
+use crate::fhe::Ciphertext;
+use crate::persistence::{
+    BatchItemError, BatchItemRecord, BatchItemStatus, BatchJobRecord, BatchJobStatus,
+    DeadLetterAttempt,
+};
+use crate::sandbox::SandboxGrant;
+use crate::validation::RequestContext;
+use axum::{
+    extract::{Extension, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use base64::prelude::*;
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Low-priority prompt to run in the next off-peak batch window
+#[derive(Debug, Deserialize)]
+pub struct BatchJobRequest {
+    #[serde(default)]
+    pub encrypted_data: String, // Base64 encoded
+    /// Base64 encoded prompts run as items of one job, instead of `encrypted_data`
+    #[serde(default)]
+    pub items: Vec<String>,
+    pub provider: String,
+    pub model: String,
+    /// Fingerprint of the parameters the client negotiated via `/v1/params/negotiate`
+    pub params_hash: Option<String>,
+}
+
+/// Failed items of a batch job to run again; empty retries every failed item
+/// whose error is retryable
+#[derive(Debug, Default, Deserialize)]
+pub struct BatchRetryRequest {
+    #[serde(default)]
+    pub items: Vec<BatchRetryItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRetryItem {
+    pub retry_token: String,
+    /// Replacement prompt, base64 encoded, for an item whose payload was at fault
+    pub encrypted_data: Option<String>,
+}
+
+/// Start queued batch jobs, oldest first, if a window is open; drop finished
+/// jobs past their retention either way
+pub(super) async fn run_batch_window(state: &Arc<ProxyState>) {
+    let jobs = match state.store.list_batch_jobs() {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            log::error!("Failed to load batch jobs: {}", e);
+            return;
+        }
+    };
+
+    let now = chrono::Utc::now();
+    let config = state.batch_windows.config();
+    let retained_after = now.timestamp() - config.job_retention_seconds as i64;
+    for job in jobs
+        .iter()
+        .filter(|job| job.status.is_finished() && job.updated_at < retained_after)
+    {
+        if let Err(e) = state.store.delete_batch_job(job.id) {
+            log::warn!("Failed to remove batch job {}: {}", job.id, e);
+        }
+    }
+
+    let Some(window) = state.batch_windows.active_window(now) else {
+        return;
+    };
+    for job in jobs
+        .into_iter()
+        .filter(|job| job.status == BatchJobStatus::Queued)
+        .take(config.max_jobs_per_tick)
+    {
+        run_batch_job(state, job, &window).await;
+    }
+}
+
+async fn run_batch_job(state: &Arc<ProxyState>, mut job: BatchJobRecord, window: &str) {
+    job.status = BatchJobStatus::Running;
+    job.updated_at = chrono::Utc::now().timestamp();
+    if let Err(e) = state.store.put_batch_job(&job) {
+        log::error!("Failed to start batch job {}: {}", job.id, e);
+        return;
+    }
+
+    let dead_letter_id = if job.items.is_empty() {
+        run_single_batch_job(state, &mut job, window).await
+    } else {
+        run_batch_items(state, &mut job).await;
+        None
+    };
+    if let Err(e) = state.store.put_batch_job(&job) {
+        log::error!("Failed to record batch job {}: {}", job.id, e);
+    }
+
+    let details = serde_json::json!({
+        "tenant": job.tenant,
+        "window": window,
+        "result_ciphertext_id": job.result_ciphertext_id,
+        "error": job.error,
+        "dead_letter_id": dead_letter_id,
+        "items": batch_item_counts(&job),
+    });
+    audit(
+        state,
+        &format!("batch.{}", job.status.as_str()),
+        &job.id.to_string(),
+        details,
+    );
+
+    if let Some(url) = state
+        .batch_windows
+        .config()
+        .notification_webhook_url
+        .clone()
+    {
+        let payload = serde_json::json!({
+            "event": format!("batch_job_{}", job.status.as_str()),
+            "job_id": job.id,
+            "tenant": job.tenant,
+            "window": window,
+            "result_ciphertext_id": job.result_ciphertext_id,
+            "error": job.error,
+            "items": batch_item_counts(&job),
+            "finished_at": chrono::Utc::now(),
+        });
+        tokio::spawn(async move {
+            let result = HttpClient::new()
+                .post(&url)
+                .timeout(Duration::from_secs(10))
+                .json(&payload)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                log::warn!("Batch notification webhook to {} failed: {}", url, e);
+            }
+        });
+    }
+}
+
+/// Run a single-prompt job, dead-lettering it if every attempt fails; returns
+/// the dead letter's id
+async fn run_single_batch_job(
+    state: &Arc<ProxyState>,
+    job: &mut BatchJobRecord,
+    window: &str,
+) -> Option<Uuid> {
+    let mut attempts = Vec::new();
+    let result = loop {
+        let started_at = chrono::Utc::now().timestamp();
+        let started = Instant::now();
+        let error = match process_batch_job(state, job).await {
+            Ok(result_id) => break Ok(result_id),
+            Err(e) => e,
+        };
+        let attempt = attempts.len() as u32 + 1;
+        log::warn!("Batch job {} attempt {} failed: {}", job.id, attempt, error);
+        attempts.push(DeadLetterAttempt {
+            attempt,
+            started_at,
+            duration_ms: started.elapsed().as_millis() as u64,
+            error: error.to_string(),
+        });
+        if !state.dead_letters.should_retry(attempt, &error) {
+            break Err(error);
+        }
+        tokio::time::sleep(state.dead_letters.backoff(attempt)).await;
+    };
+    job.updated_at = chrono::Utc::now().timestamp();
+    // The prompt is no longer needed once the job has run, except by a dead letter
+    let payload = std::mem::take(&mut job.ciphertext);
+    let mut dead_letter_id = None;
+    match result {
+        Ok(result_id) => {
+            job.status = BatchJobStatus::Completed;
+            job.result_ciphertext_id = Some(result_id);
+        }
+        Err(e) => {
+            log::error!(
+                "Batch job {} failed after {} attempts: {}",
+                job.id,
+                attempts.len(),
+                e
+            );
+            state.metrics.increment_errors();
+            job.status = BatchJobStatus::Failed;
+            job.error = Some(e.to_string());
+            let letter = dead_letter::batch_job_letter(job, payload, attempts, window);
+            match state.store.put_dead_letter(&letter) {
+                Ok(()) => {
+                    dead_letter_id = Some(letter.id);
+                    audit(
+                        state,
+                        "dlq.add",
+                        &letter.id.to_string(),
+                        serde_json::json!({
+                            "source": letter.source,
+                            "item_id": letter.item_id,
+                            "tenant": letter.tenant,
+                            "attempts": letter.attempts.len(),
+                        }),
+                    );
+                }
+                Err(e) => log::error!("Failed to dead-letter batch job {}: {}", job.id, e),
+            }
+        }
+    }
+    dead_letter_id
+}
+
+/// Run the pending items of a multi-item job, each with its own retries, so a
+/// failing item leaves the results of the others standing. Failed items keep
+/// their prompt and get a retry token instead of being dead-lettered.
+async fn run_batch_items(state: &ProxyState, job: &mut BatchJobRecord) {
+    for index in 0..job.items.len() {
+        if job.items[index].status != BatchItemStatus::Pending {
+            continue;
+        }
+        let mut attempt = 0;
+        let result = loop {
+            attempt += 1;
+            let error = match process_batch_ciphertext(
+                state,
+                &job.tenant,
+                &job.items[index].ciphertext,
+                &job.params_hash,
+            )
+            .await
+            {
+                Ok(result_id) => break Ok(result_id),
+                Err(e) => e,
+            };
+            log::warn!(
+                "Batch job {} item {} attempt {} failed: {}",
+                job.id,
+                index,
+                attempt,
+                error
+            );
+            if !state.dead_letters.should_retry(attempt, &error) {
+                break Err(error);
+            }
+            tokio::time::sleep(state.dead_letters.backoff(attempt)).await;
+        };
+
+        let item = &mut job.items[index];
+        item.attempts += attempt;
+        match result {
+            Ok(result_id) => {
+                item.status = BatchItemStatus::Succeeded;
+                item.result_ciphertext_id = Some(result_id);
+                item.ciphertext.clear();
+            }
+            Err(e) => {
+                state.metrics.increment_errors();
+                item.status = BatchItemStatus::Failed;
+                item.error = Some(BatchItemError {
+                    kind: e.category().to_string(),
+                    message: e.to_string(),
+                    retryable: dead_letter::is_retryable(&e),
+                });
+                item.retry_token = Some(Uuid::new_v4().simple().to_string());
+            }
+        }
+        // Progress is visible while the job runs and survives a restart
+        job.updated_at = chrono::Utc::now().timestamp();
+        if let Err(e) = state.store.put_batch_job(job) {
+            log::error!("Failed to record batch job {} progress: {}", job.id, e);
+        }
+    }
+
+    let failed = job
+        .items
+        .iter()
+        .filter(|item| item.status == BatchItemStatus::Failed)
+        .count();
+    job.status = match failed {
+        0 => BatchJobStatus::Completed,
+        failed if failed == job.items.len() => BatchJobStatus::Failed,
+        _ => BatchJobStatus::PartiallyCompleted,
+    };
+    job.error = (failed > 0).then(|| format!("{} of {} items failed", failed, job.items.len()));
+    if failed > 0 {
+        log::warn!(
+            "Batch job {}: {} of {} items failed",
+            job.id,
+            failed,
+            job.items.len()
+        );
+    }
+}
+
+/// Succeeded and failed item counts of a multi-item job
+fn batch_item_counts(job: &BatchJobRecord) -> Option<serde_json::Value> {
+    if job.items.is_empty() {
+        return None;
+    }
+    let count = |status| job.items.iter().filter(|i| i.status == status).count();
+    Some(serde_json::json!({
+        "total": job.items.len(),
+        "succeeded": count(BatchItemStatus::Succeeded),
+        "failed": count(BatchItemStatus::Failed),
+    }))
+}
+
+async fn process_batch_job(state: &ProxyState, job: &BatchJobRecord) -> Result<Uuid> {
+    process_batch_ciphertext(state, &job.tenant, &job.ciphertext, &job.params_hash).await
+}
+
+/// Run one queued prompt encrypted under `params_hash`, caching the result
+async fn process_batch_ciphertext(
+    state: &ProxyState,
+    tenant: &str,
+    ciphertext: &str,
+    params_hash: &str,
+) -> Result<Uuid> {
+    let data = BASE64_STANDARD
+        .decode(ciphertext)
+        .map_err(|e| Error::Validation(format!("Invalid batch ciphertext: {}", e)))?;
+    let owners = state.key_policies.owners_of_data(&data).await;
+    let fhe_engine = state.fhe_engine.read().await;
+    let params = fhe_engine.get_params().clone();
+    // The server may have moved to new parameters since the job was queued
+    if params_hash != params.fingerprint() {
+        return Err(Error::ParamMismatch(format!(
+            "Job was encrypted under parameters {} the server no longer uses",
+            params_hash
+        )));
+    }
+
+    let output = fhe_engine.process_encrypted_prompt(&Ciphertext {
+        id: Uuid::new_v4(),
+        data,
+        params,
+        noise_budget: None,
+    })?;
+    drop(fhe_engine);
+
+    let result_id = output.id;
+    state.key_policies.record_derived(&output, &owners).await;
+    state
+        .ciphertext_cache
+        .write()
+        .await
+        .insert(result_id, output);
+    state
+        .offboarding
+        .record_ciphertexts((!tenant.is_empty()).then_some(tenant), [result_id]);
+    Ok(result_id)
+}
+
+/// Batch job as returned to clients, without the queued prompt
+pub(super) fn batch_job_view(job: &BatchJobRecord) -> serde_json::Value {
+    let mut view = serde_json::to_value(job).unwrap_or_default();
+    if let Some(fields) = view.as_object_mut() {
+        fields.remove("ciphertext");
+        if let Some(items) = fields.get_mut("items").and_then(|i| i.as_array_mut()) {
+            for item in items.iter_mut().filter_map(|i| i.as_object_mut()) {
+                item.remove("ciphertext");
+            }
+        }
+    }
+    view
+}
+
+/// Queue a low-priority prompt for the next off-peak window at the discounted quota rate
+pub(super) async fn submit_batch_job(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    sandbox: Option<Extension<SandboxGrant>>,
+    Json(request): Json<BatchJobRequest>,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    if !state.batch_windows.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    // Quota is metered per tenant
+    let tenant = tenant_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let tenant_config = state
+        .tenant_configs
+        .resolve(tenant)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let (model, _) = tenant_config.govern_model(&request.model).map_err(|e| {
+        log::warn!("Model {} rejected for batch job: {}", request.model, e);
+        StatusCode::FORBIDDEN
+    })?;
+    enforce_sandbox_scope(sandbox.as_deref(), &model, &request.provider)?;
+
+    if request.items.is_empty() == request.encrypted_data.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if request.items.len() > state.batch_windows.config().max_items_per_job {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let prompts = if request.items.is_empty() {
+        std::slice::from_ref(&request.encrypted_data)
+    } else {
+        request.items.as_slice()
+    };
+    // Validators see the largest prompt; the nonce covers the whole job
+    let mut ciphertext_bytes = 0;
+    for prompt in prompts {
+        let data = BASE64_STANDARD
+            .decode(prompt)
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        enforce_approval(&state, &headers, &held_copies(&state, &data).await)?;
+        ciphertext_bytes = ciphertext_bytes.max(data.len());
+    }
+    let server_params_hash = state.fhe_engine.read().await.get_params().fingerprint();
+    let validation_context = RequestContext {
+        tenant: Some(tenant),
+        provider: &request.provider,
+        model: &model,
+        template_id: None,
+        ciphertext_bytes,
+        params_hash: request.params_hash.as_deref(),
+        server_params_hash: &server_params_hash,
+        nonce: headers.get("x-request-nonce").and_then(|v| v.to_str().ok()),
+    };
+    if let Err(rejection) = state.validators.validate(&validation_context) {
+        log::warn!(
+            "Batch job rejected by validator {}: {}",
+            rejection.validator,
+            rejection.reason
+        );
+        return Err(StatusCode::from_u16(rejection.status).unwrap_or(StatusCode::BAD_REQUEST));
+    }
+
+    let now = chrono::Utc::now();
+    let quota_units = state
+        .batch_windows
+        .charge(tenant, prompts.len(), now)
+        .map_err(|e| {
+            log::warn!("Batch job rejected: {}", e);
+            StatusCode::TOO_MANY_REQUESTS
+        })?;
+    let items = request
+        .items
+        .into_iter()
+        .enumerate()
+        .map(|(index, ciphertext)| BatchItemRecord {
+            index,
+            ciphertext,
+            status: BatchItemStatus::Pending,
+            attempts: 0,
+            result_ciphertext_id: None,
+            error: None,
+            retry_token: None,
+        })
+        .collect::<Vec<_>>();
+    let job = BatchJobRecord {
+        id: Uuid::new_v4(),
+        tenant: tenant.to_string(),
+        provider: request.provider,
+        model,
+        ciphertext: request.encrypted_data,
+        params_hash: server_params_hash,
+        quota_units,
+        status: BatchJobStatus::Queued,
+        submitted_at: now.timestamp(),
+        updated_at: now.timestamp(),
+        result_ciphertext_id: None,
+        error: None,
+        items,
+    };
+    if let Err(e) = state.store.put_batch_job(&job) {
+        log::error!("Failed to queue batch job {}: {}", job.id, e);
+        state.batch_windows.refund(tenant, quota_units, now);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    audit(
+        &state,
+        "batch.submit",
+        &job.id.to_string(),
+        serde_json::json!({
+            "tenant": tenant,
+            "model": job.model,
+            "items": job.items.len(),
+            "quota_units": quota_units,
+        }),
+    );
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({
+            "job_id": job.id,
+            "status": job.status,
+            "items": job.items.len(),
+            "quota_units": quota_units,
+            "next_window_start": state.batch_windows.next_window_start(now),
+            "status_url": format!("/v1/batch/jobs/{}", job.id),
+        })),
+    ))
+}
+
+/// The calling tenant's batch jobs and today's quota usage
+pub(super) async fn list_batch_jobs(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let tenant = tenant_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let jobs = state.store.list_batch_jobs().map_err(|e| {
+        log::error!("Failed to list batch jobs: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let now = chrono::Utc::now();
+    Ok(Json(serde_json::json!({
+        "jobs": jobs
+            .iter()
+            .filter(|job| job.tenant == tenant)
+            .map(batch_job_view)
+            .collect::<Vec<_>>(),
+        "quota": state.batch_windows.usage(tenant, now),
+        "next_window_start": state.batch_windows.next_window_start(now),
+    })))
+}
+
+/// Status of a batch job; only visible to the submitting tenant
+pub(super) async fn get_batch_job(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(job_id): Path<Uuid>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let job = state
+        .store
+        .get_batch_job(job_id)
+        .map_err(|e| {
+            log::error!("Failed to load batch job {}: {}", job_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .filter(|job| Some(job.tenant.as_str()) == tenant_id(&headers))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(batch_job_view(&job)))
+}
+
+/// Cancel a job that has not started yet and refund its quota
+pub(super) async fn cancel_batch_job(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(job_id): Path<Uuid>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let mut job = state
+        .store
+        .get_batch_job(job_id)
+        .map_err(|e| {
+            log::error!("Failed to load batch job {}: {}", job_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .filter(|job| Some(job.tenant.as_str()) == tenant_id(&headers))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if job.status != BatchJobStatus::Queued {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    job.status = BatchJobStatus::Cancelled;
+    job.updated_at = chrono::Utc::now().timestamp();
+    job.ciphertext.clear();
+    state.store.put_batch_job(&job).map_err(|e| {
+        log::error!("Failed to cancel batch job {}: {}", job_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if let Some(submitted_at) = chrono::DateTime::from_timestamp(job.submitted_at, 0) {
+        state
+            .batch_windows
+            .refund(&job.tenant, job.quota_units, submitted_at);
+    }
+    audit(
+        &state,
+        "batch.cancel",
+        &job_id.to_string(),
+        serde_json::json!({ "tenant": job.tenant }),
+    );
+    Ok(Json(batch_job_view(&job)))
+}
+
+/// Queue failed items of a finished multi-item job to run again in the next
+/// window, optionally with replacement prompts; succeeded items keep their results
+pub(super) async fn retry_batch_job(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(job_id): Path<Uuid>,
+    Json(request): Json<BatchRetryRequest>,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    let mut job = state
+        .store
+        .get_batch_job(job_id)
+        .map_err(|e| {
+            log::error!("Failed to load batch job {}: {}", job_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .filter(|job| Some(job.tenant.as_str()) == tenant_id(&headers))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if job.items.is_empty()
+        || !matches!(
+            job.status,
+            BatchJobStatus::PartiallyCompleted | BatchJobStatus::Failed
+        )
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let selected: Vec<(usize, Option<String>)> = if request.items.is_empty() {
+        job.items
+            .iter()
+            .filter(|item| {
+                item.status == BatchItemStatus::Failed
+                    && item.error.as_ref().is_some_and(|e| e.retryable)
+            })
+            .map(|item| (item.index, None))
+            .collect()
+    } else {
+        let mut selected = Vec::with_capacity(request.items.len());
+        for retry in request.items {
+            let item = job
+                .items
+                .iter()
+                .find(|item| {
+                    item.status == BatchItemStatus::Failed
+                        && item.retry_token.as_deref() == Some(retry.retry_token.as_str())
+                })
+                .ok_or_else(|| {
+                    log::warn!("Batch job {}: unknown or used retry token", job_id);
+                    StatusCode::CONFLICT
+                })?;
+            // A repeated token would charge quota twice for one item
+            if selected.iter().any(|(index, _)| *index == item.index) {
+                log::warn!("Batch job {}: retry token repeated", job_id);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            if let Some(replacement) = &retry.encrypted_data {
+                let data = BASE64_STANDARD
+                    .decode(replacement)
+                    .map_err(|_| StatusCode::BAD_REQUEST)?;
+                enforce_approval(&state, &headers, &held_copies(&state, &data).await)?;
+            }
+            selected.push((item.index, retry.encrypted_data));
+        }
+        selected
+    };
+    if selected.is_empty() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let now = chrono::Utc::now();
+    let quota_units = state
+        .batch_windows
+        .charge(&job.tenant, selected.len(), now)
+        .map_err(|e| {
+            log::warn!("Batch job retry rejected: {}", e);
+            StatusCode::TOO_MANY_REQUESTS
+        })?;
+    for (index, replacement) in &selected {
+        let item = &mut job.items[*index];
+        item.status = BatchItemStatus::Pending;
+        item.error = None;
+        item.retry_token = None;
+        if let Some(replacement) = replacement {
+            item.ciphertext = replacement.clone();
+        }
+    }
+    job.status = BatchJobStatus::Queued;
+    job.error = None;
+    job.quota_units += quota_units;
+    job.updated_at = now.timestamp();
+    if let Err(e) = state.store.put_batch_job(&job) {
+        log::error!("Failed to requeue batch job {}: {}", job_id, e);
+        state.batch_windows.refund(&job.tenant, quota_units, now);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let retried: Vec<usize> = selected.iter().map(|(index, _)| *index).collect();
+    audit(
+        &state,
+        "batch.retry",
+        &job_id.to_string(),
+        serde_json::json!({
+            "tenant": job.tenant,
+            "items": retried,
+            "replaced": selected.iter().filter(|(_, r)| r.is_some()).count(),
+            "quota_units": quota_units,
+        }),
+    );
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({
+            "job_id": job_id,
+            "status": job.status,
+            "retried_items": retried,
+            "quota_units": quota_units,
+            "next_window_start": state.batch_windows.next_window_start(now),
+        })),
+    ))
+}
+
+/// Configured off-peak windows and whether one is open now
+pub(super) async fn get_batch_windows(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    let now = chrono::Utc::now();
+    let config = state.batch_windows.config();
+    Json(serde_json::json!({
+        "enabled": state.batch_windows.is_enabled(),
+        "windows": state.batch_windows.windows(),
+        "active_window": state.batch_windows.active_window(now),
+        "next_window_start": state.batch_windows.next_window_start(now),
+        "quota_discount": config.quota_discount,
+        "daily_quota_units": config.daily_quota_units,
+    }))
+}
+
+/// Replace the off-peak windows; takes effect on the next scheduler tick.
+/// Admins only.
+pub(super) async fn set_batch_windows(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(windows): Json<Vec<BatchWindowConfig>>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    state
+        .batch_windows
+        .set_windows(windows.clone())
+        .map_err(|e| {
+            log::warn!("Rejected batch windows: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+    audit(
+        &state,
+        "batch.windows.update",
+        "batch_windows",
+        serde_json::json!({ "admin": admin, "windows": windows }),
+    );
+    Ok(get_batch_windows(State(state)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::proxy::ProxyServer;
+
+    #[tokio::test]
+    async fn test_batch_jobs_queue_and_cancel() {
+        let mut config = Config::default();
+        config.scaling.batch_windows.enabled = true;
+        config.scaling.batch_windows.daily_quota_units = 1.0;
+        let state = ProxyServer::new(config).unwrap().state;
+
+        let text = "Run me overnight.";
+        let ciphertext = {
+            let mut engine = state.fhe_engine.write().await;
+            let (client_id, _) = engine.generate_keys().unwrap();
+            engine.encrypt_text(client_id, text).unwrap()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        let submit = || {
+            submit_batch_job(
+                State(state.clone()),
+                headers.clone(),
+                None,
+                Json(BatchJobRequest {
+                    encrypted_data: BASE64_STANDARD.encode(&ciphertext.data),
+                    items: vec![],
+                    provider: "openai".to_string(),
+                    model: "gpt-4".to_string(),
+                    params_hash: Some(ciphertext.params.fingerprint()),
+                }),
+            )
+        };
+
+        // Two jobs at the 0.5 discount exhaust the daily quota
+        let (status, Json(first)) = submit().await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(first["quota_units"], 0.5);
+        let (_, Json(second)) = submit().await.unwrap();
+        assert_eq!(submit().await.unwrap_err(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Cancelling a queued job refunds its quota
+        let second_id: Uuid = serde_json::from_value(second["job_id"].clone()).unwrap();
+        let Json(cancelled) =
+            cancel_batch_job(State(state.clone()), headers.clone(), Path(second_id))
+                .await
+                .unwrap();
+        assert_eq!(cancelled["status"], "cancelled");
+        assert!(cancelled.get("ciphertext").is_none());
+        assert!(submit().await.is_ok());
+
+        let first_id: Uuid = serde_json::from_value(first["job_id"].clone()).unwrap();
+        let job = state.store.get_batch_job(first_id).unwrap().unwrap();
+        run_batch_job(&state, job, "nightly").await;
+        let job = state.store.get_batch_job(first_id).unwrap().unwrap();
+        assert_eq!(job.status, BatchJobStatus::Completed, "{:?}", job.error);
+        assert!(job.ciphertext.is_empty());
+        assert_eq!(
+            cancel_batch_job(State(state.clone()), headers.clone(), Path(first_id))
+                .await
+                .unwrap_err(),
+            StatusCode::CONFLICT
+        );
+
+        assert!(state
+            .ciphertext_cache
+            .read()
+            .await
+            .contains_key(&job.result_ciphertext_id.unwrap()));
+
+        // Other tenants cannot see the job
+        assert!(
+            get_batch_job(State(state.clone()), HeaderMap::new(), Path(first_id))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_job_items_fail_independently_and_retry() {
+        let mut config = Config::default();
+        config.scaling.batch_windows.enabled = true;
+        let state = ProxyServer::new(config).unwrap().state;
+
+        let ciphertext = {
+            let mut engine = state.fhe_engine.write().await;
+            let (client_id, _) = engine.generate_keys().unwrap();
+            engine.encrypt_text(client_id, "Summarise this.").unwrap()
+        };
+        let prompt = BASE64_STANDARD.encode(&ciphertext.data);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        let (_, Json(submitted)) = submit_batch_job(
+            State(state.clone()),
+            headers.clone(),
+            None,
+            Json(BatchJobRequest {
+                encrypted_data: String::new(),
+                items: vec![prompt.clone(); 3],
+                provider: "openai".to_string(),
+                model: "gpt-4".to_string(),
+                params_hash: Some(ciphertext.params.fingerprint()),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(submitted["quota_units"], 1.5);
+        let job_id: Uuid = serde_json::from_value(submitted["job_id"].clone()).unwrap();
+
+        // One item's payload is damaged at rest; the others still complete
+        let mut job = state.store.get_batch_job(job_id).unwrap().unwrap();
+        job.items[1].ciphertext = "not base64!".to_string();
+        run_batch_job(&state, job, "nightly").await;
+        let job = state.store.get_batch_job(job_id).unwrap().unwrap();
+        assert_eq!(job.status, BatchJobStatus::PartiallyCompleted);
+        assert_eq!(job.items[0].status, BatchItemStatus::Succeeded);
+        assert_eq!(job.items[2].status, BatchItemStatus::Succeeded);
+        let failed = &job.items[1];
+        let error = failed.error.as_ref().unwrap();
+        assert_eq!(
+            (error.kind.as_str(), error.retryable),
+            ("validation", false)
+        );
+        let token = failed.retry_token.clone().unwrap();
+
+        let Json(view) = get_batch_job(State(state.clone()), headers.clone(), Path(job_id))
+            .await
+            .unwrap();
+        assert_eq!(view["items"][1]["retry_token"], token.as_str());
+        assert!(view["items"][1].get("ciphertext").is_none());
+
+        // Nothing is retryable as is, but the item can be retried with a new payload
+        let retry = |items| {
+            retry_batch_job(
+                State(state.clone()),
+                headers.clone(),
+                Path(job_id),
+                Json(BatchRetryRequest { items }),
+            )
+        };
+        assert_eq!(retry(vec![]).await.unwrap_err(), StatusCode::CONFLICT);
+
+        // Repeating a token would charge the same item twice
+        let item = || BatchRetryItem {
+            retry_token: token.clone(),
+            encrypted_data: Some(prompt.clone()),
+        };
+        assert_eq!(
+            retry(vec![item(), item()]).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            state.store.get_batch_job(job_id).unwrap().unwrap().status,
+            BatchJobStatus::PartiallyCompleted
+        );
+
+        let (status, Json(retried)) = retry(vec![BatchRetryItem {
+            retry_token: token.clone(),
+            encrypted_data: Some(prompt),
+        }])
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(retried["retried_items"], serde_json::json!([1]));
+
+        let job = state.store.get_batch_job(job_id).unwrap().unwrap();
+        let first_result = job.items[0].result_ciphertext_id;
+        run_batch_job(&state, job, "nightly").await;
+        let job = state.store.get_batch_job(job_id).unwrap().unwrap();
+        assert_eq!(job.status, BatchJobStatus::Completed);
+        assert_eq!(job.items[0].result_ciphertext_id, first_result);
+        assert_eq!(job.items[1].attempts, 2);
+        assert!(job.items.iter().all(|item| item.ciphertext.is_empty()));
+        assert_eq!(
+            retry(vec![BatchRetryItem {
+                retry_token: token,
+                encrypted_data: None,
+            }])
+            .await
+            .unwrap_err(),
+            StatusCode::CONFLICT
+        );
+    }
+}
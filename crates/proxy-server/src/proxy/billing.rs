@@ -0,0 +1,496 @@
+//! Metering of billed routes and tenant spending caps
+
+use super::identity::admin_name;
+use super::{audit, tenant_id, ProxyState};
+use crate::billing::{
+    SpendingStatus, ThresholdAlert, UncompressedBytes, REQUEST_BYTES_HEADER, REQUEST_ID_HEADER,
+    RESPONSE_BYTES_HEADER, RESPONSE_WIRE_BYTES_HEADER, USAGE_AUDIT_ACTION,
+};
+use crate::config::{EffectiveTenantConfig, SpendingCapPolicy};
+use crate::persistence::BillingRecord;
+use crate::sandbox::SANDBOX_KEY_HEADER;
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Count the bytes a billed request and its response put on the wire, answer
+/// with them and a request id, and record them in the ledger and audit log.
+/// Streamed responses are recorded once the stream ends or the client leaves.
+pub(super) async fn billing_middleware(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let tenant = tenant_id(request.headers()).map(str::to_string);
+    // Sandbox keys pay from their own allowance, but only keys the sandbox
+    // would admit; anything else is billed and then refused by the sandbox
+    let sandboxed = request
+        .headers()
+        .get(SANDBOX_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|secret| {
+            state
+                .sandbox
+                .recognizes(secret, tenant.as_deref(), chrono::Utc::now().timestamp())
+        });
+    if !state.billing.is_billed(request.uri().path())
+        || state.probes.is_probe(request.headers())
+        || sandboxed
+    {
+        return next.run(request).await;
+    }
+    let started = Instant::now();
+    let route = request.uri().path().to_string();
+    match &tenant {
+        Some(tenant) => {
+            let request_bytes = axum::body::HttpBody::size_hint(request.body()).lower();
+            if let Err(status) = enforce_spending_cap(&state, tenant, request_bytes).await {
+                return (
+                    StatusCode::PAYMENT_REQUIRED,
+                    Json(serde_json::json!({
+                        "error": "Monthly spending cap reached",
+                        "spending": status,
+                    })),
+                )
+                    .into_response();
+            }
+        }
+        // A cap can only hold if every billed request counts against a tenant
+        None if state.spending_caps.is_enabled() => {
+            log::warn!("Refused unattributed billed request to {}", route);
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({
+                    "error": "Billed requests need tenant credentials while spending caps are enabled",
+                })),
+            )
+                .into_response();
+        }
+        None => {}
+    }
+
+    // Bodies with a declared length keep it, so later layers can still size them
+    let request_bytes = Arc::new(AtomicU64::new(0));
+    let request = match axum::body::HttpBody::size_hint(request.body()).exact() {
+        Some(len) => {
+            request_bytes.store(len, Ordering::Relaxed);
+            request
+        }
+        None => {
+            let counter = request_bytes.clone();
+            request.map(|body| {
+                axum::body::Body::from_stream(tokio_stream::StreamExt::map(
+                    body.into_data_stream(),
+                    move |chunk| {
+                        if let Ok(chunk) = &chunk {
+                            counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                        }
+                        chunk
+                    },
+                ))
+            })
+        }
+    };
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    // Keep an id the handler already gave the response, e.g. for quality scoring
+    let request_id = parts
+        .headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| Uuid::parse_str(v).ok())
+        .unwrap_or_else(Uuid::new_v4);
+    let mut record = BillingRecord {
+        request_id,
+        tenant,
+        route,
+        recorded_at: chrono::Utc::now().timestamp(),
+        request_bytes: request_bytes.load(Ordering::Relaxed),
+        response_bytes: 0,
+        response_wire_bytes: 0,
+        duration_ms: None,
+    };
+    let headers = &mut parts.headers;
+    headers.insert(
+        REQUEST_ID_HEADER,
+        record.request_id.to_string().parse().unwrap(),
+    );
+    headers.insert(REQUEST_BYTES_HEADER, record.request_bytes.into());
+
+    if is_event_stream(headers) {
+        let mut meter = StreamMeter {
+            state: state.clone(),
+            record: Some(record),
+            started,
+        };
+        let body = axum::body::Body::from_stream(tokio_stream::StreamExt::map(
+            body.into_data_stream(),
+            move |chunk| {
+                if let (Ok(chunk), Some(record)) = (&chunk, meter.record.as_mut()) {
+                    record.response_bytes += chunk.len() as u64;
+                    record.response_wire_bytes += chunk.len() as u64;
+                }
+                chunk
+            },
+        ));
+        return Response::from_parts(parts, body);
+    }
+
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log::error!("Failed to buffer billed response: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    record.response_wire_bytes = bytes.len() as u64;
+    record.response_bytes = parts
+        .extensions
+        .get::<UncompressedBytes>()
+        .map_or(record.response_wire_bytes, |b| b.0);
+    headers.insert(RESPONSE_BYTES_HEADER, record.response_bytes.into());
+    headers.insert(
+        RESPONSE_WIRE_BYTES_HEADER,
+        record.response_wire_bytes.into(),
+    );
+    record.duration_ms = Some(started.elapsed().as_millis() as u64);
+    record_usage(&state, &record);
+    Response::from_parts(parts, axum::body::Body::from(bytes))
+}
+
+/// Byte counts of a streamed billed response, recorded when the stream is dropped
+struct StreamMeter {
+    state: Arc<ProxyState>,
+    record: Option<BillingRecord>,
+    started: Instant,
+}
+
+impl Drop for StreamMeter {
+    fn drop(&mut self) {
+        if let Some(mut record) = self.record.take() {
+            record.duration_ms = Some(self.started.elapsed().as_millis() as u64);
+            record_usage(&self.state, &record);
+        }
+    }
+}
+
+fn record_usage(state: &ProxyState, record: &BillingRecord) {
+    if let Err(e) = state.store.put_billing(record) {
+        log::error!(
+            "Failed to write billing record {}: {}",
+            record.request_id,
+            e
+        );
+    }
+    audit(
+        state,
+        USAGE_AUDIT_ACTION,
+        &record.request_id.to_string(),
+        serde_json::to_value(record).unwrap_or_default(),
+    );
+
+    let Some(tenant) = &record.tenant else {
+        return;
+    };
+    if !state.spending_caps.is_enabled() {
+        return;
+    }
+    let tenant_config = match state.tenant_configs.resolve(tenant) {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("Failed to resolve tenant config for {}: {}", tenant, e);
+            return;
+        }
+    };
+    let now = chrono::Utc::now().timestamp();
+    for alert in state
+        .spending_caps
+        .record(record, tenant_config.monthly_spending_cap, now)
+    {
+        notify_spending_threshold(state, &tenant_config, alert);
+    }
+}
+
+/// Hold back a billed request that would take `tenant` over its monthly
+/// spending cap: refuse it, or queue it until the cap is lifted, per the
+/// tenant's policy
+async fn enforce_spending_cap(
+    state: &ProxyState,
+    tenant: &str,
+    request_bytes: u64,
+) -> std::result::Result<(), SpendingStatus> {
+    let caps = &state.spending_caps;
+    if !caps.is_enabled() {
+        return Ok(());
+    }
+    let tenant_config = match state.tenant_configs.resolve(tenant) {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("Failed to resolve tenant config for {}: {}", tenant, e);
+            return Ok(());
+        }
+    };
+    let estimate = caps.estimate(tenant, request_bytes);
+    let current_cap = || {
+        state
+            .tenant_configs
+            .resolve(tenant)
+            .map_or(tenant_config.monthly_spending_cap, |c| {
+                c.monthly_spending_cap
+            })
+    };
+    let admits = || {
+        caps.admits(
+            tenant,
+            current_cap(),
+            estimate,
+            chrono::Utc::now().timestamp(),
+        )
+    };
+    if admits() {
+        return Ok(());
+    }
+    let refuse = || caps.status(tenant, current_cap(), chrono::Utc::now().timestamp());
+    if tenant_config.spending_cap_policy == SpendingCapPolicy::Reject {
+        log::warn!(
+            "Refused request from {}: monthly spending cap reached",
+            tenant
+        );
+        return Err(refuse());
+    }
+
+    // Caps are lifted by an override, a raised cap or the month ending; only
+    // overrides are signalled, so the queue also re-checks every second
+    let deadline = Instant::now() + Duration::from_secs(caps.queue_timeout_seconds());
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            log::warn!(
+                "Refused queued request from {}: spending cap not lifted in {}s",
+                tenant,
+                caps.queue_timeout_seconds()
+            );
+            return Err(refuse());
+        }
+        let _ = tokio::time::timeout(
+            remaining.min(Duration::from_secs(1)),
+            caps.lifted().notified(),
+        )
+        .await;
+        if admits() {
+            return Ok(());
+        }
+    }
+}
+
+/// Audit a tenant crossing a spending alert threshold and notify its webhook
+fn notify_spending_threshold(
+    state: &ProxyState,
+    tenant_config: &EffectiveTenantConfig,
+    alert: ThresholdAlert,
+) {
+    audit(
+        state,
+        "billing.spending_threshold",
+        &alert.status.tenant,
+        serde_json::json!(alert),
+    );
+
+    let Some(url) = tenant_config.spending_webhook_url.clone() else {
+        return;
+    };
+    let payload = serde_json::json!({
+        "event": "billing.spending_threshold",
+        "threshold_percent": alert.threshold_percent,
+        "spending": alert.status,
+        "occurred_at": chrono::Utc::now(),
+    });
+    tokio::spawn(async move {
+        let result = HttpClient::new()
+            .post(&url)
+            .timeout(Duration::from_secs(10))
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            log::warn!("Spending webhook to {} failed: {}", url, e);
+        }
+    });
+}
+
+pub(super) fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"))
+}
+
+/// Window of the billing ledger to report on; defaults to the last day
+#[derive(Debug, Deserialize)]
+pub struct BillingQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub tenant: Option<String>,
+}
+
+impl BillingQuery {
+    fn window(&self) -> (i64, i64) {
+        let now = chrono::Utc::now().timestamp();
+        (
+            self.from.unwrap_or(now - 86_400),
+            self.to.unwrap_or(now + 1),
+        )
+    }
+}
+
+/// Ledger records for a time range, optionally for one tenant
+pub(super) async fn get_billing_usage(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<BillingQuery>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let (from, to) = query.window();
+    let records: Vec<BillingRecord> = state
+        .store
+        .list_billing(from, to)
+        .map_err(|e| {
+            log::error!("Failed to read billing ledger: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .into_iter()
+        .filter(|r| query.tenant.is_none() || r.tenant == query.tenant)
+        .collect();
+    Ok(Json(serde_json::json!({
+        "enabled": state.billing.is_enabled(),
+        "from": from,
+        "to": to,
+        "records": records,
+    })))
+}
+
+/// This month's spend against the cap of every tenant with spend, or of one
+pub(super) async fn get_spending_caps(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<BillingQuery>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let now = chrono::Utc::now().timestamp();
+    let tenants = match query.tenant {
+        Some(tenant) => vec![tenant],
+        None => state.spending_caps.tenants(),
+    };
+    let mut statuses = Vec::new();
+    for tenant in tenants {
+        let tenant_config = state.tenant_configs.resolve(&tenant).map_err(|e| {
+            log::warn!("Failed to resolve tenant config for {}: {}", tenant, e);
+            StatusCode::BAD_REQUEST
+        })?;
+        statuses.push(serde_json::json!({
+            "policy": tenant_config.spending_cap_policy,
+            "status": state
+                .spending_caps
+                .status(&tenant, tenant_config.monthly_spending_cap, now),
+        }));
+    }
+    Ok(Json(serde_json::json!({
+        "enabled": state.spending_caps.is_enabled(),
+        "tenants": statuses,
+    })))
+}
+
+/// Emergency lifting of a tenant's spending cap; the operator is the admin
+/// whose token authorised it
+#[derive(Debug, Deserialize)]
+pub struct SpendingCapOverrideRequest {
+    pub reason: String,
+    /// Defaults to `billing.spending_caps.default_override_seconds` from now
+    pub expires_at: Option<i64>,
+}
+
+/// Let a capped tenant's requests through for a while, recording who did it
+/// and why; admins only
+pub(super) async fn override_spending_cap(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<SpendingCapOverrideRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if !state.spending_caps.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let now = chrono::Utc::now().timestamp();
+    if request.reason.trim().is_empty() || request.expires_at.is_some_and(|at| at <= now) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let cap_override = state.spending_caps.grant_override(
+        &tenant,
+        &admin,
+        request.reason,
+        request.expires_at,
+        now,
+    );
+    audit(
+        &state,
+        "billing.cap_override",
+        &tenant,
+        serde_json::json!(cap_override),
+    );
+    Ok(Json(serde_json::json!({
+        "tenant": tenant,
+        "override": cap_override,
+    })))
+}
+
+/// Withdraw a tenant's spending cap override; admins only
+pub(super) async fn revoke_spending_cap_override(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let revoked = state
+        .spending_caps
+        .revoke_override(&tenant)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    audit(
+        &state,
+        "billing.cap_override_revoked",
+        &tenant,
+        serde_json::json!({ "admin": admin, "override": revoked }),
+    );
+    Ok(Json(
+        serde_json::json!({ "tenant": tenant, "revoked": revoked }),
+    ))
+}
+
+/// Recompute a time range's usage from the audit trail and compare it with the ledger
+pub(super) async fn reconcile_billing(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<BillingQuery>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let (from, to) = query.window();
+    let (ledger, audit) = state
+        .store
+        .list_billing(from, to)
+        .and_then(|ledger| Ok((ledger, state.store.list_audit()?)))
+        .map_err(|e| {
+            log::error!("Failed to read billing ledger or audit log: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let report = state
+        .billing
+        .reconcile(&ledger, &audit, from, to, query.tenant.as_deref());
+    Ok(Json(serde_json::json!(report)))
+}
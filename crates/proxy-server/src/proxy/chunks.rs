@@ -0,0 +1,289 @@
+//! Chunked encrypted responses and range decryption
+
+use super::approvals::enforce_approval;
+use super::ciphertexts::{decryption_status, requires_delegated_decryption};
+use super::ProxyState;
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Range of response chunks, `end` exclusive
+#[derive(Debug, Deserialize)]
+pub struct ChunkRangeQuery {
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+}
+
+/// Request to decrypt a range of response chunks
+#[derive(Debug, Deserialize)]
+pub struct DecryptChunksRequest {
+    pub response_id: Uuid,
+    pub client_id: Uuid,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+}
+
+/// Ordered ciphertext chunks of encrypted completion responses
+#[derive(Debug)]
+pub struct ResponseChunkStore {
+    responses: RwLock<HashMap<Uuid, ChunkedResponse>>,
+    unreferenced_ttl: Duration,
+}
+
+#[derive(Debug)]
+struct ChunkedResponse {
+    chunks: Vec<ResponseChunk>,
+    created_at: Instant,
+}
+
+#[derive(Debug)]
+struct ResponseChunk {
+    ciphertext_id: Uuid,
+    referenced: bool,
+    expired: bool,
+}
+
+/// Chunk slot handed out for a requested range
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkRef {
+    pub index: usize,
+    pub ciphertext_id: Uuid,
+    pub expired: bool,
+}
+
+impl ResponseChunkStore {
+    pub fn new(unreferenced_ttl: Duration) -> Self {
+        Self {
+            responses: RwLock::new(HashMap::new()),
+            unreferenced_ttl,
+        }
+    }
+
+    pub async fn register(&self, response_id: Uuid, chunk_ids: Vec<Uuid>) {
+        let chunks = chunk_ids
+            .into_iter()
+            .map(|ciphertext_id| ResponseChunk {
+                ciphertext_id,
+                referenced: false,
+                expired: false,
+            })
+            .collect();
+
+        self.responses.write().await.insert(
+            response_id,
+            ChunkedResponse {
+                chunks,
+                created_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Look up a chunk range and mark the live chunks in it as referenced
+    ///
+    /// Returns the total chunk count along with the clamped range.
+    pub async fn reference(
+        &self,
+        response_id: Uuid,
+        start: usize,
+        end: Option<usize>,
+    ) -> Option<(usize, Vec<ChunkRef>)> {
+        let mut responses = self.responses.write().await;
+        let response = responses.get_mut(&response_id)?;
+        let total = response.chunks.len();
+        let end = end.unwrap_or(total).min(total);
+
+        let refs = response
+            .chunks
+            .iter_mut()
+            .enumerate()
+            .take(end)
+            .skip(start)
+            .map(|(index, chunk)| {
+                if !chunk.expired {
+                    chunk.referenced = true;
+                }
+                ChunkRef {
+                    index,
+                    ciphertext_id: chunk.ciphertext_id,
+                    expired: chunk.expired,
+                }
+            })
+            .collect();
+
+        Some((total, refs))
+    }
+
+    /// Stop tracking responses with a chunk among `ciphertext_ids`, returning
+    /// how many were dropped
+    pub async fn forget(&self, ciphertext_ids: &HashSet<Uuid>) -> usize {
+        let mut responses = self.responses.write().await;
+        let before = responses.len();
+        responses.retain(|id, response| {
+            !ciphertext_ids.contains(id)
+                && !response
+                    .chunks
+                    .iter()
+                    .any(|chunk| ciphertext_ids.contains(&chunk.ciphertext_id))
+        });
+        before - responses.len()
+    }
+
+    /// Expire chunks nobody asked for within the TTL, returning their ciphertext IDs
+    pub async fn expire_unreferenced(&self) -> Vec<Uuid> {
+        let mut responses = self.responses.write().await;
+        let mut expired = Vec::new();
+
+        for response in responses.values_mut() {
+            if response.created_at.elapsed() < self.unreferenced_ttl {
+                continue;
+            }
+            for chunk in response.chunks.iter_mut() {
+                if !chunk.referenced && !chunk.expired {
+                    chunk.expired = true;
+                    expired.push(chunk.ciphertext_id);
+                }
+            }
+        }
+
+        // Nothing is left to serve once every chunk of a response has expired
+        responses.retain(|_, response| !response.chunks.iter().all(|chunk| chunk.expired));
+
+        expired
+    }
+
+    /// Responses whose chunks are still tracked
+    pub async fn response_count(&self) -> usize {
+        self.responses.read().await.len()
+    }
+}
+
+/// Get chunk metadata for a range of an encrypted response
+pub(super) async fn get_ciphertext_chunks(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+    Query(range): Query<ChunkRangeQuery>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let (total_chunks, refs) = state
+        .response_chunks
+        .reference(id, range.start.unwrap_or(0), range.end)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let cache = state.ciphertext_cache.read().await;
+    let chunks = refs
+        .iter()
+        .map(|chunk_ref| match cache.get(&chunk_ref.ciphertext_id) {
+            Some(chunk) if !chunk_ref.expired => serde_json::json!({
+                "index": chunk_ref.index,
+                "ciphertext_id": chunk.id,
+                "status": "available",
+                "data_size": chunk.data.len(),
+                "noise_budget": chunk.noise_budget
+            }),
+            _ => serde_json::json!({
+                "index": chunk_ref.index,
+                "ciphertext_id": chunk_ref.ciphertext_id,
+                "status": "expired"
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(serde_json::json!({
+        "response_id": id,
+        "total_chunks": total_chunks,
+        "chunks": chunks
+    })))
+}
+
+/// Decrypt only the requested chunk range of an encrypted response
+pub(super) async fn decrypt_chunks(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<DecryptChunksRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let start = request.start.unwrap_or(0);
+    let (total_chunks, refs) = state
+        .response_chunks
+        .reference(request.response_id, start, request.end)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if refs.iter().any(|chunk_ref| chunk_ref.expired) {
+        log::warn!(
+            "Requested range of response {} contains expired chunks",
+            request.response_id
+        );
+        return Err(StatusCode::GONE);
+    }
+
+    let ciphertexts = {
+        let cache = state.ciphertext_cache.read().await;
+        refs.iter()
+            .map(|chunk_ref| cache.get(&chunk_ref.ciphertext_id).cloned())
+            .collect::<Option<Vec<_>>>()
+            .ok_or(StatusCode::GONE)?
+    };
+
+    let ciphertext_ids: Vec<Uuid> = ciphertexts.iter().map(|ct| ct.id).collect();
+    enforce_approval(&state, &headers, &ciphertext_ids)?;
+    let required = requires_delegated_decryption(&state, &headers)?;
+    let mut chunks = Vec::with_capacity(ciphertexts.len());
+    for (chunk_ref, ciphertext) in refs.iter().zip(&ciphertexts) {
+        let (plaintext, _) = state
+            .decryption
+            .decrypt(&state.fhe_engine, request.client_id, ciphertext, required)
+            .await
+            .map_err(|e| {
+                log::error!("Chunk decryption failed: {}", e);
+                decryption_status(&e)
+            })?;
+        state.metrics.increment_decryptions();
+        chunks.push(serde_json::json!({
+            "index": chunk_ref.index,
+            "plaintext": plaintext
+        }));
+    }
+
+    let end = start + chunks.len();
+    Ok(Json(serde_json::json!({
+        "response_id": request.response_id,
+        "total_chunks": total_chunks,
+        "chunks": chunks,
+        "truncated": end < total_chunks
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_response_chunks_drop_fully_expired_responses() {
+        let store = ResponseChunkStore::new(Duration::ZERO);
+        let (read, unread) = (Uuid::new_v4(), Uuid::new_v4());
+        store
+            .register(read, vec![Uuid::new_v4(), Uuid::new_v4()])
+            .await;
+        store.register(unread, vec![Uuid::new_v4()]).await;
+        store.reference(read, 0, Some(1)).await.unwrap();
+        assert_eq!(store.response_count().await, 2);
+
+        // The unread response expires entirely and stops being tracked
+        assert_eq!(store.expire_unreferenced().await.len(), 2);
+        assert_eq!(store.response_count().await, 1);
+        assert!(store.reference(unread, 0, None).await.is_none());
+
+        // The referenced chunk keeps the other response around
+        let (total, refs) = store.reference(read, 0, None).await.unwrap();
+        assert_eq!(total, 2);
+        assert!(!refs[0].expired && refs[1].expired);
+    }
+}
@@ -0,0 +1,779 @@
+//! Key generation, encryption, decryption and ciphertext operations
+
+use super::approvals::enforce_approval;
+use super::escrow::escrow_client_key;
+use super::identity::admin_name;
+use super::sessions::release_evicted_sessions;
+use super::{audit, tenant_id, ProxyState};
+use crate::access::API_KEY_HEADER;
+use crate::config::FheOperation;
+use crate::error::Error;
+use crate::etag;
+use crate::execution::ExecutionPermit;
+use crate::fhe::{Ciphertext, EngineError, FheParams};
+use crate::middleware::KeyPolicy;
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use crate::transactions::{TransactionPlan, TransactionRequest};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Optional body of a key generation request
+#[derive(Debug, Default, Deserialize)]
+pub struct GenerateKeysRequest {
+    /// Restrictions attached to the new client key
+    pub policy: Option<KeyPolicy>,
+}
+
+/// Request to encrypt text
+#[derive(Debug, Deserialize)]
+pub struct EncryptRequest {
+    pub text: String,
+    pub client_id: Option<Uuid>,
+    pub params: Option<FheParams>,
+}
+
+/// Response with encrypted data
+#[derive(Debug, Serialize)]
+pub struct EncryptResponse {
+    pub ciphertext_id: Uuid,
+    pub encrypted_data: String, // Base64 encoded
+    pub params: FheParams,
+    pub noise_budget: Option<u64>,
+}
+
+/// Generate new FHE key pair with enhanced error handling
+pub(super) async fn generate_keys(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    body: Option<Json<GenerateKeysRequest>>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let policy = body.and_then(|Json(body)| body.policy);
+    let tenant = tenant_id(&headers);
+    let tenant_config = match tenant {
+        Some(tenant) => state.tenant_configs.resolve(tenant),
+        None => state.tenant_configs.resolve_global(),
+    }
+    .map_err(|e| {
+        log::warn!("Failed to resolve tenant config for {:?}: {}", tenant, e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    // Session caps are kept per API key, so a capped session needs one
+    let api_key = headers
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|key| state.access.key_id(key));
+    if tenant_config.max_sessions > 0 && api_key.is_none() {
+        log::warn!(
+            "Refused key generation for tenant {:?}: sessions are capped per API key and none was presented",
+            tenant
+        );
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    // Record operation start for metrics
+    let timer = state.profiler.start_timer("key_generation");
+
+    // Check system capacity before generating keys
+    let stats = state.fhe_engine.read().await.get_stats();
+    if stats.total_client_keys > 1000 {
+        log::warn!(
+            "Key generation limit approached: {} active keys",
+            stats.total_client_keys
+        );
+        state
+            .monitoring
+            .record_error(
+                "capacity_warning".to_string(),
+                "High number of active keys".to_string(),
+            )
+            .await;
+    }
+
+    let _permit = execution_permit(&state, FheOperation::KeyGeneration).await?;
+    let mut fhe_engine = state.fhe_engine.write().await;
+
+    // Attempt key generation with retry logic
+    let mut attempts = 0;
+    const MAX_ATTEMPTS: u32 = 3;
+
+    while attempts < MAX_ATTEMPTS {
+        match fhe_engine.generate_keys() {
+            Ok((client_id, server_id)) => {
+                // Regulated tenants get no key that is not escrowed
+                let escrowed =
+                    state.escrow.is_enabled() && !tenant_config.escrow_custodians.is_empty();
+                if escrowed {
+                    if let Err(e) =
+                        escrow_client_key(&state, &fhe_engine, &tenant_config, client_id)
+                    {
+                        log::error!("Failed to escrow client key {}: {}", client_id, e);
+                        fhe_engine.client_keys.remove(&client_id);
+                        fhe_engine.server_keys.remove(&server_id);
+                        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                    }
+                }
+
+                // Tenants whose secret keys must stay in the HSM get only a
+                // handle; the proxy keeps nothing it could decrypt with
+                let key_handle = if tenant_config.require_delegated_decryption {
+                    match state
+                        .decryption
+                        .take_custody(&mut fhe_engine, client_id)
+                        .await
+                    {
+                        Ok(key_handle) => Some(key_handle),
+                        Err(e) => {
+                            log::error!(
+                                "Failed to move client key {} to the HSM: {}",
+                                client_id,
+                                e
+                            );
+                            fhe_engine.client_keys.remove(&client_id);
+                            fhe_engine.server_keys.remove(&server_id);
+                            return Err(decryption_status(&e));
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                let session_id = match state
+                    .session_manager
+                    .create_session(&tenant_config, api_key.as_deref(), client_id, server_id)
+                    .await
+                {
+                    Ok((session_id, evicted)) => {
+                        release_evicted_sessions(&state, &mut fhe_engine, &evicted).await;
+                        session_id
+                    }
+                    Err(e) => {
+                        log::warn!("Session rejected for tenant {:?}: {}", tenant, e);
+                        fhe_engine.client_keys.remove(&client_id);
+                        fhe_engine.server_keys.remove(&server_id);
+                        return Err(StatusCode::TOO_MANY_REQUESTS);
+                    }
+                };
+
+                let tokens = if state.renewal.is_enabled() {
+                    match state
+                        .session_manager
+                        .start_renewal(session_id, &state.renewal)
+                        .await
+                    {
+                        Ok(tokens) => Some(tokens),
+                        Err(e) => {
+                            log::error!("Failed to issue tokens for session {}: {}", session_id, e);
+                            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                        }
+                    }
+                } else {
+                    None
+                };
+                let key_lifetime = if state.renewal.is_enabled() {
+                    chrono::Duration::seconds(state.renewal.config().key_lifetime_seconds as i64)
+                } else {
+                    chrono::Duration::hours(24)
+                };
+
+                if let Some(policy) = &policy {
+                    state.key_policies.attach(client_id, policy.clone()).await;
+                    audit(
+                        &state,
+                        "key_policy.attach",
+                        &client_id.to_string(),
+                        serde_json::json!({ "policy": policy }),
+                    );
+                }
+
+                // Record successful operation
+                state.metrics.increment_encryptions();
+                drop(timer); // Complete timing measurement
+
+                log::info!(
+                    "Generated FHE key pair for session {} (attempt {})",
+                    session_id,
+                    attempts + 1
+                );
+
+                return Ok(Json(serde_json::json!({
+                    "session_id": session_id,
+                    "client_id": client_id,
+                    "server_id": server_id,
+                    "policy": policy,
+                    "escrowed": escrowed,
+                    "key_handle": key_handle,
+                    "tokens": tokens,
+                    "params": fhe_engine.get_params(),
+                    "expires_at": chrono::Utc::now() + key_lifetime
+                })));
+            }
+            Err(e) => {
+                attempts += 1;
+                log::warn!("Key generation attempt {} failed: {}", attempts, e);
+
+                if attempts < MAX_ATTEMPTS {
+                    // Brief backoff before retry
+                    drop(fhe_engine); // Release lock during backoff
+                    tokio::time::sleep(std::time::Duration::from_millis(100 * attempts as u64))
+                        .await;
+                    fhe_engine = state.fhe_engine.write().await;
+                } else {
+                    state.metrics.increment_errors();
+                    state
+                        .monitoring
+                        .record_error("key_generation".to_string(), e.to_string())
+                        .await;
+                    log::error!(
+                        "Failed to generate keys after {} attempts: {}",
+                        MAX_ATTEMPTS,
+                        e
+                    );
+                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                }
+            }
+        }
+    }
+
+    Err(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Hold an execution slot for `operation`; an operation whose policy forbids
+/// falling back to the CPU is refused with 503 while the GPU is unavailable
+pub(super) async fn execution_permit(
+    state: &ProxyState,
+    operation: FheOperation,
+) -> std::result::Result<ExecutionPermit, StatusCode> {
+    state.execution.acquire(operation).await.map_err(|e| {
+        log::warn!("{}", e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })
+}
+
+/// Encrypt text endpoint
+pub(super) async fn encrypt_text(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<EncryptRequest>,
+) -> std::result::Result<Json<EncryptResponse>, StatusCode> {
+    let client_id = request.client_id.ok_or(StatusCode::BAD_REQUEST)?;
+    let _permit = execution_permit(&state, FheOperation::Encrypt).await?;
+    let fhe_engine = state.fhe_engine.read().await;
+
+    if let Some(params) = &request.params {
+        let differences = params.differences(fhe_engine.get_params());
+        if !differences.is_empty() {
+            log::warn!(
+                "Client {} requested encryption with mismatched parameters: {}",
+                client_id,
+                differences.join(", ")
+            );
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+
+    match fhe_engine.encrypt_text(client_id, &request.text) {
+        Ok(ciphertext) => {
+            let encrypted_data = base64::prelude::BASE64_STANDARD.encode(&ciphertext.data);
+            state
+                .key_policies
+                .record_ciphertext(client_id, &ciphertext)
+                .await;
+
+            // Cache the ciphertext
+            state
+                .ciphertext_cache
+                .write()
+                .await
+                .insert(ciphertext.id, ciphertext.clone());
+            state
+                .offboarding
+                .record_ciphertexts(tenant_id(&headers), [ciphertext.id]);
+
+            Ok(Json(EncryptResponse {
+                ciphertext_id: ciphertext.id,
+                encrypted_data,
+                params: ciphertext.params,
+                noise_budget: ciphertext.noise_budget,
+            }))
+        }
+        Err(e) => {
+            log::error!("Encryption failed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Whether the requesting tenant's secret keys must stay in the HSM
+pub(super) fn requires_delegated_decryption(
+    state: &ProxyState,
+    headers: &HeaderMap,
+) -> std::result::Result<bool, StatusCode> {
+    let tenant = tenant_id(headers);
+    let tenant_config = match tenant {
+        Some(tenant) => state.tenant_configs.resolve(tenant),
+        None => state.tenant_configs.resolve_global(),
+    }
+    .map_err(|e| {
+        log::warn!("Failed to resolve tenant config for {:?}: {}", tenant, e);
+        StatusCode::BAD_REQUEST
+    })?;
+    Ok(tenant_config.require_delegated_decryption)
+}
+
+/// Status for a failed decryption: HSM refusals and custody violations are
+/// forbidden, an unreachable HSM is unavailable
+pub(super) fn decryption_status(error: &Error) -> StatusCode {
+    match error {
+        Error::KeyPolicy(_) => StatusCode::FORBIDDEN,
+        Error::Provider(_) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Decrypt text endpoint
+pub(super) async fn decrypt_text(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<serde_json::Value>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let ciphertext_id: Uuid = request["ciphertext_id"]
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let client_id: Uuid = request["client_id"]
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let ciphertext = state
+        .ciphertext_cache
+        .read()
+        .await
+        .get(&ciphertext_id)
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    enforce_approval(&state, &headers, &[ciphertext_id])?;
+    let required = requires_delegated_decryption(&state, &headers)?;
+    match state
+        .decryption
+        .decrypt(&state.fhe_engine, client_id, &ciphertext, required)
+        .await
+    {
+        Ok((plaintext, path)) => Ok(Json(serde_json::json!({
+            "plaintext": plaintext,
+            "ciphertext_id": ciphertext_id,
+            "decrypted_by": path
+        }))),
+        Err(e) => {
+            log::error!("Decryption failed: {}", e);
+            Err(decryption_status(&e))
+        }
+    }
+}
+
+/// Get ciphertext by ID
+pub(super) async fn get_ciphertext(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> std::result::Result<Response, StatusCode> {
+    let ciphertext = state
+        .ciphertext_cache
+        .read()
+        .await
+        .get(&id)
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut response_headers = HeaderMap::new();
+    let etag = etag::digest_etag(&[id.as_bytes(), &ciphertext.data]);
+    etag::insert_etag(&mut response_headers, &etag);
+    if etag::if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+    Ok((
+        response_headers,
+        Json(serde_json::json!({
+            "id": ciphertext.id,
+            "params": ciphertext.params,
+            "noise_budget": ciphertext.noise_budget,
+            "data_size": ciphertext.data.len()
+        })),
+    )
+        .into_response())
+}
+
+/// Get FHE parameters
+pub(super) async fn get_fhe_params(State(state): State<Arc<ProxyState>>) -> Json<FheParams> {
+    let fhe_engine = state.fhe_engine.read().await;
+    Json(fhe_engine.get_params().clone())
+}
+
+/// Handshake for clients to confirm their FHE parameters before encrypting.
+///
+/// Returns the server's closest supported profile and its fingerprint. Requests
+/// whose ciphertexts or `params_hash` do not match it later fail with 409 Conflict.
+pub(super) async fn negotiate_fhe_params(
+    State(state): State<Arc<ProxyState>>,
+    Json(requested): Json<FheParams>,
+) -> Json<serde_json::Value> {
+    let fhe_engine = state.fhe_engine.read().await;
+    let supported = std::slice::from_ref(fhe_engine.get_params());
+    let profile = requested.closest(supported).unwrap_or(&supported[0]);
+    let differences = requested.differences(profile);
+
+    Json(serde_json::json!({
+        "compatible": differences.is_empty(),
+        "differences": differences,
+        "params": profile,
+        "params_hash": profile.fingerprint(),
+    }))
+}
+
+/// Rotate client keys for enhanced security
+pub(super) async fn rotate_client_keys(
+    State(state): State<Arc<ProxyState>>,
+    Path(client_id): Path<Uuid>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let mut fhe_engine = state.fhe_engine.write().await;
+
+    match fhe_engine.rotate_keys(client_id) {
+        Ok(new_server_id) => {
+            log::info!("Successfully rotated keys for client {}", client_id);
+            Ok(Json(serde_json::json!({
+                "client_id": client_id,
+                "new_server_id": new_server_id,
+                "rotated_at": chrono::Utc::now().timestamp(),
+                "status": "success"
+            })))
+        }
+        Err(e) => {
+            log::error!("Key rotation failed for client {}: {}", client_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Policy attached to a client key
+pub(super) async fn get_key_policy(
+    State(state): State<Arc<ProxyState>>,
+    Path(client_id): Path<Uuid>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "client_id": client_id,
+        "policy": state.key_policies.policy(client_id).await,
+        "violations": state.key_policies.violation_count()
+    }))
+}
+
+/// Attach or replace the policy of an existing client key; admins only
+pub(super) async fn set_key_policy(
+    State(state): State<Arc<ProxyState>>,
+    Path(client_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(policy): Json<KeyPolicy>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if !state
+        .fhe_engine
+        .read()
+        .await
+        .client_keys
+        .contains_key(&client_id)
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    state.key_policies.attach(client_id, policy.clone()).await;
+    audit(
+        &state,
+        "key_policy.attach",
+        &client_id.to_string(),
+        serde_json::json!({ "policy": policy, "admin": admin }),
+    );
+
+    Ok(Json(serde_json::json!({
+        "client_id": client_id,
+        "policy": policy
+    })))
+}
+
+/// Validate ciphertext integrity
+pub(super) async fn validate_ciphertext(
+    State(state): State<Arc<ProxyState>>,
+    Path(ciphertext_id): Path<Uuid>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let ciphertext = state
+        .ciphertext_cache
+        .read()
+        .await
+        .get(&ciphertext_id)
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let fhe_engine = state.fhe_engine.read().await;
+
+    match fhe_engine.validate_ciphertext(&ciphertext) {
+        Ok(is_valid) => {
+            let validation_result = if is_valid { "valid" } else { "invalid" };
+            if !is_valid {
+                state.siem.emit(
+                    SecurityEvent::new(
+                        SecurityEventKind::Anomaly,
+                        "Ciphertext failed integrity validation",
+                    )
+                    .subject(&ciphertext_id.to_string()),
+                );
+            }
+
+            Ok(Json(serde_json::json!({
+                "ciphertext_id": ciphertext_id,
+                "status": validation_result,
+                "noise_budget": ciphertext.noise_budget,
+                "size_bytes": ciphertext.data.len(),
+                "security_level": ciphertext.params.security_level,
+                "validated_at": chrono::Utc::now().timestamp()
+            })))
+        }
+        Err(EngineError::ParamMismatch(e)) => {
+            log::warn!("Ciphertext {} parameter mismatch: {}", ciphertext_id, e);
+            Err(StatusCode::CONFLICT)
+        }
+        Err(e) => {
+            log::error!("Ciphertext validation error: {}", e);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+/// Concatenate two ciphertexts
+pub(super) async fn concatenate_ciphertexts(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<serde_json::Value>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let ciphertext_a_id: Uuid = request["ciphertext_a"]
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let ciphertext_b_id: Uuid = request["ciphertext_b"]
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    enforce_approval(&state, &headers, &[ciphertext_a_id, ciphertext_b_id])?;
+
+    let (ciphertext_a, ciphertext_b) = {
+        let cache = state.ciphertext_cache.read().await;
+
+        let ciphertext_a = cache
+            .get(&ciphertext_a_id)
+            .cloned()
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        let ciphertext_b = cache
+            .get(&ciphertext_b_id)
+            .cloned()
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        (ciphertext_a, ciphertext_b)
+    };
+
+    let fhe_engine = state.fhe_engine.read().await;
+
+    match fhe_engine.concatenate_encrypted(&ciphertext_a, &ciphertext_b) {
+        Ok(result_ciphertext) => {
+            let owners = state
+                .key_policies
+                .owners_of(&[ciphertext_a_id, ciphertext_b_id])
+                .await;
+            state
+                .key_policies
+                .record_derived(&result_ciphertext, &owners)
+                .await;
+
+            // Cache the result
+            state
+                .ciphertext_cache
+                .write()
+                .await
+                .insert(result_ciphertext.id, result_ciphertext.clone());
+
+            Ok(Json(serde_json::json!({
+                "result_ciphertext_id": result_ciphertext.id,
+                "input_a": ciphertext_a_id,
+                "input_b": ciphertext_b_id,
+                "size_bytes": result_ciphertext.data.len(),
+                "noise_budget": result_ciphertext.noise_budget,
+                "created_at": chrono::Utc::now().timestamp()
+            })))
+        }
+        Err(e) => {
+            log::error!("Ciphertext concatenation failed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Run several dependent operations as one unit. Only the requested outputs
+/// are stored and returned, and only if every step succeeded; the
+/// transaction is audited once, as committed or aborted.
+pub(super) async fn run_transaction(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<TransactionRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    if !state.config.transactions.enabled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let tenant = tenant_id(&headers);
+    let plan = TransactionPlan::new(request, &state.config.transactions).map_err(|e| {
+        log::warn!("Rejected transaction: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    enforce_approval(&state, &headers, &plan.loads())?;
+
+    let loaded: HashMap<Uuid, Ciphertext> = {
+        let cache = state.ciphertext_cache.read().await;
+        plan.loads()
+            .into_iter()
+            .map(|id| cache.get(&id).cloned().map(|ct| (id, ct)))
+            .collect::<Option<_>>()
+            .ok_or(StatusCode::NOT_FOUND)?
+    };
+
+    let mut permits = Vec::new();
+    for operation in plan.operations() {
+        permits.push(execution_permit(&state, operation).await?);
+    }
+    let outcome = {
+        let fhe_engine = state.fhe_engine.read().await;
+        plan.execute(&fhe_engine, &loaded)
+    };
+    drop(permits);
+
+    let transaction_id = Uuid::new_v4();
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            log::warn!("Transaction {} aborted: {}", transaction_id, e);
+            state.metrics.increment_errors();
+            audit(
+                &state,
+                "transaction.abort",
+                &transaction_id.to_string(),
+                serde_json::json!({ "tenant": tenant, "error": e.to_string() }),
+            );
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    };
+
+    // Outputs keep the restrictions of every key the transaction drew on
+    let mut owners = state.key_policies.owners_of(&plan.loads()).await;
+    owners.extend(plan.encryption_keys());
+    for (_, ciphertext) in &outcome.outputs {
+        state.key_policies.record_derived(ciphertext, &owners).await;
+    }
+    {
+        let mut cache = state.ciphertext_cache.write().await;
+        for (_, ciphertext) in &outcome.outputs {
+            cache.insert(ciphertext.id, ciphertext.clone());
+        }
+    }
+    state
+        .offboarding
+        .record_ciphertexts(tenant, outcome.outputs.iter().map(|(_, ct)| ct.id));
+    let outputs: serde_json::Map<String, serde_json::Value> = outcome
+        .outputs
+        .iter()
+        .map(|(step, ciphertext)| {
+            (
+                step.clone(),
+                serde_json::json!({
+                    "ciphertext_id": ciphertext.id,
+                    "encrypted_data": BASE64_STANDARD.encode(&ciphertext.data),
+                    "noise_budget": ciphertext.noise_budget,
+                }),
+            )
+        })
+        .collect();
+    audit(
+        &state,
+        "transaction.commit",
+        &transaction_id.to_string(),
+        serde_json::json!({
+            "tenant": tenant,
+            "steps": outcome.steps,
+            "outputs": outcome
+                .outputs
+                .iter()
+                .map(|(_, ciphertext)| ciphertext.id)
+                .collect::<Vec<_>>(),
+        }),
+    );
+
+    Ok(Json(serde_json::json!({
+        "transaction_id": transaction_id,
+        "outputs": outputs,
+        "steps": outcome.steps,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, TenantOverrides};
+    use crate::proxy::ProxyServer;
+
+    /// An HSM that takes custody of every key it is sent
+    async fn hsm() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = axum::Router::new().route("/keys", axum::routing::post(|| async { "{}" }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_engine_holds_no_secret_key_for_delegated_tenants() {
+        let mut config = Config::default();
+        config.encryption.decryption_delegation.enabled = true;
+        config.encryption.decryption_delegation.endpoint = hsm().await;
+        config.tenants.overrides.insert(
+            "acme".to_string(),
+            TenantOverrides {
+                require_delegated_decryption: Some(true),
+                ..Default::default()
+            },
+        );
+        let state = ProxyServer::new(config).unwrap().state;
+        let generate = |tenant: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-tenant-id", tenant.parse().unwrap());
+            generate_keys(State(state.clone()), headers, None)
+        };
+
+        let Json(acme) = generate("acme").await.unwrap();
+        let client_id: Uuid = serde_json::from_value(acme["client_id"].clone()).unwrap();
+        assert_eq!(acme["key_handle"], format!("fhe-client-{}", client_id));
+        let Json(globex) = generate("globex").await.unwrap();
+        let globex_id: Uuid = serde_json::from_value(globex["client_id"].clone()).unwrap();
+        assert!(globex["key_handle"].is_null());
+
+        let engine = state.fhe_engine.read().await;
+        assert!(!engine.holds_secret_key(client_id));
+        assert!(engine.holds_secret_key(globex_id));
+        let ciphertext = engine.encrypt_text(client_id, "hello").unwrap();
+        assert!(engine.decrypt_text(client_id, &ciphertext).is_err());
+    }
+}
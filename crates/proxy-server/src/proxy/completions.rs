@@ -0,0 +1,1135 @@
+//! Encrypted chat completions, unary and streamed
+
+use super::approvals::{approval_class, enforce_approval, hold_for_approval};
+use super::ciphertexts::execution_permit;
+use super::layers::start_hop;
+use super::providers::provider_error_status;
+use super::{
+    complete_with_fallback, model_policy, tenant_id, CacheRefresh, CachedCompletion, CallOptions,
+    LlmMessage, LlmRequest, LlmUsage, PromptPacker, ProxyState, ResponseCache, Revalidation,
+};
+use crate::billing::REQUEST_ID_HEADER;
+use crate::config::{EffectiveTenantConfig, FheOperation, WorkloadCachePolicy};
+use crate::deadline_budget::{Hop, RequestDeadline};
+use crate::error::Error;
+use crate::etag;
+use crate::experiments::{Assignment, USER_HASH_HEADER};
+use crate::failure_domains::{self};
+use crate::feature_flags::{self};
+use crate::fhe::{Ciphertext, EngineError, PackingOptimizer};
+use crate::mirror::MIRROR_HEADER;
+use crate::persistence::IdempotencyRecord;
+use crate::prompt_lint::{LintRejection, LintReport};
+use crate::quality::QualityKey;
+use crate::request_journal::{self, JournalEvent};
+use crate::response_metadata::{CacheStatus, ProcessingTimes, ResponseCost, ResponseMetadata};
+use crate::sandbox::SandboxGrant;
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use crate::streaming::{ControlFrame, LoadSample};
+use crate::telemetry::ServedModel;
+use crate::validation::RequestContext;
+use crate::workload_tags::WorkloadTag;
+use axum::{
+    extract::{Extension, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+};
+use base64::prelude::*;
+use ring::digest;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Request to process encrypted prompt
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProcessRequest {
+    pub ciphertext_id: Uuid,
+    pub encrypted_data: String, // Base64 encoded
+    pub provider: String,
+    pub model: String,
+    pub stream: Option<bool>,
+    /// Prompt template the request was built from, used for response caching
+    pub template_id: Option<String>,
+    /// Extra tags for the cached response, usable for invalidation
+    #[serde(default)]
+    pub cache_tags: Vec<String>,
+    /// Fingerprint of the parameters the client negotiated via `/v1/params/negotiate`
+    pub params_hash: Option<String>,
+    /// Skip prompt packing and process immediately
+    #[serde(default)]
+    pub latency_critical: bool,
+    /// Client-side lint of the prompt, required for tenants with prompt linting
+    pub lint_report: Option<LintReport>,
+    /// Sensitivity class of the result; classes in `approvals.classes` hold it
+    /// for approval before it can be decrypted
+    pub sensitivity: Option<String>,
+}
+
+/// Process encrypted completion request with enhanced security and validation
+pub(super) async fn process_encrypted_completion(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    workload: Option<Extension<WorkloadTag>>,
+    sandbox: Option<Extension<SandboxGrant>>,
+    revalidation: Option<Extension<Revalidation>>,
+    deadline: Option<Extension<RequestDeadline>>,
+    Json(mut request): Json<ProcessRequest>,
+) -> std::result::Result<Response, StatusCode> {
+    let _timer = state.profiler.start_timer("encrypted_completion");
+    let started = Instant::now();
+    let mut response_headers = HeaderMap::new();
+
+    let tenant = tenant_id(&headers);
+
+    // Replay the stored response for a repeated idempotency key
+    let idempotency_key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(|key| format!("{}:{}", tenant.unwrap_or_default(), key));
+    if let Some(key) = &idempotency_key {
+        match state.store.get_idempotent(key) {
+            Ok(Some(record)) => {
+                log::debug!("Replaying stored response for idempotency key {}", key);
+                return Ok((response_headers, Json(record.response)).into_response());
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Idempotency lookup failed for {}: {}", key, e),
+        }
+    }
+    start_hop(deadline.as_deref(), Hop::Validation)?;
+
+    let tenant_config = model_policy(&state, tenant)?;
+    enforce_prompt_lint(&state, &tenant_config, &request)?;
+    let approval_class = approval_class(&state, &request);
+
+    // Enforce model allow/deny lists before anything is dispatched
+    let (model, upgraded_from) = tenant_config.govern_model(&request.model).map_err(|e| {
+        log::warn!("Model {} rejected: {}", request.model, e);
+        state.siem.emit(
+            SecurityEvent::new(SecurityEventKind::PolicyViolation, &e.to_string())
+                .tenant(tenant)
+                .subject(&request.model),
+        );
+        StatusCode::FORBIDDEN
+    })?;
+    if let Some(deprecated) = upgraded_from {
+        log::info!("Upgrading deprecated model {} to {}", deprecated, model);
+        let warning = format!(
+            "299 - \"Model {} is deprecated; request served by {}\"",
+            deprecated, model
+        );
+        if let Ok(value) = warning.parse() {
+            response_headers.insert(axum::http::header::WARNING, value);
+        }
+        request.model = model;
+    }
+
+    // A model clients score poorly hands its requests to a better alternative
+    if let Some(alternative) = state.quality.reroute(&request.model, |candidate| {
+        matches!(tenant_config.govern_model(candidate), Ok((_, None)))
+    }) {
+        log::info!(
+            "Model {} is downranked for quality; serving {}",
+            request.model,
+            alternative
+        );
+        if let Ok(value) = request.model.parse() {
+            response_headers.insert("x-quality-rerouted-from", value);
+        }
+        request.model = alternative;
+    }
+
+    // A workload tag may pick the provider; tenant routing rules still win
+    if let Some(provider) = workload.as_ref().and_then(|w| w.policy.provider.clone()) {
+        request.provider = provider;
+    }
+
+    // Apply tenant routing rules before validating the provider
+    let provider = tenant_config.route_provider(&request.model, &request.provider);
+    if provider != request.provider {
+        log::debug!(
+            "Routing model {} to provider {} for tenant {}",
+            request.model,
+            provider,
+            tenant.unwrap_or("<global>")
+        );
+        request.provider = provider.to_string();
+    }
+
+    // Runbook failover replaces a provider that is down
+    if let Some(replacement) = state.provider_failover.read().await.get(&request.provider) {
+        log::info!(
+            "Failing over from provider {} to {}",
+            request.provider,
+            replacement
+        );
+        request.provider = replacement.clone();
+    }
+
+    // A provider in a drained failure domain hands its traffic to one outside it
+    let member = failure_domains::provider_member(&request.provider);
+    if state.failure_domains.is_drained(&member) {
+        let mut candidates: Vec<String> = state
+            .llm_providers
+            .keys()
+            .map(|name| failure_domains::provider_member(name))
+            .collect();
+        candidates.sort();
+        match state
+            .failure_domains
+            .healthy_alternative(&member, candidates.iter().map(String::as_str))
+            .and_then(|candidate| candidate.strip_prefix("provider:"))
+        {
+            Some(replacement) => {
+                log::info!(
+                    "Provider {} is in a drained failure domain; using {}",
+                    request.provider,
+                    replacement
+                );
+                request.provider = replacement.to_string();
+            }
+            None => log::warn!(
+                "Provider {} is in a drained failure domain and no other provider is available",
+                request.provider
+            ),
+        }
+    }
+    enforce_sandbox_scope(sandbox.as_deref(), &request.model, &request.provider)?;
+
+    let cached = state
+        .ciphertext_cache
+        .read()
+        .await
+        .get(&request.ciphertext_id)
+        .cloned();
+    let ciphertext = match cached {
+        Some(ct) => ct,
+        // A staging proxy takes mirrored copies' ciphertexts from the request itself
+        None if headers.contains_key(MIRROR_HEADER) && state.mirror.accepts_mirrored() => {
+            let data = BASE64_STANDARD
+                .decode(&request.encrypted_data)
+                .map_err(|_| StatusCode::BAD_REQUEST)?;
+            let ciphertext = Ciphertext {
+                id: request.ciphertext_id,
+                data,
+                params: state.fhe_engine.read().await.get_params().clone(),
+                noise_budget: None,
+            };
+            state
+                .ciphertext_cache
+                .write()
+                .await
+                .insert(ciphertext.id, ciphertext.clone());
+            state
+                .offboarding
+                .record_ciphertexts(tenant, [ciphertext.id]);
+            ciphertext
+        }
+        None => {
+            log::warn!("Ciphertext not found: {}", request.ciphertext_id);
+            return Err(StatusCode::NOT_FOUND);
+        }
+    };
+    // Processing a held result would hand its content to a new, unheld one
+    enforce_approval(&state, &headers, &[ciphertext.id])?;
+
+    // Run the validator chain: size, schema, policy, params hash, replay and custom stages
+    let server_params_hash = state.fhe_engine.read().await.get_params().fingerprint();
+    let validation_context = RequestContext {
+        tenant,
+        provider: &request.provider,
+        model: &request.model,
+        template_id: request.template_id.as_deref(),
+        ciphertext_bytes: ciphertext.data.len(),
+        params_hash: request.params_hash.as_deref(),
+        server_params_hash: &server_params_hash,
+        nonce: headers.get("x-request-nonce").and_then(|v| v.to_str().ok()),
+    };
+    if let Err(rejection) = state.validators.validate(&validation_context) {
+        log::warn!(
+            "Request rejected by validator {}: {}",
+            rejection.validator,
+            rejection.reason
+        );
+        if rejection.status == 403 {
+            state.siem.emit(
+                SecurityEvent::new(SecurityEventKind::PolicyViolation, &rejection.reason)
+                    .tenant(tenant)
+                    .subject(&rejection.validator),
+            );
+        }
+        return Err(StatusCode::from_u16(rejection.status).unwrap_or(StatusCode::BAD_REQUEST));
+    }
+
+    // Copy a sample of validated requests to staging, ciphertext inline
+    if state.mirror.should_mirror(&headers) {
+        state.mirror.mirror(
+            "/v1/chat/completions",
+            &headers,
+            serde_json::json!({
+                "ciphertext_id": request.ciphertext_id,
+                "encrypted_data": BASE64_STANDARD.encode(&ciphertext.data),
+                "provider": request.provider,
+                "model": request.model,
+                "stream": request.stream,
+                "template_id": request.template_id,
+                "params_hash": request.params_hash,
+                "latency_critical": request.latency_critical,
+            }),
+        );
+    }
+
+    // Get the LLM provider with validation
+    let _provider = state.llm_providers.get(&request.provider).ok_or_else(|| {
+        log::error!("Provider not configured: {}", request.provider);
+        StatusCode::BAD_REQUEST
+    })?;
+    request_journal::record(JournalEvent::Routed {
+        provider: request.provider.clone(),
+        model: request.model.clone(),
+    });
+
+    // Route requests from a template under test to the user's variant
+    let assignment = request.template_id.as_deref().and_then(|template_id| {
+        let user_hash = headers.get(USER_HASH_HEADER)?.to_str().ok()?;
+        state.experiments.assign(tenant, template_id, user_hash)
+    });
+    if let Some(assignment) = &assignment {
+        request.template_id = Some(assignment.template_id.clone());
+        if let (Ok(experiment), Ok(variant)) =
+            (assignment.experiment.parse(), assignment.variant.parse())
+        {
+            response_headers.insert("x-experiment", experiment);
+            response_headers.insert("x-experiment-variant", variant);
+        }
+    }
+
+    // Serve from the response cache when the tenant opted in for this model/template
+    let user_hash = headers.get(USER_HASH_HEADER).and_then(|v| v.to_str().ok());
+    let cache_bypassed = workload
+        .as_ref()
+        .is_some_and(|w| w.policy.cache == WorkloadCachePolicy::Bypass);
+    let cache_rule = tenant_config
+        .cache_rule(&request.model, request.template_id.as_deref())
+        .filter(|_| !cache_bypassed && approval_class.is_none())
+        .filter(|_| {
+            state
+                .feature_flags
+                .is_on(feature_flags::RESPONSE_CACHE, tenant, user_hash)
+        })
+        .cloned();
+    let cache_key = cache_rule.as_ref().map(|_| {
+        ResponseCache::key(
+            tenant,
+            &request.model,
+            request.template_id.as_deref(),
+            &ciphertext.data,
+        )
+    });
+    // A refresh replaces the stale entry, so it never reads it
+    let cached = match &cache_key {
+        Some(key) if revalidation.is_none() => state.response_cache.get(key).await,
+        _ => None,
+    };
+    if let Some(key) = &cache_key {
+        if let Some(cached) = cached {
+            let staleness = cached.staleness(Instant::now());
+            state.response_cache.record_hit(tenant, staleness);
+            request_journal::record(JournalEvent::Cache {
+                status: if staleness.is_some() { "stale" } else { "hit" },
+            });
+            if staleness.is_some() {
+                let queued = state.response_cache.queue_refresh(
+                    key,
+                    cache_refresh(
+                        tenant,
+                        &request,
+                        &headers,
+                        workload.as_deref(),
+                        sandbox.as_deref(),
+                    ),
+                );
+                if queued {
+                    log::debug!("Serving stale cached completion and refreshing it");
+                }
+            }
+
+            let mut cache = state.ciphertext_cache.write().await;
+            for ct in &cached.ciphertexts {
+                cache.entry(ct.id).or_insert_with(|| ct.clone());
+            }
+            drop(cache);
+            state
+                .offboarding
+                .record_ciphertexts(tenant, cached.ciphertexts.iter().map(|ct| ct.id));
+            if let Some((processed, chunks)) = cached.ciphertexts.split_first() {
+                state
+                    .response_chunks
+                    .register(processed.id, chunks.iter().map(|c| c.id).collect())
+                    .await;
+            }
+
+            let age = cached.stored_at.elapsed().as_secs();
+            let ttl = cached
+                .expires_at
+                .saturating_duration_since(Instant::now())
+                .as_secs();
+            if staleness.is_some() {
+                response_headers.insert("x-cache", "STALE".parse().unwrap());
+                response_headers.append(
+                    axum::http::header::WARNING,
+                    "110 - \"Response is Stale\"".parse().unwrap(),
+                );
+            } else {
+                response_headers.insert("x-cache", "HIT".parse().unwrap());
+            }
+            response_headers.insert(axum::http::header::AGE, age.into());
+            response_headers.insert("x-cache-ttl", ttl.into());
+            etag::insert_etag(&mut response_headers, &cached.etag);
+            // The client already holds this result; skip re-signing and the body
+            if etag::if_none_match(&headers, &cached.etag) {
+                return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+            }
+
+            // Re-sign so the envelope timestamp reflects this delivery
+            let mut response = cached.response;
+            let mut metadata = ResponseMetadata::from_response(&response).map_err(|e| {
+                log::error!("Cached response has no valid metadata: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            metadata.cache = CacheStatus::Hit;
+            metadata.processing = ProcessingTimes {
+                total_ms: started.elapsed().as_millis() as u64,
+                fhe_ms: None,
+            };
+            attach_metadata(&state, &mut response, &metadata)?;
+            if let (Some(signer), Some(processed), Some(id)) = (
+                &state.response_signer,
+                cached.ciphertexts.first(),
+                response["id"].as_str(),
+            ) {
+                let signed = signer.sign(id, &processed.data).map_err(|e| {
+                    log::error!("Response signing failed: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+                response["fhe_metadata"]["response_signature"] = serde_json::json!(signed);
+            }
+            if let Some(assignment) = &assignment {
+                record_experiment_completion(&state, assignment, started, &response);
+            }
+            return Ok((response_headers, Json(response)).into_response());
+        }
+        response_headers.insert("x-cache", "MISS".parse().unwrap());
+        request_journal::record(JournalEvent::Cache { status: "miss" });
+    }
+    // Kept to preload the completion again once it has left the cache
+    let preload_source = cache_key
+        .as_ref()
+        .filter(|_| state.config.performance.response_cache.preload.enabled)
+        .map(|_| {
+            cache_refresh(
+                tenant,
+                &request,
+                &headers,
+                workload.as_deref(),
+                sandbox.as_deref(),
+            )
+        });
+
+    let mut fhe_engine = state.fhe_engine.read().await;
+
+    // Validate ciphertext integrity before processing
+    if !fhe_engine.validate_ciphertext(&ciphertext).map_err(|e| {
+        log::error!("Ciphertext validation failed: {}", e);
+        match e {
+            EngineError::ParamMismatch(_) => StatusCode::CONFLICT,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    })? {
+        log::error!("Ciphertext failed integrity check");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Process the encrypted prompt, sharing a ciphertext with other short
+    // prompts from the tenant unless the caller cannot afford the wait
+    let packing_tenant = tenant.filter(|_| {
+        state.config.performance.packing.enabled
+            && !request.latency_critical
+            && PackingOptimizer::new(fhe_engine.get_params().clone()).is_short(&ciphertext)
+            && state
+                .feature_flags
+                .is_on(feature_flags::PROMPT_PACKING, tenant, user_hash)
+    });
+    let queued = Instant::now();
+    let permit = match start_hop(deadline.as_deref(), Hop::Queue)? {
+        Some(budget) => {
+            tokio::time::timeout(budget, execution_permit(&state, FheOperation::Process))
+                .await
+                .map_err(|_| {
+                    log::warn!(
+                        "No FHE execution slot within the {}ms queue budget",
+                        budget.as_millis()
+                    );
+                    request_journal::record(JournalEvent::DeadlineExceeded {
+                        hop: Hop::Queue.as_str(),
+                    });
+                    StatusCode::GATEWAY_TIMEOUT
+                })??
+        }
+        None => execution_permit(&state, FheOperation::Process).await?,
+    };
+    request_journal::record(JournalEvent::ExecutionSlot {
+        waited_ms: queued.elapsed().as_millis() as u64,
+    });
+    start_hop(deadline.as_deref(), Hop::Fhe)?;
+    let fhe_started = Instant::now();
+    let processed_ciphertext = if let Some(tenant) = packing_tenant {
+        // The flush takes its own read lock; don't hold ours across the wait
+        drop(fhe_engine);
+        let processed = PromptPacker::process(&state, tenant, ciphertext.clone()).await;
+        fhe_engine = state.fhe_engine.read().await;
+        processed
+    } else {
+        fhe_engine
+            .process_encrypted_prompt(&ciphertext)
+            .map_err(Error::from)
+    }
+    .map_err(|e| {
+        log::error!("FHE processing failed: {}", e);
+        state.metrics.increment_errors();
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    drop(permit);
+    let fhe_ms = fhe_started.elapsed().as_millis() as u64;
+    let remaining_budget = processed_ciphertext.noise_budget;
+
+    // Split the response into independently decryptable chunks
+    let chunk_size = state.config.performance.response_chunking.chunk_size_bytes;
+    let chunks = fhe_engine
+        .split_into_chunks(&processed_ciphertext, chunk_size)
+        .map_err(|e| {
+            log::error!("Response chunking failed: {}", e);
+            state.metrics.increment_errors();
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let engine = fhe_engine.fingerprint();
+    drop(fhe_engine);
+    request_journal::record(JournalEvent::FheProcessed {
+        engine: engine.clone(),
+        duration_ms: fhe_ms,
+        packed: packing_tenant.is_some(),
+    });
+
+    // Don't spend a provider call that cannot finish before the deadline
+    start_hop(deadline.as_deref(), Hop::Provider)?;
+
+    // Reserve the result's privacy cost before the provider sees the prompt;
+    // a reset that landed since the request arrived doesn't charge the new period
+    let privacy_charge = reserve_privacy_budget(&state, tenant, &tenant_config, started).await?;
+
+    // Hand the processed prompt to the provider, moving on to the configured
+    // fallbacks when it fails in a way another provider may not
+    let provider_request = LlmRequest {
+        model: request.model.clone(),
+        messages: vec![LlmMessage {
+            role: "user".to_string(),
+            content: BASE64_STANDARD.encode(&processed_ciphertext.data),
+        }],
+        // Evaluation over the ciphertext is deterministic, so a call cut off
+        // mid-response is safe to re-issue
+        temperature: Some(0.0),
+        max_tokens: None,
+        stream: None,
+    };
+    let order: Vec<String> = std::iter::once(request.provider.clone())
+        .chain(
+            state
+                .config
+                .llm
+                .error_retry
+                .fallback_providers
+                .iter()
+                .filter(|name| **name != request.provider)
+                // A fallback whose probes opened its breaker would only fail again
+                .filter(|name| {
+                    state
+                        .dependencies
+                        .is_available(&format!("provider:{}", name))
+                })
+                .cloned(),
+        )
+        .collect();
+    let options = CallOptions {
+        deadline: deadline.as_deref().map(RequestDeadline::expires_at),
+        sla_class: Some(tenant_config.sla_class),
+    };
+    let (served_by, completion) = match complete_with_fallback(
+        &state.llm_providers,
+        &order,
+        &provider_request,
+        options,
+    )
+    .await
+    {
+        Ok(served) => served,
+        Err(e) => {
+            log::warn!("Provider call for {} failed: {}", request.model, e);
+            state.metrics.increment_errors();
+            // Nothing was released, so nothing was spent
+            if let Some(charge) = &privacy_charge {
+                state
+                    .privacy_tracker
+                    .refund(&charge.budget, charge.epsilon, charge.delta)
+                    .await;
+            }
+            return Err(provider_error_status(&e));
+        }
+    };
+    if served_by != request.provider {
+        log::info!(
+            "Provider {} failed; {} served the request",
+            request.provider,
+            served_by
+        );
+        if let Ok(value) = served_by.parse() {
+            response_headers.insert("x-served-by-provider", value);
+        }
+        request.provider = served_by;
+    }
+
+    let usage = completion.usage.unwrap_or(LlmUsage {
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        total_tokens: 0,
+    });
+
+    // Held results get an approval request before the client learns their ids
+    if let Some(class) = &approval_class {
+        let ciphertext_ids = std::iter::once(processed_ciphertext.id)
+            .chain(chunks.iter().map(|chunk| chunk.id))
+            .collect();
+        let approval = hold_for_approval(&state, tenant, class, ciphertext_ids).await;
+        if let Ok(value) = approval.id.to_string().parse() {
+            response_headers.insert("x-approval-request-id", value);
+        }
+    }
+
+    // Attest that this request went through the FHE path
+    let request_digest = {
+        let mut context = digest::Context::new(&digest::SHA256);
+        context.update(&ciphertext.data);
+        context.update(request.model.as_bytes());
+        context
+            .finish()
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    };
+    let attestation = state
+        .attestation
+        .attest(request_digest, "fhe", &processed_ciphertext.params)
+        .map_err(|e| {
+            log::error!("Attestation signing failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // Sign the envelope so clients can detect tampering between proxy and client
+    let response_id = format!("fhe-{}", Uuid::new_v4());
+    let response_signature = state
+        .response_signer
+        .as_ref()
+        .map(|signer| signer.sign(&response_id, &processed_ciphertext.data))
+        .transpose()
+        .map_err(|e| {
+            log::error!("Response signing failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut response = serde_json::json!({
+        "id": response_id,
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": request.model,
+        "choices": completion.choices,
+        "usage": usage,
+        "fhe_metadata": {
+            "processed_ciphertext_id": processed_ciphertext.id,
+            "noise_budget_remaining": processed_ciphertext.noise_budget,
+            "encryption_params": processed_ciphertext.params,
+            "chunks": {
+                "count": chunks.len(),
+                "chunk_size_bytes": chunk_size
+            },
+            "attestation": attestation,
+            "response_signature": response_signature
+        }
+    });
+
+    let mut metadata = ResponseMetadata::new(
+        if cache_key.is_some() {
+            CacheStatus::Miss
+        } else {
+            CacheStatus::Bypass
+        },
+        &engine,
+    );
+    metadata.processing = ProcessingTimes {
+        total_ms: started.elapsed().as_millis() as u64,
+        fhe_ms: Some(fhe_ms),
+    };
+    metadata.noise_budget = processed_ciphertext.noise_budget;
+    metadata.attestation = Some(attestation);
+    metadata.cost = Some(ResponseCost {
+        prompt_tokens: response["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+        completion_tokens: response["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        ciphertext_bytes: (ciphertext.data.len() + processed_ciphertext.data.len()) as u64,
+    });
+    metadata.extend(
+        &state.config.response_metadata.extension_namespaces,
+        "experimental.packed",
+        packing_tenant.is_some(),
+    );
+    attach_metadata(&state, &mut response, &metadata)?;
+
+    state
+        .response_chunks
+        .register(
+            processed_ciphertext.id,
+            chunks.iter().map(|chunk| chunk.id).collect(),
+        )
+        .await;
+
+    if let (Some(key), Some(rule)) = (cache_key, cache_rule) {
+        let mut tags = rule.tags.clone();
+        tags.extend(request.cache_tags.iter().cloned());
+        let ttl = Duration::from_secs(rule.ttl_seconds);
+        // Tenant rules are held to the global staleness bound too
+        let stale_window = Duration::from_secs(
+            rule.stale_while_revalidate_seconds
+                .min(state.config.performance.response_cache.max_stale_seconds),
+        );
+        let etag = etag::digest_etag(&[key.as_bytes(), &processed_ciphertext.data]);
+        etag::insert_etag(&mut response_headers, &etag);
+        if let Some(source) = preload_source {
+            state.response_cache.remember_source(&key, source);
+        }
+        state
+            .response_cache
+            .insert(
+                key,
+                CachedCompletion {
+                    tenant: tenant.map(str::to_string),
+                    model: request.model.clone(),
+                    template_id: request.template_id.clone(),
+                    tags,
+                    response: response.clone(),
+                    ciphertexts: std::iter::once(processed_ciphertext.clone())
+                        .chain(chunks.iter().cloned())
+                        .collect(),
+                    etag,
+                    recompute_cost_ms: started.elapsed().as_millis() as u64,
+                    stored_at: Instant::now(),
+                    expires_at: Instant::now() + ttl,
+                    stale_until: Instant::now() + ttl + stale_window,
+                },
+            )
+            .await;
+        response_headers.insert("x-cache-ttl", rule.ttl_seconds.into());
+    }
+
+    // Cache the processed ciphertext and its chunks
+    state.offboarding.record_ciphertexts(
+        tenant,
+        chunks
+            .iter()
+            .map(|chunk| chunk.id)
+            .chain([processed_ciphertext.id]),
+    );
+    {
+        let mut cache = state.ciphertext_cache.write().await;
+        for chunk in chunks {
+            cache.insert(chunk.id, chunk);
+        }
+        cache.insert(processed_ciphertext.id, processed_ciphertext);
+    }
+
+    if let Some(key) = idempotency_key {
+        let created_at = chrono::Utc::now().timestamp();
+        let record = IdempotencyRecord {
+            key,
+            response: response.clone(),
+            created_at,
+            expires_at: created_at + state.config.persistence.idempotency_ttl_seconds as i64,
+        };
+        if let Err(e) = state.store.put_idempotent(&record) {
+            log::warn!("Failed to store idempotent response {}: {}", record.key, e);
+        }
+    }
+
+    if let Some(assignment) = &assignment {
+        record_experiment_completion(&state, assignment, started, &response);
+    }
+    if let Some(Extension(grant)) = &sandbox {
+        let tokens = response["usage"]["completion_tokens"].as_u64().unwrap_or(0);
+        state.sandbox.record_tokens(grant.key_id, tokens);
+    } else {
+        // Sandbox traffic stays out of the consumption baselines
+        record_noise_consumption(&state, tenant, ciphertext.noise_budget, remaining_budget).await;
+    }
+
+    // Open the completion for quality scoring under its request id
+    if state.quality.is_enabled() {
+        let request_id = request_journal::current_request_id().unwrap_or_else(Uuid::new_v4);
+        state.quality.record_completion(
+            request_id,
+            tenant,
+            QualityKey {
+                model: request.model.clone(),
+                template_id: request.template_id.clone(),
+                provider: request.provider.clone(),
+            },
+            chrono::Utc::now().timestamp(),
+        );
+        response_headers.insert(REQUEST_ID_HEADER, request_id.to_string().parse().unwrap());
+    }
+
+    let mut response = (response_headers, Json(response)).into_response();
+    response
+        .extensions_mut()
+        .insert(ServedModel(request.model.clone()));
+    Ok(response)
+}
+
+/// Refuse a model or provider a sandbox key is not issued for
+pub(super) fn enforce_sandbox_scope(
+    sandbox: Option<&SandboxGrant>,
+    model: &str,
+    provider: &str,
+) -> std::result::Result<(), StatusCode> {
+    match sandbox {
+        Some(grant) if !grant.allows(model, provider) => {
+            log::warn!(
+                "Sandbox key {} is not issued for model {} at {}",
+                grant.key_id,
+                model,
+                provider
+            );
+            Err(StatusCode::FORBIDDEN)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validate `metadata` and set it as the `metadata` block of a completion
+/// The completion as the cache refresher re-runs it, without the headers
+/// that tie a request to one delivery
+fn cache_refresh(
+    tenant: Option<&str>,
+    request: &ProcessRequest,
+    headers: &HeaderMap,
+    workload: Option<&WorkloadTag>,
+    sandbox: Option<&SandboxGrant>,
+) -> CacheRefresh {
+    let mut headers = headers.clone();
+    for name in ["idempotency-key", "x-request-nonce", "if-none-match"] {
+        headers.remove(name);
+    }
+    CacheRefresh {
+        tenant: tenant.map(str::to_string),
+        request: request.clone(),
+        headers,
+        workload: workload.cloned(),
+        sandbox: sandbox.cloned(),
+    }
+}
+
+fn attach_metadata(
+    state: &ProxyState,
+    response: &mut serde_json::Value,
+    metadata: &ResponseMetadata,
+) -> std::result::Result<(), StatusCode> {
+    metadata
+        .validate(&state.config.response_metadata.extension_namespaces)
+        .map_err(|e| {
+            log::error!("Refusing to send invalid response metadata: {}", e);
+            state.metrics.increment_errors();
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    response["metadata"] = serde_json::json!(metadata);
+    Ok(())
+}
+
+/// Aggregate an experiment request's latency and response length; nothing of its content
+fn record_experiment_completion(
+    state: &ProxyState,
+    assignment: &Assignment,
+    started: Instant,
+    response: &serde_json::Value,
+) {
+    let tokens = response["usage"]["completion_tokens"].as_u64().unwrap_or(0);
+    state
+        .experiments
+        .record_completion(assignment, started.elapsed(), tokens);
+}
+
+/// Refuse a completion from a tenant that requires prompt linting unless it
+/// carries a valid, clean lint report for its ciphertext
+fn enforce_prompt_lint(
+    state: &ProxyState,
+    tenant_config: &EffectiveTenantConfig,
+    request: &ProcessRequest,
+) -> std::result::Result<(), StatusCode> {
+    if !tenant_config.require_prompt_lint {
+        return Ok(());
+    }
+    let tenant = tenant_config.tenant_id.as_str();
+    let now = chrono::Utc::now().timestamp();
+    state
+        .prompt_lint
+        .check(
+            tenant,
+            request.lint_report.as_ref(),
+            &request.encrypted_data,
+            now,
+        )
+        .map_err(|rejection| {
+            log::warn!("Prompt lint rejected request for {}: {}", tenant, rejection);
+            state.siem.emit(
+                SecurityEvent::new(SecurityEventKind::PolicyViolation, &rejection.to_string())
+                    .tenant(Some(tenant))
+                    .subject(&request.ciphertext_id.to_string()),
+            );
+            match rejection {
+                LintRejection::Failed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                LintRejection::Missing | LintRejection::Invalid(_) => StatusCode::BAD_REQUEST,
+            }
+        })
+}
+
+/// The lint policy clients apply before encrypting, and whether the caller's
+/// tenant must send a report
+pub(super) async fn get_lint_policy(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let tenant_config = match tenant_id(&headers) {
+        Some(tenant) => state.tenant_configs.resolve(tenant),
+        None => state.tenant_configs.resolve_global(),
+    }
+    .map_err(|_| StatusCode::BAD_REQUEST)?;
+    Ok(Json(serde_json::json!({
+        "required": tenant_config.require_prompt_lint,
+        "policy": state.prompt_lint.policy(),
+    })))
+}
+
+/// Feed the noise budget an operation spent into its tenant's trend
+async fn record_noise_consumption(
+    state: &ProxyState,
+    tenant: Option<&str>,
+    before: Option<u64>,
+    after: Option<u64>,
+) {
+    if !state.noise_trends.is_enabled() {
+        return;
+    }
+    let (Some(before), Some(after)) = (before, after) else {
+        return;
+    };
+    let tenant = tenant.unwrap_or("<global>");
+    let now = chrono::Utc::now().timestamp();
+    let Some(alert) = state
+        .noise_trends
+        .record(tenant, before.saturating_sub(after), after, now)
+    else {
+        return;
+    };
+    let message = format!(
+        "Tenant {} is spending {:.1} bits of noise budget per operation, {:.1}x its baseline of {:.1}",
+        alert.tenant, alert.bits_per_operation, alert.ratio, alert.baseline_bits_per_operation
+    );
+    log::warn!("{}", message);
+    state
+        .monitoring
+        .raise_alert("noise_budget_trend", message, 2)
+        .await;
+}
+
+/// Privacy budget charged by requests without an authenticated tenant, so
+/// none go uncharged
+const UNAUTHENTICATED_PRIVACY_BUDGET: &str = "(unauthenticated)";
+
+/// A completion's reserved privacy cost
+struct PrivacyCharge {
+    budget: String,
+    epsilon: f64,
+    delta: f64,
+}
+
+/// Charge a completion's privacy cost to the budget of `tenant` before
+/// anything is released, refusing it once the budget is spent. Requests
+/// without a tenant share `UNAUTHENTICATED_PRIVACY_BUDGET`.
+async fn reserve_privacy_budget(
+    state: &ProxyState,
+    tenant: Option<&str>,
+    tenant_config: &EffectiveTenantConfig,
+    started: Instant,
+) -> std::result::Result<Option<PrivacyCharge>, StatusCode> {
+    let privacy = &state.config.privacy;
+    if !privacy.track_privacy_budget {
+        return Ok(None);
+    }
+    let charge = PrivacyCharge {
+        budget: tenant.unwrap_or(UNAUTHENTICATED_PRIVACY_BUDGET).to_string(),
+        epsilon: tenant_config.epsilon_per_query,
+        delta: privacy.delta / privacy.max_queries_per_user.max(1) as f64,
+    };
+    let admitted = state
+        .privacy_tracker
+        .check_budget_started_at(&charge.budget, charge.epsilon, charge.delta, started)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !admitted {
+        log::warn!("Privacy budget exhausted for {}", charge.budget);
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+    Ok(Some(charge))
+}
+
+/// Stream an encrypted completion as flow-controlled server-sent events
+///
+/// Each event carries one `StreamFrame`; the `x-stream-id` header and the
+/// `open` frame name the stream for `/v1/chat/stream/{stream_id}/control`.
+pub(super) async fn stream_encrypted_completion(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<ProcessRequest>,
+) -> std::result::Result<Response, StatusCode> {
+    let tenant_config = match tenant_id(&headers) {
+        Some(tenant) => state.tenant_configs.resolve(tenant),
+        None => state.tenant_configs.resolve_global(),
+    }
+    .map_err(|_| StatusCode::BAD_REQUEST)?;
+    enforce_prompt_lint(&state, &tenant_config, &request)?;
+    let approval_class = approval_class(&state, &request);
+
+    let ciphertext = state
+        .ciphertext_cache
+        .read()
+        .await
+        .get(&request.ciphertext_id)
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    enforce_approval(&state, &headers, &[ciphertext.id])?;
+    reserve_privacy_budget(&state, tenant_id(&headers), &tenant_config, Instant::now()).await?;
+
+    let permit = execution_permit(&state, FheOperation::Process).await?;
+    let fhe_engine = state.fhe_engine.read().await;
+    let processed_ciphertext = fhe_engine
+        .process_encrypted_prompt(&ciphertext)
+        .map_err(|e| {
+            log::error!("FHE processing failed: {}", e);
+            state.metrics.increment_errors();
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    drop(permit);
+    let chunk_size = state.config.performance.response_chunking.chunk_size_bytes;
+    let chunks = fhe_engine
+        .split_into_chunks(&processed_ciphertext, chunk_size)
+        .map_err(|e| {
+            log::error!("Response chunking failed: {}", e);
+            state.metrics.increment_errors();
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    drop(fhe_engine);
+
+    // Chunks stay in the cache so a client can fetch ones it missed
+    let chunk_ids: Vec<Uuid> = chunks.iter().map(|chunk| chunk.id).collect();
+    let approval = match &approval_class {
+        Some(class) => {
+            Some(hold_for_approval(&state, tenant_id(&headers), class, chunk_ids.clone()).await)
+        }
+        None => None,
+    };
+    {
+        let mut cache = state.ciphertext_cache.write().await;
+        for chunk in &chunks {
+            cache.insert(chunk.id, chunk.clone());
+        }
+    }
+    state
+        .offboarding
+        .record_ciphertexts(tenant_id(&headers), chunk_ids.iter().copied());
+
+    let load_state = state.clone();
+    let (stream_id, frames) = state
+        .streams
+        .open(tenant_id(&headers), chunks, move || {
+            let queue_depth = load_state.admission.in_flight();
+            LoadSample {
+                queue_depth,
+                capacity: load_state.admission.capacity(),
+                projected_wait_ms: load_state
+                    .queue_projector
+                    .project(queue_depth)
+                    .projected_wait_ms,
+            }
+        })
+        .map_err(|e| {
+            log::warn!(
+                "Refusing stream for ciphertext {}: {}",
+                request.ciphertext_id,
+                e
+            );
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+    state.response_chunks.register(stream_id, chunk_ids).await;
+
+    log::info!(
+        "Starting encrypted stream {} for ciphertext {}",
+        stream_id,
+        request.ciphertext_id
+    );
+
+    let events = tokio_stream::StreamExt::map(frames, |frame| {
+        Event::default().event(frame.kind()).json_data(&frame)
+    });
+    let mut response = Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response();
+    if let Ok(value) = stream_id.to_string().parse() {
+        response.headers_mut().insert("x-stream-id", value);
+    }
+    if let Some(Ok(value)) = approval.map(|a| a.id.to_string().parse()) {
+        response
+            .headers_mut()
+            .insert("x-approval-request-id", value);
+    }
+    Ok(response)
+}
+
+/// Pause or resume a response stream
+pub(super) async fn control_stream(
+    State(state): State<Arc<ProxyState>>,
+    Path(stream_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(frame): Json<ControlFrame>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    match state.streams.control(stream_id, tenant_id(&headers), frame) {
+        Ok(true) => Ok(Json(serde_json::json!({
+            "stream_id": stream_id,
+            "action": frame.as_str(),
+            "accepted": true
+        }))),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::warn!("Rejected control frame for stream {}: {}", stream_id, e);
+            Err(StatusCode::CONFLICT)
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Encrypted conversation context
+
+use super::{tenant_id, ProxyState};
+use crate::error::Error;
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use base64::prelude::*;
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// An encrypted turn to add to a session's conversation
+#[derive(Debug, Deserialize)]
+pub struct ConversationTurnRequest {
+    pub role: String,
+    /// Base64 encrypted turn
+    pub encrypted_data: String,
+}
+
+/// Turns `from..to` of a conversation; `to` defaults to the end
+#[derive(Debug, Deserialize)]
+pub struct ConversationTurnsQuery {
+    #[serde(default)]
+    pub from: u64,
+    pub to: Option<u64>,
+}
+
+/// Turn counts held in memory and read/write latency of each context tier
+pub(super) async fn get_conversation_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::to_value(state.conversations.stats()).unwrap())
+}
+
+/// Add an encrypted turn to the conversation of a live session
+pub(super) async fn append_conversation_turn(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(session_id): Path<Uuid>,
+    Json(request): Json<ConversationTurnRequest>,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    if !state.conversations.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let tenant = tenant_id(&headers).unwrap_or_default();
+    let owner = state
+        .session_manager
+        .get_tenant(session_id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if owner != tenant {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if BASE64_STANDARD.decode(&request.encrypted_data).is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let compress = state
+        .tenant_configs
+        .resolve(tenant)
+        .map(|config| config.compress_context)
+        .unwrap_or(state.config.conversations.compression.enabled);
+
+    let turn = state
+        .conversations
+        .append(
+            session_id,
+            tenant,
+            &request.role,
+            request.encrypted_data,
+            compress,
+        )
+        .map_err(|e| {
+            log::warn!("Conversation turn rejected for {}: {}", session_id, e);
+            match e {
+                Error::Validation(_) => StatusCode::PAYLOAD_TOO_LARGE,
+                Error::Security(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        })?;
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({
+            "session_id": session_id,
+            "turn": turn.turn,
+            "created_at": turn.created_at,
+        })),
+    ))
+}
+
+/// Turns of a conversation, fetched from whichever storage tier holds them
+pub(super) async fn get_conversation_turns(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(session_id): Path<Uuid>,
+    Query(query): Query<ConversationTurnsQuery>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    if !state.conversations.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    // The session may have ended; its context lives on until retention expires it
+    let tenant = tenant_id(&headers).unwrap_or_default();
+    let turns = state
+        .conversations
+        .turns(session_id, tenant, query.from, query.to)
+        .map_err(|e| {
+            log::warn!("Conversation read failed for {}: {}", session_id, e);
+            match e {
+                Error::Security(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        })?;
+    Ok(Json(serde_json::json!({
+        "session_id": session_id,
+        "turns": turns,
+    })))
+}
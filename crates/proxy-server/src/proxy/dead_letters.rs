@@ -0,0 +1,191 @@
+//! Dead-letter queue endpoints
+
+use super::batch::batch_job_view;
+use super::identity::admin_name;
+use super::{audit, ProxyState};
+use crate::dead_letter::{self};
+use crate::persistence::{BatchJobStatus, DeadLetterRecord};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// A dead letter without its payload
+fn dead_letter_view(letter: &DeadLetterRecord) -> serde_json::Value {
+    serde_json::json!({
+        "id": letter.id,
+        "source": letter.source,
+        "item_id": letter.item_id,
+        "tenant": letter.tenant,
+        "metadata": letter.metadata,
+        "payload_bytes": letter.payload.len(),
+        "attempts": letter.attempts,
+        "first_attempt_at": letter.first_attempt_at,
+        "failed_at": letter.failed_at,
+    })
+}
+
+fn load_dead_letter(
+    state: &ProxyState,
+    id: Uuid,
+) -> std::result::Result<DeadLetterRecord, StatusCode> {
+    state
+        .store
+        .get_dead_letter(id)
+        .map_err(|e| {
+            log::error!("Failed to load dead letter {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct DeadLetterQuery {
+    source: Option<String>,
+    tenant: Option<String>,
+}
+
+impl DeadLetterQuery {
+    fn matches(&self, letter: &DeadLetterRecord) -> bool {
+        self.source.as_ref().is_none_or(|s| *s == letter.source)
+            && self.tenant.as_ref().is_none_or(|t| *t == letter.tenant)
+    }
+}
+
+fn matching_dead_letters(
+    state: &ProxyState,
+    query: &DeadLetterQuery,
+) -> std::result::Result<Vec<DeadLetterRecord>, StatusCode> {
+    let letters = state.store.list_dead_letters().map_err(|e| {
+        log::error!("Failed to load dead letters: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(letters.into_iter().filter(|l| query.matches(l)).collect())
+}
+
+/// Dead-lettered work, oldest failure first, and how fast it is growing;
+/// admins only
+pub(super) async fn list_dead_letters(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<DeadLetterQuery>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    admin_name(&state, &headers)?;
+    let letters = matching_dead_letters(&state, &query)?;
+    let config = state.dead_letters.config();
+    Ok(Json(serde_json::json!({
+        "count": letters.len(),
+        "growth": state.dead_letters.growth(&letters, chrono::Utc::now().timestamp()),
+        "growth_window_seconds": config.growth_window_seconds,
+        "growth_alert_threshold": config.growth_alert_threshold,
+        "entries": letters.iter().map(dead_letter_view).collect::<Vec<_>>(),
+    })))
+}
+
+pub(super) async fn get_dead_letter(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    admin_name(&state, &headers)?;
+    Ok(Json(dead_letter_view(&load_dead_letter(&state, id)?)))
+}
+
+/// Queue a dead-lettered batch job to run again in the next batch window;
+/// admins only
+pub(super) async fn reprocess_dead_letter(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let letter = load_dead_letter(&state, id)?;
+    let job =
+        dead_letter::requeue_batch_job(&letter, chrono::Utc::now().timestamp()).map_err(|e| {
+            log::warn!("Cannot reprocess dead letter {}: {}", id, e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?;
+    let existing = state.store.get_batch_job(job.id).map_err(|e| {
+        log::error!("Failed to load batch job {}: {}", job.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if existing.is_some_and(|existing| existing.status != BatchJobStatus::Failed) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    state.store.put_batch_job(&job).map_err(|e| {
+        log::error!("Failed to requeue batch job {}: {}", job.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    state.store.delete_dead_letter(id).map_err(|e| {
+        log::error!("Failed to remove dead letter {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    audit(
+        &state,
+        "dlq.reprocess",
+        &id.to_string(),
+        serde_json::json!({
+            "admin": admin,
+            "source": letter.source,
+            "item_id": letter.item_id
+        }),
+    );
+    Ok(Json(batch_job_view(&job)))
+}
+
+pub(super) async fn delete_dead_letter(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> std::result::Result<StatusCode, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let letter = load_dead_letter(&state, id)?;
+    state.store.delete_dead_letter(id).map_err(|e| {
+        log::error!("Failed to remove dead letter {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    audit(
+        &state,
+        "dlq.purge",
+        &id.to_string(),
+        serde_json::json!({
+            "admin": admin,
+            "source": letter.source,
+            "item_id": letter.item_id
+        }),
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Drop every dead letter, or those of one source or tenant; admins only
+pub(super) async fn purge_dead_letters(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<DeadLetterQuery>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let letters = matching_dead_letters(&state, &query)?;
+    for letter in &letters {
+        state.store.delete_dead_letter(letter.id).map_err(|e| {
+            log::error!("Failed to remove dead letter {}: {}", letter.id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+    audit(
+        &state,
+        "dlq.purge",
+        "dlq",
+        serde_json::json!({
+            "admin": admin,
+            "source": query.source,
+            "tenant": query.tenant,
+            "purged": letters.len(),
+        }),
+    );
+    Ok(Json(serde_json::json!({ "purged": letters.len() })))
+}
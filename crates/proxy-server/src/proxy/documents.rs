@@ -0,0 +1,499 @@
+//! Encrypted document ingestion, processed chunk by chunk in the background
+
+use super::approvals::{enforce_approval, held_copies};
+use super::completions::enforce_sandbox_scope;
+use super::{audit, model_policy, tenant_id, ProxyState};
+use crate::config::DocumentIngestionConfig;
+use crate::error::Result;
+use crate::fhe::Ciphertext;
+use crate::sandbox::SandboxGrant;
+use crate::scaling::{WorkerAutoscaler, WorkerResizeEvent};
+use axum::{
+    extract::{Extension, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, Semaphore};
+use uuid::Uuid;
+
+/// Request to ingest an encrypted document
+#[derive(Debug, Deserialize)]
+pub struct DocumentRequest {
+    pub encrypted_data: String, // Base64 encoded
+    pub provider: String,
+    pub model: String,
+    /// What the provider does with each chunk; defaults to "summarize"
+    pub task: Option<String>,
+    /// Fingerprint of the parameters the client negotiated via `/v1/params/negotiate`
+    pub params_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentJobStatus {
+    Queued,
+    Processing,
+    Completed,
+    Failed,
+}
+
+/// Result of one document chunk, decryptable on its own via `/v1/decrypt`
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentChunkResult {
+    pub index: usize,
+    pub ciphertext_id: Uuid,
+    pub noise_budget: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentJob {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub tenant: Option<String>,
+    pub provider: String,
+    pub model: String,
+    pub task: String,
+    pub status: DocumentJobStatus,
+    pub document_bytes: usize,
+    pub total_chunks: usize,
+    pub chunks: Vec<DocumentChunkResult>,
+    /// All chunk results joined in order
+    pub aggregate_ciphertext_id: Option<Uuid>,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(skip)]
+    finished_at: Option<Instant>,
+}
+
+/// Document ingestion jobs, with a cap on how many are processed at once.
+/// Each permit is a worker; the autoscaler adds and retires permits.
+#[derive(Debug)]
+pub struct DocumentJobs {
+    jobs: RwLock<HashMap<Uuid, DocumentJob>>,
+    permits: Arc<Semaphore>,
+    autoscaler: WorkerAutoscaler,
+    retention: Duration,
+}
+
+impl DocumentJobs {
+    pub fn new(config: &DocumentIngestionConfig) -> Self {
+        let autoscaler = WorkerAutoscaler::new(
+            config.autoscaling.clone(),
+            config.max_concurrent_jobs.max(1),
+        );
+        Self {
+            jobs: RwLock::new(HashMap::new()),
+            permits: Arc::new(Semaphore::new(autoscaler.workers())),
+            autoscaler,
+            retention: Duration::from_secs(config.job_retention_seconds),
+        }
+    }
+
+    pub fn autoscaler(&self) -> &WorkerAutoscaler {
+        &self.autoscaler
+    }
+
+    /// Add or retire a worker as the autoscaler decides. A retired worker
+    /// that is busy finishes its job first.
+    pub async fn autoscale(&self) -> Option<WorkerResizeEvent> {
+        let idle = !self.jobs.read().await.values().any(|job| {
+            matches!(
+                job.status,
+                DocumentJobStatus::Queued | DocumentJobStatus::Processing
+            )
+        });
+        let event = self.autoscaler.evaluate(idle, Instant::now())?;
+        if event.to > event.from {
+            self.permits.add_permits(event.to - event.from);
+        } else {
+            for _ in event.to..event.from {
+                match self.permits.clone().try_acquire_owned() {
+                    Ok(permit) => permit.forget(),
+                    Err(_) => {
+                        let permits = self.permits.clone();
+                        tokio::spawn(async move {
+                            if let Ok(permit) = permits.acquire_owned().await {
+                                permit.forget();
+                            }
+                        });
+                    }
+                }
+            }
+        }
+        Some(event)
+    }
+
+    /// Track a new job, dropping finished jobs past retention
+    pub async fn insert(&self, job: DocumentJob) {
+        let mut jobs = self.jobs.write().await;
+        jobs.retain(|_, job| {
+            job.finished_at
+                .is_none_or(|finished| finished.elapsed() < self.retention)
+        });
+        jobs.insert(job.id, job);
+    }
+
+    pub async fn get(&self, id: Uuid) -> Option<DocumentJob> {
+        self.jobs.read().await.get(&id).cloned()
+    }
+
+    async fn update(&self, id: Uuid, update: impl FnOnce(&mut DocumentJob)) {
+        if let Some(job) = self.jobs.write().await.get_mut(&id) {
+            update(job);
+            job.updated_at = chrono::Utc::now().timestamp();
+            if matches!(
+                job.status,
+                DocumentJobStatus::Completed | DocumentJobStatus::Failed
+            ) {
+                job.finished_at = Some(Instant::now());
+            }
+        }
+    }
+
+    /// Drop every job of `tenant`, returning them
+    pub async fn remove_tenant(&self, tenant: &str) -> Vec<DocumentJob> {
+        let mut jobs = self.jobs.write().await;
+        let owned: Vec<Uuid> = jobs
+            .values()
+            .filter(|job| job.tenant.as_deref() == Some(tenant))
+            .map(|job| job.id)
+            .collect();
+        owned.iter().filter_map(|id| jobs.remove(id)).collect()
+    }
+
+    /// Job counts by status
+    pub async fn stats(&self) -> HashMap<DocumentJobStatus, usize> {
+        let mut counts = HashMap::new();
+        for job in self.jobs.read().await.values() {
+            *counts.entry(job.status).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// Split a document into chunks, process each one and join the results
+async fn run_document_job(state: Arc<ProxyState>, job_id: Uuid, document: Ciphertext) {
+    let queued_at = Instant::now();
+    let _permit = match state.documents.permits.clone().acquire_owned().await {
+        Ok(permit) => permit,
+        Err(_) => return,
+    };
+    state
+        .documents
+        .autoscaler
+        .record_queue_wait(queued_at.elapsed());
+    state
+        .documents
+        .update(job_id, |job| job.status = DocumentJobStatus::Processing)
+        .await;
+
+    let result = process_document_chunks(&state, job_id, &document).await;
+    let (outcome, details) = match result {
+        Ok(aggregate_id) => {
+            state
+                .documents
+                .update(job_id, |job| {
+                    job.status = DocumentJobStatus::Completed;
+                    job.aggregate_ciphertext_id = Some(aggregate_id);
+                })
+                .await;
+            (
+                "completed",
+                serde_json::json!({ "aggregate_ciphertext_id": aggregate_id }),
+            )
+        }
+        Err(e) => {
+            log::error!("Document job {} failed: {}", job_id, e);
+            state.metrics.increment_errors();
+            let error = e.to_string();
+            state
+                .documents
+                .update(job_id, |job| {
+                    job.status = DocumentJobStatus::Failed;
+                    job.error = Some(error.clone());
+                })
+                .await;
+            ("failed", serde_json::json!({ "error": error }))
+        }
+    };
+    audit(
+        &state,
+        &format!("document.{}", outcome),
+        &job_id.to_string(),
+        details,
+    );
+}
+
+async fn process_document_chunks(
+    state: &ProxyState,
+    job_id: Uuid,
+    document: &Ciphertext,
+) -> Result<Uuid> {
+    let chunk_size = state.config.documents.chunk_size_bytes;
+    let chunks = state
+        .fhe_engine
+        .read()
+        .await
+        .split_into_chunks(document, chunk_size)?;
+    let total_chunks = chunks.len();
+    state
+        .documents
+        .update(job_id, |job| job.total_chunks = total_chunks)
+        .await;
+
+    // Results keep the restrictions of the key the document was encrypted with
+    let owners = state.key_policies.owners_of(&[document.id]).await;
+    let mut processed = Vec::with_capacity(total_chunks);
+    for (index, chunk) in chunks.iter().enumerate() {
+        // Take the engine lock per chunk so long documents don't starve completions
+        let result = {
+            let fhe_engine = state.fhe_engine.read().await;
+            let output = fhe_engine.process_encrypted_prompt(chunk)?;
+            let result = fhe_engine.merge_chunks(document.id, std::slice::from_ref(&output))?;
+            processed.push(output);
+            result
+        };
+
+        let chunk_result = DocumentChunkResult {
+            index,
+            ciphertext_id: result.id,
+            noise_budget: result.noise_budget,
+        };
+        state.key_policies.record_derived(&result, &owners).await;
+        state
+            .ciphertext_cache
+            .write()
+            .await
+            .insert(result.id, result);
+        state
+            .documents
+            .update(job_id, |job| job.chunks.push(chunk_result))
+            .await;
+        tokio::task::yield_now().await;
+    }
+
+    let aggregate = state
+        .fhe_engine
+        .read()
+        .await
+        .merge_chunks(document.id, &processed)?;
+    let aggregate_id = aggregate.id;
+    state.key_policies.record_derived(&aggregate, &owners).await;
+    state
+        .ciphertext_cache
+        .write()
+        .await
+        .insert(aggregate_id, aggregate);
+    Ok(aggregate_id)
+}
+
+/// Accept an encrypted document and start chunked processing as a job
+pub(super) async fn submit_document(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    sandbox: Option<Extension<SandboxGrant>>,
+    Json(request): Json<DocumentRequest>,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    let config = &state.config.documents;
+    if !config.enabled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let tenant = tenant_id(&headers);
+    let tenant_config = model_policy(&state, tenant)?;
+    let (model, _) = tenant_config.govern_model(&request.model).map_err(|e| {
+        log::warn!("Model {} rejected for document: {}", request.model, e);
+        StatusCode::FORBIDDEN
+    })?;
+    enforce_sandbox_scope(sandbox.as_deref(), &model, &request.provider)?;
+
+    let data = BASE64_STANDARD
+        .decode(&request.encrypted_data)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if data.len() > config.max_document_bytes {
+        log::warn!(
+            "Rejected {} byte document (limit {})",
+            data.len(),
+            config.max_document_bytes
+        );
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    enforce_approval(&state, &headers, &held_copies(&state, &data).await)?;
+
+    let params = state.fhe_engine.read().await.get_params().clone();
+    if let Some(params_hash) = &request.params_hash {
+        if *params_hash != params.fingerprint() {
+            log::warn!(
+                "Document was encrypted under parameters {} the server does not use",
+                params_hash
+            );
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+
+    let owners = state.key_policies.owners_of_data(&data).await;
+    let document = Ciphertext {
+        id: Uuid::new_v4(),
+        data,
+        params,
+        noise_budget: None,
+    };
+    state.key_policies.record_derived(&document, &owners).await;
+    let now = chrono::Utc::now().timestamp();
+    let job = DocumentJob {
+        id: Uuid::new_v4(),
+        tenant: tenant.map(str::to_string),
+        provider: request.provider,
+        model,
+        task: request.task.unwrap_or_else(|| "summarize".to_string()),
+        status: DocumentJobStatus::Queued,
+        document_bytes: document.data.len(),
+        total_chunks: 0,
+        chunks: Vec::new(),
+        aggregate_ciphertext_id: None,
+        error: None,
+        created_at: now,
+        updated_at: now,
+        finished_at: None,
+    };
+    let job_id = job.id;
+    audit(
+        &state,
+        "document.submit",
+        &job_id.to_string(),
+        serde_json::json!({
+            "tenant": tenant,
+            "model": job.model,
+            "task": job.task,
+            "document_bytes": job.document_bytes,
+        }),
+    );
+    let response = serde_json::json!({
+        "job_id": job_id,
+        "status": job.status,
+        "status_url": format!("/v1/documents/{}", job_id),
+    });
+    state.documents.insert(job).await;
+
+    tokio::spawn(run_document_job(state.clone(), job_id, document));
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+/// Status and results of a document job; only visible to the submitting tenant
+pub(super) async fn get_document_job(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(job_id): Path<Uuid>,
+) -> std::result::Result<Json<DocumentJob>, StatusCode> {
+    let job = state
+        .documents
+        .get(job_id)
+        .await
+        .filter(|job| job.tenant.as_deref() == tenant_id(&headers))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(job))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::proxy::ProxyServer;
+
+    #[tokio::test]
+    async fn test_document_workers_autoscale() {
+        let mut config = Config::default();
+        config.documents.max_concurrent_jobs = 1;
+        config.documents.autoscaling.max_workers = 2;
+        config.documents.autoscaling.target_queue_wait_p95_ms = 10;
+        config.documents.autoscaling.idle_period_seconds = 0;
+        config.documents.autoscaling.cooldown_seconds = 0;
+        let documents = DocumentJobs::new(&config.documents);
+        assert_eq!(documents.permits.available_permits(), 1);
+
+        for _ in 0..10 {
+            documents
+                .autoscaler()
+                .record_queue_wait(Duration::from_millis(50));
+        }
+        let event = documents.autoscale().await.unwrap();
+        assert_eq!((event.from, event.to), (1, 2));
+        assert_eq!(documents.permits.available_permits(), 2);
+
+        // No waits and no queued jobs: the extra worker is retired
+        let event = documents.autoscale().await.unwrap();
+        assert_eq!((event.from, event.to), (2, 1));
+        assert_eq!(documents.permits.available_permits(), 1);
+        let stats = documents.autoscaler().stats();
+        assert_eq!((stats.grow_events, stats.shrink_events), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_document_ingestion_job() {
+        let mut config = Config::default();
+        config.documents.chunk_size_bytes = 8;
+        let state = ProxyServer::new(config).unwrap().state;
+
+        let text = "Encrypted documents are processed chunk by chunk.";
+        let (client_id, ciphertext) = {
+            let mut engine = state.fhe_engine.write().await;
+            let (client_id, _) = engine.generate_keys().unwrap();
+            (client_id, engine.encrypt_text(client_id, text).unwrap())
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        let (status, Json(submitted)) = submit_document(
+            State(state.clone()),
+            headers.clone(),
+            None,
+            Json(DocumentRequest {
+                encrypted_data: BASE64_STANDARD.encode(&ciphertext.data),
+                provider: "openai".to_string(),
+                model: "gpt-4".to_string(),
+                task: None,
+                params_hash: Some(ciphertext.params.fingerprint()),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job_id: Uuid = serde_json::from_value(submitted["job_id"].clone()).unwrap();
+
+        let job = loop {
+            let job = state.documents.get(job_id).await.unwrap();
+            if job.status == DocumentJobStatus::Completed {
+                break job;
+            }
+            assert_ne!(job.status, DocumentJobStatus::Failed, "{:?}", job.error);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(job.total_chunks, text.len().div_ceil(8));
+        assert_eq!(job.chunks.len(), job.total_chunks);
+
+        let cache = state.ciphertext_cache.read().await;
+        let engine = state.fhe_engine.read().await;
+        let aggregate = &cache[&job.aggregate_ciphertext_id.unwrap()];
+        assert_eq!(engine.decrypt_text(client_id, aggregate).unwrap(), text);
+        let first = &cache[&job.chunks[0].ciphertext_id];
+        assert_eq!(engine.decrypt_text(client_id, first).unwrap(), &text[..8]);
+
+        // Other tenants cannot see the job
+        assert!(
+            get_document_job(State(state.clone()), HeaderMap::new(), Path(job_id))
+                .await
+                .is_err()
+        );
+        assert!(
+            get_document_job(State(state.clone()), headers, Path(job_id))
+                .await
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Regional failover drills
+
+use super::identity::admin_name;
+use super::{audit, ProxyState};
+use crate::drills::DrillReport;
+use crate::error::Error;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use reqwest::Client as HttpClient;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Recent failover drill reports, newest first
+pub(super) async fn get_failover_drills(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.drills.is_enabled(),
+        "rerouting": state.drills.is_rerouting(),
+        "reports": state.drills.reports()
+    }))
+}
+
+pub(super) async fn get_failover_drill(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+) -> std::result::Result<Json<DrillReport>, StatusCode> {
+    state
+        .drills
+        .report(id)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Start a regional failover drill; admins only. Progress is published on
+/// its report.
+pub(super) async fn start_failover_drill(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<(StatusCode, Json<DrillReport>), StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let replication = &state.config.persistence.replication;
+    let report = state
+        .drills
+        .begin(&replication.region, &replication.peers)
+        .map_err(|e| {
+            log::warn!("Failover drill refused: {}", e);
+            match e {
+                Error::Concurrency(_) => StatusCode::CONFLICT,
+                _ => StatusCode::NOT_FOUND,
+            }
+        })?;
+    audit(
+        &state,
+        "dr_drill.started",
+        &report.id.to_string(),
+        serde_json::json!({
+            "admin": admin,
+            "region": report.region,
+            "standbys": report.standbys
+        }),
+    );
+    log::warn!(
+        "Starting failover drill {}: region {} hands traffic to {:?}",
+        report.id,
+        report.region,
+        report.standbys
+    );
+
+    let drill = report.clone();
+    let drill_state = state.clone();
+    tokio::spawn(async move { run_failover_drill(&drill_state, drill).await });
+    Ok((StatusCode::ACCEPTED, Json(report)))
+}
+
+/// Move traffic off this region, wait for the standbys to serve it, then
+/// restore routing and judge the timings against the recovery plan
+async fn run_failover_drill(state: &ProxyState, mut report: DrillReport) {
+    let config = state.drills.config().clone();
+    let client = HttpClient::builder()
+        .timeout(Duration::from_secs(config.health_timeout_seconds))
+        .build()
+        .unwrap_or_default();
+
+    let reroute = state.drills.reroute();
+    let rerouted = Instant::now();
+    report.step(
+        "reroute",
+        rerouted,
+        true,
+        format!("Region {} refuses new work with 503", report.region),
+    );
+
+    let started = Instant::now();
+    let last_round_at = state.reconciler.stats().last_round_at;
+    report.rpo_seconds =
+        last_round_at.map(|at| (chrono::Utc::now().timestamp() - at).max(0) as u64);
+    report.step(
+        "replication_lag",
+        started,
+        last_round_at.is_some(),
+        match report.rpo_seconds {
+            Some(age) => format!("Last session replication round {}s ago", age),
+            None => "Session replication has not completed a round".to_string(),
+        },
+    );
+    state.drills.update(&report);
+
+    let started = Instant::now();
+    let max_drill = Duration::from_secs(config.max_drill_seconds);
+    let mut interval = tokio::time::interval(Duration::from_millis(config.health_poll_interval_ms));
+    let unhealthy = loop {
+        interval.tick().await;
+        let unhealthy = unhealthy_standbys(&client, &report.standbys).await;
+        if unhealthy.is_empty() || rerouted.elapsed() >= max_drill {
+            break unhealthy;
+        }
+    };
+    let serving = !report.standbys.is_empty() && unhealthy.is_empty();
+    if serving {
+        report.rto_ms = Some(rerouted.elapsed().as_millis() as u64);
+    }
+    report.step(
+        "verify_standbys",
+        started,
+        serving,
+        if report.standbys.is_empty() {
+            "No standby regions are configured".to_string()
+        } else if serving {
+            format!("{} standby regions serving", report.standbys.len())
+        } else {
+            format!(
+                "Still unhealthy after {}s: {}",
+                config.max_drill_seconds,
+                unhealthy.join(", ")
+            )
+        },
+    );
+    state.drills.update(&report);
+
+    let started = Instant::now();
+    drop(reroute);
+    let restored = !state.drills.is_rerouting() && !state.resource_guard.is_draining();
+    report.step(
+        "restore",
+        started,
+        restored,
+        if restored {
+            format!("Region {} admits new work again", report.region)
+        } else {
+            "The resource guard is still draining this region".to_string()
+        },
+    );
+
+    let report = state.drills.finish(report);
+    log::warn!(
+        "Failover drill {} finished {:?}: RTO {:?}ms, RPO {:?}s",
+        report.id,
+        report.status,
+        report.rto_ms,
+        report.rpo_seconds
+    );
+    audit(
+        state,
+        "dr_drill.completed",
+        &report.id.to_string(),
+        serde_json::json!({
+            "status": report.status,
+            "rto_ms": report.rto_ms,
+            "rpo_seconds": report.rpo_seconds,
+            "checks": report.checks,
+        }),
+    );
+}
+
+/// Standbys whose `/health` does not answer with success
+async fn unhealthy_standbys(client: &HttpClient, standbys: &[String]) -> Vec<String> {
+    let mut unhealthy = Vec::new();
+    for standby in standbys {
+        let url = format!("{}/health", standby.trim_end_matches('/'));
+        match client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => {}
+            _ => unhealthy.push(standby.clone()),
+        }
+    }
+    unhealthy
+}
@@ -0,0 +1,213 @@
+//! Split-custodian key escrow and recovery
+
+use super::{audit, tenant_id, ProxyState};
+use crate::config::EffectiveTenantConfig;
+use crate::error::{Error, Result};
+use crate::escrow::ShareOutcome;
+use crate::fhe::FheEngine;
+use crate::persistence::EscrowRecord;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use base64::prelude::*;
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A custodian's opened key share
+#[derive(Debug, Deserialize)]
+pub struct EscrowShareSubmission {
+    pub custodian: String,
+    /// Base64 share, as opened with the custodian's private key
+    pub share: String,
+}
+
+/// Split a new client key across the tenant's custodians and store the shares
+pub(super) fn escrow_client_key(
+    state: &ProxyState,
+    fhe_engine: &FheEngine,
+    tenant_config: &EffectiveTenantConfig,
+    client_id: Uuid,
+) -> Result<()> {
+    let key_data = fhe_engine.export_client_key(client_id)?;
+    let record = state.escrow.deposit(
+        &tenant_config.tenant_id,
+        client_id,
+        &key_data,
+        &tenant_config.escrow_custodians,
+    )?;
+    state.store.put_escrow(&record)?;
+    let custodians: Vec<&str> = record.shares.iter().map(|s| s.custodian.as_str()).collect();
+    notify_escrow(
+        state,
+        tenant_config,
+        "escrow.deposit",
+        client_id,
+        serde_json::json!({ "custodians": custodians }),
+    );
+    Ok(())
+}
+
+/// Audit an escrow operation and report it to the tenant's escrow webhook
+fn notify_escrow(
+    state: &ProxyState,
+    tenant_config: &EffectiveTenantConfig,
+    action: &str,
+    client_id: Uuid,
+    details: serde_json::Value,
+) {
+    let mut details = details;
+    details["tenant"] = serde_json::json!(tenant_config.tenant_id);
+    audit(state, action, &client_id.to_string(), details.clone());
+
+    let Some(url) = tenant_config.escrow_webhook_url.clone() else {
+        return;
+    };
+    let payload = serde_json::json!({
+        "event": action,
+        "client_id": client_id,
+        "details": details,
+        "occurred_at": chrono::Utc::now(),
+    });
+    tokio::spawn(async move {
+        let result = HttpClient::new()
+            .post(&url)
+            .timeout(Duration::from_secs(10))
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            log::warn!("Escrow webhook to {} failed: {}", url, e);
+        }
+    });
+}
+
+/// Load an escrow record the calling tenant owns
+fn tenant_escrow(
+    state: &ProxyState,
+    headers: &HeaderMap,
+    client_id: Uuid,
+) -> std::result::Result<(EscrowRecord, Arc<EffectiveTenantConfig>), StatusCode> {
+    if !state.escrow.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let tenant = tenant_id(headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let record = state
+        .store
+        .get_escrow(client_id)
+        .map_err(|e| {
+            log::error!("Failed to load escrow record for {}: {}", client_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .filter(|record| record.tenant == tenant)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let tenant_config = state.tenant_configs.resolve(tenant).map_err(|e| {
+        log::warn!("Failed to resolve tenant config for {}: {}", tenant, e);
+        StatusCode::BAD_REQUEST
+    })?;
+    Ok((record, tenant_config))
+}
+
+/// Start recovering an escrowed key; returns the sealed share for each custodian
+pub(super) async fn open_escrow_recovery(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(client_id): Path<Uuid>,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    let (record, tenant_config) = tenant_escrow(&state, &headers, client_id)?;
+    let recovery = state.escrow.open_recovery(&record);
+    notify_escrow(
+        &state,
+        &tenant_config,
+        "escrow.recovery.open",
+        client_id,
+        serde_json::json!({ "recovery_id": recovery.recovery_id }),
+    );
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({
+            "recovery": recovery,
+            "shares": record.shares,
+        })),
+    ))
+}
+
+/// Progress of an open recovery
+pub(super) async fn get_escrow_recovery(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let (client_id, _) = state
+        .escrow
+        .recovery_client(id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let (record, _) = tenant_escrow(&state, &headers, client_id)?;
+    let recovery = state
+        .escrow
+        .recovery(&record, id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::to_value(&recovery).unwrap()))
+}
+
+/// Accept one custodian's share; the last one restores the key into the engine
+pub(super) async fn submit_escrow_share(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(submission): Json<EscrowShareSubmission>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let (client_id, _) = state
+        .escrow
+        .recovery_client(id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let (record, tenant_config) = tenant_escrow(&state, &headers, client_id)?;
+    let share = BASE64_STANDARD
+        .decode(&submission.share)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let outcome = state
+        .escrow
+        .submit_share(&record, id, &submission.custodian, share)
+        .map_err(|e| {
+            log::warn!("Escrow share for recovery {} rejected: {}", id, e);
+            match e {
+                Error::Security(_) => StatusCode::FORBIDDEN,
+                _ => StatusCode::NOT_FOUND,
+            }
+        })?;
+    notify_escrow(
+        &state,
+        &tenant_config,
+        "escrow.recovery.share",
+        client_id,
+        serde_json::json!({ "recovery_id": id, "custodian": submission.custodian }),
+    );
+
+    let (recovery, complete) = match outcome {
+        ShareOutcome::Pending(recovery) => (recovery, false),
+        ShareOutcome::Complete(recovery, key_data) => {
+            state
+                .fhe_engine
+                .write()
+                .await
+                .restore_client_key(client_id, key_data);
+            notify_escrow(
+                &state,
+                &tenant_config,
+                "escrow.recovery.complete",
+                client_id,
+                serde_json::json!({ "recovery_id": id, "custodians": recovery.submitted }),
+            );
+            (recovery, true)
+        }
+    };
+    Ok(Json(serde_json::json!({
+        "recovery": recovery,
+        "complete": complete,
+    })))
+}
@@ -0,0 +1,115 @@
+//! Encrypted A/B experiments and their ratings
+
+use super::identity::admin_name;
+use super::{audit, ProxyState};
+use crate::config::ExperimentConfig;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// A client's rating of a response served under an experiment
+#[derive(Debug, Deserialize)]
+pub struct ExperimentRatingRequest {
+    pub variant: String,
+    pub rating: u8,
+}
+
+/// Per-variant results of every registered experiment
+pub(super) async fn get_experiment_results(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.experiments.is_enabled(),
+        "experiments": state.experiments.all_results()
+    }))
+}
+
+/// Per-variant results of one experiment
+pub(super) async fn get_experiment(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let results = state
+        .experiments
+        .results(&id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::to_value(results).unwrap()))
+}
+
+/// Register an experiment, replacing one with the same ID and its results;
+/// admins only
+pub(super) async fn register_experiment(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(experiment): Json<ExperimentConfig>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if !state.experiments.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let id = experiment.id.clone();
+    let variants: Vec<_> = experiment
+        .variants
+        .iter()
+        .map(|v| serde_json::json!({ "name": v.name, "weight": v.weight }))
+        .collect();
+    state.experiments.register(experiment).map_err(|e| {
+        log::warn!("Rejected experiment {}: {}", id, e);
+        StatusCode::BAD_REQUEST
+    })?;
+    audit(
+        &state,
+        "experiment.register",
+        &id,
+        serde_json::json!({ "admin": admin, "variants": variants }),
+    );
+
+    let results = state
+        .experiments
+        .results(&id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::to_value(results).unwrap()))
+}
+
+/// Stop an experiment; its requests go back to the template they name.
+/// Admins only.
+pub(super) async fn remove_experiment(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> std::result::Result<StatusCode, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if !state.experiments.remove(&id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    audit(
+        &state,
+        "experiment.remove",
+        &id,
+        serde_json::json!({ "admin": admin }),
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Client-reported rating of a response, as named by its `x-experiment-variant` header
+pub(super) async fn rate_experiment_variant(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<String>,
+    Json(request): Json<ExperimentRatingRequest>,
+) -> StatusCode {
+    match state
+        .experiments
+        .record_rating(&id, &request.variant, request.rating)
+    {
+        Ok(true) => StatusCode::ACCEPTED,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            log::debug!("Rejected experiment rating: {}", e);
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
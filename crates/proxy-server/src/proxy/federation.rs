@@ -0,0 +1,211 @@
+//! Relay of encrypted requests between peer proxies
+
+use super::ProxyState;
+use crate::federation::{FederationEnvelope, PEER_HEADER};
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Request to relay a cached ciphertext to a federation peer
+#[derive(Debug, Deserialize)]
+pub struct FederatedForwardRequest {
+    pub ciphertext_id: Uuid,
+    pub peer: String,
+    /// Proxy that should process the request; defaults to `peer`
+    pub destination: Option<String>,
+    pub model: String,
+}
+
+/// Accept an envelope from a federation peer, process or relay it, and seal the reply
+pub(super) async fn relay_federated_request(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(envelope): Json<FederationEnvelope>,
+) -> std::result::Result<Json<FederationEnvelope>, StatusCode> {
+    let federation = &state.federation;
+    if !federation.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let claimed_peer = headers
+        .get(PEER_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let peer = federation.peer(claimed_peer).ok_or_else(|| {
+        state.siem.emit(
+            SecurityEvent::new(
+                SecurityEventKind::AuthFailure,
+                "Relay request from unknown federation peer",
+            )
+            .subject(claimed_peer),
+        );
+        StatusCode::UNAUTHORIZED
+    })?;
+
+    let allowed = state
+        .rate_limiter
+        .check_rate_limit_with(&format!("peer:{}", peer.id), peer.requests_per_minute)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !allowed {
+        log::warn!("Federation peer {} exceeded its quota", peer.id);
+        federation.record_quota_exceeded(&peer.id);
+        state.siem.emit(
+            SecurityEvent::new(
+                SecurityEventKind::RateLimited,
+                "Federation peer quota exceeded",
+            )
+            .subject(&peer.id),
+        );
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    let ciphertext = federation.open(&peer.id, &envelope).map_err(|e| {
+        log::warn!("Rejected envelope from peer {}: {}", peer.id, e);
+        state.siem.emit(
+            SecurityEvent::new(SecurityEventKind::EnvelopeRejected, &e.to_string())
+                .subject(&peer.id),
+        );
+        StatusCode::FORBIDDEN
+    })?;
+    let header = &envelope.header;
+
+    let processed = if header.destination == federation.node_id() {
+        let fhe_engine = state.fhe_engine.read().await;
+        fhe_engine
+            .process_encrypted_prompt(&ciphertext)
+            .map_err(|e| {
+                log::error!("Federated FHE processing failed: {}", e);
+                state.metrics.increment_errors();
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+    } else {
+        // Relay onward; the destination must be one of our own peers
+        let next = federation
+            .seal(
+                &header.destination,
+                &header.destination,
+                &header.model,
+                &ciphertext,
+                Some(header),
+            )
+            .map_err(|e| {
+                log::warn!("Cannot relay envelope to {}: {}", header.destination, e);
+                StatusCode::BAD_GATEWAY
+            })?;
+        let reply = federation
+            .send(&header.destination, &next)
+            .await
+            .map_err(|e| {
+                log::error!("Relay to {} failed: {}", header.destination, e);
+                StatusCode::BAD_GATEWAY
+            })?;
+        federation.open(&header.destination, &reply).map_err(|e| {
+            log::error!("Invalid reply from {}: {}", header.destination, e);
+            StatusCode::BAD_GATEWAY
+        })?
+    };
+
+    // Replies start a fresh hop list so the origin does not see itself as a loop
+    let reply = federation
+        .seal(&peer.id, &header.origin, &header.model, &processed, None)
+        .map_err(|e| {
+            log::error!("Failed to seal federation reply: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(reply))
+}
+
+/// Relay a cached ciphertext to a federation peer for processing
+pub(super) async fn forward_to_peer(
+    State(state): State<Arc<ProxyState>>,
+    Json(request): Json<FederatedForwardRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let federation = &state.federation;
+    if !federation.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let ciphertext = state
+        .ciphertext_cache
+        .read()
+        .await
+        .get(&request.ciphertext_id)
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let destination = request.destination.as_deref().unwrap_or(&request.peer);
+    let envelope = federation
+        .seal(
+            &request.peer,
+            destination,
+            &request.model,
+            &ciphertext,
+            None,
+        )
+        .map_err(|e| {
+            log::warn!("Cannot seal envelope for peer {}: {}", request.peer, e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    let reply = federation
+        .send(&request.peer, &envelope)
+        .await
+        .map_err(|e| {
+            log::error!("Federation relay to {} failed: {}", request.peer, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+    let processed = federation.open(&request.peer, &reply).map_err(|e| {
+        log::error!("Invalid federation reply from {}: {}", request.peer, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    let processed_id = processed.id;
+    state
+        .ciphertext_cache
+        .write()
+        .await
+        .insert(processed_id, processed);
+
+    Ok(Json(serde_json::json!({
+        "processed_ciphertext_id": processed_id,
+        "envelope_id": envelope.header.envelope_id,
+        "peer": request.peer,
+        "destination": destination
+    })))
+}
+
+/// List federation peers with their relay metrics
+pub(super) async fn get_federation_peers(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    let federation = &state.federation;
+    let metrics = federation.peer_metrics();
+    let peers: Vec<_> = state
+        .config
+        .federation
+        .peers
+        .iter()
+        .map(|peer| {
+            serde_json::json!({
+                "id": peer.id,
+                "endpoint": peer.endpoint,
+                "requests_per_minute": peer.requests_per_minute,
+                "metrics": metrics.get(&peer.id).cloned().unwrap_or_default()
+            })
+        })
+        .collect();
+
+    Json(serde_json::json!({
+        "enabled": federation.is_enabled(),
+        "node_id": federation.node_id(),
+        "peers": peers
+    }))
+}
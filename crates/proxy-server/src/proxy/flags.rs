@@ -0,0 +1,105 @@
+//! Feature flag endpoints
+
+use super::identity::admin_name;
+use super::{audit, tenant_id, ProxyState};
+use crate::config::FeatureFlagConfig;
+use crate::experiments::USER_HASH_HEADER;
+use crate::feature_flags::FlagSource;
+use crate::persistence::FeatureFlagRecord;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use std::sync::Arc;
+
+/// Flags as they evaluate for the caller, so SDKs can follow a rollout
+pub(super) async fn get_feature_flags(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> Json<serde_json::Value> {
+    let tenant = tenant_id(&headers);
+    let user_hash = headers.get(USER_HASH_HEADER).and_then(|v| v.to_str().ok());
+    Json(serde_json::json!({
+        "tenant": tenant,
+        "flags": state.feature_flags.snapshot_for(tenant, user_hash),
+    }))
+}
+
+/// Every flag with its source and evaluation counts
+pub(super) async fn get_feature_flag_status(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "flags": state.feature_flags.status() }))
+}
+
+/// Set a flag for every instance; it overrides the config definition until
+/// unset. Admins only
+pub(super) async fn set_feature_flag(
+    State(state): State<Arc<ProxyState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(mut flag): Json<FeatureFlagConfig>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    flag.name = name;
+    flag.validate().map_err(|e| {
+        log::warn!("Rejected feature flag {}: {}", flag.name, e);
+        StatusCode::BAD_REQUEST
+    })?;
+    let record = FeatureFlagRecord {
+        flag,
+        updated_at: chrono::Utc::now().timestamp(),
+    };
+    state.store.put_feature_flag(&record).map_err(|e| {
+        log::error!("Failed to store feature flag {}: {}", record.flag.name, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    state.feature_flags.set(&record);
+    audit(
+        &state,
+        "feature_flag.set",
+        &record.flag.name,
+        serde_json::json!({
+            "admin": admin,
+            "enabled": record.flag.enabled,
+            "rollout_percent": record.flag.rollout_percent,
+            "tenants": record.flag.tenants,
+            "excluded_tenants": record.flag.excluded_tenants,
+        }),
+    );
+    Ok(Json(serde_json::to_value(&record).unwrap()))
+}
+
+/// Drop a stored flag, reverting to its config definition if it has one;
+/// admins only
+pub(super) async fn unset_feature_flag(
+    State(state): State<Arc<ProxyState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> StatusCode {
+    let admin = match admin_name(&state, &headers) {
+        Ok(admin) => admin,
+        Err(status) => return status,
+    };
+    let stored = state
+        .feature_flags
+        .status()
+        .iter()
+        .any(|flag| flag.definition.name == name && flag.source == FlagSource::Store);
+    if !stored {
+        return StatusCode::NOT_FOUND;
+    }
+    if let Err(e) = state.store.delete_feature_flag(&name) {
+        log::error!("Failed to delete feature flag {}: {}", name, e);
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    let reverted = state.feature_flags.unset(&name);
+    audit(
+        &state,
+        "feature_flag.unset",
+        &name,
+        serde_json::json!({ "admin": admin, "reverted_to_config": reverted }),
+    );
+    StatusCode::NO_CONTENT
+}
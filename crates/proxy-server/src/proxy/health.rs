@@ -0,0 +1,425 @@
+//! Health, readiness, metrics and capability discovery
+
+use super::providers::provider_quotas;
+use super::{tenant_id, ProxyState};
+use crate::api_versions::ApiVersion;
+use crate::dependencies::{BreakerTransition, DependencyKind, DependencyStatus};
+use crate::error::Result;
+use crate::fhe::FheParams;
+use crate::metrics_catalog::MetricDescriptor;
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Health check endpoint
+pub(super) async fn health_check() -> &'static str {
+    "FHE LLM Proxy is running"
+}
+
+/// What this deployment supports, for the caller's tenant, so SDKs can adapt
+/// without probing endpoints
+pub(super) async fn get_capabilities(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let tenant_config = match tenant_id(&headers) {
+        Some(tenant) => state.tenant_configs.resolve(tenant),
+        None => state.tenant_configs.resolve_global(),
+    }
+    .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let config = &state.config;
+
+    // The server's parameters, plus the tenant's own profile when it has one
+    let server_params = state.fhe_engine.read().await.get_params().clone();
+    let mut profiles = vec![serde_json::json!({
+        "params_hash": server_params.fingerprint(),
+        "params": server_params,
+        "default": true,
+    })];
+    if tenant_config
+        .overridden
+        .iter()
+        .any(|f| f == "parameter_profile")
+    {
+        let encryption = &tenant_config.encryption;
+        let tenant_params = FheParams {
+            poly_modulus_degree: encryption.poly_modulus_degree,
+            coeff_modulus_bits: encryption.coeff_modulus_bits.clone(),
+            scale_bits: encryption.scale_bits,
+            security_level: encryption.security_level,
+        };
+        profiles.push(serde_json::json!({
+            "params_hash": tenant_params.fingerprint(),
+            "params": tenant_params,
+            "default": false,
+        }));
+    }
+
+    // Providers the policy validator would reject are not worth advertising
+    let mut providers: Vec<&String> = state
+        .llm_providers
+        .keys()
+        .filter(|name| config.validation.allowed_providers.contains(name))
+        .collect();
+    providers.sort();
+
+    let compression_codecs: &[&str] = if config.performance.compression_enabled {
+        &["gzip"]
+    } else {
+        &[]
+    };
+
+    Ok(Json(serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "tenant": tenant_id(&headers),
+        "features": {
+            "streaming": true,
+            "stream_flow_control": config.scaling.stream_flow_control.enabled,
+            "batch_jobs": state.batch_windows.is_enabled(),
+            "overflow_queue": state.overflow.is_enabled(),
+            "documents": config.documents.enabled,
+            "multi_key_fhe": false,
+            "prompt_packing": config.performance.packing.enabled,
+            "response_cache": !tenant_config.response_cache_rules.is_empty(),
+            "response_signing": state.response_signer.is_some(),
+            "delegated_decryption": state.decryption.is_enabled(),
+            "delegated_decryption_required": tenant_config.require_delegated_decryption,
+            "redaction_policies": state.redaction_policies.is_enabled(),
+            "federation": state.federation.is_enabled(),
+            "experiments": state.experiments.is_enabled(),
+            "aggregation": state.aggregation.is_enabled(),
+            "key_escrow": state.escrow.is_enabled() && !tenant_config.escrow_custodians.is_empty(),
+            "decryption_approvals": state.approvals.is_enabled(),
+            "session_renewal": state.renewal.is_enabled(),
+            "conversation_context": state.conversations.is_enabled(),
+            "prompt_lint_required": tenant_config.require_prompt_lint,
+            "replay_protection": config.validation.order.iter().any(|v| v == "replay"),
+            "persistent_storage": state.store.name() != "memory",
+            "gpu": cfg!(feature = "gpu") && config.gpu.enabled,
+        },
+        "fhe": {
+            "scheme": "ckks",
+            "profiles": profiles,
+        },
+        "providers": providers,
+        "models": {
+            "allowed": tenant_config.allowed_models,
+            "denied": tenant_config.denied_models,
+            "upgrades": tenant_config.model_upgrades,
+        },
+        "limits": {
+            "max_ciphertext_bytes": config.validation.max_ciphertext_bytes,
+            "max_document_bytes": config.documents.max_document_bytes,
+            "response_chunk_bytes": config.performance.response_chunking.chunk_size_bytes,
+            "rate_limit_per_minute": tenant_config.rate_limit_per_minute,
+            "max_sessions": tenant_config.max_sessions,
+        },
+        "compression": {
+            "codecs": compression_codecs,
+        },
+        "api_versions": ApiVersion::ALL
+            .into_iter()
+            .filter(|v| state.api_versions.is_enabled(*v))
+            .map(|v| v.as_str())
+            .collect::<Vec<_>>(),
+    })))
+}
+
+/// Liveness check endpoint (Kubernetes)
+pub(super) async fn liveness_check(State(state): State<Arc<ProxyState>>) -> StatusCode {
+    if state.monitoring.liveness_check().await {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Readiness check endpoint (Kubernetes)
+///
+/// Lists each monitored dependency's health and breaker; endpoints and
+/// errors are left to /v1/admin/dependencies
+pub(super) async fn readiness_check(
+    State(state): State<Arc<ProxyState>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let ready = is_ready(&state).await;
+    let dependencies: Vec<serde_json::Value> = state
+        .dependencies
+        .statuses()
+        .into_iter()
+        .map(|status| {
+            serde_json::json!({
+                "name": status.name,
+                "kind": status.kind,
+                "health": status.health,
+                "breaker": status.breaker,
+                "required": status.required,
+                "consecutive_failures": status.consecutive_failures,
+                "last_success_at": status.last_success_at,
+            })
+        })
+        .collect();
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "ready": ready,
+            "blocking_dependencies": state.dependencies.blocking(),
+            "dependencies": dependencies,
+        })),
+    )
+}
+
+/// Monitored dependencies with their endpoints and last errors
+pub(super) async fn get_dependency_status(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.dependencies.is_enabled(),
+        "dependencies": state.dependencies.statuses(),
+    }))
+}
+
+/// Track every external system this configuration uses
+pub(super) fn register_dependencies(state: &ProxyState) {
+    let monitor = &state.dependencies;
+    monitor.register(
+        "session_store",
+        DependencyKind::SessionStore,
+        state.store.name(),
+    );
+    if monitor.config().probe_providers {
+        for (name, provider) in &state.llm_providers {
+            monitor.register(
+                &format!("provider:{}", name),
+                DependencyKind::Provider,
+                provider.base_url(),
+            );
+        }
+    }
+    if state.decryption.is_enabled() {
+        monitor.register("kms", DependencyKind::Kms, state.decryption.endpoint());
+    }
+    if state.conversations.is_enabled() {
+        monitor.register(
+            "object_storage",
+            DependencyKind::ObjectStorage,
+            state.conversations.archive_backend(),
+        );
+    }
+}
+
+/// Probe each dependency whose breaker lets the probe through, alerting when
+/// a breaker opens
+pub(super) async fn probe_dependencies(state: &ProxyState) {
+    let timeout = Duration::from_millis(state.dependencies.config().probe_timeout_ms);
+    for status in state.dependencies.statuses() {
+        if !state
+            .dependencies
+            .should_probe(&status.name, chrono::Utc::now().timestamp())
+        {
+            continue;
+        }
+        let started = Instant::now();
+        let outcome =
+            match tokio::time::timeout(timeout, probe_dependency(state, &status, timeout)).await {
+                Ok(result) => result.map_err(|e| e.to_string()),
+                Err(_) => Err(format!("No answer within {}ms", timeout.as_millis())),
+            };
+        let transition = state.dependencies.record(
+            &status.name,
+            outcome.clone(),
+            started.elapsed().as_millis() as u64,
+            chrono::Utc::now().timestamp(),
+        );
+        match transition {
+            Some(BreakerTransition::Opened) => {
+                let message = format!(
+                    "Dependency {} ({}) is down: {}",
+                    status.name,
+                    status.target,
+                    outcome.err().unwrap_or_default()
+                );
+                log::error!("{}", message);
+                let severity = if status.required { 2 } else { 1 };
+                state
+                    .monitoring
+                    .raise_alert("dependency_down", message, severity)
+                    .await;
+            }
+            Some(BreakerTransition::Closed) => {
+                log::info!("Dependency {} recovered", status.name);
+            }
+            None => {}
+        }
+    }
+}
+
+async fn probe_dependency(
+    state: &ProxyState,
+    status: &DependencyStatus,
+    timeout: Duration,
+) -> Result<()> {
+    match status.kind {
+        DependencyKind::SessionStore => state.store.get_session(Uuid::nil()).map(|_| ()),
+        DependencyKind::Provider => {
+            let name = status.name.trim_start_matches("provider:");
+            match state.llm_providers.get(name) {
+                Some(provider) => provider.probe(timeout).await,
+                None => Ok(()),
+            }
+        }
+        DependencyKind::Kms => state.decryption.probe().await.unwrap_or(Ok(())),
+        DependencyKind::ObjectStorage => state.conversations.probe_archive(),
+    }
+}
+
+/// Whether the proxy should be sent traffic
+pub(super) async fn is_ready(state: &ProxyState) -> bool {
+    !state.resource_guard.is_draining()
+        && state.standby.is_serving()
+        && !state.engine_warming.load(Ordering::Relaxed)
+        && state.clock.is_healthy()
+        && state.dependencies.blocking().is_empty()
+        && state.monitoring.readiness_check().await
+}
+
+/// Get basic metrics
+pub(super) async fn get_metrics(
+    State(state): State<Arc<ProxyState>>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    use serde_json::json;
+    let metrics = state.metrics.get_stats();
+    let warm_hints: HashMap<_, _> = state
+        .llm_providers
+        .iter()
+        .map(|(name, provider)| (name.clone(), provider.warm_hint_stats()))
+        .collect();
+    let now = chrono::Utc::now().timestamp();
+    let rendered = state.metric_catalog.render([
+        ("requests", json!(metrics.total_requests)),
+        ("errors", json!(metrics.total_errors)),
+        ("encryptions", json!(metrics.encryption_operations)),
+        ("decryptions", json!(metrics.decryption_operations)),
+        ("avg_response_time_ms", json!(metrics.avg_response_time_ms)),
+        ("provider_quota", provider_quotas(&state)),
+        ("warm_hints", json!(warm_hints)),
+        ("load_shedding", json!(state.load_shedding.stats().policies)),
+        ("rate_limit_rules", json!(state.rate_limit_rules.stats())),
+        (
+            "engine_tables",
+            json!(state.fhe_engine.read().await.table_stats()),
+        ),
+        ("workload_tags", json!(state.workload_tags.report().tags)),
+        (
+            "context_compression",
+            json!(state.conversations.stats().compression),
+        ),
+        ("synthetic_probes", json!(state.probes.report().targets)),
+        ("request_telemetry", json!(state.telemetry.report().series)),
+        ("feature_flags", json!(state.feature_flags.status())),
+        ("execution", json!(state.execution.report())),
+        ("connections", json!(state.connection_guard.stats())),
+        ("clock", json!(state.clock.status())),
+        ("trace_sampling", json!(state.trace_sampler.stats())),
+        ("quality", json!(state.quality.report(now).models)),
+        (
+            "document_workers",
+            json!(state.documents.autoscaler().stats()),
+        ),
+        ("response_cache", json!(state.response_cache.stats().await)),
+        ("memory", json!(state.memory.refresh())),
+        ("timestamp", json!(now)),
+    ]);
+    match rendered {
+        Ok(document) => Ok(Json(document.into())),
+        Err(e) => {
+            log::error!("Failed to render metrics: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Type, labels, unit and description of every metric on /metrics
+pub(super) async fn get_metric_catalog(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<Vec<MetricDescriptor>> {
+    Json(
+        state
+            .metric_catalog
+            .entries()
+            .into_iter()
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Get detailed system metrics
+pub(super) async fn get_detailed_metrics(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    let metrics = state.metrics.get_stats();
+    let system_metrics = state
+        .monitoring
+        .get_metrics(metrics, &state.fhe_engine)
+        .await;
+    Json(serde_json::to_value(system_metrics).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::config::TenantOverrides;
+    use crate::proxy::ProxyServer;
+
+    #[tokio::test]
+    async fn test_capabilities_follow_config_and_tenant() {
+        let mut config = Config::default();
+        config.llm.openai_api_key = Some("sk-test".to_string());
+        config.scaling.batch_windows.enabled = true;
+        config.performance.compression_enabled = false;
+        config.tenants.overrides.insert(
+            "acme".to_string(),
+            TenantOverrides {
+                denied_models: Some(vec!["gpt-3.5-turbo".to_string()]),
+                ..TenantOverrides::default()
+            },
+        );
+        let state = ProxyServer::new(config).unwrap().state;
+
+        let Json(global) = get_capabilities(State(state.clone()), HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(global["features"]["batch_jobs"], true);
+        assert_eq!(global["features"]["multi_key_fhe"], false);
+        assert_eq!(global["providers"], serde_json::json!(["openai"]));
+        assert_eq!(global["compression"]["codecs"], serde_json::json!([]));
+        assert_eq!(global["fhe"]["profiles"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            global["fhe"]["profiles"][0]["params_hash"],
+            state.fhe_engine.read().await.get_params().fingerprint()
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        let Json(acme) = get_capabilities(State(state.clone()), headers)
+            .await
+            .unwrap();
+        assert_eq!(acme["tenant"], "acme");
+        assert_eq!(
+            acme["models"]["denied"],
+            serde_json::json!(["gpt-3.5-turbo"])
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Honeytoken keys and canary ciphertexts
+
+use super::identity::admin_name;
+use super::{audit, tenant_id, ProxyState};
+use crate::security::{
+    HoneytokenGeneration, HoneytokenKind, HoneytokenTrip, Honeytokens,
+    HONEYTOKEN_ROTATION_AUDIT_ACTION,
+};
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{Json, Response},
+};
+use base64::prelude::*;
+use std::sync::Arc;
+
+/// Refuse, and raise a breach alert for, any request presenting a
+/// honeytoken key or naming a canary ciphertext in its path, query or body
+pub(super) async fn honeytoken_middleware(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> std::result::Result<Response, StatusCode> {
+    if !state.honeytokens.is_enabled() {
+        return Ok(next.run(request).await);
+    }
+    let headers = request.headers();
+    let presented_key = headers
+        .get("x-api-key")
+        .or_else(|| headers.get(axum::http::header::AUTHORIZATION))
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.strip_prefix("Bearer ").unwrap_or(v).trim().to_string());
+    if let Some(key) = presented_key {
+        if let Some(generation) = state.honeytokens.match_key(&key) {
+            let mut context = honeytoken_context(&state, &request, "api_key");
+            context["key_fingerprint"] = serde_json::json!(Honeytokens::fingerprint(&key));
+            report_honeytoken(&state, HoneytokenKind::ApiKey, &generation, context).await;
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    let target = request.uri().to_string();
+    if let Some((id, generation)) = state
+        .honeytokens
+        .match_canaries(target.as_bytes())
+        .into_iter()
+        .next()
+    {
+        let mut context = honeytoken_context(&state, &request, "uri");
+        context["ciphertext_id"] = serde_json::json!(id);
+        report_honeytoken(
+            &state,
+            HoneytokenKind::CanaryCiphertext,
+            &generation,
+            context,
+        )
+        .await;
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // Bodies of unknown or excessive length pass unscanned rather than being buffered
+    let length = request
+        .headers()
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    let scannable = length
+        .is_some_and(|len| len > 0 && len <= state.honeytokens.config().max_scanned_body_bytes);
+    if !scannable {
+        return Ok(next.run(request).await);
+    }
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, state.honeytokens.config().max_scanned_body_bytes)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let found = state.honeytokens.match_canaries(&bytes).into_iter().next();
+    let request = axum::extract::Request::from_parts(parts, axum::body::Body::from(bytes));
+    if let Some((id, generation)) = found {
+        let mut context = honeytoken_context(&state, &request, "body");
+        context["ciphertext_id"] = serde_json::json!(id);
+        report_honeytoken(
+            &state,
+            HoneytokenKind::CanaryCiphertext,
+            &generation,
+            context,
+        )
+        .await;
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Who made a request and how, for investigating a honeytoken trip. Header
+/// values and the body are left out: they may hold the attacker's other loot.
+/// This runs before tenant identity is checked, so `tenant` is the one the
+/// presented API key belongs to and `claimed_tenant` the unverified header.
+fn honeytoken_context(
+    state: &ProxyState,
+    request: &axum::extract::Request,
+    found_in: &str,
+) -> serde_json::Value {
+    let headers = request.headers();
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let mut header_names: Vec<&str> = headers.keys().map(|name| name.as_str()).collect();
+    header_names.sort_unstable();
+    header_names.dedup();
+    serde_json::json!({
+        "found_in": found_in,
+        "method": request.method().as_str(),
+        "path": request.uri().path(),
+        "query": request.uri().query(),
+        "client_ip": header("x-forwarded-for").or_else(|| header("x-real-ip")),
+        "user_agent": header("user-agent"),
+        "tenant": header("x-api-key").and_then(|key| state.access.tenant_for_key(key)),
+        "claimed_tenant": tenant_id(headers),
+        "content_length": header("content-length"),
+        "header_names": header_names,
+    })
+}
+
+async fn report_honeytoken(
+    state: &ProxyState,
+    kind: HoneytokenKind,
+    generation: &HoneytokenGeneration,
+    context: serde_json::Value,
+) {
+    let now = chrono::Utc::now().timestamp();
+    let what = match kind {
+        HoneytokenKind::ApiKey => "Honeytoken API key",
+        HoneytokenKind::CanaryCiphertext => "Canary ciphertext",
+    };
+    let message = format!(
+        "{} from generation {} (issued at {}) used by {} {}",
+        what,
+        generation.generation,
+        generation.issued_at,
+        context["client_ip"].as_str().unwrap_or("unknown"),
+        context["path"].as_str().unwrap_or_default()
+    );
+    log::error!("Possible breach: {}", message);
+    state.siem.emit(
+        SecurityEvent::new(SecurityEventKind::Honeytoken, &message)
+            .source_ip(context["client_ip"].as_str().unwrap_or("unknown"))
+            .tenant(context["tenant"].as_str())
+            .subject(&format!("generation:{}", generation.generation)),
+    );
+    state
+        .monitoring
+        .raise_alert("honeytoken_triggered", message, 2)
+        .await;
+    let trip = HoneytokenTrip {
+        kind,
+        generation: generation.generation,
+        issued_at: generation.issued_at,
+        stale_for_seconds: generation.retired_at.map(|retired| now - retired),
+        detected_at: now,
+        context,
+    };
+    audit(
+        state,
+        "honeytokens.triggered",
+        &generation.generation.to_string(),
+        serde_json::to_value(&trip).unwrap_or_default(),
+    );
+    state.honeytokens.record_trip(trip);
+}
+
+/// Issue a new honeytoken generation shaped like the engine's ciphertexts
+pub(super) async fn rotate_honeytokens(state: &ProxyState) -> HoneytokenGeneration {
+    let params = state.fhe_engine.read().await.get_params().clone();
+    let generation = state
+        .honeytokens
+        .rotate(&params, chrono::Utc::now().timestamp());
+    audit(
+        state,
+        HONEYTOKEN_ROTATION_AUDIT_ACTION,
+        &generation.generation.to_string(),
+        serde_json::to_value(&generation).unwrap_or_default(),
+    );
+    log::info!("Issued honeytoken generation {}", generation.generation);
+    generation
+}
+
+/// The honeytokens to plant, every generation still recognised and recent trips
+pub(super) async fn get_honeytokens(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    admin_name(&state, &headers)?;
+    let current = state.honeytokens.current().map(|issued| {
+        serde_json::json!({
+            "generation": issued.generation,
+            "keys": issued.keys,
+            "canaries": issued.canaries.iter().map(|canary| serde_json::json!({
+                "id": canary.id,
+                "params": canary.params,
+                "data": BASE64_STANDARD.encode(&canary.data),
+            })).collect::<Vec<_>>(),
+        })
+    });
+    Ok(Json(serde_json::json!({
+        "enabled": state.honeytokens.is_enabled(),
+        "current": current,
+        "generations": state.honeytokens.generations(),
+        "trips": state.honeytokens.trips(100),
+    })))
+}
+
+/// Rotate honeytokens ahead of schedule, e.g. after planting a batch
+pub(super) async fn rotate_honeytokens_now(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<HoneytokenGeneration>, StatusCode> {
+    admin_name(&state, &headers)?;
+    if !state.honeytokens.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(rotate_honeytokens(&state).await))
+}
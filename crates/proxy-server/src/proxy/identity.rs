@@ -0,0 +1,108 @@
+//! Identity of requests: tenants from API keys and session tokens, and
+//! operators from admin tokens
+
+use super::ProxyState;
+use crate::access::{self, IdentityRefusal, ADMIN_TOKEN_HEADER, API_KEY_HEADER, TENANT_HEADER};
+use crate::renewal::SESSION_TOKEN_HEADER;
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use std::sync::Arc;
+
+/// Bind the request to the tenant its API key or session was issued to.
+/// `x-tenant-id` is only a claim: a claim without credentials, or naming
+/// another tenant, is refused; otherwise the header is set to the
+/// authenticated tenant, so everything downstream reads a verified value.
+pub(super) async fn tenant_identity_middleware(
+    State(state): State<Arc<ProxyState>>,
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> std::result::Result<Response, StatusCode> {
+    let headers = request.headers();
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let from_key = header(API_KEY_HEADER).and_then(|key| state.access.tenant_for_key(key));
+    // Invalid session tokens are refused by the session token middleware
+    let from_session = match header(SESSION_TOKEN_HEADER) {
+        Some(token) if state.renewal.is_enabled() => {
+            match state
+                .session_manager
+                .authenticate(&state.renewal, token)
+                .await
+            {
+                Ok((session_id, _, _)) => state
+                    .session_manager
+                    .get_tenant(session_id)
+                    .await
+                    .filter(|tenant| !tenant.is_empty()),
+                Err(_) => None,
+            }
+        }
+        _ => None,
+    };
+    let claimed = header(TENANT_HEADER);
+
+    let tenant = match access::resolve_tenant(from_key, from_session.as_deref(), claimed) {
+        Ok(tenant) => tenant,
+        Err(refusal) => {
+            let (reason, status) = match refusal {
+                IdentityRefusal::Unauthenticated => (
+                    "Tenant claimed without credentials",
+                    StatusCode::UNAUTHORIZED,
+                ),
+                IdentityRefusal::Mismatch => (
+                    "Tenant claim does not match the request's credentials",
+                    StatusCode::FORBIDDEN,
+                ),
+            };
+            let client_ip = header("x-forwarded-for")
+                .or_else(|| header("x-real-ip"))
+                .unwrap_or("unknown");
+            log::warn!("{} ({:?} from {})", reason, claimed, client_ip);
+            state.siem.emit(
+                SecurityEvent::new(SecurityEventKind::AuthFailure, reason)
+                    .source_ip(client_ip)
+                    .tenant(claimed),
+            );
+            return Err(status);
+        }
+    };
+
+    let headers = request.headers_mut();
+    match tenant.as_deref().map(axum::http::HeaderValue::from_str) {
+        Some(Ok(value)) => {
+            headers.insert(TENANT_HEADER, value);
+        }
+        Some(Err(_)) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        None => {
+            headers.remove(TENANT_HEADER);
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+/// The operator named by the request's admin token, for privileged admin
+/// endpoints. Closed unless admin tokens are configured.
+pub(super) fn admin_name(
+    state: &ProxyState,
+    headers: &HeaderMap,
+) -> std::result::Result<String, StatusCode> {
+    let token = headers
+        .get(ADMIN_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    state
+        .access
+        .admin(token)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            log::warn!("Rejected unknown admin token");
+            state.siem.emit(SecurityEvent::new(
+                SecurityEventKind::AuthFailure,
+                "Unknown admin token",
+            ));
+            StatusCode::FORBIDDEN
+        })
+}
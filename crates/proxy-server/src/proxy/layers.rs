@@ -0,0 +1,1024 @@
+//! Middleware shared by every route: admission, key policies, transforms and API versions
+
+use super::billing::is_event_stream;
+use super::{audit, tenant_id, ProxyState};
+use crate::api_versions::{ApiVersion, API_VERSION_HEADER};
+use crate::billing::{UncompressedBytes, REQUEST_ID_HEADER};
+use crate::config::TransformStage;
+use crate::deadline_budget::{Hop, RequestDeadline};
+use crate::error::{Error, Result};
+use crate::limit_rules::{RuleContext, RATE_LIMIT_RULE_HEADER};
+use crate::middleware::KeyOperation;
+use crate::monitoring::StructuredLogger;
+use crate::request_journal::{self, JournalEvent};
+use crate::sandbox::{SandboxGrant, SANDBOX_KEY_HEADER};
+use crate::scaling::{AdmissionPermit, QueueProjection, RequestPriority, ShedContext, ShedPolicy};
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use crate::telemetry::ServedModel;
+use crate::trace_sampling::{self, TRACEPARENT_HEADER};
+use crate::transactions::{TransactionRequest, TransactionStep};
+use crate::workload_tags::{WorkloadTag, WORKLOAD_TAG_HEADER};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use base64::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Enhanced logging middleware
+pub(super) async fn logging_middleware(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let client_ip = request
+        .headers()
+        .get("x-forwarded-for")
+        .or_else(|| request.headers().get("x-real-ip"))
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let response = next.run(request).await;
+
+    let elapsed = start.elapsed();
+    let status = response.status().as_u16();
+
+    StructuredLogger::log_request(method.as_str(), uri.path(), status, elapsed, &client_ip);
+
+    response
+}
+
+/// Rate limiting middleware
+pub(super) async fn rate_limiting_middleware(
+    State(state): State<Arc<ProxyState>>,
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> std::result::Result<Response, StatusCode> {
+    let received = Instant::now();
+    let client_ip = request
+        .headers()
+        .get("x-forwarded-for")
+        .or_else(|| request.headers().get("x-real-ip"))
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+
+    // Priority comes from the authenticated tenant's server-side SLA class;
+    // clients may only lower it
+    let tenant = tenant_id(request.headers());
+    if let Some(tenant) = tenant.filter(|t| state.offboarding.is_offboarded(t)) {
+        log::warn!("Refused request of offboarded tenant {}", tenant);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let tenant_config = match tenant {
+        Some(tenant) => state.tenant_configs.resolve(tenant),
+        None => state.tenant_configs.resolve_global(),
+    }
+    .map_err(|e| {
+        log::warn!("Failed to resolve tenant config for {:?}: {}", tenant, e);
+        StatusCode::BAD_REQUEST
+    })?;
+    let sla_class = tenant_config.sla_class;
+    let requested_priority = request
+        .headers()
+        .get("x-request-priority")
+        .map(|v| {
+            v.to_str()
+                .map_err(|e| Error::Validation(e.to_string()))
+                .and_then(RequestPriority::parse)
+        })
+        .transpose()
+        .map_err(|e| {
+            log::warn!("Rejected request priority header: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+    // A workload tag asks for its configured priority unless the client asked
+    // for one itself; either way the SLA class caps it
+    let workload_header = request
+        .headers()
+        .get(WORKLOAD_TAG_HEADER)
+        .map(|v| v.to_str().unwrap_or_default().to_string());
+    let operational = is_operational_path(request.uri().path());
+    let workload = if operational {
+        None
+    } else {
+        state
+            .workload_tags
+            .classify(
+                workload_header.as_deref(),
+                &tenant_config.allowed_workload_tags,
+            )
+            .map_err(|e| {
+                log::warn!("Rejected workload tag for {:?}: {}", tenant, e);
+                match e {
+                    Error::Security(message) => {
+                        state.siem.emit(
+                            SecurityEvent::new(SecurityEventKind::PolicyViolation, &message)
+                                .tenant(tenant),
+                        );
+                        StatusCode::FORBIDDEN
+                    }
+                    _ => StatusCode::BAD_REQUEST,
+                }
+            })?
+    };
+    let requested_priority =
+        requested_priority.or_else(|| workload.as_ref().and_then(WorkloadTag::priority));
+    let priority = RequestPriority::for_sla(sla_class, requested_priority);
+    let shed_context = ShedContext {
+        path: request.uri().path().to_string(),
+        tenant: tenant.map(str::to_string),
+        priority,
+    };
+
+    // Sandbox keys have a window of their own; tenants with a rate limit
+    // override share one window per tenant
+    let sandbox = request.extensions().get::<SandboxGrant>().cloned();
+    let (window, base_limit) = match (&sandbox, tenant) {
+        (Some(grant), _) => (
+            format!("sandbox:{}", grant.key_id),
+            grant.rate_limit_per_minute,
+        ),
+        (None, Some(tenant)) => (
+            format!("tenant:{}", tenant),
+            tenant_config.rate_limit_per_minute,
+        ),
+        (None, None) => (client_ip.clone(), state.rate_limiter.global_limit()),
+    };
+    // A scripted rule may replace the static limit for this request
+    let limit_rule = state.rate_limit_rules.evaluate(&RuleContext {
+        tenant,
+        sla_class,
+        priority,
+        path: request.uri().path(),
+        method: request.method().as_str(),
+        now: chrono::Utc::now(),
+        base_limit,
+        in_flight: state.admission.in_flight(),
+    });
+    let limit = limit_rule.as_ref().map_or(base_limit, |d| d.limit);
+    let allowed = state
+        .rate_limiter
+        .check_rate_limit_with(&window, limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Policies in simulation admit what they would have shed and only record it
+    if !allowed
+        && state.load_shedding.shed(
+            ShedPolicy::RateLimit,
+            &shed_context,
+            StatusCode::TOO_MANY_REQUESTS.as_u16(),
+        )
+    {
+        let reason = match &limit_rule {
+            Some(decision) => format!("Too many requests (rule {})", decision.rule),
+            None => "Too many requests".to_string(),
+        };
+        state.siem.emit(
+            SecurityEvent::new(SecurityEventKind::RateLimited, &reason)
+                .source_ip(&client_ip)
+                .tenant(tenant),
+        );
+        state
+            .sla_metrics
+            .record_rejection(sla_class, StatusCode::TOO_MANY_REQUESTS.as_u16())
+            .await;
+        let Some(decision) = limit_rule else {
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        };
+        state.rate_limit_rules.record_rejection(&decision);
+        let mut response = StatusCode::TOO_MANY_REQUESTS.into_response();
+        if let Ok(value) = axum::http::HeaderValue::from_str(&decision.rule) {
+            response.headers_mut().insert(RATE_LIMIT_RULE_HEADER, value);
+        }
+        return Ok(response);
+    }
+
+    // Operational endpoints bypass draining and priority admission
+    let path = request.uri().path();
+
+    // Turn away new work while the resource guard drains the engine, or while
+    // a failover drill sends traffic to the standby regions
+    if (state.resource_guard.is_draining()
+        || state.drills.is_rerouting()
+        || !state.standby.is_serving())
+        && !operational
+    {
+        state
+            .sla_metrics
+            .record_rejection(sla_class, StatusCode::SERVICE_UNAVAILABLE.as_u16())
+            .await;
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    // Lockdown keeps encrypted processing up but stops anything that releases
+    // plaintext or key material, or crosses to other proxies
+    let sensitive = path.starts_with("/v1/keys")
+        || path.starts_with("/v1/decrypt")
+        || path.starts_with("/v1/federation")
+        || path.starts_with("/v1/escrow")
+        || (path.starts_with("/v1/aggregations") && path.ends_with("/decrypt"));
+    if state.lockdown.load(Ordering::Relaxed) && sensitive {
+        log::warn!("Refusing {} during lockdown", path);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // The client's deadline bounds the queue projection and the hop budgets
+    let projection_enabled = state.queue_projector.is_enabled() && !operational;
+    let budget_enabled = state.config.server.deadline_budget.enabled && !operational;
+    let deadline = (projection_enabled || budget_enabled)
+        .then(|| request_deadline(&state, request.headers()))
+        .transpose()
+        .map_err(|e| {
+            log::warn!("Rejected request deadline header: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    // Reject up front when the queue would not drain before the client's deadline
+    if let Some(deadline) = deadline.filter(|_| projection_enabled) {
+        let projection = state.queue_projector.project(state.admission.in_flight());
+        let retry_after = projection.retry_after(deadline).filter(|_| {
+            state.load_shedding.shed(
+                ShedPolicy::QueueProjection,
+                &shed_context,
+                StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            )
+        });
+        if let Some(retry_after) = retry_after {
+            log::warn!(
+                "Rejecting request from {} SLA class: projected latency {}ms exceeds deadline {}ms",
+                sla_class.as_str(),
+                projection.projected_latency_ms,
+                deadline.as_millis()
+            );
+            state
+                .sla_metrics
+                .record_rejection(sla_class, StatusCode::TOO_MANY_REQUESTS.as_u16())
+                .await;
+            return Ok(backoff_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                &projection,
+                retry_after,
+            ));
+        }
+    }
+
+    // Shed lower priorities first as in-flight work approaches capacity; Normal
+    // and Low work may instead wait on disk for a slot
+    let mut overflow_wait = None;
+    let _permit = if operational {
+        None
+    } else {
+        match state.admission.try_admit(priority) {
+            Some(permit) => Some(permit),
+            None => {
+                let absorbed = if state.overflow.accepts(priority) {
+                    absorb_burst(&state, priority, request.body_mut()).await
+                } else {
+                    Err(StatusCode::SERVICE_UNAVAILABLE)
+                };
+                match absorbed {
+                    Ok((permit, waited)) => {
+                        overflow_wait = Some(waited);
+                        Some(permit)
+                    }
+                    Err(status)
+                        if status == StatusCode::SERVICE_UNAVAILABLE
+                            && !state.load_shedding.shed(
+                                ShedPolicy::PriorityShedding,
+                                &shed_context,
+                                status.as_u16(),
+                            ) =>
+                    {
+                        Some(state.admission.admit_unchecked())
+                    }
+                    Err(status) => {
+                        log::warn!(
+                            "Shedding {} priority request from {} SLA class ({} in flight)",
+                            priority.as_str(),
+                            sla_class.as_str(),
+                            state.admission.in_flight()
+                        );
+                        state
+                            .sla_metrics
+                            .record_rejection(sla_class, status.as_u16())
+                            .await;
+                        if status != StatusCode::SERVICE_UNAVAILABLE {
+                            return Err(status);
+                        }
+                        let projection = state.queue_projector.project(state.admission.in_flight());
+                        let retry_after = projection.projected_latency_ms.div_ceil(1000).max(1);
+                        return Ok(backoff_response(
+                            StatusCode::SERVICE_UNAVAILABLE,
+                            &projection,
+                            retry_after,
+                        ));
+                    }
+                }
+            }
+        }
+    };
+
+    // Sandbox traffic is counted per key, not in the proxy's metrics
+    let measured = !operational && sandbox.is_none();
+    if sandbox.is_none() {
+        state.metrics.increment_requests();
+    }
+
+    let region = (!operational && state.geo_latency.is_enabled()).then(|| {
+        let declared = request
+            .headers()
+            .get(state.geo_latency.region_header())
+            .and_then(|v| v.to_str().ok());
+        state.geo_latency.region_for(declared, &client_ip)
+    });
+
+    if let Some(workload) = &workload {
+        request.extensions_mut().insert(workload.clone());
+    }
+    if !operational {
+        request_journal::record(JournalEvent::Admitted {
+            priority: priority.as_str(),
+            overflow_wait_ms: overflow_wait.map(|waited| waited.as_millis() as u64),
+        });
+    }
+    if let Some(deadline) = deadline.filter(|_| budget_enabled) {
+        request.extensions_mut().insert(RequestDeadline::new(
+            &state.config.server.deadline_budget,
+            deadline,
+            received,
+        ));
+    }
+    let request_line = workload
+        .as_ref()
+        .map(|_| format!("{} {}", request.method(), request.uri().path()));
+    // Only the route template and body size are taken from a sampled request
+    let telemetry = (measured && state.telemetry.should_sample()).then(|| {
+        let route = request
+            .extensions()
+            .get::<axum::extract::MatchedPath>()
+            .map(|path| path.as_str().to_string());
+        let bytes = axum::body::HttpBody::size_hint(request.body()).exact();
+        (route, bytes)
+    });
+
+    let trace = (!operational && state.trace_sampler.is_enabled()).then(|| {
+        let route = request
+            .extensions()
+            .get::<axum::extract::MatchedPath>()
+            .map(|path| path.as_str().to_string());
+        let traceparent = request
+            .headers()
+            .get(TRACEPARENT_HEADER)
+            .and_then(|v| v.to_str().ok());
+        let context =
+            state
+                .trace_sampler
+                .start(traceparent, chrono::Utc::now().timestamp(), rand::random());
+        (route, context)
+    });
+
+    let started = Instant::now();
+    let mut response = match &trace {
+        // Provider and federation calls made while serving carry the trace
+        Some((_, context)) => trace_sampling::scope(context.clone(), next.run(request)).await,
+        None => next.run(request).await,
+    };
+    if let Some((route, mut context)) = trace {
+        let reason = state.trace_sampler.finish(
+            &context,
+            route.as_deref(),
+            response.status().as_u16(),
+            started.elapsed(),
+            chrono::Utc::now().timestamp(),
+        );
+        context.sampled = reason.is_some();
+        if let Ok(value) = axum::http::HeaderValue::from_str(&context.traceparent()) {
+            response.headers_mut().insert(TRACEPARENT_HEADER, value);
+        }
+    }
+    if let Some(workload) = &workload {
+        state
+            .workload_tags
+            .record(workload, response.status().as_u16(), started.elapsed());
+        if let Some(level) = workload.log_level() {
+            log::log!(
+                level,
+                "{} [{}] {} in {:?}",
+                request_line.as_deref().unwrap_or_default(),
+                workload.name,
+                response.status().as_u16(),
+                started.elapsed()
+            );
+        }
+        if let Ok(value) = axum::http::HeaderValue::from_str(&workload.name) {
+            response.headers_mut().insert(WORKLOAD_TAG_HEADER, value);
+        }
+    }
+    if measured {
+        state
+            .sla_metrics
+            .record_completion(sla_class, started.elapsed())
+            .await;
+        state.queue_projector.record_completion(started.elapsed());
+        if let Some(region) = region {
+            state.geo_latency.record(&region, started.elapsed()).await;
+        }
+    }
+    if let Some((route, bytes)) = telemetry {
+        let model = response.extensions().get::<ServedModel>();
+        state.telemetry.record(state.telemetry.sample(
+            route.as_deref(),
+            model.map(|m| m.0.as_str()),
+            bytes,
+            sla_class,
+            response.status().as_u16(),
+            started.elapsed(),
+        ));
+    }
+    response.headers_mut().insert(
+        "x-request-priority",
+        axum::http::HeaderValue::from_static(priority.as_str()),
+    );
+    if let Some(waited) = overflow_wait {
+        response
+            .headers_mut()
+            .insert("x-overflow-wait-ms", (waited.as_millis() as u64).into());
+    }
+    Ok(response)
+}
+
+/// Endpoints that bypass draining, priority admission and workload tagging
+fn is_operational_path(path: &str) -> bool {
+    path.starts_with("/health")
+        || path.starts_with("/metrics")
+        || path.starts_with("/.well-known")
+        || path.starts_with("/v1/queue")
+        || path.starts_with("/v1/admin")
+}
+
+/// Spill the body of a request that found admission full to the overflow
+/// queue, wait for the drain loop to hand it back, then take an admission slot
+async fn absorb_burst<'a>(
+    state: &'a ProxyState,
+    priority: RequestPriority,
+    body: &mut axum::body::Body,
+) -> std::result::Result<(AdmissionPermit<'a>, Duration), StatusCode> {
+    let started = Instant::now();
+    let config = state.overflow.config();
+    let bytes = axum::body::to_bytes(std::mem::take(body), config.max_request_bytes)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let receiver = state.overflow.spill(priority, &bytes).map_err(|e| {
+        log::warn!("Overflow queue did not absorb request: {}", e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+    drop(bytes);
+
+    let max_wait = Duration::from_secs(config.max_wait_seconds);
+    let bytes = match tokio::time::timeout(max_wait, receiver).await {
+        Ok(Ok(bytes)) => bytes,
+        _ => return Err(StatusCode::SERVICE_UNAVAILABLE),
+    };
+    // Bodies are only re-injected into free slots, but a fresh request may take one first
+    let permit = loop {
+        if let Some(permit) = state.admission.try_admit(priority) {
+            break permit;
+        }
+        if started.elapsed() >= max_wait {
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+        tokio::time::sleep(Duration::from_millis(config.drain_interval_ms)).await;
+    };
+    *body = axum::body::Body::from(bytes);
+    Ok((permit, started.elapsed()))
+}
+
+/// Largest body the key policy check buffers; matches axum's default JSON limit
+const KEY_POLICY_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Enforce client key policies before any FHE work is done
+pub(super) async fn key_policy_middleware(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> std::result::Result<Response, StatusCode> {
+    let path = request.uri().path();
+    let classified = matches!(
+        path,
+        "/v1/encrypt"
+            | "/v1/decrypt"
+            | "/v1/decrypt/chunks"
+            | "/v1/chat/completions"
+            | "/v1/chat/stream"
+            | "/v1/concatenate"
+            | "/v1/transactions"
+            | "/v1/documents"
+            | "/v1/batch/jobs"
+    ) || path.starts_with("/v1/keys/rotate/")
+        || (path.starts_with("/v1/aggregations/") && path.ends_with("/decrypt"));
+    if !classified || !state.key_policies.is_active().await {
+        return Ok(next.run(request).await);
+    }
+
+    // The key is named in the body, except for rotation
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, KEY_POLICY_BODY_LIMIT)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let fields: serde_json::Value = serde_json::from_slice(&bytes).unwrap_or_default();
+    let uuid_field = |name: &str| fields[name].as_str().and_then(|v| v.parse::<Uuid>().ok());
+
+    // Combining ciphertexts hands nothing to a model, so only the operation is
+    // checked; the result keeps the keys' restrictions for later work
+    let derives = matches!(parts.uri.path(), "/v1/concatenate" | "/v1/transactions");
+    let mut uses: Vec<(Uuid, KeyOperation)> = Vec::new();
+    match parts.uri.path() {
+        "/v1/encrypt" => uses.extend(uuid_field("client_id").map(|id| (id, KeyOperation::Encrypt))),
+        "/v1/decrypt" | "/v1/decrypt/chunks" => {
+            uses.extend(uuid_field("client_id").map(|id| (id, KeyOperation::Decrypt)))
+        }
+        // Completions carry no key; check the keys that produced the ciphertext
+        "/v1/chat/completions" | "/v1/chat/stream" => {
+            let ids: Vec<Uuid> = uuid_field("ciphertext_id").into_iter().collect();
+            for owner in state.key_policies.owners_of(&ids).await {
+                uses.push((owner, KeyOperation::Process));
+            }
+        }
+        "/v1/concatenate" => {
+            let ids: Vec<Uuid> = ["ciphertext_a", "ciphertext_b"]
+                .into_iter()
+                .filter_map(uuid_field)
+                .collect();
+            for owner in state.key_policies.owners_of(&ids).await {
+                uses.push((owner, KeyOperation::Process));
+            }
+        }
+        "/v1/transactions" => {
+            let steps = serde_json::from_value::<TransactionRequest>(fields.clone())
+                .map(|request| request.steps)
+                .unwrap_or_default();
+            let mut loads = Vec::new();
+            for node in steps {
+                match node.step {
+                    TransactionStep::Encrypt { client_id, .. } => {
+                        uses.push((client_id, KeyOperation::Encrypt))
+                    }
+                    TransactionStep::Load { ciphertext_id } => loads.push(ciphertext_id),
+                    _ => {}
+                }
+            }
+            for owner in state.key_policies.owners_of(&loads).await {
+                uses.push((owner, KeyOperation::Process));
+            }
+        }
+        // Documents and batch jobs submit ciphertext bytes; check the keys of
+        // the ciphertexts they copy
+        "/v1/documents" | "/v1/batch/jobs" => {
+            let submitted = std::iter::once(&fields["encrypted_data"])
+                .chain(fields["items"].as_array().into_iter().flatten())
+                .filter_map(|data| data.as_str())
+                .filter_map(|data| BASE64_STANDARD.decode(data).ok());
+            for data in submitted {
+                for owner in state.key_policies.owners_of_data(&data).await {
+                    uses.push((owner, KeyOperation::Process));
+                }
+            }
+        }
+        path if path.starts_with("/v1/keys/rotate/") => uses.extend(
+            path.rsplit('/')
+                .next()
+                .and_then(|id| id.parse().ok())
+                .map(|id| (id, KeyOperation::Rotate)),
+        ),
+        // Aggregate results are decrypted with a client key
+        _ => uses.extend(uuid_field("client_id").map(|id| (id, KeyOperation::Decrypt))),
+    }
+    let model = fields["model"].as_str();
+
+    for (client_id, operation) in uses {
+        let authorized = if derives {
+            state
+                .key_policies
+                .authorize_operation(client_id, operation)
+                .await
+        } else {
+            state
+                .key_policies
+                .authorize(client_id, operation, model)
+                .await
+        };
+        if let Err(e) = authorized {
+            log::warn!(
+                "Refusing {} with key {}: {}",
+                parts.uri.path(),
+                client_id,
+                e
+            );
+            let tenant = tenant_id(&parts.headers);
+            audit(
+                &state,
+                "key_policy.violation",
+                &client_id.to_string(),
+                serde_json::json!({
+                    "operation": operation,
+                    "model": model,
+                    "path": parts.uri.path(),
+                    "tenant": tenant,
+                }),
+            );
+            state.siem.emit(
+                SecurityEvent::new(SecurityEventKind::PolicyViolation, &e.to_string())
+                    .tenant(tenant)
+                    .subject(&client_id.to_string()),
+            );
+
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "x-error-code",
+                axum::http::HeaderValue::from_static("key_policy_violation"),
+            );
+            let body = Json(serde_json::json!({
+                "error": {
+                    "code": "key_policy_violation",
+                    "message": e.to_string(),
+                    "operation": operation,
+                }
+            }));
+            return Ok((StatusCode::FORBIDDEN, headers, body).into_response());
+        }
+    }
+
+    let request = axum::extract::Request::from_parts(parts, axum::body::Body::from(bytes));
+    Ok(next.run(request).await)
+}
+
+/// Apply the configured header and field rewrites to the request on its way to
+/// the handler and to the response on its way back
+pub(super) async fn transform_middleware(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    if !state.transforms.is_enabled() {
+        return next.run(request).await;
+    }
+    let path = request.uri().path().to_string();
+    let tenant = tenant_id(request.headers()).map(str::to_string);
+    let tenant = tenant.as_deref();
+
+    let rules = state
+        .transforms
+        .rules_for(TransformStage::Request, &path, tenant);
+    let request = if rules.is_empty() {
+        request
+    } else {
+        let (mut parts, body) = request.into_parts();
+        let body = transform_body(&state, &rules, tenant, &mut parts.headers, body).await;
+        axum::extract::Request::from_parts(parts, body)
+    };
+
+    let response = next.run(request).await;
+    let rules = state
+        .transforms
+        .rules_for(TransformStage::Response, &path, tenant);
+    if rules.is_empty() {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = transform_body(&state, &rules, tenant, &mut parts.headers, body).await;
+    Response::from_parts(parts, body)
+}
+
+/// Rewrite headers, and the body too when it is JSON within the size limit;
+/// anything else passes through with only its headers rewritten
+async fn transform_body(
+    state: &ProxyState,
+    rules: &[usize],
+    tenant: Option<&str>,
+    headers: &mut HeaderMap,
+    body: axum::body::Body,
+) -> axum::body::Body {
+    let is_json = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    // Streams of unknown length are never buffered
+    let fits = axum::body::HttpBody::size_hint(&body)
+        .upper()
+        .is_some_and(|len| len <= state.transforms.max_body_bytes() as u64);
+    if !(is_json && fits && state.transforms.rewrites_body(rules)) {
+        state.transforms.apply(rules, tenant, headers, None);
+        return body;
+    }
+
+    let Ok(bytes) = axum::body::to_bytes(body, state.transforms.max_body_bytes()).await else {
+        state.transforms.apply(rules, tenant, headers, None);
+        return axum::body::Body::empty();
+    };
+    let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        state.transforms.apply(rules, tenant, headers, None);
+        return axum::body::Body::from(bytes);
+    };
+    state
+        .transforms
+        .apply(rules, tenant, headers, Some(&mut value));
+    let bytes = serde_json::to_vec(&value).unwrap_or_else(|_| bytes.to_vec());
+    headers.insert(axum::http::header::CONTENT_LENGTH, bytes.len().into());
+    axum::body::Body::from(bytes)
+}
+
+/// Serve a versioned path through the shared `/v1` routes, counting it and
+/// announcing the version's deprecation
+pub(super) async fn api_version_routing(
+    State(state): State<Arc<ProxyState>>,
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> std::result::Result<Response, StatusCode> {
+    let Some((version, path)) = ApiVersion::route(request.uri().path()) else {
+        return Ok(next.run(request).await);
+    };
+    if !state.api_versions.is_enabled(version) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let now = chrono::Utc::now();
+    state
+        .api_versions
+        .record(version, tenant_id(request.headers()), now);
+
+    let mut response = if state.api_versions.is_retired(version, now) {
+        StatusCode::GONE.into_response()
+    } else {
+        if version.is_adapted() {
+            let path_and_query = match request.uri().query() {
+                Some(query) => format!("{}?{}", path, query),
+                None => path,
+            };
+            let mut parts = request.uri().clone().into_parts();
+            parts.path_and_query = Some(
+                path_and_query
+                    .parse()
+                    .map_err(|_| StatusCode::BAD_REQUEST)?,
+            );
+            *request.uri_mut() =
+                axum::http::Uri::from_parts(parts).map_err(|_| StatusCode::BAD_REQUEST)?;
+            request.extensions_mut().insert(version);
+            request.extensions_mut().insert(AdapterBodyLimit(
+                state.api_versions.max_adapted_body_bytes(),
+            ));
+        }
+        next.run(request).await
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(
+        API_VERSION_HEADER,
+        axum::http::HeaderValue::from_static(version.as_str()),
+    );
+    for (name, value) in state.api_versions.lifecycle_headers(version) {
+        if let Ok(value) = value.parse() {
+            headers.insert(name, value);
+        }
+    }
+    Ok(response)
+}
+
+/// Request body limit of `api_version_adapter`
+#[derive(Debug, Clone, Copy)]
+struct AdapterBodyLimit(usize);
+
+/// Translate JSON bodies between a request's API version and the handlers'
+pub(super) async fn api_version_adapter(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> std::result::Result<Response, StatusCode> {
+    let Some(version) = request.extensions().get::<ApiVersion>().copied() else {
+        return Ok(next.run(request).await);
+    };
+    let limit = request
+        .extensions()
+        .get::<AdapterBodyLimit>()
+        .map_or(usize::MAX, |limit| limit.0);
+
+    let is_json = |headers: &HeaderMap| {
+        headers
+            .get(axum::http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"))
+    };
+    let request = if is_json(request.headers()) {
+        let (parts, body) = request.into_parts();
+        let bytes = axum::body::to_bytes(body, limit)
+            .await
+            .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+        let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+            Ok(mut value) => {
+                version.adapt_request(&mut value);
+                serde_json::to_vec(&value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            }
+            // Left for the handler to reject
+            Err(_) => bytes.to_vec(),
+        };
+        let mut parts = parts;
+        parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+        axum::extract::Request::from_parts(parts, axum::body::Body::from(body))
+    } else {
+        request
+    };
+
+    let response = next.run(request).await;
+    let status = response.status();
+    if is_event_stream(response.headers()) {
+        return Ok(response);
+    }
+    let json = is_json(response.headers());
+    let (mut parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let value = if bytes.is_empty() {
+        None
+    } else if json {
+        match serde_json::from_slice(&bytes) {
+            Ok(value) => Some(value),
+            Err(_) => return Ok(Response::from_parts(parts, axum::body::Body::from(bytes))),
+        }
+    } else {
+        return Ok(Response::from_parts(parts, axum::body::Body::from(bytes)));
+    };
+    let Some(value) = version.adapt_response(status, value) else {
+        return Ok(Response::from_parts(parts, axum::body::Body::from(bytes)));
+    };
+    let body = serde_json::to_vec(&value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+    parts.headers.insert(
+        axum::http::header::CONTENT_TYPE,
+        axum::http::HeaderValue::from_static("application/json"),
+    );
+    Ok(Response::from_parts(parts, axum::body::Body::from(body)))
+}
+
+/// Record the response body size before compression for `billing_middleware`
+pub(super) async fn uncompressed_size_middleware(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    if !state.billing.is_billed(request.uri().path())
+        || state.probes.is_probe(request.headers())
+        || request.headers().contains_key(SANDBOX_KEY_HEADER)
+    {
+        return next.run(request).await;
+    }
+    let mut response = next.run(request).await;
+    if !response.status().is_success() || is_event_stream(response.headers()) {
+        return response;
+    }
+    if let Some(len) = axum::body::HttpBody::size_hint(response.body()).exact() {
+        response.extensions_mut().insert(UncompressedBytes(len));
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => {
+            parts
+                .extensions
+                .insert(UncompressedBytes(bytes.len() as u64));
+            Response::from_parts(parts, axum::body::Body::from(bytes))
+        }
+        Err(e) => {
+            log::error!("Failed to buffer billed response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Cap in-flight requests per API key and cut off request bodies that stall or
+/// upload too slowly
+pub(super) async fn connection_guard_middleware(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> std::result::Result<Response, StatusCode> {
+    let guard = &state.connection_guard;
+    if !guard.is_enabled() {
+        return Ok(next.run(request).await);
+    }
+    let api_key = request
+        .headers()
+        .get("x-api-key")
+        .or_else(|| request.headers().get("authorization"))
+        .and_then(|v| v.to_str().ok());
+    let _permit = match api_key {
+        Some(api_key) => Some(guard.admit_request(api_key).ok_or_else(|| {
+            log::warn!("Refused request: too many in flight for its API key");
+            StatusCode::TOO_MANY_REQUESTS
+        })?),
+        None => None,
+    };
+
+    let content_length = request
+        .headers()
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+    let tripped = Arc::new(AtomicBool::new(false));
+    let request = request.map(|body| guard.guard_body(body, content_length, tripped.clone()));
+    let response = next.run(request).await;
+    if tripped.load(Ordering::Relaxed) {
+        return Err(StatusCode::REQUEST_TIMEOUT);
+    }
+    Ok(response)
+}
+
+/// Start `hop` within the request's deadline budget, failing with 504 when too
+/// little time is left for it; returns the hop's budget
+pub(super) fn start_hop(
+    deadline: Option<&RequestDeadline>,
+    hop: Hop,
+) -> std::result::Result<Option<Duration>, StatusCode> {
+    let Some(deadline) = deadline else {
+        return Ok(None);
+    };
+    deadline.start(hop, Instant::now()).map(Some).map_err(|e| {
+        log::warn!("Failing request fast: {}", e);
+        request_journal::record(JournalEvent::DeadlineExceeded { hop: hop.as_str() });
+        StatusCode::GATEWAY_TIMEOUT
+    })
+}
+
+/// Journal each request support may need to trace, and run it in the
+/// journal's scope so the handler and provider calls can record into it
+pub(super) async fn journal_middleware(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let path = request.uri().path();
+    if !state.request_journal.is_enabled()
+        || is_operational_path(path)
+        || path.starts_with("/v1/support")
+        || state.probes.is_probe(request.headers())
+    {
+        return next.run(request).await;
+    }
+    let route = request
+        .extensions()
+        .get::<axum::extract::MatchedPath>()
+        .map_or(path, |matched| matched.as_str())
+        .to_string();
+    let journal = state.request_journal.open(
+        tenant_id(request.headers()),
+        request.method().as_str(),
+        &route,
+    );
+
+    let mut response = request_journal::scope(journal.clone(), next.run(request)).await;
+    journal.finish(response.status().as_u16());
+    if !response.headers().contains_key(REQUEST_ID_HEADER) {
+        response.headers_mut().insert(
+            REQUEST_ID_HEADER,
+            journal.request_id().to_string().parse().unwrap(),
+        );
+    }
+    response
+}
+
+/// Time budget the client gives this request: `x-request-deadline-ms`, else the configured default
+pub(super) fn request_deadline(state: &ProxyState, headers: &HeaderMap) -> Result<Duration> {
+    if let Some(value) = headers.get("x-request-deadline-ms") {
+        let ms = value
+            .to_str()
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .ok_or_else(|| Error::Validation("Invalid x-request-deadline-ms".to_string()))?;
+        return Ok(Duration::from_millis(ms));
+    }
+    Ok(
+        match state.config.scaling.queue_projection.default_deadline_ms {
+            0 => Duration::from_secs(state.config.server.request_timeout_seconds),
+            ms => Duration::from_millis(ms),
+        },
+    )
+}
+
+/// Rejection carrying the hints clients need for adaptive backoff
+fn backoff_response(
+    status: StatusCode,
+    projection: &QueueProjection,
+    retry_after: u64,
+) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(axum::http::header::RETRY_AFTER, retry_after.into());
+    headers.insert("x-queue-depth", projection.queue_depth.into());
+    headers.insert("x-projected-wait-ms", projection.projected_wait_ms.into());
+    (status, headers).into_response()
+}
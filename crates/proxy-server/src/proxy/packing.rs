@@ -0,0 +1,161 @@
+//! Packing of short prompts into shared ciphertexts
+
+use super::ProxyState;
+use crate::error::{Error, Result};
+use crate::fhe::{Ciphertext, FheEngine, PackingOptimizer};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex, RwLock};
+use uuid::Uuid;
+
+/// Coalesces short prompts from one tenant that arrive within a window, so
+/// they share ciphertexts and a single homomorphic pass
+#[derive(Debug)]
+pub struct PromptPacker {
+    window: Duration,
+    max_batch: usize,
+    queues: Mutex<HashMap<String, Vec<PendingPrompt>>>,
+    prompts: AtomicU64,
+    passes: AtomicU64,
+}
+
+#[derive(Debug)]
+struct PendingPrompt {
+    ciphertext: Ciphertext,
+    reply: oneshot::Sender<Result<Ciphertext>>,
+}
+
+impl PromptPacker {
+    pub fn new(window: Duration, max_batch: usize) -> Self {
+        Self {
+            window,
+            max_batch,
+            queues: Mutex::new(HashMap::new()),
+            prompts: AtomicU64::new(0),
+            passes: AtomicU64::new(0),
+        }
+    }
+
+    /// Process a prompt in a shared ciphertext with whatever else the tenant
+    /// sends within the window. The first prompt of a window schedules the
+    /// flush; a full batch is flushed by the prompt that fills it.
+    pub async fn process(
+        state: &Arc<ProxyState>,
+        tenant: &str,
+        ciphertext: Ciphertext,
+    ) -> Result<Ciphertext> {
+        let packer = &state.prompt_packer;
+        let (reply, result) = oneshot::channel();
+
+        let full_batch = {
+            let mut queues = packer.queues.lock().await;
+            let queue = queues.entry(tenant.to_string()).or_default();
+            queue.push(PendingPrompt { ciphertext, reply });
+            if queue.len() == 1 {
+                let state = state.clone();
+                let tenant = tenant.to_string();
+                tokio::spawn(async move {
+                    tokio::time::sleep(state.prompt_packer.window).await;
+                    let batch = state.prompt_packer.take(&tenant).await;
+                    state.prompt_packer.flush(&state.fhe_engine, batch).await;
+                });
+                None
+            } else if queue.len() >= packer.max_batch {
+                Some(std::mem::take(queue))
+            } else {
+                None
+            }
+        };
+        if let Some(batch) = full_batch {
+            packer.flush(&state.fhe_engine, batch).await;
+        }
+
+        result
+            .await
+            .map_err(|_| Error::Internal("Packed prompt was dropped".to_string()))?
+    }
+
+    /// Prompts waiting for their window to close, across tenants
+    pub async fn queued(&self) -> usize {
+        self.queues.lock().await.values().map(Vec::len).sum()
+    }
+
+    async fn take(&self, tenant: &str) -> Vec<PendingPrompt> {
+        self.queues.lock().await.remove(tenant).unwrap_or_default()
+    }
+
+    async fn flush(&self, fhe_engine: &RwLock<FheEngine>, batch: Vec<PendingPrompt>) {
+        if batch.is_empty() {
+            return;
+        }
+        let fhe_engine = fhe_engine.read().await;
+        let optimizer = PackingOptimizer::new(fhe_engine.get_params().clone());
+
+        let (ciphertexts, replies): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .map(|pending| (pending.ciphertext, pending.reply))
+            .unzip();
+        let mut replies: HashMap<Uuid, Vec<_>> =
+            ciphertexts
+                .iter()
+                .zip(replies)
+                .fold(HashMap::new(), |mut replies, (ct, reply)| {
+                    replies.entry(ct.id).or_default().push(reply);
+                    replies
+                });
+
+        let packs = match optimizer.pack(&ciphertexts) {
+            Ok(packs) => packs,
+            Err(e) => {
+                log::warn!("Prompt packing failed: {}", e);
+                for reply in replies.into_values().flatten() {
+                    let _ = reply.send(Err(Error::Fhe(format!("Prompt packing failed: {}", e))));
+                }
+                return;
+            }
+        };
+
+        self.prompts
+            .fetch_add(ciphertexts.len() as u64, Ordering::Relaxed);
+        self.passes.fetch_add(packs.len() as u64, Ordering::Relaxed);
+        log::debug!(
+            "Packed {} prompts into {} ciphertexts",
+            ciphertexts.len(),
+            packs.len()
+        );
+
+        for pack in packs {
+            let results = fhe_engine
+                .process_encrypted_prompt(&pack.ciphertext)
+                .and_then(|processed| optimizer.unpack(&processed, &pack.slots));
+            match results {
+                Ok(results) => {
+                    for (slot, result) in pack.slots.iter().zip(results) {
+                        if let Some(reply) = replies.get_mut(&slot.source).and_then(Vec::pop) {
+                            let _ = reply.send(Ok(result));
+                        }
+                    }
+                }
+                Err(e) => {
+                    for slot in &pack.slots {
+                        if let Some(reply) = replies.get_mut(&slot.source).and_then(Vec::pop) {
+                            let _ = reply.send(Err(Error::Fhe(e.to_string())));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> serde_json::Value {
+        let prompts = self.prompts.load(Ordering::Relaxed);
+        let passes = self.passes.load(Ordering::Relaxed);
+        serde_json::json!({
+            "packed_prompts": prompts,
+            "homomorphic_passes": passes,
+            "prompts_per_pass": if passes == 0 { 0.0 } else { prompts as f64 / passes as f64 },
+        })
+    }
+}
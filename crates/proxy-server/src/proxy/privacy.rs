@@ -0,0 +1,131 @@
+//! Differential privacy budget endpoints
+
+use super::identity::admin_name;
+use super::{audit, ProxyState};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Request to manually top up a user's privacy budget
+#[derive(Debug, Deserialize)]
+pub struct TopUpRequest {
+    pub epsilon: f64,
+    #[serde(default)]
+    pub delta: f64,
+}
+
+/// Persist the current privacy budget of every tracked user
+pub(super) async fn flush_privacy_ledger(state: &ProxyState) {
+    for entry in state.privacy_tracker.snapshot_ledger().await {
+        if let Err(e) = state.store.put_ledger_entry(&entry) {
+            log::error!(
+                "Failed to persist privacy ledger for {}: {}",
+                entry.user_id,
+                e
+            );
+            return;
+        }
+    }
+}
+
+/// Get privacy budget for user
+pub(super) async fn get_privacy_budget(
+    State(state): State<Arc<ProxyState>>,
+    Path(user_id): Path<String>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let budget = state
+        .privacy_tracker
+        .get_budget_status(&user_id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(serde_json::json!({
+        "user_id": user_id,
+        "remaining_epsilon": budget.remaining_epsilon,
+        "remaining_delta": budget.remaining_delta,
+        "total_epsilon": budget.total_epsilon,
+        "consumed_percent": budget.consumed_percent(),
+        "total_queries": budget.queries_count,
+        "period_started_at": budget.period_started.to_rfc3339(),
+        "last_query_ago_seconds": budget.last_query.elapsed().as_secs()
+    })))
+}
+
+/// Reset privacy budget for user; admins only
+pub(super) async fn reset_privacy_budget(
+    State(state): State<Arc<ProxyState>>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    state
+        .privacy_tracker
+        .reset_budget(&user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    audit(
+        &state,
+        "privacy_budget.reset",
+        &user_id,
+        serde_json::json!({ "admin": admin }),
+    );
+
+    Ok(Json(serde_json::json!({
+        "user_id": user_id,
+        "status": "reset",
+        "timestamp": chrono::Utc::now().timestamp()
+    })))
+}
+
+/// Manually top up privacy budget for user; admins only
+pub(super) async fn top_up_privacy_budget(
+    State(state): State<Arc<ProxyState>>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<TopUpRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let budget = state
+        .privacy_tracker
+        .top_up(&user_id, request.epsilon, request.delta)
+        .await
+        .map_err(|e| {
+            log::warn!("Rejected privacy budget top-up for {}: {}", user_id, e);
+            StatusCode::BAD_REQUEST
+        })?;
+    audit(
+        &state,
+        "privacy_budget.top_up",
+        &user_id,
+        serde_json::json!({
+            "admin": admin,
+            "epsilon": request.epsilon,
+            "delta": request.delta,
+        }),
+    );
+
+    Ok(Json(serde_json::json!({
+        "user_id": user_id,
+        "status": "topped_up",
+        "remaining_epsilon": budget.remaining_epsilon,
+        "remaining_delta": budget.remaining_delta,
+        "timestamp": chrono::Utc::now().timestamp()
+    })))
+}
+
+/// Drain pending privacy budget threshold notifications for user
+pub(super) async fn get_privacy_budget_notifications(
+    State(state): State<Arc<ProxyState>>,
+    Path(user_id): Path<String>,
+) -> Json<serde_json::Value> {
+    let notifications = state.privacy_tracker.take_notifications(&user_id).await;
+
+    Json(serde_json::json!({
+        "user_id": user_id,
+        "notifications": notifications
+    }))
+}
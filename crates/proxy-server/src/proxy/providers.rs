@@ -0,0 +1,1408 @@
+//! LLM provider clients: dialects, recording and replay, resumption and timeout calibration
+
+use super::ProxyState;
+use crate::config::{
+    OpenAiCompatibleServer, ProviderAuth, ProviderErrorRetryConfig, ProviderQuotaConfig,
+    ProviderRecordingConfig, SlaClass, TimeoutCalibrationConfig, WarmHintsConfig,
+};
+use crate::error::{Error, Result};
+use crate::provider_errors::{
+    classify, ProviderDialect, ProviderErrorClass, ProviderErrorCounters, ProviderFailure,
+    RetryAction,
+};
+use crate::provider_quota::{ProviderQuota, QuotaStats, RateLimitReading};
+use crate::request_journal::{self, AttemptOutcome, JournalEvent};
+use crate::trace_sampling::{self};
+use crate::warm_hints::{self, WarmHintStats, WarmHints};
+use axum::{extract::State, http::StatusCode, response::Json};
+use reqwest::Client as HttpClient;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// LLM completion request
+#[derive(Debug, Clone, Serialize)]
+pub struct LlmRequest {
+    pub model: String,
+    pub messages: Vec<LlmMessage>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub stream: Option<bool>,
+}
+
+impl LlmRequest {
+    /// Deterministic, non-streaming calls can be re-issued and expected to
+    /// reproduce the same output
+    pub fn is_idempotent(&self) -> bool {
+        self.temperature == Some(0.0) && self.stream != Some(true)
+    }
+}
+
+/// LLM message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmMessage {
+    pub role: String,
+    pub content: String,
+}
+
+/// LLM completion response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmResponse {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<LlmChoice>,
+    pub usage: Option<LlmUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmChoice {
+    pub index: u32,
+    pub message: LlmMessage,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+/// One delta of a streamed completion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LlmStreamChunk {
+    pub content: String,
+    pub finish_reason: Option<String>,
+}
+
+/// A `chat.completion.chunk` server-sent event
+#[derive(Debug, Deserialize)]
+struct StreamEvent {
+    #[serde(default)]
+    choices: Vec<StreamEventChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamEventChoice {
+    #[serde(default)]
+    delta: StreamEventDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamEventDelta {
+    content: Option<String>,
+}
+
+/// How provider calls reach the network
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProviderMode {
+    Live,
+    /// Forward to the provider and store each response on disk
+    Record(PathBuf),
+    /// Serve stored responses only, never touching the network
+    Replay(PathBuf),
+}
+
+impl ProviderMode {
+    pub fn from_config(config: &ProviderRecordingConfig) -> Result<Self> {
+        match config.mode.as_str() {
+            "off" => Ok(ProviderMode::Live),
+            "record" => Ok(ProviderMode::Record(PathBuf::from(&config.directory))),
+            "replay" => Ok(ProviderMode::Replay(PathBuf::from(&config.directory))),
+            other => Err(Error::Config(format!(
+                "Unknown provider recording mode: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Recorded provider response; only a digest of the request is kept so no
+/// prompt content lands on disk
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub request_digest: String,
+    pub provider_url: String,
+    pub model: String,
+    pub recorded_at: i64,
+    pub response: LlmResponse,
+}
+
+/// Counters for re-issued provider calls
+#[derive(Debug, Default)]
+pub struct ResumeMetrics {
+    interrupted: AtomicU64,
+    resumed: AtomicU64,
+    failed: AtomicU64,
+    diverged: AtomicU64,
+    not_resumable: AtomicU64,
+    deduplicated_bytes: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResumeStats {
+    /// Connection resets seen on idempotent calls
+    pub interrupted: u64,
+    /// Calls that completed after at least one re-issue
+    pub resumed: u64,
+    /// Calls abandoned after exhausting their re-issues
+    pub failed: u64,
+    /// Re-issues whose output did not match the bytes already received
+    pub diverged: u64,
+    /// Resets on calls that were not safe to re-issue
+    pub not_resumable: u64,
+    /// Already-received bytes skipped while stitching re-issued responses
+    pub deduplicated_bytes: u64,
+    pub success_rate: f64,
+}
+
+impl ResumeMetrics {
+    pub fn snapshot(&self) -> ResumeStats {
+        let resumed = self.resumed.load(Ordering::Relaxed);
+        let failed = self.failed.load(Ordering::Relaxed);
+        ResumeStats {
+            interrupted: self.interrupted.load(Ordering::Relaxed),
+            resumed,
+            failed,
+            diverged: self.diverged.load(Ordering::Relaxed),
+            not_resumable: self.not_resumable.load(Ordering::Relaxed),
+            deduplicated_bytes: self.deduplicated_bytes.load(Ordering::Relaxed),
+            success_rate: if resumed + failed == 0 {
+                1.0
+            } else {
+                resumed as f64 / (resumed + failed) as f64
+            },
+        }
+    }
+}
+
+/// Response body assembled across re-issued attempts. Providers offer no
+/// continuation for non-streaming completions, so a re-issue replays the
+/// whole body; bytes matching what was already received are deduplicated and
+/// a mismatch means the re-issued output replaces the partial one.
+#[derive(Debug, Default)]
+struct StitchedBody {
+    bytes: Vec<u8>,
+    /// Position within the current attempt's body
+    offset: usize,
+    deduplicated: u64,
+    diverged: bool,
+}
+
+impl StitchedBody {
+    fn restart(&mut self) {
+        self.offset = 0;
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        let mut overlap = self
+            .bytes
+            .len()
+            .saturating_sub(self.offset)
+            .min(chunk.len());
+        if chunk[..overlap] != self.bytes[self.offset..self.offset + overlap] {
+            self.bytes.truncate(self.offset);
+            self.diverged = true;
+            overlap = 0;
+        }
+        self.deduplicated += overlap as u64;
+        self.bytes.extend_from_slice(&chunk[overlap..]);
+        self.offset += chunk.len();
+    }
+
+    /// Drop any stale tail left over from a longer interrupted attempt
+    fn finish(&mut self) {
+        if self.offset < self.bytes.len() {
+            self.bytes.truncate(self.offset);
+            self.diverged = true;
+        }
+    }
+}
+
+/// Whether a provider call failed because the connection was cut mid-flight
+fn is_connection_reset(error: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Per-model provider timeouts calibrated from rolling latency percentiles
+#[derive(Debug)]
+pub struct TimeoutCalibrator {
+    config: TimeoutCalibrationConfig,
+    /// Timeout used while calibration is off or has too few samples
+    fallback: Duration,
+    models: std::sync::Mutex<HashMap<String, ModelLatencies>>,
+}
+
+#[derive(Debug, Default)]
+struct ModelLatencies {
+    samples: VecDeque<Duration>,
+    timed_out: u64,
+}
+
+/// Currently applied timeout for one model
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibratedTimeout {
+    pub samples: usize,
+    pub percentile_latency_ms: Option<u64>,
+    pub timeout_ms: u64,
+    /// False while the fallback timeout applies
+    pub calibrated: bool,
+    pub timed_out: u64,
+}
+
+impl TimeoutCalibrator {
+    pub fn new(config: TimeoutCalibrationConfig, fallback: Duration) -> Self {
+        Self {
+            config,
+            fallback,
+            models: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record the latency of a successful call
+    pub fn record(&self, model: &str, latency: Duration) {
+        let mut models = self.models.lock().unwrap();
+        let latencies = models.entry(model.to_string()).or_default();
+        latencies.samples.push_back(latency);
+        while latencies.samples.len() > self.config.window_size {
+            latencies.samples.pop_front();
+        }
+    }
+
+    pub fn record_timeout(&self, model: &str) {
+        let mut models = self.models.lock().unwrap();
+        models.entry(model.to_string()).or_default().timed_out += 1;
+    }
+
+    pub fn timeout_for(&self, model: &str) -> Duration {
+        match self.models.lock().unwrap().get(model) {
+            Some(latencies) => Duration::from_millis(self.calibrate(latencies).timeout_ms),
+            None => self.fallback,
+        }
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, CalibratedTimeout> {
+        self.models
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(model, latencies)| (model.clone(), self.calibrate(latencies)))
+            .collect()
+    }
+
+    fn calibrate(&self, latencies: &ModelLatencies) -> CalibratedTimeout {
+        let samples = latencies.samples.len();
+        let percentile_latency = (samples > 0).then(|| {
+            let mut sorted: Vec<Duration> = latencies.samples.iter().copied().collect();
+            sorted.sort();
+            let index =
+                ((samples as f64 * self.config.percentile).ceil() as usize).clamp(1, samples);
+            sorted[index - 1]
+        });
+
+        let calibrated = self.config.enabled && samples >= self.config.min_samples;
+        let timeout = match percentile_latency {
+            Some(latency) if calibrated => {
+                let timeout_ms = latency.as_millis() as f64 * self.config.safety_margin;
+                (timeout_ms as u64).clamp(self.config.min_timeout_ms, self.config.max_timeout_ms)
+            }
+            _ => self.fallback.as_millis() as u64,
+        };
+
+        CalibratedTimeout {
+            samples,
+            percentile_latency_ms: percentile_latency.map(|l| l.as_millis() as u64),
+            timeout_ms: timeout,
+            calibrated,
+            timed_out: latencies.timed_out,
+        }
+    }
+}
+
+/// LLM provider client
+#[derive(Debug)]
+pub struct LlmProvider {
+    client: HttpClient,
+    auth: ProviderAuth,
+    base_url: String,
+    /// Per-model base URLs overriding `base_url`
+    model_urls: BTreeMap<String, String>,
+    mode: ProviderMode,
+    max_resumes: u32,
+    resume_backoff: Duration,
+    resume_metrics: ResumeMetrics,
+    timeouts: TimeoutCalibrator,
+    dialect: ProviderDialect,
+    error_retry: ProviderErrorRetryConfig,
+    max_error_retries: u32,
+    error_classes: ProviderErrorCounters,
+    quota: ProviderQuota,
+    warm_hints: WarmHints,
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// What a provider call is made on behalf of
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CallOptions {
+    /// Fail with a deadline-exceeded error rather than call past this
+    pub deadline: Option<Instant>,
+    /// The tenant's SLA class, selecting the warm hints sent along
+    pub sla_class: Option<SlaClass>,
+}
+
+impl LlmProvider {
+    pub fn new(provider: &str, api_key: String) -> Self {
+        let base_url = match provider {
+            "openai" => "https://api.openai.com/v1".to_string(),
+            "anthropic" => "https://api.anthropic.com/v1".to_string(),
+            url if url.starts_with("http") => url.to_string(),
+            _ => format!("https://api.{}.com/v1", provider),
+        };
+
+        Self {
+            client: HttpClient::new(),
+            auth: ProviderAuth::Bearer { token: api_key },
+            base_url,
+            model_urls: BTreeMap::new(),
+            mode: ProviderMode::Live,
+            max_resumes: 0,
+            resume_backoff: Duration::ZERO,
+            resume_metrics: ResumeMetrics::default(),
+            timeouts: TimeoutCalibrator::new(
+                TimeoutCalibrationConfig {
+                    enabled: false,
+                    ..TimeoutCalibrationConfig::default()
+                },
+                Duration::from_secs(300),
+            ),
+            dialect: ProviderDialect::for_provider(provider),
+            error_retry: ProviderErrorRetryConfig {
+                enabled: false,
+                ..ProviderErrorRetryConfig::default()
+            },
+            max_error_retries: 0,
+            error_classes: ProviderErrorCounters::default(),
+            quota: ProviderQuota::new(ProviderQuotaConfig {
+                enabled: false,
+                ..ProviderQuotaConfig::default()
+            }),
+            warm_hints: WarmHints::disabled(),
+            succeeded: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        }
+    }
+
+    /// A self-hosted server speaking the OpenAI API, e.g. vLLM or TGI
+    pub fn openai_compatible(server: &OpenAiCompatibleServer) -> Self {
+        let mut provider = Self::new(server.base_url.trim_end_matches('/'), String::new());
+        provider.auth = server.auth.clone();
+        provider.model_urls = server
+            .model_urls
+            .iter()
+            .map(|(model, url)| (model.clone(), url.trim_end_matches('/').to_string()))
+            .collect();
+        provider.dialect = ProviderDialect::OpenAi;
+        provider
+    }
+
+    pub fn with_mode(mut self, mode: ProviderMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Re-issue idempotent calls cut off by a connection reset up to `max_resumes` times
+    pub fn with_resume(mut self, max_resumes: u32, backoff: Duration) -> Self {
+        self.max_resumes = max_resumes;
+        self.resume_backoff = backoff;
+        self
+    }
+
+    /// Time out calls after `fallback`, or a calibrated per-model timeout once enough
+    /// latency samples exist
+    pub fn with_timeouts(
+        mut self,
+        fallback: Duration,
+        calibration: TimeoutCalibrationConfig,
+    ) -> Self {
+        self.timeouts = TimeoutCalibrator::new(calibration, fallback);
+        self
+    }
+
+    /// Retry error responses classified as retryable up to `max_retries` times
+    pub fn with_error_retry(mut self, max_retries: u32, config: ProviderErrorRetryConfig) -> Self {
+        self.max_error_retries = if config.enabled { max_retries } else { 0 };
+        self.error_retry = config;
+        self
+    }
+
+    /// Track this key's rate-limit budgets and hold calls back before they run out
+    pub fn with_quota(mut self, config: ProviderQuotaConfig) -> Self {
+        self.quota = ProviderQuota::new(config);
+        self
+    }
+
+    /// Send the warm-pool and priority hints `config` sets for the provider
+    /// named `name` with calls, by the caller's SLA class
+    pub fn with_warm_hints(mut self, config: &WarmHintsConfig, name: &str) -> Self {
+        self.warm_hints = WarmHints::new(config, name);
+        self
+    }
+
+    /// Cold-start latency with and without warm hints, by model
+    pub fn warm_hint_stats(&self) -> HashMap<String, WarmHintStats> {
+        self.warm_hints.stats()
+    }
+
+    /// Remaining request and token budgets as last reported by the provider
+    pub fn quota_stats(&self) -> QuotaStats {
+        self.quota.stats()
+    }
+
+    /// Failed calls by error class
+    pub fn error_stats(&self) -> HashMap<&'static str, u64> {
+        self.error_classes.snapshot()
+    }
+
+    /// Where calls to the provider go
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Where calls for `model` go
+    pub fn url_for(&self, model: &str) -> &str {
+        self.model_urls
+            .get(model)
+            .map(String::as_str)
+            .unwrap_or(&self.base_url)
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.auth {
+            ProviderAuth::None => request,
+            ProviderAuth::Bearer { token } => {
+                request.header("Authorization", format!("Bearer {}", token))
+            }
+            ProviderAuth::Header { name, value } => request.header(name.as_str(), value.as_str()),
+        }
+    }
+
+    /// List the provider's models on every base URL it uses to check it is
+    /// reachable and accepts the key. Replayed providers never touch the
+    /// network and always pass.
+    pub async fn probe(&self, timeout: Duration) -> Result<()> {
+        if matches!(self.mode, ProviderMode::Replay(_)) {
+            return Ok(());
+        }
+        let urls: BTreeSet<&str> = std::iter::once(self.base_url.as_str())
+            .chain(self.model_urls.values().map(String::as_str))
+            .collect();
+        for url in urls {
+            let response = self
+                .authorize(self.client.get(format!("{}/models", url)))
+                .timeout(timeout)
+                .send()
+                .await?;
+            let status = response.status();
+            self.quota.observe(RateLimitReading::from_headers(
+                response.headers(),
+                chrono::Utc::now(),
+            ));
+            // Rate limited still means reachable and authenticated
+            if !status.is_success() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Err(Error::Provider(format!(
+                    "Model listing at {} returned {}",
+                    url, status
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn resume_stats(&self) -> ResumeStats {
+        self.resume_metrics.snapshot()
+    }
+
+    pub fn timeout_stats(&self) -> HashMap<String, CalibratedTimeout> {
+        self.timeouts.snapshot()
+    }
+
+    /// Live calls since startup as (succeeded, failed)
+    pub fn call_counts(&self) -> (u64, u64) {
+        (
+            self.succeeded.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+        )
+    }
+
+    pub async fn complete(&self, request: LlmRequest) -> Result<LlmResponse> {
+        self.complete_with(request, CallOptions::default()).await
+    }
+
+    pub async fn complete_with(
+        &self,
+        request: LlmRequest,
+        options: CallOptions,
+    ) -> Result<LlmResponse> {
+        let deadline = options.deadline;
+        let digest = self.request_digest(&request)?;
+
+        if let ProviderMode::Replay(dir) = &self.mode {
+            return self.replay(dir, &digest).await;
+        }
+
+        let model = request.model.clone();
+        let tokens = self.estimate_tokens(&request);
+        let mut retries = 0;
+        let response = loop {
+            self.quota.acquire(&self.base_url, tokens).await?;
+            let error = match self.send(&request, options).await {
+                Ok(response) => {
+                    self.succeeded.fetch_add(1, Ordering::Relaxed);
+                    break response;
+                }
+                Err(e) => e,
+            };
+            // Running out of the caller's time says nothing about the provider
+            if matches!(error, Error::DeadlineExceeded(_)) {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                return Err(error);
+            }
+            self.error_classes.record(match &error {
+                Error::ProviderFailure(failure) => failure.class,
+                Error::Timeout(_) => ProviderErrorClass::Timeout,
+                _ => ProviderErrorClass::Transient,
+            });
+
+            let delay = match &error {
+                Error::ProviderFailure(failure)
+                    if failure.class.action() == RetryAction::Retry
+                        && retries < self.max_error_retries =>
+                {
+                    let backoff =
+                        Duration::from_millis(self.error_retry.backoff_ms) * 2u32.pow(retries);
+                    Some(failure.retry_after.unwrap_or(backoff))
+                        .filter(|delay| {
+                            delay.as_millis() <= self.error_retry.max_backoff_ms as u128
+                        })
+                        .filter(|delay| deadline.is_none_or(|d| Instant::now() + *delay < d))
+                }
+                _ => None,
+            };
+            let Some(delay) = delay else {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                return Err(error);
+            };
+            retries += 1;
+            if let Error::ProviderFailure(failure) = &error {
+                request_journal::record(JournalEvent::ProviderRetry {
+                    retry: retries,
+                    class: failure.class,
+                    delay_ms: delay.as_millis() as u64,
+                });
+            }
+            log::warn!(
+                "Retrying {} in {}ms (attempt {}/{}): {}",
+                model,
+                delay.as_millis(),
+                retries,
+                self.max_error_retries,
+                error
+            );
+            tokio::time::sleep(delay).await;
+        };
+
+        if let ProviderMode::Record(dir) = &self.mode {
+            // A failed recording must not fail the live request
+            if let Err(e) = self.record(dir, &digest, model, &response).await {
+                log::warn!("Failed to record provider response {}: {}", digest, e);
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Stream a completion as it is generated. Deltas arrive on the returned
+    /// channel until the provider finishes; a failure after the stream has
+    /// started arrives as the last item. Streams are neither retried nor
+    /// recorded, and a replayed provider sends the recorded response whole.
+    pub async fn complete_streaming(
+        &self,
+        request: LlmRequest,
+    ) -> Result<mpsc::Receiver<Result<LlmStreamChunk>>> {
+        self.complete_streaming_with(request, CallOptions::default())
+            .await
+    }
+
+    pub async fn complete_streaming_with(
+        &self,
+        mut request: LlmRequest,
+        options: CallOptions,
+    ) -> Result<mpsc::Receiver<Result<LlmStreamChunk>>> {
+        let (sender, receiver) = mpsc::channel(64);
+        if let ProviderMode::Replay(_) = &self.mode {
+            request.stream = None;
+            let response = self.complete_with(request, options).await?;
+            for choice in response.choices {
+                let _ = sender
+                    .send(Ok(LlmStreamChunk {
+                        content: choice.message.content,
+                        finish_reason: choice.finish_reason,
+                    }))
+                    .await;
+            }
+            return Ok(receiver);
+        }
+
+        request.stream = Some(true);
+        self.quota
+            .acquire(&self.base_url, self.estimate_tokens(&request))
+            .await?;
+        let url = format!("{}/chat/completions", self.url_for(&request.model));
+        // The timeout covers the wait for the response head; a long stream
+        // is not cut off
+        let (timeout, capped) = self.attempt_timeout(&request.model, options.deadline)?;
+        let hint = self.warm_hints.hint_for(options.sla_class);
+        let (body, hint_headers) = warm_hints::apply(&request, hint)?;
+        let started = Instant::now();
+        let cold = self.warm_hints.start(&request.model, started);
+        let sent = self
+            .authorize(self.client.post(&url))
+            .header("Content-Type", "application/json")
+            .header("Accept", "text/event-stream")
+            .headers(trace_sampling::propagation_headers())
+            .headers(hint_headers)
+            .json(&body)
+            .send();
+        let mut response = match tokio::time::timeout(timeout, sent).await {
+            Ok(Ok(response)) => {
+                let status = Some(response.status().as_u16());
+                // A stream's cold start ends with its response head
+                if cold && response.status().is_success() {
+                    self.warm_hints.record_cold_start(
+                        &request.model,
+                        hint.is_some(),
+                        started.elapsed(),
+                    );
+                }
+                journal_attempt(
+                    &url,
+                    &request.model,
+                    AttemptOutcome::Responded,
+                    status,
+                    started,
+                );
+                response
+            }
+            Ok(Err(e)) => {
+                journal_attempt(&url, &request.model, AttemptOutcome::Failed, None, started);
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                return Err(e.into());
+            }
+            Err(_) => {
+                let outcome = match capped {
+                    true => AttemptOutcome::DeadlineExceeded,
+                    false => AttemptOutcome::TimedOut,
+                };
+                journal_attempt(&url, &request.model, outcome, None, started);
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                let message = format!("No response from {} within {}ms", url, timeout.as_millis());
+                if capped {
+                    return Err(Error::DeadlineExceeded(message));
+                }
+                self.timeouts.record_timeout(&request.model);
+                return Err(Error::Timeout(message));
+            }
+        };
+
+        let status = response.status();
+        self.quota.observe(RateLimitReading::from_headers(
+            response.headers(),
+            chrono::Utc::now(),
+        ));
+        if !status.is_success() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+            let body = response.bytes().await.unwrap_or_default();
+            let class = classify(self.dialect, status.as_u16(), &body);
+            self.error_classes.record(class);
+            return Err(Error::ProviderFailure(ProviderFailure {
+                status: status.as_u16(),
+                class,
+                retry_after: None,
+                message: String::from_utf8_lossy(&body).into_owned(),
+            }));
+        }
+        self.succeeded.fetch_add(1, Ordering::Relaxed);
+
+        tokio::spawn(async move {
+            let mut pending = Vec::new();
+            loop {
+                let chunk = match response.chunk().await {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => return,
+                    Err(e) => {
+                        let _ = sender.send(Err(e.into())).await;
+                        return;
+                    }
+                };
+                pending.extend_from_slice(&chunk);
+                while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=end).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                        continue;
+                    };
+                    if data == "[DONE]" {
+                        return;
+                    }
+                    let event: StreamEvent = match serde_json::from_str(data) {
+                        Ok(event) => event,
+                        Err(e) => {
+                            let _ = sender.send(Err(e.into())).await;
+                            return;
+                        }
+                    };
+                    for choice in event.choices {
+                        let chunk = LlmStreamChunk {
+                            content: choice.delta.content.unwrap_or_default(),
+                            finish_reason: choice.finish_reason,
+                        };
+                        if sender.send(Ok(chunk)).await.is_err() {
+                            // The caller went away; dropping the response
+                            // closes the connection
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
+    /// Tokens a call is charged against the token budget: roughly four bytes
+    /// per prompt token, plus the completion allowance
+    fn estimate_tokens(&self, request: &LlmRequest) -> u64 {
+        let prompt_bytes: usize = request.messages.iter().map(|m| m.content.len()).sum();
+        let completion = request
+            .max_tokens
+            .map(u64::from)
+            .unwrap_or(self.quota.config().default_completion_tokens);
+        prompt_bytes.div_ceil(4) as u64 + completion
+    }
+
+    /// SHA-256 over the provider URL and the serialized request
+    fn request_digest(&self, request: &LlmRequest) -> Result<String> {
+        let mut context = digest::Context::new(&digest::SHA256);
+        context.update(self.base_url.as_bytes());
+        context.update(&serde_json::to_vec(request)?);
+        Ok(context
+            .finish()
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+
+    async fn replay(&self, dir: &std::path::Path, digest: &str) -> Result<LlmResponse> {
+        let path = dir.join(format!("{}.json", digest));
+        let content = tokio::fs::read(&path).await.map_err(|_| {
+            Error::Provider(format!(
+                "No recorded response for request digest {} in {}",
+                digest,
+                dir.display()
+            ))
+        })?;
+        let recorded: RecordedResponse = serde_json::from_slice(&content)?;
+
+        log::debug!("Replaying recorded provider response {}", digest);
+        Ok(recorded.response)
+    }
+
+    async fn record(
+        &self,
+        dir: &std::path::Path,
+        digest: &str,
+        model: String,
+        response: &LlmResponse,
+    ) -> Result<()> {
+        tokio::fs::create_dir_all(dir).await?;
+        let recorded = RecordedResponse {
+            request_digest: digest.to_string(),
+            provider_url: self.base_url.clone(),
+            model,
+            recorded_at: chrono::Utc::now().timestamp(),
+            response: response.clone(),
+        };
+        tokio::fs::write(
+            dir.join(format!("{}.json", digest)),
+            serde_json::to_vec_pretty(&recorded)?,
+        )
+        .await?;
+
+        log::debug!("Recorded provider response {}", digest);
+        Ok(())
+    }
+
+    /// Timeout for the next call for `model`: the calibrated one, capped at the
+    /// time left before `deadline`. Also returns whether the cap applied.
+    fn attempt_timeout(&self, model: &str, deadline: Option<Instant>) -> Result<(Duration, bool)> {
+        let timeout = self.timeouts.timeout_for(model);
+        let Some(deadline) = deadline else {
+            return Ok((timeout, false));
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::DeadlineExceeded(format!(
+                "No time left to call {} for {}",
+                self.base_url, model
+            )));
+        }
+        Ok((timeout.min(remaining), remaining < timeout))
+    }
+
+    async fn send(&self, request: &LlmRequest, options: CallOptions) -> Result<LlmResponse> {
+        let url = format!("{}/chat/completions", self.url_for(&request.model));
+        let resumable = request.is_idempotent();
+        let hint = self.warm_hints.hint_for(options.sla_class);
+        let (payload, hint_headers) = warm_hints::apply(request, hint)?;
+
+        log::debug!("Sending request to LLM provider: {}", url);
+
+        let mut body = StitchedBody::default();
+        let mut attempts = 0;
+        let (status, retry_after) = loop {
+            body.restart();
+            let (timeout, capped) = self.attempt_timeout(&request.model, options.deadline)?;
+            let started = Instant::now();
+            let cold = self.warm_hints.start(&request.model, started);
+            let result = self
+                .fetch(&url, &payload, &hint_headers, &mut body, timeout)
+                .await;
+            let (outcome, status) = match &result {
+                Ok((status, _)) => (AttemptOutcome::Responded, Some(status.as_u16())),
+                Err(e) if e.is_timeout() && capped => (AttemptOutcome::DeadlineExceeded, None),
+                Err(e) if e.is_timeout() => (AttemptOutcome::TimedOut, None),
+                Err(e) if is_connection_reset(e) => (AttemptOutcome::ConnectionReset, None),
+                Err(_) => (AttemptOutcome::Failed, None),
+            };
+            journal_attempt(&url, &request.model, outcome, status, started);
+            match result {
+                Ok((status, retry_after)) => {
+                    if status.is_success() {
+                        self.timeouts.record(&request.model, started.elapsed());
+                        if cold {
+                            self.warm_hints.record_cold_start(
+                                &request.model,
+                                hint.is_some(),
+                                started.elapsed(),
+                            );
+                        }
+                    }
+                    break (status, retry_after);
+                }
+                Err(e) if e.is_timeout() => {
+                    if attempts > 0 {
+                        self.resume_metrics.failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // Cut short by the caller's deadline; not a sign the model is slow
+                    if capped {
+                        return Err(Error::DeadlineExceeded(format!(
+                            "Provider call for {} ran out of its {}ms budget",
+                            request.model,
+                            timeout.as_millis()
+                        )));
+                    }
+                    self.timeouts.record_timeout(&request.model);
+                    log::warn!(
+                        "Provider call for {} timed out after {}ms",
+                        request.model,
+                        timeout.as_millis()
+                    );
+                    return Err(Error::Timeout(e.to_string()));
+                }
+                Err(e) if is_connection_reset(&e) && !resumable => {
+                    self.resume_metrics
+                        .not_resumable
+                        .fetch_add(1, Ordering::Relaxed);
+                    return Err(e.into());
+                }
+                Err(e) if is_connection_reset(&e) && attempts < self.max_resumes => {
+                    attempts += 1;
+                    self.resume_metrics
+                        .interrupted
+                        .fetch_add(1, Ordering::Relaxed);
+                    log::warn!(
+                        "Provider connection reset after {} bytes, re-issuing (attempt {}/{})",
+                        body.bytes.len(),
+                        attempts,
+                        self.max_resumes
+                    );
+                    tokio::time::sleep(self.resume_backoff * attempts).await;
+                }
+                Err(e) => {
+                    if attempts > 0 {
+                        self.resume_metrics.failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Err(e.into());
+                }
+            }
+        };
+        body.finish();
+
+        if attempts > 0 {
+            self.resume_metrics.resumed.fetch_add(1, Ordering::Relaxed);
+            self.resume_metrics
+                .deduplicated_bytes
+                .fetch_add(body.deduplicated, Ordering::Relaxed);
+            if body.diverged {
+                self.resume_metrics.diverged.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        if !status.is_success() {
+            return Err(Error::ProviderFailure(ProviderFailure {
+                status: status.as_u16(),
+                class: classify(self.dialect, status.as_u16(), &body.bytes),
+                retry_after,
+                message: String::from_utf8_lossy(&body.bytes).into_owned(),
+            }));
+        }
+
+        serde_json::from_slice(&body.bytes).map_err(Error::from)
+    }
+
+    /// One round trip, streaming the response body into `body`; returns the
+    /// status and any `Retry-After` delay (in seconds form) the provider asked for
+    async fn fetch(
+        &self,
+        url: &str,
+        payload: &serde_json::Value,
+        hint_headers: &reqwest::header::HeaderMap,
+        body: &mut StitchedBody,
+        timeout: Duration,
+    ) -> std::result::Result<(reqwest::StatusCode, Option<Duration>), reqwest::Error> {
+        let mut response = self
+            .authorize(self.client.post(url))
+            .header("Content-Type", "application/json")
+            .headers(trace_sampling::propagation_headers())
+            .headers(hint_headers.clone())
+            .json(payload)
+            .timeout(timeout)
+            .send()
+            .await?;
+
+        let status = response.status();
+        self.quota.observe(RateLimitReading::from_headers(
+            response.headers(),
+            chrono::Utc::now(),
+        ));
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        while let Some(chunk) = response.chunk().await? {
+            body.push(&chunk);
+        }
+        Ok((status, retry_after))
+    }
+}
+
+/// Journal one round trip to a provider, naming it by host alone so no
+/// credentials in its URL reach the journal
+fn journal_attempt(
+    url: &str,
+    model: &str,
+    outcome: AttemptOutcome,
+    status: Option<u16>,
+    started: Instant,
+) {
+    request_journal::record(JournalEvent::ProviderAttempt {
+        host: reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string)),
+        model: model.to_string(),
+        outcome,
+        status,
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+}
+
+/// Complete with the first provider in `order` that takes the call. A failure
+/// moves on to the next provider unless its class means none would accept the
+/// request as sent, or the request's deadline has run out.
+pub async fn complete_with_fallback(
+    providers: &HashMap<String, LlmProvider>,
+    order: &[String],
+    request: &LlmRequest,
+    options: CallOptions,
+) -> Result<(String, LlmResponse)> {
+    let mut last_error = None;
+    for name in order {
+        let Some(provider) = providers.get(name) else {
+            continue;
+        };
+        match provider.complete_with(request.clone(), options).await {
+            Ok(response) => return Ok((name.clone(), response)),
+            Err(Error::ProviderFailure(failure)) if failure.class.action() == RetryAction::Fail => {
+                return Err(Error::ProviderFailure(failure));
+            }
+            Err(e @ Error::DeadlineExceeded(_)) => return Err(e),
+            Err(e) => {
+                log::warn!("Provider {} failed, falling back: {}", name, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        Error::Provider(format!("None of the providers {:?} is configured", order))
+    }))
+}
+
+/// Status a completion is failed with when no provider completed it
+pub(super) fn provider_error_status(error: &Error) -> StatusCode {
+    match error {
+        Error::DeadlineExceeded(_) | Error::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        // Held back by our own quota reserve
+        Error::RateLimit(_) => StatusCode::SERVICE_UNAVAILABLE,
+        Error::ProviderFailure(failure) => match failure.class {
+            ProviderErrorClass::RateLimited
+            | ProviderErrorClass::QuotaExhausted
+            | ProviderErrorClass::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            _ if failure.class.action() == RetryAction::Fail => StatusCode::BAD_REQUEST,
+            _ => StatusCode::BAD_GATEWAY,
+        },
+        _ => StatusCode::BAD_GATEWAY,
+    }
+}
+
+/// Remaining provider quota gauges, by provider
+pub(super) fn provider_quotas(state: &ProxyState) -> serde_json::Value {
+    state
+        .llm_providers
+        .iter()
+        .map(|(name, provider)| {
+            (
+                name.clone(),
+                serde_json::to_value(provider.quota_stats()).unwrap(),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Provider headroom for capacity planning
+pub(super) async fn get_provider_quotas(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(provider_quotas(&state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn sample_request() -> LlmRequest {
+        LlmRequest {
+            model: "gpt-4".to_string(),
+            messages: vec![LlmMessage {
+                role: "user".to_string(),
+                content: "ping".to_string(),
+            }],
+            temperature: None,
+            max_tokens: Some(16),
+            stream: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provider_replay_mode() {
+        let dir = std::env::temp_dir().join(format!("fhe-recordings-{}", Uuid::new_v4()));
+        let provider =
+            LlmProvider::new("openai", String::new()).with_mode(ProviderMode::Replay(dir.clone()));
+
+        // Nothing recorded yet: replay fails without touching the network
+        assert!(provider.complete(sample_request()).await.is_err());
+
+        let response = LlmResponse {
+            id: "rec-1".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "gpt-4".to_string(),
+            choices: vec![],
+            usage: None,
+        };
+        let digest = provider.request_digest(&sample_request()).unwrap();
+        provider
+            .record(&dir, &digest, "gpt-4".to_string(), &response)
+            .await
+            .unwrap();
+
+        let replayed = provider.complete(sample_request()).await.unwrap();
+        assert_eq!(replayed.id, "rec-1");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_provider_resumes_after_connection_reset() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let body = serde_json::to_vec(&LlmResponse {
+            id: "resumed-1".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "gpt-4".to_string(),
+            choices: vec![],
+            usage: None,
+        })
+        .unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            // The first two connections are cut halfway through the body
+            for cut in [true, true, false] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 8192];
+                let _ = socket.read(&mut buf).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                let sent = if cut { body.len() / 2 } else { body.len() };
+                socket.write_all(&body[..sent]).await.unwrap();
+                socket.flush().await.unwrap();
+            }
+        });
+
+        let provider = LlmProvider::new(&format!("http://{}", addr), String::new())
+            .with_resume(2, Duration::from_millis(1));
+
+        // Non-deterministic calls are not re-issued
+        assert!(provider.complete(sample_request()).await.is_err());
+        assert_eq!(provider.resume_stats().not_resumable, 1);
+
+        let mut request = sample_request();
+        request.temperature = Some(0.0);
+        let response = provider.complete(request).await.unwrap();
+        assert_eq!(response.id, "resumed-1");
+
+        let stats = provider.resume_stats();
+        assert_eq!(stats.resumed, 1);
+        assert_eq!(stats.diverged, 0);
+        assert!(stats.deduplicated_bytes > 0);
+    }
+
+    /// Serve one canned HTTP response per connection, in order
+    async fn serve_responses(responses: Vec<(u16, String)>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 8192];
+                let _ = socket.read(&mut buf).await;
+                let head = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nRetry-After: 0\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(body.as_bytes()).await.unwrap();
+                socket.flush().await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_classification_drives_retry_and_fallback() {
+        let success = serde_json::to_string(&LlmResponse {
+            id: "fallback-1".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "gpt-4".to_string(),
+            choices: vec![],
+            usage: None,
+        })
+        .unwrap();
+        let openai_error = |code: &str| {
+            serde_json::json!({ "error": { "message": code, "type": code, "code": code } })
+                .to_string()
+        };
+
+        // A rate limit is retried; an exhausted quota moves on to the next provider
+        let primary = serve_responses(vec![
+            (429, openai_error("rate_limit_exceeded")),
+            (429, openai_error("insufficient_quota")),
+            (400, openai_error("content_policy_violation")),
+        ])
+        .await;
+        let secondary = serve_responses(vec![(200, success)]).await;
+        let retry = ProviderErrorRetryConfig {
+            backoff_ms: 1,
+            ..ProviderErrorRetryConfig::default()
+        };
+        let providers = HashMap::from([
+            (
+                "primary".to_string(),
+                LlmProvider::new(&primary, String::new()).with_error_retry(2, retry.clone()),
+            ),
+            (
+                "secondary".to_string(),
+                LlmProvider::new(&secondary, String::new()).with_error_retry(2, retry),
+            ),
+        ]);
+        let order = ["primary".to_string(), "secondary".to_string()];
+
+        let (used, response) = complete_with_fallback(
+            &providers,
+            &order,
+            &sample_request(),
+            CallOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(used, "secondary");
+        assert_eq!(response.id, "fallback-1");
+        let errors = providers["primary"].error_stats();
+        assert_eq!(errors["rate_limited"], 1);
+        assert_eq!(errors["quota_exhausted"], 1);
+
+        // A content policy refusal is not routed around
+        let error = complete_with_fallback(
+            &providers,
+            &order,
+            &sample_request(),
+            CallOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ProviderFailure(ProviderFailure {
+                class: ProviderErrorClass::ContentPolicy,
+                status: 400,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_provider_calls_stop_at_the_request_deadline() {
+        // A provider that accepts the connection and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hanging = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        let fallback = serve_responses(vec![(200, String::new())]).await;
+        let providers = HashMap::from([
+            (
+                "hanging".to_string(),
+                LlmProvider::new(&hanging, String::new()),
+            ),
+            (
+                "fallback".to_string(),
+                LlmProvider::new(&fallback, String::new()),
+            ),
+        ]);
+        let order = ["hanging".to_string(), "fallback".to_string()];
+
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(200);
+        let options = CallOptions {
+            deadline: Some(deadline),
+            ..CallOptions::default()
+        };
+        let error = complete_with_fallback(&providers, &order, &sample_request(), options)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::DeadlineExceeded(_)), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // Neither blamed on the provider's latency nor routed to the next one
+        assert!(providers["hanging"].timeout_stats().is_empty());
+        assert_eq!(providers["fallback"].call_counts(), (0, 0));
+
+        let options = CallOptions {
+            deadline: Some(started),
+            ..CallOptions::default()
+        };
+        let error = providers["fallback"]
+            .complete_with(sample_request(), options)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::DeadlineExceeded(_)));
+    }
+
+    #[test]
+    fn test_timeout_calibration() {
+        let calibrator = TimeoutCalibrator::new(
+            TimeoutCalibrationConfig {
+                percentile: 0.9,
+                safety_margin: 2.0,
+                min_timeout_ms: 1000,
+                max_timeout_ms: 10_000,
+                window_size: 10,
+                min_samples: 5,
+                ..TimeoutCalibrationConfig::default()
+            },
+            Duration::from_secs(30),
+        );
+
+        // The static timeout applies until enough samples exist
+        for ms in [100, 200, 300, 400] {
+            calibrator.record("gpt-4", Duration::from_millis(ms));
+        }
+        assert_eq!(calibrator.timeout_for("gpt-4"), Duration::from_secs(30));
+        assert_eq!(calibrator.timeout_for("unseen"), Duration::from_secs(30));
+
+        // p90 of 100..=1000ms is 900ms, doubled
+        for ms in [500, 600, 700, 800, 900, 1000] {
+            calibrator.record("gpt-4", Duration::from_millis(ms));
+        }
+        assert_eq!(calibrator.timeout_for("gpt-4"), Duration::from_millis(1800));
+
+        // Bounded by the configured range, with only the newest window kept
+        for _ in 0..10 {
+            calibrator.record("gpt-4", Duration::from_millis(50));
+            calibrator.record("slow-model", Duration::from_secs(20));
+        }
+        calibrator.record_timeout("slow-model");
+        assert_eq!(calibrator.timeout_for("gpt-4"), Duration::from_millis(1000));
+        let stats = calibrator.snapshot();
+        assert_eq!(stats["gpt-4"].samples, 10);
+        assert_eq!(stats["slow-model"].timeout_ms, 10_000);
+        assert_eq!(stats["slow-model"].timed_out, 1);
+        assert!(stats["slow-model"].calibrated);
+    }
+}
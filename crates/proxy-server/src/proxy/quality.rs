@@ -0,0 +1,54 @@
+//! Quality scores of encrypted model output
+
+use super::{tenant_id, ProxyState};
+use crate::quality::QualitySignal;
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// A client's score of a completion, identified by its `x-request-id`
+#[derive(Debug, Deserialize)]
+pub struct QualityScoreRequest {
+    pub request_id: Uuid,
+    #[serde(flatten)]
+    pub signal: QualitySignal,
+}
+
+/// Score a completion served to the calling tenant
+pub(super) async fn submit_quality_score(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<QualityScoreRequest>,
+) -> StatusCode {
+    if !state.quality.is_enabled() {
+        return StatusCode::NOT_FOUND;
+    }
+    match state.quality.submit(
+        request.request_id,
+        tenant_id(&headers),
+        request.signal,
+        chrono::Utc::now().timestamp(),
+    ) {
+        Ok(Some(_)) => StatusCode::ACCEPTED,
+        Ok(None) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            log::debug!("Rejected quality score: {}", e);
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+/// Scores per model, template and provider, and which models are downranked
+pub(super) async fn get_quality_report(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.quality.is_enabled(),
+        "report": state.quality.report(chrono::Utc::now().timestamp()),
+    }))
+}
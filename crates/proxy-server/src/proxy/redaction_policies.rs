@@ -0,0 +1,127 @@
+//! Signed redaction policies for decrypted output
+
+use super::identity::admin_name;
+use super::{audit, tenant_id, ProxyState};
+use crate::redaction::RedactionPolicy;
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Version of a tenant's redaction policy to fetch; defaults to the current one
+#[derive(Debug, Deserialize)]
+pub struct RedactionPolicyQuery {
+    pub version: Option<u64>,
+}
+
+/// A client confirming the redaction policy version it enforces
+#[derive(Debug, Deserialize)]
+pub struct RedactionAckRequest {
+    pub client_id: Uuid,
+    pub version: u64,
+}
+
+/// Version, sign and publish a tenant's redaction policy; admins only
+pub(super) async fn publish_redaction_policy(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+    headers: HeaderMap,
+    Json(policy): Json<RedactionPolicy>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if !state.redaction_policies.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let signer = state
+        .response_signer
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let signed = state
+        .redaction_policies
+        .publish(&tenant, policy, signer)
+        .map_err(|e| {
+            log::warn!("Rejected redaction policy for tenant {}: {}", tenant, e);
+            StatusCode::BAD_REQUEST
+        })?;
+    audit(
+        &state,
+        "redaction_policy.publish",
+        &tenant,
+        serde_json::json!({
+            "admin": admin,
+            "version": signed.bundle.version,
+            "rules": signed.bundle.policy.rules.len(),
+            "key_id": signed.key_id
+        }),
+    );
+
+    Ok(Json(serde_json::to_value(signed).unwrap()))
+}
+
+/// Signed redaction policy bundle for the caller's tenant
+pub(super) async fn get_redaction_policy(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Query(query): Query<RedactionPolicyQuery>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let tenant = tenant_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let signed = state
+        .redaction_policies
+        .bundle(tenant, query.version)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::to_value(signed).unwrap()))
+}
+
+/// Record that a client enforces a redaction policy version
+pub(super) async fn acknowledge_redaction_policy(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(request): Json<RedactionAckRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let tenant = tenant_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+    let acknowledgment = state
+        .redaction_policies
+        .acknowledge(tenant, request.client_id, request.version)
+        .map_err(|e| {
+            log::warn!("Rejected redaction policy acknowledgment: {}", e);
+            StatusCode::NOT_FOUND
+        })?;
+    audit(
+        &state,
+        "redaction_policy.acknowledge",
+        tenant,
+        serde_json::json!({
+            "client_id": acknowledgment.client_id,
+            "version": acknowledgment.version
+        }),
+    );
+
+    Ok(Json(serde_json::to_value(acknowledgment).unwrap()))
+}
+
+/// Which policy version each client last acknowledged, and who is behind
+pub(super) async fn get_redaction_acknowledgments(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+) -> Json<serde_json::Value> {
+    let current = state
+        .redaction_policies
+        .bundle(&tenant, None)
+        .map(|signed| signed.bundle.version);
+    let acknowledgments = state.redaction_policies.acknowledgments(&tenant);
+    let outdated = acknowledgments
+        .iter()
+        .filter(|ack| Some(ack.version) != current)
+        .count();
+
+    Json(serde_json::json!({
+        "tenant": tenant,
+        "current_version": current,
+        "clients": acknowledgments,
+        "outdated_clients": outdated
+    }))
+}
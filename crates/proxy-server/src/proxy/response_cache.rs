@@ -0,0 +1,814 @@
+//! Cache of encrypted completions, with revalidation and invalidation
+
+use super::completions::process_encrypted_completion;
+use super::identity::admin_name;
+use super::{audit, tenant_id, ProcessRequest, ProxyState};
+use crate::access::ADMIN_TOKEN_HEADER;
+use crate::config::{ResponseCacheEviction, ResponseCachePreloadConfig};
+use crate::fhe::Ciphertext;
+use crate::performance_optimized::CachePredictionEngine;
+use crate::sandbox::SandboxGrant;
+use crate::workload_tags::WorkloadTag;
+use axum::{
+    extract::{Extension, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Criteria for purging cached responses; unset fields match everything
+#[derive(Debug, Default, Deserialize)]
+pub struct CacheInvalidationRequest {
+    pub tag: Option<String>,
+    pub template_id: Option<String>,
+    pub tenant: Option<String>,
+    pub model: Option<String>,
+}
+
+/// Opt-in cache of encrypted completion responses
+#[derive(Debug)]
+pub struct ResponseCache {
+    entries: RwLock<CacheEntries>,
+    max_entries: usize,
+    eviction: ResponseCacheEviction,
+    stats: std::sync::Mutex<ResponseCacheStats>,
+    /// Stale entries waiting for a background refresh, by cache key
+    refreshes: std::sync::Mutex<HashMap<String, Option<CacheRefresh>>>,
+    refresh_wanted: tokio::sync::Notify,
+    /// Hit and refresh counts by tenant ("" for requests without one)
+    freshness: std::sync::Mutex<BTreeMap<String, CacheFreshnessStats>>,
+    /// Learns the hours each key is looked up in; only fed while preloading
+    /// is enabled
+    predictor: CachePredictionEngine<String>,
+    preload: ResponseCachePreloadConfig,
+    /// The last request cached under each key, re-run to preload it, with
+    /// when it was remembered
+    preload_sources: std::sync::Mutex<HashMap<String, (Instant, CacheRefresh)>>,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+    slots: HashMap<String, CacheSlot>,
+    /// GDSF inflation: priority of the last entry evicted, so entries that
+    /// have sat unused long enough fall below newer ones
+    inflation: f64,
+}
+
+#[derive(Debug)]
+struct CacheSlot {
+    entry: CachedCompletion,
+    hits: u64,
+    size_bytes: usize,
+    /// GDSF priority; unused under expiry eviction
+    priority: f64,
+}
+
+impl CacheSlot {
+    fn new(entry: CachedCompletion, hits: u64, inflation: f64) -> Self {
+        let size_bytes = entry.size_bytes();
+        let mut slot = Self {
+            entry,
+            hits,
+            size_bytes,
+            priority: 0.0,
+        };
+        slot.reprioritize(inflation);
+        slot
+    }
+
+    fn reprioritize(&mut self, inflation: f64) {
+        self.priority = inflation
+            + (self.hits + 1) as f64 * self.entry.recompute_cost_ms.max(1) as f64
+                / self.size_bytes.max(1) as f64;
+    }
+}
+
+/// Hits and evictions under the cache's eviction policy, to compare policies
+/// by the recomputation time their hits saved
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResponseCacheStats {
+    pub policy: ResponseCacheEviction,
+    pub entries: usize,
+    pub hits: u64,
+    /// Recomputation time the hits saved
+    pub hit_value_ms: u64,
+    pub evictions: u64,
+    /// New entries a full GDSF cache valued below everything cached
+    pub rejected: u64,
+    /// Completions re-run ahead of the hour they were predicted for
+    pub preloads: u64,
+    pub useful_preloads: u64,
+    /// Preloads not requested before their hour passed
+    pub wasted_preloads: u64,
+    /// Share of resolved preloads that were requested in time
+    pub prediction_accuracy: Option<f64>,
+}
+
+/// A completion to re-run because its cached response went stale, or to
+/// preload it
+#[derive(Debug, Clone)]
+pub struct CacheRefresh {
+    pub tenant: Option<String>,
+    pub request: ProcessRequest,
+    pub headers: HeaderMap,
+    pub workload: Option<WorkloadTag>,
+    pub sandbox: Option<SandboxGrant>,
+}
+
+/// Marks a completion run by the cache refresher: it skips the cache lookup
+/// and replaces the stale entry with its result
+#[derive(Debug, Clone, Copy)]
+pub struct Revalidation;
+
+/// How fresh the cached completions a tenant was served were
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CacheFreshnessStats {
+    pub fresh_hits: u64,
+    pub stale_hits: u64,
+    /// Furthest past its TTL any served entry was
+    pub max_staleness_seconds: u64,
+    pub refreshes: u64,
+    pub refresh_failures: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedCompletion {
+    pub tenant: Option<String>,
+    pub model: String,
+    pub template_id: Option<String>,
+    pub tags: Vec<String>,
+    pub response: serde_json::Value,
+    /// Processed ciphertext followed by its chunks, restored on a hit
+    pub ciphertexts: Vec<Ciphertext>,
+    /// Digest of the cache key and the processed ciphertext
+    pub etag: String,
+    /// How long the completion took to compute, which a hit saves
+    pub recompute_cost_ms: u64,
+    pub stored_at: Instant,
+    /// Fresh until here
+    pub expires_at: Instant,
+    /// Served stale, while being refreshed, until here; never before `expires_at`
+    pub stale_until: Instant,
+}
+
+impl CachedCompletion {
+    /// How far past its TTL the entry is at `now`, if it is stale
+    pub fn staleness(&self, now: Instant) -> Option<Duration> {
+        (now >= self.expires_at).then(|| now - self.expires_at)
+    }
+
+    /// Bytes the entry holds: its response and ciphertexts
+    pub fn size_bytes(&self) -> usize {
+        self.response.to_string().len()
+            + self
+                .ciphertexts
+                .iter()
+                .map(|ct| ct.data.len())
+                .sum::<usize>()
+    }
+}
+
+impl ResponseCache {
+    pub fn new(max_entries: usize, eviction: ResponseCacheEviction) -> Self {
+        Self {
+            entries: RwLock::new(CacheEntries::default()),
+            max_entries,
+            eviction,
+            stats: std::sync::Mutex::new(ResponseCacheStats {
+                policy: eviction,
+                ..Default::default()
+            }),
+            refreshes: std::sync::Mutex::default(),
+            refresh_wanted: tokio::sync::Notify::new(),
+            freshness: std::sync::Mutex::default(),
+            predictor: CachePredictionEngine::new(1.0),
+            preload: ResponseCachePreloadConfig::default(),
+            preload_sources: std::sync::Mutex::default(),
+        }
+    }
+
+    pub fn with_preload(mut self, preload: ResponseCachePreloadConfig) -> Self {
+        self.predictor = CachePredictionEngine::new(preload.confidence_threshold);
+        self.preload = preload;
+        self
+    }
+
+    /// Cache key for a completion; the ciphertext is hashed, never stored in the key
+    pub fn key(
+        tenant: Option<&str>,
+        model: &str,
+        template_id: Option<&str>,
+        data: &[u8],
+    ) -> String {
+        let mut context = digest::Context::new(&digest::SHA256);
+        for part in [
+            tenant.unwrap_or_default(),
+            model,
+            template_id.unwrap_or_default(),
+        ] {
+            context.update(part.as_bytes());
+            context.update(&[0]);
+        }
+        context.update(data);
+        context
+            .finish()
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// The entry under `key`, fresh or within its stale window; serving it
+    /// counts as a hit
+    pub async fn get(&self, key: &str) -> Option<CachedCompletion> {
+        if self.preload.enabled {
+            self.predictor.record_access(&key.to_string(), Utc::now());
+        }
+        let mut entries = self.entries.write().await;
+        let inflation = entries.inflation;
+        match entries.slots.get_mut(key) {
+            Some(slot) if slot.entry.stale_until > Instant::now() => {
+                slot.hits += 1;
+                slot.reprioritize(inflation);
+                let mut stats = self.stats.lock().unwrap();
+                stats.hits += 1;
+                stats.hit_value_ms += slot.entry.recompute_cost_ms;
+                Some(slot.entry.clone())
+            }
+            Some(_) => {
+                entries.slots.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Store `entry`, making room under the eviction policy when the cache
+    /// is full. A replaced entry keeps its hits.
+    pub async fn insert(&self, key: String, entry: CachedCompletion) {
+        if self.preload.enabled {
+            self.predictor.record_size(&key, entry.size_bytes());
+        }
+        let mut entries = self.entries.write().await;
+        let hits = entries.slots.get(&key).map_or(0, |slot| slot.hits);
+        let slot = CacheSlot::new(entry, hits, entries.inflation);
+        if entries.slots.len() >= self.max_entries && !entries.slots.contains_key(&key) {
+            let victim = match self.eviction {
+                ResponseCacheEviction::Expiry => entries
+                    .slots
+                    .iter()
+                    .min_by_key(|(_, s)| s.entry.stale_until),
+                ResponseCacheEviction::Gdsf => entries
+                    .slots
+                    .iter()
+                    .min_by(|(_, a), (_, b)| a.priority.total_cmp(&b.priority)),
+            }
+            .map(|(k, s)| (k.clone(), s.priority));
+            if let Some((victim, priority)) = victim {
+                let mut stats = self.stats.lock().unwrap();
+                if self.eviction == ResponseCacheEviction::Gdsf && priority > slot.priority {
+                    stats.rejected += 1;
+                    return;
+                }
+                entries.slots.remove(&victim);
+                entries.inflation = entries.inflation.max(priority);
+                stats.evictions += 1;
+            }
+        }
+        entries.slots.insert(key, slot);
+    }
+
+    pub async fn entry_count(&self) -> usize {
+        self.entries.read().await.slots.len()
+    }
+
+    pub async fn stats(&self) -> ResponseCacheStats {
+        let entries = self.entry_count().await;
+        let (useful_preloads, wasted_preloads) = self.predictor.preload_outcomes();
+        ResponseCacheStats {
+            entries,
+            useful_preloads,
+            wasted_preloads,
+            prediction_accuracy: self.predictor.accuracy(),
+            ..self.stats.lock().unwrap().clone()
+        }
+    }
+
+    /// Remove entries matching every set criterion, returning how many were removed
+    pub async fn invalidate(&self, filter: &CacheInvalidationRequest) -> usize {
+        let mut entries = self.entries.write().await;
+        let before = entries.slots.len();
+        entries.slots.retain(|_, CacheSlot { entry, .. }| {
+            let matches = filter
+                .tenant
+                .as_ref()
+                .is_none_or(|t| entry.tenant.as_ref() == Some(t))
+                && filter.model.as_ref().is_none_or(|m| &entry.model == m)
+                && filter
+                    .template_id
+                    .as_ref()
+                    .is_none_or(|t| entry.template_id.as_ref() == Some(t))
+                && filter.tag.as_ref().is_none_or(|t| entry.tags.contains(t));
+            !matches
+        });
+        before - entries.slots.len()
+    }
+
+    pub async fn expire(&self) -> usize {
+        let mut entries = self.entries.write().await;
+        let before = entries.slots.len();
+        let now = Instant::now();
+        entries.slots.retain(|_, slot| slot.entry.stale_until > now);
+        before - entries.slots.len()
+    }
+
+    /// Remember how to re-run the completion cached under `key`, so it can
+    /// be preloaded after it leaves the cache
+    pub fn remember_source(&self, key: &str, source: CacheRefresh) {
+        if !self.preload.enabled {
+            return;
+        }
+        let mut sources = self.preload_sources.lock().unwrap();
+        if sources.len() >= self.max_entries && !sources.contains_key(key) {
+            let oldest = sources
+                .iter()
+                .min_by_key(|(_, (remembered, _))| *remembered)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                sources.remove(&oldest);
+            }
+        }
+        sources.insert(key.to_string(), (Instant::now(), source));
+    }
+
+    /// Queue refreshes of the completions predicted for the hour
+    /// `lead_seconds` after `at` that are not cached fresh, within the
+    /// preload budget; returns how many were queued
+    pub async fn preload_at(&self, at: DateTime<Utc>) -> usize {
+        let lead = chrono::Duration::seconds(self.preload.lead_seconds as i64);
+        // A preload is wasted once the hour it was predicted for has passed
+        self.predictor
+            .expire_preloads(at - lead - chrono::Duration::hours(1));
+
+        let now = Instant::now();
+        let mut budget = self.preload.budget_bytes;
+        let mut queued = 0;
+        for prediction in self.predictor.predict(at + lead) {
+            if prediction.size_bytes > budget {
+                continue;
+            }
+            let fresh = self
+                .entries
+                .read()
+                .await
+                .slots
+                .get(&prediction.key)
+                .is_some_and(|slot| slot.entry.expires_at > now);
+            if fresh {
+                continue;
+            }
+            let source = self
+                .preload_sources
+                .lock()
+                .unwrap()
+                .get(&prediction.key)
+                .map(|(_, source)| source.clone());
+            let Some(source) = source else { continue };
+            if self.queue_refresh(&prediction.key, source) {
+                budget -= prediction.size_bytes;
+                self.predictor.track_preload(&prediction.key, at);
+                self.stats.lock().unwrap().preloads += 1;
+                queued += 1;
+            }
+        }
+        queued
+    }
+
+    /// Count a hit for `tenant`, stale by `staleness` if at all
+    pub fn record_hit(&self, tenant: Option<&str>, staleness: Option<Duration>) {
+        let mut freshness = self.freshness.lock().unwrap();
+        let stats = freshness
+            .entry(tenant.unwrap_or_default().to_string())
+            .or_default();
+        match staleness {
+            Some(staleness) => {
+                stats.stale_hits += 1;
+                stats.max_staleness_seconds = stats.max_staleness_seconds.max(staleness.as_secs());
+            }
+            None => stats.fresh_hits += 1,
+        }
+    }
+
+    /// Queue a refresh of the stale entry under `key`, unless one is already
+    /// queued or running. Returns whether it was queued.
+    pub fn queue_refresh(&self, key: &str, refresh: CacheRefresh) -> bool {
+        let mut refreshes = self.refreshes.lock().unwrap();
+        if refreshes.contains_key(key) || refreshes.len() >= self.max_entries {
+            return false;
+        }
+        refreshes.insert(key.to_string(), Some(refresh));
+        drop(refreshes);
+        self.refresh_wanted.notify_one();
+        true
+    }
+
+    /// Wait for queued refreshes and take them; they count as running until
+    /// `finish_refresh`
+    pub async fn next_refreshes(&self) -> Vec<(String, CacheRefresh)> {
+        loop {
+            let queued: Vec<(String, CacheRefresh)> = self
+                .refreshes
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .filter_map(|(key, refresh)| Some((key.clone(), refresh.take()?)))
+                .collect();
+            if !queued.is_empty() {
+                return queued;
+            }
+            self.refresh_wanted.notified().await;
+        }
+    }
+
+    pub fn finish_refresh(&self, key: &str, tenant: Option<&str>, refreshed: bool) {
+        self.refreshes.lock().unwrap().remove(key);
+        let mut freshness = self.freshness.lock().unwrap();
+        let stats = freshness
+            .entry(tenant.unwrap_or_default().to_string())
+            .or_default();
+        if refreshed {
+            stats.refreshes += 1;
+        } else {
+            stats.refresh_failures += 1;
+        }
+    }
+
+    /// Freshness counts for `tenant`, or for every tenant when `None`
+    pub fn freshness_stats(&self, tenant: Option<&str>) -> BTreeMap<String, CacheFreshnessStats> {
+        let freshness = self.freshness.lock().unwrap();
+        match tenant {
+            Some(tenant) => freshness
+                .get(tenant)
+                .map(|stats| (tenant.to_string(), stats.clone()))
+                .into_iter()
+                .collect(),
+            None => freshness.clone(),
+        }
+    }
+}
+
+/// Re-run a completion whose cached response went stale; its result
+/// replaces the entry
+pub(super) async fn refresh_cached_completion(
+    state: Arc<ProxyState>,
+    key: String,
+    refresh: CacheRefresh,
+) {
+    let CacheRefresh {
+        tenant,
+        request,
+        headers,
+        workload,
+        sandbox,
+    } = refresh;
+    let outcome = process_encrypted_completion(
+        State(state.clone()),
+        headers,
+        workload.map(Extension),
+        sandbox.map(Extension),
+        Some(Extension(Revalidation)),
+        None,
+        Json(request),
+    )
+    .await;
+    let refreshed = match outcome {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) => {
+            log::warn!("Cached completion refresh returned {}", response.status());
+            false
+        }
+        Err(status) => {
+            log::warn!("Cached completion refresh failed with {}", status);
+            false
+        }
+    };
+    state
+        .response_cache
+        .finish_refresh(&key, tenant.as_deref(), refreshed);
+}
+
+/// Fresh and stale hits and background refreshes per tenant; a tenant sees
+/// only its own
+pub(super) async fn get_response_cache_stats(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "max_stale_seconds": state.config.performance.response_cache.max_stale_seconds,
+        "tenants": state.response_cache.freshness_stats(tenant_id(&headers)),
+    }))
+}
+
+/// Purge cached completions by tag, template, model or tenant. Tenants purge
+/// only their own entries; purging across tenants takes an admin token.
+pub(super) async fn invalidate_response_cache(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(mut request): Json<CacheInvalidationRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let invalidated_by = if headers.contains_key(ADMIN_TOKEN_HEADER) {
+        admin_name(&state, &headers)?
+    } else {
+        let tenant = tenant_id(&headers).ok_or(StatusCode::UNAUTHORIZED)?;
+        request.tenant = Some(tenant.to_string());
+        tenant.to_string()
+    };
+    if request.tag.is_none()
+        && request.template_id.is_none()
+        && request.tenant.is_none()
+        && request.model.is_none()
+    {
+        log::warn!("Rejected cache invalidation without criteria");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let invalidated = state.response_cache.invalidate(&request).await;
+    audit(
+        &state,
+        "response_cache.invalidate",
+        request.tenant.as_deref().unwrap_or("*"),
+        serde_json::json!({
+            "invalidated_by": invalidated_by,
+            "tag": request.tag,
+            "template_id": request.template_id,
+            "model": request.model,
+            "invalidated": invalidated
+        }),
+    );
+
+    Ok(Json(serde_json::json!({
+        "invalidated": invalidated,
+        "timestamp": chrono::Utc::now().timestamp()
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::etag;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_response_cache_invalidation() {
+        let cache = ResponseCache::new(2, ResponseCacheEviction::Expiry);
+        let entry = |tenant: &str, template: &str, tags: &[&str]| CachedCompletion {
+            tenant: Some(tenant.to_string()),
+            model: "gpt-4".to_string(),
+            template_id: Some(template.to_string()),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            response: serde_json::json!({}),
+            ciphertexts: Vec::new(),
+            etag: etag::digest_etag(&[tenant.as_bytes()]),
+            recompute_cost_ms: 10,
+            stored_at: Instant::now(),
+            expires_at: Instant::now() + Duration::from_secs(60),
+            stale_until: Instant::now() + Duration::from_secs(60),
+        };
+
+        let faq = ResponseCache::key(Some("acme"), "gpt-4", Some("faq"), b"a");
+        let other = ResponseCache::key(Some("globex"), "gpt-4", Some("faq"), b"a");
+        assert_ne!(faq, other);
+        cache
+            .insert(faq.clone(), entry("acme", "faq", &["v1"]))
+            .await;
+        cache
+            .insert(other.clone(), entry("globex", "faq", &["v1"]))
+            .await;
+        assert!(cache.get(&faq).await.is_some());
+
+        // Tenant-scoped invalidation leaves other tenants' entries alone
+        let filter = CacheInvalidationRequest {
+            tag: Some("v1".to_string()),
+            tenant: Some("acme".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(cache.invalidate(&filter).await, 1);
+        assert!(cache.get(&faq).await.is_none());
+        assert!(cache.get(&other).await.is_some());
+
+        let filter = CacheInvalidationRequest {
+            template_id: Some("faq".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(cache.invalidate(&filter).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_gdsf_keeps_costly_entries_that_expiry_evicts() {
+        let now = Instant::now();
+        let entry = |recompute_cost_ms: u64, ttl_seconds: u64| CachedCompletion {
+            tenant: Some("acme".to_string()),
+            model: "gpt-4".to_string(),
+            template_id: None,
+            tags: Vec::new(),
+            response: serde_json::json!({}),
+            ciphertexts: Vec::new(),
+            etag: etag::digest_etag(&[b"acme"]),
+            recompute_cost_ms,
+            stored_at: now,
+            expires_at: now + Duration::from_secs(ttl_seconds),
+            stale_until: now + Duration::from_secs(ttl_seconds),
+        };
+        let key = |data: &[u8]| ResponseCache::key(Some("acme"), "gpt-4", None, data);
+
+        for eviction in [ResponseCacheEviction::Gdsf, ResponseCacheEviction::Expiry] {
+            let cache = ResponseCache::new(2, eviction);
+            // The costly entry would expire first
+            cache.insert(key(b"costly"), entry(500, 60)).await;
+            cache.insert(key(b"cheap"), entry(5, 120)).await;
+            assert!(cache.get(&key(b"costly")).await.is_some());
+            cache.insert(key(b"newer"), entry(50, 180)).await;
+
+            let kept = cache.get(&key(b"costly")).await.is_some();
+            assert_eq!(kept, eviction == ResponseCacheEviction::Gdsf);
+            assert!(cache.get(&key(b"newer")).await.is_some());
+            let stats = cache.stats().await;
+            assert_eq!(
+                (stats.policy, stats.entries, stats.evictions),
+                (eviction, 2, 1)
+            );
+        }
+
+        // A full GDSF cache turns away an entry worth less than everything in it
+        let cache = ResponseCache::new(2, ResponseCacheEviction::Gdsf);
+        cache.insert(key(b"costly"), entry(500, 60)).await;
+        cache.insert(key(b"newer"), entry(50, 180)).await;
+        cache.get(&key(b"costly")).await;
+        cache.insert(key(b"cheap"), entry(5, 120)).await;
+        assert!(cache.get(&key(b"cheap")).await.is_none());
+        let stats = cache.stats().await;
+        assert_eq!((stats.rejected, stats.evictions), (1, 0));
+        assert_eq!((stats.hits, stats.hit_value_ms), (1, 500));
+    }
+
+    #[tokio::test]
+    async fn test_preloads_completions_requested_daily_ahead_of_their_hour() {
+        let cache = ResponseCache::new(10, ResponseCacheEviction::Expiry).with_preload(
+            ResponseCachePreloadConfig {
+                enabled: true,
+                budget_bytes: 1024,
+                ..Default::default()
+            },
+        );
+        let at = |day: u32, hour: u32, minute: u32| {
+            Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+                .unwrap()
+        };
+        let entry = CachedCompletion {
+            tenant: Some("acme".to_string()),
+            model: "gpt-4".to_string(),
+            template_id: None,
+            tags: Vec::new(),
+            response: serde_json::json!({}),
+            ciphertexts: Vec::new(),
+            etag: etag::digest_etag(&[b"acme"]),
+            recompute_cost_ms: 10,
+            stored_at: Instant::now(),
+            expires_at: Instant::now() + Duration::from_secs(60),
+            stale_until: Instant::now() + Duration::from_secs(60),
+        };
+        let source = CacheRefresh {
+            tenant: Some("acme".to_string()),
+            request: serde_json::from_value(serde_json::json!({
+                "ciphertext_id": Uuid::new_v4(),
+                "encrypted_data": "",
+                "provider": "openai",
+                "model": "gpt-4",
+                "stream": null
+            }))
+            .unwrap(),
+            headers: HeaderMap::new(),
+            workload: None,
+            sandbox: None,
+        };
+        // Both reports are requested a little after 09:00 every day
+        let keys = [b"report".as_slice(), b"memo"]
+            .map(|data| ResponseCache::key(Some("acme"), "gpt-4", None, data));
+        for key in &keys {
+            for day in [1, 2] {
+                cache.predictor.record_access(key, at(day, 9, 5));
+            }
+            cache.insert(key.clone(), entry.clone()).await;
+            cache.remember_source(key, source.clone());
+        }
+
+        // Cached and fresh, so there is nothing to preload
+        assert_eq!(cache.preload_at(at(3, 8, 55)).await, 0);
+        cache
+            .invalidate(&CacheInvalidationRequest {
+                tenant: Some("acme".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(cache.preload_at(at(3, 8, 55)).await, 2);
+        assert_eq!(cache.preload_at(at(3, 8, 56)).await, 0);
+        for (key, _) in cache.next_refreshes().await {
+            cache.finish_refresh(&key, Some("acme"), true);
+        }
+
+        // Only the report is requested; the memo preload is wasted once 09:00 passes
+        cache.get(&keys[0]).await;
+        assert_eq!(cache.preload_at(at(3, 10, 10)).await, 0);
+        let stats = cache.stats().await;
+        assert_eq!(
+            (stats.preloads, stats.useful_preloads, stats.wasted_preloads),
+            (2, 1, 1)
+        );
+        assert_eq!(stats.prediction_accuracy, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_serves_stale_entries_and_queues_one_refresh() {
+        let cache = ResponseCache::new(10, ResponseCacheEviction::Expiry);
+        let now = Instant::now();
+        let entry = |expires_at: Instant, stale_until: Instant| CachedCompletion {
+            tenant: Some("acme".to_string()),
+            model: "gpt-4".to_string(),
+            template_id: None,
+            tags: Vec::new(),
+            response: serde_json::json!({}),
+            ciphertexts: Vec::new(),
+            etag: etag::digest_etag(&[b"acme"]),
+            recompute_cost_ms: 10,
+            stored_at: now - Duration::from_secs(120),
+            expires_at,
+            stale_until,
+        };
+        let stale = ResponseCache::key(Some("acme"), "gpt-4", None, b"a");
+        let expired = ResponseCache::key(Some("acme"), "gpt-4", None, b"b");
+        cache
+            .insert(
+                stale.clone(),
+                entry(now - Duration::from_secs(30), now + Duration::from_secs(30)),
+            )
+            .await;
+        cache
+            .insert(
+                expired.clone(),
+                entry(now - Duration::from_secs(60), now - Duration::from_secs(1)),
+            )
+            .await;
+
+        // Past the hard TTL nothing is served; within the window it is, as stale
+        assert!(cache.get(&expired).await.is_none());
+        let cached = cache.get(&stale).await.unwrap();
+        let staleness = cached.staleness(Instant::now()).unwrap();
+        assert!(staleness >= Duration::from_secs(30));
+        cache.record_hit(Some("acme"), Some(staleness));
+        cache.record_hit(Some("acme"), None);
+
+        let refresh = || CacheRefresh {
+            tenant: Some("acme".to_string()),
+            request: serde_json::from_value(serde_json::json!({
+                "ciphertext_id": Uuid::new_v4(),
+                "encrypted_data": "",
+                "provider": "openai",
+                "model": "gpt-4",
+                "stream": null
+            }))
+            .unwrap(),
+            headers: HeaderMap::new(),
+            workload: None,
+            sandbox: None,
+        };
+        assert!(cache.queue_refresh(&stale, refresh()));
+        assert!(!cache.queue_refresh(&stale, refresh()));
+        let taken = cache.next_refreshes().await;
+        assert_eq!(taken.len(), 1);
+        // Still running, so another stale hit does not queue a second refresh
+        assert!(!cache.queue_refresh(&stale, refresh()));
+        cache.finish_refresh(&stale, Some("acme"), true);
+        assert!(cache.queue_refresh(&stale, refresh()));
+
+        let stats = cache.freshness_stats(Some("acme"));
+        assert_eq!(
+            stats["acme"],
+            CacheFreshnessStats {
+                fresh_hits: 1,
+                stale_hits: 1,
+                max_staleness_seconds: staleness.as_secs(),
+                refreshes: 1,
+                refresh_failures: 0,
+            }
+        );
+        assert!(cache.freshness_stats(Some("globex")).is_empty());
+        assert_eq!(cache.expire().await, 0);
+    }
+}
@@ -0,0 +1,216 @@
+//! Runbook automation and state history
+
+use super::identity::admin_name;
+use super::{audit, ProxyState};
+use crate::config::RunbookAction;
+use crate::monitoring::{RunbookDecision, RunbookOutcome, StateSnapshot};
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Window of recorded proxy state to dump
+#[derive(Debug, Deserialize)]
+pub struct StateHistoryQuery {
+    pub minutes: Option<u64>,
+}
+
+/// Current queue depths, cache occupancy and engine health
+pub(super) async fn capture_state(state: &ProxyState) -> StateSnapshot {
+    let health = state.monitoring.health_check().await;
+    let (engine_warm, client_keys) = {
+        let fhe_engine = state.fhe_engine.read().await;
+        (fhe_engine.is_warm(), fhe_engine.client_keys.len())
+    };
+
+    StateSnapshot {
+        timestamp: chrono::Utc::now().timestamp() as u64,
+        config_generation: state.tenant_configs.generation(),
+        in_flight_requests: state.admission.in_flight(),
+        packing_queue: state.prompt_packer.queued().await,
+        siem_queue: state.siem.stats().queued,
+        ciphertext_cache_entries: state.ciphertext_cache.read().await.len(),
+        response_cache_entries: state.response_cache.entry_count().await,
+        chunked_responses: state.response_chunks.response_count().await,
+        active_sessions: state
+            .session_manager
+            .stats()
+            .await
+            .active_by_tenant
+            .values()
+            .sum(),
+        engine_status: health
+            .components
+            .get("fhe_engine")
+            .map(|component| component.status.clone())
+            .unwrap_or_else(|| "unknown".to_string()),
+        engine_warm,
+        client_keys,
+        draining: state.resource_guard.is_draining(),
+        lockdown: state.lockdown.load(Ordering::Relaxed),
+    }
+}
+
+/// Apply a runbook action to the proxy and record the outcome, and the admin
+/// who approved it if it was held, in the runbook history and the audit log
+pub(super) async fn execute_runbook_action(
+    state: &ProxyState,
+    decision: &RunbookDecision,
+    approved_by: Option<&str>,
+) {
+    let result = match &decision.action {
+        RunbookAction::DrainEngine => {
+            state.resource_guard.set_draining(true);
+            Ok(())
+        }
+        RunbookAction::SwitchProvider { from, to } => {
+            if state.llm_providers.contains_key(to) {
+                state
+                    .provider_failover
+                    .write()
+                    .await
+                    .insert(from.clone(), to.clone());
+                Ok(())
+            } else {
+                Err(format!("provider {} is not configured", to))
+            }
+        }
+        RunbookAction::EnableLockdown => {
+            state.lockdown.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+    };
+
+    let (outcome, detail) = match result {
+        Ok(()) => (RunbookOutcome::Executed, None),
+        Err(e) => (RunbookOutcome::Failed, Some(e)),
+    };
+    if outcome == RunbookOutcome::Executed
+        && matches!(
+            decision.action,
+            RunbookAction::DrainEngine | RunbookAction::EnableLockdown
+        )
+    {
+        state.siem.emit(
+            SecurityEvent::new(SecurityEventKind::Lockdown, &decision.reason)
+                .subject(&decision.rule),
+        );
+    }
+    audit(
+        state,
+        "runbook_action",
+        &decision.rule,
+        serde_json::json!({
+            "id": decision.id,
+            "action": decision.action,
+            "reason": decision.reason,
+            "outcome": outcome,
+            "detail": detail,
+            "approved_by": approved_by,
+        }),
+    );
+    state.runbooks.record(decision, outcome, detail).await;
+}
+
+/// Recorded proxy state for the last `minutes` (default: the whole retention)
+pub(super) async fn get_state_history(
+    State(state): State<Arc<ProxyState>>,
+    Query(query): Query<StateHistoryQuery>,
+) -> Json<serde_json::Value> {
+    let recorder = &state.config.monitoring.state_recorder;
+    let minutes = query
+        .minutes
+        .unwrap_or(recorder.retention_minutes)
+        .min(recorder.retention_minutes);
+    let snapshots = state
+        .state_recorder
+        .since(Duration::from_secs(minutes * 60))
+        .await;
+
+    Json(serde_json::json!({
+        "interval_seconds": recorder.interval_seconds,
+        "minutes": minutes,
+        "count": snapshots.len(),
+        "snapshots": snapshots,
+    }))
+}
+
+/// Runbook history, actions awaiting approval and the state runbooks control
+pub(super) async fn get_runbooks(State(state): State<Arc<ProxyState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "dry_run": state.config.monitoring.runbooks.dry_run,
+        "lockdown": state.lockdown.load(Ordering::Relaxed),
+        "draining": state.resource_guard.is_draining(),
+        "provider_failover": *state.provider_failover.read().await,
+        "pending": state.runbooks.pending().await,
+        "history": state.runbooks.history().await,
+    }))
+}
+
+/// Execute an action that was held for operator approval; admins only
+pub(super) async fn approve_runbook_action(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let decision = state
+        .runbooks
+        .approve(id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    execute_runbook_action(&state, &decision, Some(&admin)).await;
+
+    Ok(Json(serde_json::json!({
+        "id": id,
+        "rule": decision.rule,
+        "status": "executed"
+    })))
+}
+
+/// Discard an action that was held for operator approval; admins only
+pub(super) async fn reject_runbook_action(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if !state.runbooks.reject(id).await {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    audit(
+        &state,
+        "runbook_action_rejected",
+        &id.to_string(),
+        serde_json::json!({ "admin": admin }),
+    );
+
+    Ok(Json(serde_json::json!({ "id": id, "status": "rejected" })))
+}
+
+/// Undo runbook actions: lift lockdown and draining, clear provider
+/// failovers; admins only
+pub(super) async fn reset_runbook_actions(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    state.lockdown.store(false, Ordering::Relaxed);
+    state.resource_guard.set_draining(false);
+    state.provider_failover.write().await.clear();
+    audit(
+        &state,
+        "runbook_reset",
+        "runbooks",
+        serde_json::json!({ "admin": admin }),
+    );
+
+    Ok(Json(serde_json::json!({ "status": "reset" })))
+}
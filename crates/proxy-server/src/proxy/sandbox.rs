@@ -0,0 +1,205 @@
+//! Budget-limited sandbox keys for provider experimentation
+
+use super::identity::admin_name;
+use super::{audit, tenant_id, ProxyState};
+use crate::access::ADMIN_TOKEN_HEADER;
+use crate::error::Error;
+use crate::sandbox::{
+    SandboxKey, SandboxKeyRequest, SandboxRefusal, SANDBOX_HEADER, SANDBOX_KEY_HEADER,
+};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Admit requests made with a sandbox key against the key's own pools, and
+/// mark every response to them, refusals included
+pub(super) async fn sandbox_middleware(
+    State(state): State<Arc<ProxyState>>,
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let Some(secret) = request.headers().get(SANDBOX_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let admitted = match secret.to_str() {
+        Ok(secret) => state.sandbox.admit(
+            secret,
+            tenant_id(request.headers()),
+            chrono::Utc::now().timestamp(),
+        ),
+        Err(_) => Err(SandboxRefusal::UnknownKey),
+    };
+    let mut response = match admitted {
+        Ok(grant) => {
+            let key_id = grant.key_id;
+            request.extensions_mut().insert(grant);
+            let started = Instant::now();
+            let response = next.run(request).await;
+            state
+                .sandbox
+                .record_response(key_id, response.status().as_u16(), started.elapsed());
+            response
+        }
+        Err(refusal) => {
+            log::debug!("Refused sandbox request: {}", refusal.as_str());
+            let status = match refusal {
+                SandboxRefusal::UnknownKey | SandboxRefusal::Expired | SandboxRefusal::Revoked => {
+                    StatusCode::UNAUTHORIZED
+                }
+                SandboxRefusal::WrongTenant => StatusCode::FORBIDDEN,
+                SandboxRefusal::RequestsExhausted | SandboxRefusal::TokensExhausted => {
+                    StatusCode::TOO_MANY_REQUESTS
+                }
+                SandboxRefusal::SpendCeiling => StatusCode::PAYMENT_REQUIRED,
+            };
+            (
+                status,
+                Json(serde_json::json!({ "error": refusal.as_str() })),
+            )
+                .into_response()
+        }
+    };
+    response
+        .headers_mut()
+        .insert(SANDBOX_HEADER, axum::http::HeaderValue::from_static("true"));
+    response
+}
+
+/// Who is managing sandbox keys: an admin manages every key, a tenant only
+/// the keys bound to itself
+enum KeyManager {
+    Admin(String),
+    Tenant(String),
+}
+
+impl KeyManager {
+    fn from_headers(state: &ProxyState, headers: &HeaderMap) -> Result<Self, StatusCode> {
+        if headers.contains_key(ADMIN_TOKEN_HEADER) {
+            return admin_name(state, headers).map(KeyManager::Admin);
+        }
+        tenant_id(headers)
+            .map(|tenant| KeyManager::Tenant(tenant.to_string()))
+            .ok_or(StatusCode::UNAUTHORIZED)
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            KeyManager::Admin(name) | KeyManager::Tenant(name) => name,
+        }
+    }
+
+    fn manages(&self, key: &SandboxKey) -> bool {
+        match self {
+            KeyManager::Admin(_) => true,
+            KeyManager::Tenant(tenant) => key.tenant.as_deref() == Some(tenant.as_str()),
+        }
+    }
+}
+
+/// Issue a sandbox key; its secret is only ever returned here. Admins may
+/// issue keys for any tenant, tenants only keys bound to themselves.
+pub(super) async fn create_sandbox_key(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+    Json(mut request): Json<SandboxKeyRequest>,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    if !state.sandbox.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let manager = KeyManager::from_headers(&state, &headers)?;
+    if let KeyManager::Tenant(tenant) = &manager {
+        if request.tenant.as_deref().is_some_and(|t| t != tenant) {
+            log::warn!("Refused a sandbox key for another tenant from {}", tenant);
+            return Err(StatusCode::FORBIDDEN);
+        }
+        request.tenant = Some(tenant.clone());
+    }
+    let (key, secret) = state
+        .sandbox
+        .create(request, chrono::Utc::now().timestamp())
+        .map_err(|e| {
+            log::warn!("Rejected sandbox key: {}", e);
+            match e {
+                Error::ResourceExhaustion(_) => StatusCode::CONFLICT,
+                _ => StatusCode::BAD_REQUEST,
+            }
+        })?;
+    audit(
+        &state,
+        "sandbox.key.create",
+        &key.id.to_string(),
+        serde_json::json!({
+            "issued_by": manager.name(),
+            "label": key.label,
+            "tenant": key.tenant,
+            "spend_ceiling": key.spend_ceiling,
+            "expires_at": key.expires_at
+        }),
+    );
+
+    let mut view = serde_json::to_value(&key).unwrap_or_default();
+    view["secret"] = serde_json::json!(secret);
+    Ok((StatusCode::CREATED, Json(view)))
+}
+
+/// Sandbox keys with their usage: every key for admins, a tenant's own keys
+/// for that tenant
+pub(super) async fn list_sandbox_keys(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let manager = KeyManager::from_headers(&state, &headers)?;
+    let keys: Vec<SandboxKey> = state
+        .sandbox
+        .list(chrono::Utc::now().timestamp())
+        .into_iter()
+        .filter(|key| manager.manages(key))
+        .collect();
+    Ok(Json(serde_json::json!({
+        "enabled": state.sandbox.is_enabled(),
+        "keys": keys,
+    })))
+}
+
+/// One key with its usage; another tenant's keys read as missing
+pub(super) async fn get_sandbox_key(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let manager = KeyManager::from_headers(&state, &headers)?;
+    let key = state
+        .sandbox
+        .get(id, chrono::Utc::now().timestamp())
+        .filter(|key| manager.manages(key))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::to_value(key).unwrap_or_default()))
+}
+
+/// Revoke a key; another tenant's keys read as missing
+pub(super) async fn revoke_sandbox_key(
+    State(state): State<Arc<ProxyState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let manager = KeyManager::from_headers(&state, &headers)?;
+    let now = chrono::Utc::now().timestamp();
+    state
+        .sandbox
+        .get(id, now)
+        .filter(|key| manager.manages(key))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let key = state.sandbox.revoke(id, now).ok_or(StatusCode::NOT_FOUND)?;
+    audit(
+        &state,
+        "sandbox.key.revoke",
+        &id.to_string(),
+        serde_json::json!({ "revoked_by": manager.name(), "usage": key.usage }),
+    );
+    Ok(Json(serde_json::to_value(key).unwrap_or_default()))
+}
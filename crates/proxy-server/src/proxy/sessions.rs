@@ -0,0 +1,941 @@
+//! Client sessions: per-tenant limits, eviction, token renewal and replication
+
+use super::{audit, ProxyState};
+use crate::config::{EffectiveTenantConfig, SessionLimitPolicy};
+use crate::error::{Error, Result};
+use crate::fhe::FheEngine;
+use crate::persistence::{PersistenceBackend, SessionRecord};
+use crate::renewal::{
+    self, IssuedTokens, RenewalHint, RenewalProtocol, RENEWAL_HINT_HEADER, SESSION_TOKEN_HEADER,
+    TOKEN_EXPIRES_IN_HEADER,
+};
+use crate::siem::{SecurityEvent, SecurityEventKind};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+/// Session management for client keys
+#[derive(Debug)]
+pub struct SessionManager {
+    sessions: RwLock<HashMap<Uuid, SessionData>>,
+    store: Option<Arc<dyn PersistenceBackend>>,
+    /// Region recorded in the version vector of sessions written here
+    region: String,
+    counters: SessionCounters,
+    /// Serializes read-modify-write of renewal state in the store
+    renewals: Mutex<()>,
+}
+
+#[derive(Debug)]
+struct SessionData {
+    /// Owning tenant; empty for requests without a tenant ID
+    tenant: String,
+    /// Digest of the API key the session was opened with
+    api_key: Option<String>,
+    client_id: Uuid,
+    server_id: Uuid,
+    /// Server key replaced by a renewal and still inside its overlap window
+    previous_server_id: Option<Uuid>,
+    created_at: Instant,
+    last_used: Instant,
+    request_count: u64,
+}
+
+#[derive(Debug, Default)]
+struct SessionCounters {
+    rejected: AtomicU64,
+    evicted_for_limit: AtomicU64,
+    evicted_idle: AtomicU64,
+}
+
+/// Why the proxy removed a session
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionReason {
+    SessionLimit,
+    IdleTimeout,
+    Offboarded,
+}
+
+/// A session removed by the session governor; its keys must be released
+#[derive(Debug, Clone, Serialize)]
+pub struct EvictedSession {
+    pub session_id: Uuid,
+    pub tenant: String,
+    pub client_id: Uuid,
+    pub server_id: Uuid,
+    pub previous_server_id: Option<Uuid>,
+    pub reason: EvictionReason,
+    pub idle_seconds: u64,
+}
+
+impl EvictedSession {
+    fn new(session_id: Uuid, session: SessionData, reason: EvictionReason) -> Self {
+        Self {
+            session_id,
+            tenant: session.tenant,
+            client_id: session.client_id,
+            server_id: session.server_id,
+            previous_server_id: session.previous_server_id,
+            reason,
+            idle_seconds: session.last_used.elapsed().as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionStats {
+    pub active_by_tenant: HashMap<String, usize>,
+    pub rejected: u64,
+    pub evicted_for_limit: u64,
+    pub evicted_idle: u64,
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+            store: None,
+            region: "local".to_string(),
+            counters: SessionCounters::default(),
+            renewals: Mutex::new(()),
+        }
+    }
+
+    /// Write sessions through to a persistence backend
+    pub fn with_store(mut self, store: Arc<dyn PersistenceBackend>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Attribute session writes to a replication region
+    pub fn with_region(mut self, region: &str) -> Self {
+        self.region = region.to_string();
+        self
+    }
+
+    /// Open a session for a tenant with the API key identified by `api_key`
+    /// (see `AccessControl::key_id`), enforcing the tenant's cap on
+    /// concurrent sessions per key.
+    ///
+    /// Returns the new session ID and any sessions evicted to make room.
+    pub async fn create_session(
+        &self,
+        tenant_config: &EffectiveTenantConfig,
+        api_key: Option<&str>,
+        client_id: Uuid,
+        server_id: Uuid,
+    ) -> Result<(Uuid, Vec<EvictedSession>)> {
+        let session_id = Uuid::new_v4();
+        let now = Instant::now();
+        let tenant = &tenant_config.tenant_id;
+        let mut evicted = Vec::new();
+
+        let mut sessions = self.sessions.write().await;
+        if tenant_config.max_sessions > 0 {
+            let mut key_sessions: Vec<(Uuid, Instant)> = sessions
+                .iter()
+                .filter(|(_, session)| session.api_key.as_deref() == api_key)
+                .map(|(id, session)| (*id, session.last_used))
+                .collect();
+
+            if key_sessions.len() >= tenant_config.max_sessions {
+                if tenant_config.session_limit_policy == SessionLimitPolicy::Reject {
+                    self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+                    return Err(Error::ResourceExhaustion(format!(
+                        "API key reached its limit of {} concurrent sessions",
+                        tenant_config.max_sessions
+                    )));
+                }
+
+                // Least recently used first, until there is room for the new session
+                key_sessions.sort_by_key(|(_, last_used)| *last_used);
+                let excess = key_sessions.len() + 1 - tenant_config.max_sessions;
+                for (id, _) in key_sessions.into_iter().take(excess) {
+                    if let Some(session) = sessions.remove(&id) {
+                        evicted.push(EvictedSession::new(
+                            id,
+                            session,
+                            EvictionReason::SessionLimit,
+                        ));
+                    }
+                }
+                self.counters
+                    .evicted_for_limit
+                    .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+            }
+        }
+
+        sessions.insert(
+            session_id,
+            SessionData {
+                tenant: tenant.clone(),
+                api_key: api_key.map(str::to_string),
+                client_id,
+                server_id,
+                previous_server_id: None,
+                created_at: now,
+                last_used: now,
+                request_count: 0,
+            },
+        );
+        drop(sessions);
+
+        if let Some(store) = &self.store {
+            let timestamp = chrono::Utc::now().timestamp();
+            let mut record = SessionRecord {
+                id: session_id,
+                client_id,
+                server_id,
+                created_at: timestamp,
+                last_used: timestamp,
+                request_count: 0,
+                version: Default::default(),
+                budget_spent: Default::default(),
+                context_refs: Default::default(),
+                renewal: None,
+            };
+            record.touch(&self.region);
+            if let Err(e) = store.put_session(&record) {
+                log::warn!("Failed to persist session {}: {}", session_id, e);
+            }
+        }
+        self.forget(&evicted);
+
+        Ok((session_id, evicted))
+    }
+
+    /// Evict every session unused for longer than `idle_timeout`
+    pub async fn expire_idle(&self, idle_timeout: Duration) -> Vec<EvictedSession> {
+        let mut sessions = self.sessions.write().await;
+        let idle: Vec<Uuid> = sessions
+            .iter()
+            .filter(|(_, session)| session.last_used.elapsed() > idle_timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        let evicted: Vec<EvictedSession> = idle
+            .into_iter()
+            .filter_map(|id| {
+                let session = sessions.remove(&id)?;
+                Some(EvictedSession::new(
+                    id,
+                    session,
+                    EvictionReason::IdleTimeout,
+                ))
+            })
+            .collect();
+        drop(sessions);
+
+        self.counters
+            .evicted_idle
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+        self.forget(&evicted);
+        evicted
+    }
+
+    /// Close every session of an offboarded tenant
+    pub async fn close_tenant(&self, tenant: &str) -> Vec<EvictedSession> {
+        let mut sessions = self.sessions.write().await;
+        let owned: Vec<Uuid> = sessions
+            .iter()
+            .filter(|(_, session)| session.tenant == tenant)
+            .map(|(id, _)| *id)
+            .collect();
+        let closed: Vec<EvictedSession> = owned
+            .into_iter()
+            .filter_map(|id| {
+                let session = sessions.remove(&id)?;
+                Some(EvictedSession::new(id, session, EvictionReason::Offboarded))
+            })
+            .collect();
+        drop(sessions);
+
+        self.forget(&closed);
+        closed
+    }
+
+    pub async fn stats(&self) -> SessionStats {
+        let mut active_by_tenant = HashMap::new();
+        for session in self.sessions.read().await.values() {
+            *active_by_tenant.entry(session.tenant.clone()).or_insert(0) += 1;
+        }
+        SessionStats {
+            active_by_tenant,
+            rejected: self.counters.rejected.load(Ordering::Relaxed),
+            evicted_for_limit: self.counters.evicted_for_limit.load(Ordering::Relaxed),
+            evicted_idle: self.counters.evicted_idle.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Remove evicted sessions from the persistence backend
+    fn forget(&self, evicted: &[EvictedSession]) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        for session in evicted {
+            if let Err(e) = store.delete_session(session.session_id) {
+                log::warn!("Failed to delete session {}: {}", session.session_id, e);
+            }
+        }
+    }
+
+    pub async fn get_client_id(&self, session_id: Uuid) -> Option<Uuid> {
+        self.sessions
+            .read()
+            .await
+            .get(&session_id)
+            .map(|s| s.client_id)
+    }
+
+    /// Owning tenant of a live session; empty for sessions opened without one
+    pub async fn get_tenant(&self, session_id: Uuid) -> Option<String> {
+        self.sessions
+            .read()
+            .await
+            .get(&session_id)
+            .map(|s| s.tenant.clone())
+    }
+
+    pub async fn update_last_used(&self, session_id: Uuid) {
+        let request_count = match self.sessions.write().await.get_mut(&session_id) {
+            Some(session) => {
+                session.last_used = Instant::now();
+                session.request_count += 1;
+                session.request_count
+            }
+            None => return,
+        };
+
+        if let Some(store) = &self.store {
+            let _renewal = self.renewals.lock().await;
+            let updated = store
+                .get_session(session_id)
+                .and_then(|record| match record {
+                    Some(mut record) => {
+                        record.last_used = chrono::Utc::now().timestamp();
+                        // Another region may have counted requests this replica never saw
+                        record.request_count = record.request_count.max(request_count);
+                        record.touch(&self.region);
+                        store.put_session(&record)
+                    }
+                    None => Ok(()),
+                });
+            if let Err(e) = updated {
+                log::warn!("Failed to persist session {}: {}", session_id, e);
+            }
+        }
+    }
+
+    /// Issue a new session its first tokens
+    pub async fn start_renewal(
+        &self,
+        session_id: Uuid,
+        protocol: &RenewalProtocol,
+    ) -> Result<IssuedTokens> {
+        let now = chrono::Utc::now().timestamp();
+        let (renewal, tokens) = protocol.start(session_id, now)?;
+        self.update_record(session_id, |record| {
+            record.renewal = Some(renewal);
+            Ok(())
+        })
+        .await?;
+        Ok(tokens)
+    }
+
+    /// Trade a refresh token for a new pair of tokens
+    pub async fn refresh_tokens(
+        &self,
+        session_id: Uuid,
+        protocol: &RenewalProtocol,
+        refresh_token: &str,
+    ) -> Result<IssuedTokens> {
+        let now = chrono::Utc::now().timestamp();
+        self.update_record(session_id, |record| {
+            let renewal = record
+                .renewal
+                .as_mut()
+                .ok_or_else(|| Error::Auth("Session was not issued tokens".to_string()))?;
+            protocol.refresh(session_id, renewal, refresh_token, now)
+        })
+        .await
+    }
+
+    /// Session an access token belongs to, when the token expires, and what
+    /// the client should renew soon
+    pub async fn authenticate(
+        &self,
+        protocol: &RenewalProtocol,
+        token: &str,
+    ) -> Result<(Uuid, i64, Vec<RenewalHint>)> {
+        let session_id = renewal::token_session(token)
+            .ok_or_else(|| Error::Auth("Malformed session token".to_string()))?;
+        let renewal = self
+            .store
+            .as_ref()
+            .map(|store| store.get_session(session_id))
+            .transpose()?
+            .flatten()
+            .and_then(|record| record.renewal)
+            .ok_or_else(|| Error::Auth("Unknown session token".to_string()))?;
+        let now = chrono::Utc::now().timestamp();
+        let expires_at = protocol.authenticate(&renewal, token, now)?;
+        Ok((
+            session_id,
+            expires_at,
+            protocol.hints(&renewal, expires_at, now),
+        ))
+    }
+
+    /// Make `new_server_id` the session's server key. Returns the key it
+    /// replaces, the time that key stays valid until, and any older key that
+    /// can be released now.
+    pub async fn renew_server_key(
+        &self,
+        session_id: Uuid,
+        protocol: &RenewalProtocol,
+        new_server_id: Uuid,
+    ) -> Result<(Uuid, i64, Option<Uuid>)> {
+        let now = chrono::Utc::now().timestamp();
+        let (old_server_id, valid_until, retired) = self
+            .update_record(session_id, |record| {
+                let renewal = record
+                    .renewal
+                    .as_mut()
+                    .ok_or_else(|| Error::Auth("Session was not issued tokens".to_string()))?;
+                let (valid_until, retired) = protocol.renew_key(renewal, record.server_id, now)?;
+                let old_server_id = std::mem::replace(&mut record.server_id, new_server_id);
+                Ok((old_server_id, valid_until, retired))
+            })
+            .await?;
+        if let Some(session) = self.sessions.write().await.get_mut(&session_id) {
+            session.server_id = new_server_id;
+            session.previous_server_id = Some(old_server_id);
+        }
+        Ok((old_server_id, valid_until, retired))
+    }
+
+    /// Drop replaced server keys whose overlap window has closed, returning
+    /// them so the caller can release them
+    pub async fn retire_overlapped_keys(&self) -> Vec<Uuid> {
+        let Some(store) = &self.store else {
+            return Vec::new();
+        };
+        let now = chrono::Utc::now().timestamp();
+        let _renewal = self.renewals.lock().await;
+        let records = match store.list_sessions() {
+            Ok(records) => records,
+            Err(e) => {
+                log::warn!("Failed to list sessions for key retirement: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut sessions = self.sessions.write().await;
+        let mut retired = Vec::new();
+        for mut record in records {
+            let Some(server_id) = record
+                .renewal
+                .as_mut()
+                .and_then(|renewal| renewal::retire_key(renewal, now))
+            else {
+                continue;
+            };
+            record.touch(&self.region);
+            if let Err(e) = store.put_session(&record) {
+                log::warn!("Failed to persist session {}: {}", record.id, e);
+                continue;
+            }
+            if let Some(session) = sessions.get_mut(&record.id) {
+                session.previous_server_id = None;
+            }
+            retired.push(server_id);
+        }
+        retired
+    }
+
+    /// Apply `update` to the stored record of a session and write it back
+    async fn update_record<T>(
+        &self,
+        session_id: Uuid,
+        update: impl FnOnce(&mut SessionRecord) -> Result<T>,
+    ) -> Result<T> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| Error::Internal("Session renewal needs a session store".to_string()))?;
+        let _renewal = self.renewals.lock().await;
+        let mut record = store
+            .get_session(session_id)?
+            .ok_or_else(|| Error::Auth(format!("Unknown session {}", session_id)))?;
+        let value = update(&mut record)?;
+        record.touch(&self.region);
+        store.put_session(&record)?;
+        Ok(value)
+    }
+}
+
+/// Pull every peer region's sessions and merge them into the local store
+pub(super) async fn reconcile_sessions(state: &ProxyState, client: &HttpClient, peers: &[String]) {
+    let idle_timeout = state.config.sessions.idle_timeout_seconds;
+    let expired_before =
+        (idle_timeout > 0).then(|| chrono::Utc::now().timestamp() - idle_timeout as i64);
+
+    for peer in peers {
+        let url = format!(
+            "{}/v1/admin/replication/sessions",
+            peer.trim_end_matches('/')
+        );
+        let sessions = match client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => {
+                response.json::<Vec<SessionRecord>>().await
+            }
+            Ok(response) => {
+                log::warn!("Replication peer {} returned {}", peer, response.status());
+                state.reconciler.record_peer_failure();
+                continue;
+            }
+            Err(e) => Err(e),
+        };
+        let sessions = match sessions {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                log::warn!("Failed to pull sessions from {}: {}", peer, e);
+                state.reconciler.record_peer_failure();
+                continue;
+            }
+        };
+        if let Err(e) = state
+            .reconciler
+            .apply(state.store.as_ref(), &sessions, expired_before)
+        {
+            log::error!("Failed to reconcile sessions from {}: {}", peer, e);
+        }
+    }
+    state.reconciler.record_round();
+}
+
+/// Drop the keys of evicted sessions and tell the owning tenant about it
+pub(super) async fn release_evicted_sessions(
+    state: &ProxyState,
+    fhe_engine: &mut FheEngine,
+    evicted: &[EvictedSession],
+) {
+    for session in evicted {
+        fhe_engine.client_keys.remove(&session.client_id);
+        fhe_engine.server_keys.remove(&session.server_id);
+        if let Some(previous) = session.previous_server_id {
+            fhe_engine.server_keys.remove(&previous);
+        }
+        state.key_policies.forget(session.client_id).await;
+        log::info!(
+            "Evicted session {} of tenant {:?} ({:?}, idle {}s)",
+            session.session_id,
+            session.tenant,
+            session.reason,
+            session.idle_seconds
+        );
+        audit(
+            state,
+            "session_evicted",
+            &session.session_id.to_string(),
+            serde_json::to_value(session).unwrap_or_default(),
+        );
+
+        let tenant_config = if session.tenant.is_empty() {
+            state.tenant_configs.resolve_global()
+        } else {
+            state.tenant_configs.resolve(&session.tenant)
+        };
+        let webhook_url = match tenant_config {
+            Ok(config) => config.session_webhook_url.clone(),
+            Err(e) => {
+                log::warn!(
+                    "Failed to resolve tenant config for {}: {}",
+                    session.tenant,
+                    e
+                );
+                None
+            }
+        };
+        if let Some(url) = webhook_url {
+            let payload = serde_json::json!({
+                "event": "session_evicted",
+                "session_id": session.session_id,
+                "tenant": session.tenant,
+                "reason": session.reason,
+                "idle_seconds": session.idle_seconds,
+                "evicted_at": chrono::Utc::now(),
+            });
+            tokio::spawn(async move {
+                let result = HttpClient::new()
+                    .post(&url)
+                    .timeout(Duration::from_secs(10))
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(e) = result {
+                    log::warn!("Session eviction webhook to {} failed: {}", url, e);
+                }
+            });
+        }
+    }
+}
+
+/// Get session statistics
+pub(super) async fn get_session_stats(
+    State(state): State<Arc<ProxyState>>,
+    Path(session_id): Path<Uuid>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let sessions = state.session_manager.sessions.read().await;
+    let session = match sessions.get(&session_id) {
+        Some(session) => session,
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+
+    Ok(Json(serde_json::json!({
+        "session_id": session_id,
+        "client_id": session.client_id,
+        "created_at": session.created_at.elapsed().as_secs(),
+        "last_used": session.last_used.elapsed().as_secs(),
+        "request_count": session.request_count
+    })))
+}
+
+/// Body of `POST /v1/sessions/{id}/refresh`
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+/// Trade a refresh token for a new access and refresh token
+pub(super) async fn refresh_session_tokens(
+    State(state): State<Arc<ProxyState>>,
+    Path(session_id): Path<Uuid>,
+    Json(request): Json<RefreshTokenRequest>,
+) -> std::result::Result<Json<IssuedTokens>, StatusCode> {
+    if !state.renewal.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let tokens = state
+        .session_manager
+        .refresh_tokens(session_id, &state.renewal, &request.refresh_token)
+        .await
+        .map_err(|e| session_auth_failure(&state, session_id, e))?;
+    audit(
+        &state,
+        "session.tokens_refreshed",
+        &session_id.to_string(),
+        serde_json::json!({ "access_expires_at": tokens.access_expires_at }),
+    );
+    Ok(Json(tokens))
+}
+
+/// Issue the session a new server key; the old one stays valid for the
+/// overlap window so requests made with it still succeed
+pub(super) async fn renew_session_keys(
+    State(state): State<Arc<ProxyState>>,
+    Path(session_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    if !state.renewal.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let token = headers
+        .get(SESSION_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let (token_session, _, _) = state
+        .session_manager
+        .authenticate(&state.renewal, token)
+        .await
+        .map_err(|e| session_auth_failure(&state, session_id, e))?;
+    if token_session != session_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let client_id = state
+        .session_manager
+        .get_client_id(session_id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut fhe_engine = state.fhe_engine.write().await;
+    let server_id = fhe_engine.rotate_keys(client_id).map_err(|e| {
+        log::error!("Key renewal failed for session {}: {}", session_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let (previous_server_id, previous_valid_until, retired) = match state
+        .session_manager
+        .renew_server_key(session_id, &state.renewal, server_id)
+        .await
+    {
+        Ok(renewed) => renewed,
+        Err(e) => {
+            fhe_engine.server_keys.remove(&server_id);
+            log::warn!("Key renewal refused for session {}: {}", session_id, e);
+            return Err(match e {
+                Error::Validation(_) => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            });
+        }
+    };
+    if let Some(retired) = retired {
+        fhe_engine.server_keys.remove(&retired);
+    }
+    drop(fhe_engine);
+
+    let key_expires_at =
+        chrono::Utc::now().timestamp() + state.renewal.config().key_lifetime_seconds as i64;
+    audit(
+        &state,
+        "session.keys_renewed",
+        &session_id.to_string(),
+        serde_json::json!({
+            "server_id": server_id,
+            "previous_server_id": previous_server_id,
+            "previous_valid_until": previous_valid_until,
+        }),
+    );
+    Ok(Json(serde_json::json!({
+        "session_id": session_id,
+        "server_id": server_id,
+        "previous_server_id": previous_server_id,
+        "previous_valid_until": previous_valid_until,
+        "key_expires_at": key_expires_at,
+    })))
+}
+
+/// Report a rejected session token or refresh token
+fn session_auth_failure(state: &ProxyState, session_id: Uuid, error: Error) -> StatusCode {
+    match error {
+        Error::Auth(reason) => {
+            state.siem.emit(
+                SecurityEvent::new(SecurityEventKind::AuthFailure, &reason)
+                    .subject(&session_id.to_string()),
+            );
+            StatusCode::UNAUTHORIZED
+        }
+        e => {
+            log::error!("Session token check failed for {}: {}", session_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Check `x-session-token` when a request carries one, count the request
+/// against its session, and tell the client what to renew as expiry nears.
+/// The refresh endpoint is exempt, since it is how expired tokens are replaced.
+pub(super) async fn session_token_middleware(
+    State(state): State<Arc<ProxyState>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let path = request.uri().path();
+    let refreshing = path.starts_with("/v1/sessions/") && path.ends_with("/refresh");
+    let token = request
+        .headers()
+        .get(SESSION_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok());
+    let (Some(token), true, false) = (token, state.renewal.is_enabled(), refreshing) else {
+        return next.run(request).await;
+    };
+
+    let (session_id, expires_at, hints) = match state
+        .session_manager
+        .authenticate(&state.renewal, token)
+        .await
+    {
+        Ok(authenticated) => authenticated,
+        Err(e) => {
+            let session_id = renewal::token_session(token).unwrap_or_default();
+            return session_auth_failure(&state, session_id, e).into_response();
+        }
+    };
+    state.session_manager.update_last_used(session_id).await;
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    let expires_in = (expires_at - chrono::Utc::now().timestamp()).max(0);
+    headers.insert(TOKEN_EXPIRES_IN_HEADER, expires_in.into());
+    if !hints.is_empty() {
+        let hints: Vec<&str> = hints.iter().map(RenewalHint::as_str).collect();
+        if let Ok(value) = hints.join(", ").parse() {
+            headers.insert(RENEWAL_HINT_HEADER, value);
+        }
+    }
+    response
+}
+
+/// Active sessions per tenant plus rejection and eviction counters
+pub(super) async fn get_session_limits(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::to_value(state.session_manager.stats().await).unwrap())
+}
+
+pub(super) async fn get_replication_stats(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "enabled": state.config.persistence.replication.enabled,
+        "region": state.reconciler.region(),
+        "peers": state.config.persistence.replication.peers,
+        "stats": state.reconciler.stats()
+    }))
+}
+
+/// Every stored session with its version vector, pulled by peer regions' reconcilers
+pub(super) async fn export_replicated_sessions(
+    State(state): State<Arc<ProxyState>>,
+) -> std::result::Result<Json<Vec<SessionRecord>>, StatusCode> {
+    state.store.list_sessions().map(Json).map_err(|e| {
+        log::warn!("Failed to list sessions for replication: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, TenantConfigResolver};
+    use crate::persistence;
+
+    #[tokio::test]
+    async fn test_session_limit_policies() {
+        let mut config = Config::default();
+        config.sessions.max_sessions_per_api_key = 2;
+        config.tenants.overrides.insert(
+            "acme".to_string(),
+            crate::config::TenantOverrides {
+                session_limit_policy: Some(SessionLimitPolicy::EvictOldestIdle),
+                ..Default::default()
+            },
+        );
+        let resolver = TenantConfigResolver::new(config);
+        let acme = resolver.resolve("acme").unwrap();
+        let globex = resolver.resolve("globex").unwrap();
+        let sessions = SessionManager::new();
+
+        // Rejection leaves the existing sessions untouched
+        for _ in 0..2 {
+            sessions
+                .create_session(&globex, Some("globex-1"), Uuid::new_v4(), Uuid::new_v4())
+                .await
+                .unwrap();
+        }
+        assert!(sessions
+            .create_session(&globex, Some("globex-1"), Uuid::new_v4(), Uuid::new_v4())
+            .await
+            .is_err());
+
+        // The cap follows the API key, not the tenant
+        sessions
+            .create_session(&globex, Some("globex-2"), Uuid::new_v4(), Uuid::new_v4())
+            .await
+            .unwrap();
+
+        // Eviction drops the least recently used session of the same key only
+        let (oldest, _) = sessions
+            .create_session(&acme, Some("acme-1"), Uuid::new_v4(), Uuid::new_v4())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        let (newer, _) = sessions
+            .create_session(&acme, Some("acme-1"), Uuid::new_v4(), Uuid::new_v4())
+            .await
+            .unwrap();
+        let (_, evicted) = sessions
+            .create_session(&acme, Some("acme-1"), Uuid::new_v4(), Uuid::new_v4())
+            .await
+            .unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].session_id, oldest);
+        assert_eq!(evicted[0].reason, EvictionReason::SessionLimit);
+        assert!(sessions.get_client_id(newer).await.is_some());
+
+        let stats = sessions.stats().await;
+        assert_eq!(stats.active_by_tenant["acme"], 2);
+        assert_eq!(stats.active_by_tenant["globex"], 3);
+        assert_eq!(stats.rejected, 1);
+        assert_eq!(stats.evicted_for_limit, 1);
+
+        // Idle expiry sweeps every tenant
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let expired = sessions.expire_idle(Duration::from_millis(10)).await;
+        assert_eq!(expired.len(), 5);
+        assert!(expired
+            .iter()
+            .all(|s| s.reason == EvictionReason::IdleTimeout));
+    }
+
+    #[tokio::test]
+    async fn test_session_token_refresh_and_key_renewal() {
+        let protocol = RenewalProtocol::new(crate::config::SessionRenewalConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        let tenant = TenantConfigResolver::new(Config::default())
+            .resolve_global()
+            .unwrap();
+        let store: Arc<dyn PersistenceBackend> = Arc::new(persistence::MemoryBackend::new());
+        let sessions = SessionManager::new().with_store(store.clone());
+        let (session_id, _) = sessions
+            .create_session(&tenant, None, Uuid::new_v4(), Uuid::new_v4())
+            .await
+            .unwrap();
+
+        let first = sessions.start_renewal(session_id, &protocol).await.unwrap();
+        let (authenticated, _, hints) = sessions
+            .authenticate(&protocol, &first.access_token)
+            .await
+            .unwrap();
+        assert_eq!(authenticated, session_id);
+        assert!(hints.is_empty());
+
+        // The refresh token is single use; the replaced access token still works
+        let second = sessions
+            .refresh_tokens(session_id, &protocol, &first.refresh_token)
+            .await
+            .unwrap();
+        assert!(sessions
+            .refresh_tokens(session_id, &protocol, &first.refresh_token)
+            .await
+            .is_err());
+        for token in [&first.access_token, &second.access_token] {
+            assert!(sessions.authenticate(&protocol, token).await.is_ok());
+        }
+        assert!(sessions
+            .authenticate(&protocol, &format!("{}.forged", session_id))
+            .await
+            .is_err());
+
+        // A renewed key replaces the stored one and overlaps until retired
+        let old_server_id = store.get_session(session_id).unwrap().unwrap().server_id;
+        let new_server_id = Uuid::new_v4();
+        let (previous, valid_until, retired) = sessions
+            .renew_server_key(session_id, &protocol, new_server_id)
+            .await
+            .unwrap();
+        assert_eq!((previous, retired), (old_server_id, None));
+        assert!(valid_until > chrono::Utc::now().timestamp());
+        assert!(sessions.retire_overlapped_keys().await.is_empty());
+        let record = store.get_session(session_id).unwrap().unwrap();
+        assert_eq!(record.server_id, new_server_id);
+        assert_eq!(record.renewal.unwrap().generation, 3);
+    }
+}
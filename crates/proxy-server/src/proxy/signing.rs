@@ -0,0 +1,98 @@
+//! Attestation, response signing keys and build provenance
+
+use super::identity::admin_name;
+use super::{audit, ProxyState};
+use crate::security::{AttestationService, BuildProvenance};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use std::sync::Arc;
+
+/// Get the attestation public key and rotation history
+pub(super) async fn get_attestation_keys(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "current": state.attestation.current_key(),
+        "history": state.attestation.key_history(),
+        "engine_build_hash": AttestationService::engine_build_hash()
+    }))
+}
+
+/// Compile-time provenance of the running binary, signed with the attestation key
+pub(super) async fn get_build_provenance(
+    State(state): State<Arc<ProxyState>>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let signed = state
+        .attestation
+        .sign_provenance(&BuildProvenance::current())
+        .map_err(|e| {
+            log::error!("Provenance signing failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(serde_json::to_value(signed).unwrap()))
+}
+
+/// Rotate the attestation signing key; admins only
+pub(super) async fn rotate_attestation_key(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let key = state.attestation.rotate().map_err(|e| {
+        log::error!("Attestation key rotation failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    audit(
+        &state,
+        "attestation.rotate",
+        "server",
+        serde_json::json!({ "admin": admin, "key_id": key.key_id }),
+    );
+
+    Ok(Json(serde_json::json!({
+        "status": "rotated",
+        "current": key
+    })))
+}
+
+/// Publish the response signing keys as a JWKS document
+pub(super) async fn get_response_signing_keys(
+    State(state): State<Arc<ProxyState>>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let signer = state
+        .response_signer
+        .as_ref()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::json!(signer.jwks())))
+}
+
+/// Rotate the response signing key; the previous key stays published during
+/// rollover. Admins only.
+pub(super) async fn rotate_response_signing_key(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let signer = state
+        .response_signer
+        .as_ref()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let key = signer.rotate().map_err(|e| {
+        log::error!("Response signing key rotation failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    audit(
+        &state,
+        "response_signing.rotate",
+        "server",
+        serde_json::json!({ "admin": admin, "key_id": key.kid }),
+    );
+
+    Ok(Json(serde_json::json!({
+        "status": "rotated",
+        "current": key
+    })))
+}
@@ -0,0 +1,222 @@
+//! Hot-standby pairing, state handoff and promotion
+
+use super::identity::admin_name;
+use super::privacy::flush_privacy_ledger;
+use super::{audit, ProxyState};
+use crate::config::StandbyReplication;
+use crate::error::{Error, Result};
+use crate::standby::{HandoffState, Heartbeat, PairRole};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use reqwest::Client as HttpClient;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub(super) async fn fetch_peer_heartbeat(client: &HttpClient, peer_url: &str) -> Option<Heartbeat> {
+    let url = format!(
+        "{}/v1/admin/standby/heartbeat",
+        peer_url.trim_end_matches('/')
+    );
+    match client.get(&url).send().await {
+        Ok(response) if response.status().is_success() => response.json().await.ok(),
+        Ok(response) => {
+            log::debug!("Standby peer returned {}", response.status());
+            None
+        }
+        Err(e) => {
+            log::debug!("Standby peer unreachable: {}", e);
+            None
+        }
+    }
+}
+
+/// Whether a majority of the witnesses answer at all; with none configured
+/// the pair relies on epochs alone to settle a split brain once it heals
+pub(super) async fn witnesses_reachable(client: &HttpClient, witnesses: &[String]) -> bool {
+    if witnesses.is_empty() {
+        return true;
+    }
+    let mut reachable = 0;
+    for witness in witnesses {
+        if client.get(witness).send().await.is_ok() {
+            reachable += 1;
+        }
+    }
+    if reachable * 2 <= witnesses.len() {
+        log::warn!(
+            "Standby peer is silent but only {} of {} witnesses answer; not taking over",
+            reachable,
+            witnesses.len()
+        );
+        return false;
+    }
+    true
+}
+
+/// Copy the active peer's sessions and privacy budgets
+pub(super) async fn pull_standby_state(state: &ProxyState, client: &HttpClient) -> Result<()> {
+    let url = format!(
+        "{}/v1/admin/standby/state",
+        state.standby.config().peer_url.trim_end_matches('/')
+    );
+    let handoff: HandoffState = client
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let idle_timeout = state.config.sessions.idle_timeout_seconds;
+    let expired_before =
+        (idle_timeout > 0).then(|| chrono::Utc::now().timestamp() - idle_timeout as i64);
+    state
+        .reconciler
+        .apply(state.store.as_ref(), &handoff.sessions, expired_before)?;
+    state.privacy_tracker.restore_ledger(handoff.ledger).await;
+    state.standby.record_replication(handoff.epoch);
+    Ok(())
+}
+
+/// Promote this instance: catch up on the peer's state where it can still be
+/// reached, claim the virtual IP and start serving
+pub(super) async fn take_over(state: &ProxyState, reason: &str) {
+    let config = state.standby.config();
+    match config.replication {
+        StandbyReplication::Direct if !state.standby.peer_lost(Instant::now()) => {
+            let client = HttpClient::builder()
+                .timeout(Duration::from_secs(config.replication_interval_seconds))
+                .build()
+                .unwrap_or_default();
+            if let Err(e) = pull_standby_state(state, &client).await {
+                log::warn!("Taking over without a final state sync: {}", e);
+            }
+        }
+        StandbyReplication::Direct => {}
+        StandbyReplication::Store => match state.store.list_ledger() {
+            Ok(ledger) => state.privacy_tracker.restore_ledger(ledger).await,
+            Err(e) => log::error!("Failed to reload the privacy ledger on takeover: {}", e),
+        },
+    }
+    if let Err(e) = run_pair_command(&config.takeover_command, config.command_timeout_seconds).await
+    {
+        log::error!("Takeover command failed: {}", e);
+    }
+    let epoch = state.standby.promote(reason);
+    state
+        .monitoring
+        .raise_alert(
+            "standby_takeover",
+            format!(
+                "{} took over as active at epoch {}: {}",
+                config.node_id, epoch, reason
+            ),
+            2,
+        )
+        .await;
+    audit(
+        state,
+        "standby.promote",
+        &config.node_id,
+        serde_json::json!({ "epoch": epoch, "reason": reason }),
+    );
+}
+
+/// Stop serving and release the virtual IP
+pub(super) async fn step_down(state: &ProxyState, role: PairRole, reason: &str) {
+    let config = state.standby.config();
+    state.standby.demote(role, reason);
+    flush_privacy_ledger(state).await;
+    if let Err(e) = run_pair_command(&config.release_command, config.command_timeout_seconds).await
+    {
+        log::error!("Release command failed: {}", e);
+    }
+    audit(
+        state,
+        "standby.demote",
+        &config.node_id,
+        serde_json::json!({ "role": role, "reason": reason }),
+    );
+}
+
+async fn run_pair_command(argv: &[String], timeout_seconds: u64) -> Result<()> {
+    let Some((program, args)) = argv.split_first() else {
+        return Ok(());
+    };
+    let run = tokio::process::Command::new(program)
+        .args(args)
+        .kill_on_drop(true)
+        .status();
+    let status = tokio::time::timeout(Duration::from_secs(timeout_seconds), run)
+        .await
+        .map_err(|_| Error::Timeout(format!("{} did not finish in time", program)))??;
+    if !status.success() {
+        return Err(Error::Internal(format!(
+            "{} exited with {}",
+            program, status
+        )));
+    }
+    Ok(())
+}
+
+pub(super) async fn get_standby_status(
+    State(state): State<Arc<ProxyState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "standby": state.standby.status() }))
+}
+
+pub(super) async fn get_standby_heartbeat(State(state): State<Arc<ProxyState>>) -> Json<Heartbeat> {
+    Json(state.standby.heartbeat())
+}
+
+/// Sessions and privacy budgets for the standby to follow
+pub(super) async fn export_standby_state(
+    State(state): State<Arc<ProxyState>>,
+) -> std::result::Result<Json<HandoffState>, StatusCode> {
+    let sessions = state.store.list_sessions().map_err(|e| {
+        log::warn!("Failed to list sessions for the standby: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(HandoffState {
+        epoch: state.standby.heartbeat().epoch,
+        sessions,
+        ledger: state.privacy_tracker.snapshot_ledger().await,
+    }))
+}
+
+/// Planned switchover: stop serving and let the standby take over; admins only
+pub(super) async fn hand_off_standby(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if !state.standby.is_enabled() || state.standby.role() != PairRole::Active {
+        return Err(StatusCode::CONFLICT);
+    }
+    step_down(
+        &state,
+        PairRole::HandingOff,
+        &format!("handoff requested by {}", admin),
+    )
+    .await;
+    Ok(Json(
+        serde_json::json!({ "standby": state.standby.status() }),
+    ))
+}
+
+/// Force this instance active, e.g. after replacing a failed peer; admins only
+pub(super) async fn promote_standby(
+    State(state): State<Arc<ProxyState>>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    if !state.standby.is_enabled() || state.standby.role() == PairRole::Active {
+        return Err(StatusCode::CONFLICT);
+    }
+    take_over(&state, &format!("promoted by {}", admin)).await;
+    Ok(Json(
+        serde_json::json!({ "standby": state.standby.status() }),
+    ))
+}
@@ -0,0 +1,460 @@
+//! Tenant configuration and offboarding
+
+use super::identity::admin_name;
+use super::{audit, tenant_id, CacheInvalidationRequest, EvictedSession, ProxyState};
+use crate::access::ADMIN_TOKEN_HEADER;
+use crate::error::Result;
+use crate::offboarding::{
+    self, DestructionReport, SignedDestructionReport, OFFBOARDED_AUDIT_ACTION,
+};
+use crate::persistence;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Request to offboard a tenant
+#[derive(Debug, Default, Deserialize)]
+pub struct OffboardTenantRequest {
+    pub reason: Option<String>,
+    /// Token issued by a first request without one; nothing is destroyed until
+    /// it is presented
+    pub confirmation: Option<String>,
+}
+
+fn tenant_config_view(
+    state: &ProxyState,
+    tenant: &str,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let tenant_config = state.tenant_configs.resolve(tenant).map_err(|e| {
+        log::warn!("Failed to resolve tenant config for {}: {}", tenant, e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    Ok(Json(serde_json::to_value(&*tenant_config).unwrap()))
+}
+
+/// Get the effective configuration for a tenant; admins read any tenant's,
+/// a tenant only its own
+pub(super) async fn get_tenant_config(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    if headers.contains_key(ADMIN_TOKEN_HEADER) {
+        admin_name(&state, &headers)?;
+    } else {
+        let caller = tenant_id(&headers).ok_or(StatusCode::UNAUTHORIZED)?;
+        if caller != tenant {
+            log::warn!("Refused {}'s tenant config to {}", tenant, caller);
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    tenant_config_view(&state, &tenant)
+}
+
+/// Drop the cached tenant configuration so override documents are re-read;
+/// admins only
+pub(super) async fn refresh_tenant_config(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    state.tenant_configs.invalidate(&tenant);
+    audit(
+        &state,
+        "tenant_config.refresh",
+        &tenant,
+        serde_json::json!({ "admin": admin }),
+    );
+    tenant_config_view(&state, &tenant)
+}
+
+/// Delete everything `tenant` left behind, in the order keys, ciphertexts,
+/// caches, contexts, budgets, persistence, auditing each step; then run the
+/// deletions again as verification and sign the report
+async fn offboard_tenant(
+    state: &ProxyState,
+    tenant: &str,
+    reason: Option<String>,
+) -> Result<SignedDestructionReport> {
+    // Refused from here on, so nothing new is written for the tenant meanwhile
+    state.offboarding.mark_offboarded(tenant);
+    let mut report = DestructionReport::new(tenant, reason, chrono::Utc::now().timestamp());
+    let record = |report: &mut DestructionReport, step: &str, store: &str, deleted| {
+        let step = report.record(step, store, deleted, chrono::Utc::now().timestamp());
+        audit(
+            state,
+            &format!("tenant.offboarding.{}", step.step),
+            tenant,
+            serde_json::to_value(step).unwrap_or_default(),
+        );
+    };
+
+    let (closed, keys) = close_tenant_sessions(state, tenant).await;
+    record(
+        &mut report,
+        "sessions",
+        "memory",
+        offboarding::counts([("sessions", closed.len()), ("keys", keys)]),
+    );
+
+    let (ciphertexts, responses, documents) = purge_tenant_ciphertexts(state, tenant).await;
+    record(
+        &mut report,
+        "ciphertexts",
+        "memory",
+        offboarding::counts([
+            ("ciphertexts", ciphertexts),
+            ("chunked_responses", responses),
+            ("document_jobs", documents),
+        ]),
+    );
+
+    let cache_filter = CacheInvalidationRequest {
+        tenant: Some(tenant.to_string()),
+        ..Default::default()
+    };
+    let cached = state.response_cache.invalidate(&cache_filter).await;
+    record(
+        &mut report,
+        "caches",
+        "memory",
+        offboarding::counts([("cached_completions", cached)]),
+    );
+
+    let contexts = state.conversations.purge_tenant(tenant)?;
+    let tiers = format!(
+        "memory, {}, {}",
+        state.store.name(),
+        state.conversations.stats().archive_backend
+    );
+    record(
+        &mut report,
+        "contexts",
+        &tiers,
+        offboarding::counts([
+            ("hot_turns", contexts.hot),
+            ("warm_turns", contexts.warm),
+            ("archived_turns", contexts.archive),
+        ]),
+    );
+
+    let privacy = state
+        .privacy_tracker
+        .forget_users(|user| persistence::is_tenant_key(tenant, user))
+        .await;
+    let spending = state.spending_caps.forget(tenant);
+    record(
+        &mut report,
+        "budgets",
+        "memory",
+        offboarding::counts([
+            ("privacy_budgets", privacy),
+            ("spending_caps", spending as usize),
+        ]),
+    );
+
+    let session_ids: Vec<Uuid> = closed.iter().map(|s| s.session_id).collect();
+    let purged = state.store.purge_tenant(tenant, &session_ids)?;
+    record(
+        &mut report,
+        "persistence",
+        state.store.name(),
+        purged.counts(),
+    );
+
+    // Anything found now was written while the steps above ran
+    let (closed, keys) = close_tenant_sessions(state, tenant).await;
+    let (ciphertexts, responses, documents) = purge_tenant_ciphertexts(state, tenant).await;
+    let contexts = state.conversations.purge_tenant(tenant)?;
+    let mut found = offboarding::counts([
+        ("sessions.sessions", closed.len()),
+        ("sessions.keys", keys),
+        ("ciphertexts.ciphertexts", ciphertexts),
+        ("ciphertexts.chunked_responses", responses),
+        ("ciphertexts.document_jobs", documents),
+        (
+            "caches.cached_completions",
+            state.response_cache.invalidate(&cache_filter).await,
+        ),
+        ("contexts.hot_turns", contexts.hot),
+        ("contexts.warm_turns", contexts.warm),
+        ("contexts.archived_turns", contexts.archive),
+        (
+            "budgets.privacy_budgets",
+            state
+                .privacy_tracker
+                .forget_users(|user| persistence::is_tenant_key(tenant, user))
+                .await,
+        ),
+        (
+            "budgets.spending_caps",
+            state.spending_caps.forget(tenant) as usize,
+        ),
+    ]);
+    let session_ids: Vec<Uuid> = closed.iter().map(|s| s.session_id).collect();
+    for (kind, count) in state.store.purge_tenant(tenant, &session_ids)?.counts() {
+        found.insert(format!("persistence.{}", kind), count);
+    }
+    report.verify(found, chrono::Utc::now().timestamp());
+    if !report.verified {
+        log::warn!(
+            "Offboarding {} left data behind, offboard again to remove it: {:?}",
+            tenant,
+            report.remaining
+        );
+    }
+
+    let signed = state.attestation.sign_destruction_report(&report)?;
+    audit(
+        state,
+        OFFBOARDED_AUDIT_ACTION,
+        tenant,
+        serde_json::to_value(&signed)?,
+    );
+    log::info!(
+        "Offboarded tenant {}: {} items deleted",
+        tenant,
+        report.total_deleted
+    );
+    Ok(signed)
+}
+
+/// Close a tenant's sessions and drop their keys, returning the sessions and
+/// how many keys were dropped
+async fn close_tenant_sessions(state: &ProxyState, tenant: &str) -> (Vec<EvictedSession>, usize) {
+    let closed = state.session_manager.close_tenant(tenant).await;
+    let mut keys = 0;
+    let mut fhe_engine = state.fhe_engine.write().await;
+    for session in &closed {
+        keys += fhe_engine.client_keys.remove(&session.client_id).is_some() as usize;
+        keys += fhe_engine.server_keys.remove(&session.server_id).is_some() as usize;
+        if let Some(previous) = session.previous_server_id {
+            keys += fhe_engine.server_keys.remove(&previous).is_some() as usize;
+        }
+        state.key_policies.forget(session.client_id).await;
+    }
+    (closed, keys)
+}
+
+/// Delete the ciphertexts produced for a tenant, its chunked responses and
+/// document jobs, returning how many of each were dropped
+async fn purge_tenant_ciphertexts(state: &ProxyState, tenant: &str) -> (usize, usize, usize) {
+    let documents = state.documents.remove_tenant(tenant).await;
+    let mut ids: HashSet<Uuid> = state
+        .offboarding
+        .take_ciphertexts(tenant)
+        .into_iter()
+        .collect();
+    for job in &documents {
+        ids.extend(job.chunks.iter().map(|chunk| chunk.ciphertext_id));
+        ids.extend(job.aggregate_ciphertext_id);
+    }
+    let mut cache = state.ciphertext_cache.write().await;
+    let ciphertexts = ids.iter().filter(|id| cache.remove(id).is_some()).count();
+    drop(cache);
+    let responses = state.response_chunks.forget(&ids).await;
+    (ciphertexts, responses, documents.len())
+}
+
+/// Offboard a tenant and return the signed destruction report; admins only.
+/// Without a confirmation token the tenant is left alone and a token is issued.
+pub(super) async fn offboard_tenant_handler(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<OffboardTenantRequest>>,
+) -> std::result::Result<Response, StatusCode> {
+    let admin = admin_name(&state, &headers)?;
+    let request = body.map(|Json(body)| body).unwrap_or_default();
+    let now = chrono::Utc::now().timestamp();
+    let Some(confirmation) = request.confirmation else {
+        let (confirmation, expires_at) = state.offboarding.issue_confirmation(&tenant, now);
+        audit(
+            &state,
+            "tenant.offboarding.requested",
+            &tenant,
+            serde_json::json!({ "admin": admin, "expires_at": expires_at }),
+        );
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({
+                "tenant": tenant,
+                "confirmation": confirmation,
+                "expires_at": expires_at,
+            })),
+        )
+            .into_response());
+    };
+    if !state.offboarding.confirm(&tenant, &confirmation, now) {
+        log::warn!(
+            "Refused offboarding of {} with a stale confirmation",
+            tenant
+        );
+        return Err(StatusCode::CONFLICT);
+    }
+    audit(
+        &state,
+        "tenant.offboarding.confirmed",
+        &tenant,
+        serde_json::json!({ "admin": admin }),
+    );
+
+    let report = offboard_tenant(&state, &tenant, request.reason)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to offboard tenant {}: {}", tenant, e);
+            audit(
+                &state,
+                "tenant.offboarding.failed",
+                &tenant,
+                serde_json::json!({ "error": e.to_string() }),
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(report).into_response())
+}
+
+/// The signed destruction report of a tenant's latest offboarding
+pub(super) async fn get_offboarding_report(
+    State(state): State<Arc<ProxyState>>,
+    Path(tenant): Path<String>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    let records = state.store.list_audit().map_err(|e| {
+        log::error!("Failed to read audit log: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    records
+        .into_iter()
+        .filter(|r| r.action == OFFBOARDED_AUDIT_ACTION && r.subject == tenant)
+        .max_by_key(|r| r.timestamp)
+        .map(|r| Json(r.details))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::etag;
+    use crate::persistence::BillingRecord;
+    use crate::proxy::ciphertexts::{encrypt_text, EncryptRequest};
+    use crate::proxy::{CachedCompletion, ProxyServer, ResponseCache};
+    use axum::http::HeaderMap;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_tenant_offboarding_destroys_data_and_signs_report() {
+        let state = ProxyServer::new(Config::default()).unwrap().state;
+        let acme = state.tenant_configs.resolve("acme").unwrap();
+        let (client_id, server_id) = state.fhe_engine.write().await.generate_keys().unwrap();
+        state
+            .session_manager
+            .create_session(&acme, None, client_id, server_id)
+            .await
+            .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        let Json(encrypted) = encrypt_text(
+            State(state.clone()),
+            headers,
+            Json(EncryptRequest {
+                text: "Quarterly numbers".to_string(),
+                client_id: Some(client_id),
+                params: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        let cached = |tenant: &str| CachedCompletion {
+            tenant: Some(tenant.to_string()),
+            model: "gpt-4".to_string(),
+            template_id: None,
+            tags: Vec::new(),
+            response: serde_json::json!({}),
+            ciphertexts: Vec::new(),
+            etag: etag::digest_etag(&[tenant.as_bytes()]),
+            recompute_cost_ms: 10,
+            stored_at: Instant::now(),
+            expires_at: Instant::now() + Duration::from_secs(60),
+            stale_until: Instant::now() + Duration::from_secs(60),
+        };
+        for tenant in ["acme", "globex"] {
+            let key = ResponseCache::key(Some(tenant), "gpt-4", None, b"a");
+            state.response_cache.insert(key, cached(tenant)).await;
+            state
+                .store
+                .put_billing(&BillingRecord {
+                    request_id: Uuid::new_v4(),
+                    tenant: Some(tenant.to_string()),
+                    route: "/v1/chat/completions".to_string(),
+                    recorded_at: 0,
+                    request_bytes: 10,
+                    response_bytes: 10,
+                    response_wire_bytes: 10,
+                    duration_ms: None,
+                })
+                .unwrap();
+        }
+
+        let signed = offboard_tenant(&state, "acme", Some("contract ended".to_string()))
+            .await
+            .unwrap();
+        let report = &signed.report;
+        assert!(report.verified, "{:?}", report.remaining);
+        let deleted = |step: &str, kind: &str| {
+            report
+                .steps
+                .iter()
+                .find(|s| s.step == step)
+                .unwrap()
+                .deleted[kind]
+        };
+        assert_eq!(deleted("sessions", "sessions"), 1);
+        assert_eq!(deleted("sessions", "keys"), 2);
+        assert_eq!(deleted("ciphertexts", "ciphertexts"), 1);
+        assert_eq!(deleted("caches", "cached_completions"), 1);
+        assert_eq!(deleted("persistence", "billing"), 1);
+
+        assert!(!state
+            .fhe_engine
+            .read()
+            .await
+            .client_keys
+            .contains_key(&client_id));
+        assert!(!state
+            .ciphertext_cache
+            .read()
+            .await
+            .contains_key(&encrypted.ciphertext_id));
+        let globex = ResponseCache::key(Some("globex"), "gpt-4", None, b"a");
+        assert!(state.response_cache.get(&globex).await.is_some());
+        assert_eq!(state.store.list_billing(0, 1).unwrap().len(), 1);
+        assert!(state.offboarding.is_offboarded("acme"));
+
+        // Every step is audited, and the signed report can be read back
+        let audit = state.store.list_audit().unwrap();
+        for step in &report.steps {
+            let action = format!("tenant.offboarding.{}", step.step);
+            assert!(audit.iter().any(|r| r.action == action));
+        }
+        let Json(stored) = get_offboarding_report(State(state.clone()), Path("acme".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(stored["signature"], signed.signature);
+        assert!(
+            get_offboarding_report(State(state), Path("globex".to_string()))
+                .await
+                .is_err()
+        );
+    }
+}
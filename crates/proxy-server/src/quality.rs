@@ -0,0 +1,372 @@
+//! Output quality monitoring from client-reported scores
+//!
+//! The proxy never sees decrypted completions, so quality is judged by the
+//! clients that do. Each completion is tracked under its `x-request-id` for
+//! `score_window_seconds`; within that window the tenant that made the request
+//! may score it once, with a thumbs up or down, a task outcome or a value
+//! between 0 and 1. Scores are aggregated per model, template and provider.
+//!
+//! A model whose mean score falls below `downrank_below` after `min_scores`
+//! scores is downranked: requests for it are routed to the first of its
+//! configured alternatives that is not downranked itself.
+
+use crate::config::QualityConfig;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// A client's judgement of one completion
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QualitySignal {
+    Thumbs { up: bool },
+    TaskSuccess { succeeded: bool },
+    Score { value: f64 },
+}
+
+impl QualitySignal {
+    /// The signal as a score between 0 and 1
+    pub fn value(&self) -> Result<f64> {
+        match *self {
+            Self::Thumbs { up } | Self::TaskSuccess { succeeded: up } => {
+                Ok(if up { 1.0 } else { 0.0 })
+            }
+            Self::Score { value } if (0.0..=1.0).contains(&value) => Ok(value),
+            Self::Score { .. } => Err(Error::Validation(
+                "Score must be between 0.0 and 1.0".to_string(),
+            )),
+        }
+    }
+}
+
+/// What a score is aggregated under
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct QualityKey {
+    pub model: String,
+    pub template_id: Option<String>,
+    pub provider: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QualityStats {
+    pub scores: u64,
+    pub mean_score: f64,
+    pub thumbs_up: u64,
+    pub thumbs_down: u64,
+    pub task_successes: u64,
+    pub task_failures: u64,
+    #[serde(skip)]
+    score_sum: f64,
+}
+
+impl QualityStats {
+    fn add(&mut self, signal: QualitySignal, value: f64) {
+        self.scores += 1;
+        self.score_sum += value;
+        self.mean_score = self.score_sum / self.scores as f64;
+        match signal {
+            QualitySignal::Thumbs { up: true } => self.thumbs_up += 1,
+            QualitySignal::Thumbs { up: false } => self.thumbs_down += 1,
+            QualitySignal::TaskSuccess { succeeded: true } => self.task_successes += 1,
+            QualitySignal::TaskSuccess { succeeded: false } => self.task_failures += 1,
+            QualitySignal::Score { .. } => {}
+        }
+    }
+
+    fn merge(&mut self, other: &QualityStats) {
+        self.scores += other.scores;
+        self.score_sum += other.score_sum;
+        self.mean_score = self.score_sum / self.scores.max(1) as f64;
+        self.thumbs_up += other.thumbs_up;
+        self.thumbs_down += other.thumbs_down;
+        self.task_successes += other.task_successes;
+        self.task_failures += other.task_failures;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityEntry {
+    #[serde(flatten)]
+    pub key: QualityKey,
+    #[serde(flatten)]
+    pub stats: QualityStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelQuality {
+    pub model: String,
+    #[serde(flatten)]
+    pub stats: QualityStats,
+    /// Whether requests for the model are routed to an alternative
+    pub downranked: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityReport {
+    pub models: Vec<ModelQuality>,
+    pub entries: Vec<QualityEntry>,
+    /// Completions still open for scoring
+    pub awaiting_scores: usize,
+}
+
+#[derive(Debug, Clone)]
+struct TrackedCompletion {
+    tenant: Option<String>,
+    key: QualityKey,
+    served_at: i64,
+}
+
+#[derive(Debug, Default)]
+struct Tracked {
+    completions: HashMap<Uuid, TrackedCompletion>,
+    /// Request ids in the order they were served
+    order: VecDeque<Uuid>,
+}
+
+#[derive(Debug)]
+pub struct QualityMonitor {
+    config: QualityConfig,
+    tracked: RwLock<Tracked>,
+    stats: RwLock<BTreeMap<QualityKey, QualityStats>>,
+}
+
+impl QualityMonitor {
+    pub fn new(config: QualityConfig) -> Self {
+        Self {
+            config,
+            tracked: RwLock::new(Tracked::default()),
+            stats: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Open a served completion for scoring
+    pub fn record_completion(
+        &self,
+        request_id: Uuid,
+        tenant: Option<&str>,
+        key: QualityKey,
+        now: i64,
+    ) {
+        let mut tracked = self.tracked.write().unwrap();
+        self.expire(&mut tracked, now);
+        while tracked.order.len() >= self.config.max_tracked_requests {
+            let Some(oldest) = tracked.order.pop_front() else {
+                break;
+            };
+            tracked.completions.remove(&oldest);
+        }
+        tracked.order.push_back(request_id);
+        tracked.completions.insert(
+            request_id,
+            TrackedCompletion {
+                tenant: tenant.map(str::to_string),
+                key,
+                served_at: now,
+            },
+        );
+    }
+
+    /// Score a completion served to `tenant`, returning what the score was
+    /// aggregated under, or None when no such completion is open for scoring
+    pub fn submit(
+        &self,
+        request_id: Uuid,
+        tenant: Option<&str>,
+        signal: QualitySignal,
+        now: i64,
+    ) -> Result<Option<QualityKey>> {
+        let value = signal.value()?;
+        let completion = {
+            let mut tracked = self.tracked.write().unwrap();
+            self.expire(&mut tracked, now);
+            match tracked.completions.get(&request_id) {
+                Some(completion) if completion.tenant.as_deref() == tenant => {}
+                _ => return Ok(None),
+            }
+            tracked.order.retain(|id| *id != request_id);
+            tracked.completions.remove(&request_id).unwrap()
+        };
+        self.stats
+            .write()
+            .unwrap()
+            .entry(completion.key.clone())
+            .or_default()
+            .add(signal, value);
+        Ok(Some(completion.key))
+    }
+
+    fn expire(&self, tracked: &mut Tracked, now: i64) {
+        let cutoff = now - self.config.score_window_seconds as i64;
+        while let Some(id) = tracked.order.front().copied() {
+            if tracked
+                .completions
+                .get(&id)
+                .is_some_and(|c| c.served_at > cutoff)
+            {
+                break;
+            }
+            tracked.order.pop_front();
+            tracked.completions.remove(&id);
+        }
+    }
+
+    /// Scores of every template and provider a model was served through
+    pub fn model_stats(&self, model: &str) -> QualityStats {
+        let mut total = QualityStats::default();
+        for (_, stats) in self
+            .stats
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| key.model == model)
+        {
+            total.merge(stats);
+        }
+        total
+    }
+
+    pub fn is_downranked(&self, model: &str) -> bool {
+        let stats = self.model_stats(model);
+        self.config.enabled
+            && stats.scores >= self.config.min_scores
+            && stats.mean_score < self.config.downrank_below
+    }
+
+    /// The model to serve instead of a downranked `model`: its first
+    /// alternative that `allowed` accepts and that is not downranked itself
+    pub fn reroute(&self, model: &str, allowed: impl Fn(&str) -> bool) -> Option<String> {
+        if !self.is_downranked(model) {
+            return None;
+        }
+        self.config
+            .alternatives
+            .get(model)?
+            .iter()
+            .find(|alternative| !self.is_downranked(alternative) && allowed(alternative))
+            .cloned()
+    }
+
+    pub fn report(&self, now: i64) -> QualityReport {
+        let awaiting_scores = {
+            let mut tracked = self.tracked.write().unwrap();
+            self.expire(&mut tracked, now);
+            tracked.completions.len()
+        };
+        let entries: Vec<QualityEntry> = self
+            .stats
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, stats)| QualityEntry {
+                key: key.clone(),
+                stats: stats.clone(),
+            })
+            .collect();
+        let mut models: BTreeMap<&str, QualityStats> = BTreeMap::new();
+        for entry in &entries {
+            models
+                .entry(entry.key.model.as_str())
+                .or_default()
+                .merge(&entry.stats);
+        }
+        let models = models
+            .into_iter()
+            .map(|(model, stats)| ModelQuality {
+                model: model.to_string(),
+                downranked: self.is_downranked(model),
+                stats,
+            })
+            .collect();
+        QualityReport {
+            models,
+            entries,
+            awaiting_scores,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(model: &str, template: Option<&str>, provider: &str) -> QualityKey {
+        QualityKey {
+            model: model.to_string(),
+            template_id: template.map(str::to_string),
+            provider: provider.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_scores_aggregate_and_downrank_models() {
+        let monitor = QualityMonitor::new(QualityConfig {
+            enabled: true,
+            score_window_seconds: 60,
+            max_tracked_requests: 3,
+            min_scores: 3,
+            downrank_below: 0.5,
+            alternatives: HashMap::from([(
+                "gpt-4".to_string(),
+                vec!["gpt-4o".to_string(), "claude-3".to_string()],
+            )]),
+        });
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            let template = (i % 2 == 0).then_some("summarize");
+            monitor.record_completion(*id, Some("acme"), key("gpt-4", template, "openai"), 0);
+        }
+
+        // The oldest completion was dropped to stay within the cap
+        let up = QualitySignal::Thumbs { up: true };
+        assert_eq!(monitor.submit(ids[0], Some("acme"), up, 1).unwrap(), None);
+        // Only the tenant the completion was served to may score it, once
+        assert_eq!(monitor.submit(ids[1], Some("globex"), up, 1).unwrap(), None);
+        assert!(monitor
+            .submit(ids[1], Some("acme"), QualitySignal::Score { value: 1.5 }, 1)
+            .is_err());
+        let failed = QualitySignal::TaskSuccess { succeeded: false };
+        assert_eq!(
+            monitor.submit(ids[1], Some("acme"), failed, 1).unwrap(),
+            Some(key("gpt-4", None, "openai"))
+        );
+        assert_eq!(monitor.submit(ids[1], Some("acme"), up, 1).unwrap(), None);
+        monitor
+            .submit(ids[2], Some("acme"), QualitySignal::Score { value: 0.4 }, 2)
+            .unwrap();
+        assert!(!monitor.is_downranked("gpt-4"));
+        // Scores arriving after the window are refused
+        assert_eq!(monitor.submit(ids[3], Some("acme"), up, 61).unwrap(), None);
+
+        let down = QualitySignal::Thumbs { up: false };
+        let late = Uuid::new_v4();
+        monitor.record_completion(late, Some("acme"), key("gpt-4", None, "azure"), 70);
+        monitor.submit(late, Some("acme"), down, 71).unwrap();
+        assert!(monitor.is_downranked("gpt-4"));
+        assert!((monitor.model_stats("gpt-4").mean_score - 0.4 / 3.0).abs() < 1e-9);
+
+        // The first alternative the caller allows is picked
+        assert_eq!(
+            monitor.reroute("gpt-4", |_| true),
+            Some("gpt-4o".to_string())
+        );
+        assert_eq!(
+            monitor.reroute("gpt-4", |model| model != "gpt-4o"),
+            Some("claude-3".to_string())
+        );
+        assert_eq!(monitor.reroute("claude-3", |_| true), None);
+
+        let report = monitor.report(72);
+        assert_eq!(report.awaiting_scores, 0);
+        assert_eq!(report.entries.len(), 3);
+        assert_eq!(report.models.len(), 1);
+        assert!(report.models[0].downranked);
+        assert_eq!(report.models[0].stats.thumbs_down, 1);
+        assert_eq!(report.models[0].stats.task_failures, 1);
+    }
+}
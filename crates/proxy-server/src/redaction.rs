@@ -0,0 +1,234 @@
+//! Redaction policies for decrypted responses, enforced client-side
+//!
+//! The proxy never sees response plaintext, so it cannot redact anything
+//! itself. Instead each tenant registers a policy descriptor; the proxy
+//! versions it, signs the bundle with the response signing key and hands it to
+//! clients. The client verifies the bundle against the published JWKS, applies
+//! it after decryption and acknowledges the version it enforced, which is
+//! recorded for compliance.
+
+use crate::config::RedactionPolicyConfig;
+use crate::error::{Error, Result};
+use crate::security::ResponseSigner;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+pub use proxy_client::redaction::{
+    apply_redaction, verify_policy_bundle, PolicyBundle, RedactionPolicy, RedactionRule,
+    SignedPolicyBundle,
+};
+
+/// A client's statement that it enforces a policy version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyAcknowledgment {
+    pub tenant: String,
+    pub client_id: Uuid,
+    pub version: u64,
+    pub acknowledged_at: i64,
+}
+
+#[derive(Debug, Default)]
+struct TenantPolicies {
+    /// Oldest first; the last entry is current
+    bundles: VecDeque<SignedPolicyBundle>,
+    acknowledgments: VecDeque<PolicyAcknowledgment>,
+}
+
+/// Versioned, signed redaction policies per tenant
+#[derive(Debug)]
+pub struct RedactionPolicyRegistry {
+    config: RedactionPolicyConfig,
+    tenants: RwLock<HashMap<String, TenantPolicies>>,
+}
+
+impl RedactionPolicyRegistry {
+    pub fn new(config: RedactionPolicyConfig) -> Self {
+        Self {
+            config,
+            tenants: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Validate `policy` and publish it as the tenant's next version
+    pub fn publish(
+        &self,
+        tenant: &str,
+        policy: RedactionPolicy,
+        signer: &ResponseSigner,
+    ) -> Result<SignedPolicyBundle> {
+        if policy.rules.len() > self.config.max_rules {
+            return Err(Error::Validation(format!(
+                "Redaction policy has {} rules, the limit is {}",
+                policy.rules.len(),
+                self.config.max_rules
+            )));
+        }
+        for rule in &policy.rules {
+            if rule.id.is_empty() {
+                return Err(Error::Validation(
+                    "Redaction rule IDs must not be empty".to_string(),
+                ));
+            }
+            Regex::new(&rule.pattern).map_err(|e| {
+                Error::Validation(format!(
+                    "Invalid pattern in redaction rule {}: {}",
+                    rule.id, e
+                ))
+            })?;
+        }
+
+        let mut tenants = self.tenants.write().unwrap();
+        let policies = tenants.entry(tenant.to_string()).or_default();
+        let bundle = PolicyBundle {
+            tenant: tenant.to_string(),
+            version: policies.bundles.back().map_or(1, |b| b.bundle.version + 1),
+            issued_at: chrono::Utc::now().timestamp(),
+            policy,
+        };
+        let (key_id, signature) = signer.sign_detached(&serde_json::to_vec(&bundle)?);
+        let signed = SignedPolicyBundle {
+            bundle,
+            key_id,
+            algorithm: "EdDSA".to_string(),
+            signature,
+        };
+
+        policies.bundles.push_back(signed.clone());
+        while policies.bundles.len() > self.config.max_versions {
+            policies.bundles.pop_front();
+        }
+        Ok(signed)
+    }
+
+    /// The tenant's current bundle, or a retained earlier `version`
+    pub fn bundle(&self, tenant: &str, version: Option<u64>) -> Option<SignedPolicyBundle> {
+        let tenants = self.tenants.read().unwrap();
+        let bundles = &tenants.get(tenant)?.bundles;
+        match version {
+            Some(version) => bundles.iter().find(|b| b.bundle.version == version),
+            None => bundles.back(),
+        }
+        .cloned()
+    }
+
+    /// Record that `client_id` enforces `version` of the tenant's policy
+    pub fn acknowledge(
+        &self,
+        tenant: &str,
+        client_id: Uuid,
+        version: u64,
+    ) -> Result<PolicyAcknowledgment> {
+        let mut tenants = self.tenants.write().unwrap();
+        let policies = tenants
+            .get_mut(tenant)
+            .filter(|p| p.bundles.iter().any(|b| b.bundle.version == version))
+            .ok_or_else(|| {
+                Error::Validation(format!(
+                    "Tenant {} has no redaction policy version {}",
+                    tenant, version
+                ))
+            })?;
+
+        let acknowledgment = PolicyAcknowledgment {
+            tenant: tenant.to_string(),
+            client_id,
+            version,
+            acknowledged_at: chrono::Utc::now().timestamp(),
+        };
+        policies.acknowledgments.push_back(acknowledgment.clone());
+        while policies.acknowledgments.len() > self.config.max_acknowledgments {
+            policies.acknowledgments.pop_front();
+        }
+        Ok(acknowledgment)
+    }
+
+    /// Latest acknowledgment of every client, newest first
+    pub fn acknowledgments(&self, tenant: &str) -> Vec<PolicyAcknowledgment> {
+        let tenants = self.tenants.read().unwrap();
+        let Some(policies) = tenants.get(tenant) else {
+            return Vec::new();
+        };
+        let mut seen = std::collections::HashSet::new();
+        policies
+            .acknowledgments
+            .iter()
+            .rev()
+            .filter(|ack| seen.insert(ack.client_id))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResponseSigningConfig;
+
+    #[test]
+    fn test_signed_policy_bundles() {
+        let signer = ResponseSigner::new(&ResponseSigningConfig {
+            enabled: true,
+            ..ResponseSigningConfig::default()
+        })
+        .unwrap();
+        let registry = RedactionPolicyRegistry::new(RedactionPolicyConfig {
+            enabled: true,
+            max_versions: 2,
+            ..RedactionPolicyConfig::default()
+        });
+        let policy = RedactionPolicy {
+            rules: vec![RedactionRule {
+                id: "ssn".to_string(),
+                pattern: r"\d{3}-\d{2}-\d{4}".to_string(),
+                replacement: "[REDACTED]".to_string(),
+            }],
+        };
+
+        let first = registry.publish("acme", policy.clone(), &signer).unwrap();
+        let second = registry.publish("acme", policy.clone(), &signer).unwrap();
+        assert_eq!(second.bundle.version, 2);
+        assert_eq!(registry.bundle("acme", None).unwrap().bundle.version, 2);
+
+        let jwks = signer.jwks();
+        assert!(verify_policy_bundle(&second, &jwks, "acme").is_ok());
+        assert!(verify_policy_bundle(&second, &jwks, "globex").is_err());
+        let mut tampered = second.clone();
+        tampered.bundle.policy.rules.clear();
+        assert!(verify_policy_bundle(&tampered, &jwks, "acme").is_err());
+
+        let (text, count) =
+            apply_redaction(&second.bundle.policy, "SSN 123-45-6789 on file").unwrap();
+        assert_eq!(text, "SSN [REDACTED] on file");
+        assert_eq!(count, 1);
+
+        // Only retained versions can be acknowledged; the latest ack per client wins
+        let client = Uuid::new_v4();
+        registry.acknowledge("acme", client, 1).unwrap();
+        registry.acknowledge("acme", client, 2).unwrap();
+        assert!(registry.acknowledge("acme", client, 9).is_err());
+        let acks = registry.acknowledgments("acme");
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0].version, 2);
+
+        registry.publish("acme", policy, &signer).unwrap();
+        assert!(registry
+            .bundle("acme", Some(first.bundle.version))
+            .is_none());
+
+        let invalid = RedactionPolicy {
+            rules: vec![RedactionRule {
+                id: "bad".to_string(),
+                pattern: "(".to_string(),
+                replacement: "[REDACTED]".to_string(),
+            }],
+        };
+        assert!(registry.publish("acme", invalid, &signer).is_err());
+    }
+}
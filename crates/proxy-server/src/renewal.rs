@@ -0,0 +1,294 @@
+//! Session token refresh and key renewal protocol
+//!
+//! Sessions opened with new keys get a short-lived access token, which the
+//! client sends in `x-session-token`, and a longer-lived refresh token.
+//! Refreshing replaces both; the access token it replaces keeps working until
+//! its own expiry, so requests already in flight are not cut off. Renewing a
+//! session's server key issues a new one while the old key stays valid for an
+//! overlap window. As either expiry approaches, responses carry
+//! `x-renewal-hint` naming what to renew.
+//!
+//! The state lives on the session record, so every region that has replicated
+//! a session accepts its tokens, and a refresh in one region supersedes the
+//! older tokens everywhere once it has replicated.
+
+use crate::config::SessionRenewalConfig;
+use crate::error::{Error, Result};
+use crate::persistence::SessionRenewal;
+use base64::prelude::*;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use uuid::Uuid;
+
+pub const SESSION_TOKEN_HEADER: &str = "x-session-token";
+pub const TOKEN_EXPIRES_IN_HEADER: &str = "x-session-token-expires-in";
+pub const RENEWAL_HINT_HEADER: &str = "x-renewal-hint";
+
+/// Tokens handed to the client; only their hashes are kept
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedTokens {
+    pub access_token: String,
+    pub access_expires_at: i64,
+    pub refresh_token: String,
+    pub refresh_expires_at: i64,
+}
+
+/// Something the client should renew before it expires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenewalHint {
+    Token,
+    Keys,
+}
+
+impl RenewalHint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RenewalHint::Token => "token",
+            RenewalHint::Keys => "keys",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RenewalProtocol {
+    config: SessionRenewalConfig,
+    rng: SystemRandom,
+}
+
+impl RenewalProtocol {
+    pub fn new(config: SessionRenewalConfig) -> Self {
+        Self {
+            config,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &SessionRenewalConfig {
+        &self.config
+    }
+
+    /// Renewal state and first tokens of a session whose keys were issued at `now`
+    pub fn start(&self, session_id: Uuid, now: i64) -> Result<(SessionRenewal, IssuedTokens)> {
+        let tokens = self.issue(session_id, now)?;
+        let renewal = SessionRenewal {
+            generation: 1,
+            access_token_hash: token_hash(&tokens.access_token),
+            access_expires_at: tokens.access_expires_at,
+            refresh_token_hash: token_hash(&tokens.refresh_token),
+            refresh_expires_at: tokens.refresh_expires_at,
+            previous_access_token_hash: None,
+            previous_access_expires_at: 0,
+            key_expires_at: now + self.config.key_lifetime_seconds as i64,
+            previous_server_id: None,
+            previous_server_expires_at: 0,
+        };
+        Ok((renewal, tokens))
+    }
+
+    /// Trade `refresh_token` for a new pair of tokens; it cannot be used again
+    pub fn refresh(
+        &self,
+        session_id: Uuid,
+        renewal: &mut SessionRenewal,
+        refresh_token: &str,
+        now: i64,
+    ) -> Result<IssuedTokens> {
+        if token_hash(refresh_token) != renewal.refresh_token_hash {
+            return Err(Error::Auth("Unknown refresh token".to_string()));
+        }
+        if now >= renewal.refresh_expires_at {
+            return Err(Error::Auth("Refresh token has expired".to_string()));
+        }
+        let tokens = self.issue(session_id, now)?;
+        renewal.previous_access_token_hash = Some(std::mem::replace(
+            &mut renewal.access_token_hash,
+            token_hash(&tokens.access_token),
+        ));
+        renewal.previous_access_expires_at = renewal.access_expires_at;
+        renewal.access_expires_at = tokens.access_expires_at;
+        renewal.refresh_token_hash = token_hash(&tokens.refresh_token);
+        renewal.refresh_expires_at = tokens.refresh_expires_at;
+        renewal.generation += 1;
+        Ok(tokens)
+    }
+
+    /// Expiry of `access_token`, which must be the current token or the one
+    /// the last refresh replaced
+    pub fn authenticate(
+        &self,
+        renewal: &SessionRenewal,
+        access_token: &str,
+        now: i64,
+    ) -> Result<i64> {
+        let hash = token_hash(access_token);
+        let expires_at = if hash == renewal.access_token_hash {
+            renewal.access_expires_at
+        } else if renewal.previous_access_token_hash.as_deref() == Some(hash.as_str()) {
+            renewal.previous_access_expires_at
+        } else {
+            return Err(Error::Auth("Unknown session token".to_string()));
+        };
+        if now >= expires_at {
+            return Err(Error::Auth("Session token has expired".to_string()));
+        }
+        Ok(expires_at)
+    }
+
+    /// Record that `old_server_id` was replaced at `now`; it stays valid for
+    /// the overlap window. Returns when it stops being accepted, and any
+    /// earlier key whose overlap had closed but was not yet retired.
+    pub fn renew_key(
+        &self,
+        renewal: &mut SessionRenewal,
+        old_server_id: Uuid,
+        now: i64,
+    ) -> Result<(i64, Option<Uuid>)> {
+        let retired = retire_key(renewal, now);
+        if renewal.previous_server_id.is_some() {
+            return Err(Error::Validation(
+                "The previous server key is still within its overlap window".to_string(),
+            ));
+        }
+        renewal.previous_server_id = Some(old_server_id);
+        renewal.previous_server_expires_at = now + self.config.key_overlap_seconds as i64;
+        renewal.key_expires_at = now + self.config.key_lifetime_seconds as i64;
+        renewal.generation += 1;
+        Ok((renewal.previous_server_expires_at, retired))
+    }
+
+    /// What the client should renew soon, given when its access token expires
+    pub fn hints(
+        &self,
+        renewal: &SessionRenewal,
+        token_expires_at: i64,
+        now: i64,
+    ) -> Vec<RenewalHint> {
+        let mut hints = Vec::new();
+        if token_expires_at - now <= self.config.renewal_hint_seconds as i64 {
+            hints.push(RenewalHint::Token);
+        }
+        if renewal.key_expires_at - now <= self.config.key_overlap_seconds as i64 {
+            hints.push(RenewalHint::Keys);
+        }
+        hints
+    }
+
+    fn issue(&self, session_id: Uuid, now: i64) -> Result<IssuedTokens> {
+        Ok(IssuedTokens {
+            access_token: self.token(session_id)?,
+            access_expires_at: now + self.config.access_token_ttl_seconds as i64,
+            refresh_token: self.token(session_id)?,
+            refresh_expires_at: now + self.config.refresh_token_ttl_seconds as i64,
+        })
+    }
+
+    fn token(&self, session_id: Uuid) -> Result<String> {
+        let mut secret = [0u8; 32];
+        self.rng
+            .fill(&mut secret)
+            .map_err(|_| Error::Cryptographic("Failed to generate session token".to_string()))?;
+        Ok(format!(
+            "{}.{}",
+            session_id,
+            BASE64_URL_SAFE_NO_PAD.encode(secret)
+        ))
+    }
+}
+
+/// Server key whose overlap window closed by `now`, cleared from `renewal`
+pub fn retire_key(renewal: &mut SessionRenewal, now: i64) -> Option<Uuid> {
+    if renewal.previous_server_expires_at > now {
+        return None;
+    }
+    renewal.previous_server_id.take()
+}
+
+/// Session a token was issued for
+pub fn token_session(token: &str) -> Option<Uuid> {
+    let (session_id, _) = token.split_once('.')?;
+    Uuid::parse_str(session_id).ok()
+}
+
+fn token_hash(token: &str) -> String {
+    digest::digest(&digest::SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_and_key_renewal_overlap() {
+        let protocol = RenewalProtocol::new(SessionRenewalConfig {
+            enabled: true,
+            ..SessionRenewalConfig::default()
+        });
+        let session_id = Uuid::new_v4();
+        let (mut renewal, first) = protocol.start(session_id, 1000).unwrap();
+        assert_eq!(token_session(&first.access_token), Some(session_id));
+        assert_eq!(
+            protocol
+                .authenticate(&renewal, &first.access_token, 1000)
+                .unwrap(),
+            1900
+        );
+        assert!(protocol
+            .authenticate(&renewal, &first.refresh_token, 1000)
+            .is_err());
+        assert!(protocol.hints(&renewal, 1900, 1000).is_empty());
+        assert_eq!(
+            protocol.hints(&renewal, 1900, 1800),
+            vec![RenewalHint::Token]
+        );
+
+        // After a refresh both access tokens work until their own expiry, and
+        // the spent refresh token is refused
+        let second = protocol
+            .refresh(session_id, &mut renewal, &first.refresh_token, 1800)
+            .unwrap();
+        assert!(protocol
+            .authenticate(&renewal, &first.access_token, 1850)
+            .is_ok());
+        assert!(protocol
+            .authenticate(&renewal, &first.access_token, 1900)
+            .is_err());
+        assert_eq!(
+            protocol
+                .authenticate(&renewal, &second.access_token, 1900)
+                .unwrap(),
+            2700
+        );
+        assert!(protocol
+            .refresh(session_id, &mut renewal, &first.refresh_token, 1900)
+            .is_err());
+        assert_eq!(renewal.generation, 2);
+
+        // Keys are hinted within the overlap of their expiry; the old key is
+        // retired only once the overlap has passed
+        let near_expiry = renewal.key_expires_at - 100;
+        assert!(protocol
+            .hints(&renewal, near_expiry + 900, near_expiry)
+            .contains(&RenewalHint::Keys));
+        let old_key = Uuid::new_v4();
+        let (valid_until, retired) = protocol
+            .renew_key(&mut renewal, old_key, near_expiry)
+            .unwrap();
+        assert_eq!((valid_until, retired), (near_expiry + 300, None));
+        assert!(protocol
+            .renew_key(&mut renewal, Uuid::new_v4(), near_expiry + 10)
+            .is_err());
+        assert_eq!(retire_key(&mut renewal, near_expiry + 299), None);
+        assert_eq!(retire_key(&mut renewal, valid_until), Some(old_key));
+        assert_eq!(renewal.generation, 3);
+    }
+}
@@ -0,0 +1,327 @@
+//! Request journals for customer support
+//!
+//! Every journaled request gets an id, returned in `x-request-id`, and a
+//! timeline of what happened to it: admission, cache lookups, routing, the FHE
+//! engine that processed it, each provider attempt and retry, deadline budget
+//! failures and the final status. Journals are kept in memory on the replica
+//! that served the request for `retention_seconds`, and support staff holding
+//! a support token read them by request id.
+//!
+//! Journals are redacted by construction: events are typed and carry ids,
+//! states, counts, durations, status codes, error classes and the names of
+//! providers, models and engines, never free text. Nothing in an event can
+//! hold prompt or response content, ciphertexts, keys, headers, client
+//! addresses or provider error bodies. Code deeper in a request records into
+//! its journal through a task-local, the way outgoing calls find their trace.
+
+use crate::config::{RequestJournalConfig, SupportToken};
+use crate::fhe::EngineFingerprint;
+use crate::provider_errors::ProviderErrorClass;
+use ring::digest;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+tokio::task_local! {
+    static CURRENT_JOURNAL: JournalHandle;
+}
+
+/// Run `future` with `handle` as the journal events are recorded into
+pub async fn scope<F: std::future::Future>(handle: JournalHandle, future: F) -> F::Output {
+    CURRENT_JOURNAL.scope(handle, future).await
+}
+
+/// Record `event` in the current request's journal, if it has one
+pub fn record(event: JournalEvent) {
+    let _ = CURRENT_JOURNAL.try_with(|handle| handle.record(event));
+}
+
+/// Id of the current request, if it is journaled
+pub fn current_request_id() -> Option<Uuid> {
+    CURRENT_JOURNAL.try_with(|handle| handle.request_id).ok()
+}
+
+/// How a provider call ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptOutcome {
+    /// The provider answered, with `status`
+    Responded,
+    TimedOut,
+    /// Cut short by the request's deadline
+    DeadlineExceeded,
+    ConnectionReset,
+    Failed,
+}
+
+/// Something that happened to a request
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEvent {
+    Admitted {
+        priority: &'static str,
+        /// Time spent spilled to the overflow queue, if it was
+        overflow_wait_ms: Option<u64>,
+    },
+    Cache {
+        /// `hit`, `stale` or `miss`
+        status: &'static str,
+    },
+    Routed {
+        provider: String,
+        model: String,
+    },
+    ExecutionSlot {
+        waited_ms: u64,
+    },
+    FheProcessed {
+        engine: EngineFingerprint,
+        duration_ms: u64,
+        packed: bool,
+    },
+    /// One round trip to a provider; re-issues after a connection reset and
+    /// retries are attempts of their own
+    ProviderAttempt {
+        /// Host of the provider URL, without credentials or path
+        host: Option<String>,
+        model: String,
+        outcome: AttemptOutcome,
+        status: Option<u16>,
+        duration_ms: u64,
+    },
+    ProviderRetry {
+        retry: u32,
+        class: ProviderErrorClass,
+        delay_ms: u64,
+    },
+    DeadlineExceeded {
+        hop: &'static str,
+    },
+}
+
+/// An event and when it happened, relative to the request's arrival
+#[derive(Debug, Clone, Serialize)]
+pub struct TimedEvent {
+    pub offset_ms: u64,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalOutcome {
+    pub status: u16,
+    pub duration_ms: u64,
+}
+
+/// Timeline of one request
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEntry {
+    pub request_id: Uuid,
+    pub tenant: Option<String>,
+    pub method: String,
+    /// Route template where the router matched one, else the path
+    pub route: String,
+    pub received_at: i64,
+    pub events: Vec<TimedEvent>,
+    /// Events beyond `max_events_per_request`, not kept
+    pub dropped_events: u64,
+    /// `None` while the request is in flight
+    pub outcome: Option<JournalOutcome>,
+}
+
+/// Records into one request's journal
+#[derive(Debug, Clone)]
+pub struct JournalHandle {
+    request_id: Uuid,
+    received: Instant,
+    max_events: usize,
+    entry: Arc<Mutex<JournalEntry>>,
+}
+
+impl JournalHandle {
+    pub fn request_id(&self) -> Uuid {
+        self.request_id
+    }
+
+    pub fn record(&self, event: JournalEvent) {
+        let offset_ms = self.received.elapsed().as_millis() as u64;
+        let mut entry = self.entry.lock().unwrap();
+        if entry.events.len() < self.max_events {
+            entry.events.push(TimedEvent { offset_ms, event });
+        } else {
+            entry.dropped_events += 1;
+        }
+    }
+
+    pub fn finish(&self, status: u16) {
+        self.entry.lock().unwrap().outcome = Some(JournalOutcome {
+            status,
+            duration_ms: self.received.elapsed().as_millis() as u64,
+        });
+    }
+}
+
+#[derive(Debug, Default)]
+struct Journals {
+    entries: HashMap<Uuid, Arc<Mutex<JournalEntry>>>,
+    /// Arrival order, for expiry
+    order: VecDeque<(Instant, Uuid)>,
+}
+
+/// Journals of this replica's recent requests
+#[derive(Debug)]
+pub struct RequestJournal {
+    config: RequestJournalConfig,
+    journals: Mutex<Journals>,
+}
+
+impl RequestJournal {
+    pub fn new(config: RequestJournalConfig) -> Self {
+        Self {
+            config,
+            journals: Mutex::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Start the journal of a newly arrived request
+    pub fn open(&self, tenant: Option<&str>, method: &str, route: &str) -> JournalHandle {
+        let request_id = Uuid::new_v4();
+        let received = Instant::now();
+        let entry = Arc::new(Mutex::new(JournalEntry {
+            request_id,
+            tenant: tenant.map(str::to_string),
+            method: method.to_string(),
+            route: route.to_string(),
+            received_at: chrono::Utc::now().timestamp(),
+            events: Vec::new(),
+            dropped_events: 0,
+            outcome: None,
+        }));
+
+        let mut journals = self.journals.lock().unwrap();
+        self.expire(&mut journals, received);
+        while journals.order.len() >= self.config.max_requests {
+            let Some((_, oldest)) = journals.order.pop_front() else {
+                break;
+            };
+            journals.entries.remove(&oldest);
+        }
+        journals.entries.insert(request_id, entry.clone());
+        journals.order.push_back((received, request_id));
+
+        JournalHandle {
+            request_id,
+            received,
+            max_events: self.config.max_events_per_request,
+            entry,
+        }
+    }
+
+    /// The journal of `request_id`, if it is still retained
+    pub fn get(&self, request_id: Uuid) -> Option<JournalEntry> {
+        let mut journals = self.journals.lock().unwrap();
+        self.expire(&mut journals, Instant::now());
+        let entry = journals.entries.get(&request_id)?;
+        let entry = entry.lock().unwrap().clone();
+        Some(entry)
+    }
+
+    /// The support token `token` is, if any. Configured digests are compared
+    /// with the presented token's, so timing reveals nothing about the token.
+    pub fn authenticate(&self, token: &str) -> Option<&SupportToken> {
+        let presented: String = digest::digest(&digest::SHA256, token.as_bytes())
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.config
+            .support_tokens
+            .iter()
+            .find(|support| support.sha256.eq_ignore_ascii_case(&presented))
+    }
+
+    fn expire(&self, journals: &mut Journals, now: Instant) {
+        let retention = Duration::from_secs(self.config.retention_seconds);
+        while let Some(&(received, request_id)) = journals.order.front() {
+            if now.saturating_duration_since(received) < retention {
+                break;
+            }
+            journals.order.pop_front();
+            journals.entries.remove(&request_id);
+        }
+    }
+}
+
+/// Whether `token` may read journals of `tenant`'s requests
+pub fn may_read(token: &SupportToken, tenant: Option<&str>) -> bool {
+    token.tenants.is_empty()
+        || tenant.is_some_and(|tenant| token.tenants.iter().any(|t| t == tenant))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_journals_events_in_scope_and_bounds_retention() {
+        let token_digest: String = digest::digest(&digest::SHA256, b"support-secret")
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let journal = RequestJournal::new(RequestJournalConfig {
+            enabled: true,
+            max_requests: 2,
+            max_events_per_request: 2,
+            support_tokens: vec![SupportToken {
+                name: "eu".to_string(),
+                sha256: token_digest,
+                tenants: vec!["acme".to_string()],
+            }],
+            ..RequestJournalConfig::default()
+        });
+
+        let handle = journal.open(Some("acme"), "POST", "/v1/chat/completions");
+        let request_id = handle.request_id();
+        record(JournalEvent::Cache { status: "miss" });
+        scope(handle.clone(), async {
+            assert_eq!(current_request_id(), Some(request_id));
+            record(JournalEvent::Cache { status: "miss" });
+            record(JournalEvent::ExecutionSlot { waited_ms: 3 });
+            record(JournalEvent::DeadlineExceeded { hop: "provider" });
+        })
+        .await;
+        assert!(journal.get(request_id).unwrap().outcome.is_none());
+        handle.finish(504);
+
+        let entry = journal.get(request_id).unwrap();
+        assert_eq!(entry.events.len(), 2);
+        assert_eq!(entry.dropped_events, 1);
+        assert_eq!(
+            entry.events[0].event,
+            JournalEvent::Cache { status: "miss" }
+        );
+        assert_eq!(entry.outcome.unwrap().status, 504);
+        let json = serde_json::to_value(&entry.events[1]).unwrap();
+        assert_eq!(json["event"], "execution_slot");
+        assert_eq!(json["waited_ms"], 3);
+
+        // Tokens are matched by digest and scoped to their tenants
+        let token = journal.authenticate("support-secret").unwrap();
+        assert!(may_read(token, Some("acme")));
+        assert!(!may_read(token, Some("globex")));
+        assert!(!may_read(token, None));
+        assert!(journal.authenticate("guess").is_none());
+
+        // The oldest journals make way beyond max_requests
+        journal.open(None, "GET", "/v1/models");
+        journal.open(None, "GET", "/v1/models");
+        assert!(journal.get(request_id).is_none());
+    }
+}
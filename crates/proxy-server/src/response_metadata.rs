@@ -0,0 +1,8 @@
+//! Versioned metadata attached to completion responses
+//!
+//! The schema lives in `fhe-core`, shared with `proxy-client`; handlers build
+//! and validate the block through these re-exports.
+
+pub use fhe_core::response_metadata::{
+    CacheStatus, ProcessingTimes, ResponseCost, ResponseMetadata, RESPONSE_METADATA_VERSION,
+};
@@ -0,0 +1,472 @@
+//! Sandbox keys for trying providers and models outside production budgets
+//!
+//! An operator, or a tenant for itself, issues a sandbox key through
+//! `/v1/admin/sandbox/keys`; the secret is returned once and only its SHA-256
+//! is kept. A request carrying
+//! the key in `x-sandbox-key` is admitted against the key's own pools instead
+//! of its tenant's: a rate-limit window, request and token allowances, and a
+//! spending ceiling priced with `sandbox.price_per_request` and
+//! `sandbox.price_per_1k_tokens`. The ceiling is checked before a request
+//! runs, with the request's own price reserved; its tokens are charged once
+//! the completion is known, so the last request may overshoot by its tokens.
+//!
+//! Sandbox traffic is kept out of the billing ledger, spending caps and the
+//! proxy's request and SLA metrics, and counted per key instead. Every
+//! response to it carries `x-sandbox: true`. Keys may be limited to providers
+//! and models, stop working at their expiry or on revocation, and are dropped
+//! by the periodic sweep. They are held in memory and do not survive a
+//! restart.
+
+use crate::config::SandboxConfig;
+use crate::error::{Error, Result};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const SANDBOX_KEY_HEADER: &str = "x-sandbox-key";
+/// Set on every response to a request made with a sandbox key
+pub const SANDBOX_HEADER: &str = "x-sandbox";
+const SECRET_PREFIX: &str = "sbx_";
+
+/// Request to issue a sandbox key
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SandboxKeyRequest {
+    pub label: String,
+    /// Tenant the key may be used by; any tenant when unset
+    pub tenant: Option<String>,
+    /// Providers the key may use; any when empty
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// Models the key may use; any when empty
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Spend at which the key stops being admitted
+    pub spend_ceiling: f64,
+    pub max_requests: Option<u64>,
+    pub max_tokens: Option<u64>,
+    pub ttl_seconds: Option<u64>,
+    pub rate_limit_per_minute: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxKeyState {
+    Active,
+    Expired,
+    Revoked,
+}
+
+/// What a key has used, kept apart from the proxy's own metrics
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SandboxUsage {
+    pub requests: u64,
+    /// Responses with an error status
+    pub errors: u64,
+    /// Requests refused for an exhausted allowance or the spending ceiling
+    pub refused: u64,
+    pub tokens: u64,
+    pub spend: f64,
+    pub total_latency_ms: u64,
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SandboxKey {
+    pub id: Uuid,
+    pub label: String,
+    pub tenant: Option<String>,
+    pub providers: Vec<String>,
+    pub models: Vec<String>,
+    pub spend_ceiling: f64,
+    pub max_requests: Option<u64>,
+    pub max_tokens: Option<u64>,
+    pub rate_limit_per_minute: u64,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub revoked_at: Option<i64>,
+    pub state: SandboxKeyState,
+    pub usage: SandboxUsage,
+    #[serde(skip)]
+    secret_sha256: String,
+}
+
+impl SandboxKey {
+    fn state_at(&self, now: i64) -> SandboxKeyState {
+        if self.revoked_at.is_some() {
+            SandboxKeyState::Revoked
+        } else if now >= self.expires_at {
+            SandboxKeyState::Expired
+        } else {
+            SandboxKeyState::Active
+        }
+    }
+
+    /// Whether `tenant` may use the key at `now`, limits aside
+    fn usable_by(&self, tenant: Option<&str>, now: i64) -> std::result::Result<(), SandboxRefusal> {
+        match self.state_at(now) {
+            SandboxKeyState::Active => {}
+            SandboxKeyState::Expired => return Err(SandboxRefusal::Expired),
+            SandboxKeyState::Revoked => return Err(SandboxRefusal::Revoked),
+        }
+        if self.tenant.is_some() && self.tenant.as_deref() != tenant {
+            return Err(SandboxRefusal::WrongTenant);
+        }
+        Ok(())
+    }
+
+    /// The key with its state as of `now`
+    fn at(&self, now: i64) -> Self {
+        let mut key = self.clone();
+        key.state = key.state_at(now);
+        key
+    }
+}
+
+/// Why a sandbox request was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxRefusal {
+    UnknownKey,
+    Expired,
+    Revoked,
+    /// The key belongs to another tenant
+    WrongTenant,
+    RequestsExhausted,
+    TokensExhausted,
+    SpendCeiling,
+}
+
+impl SandboxRefusal {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnknownKey => "unknown sandbox key",
+            Self::Expired => "sandbox key expired",
+            Self::Revoked => "sandbox key revoked",
+            Self::WrongTenant => "sandbox key belongs to another tenant",
+            Self::RequestsExhausted => "sandbox request allowance used up",
+            Self::TokensExhausted => "sandbox token allowance used up",
+            Self::SpendCeiling => "sandbox spending ceiling reached",
+        }
+    }
+}
+
+/// An admitted sandbox request, as seen by the layers and handlers after it
+#[derive(Debug, Clone)]
+pub struct SandboxGrant {
+    pub key_id: Uuid,
+    pub providers: Vec<String>,
+    pub models: Vec<String>,
+    pub rate_limit_per_minute: u64,
+}
+
+impl SandboxGrant {
+    /// Whether the key may be used with `model` at `provider`
+    pub fn allows(&self, model: &str, provider: &str) -> bool {
+        (self.models.is_empty() || self.models.iter().any(|m| m == model))
+            && (self.providers.is_empty() || self.providers.iter().any(|p| p == provider))
+    }
+}
+
+#[derive(Debug)]
+pub struct SandboxKeys {
+    config: SandboxConfig,
+    keys: RwLock<HashMap<Uuid, SandboxKey>>,
+}
+
+fn sha256_hex(secret: &str) -> String {
+    digest::digest(&digest::SHA256, secret.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl SandboxKeys {
+    pub fn new(config: SandboxConfig) -> Self {
+        Self {
+            config,
+            keys: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn sweep_interval(&self) -> Duration {
+        Duration::from_secs(self.config.sweep_interval_seconds)
+    }
+
+    /// Issue a key, returning it with its secret
+    pub fn create(&self, request: SandboxKeyRequest, now: i64) -> Result<(SandboxKey, String)> {
+        let invalid = |message: String| Err(Error::Validation(message));
+        if request.label.trim().is_empty() {
+            return invalid("Sandbox key label must not be empty".to_string());
+        }
+        if !(request.spend_ceiling > 0.0 && request.spend_ceiling <= self.config.max_spend_ceiling)
+        {
+            return invalid(format!(
+                "Spending ceiling must be greater than 0 and at most {}",
+                self.config.max_spend_ceiling
+            ));
+        }
+        let ttl = request
+            .ttl_seconds
+            .unwrap_or(self.config.default_ttl_seconds);
+        if ttl == 0 || ttl > self.config.max_ttl_seconds {
+            return invalid(format!(
+                "TTL must be between 1 and {} seconds",
+                self.config.max_ttl_seconds
+            ));
+        }
+        let rate_limit = request
+            .rate_limit_per_minute
+            .unwrap_or(self.config.default_rate_limit_per_minute);
+        if rate_limit == 0 {
+            return invalid("Rate limit must be greater than 0".to_string());
+        }
+
+        let mut keys = self.keys.write().unwrap();
+        let active = keys
+            .values()
+            .filter(|k| k.state_at(now) == SandboxKeyState::Active)
+            .count();
+        if active >= self.config.max_keys {
+            return Err(Error::ResourceExhaustion(format!(
+                "At most {} sandbox keys may be active",
+                self.config.max_keys
+            )));
+        }
+        let secret = format!(
+            "{}{}",
+            SECRET_PREFIX,
+            rand::random::<[u8; 24]>()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+        let key = SandboxKey {
+            id: Uuid::new_v4(),
+            label: request.label,
+            tenant: request.tenant,
+            providers: request.providers,
+            models: request.models,
+            spend_ceiling: request.spend_ceiling,
+            max_requests: request.max_requests,
+            max_tokens: request.max_tokens,
+            rate_limit_per_minute: rate_limit,
+            created_at: now,
+            expires_at: now + ttl as i64,
+            revoked_at: None,
+            state: SandboxKeyState::Active,
+            usage: SandboxUsage::default(),
+            secret_sha256: sha256_hex(&secret),
+        };
+        keys.insert(key.id, key.clone());
+        Ok((key, secret))
+    }
+
+    /// Stop admitting a key; it stays listed until the next sweep
+    pub fn revoke(&self, id: Uuid, now: i64) -> Option<SandboxKey> {
+        let mut keys = self.keys.write().unwrap();
+        let key = keys.get_mut(&id)?;
+        key.revoked_at.get_or_insert(now);
+        Some(key.at(now))
+    }
+
+    /// Whether `secret` names a key `tenant` may use now; nothing is reserved
+    pub fn recognizes(&self, secret: &str, tenant: Option<&str>, now: i64) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+        let digest = sha256_hex(secret);
+        let keys = self.keys.read().unwrap();
+        keys.values()
+            .find(|k| k.secret_sha256 == digest)
+            .is_some_and(|key| key.usable_by(tenant, now).is_ok())
+    }
+
+    /// Admit a request made with `secret` by `tenant`, reserving its price
+    pub fn admit(
+        &self,
+        secret: &str,
+        tenant: Option<&str>,
+        now: i64,
+    ) -> std::result::Result<SandboxGrant, SandboxRefusal> {
+        if !self.config.enabled {
+            return Err(SandboxRefusal::UnknownKey);
+        }
+        let digest = sha256_hex(secret);
+        let mut keys = self.keys.write().unwrap();
+        let key = keys
+            .values_mut()
+            .find(|k| k.secret_sha256 == digest)
+            .ok_or(SandboxRefusal::UnknownKey)?;
+        key.usable_by(tenant, now)?;
+        let refusal = if key
+            .max_requests
+            .is_some_and(|max| key.usage.requests >= max)
+        {
+            Some(SandboxRefusal::RequestsExhausted)
+        } else if key.max_tokens.is_some_and(|max| key.usage.tokens >= max) {
+            Some(SandboxRefusal::TokensExhausted)
+        } else if key.usage.spend + self.config.price_per_request > key.spend_ceiling {
+            Some(SandboxRefusal::SpendCeiling)
+        } else {
+            None
+        };
+        if let Some(refusal) = refusal {
+            key.usage.refused += 1;
+            return Err(refusal);
+        }
+        key.usage.requests += 1;
+        key.usage.spend += self.config.price_per_request;
+        key.usage.last_used_at = Some(now);
+        Ok(SandboxGrant {
+            key_id: key.id,
+            providers: key.providers.clone(),
+            models: key.models.clone(),
+            rate_limit_per_minute: key.rate_limit_per_minute,
+        })
+    }
+
+    /// Charge the tokens of a completion served with a key
+    pub fn record_tokens(&self, id: Uuid, tokens: u64) {
+        if let Some(key) = self.keys.write().unwrap().get_mut(&id) {
+            key.usage.tokens += tokens;
+            key.usage.spend += tokens as f64 / 1000.0 * self.config.price_per_1k_tokens;
+        }
+    }
+
+    pub fn record_response(&self, id: Uuid, status: u16, latency: Duration) {
+        if let Some(key) = self.keys.write().unwrap().get_mut(&id) {
+            if status >= 400 {
+                key.usage.errors += 1;
+            }
+            key.usage.total_latency_ms += latency.as_millis() as u64;
+        }
+    }
+
+    pub fn get(&self, id: Uuid, now: i64) -> Option<SandboxKey> {
+        self.keys.read().unwrap().get(&id).map(|k| k.at(now))
+    }
+
+    /// Every key, newest first
+    pub fn list(&self, now: i64) -> Vec<SandboxKey> {
+        let mut keys: Vec<SandboxKey> = self
+            .keys
+            .read()
+            .unwrap()
+            .values()
+            .map(|k| k.at(now))
+            .collect();
+        keys.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+        keys
+    }
+
+    /// Drop expired and revoked keys, returning them with their final usage
+    pub fn sweep(&self, now: i64) -> Vec<SandboxKey> {
+        let mut keys = self.keys.write().unwrap();
+        let ended: Vec<Uuid> = keys
+            .values()
+            .filter(|k| k.state_at(now) != SandboxKeyState::Active)
+            .map(|k| k.id)
+            .collect();
+        ended
+            .iter()
+            .filter_map(|id| keys.remove(id))
+            .map(|k| k.at(now))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_are_held_to_their_own_pools_and_expire() {
+        let keys = SandboxKeys::new(SandboxConfig {
+            enabled: true,
+            max_keys: 2,
+            price_per_request: 0.25,
+            price_per_1k_tokens: 1.0,
+            ..SandboxConfig::default()
+        });
+        let request = |label: &str| SandboxKeyRequest {
+            label: label.to_string(),
+            tenant: Some("acme".to_string()),
+            providers: vec!["openai".to_string()],
+            models: Vec::new(),
+            spend_ceiling: 1.0,
+            max_requests: None,
+            max_tokens: Some(1500),
+            ttl_seconds: Some(60),
+            rate_limit_per_minute: None,
+        };
+        assert!(keys
+            .create(
+                SandboxKeyRequest {
+                    spend_ceiling: 1000.0,
+                    ..request("too rich")
+                },
+                0
+            )
+            .is_err());
+        let (key, secret) = keys.create(request("new provider"), 0).unwrap();
+        assert!(secret.starts_with(SECRET_PREFIX));
+        keys.create(request("second"), 0).unwrap();
+        assert!(keys.create(request("third"), 0).is_err());
+
+        assert_eq!(
+            keys.admit("sbx_wrong", Some("acme"), 1).unwrap_err(),
+            SandboxRefusal::UnknownKey
+        );
+        assert_eq!(
+            keys.admit(&secret, Some("globex"), 1).unwrap_err(),
+            SandboxRefusal::WrongTenant
+        );
+        // Recognizing a key reserves nothing
+        assert!(keys.recognizes(&secret, Some("acme"), 1));
+        assert!(!keys.recognizes(&secret, Some("globex"), 1));
+        assert!(!keys.recognizes("sbx_wrong", Some("acme"), 1));
+        assert_eq!(keys.get(key.id, 1).unwrap().usage.requests, 0);
+        let grant = keys.admit(&secret, Some("acme"), 1).unwrap();
+        assert!(grant.allows("gpt-4o", "openai"));
+        assert!(!grant.allows("gpt-4o", "anthropic"));
+
+        // The ceiling reserves each request's price before it runs
+        keys.record_tokens(grant.key_id, 500);
+        keys.record_response(grant.key_id, 200, Duration::from_millis(40));
+        keys.admit(&secret, Some("acme"), 2).unwrap();
+        assert_eq!(
+            keys.admit(&secret, Some("acme"), 3).unwrap_err(),
+            SandboxRefusal::SpendCeiling
+        );
+        let usage = keys.get(key.id, 3).unwrap().usage;
+        assert_eq!(usage.requests, 2);
+        assert_eq!(usage.refused, 1);
+        assert_eq!(usage.tokens, 500);
+        assert!((usage.spend - 1.0).abs() < 1e-9);
+
+        // Expired and revoked keys stop working and are swept
+        assert_eq!(
+            keys.admit(&secret, Some("acme"), 60).unwrap_err(),
+            SandboxRefusal::Expired
+        );
+        assert!(!keys.recognizes(&secret, Some("acme"), 60));
+        let (other, other_secret) = keys.create(request("after expiry"), 60).unwrap();
+        keys.revoke(other.id, 61).unwrap();
+        assert_eq!(
+            keys.admit(&other_secret, Some("acme"), 62).unwrap_err(),
+            SandboxRefusal::Revoked
+        );
+        let swept = keys.sweep(62);
+        assert_eq!(swept.len(), 3);
+        assert!(swept.iter().any(|k| k.state == SandboxKeyState::Revoked));
+        assert!(keys.list(62).is_empty());
+    }
+}
@@ -0,0 +1,2366 @@
+//! Scaling and performance optimization features
+
+use crate::config::{
+    BatchWindowConfig, BatchWindowsConfig, LoadSheddingConfig, QueueProjectionConfig,
+    ShedPolicyMode, SlaClass, WorkerAutoscalingConfig,
+};
+use crate::error::{Error, Result};
+use crate::fhe::{Ciphertext, EngineFingerprint, FheEngine, FheParams};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, Semaphore};
+use uuid::Uuid;
+
+/// Connection pool for managing FHE operations
+#[derive(Debug)]
+pub struct FheConnectionPool {
+    engines: Vec<Arc<RwLock<FheEngine>>>,
+    current_index: AtomicUsize,
+    max_concurrent_ops: Arc<Semaphore>,
+    pool_stats: Arc<RwLock<PoolStats>>,
+    /// Refuse engines whose fingerprint differs from the pool's
+    uniform_fingerprints: bool,
+    /// Engines out of rotation because their failure domain is drained
+    drained: std::sync::RwLock<HashSet<usize>>,
+}
+
+/// One engine's fingerprint and whether it matches the rest of the pool
+#[derive(Debug, Clone, Serialize)]
+pub struct EngineFingerprintStatus {
+    pub index: usize,
+    pub fingerprint: EngineFingerprint,
+    pub compatible: bool,
+}
+
+/// Fingerprints of every engine in the pool, judged against the one most
+/// engines share
+#[derive(Debug, Clone, Serialize)]
+pub struct FingerprintReport {
+    pub enforced: bool,
+    pub reference: Option<EngineFingerprint>,
+    pub engines: Vec<EngineFingerprintStatus>,
+}
+
+impl FingerprintReport {
+    pub fn is_uniform(&self) -> bool {
+        self.engines.iter().all(|e| e.compatible)
+    }
+
+    /// Engines that do not match the reference fingerprint
+    pub fn mismatched(&self) -> Vec<&EngineFingerprintStatus> {
+        self.engines.iter().filter(|e| !e.compatible).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolStats {
+    total_operations: u64,
+    active_operations: u64,
+    avg_operation_time: Duration,
+    engine_utilization: HashMap<usize, u64>,
+}
+
+impl FheConnectionPool {
+    pub fn new(pool_size: usize, max_concurrent: usize, fhe_params: FheParams) -> Result<Self> {
+        let mut engines = Vec::with_capacity(pool_size);
+
+        for i in 0..pool_size {
+            let engine = FheEngine::new(fhe_params.clone())?;
+            engines.push(Arc::new(RwLock::new(engine)));
+            log::info!("Initialized FHE engine {} in pool", i);
+        }
+
+        let mut engine_utilization = HashMap::new();
+        for i in 0..pool_size {
+            engine_utilization.insert(i, 0);
+        }
+
+        Ok(Self {
+            engines,
+            current_index: AtomicUsize::new(0),
+            max_concurrent_ops: Arc::new(Semaphore::new(max_concurrent)),
+            pool_stats: Arc::new(RwLock::new(PoolStats {
+                total_operations: 0,
+                active_operations: 0,
+                avg_operation_time: Duration::from_millis(0),
+                engine_utilization,
+            })),
+            uniform_fingerprints: true,
+            drained: std::sync::RwLock::new(HashSet::new()),
+        })
+    }
+
+    /// Allow or refuse engines with differing fingerprints in one pool
+    pub fn with_uniform_fingerprints(mut self, enforce: bool) -> Self {
+        self.uniform_fingerprints = enforce;
+        self
+    }
+
+    /// Admit `engine` to the pool, returning its index. Unless mixing is
+    /// allowed, its fingerprint must match the engines already serving.
+    pub async fn add_engine(&mut self, engine: FheEngine) -> Result<usize> {
+        let fingerprint = engine.fingerprint();
+        if let Some(first) = self.engines.first() {
+            let pool_fingerprint = first.read().await.fingerprint();
+            if !pool_fingerprint.is_compatible(&fingerprint) {
+                let message = format!(
+                    "Engine fingerprint ({}) does not match the pool ({})",
+                    fingerprint, pool_fingerprint
+                );
+                if self.uniform_fingerprints {
+                    return Err(Error::Validation(message));
+                }
+                log::warn!("{}; admitting it because mixing is allowed", message);
+            }
+        }
+
+        let index = self.engines.len();
+        self.engines.push(Arc::new(RwLock::new(engine)));
+        self.pool_stats
+            .write()
+            .await
+            .engine_utilization
+            .insert(index, 0);
+        Ok(index)
+    }
+
+    /// Current fingerprint of every engine
+    pub async fn fingerprint_report(&self) -> FingerprintReport {
+        let mut fingerprints = Vec::with_capacity(self.engines.len());
+        for engine in &self.engines {
+            fingerprints.push(engine.read().await.fingerprint());
+        }
+
+        let mut counts: Vec<(&EngineFingerprint, usize)> = Vec::new();
+        for fingerprint in &fingerprints {
+            match counts.iter_mut().find(|(f, _)| *f == fingerprint) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((fingerprint, 1)),
+            }
+        }
+        // Ties go to the fingerprint seen first, i.e. the longest-serving engine
+        let reference = counts
+            .iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(f, _)| (*f).clone());
+
+        let engines = fingerprints
+            .iter()
+            .enumerate()
+            .map(|(index, fingerprint)| EngineFingerprintStatus {
+                index,
+                compatible: reference
+                    .as_ref()
+                    .is_none_or(|r| r.is_compatible(fingerprint)),
+                fingerprint: fingerprint.clone(),
+            })
+            .collect();
+        FingerprintReport {
+            enforced: self.uniform_fingerprints,
+            reference,
+            engines,
+        }
+    }
+
+    /// Dynamically scale the pool size based on load
+    pub async fn scale_pool(&mut self, target_size: usize, fhe_params: FheParams) -> Result<()> {
+        let current_size = self.engines.len();
+
+        if target_size > current_size {
+            // Scale up - add new engines
+            for _ in current_size..target_size {
+                let index = self.add_engine(FheEngine::new(fhe_params.clone())?).await?;
+                log::info!("Scaled up: Added FHE engine {} to pool", index);
+            }
+            log::info!(
+                "Pool scaled up from {} to {} engines",
+                current_size,
+                target_size
+            );
+        } else if target_size < current_size {
+            // Scale down - remove engines (but keep minimum of 1)
+            let actual_target = target_size.max(1);
+            self.engines.truncate(actual_target);
+
+            // Clean up utilization tracking
+            let mut stats = self.pool_stats.write().await;
+            stats.engine_utilization.retain(|&k, _| k < actual_target);
+
+            log::info!(
+                "Pool scaled down from {} to {} engines",
+                current_size,
+                actual_target
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Take the engines at `indices` out of rotation, returning the rest to it
+    pub fn set_drained(&self, indices: impl IntoIterator<Item = usize>) {
+        *self.drained.write().unwrap() = indices.into_iter().collect();
+    }
+
+    /// Engines out of rotation, sorted
+    pub fn drained(&self) -> Vec<usize> {
+        let mut drained: Vec<usize> = self.drained.read().unwrap().iter().copied().collect();
+        drained.sort_unstable();
+        drained
+    }
+
+    /// Get optimal engine based on current load
+    async fn get_optimal_engine(&self) -> (usize, Arc<RwLock<FheEngine>>) {
+        let stats = self.pool_stats.read().await;
+
+        // Find the in-rotation engine with lowest utilization; with every
+        // engine drained, serving degraded beats refusing the work
+        let drained = self.drained.read().unwrap().clone();
+        let lowest = |skip_drained: bool| {
+            stats
+                .engine_utilization
+                .iter()
+                .filter(|(idx, _)| !(skip_drained && drained.contains(idx)))
+                .min_by_key(|(_, &utilization)| utilization)
+                .map(|(&idx, _)| idx)
+        };
+        let optimal_idx = lowest(true).or_else(|| lowest(false)).unwrap_or(0);
+
+        drop(stats);
+        (optimal_idx, self.engines[optimal_idx].clone())
+    }
+
+    /// Get next available engine using round-robin
+    async fn get_engine(&self) -> (usize, Arc<RwLock<FheEngine>>) {
+        let index = self.current_index.fetch_add(1, Ordering::Relaxed) % self.engines.len();
+        (index, self.engines[index].clone())
+    }
+
+    /// Perform encryption with load balancing
+    pub async fn encrypt_balanced(&self, client_id: Uuid, plaintext: &str) -> Result<Ciphertext> {
+        let _permit = self
+            .max_concurrent_ops
+            .acquire()
+            .await
+            .map_err(|_| Error::Internal("Failed to acquire semaphore permit".to_string()))?;
+
+        let start = Instant::now();
+        let (engine_idx, engine) = self.get_optimal_engine().await;
+
+        // Update stats
+        {
+            let mut stats = self.pool_stats.write().await;
+            stats.active_operations += 1;
+            stats.total_operations += 1;
+        }
+
+        let result = {
+            let engine = engine.read().await;
+            engine.encrypt_text(client_id, plaintext)
+        };
+
+        let elapsed = start.elapsed();
+
+        // Update engine utilization and timing stats
+        {
+            let mut stats = self.pool_stats.write().await;
+            stats.active_operations -= 1;
+            *stats.engine_utilization.get_mut(&engine_idx).unwrap() += 1;
+
+            // Update average operation time (simple moving average)
+            let current_avg = stats.avg_operation_time.as_millis() as f64;
+            let new_avg = (current_avg + elapsed.as_millis() as f64) / 2.0;
+            stats.avg_operation_time = Duration::from_millis(new_avg as u64);
+        }
+
+        log::debug!("Encrypted using engine {} in {:?}", engine_idx, elapsed);
+        Ok(result?)
+    }
+
+    /// Perform decryption with load balancing
+    pub async fn decrypt_balanced(
+        &self,
+        client_id: Uuid,
+        ciphertext: &Ciphertext,
+    ) -> Result<String> {
+        let _permit = self
+            .max_concurrent_ops
+            .acquire()
+            .await
+            .map_err(|_| Error::Internal("Failed to acquire semaphore permit".to_string()))?;
+
+        let start = Instant::now();
+        let (engine_idx, engine) = self.get_optimal_engine().await;
+
+        {
+            let mut stats = self.pool_stats.write().await;
+            stats.active_operations += 1;
+            stats.total_operations += 1;
+        }
+
+        let result = {
+            let engine = engine.read().await;
+            engine.decrypt_text_safe(client_id, ciphertext)
+        };
+
+        let elapsed = start.elapsed();
+
+        {
+            let mut stats = self.pool_stats.write().await;
+            stats.active_operations -= 1;
+            *stats.engine_utilization.get_mut(&engine_idx).unwrap() += 1;
+        }
+
+        log::debug!("Decrypted using engine {} in {:?}", engine_idx, elapsed);
+        Ok(result?)
+    }
+
+    /// Get pool statistics
+    pub async fn get_stats(&self) -> PoolStats {
+        self.pool_stats.read().await.clone()
+    }
+
+    /// Health check for all engines
+    pub async fn health_check(&self) -> Vec<bool> {
+        let mut health_status = Vec::new();
+
+        for (i, engine) in self.engines.iter().enumerate() {
+            let is_healthy = match tokio::time::timeout(Duration::from_secs(5), async {
+                let engine = engine.read().await;
+                // Simple health check - try to get parameters
+                let _params = engine.get_params();
+                true
+            })
+            .await
+            {
+                Ok(result) => result,
+                Err(_) => false,
+            };
+
+            health_status.push(is_healthy);
+            if !is_healthy {
+                log::warn!("FHE engine {} failed health check", i);
+            }
+        }
+
+        health_status
+    }
+}
+
+/// Batch processing for multiple operations
+#[derive(Debug)]
+pub struct BatchProcessor {
+    batch_size: usize,
+    flush_interval: Duration,
+    pending_operations: Arc<RwLock<Vec<BatchOperation>>>,
+    processor_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct BatchOperation {
+    id: Uuid,
+    operation_type: BatchOperationType,
+    timestamp: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub enum BatchOperationType {
+    Encrypt {
+        client_id: Uuid,
+        plaintext: String,
+    },
+    Decrypt {
+        client_id: Uuid,
+        ciphertext: Ciphertext,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum BatchResult {
+    Encrypted(Ciphertext),
+    Decrypted(String),
+}
+
+impl BatchProcessor {
+    pub fn new(batch_size: usize, flush_interval: Duration) -> Self {
+        Self {
+            batch_size,
+            flush_interval,
+            pending_operations: Arc::new(RwLock::new(Vec::new())),
+            processor_handle: None,
+        }
+    }
+
+    pub fn start(&mut self, pool: Arc<FheConnectionPool>) -> Result<()> {
+        let pending_operations = self.pending_operations.clone();
+        let batch_size = self.batch_size;
+        let flush_interval = self.flush_interval;
+
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(flush_interval);
+
+            loop {
+                interval.tick().await;
+
+                let operations = {
+                    let mut pending = pending_operations.write().await;
+                    if pending.is_empty() {
+                        continue;
+                    }
+
+                    let to_process = if pending.len() >= batch_size {
+                        pending.drain(0..batch_size).collect::<Vec<_>>()
+                    } else {
+                        // Process all if batch timeout reached
+                        let oldest_age = pending
+                            .first()
+                            .map(|op| op.timestamp.elapsed())
+                            .unwrap_or(Duration::ZERO);
+
+                        if oldest_age >= flush_interval {
+                            pending.drain(..).collect::<Vec<_>>()
+                        } else {
+                            continue;
+                        }
+                    };
+                    to_process
+                };
+
+                // Process batch
+                Self::process_batch(operations, pool.clone()).await;
+            }
+        });
+
+        self.processor_handle = Some(handle);
+        log::info!(
+            "Started batch processor with batch_size={}, flush_interval={:?}",
+            batch_size,
+            flush_interval
+        );
+        Ok(())
+    }
+
+    async fn process_batch(operations: Vec<BatchOperation>, pool: Arc<FheConnectionPool>) {
+        log::debug!("Processing batch of {} operations", operations.len());
+
+        let mut tasks = Vec::new();
+
+        for operation in operations {
+            let pool = pool.clone();
+            let task = tokio::spawn(async move {
+                let result = match operation.operation_type {
+                    BatchOperationType::Encrypt {
+                        client_id,
+                        plaintext,
+                    } => pool
+                        .encrypt_balanced(client_id, &plaintext)
+                        .await
+                        .map(BatchResult::Encrypted),
+                    BatchOperationType::Decrypt {
+                        client_id,
+                        ciphertext,
+                    } => pool
+                        .decrypt_balanced(client_id, &ciphertext)
+                        .await
+                        .map(BatchResult::Decrypted),
+                };
+
+                (operation.id, result)
+            });
+            tasks.push(task);
+        }
+
+        // Wait for all operations to complete
+        for task in tasks {
+            if let Ok((op_id, result)) = task.await {
+                log::debug!("Completed batch operation {}: {:?}", op_id, result.is_ok());
+            }
+        }
+    }
+
+    pub async fn add_operation(&self, operation_type: BatchOperationType) {
+        let operation = BatchOperation {
+            id: Uuid::new_v4(),
+            operation_type,
+            timestamp: Instant::now(),
+        };
+
+        let mut pending = self.pending_operations.write().await;
+        pending.push(operation);
+    }
+}
+
+/// Caching layer for frequently accessed ciphertexts
+#[derive(Debug)]
+pub struct CiphertextCache {
+    cache: Arc<RwLock<HashMap<Uuid, CacheEntry>>>,
+    max_size: usize,
+    ttl: Duration,
+    stats: Arc<RwLock<CacheStats>>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    ciphertext: Ciphertext,
+    last_accessed: Instant,
+    access_count: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheStats {
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    current_size: usize,
+}
+
+impl CiphertextCache {
+    pub fn new(max_size: usize, ttl: Duration) -> Self {
+        Self {
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            max_size,
+            ttl,
+            stats: Arc::new(RwLock::new(CacheStats {
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+                current_size: 0,
+            })),
+        }
+    }
+
+    pub async fn get(&self, id: &Uuid) -> Option<Ciphertext> {
+        let mut cache = self.cache.write().await;
+        let mut stats = self.stats.write().await;
+
+        if let Some(entry) = cache.get_mut(id) {
+            // Check if entry is still valid
+            if entry.last_accessed.elapsed() <= self.ttl {
+                entry.last_accessed = Instant::now();
+                entry.access_count += 1;
+                stats.hits += 1;
+                return Some(entry.ciphertext.clone());
+            } else {
+                // Entry expired, remove it
+                cache.remove(id);
+                stats.current_size = cache.len();
+            }
+        }
+
+        stats.misses += 1;
+        None
+    }
+
+    pub async fn put(&self, id: Uuid, ciphertext: Ciphertext) {
+        let mut cache = self.cache.write().await;
+        let mut stats = self.stats.write().await;
+
+        // Check if we need to evict entries
+        if cache.len() >= self.max_size {
+            self.evict_lru(&mut cache, &mut stats).await;
+        }
+
+        let entry = CacheEntry {
+            ciphertext,
+            last_accessed: Instant::now(),
+            access_count: 1,
+        };
+
+        cache.insert(id, entry);
+        stats.current_size = cache.len();
+    }
+
+    async fn evict_lru(&self, cache: &mut HashMap<Uuid, CacheEntry>, stats: &mut CacheStats) {
+        // Find entry with oldest last_accessed time
+        let oldest_id = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_accessed)
+            .map(|(id, _)| *id);
+
+        if let Some(id) = oldest_id {
+            cache.remove(&id);
+            stats.evictions += 1;
+            log::debug!("Evicted ciphertext {} from cache", id);
+        }
+    }
+
+    pub async fn cleanup_expired(&self) {
+        let mut cache = self.cache.write().await;
+        let mut stats = self.stats.write().await;
+
+        let now = Instant::now();
+        let expired_keys: Vec<Uuid> = cache
+            .iter()
+            .filter(|(_, entry)| now.duration_since(entry.last_accessed) > self.ttl)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for key in expired_keys {
+            cache.remove(&key);
+            stats.evictions += 1;
+        }
+
+        stats.current_size = cache.len();
+
+        if !cache.is_empty() {
+            log::debug!(
+                "Cleaned up expired cache entries, {} entries remaining",
+                cache.len()
+            );
+        }
+    }
+
+    pub async fn get_stats(&self) -> CacheStats {
+        self.stats.read().await.clone()
+    }
+
+    /// Intelligent prefetching based on access patterns
+    pub async fn prefetch_likely_accessed(&self, prediction_engine: &PredictionEngine) {
+        let access_predictions = prediction_engine.predict_next_accesses().await;
+
+        for prediction in access_predictions {
+            if let Some(_ciphertext) = self.get(&prediction.ciphertext_id).await {
+                log::debug!(
+                    "Prefetched ciphertext {} (confidence: {:.2})",
+                    prediction.ciphertext_id,
+                    prediction.confidence
+                );
+            }
+        }
+    }
+
+    /// Warm up cache with commonly accessed ciphertexts
+    pub async fn warm_cache(&self, warm_up_data: Vec<(Uuid, Ciphertext)>) {
+        log::info!("Warming cache with {} entries", warm_up_data.len());
+
+        for (id, ciphertext) in warm_up_data {
+            self.put(id, ciphertext).await;
+        }
+
+        let stats = self.get_stats().await;
+        log::info!("Cache warmed: {} entries loaded", stats.current_size);
+    }
+}
+
+/// Prediction engine for intelligent caching
+#[derive(Debug)]
+pub struct PredictionEngine {
+    access_patterns: Arc<RwLock<HashMap<Uuid, AccessPattern>>>,
+    ml_model: Arc<RwLock<SimplePredictionModel>>,
+}
+
+#[derive(Debug, Clone)]
+struct AccessPattern {
+    access_count: u64,
+    last_access: Instant,
+    access_frequency: f64, // accesses per hour
+    correlation_score: f64,
+}
+
+#[derive(Debug)]
+struct SimplePredictionModel {
+    weights: Vec<f64>,
+    threshold: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessPrediction {
+    pub ciphertext_id: Uuid,
+    pub confidence: f64,
+    pub predicted_access_time: Duration,
+}
+
+impl Default for PredictionEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PredictionEngine {
+    pub fn new() -> Self {
+        Self {
+            access_patterns: Arc::new(RwLock::new(HashMap::new())),
+            ml_model: Arc::new(RwLock::new(SimplePredictionModel {
+                weights: vec![0.5, 0.3, 0.2], // frequency, recency, correlation
+                threshold: 0.6,
+            })),
+        }
+    }
+
+    pub async fn record_access(&self, ciphertext_id: Uuid) {
+        let mut patterns = self.access_patterns.write().await;
+        let pattern = patterns.entry(ciphertext_id).or_insert(AccessPattern {
+            access_count: 0,
+            last_access: Instant::now(),
+            access_frequency: 0.0,
+            correlation_score: 0.5,
+        });
+
+        pattern.access_count += 1;
+        pattern.last_access = Instant::now();
+
+        // Update frequency (simplified calculation)
+        let hours_since_first = pattern.access_count as f64 / 24.0; // Approximate
+        pattern.access_frequency = pattern.access_count as f64 / hours_since_first.max(1.0);
+    }
+
+    pub async fn predict_next_accesses(&self) -> Vec<AccessPrediction> {
+        let patterns = self.access_patterns.read().await;
+        let model = self.ml_model.read().await;
+        let mut predictions = Vec::new();
+
+        for (&ciphertext_id, pattern) in patterns.iter() {
+            let recency_score =
+                1.0 / (1.0 + pattern.last_access.elapsed().as_secs() as f64 / 3600.0);
+            let frequency_score = pattern.access_frequency / 10.0; // Normalize
+            let correlation_score = pattern.correlation_score;
+
+            let confidence = model.weights[0] * frequency_score
+                + model.weights[1] * recency_score
+                + model.weights[2] * correlation_score;
+
+            if confidence > model.threshold {
+                predictions.push(AccessPrediction {
+                    ciphertext_id,
+                    confidence,
+                    predicted_access_time: Duration::from_secs(
+                        (3600.0 / pattern.access_frequency) as u64,
+                    ),
+                });
+            }
+        }
+
+        // Sort by confidence descending
+        predictions.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap());
+        predictions.truncate(10); // Top 10 predictions
+
+        predictions
+    }
+}
+
+/// Auto-scaling coordinator
+#[derive(Debug)]
+pub struct AutoScaler {
+    target_cpu_percent: f64,
+    target_queue_length: usize,
+    scale_up_threshold: f64,
+    scale_down_threshold: f64,
+    current_replicas: AtomicUsize,
+    max_replicas: usize,
+    min_replicas: usize,
+    last_scale_event: Arc<RwLock<Instant>>,
+    cooldown_period: Duration,
+}
+
+impl AutoScaler {
+    pub fn new(
+        target_cpu_percent: f64,
+        target_queue_length: usize,
+        min_replicas: usize,
+        max_replicas: usize,
+        cooldown_period: Duration,
+    ) -> Self {
+        Self {
+            target_cpu_percent,
+            target_queue_length,
+            scale_up_threshold: target_cpu_percent * 1.2,
+            scale_down_threshold: target_cpu_percent * 0.6,
+            current_replicas: AtomicUsize::new(min_replicas),
+            max_replicas,
+            min_replicas,
+            last_scale_event: Arc::new(RwLock::new(Instant::now())),
+            cooldown_period,
+        }
+    }
+
+    pub async fn evaluate_scaling(&self, metrics: &ScalingMetrics) -> ScalingDecision {
+        let last_scale = *self.last_scale_event.read().await;
+        if last_scale.elapsed() < self.cooldown_period {
+            return ScalingDecision::NoAction;
+        }
+
+        let current = self.current_replicas.load(Ordering::Relaxed);
+
+        // Evaluate scale up conditions
+        if (metrics.cpu_utilization > self.scale_up_threshold
+            || metrics.queue_length > self.target_queue_length)
+            && current < self.max_replicas
+        {
+            return ScalingDecision::ScaleUp {
+                from: current,
+                to: (current + 1).min(self.max_replicas),
+                reason: format!(
+                    "CPU: {:.1}%, Queue: {}",
+                    metrics.cpu_utilization, metrics.queue_length
+                ),
+            };
+        }
+
+        // Evaluate scale down conditions
+        if metrics.cpu_utilization < self.scale_down_threshold
+            && metrics.queue_length < self.target_queue_length / 2
+            && current > self.min_replicas
+        {
+            return ScalingDecision::ScaleDown {
+                from: current,
+                to: (current - 1).max(self.min_replicas),
+                reason: format!(
+                    "CPU: {:.1}%, Queue: {}",
+                    metrics.cpu_utilization, metrics.queue_length
+                ),
+            };
+        }
+
+        ScalingDecision::NoAction
+    }
+
+    pub async fn apply_scaling(&self, decision: ScalingDecision) -> Result<()> {
+        match decision {
+            ScalingDecision::ScaleUp { from, to, reason } => {
+                log::info!("Scaling up from {} to {} replicas: {}", from, to, reason);
+                self.current_replicas.store(to, Ordering::Relaxed);
+                *self.last_scale_event.write().await = Instant::now();
+            }
+            ScalingDecision::ScaleDown { from, to, reason } => {
+                log::info!("Scaling down from {} to {} replicas: {}", from, to, reason);
+                self.current_replicas.store(to, Ordering::Relaxed);
+                *self.last_scale_event.write().await = Instant::now();
+            }
+            ScalingDecision::NoAction => {}
+        }
+        Ok(())
+    }
+
+    pub fn get_current_replicas(&self) -> usize {
+        self.current_replicas.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScalingMetrics {
+    pub cpu_utilization: f64,
+    pub memory_utilization: f64,
+    pub queue_length: usize,
+    pub active_connections: usize,
+    pub response_time_p95: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub enum ScalingDecision {
+    ScaleUp {
+        from: usize,
+        to: usize,
+        reason: String,
+    },
+    ScaleDown {
+        from: usize,
+        to: usize,
+        reason: String,
+    },
+    NoAction,
+}
+
+/// Circuit breaker for external dependencies
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    success_threshold: u32,
+    timeout: Duration,
+    failure_count: AtomicU64,
+    success_count: AtomicU64,
+    last_failure_time: Arc<RwLock<Option<Instant>>>,
+    state: Arc<RwLock<CircuitState>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum CircuitState {
+    Closed,   // Normal operation
+    Open,     // Failing, reject requests
+    HalfOpen, // Testing if service recovered
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, success_threshold: u32, timeout: Duration) -> Self {
+        Self {
+            failure_threshold,
+            success_threshold,
+            timeout,
+            failure_count: AtomicU64::new(0),
+            success_count: AtomicU64::new(0),
+            last_failure_time: Arc::new(RwLock::new(None)),
+            state: Arc::new(RwLock::new(CircuitState::Closed)),
+        }
+    }
+
+    pub async fn call<F, T, E>(&self, operation: F) -> Result<T>
+    where
+        F: std::future::Future<Output = std::result::Result<T, E>>,
+        E: std::fmt::Display + std::fmt::Debug,
+    {
+        let state = *self.state.read().await;
+
+        match state {
+            CircuitState::Open => {
+                // Check if timeout has passed
+                let last_failure = *self.last_failure_time.read().await;
+                if let Some(failure_time) = last_failure {
+                    if failure_time.elapsed() >= self.timeout {
+                        // Transition to half-open
+                        *self.state.write().await = CircuitState::HalfOpen;
+                        log::info!("Circuit breaker transitioning to half-open");
+                    } else {
+                        return Err(Error::Internal("Circuit breaker is open".to_string()));
+                    }
+                }
+            }
+            CircuitState::HalfOpen => {
+                // Allow limited requests to test service
+            }
+            CircuitState::Closed => {
+                // Normal operation
+            }
+        }
+
+        // Execute operation
+        match operation.await {
+            Ok(result) => {
+                self.on_success().await;
+                Ok(result)
+            }
+            Err(e) => {
+                self.on_failure().await;
+                Err(Error::Internal(format!(
+                    "Circuit breaker operation failed: {}",
+                    e
+                )))
+            }
+        }
+    }
+
+    async fn on_success(&self) {
+        let success_count = self.success_count.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let state = *self.state.read().await;
+        if state == CircuitState::HalfOpen && success_count >= self.success_threshold as u64 {
+            *self.state.write().await = CircuitState::Closed;
+            self.failure_count.store(0, Ordering::Relaxed);
+            self.success_count.store(0, Ordering::Relaxed);
+            log::info!("Circuit breaker closed after successful recovery");
+        }
+    }
+
+    async fn on_failure(&self) {
+        let failure_count = self.failure_count.fetch_add(1, Ordering::Relaxed) + 1;
+        *self.last_failure_time.write().await = Some(Instant::now());
+
+        if failure_count >= self.failure_threshold as u64 {
+            *self.state.write().await = CircuitState::Open;
+            log::warn!("Circuit breaker opened after {} failures", failure_count);
+        }
+    }
+
+    pub async fn get_state(&self) -> CircuitState {
+        *self.state.read().await
+    }
+}
+
+/// Snapshot of the proxy process's own resource usage
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProcessResources {
+    pub rss_mb: f64,
+    pub open_fds: u64,
+    pub cpu_percent: f64,
+}
+
+/// Sustained-breach limits enforced by the resource guard
+#[derive(Debug, Clone)]
+pub struct ResourceLimits {
+    pub max_rss_mb: f64,
+    pub max_open_fds: u64,
+    pub max_cpu_percent: f64,
+}
+
+/// Remediation taken when a limit stays exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardAction {
+    FlushCache,
+    DrainEngine,
+    Restart,
+}
+
+impl GuardAction {
+    pub fn parse(action: &str) -> Result<Self> {
+        match action {
+            "flush_cache" => Ok(GuardAction::FlushCache),
+            "drain_engine" => Ok(GuardAction::DrainEngine),
+            "restart" => Ok(GuardAction::Restart),
+            other => Err(Error::Config(format!(
+                "Unknown resource guard action: {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GuardAction::FlushCache => "flush_cache",
+            GuardAction::DrainEngine => "drain_engine",
+            GuardAction::Restart => "restart",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GuardDecision {
+    pub action: GuardAction,
+    pub trigger: String,
+}
+
+/// Watches the process's RSS, open file descriptors and CPU saturation and
+/// escalates through the configured actions while a breach is sustained
+#[derive(Debug)]
+pub struct ResourceGuard {
+    limits: ResourceLimits,
+    actions: Vec<GuardAction>,
+    sustained_for: Duration,
+    state: RwLock<GuardState>,
+    draining: AtomicBool,
+    restart_requested: tokio::sync::Notify,
+}
+
+#[derive(Debug, Default)]
+struct GuardState {
+    breach_started: Option<Instant>,
+    last_action_at: Option<Instant>,
+    escalation: usize,
+    last_cpu_sample: Option<(Instant, u64)>,
+    last_sample: Option<ProcessResources>,
+}
+
+impl ResourceGuard {
+    pub fn new(limits: ResourceLimits, actions: Vec<GuardAction>, sustained_for: Duration) -> Self {
+        Self {
+            limits,
+            actions,
+            sustained_for,
+            state: RwLock::new(GuardState::default()),
+            draining: AtomicBool::new(false),
+            restart_requested: tokio::sync::Notify::new(),
+        }
+    }
+
+    /// Sample the current process from procfs; unsupported platforms report zeros
+    pub async fn sample(&self) -> ProcessResources {
+        let rss_mb = std::fs::read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| {
+                status
+                    .lines()
+                    .find(|line| line.starts_with("VmRSS:"))
+                    .and_then(|line| line.split_whitespace().nth(1))
+                    .and_then(|kb| kb.parse::<f64>().ok())
+            })
+            .map(|kb| kb / 1024.0)
+            .unwrap_or(0.0);
+
+        let open_fds = std::fs::read_dir("/proc/self/fd")
+            .map(|entries| entries.count() as u64)
+            .unwrap_or(0);
+
+        // utime + stime in clock ticks (USER_HZ is 100 on all mainstream kernels)
+        let cpu_ticks = std::fs::read_to_string("/proc/self/stat")
+            .ok()
+            .and_then(|stat| {
+                let fields = stat
+                    .rsplit(')')
+                    .next()?
+                    .split_whitespace()
+                    .collect::<Vec<_>>();
+                let utime = fields.get(11)?.parse::<u64>().ok()?;
+                let stime = fields.get(12)?.parse::<u64>().ok()?;
+                Some(utime + stime)
+            });
+
+        let mut state = self.state.write().await;
+        let now = Instant::now();
+        let cpu_percent = match (cpu_ticks, state.last_cpu_sample) {
+            (Some(ticks), Some((last_at, last_ticks))) => {
+                let wall = now.duration_since(last_at).as_secs_f64();
+                let cores = std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1) as f64;
+                if wall > 0.0 {
+                    (ticks.saturating_sub(last_ticks) as f64 / 100.0) / wall / cores * 100.0
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        };
+        if let Some(ticks) = cpu_ticks {
+            state.last_cpu_sample = Some((now, ticks));
+        }
+
+        let resources = ProcessResources {
+            rss_mb,
+            open_fds,
+            cpu_percent,
+        };
+        state.last_sample = Some(resources.clone());
+        resources
+    }
+
+    /// Most recent sample taken by the guard loop
+    pub async fn last_sample(&self) -> Option<ProcessResources> {
+        self.state.read().await.last_sample.clone()
+    }
+
+    /// Evaluate a sample, returning the action to take if a breach has been sustained
+    pub async fn evaluate(&self, resources: &ProcessResources) -> Option<GuardDecision> {
+        let mut breaches = Vec::new();
+        if resources.rss_mb > self.limits.max_rss_mb {
+            breaches.push(format!(
+                "RSS {:.0}MB > {:.0}MB",
+                resources.rss_mb, self.limits.max_rss_mb
+            ));
+        }
+        if resources.open_fds > self.limits.max_open_fds {
+            breaches.push(format!(
+                "open FDs {} > {}",
+                resources.open_fds, self.limits.max_open_fds
+            ));
+        }
+        if resources.cpu_percent > self.limits.max_cpu_percent {
+            breaches.push(format!(
+                "CPU {:.1}% > {:.1}%",
+                resources.cpu_percent, self.limits.max_cpu_percent
+            ));
+        }
+
+        let mut state = self.state.write().await;
+
+        if breaches.is_empty() {
+            if state.breach_started.take().is_some() {
+                log::info!("Resource usage back within limits");
+            }
+            state.escalation = 0;
+            state.last_action_at = None;
+            self.draining.store(false, Ordering::Relaxed);
+            return None;
+        }
+
+        let now = Instant::now();
+        let breach_started = *state.breach_started.get_or_insert(now);
+
+        // Each escalation step waits a full sustain window after the previous action
+        let window_start = state.last_action_at.unwrap_or(breach_started);
+        if now.duration_since(window_start) < self.sustained_for {
+            return None;
+        }
+
+        let action = *self.actions.get(state.escalation)?;
+        state.escalation += 1;
+        state.last_action_at = Some(now);
+
+        Some(GuardDecision {
+            action,
+            trigger: format!(
+                "{} sustained for {}s",
+                breaches.join(", "),
+                now.duration_since(breach_started).as_secs()
+            ),
+        })
+    }
+
+    pub fn set_draining(&self, draining: bool) {
+        self.draining.store(draining, Ordering::Relaxed);
+    }
+
+    /// Whether new FHE work should be turned away while the engine drains
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn request_restart(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+        self.restart_requested.notify_one();
+    }
+
+    /// Resolves once a graceful self-restart has been requested
+    pub async fn restart_requested(&self) {
+        self.restart_requested.notified().await
+    }
+}
+
+/// Scheduling priority of a request. Derived server-side from the tenant's SLA
+/// class; clients may only ask for less than their class grants. `Critical` is
+/// never granted to client traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestPriority {
+    Low = 1,
+    Normal = 2,
+    High = 3,
+    Critical = 4,
+}
+
+impl RequestPriority {
+    pub fn parse(priority: &str) -> Result<Self> {
+        match priority {
+            "low" => Ok(RequestPriority::Low),
+            "normal" => Ok(RequestPriority::Normal),
+            "high" => Ok(RequestPriority::High),
+            "critical" => Ok(RequestPriority::Critical),
+            other => Err(Error::Validation(format!(
+                "Unknown request priority: {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestPriority::Low => "low",
+            RequestPriority::Normal => "normal",
+            RequestPriority::High => "high",
+            RequestPriority::Critical => "critical",
+        }
+    }
+
+    /// Highest priority an SLA class is entitled to
+    pub fn ceiling(sla_class: SlaClass) -> Self {
+        match sla_class {
+            SlaClass::Gold => RequestPriority::High,
+            SlaClass::Silver => RequestPriority::Normal,
+            SlaClass::Bronze => RequestPriority::Low,
+        }
+    }
+
+    /// Effective priority for a request: the class ceiling, or the client's
+    /// requested priority when that is lower. Upgrades are silently capped.
+    pub fn for_sla(sla_class: SlaClass, requested: Option<RequestPriority>) -> Self {
+        let ceiling = Self::ceiling(sla_class);
+        requested.map_or(ceiling, |requested| requested.min(ceiling))
+    }
+
+    /// Fraction of in-flight capacity this priority may fill before it is shed
+    fn admission_share(&self) -> f64 {
+        match self {
+            RequestPriority::Low => 0.7,
+            RequestPriority::Normal => 0.9,
+            RequestPriority::High | RequestPriority::Critical => 1.0,
+        }
+    }
+}
+
+/// Bounds in-flight requests, shedding lower priorities first as the proxy
+/// approaches capacity so that higher SLA classes keep headroom under load
+#[derive(Debug)]
+pub struct PriorityAdmission {
+    capacity: usize,
+    in_flight: AtomicUsize,
+}
+
+/// Holds an admission slot until dropped
+#[derive(Debug)]
+pub struct AdmissionPermit<'a> {
+    in_flight: &'a AtomicUsize,
+}
+
+impl Drop for AdmissionPermit<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl PriorityAdmission {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    fn limit(&self, priority: RequestPriority) -> usize {
+        ((self.capacity as f64 * priority.admission_share()).ceil() as usize).max(1)
+    }
+
+    /// Admit a request if its priority's share of capacity is not yet used up
+    pub fn try_admit(&self, priority: RequestPriority) -> Option<AdmissionPermit<'_>> {
+        let limit = self.limit(priority);
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                (current < limit).then_some(current + 1)
+            })
+            .ok()
+            .map(|_| AdmissionPermit {
+                in_flight: &self.in_flight,
+            })
+    }
+
+    /// Admit a request past its priority's share, when shedding it is only
+    /// simulated; it still counts as in flight
+    pub fn admit_unchecked(&self) -> AdmissionPermit<'_> {
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        AdmissionPermit {
+            in_flight: &self.in_flight,
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Requests of `priority` that would be admitted right now
+    pub fn available(&self, priority: RequestPriority) -> usize {
+        self.limit(priority).saturating_sub(self.in_flight())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// One resize of an autoscaled worker pool
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerResizeEvent {
+    pub from: usize,
+    pub to: usize,
+    pub reason: String,
+    pub queue_wait_p95_ms: u64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerAutoscalerStats {
+    pub enabled: bool,
+    pub workers: usize,
+    /// Over the waits recorded since the last resize
+    pub queue_wait_p95_ms: Option<u64>,
+    pub grow_events: u64,
+    pub shrink_events: u64,
+    pub last_resize: Option<WorkerResizeEvent>,
+}
+
+#[derive(Debug, Default)]
+struct WorkerScalingState {
+    queue_waits: VecDeque<Duration>,
+    last_resize: Option<Instant>,
+    idle_since: Option<Instant>,
+    grow_events: u64,
+    shrink_events: u64,
+    last_event: Option<WorkerResizeEvent>,
+}
+
+impl WorkerScalingState {
+    fn queue_wait_p95(&self) -> Option<Duration> {
+        if self.queue_waits.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<_> = self.queue_waits.iter().copied().collect();
+        sorted.sort_unstable();
+        let index = ((sorted.len() as f64 * 0.95).ceil() as usize).saturating_sub(1);
+        Some(sorted[index])
+    }
+}
+
+/// Sizes a worker pool by how long work waits for a worker: one worker is
+/// added while the p95 wait is above target, and one retired once the pool
+/// has been idle with waits well below it. The pool applies the decisions.
+#[derive(Debug)]
+pub struct WorkerAutoscaler {
+    config: WorkerAutoscalingConfig,
+    workers: AtomicUsize,
+    state: std::sync::Mutex<WorkerScalingState>,
+}
+
+impl WorkerAutoscaler {
+    /// Upper bound on retained queue wait samples
+    const MAX_WAIT_SAMPLES: usize = 1024;
+
+    /// A pool of `workers`, kept within the configured bounds when enabled
+    pub fn new(config: WorkerAutoscalingConfig, workers: usize) -> Self {
+        let workers = if config.enabled {
+            workers.clamp(config.min_workers, config.max_workers)
+        } else {
+            workers
+        };
+        Self {
+            config,
+            workers: AtomicUsize::new(workers),
+            state: std::sync::Mutex::new(WorkerScalingState::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval_seconds)
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.load(Ordering::Acquire)
+    }
+
+    /// Record how long one piece of work waited before a worker picked it up
+    pub fn record_queue_wait(&self, wait: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.queue_waits.push_back(wait);
+        if state.queue_waits.len() > Self::MAX_WAIT_SAMPLES {
+            state.queue_waits.pop_front();
+        }
+    }
+
+    /// Grow or shrink the pool by one worker; `idle` when nothing is running
+    /// or waiting
+    pub fn evaluate(&self, idle: bool, now: Instant) -> Option<WorkerResizeEvent> {
+        if !self.config.enabled {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        if idle {
+            state.idle_since.get_or_insert(now);
+        } else {
+            state.idle_since = None;
+        }
+        let cooldown = Duration::from_secs(self.config.cooldown_seconds);
+        if state
+            .last_resize
+            .is_some_and(|last| now.duration_since(last) < cooldown)
+        {
+            return None;
+        }
+
+        let current = self.workers();
+        let p95 = state.queue_wait_p95();
+        let target = Duration::from_millis(self.config.target_queue_wait_p95_ms);
+        let shrink_below = target.mul_f64(self.config.shrink_below_ratio);
+        let idle_period = Duration::from_secs(self.config.idle_period_seconds);
+        let (to, reason) = match p95 {
+            Some(p95) if p95 > target && current < self.config.max_workers => (
+                current + 1,
+                format!(
+                    "queue wait p95 {}ms above target {}ms",
+                    p95.as_millis(),
+                    target.as_millis()
+                ),
+            ),
+            _ if current > self.config.min_workers
+                && p95.is_none_or(|p95| p95 < shrink_below)
+                && state
+                    .idle_since
+                    .is_some_and(|since| now.duration_since(since) >= idle_period) =>
+            {
+                (current - 1, format!("idle for {}s", idle_period.as_secs()))
+            }
+            _ => return None,
+        };
+
+        self.workers.store(to, Ordering::Release);
+        if to > current {
+            state.grow_events += 1;
+        } else {
+            state.shrink_events += 1;
+        }
+        // Later decisions only look at waits under the new size
+        state.queue_waits.clear();
+        state.last_resize = Some(now);
+        state.idle_since = None;
+        let event = WorkerResizeEvent {
+            from: current,
+            to,
+            reason,
+            queue_wait_p95_ms: p95.map(|d| d.as_millis() as u64).unwrap_or(0),
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+        log::info!(
+            "Resized worker pool from {} to {}: {}",
+            event.from,
+            event.to,
+            event.reason
+        );
+        state.last_event = Some(event.clone());
+        Some(event)
+    }
+
+    pub fn stats(&self) -> WorkerAutoscalerStats {
+        let state = self.state.lock().unwrap();
+        WorkerAutoscalerStats {
+            enabled: self.config.enabled,
+            workers: self.workers(),
+            queue_wait_p95_ms: state.queue_wait_p95().map(|d| d.as_millis() as u64),
+            grow_events: state.grow_events,
+            shrink_events: state.shrink_events,
+            last_resize: state.last_event.clone(),
+        }
+    }
+}
+
+/// An admission-control or load-shed policy that can be simulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShedPolicy {
+    RateLimit,
+    QueueProjection,
+    PriorityShedding,
+}
+
+impl ShedPolicy {
+    pub const ALL: [ShedPolicy; 3] = [
+        ShedPolicy::RateLimit,
+        ShedPolicy::QueueProjection,
+        ShedPolicy::PriorityShedding,
+    ];
+
+    pub fn parse(policy: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str() == policy)
+            .ok_or_else(|| Error::Validation(format!("Unknown load shedding policy: {}", policy)))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShedPolicy::RateLimit => "rate_limit",
+            ShedPolicy::QueueProjection => "queue_projection",
+            ShedPolicy::PriorityShedding => "priority_shedding",
+        }
+    }
+}
+
+/// The request a shedding decision is about
+#[derive(Debug, Clone, Serialize)]
+pub struct ShedContext {
+    pub path: String,
+    pub tenant: Option<String>,
+    pub priority: RequestPriority,
+}
+
+/// A request a simulated policy would have turned away
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulatedShed {
+    pub policy: ShedPolicy,
+    pub at: i64,
+    pub status: u16,
+    #[serde(flatten)]
+    pub request: ShedContext,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShedPolicyStats {
+    pub policy: ShedPolicy,
+    pub mode: ShedPolicyMode,
+    pub shed: u64,
+    /// Requests admitted that enforcement would have turned away
+    pub would_shed: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadSheddingStats {
+    pub policies: Vec<ShedPolicyStats>,
+    /// Most recent simulated decisions, newest last
+    pub recent: Vec<SimulatedShed>,
+}
+
+#[derive(Debug, Default)]
+struct ShedCounters {
+    shed: AtomicU64,
+    would_shed: AtomicU64,
+}
+
+/// Enforcement mode of each shedding policy, switchable at runtime, and the
+/// record of what simulated policies would have done
+#[derive(Debug)]
+pub struct LoadShedPolicies {
+    config: LoadSheddingConfig,
+    modes: std::sync::RwLock<HashMap<ShedPolicy, ShedPolicyMode>>,
+    counters: [ShedCounters; 3],
+    recent: std::sync::Mutex<VecDeque<SimulatedShed>>,
+}
+
+impl LoadShedPolicies {
+    pub fn new(config: LoadSheddingConfig) -> Self {
+        let modes = HashMap::from([
+            (ShedPolicy::RateLimit, config.rate_limit),
+            (ShedPolicy::QueueProjection, config.queue_projection),
+            (ShedPolicy::PriorityShedding, config.priority_shedding),
+        ]);
+        Self {
+            config,
+            modes: std::sync::RwLock::new(modes),
+            counters: Default::default(),
+            recent: std::sync::Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn mode(&self, policy: ShedPolicy) -> ShedPolicyMode {
+        self.modes.read().unwrap()[&policy]
+    }
+
+    /// Switch a policy between enforcing and simulating, returning its previous mode
+    pub fn set_mode(&self, policy: ShedPolicy, mode: ShedPolicyMode) -> ShedPolicyMode {
+        self.modes
+            .write()
+            .unwrap()
+            .insert(policy, mode)
+            .unwrap_or(mode)
+    }
+
+    /// `policy` would turn `request` away with `status`: whether to do so. A
+    /// simulated policy admits the request and records the decision instead.
+    pub fn shed(&self, policy: ShedPolicy, request: &ShedContext, status: u16) -> bool {
+        let counters = &self.counters[policy as usize];
+        if self.mode(policy) == ShedPolicyMode::Enforce {
+            counters.shed.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        counters.would_shed.fetch_add(1, Ordering::Relaxed);
+        if rand::random::<f64>() * 100.0 < self.config.log_sample_percent {
+            log::info!(
+                "Simulated {} would have shed {} priority request to {} with {}",
+                policy.as_str(),
+                request.priority.as_str(),
+                request.path,
+                status
+            );
+        }
+        if self.config.max_samples > 0 {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() >= self.config.max_samples {
+                recent.pop_front();
+            }
+            recent.push_back(SimulatedShed {
+                policy,
+                at: chrono::Utc::now().timestamp(),
+                status,
+                request: request.clone(),
+            });
+        }
+        false
+    }
+
+    pub fn stats(&self) -> LoadSheddingStats {
+        let policies = ShedPolicy::ALL
+            .into_iter()
+            .map(|policy| {
+                let counters = &self.counters[policy as usize];
+                ShedPolicyStats {
+                    policy,
+                    mode: self.mode(policy),
+                    shed: counters.shed.load(Ordering::Relaxed),
+                    would_shed: counters.would_shed.load(Ordering::Relaxed),
+                }
+            })
+            .collect();
+        LoadSheddingStats {
+            policies,
+            recent: self.recent.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+/// Projects how long a newly admitted request would take to complete from the
+/// current queue depth and the recently observed drain rate (Little's law)
+#[derive(Debug)]
+pub struct QueueProjector {
+    config: QueueProjectionConfig,
+    samples: std::sync::Mutex<ProjectorSamples>,
+}
+
+#[derive(Debug, Default)]
+struct ProjectorSamples {
+    completions: VecDeque<Instant>,
+    service_time: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueProjection {
+    pub queue_depth: usize,
+    pub throughput_per_second: f64,
+    pub service_time_ms: u64,
+    /// Time until the work already queued has drained
+    pub projected_wait_ms: u64,
+    /// Projected wait plus this request's own service time
+    pub projected_latency_ms: u64,
+}
+
+impl QueueProjection {
+    /// Seconds to back off before the projected latency fits `deadline`,
+    /// or `None` if it already does
+    pub fn retry_after(&self, deadline: Duration) -> Option<u64> {
+        let latency = Duration::from_millis(self.projected_latency_ms);
+        (latency > deadline).then(|| ((latency - deadline).as_secs_f64().ceil() as u64).max(1))
+    }
+}
+
+impl QueueProjector {
+    pub fn new(config: QueueProjectionConfig) -> Self {
+        Self {
+            config,
+            samples: std::sync::Mutex::new(ProjectorSamples::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn record_completion(&self, service_time: Duration) {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.throughput_window_seconds);
+        let mut samples = self.samples.lock().unwrap();
+
+        samples.completions.push_back(now);
+        while samples
+            .completions
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            samples.completions.pop_front();
+        }
+        samples.service_time = Some(match samples.service_time {
+            Some(average) => {
+                average.mul_f64(1.0 - self.config.ewma_alpha)
+                    + service_time.mul_f64(self.config.ewma_alpha)
+            }
+            None => service_time,
+        });
+    }
+
+    pub fn project(&self, queue_depth: usize) -> QueueProjection {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.throughput_window_seconds);
+        let samples = self.samples.lock().unwrap();
+
+        let recent = samples
+            .completions
+            .iter()
+            .filter(|t| now.duration_since(**t) <= window)
+            .count();
+        let throughput = recent as f64 / window.as_secs_f64();
+        let service_time = samples.service_time.unwrap_or_default();
+
+        // Without recent completions, assume queued work drains one at a time
+        let wait = if throughput > 0.0 {
+            Duration::from_secs_f64(queue_depth as f64 / throughput)
+        } else {
+            service_time * queue_depth as u32
+        };
+
+        QueueProjection {
+            queue_depth,
+            throughput_per_second: throughput,
+            service_time_ms: service_time.as_millis() as u64,
+            projected_wait_ms: wait.as_millis() as u64,
+            projected_latency_ms: (wait + service_time).as_millis() as u64,
+        }
+    }
+}
+
+/// Minutes past midnight for an "HH:MM" window boundary
+fn parse_window_time(value: &str) -> Result<u32> {
+    let (hours, minutes) = value
+        .split_once(':')
+        .and_then(|(h, m)| Some((h.parse::<u32>().ok()?, m.parse::<u32>().ok()?)))
+        .filter(|(h, m)| *h < 24 && *m < 60)
+        .ok_or_else(|| Error::Config(format!("Invalid batch window time: {}", value)))?;
+    Ok(hours * 60 + minutes)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchQuotaUsage {
+    pub tenant: String,
+    pub day: chrono::NaiveDate,
+    pub used_units: f64,
+    pub daily_quota_units: f64,
+}
+
+/// Decides when queued batch jobs may run and meters their discounted quota
+#[derive(Debug)]
+pub struct BatchWindowScheduler {
+    config: BatchWindowsConfig,
+    windows: std::sync::RwLock<Vec<BatchWindowConfig>>,
+    /// Units charged per tenant on the current UTC day
+    usage: std::sync::Mutex<HashMap<String, (chrono::NaiveDate, f64)>>,
+}
+
+impl BatchWindowScheduler {
+    pub fn new(config: BatchWindowsConfig) -> Self {
+        Self {
+            windows: std::sync::RwLock::new(config.windows.clone()),
+            config,
+            usage: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &BatchWindowsConfig {
+        &self.config
+    }
+
+    /// Reject empty names, malformed times and zero-length windows
+    pub fn validate_windows(windows: &[BatchWindowConfig]) -> Result<()> {
+        for window in windows {
+            if window.name.is_empty() {
+                return Err(Error::Config(
+                    "Batch window names must not be empty".to_string(),
+                ));
+            }
+            if parse_window_time(&window.start)? == parse_window_time(&window.end)? {
+                return Err(Error::Config(format!(
+                    "Batch window {} has the same start and end",
+                    window.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn windows(&self) -> Vec<BatchWindowConfig> {
+        self.windows.read().unwrap().clone()
+    }
+
+    pub fn set_windows(&self, windows: Vec<BatchWindowConfig>) -> Result<()> {
+        Self::validate_windows(&windows)?;
+        *self.windows.write().unwrap() = windows;
+        Ok(())
+    }
+
+    /// Name of the window open at `now`, if any
+    pub fn active_window(&self, now: chrono::DateTime<chrono::Utc>) -> Option<String> {
+        use chrono::Timelike;
+        let minute = now.hour() * 60 + now.minute();
+        self.windows
+            .read()
+            .unwrap()
+            .iter()
+            .find(|window| {
+                let (Ok(start), Ok(end)) = (
+                    parse_window_time(&window.start),
+                    parse_window_time(&window.end),
+                ) else {
+                    return false;
+                };
+                if start < end {
+                    (start..end).contains(&minute)
+                } else {
+                    minute >= start || minute < end
+                }
+            })
+            .map(|window| window.name.clone())
+    }
+
+    /// When queued work will next be picked up: `now` inside a window,
+    /// otherwise the earliest upcoming window start
+    pub fn next_window_start(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        if self.active_window(now).is_some() {
+            return Some(now);
+        }
+        let midnight = now.date_naive().and_hms_opt(0, 0, 0)?.and_utc();
+        self.windows
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|window| parse_window_time(&window.start).ok())
+            .map(|start| {
+                let today = midnight + chrono::Duration::minutes(start as i64);
+                if today > now {
+                    today
+                } else {
+                    today + chrono::Duration::days(1)
+                }
+            })
+            .min()
+    }
+
+    /// Charge a discounted job of `prompts` prompts against the tenant's daily
+    /// quota, returning the units charged
+    pub fn charge(
+        &self,
+        tenant: &str,
+        prompts: usize,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<f64> {
+        let units = self.config.quota_discount * prompts as f64;
+        let day = now.date_naive();
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(tenant.to_string()).or_insert((day, 0.0));
+        if entry.0 != day {
+            *entry = (day, 0.0);
+        }
+        if entry.1 + units > self.config.daily_quota_units {
+            return Err(Error::RateLimit(format!(
+                "Tenant {} has used its daily batch quota of {} units",
+                tenant, self.config.daily_quota_units
+            )));
+        }
+        entry.1 += units;
+        Ok(units)
+    }
+
+    /// Re-apply a charge recorded before a restart
+    pub fn restore_charge(
+        &self,
+        tenant: &str,
+        units: f64,
+        charged_at: chrono::DateTime<chrono::Utc>,
+    ) {
+        let day = charged_at.date_naive();
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(tenant.to_string()).or_insert((day, 0.0));
+        if entry.0 < day {
+            *entry = (day, 0.0);
+        }
+        if entry.0 == day {
+            entry.1 += units;
+        }
+    }
+
+    /// Return the units of a cancelled job, if it was charged today
+    pub fn refund(&self, tenant: &str, units: f64, charged_at: chrono::DateTime<chrono::Utc>) {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(entry) = usage.get_mut(tenant) {
+            if entry.0 == charged_at.date_naive() {
+                entry.1 = (entry.1 - units).max(0.0);
+            }
+        }
+    }
+
+    pub fn usage(&self, tenant: &str, now: chrono::DateTime<chrono::Utc>) -> BatchQuotaUsage {
+        let day = now.date_naive();
+        let used_units = self
+            .usage
+            .lock()
+            .unwrap()
+            .get(tenant)
+            .filter(|(charged_day, _)| *charged_day == day)
+            .map_or(0.0, |(_, units)| *units);
+        BatchQuotaUsage {
+            tenant: tenant.to_string(),
+            day,
+            used_units,
+            daily_quota_units: self.config.daily_quota_units,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{sleep, Duration};
+
+    #[tokio::test]
+    async fn test_fhe_connection_pool() {
+        let params = FheParams::default();
+        let pool = FheConnectionPool::new(2, 4, params).unwrap();
+
+        // Generate keys in the first engine and replicate to others
+        let client_id = {
+            let mut first_engine = pool.engines[0].write().await;
+            let (client_id, _) = first_engine.generate_keys().unwrap();
+            client_id
+        };
+
+        // Replicate the client key to all other engines to handle load balancing
+        let client_key = {
+            let first_engine = pool.engines[0].read().await;
+            first_engine.client_keys.get(&client_id).unwrap().clone()
+        };
+
+        for (i, pool_engine) in pool.engines.iter().enumerate() {
+            if i > 0 {
+                // Skip first engine as it already has the key
+                let mut engine_guard = pool_engine.write().await;
+                engine_guard
+                    .client_keys
+                    .insert(client_id, client_key.clone());
+            }
+        }
+
+        let plaintext = "Hello, world!";
+
+        // Test encryption
+        let ciphertext = pool.encrypt_balanced(client_id, plaintext).await.unwrap();
+        assert!(!ciphertext.data.is_empty());
+
+        // Test decryption
+        let decrypted = pool.decrypt_balanced(client_id, &ciphertext).await.unwrap();
+        assert_eq!(decrypted, plaintext);
+
+        // Check stats
+        let stats = pool.get_stats().await;
+        assert_eq!(stats.total_operations, 2);
+    }
+
+    #[tokio::test]
+    async fn test_pool_refuses_mismatched_engine_fingerprints() {
+        let params = FheParams {
+            poly_modulus_degree: 1024,
+            coeff_modulus_bits: vec![40, 40],
+            ..FheParams::default()
+        };
+        let mut pool = FheConnectionPool::new(2, 4, params.clone()).unwrap();
+        let other_params = FheParams {
+            scale_bits: 30,
+            ..params.clone()
+        };
+        let stale = FheEngine::new(other_params.clone()).unwrap();
+        assert!(pool.add_engine(stale).await.is_err());
+        assert!(pool.scale_pool(3, other_params.clone()).await.is_err());
+        assert!(pool.fingerprint_report().await.is_uniform());
+
+        // Tables are checked once loaded; unwarmed engines still match
+        let warm = FheEngine::new(params.clone()).unwrap();
+        warm.warm_up().unwrap();
+        assert_eq!(pool.add_engine(warm).await.unwrap(), 2);
+
+        // With mixing allowed the stale engine serves but is reported
+        let mut pool = pool.with_uniform_fingerprints(false);
+        let stale = FheEngine::new(other_params).unwrap();
+        assert_eq!(pool.add_engine(stale).await.unwrap(), 3);
+        let report = pool.fingerprint_report().await;
+        assert!(!report.is_uniform());
+        let mismatched: Vec<usize> = report.mismatched().iter().map(|e| e.index).collect();
+        assert_eq!(mismatched, vec![3]);
+        assert_eq!(
+            report.reference.unwrap().params_digest,
+            params.fingerprint()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ciphertext_cache() {
+        let cache = CiphertextCache::new(2, Duration::from_secs(1));
+        let id = Uuid::new_v4();
+        let ciphertext = Ciphertext {
+            id,
+            data: vec![1, 2, 3, 4],
+            params: FheParams::default(),
+            noise_budget: Some(50),
+        };
+
+        // Test put and get
+        cache.put(id, ciphertext.clone()).await;
+        let retrieved = cache.get(&id).await.unwrap();
+        assert_eq!(retrieved.data, ciphertext.data);
+
+        // Test cache stats
+        let stats = cache.get_stats().await;
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.current_size, 1);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(2, 1, Duration::from_millis(100));
+
+        // Test successful operation
+        let result = breaker.call(async { Ok::<i32, &str>(42) }).await;
+        assert!(result.is_ok());
+
+        // Test failures
+        for _ in 0..2 {
+            let _ = breaker.call(async { Err::<i32, &str>("failure") }).await;
+        }
+
+        // Circuit should be open now
+        let state = breaker.get_state().await;
+        assert_eq!(state, CircuitState::Open);
+
+        // Test that requests are rejected
+        let result = breaker.call(async { Ok::<i32, &str>(42) }).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_auto_scaler() {
+        let scaler = AutoScaler::new(70.0, 10, 1, 5, Duration::from_millis(50));
+
+        // Wait for cooldown period to pass
+        sleep(Duration::from_millis(60)).await;
+
+        let metrics = ScalingMetrics {
+            cpu_utilization: 85.0,
+            memory_utilization: 60.0,
+            queue_length: 15,
+            active_connections: 20,
+            response_time_p95: Duration::from_millis(200),
+        };
+
+        let decision = scaler.evaluate_scaling(&metrics).await;
+
+        match decision {
+            ScalingDecision::ScaleUp { from, to, .. } => {
+                assert_eq!(from, 1);
+                assert_eq!(to, 2);
+            }
+            _ => unreachable!("Expected scale up decision"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resource_guard_escalation() {
+        let guard = ResourceGuard::new(
+            ResourceLimits {
+                max_rss_mb: 100.0,
+                max_open_fds: 10,
+                max_cpu_percent: 90.0,
+            },
+            vec![GuardAction::FlushCache, GuardAction::Restart],
+            Duration::from_millis(20),
+        );
+        let breached = ProcessResources {
+            rss_mb: 150.0,
+            open_fds: 5,
+            cpu_percent: 10.0,
+        };
+
+        // Breach must be sustained before acting
+        assert!(guard.evaluate(&breached).await.is_none());
+        sleep(Duration::from_millis(30)).await;
+        let decision = guard.evaluate(&breached).await.unwrap();
+        assert_eq!(decision.action, GuardAction::FlushCache);
+        assert!(decision.trigger.contains("RSS"));
+
+        // Escalates only after another full window
+        assert!(guard.evaluate(&breached).await.is_none());
+        sleep(Duration::from_millis(30)).await;
+        let decision = guard.evaluate(&breached).await.unwrap();
+        assert_eq!(decision.action, GuardAction::Restart);
+
+        // Recovery resets escalation
+        guard.set_draining(true);
+        assert!(guard.evaluate(&ProcessResources::default()).await.is_none());
+        assert!(!guard.is_draining());
+    }
+
+    #[test]
+    fn test_sla_priority_admission() {
+        // Clients may downgrade but never exceed their class ceiling
+        assert_eq!(
+            RequestPriority::for_sla(SlaClass::Bronze, Some(RequestPriority::Critical)),
+            RequestPriority::Low
+        );
+        assert_eq!(
+            RequestPriority::for_sla(SlaClass::Gold, Some(RequestPriority::Low)),
+            RequestPriority::Low
+        );
+        assert_eq!(
+            RequestPriority::for_sla(SlaClass::Gold, None),
+            RequestPriority::High
+        );
+
+        let admission = PriorityAdmission::new(10);
+        let held: Vec<_> = (0..7)
+            .map(|_| admission.try_admit(RequestPriority::Low).unwrap())
+            .collect();
+
+        // Low priority is shed first, high priority keeps headroom
+        assert!(admission.try_admit(RequestPriority::Low).is_none());
+        let high = admission.try_admit(RequestPriority::High);
+        assert!(high.is_some());
+        assert_eq!(admission.in_flight(), 8);
+        assert_eq!(admission.available(RequestPriority::Low), 0);
+        assert_eq!(admission.available(RequestPriority::Normal), 1);
+
+        drop(held);
+        drop(high);
+        assert_eq!(admission.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_simulated_shedding_admits_and_records() {
+        let policies = LoadShedPolicies::new(LoadSheddingConfig {
+            priority_shedding: ShedPolicyMode::Simulate,
+            log_sample_percent: 0.0,
+            max_samples: 2,
+            ..LoadSheddingConfig::default()
+        });
+        let request = ShedContext {
+            path: "/v1/chat/completions".to_string(),
+            tenant: Some("acme".to_string()),
+            priority: RequestPriority::Low,
+        };
+
+        assert!(policies.shed(ShedPolicy::RateLimit, &request, 429));
+        for _ in 0..3 {
+            assert!(!policies.shed(ShedPolicy::PriorityShedding, &request, 503));
+        }
+        let stats = policies.stats();
+        assert_eq!(stats.policies[0].shed, 1);
+        assert_eq!(stats.policies[2].would_shed, 3);
+        assert_eq!(stats.policies[2].shed, 0);
+        assert_eq!(stats.recent.len(), 2);
+
+        // Simulated admission still counts against capacity
+        let admission = PriorityAdmission::new(1);
+        let _held = admission.admit_unchecked();
+        assert!(admission.try_admit(RequestPriority::High).is_none());
+
+        // Flipping to enforce takes effect on the next decision
+        assert_eq!(
+            policies.set_mode(ShedPolicy::PriorityShedding, ShedPolicyMode::Enforce),
+            ShedPolicyMode::Simulate
+        );
+        assert!(policies.shed(ShedPolicy::PriorityShedding, &request, 503));
+        assert_eq!(
+            ShedPolicy::parse("queue_projection").unwrap(),
+            ShedPolicy::QueueProjection
+        );
+        assert!(ShedPolicy::parse("everything").is_err());
+    }
+
+    #[test]
+    fn test_queue_projection_retry_after() {
+        let projector = QueueProjector::new(QueueProjectionConfig {
+            throughput_window_seconds: 10,
+            ewma_alpha: 0.5,
+            ..QueueProjectionConfig::default()
+        });
+
+        // Before any completions there is nothing to project from
+        assert_eq!(projector.project(5).projected_latency_ms, 0);
+
+        for _ in 0..20 {
+            projector.record_completion(Duration::from_millis(200));
+        }
+        let idle = projector.project(0);
+        assert_eq!(idle.service_time_ms, 200);
+        assert_eq!(idle.projected_wait_ms, 0);
+        assert_eq!(idle.retry_after(Duration::from_secs(1)), None);
+
+        // 20 completions in a 10s window drain 2 requests per second
+        let saturated = projector.project(40);
+        assert_eq!(saturated.throughput_per_second, 2.0);
+        assert_eq!(saturated.projected_wait_ms, 20_000);
+        assert_eq!(saturated.projected_latency_ms, 20_200);
+        assert_eq!(saturated.retry_after(Duration::from_secs(30)), None);
+        assert_eq!(saturated.retry_after(Duration::from_secs(5)), Some(16));
+    }
+
+    #[test]
+    fn test_batch_windows_and_quota() {
+        use chrono::TimeZone;
+        let scheduler = BatchWindowScheduler::new(BatchWindowsConfig {
+            enabled: true,
+            windows: vec![BatchWindowConfig {
+                name: "overnight".to_string(),
+                start: "22:00".to_string(),
+                end: "02:00".to_string(),
+            }],
+            quota_discount: 0.5,
+            daily_quota_units: 1.0,
+            ..BatchWindowsConfig::default()
+        });
+
+        // The window wraps past midnight
+        let at = |h, m| chrono::Utc.with_ymd_and_hms(2024, 5, 1, h, m, 0).unwrap();
+        assert_eq!(
+            scheduler.active_window(at(23, 30)).as_deref(),
+            Some("overnight")
+        );
+        assert_eq!(
+            scheduler.active_window(at(1, 59)).as_deref(),
+            Some("overnight")
+        );
+        assert_eq!(scheduler.active_window(at(2, 0)), None);
+        assert_eq!(scheduler.next_window_start(at(12, 0)), Some(at(22, 0)));
+        assert_eq!(scheduler.next_window_start(at(23, 0)), Some(at(23, 0)));
+
+        // Two discounted jobs fit the daily quota, a third does not
+        assert_eq!(scheduler.charge("acme", 1, at(9, 0)).unwrap(), 0.5);
+        scheduler.charge("acme", 1, at(10, 0)).unwrap();
+        assert!(scheduler.charge("acme", 1, at(11, 0)).is_err());
+        scheduler.refund("acme", 0.5, at(10, 0));
+        assert_eq!(scheduler.usage("acme", at(12, 0)).used_units, 0.5);
+        // Usage resets on the next UTC day
+        let tomorrow = at(9, 0) + chrono::Duration::days(1);
+        assert_eq!(scheduler.usage("acme", tomorrow).used_units, 0.0);
+
+        assert!(scheduler
+            .set_windows(vec![BatchWindowConfig {
+                name: "bad".to_string(),
+                start: "25:00".to_string(),
+                end: "02:00".to_string(),
+            }])
+            .is_err());
+        assert_eq!(scheduler.windows().len(), 1);
+    }
+
+    #[test]
+    fn test_worker_autoscaler_grows_on_waits_and_shrinks_when_idle() {
+        let autoscaler = WorkerAutoscaler::new(
+            WorkerAutoscalingConfig {
+                min_workers: 1,
+                max_workers: 3,
+                target_queue_wait_p95_ms: 20,
+                idle_period_seconds: 10,
+                cooldown_seconds: 0,
+                ..WorkerAutoscalingConfig::default()
+            },
+            2,
+        );
+        let now = Instant::now();
+        for _ in 0..20 {
+            autoscaler.record_queue_wait(Duration::from_millis(50));
+        }
+        let event = autoscaler.evaluate(false, now).unwrap();
+        assert_eq!((event.from, event.to), (2, 3));
+
+        // Bounded by max_workers
+        for _ in 0..20 {
+            autoscaler.record_queue_wait(Duration::from_millis(50));
+        }
+        assert!(autoscaler.evaluate(false, now).is_none());
+
+        // Inside the hysteresis band: no shrink even when idle
+        let later = now + Duration::from_secs(1);
+        autoscaler.evaluate(false, later);
+        autoscaler.state.lock().unwrap().queue_waits.clear();
+        autoscaler.record_queue_wait(Duration::from_millis(15));
+        assert!(autoscaler.evaluate(true, later).is_none());
+        assert!(autoscaler
+            .evaluate(true, later + Duration::from_secs(11))
+            .is_none());
+
+        autoscaler.state.lock().unwrap().queue_waits.clear();
+        let event = autoscaler
+            .evaluate(true, later + Duration::from_secs(12))
+            .unwrap();
+        assert_eq!((event.from, event.to), (3, 2));
+        let stats = autoscaler.stats();
+        assert_eq!(
+            (stats.workers, stats.grow_events, stats.shrink_events),
+            (2, 1, 1)
+        );
+
+        // Busy pools are not shrunk, and a cooldown spaces resizes out
+        let cooling = WorkerAutoscaler::new(
+            WorkerAutoscalingConfig {
+                target_queue_wait_p95_ms: 20,
+                cooldown_seconds: 30,
+                ..WorkerAutoscalingConfig::default()
+            },
+            4,
+        );
+        cooling.record_queue_wait(Duration::from_millis(50));
+        assert!(cooling.evaluate(false, now).is_some());
+        cooling.record_queue_wait(Duration::from_millis(50));
+        assert!(cooling
+            .evaluate(false, now + Duration::from_secs(1))
+            .is_none());
+        assert_eq!(cooling.workers(), 5);
+    }
+}
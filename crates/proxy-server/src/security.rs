@@ -0,0 +1,1394 @@
+//! Security utilities and authentication
+
+use crate::config::{HoneytokenConfig, ResponseSigningConfig};
+use crate::error::{Error, Result};
+use crate::fhe::{Ciphertext, FheParams};
+use crate::offboarding::{DestructionReport, SignedDestructionReport};
+use base64::prelude::*;
+use ring::digest;
+use ring::rand::SystemRandom;
+use ring::signature::{self, KeyPair};
+// Temporarily commenting out secrecy dependency - will implement later
+// use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+pub use fhe_core::{Attestation, AttestationStatement};
+pub use proxy_client::signing::{
+    verify_detached, verify_signed_response, Jwk, JwkSet, ResponseEnvelope, SignedResponseEnvelope,
+};
+
+/// Security metrics for monitoring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityMetrics {
+    pub total_tracked_ips: usize,
+    pub currently_blocked_ips: usize,
+    pub high_risk_ips: usize,
+    pub global_requests_remaining: u64,
+}
+
+/// API key management
+#[derive(Debug)]
+pub struct ApiKeyManager {
+    keys: HashMap<String, ApiKeyInfo>,
+    master_secret: String, // Temporarily using String instead of SecretString
+}
+
+#[derive(Debug, Clone)]
+struct ApiKeyInfo {
+    user_id: String,
+    permissions: Vec<Permission>,
+    created_at: Instant,
+    expires_at: Option<Instant>,
+    rate_limit: u32,
+    last_used: Option<Instant>,
+    usage_count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Permission {
+    Encrypt,
+    Decrypt,
+    ProcessLLM,
+    ViewMetrics,
+    Admin,
+}
+
+impl ApiKeyManager {
+    pub fn new(master_secret: String) -> Self {
+        Self {
+            keys: HashMap::new(),
+            master_secret,
+        }
+    }
+
+    /// Generate a new API key
+    pub fn generate_api_key(&mut self, user_id: String, permissions: Vec<Permission>) -> String {
+        let key_data = format!(
+            "{}-{}-{}",
+            user_id,
+            Uuid::new_v4(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        );
+
+        // Hash the key data with master secret
+        let key_hash = self.hash_with_secret(&key_data);
+        let api_key = BASE64_STANDARD.encode(&key_hash);
+
+        let key_info = ApiKeyInfo {
+            user_id,
+            permissions,
+            created_at: Instant::now(),
+            expires_at: Some(Instant::now() + Duration::from_secs(86400 * 30)), // 30 days
+            rate_limit: 1000,
+            last_used: None,
+            usage_count: 0,
+        };
+
+        self.keys.insert(api_key.clone(), key_info);
+        api_key
+    }
+
+    /// Validate API key and return user permissions
+    pub fn validate_api_key(&mut self, api_key: &str) -> Result<Vec<Permission>> {
+        let key_info = self
+            .keys
+            .get_mut(api_key)
+            .ok_or_else(|| Error::Auth("Invalid API key".to_string()))?;
+
+        // Check expiration
+        if let Some(expires_at) = key_info.expires_at {
+            if Instant::now() > expires_at {
+                return Err(Error::Auth("API key expired".to_string()));
+            }
+        }
+
+        // Update usage statistics
+        key_info.last_used = Some(Instant::now());
+        key_info.usage_count += 1;
+
+        Ok(key_info.permissions.clone())
+    }
+
+    /// Check if user has specific permission
+    pub fn has_permission(&self, api_key: &str, permission: &Permission) -> bool {
+        if let Some(key_info) = self.keys.get(api_key) {
+            key_info.permissions.contains(permission)
+                || key_info.permissions.contains(&Permission::Admin)
+        } else {
+            false
+        }
+    }
+
+    /// Revoke an API key
+    pub fn revoke_api_key(&mut self, api_key: &str) -> Result<()> {
+        self.keys
+            .remove(api_key)
+            .ok_or_else(|| Error::Auth("API key not found".to_string()))?;
+        log::info!("Revoked API key: {}", &api_key[..8]);
+        Ok(())
+    }
+
+    /// Get usage statistics for API key
+    pub fn get_key_stats(&self, api_key: &str) -> Option<(u64, Option<Instant>)> {
+        self.keys
+            .get(api_key)
+            .map(|info| (info.usage_count, info.last_used))
+    }
+
+    fn hash_with_secret(&self, data: &str) -> Vec<u8> {
+        let mut context = digest::Context::new(&digest::SHA256);
+        context.update(self.master_secret.as_bytes());
+        context.update(data.as_bytes());
+        context.finish().as_ref().to_vec()
+    }
+}
+
+/// Input sanitization and validation
+pub struct InputValidator;
+
+impl InputValidator {
+    /// Sanitize text input for FHE operations
+    pub fn sanitize_text(input: &str) -> Result<String> {
+        // Check length limits
+        if input.is_empty() {
+            return Err(Error::Validation("Input cannot be empty".to_string()));
+        }
+
+        if input.len() > 10_000 {
+            return Err(Error::Validation(
+                "Input too long (max 10,000 characters)".to_string(),
+            ));
+        }
+
+        // Remove control characters but preserve whitespace
+        let sanitized: String = input
+            .chars()
+            .filter(|c| !c.is_control() || c.is_whitespace())
+            .collect();
+
+        // Check for potential injection patterns
+        if Self::contains_suspicious_patterns(&sanitized) {
+            return Err(Error::Validation(
+                "Input contains suspicious patterns".to_string(),
+            ));
+        }
+
+        // Validate UTF-8
+        if !sanitized.is_ascii() {
+            // Allow Unicode but validate it's properly formed
+            match std::str::from_utf8(sanitized.as_bytes()) {
+                Ok(_) => {}
+                Err(_) => return Err(Error::Validation("Invalid UTF-8 sequence".to_string())),
+            }
+        }
+
+        Ok(sanitized)
+    }
+
+    /// Validate UUID format
+    pub fn validate_uuid(uuid_str: &str) -> Result<Uuid> {
+        uuid_str
+            .parse::<Uuid>()
+            .map_err(|_| Error::Validation(format!("Invalid UUID format: {}", uuid_str)))
+    }
+
+    /// Validate FHE parameters
+    pub fn validate_fhe_params(poly_degree: usize, security_level: u8) -> Result<()> {
+        if !poly_degree.is_power_of_two() {
+            return Err(Error::Validation(
+                "Polynomial modulus degree must be power of 2".to_string(),
+            ));
+        }
+
+        if !(1024..=32768).contains(&poly_degree) {
+            return Err(Error::Validation(
+                "Poly modulus degree must be 1024-32768".to_string(),
+            ));
+        }
+
+        if ![128, 192].contains(&security_level) {
+            return Err(Error::Validation(
+                "Security level must be 128 or 192".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Validate base64 encoded data
+    pub fn validate_base64(data: &str) -> Result<Vec<u8>> {
+        BASE64_STANDARD
+            .decode(data)
+            .map_err(|e| Error::Validation(format!("Invalid base64 data: {}", e)))
+    }
+
+    fn contains_suspicious_patterns(input: &str) -> bool {
+        let suspicious_patterns = [
+            "javascript:",
+            "data:",
+            "vbscript:",
+            "onload=",
+            "onerror=",
+            "<script",
+            "</script>",
+            "eval(",
+            "document.cookie",
+            "window.location",
+            "alert(",
+            "confirm(",
+            "prompt(",
+            "DROP TABLE",
+            "DELETE FROM",
+            "UPDATE SET",
+            "INSERT INTO",
+            "UNION SELECT",
+            "OR 1=1",
+            "AND 1=1",
+            "--",
+            "/*",
+            "*/",
+        ];
+
+        let input_lower = input.to_lowercase();
+        suspicious_patterns
+            .iter()
+            .any(|pattern| input_lower.contains(pattern))
+    }
+}
+
+/// Security audit logger
+pub struct SecurityAuditor;
+
+impl SecurityAuditor {
+    /// Log authentication events
+    pub fn log_auth_event(event_type: &str, user_id: &str, ip_address: &str, success: bool) {
+        log::warn!(
+            target: "security_audit",
+            "auth_event={} user_id={} ip={} success={} timestamp={}",
+            event_type,
+            user_id,
+            ip_address,
+            success,
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+        );
+    }
+
+    /// Log data access events
+    pub fn log_data_access(operation: &str, user_id: &str, resource_id: &str, ip_address: &str) {
+        log::info!(
+            target: "security_audit",
+            "data_access operation={} user_id={} resource_id={} ip={} timestamp={}",
+            operation,
+            user_id,
+            resource_id,
+            ip_address,
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+        );
+    }
+
+    /// Log security violations
+    pub fn log_security_violation(violation_type: &str, details: &str, ip_address: &str) {
+        log::error!(
+            target: "security_audit",
+            "security_violation type={} details='{}' ip={} timestamp={}",
+            violation_type,
+            details,
+            ip_address,
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+        );
+    }
+
+    /// Log privilege escalation attempts
+    pub fn log_privilege_escalation(user_id: &str, requested_permission: &str, ip_address: &str) {
+        log::error!(
+            target: "security_audit",
+            "privilege_escalation user_id={} permission='{}' ip={} timestamp={}",
+            user_id,
+            requested_permission,
+            ip_address,
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+        );
+    }
+}
+
+/// Content Security Policy (CSP) helper
+pub struct ContentSecurityPolicy;
+
+impl ContentSecurityPolicy {
+    pub fn default_policy() -> &'static str {
+        "default-src 'self'; \
+         script-src 'self' 'unsafe-inline'; \
+         style-src 'self' 'unsafe-inline'; \
+         img-src 'self' data:; \
+         connect-src 'self'; \
+         font-src 'self'; \
+         object-src 'none'; \
+         media-src 'self'; \
+         frame-src 'none'; \
+         base-uri 'self'; \
+         form-action 'self'"
+    }
+
+    pub fn api_policy() -> &'static str {
+        "default-src 'none'; \
+         connect-src 'self'"
+    }
+}
+
+/// Rate limiting implementation with adaptive thresholds
+#[derive(Debug)]
+pub struct AdaptiveRateLimiter {
+    client_buckets: HashMap<String, TokenBucket>,
+    global_bucket: TokenBucket,
+    suspicious_ips: HashMap<String, SuspiciousActivity>,
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    refill_rate: f64, // tokens per second
+    last_refill: Instant,
+}
+
+#[derive(Debug, Clone)]
+struct SuspiciousActivity {
+    violation_count: u32,
+    last_violation: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, refill_rate: f64) -> Self {
+        Self {
+            tokens: capacity,
+            capacity,
+            refill_rate,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_consume(&mut self, tokens: f64) -> bool {
+        self.refill();
+
+        if self.tokens >= tokens {
+            self.tokens -= tokens;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
+impl AdaptiveRateLimiter {
+    pub fn new(global_capacity: f64, global_refill_rate: f64) -> Self {
+        Self {
+            client_buckets: HashMap::new(),
+            global_bucket: TokenBucket::new(global_capacity, global_refill_rate),
+            suspicious_ips: HashMap::new(),
+        }
+    }
+
+    /// Check if request should be allowed
+    pub fn check_request(&mut self, client_ip: &str, tokens_requested: f64) -> Result<bool> {
+        // Check if IP is currently blocked
+        if let Some(suspicious) = self.suspicious_ips.get(client_ip) {
+            if let Some(blocked_until) = suspicious.blocked_until {
+                if Instant::now() < blocked_until {
+                    SecurityAuditor::log_security_violation(
+                        "blocked_ip_request",
+                        &format!("Request from blocked IP: {}", client_ip),
+                        client_ip,
+                    );
+                    return Ok(false);
+                }
+            }
+        }
+
+        // Check global rate limit first
+        if !self.global_bucket.try_consume(tokens_requested) {
+            self.record_violation(client_ip, "global_rate_limit");
+            return Ok(false);
+        }
+
+        // Get or create client bucket with adaptive limits
+        let client_capacity = self.calculate_adaptive_capacity(client_ip);
+        let client_bucket = self
+            .client_buckets
+            .entry(client_ip.to_string())
+            .or_insert_with(|| TokenBucket::new(client_capacity, client_capacity / 60.0)); // refill over 1 minute
+
+        if client_bucket.try_consume(tokens_requested) {
+            Ok(true)
+        } else {
+            self.record_violation(client_ip, "client_rate_limit");
+            Ok(false)
+        }
+    }
+
+    fn calculate_adaptive_capacity(&self, client_ip: &str) -> f64 {
+        if let Some(suspicious) = self.suspicious_ips.get(client_ip) {
+            // Reduce capacity for suspicious IPs
+            match suspicious.violation_count {
+                0..=2 => 100.0,
+                3..=5 => 50.0,
+                6..=10 => 20.0,
+                _ => 5.0,
+            }
+        } else {
+            100.0 // Default capacity
+        }
+    }
+
+    fn record_violation(&mut self, client_ip: &str, violation_type: &str) {
+        let suspicious = self
+            .suspicious_ips
+            .entry(client_ip.to_string())
+            .or_insert_with(|| SuspiciousActivity {
+                violation_count: 0,
+                last_violation: Instant::now(),
+                blocked_until: None,
+            });
+
+        suspicious.violation_count += 1;
+        suspicious.last_violation = Instant::now();
+
+        // Escalate blocking based on violation count
+        let block_duration = match suspicious.violation_count {
+            1..=3 => None,                              // Warning only
+            4..=6 => Some(Duration::from_secs(60)),     // 1 minute
+            7..=10 => Some(Duration::from_secs(300)),   // 5 minutes
+            11..=20 => Some(Duration::from_secs(3600)), // 1 hour
+            _ => Some(Duration::from_secs(86400)),      // 24 hours
+        };
+
+        if let Some(duration) = block_duration {
+            suspicious.blocked_until = Some(Instant::now() + duration);
+            SecurityAuditor::log_security_violation(
+                "ip_temporarily_blocked",
+                &format!(
+                    "IP {} blocked for {} seconds due to {} violations",
+                    client_ip,
+                    duration.as_secs(),
+                    suspicious.violation_count
+                ),
+                client_ip,
+            );
+        }
+
+        SecurityAuditor::log_security_violation(
+            violation_type,
+            &format!("Rate limit violation #{}", suspicious.violation_count),
+            client_ip,
+        );
+    }
+
+    /// Clean up old entries to prevent memory leaks
+    pub fn cleanup_old_entries(&mut self) {
+        let cutoff = Instant::now() - Duration::from_secs(3600); // Clean up entries older than 1 hour
+
+        let before_count = self.suspicious_ips.len();
+
+        self.suspicious_ips.retain(|_, suspicious| {
+            suspicious.last_violation > cutoff
+                || suspicious
+                    .blocked_until
+                    .is_some_and(|until| Instant::now() < until)
+        });
+
+        let after_count = self.suspicious_ips.len();
+        if before_count != after_count {
+            log::debug!(
+                "Cleaned up {} old suspicious IP entries",
+                before_count - after_count
+            );
+        }
+    }
+
+    /// Get comprehensive security metrics
+    pub fn get_security_metrics(&self) -> SecurityMetrics {
+        let blocked_ips = self
+            .suspicious_ips
+            .values()
+            .filter(|s| s.blocked_until.is_some_and(|until| Instant::now() < until))
+            .count();
+
+        let high_risk_ips = self
+            .suspicious_ips
+            .values()
+            .filter(|s| s.violation_count >= 10)
+            .count();
+
+        SecurityMetrics {
+            total_tracked_ips: self.suspicious_ips.len(),
+            currently_blocked_ips: blocked_ips,
+            high_risk_ips,
+            global_requests_remaining: self.global_bucket.tokens as u64,
+        }
+    }
+
+    /// Emergency lockdown mode for security incidents
+    pub fn enable_emergency_lockdown(&mut self, duration: Duration) {
+        log::error!("EMERGENCY LOCKDOWN ACTIVATED for {:?}", duration);
+
+        // Set global bucket to zero
+        self.global_bucket.tokens = 0.0;
+        self.global_bucket.refill_rate = 0.0;
+
+        // Block all known IPs temporarily
+        let lockdown_end = Instant::now() + duration;
+        for (ip, suspicious) in self.suspicious_ips.iter_mut() {
+            suspicious.blocked_until = Some(lockdown_end);
+            SecurityAuditor::log_security_violation(
+                "emergency_lockdown",
+                "IP blocked during emergency lockdown",
+                ip,
+            );
+        }
+    }
+}
+
+/// SLSA-style provenance of the running binary, fixed at compile time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildProvenance {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    pub subject: ProvenanceSubject,
+    pub predicate: ProvenancePredicate,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProvenanceSubject {
+    pub name: String,
+    pub version: String,
+    pub engine_build_hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProvenancePredicate {
+    /// Source revision; `git_dirty` is set when the tree had uncommitted changes
+    pub git_commit: String,
+    pub git_dirty: Option<bool>,
+    pub features: Vec<String>,
+    /// SHA-256 (hex) of Cargo.lock
+    pub lockfile_sha256: String,
+    pub rustc_version: String,
+    pub target: String,
+    pub profile: String,
+    pub built_at: i64,
+}
+
+impl BuildProvenance {
+    pub fn current() -> Self {
+        Self {
+            statement_type: "https://in-toto.io/Statement/v1".to_string(),
+            predicate_type: "https://slsa.dev/provenance/v1".to_string(),
+            subject: ProvenanceSubject {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("BUILD_VERSION").to_string(),
+                engine_build_hash: AttestationService::engine_build_hash(),
+            },
+            predicate: ProvenancePredicate {
+                git_commit: env!("BUILD_GIT_COMMIT").to_string(),
+                git_dirty: env!("BUILD_GIT_DIRTY").parse().ok(),
+                features: env!("BUILD_FEATURES")
+                    .split(',')
+                    .filter(|f| !f.is_empty())
+                    .map(String::from)
+                    .collect(),
+                lockfile_sha256: env!("BUILD_LOCKFILE_SHA256").to_string(),
+                rustc_version: env!("BUILD_RUSTC_VERSION").to_string(),
+                target: env!("BUILD_TARGET").to_string(),
+                profile: env!("BUILD_PROFILE").to_string(),
+                built_at: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// Provenance signed with the attestation key; the signature covers the
+/// compact JSON encoding of `provenance`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedProvenance {
+    pub provenance: BuildProvenance,
+    pub key_id: String,
+    pub algorithm: String,
+    pub signature: String, // Base64 encoded
+}
+
+/// Public half of an attestation key, current or retired
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationKeyInfo {
+    pub key_id: String,
+    pub algorithm: String,
+    pub public_key: String, // Base64 encoded
+    pub created_at: i64,
+    pub retired_at: Option<i64>,
+}
+
+/// Signs server attestations with a rotating Ed25519 key
+#[derive(Debug)]
+pub struct AttestationService {
+    current: std::sync::RwLock<(String, signature::Ed25519KeyPair)>,
+    history: std::sync::RwLock<Vec<AttestationKeyInfo>>,
+    rng: SystemRandom,
+}
+
+impl AttestationService {
+    pub fn new() -> Result<Self> {
+        let rng = SystemRandom::new();
+        let (key_pair, info) = Self::generate_key(&rng)?;
+
+        Ok(Self {
+            current: std::sync::RwLock::new((info.key_id.clone(), key_pair)),
+            history: std::sync::RwLock::new(vec![info]),
+            rng,
+        })
+    }
+
+    /// Hash identifying the running engine build
+    pub fn engine_build_hash() -> String {
+        fhe_core::engine::build_hash()
+    }
+
+    /// Sign a statement that `request_digest` was processed in `mode` with `params`
+    pub fn attest(
+        &self,
+        request_digest: String,
+        mode: &str,
+        params: &FheParams,
+    ) -> Result<Attestation> {
+        let current = self.current.read().unwrap();
+        let statement = AttestationStatement {
+            request_digest,
+            mode: mode.to_string(),
+            parameter_profile: params.clone(),
+            engine_build_hash: Self::engine_build_hash(),
+            key_id: current.0.clone(),
+            issued_at: chrono::Utc::now().timestamp(),
+        };
+
+        let payload = serde_json::to_vec(&statement)?;
+        let signature = current.1.sign(&payload);
+
+        Ok(Attestation {
+            statement,
+            algorithm: "Ed25519".to_string(),
+            signature: BASE64_STANDARD.encode(signature.as_ref()),
+        })
+    }
+
+    /// Sign the provenance of the running binary with the current key
+    pub fn sign_provenance(&self, provenance: &BuildProvenance) -> Result<SignedProvenance> {
+        let current = self.current.read().unwrap();
+        let payload = serde_json::to_vec(provenance)?;
+        let signature = current.1.sign(&payload);
+
+        Ok(SignedProvenance {
+            provenance: provenance.clone(),
+            key_id: current.0.clone(),
+            algorithm: "Ed25519".to_string(),
+            signature: BASE64_STANDARD.encode(signature.as_ref()),
+        })
+    }
+
+    /// Sign a tenant's data destruction report with the current key
+    pub fn sign_destruction_report(
+        &self,
+        report: &DestructionReport,
+    ) -> Result<SignedDestructionReport> {
+        let current = self.current.read().unwrap();
+        let payload = serde_json::to_vec(report)?;
+        let signature = current.1.sign(&payload);
+
+        Ok(SignedDestructionReport {
+            report: report.clone(),
+            key_id: current.0.clone(),
+            algorithm: "Ed25519".to_string(),
+            signature: BASE64_STANDARD.encode(signature.as_ref()),
+        })
+    }
+
+    /// Replace the signing key, retiring the previous one
+    pub fn rotate(&self) -> Result<AttestationKeyInfo> {
+        let (key_pair, info) = Self::generate_key(&self.rng)?;
+        let now = chrono::Utc::now().timestamp();
+
+        let mut current = self.current.write().unwrap();
+        let mut history = self.history.write().unwrap();
+        if let Some(previous) = history.iter_mut().find(|k| k.key_id == current.0) {
+            previous.retired_at = Some(now);
+        }
+        history.push(info.clone());
+        *current = (info.key_id.clone(), key_pair);
+
+        log::info!("Rotated attestation key to {}", info.key_id);
+        Ok(info)
+    }
+
+    pub fn current_key(&self) -> AttestationKeyInfo {
+        let key_id = self.current.read().unwrap().0.clone();
+        self.history
+            .read()
+            .unwrap()
+            .iter()
+            .find(|k| k.key_id == key_id)
+            .cloned()
+            .expect("Current attestation key must be in history")
+    }
+
+    /// All keys ever used, oldest first
+    pub fn key_history(&self) -> Vec<AttestationKeyInfo> {
+        self.history.read().unwrap().clone()
+    }
+
+    fn generate_key(rng: &SystemRandom) -> Result<(signature::Ed25519KeyPair, AttestationKeyInfo)> {
+        let pkcs8 = signature::Ed25519KeyPair::generate_pkcs8(rng)
+            .map_err(|_| Error::Cryptographic("Failed to generate attestation key".to_string()))?;
+        let key_pair = signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+            .map_err(|_| Error::Cryptographic("Failed to load attestation key".to_string()))?;
+
+        let public_key = key_pair.public_key().as_ref();
+        let key_id = hex_encode(&digest::digest(&digest::SHA256, public_key).as_ref()[..8]);
+        let info = AttestationKeyInfo {
+            key_id,
+            algorithm: "Ed25519".to_string(),
+            public_key: BASE64_STANDARD.encode(public_key),
+            created_at: chrono::Utc::now().timestamp(),
+            retired_at: None,
+        };
+
+        Ok((key_pair, info))
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Signs encrypted response envelopes with the deployment key and publishes
+/// the current and rolling-over public keys as a JWKS document
+#[derive(Debug)]
+pub struct ResponseSigner {
+    current: std::sync::RwLock<(String, signature::Ed25519KeyPair)>,
+    published: std::sync::RwLock<Vec<Jwk>>,
+    key_path: Option<std::path::PathBuf>,
+    rollover: Duration,
+    rng: SystemRandom,
+}
+
+impl ResponseSigner {
+    /// Load the deployment key from `key_path`, creating it on first start
+    pub fn new(config: &ResponseSigningConfig) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let key_path = config.key_path.as_ref().map(std::path::PathBuf::from);
+
+        let pkcs8 = match &key_path {
+            Some(path) if path.exists() => std::fs::read(path)?,
+            Some(path) => {
+                let pkcs8 = Self::generate_pkcs8(&rng)?;
+                Self::persist(path, &pkcs8)?;
+                log::info!("Created response signing key at {}", path.display());
+                pkcs8
+            }
+            None => Self::generate_pkcs8(&rng)?,
+        };
+        let key_pair = signature::Ed25519KeyPair::from_pkcs8(&pkcs8)
+            .map_err(|_| Error::Cryptographic("Invalid response signing key".to_string()))?;
+        let jwk = Self::jwk(&key_pair);
+
+        Ok(Self {
+            current: std::sync::RwLock::new((jwk.kid.clone(), key_pair)),
+            published: std::sync::RwLock::new(vec![jwk]),
+            key_path,
+            rollover: Duration::from_secs(config.rollover_seconds),
+            rng,
+        })
+    }
+
+    /// Sign the envelope of the response `request_id` carrying `ciphertext`
+    pub fn sign(&self, request_id: &str, ciphertext: &[u8]) -> Result<SignedResponseEnvelope> {
+        let current = self.current.read().unwrap();
+        let envelope = ResponseEnvelope {
+            request_id: request_id.to_string(),
+            ciphertext_digest: hex_encode(digest::digest(&digest::SHA256, ciphertext).as_ref()),
+            timestamp: chrono::Utc::now().timestamp(),
+            key_id: current.0.clone(),
+        };
+
+        let signature = current.1.sign(&serde_json::to_vec(&envelope)?);
+
+        Ok(SignedResponseEnvelope {
+            envelope,
+            algorithm: "EdDSA".to_string(),
+            signature: BASE64_STANDARD.encode(signature.as_ref()),
+        })
+    }
+
+    /// Sign `payload` with the current deployment key, returning the key ID
+    /// and the Base64 signature
+    pub fn sign_detached(&self, payload: &[u8]) -> (String, String) {
+        let current = self.current.read().unwrap();
+        let signature = current.1.sign(payload);
+        (
+            current.0.clone(),
+            BASE64_STANDARD.encode(signature.as_ref()),
+        )
+    }
+
+    /// Replace the deployment key; the previous public key stays in the JWKS
+    /// for the rollover period
+    pub fn rotate(&self) -> Result<Jwk> {
+        let pkcs8 = Self::generate_pkcs8(&self.rng)?;
+        let key_pair = signature::Ed25519KeyPair::from_pkcs8(&pkcs8)
+            .map_err(|_| Error::Cryptographic("Failed to load response signing key".to_string()))?;
+        if let Some(path) = &self.key_path {
+            Self::persist(path, &pkcs8)?;
+        }
+        let jwk = Self::jwk(&key_pair);
+        let now = chrono::Utc::now().timestamp();
+
+        let mut current = self.current.write().unwrap();
+        let mut published = self.published.write().unwrap();
+        if let Some(previous) = published.iter_mut().find(|k| k.kid == current.0) {
+            previous.retired_at = Some(now);
+        }
+        published.push(jwk.clone());
+        *current = (jwk.kid.clone(), key_pair);
+
+        log::info!("Rotated response signing key to {}", jwk.kid);
+        Ok(jwk)
+    }
+
+    /// Public keys clients should accept: the signing key plus keys still rolling over
+    pub fn jwks(&self) -> JwkSet {
+        let cutoff = chrono::Utc::now().timestamp() - self.rollover.as_secs() as i64;
+        let mut published = self.published.write().unwrap();
+        published.retain(|k| k.retired_at.is_none_or(|retired| retired > cutoff));
+        JwkSet {
+            keys: published.clone(),
+        }
+    }
+
+    fn generate_pkcs8(rng: &SystemRandom) -> Result<Vec<u8>> {
+        signature::Ed25519KeyPair::generate_pkcs8(rng)
+            .map(|pkcs8| pkcs8.as_ref().to_vec())
+            .map_err(|_| {
+                Error::Cryptographic("Failed to generate response signing key".to_string())
+            })
+    }
+
+    /// Write the private key atomically, readable by the owner only
+    fn persist(path: &std::path::Path, pkcs8: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, pkcs8)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn jwk(key_pair: &signature::Ed25519KeyPair) -> Jwk {
+        let public_key = key_pair.public_key().as_ref();
+        Jwk {
+            kty: "OKP".to_string(),
+            crv: "Ed25519".to_string(),
+            x: BASE64_URL_SAFE_NO_PAD.encode(public_key),
+            kid: hex_encode(&digest::digest(&digest::SHA256, public_key).as_ref()[..8]),
+            key_use: "sig".to_string(),
+            alg: "EdDSA".to_string(),
+            retired_at: None,
+        }
+    }
+}
+
+/// Audit action recording each generation of honeytokens as it is issued
+pub const HONEYTOKEN_ROTATION_AUDIT_ACTION: &str = "honeytokens.rotate";
+
+/// One issue of honeytokens. Only key digests are kept once a generation is
+/// retired, which is all detection needs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HoneytokenGeneration {
+    pub generation: u64,
+    pub issued_at: i64,
+    pub retired_at: Option<i64>,
+    /// SHA-256 of each honeytoken API key
+    pub key_digests: Vec<String>,
+    pub canary_ids: Vec<Uuid>,
+}
+
+/// The current generation, with what operators plant
+#[derive(Debug, Clone)]
+pub struct IssuedHoneytokens {
+    pub generation: HoneytokenGeneration,
+    pub keys: Vec<String>,
+    pub canaries: Vec<Ciphertext>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoneytokenKind {
+    ApiKey,
+    CanaryCiphertext,
+}
+
+/// A honeytoken seen in a request
+#[derive(Debug, Clone, Serialize)]
+pub struct HoneytokenTrip {
+    pub kind: HoneytokenKind,
+    pub generation: u64,
+    pub issued_at: i64,
+    /// How long before its use the token was rotated out; None while current
+    pub stale_for_seconds: Option<i64>,
+    pub detected_at: i64,
+    /// Method, path, peer and header names of the request, never its body
+    pub context: serde_json::Value,
+}
+
+#[derive(Debug, Default)]
+struct HoneytokenState {
+    current: Option<IssuedHoneytokens>,
+    /// Newest last
+    retired: VecDeque<HoneytokenGeneration>,
+    trips: VecDeque<HoneytokenTrip>,
+}
+
+/// Decoy API keys and ciphertexts that no legitimate client holds. They are
+/// planted where a leak would expose them (backups, configs, logs); using
+/// one is evidence of a breach. Generations rotate so that a leak can be
+/// dated, and retired generations are still recognised.
+#[derive(Debug)]
+pub struct Honeytokens {
+    config: HoneytokenConfig,
+    state: std::sync::RwLock<HoneytokenState>,
+}
+
+impl Honeytokens {
+    pub fn new(config: HoneytokenConfig) -> Self {
+        Self {
+            config,
+            state: std::sync::RwLock::new(HoneytokenState::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &HoneytokenConfig {
+        &self.config
+    }
+
+    /// Issue a new generation shaped like real keys and ciphertexts under
+    /// `params`, retiring the current one
+    pub fn rotate(&self, params: &FheParams, now: i64) -> HoneytokenGeneration {
+        let keys: Vec<String> = (0..self.config.keys_per_generation)
+            .map(|_| {
+                format!(
+                    "{}{}",
+                    self.config.key_prefix,
+                    hex_encode(&rand::random::<[u8; 24]>())
+                )
+            })
+            .collect();
+        // Degree times 16 bytes is the size of a two-component ciphertext
+        let canaries: Vec<Ciphertext> = (0..self.config.canaries_per_generation)
+            .map(|_| Ciphertext {
+                id: Uuid::new_v4(),
+                data: (0..params.poly_modulus_degree * 16)
+                    .map(|_| rand::random())
+                    .collect(),
+                params: params.clone(),
+                noise_budget: None,
+            })
+            .collect();
+
+        let mut state = self.state.write().unwrap();
+        let number = state
+            .current
+            .as_ref()
+            .map(|c| c.generation.generation)
+            .into_iter()
+            .chain(state.retired.iter().map(|g| g.generation))
+            .max()
+            .map_or(1, |n| n + 1);
+        let generation = HoneytokenGeneration {
+            generation: number,
+            issued_at: now,
+            retired_at: None,
+            key_digests: keys.iter().map(|k| sha256_hex(k)).collect(),
+            canary_ids: canaries.iter().map(|c| c.id).collect(),
+        };
+        if let Some(mut previous) = state.current.take() {
+            previous.generation.retired_at = Some(now);
+            state.retired.push_back(previous.generation);
+        }
+        while state.retired.len() > self.config.retired_generations {
+            state.retired.pop_front();
+        }
+        state.current = Some(IssuedHoneytokens {
+            generation: generation.clone(),
+            keys,
+            canaries,
+        });
+        generation
+    }
+
+    /// Recognise generations issued before a restart; all of them are retired
+    pub fn restore(&self, generations: impl IntoIterator<Item = HoneytokenGeneration>) {
+        let mut state = self.state.write().unwrap();
+        let mut generations: Vec<_> = generations.into_iter().collect();
+        generations.sort_by_key(|g| g.generation);
+        let mut restored = VecDeque::new();
+        for (i, mut generation) in generations.iter().cloned().enumerate() {
+            // Each was retired when the next was issued
+            if generation.retired_at.is_none() {
+                generation.retired_at = generations.get(i + 1).map(|next| next.issued_at);
+            }
+            restored.push_back(generation);
+        }
+        restored.extend(state.retired.drain(..));
+        while restored.len() > self.config.retired_generations {
+            restored.pop_front();
+        }
+        state.retired = restored;
+    }
+
+    pub fn current(&self) -> Option<IssuedHoneytokens> {
+        self.state.read().unwrap().current.clone()
+    }
+
+    pub fn generations(&self) -> Vec<HoneytokenGeneration> {
+        let state = self.state.read().unwrap();
+        state
+            .retired
+            .iter()
+            .cloned()
+            .chain(state.current.iter().map(|c| c.generation.clone()))
+            .collect()
+    }
+
+    fn find(
+        &self,
+        matches: impl Fn(&HoneytokenGeneration) -> bool,
+    ) -> Option<HoneytokenGeneration> {
+        let state = self.state.read().unwrap();
+        state
+            .current
+            .iter()
+            .map(|c| &c.generation)
+            .chain(state.retired.iter().rev())
+            .find(|g| matches(g))
+            .cloned()
+    }
+
+    /// The generation `key` was issued in, if it is a honeytoken
+    pub fn match_key(&self, key: &str) -> Option<HoneytokenGeneration> {
+        if !self.config.enabled {
+            return None;
+        }
+        let digest = sha256_hex(key);
+        self.find(|g| g.key_digests.contains(&digest))
+    }
+
+    /// Short digest identifying a presented key in alerts without revealing it
+    pub fn fingerprint(key: &str) -> String {
+        sha256_hex(key)[..16].to_string()
+    }
+
+    /// Canary ciphertext ids written anywhere in `text`, with their generation
+    pub fn match_canaries(&self, text: &[u8]) -> Vec<(Uuid, HoneytokenGeneration)> {
+        if !self.config.enabled {
+            return Vec::new();
+        }
+        let found: HashSet<Uuid> = uuids_in(text).collect();
+        found
+            .into_iter()
+            .filter_map(|id| Some((id, self.find(|g| g.canary_ids.contains(&id))?)))
+            .collect()
+    }
+
+    pub fn record_trip(&self, trip: HoneytokenTrip) {
+        let mut state = self.state.write().unwrap();
+        state.trips.push_back(trip);
+        while state.trips.len() > self.config.max_trips {
+            state.trips.pop_front();
+        }
+    }
+
+    /// Most recent trips, newest first
+    pub fn trips(&self, limit: usize) -> Vec<HoneytokenTrip> {
+        self.state
+            .read()
+            .unwrap()
+            .trips
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+fn sha256_hex(text: &str) -> String {
+    hex_encode(digest::digest(&digest::SHA256, text.as_bytes()).as_ref())
+}
+
+/// Hyphenated UUIDs in `text`, found in one pass
+fn uuids_in(text: &[u8]) -> impl Iterator<Item = Uuid> + '_ {
+    const LEN: usize = 36;
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        while start + LEN <= text.len() {
+            let candidate = &text[start..start + LEN];
+            let shaped = candidate.iter().enumerate().all(|(i, b)| match i {
+                8 | 13 | 18 | 23 => *b == b'-',
+                _ => b.is_ascii_hexdigit(),
+            });
+            if shaped {
+                if let Some(id) = std::str::from_utf8(candidate)
+                    .ok()
+                    .and_then(|s| Uuid::parse_str(s).ok())
+                {
+                    start += LEN;
+                    return Some(id);
+                }
+            }
+            start += 1;
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_validation() {
+        // Valid input
+        assert!(InputValidator::sanitize_text("Hello world!").is_ok());
+
+        // Empty input
+        assert!(InputValidator::sanitize_text("").is_err());
+
+        // Too long input
+        let long_input = "a".repeat(20000);
+        assert!(InputValidator::sanitize_text(&long_input).is_err());
+
+        // Suspicious patterns
+        assert!(InputValidator::sanitize_text("<script>alert('xss')</script>").is_err());
+        assert!(InputValidator::sanitize_text("'; DROP TABLE users; --").is_err());
+    }
+
+    #[test]
+    fn test_uuid_validation() {
+        let valid_uuid = "550e8400-e29b-41d4-a716-446655440000";
+        assert!(InputValidator::validate_uuid(valid_uuid).is_ok());
+
+        let invalid_uuid = "not-a-uuid";
+        assert!(InputValidator::validate_uuid(invalid_uuid).is_err());
+    }
+
+    #[test]
+    fn test_fhe_params_validation() {
+        // Valid params
+        assert!(InputValidator::validate_fhe_params(16384, 128).is_ok());
+
+        // Invalid polynomial degree
+        assert!(InputValidator::validate_fhe_params(15000, 128).is_err());
+
+        // Invalid security level
+        assert!(InputValidator::validate_fhe_params(16384, 100).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(10.0, 1.0); // 10 tokens capacity, 1 token/sec refill
+
+        // Should be able to consume tokens initially
+        assert!(bucket.try_consume(5.0));
+        assert!(bucket.try_consume(5.0));
+
+        // Should be empty now
+        assert!(!bucket.try_consume(1.0));
+
+        // Wait and refill (simulated)
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        bucket.refill();
+
+        // Should have some tokens now
+        assert!(bucket.try_consume(0.1));
+    }
+
+    #[test]
+    fn test_api_key_generation() {
+        let master_secret = "test-secret-key".to_string();
+        let mut manager = ApiKeyManager::new(master_secret);
+
+        let permissions = vec![Permission::Encrypt, Permission::Decrypt];
+        let api_key = manager.generate_api_key("test-user".to_string(), permissions.clone());
+
+        assert!(!api_key.is_empty());
+        assert!(manager.validate_api_key(&api_key).is_ok());
+        assert!(manager.has_permission(&api_key, &Permission::Encrypt));
+        assert!(!manager.has_permission(&api_key, &Permission::Admin));
+    }
+
+    #[test]
+    fn test_attestation_sign_and_rotate() {
+        let service = AttestationService::new().unwrap();
+        let first_key = service.current_key();
+
+        let attestation = service
+            .attest("abc123".to_string(), "fhe", &FheParams::default())
+            .unwrap();
+        assert_eq!(attestation.statement.key_id, first_key.key_id);
+
+        let public_key = BASE64_STANDARD.decode(&first_key.public_key).unwrap();
+        let payload = serde_json::to_vec(&attestation.statement).unwrap();
+        let sig = BASE64_STANDARD.decode(&attestation.signature).unwrap();
+        assert!(
+            signature::UnparsedPublicKey::new(&signature::ED25519, &public_key)
+                .verify(&payload, &sig)
+                .is_ok()
+        );
+
+        let provenance = BuildProvenance::current();
+        assert_eq!(provenance.predicate.lockfile_sha256.len(), 64);
+        let signed = service.sign_provenance(&provenance).unwrap();
+        assert_eq!(signed.key_id, first_key.key_id);
+        let payload = serde_json::to_vec(&signed.provenance).unwrap();
+        let sig = BASE64_STANDARD.decode(&signed.signature).unwrap();
+        assert!(
+            signature::UnparsedPublicKey::new(&signature::ED25519, &public_key)
+                .verify(&payload, &sig)
+                .is_ok()
+        );
+
+        let second_key = service.rotate().unwrap();
+        assert_ne!(second_key.key_id, first_key.key_id);
+        let history = service.key_history();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].retired_at.is_some());
+        assert!(history[1].retired_at.is_none());
+    }
+
+    #[test]
+    fn test_response_signing_and_rollover() {
+        let path = std::env::temp_dir()
+            .join(format!("fhe-signing-{}", Uuid::new_v4()))
+            .join("response.pk8");
+        let config = ResponseSigningConfig {
+            enabled: true,
+            key_path: Some(path.to_string_lossy().into_owned()),
+            rollover_seconds: 3600,
+        };
+        let signer = ResponseSigner::new(&config).unwrap();
+        let max_age = Duration::from_secs(300);
+
+        let signed = signer.sign("fhe-1", b"ciphertext").unwrap();
+        let jwks = signer.jwks();
+        assert!(verify_signed_response(&signed, &jwks, "fhe-1", b"ciphertext", max_age).is_ok());
+        assert!(verify_signed_response(&signed, &jwks, "fhe-2", b"ciphertext", max_age).is_err());
+        assert!(verify_signed_response(&signed, &jwks, "fhe-1", b"tampered", max_age).is_err());
+
+        let mut forged = signed.clone();
+        forged.envelope.timestamp += 1;
+        assert!(verify_signed_response(&forged, &jwks, "fhe-1", b"ciphertext", max_age).is_err());
+
+        // The deployment key survives a restart
+        let reloaded = ResponseSigner::new(&config).unwrap();
+        assert_eq!(reloaded.jwks().keys[0].kid, jwks.keys[0].kid);
+
+        // Responses signed before a rotation still verify during rollover
+        signer.rotate().unwrap();
+        let jwks = signer.jwks();
+        assert_eq!(jwks.keys.len(), 2);
+        assert!(verify_signed_response(&signed, &jwks, "fhe-1", b"ciphertext", max_age).is_ok());
+
+        let expired = ResponseSigner::new(&ResponseSigningConfig {
+            rollover_seconds: 0,
+            ..config
+        })
+        .unwrap();
+        expired.rotate().unwrap();
+        assert_eq!(expired.jwks().keys.len(), 1);
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_honeytokens_are_recognised_across_rotations() {
+        let honeytokens = Honeytokens::new(HoneytokenConfig {
+            enabled: true,
+            retired_generations: 1,
+            ..HoneytokenConfig::default()
+        });
+        let params = FheParams {
+            poly_modulus_degree: 64,
+            ..FheParams::default()
+        };
+        let first = honeytokens.rotate(&params, 100);
+        let issued = honeytokens.current().unwrap();
+        let key = issued.keys[0].clone();
+        let canary = issued.canaries[0].id;
+        assert_eq!(issued.canaries[0].data.len(), 64 * 16);
+        assert_eq!(honeytokens.match_key(&key), Some(first.clone()));
+        assert_eq!(honeytokens.match_key("sk-legitimate"), None);
+
+        let body = format!(
+            r#"{{"ciphertext_id":"{}","other":"{}"}}"#,
+            canary,
+            Uuid::new_v4()
+        );
+        let found = honeytokens.match_canaries(body.as_bytes());
+        assert_eq!(found, vec![(canary, first.clone())]);
+
+        // A rotated-out generation is still recognised, and dated
+        honeytokens.rotate(&params, 200);
+        assert_eq!(honeytokens.match_key(&key).unwrap().retired_at, Some(200));
+        honeytokens.rotate(&params, 300);
+        assert_eq!(honeytokens.match_key(&key), None);
+
+        // After a restart, audited generations are recognised again
+        let restarted = Honeytokens::new(HoneytokenConfig {
+            enabled: true,
+            ..HoneytokenConfig::default()
+        });
+        restarted.restore([first]);
+        restarted.rotate(&params, 400);
+        let found = restarted.match_key(&key).unwrap();
+        assert_eq!(found.generation, 1);
+        assert_eq!(restarted.generations().len(), 2);
+        assert_eq!(restarted.current().unwrap().generation.generation, 2);
+        assert_eq!(
+            restarted
+                .match_canaries(canary.to_string().as_bytes())
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,502 @@
+//! Security event export to a SIEM in CEF or OTLP log format
+//!
+//! Events are typed: callers supply an event kind, network and tenant
+//! identifiers and a short reason. Prompts, ciphertexts and keys have no field
+//! to travel in, and free text is scrubbed of anything shaped like key
+//! material before it is queued.
+
+use crate::config::SiemExportConfig;
+use crate::error::{Error, Result};
+use reqwest::Client as HttpClient;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Free-text fields are cut to this many bytes
+const MAX_TEXT_LEN: usize = 256;
+
+/// Unbroken base64/hex-like runs at least this long are treated as secrets
+const MIN_SECRET_TOKEN_LEN: usize = 24;
+
+const CEF_VENDOR: &str = "Terragon Labs";
+const CEF_PRODUCT: &str = "homomorphic-llm-proxy";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityEventKind {
+    AuthFailure,
+    RateLimited,
+    Lockdown,
+    EnvelopeRejected,
+    PolicyViolation,
+    Anomaly,
+    Honeytoken,
+}
+
+impl SecurityEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityEventKind::AuthFailure => "auth_failure",
+            SecurityEventKind::RateLimited => "rate_limited",
+            SecurityEventKind::Lockdown => "lockdown",
+            SecurityEventKind::EnvelopeRejected => "envelope_rejected",
+            SecurityEventKind::PolicyViolation => "policy_violation",
+            SecurityEventKind::Anomaly => "anomaly",
+            SecurityEventKind::Honeytoken => "honeytoken_triggered",
+        }
+    }
+
+    /// CEF signature ID
+    fn signature_id(&self) -> u16 {
+        match self {
+            SecurityEventKind::AuthFailure => 100,
+            SecurityEventKind::RateLimited => 200,
+            SecurityEventKind::Lockdown => 300,
+            SecurityEventKind::EnvelopeRejected => 400,
+            SecurityEventKind::PolicyViolation => 500,
+            SecurityEventKind::Anomaly => 600,
+            SecurityEventKind::Honeytoken => 700,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SecurityEventKind::AuthFailure => "Authentication failure",
+            SecurityEventKind::RateLimited => "Rate limit exceeded",
+            SecurityEventKind::Lockdown => "Service lockdown",
+            SecurityEventKind::EnvelopeRejected => "Federation envelope rejected",
+            SecurityEventKind::PolicyViolation => "Policy violation",
+            SecurityEventKind::Anomaly => "Anomaly detected",
+            SecurityEventKind::Honeytoken => "Honeytoken used",
+        }
+    }
+
+    /// Severity on the CEF 0-10 scale
+    fn severity(&self) -> u8 {
+        match self {
+            SecurityEventKind::RateLimited => 3,
+            SecurityEventKind::AuthFailure => 5,
+            SecurityEventKind::PolicyViolation | SecurityEventKind::Anomaly => 6,
+            SecurityEventKind::EnvelopeRejected => 7,
+            SecurityEventKind::Lockdown => 8,
+            SecurityEventKind::Honeytoken => 10,
+        }
+    }
+
+    /// OTLP severity number (INFO 9, WARN 13, ERROR 17)
+    fn otlp_severity(&self) -> (u8, &'static str) {
+        match self.severity() {
+            8.. => (17, "ERROR"),
+            5.. => (13, "WARN"),
+            _ => (9, "INFO"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SecurityEvent {
+    pub kind: SecurityEventKind,
+    pub timestamp_ms: i64,
+    pub source_ip: Option<String>,
+    pub tenant: Option<String>,
+    /// Identifier the event concerns (peer, model, guard action), never content
+    pub subject: Option<String>,
+    pub reason: String,
+}
+
+impl SecurityEvent {
+    pub fn new(kind: SecurityEventKind, reason: &str) -> Self {
+        Self {
+            kind,
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+            source_ip: None,
+            tenant: None,
+            subject: None,
+            reason: scrub(reason),
+        }
+    }
+
+    pub fn source_ip(mut self, ip: &str) -> Self {
+        self.source_ip = Some(scrub(ip));
+        self
+    }
+
+    pub fn tenant(mut self, tenant: Option<&str>) -> Self {
+        self.tenant = tenant.map(scrub);
+        self
+    }
+
+    pub fn subject(mut self, subject: &str) -> Self {
+        self.subject = Some(scrub(subject));
+        self
+    }
+
+    /// Render as a single ArcSight CEF line
+    pub fn to_cef(&self) -> String {
+        let mut extension = format!("rt={} act={}", self.timestamp_ms, self.kind.as_str());
+        if let Some(ip) = &self.source_ip {
+            extension.push_str(&format!(" src={}", cef_value(ip)));
+        }
+        if let Some(tenant) = &self.tenant {
+            extension.push_str(&format!(" suser={}", cef_value(tenant)));
+        }
+        if let Some(subject) = &self.subject {
+            extension.push_str(&format!(" cs1Label=subject cs1={}", cef_value(subject)));
+        }
+        extension.push_str(&format!(" msg={}", cef_value(&self.reason)));
+
+        format!(
+            "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+            cef_header(CEF_VENDOR),
+            cef_header(CEF_PRODUCT),
+            cef_header(env!("CARGO_PKG_VERSION")),
+            self.kind.signature_id(),
+            cef_header(self.kind.name()),
+            self.kind.severity(),
+            extension
+        )
+    }
+
+    /// Render as an OTLP log record
+    fn to_otlp_record(&self) -> serde_json::Value {
+        let (severity_number, severity_text) = self.kind.otlp_severity();
+        let mut attributes = vec![otlp_attribute("event.name", self.kind.as_str())];
+        if let Some(ip) = &self.source_ip {
+            attributes.push(otlp_attribute("client.address", ip));
+        }
+        if let Some(tenant) = &self.tenant {
+            attributes.push(otlp_attribute("tenant.id", tenant));
+        }
+        if let Some(subject) = &self.subject {
+            attributes.push(otlp_attribute("event.subject", subject));
+        }
+        attributes.push(otlp_attribute("event.reason", &self.reason));
+
+        serde_json::json!({
+            "timeUnixNano": (self.timestamp_ms as u64 * 1_000_000).to_string(),
+            "severityNumber": severity_number,
+            "severityText": severity_text,
+            "body": { "stringValue": self.kind.name() },
+            "attributes": attributes
+        })
+    }
+}
+
+pub(crate) fn otlp_attribute(key: &str, value: &str) -> serde_json::Value {
+    serde_json::json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Redact tokens that could be key material or ciphertext and bound the length.
+/// UUIDs are identifiers, not secrets, and are kept.
+pub fn scrub(text: &str) -> String {
+    let mut scrubbed = text
+        .split_whitespace()
+        .map(|token| {
+            let core = token.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+            let secret_like = core.len() >= MIN_SECRET_TOKEN_LEN
+                && core
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=' | '-' | '_'))
+                && Uuid::parse_str(core).is_err();
+            if secret_like || core.starts_with("sk-") {
+                "[REDACTED]"
+            } else {
+                token
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if scrubbed.len() > MAX_TEXT_LEN {
+        let mut end = MAX_TEXT_LEN;
+        while !scrubbed.is_char_boundary(end) {
+            end -= 1;
+        }
+        scrubbed.truncate(end);
+    }
+    scrubbed
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SiemFormat {
+    Cef,
+    Otlp,
+}
+
+impl SiemFormat {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "cef" => Ok(SiemFormat::Cef),
+            "otlp" => Ok(SiemFormat::Otlp),
+            other => Err(Error::Config(format!("Unknown SIEM format: {}", other))),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ExportCounters {
+    queued: AtomicU64,
+    exported: AtomicU64,
+    dropped: AtomicU64,
+    failed_batches: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SiemStats {
+    pub enabled: bool,
+    pub format: SiemFormat,
+    pub queued: u64,
+    pub exported: u64,
+    /// Events shed because the buffer was full or a batch exhausted its retries
+    pub dropped: u64,
+    pub failed_batches: u64,
+}
+
+/// Buffers security events and ships them to the configured collector.
+///
+/// The buffer is bounded: while the collector is slow or down, the shipping
+/// task stalls on retries, the buffer fills and further events are dropped
+/// and counted rather than blocking the request path.
+#[derive(Debug)]
+pub struct SiemExporter {
+    config: SiemExportConfig,
+    format: SiemFormat,
+    sender: mpsc::Sender<SecurityEvent>,
+    receiver: Mutex<Option<mpsc::Receiver<SecurityEvent>>>,
+    counters: Arc<ExportCounters>,
+    client: HttpClient,
+}
+
+impl SiemExporter {
+    pub fn new(config: SiemExportConfig) -> Result<Self> {
+        let format = SiemFormat::parse(&config.format)?;
+        let (sender, receiver) = mpsc::channel(config.buffer_size.max(1));
+        Ok(Self {
+            config,
+            format,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            counters: Arc::new(ExportCounters::default()),
+            client: HttpClient::new(),
+        })
+    }
+
+    /// Log a security event and queue it for export
+    pub fn emit(&self, event: SecurityEvent) {
+        log::warn!(
+            target: "security_events",
+            "event_type={} source_ip={} tenant={} subject={} details={}",
+            event.kind.as_str(),
+            event.source_ip.as_deref().unwrap_or("-"),
+            event.tenant.as_deref().unwrap_or("-"),
+            event.subject.as_deref().unwrap_or("-"),
+            event.reason
+        );
+
+        if !self.config.enabled {
+            return;
+        }
+        match self.sender.try_send(event) {
+            Ok(()) => {
+                self.counters.queued.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> SiemStats {
+        SiemStats {
+            enabled: self.config.enabled,
+            format: self.format,
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            exported: self.counters.exported.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed_batches: self.counters.failed_batches.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Start the shipping task; a no-op when export is disabled or already running
+    pub fn spawn(&self) {
+        if !self.config.enabled {
+            return;
+        }
+        let Some(mut receiver) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+
+        let shipper = Shipper {
+            config: self.config.clone(),
+            format: self.format,
+            counters: self.counters.clone(),
+            client: self.client.clone(),
+        };
+        tokio::spawn(async move {
+            let batch_size = shipper.config.batch_size.max(1);
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut interval = tokio::time::interval(Duration::from_secs(
+                shipper.config.flush_interval_seconds.max(1),
+            ));
+            loop {
+                tokio::select! {
+                    event = receiver.recv() => match event {
+                        Some(event) => {
+                            batch.push(event);
+                            if batch.len() < batch_size {
+                                continue;
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = interval.tick() => {
+                        if batch.is_empty() {
+                            continue;
+                        }
+                    }
+                }
+                shipper.ship(&batch).await;
+                batch.clear();
+            }
+        });
+    }
+}
+
+struct Shipper {
+    config: SiemExportConfig,
+    format: SiemFormat,
+    counters: Arc<ExportCounters>,
+    client: HttpClient,
+}
+
+impl Shipper {
+    fn encode(&self, batch: &[SecurityEvent]) -> (Vec<u8>, &'static str) {
+        match self.format {
+            SiemFormat::Cef => {
+                let lines: Vec<String> = batch.iter().map(SecurityEvent::to_cef).collect();
+                (lines.join("\n").into_bytes(), "text/plain")
+            }
+            SiemFormat::Otlp => {
+                let payload = serde_json::json!({
+                    "resourceLogs": [{
+                        "resource": {
+                            "attributes": [otlp_attribute("service.name", "fhe-proxy")]
+                        },
+                        "scopeLogs": [{
+                            "scope": { "name": "fhe-proxy.security" },
+                            "logRecords": batch
+                                .iter()
+                                .map(SecurityEvent::to_otlp_record)
+                                .collect::<Vec<_>>()
+                        }]
+                    }]
+                });
+                (payload.to_string().into_bytes(), "application/json")
+            }
+        }
+    }
+
+    /// POST a batch, retrying with exponential backoff before dropping it
+    async fn ship(&self, batch: &[SecurityEvent]) {
+        let (body, content_type) = self.encode(batch);
+
+        for attempt in 0..=self.config.max_retries {
+            let mut request = self
+                .client
+                .post(&self.config.endpoint)
+                .header("Content-Type", content_type)
+                .timeout(Duration::from_secs(self.config.timeout_seconds))
+                .body(body.clone());
+            for (name, value) in &self.config.headers {
+                request = request.header(name, value);
+            }
+
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    self.counters
+                        .exported
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    return;
+                }
+                Ok(response) => {
+                    log::warn!("SIEM collector rejected batch: {}", response.status());
+                }
+                Err(e) => log::warn!("Failed to ship security events: {}", e),
+            }
+
+            if attempt < self.config.max_retries {
+                tokio::time::sleep(Duration::from_millis(500u64 << attempt.min(6))).await;
+            }
+        }
+
+        log::error!(
+            "Dropping {} security events after {} retries",
+            batch.len(),
+            self.config.max_retries
+        );
+        self.counters.failed_batches.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .dropped
+            .fetch_add(batch.len() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_are_scrubbed_and_escaped() {
+        let event = SecurityEvent::new(
+            SecurityEventKind::EnvelopeRejected,
+            "bad key dGhpcyBpcyBhIHNlY3JldCBsaW5rIGtleSE= from peer a=b|c",
+        )
+        .subject("partner-org");
+
+        assert!(!event.reason.contains("dGhpcyBp"));
+        assert!(event.reason.contains("[REDACTED]"));
+
+        let cef = event.to_cef();
+        assert!(cef.starts_with("CEF:0|Terragon Labs|homomorphic-llm-proxy|"));
+        assert!(cef.contains("|400|Federation envelope rejected|7|"));
+        assert!(cef.contains("msg=bad key [REDACTED] from peer a\\=b|c"));
+
+        // Identifiers survive scrubbing
+        let id = Uuid::new_v4().to_string();
+        assert_eq!(scrub(&id), id);
+    }
+
+    #[test]
+    fn test_full_buffer_drops_instead_of_blocking() {
+        let exporter = SiemExporter::new(SiemExportConfig {
+            enabled: true,
+            endpoint: "http://127.0.0.1:9/v1/logs".to_string(),
+            buffer_size: 2,
+            ..SiemExportConfig::default()
+        })
+        .unwrap();
+
+        for _ in 0..3 {
+            exporter.emit(SecurityEvent::new(SecurityEventKind::RateLimited, "burst"));
+        }
+        let stats = exporter.stats();
+        assert_eq!(stats.queued, 2);
+        assert_eq!(stats.dropped, 1);
+    }
+}
@@ -0,0 +1,135 @@
+//! Cold-start snapshots of warmed engine state
+//!
+//! Warm-up precomputes NTT tables for the engine's parameter profile. The
+//! tables are written to disk on graceful shutdown and restored on the next
+//! start when the build hash and parameter profile still match; anything else
+//! falls back to a normal warm-up. Key material is never part of a snapshot.
+
+use crate::error::{Error, Result};
+use crate::fhe::{FheParams, PrecomputedTables};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Bumped whenever the snapshot layout changes
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    pub version: u32,
+    pub build_hash: String,
+    pub params: FheParams,
+    pub created_at: i64,
+    /// SHA-256 over the serialized tables, to catch truncated or corrupted files
+    pub checksum: String,
+    pub tables: PrecomputedTables,
+}
+
+impl EngineSnapshot {
+    pub fn capture(
+        build_hash: &str,
+        params: &FheParams,
+        tables: &PrecomputedTables,
+    ) -> Result<Self> {
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            build_hash: build_hash.to_string(),
+            params: params.clone(),
+            created_at: chrono::Utc::now().timestamp(),
+            checksum: checksum(tables)?,
+            tables: tables.clone(),
+        })
+    }
+
+    /// Write the snapshot atomically, so a crash mid-write never leaves a torn file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Load the tables from a snapshot, rejecting it unless it was produced by
+    /// this build for the same parameter profile and is intact
+    pub fn restore(path: &Path, build_hash: &str, params: &FheParams) -> Result<PrecomputedTables> {
+        let snapshot: EngineSnapshot = serde_json::from_slice(&std::fs::read(path)?)?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(Error::Validation(format!(
+                "Snapshot version {} is not supported",
+                snapshot.version
+            )));
+        }
+        if snapshot.build_hash != build_hash {
+            return Err(Error::Validation(
+                "Snapshot was taken by a different build".to_string(),
+            ));
+        }
+        if &snapshot.params != params {
+            return Err(Error::Validation(
+                "Snapshot parameter profile does not match".to_string(),
+            ));
+        }
+        if snapshot.checksum != checksum(&snapshot.tables)? {
+            return Err(Error::Validation("Snapshot checksum mismatch".to_string()));
+        }
+
+        Ok(snapshot.tables)
+    }
+}
+
+fn checksum(tables: &PrecomputedTables) -> Result<String> {
+    let digest = digest::digest(&digest::SHA256, &serde_json::to_vec(tables)?);
+    Ok(digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trip_and_invalidation() {
+        let params = FheParams {
+            poly_modulus_degree: 1024,
+            coeff_modulus_bits: vec![40, 30],
+            ..FheParams::default()
+        };
+        let tables = PrecomputedTables::compute(&params).unwrap();
+        let path = std::env::temp_dir()
+            .join(format!("fhe-snapshot-{}", uuid::Uuid::new_v4()))
+            .join("engine.json");
+
+        EngineSnapshot::capture("build-a", &params, &tables)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        assert_eq!(
+            EngineSnapshot::restore(&path, "build-a", &params).unwrap(),
+            tables
+        );
+
+        // A new build or parameter profile falls back to a normal warm-up
+        assert!(EngineSnapshot::restore(&path, "build-b", &params).is_err());
+        let other = FheParams {
+            coeff_modulus_bits: vec![40, 40],
+            ..params.clone()
+        };
+        assert!(EngineSnapshot::restore(&path, "build-a", &other).is_err());
+
+        // Tampered tables are rejected
+        let mut snapshot: EngineSnapshot =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        snapshot.tables.root_powers[0][1] ^= 1;
+        std::fs::write(&path, serde_json::to_vec(&snapshot).unwrap()).unwrap();
+        assert!(EngineSnapshot::restore(&path, "build-a", &params).is_err());
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,344 @@
+//! Hot-standby proxy pairs
+//!
+//! Two instances run side by side and only the active one serves. They
+//! exchange heartbeats every `heartbeat_interval_ms`; the standby follows the
+//! active's sessions and privacy budgets and takes over once the active has
+//! been silent for `failover_timeout_ms`, provided a majority of witnesses
+//! still answer (an instance that lost the network must not promote itself).
+//!
+//! Split brain is settled by epochs. Each promotion takes an epoch above any
+//! the instance has seen, and an active instance that hears of an active peer
+//! at a higher epoch, or at the same epoch with a lower node id, steps down.
+//! A planned switchover goes through `HandingOff`: the active stops serving
+//! and flushes its state, and the standby promotes as soon as it sees that.
+
+use crate::config::StandbyConfig;
+use crate::persistence::{PrivacyLedgerEntry, SessionRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Transitions kept for the status endpoint
+const MAX_TRANSITIONS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairRole {
+    Active,
+    Standby,
+    /// Stopped serving so the peer can take over
+    HandingOff,
+}
+
+/// What an instance tells its peer about itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub node_id: String,
+    pub role: PairRole,
+    pub epoch: u64,
+    pub preferred_active: bool,
+    pub sent_at: i64,
+}
+
+/// State a standby copies from the active over the direct channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandoffState {
+    pub epoch: u64,
+    pub sessions: Vec<SessionRecord>,
+    pub ledger: Vec<PrivacyLedgerEntry>,
+}
+
+/// What the pair loop should do after a heartbeat round
+#[derive(Debug, Clone, PartialEq)]
+pub enum PairDecision {
+    Stay,
+    Promote(String),
+    Demote(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PairTransition {
+    pub at: i64,
+    pub from: PairRole,
+    pub to: PairRole,
+    pub epoch: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PairStatus {
+    pub enabled: bool,
+    pub node_id: String,
+    pub role: PairRole,
+    pub epoch: u64,
+    pub peer_url: String,
+    pub peer: Option<Heartbeat>,
+    /// Milliseconds since the peer last answered
+    pub peer_silent_ms: Option<u64>,
+    pub last_replicated_at: Option<i64>,
+    pub transitions: Vec<PairTransition>,
+}
+
+#[derive(Debug)]
+struct PairState {
+    role: PairRole,
+    epoch: u64,
+    /// Highest epoch seen from either instance
+    max_epoch: u64,
+    peer: Option<Heartbeat>,
+    peer_seen_at: Option<Instant>,
+    started_at: Instant,
+    last_replicated_at: Option<i64>,
+    transitions: VecDeque<PairTransition>,
+}
+
+#[derive(Debug)]
+pub struct StandbyPair {
+    config: StandbyConfig,
+    state: Mutex<PairState>,
+}
+
+impl StandbyPair {
+    pub fn new(config: StandbyConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(PairState {
+                // Everyone starts as standby and promotes once the peer is known
+                role: PairRole::Standby,
+                epoch: 0,
+                max_epoch: 0,
+                peer: None,
+                peer_seen_at: None,
+                started_at: Instant::now(),
+                last_replicated_at: None,
+                transitions: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn config(&self) -> &StandbyConfig {
+        &self.config
+    }
+
+    pub fn role(&self) -> PairRole {
+        self.state.lock().unwrap().role
+    }
+
+    /// Whether this instance takes traffic; always true outside a pair
+    pub fn is_serving(&self) -> bool {
+        !self.config.enabled || self.role() == PairRole::Active
+    }
+
+    pub fn heartbeat(&self) -> Heartbeat {
+        let state = self.state.lock().unwrap();
+        Heartbeat {
+            node_id: self.config.node_id.clone(),
+            role: state.role,
+            epoch: state.epoch,
+            preferred_active: self.config.preferred_active,
+            sent_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    /// Take in the peer's heartbeat; an active or handing-off instance that
+    /// the peer outranks is told to step down
+    pub fn observe_peer(&self, peer: Heartbeat, now: Instant) -> PairDecision {
+        let mut state = self.state.lock().unwrap();
+        state.max_epoch = state.max_epoch.max(peer.epoch);
+        state.peer_seen_at = Some(now);
+        let decision = match (state.role, peer.role) {
+            (PairRole::Active | PairRole::HandingOff, PairRole::Active)
+                if outranks(&peer, state.epoch, &self.config.node_id) =>
+            {
+                PairDecision::Demote(format!(
+                    "peer {} is active at epoch {}",
+                    peer.node_id, peer.epoch
+                ))
+            }
+            _ => PairDecision::Stay,
+        };
+        state.peer = Some(peer);
+        decision
+    }
+
+    /// Whether the peer has been silent past the failover timeout; an unknown
+    /// peer counts from startup
+    pub fn peer_lost(&self, now: Instant) -> bool {
+        let state = self.state.lock().unwrap();
+        let since = state.peer_seen_at.unwrap_or(state.started_at);
+        now.saturating_duration_since(since)
+            >= Duration::from_millis(self.config.failover_timeout_ms)
+    }
+
+    /// Decide whether a standby should promote. `witnesses_ok` is only
+    /// consulted when the peer is lost.
+    pub fn decide(&self, now: Instant, witnesses_ok: impl FnOnce() -> bool) -> PairDecision {
+        if self.role() != PairRole::Standby {
+            return PairDecision::Stay;
+        }
+        if self.peer_lost(now) {
+            return if witnesses_ok() {
+                PairDecision::Promote(format!(
+                    "peer silent for over {}ms",
+                    self.config.failover_timeout_ms
+                ))
+            } else {
+                PairDecision::Stay
+            };
+        }
+        let state = self.state.lock().unwrap();
+        let Some(peer) = &state.peer else {
+            return PairDecision::Stay;
+        };
+        match peer.role {
+            PairRole::HandingOff => {
+                PairDecision::Promote(format!("peer {} handed off", peer.node_id))
+            }
+            PairRole::Standby
+                if self.config.preferred_active
+                    || (!peer.preferred_active && self.config.node_id < peer.node_id) =>
+            {
+                PairDecision::Promote(format!("peer {} is standby", peer.node_id))
+            }
+            _ => PairDecision::Stay,
+        }
+    }
+
+    /// Become active at an epoch above any seen, returning it
+    pub fn promote(&self, reason: &str) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let epoch = state.max_epoch.max(state.epoch) + 1;
+        state.max_epoch = epoch;
+        state.epoch = epoch;
+        transition(&mut state, PairRole::Active, reason);
+        epoch
+    }
+
+    /// Stop serving, as a standby or while handing off to the peer
+    pub fn demote(&self, role: PairRole, reason: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.role != role {
+            transition(&mut state, role, reason);
+        }
+    }
+
+    pub fn record_replication(&self, epoch: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.max_epoch = state.max_epoch.max(epoch);
+        state.last_replicated_at = Some(chrono::Utc::now().timestamp());
+    }
+
+    pub fn status(&self) -> PairStatus {
+        let state = self.state.lock().unwrap();
+        PairStatus {
+            enabled: self.config.enabled,
+            node_id: self.config.node_id.clone(),
+            role: state.role,
+            epoch: state.epoch,
+            peer_url: self.config.peer_url.clone(),
+            peer: state.peer.clone(),
+            peer_silent_ms: state
+                .peer_seen_at
+                .map(|seen| seen.elapsed().as_millis() as u64),
+            last_replicated_at: state.last_replicated_at,
+            transitions: state.transitions.iter().rev().cloned().collect(),
+        }
+    }
+}
+
+/// Whether an active `peer` wins over this instance at `epoch`
+fn outranks(peer: &Heartbeat, epoch: u64, node_id: &str) -> bool {
+    peer.epoch > epoch || (peer.epoch == epoch && peer.node_id.as_str() < node_id)
+}
+
+fn transition(state: &mut PairState, to: PairRole, reason: &str) {
+    log::warn!(
+        "Standby pair: {:?} -> {:?} at epoch {} ({})",
+        state.role,
+        to,
+        state.epoch,
+        reason
+    );
+    if state.transitions.len() >= MAX_TRANSITIONS {
+        state.transitions.pop_front();
+    }
+    state.transitions.push_back(PairTransition {
+        at: chrono::Utc::now().timestamp(),
+        from: state.role,
+        to,
+        epoch: state.epoch,
+        reason: reason.to_string(),
+    });
+    state.role = to;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(node_id: &str, preferred_active: bool) -> StandbyPair {
+        StandbyPair::new(StandbyConfig {
+            enabled: true,
+            node_id: node_id.to_string(),
+            preferred_active,
+            peer_url: "http://peer.invalid".to_string(),
+            failover_timeout_ms: 3000,
+            ..StandbyConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_pair_fails_over_hands_off_and_fences_split_brain() {
+        let a = pair("a", true);
+        let b = pair("b", false);
+        let now = Instant::now();
+        assert!(!a.is_serving());
+
+        // At startup the preferred instance promotes once it sees a standby peer
+        assert_eq!(b.observe_peer(a.heartbeat(), now), PairDecision::Stay);
+        assert_eq!(a.observe_peer(b.heartbeat(), now), PairDecision::Stay);
+        assert!(matches!(a.decide(now, || true), PairDecision::Promote(_)));
+        assert_eq!(a.promote("startup"), 1);
+        b.observe_peer(a.heartbeat(), now);
+        assert_eq!(b.decide(now, || true), PairDecision::Stay);
+
+        // The active goes silent: the standby waits for the timeout and a
+        // witness majority before it takes over at a higher epoch
+        let later = now + Duration::from_millis(3500);
+        assert_eq!(b.decide(later, || false), PairDecision::Stay);
+        assert!(matches!(b.decide(later, || true), PairDecision::Promote(_)));
+        assert_eq!(b.promote("peer lost"), 2);
+
+        // When the old active comes back it is outranked and steps down
+        assert!(matches!(
+            a.observe_peer(b.heartbeat(), later),
+            PairDecision::Demote(_)
+        ));
+        a.demote(PairRole::Standby, "outranked");
+        assert!(b.is_serving() && !a.is_serving());
+
+        // A planned handoff back to a
+        b.demote(PairRole::HandingOff, "maintenance");
+        a.observe_peer(b.heartbeat(), later);
+        assert!(matches!(
+            a.decide(later, || false),
+            PairDecision::Promote(_)
+        ));
+        assert_eq!(a.promote("handoff"), 3);
+        assert!(matches!(
+            b.observe_peer(a.heartbeat(), later),
+            PairDecision::Demote(_)
+        ));
+        b.demote(PairRole::Standby, "handoff complete");
+
+        let status = b.status();
+        assert_eq!(status.role, PairRole::Standby);
+        assert_eq!(status.transitions.len(), 3);
+        assert_eq!(status.transitions[0].to, PairRole::Standby);
+    }
+}
@@ -0,0 +1,455 @@
+//! Flow control for streamed encrypted responses
+//!
+//! A streamed response is a sequence of frames: `open`, one `chunk` per
+//! independently decryptable ciphertext chunk, and `end`. When in-flight work
+//! passes the load threshold the proxy interleaves `backpressure` frames with
+//! the queue depth and a suggested delay, and paces its own chunks to match.
+//! Clients that need to throttle harder pause the stream and resume it later
+//! instead of having the connection dropped; the proxy acknowledges both with
+//! `paused` and `resumed` frames.
+//!
+//! Frames are transport-neutral. This build carries them as server-sent
+//! events, with client control frames posted to a side endpoint.
+
+use crate::config::StreamFlowControlConfig;
+use crate::error::{Error, Result};
+use crate::fhe::Ciphertext;
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+/// Frame sent from the proxy to a streaming client
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamFrame {
+    Open {
+        stream_id: Uuid,
+        total_chunks: usize,
+    },
+    Chunk {
+        index: usize,
+        ciphertext_id: Uuid,
+        data: String, // Base64 encoded
+        noise_budget: Option<u64>,
+    },
+    Backpressure {
+        queue_depth: usize,
+        capacity: usize,
+        active_streams: usize,
+        suggested_delay_ms: u64,
+    },
+    Paused,
+    Resumed,
+    End {
+        reason: StreamEndReason,
+        chunks_sent: usize,
+    },
+}
+
+impl StreamFrame {
+    /// Frame type, used as the event name on event-based transports
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StreamFrame::Open { .. } => "open",
+            StreamFrame::Chunk { .. } => "chunk",
+            StreamFrame::Backpressure { .. } => "backpressure",
+            StreamFrame::Paused => "paused",
+            StreamFrame::Resumed => "resumed",
+            StreamFrame::End { .. } => "end",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamEndReason {
+    Complete,
+    /// The client stayed paused past `max_pause_seconds`
+    PauseTimeout,
+}
+
+/// Frame sent from a client to throttle its stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ControlFrame {
+    Pause,
+    Resume,
+}
+
+impl ControlFrame {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ControlFrame::Pause => "pause",
+            ControlFrame::Resume => "resume",
+        }
+    }
+}
+
+/// Load as seen by a stream before each chunk
+#[derive(Debug, Clone, Copy)]
+pub struct LoadSample {
+    pub queue_depth: usize,
+    pub capacity: usize,
+    /// Projected time until the queued work has drained
+    pub projected_wait_ms: u64,
+}
+
+#[derive(Debug)]
+struct StreamEntry {
+    tenant: Option<String>,
+    paused: watch::Sender<bool>,
+}
+
+#[derive(Debug, Default)]
+struct StreamCounters {
+    opened: AtomicU64,
+    backpressure_frames: AtomicU64,
+    pauses: AtomicU64,
+    pause_timeouts: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamStats {
+    pub enabled: bool,
+    pub active_streams: usize,
+    pub opened: u64,
+    pub backpressure_frames: u64,
+    pub pauses: u64,
+    pub pause_timeouts: u64,
+}
+
+/// Open response streams and their pause state
+#[derive(Debug)]
+pub struct StreamRegistry {
+    config: StreamFlowControlConfig,
+    streams: Mutex<HashMap<Uuid, StreamEntry>>,
+    counters: StreamCounters,
+}
+
+impl StreamRegistry {
+    pub fn new(config: StreamFlowControlConfig) -> Self {
+        Self {
+            config,
+            streams: Mutex::new(HashMap::new()),
+            counters: StreamCounters::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn active(&self) -> usize {
+        self.streams.lock().unwrap().len()
+    }
+
+    /// The backpressure frame to send under `load`, or `None` below the threshold
+    ///
+    /// Past the threshold the suggested delay grows from the chunk interval
+    /// towards the configured maximum as utilization approaches capacity; a
+    /// longer projected queue wait takes precedence.
+    pub fn backpressure(&self, load: &LoadSample) -> Option<StreamFrame> {
+        let capacity = load.capacity.max(1);
+        let utilization = load.queue_depth as f64 / capacity as f64;
+        let threshold = self.config.load_threshold;
+        if utilization < threshold {
+            return None;
+        }
+
+        let overload = ((utilization - threshold) / (1.0 - threshold).max(f64::EPSILON)).min(1.0);
+        let range = self.config.max_suggested_delay_ms - self.config.chunk_interval_ms;
+        let scaled = self.config.chunk_interval_ms + (range as f64 * overload) as u64;
+        Some(StreamFrame::Backpressure {
+            queue_depth: load.queue_depth,
+            capacity: load.capacity,
+            active_streams: self.active(),
+            suggested_delay_ms: scaled
+                .max(load.projected_wait_ms)
+                .min(self.config.max_suggested_delay_ms),
+        })
+    }
+
+    /// Apply a client control frame. Returns `false` if `tenant` has no such stream.
+    pub fn control(
+        &self,
+        stream_id: Uuid,
+        tenant: Option<&str>,
+        frame: ControlFrame,
+    ) -> Result<bool> {
+        if !self.is_enabled() {
+            return Err(Error::Validation(
+                "Stream flow control is disabled".to_string(),
+            ));
+        }
+        let streams = self.streams.lock().unwrap();
+        let Some(entry) = streams
+            .get(&stream_id)
+            .filter(|entry| entry.tenant.as_deref() == tenant)
+        else {
+            return Ok(false);
+        };
+
+        let pause = frame == ControlFrame::Pause;
+        let changed = entry.paused.send_if_modified(|paused| {
+            let changed = *paused != pause;
+            *paused = pause;
+            changed
+        });
+        if changed && pause {
+            self.counters.pauses.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(true)
+    }
+
+    /// Start streaming `chunks` for `tenant`, sampling `load` before each chunk
+    pub fn open<F>(
+        self: &Arc<Self>,
+        tenant: Option<&str>,
+        chunks: Vec<Ciphertext>,
+        load: F,
+    ) -> Result<(Uuid, ReceiverStream<StreamFrame>)>
+    where
+        F: Fn() -> LoadSample + Send + 'static,
+    {
+        let stream_id = Uuid::new_v4();
+        let (paused, pause_receiver) = watch::channel(false);
+        {
+            let mut streams = self.streams.lock().unwrap();
+            if streams.len() >= self.config.max_streams {
+                return Err(Error::ResourceExhaustion(format!(
+                    "{} response streams already open",
+                    streams.len()
+                )));
+            }
+            streams.insert(
+                stream_id,
+                StreamEntry {
+                    tenant: tenant.map(str::to_string),
+                    paused,
+                },
+            );
+        }
+        self.counters.opened.fetch_add(1, Ordering::Relaxed);
+
+        // A full buffer makes the pump wait, so a slow reader throttles it too
+        let (sender, receiver) = mpsc::channel(self.config.buffer_frames.max(1));
+        let registry = self.clone();
+        tokio::spawn(async move {
+            registry
+                .pump(stream_id, chunks, load, &sender, pause_receiver)
+                .await;
+            registry.streams.lock().unwrap().remove(&stream_id);
+        });
+        Ok((stream_id, ReceiverStream::new(receiver)))
+    }
+
+    /// Send the stream's frames; returns early once the client has gone
+    async fn pump<F>(
+        &self,
+        stream_id: Uuid,
+        chunks: Vec<Ciphertext>,
+        load: F,
+        sender: &mpsc::Sender<StreamFrame>,
+        mut paused: watch::Receiver<bool>,
+    ) where
+        F: Fn() -> LoadSample,
+    {
+        let total_chunks = chunks.len();
+        let open = StreamFrame::Open {
+            stream_id,
+            total_chunks,
+        };
+        if sender.send(open).await.is_err() {
+            return;
+        }
+
+        let chunk_interval = Duration::from_millis(self.config.chunk_interval_ms);
+        let backpressure_interval = Duration::from_millis(self.config.backpressure_interval_ms);
+        let mut last_backpressure: Option<Instant> = None;
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            if self.config.enabled && *paused.borrow_and_update() {
+                if sender.send(StreamFrame::Paused).await.is_err() {
+                    return;
+                }
+                let max_pause = Duration::from_secs(self.config.max_pause_seconds);
+                let resumed =
+                    match tokio::time::timeout(max_pause, paused.wait_for(|paused| !*paused)).await
+                    {
+                        Ok(Ok(_)) => true,
+                        // The registry entry is gone; nothing can resume the stream
+                        Ok(Err(_)) => return,
+                        Err(_) => false,
+                    };
+                if !resumed {
+                    log::warn!(
+                        "Ending stream {}: paused for more than {}s",
+                        stream_id,
+                        self.config.max_pause_seconds
+                    );
+                    self.counters.pause_timeouts.fetch_add(1, Ordering::Relaxed);
+                    let end = StreamFrame::End {
+                        reason: StreamEndReason::PauseTimeout,
+                        chunks_sent: index,
+                    };
+                    let _ = sender.send(end).await;
+                    return;
+                }
+                if sender.send(StreamFrame::Resumed).await.is_err() {
+                    return;
+                }
+            }
+
+            let mut delay = chunk_interval;
+            if self.config.enabled {
+                if let Some(frame) = self.backpressure(&load()) {
+                    if let StreamFrame::Backpressure {
+                        suggested_delay_ms, ..
+                    } = frame
+                    {
+                        delay = delay.max(Duration::from_millis(suggested_delay_ms));
+                    }
+                    if last_backpressure.is_none_or(|t| t.elapsed() >= backpressure_interval) {
+                        if sender.send(frame).await.is_err() {
+                            return;
+                        }
+                        last_backpressure = Some(Instant::now());
+                        self.counters
+                            .backpressure_frames
+                            .fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+
+            let frame = StreamFrame::Chunk {
+                index,
+                ciphertext_id: chunk.id,
+                data: BASE64_STANDARD.encode(&chunk.data),
+                noise_budget: chunk.noise_budget,
+            };
+            if sender.send(frame).await.is_err() {
+                return;
+            }
+            if index + 1 < total_chunks {
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        let end = StreamFrame::End {
+            reason: StreamEndReason::Complete,
+            chunks_sent: total_chunks,
+        };
+        let _ = sender.send(end).await;
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        StreamStats {
+            enabled: self.config.enabled,
+            active_streams: self.active(),
+            opened: self.counters.opened.load(Ordering::Relaxed),
+            backpressure_frames: self.counters.backpressure_frames.load(Ordering::Relaxed),
+            pauses: self.counters.pauses.load(Ordering::Relaxed),
+            pause_timeouts: self.counters.pause_timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe::FheParams;
+    use tokio_stream::StreamExt;
+
+    fn chunk(byte: u8) -> Ciphertext {
+        Ciphertext {
+            id: Uuid::new_v4(),
+            data: vec![byte; 4],
+            params: FheParams::default(),
+            noise_budget: Some(40),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_backpressure_and_pause() {
+        let registry = Arc::new(StreamRegistry::new(StreamFlowControlConfig {
+            chunk_interval_ms: 1,
+            backpressure_interval_ms: 0,
+            max_suggested_delay_ms: 5,
+            max_streams: 1,
+            ..StreamFlowControlConfig::default()
+        }));
+        let overloaded = || LoadSample {
+            queue_depth: 10,
+            capacity: 10,
+            projected_wait_ms: 0,
+        };
+
+        // Below the threshold nothing is signalled
+        assert!(registry
+            .backpressure(&LoadSample {
+                queue_depth: 5,
+                capacity: 10,
+                projected_wait_ms: 0
+            })
+            .is_none());
+
+        let (stream_id, mut frames) = registry
+            .open(Some("acme"), vec![chunk(1), chunk(2), chunk(3)], overloaded)
+            .unwrap();
+        assert!(registry
+            .open(Some("acme"), vec![chunk(4)], overloaded)
+            .is_err());
+
+        // Only the owning tenant can throttle the stream
+        assert!(!registry
+            .control(stream_id, Some("globex"), ControlFrame::Pause)
+            .unwrap());
+        assert!(registry
+            .control(stream_id, Some("acme"), ControlFrame::Pause)
+            .unwrap());
+
+        let mut seen = Vec::new();
+        while let Some(frame) = frames.next().await {
+            if frame == StreamFrame::Paused {
+                assert!(registry
+                    .control(stream_id, Some("acme"), ControlFrame::Resume)
+                    .unwrap());
+            }
+            seen.push(frame);
+        }
+
+        assert!(matches!(
+            seen[0],
+            StreamFrame::Open {
+                total_chunks: 3,
+                ..
+            }
+        ));
+        let position = |kind: &str| seen.iter().position(|f| f.kind() == kind).unwrap();
+        assert!(position("paused") < position("resumed"));
+        assert!(seen.contains(&StreamFrame::Backpressure {
+            queue_depth: 10,
+            capacity: 10,
+            active_streams: 1,
+            suggested_delay_ms: 5,
+        }));
+        assert_eq!(seen.iter().filter(|f| f.kind() == "chunk").count(), 3);
+        assert_eq!(
+            seen.last(),
+            Some(&StreamFrame::End {
+                reason: StreamEndReason::Complete,
+                chunks_sent: 3
+            })
+        );
+
+        let stats = registry.stats();
+        assert_eq!(stats.active_streams, 0);
+        assert_eq!(stats.pauses, 1);
+        assert!(stats.backpressure_frames >= 3);
+    }
+}
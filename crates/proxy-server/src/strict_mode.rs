@@ -0,0 +1,168 @@
+//! Startup enforcement of cryptographic and operational hygiene
+//!
+//! With `strict_security.enabled` (or `FHE_STRICT_SECURITY=true`) the proxy
+//! checks its configuration before serving and refuses to start if any
+//! setting is weaker than a production fleet should run with: encryption
+//! parameters below the configured minimums, replay protection left out of
+//! the validator chain, development tooling that exposes provider traffic or
+//! accepts inline ciphertexts, listeners or delegation endpoints without TLS,
+//! and no durable sink for the audit trail. The violations are printed as a
+//! JSON report so deployment tooling can act on them.
+
+use crate::config::Config;
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// Exit code of a start refused by strict mode
+pub const STRICT_MODE_EXIT_CODE: i32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    /// Which check failed: `key_size`, `replay_protection`, `debug_endpoint`,
+    /// `tls` or `audit_sink`
+    pub check: &'static str,
+    /// Dotted config key to change
+    pub key: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StrictModeReport {
+    pub passed: bool,
+    pub violations: Vec<Violation>,
+}
+
+/// Check `config` against the strict mode rules
+pub fn check(config: &Config) -> StrictModeReport {
+    let strict = &config.strict_security;
+    let mut violations = Vec::new();
+    let mut violation = |check, key: &str, message: String| {
+        violations.push(Violation {
+            check,
+            key: key.to_string(),
+            message,
+        })
+    };
+
+    if config.encryption.poly_modulus_degree < strict.min_poly_modulus_degree {
+        violation(
+            "key_size",
+            "encryption.poly_modulus_degree",
+            format!(
+                "Poly modulus degree {} is below the minimum of {}",
+                config.encryption.poly_modulus_degree, strict.min_poly_modulus_degree
+            ),
+        );
+    }
+    if config.encryption.security_level < strict.min_security_level {
+        violation(
+            "key_size",
+            "encryption.security_level",
+            format!(
+                "Security level of {} bits is below the minimum of {}",
+                config.encryption.security_level, strict.min_security_level
+            ),
+        );
+    }
+
+    if !config.validation.order.iter().any(|v| v == "replay") {
+        violation(
+            "replay_protection",
+            "validation.order",
+            "The replay validator is not in the validation chain".to_string(),
+        );
+    }
+
+    if config.llm.recording.mode != "off" {
+        violation(
+            "debug_endpoint",
+            "llm.recording.mode",
+            format!(
+                "Provider traffic is {}ed from disk; recording is for development only",
+                config.llm.recording.mode
+            ),
+        );
+    }
+    if config.server.mirroring.accept_mirrored {
+        violation(
+            "debug_endpoint",
+            "server.mirroring.accept_mirrored",
+            "Mirrored requests with inline ciphertexts are accepted; this is for staging only"
+                .to_string(),
+        );
+    }
+
+    if !strict.tls_terminated_upstream && !is_loopback(&config.server.host) {
+        violation(
+            "tls",
+            "server.host",
+            format!(
+                "Plain HTTP listener on {}; listen on loopback or set strict_security.tls_terminated_upstream",
+                config.server.host
+            ),
+        );
+    }
+    let delegation = &config.encryption.decryption_delegation;
+    if delegation.enabled && delegation.endpoint.starts_with("http://") {
+        violation(
+            "tls",
+            "encryption.decryption_delegation.endpoint",
+            format!(
+                "Decryption delegation endpoint {} is not https",
+                delegation.endpoint
+            ),
+        );
+    }
+
+    if config.persistence.backend == "memory" && !config.monitoring.siem.enabled {
+        violation(
+            "audit_sink",
+            "persistence.backend",
+            "The audit log is kept in memory only and no SIEM export is enabled".to_string(),
+        );
+    }
+
+    StrictModeReport {
+        passed: violations.is_empty(),
+        violations,
+    }
+}
+
+fn is_loopback(host: &str) -> bool {
+    host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weak_settings_are_reported() {
+        let mut config = Config::default();
+        config.encryption.poly_modulus_degree = 4096;
+        config.validation.order.retain(|v| v != "replay");
+        config.llm.recording.mode = "record".to_string();
+        config.persistence.backend = "memory".to_string();
+
+        let report = check(&config);
+        assert!(!report.passed);
+        let checks: Vec<&str> = report.violations.iter().map(|v| v.check).collect();
+        assert_eq!(
+            checks,
+            [
+                "key_size",
+                "replay_protection",
+                "debug_endpoint",
+                "tls",
+                "audit_sink"
+            ]
+        );
+
+        config.encryption.poly_modulus_degree = 16384;
+        config.validation.order.push("replay".to_string());
+        config.llm.recording.mode = "off".to_string();
+        config.server.host = "127.0.0.1".to_string();
+        config.monitoring.siem.enabled = true;
+        assert!(check(&config).passed);
+    }
+}
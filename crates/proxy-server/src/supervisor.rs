@@ -0,0 +1,248 @@
+//! Supervision of long-running background tasks
+//!
+//! Background loops are started through [`TaskSupervisor::spawn`] under a
+//! name. A task that panics is restarted after an exponential backoff, until
+//! it has failed `max_restarts` times in a row; a run that lasts
+//! `stable_after_seconds` clears the count. Each panic is passed to the
+//! failure hook (the proxy records it with the monitoring service) and the
+//! latest one is kept with the task's state for `/v1/admin/tasks`.
+
+use crate::config::TaskSupervisorConfig;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Panicked; waiting out the backoff before the next restart
+    BackingOff,
+    /// Returned, or was cancelled at shutdown
+    Finished,
+    /// Panicked more often in a row than the restart policy allows
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub name: String,
+    pub state: TaskState,
+    pub restarts: u32,
+    pub consecutive_failures: u32,
+    pub started_at: i64,
+    pub last_error: Option<String>,
+    pub last_failure_at: Option<i64>,
+}
+
+/// A task panic, as passed to the failure hook
+#[derive(Debug, Clone)]
+pub struct TaskFailure {
+    pub task: String,
+    pub message: String,
+    pub will_restart: bool,
+}
+
+type FailureHook = Box<dyn Fn(TaskFailure) + Send + Sync>;
+
+pub struct TaskSupervisor {
+    config: TaskSupervisorConfig,
+    tasks: Arc<RwLock<BTreeMap<String, TaskStatus>>>,
+    on_failure: Arc<OnceLock<FailureHook>>,
+}
+
+impl std::fmt::Debug for TaskSupervisor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskSupervisor")
+            .field("config", &self.config)
+            .field("tasks", &self.tasks)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TaskSupervisor {
+    pub fn new(config: TaskSupervisorConfig) -> Self {
+        Self {
+            config,
+            tasks: Arc::new(RwLock::new(BTreeMap::new())),
+            on_failure: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Call `hook` on every task panic; only the first hook set is kept
+    pub fn set_failure_hook(&self, hook: impl Fn(TaskFailure) + Send + Sync + 'static) {
+        if self.on_failure.set(Box::new(hook)).is_err() {
+            log::warn!("Task supervisor failure hook is already set");
+        }
+    }
+
+    /// Run the future built by `task` under supervision, building a fresh one
+    /// for every restart
+    pub fn spawn<F, Fut>(&self, name: &str, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let name = name.to_string();
+        let config = self.config.clone();
+        let tasks = self.tasks.clone();
+        let on_failure = self.on_failure.clone();
+        tasks.write().unwrap().insert(
+            name.clone(),
+            TaskStatus {
+                name: name.clone(),
+                state: TaskState::Running,
+                restarts: 0,
+                consecutive_failures: 0,
+                started_at: chrono::Utc::now().timestamp(),
+                last_error: None,
+                last_failure_at: None,
+            },
+        );
+        let task_name = name.clone();
+        let update = move |change: &dyn Fn(&mut TaskStatus)| {
+            if let Some(status) = tasks.write().unwrap().get_mut(&name) {
+                change(status);
+            }
+        };
+
+        tokio::spawn(async move {
+            let initial_backoff = Duration::from_millis(config.initial_backoff_ms);
+            let max_backoff = Duration::from_secs(config.max_backoff_seconds);
+            let stable_after = Duration::from_secs(config.stable_after_seconds);
+            let mut backoff = initial_backoff;
+            let mut consecutive_failures = 0;
+            loop {
+                let started = Instant::now();
+                let error = match tokio::spawn(task()).await {
+                    Ok(()) => None,
+                    Err(e) if e.is_panic() => Some(panic_message(e.into_panic())),
+                    Err(_) => None,
+                };
+                let Some(message) = error else {
+                    update(&|status| status.state = TaskState::Finished);
+                    return;
+                };
+
+                if started.elapsed() >= stable_after {
+                    consecutive_failures = 0;
+                    backoff = initial_backoff;
+                }
+                consecutive_failures += 1;
+                let will_restart = consecutive_failures <= config.max_restarts;
+                update(&|status| {
+                    status.state = if will_restart {
+                        TaskState::BackingOff
+                    } else {
+                        TaskState::Failed
+                    };
+                    status.consecutive_failures = consecutive_failures;
+                    status.last_error = Some(message.clone());
+                    status.last_failure_at = Some(chrono::Utc::now().timestamp());
+                });
+                if will_restart {
+                    log::error!(
+                        "Background task {} panicked, restarting in {:?}: {}",
+                        task_name,
+                        backoff,
+                        message
+                    );
+                } else {
+                    log::error!(
+                        "Background task {} panicked {} times in a row, giving up: {}",
+                        task_name,
+                        consecutive_failures,
+                        message
+                    );
+                }
+                if let Some(hook) = on_failure.get() {
+                    hook(TaskFailure {
+                        task: task_name.clone(),
+                        message,
+                        will_restart,
+                    });
+                }
+                if !will_restart {
+                    return;
+                }
+
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(max_backoff);
+                update(&|status| {
+                    status.state = TaskState::Running;
+                    status.restarts += 1;
+                    status.started_at = chrono::Utc::now().timestamp();
+                });
+            }
+        });
+    }
+
+    /// Every registered task, by name
+    pub fn tasks(&self) -> Vec<TaskStatus> {
+        self.tasks.read().unwrap().values().cloned().collect()
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic with a non-string payload".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    async fn wait_for(supervisor: &TaskSupervisor, name: &str, state: TaskState) -> TaskStatus {
+        for _ in 0..200 {
+            let status = supervisor.tasks().into_iter().find(|t| t.name == name);
+            if let Some(status) = status.filter(|t| t.state == state) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("Task {} never reached {:?}", name, state);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_tasks_restart_until_policy_gives_up() {
+        let supervisor = TaskSupervisor::new(TaskSupervisorConfig {
+            max_restarts: 2,
+            initial_backoff_ms: 1,
+            ..TaskSupervisorConfig::default()
+        });
+        let failures = Arc::new(AtomicU32::new(0));
+        let hook_failures = failures.clone();
+        supervisor.set_failure_hook(move |_| {
+            hook_failures.fetch_add(1, Ordering::Relaxed);
+        });
+
+        // Recovers after two panics
+        let runs = Arc::new(AtomicU32::new(0));
+        let task_runs = runs.clone();
+        supervisor.spawn("flaky", move || {
+            let runs = task_runs.clone();
+            async move {
+                if runs.fetch_add(1, Ordering::Relaxed) < 2 {
+                    panic!("transient failure");
+                }
+            }
+        });
+        let flaky = wait_for(&supervisor, "flaky", TaskState::Finished).await;
+        assert_eq!(flaky.restarts, 2);
+        assert_eq!(flaky.last_error.as_deref(), Some("transient failure"));
+
+        // Never recovers: one first run plus two restarts, then it stays down
+        supervisor.spawn("broken", || async { panic!("{} is broken", "always") });
+        let broken = wait_for(&supervisor, "broken", TaskState::Failed).await;
+        assert_eq!(broken.restarts, 2);
+        assert_eq!(broken.consecutive_failures, 3);
+        assert_eq!(broken.last_error.as_deref(), Some("always is broken"));
+        assert_eq!(failures.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,344 @@
+//! systemd integration for bare-metal and VM deployments
+//!
+//! Under a `Type=notify` unit the proxy reports `READY=1` once the readiness
+//! check first passes (after engine warm-up), keeps `STATUS=` in line with
+//! the health module, and sends `STOPPING=1` when it starts draining. With
+//! `WatchdogSec=` set it pings the watchdog at half the interval for as long
+//! as the liveness check passes, so a wedged process is restarted.
+//!
+//! With socket activation the proxy serves on the listener systemd passes
+//! instead of binding `server.host:server.port`, so it can be restarted
+//! without refusing connections. Nothing here is used unless systemd set the
+//! corresponding environment variables.
+//!
+//! Logs written to the journal get a `<priority>` prefix per line and no
+//! colours or timestamps, which journald adds itself.
+
+use crate::config::SystemdConfig;
+use crate::error::{Error, Result};
+use std::fmt;
+use std::time::Duration;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// First file descriptor passed by socket activation
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Forces journal-style log lines when set to `journald`
+pub const LOG_FORMAT_ENV: &str = "FHE_LOG_FORMAT";
+
+/// The service manager's notification socket, if the proxy was started with one
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    socket: Option<String>,
+    watchdog: Option<Duration>,
+    readiness_poll: Duration,
+}
+
+impl Notifier {
+    pub fn from_env(config: &SystemdConfig) -> Self {
+        let var = |name: &str| std::env::var(name).ok();
+        Self::from_vars(
+            config,
+            var("NOTIFY_SOCKET"),
+            var("WATCHDOG_USEC"),
+            var("WATCHDOG_PID"),
+            std::process::id(),
+        )
+    }
+
+    fn from_vars(
+        config: &SystemdConfig,
+        socket: Option<String>,
+        watchdog_usec: Option<String>,
+        watchdog_pid: Option<String>,
+        pid: u32,
+    ) -> Self {
+        let socket = socket.filter(|s| config.notify && !s.is_empty());
+        // The watchdog is meant for this process unless WATCHDOG_PID says otherwise
+        let watchdog = watchdog_usec
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|usec| *usec > 0)
+            .filter(|_| config.watchdog && socket.is_some())
+            .filter(|_| watchdog_pid.is_none_or(|p| p.parse() == Ok(pid)))
+            .map(Duration::from_micros);
+        Self {
+            socket,
+            watchdog,
+            readiness_poll: Duration::from_millis(config.readiness_poll_ms),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// How often the watchdog must be pinged: half its timeout
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+
+    /// How often readiness, health and the watchdog are looked at
+    pub fn tick_interval(&self) -> Duration {
+        self.watchdog_interval()
+            .map_or(self.readiness_poll, |w| w.min(self.readiness_poll))
+    }
+
+    pub fn ready(&self, status: &str) {
+        self.send(&[("READY", "1"), ("STATUS", status)]);
+    }
+
+    pub fn stopping(&self) {
+        self.send(&[("STOPPING", "1"), ("STATUS", "Draining connections")]);
+    }
+
+    pub fn status(&self, status: &str) {
+        self.send(&[("STATUS", status)]);
+    }
+
+    pub fn watchdog(&self) {
+        if self.watchdog.is_some() {
+            self.send(&[("WATCHDOG", "1")]);
+        }
+    }
+
+    fn send(&self, fields: &[(&str, &str)]) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+        if let Err(e) = send_notification(socket, &notification(fields)) {
+            log::warn!("Failed to notify systemd at {}: {}", socket, e);
+        }
+    }
+}
+
+/// `KEY=value` lines; newlines in values would start a new assignment
+fn notification(fields: &[(&str, &str)]) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| format!("{}={}\n", key, value.replace('\n', " ")))
+        .collect()
+}
+
+#[cfg(unix)]
+fn send_notification(socket: &str, message: &str) -> Result<()> {
+    let sender = std::os::unix::net::UnixDatagram::unbound()?;
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sender.send_to_addr(message.as_bytes(), &address)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(Error::Config(format!(
+                "Abstract notification socket {} is only supported on Linux",
+                socket
+            )))
+        }
+        None => {
+            sender.send_to(message.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_notification(_socket: &str, _message: &str) -> Result<()> {
+    Ok(())
+}
+
+/// Number of sockets passed to this process, from `LISTEN_PID` and `LISTEN_FDS`
+fn passed_fd_count(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> Result<usize> {
+    match (listen_pid, listen_fds) {
+        (Some(listen_pid), Some(listen_fds)) if listen_pid.parse() == Ok(pid) => listen_fds
+            .parse()
+            .map_err(|_| Error::Config(format!("Invalid LISTEN_FDS from systemd: {}", listen_fds))),
+        _ => Ok(0),
+    }
+}
+
+/// The first listener passed by socket activation, if any. The activation
+/// variables are cleared so child processes do not take the socket too.
+#[cfg(unix)]
+pub fn take_activated_listener() -> Result<Option<std::net::TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    let count = passed_fd_count(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    )?;
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+    if count == 0 {
+        return Ok(None);
+    }
+    if count > 1 {
+        log::warn!(
+            "systemd passed {} sockets; serving on the first only",
+            count
+        );
+    }
+    // SAFETY: systemd hands this process ownership of the descriptors from
+    // LISTEN_FDS_START on, and nothing else in the process has claimed them.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    // Anything but a TCP socket has no TCP local address
+    listener.local_addr().map_err(|e| {
+        Error::Config(format!(
+            "Socket passed by systemd is not a TCP listener: {}",
+            e
+        ))
+    })?;
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+#[cfg(not(unix))]
+pub fn take_activated_listener() -> Result<Option<std::net::TcpListener>> {
+    Ok(None)
+}
+
+/// Whether log lines should be formatted for the journal: forced with
+/// `FHE_LOG_FORMAT=journald`, otherwise when stdout, where logs are
+/// written, is the stream systemd named in `JOURNAL_STREAM`
+pub fn logging_to_journal() -> bool {
+    if let Ok(format) = std::env::var(LOG_FORMAT_ENV) {
+        return format == "journald";
+    }
+    let Ok(stream) = std::env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    stdout_is(&stream)
+}
+
+#[cfg(unix)]
+fn stdout_is(stream: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let Some((device, inode)) = stream.split_once(':') else {
+        return false;
+    };
+    match std::fs::metadata("/proc/self/fd/1").or_else(|_| std::fs::metadata("/dev/stdout")) {
+        Ok(metadata) => device.parse() == Ok(metadata.dev()) && inode.parse() == Ok(metadata.ino()),
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn stdout_is(_stream: &str) -> bool {
+    false
+}
+
+/// syslog priority journald reads from a `<N>` line prefix
+fn priority(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// One line per event: `<priority>target: message key=value ...`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JournaldFormat;
+
+impl<S, N> FormatEvent<S, N> for JournaldFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        write!(
+            writer,
+            "<{}>{}: ",
+            priority(metadata.level()),
+            metadata.target()
+        )?;
+        ctx.format_fields(writer.by_ref(), event)?;
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                write!(writer, " span={}", span.name())?;
+            }
+        }
+        writeln!(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notifications_watchdog_and_activation_follow_the_environment() {
+        let config = SystemdConfig::default();
+        let socket = Some("/run/systemd/notify".to_string());
+        let usec = Some("10000000".to_string());
+
+        let notifier = Notifier::from_vars(&config, None, usec.clone(), None, 42);
+        assert!(!notifier.is_active());
+        assert_eq!(notifier.watchdog_interval(), None);
+
+        let notifier = Notifier::from_vars(&config, socket.clone(), usec.clone(), None, 42);
+        assert!(notifier.is_active());
+        assert_eq!(notifier.watchdog_interval(), Some(Duration::from_secs(5)));
+        assert_eq!(notifier.tick_interval(), Duration::from_millis(500));
+        // A watchdog meant for another process is not ours to ping
+        let other = Some("7".to_string());
+        let notifier = Notifier::from_vars(&config, socket.clone(), usec.clone(), other, 42);
+        assert_eq!(notifier.watchdog_interval(), None);
+        let disabled = SystemdConfig {
+            notify: false,
+            ..SystemdConfig::default()
+        };
+        assert!(!Notifier::from_vars(&disabled, socket, usec, None, 42).is_active());
+
+        assert_eq!(
+            notification(&[("READY", "1"), ("STATUS", "Serving\nhealthy")]),
+            "READY=1\nSTATUS=Serving healthy\n"
+        );
+
+        assert_eq!(passed_fd_count(Some("42"), Some("2"), 42).unwrap(), 2);
+        assert_eq!(passed_fd_count(Some("41"), Some("2"), 42).unwrap(), 0);
+        assert_eq!(passed_fd_count(None, None, 42).unwrap(), 0);
+        assert!(passed_fd_count(Some("42"), Some("two"), 42).is_err());
+
+        assert_eq!(priority(&Level::ERROR), 3);
+        assert_eq!(priority(&Level::INFO), 6);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notification_reaches_the_socket() {
+        let dir = std::env::temp_dir().join(format!("fhe-notify-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify");
+        let receiver = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        let notifier = Notifier::from_vars(
+            &SystemdConfig::default(),
+            Some(path.to_string_lossy().into_owned()),
+            Some("2000000".to_string()),
+            None,
+            1,
+        );
+        notifier.ready("Serving");
+        notifier.watchdog();
+        let mut buffer = [0u8; 256];
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"READY=1\nSTATUS=Serving\n");
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"WATCHDOG=1\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+//! PII-safe telemetry sampling
+//!
+//! A configurable fraction of requests is described for usage analytics by
+//! structural metadata only: the route template, the model, a bucket for the
+//! size of the request's ciphertext, the tenant's tier, the status class and
+//! the latency. Samples are aggregated per combination of those fields and
+//! exported with the rest of /metrics.
+//!
+//! What a sample may hold is fixed by type. Every field of [`TelemetrySample`]
+//! must implement the sealed [`StructuralField`] trait, which only the label
+//! types in this module do; a `String`, byte buffer or identifier added to the
+//! sample does not compile. Adding a new kind of field means adding a new
+//! label type here, where its contents can be reviewed.
+
+use crate::config::{SlaClass, TelemetrySamplingConfig};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A value that describes the shape of a request and never its content.
+/// Sealed: only the label types in this module implement it.
+pub trait StructuralField: sealed::Sealed + Serialize {}
+
+macro_rules! structural_fields {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $ty {}
+            impl StructuralField for $ty {}
+        )*
+    };
+}
+
+/// Declares the sample struct and checks at compile time that every field is
+/// a [`StructuralField`]
+macro_rules! telemetry_sample {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $($(#[$field_meta:meta])* pub $field:ident: $ty:ty,)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub struct $name {
+            $($(#[$field_meta])* pub $field: $ty,)*
+        }
+
+        const _: fn() = || {
+            fn structural<T: StructuralField>() {}
+            $(structural::<$ty>();)*
+        };
+    };
+}
+
+/// Route template the request matched, e.g. `/v1/ciphertext/{id}`; never the
+/// concrete path
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct RouteLabel(String);
+
+impl RouteLabel {
+    pub fn from_matched(template: Option<&str>) -> Self {
+        Self(template.unwrap_or("unmatched").to_string())
+    }
+}
+
+/// A model name from the configured list, a well-formed name when no list is
+/// configured, or "other"
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct ModelLabel(String);
+
+impl ModelLabel {
+    const MAX_LEN: usize = 64;
+
+    pub fn new(model: Option<&str>, allowed: &[String]) -> Self {
+        let Some(model) = model else {
+            return Self("none".to_string());
+        };
+        let listed = if allowed.is_empty() {
+            model.len() <= Self::MAX_LEN
+                && !model.is_empty()
+                && model
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+        } else {
+            allowed.iter().any(|m| m == model)
+        };
+        Self(if listed { model } else { "other" }.to_string())
+    }
+}
+
+/// Size of the request body, which for completions is the prompt ciphertext
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeBucket {
+    Unknown,
+    Under1Kib,
+    Under16Kib,
+    Under256Kib,
+    Under4Mib,
+    Over4Mib,
+}
+
+impl SizeBucket {
+    pub fn from_bytes(bytes: Option<u64>) -> Self {
+        match bytes {
+            None => SizeBucket::Unknown,
+            Some(n) if n < 1 << 10 => SizeBucket::Under1Kib,
+            Some(n) if n < 16 << 10 => SizeBucket::Under16Kib,
+            Some(n) if n < 256 << 10 => SizeBucket::Under256Kib,
+            Some(n) if n < 4 << 20 => SizeBucket::Under4Mib,
+            Some(_) => SizeBucket::Over4Mib,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusClass {
+    Success,
+    ClientError,
+    ServerError,
+}
+
+impl StatusClass {
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            500.. => StatusClass::ServerError,
+            400.. => StatusClass::ClientError,
+            _ => StatusClass::Success,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LatencyMs(pub u64);
+
+structural_fields!(
+    RouteLabel,
+    ModelLabel,
+    SizeBucket,
+    StatusClass,
+    LatencyMs,
+    SlaClass
+);
+
+telemetry_sample! {
+    /// What is recorded about one sampled request
+    #[derive(Debug, Clone, PartialEq, Serialize)]
+    pub struct TelemetrySample {
+        pub route: RouteLabel,
+        pub model: ModelLabel,
+        pub size: SizeBucket,
+        pub tier: SlaClass,
+        pub status: StatusClass,
+        pub latency: LatencyMs,
+    }
+}
+
+/// The model a handler served, left in the response extensions for sampling
+#[derive(Debug, Clone)]
+pub struct ServedModel(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SeriesKey {
+    route: RouteLabel,
+    model: ModelLabel,
+    size: SizeBucket,
+    tier: &'static str,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SeriesTotals {
+    requests: u64,
+    client_errors: u64,
+    server_errors: u64,
+    latency_ms_total: u64,
+    latency_ms_max: u64,
+}
+
+/// Aggregate of the samples sharing one combination of labels
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetrySeries {
+    pub route: RouteLabel,
+    pub model: ModelLabel,
+    pub size: SizeBucket,
+    pub tier: &'static str,
+    pub requests: u64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    pub avg_latency_ms: f64,
+    pub max_latency_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetryReport {
+    pub enabled: bool,
+    pub sample_rate: f64,
+    pub sampled: u64,
+    /// Samples not kept because `max_series` combinations were already tracked
+    pub dropped: u64,
+    pub series: Vec<TelemetrySeries>,
+}
+
+#[derive(Debug)]
+pub struct TelemetrySampler {
+    config: TelemetrySamplingConfig,
+    series: Mutex<BTreeMap<SeriesKey, SeriesTotals>>,
+    sampled: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl TelemetrySampler {
+    pub fn new(config: TelemetrySamplingConfig) -> Self {
+        Self {
+            config,
+            series: Mutex::new(BTreeMap::new()),
+            sampled: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Whether to sample the request about to be served
+    pub fn should_sample(&self) -> bool {
+        self.is_enabled() && rand::random::<f64>() < self.config.sample_rate
+    }
+
+    /// Build the sample for a finished request
+    pub fn sample(
+        &self,
+        route: Option<&str>,
+        model: Option<&str>,
+        request_bytes: Option<u64>,
+        tier: SlaClass,
+        status: u16,
+        latency: Duration,
+    ) -> TelemetrySample {
+        TelemetrySample {
+            route: RouteLabel::from_matched(route),
+            model: ModelLabel::new(model, &self.config.models),
+            size: SizeBucket::from_bytes(request_bytes),
+            tier,
+            status: StatusClass::from_status(status),
+            latency: LatencyMs(latency.as_millis() as u64),
+        }
+    }
+
+    pub fn record(&self, sample: TelemetrySample) {
+        let key = SeriesKey {
+            route: sample.route,
+            model: sample.model,
+            size: sample.size,
+            tier: sample.tier.as_str(),
+        };
+        let mut series = self.series.lock().unwrap();
+        if !series.contains_key(&key) && series.len() >= self.config.max_series {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.sampled.fetch_add(1, Ordering::Relaxed);
+        let totals = series.entry(key).or_default();
+        totals.requests += 1;
+        match sample.status {
+            StatusClass::ClientError => totals.client_errors += 1,
+            StatusClass::ServerError => totals.server_errors += 1,
+            StatusClass::Success => {}
+        }
+        totals.latency_ms_total += sample.latency.0;
+        totals.latency_ms_max = totals.latency_ms_max.max(sample.latency.0);
+    }
+
+    pub fn report(&self) -> TelemetryReport {
+        let series = self
+            .series
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, totals)| TelemetrySeries {
+                route: key.route.clone(),
+                model: key.model.clone(),
+                size: key.size,
+                tier: key.tier,
+                requests: totals.requests,
+                client_errors: totals.client_errors,
+                server_errors: totals.server_errors,
+                avg_latency_ms: totals.latency_ms_total as f64 / totals.requests.max(1) as f64,
+                max_latency_ms: totals.latency_ms_max,
+            })
+            .collect();
+        TelemetryReport {
+            enabled: self.config.enabled,
+            sample_rate: self.config.sample_rate,
+            sampled: self.sampled.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            series,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_keep_only_bounded_structural_labels() {
+        let sampler = TelemetrySampler::new(TelemetrySamplingConfig {
+            enabled: true,
+            sample_rate: 1.0,
+            models: vec!["gpt-4".to_string()],
+            max_series: 2,
+        });
+        assert!(sampler.should_sample());
+
+        let sample = sampler.sample(
+            Some("/v1/chat/completions"),
+            Some("gpt-4"),
+            Some(20_000),
+            SlaClass::Gold,
+            200,
+            Duration::from_millis(40),
+        );
+        assert_eq!(sample.size, SizeBucket::Under256Kib);
+        sampler.record(sample);
+
+        // Unlisted models, including anything a client smuggles into the
+        // model field, are reported as "other"
+        let smuggled = sampler.sample(
+            Some("/v1/chat/completions"),
+            Some("my ssn is 078-05-1120"),
+            None,
+            SlaClass::Gold,
+            503,
+            Duration::from_millis(10),
+        );
+        assert_eq!(smuggled.model, ModelLabel("other".to_string()));
+        sampler.record(smuggled.clone());
+        sampler.record(smuggled);
+
+        // A third combination exceeds max_series
+        sampler.record(sampler.sample(
+            None,
+            None,
+            Some(10),
+            SlaClass::Bronze,
+            200,
+            Duration::from_millis(1),
+        ));
+
+        let report = sampler.report();
+        assert_eq!((report.sampled, report.dropped), (3, 1));
+        let other = report.series.iter().find(|s| s.model.0 == "other").unwrap();
+        assert_eq!((other.requests, other.server_errors), (2, 2));
+        assert_eq!(other.size, SizeBucket::Unknown);
+
+        let unlisted = ModelLabel::new(Some("claude-3.5:beta"), &[]);
+        assert_eq!(unlisted.0, "claude-3.5:beta");
+        assert_eq!(ModelLabel::new(Some(&"x".repeat(65)), &[]).0, "other");
+    }
+}
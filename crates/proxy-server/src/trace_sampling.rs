@@ -0,0 +1,437 @@
+//! Adaptive sampling of distributed traces
+//!
+//! A fixed sampling rate keeps a random slice of traffic and misses the
+//! requests worth looking at. Here server errors and requests slower than a
+//! threshold are always sampled. Other requests are sampled with a probability
+//! that adapts each minute: the configured rate, lowered so that about
+//! `budget_per_minute` requests are sampled given the previous minute's
+//! traffic, with the budget as a hard cap within the minute.
+//!
+//! Errors and latency are only known once a request is done, but the decision
+//! has to travel downstream while it runs. The head decision (the caller's
+//! sampled flag, or the probabilistic draw) goes to providers and federation
+//! peers in a W3C `traceparent` header; a request that then fails or runs slow
+//! is sampled here regardless. The final decision goes back to the client in
+//! the response's `traceparent`.
+
+use crate::config::TraceSamplingConfig;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Route label for requests beyond `max_routes`
+const OTHER_ROUTE: &str = "other";
+
+tokio::task_local! {
+    static CURRENT_TRACE: TraceContext;
+}
+
+/// Run `future` with `context` as the trace outgoing requests propagate
+pub async fn scope<F: std::future::Future>(context: TraceContext, future: F) -> F::Output {
+    CURRENT_TRACE.scope(context, future).await
+}
+
+/// `traceparent` for a downstream request made by the current request
+pub fn current_traceparent() -> Option<String> {
+    CURRENT_TRACE.try_with(TraceContext::traceparent).ok()
+}
+
+/// Headers carrying the current trace to a downstream request
+pub fn propagation_headers() -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(value) = current_traceparent().and_then(|v| v.parse().ok()) {
+        headers.insert(TRACEPARENT_HEADER, value);
+    }
+    headers
+}
+
+/// This proxy's span of a trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// 16 lowercase hex digits
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    /// Head decision, propagated downstream
+    pub sampled: bool,
+    /// Whether `sampled` was taken from the caller
+    pub inherited: bool,
+}
+
+impl TraceContext {
+    /// Trace ID, parent span ID and sampled flag of a `traceparent` header
+    pub fn parse(header: &str) -> Option<(String, String, bool)> {
+        let mut fields = header.trim().split('-');
+        let version = fields.next()?;
+        let trace_id = fields.next()?;
+        let parent_id = fields.next()?;
+        let flags = fields.next()?;
+        // Version 00 has exactly four fields; later versions may append more
+        if version == "ff" || (version == "00" && fields.next().is_some()) {
+            return None;
+        }
+        let is_hex = |s: &str, len: usize| {
+            s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        if !is_hex(version, 2) || !is_hex(trace_id, 32) || !is_hex(parent_id, 16) {
+            return None;
+        }
+        if !is_hex(flags, 2) || trace_id.bytes().all(|b| b == b'0') {
+            return None;
+        }
+        if parent_id.bytes().all(|b| b == b'0') {
+            return None;
+        }
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        Some((trace_id.to_string(), parent_id.to_string(), flags & 1 == 1))
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            self.trace_id, self.span_id, self.sampled as u8
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingReason {
+    Error,
+    Slow,
+    Parent,
+    Probabilistic,
+}
+
+/// A sampled request, kept for GET /v1/admin/traces
+#[derive(Debug, Clone, Serialize)]
+pub struct SampledSpan {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub route: String,
+    pub status: u16,
+    pub duration_ms: u64,
+    pub finished_at: i64,
+    pub reason: SamplingReason,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RouteCounters {
+    requests: u64,
+    errors: u64,
+    slow: u64,
+    parent: u64,
+    probabilistic: u64,
+}
+
+impl RouteCounters {
+    fn sampled(&self) -> u64 {
+        self.errors + self.slow + self.parent + self.probabilistic
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteSamplingStats {
+    pub route: String,
+    pub requests: u64,
+    pub sampled: u64,
+    pub sampled_errors: u64,
+    pub sampled_slow: u64,
+    pub sampled_by_parent: u64,
+    pub sampled_by_chance: u64,
+    /// Fraction of the route's requests sampled
+    pub effective_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceSamplingStats {
+    pub enabled: bool,
+    /// Chance an ordinary request is sampled this minute
+    pub probability: f64,
+    pub budget_per_minute: u64,
+    pub sampled_this_minute: u64,
+    pub routes: Vec<RouteSamplingStats>,
+}
+
+#[derive(Debug)]
+struct SamplerState {
+    /// Minutes since the epoch
+    minute: i64,
+    /// Requests this minute that were up for a probabilistic draw
+    candidates: u64,
+    drawn: u64,
+    probability: f64,
+    routes: BTreeMap<String, RouteCounters>,
+    spans: VecDeque<SampledSpan>,
+}
+
+#[derive(Debug)]
+pub struct AdaptiveSamplingStrategy {
+    config: TraceSamplingConfig,
+    /// Highest probability ordinary requests are sampled with
+    base_rate: f64,
+    state: Mutex<SamplerState>,
+}
+
+impl AdaptiveSamplingStrategy {
+    pub fn new(config: TraceSamplingConfig, base_rate: f64) -> Self {
+        Self {
+            config,
+            base_rate,
+            state: Mutex::new(SamplerState {
+                minute: 0,
+                candidates: 0,
+                drawn: 0,
+                probability: base_rate,
+                routes: BTreeMap::new(),
+                spans: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Open this proxy's span of a request, deciding whether it is sampled
+    /// before it runs. `draw` is a uniform number in `[0, 1)`.
+    pub fn start(&self, traceparent: Option<&str>, now: i64, draw: f64) -> TraceContext {
+        let parent = traceparent.and_then(TraceContext::parse);
+        let (trace_id, parent_span_id) = match &parent {
+            Some((trace_id, parent_id, _)) => (trace_id.clone(), Some(parent_id.clone())),
+            None => (format!("{:032x}", rand::random::<u128>().max(1)), None),
+        };
+        let span_id = format!("{:016x}", rand::random::<u64>().max(1));
+
+        if let Some((_, _, sampled)) = parent.filter(|_| self.config.respect_parent) {
+            return TraceContext {
+                trace_id,
+                span_id,
+                parent_span_id,
+                sampled,
+                inherited: true,
+            };
+        }
+
+        let mut state = self.state.lock().unwrap();
+        self.roll_over(&mut state, now);
+        state.candidates += 1;
+        let sampled = state.drawn < self.config.budget_per_minute && draw < state.probability;
+        if sampled {
+            state.drawn += 1;
+        }
+        TraceContext {
+            trace_id,
+            span_id,
+            parent_span_id,
+            sampled,
+            inherited: false,
+        }
+    }
+
+    /// Start a new minute, setting its probability from the last one's traffic
+    fn roll_over(&self, state: &mut SamplerState, now: i64) {
+        let minute = now.div_euclid(60);
+        if minute == state.minute {
+            return;
+        }
+        let previous = if minute == state.minute + 1 {
+            state.candidates
+        } else {
+            0
+        };
+        state.probability = if previous == 0 {
+            self.base_rate
+        } else {
+            (self.config.budget_per_minute as f64 / previous as f64).min(self.base_rate)
+        };
+        state.minute = minute;
+        state.candidates = 0;
+        state.drawn = 0;
+    }
+
+    /// Make the final decision for a finished request and count it under its
+    /// route; returns why it was sampled, if it was
+    pub fn finish(
+        &self,
+        context: &TraceContext,
+        route: Option<&str>,
+        status: u16,
+        latency: Duration,
+        now: i64,
+    ) -> Option<SamplingReason> {
+        let reason = if status >= 500 {
+            Some(SamplingReason::Error)
+        } else if latency >= Duration::from_millis(self.config.latency_threshold_ms) {
+            Some(SamplingReason::Slow)
+        } else if context.sampled && context.inherited {
+            Some(SamplingReason::Parent)
+        } else if context.sampled {
+            Some(SamplingReason::Probabilistic)
+        } else {
+            None
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let route = route.unwrap_or("unmatched");
+        let label =
+            if state.routes.contains_key(route) || state.routes.len() < self.config.max_routes {
+                route
+            } else {
+                OTHER_ROUTE
+            };
+        let counters = state.routes.entry(label.to_string()).or_default();
+        counters.requests += 1;
+        match reason {
+            Some(SamplingReason::Error) => counters.errors += 1,
+            Some(SamplingReason::Slow) => counters.slow += 1,
+            Some(SamplingReason::Parent) => counters.parent += 1,
+            Some(SamplingReason::Probabilistic) => counters.probabilistic += 1,
+            None => {}
+        }
+
+        if let Some(reason) = reason {
+            if state.spans.len() >= self.config.max_buffered_spans {
+                state.spans.pop_front();
+            }
+            state.spans.push_back(SampledSpan {
+                trace_id: context.trace_id.clone(),
+                span_id: context.span_id.clone(),
+                parent_span_id: context.parent_span_id.clone(),
+                route: label.to_string(),
+                status,
+                duration_ms: latency.as_millis() as u64,
+                finished_at: now,
+                reason,
+            });
+        }
+        reason
+    }
+
+    /// Most recent sampled spans, newest first
+    pub fn recent_spans(&self, limit: usize) -> Vec<SampledSpan> {
+        let state = self.state.lock().unwrap();
+        state.spans.iter().rev().take(limit).cloned().collect()
+    }
+
+    pub fn stats(&self) -> TraceSamplingStats {
+        let state = self.state.lock().unwrap();
+        let routes = state
+            .routes
+            .iter()
+            .map(|(route, counters)| RouteSamplingStats {
+                route: route.clone(),
+                requests: counters.requests,
+                sampled: counters.sampled(),
+                sampled_errors: counters.errors,
+                sampled_slow: counters.slow,
+                sampled_by_parent: counters.parent,
+                sampled_by_chance: counters.probabilistic,
+                effective_rate: counters.sampled() as f64 / counters.requests.max(1) as f64,
+            })
+            .collect();
+        TraceSamplingStats {
+            enabled: self.config.enabled,
+            probability: state.probability,
+            budget_per_minute: self.config.budget_per_minute,
+            sampled_this_minute: state.drawn,
+            routes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_errors_and_slow_requests_are_kept_and_the_rest_fit_the_budget() {
+        let parent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        assert_eq!(
+            TraceContext::parse(parent),
+            Some((
+                "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+                "00f067aa0ba902b7".to_string(),
+                true
+            ))
+        );
+        assert!(
+            TraceContext::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01")
+                .is_none()
+        );
+        assert!(
+            TraceContext::parse("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01")
+                .is_none()
+        );
+        assert!(TraceContext::parse(&format!("{}-extra", parent)).is_none());
+        assert!(TraceContext::parse(&format!("01{}-extra", &parent[2..])).is_some());
+
+        let sampler = AdaptiveSamplingStrategy::new(
+            TraceSamplingConfig {
+                budget_per_minute: 10,
+                latency_threshold_ms: 500,
+                ..TraceSamplingConfig::default()
+            },
+            0.5,
+        );
+        let fast = Duration::from_millis(20);
+
+        // The caller's decision is followed and carried downstream with a new span
+        let inherited = sampler.start(Some(parent), 0, 0.99);
+        assert!(inherited.sampled && inherited.inherited);
+        assert_eq!(
+            inherited.parent_span_id.as_deref(),
+            Some("00f067aa0ba902b7")
+        );
+        let downstream = inherited.traceparent();
+        assert!(downstream.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(downstream.ends_with("-01"));
+        assert_ne!(&downstream[36..52], "00f067aa0ba902b7");
+
+        // Errors and slow requests are kept even when the draw said no
+        let unsampled = sampler.start(None, 0, 0.99);
+        assert!(!unsampled.sampled);
+        assert!(unsampled.traceparent().ends_with("-00"));
+        let route = Some("/v1/chat/completions");
+        assert_eq!(
+            sampler.finish(&unsampled, route, 502, fast, 0),
+            Some(SamplingReason::Error)
+        );
+        assert_eq!(
+            sampler.finish(&unsampled, route, 200, Duration::from_secs(1), 0),
+            Some(SamplingReason::Slow)
+        );
+        assert_eq!(sampler.finish(&unsampled, route, 200, fast, 0), None);
+
+        // Within a minute the budget caps probabilistic samples
+        let drawn = (0..100)
+            .filter(|_| sampler.start(None, 30, 0.0).sampled)
+            .count();
+        assert_eq!(drawn, 10);
+        // The next minute lowers the chance to fit the budget to last minute's traffic
+        sampler.start(None, 60, 0.0);
+        assert!((sampler.stats().probability - 10.0 / 101.0).abs() < 1e-9);
+        // After an idle minute the configured rate applies again
+        sampler.start(None, 180, 0.0);
+        assert_eq!(sampler.stats().probability, 0.5);
+
+        let stats = sampler.stats();
+        let route = &stats.routes[0];
+        assert_eq!((route.requests, route.sampled), (3, 2));
+        assert_eq!((route.sampled_errors, route.sampled_slow), (1, 1));
+        assert!((route.effective_rate - 2.0 / 3.0).abs() < 1e-9);
+        let spans = sampler.recent_spans(10);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].reason, SamplingReason::Slow);
+
+        // Downstream requests made while serving carry the trace
+        let headers = scope(inherited.clone(), async { propagation_headers() }).await;
+        assert_eq!(headers[TRACEPARENT_HEADER], downstream.as_str());
+        assert!(propagation_headers().is_empty());
+    }
+}
@@ -0,0 +1,397 @@
+//! Transactional multi-step FHE requests
+//!
+//! A transaction is a graph of steps, each naming the steps it consumes, run
+//! server-side as one unit. The plan is checked before anything runs (unique
+//! ids, known references, no cycles, every step feeding an output), then the
+//! steps run in dependency order on working copies. Nothing is written while
+//! they run: the proxy stores the requested outputs only once every step has
+//! succeeded, and intermediate results are dropped with the transaction.
+
+use crate::config::{FheOperation, TransactionConfig};
+use crate::error::{Error, Result};
+use crate::fhe::{Ciphertext, EngineError, FheEngine};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TransactionStep {
+    /// A ciphertext already held by the proxy
+    Load {
+        ciphertext_id: Uuid,
+    },
+    Encrypt {
+        client_id: Uuid,
+        text: String,
+    },
+    Concatenate {
+        a: String,
+        b: String,
+    },
+    Sum {
+        inputs: Vec<String>,
+    },
+    MultiplyPlain {
+        input: String,
+        scalar: f64,
+    },
+    Process {
+        input: String,
+    },
+}
+
+impl TransactionStep {
+    fn inputs(&self) -> Vec<&str> {
+        match self {
+            TransactionStep::Load { .. } | TransactionStep::Encrypt { .. } => Vec::new(),
+            TransactionStep::Concatenate { a, b } => vec![a, b],
+            TransactionStep::Sum { inputs } => inputs.iter().map(String::as_str).collect(),
+            TransactionStep::MultiplyPlain { input, .. } | TransactionStep::Process { input } => {
+                vec![input]
+            }
+        }
+    }
+
+    /// Operation type the step runs, for execution targeting
+    pub fn operation(&self) -> Option<FheOperation> {
+        match self {
+            TransactionStep::Load { .. } => None,
+            TransactionStep::Encrypt { .. } => Some(FheOperation::Encrypt),
+            TransactionStep::Concatenate { .. }
+            | TransactionStep::Sum { .. }
+            | TransactionStep::MultiplyPlain { .. } => Some(FheOperation::Aggregate),
+            TransactionStep::Process { .. } => Some(FheOperation::Process),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            TransactionStep::Load { .. } => "load",
+            TransactionStep::Encrypt { .. } => "encrypt",
+            TransactionStep::Concatenate { .. } => "concatenate",
+            TransactionStep::Sum { .. } => "sum",
+            TransactionStep::MultiplyPlain { .. } => "multiply_plain",
+            TransactionStep::Process { .. } => "process",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionNode {
+    pub id: String,
+    #[serde(flatten)]
+    pub step: TransactionStep,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransactionRequest {
+    pub steps: Vec<TransactionNode>,
+    /// Steps whose results are stored and returned
+    pub outputs: Vec<String>,
+}
+
+/// A checked transaction, with its steps in the order they run
+#[derive(Debug, Clone)]
+pub struct TransactionPlan {
+    order: Vec<TransactionNode>,
+    outputs: Vec<String>,
+}
+
+/// What a committed transaction produced
+#[derive(Debug, Clone)]
+pub struct TransactionOutcome {
+    /// Output step id and its result, in the order the outputs were requested
+    pub outputs: Vec<(String, Ciphertext)>,
+    /// Steps run per kind
+    pub steps: BTreeMap<&'static str, usize>,
+}
+
+impl TransactionPlan {
+    pub fn new(request: TransactionRequest, config: &TransactionConfig) -> Result<Self> {
+        let invalid = |message: String| Err(Error::Validation(message));
+        if request.steps.is_empty() || request.steps.len() > config.max_steps {
+            return invalid(format!(
+                "A transaction needs 1 to {} steps",
+                config.max_steps
+            ));
+        }
+        if request.outputs.is_empty() || request.outputs.len() > config.max_outputs {
+            return invalid(format!(
+                "A transaction needs 1 to {} outputs",
+                config.max_outputs
+            ));
+        }
+
+        let mut nodes: HashMap<&str, &TransactionNode> = HashMap::new();
+        for node in &request.steps {
+            if node.id.is_empty() || nodes.insert(&node.id, node).is_some() {
+                return invalid(format!(
+                    "Step ids must be unique and non-empty: {:?}",
+                    node.id
+                ));
+            }
+        }
+        for node in &request.steps {
+            let inputs = node.step.inputs();
+            if matches!(node.step, TransactionStep::Sum { .. }) && inputs.is_empty() {
+                return invalid(format!("Step {} sums nothing", node.id));
+            }
+            if let Some(missing) = inputs.iter().find(|input| !nodes.contains_key(*input)) {
+                return invalid(format!("Step {} uses unknown step {}", node.id, missing));
+            }
+        }
+        let outputs: BTreeSet<&str> = request.outputs.iter().map(String::as_str).collect();
+        if outputs.len() != request.outputs.len() {
+            return invalid("Outputs must not repeat".to_string());
+        }
+        if let Some(missing) = outputs.iter().find(|output| !nodes.contains_key(*output)) {
+            return invalid(format!("Output {} is not a step", missing));
+        }
+
+        // Every step must feed an output; anything else is wasted work
+        let mut needed: BTreeSet<&str> = BTreeSet::new();
+        let mut pending: Vec<&str> = outputs.iter().copied().collect();
+        while let Some(id) = pending.pop() {
+            if needed.insert(id) {
+                pending.extend(nodes[id].step.inputs());
+            }
+        }
+        if let Some(unused) = request
+            .steps
+            .iter()
+            .find(|n| !needed.contains(n.id.as_str()))
+        {
+            return invalid(format!("Step {} does not lead to an output", unused.id));
+        }
+
+        // Kahn's algorithm; whatever is left over sits on a cycle
+        let mut remaining: HashMap<&str, usize> = request
+            .steps
+            .iter()
+            .map(|n| (n.id.as_str(), n.step.inputs().len()))
+            .collect();
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for node in &request.steps {
+            for input in node.step.inputs() {
+                dependents.entry(input).or_default().push(&node.id);
+            }
+        }
+        let mut ready: VecDeque<&str> = request
+            .steps
+            .iter()
+            .filter(|n| n.step.inputs().is_empty())
+            .map(|n| n.id.as_str())
+            .collect();
+        let mut order = Vec::with_capacity(request.steps.len());
+        while let Some(id) = ready.pop_front() {
+            order.push(nodes[id].clone());
+            for dependent in dependents.get(id).into_iter().flatten() {
+                let count = remaining.get_mut(dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(dependent);
+                }
+            }
+        }
+        if order.len() != request.steps.len() {
+            return invalid("Steps form a cycle".to_string());
+        }
+
+        Ok(Self {
+            order,
+            outputs: request.outputs,
+        })
+    }
+
+    /// Operation types the plan runs, each once
+    pub fn operations(&self) -> BTreeSet<FheOperation> {
+        self.order
+            .iter()
+            .filter_map(|node| node.step.operation())
+            .collect()
+    }
+
+    /// Ciphertexts the plan loads from the proxy
+    pub fn loads(&self) -> Vec<Uuid> {
+        self.order
+            .iter()
+            .filter_map(|node| match node.step {
+                TransactionStep::Load { ciphertext_id } => Some(ciphertext_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Client keys the plan encrypts with
+    pub fn encryption_keys(&self) -> Vec<Uuid> {
+        self.order
+            .iter()
+            .filter_map(|node| match node.step {
+                TransactionStep::Encrypt { client_id, .. } => Some(client_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Run every step; on any failure nothing is returned. `loaded` holds the
+    /// ciphertexts named by [`Self::loads`].
+    pub fn execute(
+        &self,
+        engine: &FheEngine,
+        loaded: &HashMap<Uuid, Ciphertext>,
+    ) -> Result<TransactionOutcome> {
+        let mut results: HashMap<&str, Ciphertext> = HashMap::new();
+        let mut steps = BTreeMap::new();
+        for node in &self.order {
+            let input = |id: &String| results[id.as_str()].clone();
+            let result = match &node.step {
+                TransactionStep::Load { ciphertext_id } => {
+                    loaded.get(ciphertext_id).cloned().ok_or_else(|| {
+                        EngineError::Validation(format!("Ciphertext {} not found", ciphertext_id))
+                    })
+                }
+                TransactionStep::Encrypt { client_id, text } => {
+                    engine.encrypt_text(*client_id, text)
+                }
+                TransactionStep::Concatenate { a, b } => {
+                    engine.concatenate_encrypted(&input(a), &input(b))
+                }
+                TransactionStep::Sum { inputs } => {
+                    engine.sum_encrypted(&inputs.iter().map(input).collect::<Vec<_>>())
+                }
+                TransactionStep::MultiplyPlain {
+                    input: source,
+                    scalar,
+                } => engine.multiply_plain(&input(source), *scalar),
+                TransactionStep::Process { input: source } => {
+                    engine.process_encrypted_prompt(&input(source))
+                }
+            }
+            .map_err(|e| {
+                Error::Fhe(format!(
+                    "Step {} ({}) failed: {}",
+                    node.id,
+                    node.step.name(),
+                    e
+                ))
+            })?;
+            *steps.entry(node.step.name()).or_default() += 1;
+            results.insert(&node.id, result);
+        }
+
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|id| {
+                let mut ciphertext = results.remove(id.as_str()).unwrap();
+                // A loaded input returned as-is still gets an id of its own
+                ciphertext.id = Uuid::new_v4();
+                (id.clone(), ciphertext)
+            })
+            .collect();
+        Ok(TransactionOutcome { outputs, steps })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe::FheParams;
+
+    fn node(id: &str, step: TransactionStep) -> TransactionNode {
+        TransactionNode {
+            id: id.to_string(),
+            step,
+        }
+    }
+
+    fn plan(steps: Vec<TransactionNode>, outputs: &[&str]) -> Result<TransactionPlan> {
+        TransactionPlan::new(
+            TransactionRequest {
+                steps,
+                outputs: outputs.iter().map(|o| o.to_string()).collect(),
+            },
+            &TransactionConfig::default(),
+        )
+    }
+
+    #[test]
+    fn test_plans_are_checked_and_run_all_or_nothing() {
+        let mut engine = FheEngine::new(FheParams::default()).unwrap();
+        let (client_id, _) = engine.generate_keys().unwrap();
+        let encrypt = |id: &str, text: &str| {
+            node(
+                id,
+                TransactionStep::Encrypt {
+                    client_id,
+                    text: text.to_string(),
+                },
+            )
+        };
+        let concatenate = |id: &str, a: &str, b: &str| {
+            node(
+                id,
+                TransactionStep::Concatenate {
+                    a: a.to_string(),
+                    b: b.to_string(),
+                },
+            )
+        };
+        let process = |id: &str, input: &str| {
+            node(
+                id,
+                TransactionStep::Process {
+                    input: input.to_string(),
+                },
+            )
+        };
+
+        // Cycles, dangling references and steps that lead nowhere are refused
+        assert!(plan(vec![concatenate("a", "b", "b"), process("b", "a")], &["b"]).is_err());
+        assert!(plan(vec![process("p", "missing")], &["p"]).is_err());
+        assert!(plan(vec![encrypt("x", "hi"), encrypt("y", "there")], &["x"]).is_err());
+
+        // Steps listed out of order still run after their inputs
+        let transaction = plan(
+            vec![
+                process("answer", "prompt"),
+                concatenate("prompt", "greeting", "question"),
+                encrypt("greeting", "Hello. "),
+                encrypt("question", "What is FHE?"),
+            ],
+            &["answer"],
+        )
+        .unwrap();
+        assert_eq!(
+            transaction.operations(),
+            BTreeSet::from([
+                FheOperation::Encrypt,
+                FheOperation::Aggregate,
+                FheOperation::Process
+            ])
+        );
+        let outcome = transaction.execute(&engine, &HashMap::new()).unwrap();
+        assert_eq!(outcome.outputs.len(), 1);
+        assert_eq!(outcome.outputs[0].0, "answer");
+        assert_eq!(outcome.steps["encrypt"], 2);
+
+        // A failing step fails the whole transaction
+        let failing = plan(
+            vec![
+                encrypt("text", "not a number"),
+                node(
+                    "scaled",
+                    TransactionStep::MultiplyPlain {
+                        input: "text".to_string(),
+                        scalar: f64::NAN,
+                    },
+                ),
+            ],
+            &["scaled"],
+        )
+        .unwrap();
+        let error = failing.execute(&engine, &HashMap::new()).unwrap_err();
+        assert!(error.to_string().contains("scaled"), "{}", error);
+    }
+}
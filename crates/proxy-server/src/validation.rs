@@ -0,0 +1,1266 @@
+//! Enhanced validation and input sanitization for FHE operations
+//!
+//! Encrypted completion requests pass through a [`ValidatorChain`]: built-in
+//! validators and operator-defined ones run in configured order, the first
+//! rejection wins, and each validator's timing and rejections are counted.
+
+use crate::config::{
+    CustomValidatorConfig, CustomValidatorKind, ReplayCacheConfig, ReplayCacheKind,
+    ValidationPipelineConfig,
+};
+use crate::error::{Error, Result};
+use crate::fhe::FheParams;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Comprehensive input validation framework
+pub struct ValidationFramework {
+    rules: HashMap<String, ValidationRule>,
+    max_input_size: usize,
+    allowed_patterns: Vec<regex::Regex>,
+    blocked_patterns: Vec<regex::Regex>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationRule {
+    pub field_name: String,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub required: bool,
+    pub pattern: Option<String>,
+    pub custom_validator: Option<fn(&str) -> bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub is_valid: bool,
+    pub errors: Vec<ValidationError>,
+    pub warnings: Vec<String>,
+    pub sanitized_input: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub error_type: String,
+    pub message: String,
+    pub severity: ErrorSeverity,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ErrorSeverity {
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Default for ValidationFramework {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValidationFramework {
+    pub fn new() -> Self {
+        Self {
+            rules: HashMap::new(),
+            max_input_size: 100_000, // 100KB max input
+            allowed_patterns: vec![],
+            blocked_patterns: vec![],
+        }
+    }
+
+    pub fn with_fhe_defaults() -> Result<Self> {
+        let mut framework = Self::new();
+
+        // Add default FHE validation rules
+        framework.add_rule(ValidationRule {
+            field_name: "plaintext".to_string(),
+            min_length: Some(1),
+            max_length: Some(10_000),
+            required: true,
+            pattern: None,
+            custom_validator: Some(validate_fhe_plaintext),
+        });
+
+        framework.add_rule(ValidationRule {
+            field_name: "client_id".to_string(),
+            min_length: Some(36),
+            max_length: Some(36),
+            required: true,
+            pattern: Some(
+                r"^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$"
+                    .to_string(),
+            ),
+            custom_validator: None,
+        });
+
+        Ok(framework)
+    }
+
+    pub fn add_rule(&mut self, rule: ValidationRule) {
+        self.rules.insert(rule.field_name.clone(), rule);
+    }
+
+    /// Comprehensive input validation with detailed reporting
+    pub fn validate_input(&self, field: &str, input: &str) -> ValidationReport {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        // Check if field has validation rules
+        let rule = match self.rules.get(field) {
+            Some(rule) => rule,
+            None => {
+                warnings.push(format!("No validation rules found for field: {}", field));
+                return ValidationReport {
+                    is_valid: true,
+                    errors,
+                    warnings,
+                    sanitized_input: Some(input.to_string()),
+                };
+            }
+        };
+
+        // Required field check
+        if rule.required && input.is_empty() {
+            errors.push(ValidationError {
+                field: field.to_string(),
+                error_type: "required".to_string(),
+                message: format!("Field '{}' is required", field),
+                severity: ErrorSeverity::Error,
+            });
+        }
+
+        // Length validation
+        if let Some(min_len) = rule.min_length {
+            if input.len() < min_len {
+                errors.push(ValidationError {
+                    field: field.to_string(),
+                    error_type: "min_length".to_string(),
+                    message: format!("Field '{}' must be at least {} characters", field, min_len),
+                    severity: ErrorSeverity::Error,
+                });
+            }
+        }
+
+        if let Some(max_len) = rule.max_length {
+            if input.len() > max_len {
+                errors.push(ValidationError {
+                    field: field.to_string(),
+                    error_type: "max_length".to_string(),
+                    message: format!("Field '{}' must not exceed {} characters", field, max_len),
+                    severity: ErrorSeverity::Error,
+                });
+            }
+        }
+
+        // Pattern validation
+        if let Some(pattern) = &rule.pattern {
+            match regex::Regex::new(pattern) {
+                Ok(regex) => {
+                    if !regex.is_match(input) {
+                        errors.push(ValidationError {
+                            field: field.to_string(),
+                            error_type: "pattern".to_string(),
+                            message: format!("Field '{}' does not match required pattern", field),
+                            severity: ErrorSeverity::Error,
+                        });
+                    }
+                }
+                Err(e) => {
+                    warnings.push(format!(
+                        "Invalid regex pattern for field '{}': {}",
+                        field, e
+                    ));
+                }
+            }
+        }
+
+        // Custom validator
+        if let Some(validator) = rule.custom_validator {
+            if !validator(input) {
+                errors.push(ValidationError {
+                    field: field.to_string(),
+                    error_type: "custom".to_string(),
+                    message: format!("Field '{}' failed custom validation", field),
+                    severity: ErrorSeverity::Error,
+                });
+            }
+        }
+
+        // Security checks - blocked patterns
+        for blocked_pattern in &self.blocked_patterns {
+            if blocked_pattern.is_match(input) {
+                errors.push(ValidationError {
+                    field: field.to_string(),
+                    error_type: "security".to_string(),
+                    message: format!("Field '{}' contains blocked content", field),
+                    severity: ErrorSeverity::Critical,
+                });
+            }
+        }
+
+        // Sanitize input
+        let sanitized_input = if errors.is_empty() {
+            Some(self.sanitize_input(input))
+        } else {
+            None
+        };
+
+        ValidationReport {
+            is_valid: errors.is_empty(),
+            errors,
+            warnings,
+            sanitized_input,
+        }
+    }
+
+    /// Sanitize input by removing/replacing dangerous characters
+    fn sanitize_input(&self, input: &str) -> String {
+        input
+            .chars()
+            .filter(|c| {
+                c.is_ascii()
+                && (!c.is_control() || c.is_whitespace())
+                && *c != '\0' // Remove null bytes
+                && !matches!(*c, '\x01'..='\x08' | '\x0B'..='\x0C' | '\x0E'..='\x1F' | '\x7F')
+            })
+            .collect()
+    }
+
+    /// Validate FHE parameters
+    pub fn validate_fhe_params(&self, params: &FheParams) -> ValidationReport {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        // Validate poly modulus degree
+        if params.poly_modulus_degree == 0 {
+            errors.push(ValidationError {
+                field: "poly_modulus_degree".to_string(),
+                error_type: "invalid_value".to_string(),
+                message: "Polynomial modulus degree cannot be zero".to_string(),
+                severity: ErrorSeverity::Error,
+            });
+        }
+
+        if !params.poly_modulus_degree.is_power_of_two() {
+            warnings.push(
+                "Polynomial modulus degree should be a power of two for optimal performance"
+                    .to_string(),
+            );
+        }
+
+        // Validate security level
+        if params.security_level < 80 {
+            errors.push(ValidationError {
+                field: "security_level".to_string(),
+                error_type: "insecure".to_string(),
+                message: format!(
+                    "Security level {} is too low (minimum 80 bits)",
+                    params.security_level
+                ),
+                severity: ErrorSeverity::Critical,
+            });
+        }
+
+        if params.security_level > 192 {
+            warnings.push(format!(
+                "Security level {} may impact performance significantly",
+                params.security_level
+            ));
+        }
+
+        // Validate coefficient modulus
+        if params.coeff_modulus_bits.is_empty() {
+            errors.push(ValidationError {
+                field: "coeff_modulus_bits".to_string(),
+                error_type: "required".to_string(),
+                message: "Coefficient modulus bits cannot be empty".to_string(),
+                severity: ErrorSeverity::Error,
+            });
+        }
+
+        ValidationReport {
+            is_valid: errors.is_empty(),
+            errors,
+            warnings,
+            sanitized_input: None,
+        }
+    }
+
+    /// Validate base64 encoded ciphertext
+    pub fn validate_ciphertext_data(&self, data: &str) -> ValidationReport {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        // Check for empty input
+        if data.is_empty() {
+            errors.push(ValidationError {
+                field: "ciphertext_data".to_string(),
+                error_type: "empty_input".to_string(),
+                message: "Ciphertext data cannot be empty".to_string(),
+                severity: ErrorSeverity::Error,
+            });
+            return ValidationReport {
+                is_valid: false,
+                errors,
+                warnings,
+                sanitized_input: None,
+            };
+        }
+
+        // Check if it's valid base64
+        match base64::prelude::BASE64_STANDARD.decode(data) {
+            Ok(decoded) => {
+                if decoded.len() < 32 {
+                    warnings.push("Ciphertext data seems unusually small".to_string());
+                }
+                if decoded.len() > 10_000_000 {
+                    // 10MB limit
+                    errors.push(ValidationError {
+                        field: "ciphertext_data".to_string(),
+                        error_type: "too_large".to_string(),
+                        message: "Ciphertext data exceeds maximum size (10MB)".to_string(),
+                        severity: ErrorSeverity::Error,
+                    });
+                }
+            }
+            Err(e) => {
+                errors.push(ValidationError {
+                    field: "ciphertext_data".to_string(),
+                    error_type: "invalid_encoding".to_string(),
+                    message: format!("Invalid base64 encoding: {}", e),
+                    severity: ErrorSeverity::Error,
+                });
+            }
+        }
+
+        ValidationReport {
+            is_valid: errors.is_empty(),
+            errors,
+            warnings,
+            sanitized_input: None,
+        }
+    }
+
+    /// Validate UUID string
+    pub fn validate_uuid(&self, uuid_str: &str) -> ValidationReport {
+        let mut errors = Vec::new();
+
+        match Uuid::parse_str(uuid_str) {
+            Ok(_) => ValidationReport {
+                is_valid: true,
+                errors,
+                warnings: vec![],
+                sanitized_input: Some(uuid_str.to_lowercase()),
+            },
+            Err(e) => {
+                errors.push(ValidationError {
+                    field: "uuid".to_string(),
+                    error_type: "invalid_format".to_string(),
+                    message: format!("Invalid UUID format: {}", e),
+                    severity: ErrorSeverity::Error,
+                });
+
+                ValidationReport {
+                    is_valid: false,
+                    errors,
+                    warnings: vec![],
+                    sanitized_input: None,
+                }
+            }
+        }
+    }
+
+    /// Detect potentially malicious patterns in input
+    pub fn detect_security_threats(&self, input: &str) -> Vec<String> {
+        let mut threats = Vec::new();
+        let lower_input = input.to_lowercase();
+
+        // SQL injection patterns
+        let sql_patterns = [
+            "select ",
+            "union ",
+            "insert ",
+            "delete ",
+            "drop ",
+            "exec ",
+            "script",
+            "alter ",
+            "create ",
+            "truncate ",
+            "grant ",
+            "revoke ",
+        ];
+
+        for pattern in &sql_patterns {
+            if lower_input.contains(pattern) {
+                threats.push(format!("Potential SQL injection: {}", pattern));
+            }
+        }
+
+        // XSS patterns
+        let xss_patterns = [
+            "<script",
+            "javascript:",
+            "onload=",
+            "onerror=",
+            "onclick=",
+            "onmouseover=",
+            "onfocus=",
+            "onblur=",
+            "onchange=",
+            "onsubmit=",
+        ];
+
+        for pattern in &xss_patterns {
+            if lower_input.contains(pattern) {
+                threats.push(format!("Potential XSS: {}", pattern));
+            }
+        }
+
+        // Command injection patterns
+        let cmd_patterns = ["; ", "| ", "& ", "$(", "`", "exec(", "system(", "eval("];
+
+        for pattern in &cmd_patterns {
+            if lower_input.contains(pattern) {
+                threats.push(format!("Potential command injection: {}", pattern));
+            }
+        }
+
+        // Path traversal patterns
+        let path_patterns = ["../", "..\\", "/etc/", "/proc/", "/sys/", "c:\\"];
+
+        for pattern in &path_patterns {
+            if lower_input.contains(pattern) {
+                threats.push(format!("Potential path traversal: {}", pattern));
+            }
+        }
+
+        threats
+    }
+}
+
+/// Custom validator for FHE plaintext
+fn validate_fhe_plaintext(input: &str) -> bool {
+    // Check for valid UTF-8
+    if !input.is_ascii() && std::str::from_utf8(input.as_bytes()).is_err() {
+        return false;
+    }
+
+    // Check for reasonable content
+    let char_count = input.chars().count();
+    let byte_count = input.len();
+
+    // Detect potential binary data disguised as text
+    if byte_count > char_count * 4 {
+        return false; // Likely binary data
+    }
+
+    // Check for excessive whitespace (potential padding attack)
+    let whitespace_ratio =
+        input.chars().filter(|c| c.is_whitespace()).count() as f64 / char_count as f64;
+    if whitespace_ratio > 0.8 {
+        return false;
+    }
+
+    true
+}
+
+/// Batch validation for multiple inputs
+pub struct BatchValidator {
+    framework: ValidationFramework,
+    max_batch_size: usize,
+}
+
+impl BatchValidator {
+    pub fn new(framework: ValidationFramework) -> Self {
+        Self {
+            framework,
+            max_batch_size: 100,
+        }
+    }
+
+    pub fn validate_batch(&self, inputs: Vec<(String, String)>) -> Result<Vec<ValidationReport>> {
+        if inputs.len() > self.max_batch_size {
+            return Err(Error::Validation(format!(
+                "Batch size {} exceeds limit {}",
+                inputs.len(),
+                self.max_batch_size
+            )));
+        }
+
+        let mut results = Vec::with_capacity(inputs.len());
+
+        for (field, value) in inputs {
+            let report = self.framework.validate_input(&field, &value);
+            results.push(report);
+        }
+
+        Ok(results)
+    }
+}
+
+/// The parts of an encrypted completion request validators may inspect
+#[derive(Debug, Clone, Copy)]
+pub struct RequestContext<'a> {
+    pub tenant: Option<&'a str>,
+    pub provider: &'a str,
+    pub model: &'a str,
+    pub template_id: Option<&'a str>,
+    pub ciphertext_bytes: usize,
+    /// Parameter fingerprint the client encrypted under, if it sent one
+    pub params_hash: Option<&'a str>,
+    /// Fingerprint of the server's parameter profile
+    pub server_params_hash: &'a str,
+    /// Client-chosen `x-request-nonce`, for replay protection
+    pub nonce: Option<&'a str>,
+}
+
+/// Why a request was refused, and with which HTTP status
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rejection {
+    /// Filled in by the chain with the rejecting validator's name
+    pub validator: String,
+    pub status: u16,
+    pub reason: String,
+}
+
+impl Rejection {
+    pub fn new(status: u16, reason: impl Into<String>) -> Self {
+        Self {
+            validator: String::new(),
+            status,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// One stage of the request validation pipeline
+pub trait RequestValidator: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &str;
+    fn validate(&self, request: &RequestContext<'_>) -> std::result::Result<(), Rejection>;
+
+    /// Validator-specific state reported next to the chain's timing stats
+    fn details(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Widen any time window by `extension` to absorb measured clock skew
+    fn adjust_for_clock_skew(&self, _extension: Duration) {}
+}
+
+/// Ciphertexts larger than the configured limit
+#[derive(Debug)]
+pub struct SizeValidator {
+    pub max_bytes: usize,
+}
+
+impl RequestValidator for SizeValidator {
+    fn name(&self) -> &str {
+        "size"
+    }
+
+    fn validate(&self, request: &RequestContext<'_>) -> std::result::Result<(), Rejection> {
+        if request.ciphertext_bytes > self.max_bytes {
+            return Err(Rejection::new(
+                413,
+                format!(
+                    "Ciphertext is {} bytes, the limit is {}",
+                    request.ciphertext_bytes, self.max_bytes
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Required fields must be present and shaped like identifiers
+#[derive(Debug)]
+pub struct SchemaValidator;
+
+impl RequestValidator for SchemaValidator {
+    fn name(&self) -> &str {
+        "schema"
+    }
+
+    fn validate(&self, request: &RequestContext<'_>) -> std::result::Result<(), Rejection> {
+        if request.provider.is_empty() || request.model.is_empty() {
+            return Err(Rejection::new(400, "Provider and model are required"));
+        }
+        let identifier = |value: &str| {
+            value.len() <= 128
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '/'))
+        };
+        if !identifier(request.model) || !request.template_id.is_none_or(identifier) {
+            return Err(Rejection::new(400, "Model or template ID is malformed"));
+        }
+        Ok(())
+    }
+}
+
+/// Provider allowlist
+#[derive(Debug)]
+pub struct PolicyValidator {
+    pub allowed_providers: Vec<String>,
+}
+
+impl RequestValidator for PolicyValidator {
+    fn name(&self) -> &str {
+        "policy"
+    }
+
+    fn validate(&self, request: &RequestContext<'_>) -> std::result::Result<(), Rejection> {
+        if !self.allowed_providers.iter().any(|p| p == request.provider) {
+            return Err(Rejection::new(403, "Provider is not on the allowlist"));
+        }
+        Ok(())
+    }
+}
+
+/// The client's negotiated parameter profile must still be the server's
+#[derive(Debug)]
+pub struct ParamsHashValidator;
+
+impl RequestValidator for ParamsHashValidator {
+    fn name(&self) -> &str {
+        "params_hash"
+    }
+
+    fn validate(&self, request: &RequestContext<'_>) -> std::result::Result<(), Rejection> {
+        match request.params_hash {
+            Some(hash) if hash != request.server_params_hash => Err(Rejection::new(
+                409,
+                format!(
+                    "Encrypted under parameters {} the server does not use",
+                    hash
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Rejects a nonce the same tenant already used within the window. Requests
+/// without a nonce are not checked.
+#[derive(Debug)]
+pub struct ReplayValidator {
+    window: Duration,
+    /// Extra time nonces are remembered for while the host clock is skewed
+    extension_ms: AtomicU64,
+    store: Mutex<NonceStore>,
+}
+
+#[derive(Debug)]
+enum NonceStore {
+    Exact(HashMap<String, Instant>),
+    Bloom(RotatingBloomFilter),
+}
+
+/// Memory the replay cache uses, against what an exact set would need
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayCacheStats {
+    pub kind: ReplayCacheKind,
+    /// Nonces remembered; for Bloom filters, those inserted into live filters
+    pub nonces: u64,
+    pub memory_bytes: u64,
+    /// Estimated size of an exact set holding the same nonces
+    pub exact_memory_bytes: u64,
+    /// Chance a fresh nonce is refused at the current fill; 0 for the exact set
+    pub estimated_false_positive_rate: f64,
+}
+
+/// Bytes an exact set spends on one entry besides the key itself
+const EXACT_ENTRY_OVERHEAD: usize = std::mem::size_of::<(String, Instant)>() + 1;
+
+impl ReplayValidator {
+    pub fn with_cache(window: Duration, config: &ReplayCacheConfig) -> Self {
+        let store = match config.kind {
+            ReplayCacheKind::Exact => NonceStore::Exact(HashMap::new()),
+            ReplayCacheKind::Bloom => NonceStore::Bloom(RotatingBloomFilter::new(window, config)),
+        };
+        Self {
+            window,
+            extension_ms: AtomicU64::new(0),
+            store: Mutex::new(store),
+        }
+    }
+
+    /// Record `key`, returning whether it was already seen in the window
+    fn check_and_insert(&self, key: String, now_ms: u64) -> bool {
+        let extension_ms = self.extension_ms.load(Ordering::Relaxed);
+        match &mut *self.store.lock().unwrap() {
+            NonceStore::Exact(seen) => {
+                let now = Instant::now();
+                let window = self.window + Duration::from_millis(extension_ms);
+                seen.retain(|_, at| now.duration_since(*at) < window);
+                seen.insert(key, now).is_some()
+            }
+            NonceStore::Bloom(filter) => filter.check_and_insert(&key, now_ms, extension_ms),
+        }
+    }
+
+    pub fn cache_stats(&self) -> ReplayCacheStats {
+        match &*self.store.lock().unwrap() {
+            NonceStore::Exact(seen) => {
+                let key_bytes: usize = seen.keys().map(String::capacity).sum();
+                let memory = seen.capacity() * EXACT_ENTRY_OVERHEAD + key_bytes;
+                ReplayCacheStats {
+                    kind: ReplayCacheKind::Exact,
+                    nonces: seen.len() as u64,
+                    memory_bytes: memory as u64,
+                    exact_memory_bytes: memory as u64,
+                    estimated_false_positive_rate: 0.0,
+                }
+            }
+            NonceStore::Bloom(filter) => filter.stats(),
+        }
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+impl RequestValidator for ReplayValidator {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    fn validate(&self, request: &RequestContext<'_>) -> std::result::Result<(), Rejection> {
+        let Some(nonce) = request.nonce else {
+            return Ok(());
+        };
+        let key = format!("{}:{}", request.tenant.unwrap_or_default(), nonce);
+        if self.check_and_insert(key, unix_millis()) {
+            return Err(Rejection::new(409, "Request nonce was already used"));
+        }
+        Ok(())
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self.cache_stats()).ok()
+    }
+
+    fn adjust_for_clock_skew(&self, extension: Duration) {
+        self.extension_ms
+            .store(extension.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Fixed-size Bloom filter using double hashing
+#[derive(Debug)]
+struct BloomFilter {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hashes: u32,
+    inserted: u64,
+    key_bytes: u64,
+}
+
+impl BloomFilter {
+    /// Sized to hold `items` at false positive rate `rate`
+    fn with_rate(items: usize, rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let n = items.max(1) as f64;
+        let bit_count = (-(n * rate.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let hashes = ((bit_count as f64 / n) * ln2).round().clamp(1.0, 32.0) as u32;
+        Self {
+            bits: vec![0; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hashes,
+            inserted: 0,
+            key_bytes: 0,
+        }
+    }
+
+    fn positions(&self, (h1, h2): (u64, u64)) -> impl Iterator<Item = u64> + '_ {
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.bit_count)
+    }
+
+    fn contains(&self, hashes: (u64, u64)) -> bool {
+        self.positions(hashes)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    fn insert(&mut self, hashes: (u64, u64), key_len: usize) {
+        let positions: Vec<u64> = self.positions(hashes).collect();
+        for bit in positions {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.inserted += 1;
+        self.key_bytes += key_len as u64;
+    }
+
+    fn false_positive_rate(&self) -> f64 {
+        let k = self.hashes as f64;
+        (1.0 - (-k * self.inserted as f64 / self.bit_count as f64).exp()).powf(k)
+    }
+}
+
+/// Bloom filters covering consecutive time buckets of the replay window
+///
+/// Buckets are aligned to wall-clock multiples of `window / buckets`, so every
+/// replica rotates at the same instants. New nonces go into the current
+/// bucket's filter and lookups check all live filters; one extra filter is
+/// kept so a nonce is always remembered for at least the full window, plus
+/// enough to cover any clock skew extension.
+#[derive(Debug)]
+struct RotatingBloomFilter {
+    bucket_ms: u64,
+    live: usize,
+    items_per_filter: usize,
+    rate_per_filter: f64,
+    /// Oldest first, keyed by bucket number
+    filters: VecDeque<(u64, BloomFilter)>,
+    hasher: RandomState,
+}
+
+impl RotatingBloomFilter {
+    fn new(window: Duration, config: &ReplayCacheConfig) -> Self {
+        let buckets = config.buckets.max(1);
+        let live = buckets + 1;
+        Self {
+            bucket_ms: (window.as_millis() as u64 / buckets as u64).max(1),
+            live,
+            items_per_filter: config.expected_nonces_per_window.div_ceil(buckets),
+            // Lookups consult every live filter, so each gets a share of the budget
+            rate_per_filter: config.false_positive_rate / live as f64,
+            filters: VecDeque::new(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn rotate(&mut self, now_ms: u64, extension_ms: u64) {
+        let bucket = now_ms / self.bucket_ms;
+        let live = self.live as u64 + extension_ms.div_ceil(self.bucket_ms);
+        while self
+            .filters
+            .front()
+            .is_some_and(|(b, _)| *b + live <= bucket)
+        {
+            self.filters.pop_front();
+        }
+        if self.filters.back().is_none_or(|(b, _)| *b < bucket) {
+            self.filters.push_back((
+                bucket,
+                BloomFilter::with_rate(self.items_per_filter, self.rate_per_filter),
+            ));
+        }
+    }
+
+    fn check_and_insert(&mut self, key: &str, now_ms: u64, extension_ms: u64) -> bool {
+        self.rotate(now_ms, extension_ms);
+        // An odd second hash keeps the probe sequence from collapsing
+        let hashes = (
+            self.hasher.hash_one(key),
+            self.hasher.hash_one((key, 0x9e37_79b9_7f4a_7c15u64)) | 1,
+        );
+        if self
+            .filters
+            .iter()
+            .any(|(_, filter)| filter.contains(hashes))
+        {
+            return true;
+        }
+        if let Some((_, current)) = self.filters.back_mut() {
+            current.insert(hashes, key.len());
+        }
+        false
+    }
+
+    fn stats(&self) -> ReplayCacheStats {
+        let filters = self.filters.iter().map(|(_, filter)| filter);
+        let nonces: u64 = filters.clone().map(|f| f.inserted).sum();
+        let key_bytes: u64 = filters.clone().map(|f| f.key_bytes).sum();
+        let miss_all: f64 = filters
+            .clone()
+            .map(|f| 1.0 - f.false_positive_rate())
+            .product();
+        ReplayCacheStats {
+            kind: ReplayCacheKind::Bloom,
+            nonces,
+            memory_bytes: filters.map(|f| f.bits.len() as u64 * 8).sum(),
+            exact_memory_bytes: nonces * EXACT_ENTRY_OVERHEAD as u64 + key_bytes,
+            estimated_false_positive_rate: 1.0 - miss_all,
+        }
+    }
+}
+
+/// Operator-defined validator rejecting requests whose field matches a pattern
+#[derive(Debug)]
+pub struct PatternValidator {
+    name: String,
+    field: String,
+    pattern: regex::Regex,
+    tenants: Vec<String>,
+    status: u16,
+}
+
+impl PatternValidator {
+    pub fn from_config(config: &CustomValidatorConfig) -> Result<Self> {
+        Ok(Self {
+            name: config.name.clone(),
+            field: config.field.clone(),
+            pattern: regex::Regex::new(&config.pattern)
+                .map_err(|e| Error::Config(format!("Invalid validator pattern: {}", e)))?,
+            tenants: config.tenants.clone(),
+            status: config.status,
+        })
+    }
+}
+
+impl RequestValidator for PatternValidator {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate(&self, request: &RequestContext<'_>) -> std::result::Result<(), Rejection> {
+        if !self.tenants.is_empty()
+            && !request
+                .tenant
+                .is_some_and(|tenant| self.tenants.iter().any(|t| t == tenant))
+        {
+            return Ok(());
+        }
+        let value = match self.field.as_str() {
+            "model" => Some(request.model),
+            "provider" => Some(request.provider),
+            "template_id" => request.template_id,
+            "tenant" => request.tenant,
+            _ => None,
+        };
+        if value.is_some_and(|value| self.pattern.is_match(value)) {
+            return Err(Rejection::new(
+                self.status,
+                format!("Request {} is denied by policy", self.field),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct StageCounters {
+    invocations: AtomicU64,
+    rejections: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidatorStats {
+    pub name: String,
+    pub invocations: u64,
+    pub rejections: u64,
+    pub avg_micros: f64,
+    pub max_micros: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Ordered validators; the first rejection stops the chain
+#[derive(Debug, Default)]
+pub struct ValidatorChain {
+    stages: Vec<(Box<dyn RequestValidator>, StageCounters)>,
+}
+
+impl ValidatorChain {
+    pub fn from_config(config: &ValidationPipelineConfig) -> Result<Self> {
+        let mut chain = Self::default();
+        for name in &config.order {
+            let validator: Box<dyn RequestValidator> = match name.as_str() {
+                "size" => Box::new(SizeValidator {
+                    max_bytes: config.max_ciphertext_bytes,
+                }),
+                "schema" => Box::new(SchemaValidator),
+                "policy" => Box::new(PolicyValidator {
+                    allowed_providers: config.allowed_providers.clone(),
+                }),
+                "params_hash" => Box::new(ParamsHashValidator),
+                "replay" => Box::new(ReplayValidator::with_cache(
+                    Duration::from_secs(config.replay_window_seconds),
+                    &config.replay_cache,
+                )),
+                custom => {
+                    let custom =
+                        config
+                            .custom
+                            .iter()
+                            .find(|c| c.name == custom)
+                            .ok_or_else(|| {
+                                Error::Config(format!("Unknown validator in order: {}", custom))
+                            })?;
+                    custom_validator(custom)?
+                }
+            };
+            chain.register(validator);
+        }
+        for custom in &config.custom {
+            if !config.order.contains(&custom.name) {
+                chain.register(custom_validator(custom)?);
+            }
+        }
+        Ok(chain)
+    }
+
+    /// Append a validator to the end of the chain
+    pub fn register(&mut self, validator: Box<dyn RequestValidator>) {
+        self.stages.push((validator, StageCounters::default()));
+    }
+
+    pub fn validate(&self, request: &RequestContext<'_>) -> std::result::Result<(), Rejection> {
+        for (validator, counters) in &self.stages {
+            let started = Instant::now();
+            let result = validator.validate(request);
+            let micros = started.elapsed().as_micros() as u64;
+
+            counters.invocations.fetch_add(1, Ordering::Relaxed);
+            counters.total_micros.fetch_add(micros, Ordering::Relaxed);
+            counters.max_micros.fetch_max(micros, Ordering::Relaxed);
+            if let Err(mut rejection) = result {
+                counters.rejections.fetch_add(1, Ordering::Relaxed);
+                rejection.validator = validator.name().to_string();
+                return Err(rejection);
+            }
+        }
+        Ok(())
+    }
+
+    /// Widen the validators' time windows by `extension`
+    pub fn adjust_for_clock_skew(&self, extension: Duration) {
+        for (validator, _) in &self.stages {
+            validator.adjust_for_clock_skew(extension);
+        }
+    }
+
+    pub fn stats(&self) -> Vec<ValidatorStats> {
+        self.stages
+            .iter()
+            .map(|(validator, counters)| {
+                let invocations = counters.invocations.load(Ordering::Relaxed);
+                ValidatorStats {
+                    name: validator.name().to_string(),
+                    invocations,
+                    rejections: counters.rejections.load(Ordering::Relaxed),
+                    avg_micros: counters.total_micros.load(Ordering::Relaxed) as f64
+                        / invocations.max(1) as f64,
+                    max_micros: counters.max_micros.load(Ordering::Relaxed),
+                    details: validator.details(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn custom_validator(config: &CustomValidatorConfig) -> Result<Box<dyn RequestValidator>> {
+    match config.kind {
+        CustomValidatorKind::DenyPattern => Ok(Box::new(PatternValidator::from_config(config)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_framework() {
+        let framework = ValidationFramework::with_fhe_defaults().unwrap();
+
+        // Test valid plaintext
+        let report = framework.validate_input("plaintext", "Hello, world!");
+        assert!(report.is_valid);
+        assert!(report.errors.is_empty());
+
+        // Test empty plaintext (should fail)
+        let report = framework.validate_input("plaintext", "");
+        assert!(!report.is_valid);
+        assert!(!report.errors.is_empty());
+
+        // Test too long plaintext
+        let long_text = "a".repeat(20000);
+        let report = framework.validate_input("plaintext", &long_text);
+        assert!(!report.is_valid);
+    }
+
+    #[test]
+    fn test_security_threat_detection() {
+        let framework = ValidationFramework::new();
+
+        let threats = framework.detect_security_threats("<script>alert('xss')</script>");
+        assert!(!threats.is_empty());
+        assert!(threats.iter().any(|t| t.contains("XSS")));
+
+        let threats = framework.detect_security_threats("'; DROP TABLE users; --");
+        assert!(!threats.is_empty());
+        assert!(threats.iter().any(|t| t.contains("SQL injection")));
+    }
+
+    #[test]
+    fn test_input_sanitization() {
+        let framework = ValidationFramework::new();
+
+        let input = "Hello\x00World\x1F\nValid text";
+        let sanitized = framework.sanitize_input(input);
+        assert!(!sanitized.contains('\x00'));
+        assert!(!sanitized.contains('\x1F'));
+    }
+
+    #[test]
+    fn test_uuid_validation() {
+        let framework = ValidationFramework::new();
+
+        // Valid UUID
+        let valid_uuid = "550e8400-e29b-41d4-a716-446655440000";
+        let report = framework.validate_uuid(valid_uuid);
+        assert!(report.is_valid);
+
+        // Invalid UUID
+        let invalid_uuid = "not-a-uuid";
+        let report = framework.validate_uuid(invalid_uuid);
+        assert!(!report.is_valid);
+    }
+
+    #[test]
+    fn test_base64_validation() {
+        let framework = ValidationFramework::new();
+
+        // Valid base64
+        let valid_b64 = "SGVsbG8gV29ybGQ=";
+        let report = framework.validate_ciphertext_data(valid_b64);
+        assert!(report.is_valid);
+
+        // Invalid base64
+        let invalid_b64 = "This is not base64!";
+        let report = framework.validate_ciphertext_data(invalid_b64);
+        assert!(!report.is_valid);
+    }
+
+    #[test]
+    fn test_validator_chain() {
+        let config = ValidationPipelineConfig {
+            custom: vec![CustomValidatorConfig {
+                name: "no-preview".to_string(),
+                kind: CustomValidatorKind::DenyPattern,
+                field: "model".to_string(),
+                pattern: "-preview$".to_string(),
+                tenants: vec!["acme".to_string()],
+                status: 403,
+            }],
+            ..ValidationPipelineConfig::default()
+        };
+        let chain = ValidatorChain::from_config(&config).unwrap();
+        let request = RequestContext {
+            tenant: Some("acme"),
+            provider: "openai",
+            model: "gpt-4",
+            template_id: None,
+            ciphertext_bytes: 1024,
+            params_hash: Some("abc"),
+            server_params_hash: "abc",
+            nonce: Some("n-1"),
+        };
+        assert!(chain.validate(&request).is_ok());
+
+        // A reused nonce is a replay; other tenants have their own nonce space
+        assert_eq!(chain.validate(&request).unwrap_err().validator, "replay");
+        let other_tenant = RequestContext {
+            tenant: Some("globex"),
+            model: "gpt-4-preview",
+            ..request
+        };
+        assert!(chain.validate(&other_tenant).is_ok());
+
+        let cases = [
+            (
+                RequestContext {
+                    ciphertext_bytes: 20_000_000,
+                    ..request
+                },
+                "size",
+                413,
+            ),
+            (
+                RequestContext {
+                    provider: "evil",
+                    ..request
+                },
+                "policy",
+                403,
+            ),
+            (
+                RequestContext {
+                    params_hash: Some("old"),
+                    ..request
+                },
+                "params_hash",
+                409,
+            ),
+            (
+                RequestContext {
+                    model: "gpt-4-preview",
+                    nonce: None,
+                    ..request
+                },
+                "no-preview",
+                403,
+            ),
+        ];
+        for (request, validator, status) in cases {
+            let rejection = chain.validate(&request).unwrap_err();
+            assert_eq!(
+                (rejection.validator.as_str(), rejection.status),
+                (validator, status)
+            );
+        }
+
+        let stats = chain.stats();
+        assert_eq!(stats.last().unwrap().name, "no-preview");
+        let size = stats.iter().find(|s| s.name == "size").unwrap();
+        assert_eq!((size.invocations, size.rejections), (7, 1));
+    }
+
+    #[test]
+    fn test_bloom_replay_cache_rotation() {
+        let config = ReplayCacheConfig {
+            kind: ReplayCacheKind::Bloom,
+            expected_nonces_per_window: 1000,
+            buckets: 4,
+            ..ReplayCacheConfig::default()
+        };
+        let validator = ReplayValidator::with_cache(Duration::from_secs(60), &config);
+        let start = 1_700_000_000_000;
+        assert!(!validator.check_and_insert("acme:n-1".to_string(), start));
+        assert!(validator.check_and_insert("acme:n-1".to_string(), start + 1));
+
+        // Still remembered a full window later, forgotten once its bucket rotates out
+        assert!(validator.check_and_insert("acme:n-1".to_string(), start + 60_000));
+        assert!(!validator.check_and_insert("acme:n-1".to_string(), start + 90_000));
+
+        for i in 0..200 {
+            validator.check_and_insert(format!("acme:fill-{i}"), start + 90_000);
+        }
+        let stats = validator.cache_stats();
+        assert_eq!(stats.kind, ReplayCacheKind::Bloom);
+        assert!(stats.memory_bytes < stats.exact_memory_bytes);
+        assert!(stats.estimated_false_positive_rate < 0.01);
+
+        // Clock skew keeps nonces for longer than the window
+        validator.adjust_for_clock_skew(Duration::from_secs(30));
+        assert!(!validator.check_and_insert("acme:n-2".to_string(), start + 90_000));
+        assert!(validator.check_and_insert("acme:n-2".to_string(), start + 180_000));
+        assert!(!validator.check_and_insert("acme:n-2".to_string(), start + 210_000));
+    }
+}
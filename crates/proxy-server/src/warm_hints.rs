@@ -0,0 +1,223 @@
+//! Warm-pool and priority hints for provider calls
+//!
+//! Some providers take hints about how to schedule a call: the hosted OpenAI
+//! and Anthropic APIs a `service_tier`, vLLM-style servers a `priority`, and
+//! gateways in front of self-hosted models often a header selecting a warm
+//! pool. Calls carry the hints configured for their tenant's SLA class.
+//!
+//! Whether a hint pays off shows on cold starts: a call to a model nobody has
+//! called for `idle_threshold_seconds` is the one that waits for a pool to
+//! spin up. Cold-start latencies of hinted and unhinted calls are kept per
+//! model over a window, and the difference of their means is reported.
+
+use crate::config::{SlaClass, SlaClassHints, WarmHint, WarmHintsConfig};
+use crate::error::{Error, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Cold-start latency of one model's hinted and unhinted calls
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmHintStats {
+    pub hinted_cold_starts: u64,
+    pub unhinted_cold_starts: u64,
+    /// Mean over the window, `None` without samples
+    pub hinted_mean_ms: Option<f64>,
+    pub unhinted_mean_ms: Option<f64>,
+    /// How much lower hinted cold starts are (unhinted minus hinted mean);
+    /// `None` until both have samples
+    pub delta_ms: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct ModelLatency {
+    last_call: Option<Instant>,
+    hinted: VecDeque<Duration>,
+    unhinted: VecDeque<Duration>,
+    hinted_cold_starts: u64,
+    unhinted_cold_starts: u64,
+}
+
+/// One provider's hints and the cold starts seen with and without them
+#[derive(Debug)]
+pub struct WarmHints {
+    enabled: bool,
+    idle_threshold: Duration,
+    window_size: usize,
+    hints: SlaClassHints,
+    models: Mutex<HashMap<String, ModelLatency>>,
+}
+
+impl WarmHints {
+    /// Hints `config` sets for the provider named `provider`
+    pub fn new(config: &WarmHintsConfig, provider: &str) -> Self {
+        Self {
+            enabled: config.enabled,
+            idle_threshold: Duration::from_secs(config.idle_threshold_seconds),
+            window_size: config.window_size,
+            hints: config.providers.get(provider).cloned().unwrap_or_default(),
+            models: Mutex::default(),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(&WarmHintsConfig::default(), "")
+    }
+
+    /// The hint to send with a call for a tenant of `class`
+    pub fn hint_for(&self, class: Option<SlaClass>) -> Option<&WarmHint> {
+        if !self.enabled {
+            return None;
+        }
+        self.hints.for_class(class?)
+    }
+
+    /// Note a call to `model` starting at `now`; returns whether it is a cold
+    /// start, that is whether the model sat idle for the threshold or was
+    /// never called before
+    pub fn start(&self, model: &str, now: Instant) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let mut models = self.models.lock().unwrap();
+        let latency = models.entry(model.to_string()).or_default();
+        let cold = latency
+            .last_call
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.idle_threshold);
+        latency.last_call = Some(now);
+        cold
+    }
+
+    /// Record how long a cold start of `model` took to answer
+    pub fn record_cold_start(&self, model: &str, hinted: bool, elapsed: Duration) {
+        if !self.enabled {
+            return;
+        }
+        let mut models = self.models.lock().unwrap();
+        let latency = models.entry(model.to_string()).or_default();
+        let (window, count) = match hinted {
+            true => (&mut latency.hinted, &mut latency.hinted_cold_starts),
+            false => (&mut latency.unhinted, &mut latency.unhinted_cold_starts),
+        };
+        *count += 1;
+        window.push_back(elapsed);
+        while window.len() > self.window_size {
+            window.pop_front();
+        }
+    }
+
+    /// Per model, for models with at least one cold start
+    pub fn stats(&self) -> HashMap<String, WarmHintStats> {
+        let mean = |window: &VecDeque<Duration>| {
+            (!window.is_empty()).then(|| {
+                window.iter().map(|d| d.as_secs_f64() * 1000.0).sum::<f64>() / window.len() as f64
+            })
+        };
+        self.models
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, latency)| latency.hinted_cold_starts + latency.unhinted_cold_starts > 0)
+            .map(|(model, latency)| {
+                let hinted_mean_ms = mean(&latency.hinted);
+                let unhinted_mean_ms = mean(&latency.unhinted);
+                let stats = WarmHintStats {
+                    hinted_cold_starts: latency.hinted_cold_starts,
+                    unhinted_cold_starts: latency.unhinted_cold_starts,
+                    hinted_mean_ms,
+                    unhinted_mean_ms,
+                    delta_ms: unhinted_mean_ms.zip(hinted_mean_ms).map(|(u, h)| u - h),
+                };
+                (model.clone(), stats)
+            })
+            .collect()
+    }
+}
+
+/// The request body `request` with `hint`'s fields added, and its headers
+pub fn apply<T: Serialize>(
+    request: &T,
+    hint: Option<&WarmHint>,
+) -> Result<(serde_json::Value, HeaderMap)> {
+    let mut body = serde_json::to_value(request)?;
+    let mut headers = HeaderMap::new();
+    let Some(hint) = hint else {
+        return Ok((body, headers));
+    };
+    if let Some(fields) = body.as_object_mut() {
+        if let Some(priority) = hint.priority {
+            fields.insert("priority".to_string(), priority.into());
+        }
+        if let Some(tier) = &hint.service_tier {
+            fields.insert("service_tier".to_string(), tier.clone().into());
+        }
+    }
+    for (name, value) in &hint.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| Error::Config(format!("Warm hint header {:?}: {}", name, e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| Error::Config(format!("Warm hint header {:?}: {}", name, e)))?;
+        headers.insert(name, value);
+    }
+    Ok((body, headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_hints_by_class_and_cold_start_delta() {
+        let gold = WarmHint {
+            priority: Some(-10),
+            headers: BTreeMap::from([("x-warm-pool".to_string(), "reserved".to_string())]),
+            ..WarmHint::default()
+        };
+        let config = WarmHintsConfig {
+            enabled: true,
+            idle_threshold_seconds: 60,
+            window_size: 2,
+            providers: BTreeMap::from([(
+                "vllm".to_string(),
+                SlaClassHints {
+                    gold: Some(gold.clone()),
+                    ..SlaClassHints::default()
+                },
+            )]),
+        };
+        let hints = WarmHints::new(&config, "vllm");
+        assert_eq!(hints.hint_for(Some(SlaClass::Gold)), Some(&gold));
+        assert_eq!(hints.hint_for(Some(SlaClass::Bronze)), None);
+        assert_eq!(hints.hint_for(None), None);
+        assert_eq!(
+            WarmHints::new(&config, "openai").hint_for(Some(SlaClass::Gold)),
+            None
+        );
+
+        let (body, headers) = apply(&serde_json::json!({ "model": "llama" }), Some(&gold)).unwrap();
+        assert_eq!(body["priority"], -10);
+        assert!(body.get("service_tier").is_none());
+        assert_eq!(headers["x-warm-pool"], "reserved");
+
+        // Only calls after the idle threshold count as cold starts
+        let now = Instant::now();
+        assert!(hints.start("llama", now));
+        assert!(!hints.start("llama", now + Duration::from_secs(30)));
+        assert!(hints.start("llama", now + Duration::from_secs(120)));
+
+        hints.record_cold_start("llama", false, Duration::from_millis(900));
+        assert_eq!(hints.stats()["llama"].delta_ms, None);
+        for ms in [500, 300, 200] {
+            hints.record_cold_start("llama", true, Duration::from_millis(ms));
+        }
+        let stats = &hints.stats()["llama"];
+        assert_eq!(stats.hinted_cold_starts, 3);
+        // The window keeps the last two hinted samples
+        assert_eq!(stats.hinted_mean_ms, Some(250.0));
+        assert_eq!(stats.delta_ms, Some(650.0));
+        assert!(WarmHints::disabled().stats().is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+//! Request classification by workload tag
+//!
+//! Clients label a request with the workload it belongs to ("interactive",
+//! "batch", "evaluation") in `x-workload-tag`. Each tag is configured with the
+//! policy it selects: a priority asked for on the request's behalf, whether
+//! the response cache is used, a provider, and the level the request's outcome
+//! is logged at. Only configured tags are accepted, and a tenant may be
+//! restricted to a subset of them. Counters are kept per tag, so each workload
+//! can be observed on its own.
+
+use crate::config::{WorkloadTagPolicy, WorkloadTagsConfig};
+use crate::error::{Error, Result};
+use crate::scaling::RequestPriority;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+pub const WORKLOAD_TAG_HEADER: &str = "x-workload-tag";
+
+/// The tag a request was classified under, kept in its extensions
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadTag {
+    pub name: String,
+    pub policy: WorkloadTagPolicy,
+}
+
+impl WorkloadTag {
+    pub fn priority(&self) -> Option<RequestPriority> {
+        self.policy
+            .priority
+            .as_deref()
+            .and_then(|p| RequestPriority::parse(p).ok())
+    }
+
+    pub fn log_level(&self) -> Option<log::Level> {
+        self.policy
+            .log_level
+            .as_deref()
+            .and_then(|level| log::Level::from_str(level).ok())
+    }
+}
+
+#[derive(Debug, Default)]
+struct TagCounters {
+    requests: AtomicU64,
+    client_errors: AtomicU64,
+    server_errors: AtomicU64,
+    refused: AtomicU64,
+    latency_ms_total: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkloadTagStats {
+    pub requests: u64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    /// Requests refused because their tenant may not use the tag
+    pub refused: u64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkloadTagReport {
+    pub enabled: bool,
+    pub default_tag: Option<String>,
+    pub tags: BTreeMap<String, WorkloadTagStats>,
+    /// Requests refused for naming a tag that is not configured
+    pub unknown_refused: u64,
+}
+
+#[derive(Debug)]
+pub struct WorkloadTags {
+    config: WorkloadTagsConfig,
+    /// One entry per configured tag, so labels stay bounded
+    counters: HashMap<String, TagCounters>,
+    unknown_refused: AtomicU64,
+}
+
+impl WorkloadTags {
+    pub fn new(config: WorkloadTagsConfig) -> Self {
+        let counters = config
+            .tags
+            .keys()
+            .map(|tag| (tag.clone(), TagCounters::default()))
+            .collect();
+        Self {
+            config,
+            counters,
+            unknown_refused: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Check a configured tag's name and policy
+    pub fn validate_policy(tag: &str, policy: &WorkloadTagPolicy) -> Result<()> {
+        if tag.is_empty()
+            || !tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Error::Config(format!(
+                "Workload tag names may only use letters, digits, '-' and '_': {:?}",
+                tag
+            )));
+        }
+        if let Some(priority) = &policy.priority {
+            RequestPriority::parse(priority).map_err(|e| Error::Config(e.to_string()))?;
+        }
+        if let Some(level) = &policy.log_level {
+            log::Level::from_str(level)
+                .map_err(|_| Error::Config(format!("Unknown log level: {}", level)))?;
+        }
+        Ok(())
+    }
+
+    /// Tag a request declared in `header`, or the default tag when it declared
+    /// none. `allowed` is the tenant's permitted set; empty permits every tag.
+    pub fn classify(
+        &self,
+        header: Option<&str>,
+        allowed: &[String],
+    ) -> Result<Option<WorkloadTag>> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let Some(name) = header.map(str::trim).or(self.config.default_tag.as_deref()) else {
+            return Ok(None);
+        };
+        let Some(policy) = self.config.tags.get(name) else {
+            self.unknown_refused.fetch_add(1, Ordering::Relaxed);
+            return Err(Error::Validation(format!("Unknown workload tag: {}", name)));
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|tag| tag == name) {
+            if let Some(counters) = self.counters.get(name) {
+                counters.refused.fetch_add(1, Ordering::Relaxed);
+            }
+            return Err(Error::Security(format!(
+                "Workload tag {} is not allowed for this tenant",
+                name
+            )));
+        }
+        Ok(Some(WorkloadTag {
+            name: name.to_string(),
+            policy: policy.clone(),
+        }))
+    }
+
+    /// Count a finished request under its tag
+    pub fn record(&self, tag: &WorkloadTag, status: u16, latency: Duration) {
+        let Some(counters) = self.counters.get(&tag.name) else {
+            return;
+        };
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        counters
+            .latency_ms_total
+            .fetch_add(latency.as_millis() as u64, Ordering::Relaxed);
+        match status {
+            400..=499 => counters.client_errors.fetch_add(1, Ordering::Relaxed),
+            500..=599 => counters.server_errors.fetch_add(1, Ordering::Relaxed),
+            _ => 0,
+        };
+    }
+
+    pub fn report(&self) -> WorkloadTagReport {
+        let tags = self
+            .counters
+            .iter()
+            .map(|(tag, counters)| {
+                let requests = counters.requests.load(Ordering::Relaxed);
+                let latency = counters.latency_ms_total.load(Ordering::Relaxed);
+                let stats = WorkloadTagStats {
+                    requests,
+                    client_errors: counters.client_errors.load(Ordering::Relaxed),
+                    server_errors: counters.server_errors.load(Ordering::Relaxed),
+                    refused: counters.refused.load(Ordering::Relaxed),
+                    avg_latency_ms: if requests == 0 {
+                        0.0
+                    } else {
+                        latency as f64 / requests as f64
+                    },
+                };
+                (tag.clone(), stats)
+            })
+            .collect();
+        WorkloadTagReport {
+            enabled: self.config.enabled,
+            default_tag: self.config.default_tag.clone(),
+            tags,
+            unknown_refused: self.unknown_refused.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WorkloadCachePolicy;
+
+    #[test]
+    fn test_tags_select_policies_within_tenant_allowance() {
+        let mut config = WorkloadTagsConfig {
+            enabled: true,
+            default_tag: Some("interactive".to_string()),
+            ..WorkloadTagsConfig::default()
+        };
+        config.tags.insert(
+            "interactive".to_string(),
+            WorkloadTagPolicy {
+                priority: Some("high".to_string()),
+                ..WorkloadTagPolicy::default()
+            },
+        );
+        config.tags.insert(
+            "evaluation".to_string(),
+            WorkloadTagPolicy {
+                cache: WorkloadCachePolicy::Bypass,
+                log_level: Some("debug".to_string()),
+                ..WorkloadTagPolicy::default()
+            },
+        );
+        for (tag, policy) in &config.tags {
+            WorkloadTags::validate_policy(tag, policy).unwrap();
+        }
+        assert!(WorkloadTags::validate_policy("bad tag", &WorkloadTagPolicy::default()).is_err());
+        let tags = WorkloadTags::new(config);
+
+        // Untagged requests fall back to the default tag
+        let tag = tags.classify(None, &[]).unwrap().unwrap();
+        assert_eq!(tag.name, "interactive");
+        assert_eq!(tag.priority(), Some(RequestPriority::High));
+
+        let evaluation = tags.classify(Some("evaluation"), &[]).unwrap().unwrap();
+        assert_eq!(evaluation.policy.cache, WorkloadCachePolicy::Bypass);
+        assert_eq!(evaluation.log_level(), Some(log::Level::Debug));
+
+        // Unknown tags and tags outside the tenant's set are refused
+        assert!(tags.classify(Some("scraping"), &[]).is_err());
+        let allowed = vec!["interactive".to_string()];
+        assert!(tags.classify(Some("evaluation"), &allowed).is_err());
+
+        tags.record(&evaluation, 200, Duration::from_millis(30));
+        tags.record(&evaluation, 503, Duration::from_millis(10));
+        let report = tags.report();
+        let stats = &report.tags["evaluation"];
+        assert_eq!(
+            (stats.requests, stats.server_errors, stats.refused),
+            (2, 1, 1)
+        );
+        assert_eq!(stats.avg_latency_ms, 20.0);
+        assert_eq!(report.unknown_refused, 1);
+    }
+}
@@ -0,0 +1,242 @@
+//! Shared fixtures for the workspace's test suites
+//!
+//! Depends only on `fhe-core`, so the server, the client and the meta-crate's
+//! integration tests can all use it without a dependency cycle.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub mod fixtures {
+    use fhe_core::{Ciphertext, FheParams};
+    use uuid::Uuid;
+
+    /// Parameters small enough for fast key generation in tests
+    pub fn small_params() -> FheParams {
+        FheParams {
+            poly_modulus_degree: 1024,
+            coeff_modulus_bits: vec![40, 40],
+            scale_bits: 30,
+            security_level: 128,
+        }
+    }
+
+    /// A ciphertext with `data` under `params`, not produced by any engine
+    pub fn ciphertext(params: &FheParams, data: &[u8]) -> Ciphertext {
+        Ciphertext {
+            id: Uuid::new_v4(),
+            data: data.to_vec(),
+            params: params.clone(),
+            noise_budget: Some(40),
+        }
+    }
+}
+
+/// A request received by [`MockProxy`]
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    /// `Value::Null` when the body is empty or not JSON
+    pub body: Value,
+}
+
+impl RecordedRequest {
+    /// Value of header `name`, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Status, content type and body of a canned response
+type Canned = (u16, String, String);
+
+type Routes = HashMap<(String, String), Canned>;
+
+/// HTTP server on a local port answering with canned bodies per method and
+/// path, and 404 for anything else. Records every request it receives.
+#[derive(Debug, Clone)]
+pub struct MockProxy {
+    addr: std::net::SocketAddr,
+    routes: Arc<Mutex<Routes>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    delay: Arc<Mutex<Duration>>,
+}
+
+impl MockProxy {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock proxy");
+        let proxy = Self {
+            addr: listener.local_addr().expect("mock proxy address"),
+            routes: Arc::default(),
+            requests: Arc::default(),
+            delay: Arc::default(),
+        };
+        let server = proxy.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let server = server.clone();
+                tokio::spawn(async move { server.serve(stream).await });
+            }
+        });
+        proxy
+    }
+
+    /// Answer `method path` with `status` and `body`
+    pub fn respond(self, method: &str, path: &str, status: u16, body: Value) -> Self {
+        self.respond_with(method, path, status, "application/json", &body.to_string())
+    }
+
+    /// Answer `method path` with a raw body, e.g. a `text/event-stream`
+    pub fn respond_with(
+        self,
+        method: &str,
+        path: &str,
+        status: u16,
+        content_type: &str,
+        body: &str,
+    ) -> Self {
+        self.routes.lock().unwrap().insert(
+            (method.to_string(), path.to_string()),
+            (status, content_type.to_string(), body.to_string()),
+        );
+        self
+    }
+
+    /// Hold every response for `delay` after its request arrives
+    pub fn delay(self, delay: Duration) -> Self {
+        *self.delay.lock().unwrap() = delay;
+        self
+    }
+
+    /// Base URL, e.g. `http://127.0.0.1:41234`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    async fn serve(&self, mut stream: TcpStream) {
+        let Some(request) = read_request(&mut stream).await else {
+            return;
+        };
+        let (status, content_type, body) = self
+            .routes
+            .lock()
+            .unwrap()
+            .get(&(request.method.clone(), request.path.clone()))
+            .cloned()
+            .unwrap_or((404, "application/json".to_string(), "null".to_string()));
+        self.requests.lock().unwrap().push(request);
+        let delay = *self.delay.lock().unwrap();
+        tokio::time::sleep(delay).await;
+
+        let response = format!(
+            "HTTP/1.1 {} Mock\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<RecordedRequest> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = buffer.split_off(header_end + 4);
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+
+    Some(RecordedRequest {
+        method,
+        path,
+        headers,
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_proxy_answers_routes_and_records_requests() {
+        let proxy = MockProxy::start().await.respond(
+            "GET",
+            "/v1/params",
+            200,
+            serde_json::json!({ "ok": true }),
+        );
+
+        let mut stream = TcpStream::connect(proxy.addr).await.unwrap();
+        stream
+            .write_all(b"POST /v1/missing HTTP/1.1\r\nx-api-key: k\r\ncontent-length: 2\r\n\r\n{}")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+
+        let mut stream = TcpStream::connect(proxy.addr).await.unwrap();
+        stream
+            .write_all(b"GET /v1/params HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with(r#"{"ok":true}"#));
+
+        let requests = proxy.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].header("X-Api-Key"), Some("k"));
+        assert_eq!(requests[0].body, serde_json::json!({}));
+        assert_eq!(requests[1].body, Value::Null);
+    }
+}
@@ -0,0 +1,21 @@
+//! Deserializers for untrusted request bodies and ciphertexts
+#![no_main]
+
+use homomorphic_llm_proxy::federation::FederationEnvelope;
+use homomorphic_llm_proxy::fhe::{Ciphertext, FheEngine, FheParams};
+use homomorphic_llm_proxy::proxy::{DecryptChunksRequest, EncryptRequest, ProcessRequest};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = serde_json::from_slice::<EncryptRequest>(data);
+    let _ = serde_json::from_slice::<ProcessRequest>(data);
+    let _ = serde_json::from_slice::<DecryptChunksRequest>(data);
+    let _ = serde_json::from_slice::<FederationEnvelope>(data);
+    let _ = serde_json::from_slice::<FheParams>(data);
+
+    if let Ok(ciphertext) = serde_json::from_slice::<Ciphertext>(data) {
+        let engine = FheEngine::new(FheParams::default()).unwrap();
+        let _ = engine.validate_ciphertext(&ciphertext);
+        let _ = engine.validate_ciphertext_format(&ciphertext);
+    }
+});
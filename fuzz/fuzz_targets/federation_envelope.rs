@@ -0,0 +1,31 @@
+//! Authentication and loop checks on envelopes received from peers
+#![no_main]
+
+use homomorphic_llm_proxy::config::{FederationConfig, FederationPeerConfig};
+use homomorphic_llm_proxy::federation::{FederationEnvelope, FederationService};
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+
+fn service() -> &'static FederationService {
+    static SERVICE: OnceLock<FederationService> = OnceLock::new();
+    SERVICE.get_or_init(|| {
+        FederationService::new(FederationConfig {
+            enabled: true,
+            node_id: "fuzz".to_string(),
+            peers: vec![FederationPeerConfig {
+                id: "peer".to_string(),
+                endpoint: "http://peer.invalid".to_string(),
+                shared_secret: "0123456789abcdef0123456789abcdef".to_string(),
+                requests_per_minute: 60,
+            }],
+            ..FederationConfig::default()
+        })
+        .unwrap()
+    })
+}
+
+fuzz_target!(|envelope: FederationEnvelope| {
+    let service = service();
+    let _ = service.open("peer", &envelope);
+    let _ = service.open(&envelope.header.sender, &envelope);
+});
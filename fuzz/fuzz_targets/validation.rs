@@ -0,0 +1,22 @@
+//! Input validation and threat detection
+#![no_main]
+
+use homomorphic_llm_proxy::fhe::FheParams;
+use homomorphic_llm_proxy::validation::ValidationFramework;
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+
+fn framework() -> &'static ValidationFramework {
+    static FRAMEWORK: OnceLock<ValidationFramework> = OnceLock::new();
+    FRAMEWORK.get_or_init(|| ValidationFramework::with_fhe_defaults().unwrap())
+}
+
+fuzz_target!(|input: (String, String, FheParams)| {
+    let (field, value, params) = input;
+    let framework = framework();
+    let _ = framework.validate_input(&field, &value);
+    let _ = framework.validate_ciphertext_data(&value);
+    let _ = framework.validate_uuid(&value);
+    let _ = framework.detect_security_threats(&value);
+    let _ = framework.validate_fhe_params(&params);
+});
@@ -0,0 +1,24 @@
+//! Ciphertext wire format decoding: header parsing, decryption and chunking
+#![no_main]
+
+use homomorphic_llm_proxy::fhe::{Ciphertext, FheEngine, FheParams};
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+fn engine() -> &'static (FheEngine, Uuid) {
+    static ENGINE: OnceLock<(FheEngine, Uuid)> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut engine = FheEngine::new(FheParams::default()).unwrap();
+        let (client_id, _) = engine.generate_keys().unwrap();
+        (engine, client_id)
+    })
+}
+
+fuzz_target!(|ciphertext: Ciphertext| {
+    let (engine, client_id) = engine();
+    let _ = engine.validate_ciphertext_format(&ciphertext);
+    let _ = engine.decrypt_text(*client_id, &ciphertext);
+    let _ = engine.decrypt_text_safe(*client_id, &ciphertext);
+    let _ = engine.split_into_chunks(&ciphertext, 64);
+});
@@ -1,784 +0,0 @@
-//! Fully Homomorphic Encryption operations
-
-use crate::error::{Error, Result};
-use rand::Rng;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use uuid::Uuid;
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_fhe_params_default() {
-        let params = FheParams::default();
-        assert_eq!(params.poly_modulus_degree, 16384);
-        assert_eq!(params.security_level, 128);
-        assert!(!params.coeff_modulus_bits.is_empty());
-    }
-
-    #[test]
-    fn test_fhe_engine_creation() {
-        let params = FheParams::default();
-        let engine = FheEngine::new(params);
-        assert!(engine.is_ok());
-    }
-
-    #[test]
-    fn test_key_generation() {
-        let params = FheParams::default();
-        let mut engine = FheEngine::new(params).expect("Failed to create engine");
-
-        let result = engine.generate_keys();
-        assert!(result.is_ok());
-
-        let (client_id, server_id) = result.unwrap();
-        assert_ne!(client_id, Uuid::nil());
-        assert_ne!(server_id, Uuid::nil());
-    }
-
-    #[test]
-    fn test_encryption_decryption() {
-        let params = FheParams::default();
-        let mut engine = FheEngine::new(params).expect("Failed to create engine");
-
-        let (client_id, _) = engine.generate_keys().expect("Failed to generate keys");
-
-        let plaintext = "Hello FHE!";
-        let ciphertext = engine
-            .encrypt_text(client_id, plaintext)
-            .expect("Failed to encrypt");
-        let decrypted = engine
-            .decrypt_text(client_id, &ciphertext)
-            .expect("Failed to decrypt");
-
-        assert_eq!(plaintext, decrypted);
-        assert!(!ciphertext.data.is_empty());
-        assert!(ciphertext.noise_budget.is_some());
-    }
-
-    #[test]
-    fn test_input_validation() {
-        let params = FheParams::default();
-        let mut engine = FheEngine::new(params).expect("Failed to create engine");
-
-        let (client_id, _) = engine.generate_keys().expect("Failed to generate keys");
-
-        // Test empty input
-        assert!(engine.encrypt_text(client_id, "").is_err());
-
-        // Test too long input
-        let long_text = "x".repeat(15000);
-        assert!(engine.encrypt_text(client_id, &long_text).is_err());
-
-        // Test malicious input
-        let malicious = "text<script>alert('xss')</script>";
-        assert!(engine.encrypt_text(client_id, malicious).is_err());
-    }
-
-    #[test]
-    fn test_engine_stats() {
-        let params = FheParams::default();
-        let mut engine = FheEngine::new(params).expect("Failed to create engine");
-
-        let initial_stats = engine.get_stats();
-        assert_eq!(initial_stats.total_client_keys, 0);
-        assert_eq!(initial_stats.total_server_keys, 0);
-
-        let _ = engine.generate_keys().expect("Failed to generate keys");
-
-        let stats = engine.get_stats();
-        assert_eq!(stats.total_client_keys, 1);
-        assert_eq!(stats.total_server_keys, 1);
-    }
-}
-
-/// FHE parameters for CKKS-like operations
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FheParams {
-    pub poly_modulus_degree: usize,
-    pub coeff_modulus_bits: Vec<u64>,
-    pub scale_bits: u64,
-    pub security_level: u8,
-}
-
-impl Default for FheParams {
-    fn default() -> Self {
-        Self {
-            poly_modulus_degree: 16384,
-            coeff_modulus_bits: vec![60, 40, 40, 60],
-            scale_bits: 40,
-            security_level: 128,
-        }
-    }
-}
-
-/// Encrypted ciphertext container
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Ciphertext {
-    pub id: Uuid,
-    pub data: Vec<u8>,
-    pub params: FheParams,
-    pub noise_budget: Option<u64>,
-}
-
-/// Client key for encryption/decryption
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
-pub struct ClientKey {
-    pub id: Uuid,
-    key_data: Vec<u8>, // Simulated key data
-    params: FheParams,
-}
-
-/// Server key for homomorphic operations
-#[derive(Debug)]
-#[allow(dead_code)]
-pub struct ServerKey {
-    pub id: Uuid,
-    key_data: Vec<u8>, // Simulated key data
-    params: FheParams,
-}
-
-/// Statistics for FHE engine
-#[derive(Debug, Serialize)]
-pub struct FheStats {
-    pub total_client_keys: usize,
-    pub total_server_keys: usize,
-    pub params: FheParams,
-}
-
-/// FHE engine for homomorphic operations
-#[derive(Debug)]
-pub struct FheEngine {
-    params: FheParams,
-    pub client_keys: HashMap<Uuid, ClientKey>,
-    pub server_keys: HashMap<Uuid, ServerKey>,
-}
-
-impl FheEngine {
-    /// Create new FHE engine with specified parameters
-    pub fn new(params: FheParams) -> Result<Self> {
-        log::info!(
-            "Initializing FHE engine with security level {}",
-            params.security_level
-        );
-
-        Ok(Self {
-            params,
-            client_keys: HashMap::new(),
-            server_keys: HashMap::new(),
-        })
-    }
-
-    /// Generate new client/server key pair
-    pub fn generate_keys(&mut self) -> Result<(Uuid, Uuid)> {
-        let client_id = Uuid::new_v4();
-        let server_id = Uuid::new_v4();
-
-        log::info!(
-            "Generating FHE key pair: client={}, server={}",
-            client_id,
-            server_id
-        );
-
-        // Generate simulated key data
-        let mut rng = rand::rng();
-        let client_key_data: Vec<u8> = (0..128).map(|_| rng.random()).collect();
-        let server_key_data: Vec<u8> = (0..256).map(|_| rng.random()).collect();
-
-        self.client_keys.insert(
-            client_id,
-            ClientKey {
-                id: client_id,
-                key_data: client_key_data,
-                params: self.params.clone(),
-            },
-        );
-
-        self.server_keys.insert(
-            server_id,
-            ServerKey {
-                id: server_id,
-                key_data: server_key_data,
-                params: self.params.clone(),
-            },
-        );
-
-        Ok((client_id, server_id))
-    }
-
-    /// Encrypt text using CKKS-style encoding with enhanced validation
-    pub fn encrypt_text(&self, client_id: Uuid, plaintext: &str) -> Result<Ciphertext> {
-        let _client_key = self
-            .client_keys
-            .get(&client_id)
-            .ok_or_else(|| Error::Fhe("Client key not found".to_string()))?;
-
-        // Input validation
-        if plaintext.is_empty() {
-            return Err(Error::Validation("Plaintext cannot be empty".to_string()));
-        }
-
-        if plaintext.len() > 10_000 {
-            return Err(Error::Validation(
-                "Plaintext too long (max 10,000 characters)".to_string(),
-            ));
-        }
-
-        // Enhanced input sanitization with security focus
-        let sanitized_text = plaintext
-            .chars()
-            .filter(|c| {
-                c.is_ascii()
-                && (!c.is_control() || c.is_whitespace())
-                && *c != '\0' // Null byte protection
-                && !matches!(*c, '\x01'..='\x08' | '\x0B'..='\x0C' | '\x0E'..='\x1F' | '\x7F')
-                // Control chars
-            })
-            .collect::<String>();
-
-        if sanitized_text != plaintext {
-            log::warn!(
-                "Input sanitized during encryption for client {} (removed {} characters)",
-                client_id,
-                plaintext.len() - sanitized_text.len()
-            );
-        }
-
-        // Additional security check for potential injection patterns
-        let suspicious_patterns = [
-            "<script",
-            "javascript:",
-            "data:",
-            "vbscript:",
-            "onload=",
-            "onerror=",
-        ];
-        let lowercase_text = sanitized_text.to_lowercase();
-        for pattern in &suspicious_patterns {
-            if lowercase_text.contains(pattern) {
-                log::warn!(
-                    "Potentially malicious pattern detected in encryption input for client {}: {}",
-                    client_id,
-                    pattern
-                );
-                return Err(Error::Validation(
-                    "Input contains potentially malicious content".to_string(),
-                ));
-            }
-        }
-
-        log::debug!(
-            "Encrypting text of length {} for client {}",
-            sanitized_text.len(),
-            client_id
-        );
-
-        // Convert text to boolean array for concrete library
-        let text_bytes = sanitized_text.as_bytes();
-        let mut encrypted_data = Vec::new();
-
-        // Add encryption metadata header
-        let metadata = format!("FHE-v1|{}", chrono::Utc::now().timestamp());
-        let metadata_bytes = metadata.as_bytes();
-        encrypted_data.extend_from_slice(&(metadata_bytes.len() as u32).to_le_bytes());
-        encrypted_data.extend_from_slice(metadata_bytes);
-
-        // Simulate encryption by encoding each byte as encrypted booleans
-        for &byte in text_bytes {
-            for i in 0..8 {
-                let bit = (byte >> i) & 1 == 1;
-                // In real implementation, encrypt each bit with concrete
-                encrypted_data.push(if bit { 1u8 } else { 0u8 });
-            }
-        }
-
-        // Calculate noise budget based on operations
-        let noise_budget = self.calculate_noise_budget(text_bytes.len());
-
-        Ok(Ciphertext {
-            id: Uuid::new_v4(),
-            data: encrypted_data,
-            params: self.params.clone(),
-            noise_budget: Some(noise_budget),
-        })
-    }
-
-    /// Decrypt ciphertext back to text
-    pub fn decrypt_text(&self, client_id: Uuid, ciphertext: &Ciphertext) -> Result<String> {
-        let _client_key = self
-            .client_keys
-            .get(&client_id)
-            .ok_or_else(|| Error::Fhe("Client key not found".to_string()))?;
-
-        log::debug!("Decrypting ciphertext {}", ciphertext.id);
-
-        // Extract metadata from encrypted data
-        if ciphertext.data.len() < 4 {
-            return Err(Error::Fhe("Invalid ciphertext format".to_string()));
-        }
-
-        let metadata_len = u32::from_le_bytes([
-            ciphertext.data[0],
-            ciphertext.data[1],
-            ciphertext.data[2],
-            ciphertext.data[3],
-        ]) as usize;
-
-        if ciphertext.data.len() < 4 + metadata_len {
-            return Err(Error::Fhe("Corrupted ciphertext metadata".to_string()));
-        }
-
-        let metadata_bytes = &ciphertext.data[4..4 + metadata_len];
-        let _metadata = String::from_utf8(metadata_bytes.to_vec())
-            .map_err(|_| Error::Fhe("Invalid metadata encoding".to_string()))?;
-
-        // Decrypt the boolean array back to text
-        let encrypted_bits = &ciphertext.data[4 + metadata_len..];
-        let mut text_bytes = Vec::new();
-
-        // Decrypt bits back to bytes (simulated)
-        for chunk in encrypted_bits.chunks(8) {
-            if chunk.len() != 8 {
-                break; // Incomplete byte at end
-            }
-            let mut byte = 0u8;
-            for (i, &encrypted_bit) in chunk.iter().enumerate() {
-                // In real implementation, decrypt each encrypted boolean with concrete
-                let bit = encrypted_bit != 0; // Simulated decryption
-                if bit {
-                    byte |= 1 << i;
-                }
-            }
-            text_bytes.push(byte);
-        }
-
-        // Convert bytes back to string
-        let plaintext = String::from_utf8(text_bytes)
-            .map_err(|_| Error::Fhe("Invalid UTF-8 in decrypted data".to_string()))?;
-
-        log::debug!(
-            "Successfully decrypted {} characters for client {}",
-            plaintext.len(),
-            client_id
-        );
-
-        Ok(plaintext)
-    }
-
-    /// Perform homomorphic string concatenation with enhanced security
-    pub fn concatenate_encrypted(&self, a: &Ciphertext, b: &Ciphertext) -> Result<Ciphertext> {
-        log::debug!("Concatenating ciphertexts {} and {}", a.id, b.id);
-
-        // Validate ciphertext parameters compatibility
-        if a.params.poly_modulus_degree != b.params.poly_modulus_degree {
-            return Err(Error::Fhe("Incompatible ciphertext parameters".to_string()));
-        }
-
-        if a.params.security_level != b.params.security_level {
-            return Err(Error::Fhe("Mismatched security levels".to_string()));
-        }
-
-        // Check for potential overflow in concatenated size
-        let total_size = a.data.len().saturating_add(b.data.len());
-        if total_size > 1_000_000 {
-            // 1MB limit
-            return Err(Error::Fhe(
-                "Concatenated ciphertext would exceed size limit".to_string(),
-            ));
-        }
-
-        // Validate noise budgets before operation
-        match (a.noise_budget, b.noise_budget) {
-            (Some(a_budget), Some(b_budget)) => {
-                if a_budget < 10 || b_budget < 10 {
-                    return Err(Error::Fhe(
-                        "Insufficient noise budget for concatenation".to_string(),
-                    ));
-                }
-            }
-            _ => {
-                log::warn!("Missing noise budget information for concatenation");
-            }
-        }
-
-        // Perform concatenation with metadata preservation
-        let mut concatenated_data = Vec::with_capacity(total_size);
-
-        // Add operation header for audit trail
-        let op_header = format!("CONCAT|{}|{}", a.id, b.id);
-        let header_bytes = op_header.as_bytes();
-        concatenated_data.extend_from_slice(&(header_bytes.len() as u32).to_le_bytes());
-        concatenated_data.extend_from_slice(header_bytes);
-
-        // Concatenate actual ciphertext data
-        concatenated_data.extend_from_slice(&a.data);
-        concatenated_data.extend_from_slice(&b.data);
-
-        // Calculate remaining noise budget (conservative estimate)
-        let noise_budget = match (a.noise_budget, b.noise_budget) {
-            (Some(a_budget), Some(b_budget)) => {
-                let min_budget = a_budget.min(b_budget);
-                Some(min_budget.saturating_sub(3)) // Subtract cost of operation
-            }
-            _ => None,
-        };
-
-        log::info!(
-            "Successfully concatenated ciphertexts {} + {} -> new size: {} bytes",
-            a.id,
-            b.id,
-            concatenated_data.len()
-        );
-
-        Ok(Ciphertext {
-            id: Uuid::new_v4(),
-            data: concatenated_data,
-            params: a.params.clone(),
-            noise_budget,
-        })
-    }
-
-    /// Process encrypted prompt through homomorphic operations
-    pub fn process_encrypted_prompt(&self, ciphertext: &Ciphertext) -> Result<Ciphertext> {
-        log::debug!("Processing encrypted prompt {}", ciphertext.id);
-
-        // Simulate processing by applying transformation to encrypted data
-        let processed_data = ciphertext.data.clone();
-
-        // Add processing header to indicate transformation
-        let header = b"PROCESSED:";
-        let mut result_data = header.to_vec();
-        result_data.extend_from_slice(&processed_data);
-
-        Ok(Ciphertext {
-            id: Uuid::new_v4(),
-            data: result_data,
-            params: ciphertext.params.clone(),
-            noise_budget: ciphertext.noise_budget.map(|b| b.saturating_sub(5)),
-        })
-    }
-
-    /// Validate ciphertext integrity
-    pub fn validate_ciphertext(&self, ciphertext: &Ciphertext) -> Result<bool> {
-        // Check noise budget
-        if let Some(budget) = ciphertext.noise_budget {
-            if budget < 10 {
-                log::warn!("Low noise budget: {} bits", budget);
-                return Ok(false);
-            }
-        }
-
-        // Check parameter consistency
-        if ciphertext.params.poly_modulus_degree != self.params.poly_modulus_degree {
-            return Err(Error::Fhe("Parameter mismatch".to_string()));
-        }
-
-        Ok(true)
-    }
-
-    /// Get engine statistics
-    pub fn get_stats(&self) -> FheStats {
-        FheStats {
-            total_client_keys: self.client_keys.len(),
-            total_server_keys: self.server_keys.len(),
-            params: self.params.clone(),
-        }
-    }
-
-    /// Create default engine with standard parameters
-    pub fn default() -> Result<Self> {
-        Self::new(FheParams::default())
-    }
-
-    /// Get encryption parameters
-    pub fn get_params(&self) -> &FheParams {
-        &self.params
-    }
-
-    /// Validate engine state for health checks
-    pub fn validate_state(&self) -> Result<()> {
-        if self.client_keys.is_empty() && self.server_keys.is_empty() {
-            return Err(Error::Configuration("No keys generated".to_string()));
-        }
-
-        // Check if parameters are valid
-        if self.params.poly_modulus_degree == 0 {
-            return Err(Error::Configuration("Invalid FHE parameters".to_string()));
-        }
-
-        Ok(())
-    }
-
-    /// Estimate computation cost for operation
-    pub fn estimate_cost(&self, operation: &str, input_size: usize) -> Result<u64> {
-        let base_cost = match operation {
-            "encrypt" => input_size as u64 * 100,
-            "decrypt" => input_size as u64 * 80,
-            "add" => input_size as u64 * 50,
-            "multiply" => input_size as u64 * 200,
-            "concatenate" => input_size as u64 * 10,
-            _ => return Err(Error::Fhe(format!("Unknown operation: {}", operation))),
-        };
-
-        // Scale by security level
-        let security_multiplier = self.params.security_level as u64 / 64;
-        Ok(base_cost * security_multiplier)
-    }
-
-    /// Calculate noise budget for ciphertext
-    fn calculate_noise_budget(&self, data_size: usize) -> u64 {
-        let base_budget: u64 = 60; // Starting noise budget
-        let size_penalty = (data_size / 100) as u64; // Penalty for larger data
-        let security_bonus = (self.params.security_level as u64 / 32).saturating_sub(1);
-
-        base_budget
-            .saturating_sub(size_penalty)
-            .saturating_add(security_bonus)
-    }
-
-    /// Validate ciphertext format and metadata
-    pub fn validate_ciphertext_format(&self, ciphertext: &Ciphertext) -> Result<()> {
-        if ciphertext.data.len() < 8 {
-            return Err(Error::Fhe("Ciphertext data too short".to_string()));
-        }
-
-        // Extract metadata header
-        let metadata_len = u32::from_le_bytes([
-            ciphertext.data[0],
-            ciphertext.data[1],
-            ciphertext.data[2],
-            ciphertext.data[3],
-        ]) as usize;
-
-        if metadata_len > 100 || ciphertext.data.len() < 4 + metadata_len {
-            return Err(Error::Fhe("Invalid ciphertext metadata".to_string()));
-        }
-
-        let metadata = String::from_utf8_lossy(&ciphertext.data[4..4 + metadata_len]);
-
-        if !metadata.starts_with("FHE-v1|") {
-            return Err(Error::Fhe("Unsupported ciphertext version".to_string()));
-        }
-
-        log::debug!(
-            "Validated ciphertext {} with metadata: {}",
-            ciphertext.id,
-            metadata
-        );
-        Ok(())
-    }
-
-    /// Enhanced decrypt with validation and retry logic
-    pub fn decrypt_text_safe(&self, client_id: Uuid, ciphertext: &Ciphertext) -> Result<String> {
-        let _client_key = self
-            .client_keys
-            .get(&client_id)
-            .ok_or_else(|| Error::Fhe("Client key not found".to_string()))?;
-
-        log::debug!(
-            "Decrypting ciphertext {} for client {}",
-            ciphertext.id,
-            client_id
-        );
-
-        // Validate noise budget before attempting decryption
-        if let Some(budget) = ciphertext.noise_budget {
-            if budget < 5 {
-                return Err(Error::Fhe(format!(
-                    "Insufficient noise budget for decryption: {} bits (minimum 5 required)",
-                    budget
-                )));
-            }
-        }
-
-        // Check if this is a processed ciphertext
-        let data = if ciphertext.data.starts_with(b"PROCESSED:") {
-            &ciphertext.data[10..] // Skip "PROCESSED:" prefix
-        } else {
-            &ciphertext.data
-        };
-
-        // Validate ciphertext format on clean data
-        let temp_ciphertext = Ciphertext {
-            id: ciphertext.id,
-            data: data.to_vec(),
-            params: ciphertext.params.clone(),
-            noise_budget: ciphertext.noise_budget,
-        };
-
-        self.validate_ciphertext_format(&temp_ciphertext)?;
-        self.validate_ciphertext(&temp_ciphertext)?;
-
-        // Extract metadata header
-        if data.len() < 4 {
-            return Err(Error::Fhe("Invalid ciphertext metadata".to_string()));
-        }
-
-        let metadata_len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
-
-        if data.len() < 4 + metadata_len {
-            return Err(Error::Fhe("Invalid ciphertext metadata length".to_string()));
-        }
-
-        // Skip metadata and decrypt the actual data
-        let encrypted_bits = &data[4 + metadata_len..];
-        let mut text_bytes = Vec::new();
-
-        for chunk in encrypted_bits.chunks(8) {
-            if chunk.len() == 8 {
-                let mut byte = 0u8;
-                for (i, &bit_byte) in chunk.iter().enumerate() {
-                    if bit_byte != 0 {
-                        byte |= 1 << i;
-                    }
-                }
-                text_bytes.push(byte);
-            }
-        }
-
-        let result = String::from_utf8(text_bytes)
-            .map_err(|e| Error::Fhe(format!("UTF-8 decode error: {}", e)))?;
-
-        // Additional validation
-        if result.len() > 10_000 {
-            return Err(Error::Fhe("Decrypted text suspiciously long".to_string()));
-        }
-
-        Ok(result)
-    }
-
-    /// Perform key rotation for enhanced security
-    pub fn rotate_keys(&mut self, client_id: Uuid) -> Result<Uuid> {
-        if !self.client_keys.contains_key(&client_id) {
-            return Err(Error::Fhe("Client key not found".to_string()));
-        }
-
-        let new_server_id = Uuid::new_v4();
-        let mut rng = rand::rng();
-        let server_key_data: Vec<u8> = (0..256).map(|_| rng.random()).collect();
-
-        self.server_keys.insert(
-            new_server_id,
-            ServerKey {
-                id: new_server_id,
-                key_data: server_key_data,
-                params: self.params.clone(),
-            },
-        );
-
-        log::info!(
-            "Rotated server key for client {}: new server key {}",
-            client_id,
-            new_server_id
-        );
-        Ok(new_server_id)
-    }
-
-    /// Get encryption statistics
-    pub fn get_encryption_stats(&self) -> EncryptionStats {
-        EncryptionStats {
-            total_client_keys: self.client_keys.len(),
-            total_server_keys: self.server_keys.len(),
-            security_level: self.params.security_level,
-            poly_modulus_degree: self.params.poly_modulus_degree,
-            max_noise_budget: 60,
-        }
-    }
-
-    /// Batch key generation for improved efficiency
-    pub fn generate_key_batch(&mut self, count: usize) -> Result<Vec<(Uuid, Uuid)>> {
-        let mut key_pairs = Vec::with_capacity(count);
-
-        log::info!("Generating batch of {} FHE key pairs", count);
-
-        for i in 0..count {
-            let (client_id, server_id) = self.generate_keys()?;
-            key_pairs.push((client_id, server_id));
-
-            if (i + 1) % 10 == 0 {
-                log::debug!("Generated {}/{} key pairs", i + 1, count);
-            }
-        }
-
-        log::info!("Successfully generated {} key pairs", count);
-        Ok(key_pairs)
-    }
-
-    /// Cleanup expired keys to prevent memory leaks
-    pub fn cleanup_expired_keys(&mut self, max_age: std::time::Duration) -> Result<usize> {
-        let _now = std::time::Instant::now();
-        let removed_count = 0;
-
-        // Note: In a real implementation, you'd store creation timestamps with keys
-        // For simulation, we'll just log the cleanup operation
-        log::debug!(
-            "Performed key cleanup (would remove keys older than {:?})",
-            max_age
-        );
-
-        Ok(removed_count)
-    }
-
-    /// Validate and repair ciphertext if possible
-    pub fn repair_ciphertext(&self, ciphertext: &mut Ciphertext) -> Result<bool> {
-        // Check if noise budget is critically low
-        if let Some(budget) = ciphertext.noise_budget {
-            if budget < 10 {
-                log::warn!(
-                    "Ciphertext {} has critically low noise budget: {} bits",
-                    ciphertext.id,
-                    budget
-                );
-
-                // In a real FHE implementation, bootstrapping would be performed here
-                // For simulation, we'll just log the operation
-                log::info!("Performing bootstrapping on ciphertext {}", ciphertext.id);
-
-                // Simulate bootstrapping by resetting noise budget
-                ciphertext.noise_budget = Some(50);
-                return Ok(true);
-            }
-        }
-
-        Ok(false)
-    }
-
-    /// Generate compressed public key for bandwidth optimization
-    pub fn generate_compressed_public_key(&self, client_id: Uuid) -> Result<Vec<u8>> {
-        let _client_key = self
-            .client_keys
-            .get(&client_id)
-            .ok_or_else(|| Error::Fhe("Client key not found".to_string()))?;
-
-        // Simulate compressed public key generation
-        let mut rng = rand::rng();
-        let compressed_key: Vec<u8> = (0..64).map(|_| rng.random()).collect();
-
-        log::debug!(
-            "Generated compressed public key for client {}: {} bytes",
-            client_id,
-            compressed_key.len()
-        );
-
-        Ok(compressed_key)
-    }
-}
-
-/// Encryption statistics
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct EncryptionStats {
-    pub total_client_keys: usize,
-    pub total_server_keys: usize,
-    pub security_level: u8,
-    pub poly_modulus_degree: usize,
-    pub max_noise_budget: u64,
-}
-
-impl Default for FheEngine {
-    fn default() -> Self {
-        Self::new(FheParams::default()).expect("Failed to create FHE engine")
-    }
-}
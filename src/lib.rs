@@ -1,25 +1,15 @@
 //! Homomorphic LLM Proxy Library
 //!
-//! Core library for FHE-based LLM inference proxy.
-
-pub mod config;
-// pub mod deployment; // Temporarily disabled due to compilation issues
-pub mod error;
-pub mod fhe;
-// pub mod global_scaling; // Temporarily disabled due to compilation issues  
-pub mod health;
-pub mod i18n;
-pub mod middleware;
-pub mod monitoring;
-// pub mod observability; // Temporarily disabled due to compilation issues
-pub mod performance;
-pub mod performance_optimized;
-pub mod proxy;
-// pub mod resilience; // Temporarily disabled due to compilation issues
-pub mod scaling;
-pub mod security;
-pub mod security_enhanced;
-pub mod validation;
+//! Meta-crate over the workspace: everything from `proxy-server` is
+//! re-exported at the root, so existing `homomorphic_llm_proxy::` paths keep
+//! working. Embedders should use the [`api`] module, which is covered by
+//! semantic versioning; the other modules are internal and may change in any
+//! release.
+//!
+//! Applications that only talk to a running proxy can depend on
+//! [`proxy_client`] (and [`fhe_core`] for the FHE types) instead, without the
+//! server's HTTP stack, storage backends or GPU bindings.
 
-pub use config::Config;
-pub use error::{Error, Result};
+pub use fhe_core;
+pub use proxy_client;
+pub use proxy_server::*;
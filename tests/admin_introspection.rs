@@ -0,0 +1,519 @@
+//! What the proxy reports about itself and its tenants, read through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    add_admin_token, add_tenant_keys, config_with_provider, provider, tenant_key, with_tenant,
+    Proxy, ADMIN,
+};
+use homomorphic_llm_proxy::config::{Config, SessionLimitPolicy, SlaClass, TenantOverrides};
+use serde_json::json;
+use std::collections::BTreeSet;
+
+#[tokio::test]
+async fn test_capabilities_and_tenant_config_reflect_overrides() {
+    let provider = provider().await;
+    let config = with_tenant(
+        config_with_provider("primary", &provider.url()),
+        "acme",
+        TenantOverrides {
+            rate_limit_per_minute: Some(7),
+            denied_models: Some(vec!["unvetted".to_string()]),
+            ..Default::default()
+        },
+    );
+    let mut config = config;
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, capabilities) = proxy
+        .call(
+            "GET",
+            "/v1/capabilities",
+            &[("x-api-key", "key-acme")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(capabilities["tenant"], "acme");
+    assert_eq!(capabilities["limits"]["rate_limit_per_minute"], 7);
+    assert_eq!(capabilities["models"]["denied"], json!(["unvetted"]));
+    assert_eq!(capabilities["providers"], json!(["primary"]));
+    let params = proxy.get("/v1/params").await;
+    assert_eq!(capabilities["fhe"]["profiles"][0]["params"], params);
+
+    // A tenant reads its own config and nobody else's; admins read any
+    let acme = [("x-api-key", "key-acme")];
+    let config = proxy.get_with("/v1/admin/tenants/acme/config", &acme).await;
+    assert_eq!(config["rate_limit_per_minute"], 7);
+    assert_eq!(
+        config["overridden"],
+        json!(["rate_limit_per_minute", "denied_models"])
+    );
+    let (status, _, _) = proxy
+        .call("GET", "/v1/admin/tenants/acme/config", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .call("GET", "/v1/admin/tenants/globex/config", &acme, None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let global = proxy
+        .get_with("/v1/admin/tenants/globex/config", &[ADMIN])
+        .await;
+    assert_eq!(global["overridden"], json!([]));
+    assert_eq!(global["denied_models"], json!([]));
+
+    // Refreshing is for admins only
+    let refresh = "/v1/admin/tenants/acme/config/refresh";
+    let (status, _, _) = proxy.call("POST", refresh, &acme, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, refreshed) = proxy.call("POST", refresh, &[ADMIN], None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(refreshed["rate_limit_per_minute"], 7);
+}
+
+#[tokio::test]
+async fn test_negotiation_names_the_parameters_that_differ() {
+    let provider = provider().await;
+    let proxy = Proxy::new(config_with_provider("primary", &provider.url())).await;
+    let mut params = proxy.get("/v1/params").await;
+
+    let (_, _, same) = proxy
+        .call("POST", "/v1/params/negotiate", &[], Some(params.clone()))
+        .await;
+    assert_eq!(same["compatible"], true);
+
+    params["poly_modulus_degree"] = json!(8192);
+    let (status, _, negotiated) = proxy
+        .call("POST", "/v1/params/negotiate", &[], Some(params))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(negotiated["compatible"], false);
+    assert_eq!(negotiated["differences"], json!(["poly_modulus_degree"]));
+    assert_eq!(negotiated["params_hash"], same["params_hash"]);
+}
+
+#[tokio::test]
+async fn test_usage_is_reported_per_version_class_and_tenant() {
+    let provider = provider().await;
+    let mut config = with_tenant(
+        config_with_provider("primary", &provider.url()),
+        "acme",
+        TenantOverrides {
+            sla_class: Some(SlaClass::Gold),
+            ..Default::default()
+        },
+    );
+    config.billing.enabled = true;
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+
+    let (status, headers, body) = proxy
+        .complete("primary", "llama", &[("x-api-key", "key-acme")], "hi")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(headers["x-api-version"], "v1");
+    let request_id = headers["x-request-id"].to_str().unwrap();
+
+    let versions = proxy.get("/v1/admin/api-versions").await;
+    let v1 = &versions["versions"][0];
+    assert_eq!(v1["version"], "v1");
+    assert_eq!(v1["tenants"]["acme"], 1);
+
+    let sla = proxy.get("/v1/admin/sla").await;
+    let gold = sla["classes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|class| class["sla_class"] == "gold")
+        .unwrap();
+    assert_eq!(gold["requests"], 1);
+
+    let billing = proxy.get("/v1/admin/billing?tenant=acme").await;
+    let records = billing["records"].as_array().unwrap();
+    assert_eq!(records.len(), 1, "{}", billing);
+    assert_eq!(records[0]["request_id"], request_id);
+    assert_eq!(records[0]["route"], "/v1/chat/completions");
+    assert_eq!(
+        records[0]["response_bytes"],
+        headers["content-length"]
+            .to_str()
+            .unwrap()
+            .parse::<u64>()
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_sla_class_follows_the_authenticated_tenant() {
+    let provider = provider().await;
+    let mut config = with_tenant(
+        config_with_provider("primary", &provider.url()),
+        "acme",
+        TenantOverrides {
+            sla_class: Some(SlaClass::Gold),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+
+    // A silver tenant can neither claim a gold tenant nor ask for more than its ceiling
+    let (status, _, _) = proxy
+        .complete(
+            "primary",
+            "llama",
+            &[("x-api-key", "key-globex"), ("x-tenant-id", "acme")],
+            "hi",
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = proxy
+        .complete("primary", "llama", &[("x-tenant-id", "acme")], "hi")
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, headers, body) = proxy
+        .complete(
+            "primary",
+            "llama",
+            &[
+                ("x-api-key", "key-globex"),
+                ("x-request-priority", "critical"),
+            ],
+            "hi",
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(headers["x-request-priority"], "normal");
+
+    let (status, headers, body) = proxy
+        .complete("primary", "llama", &[("x-api-key", "key-acme")], "hi")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(headers["x-request-priority"], "high");
+
+    let sla = proxy.get("/v1/admin/sla").await;
+    let gold = sla["classes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|class| class["sla_class"] == "gold")
+        .unwrap();
+    assert_eq!(gold["requests"], 1, "{}", sla);
+}
+
+#[tokio::test]
+async fn test_billing_ledger_reconciles_with_the_audit_log() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.billing.enabled = true;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    for tenant in ["acme", "globex"] {
+        let (status, _, body) = proxy
+            .complete(
+                "primary",
+                "llama",
+                &[("x-api-key", &tenant_key(tenant))],
+                "hi",
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    let report = proxy.get("/v1/admin/billing/reconcile?tenant=acme").await;
+    assert_eq!(report["balanced"], true, "{}", report);
+    assert_eq!(report["tenant"], "acme");
+    assert_eq!(report["ledger"]["requests"], 1);
+    assert_eq!(report["ledger"], report["audit"]);
+
+    // Both tenants' completions and the untagged encrypts
+    let all = proxy.get("/v1/admin/billing/reconcile").await;
+    assert_eq!(all["balanced"], true, "{}", all);
+    assert_eq!(all["ledger"]["requests"], 4);
+    assert_eq!(all["ledger"], all["audit"]);
+
+    let before = proxy.get("/v1/admin/billing/reconcile?from=0&to=1").await;
+    assert_eq!(before["ledger"]["requests"], 0);
+    assert_eq!(before["balanced"], true);
+}
+
+#[tokio::test]
+async fn test_flags_target_tenants_and_roll_out_to_a_share_of_users() {
+    let mut config = Config::default();
+    add_tenant_keys(&mut config, &["acme", "globex", "initech"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let flag = json!({
+        "name": "beta",
+        "enabled": true,
+        "rollout_percent": 50.0,
+        "tenants": ["acme"],
+        "excluded_tenants": ["initech"]
+    });
+    // Flags change behaviour for every tenant, so only operators may set them
+    let acme = ("x-api-key", "key-acme");
+    let (status, _, _) = proxy
+        .call("PUT", "/v1/admin/flags/beta", &[acme], Some(flag.clone()))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .call(
+            "PUT",
+            "/v1/admin/flags/beta",
+            &[("x-admin-token", "guessed")],
+            Some(flag.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, body) = proxy
+        .call("PUT", "/v1/admin/flags/beta", &[ADMIN], Some(flag.clone()))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let mut invalid = flag.clone();
+    invalid["rollout_percent"] = json!(150.0);
+    let (status, _, _) = proxy
+        .call("PUT", "/v1/admin/flags/beta", &[ADMIN], Some(invalid))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let flags_for = |tenant: &'static str, user: String| {
+        let proxy = &proxy;
+        async move {
+            let (_, _, body) = proxy
+                .call(
+                    "GET",
+                    "/v1/flags",
+                    &[("x-api-key", &tenant_key(tenant)), ("x-user-hash", &user)],
+                    None,
+                )
+                .await;
+            body["flags"]["beta"].as_bool().unwrap()
+        }
+    };
+    let mut on = 0;
+    for user in 0..40 {
+        let user = format!("user-{}", user);
+        assert!(flags_for("acme", user.clone()).await);
+        assert!(!flags_for("initech", user.clone()).await);
+        let rolled_out = flags_for("globex", user.clone()).await;
+        // A user's place in the rollout is stable
+        assert_eq!(flags_for("globex", user).await, rolled_out);
+        on += rolled_out as usize;
+    }
+    assert!(on > 0 && on < 40, "{} of 40 users", on);
+
+    let status = proxy.get("/v1/admin/flags").await;
+    assert_eq!(status["flags"][0]["name"], "beta");
+    assert_eq!(status["flags"][0]["source"], "store");
+
+    let (status, _, _) = proxy
+        .call("DELETE", "/v1/admin/flags/beta", &[acme], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .call("DELETE", "/v1/admin/flags/beta", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn test_performance_stats_select_subsystems_time_ranges_and_formats() {
+    let provider = provider().await;
+    let proxy = Proxy::new(config_with_provider("primary", &provider.url())).await;
+    let (status, _, _) = proxy.complete("primary", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let everything = proxy.get("/v1/admin/performance").await;
+    assert!(everything.get("prompt_packing").is_some());
+    assert_eq!(everything["provider_resume"]["primary"]["interrupted"], 0);
+
+    let selected = proxy
+        .get("/v1/admin/performance?subsystems=provider_resume,provider_timeouts")
+        .await;
+    let mut keys: Vec<_> = selected.as_object().unwrap().keys().collect();
+    keys.sort();
+    assert_eq!(keys, ["provider_resume", "provider_timeouts"]);
+    assert_eq!(
+        selected["provider_timeouts"]["primary"]["llama"]["samples"],
+        1
+    );
+
+    let (status, headers, text) = proxy
+        .text("/v1/admin/performance?subsystems=provider_timeouts&format=prometheus")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "text/plain; version=0.0.4");
+    assert!(text.starts_with("# TYPE fhe_performance_provider_timeouts gauge\n"));
+    assert!(
+        text.contains("fhe_performance_provider_timeouts{path=\"primary.llama.samples\"} 1 "),
+        "{}",
+        text
+    );
+    assert!(!text.contains("fhe_performance_provider_resume"));
+
+    // Ranges are answered from the recorded history, which only the running
+    // server samples
+    let history = proxy
+        .get("/v1/admin/performance?subsystems=documents&since=0&until=4102444800")
+        .await;
+    assert_eq!(history, json!({ "samples": [], "retained": 0 }));
+
+    for query in [
+        "subsystems=cache",
+        "since=20&until=10",
+        "format=xml",
+        "since=yesterday",
+    ] {
+        let (status, _, _) = proxy
+            .call(
+                "GET",
+                &format!("/v1/admin/performance?{}", query),
+                &[],
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_metrics_only_reports_cataloged_metrics() {
+    let provider = provider().await;
+    let proxy = Proxy::new(config_with_provider("primary", &provider.url())).await;
+    let catalog: BTreeSet<_> = proxy
+        .get("/metrics/catalog")
+        .await
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|metric| metric["name"].as_str().unwrap().to_string())
+        .collect();
+
+    let metrics = proxy.get("/metrics").await;
+    let reported = metrics.as_object().unwrap();
+    assert!(reported.contains_key("requests"));
+    assert_eq!(reported["document_workers"]["workers"], 4);
+    if cfg!(target_os = "linux") {
+        assert!(reported["memory"]["rss_bytes"].as_u64().unwrap() > 0);
+    }
+    // Only what the allocator actually reports
+    assert!(reported["memory"].get("largest_free_block").is_none());
+    for name in reported.keys() {
+        assert!(catalog.contains(name), "{} is not in the catalog", name);
+    }
+}
+
+#[tokio::test]
+async fn test_session_cap_rejects_or_evicts_per_api_key() {
+    let provider = provider().await;
+    let config = with_tenant(
+        config_with_provider("primary", &provider.url()),
+        "acme",
+        TenantOverrides {
+            max_sessions: Some(1),
+            ..Default::default()
+        },
+    );
+    let config = with_tenant(
+        config,
+        "globex",
+        TenantOverrides {
+            max_sessions: Some(1),
+            session_limit_policy: Some(SessionLimitPolicy::EvictOldestIdle),
+            ..Default::default()
+        },
+    );
+    let mut config = config;
+    add_tenant_keys(&mut config, &["acme", "globex", "acme-batch"]);
+    // A second key issued to acme, capped on its own
+    config.access.tenant_keys.last_mut().unwrap().tenant = "acme".to_string();
+    let proxy = Proxy::new(config).await;
+    let generate = |tenant: &'static str| {
+        let proxy = &proxy;
+        async move {
+            proxy
+                .call(
+                    "POST",
+                    "/v1/keys/generate",
+                    &[("x-api-key", &tenant_key(tenant))],
+                    None,
+                )
+                .await
+        }
+    };
+
+    let (status, _, _) = generate("acme").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = generate("acme").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let (status, _, _) = generate("acme-batch").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Evicting the older session releases its key
+    let (_, _, first) = generate("globex").await;
+    let (status, _, _) = generate("globex").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/encrypt",
+            &[],
+            Some(json!({ "text": "hello", "client_id": first["client_id"] })),
+        )
+        .await;
+    assert_ne!(status, StatusCode::OK);
+
+    let stats = proxy.get("/v1/admin/sessions").await;
+    assert_eq!(stats["active_by_tenant"], json!({ "acme": 2, "globex": 1 }));
+    assert_eq!(stats["rejected"], 1);
+    assert_eq!(stats["evicted_for_limit"], 1);
+
+    // A capped session cannot be opened without an API key
+    let mut config = config_with_provider("primary", &provider.url());
+    config.sessions.max_sessions_per_api_key = 1;
+    let proxy = Proxy::new(config).await;
+    let (status, _, _) = proxy.call("POST", "/v1/keys/generate", &[], None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_runbook_controls_require_an_admin_and_record_them() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    add_admin_token(&mut config);
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+    let held = format!("/v1/admin/runbooks/{}", uuid::Uuid::new_v4());
+    let tenant = [("x-api-key", "key-acme")];
+
+    for (path, headers) in [
+        ("/v1/admin/runbooks/reset".to_string(), &[][..]),
+        ("/v1/admin/runbooks/reset".to_string(), &tenant[..]),
+        (format!("{held}/approve"), &[][..]),
+        (format!("{held}/reject"), &tenant[..]),
+    ] {
+        let (status, _, _) = proxy.call("POST", &path, headers, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", path);
+    }
+    let (status, _, _) = proxy
+        .call("POST", &format!("{held}/approve"), &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = proxy
+        .call("POST", "/v1/admin/runbooks/reset", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let audit = proxy.get_with("/v1/admin/audit", &[ADMIN]).await;
+    let reset = audit["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|record| record["action"] == "runbook_reset")
+        .expect("reset audited");
+    assert_eq!(reset["details"]["admin"], "ops");
+}
@@ -0,0 +1,307 @@
+//! API versions, session renewal and stream flow control, driven through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    add_tenant_keys, completion, completion_request, config_with_provider, Events, Proxy,
+};
+use homomorphic_llm_proxy::config::{ApiVersionLifecycle, Config};
+use serde_json::json;
+use test_utils::MockProxy;
+
+#[tokio::test]
+async fn test_v2_renames_ciphertext_fields_and_wraps_errors() {
+    let provider = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "ok"),
+    );
+    let proxy = Proxy::new(config_with_provider("primary", &provider.url())).await;
+
+    let (status, headers, keys) = proxy.call("POST", "/v2/keys/generate", &[], None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["x-api-version"], "v2");
+    let (status, _, encrypted) = proxy
+        .call(
+            "POST",
+            "/v2/encrypt",
+            &[],
+            Some(json!({ "text": "hello", "client_id": keys["client_id"] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", encrypted);
+    assert!(encrypted["ciphertext"].is_string(), "{}", encrypted);
+    assert!(encrypted.get("encrypted_data").is_none());
+
+    let (status, _, body) = proxy
+        .call(
+            "POST",
+            "/v2/chat/completions",
+            &[],
+            Some(json!({
+                "ciphertext_id": encrypted["ciphertext_id"],
+                "ciphertext": encrypted["ciphertext"],
+                "provider": "primary",
+                "model": "llama",
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(provider.requests().len(), 1);
+
+    let (status, _, body) = proxy
+        .call(
+            "GET",
+            &format!("/v2/ciphertext/{}", uuid::Uuid::new_v4()),
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["status"], 404);
+    // v1 keeps its empty error bodies
+    let (_, _, body) = proxy
+        .call(
+            "GET",
+            &format!("/v1/ciphertext/{}", uuid::Uuid::new_v4()),
+            &[],
+            None,
+        )
+        .await;
+    assert!(body.is_null());
+}
+
+#[tokio::test]
+async fn test_deprecated_version_is_announced_then_retired() {
+    let mut config = Config::default();
+    config.api_versions.lifecycle.push(ApiVersionLifecycle {
+        version: "v1".to_string(),
+        deprecated_at: Some("2026-01-01T00:00:00Z".to_string()),
+        sunset_at: Some("2030-01-01T00:00:00Z".to_string()),
+        link: Some("https://example.com/migrate".to_string()),
+    });
+    let proxy = Proxy::new(config.clone()).await;
+
+    let (status, headers, _) = proxy.call("GET", "/v1/params", &[], None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["deprecation"], "@1767225600");
+    assert_eq!(headers["sunset"], "Tue, 01 Jan 2030 00:00:00 GMT");
+    assert_eq!(
+        headers["link"],
+        "<https://example.com/migrate>; rel=\"deprecation\""
+    );
+    let (_, headers, _) = proxy.call("GET", "/v2/params", &[], None).await;
+    assert!(headers.get("deprecation").is_none());
+
+    config.api_versions.lifecycle[0].sunset_at = Some("2026-06-01T00:00:00Z".to_string());
+    config.api_versions.reject_after_sunset = true;
+    let proxy = Proxy::new(config.clone()).await;
+    let (status, _, _) = proxy.call("GET", "/v1/params", &[], None).await;
+    assert_eq!(status, StatusCode::GONE);
+    proxy.get("/v2/params").await;
+
+    config.api_versions.v2_enabled = false;
+    let proxy = Proxy::new(config).await;
+    let (status, _, _) = proxy.call("GET", "/v2/params", &[], None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_session_tokens_refresh_and_keys_renew() {
+    let mut config = Config::default();
+    config.sessions.renewal.enabled = true;
+    // Shorter than the hint window, so every response hints a token refresh
+    config.sessions.renewal.access_token_ttl_seconds = 60;
+    let proxy = Proxy::new(config).await;
+
+    let (_, _, session) = proxy.call("POST", "/v1/keys/generate", &[], None).await;
+    let session_id = session["session_id"].as_str().unwrap();
+    let tokens = &session["tokens"];
+    let access = tokens["access_token"].as_str().unwrap();
+
+    let (status, headers, _) = proxy
+        .call("GET", "/v1/params", &[("x-session-token", access)], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["x-renewal-hint"], "token");
+    let expires_in: i64 = headers["x-session-token-expires-in"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((0..=60).contains(&expires_in));
+    let (status, _, _) = proxy
+        .call("GET", "/v1/params", &[("x-session-token", "forged")], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let refresh = format!("/v1/sessions/{}/refresh", session_id);
+    let (status, _, renewed) = proxy
+        .call(
+            "POST",
+            &refresh,
+            &[],
+            Some(json!({ "refresh_token": tokens["refresh_token"] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(renewed["access_token"], tokens["access_token"]);
+    // Refresh tokens are single use
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &refresh,
+            &[],
+            Some(json!({ "refresh_token": tokens["refresh_token"] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let renew = format!("/v1/sessions/{}/keys/renew", session_id);
+    let (status, _, _) = proxy.call("POST", &renew, &[], None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (_, _, other) = proxy.call("POST", "/v1/keys/generate", &[], None).await;
+    let other_access = other["tokens"]["access_token"].as_str().unwrap();
+    let (status, _, _) = proxy
+        .call("POST", &renew, &[("x-session-token", other_access)], None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let access = renewed["access_token"].as_str().unwrap();
+    let (status, _, keys) = proxy
+        .call("POST", &renew, &[("x-session-token", access)], None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", keys);
+    assert_eq!(keys["previous_server_id"], session["server_id"]);
+    assert_ne!(keys["server_id"], session["server_id"]);
+    assert!(keys["previous_valid_until"].is_i64());
+}
+
+/// Stream the completion of `text` for acme; returns the stream's events and ID
+async fn open_stream(proxy: &Proxy, text: &str) -> (Events, String) {
+    let encrypted = proxy.encrypt(text).await;
+    let response = proxy
+        .send(
+            "POST",
+            "/v1/chat/stream",
+            &[("x-api-key", "key-acme")],
+            Some(completion_request(&encrypted, "primary", "llama")),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let stream_id = response.headers()["x-stream-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    (Events::new(response), stream_id)
+}
+
+fn stream_config() -> Config {
+    let mut config = Config::default();
+    config.performance.response_chunking.chunk_size_bytes = 1;
+    config.scaling.stream_flow_control.chunk_interval_ms = 100;
+    config.scaling.stream_flow_control.max_pause_seconds = 1;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    config
+}
+
+#[tokio::test]
+async fn test_stream_pauses_and_resumes_on_client_control() {
+    let proxy = Proxy::new(stream_config()).await;
+    let (mut events, stream_id) = open_stream(&proxy, "chunked prompt").await;
+    let (name, open) = events.next().await.unwrap();
+    assert_eq!(name, "open");
+    let total_chunks = open["total_chunks"].as_u64().unwrap();
+    assert!(total_chunks > 2, "{}", open);
+
+    let control = format!("/v1/chat/stream/{}/control", stream_id);
+    let pause = json!({ "action": "pause" });
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &control,
+            &[("x-api-key", "key-globex")],
+            Some(pause.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, accepted) = proxy
+        .call("POST", &control, &[("x-api-key", "key-acme")], Some(pause))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(accepted["accepted"], true);
+
+    loop {
+        let (name, _) = events.next().await.unwrap();
+        if name == "paused" {
+            break;
+        }
+        assert_eq!(name, "chunk");
+    }
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &control,
+            &[("x-api-key", "key-acme")],
+            Some(json!({ "action": "resume" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(events.next().await.unwrap().0, "resumed");
+
+    let end = loop {
+        let (name, frame) = events.next().await.unwrap();
+        if name == "end" {
+            break frame;
+        }
+    };
+    assert_eq!(end["reason"], "complete");
+    assert_eq!(end["chunks_sent"], total_chunks);
+
+    let stats = proxy.get("/v1/admin/streams").await;
+    assert_eq!(stats["streams"]["opened"], 1);
+    assert_eq!(stats["streams"]["pauses"], 1);
+    assert_eq!(stats["streams"]["pause_timeouts"], 0);
+}
+
+#[tokio::test]
+async fn test_stream_paused_too_long_is_ended() {
+    let proxy = Proxy::new(stream_config()).await;
+    let (mut events, stream_id) = open_stream(&proxy, "chunked prompt").await;
+    events.next().await.unwrap();
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &format!("/v1/chat/stream/{}/control", stream_id),
+            &[("x-api-key", "key-acme")],
+            Some(json!({ "action": "pause" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let end = loop {
+        let (name, frame) = events.next().await.unwrap();
+        if name == "end" {
+            break frame;
+        }
+    };
+    assert_eq!(end["reason"], "pause_timeout");
+    assert!(events.next().await.is_none());
+    let stats = proxy.get("/v1/admin/streams").await;
+    assert_eq!(stats["streams"]["pause_timeouts"], 1);
+
+    let mut config = stream_config();
+    config.scaling.stream_flow_control.enabled = false;
+    let proxy = Proxy::new(config).await;
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &format!("/v1/chat/stream/{}/control", stream_id),
+            &[("x-api-key", "key-acme")],
+            Some(json!({ "action": "pause" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
@@ -0,0 +1,363 @@
+//! Drives the proxy's router in-process, the way a client would over HTTP
+
+#![allow(dead_code)]
+
+use axum::body::{Body, BodyDataStream};
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use futures::StreamExt;
+use homomorphic_llm_proxy::config::{
+    AdminTokenConfig, Config, OpenAiCompatibleServer, ProviderAuth, TenantKeyConfig,
+    TenantOverrides,
+};
+use homomorphic_llm_proxy::proxy::ProxyServer;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use test_utils::MockProxy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tower::ServiceExt;
+
+/// The proxy's router, built from `config`
+pub struct Proxy {
+    app: Router,
+}
+
+impl Proxy {
+    pub async fn new(config: Config) -> Self {
+        let server = ProxyServer::new(config).expect("proxy server");
+        Self {
+            app: server.create_router().await,
+        }
+    }
+
+    /// Send one request; the body is JSON when present
+    pub async fn call(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let response = self.send(method, path, headers, body).await;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, headers, body)
+    }
+
+    /// GET a plain-text endpoint
+    pub async fn text(&self, path: &str) -> (StatusCode, HeaderMap, String) {
+        let response = self.send("GET", path, &[], None).await;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            headers,
+            String::from_utf8_lossy(&bytes).into_owned(),
+        )
+    }
+
+    /// Send one request and return the response before its body is read
+    pub async fn send(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> Response {
+        let mut request = Request::builder().method(method).uri(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        // Declare the length like an HTTP client would; some layers only read sized bodies
+        let request = match body.map(|body| body.to_string()) {
+            Some(body) => request
+                .header("content-type", "application/json")
+                .header("content-length", body.len())
+                .body(Body::from(body)),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        self.app.clone().oneshot(request).await.unwrap()
+    }
+
+    /// Serve the router on a local port, for other proxies to reach; returns its URL
+    pub async fn serve(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = self
+            .app
+            .clone()
+            .into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    pub async fn get(&self, path: &str) -> Value {
+        self.get_with(path, &[]).await
+    }
+
+    /// GET sending `headers`, such as an admin token
+    pub async fn get_with(&self, path: &str, headers: &[(&str, &str)]) -> Value {
+        let (status, _, body) = self.call("GET", path, headers, None).await;
+        assert_eq!(status, StatusCode::OK, "GET {}: {}", path, body);
+        body
+    }
+
+    /// Generate a client key; returns its client ID
+    pub async fn generate_keys(&self) -> Value {
+        let (status, _, keys) = self.call("POST", "/v1/keys/generate", &[], None).await;
+        assert_eq!(status, StatusCode::OK, "key generation: {}", keys);
+        keys["client_id"].clone()
+    }
+
+    /// Generate a key and encrypt `text` under it; returns the encrypt response
+    pub async fn encrypt(&self, text: &str) -> Value {
+        let client_id = self.generate_keys().await;
+        self.encrypt_for(&client_id, text).await
+    }
+
+    /// Encrypt `text` under the key of `client_id`; returns the encrypt response
+    pub async fn encrypt_for(&self, client_id: &Value, text: &str) -> Value {
+        let (status, _, encrypted) = self
+            .call(
+                "POST",
+                "/v1/encrypt",
+                &[],
+                Some(json!({ "text": text, "client_id": client_id })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "encryption: {}", encrypted);
+        encrypted
+    }
+
+    /// Encrypt `text` and complete it with `provider`
+    pub async fn complete(
+        &self,
+        provider: &str,
+        model: &str,
+        headers: &[(&str, &str)],
+        text: &str,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let encrypted = self.encrypt(text).await;
+        let request = completion_request(&encrypted, provider, model);
+        self.call("POST", "/v1/chat/completions", headers, Some(request))
+            .await
+    }
+}
+
+/// Server-sent events of a streaming response, read as they arrive
+pub struct Events {
+    body: BodyDataStream,
+    buffer: String,
+}
+
+impl Events {
+    pub fn new(response: Response) -> Self {
+        Self {
+            body: response.into_body().into_data_stream(),
+            buffer: String::new(),
+        }
+    }
+
+    /// The next event's name and JSON data; None once the stream has ended
+    pub async fn next(&mut self) -> Option<(String, Value)> {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let event: String = self.buffer.drain(..end + 2).collect();
+                let mut name = String::from("message");
+                let mut data = String::new();
+                for line in event.lines() {
+                    if let Some(value) = line.strip_prefix("event:") {
+                        name = value.trim().to_string();
+                    } else if let Some(value) = line.strip_prefix("data:") {
+                        data.push_str(value.trim());
+                    }
+                }
+                // Keep-alive comments carry no data
+                if data.is_empty() {
+                    continue;
+                }
+                return Some((name, serde_json::from_str(&data).unwrap()));
+            }
+            let bytes = self.body.next().await?.unwrap();
+            self.buffer.push_str(std::str::from_utf8(&bytes).unwrap());
+        }
+    }
+}
+
+/// A `/v1/chat/completions` body for the ciphertext of an encrypt response
+pub fn completion_request(encrypted: &Value, provider: &str, model: &str) -> Value {
+    json!({
+        "ciphertext_id": encrypted["ciphertext_id"],
+        "encrypted_data": encrypted["encrypted_data"],
+        "provider": provider,
+        "model": model,
+    })
+}
+
+/// Default configuration with an OpenAI-compatible provider named `name` at
+/// `url` (without the `/v1` suffix)
+pub fn config_with_provider(name: &str, url: &str) -> Config {
+    let mut config = Config::default();
+    add_provider(&mut config, name, url);
+    config
+}
+
+/// A provider answering every chat completion with "ok"
+pub async fn provider() -> MockProxy {
+    MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "ok"),
+    )
+}
+
+/// `config` with `overrides` for `tenant`
+pub fn with_tenant(mut config: Config, tenant: &str, overrides: TenantOverrides) -> Config {
+    config
+        .tenants
+        .overrides
+        .insert(tenant.to_string(), overrides);
+    config
+}
+
+pub fn add_provider(config: &mut Config, name: &str, url: &str) {
+    config.llm.openai_compatible.push(OpenAiCompatibleServer {
+        name: name.to_string(),
+        base_url: format!("{}/v1", url),
+        model_urls: BTreeMap::new(),
+        auth: ProviderAuth::None,
+    });
+    config.validation.allowed_providers.push(name.to_string());
+}
+
+/// API key the tests issue to `tenant`
+pub fn tenant_key(tenant: &str) -> String {
+    format!("key-{}", tenant)
+}
+
+/// Issue each of `tenants` its `tenant_key`
+pub fn add_tenant_keys(config: &mut Config, tenants: &[&str]) {
+    for tenant in tenants {
+        config.access.tenant_keys.push(TenantKeyConfig {
+            tenant: tenant.to_string(),
+            sha256: sha256_hex(&tenant_key(tenant)),
+        });
+    }
+}
+
+/// Admin token the tests issue, and the header carrying it
+pub const ADMIN: (&str, &str) = ("x-admin-token", "admin-token");
+
+/// Issue the `ADMIN` token to an operator named "ops"
+pub fn add_admin_token(config: &mut Config) {
+    config.access.admin_tokens.push(AdminTokenConfig {
+        name: "ops".to_string(),
+        sha256: sha256_hex(ADMIN.1),
+    });
+}
+
+fn sha256_hex(value: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, value.as_bytes());
+    digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A provider's `chat.completion` body
+pub fn completion(model: &str, content: &str) -> Value {
+    json!({
+        "id": "cmpl-1",
+        "object": "chat.completion",
+        "created": 0,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": "stop"
+        }],
+        "usage": { "prompt_tokens": 7, "completion_tokens": 5, "total_tokens": 12 }
+    })
+}
+
+/// A provider that accepts connections and never answers; returns its URL
+pub async fn hanging_provider() -> String {
+    scripted_provider(Vec::new()).await
+}
+
+/// A provider answering each connection with the next raw HTTP response of
+/// `responses`, written as is: one shorter than its `Content-Length` is a
+/// connection cut mid-body. Connections after the last response are held
+/// open unanswered. Returns its URL.
+pub async fn scripted_provider(responses: Vec<String>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        for response in responses {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            read_request(&mut socket).await;
+            let _ = socket.write_all(response.as_bytes()).await;
+            let _ = socket.flush().await;
+        }
+        let mut open = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            open.push(stream);
+        }
+    });
+    url
+}
+
+/// An HTTP response with a JSON body
+pub fn http_response(status: u16, headers: &[(&str, &str)], body: &Value) -> String {
+    let body = body.to_string();
+    let mut response = format!(
+        "HTTP/1.1 {} Scripted\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    response + &body
+}
+
+/// Read a request through the end of its body, so closing the connection
+/// afterwards does not reset it
+async fn read_request(socket: &mut TcpStream) {
+    let mut received = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+        }
+        let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") else {
+            continue;
+        };
+        let head = String::from_utf8_lossy(&received[..end]).to_ascii_lowercase();
+        let length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0);
+        if received.len() >= end + 4 + length {
+            return;
+        }
+    }
+}
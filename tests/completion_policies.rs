@@ -0,0 +1,432 @@
+//! Per-tenant and per-key policies enforced on completions through the router
+
+mod common;
+
+use axum::http::{HeaderMap, StatusCode};
+use common::{
+    add_admin_token, add_tenant_keys, completion_request, config_with_provider, provider,
+    with_tenant, Proxy, ADMIN,
+};
+use homomorphic_llm_proxy::config::{SpendingCapPolicy, TenantOverrides};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn test_denied_model_is_refused_and_deprecated_model_upgraded() {
+    let provider = provider().await;
+    let config = with_tenant(
+        config_with_provider("primary", &provider.url()),
+        "acme",
+        TenantOverrides {
+            denied_models: Some(vec!["unvetted".to_string()]),
+            model_upgrades: Some(HashMap::from([(
+                "llama-old".to_string(),
+                "llama".to_string(),
+            )])),
+            ..Default::default()
+        },
+    );
+    let mut config = config;
+    add_tenant_keys(&mut config, &["acme", "other"]);
+    let proxy = Proxy::new(config).await;
+    let tenant = [("x-api-key", "key-acme")];
+
+    let (status, _, _) = proxy.complete("primary", "unvetted", &tenant, "hi").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(provider.requests().is_empty());
+
+    // The deprecated name is served by its replacement, with a warning
+    let (status, headers, body) = proxy.complete("primary", "llama-old", &tenant, "hi").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let warning = headers["warning"].to_str().unwrap();
+    assert!(warning.starts_with("299 - "), "{}", warning);
+    assert!(warning.contains("llama-old"), "{}", warning);
+    assert_eq!(body["model"], "llama");
+    assert_eq!(provider.requests()[0].body["model"], "llama");
+
+    // Other tenants keep the global policy
+    let (status, _, _) = proxy
+        .complete("primary", "unvetted", &[("x-api-key", "key-other")], "hi")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Leaving out the credentials doesn't leave acme's policy behind
+    let (status, _, _) = proxy.complete("primary", "unvetted", &[], "hi").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .complete("primary", "unvetted", &[("x-tenant-id", "other")], "hi")
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(provider.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_repeated_idempotency_key_replays_the_first_response() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let encrypted = proxy.encrypt("hello").await;
+    let request = completion_request(&encrypted, "primary", "llama");
+    let headers = [("x-api-key", "key-acme"), ("idempotency-key", "order-17")];
+
+    let (status, _, first) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &headers,
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", first);
+    let (status, _, second) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &headers,
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["id"], first["id"]);
+    assert_eq!(provider.requests().len(), 1);
+
+    // Keys are scoped to the tenant
+    let (_, _, other) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[("x-api-key", "key-globex"), ("idempotency-key", "order-17")],
+            Some(request),
+        )
+        .await;
+    assert_ne!(other["id"], first["id"]);
+    assert_eq!(provider.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_reused_request_nonce_is_rejected() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+    let encrypted = proxy.encrypt("hello").await;
+    let request = completion_request(&encrypted, "primary", "llama");
+    let headers = [("x-api-key", "key-acme"), ("x-request-nonce", "n-1")];
+
+    let (status, _, body) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &headers,
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _, _) = proxy
+        .call("POST", "/v1/chat/completions", &headers, Some(request))
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_key_policy_limits_operations_and_models() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let client_id = proxy.generate_keys().await;
+    let policy_path = format!("/v1/admin/keys/{}/policy", client_id.as_str().unwrap());
+    let policy = json!({ "operations": ["encrypt", "process"], "models": ["llama"] });
+
+    // Only operators may change a key's policy
+    let (status, _, _) = proxy
+        .call("POST", &policy_path, &[], Some(policy.clone()))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &policy_path,
+            &[("x-admin-token", "not-an-admin")],
+            Some(policy.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, body) = proxy
+        .call("POST", &policy_path, &[ADMIN], Some(policy))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let encrypted = proxy.encrypt_for(&client_id, "hello").await;
+    let (status, headers, _) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[],
+            Some(completion_request(&encrypted, "primary", "mistral")),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(headers["x-error-code"], "key_policy_violation");
+    assert!(provider.requests().is_empty());
+
+    let (status, _, body) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[],
+            Some(completion_request(&encrypted, "primary", "llama")),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // The key may not decrypt, even its own ciphertext
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/decrypt",
+            &[],
+            Some(json!({
+                "ciphertext_id": encrypted["ciphertext_id"],
+                "client_id": client_id
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_spending_cap_refuses_billed_requests_until_overridden() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.billing.enabled = true;
+    config.billing.pricing.per_request = 1.0;
+    config.billing.pricing.per_request_mib = 0.0;
+    config.billing.pricing.per_response_mib = 0.0;
+    config.billing.spending_caps.enabled = true;
+    add_admin_token(&mut config);
+    let config = with_tenant(
+        config,
+        "acme",
+        TenantOverrides {
+            monthly_spending_cap: Some(1.5),
+            spending_cap_policy: Some(SpendingCapPolicy::Reject),
+            ..Default::default()
+        },
+    );
+    let mut config = config;
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+    let client_id = proxy.generate_keys().await;
+    let encrypt = || {
+        proxy.call(
+            "POST",
+            "/v1/encrypt",
+            &[("x-api-key", "key-acme")],
+            Some(json!({ "text": "hello", "client_id": client_id })),
+        )
+    };
+
+    let (status, _, body) = encrypt().await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _, body) = encrypt().await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(body["error"], "Monthly spending cap reached");
+
+    // Neither dropping the credentials nor naming a sandbox key gets around the cap
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/encrypt",
+            &[],
+            Some(json!({ "text": "hello", "client_id": client_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/encrypt",
+            &[("x-api-key", "key-acme"), ("x-sandbox-key", "sbx_made_up")],
+            Some(json!({ "text": "hello", "client_id": client_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+
+    // Only an admin can lift the cap, and is recorded as having done so
+    let lift = |headers: &'static [(&'static str, &'static str)]| {
+        proxy.call(
+            "POST",
+            "/v1/admin/billing/caps/acme/override",
+            headers,
+            Some(json!({ "reason": "quarter end" })),
+        )
+    };
+    let (status, _, _) = lift(&[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = lift(&[("x-api-key", "key-acme")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, body) = lift(&[ADMIN]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["override"]["granted_by"], "ops");
+    let (status, _, body) = encrypt().await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+async fn restrict(proxy: &Proxy, client_id: &Value, policy: Value) {
+    let (status, _, body) = proxy
+        .call(
+            "POST",
+            &format!("/v1/admin/keys/{}/policy", client_id.as_str().unwrap()),
+            &[ADMIN],
+            Some(policy),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn test_key_policy_follows_ciphertexts_into_combined_and_submitted_work() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.scaling.batch_windows.enabled = true;
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let model_bound = proxy.generate_keys().await;
+    restrict(
+        &proxy,
+        &model_bound,
+        json!({ "operations": ["encrypt", "process"], "models": ["llama"] }),
+    )
+    .await;
+    let encrypt_only = proxy.generate_keys().await;
+    restrict(&proxy, &encrypt_only, json!({ "operations": ["encrypt"] })).await;
+    let decrypt_only = proxy.generate_keys().await;
+    restrict(&proxy, &decrypt_only, json!({ "operations": ["decrypt"] })).await;
+    let greeting = proxy.encrypt_for(&model_bound, "Hello. ").await;
+    let question = proxy.encrypt_for(&model_bound, "What is FHE?").await;
+    let leaked = proxy.encrypt_for(&encrypt_only, "secret").await;
+    let refused = |status: StatusCode, headers: &HeaderMap| {
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(headers["x-error-code"], "key_policy_violation");
+    };
+    let complete = |ciphertext_id: Value, model: &str| {
+        let request = json!({ "ciphertext_id": ciphertext_id, "encrypted_data": "" });
+        proxy.call(
+            "POST",
+            "/v1/chat/completions",
+            &[],
+            Some(completion_request(&request, "primary", model)),
+        )
+    };
+
+    // Derived ciphertexts keep the model restriction of their inputs
+    let (status, _, combined) = proxy
+        .call(
+            "POST",
+            "/v1/concatenate",
+            &[],
+            Some(json!({
+                "ciphertext_a": greeting["ciphertext_id"],
+                "ciphertext_b": question["ciphertext_id"],
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", combined);
+    let (status, headers, _) = complete(combined["result_ciphertext_id"].clone(), "mistral").await;
+    refused(status, &headers);
+    let (status, _, body) = complete(combined["result_ciphertext_id"].clone(), "llama").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, _, committed) = proxy
+        .call(
+            "POST",
+            "/v1/transactions",
+            &[],
+            Some(json!({
+                "steps": [
+                    { "id": "greeting", "op": "load", "ciphertext_id": greeting["ciphertext_id"] },
+                    { "id": "more", "op": "encrypt", "client_id": model_bound, "text": "More?" },
+                    { "id": "prompt", "op": "concatenate", "a": "greeting", "b": "more" },
+                ],
+                "outputs": ["prompt"],
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", committed);
+    let (status, headers, _) = complete(
+        committed["outputs"]["prompt"]["ciphertext_id"].clone(),
+        "mistral",
+    )
+    .await;
+    refused(status, &headers);
+
+    // Keys that may not process can't have their ciphertexts combined
+    let (status, headers, _) = proxy
+        .call(
+            "POST",
+            "/v1/concatenate",
+            &[],
+            Some(json!({
+                "ciphertext_a": greeting["ciphertext_id"],
+                "ciphertext_b": leaked["ciphertext_id"],
+            })),
+        )
+        .await;
+    refused(status, &headers);
+    for step in [
+        json!({ "id": "out", "op": "load", "ciphertext_id": leaked["ciphertext_id"] }),
+        json!({ "id": "out", "op": "encrypt", "client_id": decrypt_only, "text": "hi" }),
+    ] {
+        let (status, headers, _) = proxy
+            .call(
+                "POST",
+                "/v1/transactions",
+                &[],
+                Some(json!({ "steps": [step], "outputs": ["out"] })),
+            )
+            .await;
+        refused(status, &headers);
+    }
+
+    // Submitted ciphertext bytes are matched to the key that produced them
+    let (status, headers, _) = proxy
+        .call(
+            "POST",
+            "/v1/documents",
+            &[],
+            Some(json!({
+                "encrypted_data": greeting["encrypted_data"],
+                "provider": "primary",
+                "model": "mistral",
+            })),
+        )
+        .await;
+    refused(status, &headers);
+    let (status, headers, _) = proxy
+        .call(
+            "POST",
+            "/v1/batch/jobs",
+            &[],
+            Some(json!({
+                "items": [question["encrypted_data"]],
+                "provider": "primary",
+                "model": "mistral",
+            })),
+        )
+        .await;
+    refused(status, &headers);
+
+    let (status, headers, _) = proxy
+        .call(
+            "POST",
+            &format!("/v1/aggregations/{}/decrypt", uuid::Uuid::new_v4()),
+            &[],
+            Some(json!({ "client_id": encrypt_only })),
+        )
+        .await;
+    refused(status, &headers);
+    assert_eq!(provider.requests().len(), 1);
+}
@@ -0,0 +1,389 @@
+//! Redaction policies, validators, conversation context and delegated
+//! decryption, driven through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use base64::prelude::*;
+use common::{
+    add_admin_token, add_tenant_keys, completion_request, config_with_provider, provider,
+    tenant_key, Proxy, ADMIN,
+};
+use homomorphic_llm_proxy::config::{
+    Config, CustomValidatorConfig, CustomValidatorKind, DelegationFallback, ReplayCacheKind,
+    TenantOverrides,
+};
+use homomorphic_llm_proxy::redaction::{apply_redaction, verify_policy_bundle, SignedPolicyBundle};
+use homomorphic_llm_proxy::security::JwkSet;
+use serde_json::{json, Value};
+use test_utils::MockProxy;
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_redaction_policy_is_signed_served_and_acknowledged() {
+    let mut config = Config::default();
+    config.tenants.redaction.enabled = true;
+    config.encryption.response_signing.enabled = true;
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+
+    let publish = |pattern: &str| json!({ "rules": [{ "id": "ssn", "pattern": pattern }] });
+    let path = "/v1/admin/tenants/acme/redaction-policy";
+    // Only an admin publishes; a tenant can't loosen its own policy
+    for headers in [&[][..], &[("x-api-key", "key-acme")][..]] {
+        let (status, _, _) = proxy
+            .call("POST", path, headers, Some(publish("nothing")))
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, _, first) = proxy
+        .call("POST", path, &[ADMIN], Some(publish(r"\d{3}-\d{2}-\d{4}")))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", first);
+    assert_eq!(first["bundle"]["version"], 1);
+    let (status, _, _) = proxy
+        .call("POST", path, &[ADMIN], Some(publish("(unclosed")))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, _, second) = proxy
+        .call("POST", path, &[ADMIN], Some(publish(r"\d{9}")))
+        .await;
+    assert_eq!(second["bundle"]["version"], 2);
+
+    let (status, _, _) = proxy.call("GET", "/v1/redaction-policy", &[], None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let acme = [("x-api-key", "key-acme")];
+    let (status, _, fetched) = proxy
+        .call("GET", "/v1/redaction-policy?version=1", &acme, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = proxy
+        .call("GET", "/v1/redaction-policy?version=9", &acme, None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Clients check the bundle against the published keys before enforcing it
+    let signed: SignedPolicyBundle = serde_json::from_value(fetched).unwrap();
+    let jwks: JwkSet = serde_json::from_value(proxy.get("/.well-known/jwks.json").await).unwrap();
+    verify_policy_bundle(&signed, &jwks, "acme").unwrap();
+    assert!(verify_policy_bundle(&signed, &jwks, "globex").is_err());
+    let (redacted, count) = apply_redaction(&signed.bundle.policy, "ssn 123-45-6789").unwrap();
+    assert_eq!(redacted, "ssn [REDACTED]");
+    assert_eq!(count, 1);
+
+    let behind = uuid::Uuid::new_v4();
+    for (client_id, version) in [(behind, 1), (uuid::Uuid::new_v4(), 2)] {
+        let (status, _, _) = proxy
+            .call(
+                "POST",
+                "/v1/redaction-policy/ack",
+                &acme,
+                Some(json!({ "client_id": client_id, "version": version })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/redaction-policy/ack",
+            &acme,
+            Some(json!({ "client_id": behind, "version": 9 })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let acknowledgments = proxy
+        .get("/v1/admin/tenants/acme/redaction-policy/acknowledgments")
+        .await;
+    assert_eq!(acknowledgments["current_version"], 2);
+    assert_eq!(acknowledgments["clients"].as_array().unwrap().len(), 2);
+    assert_eq!(acknowledgments["outdated_clients"], 1);
+
+    // Bundles cannot be signed without the response signing key
+    let mut config = Config::default();
+    config.tenants.redaction.enabled = true;
+    add_admin_token(&mut config);
+    let (status, _, _) = Proxy::new(config)
+        .await
+        .call("POST", path, &[ADMIN], Some(publish(r"\d{9}")))
+        .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn test_custom_validator_rejects_matching_requests_of_its_tenants() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.validation.custom.push(CustomValidatorConfig {
+        name: "no-llama".to_string(),
+        kind: CustomValidatorKind::DenyPattern,
+        field: "model".to_string(),
+        pattern: "^llama".to_string(),
+        tenants: vec!["acme".to_string()],
+        status: 451,
+    });
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, _) = proxy
+        .complete("primary", "llama", &[("x-api-key", "key-acme")], "hello")
+        .await;
+    assert_eq!(status.as_u16(), 451);
+    assert!(provider.requests().is_empty());
+    let (status, _, body) = proxy
+        .complete("primary", "llama", &[("x-api-key", "key-globex")], "hello")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let stats = proxy.get("/v1/admin/validation").await;
+    let validator = stats["validators"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|validator| validator["name"] == "no-llama")
+        .unwrap_or_else(|| panic!("no custom validator in {}", stats));
+    assert_eq!(validator["rejections"], 1);
+    assert!(validator["invocations"].as_u64().unwrap() >= 1);
+}
+
+#[tokio::test]
+async fn test_bloom_replay_cache_rejects_reused_nonces() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.validation.replay_cache.kind = ReplayCacheKind::Bloom;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let encrypted = proxy.encrypt("hello").await;
+    let request = completion_request(&encrypted, "primary", "llama");
+
+    for (tenant, expected) in [
+        ("acme", StatusCode::OK),
+        ("acme", StatusCode::CONFLICT),
+        // Nonces are remembered per tenant
+        ("globex", StatusCode::OK),
+    ] {
+        let (status, _, _) = proxy
+            .call(
+                "POST",
+                "/v1/chat/completions",
+                &[
+                    ("x-api-key", &tenant_key(tenant)),
+                    ("x-request-nonce", "n-1"),
+                ],
+                Some(request.clone()),
+            )
+            .await;
+        assert_eq!(status, expected, "{}", tenant);
+    }
+    assert_eq!(provider.requests().len(), 2);
+
+    let stats = proxy.get("/v1/admin/validation").await;
+    let replay = stats["validators"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|validator| validator["name"] == "replay")
+        .unwrap_or_else(|| panic!("no replay validator in {}", stats));
+    assert_eq!(replay["rejections"], 1);
+    let details = &replay["details"];
+    assert_eq!(details["kind"], "bloom");
+    assert!(details["memory_bytes"].as_u64().unwrap() > 0);
+    assert!(details["estimated_false_positive_rate"].as_f64().unwrap() < 0.01);
+}
+
+#[tokio::test]
+async fn test_conversation_turns_spill_to_compressed_storage() {
+    let mut config = Config::default();
+    config.conversations.enabled = true;
+    config.conversations.hot_turns = 2;
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            compress_context: Some(true),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let acme = [("x-api-key", "key-acme")];
+    let (_, _, session) = proxy.call("POST", "/v1/keys/generate", &acme, None).await;
+    let turns = format!(
+        "/v1/conversations/{}/turns",
+        session["session_id"].as_str().unwrap()
+    );
+
+    // Repetitive enough to compress, and over the compression threshold
+    let ciphertext = BASE64_STANDARD.encode([7u8; 1024]);
+    for turn in 0..3 {
+        let (status, _, appended) = proxy
+            .call(
+                "POST",
+                &turns,
+                &acme,
+                Some(json!({ "role": "user", "encrypted_data": ciphertext })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", appended);
+        assert_eq!(appended["turn"], turn);
+    }
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &turns,
+            &acme,
+            Some(json!({ "role": "user", "encrypted_data": "not base64!" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &turns,
+            &[("x-api-key", "key-globex")],
+            Some(json!({ "role": "user", "encrypted_data": ciphertext })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, read) = proxy.call("GET", &turns, &acme, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let read = read["turns"].as_array().unwrap();
+    let tiers: Vec<&Value> = read.iter().map(|turn| &turn["tier"]).collect();
+    assert_eq!(tiers, ["warm", "hot", "hot"]);
+    // Compression is invisible to readers
+    assert!(read
+        .iter()
+        .all(|turn| turn["ciphertext"] == ciphertext.as_str()));
+    let (status, _, _) = proxy
+        .call("GET", &turns, &[("x-api-key", "key-globex")], None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let stats = proxy.get("/v1/admin/conversations").await;
+    assert_eq!(stats["hot_turns"], 2);
+    assert_eq!(stats["warm"]["writes"], 1);
+    assert_eq!(stats["compression"]["compressed_turns"], 1);
+    assert!(stats["compression"]["saved_bytes"].as_u64().unwrap() > 0);
+    assert_eq!(stats["compression"]["decompressions"], 1);
+}
+
+/// Decrypt a fresh ciphertext of "hello" as `tenant`
+async fn decrypt(proxy: &Proxy, tenant: &str) -> (StatusCode, Value, Value) {
+    let client_id = proxy.generate_keys().await;
+    let encrypted = proxy.encrypt_for(&client_id, "hello").await;
+    let (status, _, decrypted) = proxy
+        .call(
+            "POST",
+            "/v1/decrypt",
+            &[("x-api-key", &tenant_key(tenant))],
+            Some(json!({
+                "ciphertext_id": encrypted["ciphertext_id"],
+                "client_id": client_id,
+            })),
+        )
+        .await;
+    (status, client_id, decrypted)
+}
+
+#[tokio::test]
+async fn test_decryption_is_delegated_to_the_hsm() {
+    let hsm = MockProxy::start().await.respond(
+        "POST",
+        "/decrypt",
+        200,
+        json!({ "plaintext": BASE64_STANDARD.encode("from the hsm") }),
+    );
+    let mut config = Config::default();
+    config.encryption.decryption_delegation.enabled = true;
+    config.encryption.decryption_delegation.endpoint = hsm.url();
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+
+    let (status, client_id, decrypted) = decrypt(&proxy, "acme").await;
+    assert_eq!(status, StatusCode::OK, "{}", decrypted);
+    assert_eq!(decrypted["plaintext"], "from the hsm");
+    assert_eq!(decrypted["decrypted_by"], "delegated");
+    let calls = hsm.requests();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(
+        calls[0].body["key_handle"],
+        format!("fhe-client-{}", client_id.as_str().unwrap())
+    );
+
+    let stats = proxy.get("/v1/admin/decryption").await;
+    assert_eq!(stats["decryption_delegation"]["delegated"], 1);
+}
+
+#[tokio::test]
+async fn test_keys_of_tenants_that_require_delegation_go_to_the_hsm() {
+    let hsm = MockProxy::start()
+        .await
+        .respond("POST", "/keys", 200, json!({}));
+    let mut config = Config::default();
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            require_delegated_decryption: Some(true),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme"]);
+    let acme = [("x-api-key", "key-acme")];
+
+    // Without an HSM to hold them, the proxy generates no keys for acme
+    let proxy = Proxy::new(config.clone()).await;
+    let (status, _, _) = proxy.call("POST", "/v1/keys/generate", &acme, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    config.encryption.decryption_delegation.enabled = true;
+    config.encryption.decryption_delegation.endpoint = hsm.url();
+    let proxy = Proxy::new(config).await;
+    let (status, _, keys) = proxy.call("POST", "/v1/keys/generate", &acme, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", keys);
+    let handle = format!("fhe-client-{}", keys["client_id"].as_str().unwrap());
+    assert_eq!(keys["key_handle"], handle.as_str());
+    let calls = hsm.requests();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].body["key_handle"], handle.as_str());
+    assert!(calls[0].body["key"].is_string());
+}
+
+#[tokio::test]
+async fn test_unreachable_hsm_falls_back_unless_the_tenant_requires_it() {
+    // Nothing listens where the HSM should be
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let unreachable = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let mut config = Config::default();
+    config.encryption.decryption_delegation.enabled = true;
+    config.encryption.decryption_delegation.endpoint = unreachable;
+    config.encryption.decryption_delegation.fallback = DelegationFallback::Local;
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            require_delegated_decryption: Some(true),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config.clone()).await;
+
+    let (status, _, decrypted) = decrypt(&proxy, "globex").await;
+    assert_eq!(status, StatusCode::OK, "{}", decrypted);
+    assert_eq!(decrypted["plaintext"], "hello");
+    assert_eq!(decrypted["decrypted_by"], "local");
+    // The HSM is still cooling down, and acme's keys may not leave it
+    let (status, _, _) = decrypt(&proxy, "acme").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    let stats = proxy.get("/v1/admin/decryption").await;
+    let stats = &stats["decryption_delegation"];
+    assert_eq!(stats["unreachable"], 1);
+    assert_eq!(stats["fallbacks"], 1);
+    assert_eq!(stats["cooling_down"], true);
+
+    config.encryption.decryption_delegation.fallback = DelegationFallback::FailClosed;
+    let (status, _, _) = decrypt(&Proxy::new(config).await, "globex").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
@@ -0,0 +1,127 @@
+//! Engine identity, table reloads and GPU placement checked through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{add_admin_token, Proxy, ADMIN};
+use homomorphic_llm_proxy::config::{Config, FheOperation, GpuFallbackPolicy};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_pool_engines_match_the_reference_fingerprint() {
+    let proxy = Proxy::new(Config::default()).await;
+    let report = proxy.get("/v1/admin/engines/fingerprints").await;
+
+    let engines = report["engines"].as_array().unwrap();
+    assert!(!engines.is_empty(), "{}", report);
+    for engine in engines {
+        assert_eq!(engine["compatible"], true, "{}", report);
+        assert_eq!(
+            engine["fingerprint"]["params_digest"],
+            report["reference"]["params_digest"]
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_table_reload_swaps_in_a_new_generation() {
+    let mut config = Config::default();
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let before = proxy.get("/v1/admin/engines/tables").await;
+    assert_eq!(before["reloading"], false);
+    let generation = before["tables"]["generation"].as_u64().unwrap();
+
+    let (status, _, _) = proxy
+        .call("POST", "/v1/admin/engines/tables/reload", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, body) = proxy
+        .call("POST", "/v1/admin/engines/tables/reload", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    assert_eq!(body["reloading"], true);
+
+    // The tables are computed in the background; encryption keeps working
+    let client_id = proxy.generate_keys().await;
+    proxy.encrypt_for(&client_id, "hello").await;
+    let mut after = Value::Null;
+    for _ in 0..200 {
+        after = proxy.get("/v1/admin/engines/tables").await;
+        if after["reloading"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(after["reloading"], false, "{}", after);
+    assert_eq!(after["tables"]["generation"], generation + 1);
+    assert_eq!(
+        after["tables"]["swaps"],
+        before["tables"]["swaps"].as_u64().unwrap() + 1
+    );
+
+    let audit = proxy.get_with("/v1/admin/audit", &[ADMIN]).await;
+    let records = audit["records"].as_array().unwrap();
+    let reload = records
+        .iter()
+        .find(|record| record["action"] == "engine.tables.reload")
+        .expect("reload audited");
+    assert_eq!(reload["details"]["admin"], "ops");
+    assert!(
+        records
+            .iter()
+            .any(|record| record["action"] == "engine.tables.swap"),
+        "{}",
+        audit
+    );
+}
+
+#[tokio::test]
+async fn test_operations_without_a_gpu_follow_their_fallback_policy() {
+    // Built without the gpu feature, so every operation finds the GPU absent
+    let mut config = Config::default();
+    config.gpu.enabled = true;
+    config
+        .gpu
+        .fallback
+        .policies
+        .insert(FheOperation::Encrypt, GpuFallbackPolicy::Reject);
+    let proxy = Proxy::new(config).await;
+
+    let client_id = proxy.generate_keys().await;
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/encrypt",
+            &[],
+            Some(json!({ "text": "hello", "client_id": client_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    let metrics = proxy.get("/metrics").await;
+    let execution = &metrics["execution"];
+    assert_eq!(execution["gpu_enabled"], true, "{}", execution);
+    assert_eq!(execution["gpu_present"], false);
+    assert_eq!(execution["operations"]["encrypt"]["rejected"], 1);
+    assert_eq!(execution["operations"]["key_generation"]["cpu_fallback"], 1);
+}
+
+#[tokio::test]
+async fn test_only_admins_restore_a_failure_domain() {
+    let mut config = Config::default();
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let restore = "/v1/admin/failure-domains/zone/eu-west-1a/restore";
+
+    let (status, _, _) = proxy.call("POST", restore, &[], None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .call("POST", restore, &[("x-admin-token", "guessed")], None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    // Nothing is drained, so there is nothing to restore
+    let (status, _, _) = proxy.call("POST", restore, &[ADMIN], None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
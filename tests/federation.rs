@@ -0,0 +1,125 @@
+//! Federation relays and replicated sessions between proxies, driven through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::Proxy;
+use homomorphic_llm_proxy::config::{Config, FederationPeerConfig};
+use serde_json::json;
+
+fn node(id: &str, peer: &str, endpoint: &str, shared_secret: &str) -> Config {
+    let mut config = Config::default();
+    config.federation.enabled = true;
+    config.federation.node_id = id.to_string();
+    config.federation.peers.push(FederationPeerConfig {
+        id: peer.to_string(),
+        endpoint: endpoint.to_string(),
+        shared_secret: shared_secret.to_string(),
+        requests_per_minute: 1,
+    });
+    config
+}
+
+#[tokio::test]
+async fn test_ciphertext_is_processed_by_a_federated_peer() {
+    let partner = Proxy::new(node("partner", "origin", "http://unused", "link-secret")).await;
+    let partner_url = partner.serve().await;
+    let origin = Proxy::new(node("origin", "partner", &partner_url, "link-secret")).await;
+
+    let encrypted = origin.encrypt("hello").await;
+    let forward = json!({
+        "ciphertext_id": encrypted["ciphertext_id"],
+        "peer": "partner",
+        "model": "llama",
+    });
+    let (status, _, forwarded) = origin
+        .call("POST", "/v1/federation/forward", &[], Some(forward.clone()))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", forwarded);
+    assert_eq!(forwarded["destination"], "partner");
+    // The processed ciphertext comes back into the origin's cache
+    let processed = forwarded["processed_ciphertext_id"].as_str().unwrap();
+    origin.get(&format!("/v1/ciphertext/{}", processed)).await;
+
+    let peers = partner.get("/v1/federation/peers").await;
+    assert_eq!(peers["node_id"], "partner");
+    assert_eq!(peers["peers"][0]["id"], "origin");
+    assert_eq!(peers["peers"][0]["metrics"]["received"], 1);
+    // The partner allows the origin one relay a minute
+    let (status, _, _) = origin
+        .call("POST", "/v1/federation/forward", &[], Some(forward))
+        .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    let peers = partner.get("/v1/federation/peers").await;
+    assert_eq!(peers["peers"][0]["metrics"]["quota_exceeded"], 1);
+
+    let (status, _, _) = Proxy::new(Config::default())
+        .await
+        .call(
+            "POST",
+            "/v1/federation/forward",
+            &[],
+            Some(json!({
+                "ciphertext_id": uuid::Uuid::new_v4(),
+                "peer": "partner",
+                "model": "llama",
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_envelopes_sealed_with_another_secret_are_rejected() {
+    let partner = Proxy::new(node("partner", "origin", "http://unused", "link-secret")).await;
+    let partner_url = partner.serve().await;
+    let origin = Proxy::new(node("origin", "partner", &partner_url, "wrong-secret")).await;
+
+    let encrypted = origin.encrypt("hello").await;
+    let (status, _, _) = origin
+        .call(
+            "POST",
+            "/v1/federation/forward",
+            &[],
+            Some(json!({
+                "ciphertext_id": encrypted["ciphertext_id"],
+                "peer": "partner",
+                "model": "llama",
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    let peers = partner.get("/v1/federation/peers").await;
+    assert_eq!(peers["peers"][0]["metrics"]["rejected"], 1);
+}
+
+#[tokio::test]
+async fn test_sessions_are_exported_with_their_region_versions() {
+    let mut config = Config::default();
+    config.persistence.replication.enabled = true;
+    config.persistence.replication.region = "eu-west".to_string();
+    let proxy = Proxy::new(config).await;
+
+    let (_, _, session) = proxy.call("POST", "/v1/keys/generate", &[], None).await;
+    let (status, _, exported) = proxy
+        .call("GET", "/v1/admin/replication/sessions", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let record = exported
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|record| record["id"] == session["session_id"])
+        .unwrap_or_else(|| panic!("session not exported: {}", exported));
+    assert!(
+        record["version"]["eu-west"].as_u64().unwrap() >= 1,
+        "{}",
+        record
+    );
+    assert_eq!(record["client_id"], session["client_id"]);
+
+    let replication = proxy.get("/v1/admin/replication").await;
+    assert_eq!(replication["enabled"], true);
+    assert_eq!(replication["region"], "eu-west");
+    assert_eq!(replication["stats"]["rounds"], 0);
+}
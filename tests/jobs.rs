@@ -0,0 +1,522 @@
+//! Documents, batch jobs, aggregations, transactions, key escrow and tenant
+//! offboarding driven through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use base64::prelude::*;
+use common::{
+    add_admin_token, add_tenant_keys, config_with_provider, provider, tenant_key, with_tenant,
+    Proxy, ADMIN,
+};
+use homomorphic_llm_proxy::config::{
+    Config, EscrowCustodianConfig, SessionLimitPolicy, TenantOverrides,
+};
+use homomorphic_llm_proxy::escrow::open_share;
+use homomorphic_llm_proxy::persistence::EscrowShareRecord;
+use ring::agreement::{EphemeralPrivateKey, X25519};
+use ring::rand::SystemRandom;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Generate a key for `tenant` and encrypt `text` under it
+async fn encrypt_as(proxy: &Proxy, tenant: &str, text: &str) -> (Value, Value) {
+    let key = tenant_key(tenant);
+    let headers = [("x-api-key", key.as_str())];
+    let (status, _, keys) = proxy
+        .call("POST", "/v1/keys/generate", &headers, None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", keys);
+    let client_id = keys["client_id"].clone();
+    let (status, _, encrypted) = proxy
+        .call(
+            "POST",
+            "/v1/encrypt",
+            &headers,
+            Some(json!({ "text": text, "client_id": client_id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", encrypted);
+    (client_id, encrypted)
+}
+
+#[tokio::test]
+async fn test_document_is_chunked_and_processed_in_the_background() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.documents.chunk_size_bytes = 8;
+    config.documents.max_document_bytes = 4096;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let (_, encrypted) = encrypt_as(&proxy, "acme", "a short document to summarize").await;
+    let document = json!({
+        "encrypted_data": encrypted["encrypted_data"],
+        "provider": "primary",
+        "model": "llama",
+    });
+
+    let (status, _, submitted) = proxy
+        .call(
+            "POST",
+            "/v1/documents",
+            &[("x-api-key", "key-acme")],
+            Some(document.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", submitted);
+    let status_url = submitted["status_url"].as_str().unwrap().to_string();
+
+    let mut job = Value::Null;
+    for _ in 0..100 {
+        let (status, _, current) = proxy
+            .call("GET", &status_url, &[("x-api-key", "key-acme")], None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        job = current;
+        if job["status"] == "completed" || job["status"] == "failed" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(job["status"], "completed", "{}", job);
+    assert!(job["total_chunks"].as_u64().unwrap() > 1, "{}", job);
+    assert_eq!(job["chunks"].as_array().unwrap().len(), job["total_chunks"]);
+    let aggregate = job["aggregate_ciphertext_id"].as_str().unwrap();
+    let (status, _, _) = proxy
+        .call("GET", &format!("/v1/ciphertext/{}", aggregate), &[], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Jobs are private to the submitting tenant
+    let (status, _, _) = proxy
+        .call("GET", &status_url, &[("x-api-key", "key-globex")], None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Documents are only dispatched for an authenticated tenant
+    let (status, _, _) = proxy
+        .call("POST", "/v1/documents", &[], Some(document.clone()))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let mut mismatched = document.clone();
+    mismatched["params_hash"] = json!("not-the-server-params");
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/documents",
+            &[("x-api-key", "key-acme")],
+            Some(mismatched),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let mut oversized = document;
+    oversized["encrypted_data"] = json!(BASE64_STANDARD.encode(vec![0u8; 4097]));
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/documents",
+            &[("x-api-key", "key-acme")],
+            Some(oversized),
+        )
+        .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_batch_job_is_queued_charged_and_cancelled() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.scaling.batch_windows.enabled = true;
+    config.scaling.batch_windows.quota_discount = 0.5;
+    config.scaling.batch_windows.daily_quota_units = 1.0;
+    config.aggregation.enabled = true;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let (_, encrypted) = encrypt_as(&proxy, "acme", "overnight report").await;
+    let job = json!({
+        "encrypted_data": encrypted["encrypted_data"],
+        "provider": "primary",
+        "model": "llama",
+    });
+    let submit = || {
+        proxy.call(
+            "POST",
+            "/v1/batch/jobs",
+            &[("x-api-key", "key-acme")],
+            Some(job.clone()),
+        )
+    };
+
+    let (status, _, first) = submit().await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", first);
+    assert_eq!(first["status"], "queued");
+    let (status, _, _) = submit().await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    // Two half-price jobs spent the day's single unit
+    let (status, _, _) = submit().await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(provider.requests().is_empty());
+
+    let job_url = format!("/v1/batch/jobs/{}", first["job_id"].as_str().unwrap());
+    let (status, _, listed) = proxy
+        .call("GET", "/v1/batch/jobs", &[("x-api-key", "key-acme")], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed["jobs"].as_array().unwrap().len(), 2);
+    assert!(listed["jobs"][0].get("ciphertext").is_none());
+    let (status, _, _) = proxy
+        .call("GET", &job_url, &[("x-api-key", "key-globex")], None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Unfinished jobs can't be aggregated
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/aggregations",
+            &[("x-api-key", "key-acme")],
+            Some(json!({ "operation": "sum", "batch_job_ids": [first["job_id"]] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _, cancelled) = proxy
+        .call("DELETE", &job_url, &[("x-api-key", "key-acme")], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(cancelled["status"], "cancelled");
+    let (status, _, _) = proxy
+        .call("DELETE", &job_url, &[("x-api-key", "key-acme")], None)
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    // The refund makes room for another job
+    let (status, _, _) = submit().await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
+
+#[tokio::test]
+async fn test_aggregation_refuses_too_few_or_non_numeric_items() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.aggregation.enabled = true;
+    config.aggregation.min_items = 2;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let (_, first) = encrypt_as(&proxy, "acme", "not a number").await;
+    let (_, second) = encrypt_as(&proxy, "acme", "nor this").await;
+    let aggregate = |ids: Value| {
+        proxy.call(
+            "POST",
+            "/v1/aggregations",
+            &[("x-api-key", "key-acme")],
+            Some(json!({ "operation": "sum", "ciphertext_ids": ids })),
+        )
+    };
+
+    // A single item would give its value away
+    let (status, _, _) = aggregate(json!([first["ciphertext_id"]])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = aggregate(json!([first["ciphertext_id"], second["ciphertext_id"]])).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _, _) = aggregate(json!([first["ciphertext_id"], uuid::Uuid::new_v4()])).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/aggregations",
+            &[],
+            Some(json!({ "operation": "sum", "ciphertext_ids": [] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_transaction_stores_only_requested_outputs_of_a_committed_plan() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let (client_id, stored) = encrypt_as(&proxy, "acme", "Hello. ").await;
+    let run = |body: Value| proxy.call("POST", "/v1/transactions", &[], Some(body));
+
+    let (status, _, committed) = run(json!({
+        "steps": [
+            { "id": "greeting", "op": "load", "ciphertext_id": stored["ciphertext_id"] },
+            { "id": "question", "op": "encrypt", "client_id": client_id, "text": "What is FHE?" },
+            { "id": "prompt", "op": "concatenate", "a": "greeting", "b": "question" },
+        ],
+        "outputs": ["prompt"],
+    }))
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", committed);
+    assert_eq!(
+        committed["steps"],
+        json!({ "concatenate": 1, "encrypt": 1, "load": 1 })
+    );
+    // Intermediate results are dropped with the transaction
+    let outputs = committed["outputs"].as_object().unwrap();
+    assert_eq!(outputs.keys().collect::<Vec<_>>(), ["prompt"]);
+    let output = outputs["prompt"]["ciphertext_id"].as_str().unwrap();
+    let (status, _, _) = proxy
+        .call("GET", &format!("/v1/ciphertext/{}", output), &[], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A failing step aborts the transaction
+    let (status, _, _) = run(json!({
+        "steps": [
+            { "id": "text", "op": "encrypt", "client_id": client_id, "text": "words" },
+            { "id": "scaled", "op": "multiply_plain", "input": "text", "scalar": 2.0 },
+        ],
+        "outputs": ["scaled"],
+    }))
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    // Plans are checked before anything runs
+    let (status, _, _) = run(json!({
+        "steps": [{ "id": "p", "op": "process", "input": "missing" }],
+        "outputs": ["p"],
+    }))
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = run(json!({
+        "steps": [{ "id": "l", "op": "load", "ciphertext_id": uuid::Uuid::new_v4() }],
+        "outputs": ["l"],
+    }))
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_escrowed_key_is_restored_once_every_custodian_submits() {
+    let provider = provider().await;
+    let rng = SystemRandom::new();
+    let mut privates = Vec::new();
+    let mut custodians = Vec::new();
+    for name in ["legal", "security"] {
+        let private = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+        custodians.push(EscrowCustodianConfig {
+            name: name.to_string(),
+            public_key: BASE64_STANDARD.encode(private.compute_public_key().unwrap()),
+        });
+        privates.push((name, private));
+    }
+    let mut config = with_tenant(
+        config_with_provider("primary", &provider.url()),
+        "acme",
+        TenantOverrides {
+            escrow_custodians: Some(custodians),
+            max_sessions: Some(1),
+            session_limit_policy: Some(SessionLimitPolicy::EvictOldestIdle),
+            ..Default::default()
+        },
+    );
+    config.escrow.enabled = true;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let tenant = [("x-api-key", "key-acme")];
+
+    let (client_id, encrypted) = encrypt_as(&proxy, "acme", "ledger").await;
+    let decrypt = json!({
+        "ciphertext_id": encrypted["ciphertext_id"],
+        "client_id": client_id,
+    });
+    // A second session evicts the first, and its key with it
+    let (status, _, _) = proxy.call("POST", "/v1/keys/generate", &tenant, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = proxy
+        .call("POST", "/v1/decrypt", &[], Some(decrypt.clone()))
+        .await;
+    assert_ne!(status, StatusCode::OK);
+
+    let client = client_id.as_str().unwrap();
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &format!("/v1/escrow/{}/recoveries", client),
+            &[("x-api-key", "key-globex")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, opened) = proxy
+        .call(
+            "POST",
+            &format!("/v1/escrow/{}/recoveries", client),
+            &tenant,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", opened);
+    let recovery_id = opened["recovery"]["recovery_id"].as_str().unwrap();
+    let shares: Vec<EscrowShareRecord> = serde_json::from_value(opened["shares"].clone()).unwrap();
+    let submit_url = format!("/v1/escrow/recoveries/{}/shares", recovery_id);
+
+    let mut outcomes = Vec::new();
+    for ((name, private), share) in privates.into_iter().zip(&shares) {
+        assert_eq!(share.custodian, name);
+        let plain = open_share(private, client.parse().unwrap(), share).unwrap();
+        let (status, _, outcome) = proxy
+            .call(
+                "POST",
+                &submit_url,
+                &tenant,
+                Some(json!({ "custodian": name, "share": BASE64_STANDARD.encode(plain) })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", outcome);
+        outcomes.push(outcome["complete"].clone());
+    }
+    assert_eq!(outcomes, [json!(false), json!(true)]);
+
+    let (status, _, decrypted) = proxy.call("POST", "/v1/decrypt", &[], Some(decrypt)).await;
+    assert_eq!(status, StatusCode::OK, "{}", decrypted);
+    assert_eq!(decrypted["plaintext"], "ledger");
+}
+
+#[tokio::test]
+async fn test_offboarding_destroys_only_the_tenants_data() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let (_, acme) = encrypt_as(&proxy, "acme", "acme secret").await;
+    let (_, globex) = encrypt_as(&proxy, "globex", "globex secret").await;
+    let ciphertext = |encrypted: &Value| {
+        format!(
+            "/v1/ciphertext/{}",
+            encrypted["ciphertext_id"].as_str().unwrap()
+        )
+    };
+    let offboard = |headers: &'static [(&'static str, &'static str)], body: Value| {
+        proxy.call(
+            "POST",
+            "/v1/admin/tenants/acme/offboard",
+            headers,
+            Some(body),
+        )
+    };
+
+    // Only operators may offboard, and only with a confirmation token issued
+    // for that tenant
+    let (status, _, _) = offboard(&[], json!({ "reason": "contract ended" })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, issued) = offboard(&[ADMIN], json!({ "reason": "contract ended" })).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", issued);
+    let confirmation = issued["confirmation"].as_str().unwrap();
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/admin/tenants/globex/offboard",
+            &[ADMIN],
+            Some(json!({ "confirmation": confirmation })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _, _) = offboard(&[ADMIN], json!({ "confirmation": "guessed" })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _, _) = proxy.call("GET", &ciphertext(&acme), &[], None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, signed) = offboard(
+        &[ADMIN],
+        json!({ "reason": "contract ended", "confirmation": confirmation }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", signed);
+    let report = &signed["report"];
+    assert_eq!(report["verified"], true, "{}", report);
+    let sessions = report["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|step| step["step"] == "sessions")
+        .unwrap();
+    assert_eq!(sessions["deleted"]["sessions"], 1, "{}", sessions);
+    let keys = proxy.get("/v1/attestation/keys").await;
+    assert_eq!(signed["key_id"], keys["current"]["key_id"]);
+
+    let (status, _, _) = proxy.call("GET", &ciphertext(&acme), &[], None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = proxy.call("GET", &ciphertext(&globex), &[], None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The tenant is refused from now on
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/keys/generate",
+            &[("x-api-key", "key-acme")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let latest = proxy.get("/v1/admin/tenants/acme/offboarding").await;
+    assert_eq!(latest["report"]["report_id"], report["report_id"]);
+    let (status, _, _) = proxy
+        .call("GET", "/v1/admin/tenants/globex/offboarding", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_dead_letters_are_admin_only() {
+    let mut config = Config::default();
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let letter = format!("/v1/admin/dlq/{}", uuid::Uuid::new_v4());
+    let acme = [("x-api-key", "key-acme")];
+
+    for (method, path) in [
+        ("GET", "/v1/admin/dlq".to_string()),
+        ("DELETE", "/v1/admin/dlq".to_string()),
+        ("GET", letter.clone()),
+        ("DELETE", letter.clone()),
+        ("POST", format!("{letter}/reprocess")),
+    ] {
+        for headers in [&[][..], &acme[..]] {
+            let (status, _, _) = proxy.call(method, &path, headers, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, path);
+        }
+    }
+
+    let listed = proxy.get_with("/v1/admin/dlq", &[ADMIN]).await;
+    assert_eq!(listed["count"], 0);
+    let (status, _, purged) = proxy.call("DELETE", "/v1/admin/dlq", &[ADMIN], None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(purged["purged"], 0);
+    let (status, _, _) = proxy.call("GET", &letter, &[ADMIN], None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_only_admins_replace_the_batch_windows() {
+    let mut config = Config::default();
+    config.scaling.batch_windows.enabled = true;
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let windows = json!([{ "name": "overnight", "start": "01:00", "end": "05:00" }]);
+
+    for headers in [&[][..], &[("x-api-key", "key-acme")][..]] {
+        let (status, _, _) = proxy
+            .call(
+                "PUT",
+                "/v1/admin/batch/windows",
+                headers,
+                Some(windows.clone()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, _, body) = proxy
+        .call("PUT", "/v1/admin/batch/windows", &[ADMIN], Some(windows))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body.to_string().contains("overnight"), "{}", body);
+}
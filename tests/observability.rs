@@ -0,0 +1,202 @@
+//! Latency, tracing, noise budget and backfilled metrics, observed through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{add_admin_token, add_tenant_keys, config_with_provider, provider, Proxy, ADMIN};
+use homomorphic_llm_proxy::config::Config;
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+use test_utils::MockProxy;
+
+#[tokio::test]
+async fn test_latency_heatmap_groups_requests_by_declared_region() {
+    let proxy = Proxy::new(Config::default()).await;
+    for region in ["eu-west", "eu-west", "us-east"] {
+        let (status, _, _) = proxy
+            .call("GET", "/v1/params", &[("x-client-region", region)], None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    // Operational routes are not client latency
+    proxy.get("/v1/admin/latency-heatmap").await;
+
+    let heatmap = proxy.get("/v1/admin/latency-heatmap").await;
+    assert_eq!(heatmap["enabled"], true);
+    let regions = heatmap["heatmap"]["regions"].as_array().unwrap();
+    let requests = |name: &str| {
+        let region = regions
+            .iter()
+            .find(|region| region["region"] == name)
+            .unwrap_or_else(|| panic!("no {} in {}", name, heatmap));
+        let bucketed: u64 = region["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|count| count.as_u64().unwrap())
+            .sum();
+        assert_eq!(region["requests"], bucketed);
+        bucketed
+    };
+    assert_eq!(requests("eu-west"), 2);
+    assert_eq!(requests("us-east"), 1);
+    assert_eq!(regions.len(), 2, "{}", heatmap);
+}
+
+#[tokio::test]
+async fn test_traces_follow_sampled_parents_and_always_keep_errors() {
+    let mut config = Config::default();
+    // No budget for chance sampling, so only parents and errors are kept
+    config.monitoring.trace_sampling.budget_per_minute = 0;
+    let proxy = Proxy::new(config).await;
+
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let sampled = format!("00-{}-00f067aa0ba902b7-01", trace_id);
+    let (_, headers, _) = proxy
+        .call("GET", "/v1/params", &[("traceparent", &sampled)], None)
+        .await;
+    let traceparent = headers["traceparent"].to_str().unwrap();
+    assert!(traceparent.starts_with(&format!("00-{}-", trace_id)));
+    assert!(traceparent.ends_with("-01"));
+    let unsampled = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00";
+    let (_, headers, _) = proxy
+        .call("GET", "/v1/params", &[("traceparent", unsampled)], None)
+        .await;
+    assert!(headers["traceparent"].to_str().unwrap().ends_with("-00"));
+
+    // Decrypting under a key that does not exist fails on the server side
+    let encrypted = proxy.encrypt("hello").await;
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/decrypt",
+            &[],
+            Some(json!({
+                "ciphertext_id": encrypted["ciphertext_id"],
+                "client_id": uuid::Uuid::new_v4(),
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let traces = proxy.get("/v1/admin/traces").await;
+    let spans = traces["spans"].as_array().unwrap();
+    assert_eq!(spans.len(), 2, "{}", traces);
+    assert_eq!(spans[0]["reason"], "error");
+    assert_eq!(spans[0]["status"], 500);
+    assert_eq!(spans[1]["reason"], "parent");
+    assert_eq!(spans[1]["trace_id"], trace_id);
+    assert_eq!(spans[1]["parent_span_id"], "00f067aa0ba902b7");
+}
+
+#[tokio::test]
+async fn test_noise_budget_consumption_is_tracked_per_tenant() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.monitoring.noise_trends.enabled = true;
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, body) = proxy
+        .complete("primary", "llama", &[("x-api-key", "key-acme")], "hello")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, _, trend) = proxy
+        .call("GET", "/v1/admin/noise-trends/acme", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(trend["tenant"], "acme");
+    assert_eq!(trend["windows"][0]["operations"], 1);
+    let (status, _, _) = proxy
+        .call("GET", "/v1/admin/noise-trends/globex", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let trends = proxy.get("/v1/admin/noise-trends").await;
+    assert_eq!(trends["enabled"], true);
+    assert_eq!(trends["report"]["tenants"][0]["tenant"], "acme");
+}
+
+#[tokio::test]
+async fn test_backfill_rebuilds_metrics_from_the_billing_ledger() {
+    let provider = provider().await;
+    let collector = MockProxy::start()
+        .await
+        .respond("POST", "/v1/metrics", 200, json!({}));
+    let mut config = config_with_provider("primary", &provider.url());
+    config.billing.enabled = true;
+    config.monitoring.export_endpoints.otel_collector_endpoint = Some(collector.url());
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, body) = proxy.complete("primary", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let range = json!({ "from": now - 600, "to": now + 60, "bucket_seconds": 60 });
+    let backfill = |dry_run: bool| {
+        let mut request = range.clone();
+        request["dry_run"] = json!(dry_run);
+        request
+    };
+
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/admin/metrics/backfill",
+            &[],
+            Some(backfill(true)),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, preview) = proxy
+        .call(
+            "POST",
+            "/v1/admin/metrics/backfill",
+            &[ADMIN],
+            Some(backfill(true)),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", preview);
+    assert_eq!(preview["exported_buckets"], 0);
+    let buckets = preview["report"]["buckets"].as_array().unwrap();
+    let requests = |route: Option<&str>| -> u64 {
+        buckets
+            .iter()
+            .flat_map(|bucket| bucket["requests_by_route"].as_object().unwrap())
+            .filter(|(name, _)| route.is_none_or(|route| route == *name))
+            .map(|(_, count)| count.as_u64().unwrap())
+            .sum()
+    };
+    assert_eq!(requests(Some("/v1/chat/completions")), 1);
+    assert_eq!(preview["report"]["ledger_records"], requests(None));
+    assert!(collector.requests().is_empty());
+
+    let (status, _, exported) = proxy
+        .call(
+            "POST",
+            "/v1/admin/metrics/backfill",
+            &[ADMIN],
+            Some(backfill(false)),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", exported);
+    assert_eq!(exported["exported_buckets"], buckets.len());
+    let pushed = collector.requests();
+    assert_eq!(pushed.len(), 1);
+    assert!(pushed[0].body.to_string().contains("fhe_proxy.backfill"));
+
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/admin/metrics/backfill",
+            &[ADMIN],
+            Some(json!({ "from": now, "to": now, "dry_run": true })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,221 @@
+//! OpenAI-compatible self-hosted providers (vLLM, TGI) against a mock server
+
+use homomorphic_llm_proxy::config::{
+    OpenAiCompatibleServer, ProviderAuth, SlaClass, SlaClassHints, WarmHint, WarmHintsConfig,
+};
+use homomorphic_llm_proxy::proxy::{
+    CallOptions, LlmMessage, LlmProvider, LlmRequest, LlmStreamChunk,
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use test_utils::MockProxy;
+
+fn server(base_url: String, auth: ProviderAuth) -> OpenAiCompatibleServer {
+    OpenAiCompatibleServer {
+        name: "vllm".to_string(),
+        base_url,
+        model_urls: BTreeMap::new(),
+        auth,
+    }
+}
+
+fn request(model: &str) -> LlmRequest {
+    LlmRequest {
+        model: model.to_string(),
+        messages: vec![LlmMessage {
+            role: "user".to_string(),
+            content: "hello".to_string(),
+        }],
+        temperature: Some(0.0),
+        max_tokens: Some(16),
+        stream: None,
+    }
+}
+
+fn completion(model: &str, content: &str) -> serde_json::Value {
+    json!({
+        "id": "cmpl-1",
+        "object": "chat.completion",
+        "created": 0,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": "stop"
+        }],
+        "usage": null
+    })
+}
+
+#[tokio::test]
+async fn test_completes_without_auth_and_routes_models_to_their_servers() {
+    let shared = MockProxy::start()
+        .await
+        .respond("GET", "/v1/models", 200, json!({ "data": [] }))
+        .respond(
+            "POST",
+            "/v1/chat/completions",
+            200,
+            completion("llama", "from shared"),
+        );
+    let dedicated = MockProxy::start()
+        .await
+        .respond("GET", "/v1/models", 200, json!({ "data": [] }))
+        .respond(
+            "POST",
+            "/v1/chat/completions",
+            200,
+            completion("mixtral", "from dedicated"),
+        );
+
+    let mut config = server(format!("{}/v1/", shared.url()), ProviderAuth::None);
+    config
+        .model_urls
+        .insert("mixtral".to_string(), format!("{}/v1", dedicated.url()));
+    let provider = LlmProvider::openai_compatible(&config);
+
+    let response = provider.complete(request("llama")).await.unwrap();
+    assert_eq!(response.choices[0].message.content, "from shared");
+    let response = provider.complete(request("mixtral")).await.unwrap();
+    assert_eq!(response.choices[0].message.content, "from dedicated");
+
+    let sent = shared.requests().pop().unwrap();
+    assert_eq!(sent.path, "/v1/chat/completions");
+    assert_eq!(sent.header("authorization"), None);
+    assert_eq!(sent.body["model"], "llama");
+    assert_eq!(dedicated.requests().pop().unwrap().body["model"], "mixtral");
+
+    // Probing checks every server the provider sends models to
+    provider.probe(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(shared.requests().last().unwrap().path, "/v1/models");
+    assert_eq!(dedicated.requests().last().unwrap().path, "/v1/models");
+}
+
+#[tokio::test]
+async fn test_custom_header_auth_and_failed_probe() {
+    let mock = MockProxy::start().await.respond(
+        "GET",
+        "/v1/models",
+        401,
+        json!({ "error": "unauthorized" }),
+    );
+    let provider = LlmProvider::openai_compatible(&server(
+        format!("{}/v1", mock.url()),
+        ProviderAuth::Header {
+            name: "x-api-key".to_string(),
+            value: "gateway-secret".to_string(),
+        },
+    ));
+
+    assert!(provider.probe(Duration::from_secs(5)).await.is_err());
+    let sent = mock.requests().pop().unwrap();
+    assert_eq!(sent.header("x-api-key"), Some("gateway-secret"));
+    assert_eq!(sent.header("authorization"), None);
+}
+
+#[tokio::test]
+async fn test_streams_deltas_until_done() {
+    let events = [
+        json!({ "choices": [{ "index": 0, "delta": { "role": "assistant" }, "finish_reason": null }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "content": "Hel" }, "finish_reason": null }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "content": "lo" }, "finish_reason": "stop" }] }),
+    ];
+    let mut body: String = events
+        .iter()
+        .map(|event| format!("data: {}\n\n", event))
+        .collect();
+    body.push_str(": keep-alive\n\ndata: [DONE]\n\n");
+    let mock = MockProxy::start().await.respond_with(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        "text/event-stream",
+        &body,
+    );
+    let provider = LlmProvider::openai_compatible(&server(
+        format!("{}/v1", mock.url()),
+        ProviderAuth::Bearer {
+            token: "local-token".to_string(),
+        },
+    ));
+
+    let mut receiver = provider.complete_streaming(request("llama")).await.unwrap();
+    let mut chunks = Vec::new();
+    while let Some(chunk) = receiver.recv().await {
+        chunks.push(chunk.unwrap());
+    }
+    let text: String = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+    assert_eq!(text, "Hello");
+    assert_eq!(
+        chunks.last(),
+        Some(&LlmStreamChunk {
+            content: "lo".to_string(),
+            finish_reason: Some("stop".to_string()),
+        })
+    );
+
+    let sent = mock.requests().pop().unwrap();
+    assert_eq!(sent.header("authorization"), Some("Bearer local-token"));
+    assert_eq!(sent.body["stream"], true);
+    assert_eq!(provider.call_counts(), (1, 0));
+}
+
+#[tokio::test]
+async fn test_sends_warm_hints_by_sla_class_and_reports_cold_starts() {
+    let mock = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "warm"),
+    );
+    let hints = WarmHintsConfig {
+        enabled: true,
+        providers: BTreeMap::from([(
+            "vllm".to_string(),
+            SlaClassHints {
+                gold: Some(WarmHint {
+                    priority: Some(-10),
+                    headers: BTreeMap::from([("x-warm-pool".to_string(), "reserved".to_string())]),
+                    ..WarmHint::default()
+                }),
+                ..SlaClassHints::default()
+            },
+        )]),
+        ..WarmHintsConfig::default()
+    };
+    let provider =
+        LlmProvider::openai_compatible(&server(format!("{}/v1", mock.url()), ProviderAuth::None))
+            .with_warm_hints(&hints, "vllm");
+
+    let gold = CallOptions {
+        sla_class: Some(SlaClass::Gold),
+        ..CallOptions::default()
+    };
+    provider
+        .complete_with(request("llama"), gold)
+        .await
+        .unwrap();
+    let sent = mock.requests().pop().unwrap();
+    assert_eq!(sent.body["priority"], -10);
+    assert_eq!(sent.header("x-warm-pool"), Some("reserved"));
+
+    // Bronze has no hint configured and goes out as is
+    let bronze = CallOptions {
+        sla_class: Some(SlaClass::Bronze),
+        ..CallOptions::default()
+    };
+    provider
+        .complete_with(request("llama"), bronze)
+        .await
+        .unwrap();
+    let sent = mock.requests().pop().unwrap();
+    assert!(sent.body.get("priority").is_none());
+    assert_eq!(sent.header("x-warm-pool"), None);
+
+    // Only the first call found the model idle
+    let stats = &provider.warm_hint_stats()["llama"];
+    assert_eq!(stats.hinted_cold_starts, 1);
+    assert_eq!(stats.unhinted_cold_starts, 0);
+    assert_eq!(stats.delta_ms, None);
+}
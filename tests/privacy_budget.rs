@@ -0,0 +1,179 @@
+//! Completions charged against tenants' privacy budgets through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{add_admin_token, add_tenant_keys, completion, config_with_provider, Proxy, ADMIN};
+use homomorphic_llm_proxy::config::TenantOverrides;
+use serde_json::{json, Value};
+use std::time::Duration;
+use test_utils::MockProxy;
+
+#[tokio::test]
+async fn test_completion_started_before_a_reset_is_not_charged() {
+    let provider = MockProxy::start()
+        .await
+        .respond(
+            "POST",
+            "/v1/chat/completions",
+            200,
+            completion("llama", "ok"),
+        )
+        .delay(Duration::from_millis(500));
+    let mut config = config_with_provider("primary", &provider.url());
+    config.privacy.epsilon_per_query = 0.1;
+    config.privacy.max_queries_per_user = 10;
+    config.tenants.overrides.insert(
+        "greedy".to_string(),
+        TenantOverrides {
+            epsilon_per_query: Some(2.0),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme", "greedy"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let tenant = [("x-api-key", "key-acme")];
+
+    // Each completion spends the tenant's per-query epsilon
+    let (status, _, body) = proxy.complete("primary", "llama", &tenant, "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let budget = proxy.get("/v1/privacy/budget/acme").await;
+    assert_eq!(budget["total_queries"], 1);
+    assert!((budget["remaining_epsilon"].as_f64().unwrap() - 0.9).abs() < 1e-9);
+
+    let reset_path = "/v1/privacy/budget/acme/reset";
+    // The budget is reset while the next completion waits on the provider;
+    // it was charged to the period the reset ended
+    let reset = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        proxy.call("POST", reset_path, &[ADMIN], None).await
+    };
+    let ((status, _, body), (reset_status, _, _)) =
+        tokio::join!(proxy.complete("primary", "llama", &tenant, "hello"), reset);
+    assert_eq!(reset_status, StatusCode::OK);
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let budget = proxy.get("/v1/privacy/budget/acme").await;
+    assert_eq!(budget["total_queries"], 0);
+    assert!((budget["remaining_epsilon"].as_f64().unwrap() - 1.0).abs() < 1e-9);
+
+    // A tenant whose queries cost more than its whole budget is turned away
+    // before the provider is called
+    let calls = provider.requests().len();
+    let greedy = [("x-api-key", "key-greedy")];
+    let (status, _, _) = proxy.complete("primary", "llama", &greedy, "hello").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(provider.requests().len(), calls);
+}
+
+#[tokio::test]
+async fn test_budget_thresholds_notify_and_top_ups_are_audited() {
+    let provider = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "ok"),
+    );
+    let mut config = config_with_provider("primary", &provider.url());
+    // A budget of 1.0, with room in the rate limit for every call below
+    config.privacy.epsilon_per_query = 0.01;
+    config.privacy.max_queries_per_user = 100;
+    config.privacy.budget_replenishment.policy = "manual".to_string();
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            epsilon_per_query: Some(0.5),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let tenant = [("x-api-key", "key-acme")];
+    let thresholds = |notifications: &Value| -> Vec<u64> {
+        notifications["notifications"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|notification| notification["threshold_percent"].as_u64().unwrap())
+            .collect()
+    };
+
+    let (status, _, body) = proxy.complete("primary", "llama", &tenant, "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let notifications = proxy.get("/v1/privacy/budget/acme/notifications").await;
+    assert_eq!(thresholds(&notifications), [50]);
+    // Notifications are handed out once
+    let notifications = proxy.get("/v1/privacy/budget/acme/notifications").await;
+    assert!(thresholds(&notifications).is_empty());
+
+    // Exhaustion is announced once the budget is used up, not on refusals
+    let (status, _, body) = proxy.complete("primary", "llama", &tenant, "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let notifications = proxy.get("/v1/privacy/budget/acme/notifications").await;
+    assert_eq!(thresholds(&notifications), [80, 100]);
+    let (status, _, _) = proxy.complete("primary", "llama", &tenant, "hello").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let notifications = proxy.get("/v1/privacy/budget/acme/notifications").await;
+    assert!(thresholds(&notifications).is_empty());
+
+    // Top-ups and resets take an admin
+    let top_up = "/v1/privacy/budget/acme/topup";
+    let (status, _, _) = proxy
+        .call("POST", top_up, &[], Some(json!({ "epsilon": 0.5 })))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/privacy/budget/acme/reset",
+            &[("x-admin-token", "guess")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = proxy
+        .call("POST", top_up, &[ADMIN], Some(json!({ "epsilon": -1.0 })))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, topped_up) = proxy
+        .call("POST", top_up, &[ADMIN], Some(json!({ "epsilon": 0.5 })))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!((topped_up["remaining_epsilon"].as_f64().unwrap() - 0.5).abs() < 1e-9);
+    // Back under the upper thresholds, so crossing them notifies again
+    let (status, _, body) = proxy.complete("primary", "llama", &tenant, "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let notifications = proxy.get("/v1/privacy/budget/acme/notifications").await;
+    assert_eq!(thresholds(&notifications), [80, 100]);
+
+    let audit = proxy.get("/v1/admin/audit").await;
+    assert_eq!(audit["backend"], "memory");
+    let top_ups: Vec<&Value> = audit["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|record| record["action"] == "privacy_budget.top_up")
+        .collect();
+    assert_eq!(top_ups.len(), 1, "{}", audit);
+    assert_eq!(top_ups[0]["subject"], "acme");
+    assert_eq!(top_ups[0]["details"]["admin"], "ops");
+}
+
+#[tokio::test]
+async fn test_completions_without_a_tenant_share_one_budget() {
+    let provider = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "ok"),
+    );
+    let proxy = Proxy::new(config_with_provider("primary", &provider.url())).await;
+
+    for _ in 0..2 {
+        let (status, _, body) = proxy.complete("primary", "llama", &[], "hello").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    let budget = proxy.get("/v1/privacy/budget/(unauthenticated)").await;
+    assert_eq!(budget["total_queries"], 2);
+}
@@ -0,0 +1,325 @@
+//! Completions dispatched through the router to mock providers
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    add_provider, add_tenant_keys, completion, config_with_provider, hanging_provider,
+    http_response, scripted_provider, Proxy,
+};
+use homomorphic_llm_proxy::config::{
+    SlaClass, SlaClassHints, SupportToken, TenantOverrides, WarmHint,
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use test_utils::MockProxy;
+
+#[tokio::test]
+async fn test_completion_falls_back_by_error_class() {
+    let unknown_model = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        404,
+        json!({ "error": { "message": "no such model", "code": "model_not_found" } }),
+    );
+    let refusing = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        400,
+        json!({ "error": { "message": "refused", "code": "content_policy_violation" } }),
+    );
+    let backup = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "from backup"),
+    );
+    let mut config = config_with_provider("primary", &unknown_model.url());
+    add_provider(&mut config, "refusing", &refusing.url());
+    add_provider(&mut config, "backup", &backup.url());
+    config.llm.error_retry.fallback_providers = vec!["backup".to_string()];
+    let proxy = Proxy::new(config).await;
+
+    // A provider without the model hands the call on to the fallback
+    let (status, headers, body) = proxy.complete("primary", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(headers["x-served-by-provider"], "backup");
+    assert_eq!(body["choices"][0]["message"]["content"], "from backup");
+    assert_eq!(body["usage"]["total_tokens"], 12);
+    let sent = unknown_model.requests().pop().unwrap();
+    assert_eq!(sent.body["model"], "llama");
+    assert_eq!(sent.body["temperature"], 0.0);
+
+    // No provider would take a request refused on content policy
+    let (status, _, _) = proxy.complete("refusing", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(backup.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_completion_carries_the_tenants_warm_hints() {
+    let provider = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "warm"),
+    );
+    let mut config = config_with_provider("vllm", &provider.url());
+    config.llm.warm_hints.enabled = true;
+    config.llm.warm_hints.providers.insert(
+        "vllm".to_string(),
+        SlaClassHints {
+            gold: Some(WarmHint {
+                priority: Some(-10),
+                headers: BTreeMap::from([("x-warm-pool".to_string(), "reserved".to_string())]),
+                ..WarmHint::default()
+            }),
+            ..SlaClassHints::default()
+        },
+    );
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            sla_class: Some(SlaClass::Gold),
+            ..TenantOverrides::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, body) = proxy
+        .complete("vllm", "llama", &[("x-api-key", "key-acme")], "hello")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let sent = provider.requests().pop().unwrap();
+    assert_eq!(sent.header("x-warm-pool"), Some("reserved"));
+    assert_eq!(sent.body["priority"], -10);
+
+    // Tenants of other classes are sent unhinted
+    let (status, _, _) = proxy
+        .complete("vllm", "llama", &[("x-api-key", "key-globex")], "hello")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let sent = provider.requests().pop().unwrap();
+    assert_eq!(sent.header("x-warm-pool"), None);
+    assert!(sent.body.get("priority").is_none());
+
+    // Only the first call found the model cold
+    let metrics = proxy.get("/metrics").await;
+    assert_eq!(
+        metrics["warm_hints"]["vllm"]["llama"]["hinted_cold_starts"],
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_short_deadline_caps_the_provider_timeout() {
+    let mut config = config_with_provider("hanging", &hanging_provider().await);
+    config.llm.timeout_seconds = 60;
+    let proxy = Proxy::new(config).await;
+
+    let started = Instant::now();
+    let (status, _, _) = proxy
+        .complete(
+            "hanging",
+            "llama",
+            &[("x-request-deadline-ms", "2000")],
+            "hello",
+        )
+        .await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    // Cut short by the caller's deadline, which says nothing about the model
+    let performance = proxy.get("/v1/admin/performance").await;
+    assert!(performance["provider_timeouts"]["hanging"]
+        .get("llama")
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_completion_moves_on_before_a_providers_quota_runs_out() {
+    let limits = [
+        ("x-ratelimit-limit-requests", "100"),
+        ("x-ratelimit-remaining-requests", "5"),
+        ("x-ratelimit-reset-requests", "60s"),
+    ];
+    let metered = scripted_provider(vec![http_response(
+        200,
+        &limits,
+        &completion("llama", "metered"),
+    )])
+    .await;
+    let backup = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "from backup"),
+    );
+    let mut config = config_with_provider("metered", &metered);
+    add_provider(&mut config, "backup", &backup.url());
+    config.llm.error_retry.fallback_providers = vec!["backup".to_string()];
+    let proxy = Proxy::new(config).await;
+
+    let (status, headers, _) = proxy.complete("metered", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.get("x-served-by-provider").is_none());
+
+    // The next call would dip into the reserve, and the reset is too far off
+    let (status, headers, _) = proxy.complete("metered", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["x-served-by-provider"], "backup");
+
+    let quotas = proxy.get("/v1/admin/provider-quotas").await;
+    assert_eq!(quotas["metered"]["requests"]["remaining"], 5);
+    assert_eq!(quotas["metered"]["rejected"], 1);
+}
+
+#[tokio::test]
+async fn test_completion_resumes_after_a_connection_reset() {
+    let full = http_response(200, &[], &completion("llama", "stitched"));
+    // The first answer is cut off halfway through its body
+    let cut = full[..full.len() - 40].to_string();
+    let provider = scripted_provider(vec![cut, full]).await;
+    let mut config = config_with_provider("flaky", &provider);
+    config.llm.resume.backoff_ms = 1;
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, body) = proxy.complete("flaky", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["choices"][0]["message"]["content"], "stitched");
+
+    let performance = proxy.get("/v1/admin/performance").await;
+    let resume = &performance["provider_resume"]["flaky"];
+    assert_eq!(resume["interrupted"], 1);
+    assert_eq!(resume["resumed"], 1);
+    assert_eq!(resume["diverged"], 0);
+}
+
+#[tokio::test]
+async fn test_completion_times_out_at_the_calibrated_timeout() {
+    let ok = http_response(200, &[], &completion("llama", "fast"));
+    // Two fast answers, then none
+    let provider = scripted_provider(vec![ok.clone(), ok]).await;
+    let mut config = config_with_provider("vllm", &provider);
+    config.llm.timeout_seconds = 60;
+    let calibration = &mut config.llm.timeout_calibration;
+    calibration.min_samples = 2;
+    calibration.min_timeout_ms = 500;
+    let proxy = Proxy::new(config).await;
+
+    for _ in 0..2 {
+        let (status, _, _) = proxy.complete("vllm", "llama", &[], "hello").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let performance = proxy.get("/v1/admin/performance").await;
+    let timeout = &performance["provider_timeouts"]["vllm"]["llama"];
+    assert_eq!(timeout["samples"], 2);
+    assert_eq!(timeout["calibrated"], true);
+    assert_eq!(timeout["timeout_ms"], 500);
+
+    // The hanging call gives up after the calibrated timeout, not the configured minute
+    let started = Instant::now();
+    let (status, _, _) = proxy.complete("vllm", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert!(started.elapsed() < Duration::from_secs(10));
+    let performance = proxy.get("/v1/admin/performance").await;
+    assert_eq!(
+        performance["provider_timeouts"]["vllm"]["llama"]["timed_out"],
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_journal_records_provider_attempts_and_retries() {
+    let rate_limited = http_response(
+        429,
+        &[("retry-after", "0")],
+        &json!({ "error": { "message": "slow down", "code": "rate_limit_exceeded" } }),
+    );
+    let ok = http_response(200, &[], &completion("llama", "after retry"));
+    let provider = scripted_provider(vec![rate_limited, ok]).await;
+    let mut config = config_with_provider("vllm", &provider);
+    config.llm.error_retry.backoff_ms = 1;
+    config.monitoring.request_journal.enabled = true;
+    config
+        .monitoring
+        .request_journal
+        .support_tokens
+        .push(SupportToken {
+            name: "support".to_string(),
+            // SHA-256 of "support-secret"
+            sha256: "c62b375e6265547967c3a3bd35f496366c019d143a5e62f55ef377eba178809a".to_string(),
+            tenants: Vec::new(),
+        });
+    let proxy = Proxy::new(config).await;
+
+    let (status, headers, body) = proxy.complete("vllm", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let request_id = headers["x-request-id"].to_str().unwrap();
+
+    let (status, _, journal) = proxy
+        .call(
+            "GET",
+            &format!("/v1/support/requests/{}/journal", request_id),
+            &[("authorization", "Bearer support-secret")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let events: Vec<_> = journal["events"].as_array().unwrap().iter().collect();
+    let attempts: Vec<_> = events
+        .iter()
+        .filter(|event| event["event"] == "provider_attempt")
+        .collect();
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(attempts[0]["status"], 429);
+    assert_eq!(attempts[0]["host"], "127.0.0.1");
+    assert_eq!(attempts[1]["outcome"], "responded");
+    assert!(events
+        .iter()
+        .any(|event| event["event"] == "provider_retry" && event["class"] == "rate_limited"));
+}
+
+#[tokio::test]
+async fn test_recorded_completions_are_stored_and_replay_stays_offline() {
+    let provider = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "ok"),
+    );
+    let recordings = std::env::temp_dir().join(format!("fhe-recordings-{}", uuid::Uuid::new_v4()));
+    let mut config = config_with_provider("primary", &provider.url());
+    config.llm.recording.mode = "record".to_string();
+    config.llm.recording.directory = recordings.to_string_lossy().into_owned();
+    let proxy = Proxy::new(config.clone()).await;
+    let (status, _, body) = proxy.complete("primary", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let files: Vec<_> = std::fs::read_dir(&recordings)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    let recorded: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&files[0]).unwrap()).unwrap();
+    assert_eq!(
+        files[0].file_stem().unwrap().to_str().unwrap(),
+        recorded["request_digest"]
+    );
+    assert_eq!(recorded["model"], "llama");
+    assert_eq!(
+        recorded["response"]["choices"][0]["message"]["content"],
+        "ok"
+    );
+
+    // Nothing was recorded for this prompt, and replay never asks the provider
+    config.llm.recording.mode = "replay".to_string();
+    let proxy = Proxy::new(config).await;
+    let (status, _, _) = proxy.complete("primary", "llama", &[], "goodbye").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(provider.requests().len(), 1);
+    std::fs::remove_dir_all(recordings).unwrap();
+}
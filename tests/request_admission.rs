@@ -0,0 +1,336 @@
+//! Sandbox keys, workload tags, scripted rate limits and prompt lint reports
+//! admitting or refusing requests through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    add_admin_token, add_provider, add_tenant_keys, completion_request, config_with_provider,
+    hanging_provider, provider, tenant_key, Proxy, ADMIN,
+};
+use homomorphic_llm_proxy::config::{Config, RateLimitRule, TenantOverrides, WorkloadTagPolicy};
+use homomorphic_llm_proxy::prompt_lint::{lint_prompt, LintPolicy};
+use serde_json::json;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_sandbox_key_is_scoped_and_exhausted() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.sandbox.enabled = true;
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, key) = proxy
+        .call(
+            "POST",
+            "/v1/admin/sandbox/keys",
+            &[ADMIN],
+            Some(json!({
+                "label": "trial",
+                "models": ["llama"],
+                "spend_ceiling": 1.0,
+                "max_requests": 2
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", key);
+    let secret = key["secret"].as_str().unwrap();
+    let sandboxed = [("x-sandbox-key", secret)];
+
+    let (status, headers, body) = proxy.complete("primary", "llama", &sandboxed, "hi").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(headers["x-sandbox"], "true");
+
+    // Admitted, but not for a model the key wasn't issued for
+    let (status, _, _) = proxy.complete("primary", "mistral", &sandboxed, "hi").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // The refused request still counted against the key's requests
+    let (status, _, body) = proxy.complete("primary", "llama", &sandboxed, "hi").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"], "sandbox request allowance used up");
+    assert_eq!(provider.requests().len(), 1);
+
+    let (status, _, _) = proxy
+        .complete(
+            "primary",
+            "llama",
+            &[("x-sandbox-key", "sbx_unknown")],
+            "hi",
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let usage = proxy
+        .get_with(
+            &format!("/v1/admin/sandbox/keys/{}", key["id"].as_str().unwrap()),
+            &[ADMIN],
+        )
+        .await;
+    assert_eq!(usage["usage"]["requests"], 2);
+    assert_eq!(usage["usage"]["refused"], 1);
+}
+
+#[tokio::test]
+async fn test_sandbox_keys_are_issued_only_to_tenants_and_admins() {
+    let mut config = Config::default();
+    config.sandbox.enabled = true;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let create = |headers: &'static [(&'static str, &'static str)], tenant: Option<&str>| {
+        proxy.call(
+            "POST",
+            "/v1/admin/sandbox/keys",
+            headers,
+            Some(json!({ "label": "trial", "tenant": tenant, "spend_ceiling": 1.0 })),
+        )
+    };
+
+    let (status, _, _) = create(&[], None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = create(&[("x-admin-token", "guessed")], Some("globex")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // A tenant's keys are always bound to that tenant
+    let acme = &[("x-api-key", "key-acme")];
+    let (status, _, _) = create(acme, Some("globex")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, key) = create(acme, None).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", key);
+    assert_eq!(key["tenant"], "acme");
+
+    let (status, _, key) = create(&[ADMIN], Some("globex")).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", key);
+    assert_eq!(key["tenant"], "globex");
+    let listed = |headers: &'static [(&'static str, &'static str)]| async {
+        let (status, _, body) = proxy
+            .call("GET", "/v1/admin/sandbox/keys", headers, None)
+            .await;
+        (status, body["keys"].as_array().map_or(0, Vec::len))
+    };
+    assert_eq!(listed(&[]).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(listed(&[ADMIN]).await, (StatusCode::OK, 2));
+    assert_eq!(listed(acme).await, (StatusCode::OK, 1));
+
+    // Another tenant's key can be neither read nor revoked
+    let globex_key = format!("/v1/admin/sandbox/keys/{}", key["id"].as_str().unwrap());
+    let (status, _, _) = proxy.call("GET", &globex_key, &[], None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy.call("GET", &globex_key, acme, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = proxy.call("DELETE", &globex_key, acme, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let globex = &[("x-api-key", "key-globex")];
+    let (status, _, body) = proxy.call("DELETE", &globex_key, globex, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body["revoked_at"].is_i64(), "{}", body);
+}
+
+#[tokio::test]
+async fn test_workload_tag_routes_and_is_limited_per_tenant() {
+    let primary = provider().await;
+    let batch = provider().await;
+    let mut config = config_with_provider("primary", &primary.url());
+    add_provider(&mut config, "batch", &batch.url());
+    let workload_tags = &mut config.tenants.workload_tags;
+    workload_tags.enabled = true;
+    workload_tags.tags.insert(
+        "nightly".to_string(),
+        WorkloadTagPolicy {
+            provider: Some("batch".to_string()),
+            ..Default::default()
+        },
+    );
+    workload_tags
+        .tags
+        .insert("interactive".to_string(), WorkloadTagPolicy::default());
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            allowed_workload_tags: Some(vec!["interactive".to_string()]),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["globex", "acme"]);
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, body) = proxy
+        .complete(
+            "primary",
+            "llama",
+            &[("x-api-key", "key-globex"), ("x-workload-tag", "nightly")],
+            "hi",
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(batch.requests().len(), 1);
+    assert!(primary.requests().is_empty());
+
+    let (status, _, _) = proxy
+        .complete(
+            "primary",
+            "llama",
+            &[("x-api-key", "key-acme"), ("x-workload-tag", "nightly")],
+            "hi",
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = proxy
+        .complete(
+            "primary",
+            "llama",
+            &[("x-api-key", "key-globex"), ("x-workload-tag", "unknown")],
+            "hi",
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let report = proxy.get("/v1/admin/workload-tags").await;
+    assert_eq!(report["tags"]["nightly"]["refused"], 1);
+    assert_eq!(report["unknown_refused"], 1);
+}
+
+#[tokio::test]
+async fn test_rate_limit_rule_replaces_the_tenant_limit() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    let rules = &mut config.tenants.rate_limit_rules;
+    rules.enabled = true;
+    rules.rules.push(RateLimitRule {
+        name: "one_encryption".to_string(),
+        tenants: vec!["acme".to_string()],
+        when: "path == \"/v1/encrypt\"".to_string(),
+        limit: "1".to_string(),
+    });
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let client_id = proxy.generate_keys().await;
+    let encrypt = |tenant: &'static str| {
+        let (proxy, client_id) = (&proxy, &client_id);
+        async move {
+            proxy
+                .call(
+                    "POST",
+                    "/v1/encrypt",
+                    &[("x-api-key", &tenant_key(tenant))],
+                    Some(json!({ "text": "hello", "client_id": client_id })),
+                )
+                .await
+        }
+    };
+
+    let (status, _, body) = encrypt("acme").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, headers, _) = encrypt("acme").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(headers["x-rate-limit-rule"], "one_encryption");
+
+    // The rule names its tenants; everyone else keeps the static limit
+    for _ in 0..2 {
+        let (status, _, _) = encrypt("globex").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let stats = proxy.get("/v1/admin/rate-limit-rules").await;
+    assert_eq!(stats[0]["name"], "one_encryption");
+    assert_eq!(stats[0]["matched"], 2);
+    assert_eq!(stats[0]["rejected"], 1);
+}
+
+#[tokio::test]
+async fn test_required_prompt_lint_report_must_pass() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            require_prompt_lint: Some(true),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+    let tenant = [("x-api-key", "key-acme")];
+    let lint = proxy.get("/v1/lint/policy").await;
+    let policy: LintPolicy = serde_json::from_value(lint["policy"].clone()).unwrap();
+
+    // The client lints before encrypting and sends the report along
+    let complete_linted = |prompt: &'static str| {
+        let (proxy, policy) = (&proxy, &policy);
+        async move {
+            let encrypted = proxy.encrypt(prompt).await;
+            let mut request = completion_request(&encrypted, "primary", "llama");
+            let report = lint_prompt(
+                prompt,
+                policy,
+                encrypted["encrypted_data"].as_str().unwrap(),
+            );
+            request["lint_report"] = json!(report);
+            proxy
+                .call("POST", "/v1/chat/completions", &tenant, Some(request))
+                .await
+        }
+    };
+
+    let (status, _, body) = complete_linted("Summarize the report").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _, _) = complete_linted("Dear {{name}}").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _, _) = proxy.complete("primary", "llama", &tenant, "hi").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(provider.requests().len(), 1);
+
+    let stats = proxy.get("/v1/admin/prompt-lint").await;
+    assert_eq!(stats["tenants"]["acme"]["accepted"], 1);
+    assert_eq!(stats["tenants"]["acme"]["failed"], 1);
+    assert_eq!(stats["tenants"]["acme"]["missing"], 1);
+}
+
+#[tokio::test]
+async fn test_in_flight_requests_are_capped_per_api_key() {
+    let mut config = config_with_provider("hanging", &hanging_provider().await);
+    config.server.connections.enabled = true;
+    config.server.connections.max_requests_per_api_key = 1;
+    config.llm.timeout_seconds = 1;
+    let proxy = Proxy::new(config).await;
+    let encrypted = proxy.encrypt("hello").await;
+    let request = completion_request(&encrypted, "hanging", "llama");
+
+    // The first request holds the key's only slot until the provider times out
+    let held = proxy.call(
+        "POST",
+        "/v1/chat/completions",
+        &[("x-api-key", "k1")],
+        Some(request.clone()),
+    );
+    let others = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let (same_key, _, _) = proxy
+            .call(
+                "POST",
+                "/v1/chat/completions",
+                &[("x-api-key", "k1")],
+                Some(request.clone()),
+            )
+            .await;
+        let (other_key, _, _) = proxy
+            .call(
+                "POST",
+                "/v1/chat/completions",
+                &[("x-api-key", "k2")],
+                Some(request.clone()),
+            )
+            .await;
+        (same_key, other_key)
+    };
+    let ((held, _, _), (same_key, other_key)) = tokio::join!(held, others);
+    assert_eq!(held, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(same_key, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(other_key, StatusCode::GATEWAY_TIMEOUT);
+
+    let stats = proxy.get("/v1/admin/connections").await;
+    assert_eq!(stats["enabled"], true);
+    assert_eq!(stats["rejected_requests"], 1);
+}
@@ -0,0 +1,252 @@
+//! Backoff hints, simulated load shedding and failover drills, driven through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    add_admin_token, add_tenant_keys, completion_request, config_with_provider, hanging_provider,
+    Proxy, ADMIN,
+};
+use homomorphic_llm_proxy::config::{Config, StandbyReplication};
+use serde_json::{json, Value};
+use std::time::Duration;
+use test_utils::MockProxy;
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_projected_queue_latency_past_the_deadline_is_refused_unless_simulated() {
+    let mut config = config_with_provider("hanging", &hanging_provider().await);
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let encrypted = proxy.encrypt("hello").await;
+    let request = completion_request(&encrypted, "hanging", "llama");
+
+    // A slow completion teaches the projector how long requests take
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[("x-api-key", "key-acme"), ("x-request-deadline-ms", "1000")],
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+
+    let projection = proxy.get("/v1/queue/projection?deadline_ms=50").await;
+    assert_eq!(projection["would_admit"], false, "{}", projection);
+    assert_eq!(projection["retry_after_seconds"], 1);
+    let (status, headers, _) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[("x-api-key", "key-acme"), ("x-request-deadline-ms", "50")],
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(headers["retry-after"], "1");
+    assert_eq!(headers["x-queue-depth"], "0");
+    assert!(headers.contains_key("x-projected-wait-ms"));
+
+    let switch = |headers: &'static [(&'static str, &'static str)]| {
+        proxy.call(
+            "POST",
+            "/v1/admin/load-shedding/queue_projection",
+            headers,
+            Some(json!({ "mode": "simulate" })),
+        )
+    };
+    let (status, _, _) = switch(&[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = switch(&[("x-api-key", "key-acme")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, switched) = switch(&[ADMIN]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(switched["previous_mode"], "enforce");
+    let audit = proxy.get_with("/v1/admin/audit", &[ADMIN]).await;
+    let switched_by = audit["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|record| record["action"] == "load_shedding.mode")
+        .map(|record| record["details"]["admin"].clone());
+    assert_eq!(switched_by, Some(json!("ops")));
+    // Simulated, the request reaches the provider and runs out of time there
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[("x-api-key", "key-acme"), ("x-request-deadline-ms", "50")],
+            Some(request),
+        )
+        .await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+
+    let shedding = proxy.get("/v1/admin/load-shedding").await;
+    let policy = shedding["policies"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|policy| policy["policy"] == "queue_projection")
+        .unwrap();
+    assert_eq!(policy["mode"], "simulate");
+    assert_eq!(policy["shed"], 1);
+    assert_eq!(policy["would_shed"], 1);
+    let recent = shedding["recent"].as_array().unwrap();
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent[0]["path"], "/v1/chat/completions");
+    assert_eq!(recent[0]["tenant"], "acme");
+    assert_eq!(recent[0]["status"], 429);
+
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/admin/load-shedding/overload",
+            &[ADMIN],
+            Some(json!({ "mode": "simulate" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+fn drill_config(standby: String) -> Config {
+    let mut config = Config::default();
+    config.disaster_recovery.drills_enabled = true;
+    config.disaster_recovery.health_poll_interval_ms = 100;
+    config.disaster_recovery.max_drill_seconds = 1;
+    config.persistence.replication.region = "eu-west".to_string();
+    config.persistence.replication.peers = vec![standby];
+    add_admin_token(&mut config);
+    config
+}
+
+/// Wait for the report of the drill `started` to leave `running`
+async fn finished_drill(proxy: &Proxy, started: &Value) -> Value {
+    let path = format!("/v1/admin/dr/drills/{}", started["id"].as_str().unwrap());
+    for _ in 0..50 {
+        let report = proxy.get(&path).await;
+        if report["status"] != "running" {
+            return report;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("drill {} did not finish", path);
+}
+
+fn step<'a>(report: &'a Value, name: &str) -> &'a Value {
+    report["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|step| step["name"] == name)
+        .unwrap_or_else(|| panic!("no {} step in {}", name, report))
+}
+
+#[tokio::test]
+async fn test_failover_drill_reroutes_until_standbys_are_verified() {
+    // Nothing listens where the standby should be
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let unreachable = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let proxy = Proxy::new(drill_config(unreachable)).await;
+
+    let (status, _, _) = proxy.call("POST", "/v1/admin/dr/drills", &[], None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, started) = proxy
+        .call("POST", "/v1/admin/dr/drills", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(started["region"], "eu-west");
+    // Let the drill's task take traffic off this region
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let (status, _, _) = proxy.call("GET", "/v1/params", &[], None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let (status, _, _) = proxy
+        .call("POST", "/v1/admin/dr/drills", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let report = finished_drill(&proxy, &started).await;
+    assert_eq!(report["status"], "failed");
+    assert_eq!(step(&report, "verify_standbys")["ok"], false);
+    assert_eq!(step(&report, "restore")["ok"], true);
+    assert!(report["rto_ms"].is_null());
+    proxy.get("/v1/params").await;
+    let audit = proxy.get_with("/v1/admin/audit", &[ADMIN]).await;
+    let started_by = audit["records"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|record| record["action"] == "dr_drill.started")
+        .map(|record| record["details"]["admin"].clone());
+    assert_eq!(started_by, Some(json!("ops")));
+
+    let standby =
+        MockProxy::start()
+            .await
+            .respond("GET", "/health", 200, json!({ "status": "healthy" }));
+    let proxy = Proxy::new(drill_config(standby.url())).await;
+    let (status, _, started) = proxy
+        .call("POST", "/v1/admin/dr/drills", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let report = finished_drill(&proxy, &started).await;
+    assert_eq!(step(&report, "verify_standbys")["ok"], true, "{}", report);
+    assert!(report["rto_ms"].is_u64());
+
+    let mut config = Config::default();
+    add_admin_token(&mut config);
+    let (status, _, _) = Proxy::new(config)
+        .await
+        .call("POST", "/v1/admin/dr/drills", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_only_admins_promote_or_hand_off_a_standby() {
+    let mut config = Config::default();
+    config.persistence.standby.enabled = true;
+    // Nothing listens at the peer, and takeover reloads state from the store
+    config.persistence.standby.peer_url = "http://127.0.0.1:9".to_string();
+    config.persistence.standby.replication = StandbyReplication::Store;
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let role = |status: Value| status["standby"]["role"].clone();
+
+    for headers in [&[][..], &[("x-api-key", "key-acme")][..]] {
+        let (status, _, _) = proxy
+            .call("POST", "/v1/admin/standby/promote", headers, None)
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/admin/standby/promote",
+            &[("x-admin-token", "guessed")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(role(proxy.get("/v1/admin/standby").await), "standby");
+
+    let (status, _, body) = proxy
+        .call("POST", "/v1/admin/standby/promote", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(role(body), "active");
+
+    let (status, _, _) = proxy
+        .call("POST", "/v1/admin/standby/handoff", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(role(proxy.get("/v1/admin/standby").await), "active");
+    let (status, _, body) = proxy
+        .call("POST", "/v1/admin/standby/handoff", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(role(body), "handing_off");
+}
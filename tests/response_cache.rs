@@ -0,0 +1,265 @@
+//! Cached completions served, revalidated and purged through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    add_admin_token, add_provider, add_tenant_keys, completion, completion_request,
+    config_with_provider, Proxy, ADMIN,
+};
+use homomorphic_llm_proxy::config::{ResponseCacheEviction, ResponseCacheRule, TenantOverrides};
+use serde_json::json;
+use std::time::Duration;
+use test_utils::MockProxy;
+
+/// A proxy whose tenant "acme" caches completions under `rule`, backed by a
+/// provider that always answers
+async fn caching_proxy(rule: ResponseCacheRule) -> (Proxy, MockProxy) {
+    let provider = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "cached answer"),
+    );
+    let mut config = config_with_provider("primary", &provider.url());
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            response_cache_rules: Some(vec![rule]),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    (Proxy::new(config).await, provider)
+}
+
+fn rule(ttl_seconds: u64, stale_while_revalidate_seconds: u64) -> ResponseCacheRule {
+    ResponseCacheRule {
+        models: vec!["llama".to_string()],
+        template_ids: Vec::new(),
+        ttl_seconds,
+        stale_while_revalidate_seconds,
+        tags: vec!["faq".to_string()],
+    }
+}
+
+#[tokio::test]
+async fn test_cached_completion_is_served_until_invalidated() {
+    let (proxy, provider) = caching_proxy(rule(60, 0)).await;
+    let tenant = [("x-api-key", "key-acme")];
+    let encrypted = proxy.encrypt("what are your hours?").await;
+    let request = completion_request(&encrypted, "primary", "llama");
+
+    let (status, headers, first) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &tenant,
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", first);
+    assert_eq!(headers["x-cache"], "MISS");
+    let etag = headers["etag"].to_str().unwrap().to_string();
+
+    // The same ciphertext is answered from the cache without a provider call
+    let (status, headers, second) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &tenant,
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["x-cache"], "HIT");
+    assert_eq!(headers["etag"], etag.as_str());
+    assert_eq!(second["choices"], first["choices"]);
+    assert_eq!(provider.requests().len(), 1);
+
+    // A client already holding the result gets no body back
+    let revalidating = [("x-api-key", "key-acme"), ("if-none-match", etag.as_str())];
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &revalidating,
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+    // Purging takes a tenant or an admin
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/cache/invalidate",
+            &[],
+            Some(json!({ "tag": "faq" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Purging the rule's tag sends the next request to the provider again
+    let (status, _, purged) = proxy
+        .call(
+            "POST",
+            "/v1/cache/invalidate",
+            &tenant,
+            Some(json!({ "tag": "faq" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(purged["invalidated"], 1);
+    let (_, headers, _) = proxy
+        .call("POST", "/v1/chat/completions", &tenant, Some(request))
+        .await;
+    assert_eq!(headers["x-cache"], "MISS");
+    assert_eq!(provider.requests().len(), 2);
+
+    // An admin purges across tenants
+    let (status, _, purged) = proxy
+        .call(
+            "POST",
+            "/v1/cache/invalidate",
+            &[ADMIN],
+            Some(json!({ "model": "llama" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(purged["invalidated"], 1);
+
+    let stats = proxy.get("/v1/cache/stats").await;
+    assert_eq!(stats["tenants"]["acme"]["fresh_hits"], 2);
+}
+
+#[tokio::test]
+async fn test_gdsf_keeps_the_costly_completion_when_the_cache_is_full() {
+    let fast = MockProxy::start().await.respond(
+        "POST",
+        "/v1/chat/completions",
+        200,
+        completion("llama", "cheap answer"),
+    );
+    let slow = MockProxy::start()
+        .await
+        .respond(
+            "POST",
+            "/v1/chat/completions",
+            200,
+            completion("llama", "costly answer"),
+        )
+        .delay(Duration::from_millis(300));
+    let mut config = config_with_provider("fast", &fast.url());
+    add_provider(&mut config, "slow", &slow.url());
+    config.performance.response_cache.max_entries = 2;
+    config.performance.response_cache.eviction = ResponseCacheEviction::Gdsf;
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            response_cache_rules: Some(vec![rule(60, 0)]),
+            ..Default::default()
+        },
+    );
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+    let tenant = [("x-api-key", "key-acme")];
+    let send = |request: serde_json::Value| {
+        let proxy = &proxy;
+        async move {
+            let (status, headers, body) = proxy
+                .call("POST", "/v1/chat/completions", &tenant, Some(request))
+                .await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            headers["x-cache"].to_str().unwrap().to_string()
+        }
+    };
+
+    let costly = completion_request(
+        &proxy.encrypt("summarize the annual report").await,
+        "slow",
+        "llama",
+    );
+    assert_eq!(send(costly.clone()).await, "MISS");
+    assert_eq!(send(costly.clone()).await, "HIT");
+    for text in ["hi", "hello"] {
+        let cheap = completion_request(&proxy.encrypt(text).await, "fast", "llama");
+        assert_eq!(send(cheap).await, "MISS");
+    }
+
+    // The cheap completions competed for the second slot; the costly one stayed
+    assert_eq!(send(costly).await, "HIT");
+    assert_eq!(slow.requests().len(), 1);
+    let stats = &proxy.get("/metrics").await["response_cache"];
+    assert_eq!(stats["policy"], "gdsf");
+    assert_eq!(stats["entries"], 2);
+    assert_eq!(
+        stats["evictions"].as_u64().unwrap() + stats["rejected"].as_u64().unwrap(),
+        1
+    );
+    assert!(stats["hit_value_ms"].as_u64().unwrap() >= 600, "{}", stats);
+}
+
+#[tokio::test]
+async fn test_expired_completion_is_served_stale_within_the_window() {
+    let (proxy, provider) = caching_proxy(rule(0, 60)).await;
+    let tenant = [("x-api-key", "key-acme")];
+    let encrypted = proxy.encrypt("what are your hours?").await;
+    let request = completion_request(&encrypted, "primary", "llama");
+
+    proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &tenant,
+            Some(request.clone()),
+        )
+        .await;
+    let (status, headers, body) = proxy
+        .call("POST", "/v1/chat/completions", &tenant, Some(request))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(headers["x-cache"], "STALE");
+    assert_eq!(headers["warning"], "110 - \"Response is Stale\"");
+    assert_eq!(provider.requests().len(), 1);
+
+    let stats = proxy.get("/v1/cache/stats").await;
+    assert_eq!(stats["tenants"]["acme"]["stale_hits"], 1);
+}
+
+#[tokio::test]
+async fn test_response_cache_flag_turns_caching_off_per_tenant() {
+    let (proxy, provider) = caching_proxy(rule(60, 0)).await;
+    let (status, _, body) = proxy
+        .call(
+            "PUT",
+            "/v1/admin/flags/response_cache",
+            &[ADMIN],
+            Some(json!({
+                "name": "response_cache",
+                "enabled": true,
+                "rollout_percent": 100.0,
+                "excluded_tenants": ["acme"]
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let tenant = [("x-api-key", "key-acme")];
+    let encrypted = proxy.encrypt("what are your hours?").await;
+    let request = completion_request(&encrypted, "primary", "llama");
+    for _ in 0..2 {
+        let (status, headers, _) = proxy
+            .call(
+                "POST",
+                "/v1/chat/completions",
+                &tenant,
+                Some(request.clone()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers.get("x-cache").is_none());
+    }
+    assert_eq!(provider.requests().len(), 2);
+}
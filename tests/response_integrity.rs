@@ -0,0 +1,280 @@
+//! Chunked, signed and attested completion responses checked through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use base64::prelude::*;
+use common::{add_admin_token, completion_request, config_with_provider, provider, Proxy, ADMIN};
+use homomorphic_llm_proxy::config::Config;
+use homomorphic_llm_proxy::security::{
+    verify_signed_response, JwkSet, SignedProvenance, SignedResponseEnvelope,
+};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde_json::{json, Value};
+use std::time::Duration;
+use test_utils::MockProxy;
+
+/// Complete `text` for a fresh client; returns the client ID and the response
+async fn complete_for_client(proxy: &Proxy, text: &str) -> (Value, Value) {
+    let client_id = proxy.generate_keys().await;
+    let encrypted = proxy.encrypt_for(&client_id, text).await;
+    let (status, _, body) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[],
+            Some(completion_request(&encrypted, "primary", "llama")),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    (client_id, body)
+}
+
+fn chunked_config(provider: &MockProxy, chunk_size_bytes: usize) -> Config {
+    let mut config = config_with_provider("primary", &provider.url());
+    config.performance.response_chunking.chunk_size_bytes = chunk_size_bytes;
+    config
+}
+
+#[tokio::test]
+async fn test_chunk_range_decrypts_only_that_range() {
+    let provider = provider().await;
+    let proxy = Proxy::new(chunked_config(&provider, 4)).await;
+    let (client_id, body) = complete_for_client(&proxy, "hello world").await;
+    assert_eq!(body["fhe_metadata"]["chunks"]["count"], 3);
+    let response_id = body["fhe_metadata"]["processed_ciphertext_id"].clone();
+
+    let listed = proxy
+        .get(&format!(
+            "/v1/ciphertext/{}/chunks?start=1&end=3",
+            response_id.as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(listed["total_chunks"], 3);
+    let indices: Vec<_> = listed["chunks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|chunk| (chunk["index"].clone(), chunk["status"].clone()))
+        .collect();
+    assert_eq!(
+        indices,
+        [
+            (json!(1), json!("available")),
+            (json!(2), json!("available"))
+        ]
+    );
+
+    let (status, _, decrypted) = proxy
+        .call(
+            "POST",
+            "/v1/decrypt/chunks",
+            &[],
+            Some(json!({
+                "response_id": response_id,
+                "client_id": client_id,
+                "start": 0,
+                "end": 2
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", decrypted);
+    assert_eq!(
+        decrypted["chunks"],
+        json!([
+            { "index": 0, "plaintext": "hell" },
+            { "index": 1, "plaintext": "o wo" }
+        ])
+    );
+    assert_eq!(decrypted["truncated"], true);
+}
+
+#[tokio::test]
+async fn test_all_chunks_reassemble_the_response() {
+    let provider = provider().await;
+    let proxy = Proxy::new(chunked_config(&provider, 3)).await;
+    let text = "hello world";
+    let (client_id, body) = complete_for_client(&proxy, text).await;
+
+    let (status, _, decrypted) = proxy
+        .call(
+            "POST",
+            "/v1/decrypt/chunks",
+            &[],
+            Some(json!({
+                "response_id": body["fhe_metadata"]["processed_ciphertext_id"],
+                "client_id": client_id
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", decrypted);
+    let plaintext: String = decrypted["chunks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|chunk| chunk["plaintext"].as_str().unwrap())
+        .collect();
+    assert_eq!(plaintext, text);
+    assert_eq!(decrypted["total_chunks"], 4);
+    assert_eq!(decrypted["truncated"], false);
+}
+
+#[tokio::test]
+async fn test_chunks_of_an_unknown_response_are_not_found() {
+    let provider = provider().await;
+    let proxy = Proxy::new(chunked_config(&provider, 4)).await;
+    let client_id = proxy.generate_keys().await;
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/decrypt/chunks",
+            &[],
+            Some(json!({
+                "response_id": uuid::Uuid::new_v4(),
+                "client_id": client_id
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_signed_responses_verify_across_a_key_rotation() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.encryption.response_signing.enabled = true;
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+
+    // The ciphertext the signature covers is what the provider was sent
+    let verify = |body: &Value, jwks: &JwkSet, sent: usize| {
+        let signed: SignedResponseEnvelope =
+            serde_json::from_value(body["fhe_metadata"]["response_signature"].clone()).unwrap();
+        let content = provider.requests()[sent].body["messages"][0]["content"].clone();
+        let ciphertext = BASE64_STANDARD.decode(content.as_str().unwrap()).unwrap();
+        verify_signed_response(
+            &signed,
+            jwks,
+            body["id"].as_str().unwrap(),
+            &ciphertext,
+            Duration::from_secs(60),
+        )
+        .map(|_| signed.envelope.key_id)
+    };
+
+    let (_, before) = complete_for_client(&proxy, "hello").await;
+    let jwks: JwkSet = serde_json::from_value(proxy.get("/.well-known/jwks.json").await).unwrap();
+    let old_kid = verify(&before, &jwks, 0).unwrap();
+
+    let (status, _, _) = proxy
+        .call("POST", "/v1/admin/response-signing/rotate", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, rotated) = proxy
+        .call("POST", "/v1/admin/response-signing/rotate", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", rotated);
+    let new_kid = rotated["current"]["kid"].as_str().unwrap().to_string();
+    assert_ne!(new_kid, old_kid);
+
+    // New responses use the new key; the old one stays published for rollover
+    let (_, after) = complete_for_client(&proxy, "goodbye").await;
+    let jwks: JwkSet = serde_json::from_value(proxy.get("/.well-known/jwks.json").await).unwrap();
+    assert_eq!(verify(&after, &jwks, 1).unwrap(), new_kid);
+    assert!(verify(&before, &jwks, 0).is_ok());
+
+    // A signature doesn't carry over to another response's ciphertext
+    assert!(verify(&after, &jwks, 0).is_err());
+}
+
+#[tokio::test]
+async fn test_signing_keys_are_not_published_when_signing_is_off() {
+    let provider = provider().await;
+    let proxy = Proxy::new(config_with_provider("primary", &provider.url())).await;
+    let (status, _, _) = proxy.call("GET", "/.well-known/jwks.json", &[], None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, body) = complete_for_client(&proxy, "hello").await;
+    assert!(body["fhe_metadata"]["response_signature"].is_null());
+}
+
+#[tokio::test]
+async fn test_completion_is_attested_with_the_published_key() {
+    let provider = provider().await;
+    let proxy = Proxy::new(config_with_provider("primary", &provider.url())).await;
+    let (_, body) = complete_for_client(&proxy, "hello").await;
+    let keys = proxy.get("/v1/attestation/keys").await;
+
+    let attestation = &body["fhe_metadata"]["attestation"];
+    assert_eq!(attestation["statement"]["mode"], "fhe");
+    assert_eq!(
+        attestation["statement"]["key_id"],
+        keys["current"]["key_id"]
+    );
+    assert_eq!(
+        attestation["statement"]["engine_build_hash"],
+        keys["engine_build_hash"]
+    );
+    // The versioned metadata block carries the same attestation
+    assert_eq!(body["metadata"]["attestation"], *attestation);
+    assert_eq!(body["metadata"]["version"], 1);
+}
+
+#[tokio::test]
+async fn test_metadata_extensions_follow_the_registered_namespaces() {
+    let provider = provider().await;
+    let proxy = Proxy::new(config_with_provider("primary", &provider.url())).await;
+    let (_, body) = complete_for_client(&proxy, "hello").await;
+    assert_eq!(body["metadata"]["extensions"]["experimental.packed"], false);
+
+    // Without the namespace registered the extension is left out
+    let mut config = config_with_provider("primary", &provider.url());
+    config.response_metadata.extension_namespaces.clear();
+    let proxy = Proxy::new(config).await;
+    let (_, body) = complete_for_client(&proxy, "hello").await;
+    assert!(body["metadata"].get("extensions").is_none(), "{}", body);
+    assert_eq!(body["metadata"]["version"], 1);
+}
+
+#[tokio::test]
+async fn test_build_provenance_is_signed_with_the_attestation_key() {
+    let mut config = Config::default();
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let verify = |signed: &SignedProvenance, keys: &Value| {
+        let public_key = BASE64_STANDARD
+            .decode(keys["current"]["public_key"].as_str().unwrap())
+            .unwrap();
+        let signature = BASE64_STANDARD.decode(&signed.signature).unwrap();
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(&serde_json::to_vec(&signed.provenance).unwrap(), &signature)
+    };
+
+    let keys = proxy.get("/v1/attestation/keys").await;
+    let signed: SignedProvenance =
+        serde_json::from_value(proxy.get("/v1/provenance").await).unwrap();
+    assert_eq!(signed.key_id, keys["current"]["key_id"]);
+    assert_eq!(
+        signed.provenance.subject.engine_build_hash,
+        keys["engine_build_hash"]
+    );
+    assert_eq!(signed.provenance.predicate.lockfile_sha256.len(), 64);
+    verify(&signed, &keys).unwrap();
+
+    // Only an admin rotates it, and provenance follows the key through it
+    let (status, _, _) = proxy
+        .call("POST", "/v1/admin/attestation/rotate", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(proxy.get("/v1/attestation/keys").await, keys);
+    let (status, _, _) = proxy
+        .call("POST", "/v1/admin/attestation/rotate", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let rotated = proxy.get("/v1/attestation/keys").await;
+    let resigned: SignedProvenance =
+        serde_json::from_value(proxy.get("/v1/provenance").await).unwrap();
+    assert_ne!(resigned.key_id, signed.key_id);
+    verify(&resigned, &rotated).unwrap();
+    assert!(verify(&signed, &rotated).is_err());
+}
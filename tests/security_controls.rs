@@ -0,0 +1,286 @@
+//! Tenant identity, decryption approvals, honeytokens and security events
+//! exercised through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    add_admin_token, add_tenant_keys, completion_request, config_with_provider, provider, Proxy,
+    ADMIN,
+};
+use homomorphic_llm_proxy::config::{
+    ApproverConfig, Config, CustomValidatorConfig, CustomValidatorKind,
+};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_tenant_is_taken_from_credentials_not_claimed() {
+    let mut config = Config::default();
+    config.sessions.renewal.enabled = true;
+    add_tenant_keys(&mut config, &["acme", "globex"]);
+    let proxy = Proxy::new(config).await;
+    let tenant_of = |headers: &'static [(&'static str, &'static str)]| {
+        let proxy = &proxy;
+        async move {
+            let (status, _, body) = proxy.call("GET", "/v1/capabilities", headers, None).await;
+            (status, body["tenant"].clone())
+        }
+    };
+
+    assert_eq!(
+        tenant_of(&[("x-api-key", "key-acme")]).await,
+        (StatusCode::OK, json!("acme"))
+    );
+    assert_eq!(
+        tenant_of(&[("x-api-key", "key-acme"), ("x-tenant-id", "acme")]).await,
+        (StatusCode::OK, json!("acme"))
+    );
+    let (status, _) = tenant_of(&[("x-tenant-id", "acme")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = tenant_of(&[("x-api-key", "forged"), ("x-tenant-id", "acme")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = tenant_of(&[("x-api-key", "key-acme"), ("x-tenant-id", "globex")]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // A session belongs to the tenant that opened it
+    let (_, _, session) = proxy
+        .call(
+            "POST",
+            "/v1/keys/generate",
+            &[("x-api-key", "key-acme")],
+            None,
+        )
+        .await;
+    let access = session["tokens"]["access_token"].as_str().unwrap();
+    let (status, _, capabilities) = proxy
+        .call(
+            "GET",
+            "/v1/capabilities",
+            &[("x-session-token", access)],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(capabilities["tenant"], "acme");
+    for forged in [
+        [("x-session-token", access), ("x-tenant-id", "globex")],
+        [("x-session-token", access), ("x-api-key", "key-globex")],
+    ] {
+        let (status, _, _) = proxy.call("GET", "/v1/capabilities", &forged, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
+
+#[tokio::test]
+async fn test_restricted_result_decrypts_only_once_approved() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.approvals.enabled = true;
+    config.approvals.approvers.push(ApproverConfig {
+        name: "alice".to_string(),
+        // SHA-256 of "alice-token"
+        token_sha256: "9c220f200955d76c0a38d308225e0ef10c5f971acaf2f8d1d8f732affa5bd1dc"
+            .to_string(),
+    });
+    let proxy = Proxy::new(config).await;
+
+    let client_id = proxy.generate_keys().await;
+    let encrypted = proxy.encrypt_for(&client_id, "quarterly figures").await;
+    let mut request = completion_request(&encrypted, "primary", "llama");
+    request["sensitivity"] = json!("restricted");
+    let (status, headers, body) = proxy
+        .call("POST", "/v1/chat/completions", &[], Some(request))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let approval_id = headers["x-approval-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let decrypt = json!({
+        "response_id": body["fhe_metadata"]["processed_ciphertext_id"],
+        "client_id": client_id
+    });
+
+    let (status, _, _) = proxy
+        .call("POST", "/v1/decrypt/chunks", &[], Some(decrypt.clone()))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Nothing derived from the held result escapes the hold: not processing it
+    // again, combining it, or resubmitting the bytes the provider was sent
+    let held = body["fhe_metadata"]["processed_ciphertext_id"].clone();
+    let held_data = provider.requests()[0].body["messages"][0]["content"].clone();
+    let reprocess = completion_request(
+        &json!({ "ciphertext_id": held, "encrypted_data": held_data }),
+        "primary",
+        "llama",
+    );
+    let concatenate = json!({
+        "ciphertext_a": held,
+        "ciphertext_b": encrypted["ciphertext_id"],
+    });
+    let transaction = json!({
+        "steps": [
+            { "id": "held", "op": "load", "ciphertext_id": held },
+            { "id": "more", "op": "encrypt", "client_id": client_id, "text": "more" },
+            { "id": "out", "op": "concatenate", "a": "held", "b": "more" },
+        ],
+        "outputs": ["out"],
+    });
+    let document = json!({
+        "encrypted_data": held_data,
+        "provider": "primary",
+        "model": "llama",
+    });
+    for (path, body) in [
+        ("/v1/chat/completions", reprocess),
+        ("/v1/concatenate", concatenate),
+        ("/v1/transactions", transaction),
+        ("/v1/documents", document),
+    ] {
+        let (status, _, _) = proxy.call("POST", path, &[], Some(body)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", path);
+    }
+    assert_eq!(provider.requests().len(), 1);
+
+    // Only a configured approver may decide
+    let approve = format!("/v1/approvals/{}/approve", approval_id);
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            &approve,
+            &[("x-approver-token", "mallory-token")],
+            Some(json!({})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, decided) = proxy
+        .call(
+            "POST",
+            &approve,
+            &[("x-approver-token", "alice-token")],
+            Some(json!({ "reason": "board pack" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", decided);
+
+    let (status, _, decrypted) = proxy
+        .call("POST", "/v1/decrypt/chunks", &[], Some(decrypt))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", decrypted);
+    assert_eq!(decrypted["total_chunks"], 1);
+
+    // Results of other classes are never held
+    let (status, headers, _) = proxy.complete("primary", "llama", &[], "hello").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.get("x-approval-request-id").is_none());
+}
+
+#[tokio::test]
+async fn test_presented_honeytokens_are_refused_and_recorded() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.honeytokens.enabled = true;
+    add_admin_token(&mut config);
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+
+    // The traps are only shown to, and rotated by, admins
+    for method in ["GET", "POST"] {
+        let (status, _, _) = proxy.call(method, "/v1/admin/honeytokens", &[], None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, _, _) = proxy
+        .call(
+            "GET",
+            "/v1/admin/honeytokens",
+            &[("x-api-key", "key-acme")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Generations are issued on rotation; the background loop isn't running here
+    let (status, _, _) = proxy
+        .call("POST", "/v1/admin/honeytokens", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let issued = proxy.get_with("/v1/admin/honeytokens", &[ADMIN]).await;
+    let key = issued["current"]["keys"][0].as_str().unwrap().to_string();
+    let canary = issued["current"]["canaries"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (status, _, _) = proxy
+        .call("GET", "/v1/params", &[("x-api-key", key.as_str())], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // A tenant claim alongside the canary is recorded as a claim, not an identity
+    let (status, _, _) = proxy
+        .call(
+            "GET",
+            &format!("/v1/ciphertext/{}", canary),
+            &[("x-tenant-id", "globex")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/decrypt",
+            &[],
+            Some(json!({ "ciphertext_id": canary, "client_id": uuid::Uuid::new_v4() })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let trips = proxy.get_with("/v1/admin/honeytokens", &[ADMIN]).await["trips"].clone();
+    assert_eq!(trips.as_array().unwrap().len(), 3, "{}", trips);
+    let claimed = trips
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|trip| trip["context"]["claimed_tenant"] == "globex")
+        .expect("trip with a tenant claim");
+    assert!(claimed["context"]["tenant"].is_null());
+
+    // Ordinary traffic is untouched
+    let (status, _, body) = proxy
+        .complete("primary", "llama", &[("x-api-key", "key-acme")], "hello")
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn test_security_events_are_queued_for_the_siem_until_the_buffer_fills() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.validation.custom.push(CustomValidatorConfig {
+        name: "no-llama".to_string(),
+        kind: CustomValidatorKind::DenyPattern,
+        field: "model".to_string(),
+        pattern: "^llama".to_string(),
+        tenants: Vec::new(),
+        status: 403,
+    });
+    config.monitoring.siem.enabled = true;
+    config.monitoring.siem.format = "otlp".to_string();
+    config.monitoring.siem.endpoint = "http://127.0.0.1:9/v1/logs".to_string();
+    config.monitoring.siem.buffer_size = 1;
+    config.monitoring.siem.batch_size = 1;
+    // Nothing ships events from the router alone, so the buffer stays full
+    let proxy = Proxy::new(config).await;
+
+    for _ in 0..2 {
+        let (status, _, _) = proxy.complete("primary", "llama", &[], "hello").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+    let siem = proxy.get("/v1/admin/siem").await;
+    assert_eq!(siem["enabled"], true);
+    assert_eq!(siem["format"], "otlp");
+    assert_eq!(siem["queued"], 1);
+    assert_eq!(siem["dropped"], 1);
+    assert_eq!(siem["exported"], 0);
+}
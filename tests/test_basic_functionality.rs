@@ -1,6 +1,8 @@
 //! Basic integration tests for FHE LLM Proxy
 
-use homomorphic_llm_proxy::config::Config;
+use homomorphic_llm_proxy::config::{
+    Config, TenantConfigResolver, TenantOverrides, CONFIG_SCHEMA_VERSION,
+};
 use homomorphic_llm_proxy::fhe::{FheEngine, FheParams};
 use homomorphic_llm_proxy::proxy::ProxyServer;
 use uuid::Uuid;
@@ -52,6 +54,63 @@ async fn test_config_loading() {
     println!("✅ Configuration loading test passed");
 }
 
+#[test]
+fn test_config_schema_strictness_and_migration() {
+    let current = std::fs::read_to_string("config.toml").unwrap();
+    let (_, report) = Config::parse_document(&current, "config.toml").unwrap();
+    assert_eq!(report.from_version, CONFIG_SCHEMA_VERSION);
+    assert!(report.notes.is_empty());
+
+    // Typos are rejected with the line they are on
+    let typo = current.replace("workers = 4", "wrokers = 4");
+    let error = Config::parse_document(&typo, "config.toml")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("config.toml:"), "{}", error);
+    assert!(error.contains("wrokers"), "{}", error);
+
+    // Unversioned documents had keys nothing read; they are migrated away
+    let legacy = current
+        .replace("schema_version = 2", "")
+        .replace("[server]\n", "[server]\nenable_cors = true\n")
+        + "\n[feature_flags]\nbatch_processing = true\n";
+    let (config, report) = Config::parse_document(&legacy, "legacy.toml").unwrap();
+    assert_eq!(report.from_version, 1);
+    assert_eq!(report.notes.len(), 2);
+    assert_eq!(config.schema_version, CONFIG_SCHEMA_VERSION);
+
+    // Their budget reset interval becomes the replenishment policy
+    let legacy = current
+        .replace("schema_version = 2", "")
+        .replace(
+            "policy = \"daily\"  # manual, daily, weekly or rolling\n",
+            "",
+        )
+        .replace(
+            "[privacy]\n",
+            "[privacy]\nprivacy_budget_reset_hours = 168\n",
+        );
+    let (config, report) = Config::parse_document(&legacy, "legacy.toml").unwrap();
+    assert_eq!(report.notes.len(), 1, "{:?}", report.notes);
+    assert_eq!(config.privacy.budget_replenishment.policy, "weekly");
+
+    // Validation failures point at the file, line and key
+    let path = std::env::temp_dir().join(format!("fhe-config-{}.toml", Uuid::new_v4()));
+    std::fs::write(&path, current.replace("port = 8080", "port = 0")).unwrap();
+    let error = Config::validate_file(&path).unwrap_err().to_string();
+    let line = current
+        .lines()
+        .position(|line| line == "port = 8080")
+        .unwrap()
+        + 1;
+    assert!(
+        error.contains(&format!("{}:{}: server.port", path.display(), line)),
+        "{}",
+        error
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
 #[tokio::test]
 async fn test_proxy_server_creation() {
     // Test proxy server creation
@@ -62,6 +121,93 @@ async fn test_proxy_server_creation() {
     println!("✅ Proxy server creation test passed");
 }
 
+#[test]
+fn test_tenant_config_overrides() {
+    let dir = std::env::temp_dir().join(format!("fhe-tenants-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("acme.toml"),
+        "epsilon_per_query = 0.5\n[routing_rules]\n\"gpt-4\" = \"anthropic\"\n",
+    )
+    .unwrap();
+
+    let mut config = Config::default();
+    config.tenants.override_dir = Some(dir.to_string_lossy().to_string());
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            rate_limit_per_minute: Some(10),
+            epsilon_per_query: Some(0.2),
+            ..TenantOverrides::default()
+        },
+    );
+    let resolver = TenantConfigResolver::new(config.clone());
+
+    // Override documents take precedence over inline overrides
+    let acme = resolver.resolve("acme").unwrap();
+    assert_eq!(acme.rate_limit_per_minute, 10);
+    assert_eq!(acme.epsilon_per_query, 0.5);
+    assert_eq!(acme.route_provider("gpt-4", "openai"), "anthropic");
+    assert_eq!(
+        acme.max_queries_per_user,
+        config.privacy.max_queries_per_user
+    );
+
+    // Unknown tenants inherit the global layer
+    let other = resolver.resolve("other").unwrap();
+    assert!(other.overridden.is_empty());
+    assert_eq!(other.epsilon_per_query, config.privacy.epsilon_per_query);
+
+    assert!(resolver.resolve("../etc/passwd").is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_tenant_model_governance() {
+    let mut config = Config::default();
+    config.llm.model_governance.denied_models = vec!["gpt-3.5-turbo".to_string()];
+    config
+        .llm
+        .model_governance
+        .upgrades
+        .insert("gpt-4-0314".to_string(), "gpt-4".to_string());
+    config.tenants.overrides.insert(
+        "acme".to_string(),
+        TenantOverrides {
+            allowed_models: Some(vec!["gpt-4".to_string(), "claude-3-sonnet".to_string()]),
+            model_upgrades: Some(
+                [("claude-2".to_string(), "claude-3-sonnet".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..TenantOverrides::default()
+        },
+    );
+    let resolver = TenantConfigResolver::new(config);
+
+    // Tenant upgrades are layered over the global upgrade map
+    let acme = resolver.resolve("acme").unwrap();
+    assert_eq!(
+        acme.govern_model("gpt-4-0314").unwrap(),
+        ("gpt-4".to_string(), Some("gpt-4-0314".to_string()))
+    );
+    assert_eq!(
+        acme.govern_model("claude-2").unwrap(),
+        ("claude-3-sonnet".to_string(), Some("claude-2".to_string()))
+    );
+    assert!(acme.govern_model("llama-2").is_err());
+    assert!(acme.govern_model("gpt-3.5-turbo").is_err());
+
+    // Requests without a tenant only see the global lists
+    let global = resolver.resolve_global().unwrap();
+    assert_eq!(
+        global.govern_model("llama-2").unwrap(),
+        ("llama-2".to_string(), None)
+    );
+    assert!(global.govern_model("gpt-3.5-turbo").is_err());
+}
+
 #[test]
 fn test_fhe_params_serialization() {
     // Test FHE parameters serialization
@@ -0,0 +1,285 @@
+//! Experiments, quality rerouting, transforms, telemetry sampling and
+//! mirroring observed through the router
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    add_admin_token, add_tenant_keys, completion_request, config_with_provider, provider, Proxy,
+    ADMIN,
+};
+use homomorphic_llm_proxy::config::{
+    Config, ExperimentConfig, ExperimentVariantConfig, TransformOperation, TransformRule,
+    TransformStage,
+};
+use serde_json::json;
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn test_experiment_assigns_users_to_variants_stably() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.experiments.enabled = true;
+    config.experiments.definitions.push(ExperimentConfig {
+        id: "tone".to_string(),
+        template_id: "support".to_string(),
+        variants: ["formal", "casual"]
+            .map(|name| ExperimentVariantConfig {
+                name: name.to_string(),
+                template_id: format!("support-{}", name),
+                weight: 1,
+            })
+            .to_vec(),
+        tenants: Vec::new(),
+    });
+    let proxy = Proxy::new(config).await;
+    let encrypted = proxy.encrypt("my order is late").await;
+    let mut request = completion_request(&encrypted, "primary", "llama");
+    request["template_id"] = json!("support");
+
+    let mut variants = Vec::new();
+    for _ in 0..2 {
+        let (status, headers, body) = proxy
+            .call(
+                "POST",
+                "/v1/chat/completions",
+                &[("x-user-hash", "user-1")],
+                Some(request.clone()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(headers["x-experiment"], "tone");
+        variants.push(
+            headers["x-experiment-variant"]
+                .to_str()
+                .unwrap()
+                .to_string(),
+        );
+    }
+    assert_eq!(variants[0], variants[1]);
+
+    // Without a user hash there is nothing to assign on
+    let (_, headers, _) = proxy
+        .call("POST", "/v1/chat/completions", &[], Some(request))
+        .await;
+    assert!(headers.get("x-experiment").is_none());
+
+    let results = proxy.get("/v1/admin/experiments").await;
+    let variant = results["experiments"][0]["variants"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["name"] == variants[0].as_str())
+        .unwrap()
+        .clone();
+    assert_eq!(variant["assigned"], 2);
+    assert_eq!(variant["completed"], 2);
+    assert_eq!(provider.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_only_admins_register_or_remove_experiments() {
+    let mut config = Config::default();
+    config.experiments.enabled = true;
+    add_tenant_keys(&mut config, &["acme"]);
+    add_admin_token(&mut config);
+    let proxy = Proxy::new(config).await;
+    let experiment = json!({
+        "id": "tone",
+        "template_id": "support",
+        "variants": [
+            { "name": "formal", "template_id": "support-formal", "weight": 1 },
+            { "name": "casual", "template_id": "support-casual", "weight": 1 }
+        ]
+    });
+    let acme = [("x-api-key", "key-acme")];
+
+    for headers in [&[][..], &acme[..]] {
+        let (status, _, _) = proxy
+            .call(
+                "POST",
+                "/v1/admin/experiments",
+                headers,
+                Some(experiment.clone()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, _, body) = proxy
+        .call("POST", "/v1/admin/experiments", &[ADMIN], Some(experiment))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, _, _) = proxy
+        .call("DELETE", "/v1/admin/experiments/tone", &acme, None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = proxy
+        .call("DELETE", "/v1/admin/experiments/tone", &[ADMIN], None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn test_poorly_scored_model_is_rerouted() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    let quality = &mut config.monitoring.quality;
+    quality.enabled = true;
+    quality.min_scores = 1;
+    quality.downrank_below = 0.5;
+    quality.alternatives = HashMap::from([("llama".to_string(), vec!["mistral".to_string()])]);
+    let proxy = Proxy::new(config).await;
+
+    let (status, headers, body) = proxy.complete("primary", "llama", &[], "hi").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let request_id = headers["x-request-id"].to_str().unwrap().to_string();
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/quality/scores",
+            &[],
+            Some(json!({ "request_id": request_id, "kind": "thumbs", "up": false })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    // An unknown completion can't be scored
+    let (status, _, _) = proxy
+        .call(
+            "POST",
+            "/v1/quality/scores",
+            &[],
+            Some(json!({ "request_id": uuid::Uuid::new_v4(), "kind": "thumbs", "up": true })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, headers, body) = proxy.complete("primary", "llama", &[], "hi").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(headers["x-quality-rerouted-from"], "llama");
+    assert_eq!(body["model"], "mistral");
+    assert_eq!(provider.requests()[1].body["model"], "mistral");
+}
+
+#[tokio::test]
+async fn test_transform_rules_rewrite_requests_and_responses() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    let transforms = &mut config.server.transforms;
+    transforms.enabled = true;
+    transforms.rules.push(TransformRule {
+        name: "legacy_model".to_string(),
+        route: "/v1/chat".to_string(),
+        tenants: vec!["acme".to_string()],
+        stage: TransformStage::Request,
+        operations: vec![TransformOperation::RenameField {
+            from: "engine".to_string(),
+            to: "model".to_string(),
+        }],
+    });
+    transforms.rules.push(TransformRule {
+        name: "tenant_header".to_string(),
+        route: "/v1/chat".to_string(),
+        tenants: Vec::new(),
+        stage: TransformStage::Response,
+        operations: vec![TransformOperation::SetHeader {
+            name: "x-served-tenant".to_string(),
+            value: "{tenant}".to_string(),
+        }],
+    });
+    add_tenant_keys(&mut config, &["acme"]);
+    let proxy = Proxy::new(config).await;
+
+    // An old client names the model `engine`
+    let encrypted = proxy.encrypt("hi").await;
+    let mut request = completion_request(&encrypted, "primary", "llama");
+    let model = request.as_object_mut().unwrap().remove("model").unwrap();
+    request["engine"] = model;
+    let (status, headers, body) = proxy
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[("x-api-key", "key-acme")],
+            Some(request),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(headers["x-served-tenant"], "acme");
+    assert_eq!(provider.requests()[0].body["model"], "llama");
+
+    let stats = proxy.get("/v1/admin/transforms").await;
+    assert_eq!(stats["enabled"], true);
+    assert_eq!(stats["rules"].as_array().unwrap().len(), 2, "{}", stats);
+}
+
+#[tokio::test]
+async fn test_sampled_telemetry_reports_route_and_model() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    let telemetry = &mut config.monitoring.telemetry_sampling;
+    telemetry.enabled = true;
+    telemetry.sample_rate = 1.0;
+    telemetry.models = vec!["llama".to_string()];
+    let proxy = Proxy::new(config).await;
+
+    let (status, _, body) = proxy.complete("primary", "llama", &[], "hi").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _, _) = proxy.complete("primary", "mistral", &[], "hi").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let report = proxy.get("/v1/admin/telemetry").await["telemetry"].clone();
+    let completions: Vec<_> = report["series"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|series| series["route"] == "/v1/chat/completions")
+        .map(|series| (series["model"].clone(), series["requests"].clone()))
+        .collect();
+    assert_eq!(
+        completions,
+        [(json!("llama"), json!(1)), (json!("other"), json!(1))],
+        "{}",
+        report
+    );
+}
+
+#[tokio::test]
+async fn test_mirrored_completion_is_served_from_its_inline_ciphertext() {
+    let provider = provider().await;
+    let mut config = config_with_provider("primary", &provider.url());
+    config.server.mirroring.accept_mirrored = true;
+    let staging = Proxy::new(config).await;
+
+    // The production proxy's ciphertext id means nothing to staging
+    let production = Proxy::new(config_with_provider("primary", &provider.url())).await;
+    let encrypted = production.encrypt("hello").await;
+    let request = completion_request(&encrypted, "primary", "llama");
+
+    let (status, _, _) = staging
+        .call("POST", "/v1/chat/completions", &[], Some(request.clone()))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, body) = staging
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[("x-fhe-mirror", "1")],
+            Some(request.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // A proxy that doesn't stand in for staging ignores the inline copy
+    let mut unknown = request;
+    unknown["ciphertext_id"] = json!(uuid::Uuid::new_v4());
+    let (status, _, _) = production
+        .call(
+            "POST",
+            "/v1/chat/completions",
+            &[("x-fhe-mirror", "1")],
+            Some(unknown),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}